@@ -0,0 +1,110 @@
+//! Admission lookups against 100k-entry blocklists: the compiled
+//! `Blocklist` versus string-keyed IPs plus a linear scan of CIDRs
+//!
+//! cargo bench --bench blocklist
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use quantra::security::blocklist::{Blocklist, DenyEntry};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const ENTRIES: usize = 100_000;
+
+/// The lookup this replaces: exact IPs by string, then every CIDR in turn
+struct Naive {
+    ips: HashSet<String>,
+    cidrs: Vec<DenyEntry>,
+    peers: HashSet<String>,
+}
+
+impl Naive {
+    fn denies(&self, ip: IpAddr, peer_id: &str) -> bool {
+        self.ips.contains(&ip.to_string())
+            || self.cidrs.iter().any(|c| c.contains(ip))
+            || self.peers.contains(peer_id)
+    }
+}
+
+fn random_ip(rng: &mut StdRng) -> IpAddr {
+    if rng.gen_bool(0.8) {
+        IpAddr::V4(Ipv4Addr::from(rng.gen::<u32>()))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(rng.gen::<u128>()))
+    }
+}
+
+/// 90% single IPs, 9% CIDRs, 1% peer IDs
+fn lists(rng: &mut StdRng) -> (Blocklist, Naive, Vec<IpAddr>) {
+    let mut entries = Vec::new();
+    let mut peers = Vec::new();
+    for i in 0..ENTRIES {
+        match i % 100 {
+            0 => peers.push(format!("12D3KooW{:016x}", rng.gen::<u64>())),
+            1..=9 => {
+                let ip = random_ip(rng);
+                let len = if ip.is_ipv4() { rng.gen_range(8..=28) } else { rng.gen_range(32..=64) };
+                entries.push(format!("{}/{}", ip, len).parse().unwrap());
+            }
+            _ => entries.push(DenyEntry::Ip(random_ip(rng))),
+        }
+    }
+
+    let blocklist = Blocklist::new();
+    blocklist.block_all(entries.iter().copied(), peers.iter().cloned());
+    let blocked_ips: Vec<IpAddr> = entries
+        .iter()
+        .filter_map(|e| match e {
+            DenyEntry::Ip(ip) => Some(*ip),
+            DenyEntry::Cidr(..) => None,
+        })
+        .collect();
+    let naive = Naive {
+        ips: blocked_ips.iter().map(ToString::to_string).collect(),
+        cidrs: entries.iter().filter(|e| matches!(e, DenyEntry::Cidr(..))).copied().collect(),
+        peers: peers.into_iter().collect(),
+    };
+    (blocklist, naive, blocked_ips)
+}
+
+fn bench_lookup(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(7);
+    let (blocklist, naive, blocked_ips) = lists(&mut rng);
+    let misses: Vec<IpAddr> = (0..1024).map(|_| random_ip(&mut rng)).collect();
+    let hits: Vec<IpAddr> = blocked_ips.iter().take(1024).copied().collect();
+
+    let mut group = c.benchmark_group("blocklist_100k");
+    for (name, probes) in [("miss", &misses), ("hit", &hits)] {
+        group.bench_with_input(BenchmarkId::new("naive", name), probes, |b, probes| {
+            let mut i = 0;
+            b.iter(|| {
+                i = (i + 1) % probes.len();
+                black_box(naive.denies(black_box(probes[i]), "12D3KooWallowed"))
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("compiled", name), probes, |b, probes| {
+            let mut i = 0;
+            b.iter(|| {
+                i = (i + 1) % probes.len();
+                black_box(blocklist.denies(Some(black_box(probes[i])), Some("12D3KooWallowed")))
+            })
+        });
+    }
+    group.finish();
+
+    // A mutation recompiles the whole list
+    let mut group = c.benchmark_group("blocklist_100k");
+    group.sample_size(10);
+    group.bench_function("block_unblock", |b| {
+        let entry: DenyEntry = "192.0.2.1".parse().unwrap();
+        b.iter(|| {
+            blocklist.block(entry);
+            blocklist.unblock(&entry);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_lookup);
+criterion_main!(benches);
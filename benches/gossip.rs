@@ -0,0 +1,105 @@
+//! Received gossip through `P2PNode::handle_gossip_message`: size and rate
+//! checks, routing, and hand-off to a subscriber, 100k messages per batch.
+//! `previous_path` repeats the per-message work the node did before the
+//! handler, for comparison
+//!
+//! cargo bench --bench gossip
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use libp2p::gossipsub::{IdentTopic, Message, MessageId};
+use libp2p::PeerId;
+use quantra::p2p::rate_limiter::{RateLimitConfig, RateLimiter};
+use quantra::p2p::{carrier_sync, depth, P2PEvent, P2PNode};
+use quantra::terminal::{self, sanitize_for_terminal};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::hint::black_box;
+use std::sync::mpsc;
+
+const MESSAGES: usize = 100_000;
+
+/// A quote update and a bulkier 16 KiB payload
+fn payloads() -> [(&'static str, Vec<u8>); 2] {
+    let quote = br#"{"symbol":"AAPL","bid":"189.42","ask":"189.44","last":"189.43","volume":1200}"#.to_vec();
+    [("quote", quote), ("16k", vec![b'x'; 16 * 1024])]
+}
+
+/// The node's work per message before `handle_gossip_message`: a decimal
+/// string message id, the lossy UTF-8 rendering of every payload for the
+/// log line, and a `Vec` copy to hand the payload on. The rate limiter is
+/// today's, which no longer builds a quota per message
+fn previous_path(limiter: &mut RateLimiter, relay: &PeerId, message: &Message, out: &mpsc::Sender<Vec<u8>>) {
+    let mut s = DefaultHasher::new();
+    message.data.hash(&mut s);
+    let id = MessageId::from(s.finish().to_string());
+    if message.data.len() > 10 * 1024 * 1024 || !limiter.check_message(relay) {
+        return;
+    }
+    let topic = message.topic.as_str();
+    if depth::symbol_from_topic(topic).is_some() || topic == carrier_sync::CARRIER_DB_TOPIC {
+        return;
+    }
+    let rendered = sanitize_for_terminal(&String::from_utf8_lossy(&message.data), terminal::MESSAGE_RENDER_LEN);
+    black_box((&id, &rendered));
+    out.send(message.data.clone()).unwrap();
+}
+
+fn bench_handler(c: &mut Criterion) {
+    // The node's swarm wants a runtime, even though nothing here awaits
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let _guard = runtime.enter();
+    let mut node = P2PNode::new().unwrap();
+    node.set_rate_limits(&RateLimitConfig { messages_per_second: u32::MAX, ..Default::default() });
+    let mut events = node.subscribe_events();
+    let topic = IdentTopic::new("quantra-market-data").hash();
+    let relay = PeerId::random();
+    let origins: Vec<PeerId> = (0..64).map(|_| PeerId::random()).collect();
+
+    let mut group = c.benchmark_group("gossip_100k");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(MESSAGES as u64));
+    for (name, payload) in payloads() {
+        group.bench_with_input(BenchmarkId::new("handle_and_receive", name), &payload, |b, payload| {
+            b.iter(|| {
+                for i in 0..MESSAGES {
+                    let message = Message {
+                        source: Some(origins[i % origins.len()]),
+                        data: payload.clone(),
+                        sequence_number: Some(i as u64),
+                        topic: topic.clone(),
+                    };
+                    node.inject_gossip(relay, message);
+                    let Ok(P2PEvent::Message { data, .. }) = events.try_recv() else {
+                        panic!("message {} was not delivered", i);
+                    };
+                    assert_eq!(data.len(), payload.len());
+                }
+            })
+        });
+    }
+
+    let mut limiter = RateLimiter::from_config(&RateLimitConfig { messages_per_second: u32::MAX, ..Default::default() });
+    limiter.register_peer(relay);
+    let (out, received) = mpsc::channel();
+    for (name, payload) in payloads() {
+        group.bench_with_input(BenchmarkId::new("previous_path", name), &payload, |b, payload| {
+            b.iter(|| {
+                for i in 0..MESSAGES {
+                    let message = Message {
+                        source: Some(origins[i % origins.len()]),
+                        data: payload.clone(),
+                        sequence_number: Some(i as u64),
+                        topic: topic.clone(),
+                    };
+                    previous_path(&mut limiter, &relay, &message, &out);
+                    let data = received.try_recv().unwrap_or_else(|_| panic!("message {} was not delivered", i));
+                    assert_eq!(data.len(), payload.len());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_handler);
+criterion_main!(benches);
@@ -2,6 +2,29 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Future: Add gRPC proto compilation here
     // tonic_build::compile_protos("proto/quantra.proto")?;
 
+    // Build manifest for peer attestation (src/zerotrust/attestation.rs).
+    // A digest supplied by the release pipeline passes straight through
+    println!("cargo:rerun-if-env-changed=QUANTRA_BUILD_DIGEST");
+    println!("cargo:rerun-if-env-changed=QUANTRA_GIT_COMMIT");
+    if std::env::var_os("QUANTRA_GIT_COMMIT").is_none() {
+        let commit = std::process::Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .output()
+            .ok()
+            .filter(|out| out.status.success())
+            .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string());
+        if let Some(commit) = commit {
+            println!("cargo:rustc-env=QUANTRA_GIT_COMMIT={}", commit);
+        }
+        // HEAD, and the branch it points at, move on checkout and commit
+        if let Ok(head) = std::fs::read_to_string(".git/HEAD") {
+            println!("cargo:rerun-if-changed=.git/HEAD");
+            if let Some(branch) = head.trim().strip_prefix("ref: ") {
+                println!("cargo:rerun-if-changed=.git/{}", branch);
+            }
+        }
+    }
+
     println!("cargo:rerun-if-changed=build.rs");
     Ok(())
 }
@@ -0,0 +1,71 @@
+//! Two nodes in one process exchanging encrypted messages through
+//! `NodeHandle`, with no access to the swarm or its locks.
+//!
+//!     cargo run --example embedded_chat
+
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use quantra::p2p::P2PEvent;
+use quantra::{NodeConfig, NodeHandle};
+use std::time::Duration;
+
+const MESSAGES: [&str; 2] = ["hello alice", "this is sealed to your identity key"];
+
+fn loopback() -> NodeConfig {
+    NodeConfig { listen: vec!["/ip4/127.0.0.1/tcp/0".to_string()], mdns: false, ..Default::default() }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let (alice, alice_task) = NodeHandle::spawn(loopback()).await?;
+    let (bob, bob_task) = NodeHandle::spawn(loopback()).await?;
+    println!("alice: {}\nbob:   {}", alice.peer_id(), bob.peer_id());
+
+    // Alice prints what she receives and answers each message
+    let mut inbox = alice.events().await?;
+    let replier = alice.clone();
+    let alice_loop = tokio::spawn(async move {
+        let mut answered = 0;
+        while answered < MESSAGES.len() {
+            let Some(event) = inbox.next().await else { break };
+            if let P2PEvent::DirectMessage { source, data, delivery, .. } = event {
+                println!("alice <- {}: {}", source, String::from_utf8_lossy(&data));
+                if let Some(delivery) = delivery {
+                    delivery.ack()?;
+                }
+                replier.send_encrypted(source, format!("got {} bytes", data.len())).await?;
+                answered += 1;
+            }
+        }
+        anyhow::Ok(())
+    });
+
+    let mut bob_inbox = bob.events().await?;
+    let alice_addr = alice.status().await?.listeners[0].addresses[0].clone();
+    bob.dial(&alice_addr).await?;
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while bob.status().await?.connected_peers == 0 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        anyhow::Ok(())
+    })
+    .await
+    .context("bob could not reach alice")??;
+
+    for text in MESSAGES {
+        bob.send_encrypted(alice.peer_id(), text).await?;
+        while let Some(event) = bob_inbox.next().await {
+            if let P2PEvent::DirectMessage { data, .. } = event {
+                println!("bob   <- alice: {}", String::from_utf8_lossy(&data));
+                break;
+            }
+        }
+    }
+
+    alice_loop.await??;
+    bob.shutdown().await;
+    alice.shutdown().await;
+    bob_task.await??;
+    alice_task.await??;
+    Ok(())
+}
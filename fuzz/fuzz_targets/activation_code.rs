@@ -0,0 +1,10 @@
+//! Fuzz Target: Activation Codes
+//! Input: text scanned from a QR code or pasted, as a raw `LPA:1$...`
+//! code, iOS universal link or Android intent URI; not necessarily UTF-8
+//! Invariants are checked in `quantra::fuzzing::activation_code`
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| quantra::fuzzing::activation_code(data));
@@ -0,0 +1,10 @@
+//! Fuzz Target: Audit Log Lines
+//! Input: one audit log line (base64 of nonce and ciphertext), or the
+//! event JSON encrypted inside one
+//! Invariants are checked in `quantra::fuzzing::audit_line`
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| quantra::fuzzing::audit_line(data));
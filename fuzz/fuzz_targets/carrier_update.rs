@@ -0,0 +1,10 @@
+//! Fuzz Target: Carrier Database Updates
+//! Input: a signed update file as given to `carriers publish`, or the
+//! same JSON from the carrier DB topic
+//! Invariants are checked in `quantra::fuzzing::carrier_update`
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| quantra::fuzzing::carrier_update(data));
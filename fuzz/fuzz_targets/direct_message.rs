@@ -0,0 +1,10 @@
+//! Fuzz Target: Direct Messages
+//! Input: a sealed box from a peer, and as the plaintext inside one,
+//! rendered to the terminal
+//! Invariants are checked in `quantra::fuzzing::direct_message`
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| quantra::fuzzing::direct_message(data));
@@ -0,0 +1,10 @@
+//! Fuzz Target: Request Envelopes
+//! Input: the body of a peer's request stream, CBOR read by the codec
+//! under both `/quantra/1.0.0` and `/quantra/1.1.0`
+//! Invariants are checked in `quantra::fuzzing::request_envelope`
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| quantra::fuzzing::request_envelope(data));
@@ -0,0 +1,10 @@
+//! Fuzz Target: Signed Stats Reports
+//! Input: a gossiped `quantra-telemetry` message, JSON signed by an
+//! unknown reporter key
+//! Invariants are checked in `quantra::fuzzing::signed_report`
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| quantra::fuzzing::signed_report(data));
@@ -0,0 +1,80 @@
+//! Alert Delivery
+//! Sinks that fired alerts are fanned out to
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+use super::AlertFired;
+use crate::security::webhook::WebhookSender;
+
+#[async_trait]
+pub trait AlertSink: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn deliver(&self, alert: &AlertFired) -> Result<()>;
+}
+
+/// Writes alerts to the tracing log
+pub struct LogSink;
+
+#[async_trait]
+impl AlertSink for LogSink {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn deliver(&self, alert: &AlertFired) -> Result<()> {
+        tracing::warn!("{}", alert.message());
+        Ok(())
+    }
+}
+
+/// Posts alerts to a Slack / Discord style webhook
+pub struct WebhookSink {
+    sender: WebhookSender,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str) -> Self {
+        Self {
+            sender: WebhookSender::new(url),
+        }
+    }
+}
+
+#[async_trait]
+impl AlertSink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn deliver(&self, alert: &AlertFired) -> Result<()> {
+        self.sender.send_text(&alert.message()).await
+    }
+}
+
+/// Hands alerts to a running P2P node, which publishes them on its
+/// `alerts/<peer_id>` gossipsub topic
+pub struct GossipSink {
+    tx: mpsc::UnboundedSender<String>,
+}
+
+impl GossipSink {
+    pub fn new(tx: mpsc::UnboundedSender<String>) -> Self {
+        Self { tx }
+    }
+}
+
+#[async_trait]
+impl AlertSink for GossipSink {
+    fn name(&self) -> &'static str {
+        "gossip"
+    }
+
+    async fn deliver(&self, alert: &AlertFired) -> Result<()> {
+        self.tx
+            .send(serde_json::to_string(alert)?)
+            .ok()
+            .context("P2P node is no longer running")
+    }
+}
@@ -0,0 +1,367 @@
+//! Quote Alerts
+//! Watch-only threshold rules evaluated against streamed quotes, with
+//! cooldowns and delivery to the log, a webhook and the P2P network
+
+pub mod delivery;
+pub mod positions;
+pub mod store;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::data_dirs::DataDirs;
+use crate::quant::market_stream::MarketDataStream;
+use crate::quant::Quote;
+use crate::units::HumanDuration;
+use delivery::AlertSink;
+use positions::PositionMonitor;
+use store::AlertStore;
+
+/// `[alerts]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertSettings {
+    /// Rule database location (default: `alerts/` in the data directory)
+    pub store_path: Option<PathBuf>,
+    /// Time between REST polls of symbols not streamed, and between re-reads
+    /// of the watched symbols (legacy: `poll_interval_secs`)
+    #[serde(alias = "poll_interval_secs", deserialize_with = "crate::units::duration_or_secs")]
+    pub poll_interval: HumanDuration,
+    /// Optional webhook for fired alerts
+    pub webhook_url: Option<String>,
+}
+
+impl AlertSettings {
+    /// Configured store path, or the profile's alerts directory
+    pub fn store_path(&self, dirs: &DataDirs) -> Result<PathBuf> {
+        match &self.store_path {
+            Some(path) => Ok(path.clone()),
+            None => dirs.alerts_dir(),
+        }
+    }
+}
+
+impl Default for AlertSettings {
+    fn default() -> Self {
+        Self {
+            store_path: None,
+            poll_interval: HumanDuration::from_secs(15),
+            webhook_url: None,
+        }
+    }
+}
+
+/// What a rule watches
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlertCondition {
+    /// Last price above the threshold
+    PriceAbove,
+    /// Last price below the threshold
+    PriceBelow,
+    /// Absolute % change of the last price over the window exceeds the threshold
+    PctChangeOver { window_secs: i64 },
+    /// Ask - bid above the threshold
+    SpreadAbove,
+    /// Position risk rules (see `positions`); never stored as alert rules
+    StopLoss,
+    TrailingStop,
+    TakeProfit,
+}
+
+impl fmt::Display for AlertCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PriceAbove => write!(f, "price above"),
+            Self::PriceBelow => write!(f, "price below"),
+            Self::PctChangeOver { window_secs } => write!(f, "% change over {}s above", window_secs),
+            Self::SpreadAbove => write!(f, "spread above"),
+            Self::StopLoss => write!(f, "stop loss at"),
+            Self::TrailingStop => write!(f, "trailing stop at"),
+            Self::TakeProfit => write!(f, "take profit at"),
+        }
+    }
+}
+
+/// A persisted alert rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub id: String,
+    pub symbol: String,
+    pub condition: AlertCondition,
+    pub threshold: Decimal,
+    /// Minimum seconds between firings
+    pub cooldown_secs: i64,
+    pub created_at: DateTime<Utc>,
+    pub last_fired: Option<DateTime<Utc>>,
+}
+
+impl AlertRule {
+    pub fn new(symbol: &str, condition: AlertCondition, threshold: Decimal, cooldown: Duration) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().simple().to_string()[..8].to_string(),
+            symbol: symbol.to_uppercase(),
+            condition,
+            threshold,
+            cooldown_secs: cooldown.num_seconds(),
+            created_at: Utc::now(),
+            last_fired: None,
+        }
+    }
+
+    fn cooling_down(&self, now: DateTime<Utc>) -> bool {
+        self.last_fired
+            .is_some_and(|fired| now < fired + Duration::seconds(self.cooldown_secs))
+    }
+}
+
+/// A rule that fired
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertFired {
+    pub rule_id: String,
+    pub symbol: String,
+    pub condition: AlertCondition,
+    pub threshold: Decimal,
+    /// Value that crossed the threshold (price, spread or % change)
+    pub observed: Decimal,
+    pub fired_at: DateTime<Utc>,
+}
+
+impl AlertFired {
+    pub fn message(&self) -> String {
+        format!(
+            "🔔 {} {} {} (observed {}) [rule {}]",
+            self.symbol, self.condition, self.threshold, self.observed, self.rule_id
+        )
+    }
+}
+
+/// Evaluates quotes against stored rules
+pub struct AlertEvaluator {
+    store: AlertStore,
+    /// Recent last prices per symbol for % change rules
+    history: HashMap<String, VecDeque<(DateTime<Utc>, Decimal)>>,
+}
+
+impl AlertEvaluator {
+    pub fn new(store: AlertStore) -> Self {
+        Self {
+            store,
+            history: HashMap::new(),
+        }
+    }
+
+    /// Symbols with at least one rule
+    pub fn symbols(&self) -> Result<Vec<String>> {
+        let mut symbols: Vec<String> = self.store.list()?.into_iter().map(|r| r.symbol).collect();
+        symbols.sort();
+        symbols.dedup();
+        Ok(symbols)
+    }
+
+    /// Evaluate one quote; the quote timestamp is the evaluation time
+    /// Fired rules are persisted with their new `last_fired`
+    pub fn evaluate(&mut self, quote: &Quote) -> Result<Vec<AlertFired>> {
+        let symbol = quote.symbol.to_uppercase();
+        let now = quote.timestamp;
+        let rules = self.store.for_symbol(&symbol)?;
+
+        let longest_window = rules
+            .iter()
+            .filter_map(|r| match r.condition {
+                AlertCondition::PctChangeOver { window_secs } => Some(window_secs),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        let history = self.history.entry(symbol.clone()).or_default();
+        history.push_back((now, quote.last));
+        while history
+            .front()
+            .is_some_and(|(t, _)| *t < now - Duration::seconds(longest_window))
+        {
+            history.pop_front();
+        }
+
+        let mut fired = Vec::new();
+        for mut rule in rules {
+            let Some(observed) = triggered(&rule, quote, history) else { continue };
+            if rule.cooling_down(now) {
+                tracing::debug!("🔕 Alert {} suppressed (cooldown)", rule.id);
+                continue;
+            }
+
+            rule.last_fired = Some(now);
+            self.store.put(&rule)?;
+            fired.push(AlertFired {
+                rule_id: rule.id,
+                symbol: symbol.clone(),
+                condition: rule.condition,
+                threshold: rule.threshold,
+                observed,
+                fired_at: now,
+            });
+        }
+        Ok(fired)
+    }
+}
+
+/// The observed value if the rule's condition holds
+fn triggered(
+    rule: &AlertRule,
+    quote: &Quote,
+    history: &VecDeque<(DateTime<Utc>, Decimal)>,
+) -> Option<Decimal> {
+    match rule.condition {
+        AlertCondition::PriceAbove => (quote.last > rule.threshold).then_some(quote.last),
+        AlertCondition::PriceBelow => (quote.last < rule.threshold).then_some(quote.last),
+        AlertCondition::SpreadAbove => {
+            let spread = quote.ask - quote.bid;
+            (spread > rule.threshold).then_some(spread)
+        }
+        AlertCondition::PctChangeOver { window_secs } => {
+            let since = quote.timestamp - Duration::seconds(window_secs);
+            let (_, base) = history.iter().find(|(t, _)| *t >= since)?;
+            if base.is_zero() {
+                return None;
+            }
+            let pct = (quote.last - base) / base * Decimal::ONE_HUNDRED;
+            (pct.abs() > rule.threshold).then_some(pct.round_dp(4))
+        }
+        AlertCondition::StopLoss | AlertCondition::TrailingStop | AlertCondition::TakeProfit => None,
+    }
+}
+
+/// Follow quotes for every watched symbol and position with a risk rule,
+/// and deliver fired alerts. The watched set is re-read every `refresh`
+pub async fn run(
+    mut evaluator: AlertEvaluator,
+    mut positions: Option<PositionMonitor>,
+    feed: Arc<dyn MarketDataStream>,
+    sinks: Vec<Box<dyn AlertSink>>,
+    refresh: std::time::Duration,
+) -> Result<()> {
+    tracing::info!("🔔 Alert evaluation running (watched symbols refreshed every {:?})", refresh);
+    let mut quotes = feed.subscribe(&watched(&evaluator, positions.as_ref())?);
+    let mut tick = tokio::time::interval(refresh);
+    tick.tick().await;
+
+    loop {
+        let quote = tokio::select! {
+            _ = tick.tick() => {
+                quotes.set_symbols(&watched(&evaluator, positions.as_ref())?);
+                continue;
+            }
+            quote = quotes.next_quote() => quote.context("Quote feed stopped")?,
+        };
+        let mut fired = evaluator.evaluate(&quote)?;
+        if let Some(monitor) = positions.as_mut() {
+            fired.extend(monitor.evaluate(&quote)?);
+        }
+        for alert in fired {
+            for sink in &sinks {
+                if let Err(e) = sink.deliver(&alert).await {
+                    tracing::warn!("🔔 Alert delivery via {} failed: {}", sink.name(), e);
+                }
+            }
+        }
+    }
+}
+
+fn watched(evaluator: &AlertEvaluator, positions: Option<&PositionMonitor>) -> Result<Vec<String>> {
+    let mut symbols = evaluator.symbols()?;
+    if let Some(monitor) = positions {
+        symbols.extend(monitor.symbols()?);
+    }
+    Ok(symbols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::RuntimeMode;
+    use std::str::FromStr;
+    use tempfile::TempDir;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn quote(symbol: &str, last: &str, at: DateTime<Utc>) -> Quote {
+        Quote {
+            symbol: symbol.to_string(),
+            bid: dec(last) - dec("0.05"),
+            ask: dec(last) + dec("0.05"),
+            last: dec(last),
+            volume: 1000,
+            timestamp: at,
+        }
+    }
+
+    fn t0() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-03-02T14:30:00Z").unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_fire_cooldown_and_persisted_state() {
+        let dir = TempDir::new().unwrap();
+        let rule = AlertRule::new("aapl", AlertCondition::PriceAbove, dec("200"), Duration::minutes(10));
+        let rule_id = rule.id.clone();
+
+        {
+            let store = AlertStore::open(dir.path(), RuntimeMode::Persistent).unwrap();
+            store.put(&rule).unwrap();
+            let mut evaluator = AlertEvaluator::new(store);
+
+            assert!(evaluator.evaluate(&quote("AAPL", "199.99", t0())).unwrap().is_empty());
+            let fired = evaluator.evaluate(&quote("AAPL", "200.01", t0() + Duration::minutes(1))).unwrap();
+            assert_eq!(fired.len(), 1);
+            assert_eq!(fired[0].observed, dec("200.01"));
+
+            // Within the cooldown: suppressed
+            assert!(evaluator.evaluate(&quote("AAPL", "205", t0() + Duration::minutes(5))).unwrap().is_empty());
+        }
+
+        // Restart: last_fired survives and still suppresses
+        let store = AlertStore::open(dir.path(), RuntimeMode::Persistent).unwrap();
+        assert_eq!(store.get(&rule_id).unwrap().unwrap().last_fired, Some(t0() + Duration::minutes(1)));
+        let mut evaluator = AlertEvaluator::new(store);
+        assert!(evaluator.evaluate(&quote("AAPL", "206", t0() + Duration::minutes(9))).unwrap().is_empty());
+        assert_eq!(evaluator.evaluate(&quote("AAPL", "207", t0() + Duration::minutes(12))).unwrap().len(), 1);
+        println!("✅ Alert cooldown / persistence test PASSED!");
+    }
+
+    #[test]
+    fn test_pct_change_and_spread_rules() {
+        let store = AlertStore::open(std::path::Path::new("unused"), RuntimeMode::Ephemeral).unwrap();
+        store
+            .put(&AlertRule::new(
+                "BTC",
+                AlertCondition::PctChangeOver { window_secs: 600 },
+                dec("5"),
+                Duration::zero(),
+            ))
+            .unwrap();
+        store
+            .put(&AlertRule::new("ETH", AlertCondition::SpreadAbove, dec("0.5"), Duration::zero()))
+            .unwrap();
+        let mut evaluator = AlertEvaluator::new(store);
+
+        assert!(evaluator.evaluate(&quote("BTC", "100", t0())).unwrap().is_empty());
+        assert!(evaluator.evaluate(&quote("BTC", "104.9", t0() + Duration::minutes(5))).unwrap().is_empty());
+        let fired = evaluator.evaluate(&quote("BTC", "105.1", t0() + Duration::minutes(9))).unwrap();
+        assert_eq!(fired[0].observed, dec("5.1"));
+        // The 100 sample has left the 10 minute window; base is now 104.9
+        assert!(evaluator.evaluate(&quote("BTC", "105.2", t0() + Duration::minutes(11))).unwrap().is_empty());
+
+        let mut wide = quote("ETH", "3000", t0());
+        wide.ask = dec("3000.6");
+        assert_eq!(evaluator.evaluate(&wide).unwrap()[0].observed, dec("0.65"));
+        assert!(evaluator.evaluate(&quote("ETH", "3000", t0())).unwrap().is_empty());
+    }
+}
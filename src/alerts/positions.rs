@@ -0,0 +1,180 @@
+//! Position Risk Monitoring
+//! Marks positions with a risk rule to market and fires stop-loss /
+//! take-profit alerts; in paper trading, `auto_close` rules also sell
+
+use anyhow::Result;
+use std::sync::Arc;
+
+use super::{AlertCondition, AlertFired};
+use crate::quant::portfolio::RiskTriggerKind;
+use crate::quant::portfolio_store::{self, PortfolioStore};
+use crate::quant::{Quote, TradeSide};
+
+pub struct PositionMonitor {
+    store: Arc<PortfolioStore>,
+    paper_trading: bool,
+}
+
+impl PositionMonitor {
+    /// Takes the store shared (it can only be opened once per process),
+    /// e.g. with the `PerformanceTracker`
+    pub fn new(store: impl Into<Arc<PortfolioStore>>, paper_trading: bool) -> Self {
+        Self { store: store.into(), paper_trading }
+    }
+
+    /// Symbols of positions with an armed risk rule
+    pub fn symbols(&self) -> Result<Vec<String>> {
+        let portfolio = self.store.load()?;
+        let mut symbols: Vec<String> = portfolio
+            .positions
+            .into_values()
+            .filter(|p| p.risk.as_ref().is_some_and(|r| r.triggered_at.is_none()))
+            .map(|p| p.symbol)
+            .collect();
+        symbols.sort();
+        Ok(symbols)
+    }
+
+    /// Mark the position to `quote.last`, update the trailing high-water
+    /// mark and fire if a threshold is crossed. Auto-close sells at the bid,
+    /// so a gap through the stop fills at the price actually available.
+    pub fn evaluate(&mut self, quote: &Quote) -> Result<Option<AlertFired>> {
+        let symbol = quote.symbol.to_uppercase();
+        let mut portfolio = self.store.load()?;
+        let Some(position) = portfolio.positions.get_mut(&symbol) else { return Ok(None) };
+        let Some(mut rule) = position.risk.clone() else { return Ok(None) };
+
+        position.current_price = quote.last;
+        rule.observe(quote.last, position.average_cost);
+        let trigger = rule.check(quote.last, position.average_cost);
+        if trigger.is_some() {
+            rule.triggered_at = Some(quote.timestamp);
+        }
+        let close = trigger.is_some() && rule.auto_close && self.paper_trading;
+        position.risk = Some(rule);
+        self.store.put_position(position)?;
+
+        let Some(trigger) = trigger else { return Ok(None) };
+        if close {
+            let quantity = position.quantity;
+            let trade = portfolio_store::market_trade(&symbol, TradeSide::Sell, quantity, quote.bid);
+            self.store
+                .execute(&mut portfolio, trade, Some(trigger.kind.as_str().to_string()))?;
+            tracing::warn!(
+                "📉 Paper-closed {} {} at {} ({} at {})",
+                quantity, symbol, quote.bid, trigger.kind.as_str(), trigger.trigger_price
+            );
+        }
+
+        let condition = match trigger.kind {
+            RiskTriggerKind::StopLoss => AlertCondition::StopLoss,
+            RiskTriggerKind::TrailingStop => AlertCondition::TrailingStop,
+            RiskTriggerKind::TakeProfit => AlertCondition::TakeProfit,
+        };
+        Ok(Some(AlertFired {
+            rule_id: format!("position/{}", symbol),
+            symbol,
+            condition,
+            threshold: trigger.trigger_price,
+            observed: quote.last,
+            fired_at: quote.timestamp,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quant::portfolio::RiskRule;
+    use crate::storage::RuntimeMode;
+    use chrono::{DateTime, Utc};
+    use rust_decimal::Decimal;
+    use std::str::FromStr;
+    use tempfile::TempDir;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn quote(last: &str, bid: &str) -> Quote {
+        Quote {
+            symbol: "AAPL".to_string(),
+            bid: dec(bid),
+            ask: dec(last) + dec("0.05"),
+            last: dec(last),
+            volume: 1000,
+            timestamp: DateTime::parse_from_rfc3339("2026-03-02T14:30:00Z").unwrap().with_timezone(&Utc),
+        }
+    }
+
+    /// 10 AAPL bought at 100 with `rule` attached
+    fn holding(path: &std::path::Path, rule: RiskRule) -> PortfolioStore {
+        let store = PortfolioStore::open(path, RuntimeMode::Persistent).unwrap();
+        let mut portfolio = store.load().unwrap();
+        let buy = portfolio_store::market_trade("aapl", TradeSide::Buy, dec("10"), dec("100"));
+        store.execute(&mut portfolio, buy, None).unwrap();
+        assert!(portfolio.set_risk_rule("AAPL", rule));
+        store.put_position(&portfolio.positions["AAPL"]).unwrap();
+        store
+    }
+
+    #[test]
+    fn test_stop_fires_once_without_auto_close() {
+        let dir = TempDir::new().unwrap();
+        let rule = RiskRule { stop_loss_pct: Some(dec("5")), ..Default::default() };
+        let mut monitor = PositionMonitor::new(holding(dir.path(), rule), false);
+
+        assert!(monitor.evaluate(&quote("96", "95.95")).unwrap().is_none());
+        let fired = monitor.evaluate(&quote("94.5", "94.45")).unwrap().unwrap();
+        assert_eq!((fired.condition, fired.threshold), (AlertCondition::StopLoss, dec("95")));
+        assert!(monitor.evaluate(&quote("90", "89.95")).unwrap().is_none());
+        assert!(monitor.symbols().unwrap().is_empty());
+        // Notify only: the position is still held
+        assert_eq!(monitor.store.load().unwrap().positions["AAPL"].quantity, dec("10"));
+    }
+
+    #[test]
+    fn test_gap_through_stop_fills_at_quote() {
+        let dir = TempDir::new().unwrap();
+        let rule = RiskRule { stop_loss_pct: Some(dec("5")), auto_close: true, ..Default::default() };
+        let mut monitor = PositionMonitor::new(holding(dir.path(), rule), true);
+
+        // Opens well below the 95 stop
+        let fired = monitor.evaluate(&quote("88", "87.90")).unwrap().unwrap();
+        assert_eq!(fired.observed, dec("88"));
+
+        assert!(monitor.store.load().unwrap().positions.is_empty());
+        let ledger = monitor.store.ledger().unwrap();
+        assert_eq!(ledger.len(), 2);
+        let close = &ledger[1];
+        assert!(matches!(close.trade.side, TradeSide::Sell));
+        assert_eq!((close.trade.quantity, close.trade.price), (dec("10"), dec("87.90")));
+        assert_eq!(close.triggered_by.as_deref(), Some("stop_loss"));
+        println!("✅ Gap-through stop fill test PASSED!");
+    }
+
+    #[test]
+    fn test_high_water_mark_survives_restart() {
+        let dir = TempDir::new().unwrap();
+        let rule = RiskRule { stop_loss_pct: Some(dec("5")), trailing: true, auto_close: true, ..Default::default() };
+        {
+            let mut monitor = PositionMonitor::new(holding(dir.path(), rule), true);
+            for last in ["103", "120", "115"] {
+                assert!(monitor.evaluate(&quote(last, last)).unwrap().is_none());
+            }
+        }
+
+        let store = PortfolioStore::open(dir.path(), RuntimeMode::Persistent).unwrap();
+        let position = &store.load().unwrap().positions["AAPL"];
+        assert_eq!(position.risk.as_ref().unwrap().high_water_mark, Some(dec("120")));
+        assert_eq!(position.current_price, dec("115"));
+
+        // 5% under 120 is 114, well above the entry-based 95
+        let mut monitor = PositionMonitor::new(store, true);
+        let fired = monitor.evaluate(&quote("113.9", "113.85")).unwrap().unwrap();
+        assert_eq!((fired.condition, fired.threshold), (AlertCondition::TrailingStop, dec("114")));
+        let ledger = monitor.store.ledger().unwrap();
+        assert_eq!(ledger.last().unwrap().triggered_by.as_deref(), Some("trailing_stop"));
+        assert_eq!(ledger.last().unwrap().trade.price, dec("113.85"));
+    }
+}
@@ -0,0 +1,87 @@
+//! Alert Rule Store
+//! Rules are persisted as JSON keyed by rule id
+
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use super::AlertRule;
+use crate::migrations::{self, StoreSchema};
+use crate::storage::{IndexFields, IndexQuery, KvStore, RuntimeMode};
+
+const RULES_TREE: &str = "alert_rules";
+
+pub const SCHEMA: StoreSchema = StoreSchema {
+    name: "alerts",
+    tree: Some(RULES_TREE),
+    version: 1,
+    migrations: &[],
+    index: Some(index_rule),
+};
+
+/// Rules are found by symbol and ordered by creation
+fn index_rule(_key: &[u8], value: &[u8]) -> IndexFields {
+    match serde_json::from_slice::<AlertRule>(value) {
+        Ok(rule) => IndexFields {
+            symbol: Some(rule.symbol),
+            timestamp: Some(rule.created_at.timestamp_millis()),
+            ..IndexFields::default()
+        },
+        Err(_) => IndexFields::default(),
+    }
+}
+
+fn decode(entries: Vec<(Vec<u8>, Vec<u8>)>) -> Result<Vec<AlertRule>> {
+    entries
+        .into_iter()
+        .map(|(_, bytes)| serde_json::from_slice(&bytes).context("Corrupt alert rule"))
+        .collect()
+}
+
+pub struct AlertStore {
+    db: Box<dyn KvStore>,
+}
+
+impl AlertStore {
+    /// Open the rule database at `path`, or keep rules in memory when ephemeral
+    pub fn open(path: &Path, mode: RuntimeMode) -> Result<Self> {
+        let db = migrations::open_store(mode, path, &SCHEMA)
+            .with_context(|| format!("Failed to open alert store at {}", path.display()))?;
+        Ok(Self { db })
+    }
+
+    /// Insert or replace a rule
+    pub fn put(&self, rule: &AlertRule) -> Result<()> {
+        self.db.insert(rule.id.as_bytes(), &serde_json::to_vec(rule)?)?;
+        self.db.flush()
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<AlertRule>> {
+        self.db
+            .get(id.as_bytes())?
+            .map(|bytes| serde_json::from_slice(&bytes).context("Corrupt alert rule"))
+            .transpose()
+    }
+
+    /// Remove a rule; returns false if it did not exist
+    pub fn remove(&self, id: &str) -> Result<bool> {
+        let existed = self.db.get(id.as_bytes())?.is_some();
+        self.db.remove(id.as_bytes())?;
+        self.db.flush()?;
+        Ok(existed)
+    }
+
+    /// All rules, oldest first
+    pub fn list(&self) -> Result<Vec<AlertRule>> {
+        let mut rules = decode(self.db.find(IndexQuery::ALL_TIMES)?)?;
+        // The index keeps milliseconds
+        rules.sort_by_key(|r| r.created_at);
+        Ok(rules)
+    }
+
+    /// Rules on `symbol`, oldest first
+    pub fn for_symbol(&self, symbol: &str) -> Result<Vec<AlertRule>> {
+        let mut rules = decode(self.db.find(IndexQuery::Symbol(symbol))?)?;
+        rules.sort_by_key(|r| r.created_at);
+        Ok(rules)
+    }
+}
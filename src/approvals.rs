@@ -0,0 +1,381 @@
+//! Approval Queue
+//! Decisions that wait on an operator. Subsystems enqueue a request with a
+//! category and the JSON context needed to carry on; approving or denying
+//! it (expiry denies) runs the handler registered for the category. Pending
+//! requests are persisted, so a restart keeps them and their expiries
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::migrations::{self, StoreSchema};
+use crate::storage::{KvStore, RuntimeMode};
+use crate::units::HumanDuration;
+use crate::zerotrust::ZeroTrustContext;
+
+pub const SCHEMA: StoreSchema = StoreSchema {
+    name: "approvals",
+    tree: Some("approvals"),
+    version: 1,
+    migrations: &[],
+    index: None,
+};
+
+/// Who resolved a request from the CLI or the node console
+pub const LOCAL_CLI: &str = "local-cli";
+/// Who resolved a request that nobody answered in time
+pub const EXPIRY: &str = "expiry";
+
+/// `[approvals]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApprovalSettings {
+    /// How long a request waits before it is denied
+    pub ttl: HumanDuration,
+}
+
+impl Default for ApprovalSettings {
+    fn default() -> Self {
+        Self { ttl: HumanDuration::from_secs(24 * 3600) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    Approve,
+    Deny,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Approve => "approved",
+            Self::Deny => "denied",
+        })
+    }
+}
+
+/// A decision recorded against a request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Resolution {
+    pub decision: Decision,
+    /// API token name, `local-cli` or `expiry`
+    pub by: String,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingApproval {
+    pub id: String,
+    pub category: String,
+    pub description: String,
+    pub requested_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// What the category's handler needs to carry on
+    pub context: serde_json::Value,
+    /// Set once decided; kept until the handler has run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<Resolution>,
+}
+
+impl fmt::Display for PendingApproval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}  [{}] {}", self.id, self.category, self.description)?;
+        match &self.resolution {
+            Some(r) => write!(f, " ({} by {}, not yet applied)", r.decision, r.by),
+            None => write!(f, " (expires {})", self.expires_at.format("%Y-%m-%d %H:%M UTC")),
+        }
+    }
+}
+
+/// Why a request couldn't be resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApprovalError {
+    NotFound(String),
+    AlreadyResolved { id: String, decision: Decision },
+}
+
+impl fmt::Display for ApprovalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(id) => write!(f, "no pending approval {}", id),
+            Self::AlreadyResolved { id, decision } => write!(f, "approval {} was already {}", id, decision),
+        }
+    }
+}
+
+impl std::error::Error for ApprovalError {}
+
+/// Carries on after a decision; runs on the caller's thread, so slow work
+/// should be spawned
+pub type ApprovalHandler = Arc<dyn Fn(&PendingApproval, Decision) -> Result<()> + Send + Sync>;
+
+pub struct ApprovalQueue {
+    db: Box<dyn KvStore>,
+    handlers: RwLock<HashMap<String, ApprovalHandler>>,
+    audit: Option<ZeroTrustContext>,
+}
+
+impl ApprovalQueue {
+    /// Open the queue in `dir`, or keep it in memory when ephemeral
+    pub fn open(dir: &Path, mode: RuntimeMode) -> Result<Self> {
+        let db = migrations::open_store(mode, dir, &SCHEMA)
+            .with_context(|| format!("Failed to open the approval queue at {}", dir.display()))?;
+        Ok(Self { db, handlers: RwLock::new(HashMap::new()), audit: None })
+    }
+
+    /// Record each resolution as an `approval_resolved` audit event
+    pub fn set_audit(&mut self, zt: ZeroTrustContext) {
+        self.audit = Some(zt);
+    }
+
+    /// Run `handler` when a `category` request is decided. Requests decided
+    /// while no handler was registered (from the CLI, with the node
+    /// stopped) are handed to it now
+    pub fn register(&self, category: &str, handler: impl Fn(&PendingApproval, Decision) -> Result<()> + Send + Sync + 'static) {
+        self.handlers.write().insert(category.to_string(), Arc::new(handler));
+        match self.list() {
+            Ok(requests) => {
+                for request in requests.into_iter().filter(|r| r.category == category && r.resolution.is_some()) {
+                    if let Err(e) = self.apply(&request) {
+                        tracing::warn!("✋ Approval {} ({}) not applied: {:#}", request.id, category, e);
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("✋ Could not read the approval queue: {:#}", e),
+        }
+    }
+
+    pub fn enqueue(
+        &self,
+        category: &str,
+        description: &str,
+        context: serde_json::Value,
+        ttl: ChronoDuration,
+        now: DateTime<Utc>,
+    ) -> Result<PendingApproval> {
+        let mut id = new_id();
+        while self.db.get(id.as_bytes())?.is_some() {
+            id = new_id();
+        }
+        let request = PendingApproval {
+            id,
+            category: category.to_string(),
+            description: description.to_string(),
+            requested_at: now,
+            expires_at: now + ttl,
+            context,
+            resolution: None,
+        };
+        self.put(&request)?;
+        tracing::info!("✋ Approval {} requested: [{}] {}", request.id, category, description);
+        Ok(request)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<PendingApproval>> {
+        self.db
+            .get(id.as_bytes())?
+            .map(|bytes| serde_json::from_slice(&bytes).context("Corrupt approval request"))
+            .transpose()
+    }
+
+    /// Every request not yet carried out, oldest first
+    pub fn list(&self) -> Result<Vec<PendingApproval>> {
+        let mut requests = self
+            .db
+            .entries()?
+            .into_iter()
+            .map(|(_, bytes)| serde_json::from_slice(&bytes).context("Corrupt approval request"))
+            .collect::<Result<Vec<PendingApproval>>>()?;
+        requests.sort_by_key(|r| r.requested_at);
+        Ok(requests)
+    }
+
+    /// Undecided requests in `category`
+    pub fn pending(&self, category: &str) -> Result<Vec<PendingApproval>> {
+        let mut requests = self.list()?;
+        requests.retain(|r| r.category == category && r.resolution.is_none());
+        Ok(requests)
+    }
+
+    /// Earliest expiry among undecided requests
+    pub fn next_expiry(&self) -> Result<Option<DateTime<Utc>>> {
+        Ok(self.list()?.iter().filter(|r| r.resolution.is_none()).map(|r| r.expires_at).min())
+    }
+
+    /// Decide `id` as `by` and run its category's handler. The decision is
+    /// stored first, so one made while no handler is registered (or whose
+    /// handler fails) is carried out when the handler is next registered
+    pub async fn resolve(&self, id: &str, decision: Decision, by: &str, now: DateTime<Utc>) -> Result<PendingApproval> {
+        let mut request = self.get(id)?.ok_or_else(|| ApprovalError::NotFound(id.to_string()))?;
+        if let Some(resolution) = &request.resolution {
+            return Err(ApprovalError::AlreadyResolved { id: id.to_string(), decision: resolution.decision }.into());
+        }
+        request.resolution = Some(Resolution { decision, by: by.to_string(), at: now });
+        self.put(&request)?;
+        self.audit(&request).await;
+        tracing::info!("✋ Approval {} [{}] {} by {}", request.id, request.category, decision, by);
+        self.apply(&request)?;
+        Ok(request)
+    }
+
+    /// Deny every undecided request past its expiry
+    pub async fn expire_due(&self, now: DateTime<Utc>) -> Result<Vec<PendingApproval>> {
+        let mut expired = Vec::new();
+        for request in self.list()? {
+            if request.resolution.is_some() || request.expires_at > now {
+                continue;
+            }
+            match self.resolve(&request.id, Decision::Deny, EXPIRY, now).await {
+                Ok(request) => expired.push(request),
+                Err(e) => tracing::warn!("✋ Expiring approval {}: {:#}", request.id, e),
+            }
+        }
+        Ok(expired)
+    }
+
+    /// Run the handler for a decided request and drop it from the queue;
+    /// kept when there is no handler yet or it failed
+    fn apply(&self, request: &PendingApproval) -> Result<()> {
+        let Some(resolution) = &request.resolution else { return Ok(()) };
+        let handler = self.handlers.read().get(&request.category).cloned();
+        let Some(handler) = handler else {
+            tracing::debug!("✋ No handler for {} yet; approval {} kept", request.category, request.id);
+            return Ok(());
+        };
+        handler(request, resolution.decision)?;
+        self.db.remove(request.id.as_bytes())?;
+        self.db.flush()
+    }
+
+    async fn audit(&self, request: &PendingApproval) {
+        let (Some(zt), Some(resolution)) = (&self.audit, &request.resolution) else { return };
+        let details = HashMap::from([
+            ("approval_id".to_string(), request.id.clone()),
+            ("category".to_string(), request.category.clone()),
+            ("description".to_string(), request.description.clone()),
+            ("decision".to_string(), resolution.decision.to_string()),
+            ("resolved_by".to_string(), resolution.by.clone()),
+        ]);
+        if let Err(e) = zt.log_local_event("approval_resolved", details).await {
+            tracing::warn!("✋ Could not audit approval {}: {:#}", request.id, e);
+        }
+    }
+
+    fn put(&self, request: &PendingApproval) -> Result<()> {
+        self.db.insert(request.id.as_bytes(), &serde_json::to_vec(request)?)?;
+        self.db.flush()
+    }
+}
+
+/// Short enough to type at the prompt
+fn new_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()[..8].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_750_000_000, 0).unwrap() + ChronoDuration::minutes(minutes)
+    }
+
+    type Calls = Arc<Mutex<Vec<(String, Decision)>>>;
+
+    fn record(calls: &Calls) -> impl Fn(&PendingApproval, Decision) -> Result<()> + Send + Sync + 'static {
+        let calls = calls.clone();
+        move |request, decision| {
+            calls.lock().push((request.context["target"].as_str().unwrap().to_string(), decision));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_expire_and_restore() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let zt = ZeroTrustContext::with_mode(RuntimeMode::Ephemeral).await.unwrap();
+        let calls: Calls = Arc::default();
+        let ttl = ChronoDuration::hours(1);
+
+        let mut queue = ApprovalQueue::open(dir.path(), RuntimeMode::Persistent).unwrap();
+        queue.set_audit(zt.clone());
+        queue.register("bait.deactivate", record(&calls));
+        queue.register("emergency.full_wipe", record(&calls));
+        let wallet = queue
+            .enqueue("bait.deactivate", "Deactivate wallet w1", serde_json::json!({ "target": "w1" }), ttl, at(0))
+            .unwrap();
+        let wipe = queue
+            .enqueue("emergency.full_wipe", "Full wipe", serde_json::json!({ "target": "disk" }), ttl, at(1))
+            .unwrap();
+        let denied = queue
+            .enqueue("bait.deactivate", "Deactivate wallet w2", serde_json::json!({ "target": "w2" }), ttl, at(2))
+            .unwrap();
+        assert_eq!(queue.list().unwrap().len(), 3);
+        assert_eq!(queue.next_expiry().unwrap(), Some(at(60)));
+
+        queue.resolve(&wallet.id, Decision::Approve, LOCAL_CLI, at(5)).await.unwrap();
+        queue.resolve(&denied.id, Decision::Deny, "ops-token", at(6)).await.unwrap();
+        assert_eq!(*calls.lock(), vec![("w1".to_string(), Decision::Approve), ("w2".to_string(), Decision::Deny)]);
+        let err = queue.resolve(&wallet.id, Decision::Deny, LOCAL_CLI, at(6)).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<ApprovalError>(), Some(&ApprovalError::NotFound(wallet.id.clone())));
+
+        // Restart with the wipe still pending: it comes back and still expires
+        drop(queue);
+        let mut queue = ApprovalQueue::open(dir.path(), RuntimeMode::Persistent).unwrap();
+        queue.set_audit(zt.clone());
+        queue.register("emergency.full_wipe", record(&calls));
+        assert_eq!(queue.list().unwrap(), vec![wipe.clone()]);
+        assert_eq!(queue.next_expiry().unwrap(), Some(at(61)));
+        assert!(queue.expire_due(at(60)).await.unwrap().is_empty());
+        let expired = queue.expire_due(at(61)).await.unwrap();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].resolution.as_ref().unwrap().by, EXPIRY);
+        assert_eq!(calls.lock().last(), Some(&("disk".to_string(), Decision::Deny)));
+        assert!(queue.list().unwrap().is_empty());
+
+        let resolutions: Vec<(String, String, String)> = zt
+            .audit_events()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|e| e.event_type == "approval_resolved")
+            .map(|e| (e.details["approval_id"].clone(), e.details["decision"].clone(), e.details["resolved_by"].clone()))
+            .collect();
+        assert_eq!(
+            resolutions,
+            vec![
+                (wallet.id, "approved".to_string(), LOCAL_CLI.to_string()),
+                (denied.id, "denied".to_string(), "ops-token".to_string()),
+                (wipe.id, "denied".to_string(), EXPIRY.to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_decision_without_handler_is_applied_on_register() {
+        let queue = ApprovalQueue::open(Path::new("unused"), RuntimeMode::Ephemeral).unwrap();
+        let request = queue
+            .enqueue("carrier_db.update", "Apply v7", serde_json::json!({ "target": "v7" }), ChronoDuration::hours(1), at(0))
+            .unwrap();
+        // Decided from the CLI while the node (and its handler) was down
+        queue.resolve(&request.id, Decision::Approve, LOCAL_CLI, at(1)).await.unwrap();
+        assert!(queue.get(&request.id).unwrap().unwrap().resolution.is_some());
+        assert!(queue.pending("carrier_db.update").unwrap().is_empty());
+        assert_eq!(queue.next_expiry().unwrap(), None);
+        let err = queue.resolve(&request.id, Decision::Deny, LOCAL_CLI, at(2)).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ApprovalError>(), Some(ApprovalError::AlreadyResolved { decision: Decision::Approve, .. })));
+
+        let calls: Calls = Arc::default();
+        queue.register("carrier_db.update", record(&calls));
+        assert_eq!(*calls.lock(), vec![("v7".to_string(), Decision::Approve)]);
+        assert!(queue.list().unwrap().is_empty());
+    }
+}
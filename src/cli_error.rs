@@ -0,0 +1,365 @@
+//! CLI Errors
+//! Maps failures to documented exit codes and stable string codes, and
+//! renders the `--output json` error envelope
+//!
+//! Exit codes: 0 success, 1 other failure, 2 usage, 3 not found,
+//! 4 validation, 5 network/upstream, 6 permission, 7 integrity/corruption
+
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::io;
+
+use crate::crypto::key_provider::{KeyProblem, KeyProviderError};
+use crate::crypto::secrets::SecretError;
+use crate::crypto::KeystoreError;
+use crate::data_dirs::LegacyKeystoreConflict;
+use crate::esim::carrier_updates::UpdateRejection;
+use crate::esim::compat::EidError;
+use crate::esim::health::CarrierUnhealthy;
+use crate::migrations::DowngradeError;
+use crate::p2p::node_key::CorruptNodeKey;
+use crate::p2p::transcript::TranscriptError;
+use crate::quant::account::OrderRejection;
+use crate::quant::cost_basis::LotError;
+use crate::quant::market_data::UnknownSymbol;
+use crate::quant::plugin::PluginError;
+use crate::quant::fixed_income::BondError;
+use crate::quant::sizing::SizingError;
+use crate::quant::watchlist::WatchlistError;
+use crate::search::EmptyQuery;
+
+/// Shown under `--help`
+pub const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  success
+  1  other failure
+  2  usage error (bad arguments)
+  3  not found (carrier, profile, file, ...)
+  4  validation (malformed input or config)
+  5  network or upstream service unavailable
+  6  permission denied
+  7  integrity failure (bad signature, corrupt data)
+
+With --output json, failures print {\"error\": {\"code\", \"message\", \"details\"}} on stderr.";
+
+/// Failure category; each has a fixed process exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Failure,
+    Usage,
+    NotFound,
+    Validation,
+    Network,
+    Permission,
+    Integrity,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::Failure => 1,
+            Self::Usage => 2,
+            Self::NotFound => 3,
+            Self::Validation => 4,
+            Self::Network => 5,
+            Self::Permission => 6,
+            Self::Integrity => 7,
+        }
+    }
+}
+
+/// Error raised by a command with a stable code for scripts
+#[derive(Debug)]
+pub struct CliError {
+    pub kind: ErrorKind,
+    /// SCREAMING_SNAKE_CASE, never renamed once released
+    pub code: &'static str,
+    pub message: String,
+    pub details: Value,
+}
+
+impl CliError {
+    pub fn new(kind: ErrorKind, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            code,
+            message: message.into(),
+            details: Value::Null,
+        }
+    }
+
+    pub fn not_found(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::NotFound, code, message)
+    }
+
+    pub fn validation(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Validation, code, message)
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// `{"error": {...}}` body
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    pub details: Value,
+    /// The failed command's trace ID, to find its log lines and audit events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    error: &'a ErrorBody,
+}
+
+impl ErrorBody {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&Envelope { error: self }).expect("error envelope serializes")
+    }
+}
+
+/// Exit category and envelope for a failed command
+pub fn classify(err: &anyhow::Error) -> (ErrorKind, ErrorBody) {
+    let message = format!("{:#}", err);
+    let (kind, code, details) = match err.downcast_ref::<CliError>() {
+        Some(cli) => (cli.kind, cli.code, cli.details.clone()),
+        None => err
+            .chain()
+            .find_map(classify_cause)
+            .unwrap_or((ErrorKind::Failure, "INTERNAL", Value::Null)),
+    };
+    let body = ErrorBody {
+        code: code.to_string(),
+        message,
+        details,
+        trace_id: crate::trace::current().map(String::from),
+    };
+    (kind, body)
+}
+
+/// Recognized library errors anywhere in the cause chain
+fn classify_cause(cause: &(dyn std::error::Error + 'static)) -> Option<(ErrorKind, &'static str, Value)> {
+    if let Some(rejection) = cause.downcast_ref::<UpdateRejection>() {
+        let kind = match rejection {
+            UpdateRejection::Gap { .. } => ErrorKind::Validation,
+            _ => ErrorKind::Integrity,
+        };
+        return Some((kind, rejection.code(), Value::Null));
+    }
+    if let Some(e) = cause.downcast_ref::<DowngradeError>() {
+        let details = serde_json::json!({ "store": e.store, "found": e.found, "supported": e.supported });
+        return Some((ErrorKind::Validation, "SCHEMA_TOO_NEW", details));
+    }
+    if let Some(e) = cause.downcast_ref::<io::Error>() {
+        let details = serde_json::json!({ "io_kind": format!("{:?}", e.kind()) });
+        let (kind, code) = match e.kind() {
+            io::ErrorKind::NotFound => (ErrorKind::NotFound, "FILE_NOT_FOUND"),
+            io::ErrorKind::PermissionDenied => (ErrorKind::Permission, "PERMISSION_DENIED"),
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::AddrNotAvailable
+            | io::ErrorKind::TimedOut => (ErrorKind::Network, "NETWORK_UNREACHABLE"),
+            io::ErrorKind::InvalidData => (ErrorKind::Integrity, "CORRUPT_DATA"),
+            _ => return None,
+        };
+        return Some((kind, code, details));
+    }
+    if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+        let details = match e.status() {
+            Some(status) => serde_json::json!({ "status": status.as_u16() }),
+            None => Value::Null,
+        };
+        return Some((ErrorKind::Network, "UPSTREAM_UNAVAILABLE", details));
+    }
+    if let Some(sled::Error::Corruption { .. }) = cause.downcast_ref::<sled::Error>() {
+        return Some((ErrorKind::Integrity, "STORE_CORRUPT", Value::Null));
+    }
+    if cause.downcast_ref::<EmptyQuery>().is_some() {
+        return Some((ErrorKind::Validation, "EMPTY_QUERY", Value::Null));
+    }
+    if let Some(e) = cause.downcast_ref::<SizingError>() {
+        return Some((ErrorKind::Validation, "INVALID_SIZING", serde_json::json!({ "reason": e.to_string() })));
+    }
+    match cause.downcast_ref::<BondError>() {
+        Some(BondError::Invalid { input, value, .. }) => {
+            return Some((ErrorKind::Validation, "INVALID_BOND", serde_json::json!({ "input": input, "value": value })));
+        }
+        Some(BondError::NoConvergence { iterations, last }) => {
+            return Some((ErrorKind::Failure, "NO_CONVERGENCE", serde_json::json!({ "iterations": iterations, "last_ytm": last })));
+        }
+        None => {}
+    }
+    if let Some(e) = cause.downcast_ref::<EidError>() {
+        return Some((ErrorKind::Validation, "INVALID_EID", serde_json::json!({ "reason": e.to_string() })));
+    }
+    if let Some(OrderRejection::InsufficientBuyingPower { required, available }) = cause.downcast_ref::<OrderRejection>() {
+        let details = serde_json::json!({ "required": required, "available": available });
+        return Some((ErrorKind::Validation, "INSUFFICIENT_BUYING_POWER", details));
+    }
+    if let Some(e) = cause.downcast_ref::<LotError>() {
+        return Some(match e {
+            LotError::UnknownLot { symbol, lot_id } => {
+                (ErrorKind::NotFound, "UNKNOWN_LOT", serde_json::json!({ "symbol": symbol, "lot": lot_id }))
+            }
+            LotError::Uncovered { symbol, quantity, covered } => (
+                ErrorKind::Validation,
+                "LOTS_UNCOVERED",
+                serde_json::json!({ "symbol": symbol, "quantity": quantity, "covered": covered }),
+            ),
+            LotError::NoLotsSelected { symbol } => {
+                (ErrorKind::Validation, "LOTS_REQUIRED", serde_json::json!({ "symbol": symbol }))
+            }
+            LotError::Oversold { symbol, quantity, held } => (
+                ErrorKind::Validation,
+                "INSUFFICIENT_POSITION",
+                serde_json::json!({ "symbol": symbol, "quantity": quantity, "held": held }),
+            ),
+        });
+    }
+    if let Some(e) = cause.downcast_ref::<PluginError>() {
+        return Some(match e {
+            PluginError::Invalid(_) | PluginError::InitFailed(_) => (ErrorKind::Validation, "INVALID_STRATEGY", Value::Null),
+            _ => (ErrorKind::Failure, "STRATEGY_ERROR", serde_json::json!({ "reason": e.to_string() })),
+        });
+    }
+    if let Some(CarrierUnhealthy(health)) = cause.downcast_ref::<CarrierUnhealthy>() {
+        return Some((ErrorKind::Network, "CARRIER_UNHEALTHY", serde_json::to_value(health).unwrap_or_default()));
+    }
+    if let Some(e) = cause.downcast_ref::<TranscriptError>() {
+        let kind = match e {
+            TranscriptError::EmptyRange(_) => ErrorKind::Validation,
+            TranscriptError::Signing(_) => ErrorKind::Failure,
+            _ => ErrorKind::Integrity,
+        };
+        return Some((kind, "TRANSCRIPT_INVALID", serde_json::json!({ "reason": e.to_string() })));
+    }
+    if let Some(e) = cause.downcast_ref::<KeyProviderError>() {
+        let kind = match e.problem {
+            KeyProblem::WrongPin => ErrorKind::Permission,
+            _ => ErrorKind::Failure,
+        };
+        let details = serde_json::json!({ "provider": e.provider, "problem": e.problem, "hint": e.hint });
+        return Some((kind, "KEY_PROVIDER_UNAVAILABLE", details));
+    }
+    if let Some(e) = cause.downcast_ref::<KeystoreError>() {
+        return Some(match e {
+            KeystoreError::Unknown(name) => (ErrorKind::NotFound, "UNKNOWN_KEYSTORE", serde_json::json!({ "keystore": name })),
+            KeystoreError::Locked(name) => (ErrorKind::Permission, "KEYSTORE_LOCKED", serde_json::json!({ "keystore": name })),
+            KeystoreError::WrongPassphrase(name) => (ErrorKind::Permission, "WRONG_PASSPHRASE", serde_json::json!({ "keystore": name })),
+            KeystoreError::AlreadyProtected(name) => (ErrorKind::Validation, "KEYSTORE_PROTECTED", serde_json::json!({ "keystore": name })),
+            KeystoreError::KeyNotFound { key, known } => {
+                (ErrorKind::NotFound, "KEY_NOT_FOUND", serde_json::json!({ "key": key, "known": known }))
+            }
+            KeystoreError::AmbiguousFingerprint { fingerprint, keystores } => (
+                ErrorKind::Validation,
+                "AMBIGUOUS_FINGERPRINT",
+                serde_json::json!({ "fingerprint": fingerprint, "keystores": keystores }),
+            ),
+            KeystoreError::AmbiguousUserId { user_id, keys } => {
+                (ErrorKind::Validation, "AMBIGUOUS_USER_ID", serde_json::json!({ "user_id": user_id, "keys": keys }))
+            }
+            KeystoreError::SecretExportUnconfirmed(key) => {
+                (ErrorKind::Validation, "SECRET_EXPORT_UNCONFIRMED", serde_json::json!({ "key": key }))
+            }
+        });
+    }
+    if let Some(e) = cause.downcast_ref::<WatchlistError>() {
+        return Some(match e {
+            WatchlistError::InvalidSymbol(symbol) => {
+                (ErrorKind::Validation, "INVALID_SYMBOL", serde_json::json!({ "symbol": symbol }))
+            }
+            WatchlistError::Undecryptable => (ErrorKind::Integrity, "WATCHLIST_UNDECRYPTABLE", Value::Null),
+        });
+    }
+    if let Some(e) = cause.downcast_ref::<LegacyKeystoreConflict>() {
+        let details = serde_json::json!({ "legacy": e.legacy, "keystore": e.target });
+        return Some((ErrorKind::Validation, "LEGACY_KEYSTORE_CONFLICT", details));
+    }
+    if let Some(e) = cause.downcast_ref::<CorruptNodeKey>() {
+        return Some((ErrorKind::Integrity, "CORRUPT_NODE_KEY", serde_json::json!({ "path": e.path })));
+    }
+    if let Some(UnknownSymbol(symbol)) = cause.downcast_ref::<UnknownSymbol>() {
+        return Some((ErrorKind::NotFound, "UNKNOWN_SYMBOL", serde_json::json!({ "symbol": symbol })));
+    }
+    if let Some(e) = cause.downcast_ref::<SecretError>() {
+        return Some(match e {
+            SecretError::InvalidName(name) => {
+                (ErrorKind::Validation, "INVALID_SECRET_NAME", serde_json::json!({ "secret": name }))
+            }
+            SecretError::NotFound(name) => (ErrorKind::NotFound, "UNKNOWN_SECRET", serde_json::json!({ "secret": name })),
+            SecretError::Unresolved { secret, config_key } => (
+                ErrorKind::NotFound,
+                "SECRET_UNRESOLVED",
+                serde_json::json!({ "secret": secret, "config_key": config_key }),
+            ),
+            SecretError::ProviderMismatch { sealed_by, configured } => (
+                ErrorKind::Validation,
+                "SECRETS_PROVIDER_MISMATCH",
+                serde_json::json!({ "sealed_by": sealed_by, "configured": configured }),
+            ),
+        });
+    }
+    if cause.is::<toml::de::Error>() {
+        return Some((ErrorKind::Validation, "INVALID_CONFIG", Value::Null));
+    }
+    if cause.is::<serde_json::Error>() {
+        return Some((ErrorKind::Validation, "INVALID_JSON", Value::Null));
+    }
+    if cause.is::<libp2p::multiaddr::Error>() {
+        return Some((ErrorKind::Validation, "INVALID_ADDRESS", Value::Null));
+    }
+    None
+}
+
+/// Usage errors from argument parsing
+pub fn classify_usage(err: &clap::Error) -> ErrorBody {
+    ErrorBody {
+        code: "USAGE".to_string(),
+        message: err.to_string().lines().next().unwrap_or_default().trim_start_matches("error: ").to_string(),
+        details: serde_json::json!({ "kind": format!("{:?}", err.kind()) }),
+        trace_id: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_classify_chain() {
+        let err = anyhow::Error::new(CliError::not_found("UNKNOWN_CARRIER", "no such carrier"))
+            .context("provisioning failed");
+        let (kind, body) = classify(&err);
+        assert_eq!((kind.exit_code(), body.code.as_str()), (3, "UNKNOWN_CARRIER"));
+        assert_eq!(body.message, "provisioning failed: no such carrier");
+
+        let err = std::fs::read("/nonexistent/quantra").context("read input").unwrap_err();
+        assert_eq!(classify(&err).0, ErrorKind::NotFound);
+
+        let err = anyhow::Error::new(UpdateRejection::BadSignature);
+        assert_eq!(classify(&err).1.code, "BAD_SIGNATURE");
+        assert_eq!(classify(&anyhow::anyhow!("boom")).0.exit_code(), 1);
+
+        let json: Value = serde_json::from_str(&classify(&err).1.to_json()).unwrap();
+        assert_eq!(json["error"]["code"], "BAD_SIGNATURE");
+        println!("✅ Error classification test PASSED!");
+    }
+}
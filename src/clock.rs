@@ -0,0 +1,37 @@
+//! Wall Clock
+//! The time the CLI stamps records with and renders reports against.
+//! `QUANTRA_CLOCK` (RFC 3339) pins it, so golden-file tests of the output
+//! are the same on every run
+
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use std::sync::OnceLock;
+
+/// Variable that pins the clock, e.g. `2026-03-02T09:30:00Z`
+pub const CLOCK_ENV: &str = "QUANTRA_CLOCK";
+
+fn pinned() -> Option<DateTime<Utc>> {
+    static PINNED: OnceLock<Option<DateTime<Utc>>> = OnceLock::new();
+    *PINNED.get_or_init(|| {
+        let value = std::env::var(CLOCK_ENV).ok()?;
+        match DateTime::parse_from_rfc3339(&value) {
+            Ok(at) => Some(at.with_timezone(&Utc)),
+            Err(e) => {
+                tracing::warn!("⚠️  Ignoring {}={}: {}", CLOCK_ENV, value, e);
+                None
+            }
+        }
+    })
+}
+
+pub fn now() -> DateTime<Utc> {
+    pinned().unwrap_or_else(Utc::now)
+}
+
+/// Today's local date; the UTC date while pinned, so it doesn't depend on
+/// the machine's time zone
+pub fn today() -> NaiveDate {
+    match pinned() {
+        Some(at) => at.date_naive(),
+        None => Local::now().date_naive(),
+    }
+}
@@ -0,0 +1,474 @@
+//! Key Providers
+//! Where the node's Ed25519 identity key and the audit log data key live:
+//! in software (the default, unchanged), on a PKCS#11 token, or sealed to
+//! a TPM. Everything that signs as the node or loads the audit key goes
+//! through a `KeyProvider`
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::Result;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::data_dirs::DataDirs;
+
+/// Signing and data key protection backed by some key store
+pub trait KeyProvider: Send + Sync {
+    /// `file`, `pkcs11` or `tpm2`
+    fn name(&self) -> &'static str;
+    fn public_key(&self) -> VerifyingKey;
+    fn sign(&self, message: &[u8]) -> Result<Signature>;
+    /// Protect a data key for storage at rest
+    fn wrap_data_key(&self, key: &[u8; 32]) -> Result<Vec<u8>>;
+    fn unwrap_data_key(&self, wrapped: &[u8]) -> Result<[u8; 32]>;
+    /// The secret key, for providers that hold it in memory. libp2p and
+    /// sealed-box decryption can only use such a key
+    fn secret_key(&self) -> Option<SigningKey> {
+        None
+    }
+}
+
+/// Signed to derive the file provider's data key wrapping key
+const WRAP_DERIVATION_MESSAGE: &[u8] = b"quantra-file-provider-data-key-wrap-v1";
+const WRAP_NONCE_LEN: usize = 12;
+
+/// Key held in memory; data keys are wrapped with AES-256-GCM under a key
+/// derived from it, so they are only as exposed as the identity key
+pub struct FileKeyProvider {
+    key: SigningKey,
+}
+
+impl FileKeyProvider {
+    pub fn new(key: SigningKey) -> Self {
+        Self { key }
+    }
+
+    pub fn generate() -> Self {
+        Self::new(SigningKey::from_bytes(&rand::random::<[u8; 32]>()))
+    }
+
+    /// HKDF over a signature of a fixed message; Ed25519 signatures are
+    /// deterministic, so the same identity always derives the same key
+    fn wrap_cipher(&self) -> Result<Aes256Gcm> {
+        let signature = self.key.sign(WRAP_DERIVATION_MESSAGE);
+        let hkdf = Hkdf::<Sha256>::new(Some(WRAP_DERIVATION_MESSAGE), &signature.to_bytes());
+        let mut key = [0u8; 32];
+        hkdf.expand(b"data-key-wrap", &mut key).map_err(|_| anyhow::anyhow!("HKDF expand failed"))?;
+        Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+    }
+}
+
+impl KeyProvider for FileKeyProvider {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn public_key(&self) -> VerifyingKey {
+        self.key.verifying_key()
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Signature> {
+        Ok(self.key.sign(message))
+    }
+
+    /// nonce || ciphertext
+    fn wrap_data_key(&self, key: &[u8; 32]) -> Result<Vec<u8>> {
+        let nonce: [u8; WRAP_NONCE_LEN] = rand::random();
+        let ciphertext = self
+            .wrap_cipher()?
+            .encrypt(Nonce::from_slice(&nonce), key.as_slice())
+            .map_err(|_| anyhow::anyhow!("Failed to wrap data key"))?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    fn unwrap_data_key(&self, wrapped: &[u8]) -> Result<[u8; 32]> {
+        anyhow::ensure!(wrapped.len() > WRAP_NONCE_LEN, "Invalid wrapped key size: {} bytes", wrapped.len());
+        let (nonce, ciphertext) = wrapped.split_at(WRAP_NONCE_LEN);
+        let key = self
+            .wrap_cipher()?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("Data key was not wrapped by this identity key"))?;
+        key.as_slice()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid key size: {} bytes", key.len()))
+    }
+
+    fn secret_key(&self) -> Option<SigningKey> {
+        Some(self.key.clone())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    File,
+    Pkcs11,
+    Tpm2,
+}
+
+impl fmt::Display for ProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File => write!(f, "file"),
+            Self::Pkcs11 => write!(f, "pkcs11"),
+            Self::Tpm2 => write!(f, "tpm2"),
+        }
+    }
+}
+
+/// `[keys]` configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeysSettings {
+    pub provider: ProviderKind,
+    pub pkcs11: Pkcs11Config,
+    pub tpm2: Tpm2Config,
+}
+
+/// `[keys.pkcs11]`: an HSM or smartcard holding a non-exportable Ed25519
+/// key and an AES key for wrapping data keys
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pkcs11Config {
+    /// The vendor's PKCS#11 module, e.g. `/usr/lib/softhsm/libsofthsm2.so`
+    pub module: Option<PathBuf>,
+    /// Slot ID (first slot with a token when unset)
+    pub slot: Option<u64>,
+    /// User PIN; prefer `pin_env`
+    pub pin: Option<String>,
+    /// Environment variable holding the user PIN
+    pub pin_env: String,
+    pub key_label: String,
+    pub wrap_key_label: String,
+}
+
+impl Default for Pkcs11Config {
+    fn default() -> Self {
+        Self {
+            module: None,
+            slot: None,
+            pin: None,
+            pin_env: "QUANTRA_PKCS11_PIN".to_string(),
+            key_label: "quantra-identity".to_string(),
+            wrap_key_label: "quantra-audit-wrap".to_string(),
+        }
+    }
+}
+
+impl Pkcs11Config {
+    /// `pin`, else the `pin_env` variable
+    pub fn resolve_pin(&self) -> Option<String> {
+        self.pin.clone().or_else(|| std::env::var(&self.pin_env).ok())
+    }
+}
+
+/// `[keys.tpm2]`: secrets sealed to the TPM under a PCR policy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tpm2Config {
+    /// TSS2 TCTI, e.g. `device:/dev/tpmrm0` or `swtpm:port=2321`
+    pub tcti: String,
+    /// SHA-256 PCRs the sealed secrets are bound to
+    pub pcrs: Vec<u8>,
+}
+
+impl Default for Tpm2Config {
+    fn default() -> Self {
+        Self { tcti: "device:/dev/tpmrm0".to_string(), pcrs: vec![0, 2, 4, 7] }
+    }
+}
+
+/// What went wrong opening or using a provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyProblem {
+    /// Provider support isn't in this build
+    NotCompiled,
+    /// PKCS#11 module or TPM device unavailable
+    ModuleMissing,
+    /// No token in the slot
+    TokenMissing,
+    WrongPin,
+    /// The token or TPM has no usable key under the configured name
+    KeyMissing,
+    /// The PCR state no longer matches the sealing policy
+    PolicyMismatch,
+    /// The device refused an operation
+    Device,
+}
+
+/// A provider that can't be used, with what to do about it
+#[derive(Debug, Clone)]
+pub struct KeyProviderError {
+    pub provider: ProviderKind,
+    pub problem: KeyProblem,
+    pub detail: String,
+    pub hint: String,
+}
+
+impl KeyProviderError {
+    pub fn new(provider: ProviderKind, problem: KeyProblem, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self { provider, problem, detail: detail.into(), hint: hint.into() }
+    }
+}
+
+impl fmt::Display for KeyProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} key provider: {} (hint: {})", self.provider, self.detail, self.hint)
+    }
+}
+
+impl std::error::Error for KeyProviderError {}
+
+/// The configured provider, or `None` for the default file-based keys
+pub fn open(settings: &KeysSettings, dirs: &DataDirs) -> Result<Option<Arc<dyn KeyProvider>>> {
+    match settings.provider {
+        ProviderKind::File => Ok(None),
+        ProviderKind::Pkcs11 => open_pkcs11(&settings.pkcs11).map(Some),
+        ProviderKind::Tpm2 => open_tpm2(&settings.tpm2, dirs).map(Some),
+    }
+}
+
+#[cfg(feature = "pkcs11")]
+fn open_pkcs11(config: &Pkcs11Config) -> Result<Arc<dyn KeyProvider>> {
+    Ok(Arc::new(super::pkcs11::Pkcs11Provider::open(config)?))
+}
+
+#[cfg(not(feature = "pkcs11"))]
+fn open_pkcs11(_config: &Pkcs11Config) -> Result<Arc<dyn KeyProvider>> {
+    Err(not_compiled(ProviderKind::Pkcs11, "pkcs11").into())
+}
+
+#[cfg(feature = "tpm2")]
+fn open_tpm2(config: &Tpm2Config, dirs: &DataDirs) -> Result<Arc<dyn KeyProvider>> {
+    Ok(Arc::new(super::tpm2::Tpm2Provider::open(config, &dirs.keys_dir()?)?))
+}
+
+#[cfg(not(feature = "tpm2"))]
+fn open_tpm2(_config: &Tpm2Config, _dirs: &DataDirs) -> Result<Arc<dyn KeyProvider>> {
+    Err(not_compiled(ProviderKind::Tpm2, "tpm2").into())
+}
+
+#[cfg(any(not(feature = "pkcs11"), not(feature = "tpm2")))]
+fn not_compiled(provider: ProviderKind, feature: &str) -> KeyProviderError {
+    KeyProviderError::new(
+        provider,
+        KeyProblem::NotCompiled,
+        "support is not compiled into this build",
+        format!("rebuild with `--features {}`, or set keys.provider = \"file\"", feature),
+    )
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorCheck {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+    pub problem: Option<KeyProblem>,
+    pub hint: Option<String>,
+}
+
+/// `keys doctor`: can the configured provider open, sign and protect a
+/// data key
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorReport {
+    pub provider: ProviderKind,
+    pub checks: Vec<DoctorCheck>,
+}
+
+impl DoctorReport {
+    pub fn healthy(&self) -> bool {
+        self.checks.iter().all(|c| c.ok)
+    }
+
+    /// Run the checks against `open`'s result (`None` is the file default)
+    pub fn run(kind: ProviderKind, opened: Result<Option<Arc<dyn KeyProvider>>>) -> Self {
+        let mut report = Self { provider: kind, checks: Vec::new() };
+        let provider = match opened {
+            Ok(Some(provider)) => provider,
+            Ok(None) => Arc::new(FileKeyProvider::generate()),
+            Err(e) => {
+                let (problem, hint) = match e.downcast_ref::<KeyProviderError>() {
+                    Some(e) => (Some(e.problem), Some(e.hint.clone())),
+                    None => (None, None),
+                };
+                report.push("open", Err(format!("{:#}", e)), problem, hint);
+                return report;
+            }
+        };
+        let opened = match kind {
+            ProviderKind::File => "keys are stored in the data directory".to_string(),
+            _ => format!("public key {}", hex::encode(provider.public_key().to_bytes())),
+        };
+        report.push("open", Ok(opened), None, None);
+
+        let probe = b"quantra keys doctor";
+        let signed = provider.sign(probe).and_then(|signature| {
+            provider.public_key().verify(probe, &signature)?;
+            Ok("Ed25519 signature verifies".to_string())
+        });
+        report.push_result("sign", signed);
+
+        let data_key = [0x5a; 32];
+        let wrapped = provider
+            .wrap_data_key(&data_key)
+            .and_then(|wrapped| provider.unwrap_data_key(&wrapped))
+            .and_then(|unwrapped| match unwrapped == data_key {
+                true => Ok("audit data key round-trips".to_string()),
+                false => anyhow::bail!("unwrapped key differs from the original"),
+            });
+        report.push_result("data key", wrapped);
+        report
+    }
+
+    fn push_result(&mut self, name: &'static str, result: Result<String>) {
+        match result {
+            Ok(detail) => self.push(name, Ok(detail), None, None),
+            Err(e) => {
+                let known = e.downcast_ref::<KeyProviderError>().map(|e| (e.problem, e.hint.clone()));
+                self.push(name, Err(format!("{:#}", e)), known.as_ref().map(|k| k.0), known.map(|k| k.1));
+            }
+        }
+    }
+
+    fn push(&mut self, name: &'static str, result: Result<String, String>, problem: Option<KeyProblem>, hint: Option<String>) {
+        let (ok, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+        self.checks.push(DoctorCheck { name, ok, detail, problem, hint });
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "🔑 Key provider: {}", self.provider)?;
+        for check in &self.checks {
+            writeln!(f, "  {} {:<9} {}", if check.ok { "✅" } else { "❌" }, check.name, check.detail)?;
+            if let Some(hint) = &check.hint {
+                writeln!(f, "     💡 {}", hint)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A token-style provider for tests: the key never leaves it, data keys
+/// are wrapped with a key of its own, and it can be told to reject the PIN
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+    use aes_gcm::aead::{Aead, KeyInit};
+    use aes_gcm::{Aes256Gcm, Nonce};
+
+    pub struct SoftHsm {
+        key: SigningKey,
+        wrap_key: [u8; 32],
+    }
+
+    impl SoftHsm {
+        pub fn new(seed: u8) -> Self {
+            Self { key: SigningKey::from_bytes(&[seed; 32]), wrap_key: [seed.wrapping_add(1); 32] }
+        }
+
+        /// Log in like a token would
+        pub fn login(seed: u8, pin: &str) -> Result<Self> {
+            if pin != "1234" {
+                return Err(KeyProviderError::new(
+                    ProviderKind::Pkcs11,
+                    KeyProblem::WrongPin,
+                    "CKR_PIN_INCORRECT",
+                    "check keys.pkcs11.pin / $QUANTRA_PKCS11_PIN",
+                )
+                .into());
+            }
+            Ok(Self::new(seed))
+        }
+    }
+
+    impl KeyProvider for SoftHsm {
+        fn name(&self) -> &'static str {
+            "pkcs11"
+        }
+
+        fn public_key(&self) -> VerifyingKey {
+            self.key.verifying_key()
+        }
+
+        fn sign(&self, message: &[u8]) -> Result<Signature> {
+            Ok(self.key.sign(message))
+        }
+
+        fn wrap_data_key(&self, key: &[u8; 32]) -> Result<Vec<u8>> {
+            let nonce = rand::random::<[u8; 12]>();
+            let cipher = Aes256Gcm::new_from_slice(&self.wrap_key)?;
+            let mut wrapped = nonce.to_vec();
+            wrapped.extend(cipher.encrypt(Nonce::from_slice(&nonce), key.as_slice()).map_err(|_| anyhow::anyhow!("wrap failed"))?);
+            Ok(wrapped)
+        }
+
+        fn unwrap_data_key(&self, wrapped: &[u8]) -> Result<[u8; 32]> {
+            anyhow::ensure!(wrapped.len() > 12, "wrapped key too short");
+            let cipher = Aes256Gcm::new_from_slice(&self.wrap_key)?;
+            let key = cipher
+                .decrypt(Nonce::from_slice(&wrapped[..12]), &wrapped[12..])
+                .map_err(|_| anyhow::anyhow!("CKR_ENCRYPTED_DATA_INVALID"))?;
+            key.as_slice().try_into().map_err(|_| anyhow::anyhow!("unwrapped key is not 32 bytes"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::SoftHsm;
+    use super::*;
+
+    #[test]
+    fn test_doctor_reports_wrong_pin_with_hint() {
+        let report = DoctorReport::run(ProviderKind::Pkcs11, SoftHsm::login(1, "0000").map(|p| Some(Arc::new(p) as _)));
+        assert!(!report.healthy());
+        assert_eq!(report.checks[0].problem, Some(KeyProblem::WrongPin));
+        assert!(report.to_string().contains("💡 check keys.pkcs11.pin"), "{}", report);
+
+        let report = DoctorReport::run(ProviderKind::Pkcs11, SoftHsm::login(1, "1234").map(|p| Some(Arc::new(p) as _)));
+        assert!(report.healthy(), "{}", report);
+        assert_eq!(report.checks.len(), 3);
+
+        // The default file provider needs no setup
+        assert!(DoctorReport::run(ProviderKind::File, Ok(None)).healthy());
+    }
+
+    #[test]
+    fn test_file_provider_wraps_data_keys() {
+        let provider = FileKeyProvider::new(SigningKey::from_bytes(&[4; 32]));
+        let data_key = [0x5a; 32];
+        let wrapped = provider.wrap_data_key(&data_key).unwrap();
+        assert!(!wrapped.windows(32).any(|w| w == data_key), "data key stored in the clear");
+        assert_eq!(provider.unwrap_data_key(&wrapped).unwrap(), data_key);
+
+        // The same identity unwraps it later; another one can't
+        assert_eq!(FileKeyProvider::new(SigningKey::from_bytes(&[4; 32])).unwrap_data_key(&wrapped).unwrap(), data_key);
+        assert!(FileKeyProvider::new(SigningKey::from_bytes(&[5; 32])).unwrap_data_key(&wrapped).is_err());
+        assert!(provider.unwrap_data_key(&data_key).is_err());
+    }
+
+    #[cfg(not(feature = "pkcs11"))]
+    #[test]
+    fn test_provider_missing_from_build_is_actionable() {
+        let settings = KeysSettings { provider: ProviderKind::Pkcs11, ..Default::default() };
+        let dirs = DataDirs::resolve(Some(std::path::Path::new("/nonexistent")), None, crate::storage::RuntimeMode::Ephemeral).unwrap();
+        let err = open(&settings, &dirs).err().expect("pkcs11 is not compiled in");
+        let err = err.downcast_ref::<KeyProviderError>().unwrap();
+        assert_eq!(err.problem, KeyProblem::NotCompiled);
+        assert!(err.hint.contains("--features pkcs11"));
+        assert!(open(&KeysSettings::default(), &dirs).unwrap().is_none());
+    }
+}
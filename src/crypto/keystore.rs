@@ -1,32 +1,333 @@
+//! Keystore
+//! Public keys by fingerprint, with the secret keys generated alongside
+//! them under `secret/<fingerprint>`. On Unix its files are owner-only
+//! (0600). A keystore given a passphrase keeps its
+//! entries encrypted (AES-256-GCM under a PBKDF2-SHA256 key) and refuses
+//! every read and write until `unlock`
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Key, Nonce,
+};
 use anyhow::{Context, Result};
-use sled::Db;
-use std::path::Path;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use pgp::composed::{Deserializable, SignedPublicKey};
+use pgp::types::KeyTrait;
+use pgp::ArmorOptions;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+use super::KeystoreError;
+use crate::data_dirs::restrict_to_owner;
+use crate::migrations::{self, StoreSchema};
+use crate::storage::{KvStore, RuntimeMode};
+
+pub const SCHEMA: StoreSchema = StoreSchema {
+    name: "keystore",
+    tree: None,
+    version: 1,
+    migrations: &[],
+    index: None,
+};
+
+/// Holds the passphrase salt and verifier; skipped when listing keys
+const LOCK_KEY: &[u8] = b"__lock";
+/// Secret keys are stored under this prefix and also skipped when listing
+const SECRET_PREFIX: &str = "secret/";
+const PBKDF2_ITERATIONS: u32 = 100_000;
+/// Encrypted under the passphrase key to check a passphrase
+const VERIFIER: &[u8] = b"quantra-keystore-v1";
+const NONCE_LEN: usize = 12;
+
+/// Why a keystore refused an operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockError {
+    /// Protected and not unlocked
+    Locked,
+    WrongPassphrase,
+    /// `set_passphrase` on a store that already has one
+    AlreadyProtected,
+}
+
+impl std::fmt::Display for LockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Locked => write!(f, "keystore is locked"),
+            Self::WrongPassphrase => write!(f, "wrong passphrase"),
+            Self::AlreadyProtected => write!(f, "keystore already has a passphrase"),
+        }
+    }
+}
+
+impl std::error::Error for LockError {}
+
+/// A stored public key, as `keys list` shows it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StoredKey {
+    pub fingerprint: String,
+    /// Primary user ID; `None` for a key that doesn't parse as OpenPGP
+    pub user_id: Option<String>,
+    pub created: Option<DateTime<Utc>>,
+    /// Generated here, rather than imported from a peer
+    pub has_secret: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LockRecord {
+    salt: String,
+    iterations: u32,
+    /// Hex nonce || ciphertext of `VERIFIER`
+    verifier: String,
+}
 
 pub struct KeyStore {
-    db: Db,
+    db: Box<dyn KvStore>,
+    /// Database directory kept owner-only; `None` in memory
+    dir: Option<PathBuf>,
+    /// Entry key of a protected store while unlocked
+    key: Mutex<Option<[u8; 32]>>,
 }
 
 impl KeyStore {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let db = sled::open(path).context("Failed to open keystore database")?;
-        Ok(Self { db })
+        Self::with_mode(path, RuntimeMode::Persistent)
+    }
+
+    /// Open the keystore at `path`, or in memory when ephemeral. A protected
+    /// keystore opens locked
+    pub fn with_mode<P: AsRef<Path>>(path: P, mode: RuntimeMode) -> Result<Self> {
+        let db = migrations::open_store(mode, path.as_ref(), &SCHEMA)
+            .context("Failed to open keystore database")?;
+        let dir = (!mode.is_ephemeral()).then(|| path.as_ref().to_path_buf());
+        let store = Self { db, dir, key: Mutex::new(None) };
+        store.restrict()?;
+        Ok(store)
+    }
+
+    /// Whether the keystore has a passphrase
+    pub fn is_protected(&self) -> Result<bool> {
+        Ok(self.db.get(LOCK_KEY)?.is_some())
+    }
+
+    pub fn is_locked(&self) -> Result<bool> {
+        Ok(self.is_protected()? && self.key.lock().is_none())
+    }
+
+    /// Protect the keystore with `passphrase`, encrypting its existing
+    /// entries; it stays unlocked
+    pub fn set_passphrase(&self, passphrase: &str) -> Result<()> {
+        if self.is_protected()? {
+            return Err(LockError::AlreadyProtected.into());
+        }
+        let salt: [u8; 16] = rand::random();
+        let key = derive_key(passphrase, &salt, PBKDF2_ITERATIONS);
+        for (fingerprint, plaintext) in self.db.entries()? {
+            self.db.insert(&fingerprint, &encrypt(&key, &plaintext)?)?;
+        }
+        let record = LockRecord {
+            salt: hex::encode(salt),
+            iterations: PBKDF2_ITERATIONS,
+            verifier: hex::encode(encrypt(&key, VERIFIER)?),
+        };
+        self.db.insert(LOCK_KEY, &serde_json::to_vec(&record)?)?;
+        self.flush()?;
+        *self.key.lock() = Some(key);
+        Ok(())
+    }
+
+    /// Unlock with `passphrase`; a no-op for an unprotected keystore
+    pub fn unlock(&self, passphrase: &str) -> Result<()> {
+        let Some(bytes) = self.db.get(LOCK_KEY)? else { return Ok(()) };
+        let record: LockRecord = serde_json::from_slice(&bytes).context("Corrupt keystore lock record")?;
+        let salt = hex::decode(&record.salt).context("Corrupt keystore salt")?;
+        let key = derive_key(passphrase, &salt, record.iterations);
+        let verifier = hex::decode(&record.verifier).context("Corrupt keystore verifier")?;
+        match decrypt(&key, &verifier) {
+            Ok(plaintext) if plaintext == VERIFIER => {
+                *self.key.lock() = Some(key);
+                Ok(())
+            }
+            _ => Err(LockError::WrongPassphrase.into()),
+        }
+    }
+
+    /// Forget the unlocked key
+    pub fn lock(&self) {
+        *self.key.lock() = None;
     }
 
     pub async fn store_keypair(&self, fingerprint: &str, public_key: &str) -> Result<()> {
+        let value = self.seal(public_key.as_bytes())?;
         self.db
-            .insert(fingerprint.as_bytes(), public_key.as_bytes())
+            .insert(fingerprint.as_bytes(), &value)
             .context("Failed to store keypair")?;
 
-        self.db.flush_async().await?;
+        self.flush()?;
         Ok(())
     }
 
     pub async fn get_keypair(&self, fingerprint: &str) -> Result<Option<String>> {
-        if let Some(data) = self.db.get(fingerprint.as_bytes())? {
-            let key = String::from_utf8(data.to_vec())?;
-            Ok(Some(key))
+        self.get(fingerprint.as_bytes())
+    }
+
+    /// Store the armored secret key of `fingerprint`
+    pub async fn store_secret_key(&self, fingerprint: &str, secret_key: &str) -> Result<()> {
+        let value = self.seal(secret_key.as_bytes())?;
+        self.db
+            .insert(secret_entry(fingerprint).as_bytes(), &value)
+            .context("Failed to store secret key")?;
+
+        self.flush()?;
+        Ok(())
+    }
+
+    /// Armored secret key of `fingerprint`; `None` for an imported public key
+    pub async fn get_secret_key(&self, fingerprint: &str) -> Result<Option<String>> {
+        self.get(secret_entry(fingerprint).as_bytes())
+    }
+
+    /// Add a peer's armored public key, returning its fingerprint. A key
+    /// already stored is left as it is
+    pub async fn import_public_key(&self, armored: &str) -> Result<String> {
+        let (key, _) = SignedPublicKey::from_string(armored).context("Not an OpenPGP public key")?;
+        key.verify().context("Public key's self-signatures don't verify")?;
+        let fingerprint = hex::encode_upper(key.fingerprint());
+        if self.get(fingerprint.as_bytes())?.is_none() {
+            self.store_keypair(&fingerprint, &key.to_armored_string(ArmorOptions::default())?).await?;
+        }
+        Ok(fingerprint)
+    }
+
+    /// Armored secret key of `fingerprint`. Refused unless `confirmed`, as
+    /// the export leaves the keystore's protection behind
+    pub async fn export_secret_key(&self, fingerprint: &str, confirmed: bool) -> Result<String> {
+        if !confirmed {
+            return Err(KeystoreError::SecretExportUnconfirmed(fingerprint.to_string()).into());
+        }
+        self.get_secret_key(fingerprint)
+            .await?
+            .with_context(|| format!("No secret key for {} (imported keys have none)", fingerprint))
+    }
+
+    /// Delete `fingerprint` and its secret key; fails if it isn't stored
+    pub async fn delete_key(&self, fingerprint: &str) -> Result<()> {
+        if self.get(fingerprint.as_bytes())?.is_none() {
+            anyhow::bail!("No key with fingerprint {}", fingerprint);
+        }
+        self.db.remove(secret_entry(fingerprint).as_bytes())?;
+        self.db.remove(fingerprint.as_bytes())?;
+        self.flush()
+    }
+
+    /// Stored public keys with their user IDs and creation times, by fingerprint
+    pub fn list_keys(&self) -> Result<Vec<StoredKey>> {
+        let mut keys = Vec::new();
+        for fingerprint in self.fingerprints()? {
+            let Some(public_key) = self.get(fingerprint.as_bytes())? else { continue };
+            let parsed = SignedPublicKey::from_string(&public_key).ok().map(|(key, _)| key);
+            keys.push(StoredKey {
+                user_id: parsed.as_ref().and_then(|key| key.details.users.first()).map(|user| user.id.id().to_string()),
+                created: parsed.as_ref().map(|key| *key.primary_key.created_at()),
+                has_secret: self.db.get(secret_entry(&fingerprint).as_bytes())?.is_some(),
+                fingerprint,
+            });
+        }
+        Ok(keys)
+    }
+
+    /// Fingerprints of the stored keys, sorted
+    pub fn fingerprints(&self) -> Result<Vec<String>> {
+        self.entry_key()?;
+        self.db
+            .entries()?
+            .into_iter()
+            .filter(|(key, _)| key != LOCK_KEY && !key.starts_with(SECRET_PREFIX.as_bytes()))
+            .map(|(key, _)| String::from_utf8(key).context("Corrupt keystore fingerprint"))
+            .collect()
+    }
+
+    /// `Some(key)` for an unlocked protected store, `None` for an
+    /// unprotected one
+    fn entry_key(&self) -> Result<Option<[u8; 32]>> {
+        let key = *self.key.lock();
+        match key {
+            Some(key) => Ok(Some(key)),
+            None if self.is_protected()? => Err(LockError::Locked.into()),
+            None => Ok(None),
+        }
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.db.flush()?;
+        self.restrict()
+    }
+
+    /// Make every file under the database directory owner-only; the store
+    /// may have created new ones since the last write
+    fn restrict(&self) -> Result<()> {
+        match &self.dir {
+            Some(dir) if dir.is_dir() => restrict_tree(dir).context("Failed to restrict keystore permissions"),
+            _ => Ok(()),
+        }
+    }
+
+    fn get(&self, entry: &[u8]) -> Result<Option<String>> {
+        let key = self.entry_key()?;
+        let Some(data) = self.db.get(entry)? else { return Ok(None) };
+        let data = match key {
+            Some(key) => decrypt(&key, &data)?,
+            None => data,
+        };
+        Ok(Some(String::from_utf8(data)?))
+    }
+
+    fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        match self.entry_key()? {
+            Some(key) => encrypt(&key, plaintext),
+            None => Ok(plaintext.to_vec()),
+        }
+    }
+}
+
+fn restrict_tree(dir: &Path) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            restrict_tree(&entry.path())?;
         } else {
-            Ok(None)
+            restrict_to_owner(&entry.path())?;
         }
     }
+    Ok(())
+}
+
+fn secret_entry(fingerprint: &str) -> String {
+    format!("{}{}", SECRET_PREFIX, fingerprint)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    let iterations = NonZeroU32::new(iterations).unwrap_or(NonZeroU32::MIN);
+    ring::pbkdf2::derive(ring::pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, passphrase.as_bytes(), &mut key);
+    key
+}
+
+/// nonce || ciphertext
+fn encrypt(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|e| anyhow::anyhow!("Keystore encryption failed: {:?}", e))?;
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+fn decrypt(key: &[u8; 32], sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        anyhow::bail!("Keystore entry too short");
+    }
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+        .decrypt(Nonce::from_slice(&sealed[..NONCE_LEN]), &sealed[NONCE_LEN..])
+        .map_err(|_| anyhow::anyhow!("Keystore entry does not decrypt under this passphrase"))
 }
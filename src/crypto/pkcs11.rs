@@ -0,0 +1,167 @@
+//! PKCS#11 Key Provider
+//! Signs with a non-exportable Ed25519 key on an HSM or smartcard and wraps
+//! data keys with an AES key on the same token
+
+use anyhow::{Context, Result};
+use cryptoki::context::{CInitializeArgs, Pkcs11};
+use cryptoki::error::{Error as CkError, RvError};
+use cryptoki::mechanism::Mechanism;
+use cryptoki::object::{Attribute, AttributeType, ObjectClass, ObjectHandle};
+use cryptoki::session::{Session, UserType};
+use cryptoki::slot::Slot;
+use cryptoki::types::AuthPin;
+use ed25519_dalek::{Signature, VerifyingKey};
+use std::sync::Mutex;
+
+use super::key_provider::{KeyProblem, KeyProvider, KeyProviderError, Pkcs11Config, ProviderKind};
+
+pub struct Pkcs11Provider {
+    session: Mutex<Session>,
+    signing_key: ObjectHandle,
+    wrap_key: ObjectHandle,
+    public_key: VerifyingKey,
+    // Keeps the module loaded for as long as the session lives
+    _context: Pkcs11,
+}
+
+impl Pkcs11Provider {
+    /// Load the module, log in and find the configured keys
+    pub fn open(config: &Pkcs11Config) -> Result<Self> {
+        let module = config.module.as_ref().ok_or_else(|| {
+            problem(KeyProblem::ModuleMissing, "no module configured", "set keys.pkcs11.module to your vendor's PKCS#11 library")
+        })?;
+        let context = Pkcs11::new(module).map_err(|e| {
+            problem(
+                KeyProblem::ModuleMissing,
+                format!("cannot load {}: {}", module.display(), e),
+                "check the path and that the library matches this architecture",
+            )
+        })?;
+        context.initialize(CInitializeArgs::OsThreads).map_err(device)?;
+
+        let slot = pick_slot(&context, config.slot)?;
+        let session = context.open_rw_session(slot).map_err(device)?;
+        let pin = config.resolve_pin().ok_or_else(|| {
+            problem(
+                KeyProblem::WrongPin,
+                "no PIN configured",
+                format!("export {} or set keys.pkcs11.pin", config.pin_env),
+            )
+        })?;
+        match session.login(UserType::User, Some(&AuthPin::new(pin))) {
+            Ok(()) | Err(CkError::Pkcs11(RvError::UserAlreadyLoggedIn, _)) => {}
+            Err(CkError::Pkcs11(RvError::PinIncorrect | RvError::PinInvalid | RvError::PinLenRange, _)) => {
+                return Err(problem(
+                    KeyProblem::WrongPin,
+                    "the token rejected the PIN",
+                    format!("check keys.pkcs11.pin / ${}; repeated failures lock the token", config.pin_env),
+                )
+                .into())
+            }
+            Err(CkError::Pkcs11(RvError::PinLocked, _)) => {
+                return Err(problem(KeyProblem::WrongPin, "the PIN is locked", "unlock the token with the SO PIN").into())
+            }
+            Err(e) => return Err(device(e).into()),
+        }
+
+        let signing_key = find(&session, ObjectClass::PRIVATE_KEY, &config.key_label)?;
+        let public = find(&session, ObjectClass::PUBLIC_KEY, &config.key_label)?;
+        let wrap_key = find(&session, ObjectClass::SECRET_KEY, &config.wrap_key_label)?;
+        let public_key = read_ed25519_public(&session, public)?;
+
+        tracing::info!("🔑 PKCS#11 key '{}' on slot {}", config.key_label, slot.id());
+        Ok(Self { session: Mutex::new(session), signing_key, wrap_key, public_key, _context: context })
+    }
+}
+
+impl KeyProvider for Pkcs11Provider {
+    fn name(&self) -> &'static str {
+        "pkcs11"
+    }
+
+    fn public_key(&self) -> VerifyingKey {
+        self.public_key
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Signature> {
+        let session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        let signature = session.sign(&Mechanism::Eddsa, self.signing_key, message).map_err(device)?;
+        Signature::from_slice(&signature).context("Token returned a malformed Ed25519 signature")
+    }
+
+    fn wrap_data_key(&self, key: &[u8; 32]) -> Result<Vec<u8>> {
+        let session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(session.encrypt(&Mechanism::AesKeyWrap, self.wrap_key, key).map_err(device)?)
+    }
+
+    fn unwrap_data_key(&self, wrapped: &[u8]) -> Result<[u8; 32]> {
+        let session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        let key = session.decrypt(&Mechanism::AesKeyWrap, self.wrap_key, wrapped).map_err(device)?;
+        key.as_slice()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Unwrapped data key is {} bytes, expected 32", key.len()))
+    }
+}
+
+fn pick_slot(context: &Pkcs11, wanted: Option<u64>) -> Result<Slot> {
+    let slots = context.get_slots_with_token().map_err(device)?;
+    let slot = match wanted {
+        Some(id) => slots.into_iter().find(|s| s.id() == id),
+        None => slots.into_iter().next(),
+    };
+    slot.ok_or_else(|| {
+        let which = wanted.map(|id| format!("slot {}", id)).unwrap_or_else(|| "any slot".to_string());
+        problem(
+            KeyProblem::TokenMissing,
+            format!("no token in {}", which),
+            "insert the token, or list slots with `pkcs11-tool --list-slots` and set keys.pkcs11.slot",
+        )
+        .into()
+    })
+}
+
+fn find(session: &Session, class: ObjectClass, label: &str) -> Result<ObjectHandle> {
+    let template = [Attribute::Class(class), Attribute::Label(label.as_bytes().to_vec())];
+    let found = session.find_objects(&template).map_err(device)?;
+    found.into_iter().next().ok_or_else(|| {
+        problem(
+            KeyProblem::KeyMissing,
+            format!("no {} labelled '{}'", class, label),
+            "generate it on the token (e.g. `pkcs11-tool --keypairgen --key-type EC:edwards25519 --label ...`) or fix the label in [keys.pkcs11]",
+        )
+        .into()
+    })
+}
+
+/// CKA_EC_POINT holds the 32-byte key, usually wrapped in a DER OCTET STRING
+fn read_ed25519_public(session: &Session, handle: ObjectHandle) -> Result<VerifyingKey> {
+    let attributes = session.get_attributes(handle, &[AttributeType::EcPoint]).map_err(device)?;
+    let point = attributes
+        .into_iter()
+        .find_map(|a| match a {
+            Attribute::EcPoint(point) => Some(point),
+            _ => None,
+        })
+        .context("Public key has no CKA_EC_POINT")?;
+    let raw = match point.as_slice() {
+        [0x04, 32, rest @ ..] if rest.len() == 32 => rest,
+        raw => raw,
+    };
+    let bytes: [u8; 32] = raw.try_into().map_err(|_| {
+        problem(KeyProblem::KeyMissing, "identity key is not Ed25519", "create an EC:edwards25519 key pair")
+    })?;
+    VerifyingKey::from_bytes(&bytes).context("Token public key is not a valid Ed25519 point")
+}
+
+fn problem(kind: KeyProblem, detail: impl Into<String>, hint: impl Into<String>) -> KeyProviderError {
+    KeyProviderError::new(ProviderKind::Pkcs11, kind, detail, hint)
+}
+
+fn device(e: CkError) -> KeyProviderError {
+    match e {
+        CkError::Pkcs11(RvError::TokenNotPresent | RvError::DeviceRemoved, _) => {
+            problem(KeyProblem::TokenMissing, e.to_string(), "reinsert the token and restart")
+        }
+        e => problem(KeyProblem::Device, e.to_string(), "run `quantraband keys doctor` and check the token vendor's logs"),
+    }
+}
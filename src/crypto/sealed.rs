@@ -0,0 +1,81 @@
+//! Sealed Boxes
+//! Encrypt to a node's Ed25519 identity key: ephemeral X25519 agreement
+//! with the key's Montgomery form, HKDF-SHA256, then AES-256-GCM
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::{Context, Result};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// HKDF info prefix; the ephemeral and recipient keys follow it
+const SEAL_INFO: &[u8] = b"quantra-sealed-box-v1\0";
+const NONCE_LEN: usize = 12;
+/// Bytes a sealed box adds to its plaintext
+pub const OVERHEAD: usize = 32 + NONCE_LEN + 16;
+
+fn box_key(shared: &[u8; 32], ephemeral: &PublicKey, recipient: &PublicKey) -> Result<[u8; 32]> {
+    let info = [SEAL_INFO, ephemeral.as_bytes(), recipient.as_bytes()].concat();
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, shared)
+        .expand(&info, &mut key)
+        .map_err(|_| anyhow::anyhow!("HKDF expand failed"))?;
+    Ok(key)
+}
+
+/// Encrypt `plaintext` so only the holder of `recipient`'s signing key can
+/// read it. Layout: ephemeral public key (32) || nonce (12) || ciphertext
+pub fn seal(recipient: &VerifyingKey, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let recipient = PublicKey::from(recipient.to_montgomery().to_bytes());
+    let ephemeral_secret = StaticSecret::random_from_rng(rand::rngs::OsRng);
+    let ephemeral = PublicKey::from(&ephemeral_secret);
+    let shared = ephemeral_secret.diffie_hellman(&recipient);
+    let key = box_key(shared.as_bytes(), &ephemeral, &recipient)?;
+
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|e| anyhow::anyhow!("Sealing failed: {:?}", e))?;
+    Ok([ephemeral.as_bytes().as_slice(), &nonce, &ciphertext].concat())
+}
+
+/// Open a box sealed to `recipient`'s verifying key
+pub fn open(recipient: &SigningKey, sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < 32 + NONCE_LEN {
+        anyhow::bail!("Sealed box too short");
+    }
+    let ephemeral_bytes: [u8; 32] = sealed[..32].try_into().context("Malformed sealed box")?;
+    let ephemeral = PublicKey::from(ephemeral_bytes);
+    let secret = StaticSecret::from(recipient.to_scalar_bytes());
+    let shared = secret.diffie_hellman(&ephemeral);
+    let key = box_key(shared.as_bytes(), &ephemeral, &PublicKey::from(&secret))?;
+
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+        .decrypt(Nonce::from_slice(&sealed[32..32 + NONCE_LEN]), &sealed[32 + NONCE_LEN..])
+        .map_err(|_| anyhow::anyhow!("Sealed box is not addressed to this key or was tampered with"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_round_trip() {
+        let alice = SigningKey::from_bytes(&[1u8; 32]);
+        let mallory = SigningKey::from_bytes(&[2u8; 32]);
+
+        let sealed = seal(&alice.verifying_key(), b"epoch key").unwrap();
+        assert_eq!(open(&alice, &sealed).unwrap(), b"epoch key");
+        assert!(open(&mallory, &sealed).is_err());
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(open(&alice, &tampered).is_err());
+        // Fresh ephemeral key each time
+        assert_ne!(seal(&alice.verifying_key(), b"epoch key").unwrap()[..32], sealed[..32]);
+    }
+}
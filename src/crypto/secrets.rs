@@ -0,0 +1,493 @@
+//! Sealed Secrets
+//! API keys, passwords and webhook tokens kept out of the config file, in
+//! one file sealed with AES-256-GCM under a data key that the key provider
+//! wraps (the file provider wraps it under a key derived from the node
+//! identity key). A config string
+//! `secret://<name>` is replaced at load time by `QUANTRA_SECRET_<NAME>`
+//! when that is set, else by the stored secret
+
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::key_provider::KeyProvider;
+use crate::settings::Settings;
+
+/// Prefix of a config value naming a secret
+pub const SECRET_SCHEME: &str = "secret://";
+/// Authenticated with every sealed file, so ciphertext from elsewhere doesn't open
+const SEALED_AAD: &[u8] = b"quantra-secrets-v1";
+const NONCE_LEN: usize = 12;
+const MAX_NAME_LEN: usize = 128;
+/// Config keys (or `_<key>` suffixes) whose plaintext values `config show` masks
+const SENSITIVE_KEYS: &[&str] = &["password", "passphrase", "pin", "api_key", "token", "secret", "url"];
+const REDACTED: &str = "<redacted>";
+/// `provider` of the file provider, which stored data keys unwrapped
+/// before it wrapped them
+const FILE_PROVIDER: &str = "file";
+
+/// Data key and secrets by name
+type Contents = ([u8; 32], BTreeMap<String, String>);
+
+/// Variable that overrides secret `name`, e.g. `QUANTRA_SECRET_MARKET_DATA_API_KEY`
+/// for `market_data.api_key`
+pub fn env_var(name: &str) -> String {
+    format!("QUANTRA_SECRET_{}", name.to_uppercase().replace(['.', '-'], "_"))
+}
+
+/// Letters, digits, `.`, `_` and `-`
+pub fn validate_name(name: &str) -> std::result::Result<(), SecretError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    match valid {
+        true => Ok(()),
+        false => Err(SecretError::InvalidName(name.to_string())),
+    }
+}
+
+/// A secret that can't be stored or resolved. Never carries a value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretError {
+    InvalidName(String),
+    NotFound(String),
+    /// `config_key` refers to a secret that is neither set in the
+    /// environment nor stored
+    Unresolved { secret: String, config_key: String },
+    /// The store's data key is wrapped by a provider other than the configured one
+    ProviderMismatch { sealed_by: String, configured: String },
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName(name) => {
+                write!(f, "invalid secret name '{}': use letters, digits, '.', '_' and '-'", name)
+            }
+            Self::NotFound(name) => write!(f, "no secret named '{}' is stored", name),
+            Self::Unresolved { secret, config_key } => write!(
+                f,
+                "{} refers to secret '{}', which is not stored and {} is not set",
+                config_key,
+                secret,
+                env_var(secret)
+            ),
+            Self::ProviderMismatch { sealed_by, configured } => write!(
+                f,
+                "secrets are sealed by the {} key provider but keys.provider is {}",
+                sealed_by, configured
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+#[derive(Serialize, Deserialize)]
+struct SealedFile {
+    /// Provider that wrapped `data_key`
+    provider: String,
+    /// Hex
+    data_key: String,
+    /// Hex of nonce || ciphertext of the JSON name → value map
+    sealed: String,
+}
+
+/// The sealed secrets file of one profile
+pub struct SecretStore {
+    path: PathBuf,
+    provider: Arc<dyn KeyProvider>,
+}
+
+impl SecretStore {
+    /// The store at `path`, created on the first `set`; its data key is
+    /// wrapped by `provider`
+    pub fn open(path: &Path, provider: Arc<dyn KeyProvider>) -> Self {
+        Self { path: path.to_path_buf(), provider }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stored secret names, sorted
+    pub fn names(&self) -> Result<Vec<String>> {
+        Ok(self.read()?.map(|(_, secrets)| secrets.into_keys().collect()).unwrap_or_default())
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.read()?.and_then(|(_, mut secrets)| secrets.remove(name)))
+    }
+
+    /// Store `value` under `name`, replacing any previous value
+    pub fn set(&self, name: &str, value: &str) -> Result<()> {
+        validate_name(name)?;
+        let (key, mut secrets) = self.read()?.unwrap_or_else(|| (rand::random(), BTreeMap::new()));
+        secrets.insert(name.to_string(), value.to_string());
+        self.write(&key, &secrets)
+    }
+
+    pub fn remove(&self, name: &str) -> Result<()> {
+        let Some((key, mut secrets)) = self.read()? else {
+            return Err(SecretError::NotFound(name.to_string()).into());
+        };
+        if secrets.remove(name).is_none() {
+            return Err(SecretError::NotFound(name.to_string()).into());
+        }
+        self.write(&key, &secrets)
+    }
+
+    /// Re-encrypt every secret under a fresh data key, wrapped by the
+    /// configured provider; returns how many were re-encrypted
+    pub fn rotate_key(&self) -> Result<usize> {
+        let Some((_, secrets)) = self.read()? else {
+            return Ok(0);
+        };
+        self.write(&rand::random(), &secrets)?;
+        Ok(secrets.len())
+    }
+
+    /// Data key and secrets, or `None` before the first `set`
+    fn read(&self) -> Result<Option<Contents>> {
+        let bytes = match std::fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read secrets from {}", self.path.display())),
+        };
+        let file: SealedFile = serde_json::from_slice(&bytes).context("Corrupt secrets file")?;
+        let wrapped = hex::decode(&file.data_key).context("Corrupt secrets data key")?;
+
+        // A key the file provider stored as is gets wrapped right away
+        let unwrapped = file.provider == FILE_PROVIDER && wrapped.len() == 32;
+        let key: [u8; 32] = match file.provider.as_str() {
+            _ if unwrapped => wrapped.as_slice().try_into().expect("length checked"),
+            sealed_by if sealed_by == self.provider.name() => self
+                .provider
+                .unwrap_data_key(&wrapped)
+                .with_context(|| format!("Failed to unwrap the secrets key with the {} key provider", sealed_by))?,
+            sealed_by => {
+                return Err(SecretError::ProviderMismatch {
+                    sealed_by: sealed_by.to_string(),
+                    configured: self.provider.name().to_string(),
+                }
+                .into())
+            }
+        };
+
+        let sealed = hex::decode(&file.sealed).context("Corrupt secrets file")?;
+        anyhow::ensure!(sealed.len() > NONCE_LEN, "Corrupt secrets file");
+        let plaintext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+            .decrypt(
+                Nonce::from_slice(&sealed[..NONCE_LEN]),
+                Payload { msg: &sealed[NONCE_LEN..], aad: SEALED_AAD },
+            )
+            .map_err(|_| anyhow::anyhow!("Secrets file doesn't decrypt with its data key; it was modified or replaced"))?;
+        let secrets = serde_json::from_slice(&plaintext).context("Corrupt secrets file")?;
+        if unwrapped {
+            self.write(&key, &secrets)?;
+            tracing::info!("🔒 Wrapped the secrets key with the {} key provider", self.provider.name());
+        }
+        Ok(Some((key, secrets)))
+    }
+
+    /// Replace the file in one rename, so a crash leaves the old or the new
+    /// store; it is owner-only from the start
+    fn write(&self, key: &[u8; 32], secrets: &BTreeMap<String, String>) -> Result<()> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &serde_json::to_vec(secrets)?, aad: SEALED_AAD })
+            .map_err(|e| anyhow::anyhow!("Sealing secrets failed: {:?}", e))?;
+        let data_key = self
+            .provider
+            .wrap_data_key(key)
+            .with_context(|| format!("Failed to wrap the secrets key with the {} key provider", self.provider.name()))?;
+        let file = SealedFile {
+            provider: self.provider.name().to_string(),
+            data_key: hex::encode(data_key),
+            sealed: hex::encode([nonce.as_slice(), &ciphertext].concat()),
+        };
+        crate::data_dirs::atomic_write_private(&self.path, &serde_json::to_vec_pretty(&file)?)
+            .with_context(|| format!("Failed to write secrets to {}", self.path.display()))
+    }
+}
+
+/// A config value naming a secret
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretReference {
+    /// Dotted config key, e.g. `intel.taxii.password`
+    pub config_key: String,
+    pub secret: String,
+    /// JSON pointer into the serialized settings
+    pointer: String,
+}
+
+/// Every `secret://` value in `settings`
+pub fn references(settings: &Settings) -> Result<Vec<SecretReference>> {
+    let mut found = Vec::new();
+    collect_references(&serde_json::to_value(settings)?, String::new(), String::new(), &mut found);
+    Ok(found)
+}
+
+fn collect_references(value: &Value, key: String, pointer: String, found: &mut Vec<SecretReference>) {
+    let join = |key: &str, child: &str| match key.is_empty() {
+        true => child.to_string(),
+        false => format!("{}.{}", key, child),
+    };
+    match value {
+        Value::String(s) => {
+            if let Some(secret) = s.strip_prefix(SECRET_SCHEME) {
+                found.push(SecretReference { config_key: key, secret: secret.to_string(), pointer });
+            }
+        }
+        Value::Object(map) => {
+            for (child, value) in map {
+                let escaped = child.replace('~', "~0").replace('/', "~1");
+                collect_references(value, join(&key, child), format!("{}/{}", pointer, escaped), found);
+            }
+        }
+        Value::Array(items) => {
+            for (i, value) in items.iter().enumerate() {
+                collect_references(value, format!("{}[{}]", key, i), format!("{}/{}", pointer, i), found);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `settings` with every `secret://` reference replaced by its
+/// `QUANTRA_SECRET_<NAME>` variable or, failing that, the secret in
+/// `store`, which is only read if some reference needs it
+pub fn resolve(settings: &Settings, store: Option<&SecretStore>) -> Result<Settings> {
+    let found = references(settings)?;
+    if found.is_empty() {
+        return Ok(settings.clone());
+    }
+
+    let mut value = serde_json::to_value(settings)?;
+    let mut stored: Option<BTreeMap<String, String>> = None;
+    for reference in &found {
+        validate_name(&reference.secret).with_context(|| format!("Invalid secret reference at {}", reference.config_key))?;
+        let secret = match std::env::var(env_var(&reference.secret)) {
+            Ok(secret) => Some(secret),
+            Err(_) => {
+                if stored.is_none() {
+                    stored = Some(match store {
+                        Some(store) => store.read()?.map(|(_, secrets)| secrets).unwrap_or_default(),
+                        None => BTreeMap::new(),
+                    });
+                }
+                stored.as_ref().and_then(|secrets| secrets.get(&reference.secret).cloned())
+            }
+        };
+        let Some(secret) = secret else {
+            return Err(SecretError::Unresolved { secret: reference.secret.clone(), config_key: reference.config_key.clone() }.into());
+        };
+        if let Some(slot) = value.pointer_mut(&reference.pointer) {
+            *slot = Value::String(secret);
+        }
+    }
+
+    // The parse error would quote the value, so it's left out
+    serde_json::from_value(value).map_err(|_| {
+        let mut keys: Vec<&str> = found.iter().map(|r| r.config_key.as_str()).collect();
+        keys.sort();
+        anyhow::anyhow!("Settings don't parse with the secrets for {} filled in", keys.join(", "))
+    })
+}
+
+/// `settings` for display: `secret://` references as written, plaintext
+/// values of sensitive keys (passwords, PINs, sink URLs, ...) masked and
+/// unset options left out
+pub fn redacted(settings: &Settings) -> Result<Value> {
+    let mut value = serde_json::to_value(settings)?;
+    redact(&mut value);
+    Ok(value)
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, value| !value.is_null());
+            for (key, value) in map.iter_mut() {
+                let sensitive = SENSITIVE_KEYS
+                    .iter()
+                    .any(|s| key == s || key.strip_suffix(s).is_some_and(|rest| rest.ends_with('_')));
+                match value {
+                    Value::String(s) if sensitive && !s.starts_with(SECRET_SCHEME) => *s = REDACTED.to_string(),
+                    _ => redact(value),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::key_provider::testing::SoftHsm;
+    use crate::crypto::key_provider::FileKeyProvider;
+    use ed25519_dalek::SigningKey;
+    use tempfile::TempDir;
+
+    fn hsm(seed: u8) -> Arc<dyn KeyProvider> {
+        Arc::new(SoftHsm::new(seed))
+    }
+
+    fn file_provider(seed: u8) -> Arc<dyn KeyProvider> {
+        Arc::new(FileKeyProvider::new(SigningKey::from_bytes(&[seed; 32])))
+    }
+
+    fn sealed_file(store: &SecretStore) -> SealedFile {
+        serde_json::from_slice(&std::fs::read(store.path()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_set_get_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("secrets.sealed");
+        let store = SecretStore::open(&path, hsm(3));
+        assert!(store.names().unwrap().is_empty());
+        store.set("market_data.api_key", "mk-live-123").unwrap();
+        store.set("taxii.password", "hunter2").unwrap();
+        assert!(matches!(
+            store.set("bad name", "x").unwrap_err().downcast_ref::<SecretError>(),
+            Some(SecretError::InvalidName(_))
+        ));
+
+        let reopened = SecretStore::open(&path, hsm(3));
+        assert_eq!(reopened.get("market_data.api_key").unwrap().as_deref(), Some("mk-live-123"));
+        assert_eq!(reopened.names().unwrap(), vec!["market_data.api_key", "taxii.password"]);
+        let on_disk = std::fs::read_to_string(&path).unwrap();
+        assert!(!on_disk.contains("mk-live-123") && !on_disk.contains("market_data"));
+
+        reopened.remove("taxii.password").unwrap();
+        assert_eq!(reopened.get("taxii.password").unwrap(), None);
+        assert!(matches!(
+            reopened.remove("taxii.password").unwrap_err().downcast_ref::<SecretError>(),
+            Some(SecretError::NotFound(_))
+        ));
+        // Another token can't unwrap the data key
+        assert!(SecretStore::open(&path, hsm(4)).get("market_data.api_key").is_err());
+    }
+
+    #[test]
+    fn test_references_resolve() {
+        let dir = TempDir::new().unwrap();
+        let store = SecretStore::open(&dir.path().join("secrets.sealed"), file_provider(1));
+        store.set("taxii.password", "hunter2").unwrap();
+        store.set("ops.webhook", "https://hooks.example/T0KEN").unwrap();
+
+        let settings: Settings = toml::from_str(
+            r#"
+            [intel.taxii]
+            password = "secret://taxii.password"
+
+            [[notifications.sinks]]
+            name = "ops"
+            kind = "webhook"
+            url = "secret://ops.webhook"
+            "#,
+        )
+        .unwrap();
+        let found = references(&settings).unwrap();
+        let mut keys: Vec<&str> = found.iter().map(|r| r.config_key.as_str()).collect();
+        keys.sort();
+        assert_eq!(keys, vec!["intel.taxii.password", "notifications.sinks[0].url"]);
+
+        let resolved = resolve(&settings, Some(&store)).unwrap();
+        assert_eq!(resolved.intel.taxii.password.as_deref(), Some("hunter2"));
+        let crate::security::notifications::SinkKind::Webhook { url, .. } = &resolved.notifications.sinks[0].kind else {
+            panic!("expected a webhook sink");
+        };
+        assert_eq!(url, "https://hooks.example/T0KEN");
+
+        store.remove("ops.webhook").unwrap();
+        let err = resolve(&settings, Some(&store)).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SecretError>(),
+            Some(&SecretError::Unresolved {
+                secret: "ops.webhook".to_string(),
+                config_key: "notifications.sinks[0].url".to_string()
+            })
+        );
+        assert!(!format!("{:#}", err).contains("hunter2"));
+    }
+
+    #[test]
+    fn test_environment_takes_precedence() {
+        let dir = TempDir::new().unwrap();
+        let store = SecretStore::open(&dir.path().join("secrets.sealed"), file_provider(1));
+        store.set("env-test.password", "from-store").unwrap();
+        let settings: Settings =
+            toml::from_str("[intel.taxii]\npassword = \"secret://env-test.password\"\n").unwrap();
+        assert_eq!(env_var("env-test.password"), "QUANTRA_SECRET_ENV_TEST_PASSWORD");
+
+        std::env::set_var("QUANTRA_SECRET_ENV_TEST_PASSWORD", "from-env");
+        let resolved = resolve(&settings, Some(&store));
+        // Works without a store at all
+        let storeless = resolve(&settings, None);
+        std::env::remove_var("QUANTRA_SECRET_ENV_TEST_PASSWORD");
+        assert_eq!(resolved.unwrap().intel.taxii.password.as_deref(), Some("from-env"));
+        assert_eq!(storeless.unwrap().intel.taxii.password.as_deref(), Some("from-env"));
+        assert_eq!(resolve(&settings, Some(&store)).unwrap().intel.taxii.password.as_deref(), Some("from-store"));
+    }
+
+    #[test]
+    fn test_file_provider_wraps_the_data_key() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("secrets.sealed");
+        let store = SecretStore::open(&path, file_provider(1));
+        store.set("a", "alpha").unwrap();
+        let wrapped = hex::decode(sealed_file(&store).data_key).unwrap();
+        assert_ne!(wrapped.len(), 32);
+        assert!(SecretStore::open(&path, file_provider(2)).get("a").is_err());
+
+        // A key stored as is, as before wrapping, opens and is wrapped at once
+        let (key, secrets) = store.read().unwrap().unwrap();
+        let legacy = SealedFile { data_key: hex::encode(key), ..sealed_file(&store) };
+        std::fs::write(&path, serde_json::to_vec(&legacy).unwrap()).unwrap();
+        assert_eq!(store.read().unwrap().unwrap().1, secrets);
+        assert_ne!(hex::decode(sealed_file(&store).data_key).unwrap().len(), 32);
+        assert_eq!(store.get("a").unwrap().as_deref(), Some("alpha"));
+    }
+
+    #[test]
+    fn test_key_rotation_reencrypts() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("secrets.sealed");
+        let store = SecretStore::open(&path, file_provider(1));
+        store.set("a", "alpha").unwrap();
+        store.set("b", "bravo").unwrap();
+        let (key, _) = store.read().unwrap().unwrap();
+        let before = SealedFile { data_key: hex::encode(key), ..sealed_file(&store) };
+        assert_eq!(before.provider, "file");
+        std::fs::write(&path, serde_json::to_vec(&before).unwrap()).unwrap();
+
+        // Rotating once a provider is configured moves a key stored as is under it
+        let store = SecretStore::open(&path, hsm(9));
+        assert_eq!(store.rotate_key().unwrap(), 2);
+        let after = sealed_file(&store);
+        assert_eq!(after.provider, "pkcs11");
+        assert_ne!(after.data_key, before.data_key);
+        assert_ne!(after.sealed, before.sealed);
+        assert_eq!(store.get("b").unwrap().as_deref(), Some("bravo"));
+
+        let rotated = store.rotate_key().unwrap();
+        assert_eq!(rotated, 2);
+        assert_ne!(sealed_file(&store).data_key, after.data_key);
+        assert_eq!(store.get("a").unwrap().as_deref(), Some("alpha"));
+
+        let err = SecretStore::open(&path, file_provider(1)).names().unwrap_err();
+        assert!(matches!(err.downcast_ref::<SecretError>(), Some(SecretError::ProviderMismatch { .. })));
+    }
+}
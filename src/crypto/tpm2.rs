@@ -0,0 +1,196 @@
+//! TPM 2.0 Key Provider
+//! Seals the identity seed and audit data keys to the TPM under a PCR
+//! policy, so they only unseal on this machine in a known boot state.
+//! TPMs don't do Ed25519, so the identity key is unsealed into memory to
+//! sign; what the TPM guarantees is where and when it can be recovered
+
+use anyhow::{Context as _, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use tss_esapi::attributes::ObjectAttributesBuilder;
+use tss_esapi::constants::SessionType;
+use tss_esapi::handles::KeyHandle;
+use tss_esapi::interface_types::algorithm::{HashingAlgorithm, PublicAlgorithm};
+use tss_esapi::interface_types::key_bits::RsaKeyBits;
+use tss_esapi::interface_types::resource_handles::Hierarchy;
+use tss_esapi::interface_types::session_handles::PolicySession;
+use tss_esapi::structures::{
+    Digest, KeyedHashScheme, PcrSelectionList, PcrSelectionListBuilder, PcrSlot, Private, Public, PublicBuilder,
+    PublicKeyedHashParameters, RsaExponent, SensitiveData, SymmetricDefinition, SymmetricDefinitionObject,
+};
+use tss_esapi::traits::{Marshall, UnMarshall};
+use tss_esapi::utils::create_restricted_decryption_rsa_public;
+use tss_esapi::{Context, TctiNameConf};
+
+use super::key_provider::{KeyProblem, KeyProvider, KeyProviderError, ProviderKind, Tpm2Config};
+use crate::data_dirs::restrict_to_owner;
+
+const IDENTITY_FILE: &str = "identity.sealed";
+
+pub struct Tpm2Provider {
+    context: Mutex<Context>,
+    pcrs: PcrSelectionList,
+    key: SigningKey,
+}
+
+impl Tpm2Provider {
+    /// Connect to the TPM and unseal the identity from `keys_dir`, sealing
+    /// a new one on first use
+    pub fn open(config: &Tpm2Config, keys_dir: &Path) -> Result<Self> {
+        let tcti = TctiNameConf::from_str(&config.tcti).map_err(|e| {
+            problem(KeyProblem::ModuleMissing, format!("bad TCTI '{}': {}", config.tcti, e), "use e.g. device:/dev/tpmrm0")
+        })?;
+        let context = Context::new(tcti).map_err(|e| {
+            problem(
+                KeyProblem::ModuleMissing,
+                format!("cannot open {}: {}", config.tcti, e),
+                "check the device exists and you're in the `tss` group, or point keys.tpm2.tcti at a running swtpm",
+            )
+        })?;
+        let slots = config
+            .pcrs
+            .iter()
+            .map(|&index| {
+                PcrSlot::try_from(1u32 << index).map_err(|_| {
+                    problem(KeyProblem::PolicyMismatch, format!("PCR {} does not exist", index), "use PCRs 0-23 in keys.tpm2.pcrs")
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let pcrs = PcrSelectionListBuilder::new().with_selection(HashingAlgorithm::Sha256, &slots).build()?;
+
+        let mut provider = Self { context: Mutex::new(context), pcrs, key: SigningKey::from_bytes(&[0; 32]) };
+        let path: PathBuf = keys_dir.join(IDENTITY_FILE);
+        let seed: [u8; 32] = if path.exists() {
+            let blob = std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            provider.unseal(&blob)?
+        } else {
+            let seed = rand::random::<[u8; 32]>();
+            std::fs::write(&path, provider.seal(&seed)?).with_context(|| format!("Failed to write {}", path.display()))?;
+            restrict_to_owner(&path)?;
+            tracing::info!("🔒 Sealed a new node identity to TPM PCRs {:?}", config.pcrs);
+            seed
+        };
+        provider.key = SigningKey::from_bytes(&seed);
+        Ok(provider)
+    }
+
+    fn seal(&self, secret: &[u8; 32]) -> Result<Vec<u8>> {
+        let mut context = self.context.lock().unwrap_or_else(|e| e.into_inner());
+        let primary = primary(&mut context)?;
+        let policy = pcr_policy(&mut context, SessionType::Trial, &self.pcrs)?;
+        let digest = context.policy_get_digest(policy).map_err(device)?;
+        context.flush_context(tss_esapi::handles::SessionHandle::from(policy).into()).map_err(device)?;
+
+        let attributes = ObjectAttributesBuilder::new()
+            .with_fixed_tpm(true)
+            .with_fixed_parent(true)
+            .with_no_da(true)
+            .build()?;
+        let public = PublicBuilder::new()
+            .with_public_algorithm(PublicAlgorithm::KeyedHash)
+            .with_name_hashing_algorithm(HashingAlgorithm::Sha256)
+            .with_auth_policy(digest)
+            .with_object_attributes(attributes)
+            .with_keyed_hash_parameters(PublicKeyedHashParameters::new(KeyedHashScheme::Null))
+            .with_keyed_hash_unique_identifier(Digest::default())
+            .build()?;
+        let sealed = context
+            .execute_with_nullauth_session(|ctx| {
+                ctx.create(primary, public, None, Some(SensitiveData::try_from(secret.to_vec())?), None, None)
+            })
+            .map_err(device)?;
+        context.flush_context(primary.into()).map_err(device)?;
+
+        let public = sealed.out_public.marshall()?;
+        let mut blob = (public.len() as u16).to_be_bytes().to_vec();
+        blob.extend(public);
+        blob.extend(sealed.out_private.value());
+        Ok(blob)
+    }
+
+    fn unseal(&self, blob: &[u8]) -> Result<[u8; 32]> {
+        anyhow::ensure!(blob.len() > 2, "Sealed blob is truncated");
+        let public_len = u16::from_be_bytes([blob[0], blob[1]]) as usize;
+        anyhow::ensure!(blob.len() > 2 + public_len, "Sealed blob is truncated");
+        let public = Public::unmarshall(&blob[2..2 + public_len])?;
+        let private = Private::try_from(blob[2 + public_len..].to_vec())?;
+
+        let mut context = self.context.lock().unwrap_or_else(|e| e.into_inner());
+        let primary = primary(&mut context)?;
+        let object = context
+            .execute_with_nullauth_session(|ctx| ctx.load(primary, private, public))
+            .map_err(device)?;
+        let policy = pcr_policy(&mut context, SessionType::Policy, &self.pcrs)?;
+        let unsealed = context.execute_with_session(Some(policy.into()), |ctx| ctx.unseal(object.into()));
+        context.flush_context(object.into()).map_err(device)?;
+        context.flush_context(primary.into()).map_err(device)?;
+        let secret = unsealed.map_err(|e| {
+            problem(
+                KeyProblem::PolicyMismatch,
+                format!("unseal refused: {}", e),
+                "the PCRs changed since sealing (firmware, bootloader or Secure Boot update?); restore the boot state or remove the sealed file to enrol a new key",
+            )
+        })?;
+        secret.value().try_into().map_err(|_| anyhow::anyhow!("Sealed secret is not 32 bytes"))
+    }
+}
+
+impl KeyProvider for Tpm2Provider {
+    fn name(&self) -> &'static str {
+        "tpm2"
+    }
+
+    fn public_key(&self) -> VerifyingKey {
+        self.key.verifying_key()
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Signature> {
+        Ok(self.key.sign(message))
+    }
+
+    fn wrap_data_key(&self, key: &[u8; 32]) -> Result<Vec<u8>> {
+        self.seal(key)
+    }
+
+    fn unwrap_data_key(&self, wrapped: &[u8]) -> Result<[u8; 32]> {
+        self.unseal(wrapped)
+    }
+
+    fn secret_key(&self) -> Option<SigningKey> {
+        Some(self.key.clone())
+    }
+}
+
+/// The owner hierarchy's standard storage key; deterministic, so it is
+/// recreated rather than persisted
+fn primary(context: &mut Context) -> Result<KeyHandle> {
+    let template = create_restricted_decryption_rsa_public(
+        SymmetricDefinitionObject::AES_128_CFB,
+        RsaKeyBits::Rsa2048,
+        RsaExponent::default(),
+    )?;
+    let primary = context
+        .execute_with_nullauth_session(|ctx| ctx.create_primary(Hierarchy::Owner, template, None, None, None, None))
+        .map_err(device)?;
+    Ok(primary.key_handle)
+}
+
+fn pcr_policy(context: &mut Context, kind: SessionType, pcrs: &PcrSelectionList) -> Result<PolicySession> {
+    let session = context
+        .start_auth_session(None, None, None, kind, SymmetricDefinition::AES_128_CFB, HashingAlgorithm::Sha256)
+        .map_err(device)?
+        .context("TPM returned no session")?;
+    let policy = PolicySession::try_from(session)?;
+    context.policy_pcr(policy, Digest::default(), pcrs.clone()).map_err(device)?;
+    Ok(policy)
+}
+
+fn problem(kind: KeyProblem, detail: impl Into<String>, hint: impl Into<String>) -> KeyProviderError {
+    KeyProviderError::new(ProviderKind::Tpm2, kind, detail, hint)
+}
+
+fn device(e: tss_esapi::Error) -> KeyProviderError {
+    problem(KeyProblem::Device, e.to_string(), "run `tpm2_getcap properties-fixed` to check the TPM responds")
+}
@@ -0,0 +1,505 @@
+//! Data Directories
+//! Resolves the data root once at startup (explicit `--data-dir`, XDG, or
+//! `./data`), optionally namespaced by `--profile`, and hands out typed paths
+
+use anyhow::{Context, Result};
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::storage::RuntimeMode;
+
+/// Directory under the XDG data home
+const APP_DIR: &str = "quantra";
+
+/// Fallback root when neither XDG_DATA_HOME nor HOME is set
+const FALLBACK_ROOT: &str = "./data";
+
+/// Where the keystore lived before data directories, relative to the
+/// working directory
+pub const LEGACY_KEYSTORE_DIR: &str = "./keystore";
+
+/// Per-user data layout for one profile
+#[derive(Debug, Clone)]
+pub struct DataDirs {
+    /// Profile root everything else lives under
+    root: PathBuf,
+    profile: Option<String>,
+    mode: RuntimeMode,
+}
+
+impl DataDirs {
+    /// Resolve from the CLI flags and the process environment
+    pub fn resolve(data_dir: Option<&Path>, profile: Option<&str>, mode: RuntimeMode) -> Result<Self> {
+        Self::resolve_with_env(data_dir, profile, mode, |key| std::env::var_os(key))
+    }
+
+    /// Resolve with an explicit environment lookup
+    pub fn resolve_with_env(
+        data_dir: Option<&Path>,
+        profile: Option<&str>,
+        mode: RuntimeMode,
+        env: impl Fn(&str) -> Option<OsString>,
+    ) -> Result<Self> {
+        let base = match data_dir {
+            Some(dir) => dir.to_path_buf(),
+            None => match (env("XDG_DATA_HOME"), env("HOME")) {
+                (Some(xdg), _) if !xdg.is_empty() => PathBuf::from(xdg).join(APP_DIR),
+                (_, Some(home)) if !home.is_empty() => {
+                    PathBuf::from(home).join(".local/share").join(APP_DIR)
+                }
+                _ => PathBuf::from(FALLBACK_ROOT),
+            },
+        };
+
+        let root = match profile {
+            Some(name) => {
+                validate_profile_name(name)?;
+                base.join("profiles").join(name)
+            }
+            None => base,
+        };
+
+        Ok(Self {
+            root,
+            profile: profile.map(str::to_string),
+            mode,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Active profile name (`None` for the default profile)
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    pub fn mode(&self) -> RuntimeMode {
+        self.mode
+    }
+
+    pub fn keystore_dir(&self) -> Result<PathBuf> {
+        self.dir("keystore")
+    }
+
+    /// Keystore `name` of `[crypto.keystores]` without a configured path
+    pub fn named_keystore_dir(&self, name: &str) -> Result<PathBuf> {
+        self.dir(&format!("keystores/{}", name))
+    }
+
+    pub fn audit_log_path(&self) -> Result<PathBuf> {
+        Ok(self.dir("logs")?.join("audit.log"))
+    }
+
+    /// Append-only journal of honeypot events for `intel export`
+    pub fn intel_journal_path(&self) -> Result<PathBuf> {
+        Ok(self.dir("logs")?.join("intel.jsonl"))
+    }
+
+    /// How far the TAXII collection has received the intel journal
+    pub fn intel_cursor_path(&self) -> Result<PathBuf> {
+        Ok(self.dir("logs")?.join("intel-taxii.json"))
+    }
+
+    pub fn evidence_dir(&self) -> Result<PathBuf> {
+        self.dir("logs/evidence")
+    }
+
+    pub fn esim_store_dir(&self) -> Result<PathBuf> {
+        self.dir("esim")
+    }
+
+    pub fn carrier_db_dir(&self) -> Result<PathBuf> {
+        self.dir("carriers")
+    }
+
+    pub fn carrier_health_dir(&self) -> Result<PathBuf> {
+        self.dir("carrier_health")
+    }
+
+    pub fn peer_registry_path(&self) -> Result<PathBuf> {
+        Ok(self.dir("p2p")?.join("peers.json"))
+    }
+
+    pub fn dht_journal_dir(&self) -> Result<PathBuf> {
+        self.dir("p2p/dht")
+    }
+
+    pub fn telemetry_summary_path(&self) -> Result<PathBuf> {
+        Ok(self.dir("p2p")?.join("telemetry.json"))
+    }
+
+    pub fn replay_registry_dir(&self) -> Result<PathBuf> {
+        self.dir("p2p/replay")
+    }
+
+    pub fn retention_dir(&self) -> Result<PathBuf> {
+        self.dir("p2p/retention")
+    }
+
+    pub fn receipts_dir(&self) -> Result<PathBuf> {
+        self.dir("p2p/receipts")
+    }
+
+    pub fn key_log_dir(&self) -> Result<PathBuf> {
+        self.dir("p2p/key-log")
+    }
+
+    pub fn outbox_path(&self) -> Result<PathBuf> {
+        Ok(self.dir("p2p")?.join("outbox.wal"))
+    }
+
+    pub fn approvals_dir(&self) -> Result<PathBuf> {
+        self.dir("approvals")
+    }
+
+    pub fn alerts_dir(&self) -> Result<PathBuf> {
+        self.dir("alerts")
+    }
+
+    pub fn portfolio_dir(&self) -> Result<PathBuf> {
+        self.dir("portfolio")
+    }
+
+    pub fn watchlist_dir(&self) -> Result<PathBuf> {
+        self.dir("watchlist")
+    }
+
+    pub fn identity_dir(&self) -> Result<PathBuf> {
+        self.dir("identity")
+    }
+
+    /// Sealed secrets referenced from config as `secret://<name>`
+    pub fn secrets_path(&self) -> Result<PathBuf> {
+        Ok(self.dir("keys")?.join("secrets.sealed"))
+    }
+
+    pub fn keys_dir(&self) -> Result<PathBuf> {
+        self.dir("keys")
+    }
+
+    /// libp2p identity of the node, with the file key provider
+    pub fn node_key_path(&self) -> Result<PathBuf> {
+        Ok(self.keys_dir()?.join("node_key"))
+    }
+
+    /// Move a keystore that older versions left at `legacy` into
+    /// `keystore_dir()`, if that holds no keys yet. True if it was moved.
+    /// Does nothing in ephemeral mode, or when `legacy` is missing, empty
+    /// or the same directory. Fails, moving nothing, when both hold keys
+    pub fn adopt_legacy_keystore(&self, legacy: &Path) -> Result<bool> {
+        let target = self.root.join("keystore");
+        let occupied = |dir: &Path| dir.read_dir().is_ok_and(|mut entries| entries.next().is_some());
+        if self.mode.is_ephemeral() || !occupied(legacy) {
+            return Ok(false);
+        }
+        if target.exists() && legacy.canonicalize()? == target.canonicalize()? {
+            return Ok(false);
+        }
+        if occupied(&target) {
+            let keys = crate::crypto::keystore::KeyStore::new(&target)?;
+            if keys.is_protected()? || !keys.fingerprints()?.is_empty() {
+                return Err(LegacyKeystoreConflict { legacy: legacy.to_path_buf(), target }.into());
+            }
+        }
+
+        create_private_dir(&self.root)
+            .with_context(|| format!("Failed to create data directory {}", self.root.display()))?;
+        if target.exists() {
+            std::fs::remove_dir_all(&target)
+                .with_context(|| format!("Failed to replace empty keystore {}", target.display()))?;
+        }
+        // Across filesystems rename fails; copy, then remove the original
+        if std::fs::rename(legacy, &target).is_err() {
+            copy_dir(legacy, &target).with_context(|| format!("Failed to copy {} to {}", legacy.display(), target.display()))?;
+            std::fs::remove_dir_all(legacy).with_context(|| format!("Failed to remove {}", legacy.display()))?;
+        }
+        tracing::warn!("🔑 Moved the keystore from {} to {}", legacy.display(), target.display());
+        Ok(true)
+    }
+
+    /// `root/<relative>`, created owner-only on first use
+    /// Nothing is created in ephemeral mode
+    fn dir(&self, relative: &str) -> Result<PathBuf> {
+        let path = self.root.join(relative);
+        if !self.mode.is_ephemeral() && !path.is_dir() {
+            create_private_dir(&path)
+                .with_context(|| format!("Failed to create data directory {}", path.display()))?;
+        }
+        Ok(path)
+    }
+}
+
+impl fmt::Display for DataDirs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "profile {} at {}",
+            self.profile.as_deref().unwrap_or("default"),
+            self.root.display()
+        )
+    }
+}
+
+/// Keys in both the pre-data-directory keystore and the profile's own
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LegacyKeystoreConflict {
+    pub legacy: PathBuf,
+    pub target: PathBuf,
+}
+
+impl fmt::Display for LegacyKeystoreConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Keys found both in {} (used before data directories) and in {}; \
+             export the keys you need from one with `--data-dir`, import them into the other, \
+             then remove {}",
+            self.legacy.display(),
+            self.target.display(),
+            self.legacy.display()
+        )
+    }
+}
+
+impl std::error::Error for LegacyKeystoreConflict {}
+
+/// Copy `from` into a new owner-only directory `to`, recursively
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    create_private_dir(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &dest)?;
+        } else {
+            std::fs::copy(entry.path(), &dest)?;
+        }
+    }
+    Ok(())
+}
+
+fn validate_profile_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        anyhow::bail!(
+            "Invalid profile name '{}': use 1-64 letters, digits, '-' or '_'",
+            name
+        );
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_private_dir(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    std::fs::DirBuilder::new().recursive(true).mode(0o700).create(path)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(path: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(path)?;
+    warn_no_owner_only(path);
+    Ok(())
+}
+
+/// Make a file readable and writable by its owner only (0600). A no-op with a
+/// warning where Unix permission bits don't exist
+#[cfg(unix)]
+pub fn restrict_to_owner(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+pub fn restrict_to_owner(path: &Path) -> std::io::Result<()> {
+    warn_no_owner_only(path);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn warn_no_owner_only(path: &Path) {
+    tracing::warn!(
+        "⚠️  Owner-only permissions aren't supported on this platform; {} keeps the inherited ACL",
+        path.display()
+    );
+}
+
+/// Replace `path` with `contents` so a crash leaves either the old file or
+/// the new one: written and synced as `<path>.tmp`, renamed over `path`, and
+/// the directory synced so the rename itself is durable
+pub fn atomic_write(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    replace(path, contents, &options)
+}
+
+/// `atomic_write` for keys and secrets: the file is owner-only from the
+/// moment it is created, never readable by others in between
+pub fn atomic_write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    #[cfg(not(unix))]
+    warn_no_owner_only(path);
+    // A leftover from a crash may have other permissions; start afresh
+    match std::fs::remove_file(tmp_path(path)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    replace(path, contents, &options)
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    PathBuf::from(tmp_path)
+}
+
+fn replace(path: &Path, contents: &[u8], options: &std::fs::OpenOptions) -> std::io::Result<()> {
+    use std::io::Write;
+    let tmp_path = tmp_path(path);
+    let mut file = options.open(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&tmp_path, path)?;
+    sync_parent(path)
+}
+
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> std::io::Result<()> {
+    let parent = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    std::fs::File::open(parent)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::store::AlertStore;
+    use crate::alerts::{AlertCondition, AlertRule};
+    use crate::crypto::keystore::KeyStore;
+    use crate::p2p::dht_records::{DhtRecordManager, OwnedRecordKind};
+    use rust_decimal::Decimal;
+    use tempfile::TempDir;
+
+    fn profile(base: &Path, name: &str) -> DataDirs {
+        DataDirs::resolve(Some(base), Some(name), RuntimeMode::Persistent).unwrap()
+    }
+
+    /// Number of keys, alert rules and DHT records visible in a profile
+    async fn visible_state(dirs: &DataDirs) -> (bool, usize, usize) {
+        let keys = KeyStore::new(dirs.keystore_dir().unwrap()).unwrap();
+        let has_key = keys.get_keypair("fp-a").await.unwrap().is_some();
+        let alerts = AlertStore::open(&dirs.alerts_dir().unwrap(), dirs.mode()).unwrap();
+        let records = DhtRecordManager::open(&dirs.dht_journal_dir().unwrap()).unwrap();
+        (has_key, alerts.list().unwrap().len(), records.owned_records().len())
+    }
+
+    #[tokio::test]
+    async fn test_profiles_are_isolated() {
+        let base = TempDir::new().unwrap();
+
+        let a = profile(base.path(), "a");
+        {
+            let keys = KeyStore::new(a.keystore_dir().unwrap()).unwrap();
+            keys.store_keypair("fp-a", "public key").await.unwrap();
+            let alerts = AlertStore::open(&a.alerts_dir().unwrap(), a.mode()).unwrap();
+            alerts
+                .put(&AlertRule::new("AAPL", AlertCondition::PriceAbove, Decimal::from(200), chrono::Duration::zero()))
+                .unwrap();
+            let mut records = DhtRecordManager::open(&a.dht_journal_dir().unwrap()).unwrap();
+            records.track(b"k".to_vec(), b"v".to_vec(), OwnedRecordKind::Record).unwrap();
+        }
+        assert_eq!(visible_state(&a).await, (true, 1, 1));
+
+        let b = profile(base.path(), "b");
+        assert_eq!(visible_state(&b).await, (false, 0, 0));
+
+        assert_eq!(visible_state(&profile(base.path(), "a")).await, (true, 1, 1));
+        assert!(base.path().join("profiles/a/keystore").is_dir());
+        println!("✅ Profile isolation test PASSED!");
+    }
+
+    #[tokio::test]
+    async fn test_legacy_keystore_adopted() {
+        let base = TempDir::new().unwrap();
+        let legacy = base.path().join("keystore");
+        KeyStore::new(&legacy).unwrap().store_keypair("fp-old", "public key").await.unwrap();
+        let dirs = DataDirs::resolve(Some(&base.path().join("data")), None, RuntimeMode::Persistent).unwrap();
+        // An empty keystore at the new location doesn't count as keys
+        drop(KeyStore::new(dirs.keystore_dir().unwrap()).unwrap());
+
+        assert!(dirs.adopt_legacy_keystore(&legacy).unwrap());
+        assert!(!legacy.exists());
+        let keys = KeyStore::new(dirs.keystore_dir().unwrap()).unwrap();
+        assert!(keys.get_keypair("fp-old").await.unwrap().is_some());
+        drop(keys);
+        assert!(!dirs.adopt_legacy_keystore(&legacy).unwrap());
+
+        // Keys in both: nothing is moved
+        KeyStore::new(&legacy).unwrap().store_keypair("fp-other", "public key").await.unwrap();
+        let err = dirs.adopt_legacy_keystore(&legacy).unwrap_err();
+        assert!(err.downcast_ref::<LegacyKeystoreConflict>().is_some());
+        assert!(KeyStore::new(&legacy).unwrap().get_keypair("fp-other").await.unwrap().is_some());
+
+        // The data directory is the working directory: same keystore
+        let here = DataDirs::resolve(Some(base.path()), None, RuntimeMode::Persistent).unwrap();
+        assert!(!here.adopt_legacy_keystore(&legacy).unwrap());
+    }
+
+    #[test]
+    fn test_root_resolution() {
+        let env = |xdg: Option<&str>, home: Option<&str>| {
+            let (xdg, home) = (xdg.map(OsString::from), home.map(OsString::from));
+            move |key: &str| match key {
+                "XDG_DATA_HOME" => xdg.clone(),
+                "HOME" => home.clone(),
+                _ => None,
+            }
+        };
+        let mode = RuntimeMode::Ephemeral;
+
+        let dirs = DataDirs::resolve_with_env(None, None, mode, env(Some("/xdg"), Some("/home/u"))).unwrap();
+        assert_eq!(dirs.root(), Path::new("/xdg/quantra"));
+        let dirs = DataDirs::resolve_with_env(None, Some("lab"), mode, env(None, Some("/home/u"))).unwrap();
+        assert_eq!(dirs.root(), Path::new("/home/u/.local/share/quantra/profiles/lab"));
+        let dirs = DataDirs::resolve_with_env(Some(Path::new("/srv/q")), None, mode, env(Some("/xdg"), None)).unwrap();
+        assert_eq!(dirs.keystore_dir().unwrap(), Path::new("/srv/q/keystore"));
+        assert!(DataDirs::resolve_with_env(None, Some("../etc"), mode, env(None, None)).is_err());
+    }
+
+    #[test]
+    fn test_atomic_write_replaces() {
+        let base = TempDir::new().unwrap();
+        let path = base.path().join("state.json");
+        atomic_write(&path, b"{\"v\":1}").unwrap();
+        atomic_write(&path, b"{\"v\":2}").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"{\"v\":2}");
+        assert!(!base.path().join("state.json.tmp").exists());
+        assert!(atomic_write(&base.path().join("missing/state.json"), b"").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_atomic_write_private_is_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let base = TempDir::new().unwrap();
+        let path = base.path().join("secrets.sealed");
+        // A world-readable leftover from a crash is not reused
+        std::fs::write(base.path().join("secrets.sealed.tmp"), b"stale").unwrap();
+        atomic_write_private(&path, b"sealed").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"sealed");
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,311 @@
+//! Activation Code Formats
+//! Parses GSMA activation codes (`LPA:1$<SM-DP+>$<matching id>[$<confirmation>]`)
+//! and renders them in the forms different devices accept
+
+use anyhow::{Context, Result};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use super::{qrcode_generator, ESimProfile};
+
+const LPA_PREFIX: &str = "LPA:1$";
+
+/// iOS opens the eSIM setup flow for this URL with the code in `carddata`
+const UNIVERSAL_LINK_PREFIX: &str = "https://esimsetup.apple.com/esim_qrcode_provisioning?";
+const UNIVERSAL_LINK_PARAM: &str = "carddata";
+
+const INTENT_PREFIX: &str = "intent:#Intent;";
+const INTENT_ACTION: &str = "android.telephony.euicc.action.START_EUICC_ACTIVATION";
+const INTENT_EXTRA: &str = "S.activation_code";
+
+/// RFC 3986 unreserved characters pass through; everything else is escaped,
+/// including `+` (read as a space by form decoders) and `;` (intent separator)
+const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/// Components of an activation code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivationCode {
+    pub sm_dp_address: String,
+    /// May be empty (default SM-DP+ lookup)
+    pub matching_id: String,
+    pub confirmation_code: Option<String>,
+}
+
+impl ActivationCode {
+    /// Fields are trimmed: `parse` trims its input, so whitespace kept at
+    /// the edge of a field would not survive a round trip
+    pub fn new(sm_dp_address: &str, matching_id: &str, confirmation_code: Option<&str>) -> Result<Self> {
+        let (sm_dp_address, matching_id) = (sm_dp_address.trim(), matching_id.trim());
+        let confirmation_code = confirmation_code.map(str::trim);
+        if sm_dp_address.is_empty() {
+            anyhow::bail!("Activation code needs an SM-DP+ address");
+        }
+        for (name, value) in [
+            ("SM-DP+ address", sm_dp_address),
+            ("matching ID", matching_id),
+            ("confirmation code", confirmation_code.unwrap_or_default()),
+        ] {
+            if value.contains('$') {
+                anyhow::bail!("{} must not contain '$'", name);
+            }
+        }
+        Ok(Self {
+            sm_dp_address: sm_dp_address.to_string(),
+            matching_id: matching_id.to_string(),
+            confirmation_code: confirmation_code.filter(|c| !c.is_empty()).map(str::to_string),
+        })
+    }
+
+    /// Parse a raw `LPA:1$...` string, an iOS universal link or an Android intent URI
+    pub fn parse(input: &str) -> Result<Self> {
+        let input = input.trim();
+        if let Some(query) = input.strip_prefix(UNIVERSAL_LINK_PREFIX) {
+            let encoded = query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix(UNIVERSAL_LINK_PARAM)?.strip_prefix('='))
+                .context("Universal link has no carddata parameter")?;
+            return Self::parse_raw(&decode(encoded)?);
+        }
+        if let Some(rest) = input.strip_prefix(INTENT_PREFIX) {
+            let body = rest.strip_suffix(";end").context("Intent URI is missing ';end'")?;
+            let encoded = body
+                .split(';')
+                .find_map(|part| part.strip_prefix(INTENT_EXTRA)?.strip_prefix('='))
+                .context("Intent URI has no activation code extra")?;
+            return Self::parse_raw(&decode(encoded)?);
+        }
+        Self::parse_raw(input)
+    }
+
+    fn parse_raw(raw: &str) -> Result<Self> {
+        let body = raw
+            .strip_prefix(LPA_PREFIX)
+            .with_context(|| format!("Activation code must start with '{}'", LPA_PREFIX))?;
+        let parts: Vec<&str> = body.split('$').collect();
+        match parts.as_slice() {
+            [address] => Self::new(address, "", None),
+            [address, matching_id] => Self::new(address, matching_id, None),
+            [address, matching_id, confirmation] => Self::new(address, matching_id, Some(confirmation)),
+            _ => anyhow::bail!("Activation code has {} fields, expected at most 3", parts.len()),
+        }
+    }
+
+    /// The profile's code, with its separately stored confirmation code filled in
+    pub fn from_profile(profile: &ESimProfile) -> Result<Self> {
+        let mut code = Self::parse(&profile.activation_code)
+            .with_context(|| format!("Profile {} has an invalid activation code", profile.iccid))?;
+        if code.confirmation_code.is_none() {
+            code.confirmation_code = profile.confirmation_code.clone().filter(|c| !c.is_empty());
+        }
+        Ok(code)
+    }
+
+    pub fn universal_link(&self) -> String {
+        format!("{}{}={}", UNIVERSAL_LINK_PREFIX, UNIVERSAL_LINK_PARAM, encode(&self.to_string()))
+    }
+
+    pub fn android_intent_uri(&self) -> String {
+        format!(
+            "{}action={};{}={};end",
+            INTENT_PREFIX,
+            INTENT_ACTION,
+            INTENT_EXTRA,
+            encode(&self.to_string())
+        )
+    }
+}
+
+impl fmt::Display for ActivationCode {
+    /// Raw `LPA:1$...` form
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}${}", LPA_PREFIX, self.sm_dp_address, self.matching_id)?;
+        if let Some(confirmation) = &self.confirmation_code {
+            write!(f, "${}", confirmation)?;
+        }
+        Ok(())
+    }
+}
+
+fn encode(value: &str) -> String {
+    utf8_percent_encode(value, COMPONENT).to_string()
+}
+
+fn decode(value: &str) -> Result<String> {
+    Ok(percent_decode_str(value)
+        .decode_utf8()
+        .context("Activation code is not valid UTF-8 after decoding")?
+        .into_owned())
+}
+
+/// Output representation for `format_activation`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActivationFormat {
+    RawLpa,
+    QrPng,
+    QrSvg,
+    UniversalLink,
+    AndroidIntentUri,
+}
+
+impl ActivationFormat {
+    /// File extension when written to disk
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::QrPng => "png",
+            Self::QrSvg => "svg",
+            _ => "txt",
+        }
+    }
+}
+
+impl fmt::Display for ActivationFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::RawLpa => "lpa",
+            Self::QrPng => "qr-png",
+            Self::QrSvg => "qr-svg",
+            Self::UniversalLink => "universal-link",
+            Self::AndroidIntentUri => "android-intent",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ActivationFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "lpa" | "raw" => Ok(Self::RawLpa),
+            "qr-png" | "png" => Ok(Self::QrPng),
+            "qr-svg" | "svg" => Ok(Self::QrSvg),
+            "universal-link" | "ios" => Ok(Self::UniversalLink),
+            "android-intent" | "android" => Ok(Self::AndroidIntentUri),
+            _ => anyhow::bail!(
+                "Unknown activation format '{}' (use lpa, qr-png, qr-svg, universal-link or android-intent)",
+                s
+            ),
+        }
+    }
+}
+
+/// An activation code rendered in one format
+#[derive(Debug, Clone)]
+pub struct FormattedActivation {
+    pub format: ActivationFormat,
+    pub data: Vec<u8>,
+}
+
+impl FormattedActivation {
+    /// Text content (everything but PNG)
+    pub fn text(&self) -> Option<&str> {
+        match self.format {
+            ActivationFormat::QrPng => None,
+            _ => std::str::from_utf8(&self.data).ok(),
+        }
+    }
+}
+
+/// Render a profile's activation code; QR codes encode the raw LPA string
+pub fn format_activation(profile: &ESimProfile, format: ActivationFormat) -> Result<FormattedActivation> {
+    let code = ActivationCode::from_profile(profile)?;
+    let data = match format {
+        ActivationFormat::RawLpa => code.to_string().into_bytes(),
+        ActivationFormat::QrPng => qrcode_generator::generate_qr_code(&code.to_string())
+            .context("Failed to generate QR code")?,
+        ActivationFormat::QrSvg => qrcode_generator::generate_qr_code_svg(&code.to_string())
+            .context("Failed to generate QR code")?
+            .into_bytes(),
+        ActivationFormat::UniversalLink => code.universal_link().into_bytes(),
+        ActivationFormat::AndroidIntentUri => code.android_intent_uri().into_bytes(),
+    };
+    Ok(FormattedActivation { format, data })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(matching_id: &str, confirmation_code: Option<&str>) -> ESimProfile {
+        ESimProfile {
+            iccid: "8901000000000000001".to_string(),
+            activation_code: format!("LPA:1$sm-dp.example.com${}", matching_id),
+            sm_dp_address: "sm-dp.example.com".to_string(),
+            matching_id: Some(matching_id.to_string()),
+            confirmation_code: confirmation_code.map(str::to_string),
+            carrier_name: "Test".to_string(),
+            plan_type: "Test".to_string(),
+            state: Default::default(),
+            plan_expires_at: None,
+        }
+    }
+
+    #[test]
+    fn test_each_format_structure() {
+        let p = profile("ABC-123", Some("4711"));
+
+        let raw = format_activation(&p, ActivationFormat::RawLpa).unwrap();
+        assert_eq!(raw.text(), Some("LPA:1$sm-dp.example.com$ABC-123$4711"));
+
+        let link = format_activation(&p, ActivationFormat::UniversalLink).unwrap();
+        assert_eq!(
+            link.text(),
+            Some("https://esimsetup.apple.com/esim_qrcode_provisioning?carddata=LPA%3A1%24sm-dp.example.com%24ABC-123%244711")
+        );
+
+        let intent = format_activation(&p, ActivationFormat::AndroidIntentUri).unwrap();
+        let intent = intent.text().unwrap();
+        assert!(intent.starts_with("intent:#Intent;action=android.telephony.euicc.action.START_EUICC_ACTIVATION;"));
+        assert!(intent.ends_with(";S.activation_code=LPA%3A1%24sm-dp.example.com%24ABC-123%244711;end"));
+
+        let png = format_activation(&p, ActivationFormat::QrPng).unwrap();
+        assert!(png.data.starts_with(b"\x89PNG"));
+        assert!(png.text().is_none());
+
+        let svg = format_activation(&p, ActivationFormat::QrSvg).unwrap();
+        assert!(svg.text().unwrap().contains("<svg"));
+
+        // Without a confirmation code there is no trailing field
+        let plain = format_activation(&profile("ABC-123", None), ActivationFormat::RawLpa).unwrap();
+        assert_eq!(plain.text(), Some("LPA:1$sm-dp.example.com$ABC-123"));
+        println!("✅ Activation format structure test PASSED!");
+    }
+
+    #[test]
+    fn test_special_characters_are_encoded() {
+        let p = profile("A+B/C&D=E;F#G %H", Some("12&3"));
+        let link = format_activation(&p, ActivationFormat::UniversalLink).unwrap();
+        let link = link.text().unwrap();
+        let query = link.split_once('?').unwrap().1;
+        assert_eq!(query.matches('=').count(), 1, "{}", link);
+        assert!(query.contains("A%2BB%2FC%26D%3DE%3BF%23G%20%25H%2412%263"), "{}", link);
+
+        let intent = format_activation(&p, ActivationFormat::AndroidIntentUri).unwrap();
+        let intent = intent.text().unwrap();
+        // Only the URI's own separators remain unescaped
+        assert_eq!(intent.matches(';').count(), 3, "{}", intent);
+        assert_eq!(intent.matches('#').count(), 1, "{}", intent);
+    }
+
+    #[test]
+    fn test_link_formats_round_trip() {
+        for (matching_id, confirmation) in [("ABC-123", None), ("A+B/C&D=E;F#G %H", Some("12&3")), ("", None)] {
+            let p = profile(matching_id, confirmation);
+            let original = ActivationCode::from_profile(&p).unwrap();
+            for format in [ActivationFormat::RawLpa, ActivationFormat::UniversalLink, ActivationFormat::AndroidIntentUri] {
+                let rendered = format_activation(&p, format).unwrap();
+                let parsed = ActivationCode::parse(rendered.text().unwrap()).unwrap();
+                assert_eq!(parsed, original, "{}", format);
+            }
+        }
+
+        assert!(ActivationCode::parse("LPA:2$x$y").is_err());
+        assert!(ActivationCode::parse("LPA:1$$id").is_err());
+        assert!(ActivationCode::parse("LPA:1$a$b$c$d").is_err());
+        assert!(ActivationCode::parse("https://esimsetup.apple.com/esim_qrcode_provisioning?foo=1").is_err());
+        assert!("qr-svg".parse::<ActivationFormat>().is_ok());
+        assert!("bmp".parse::<ActivationFormat>().is_err());
+    }
+}
@@ -0,0 +1,168 @@
+//! Carrier Database Updates
+//! Curated carrier changes signed by a maintainer Ed25519 key, so nodes can
+//! take new SM-DP+ endpoints from the network instead of waiting for releases
+
+use anyhow::{Context, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::carriers::CarrierInfo;
+use crate::p2p::wire;
+
+/// Domain separator so update signatures can't be replayed as other messages
+/// v2 signs canonical CBOR; v1 (JSON) signatures are still verified
+const SIGNING_CONTEXT: &[u8] = b"quantra-carrier-db-update-v2\0";
+const LEGACY_SIGNING_CONTEXT: &[u8] = b"quantra-carrier-db-update-v1\0";
+
+/// One versioned batch of carrier changes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CarrierDbUpdate {
+    /// Must be exactly one above the last applied version
+    pub version: u32,
+    #[serde(default)]
+    pub added: Vec<(String, CarrierInfo)>,
+    #[serde(default)]
+    pub removed: Vec<String>,
+    #[serde(default)]
+    pub updated: Vec<(String, CarrierInfo)>,
+    /// Hex Ed25519 signature over everything above
+    #[serde(default)]
+    pub signature: String,
+}
+
+impl CarrierDbUpdate {
+    pub fn new(version: u32) -> Self {
+        Self {
+            version,
+            added: Vec::new(),
+            removed: Vec::new(),
+            updated: Vec::new(),
+            signature: String::new(),
+        }
+    }
+
+    fn signing_bytes(&self) -> Result<Vec<u8>> {
+        wire::signing_bytes(SIGNING_CONTEXT, &(self.version, &self.added, &self.removed, &self.updated))
+    }
+
+    fn legacy_signing_bytes(&self) -> Result<Vec<u8>> {
+        let body = serde_json::to_vec(&(self.version, &self.added, &self.removed, &self.updated))?;
+        Ok([LEGACY_SIGNING_CONTEXT, body.as_slice()].concat())
+    }
+
+    pub fn sign(mut self, key: &SigningKey) -> Result<Self> {
+        self.signature = hex::encode(key.sign(&self.signing_bytes()?).to_bytes());
+        Ok(self)
+    }
+
+    pub fn verify(&self, key: &VerifyingKey) -> Result<()> {
+        let bytes: [u8; 64] = hex::decode(&self.signature)
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .context("Malformed update signature")?;
+        wire::verify_signature(key, &Signature::from_bytes(&bytes), &self.signing_bytes()?, || self.legacy_signing_bytes())
+            .context("Update signature does not match the maintainer key")
+    }
+}
+
+/// Parse the hex maintainer public key from config
+pub fn parse_maintainer_key(hex_key: &str) -> Result<VerifyingKey> {
+    let bytes: [u8; 32] = hex::decode(hex_key.trim())
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .context("Maintainer key must be 32 bytes of hex")?;
+    VerifyingKey::from_bytes(&bytes).context("Invalid Ed25519 maintainer key")
+}
+
+/// Parse a hex maintainer secret key (signing side)
+pub fn parse_signing_key(hex_key: &str) -> Result<SigningKey> {
+    let bytes: [u8; 32] = hex::decode(hex_key.trim())
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .context("Signing key must be 32 bytes of hex")?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// Why an update was not applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateRejection {
+    BadSignature,
+    /// Version at or below the applied one (replay or rollback)
+    Stale { current: u32, offered: u32 },
+    /// Versions in between are missing; fetch them first
+    Gap { current: u32, offered: u32 },
+}
+
+impl UpdateRejection {
+    /// Stable code for scripts and the CLI error envelope
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::BadSignature => "BAD_SIGNATURE",
+            Self::Stale { .. } => "STALE_UPDATE",
+            Self::Gap { .. } => "UPDATE_GAP",
+        }
+    }
+}
+
+impl fmt::Display for UpdateRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadSignature => write!(f, "bad maintainer signature"),
+            Self::Stale { current, offered } => {
+                write!(f, "version {} is not newer than applied version {}", offered, current)
+            }
+            Self::Gap { current, offered } => {
+                write!(f, "version {} skips ahead of applied version {}", offered, current)
+            }
+        }
+    }
+}
+
+impl std::error::Error for UpdateRejection {}
+
+/// Rejected update counts
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct UpdateRejections {
+    pub bad_signature: u64,
+    pub stale_version: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let mut update = CarrierDbUpdate::new(1);
+        update.removed.push("sprint".to_string());
+        let update = update.sign(&key).unwrap();
+        assert!(update.verify(&key.verifying_key()).is_ok());
+
+        let mut tampered = update.clone();
+        tampered.removed.push("att".to_string());
+        assert!(tampered.verify(&key.verifying_key()).is_err());
+
+        let other = SigningKey::from_bytes(&[8u8; 32]);
+        assert!(update.verify(&other.verifying_key()).is_err());
+        assert!(parse_maintainer_key(&hex::encode(key.verifying_key().to_bytes())).is_ok());
+    }
+
+    #[test]
+    fn test_signature_survives_reserialization() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let mut update = CarrierDbUpdate::new(4);
+        update.removed.push("sprint".to_string());
+        let update = update.sign(&key).unwrap();
+        for format in wire::WireFormat::ALL {
+            let decoded: CarrierDbUpdate = format.decode(&format.encode(&update).unwrap()).unwrap();
+            assert!(decoded.verify(&key.verifying_key()).is_ok(), "{}", format);
+        }
+
+        // Updates signed before v2 still verify
+        let mut legacy = update.clone();
+        legacy.signature = hex::encode(key.sign(&update.legacy_signing_bytes().unwrap()).to_bytes());
+        assert!(legacy.verify(&key.verifying_key()).is_ok());
+    }
+}
@@ -1,8 +1,29 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use ed25519_dalek::VerifyingKey;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use super::carrier_updates::{CarrierDbUpdate, UpdateRejection, UpdateRejections};
+use crate::migrations::{self, StoreSchema};
+use crate::search::{Query, SearchHit, SearchStore, Searchable, TopHits};
+use crate::storage::{KvStore, RuntimeMode};
+
+const CARRIERS_TREE: &str = "carrier_db";
+const UPDATE_PREFIX: &str = "update/";
+const OVERRIDE_PREFIX: &str = "override/";
+
+pub const SCHEMA: StoreSchema = StoreSchema {
+    name: "carriers",
+    tree: Some(CARRIERS_TREE),
+    version: 1,
+    migrations: &[],
+    index: None,
+};
 
 /// Carrier information and SM-DP+ server details
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CarrierInfo {
     pub name: String,
     pub country: String,
@@ -13,20 +34,165 @@ pub struct CarrierInfo {
 }
 
 /// Global carrier database
+/// Layers, lowest first: built-ins, signed distributed updates, local overrides
 /// NOTE: SM-DP+ addresses are examples - use actual carrier endpoints in production
 pub struct CarrierDatabase {
+    /// Effective view of all layers
     carriers: HashMap<String, CarrierInfo>,
+    builtin: HashMap<String, CarrierInfo>,
+    /// Latest distributed entry per carrier (`None` = removed)
+    distributed: HashMap<String, Option<CarrierInfo>>,
+    overrides: HashMap<String, CarrierInfo>,
+    /// Applied updates by version, served to peers catching up
+    applied: BTreeMap<u32, CarrierDbUpdate>,
+    rejections: UpdateRejections,
+    /// Persistence for updates and overrides (in-memory only when unset)
+    store: Option<Box<dyn KvStore>>,
 }
 
 impl CarrierDatabase {
     pub fn new() -> Self {
         let mut db = Self {
             carriers: HashMap::new(),
+            builtin: HashMap::new(),
+            distributed: HashMap::new(),
+            overrides: HashMap::new(),
+            applied: BTreeMap::new(),
+            rejections: UpdateRejections::default(),
+            store: None,
         };
         db.populate_carriers();
+        db.rebuild();
         db
     }
 
+    /// Built-ins plus the updates and overrides persisted at `path`
+    pub fn open(path: &Path, mode: RuntimeMode) -> Result<Self> {
+        let store = migrations::open_store(mode, path, &SCHEMA)
+            .with_context(|| format!("Failed to open carrier database at {}", path.display()))?;
+        let mut db = Self::new();
+        for (key, value) in store.entries()? {
+            let key = String::from_utf8_lossy(&key);
+            if key.starts_with(UPDATE_PREFIX) {
+                let update: CarrierDbUpdate = serde_json::from_slice(&value).context("Corrupt carrier update")?;
+                db.merge_update(&update);
+                db.applied.insert(update.version, update);
+            } else if let Some(id) = key.strip_prefix(OVERRIDE_PREFIX) {
+                let info = serde_json::from_slice(&value).context("Corrupt carrier override")?;
+                db.overrides.insert(id.to_string(), info);
+            }
+        }
+        db.store = Some(store);
+        db.rebuild();
+        Ok(db)
+    }
+
+    /// Highest applied update version (0 = built-ins only)
+    pub fn version(&self) -> u32 {
+        self.applied.keys().next_back().copied().unwrap_or(0)
+    }
+
+    /// Verify and apply the next signed update
+    /// Fails with an `UpdateRejection` for bad signatures, replays or gaps
+    pub fn apply_update(&mut self, update: CarrierDbUpdate, maintainer_key: &VerifyingKey) -> Result<()> {
+        self.check_update(&update, maintainer_key)?;
+        let current = self.version();
+        if update.version != current + 1 {
+            return Err(UpdateRejection::Gap { current, offered: update.version }.into());
+        }
+
+        if let Some(store) = &self.store {
+            let key = format!("{}{:010}", UPDATE_PREFIX, update.version);
+            store.insert(key.as_bytes(), &serde_json::to_vec(&update)?)?;
+            store.flush()?;
+        }
+        self.merge_update(&update);
+        tracing::info!(
+            "📡 Applied carrier update v{} (+{} ~{} -{})",
+            update.version,
+            update.added.len(),
+            update.updated.len(),
+            update.removed.len()
+        );
+        self.applied.insert(update.version, update);
+        self.rebuild();
+        Ok(())
+    }
+
+    /// Signature and replay checks of `apply_update`, counted the same way,
+    /// for an update that is not applied yet
+    pub fn check_update(&mut self, update: &CarrierDbUpdate, maintainer_key: &VerifyingKey) -> Result<()> {
+        let current = self.version();
+        if update.verify(maintainer_key).is_err() {
+            self.rejections.bad_signature += 1;
+            tracing::warn!("📡 Rejected carrier update v{}: bad signature", update.version);
+            return Err(UpdateRejection::BadSignature.into());
+        }
+        if update.version <= current {
+            self.rejections.stale_version += 1;
+            tracing::warn!("📡 Rejected carrier update v{}: already at v{}", update.version, current);
+            return Err(UpdateRejection::Stale { current, offered: update.version }.into());
+        }
+        Ok(())
+    }
+
+    fn merge_update(&mut self, update: &CarrierDbUpdate) {
+        for (id, info) in update.added.iter().chain(&update.updated) {
+            self.distributed.insert(id.clone(), Some(info.clone()));
+        }
+        for id in &update.removed {
+            self.distributed.insert(id.clone(), None);
+        }
+    }
+
+    /// Applied updates newer than `version`, oldest first
+    pub fn updates_since(&self, version: u32) -> Vec<CarrierDbUpdate> {
+        self.applied
+            .range(version.saturating_add(1)..)
+            .map(|(_, update)| update.clone())
+            .collect()
+    }
+
+    pub fn rejections(&self) -> &UpdateRejections {
+        &self.rejections
+    }
+
+    /// Local override that wins over built-ins and distributed updates
+    pub fn set_override(&mut self, id: &str, info: CarrierInfo) -> Result<()> {
+        if let Some(store) = &self.store {
+            store.insert(format!("{}{}", OVERRIDE_PREFIX, id).as_bytes(), &serde_json::to_vec(&info)?)?;
+            store.flush()?;
+        }
+        self.overrides.insert(id.to_string(), info);
+        self.rebuild();
+        Ok(())
+    }
+
+    /// Returns false if there was no override for `id`
+    pub fn remove_override(&mut self, id: &str) -> Result<bool> {
+        if let Some(store) = &self.store {
+            store.remove(format!("{}{}", OVERRIDE_PREFIX, id).as_bytes())?;
+            store.flush()?;
+        }
+        let existed = self.overrides.remove(id).is_some();
+        self.rebuild();
+        Ok(existed)
+    }
+
+    fn rebuild(&mut self) {
+        let mut carriers = self.builtin.clone();
+        for (id, entry) in &self.distributed {
+            match entry {
+                Some(info) => carriers.insert(id.clone(), info.clone()),
+                None => carriers.remove(id),
+            };
+        }
+        for (id, info) in &self.overrides {
+            carriers.insert(id.clone(), info.clone());
+        }
+        self.carriers = carriers;
+    }
+
     fn populate_carriers(&mut self) {
         // === UNITED STATES ===
         self.add_carrier("verizon", CarrierInfo {
@@ -277,7 +443,7 @@ impl CarrierDatabase {
     }
 
     fn add_carrier(&mut self, id: &str, info: CarrierInfo) {
-        self.carriers.insert(id.to_string(), info);
+        self.builtin.insert(id.to_string(), info);
     }
 
     pub fn get_carrier(&self, id: &str) -> Option<&CarrierInfo> {
@@ -318,6 +484,26 @@ impl Default for CarrierDatabase {
     }
 }
 
+#[async_trait]
+impl Searchable for CarrierDatabase {
+    fn store(&self) -> SearchStore {
+        SearchStore::Carriers
+    }
+
+    async fn search(&self, query: &Query, limit: usize) -> Result<Vec<SearchHit>> {
+        let mut matches = self.search_carriers(query.as_str());
+        matches.sort_by(|a, b| a.0.cmp(b.0));
+        let mut hits = TopHits::new(limit);
+        for (id, info) in matches {
+            let fields = [("id", id.as_str()), ("name", info.name.as_str()), ("country", info.country.as_str())];
+            if let Some((field, value, rank)) = query.rank_fields(&fields) {
+                hits.push(SearchHit::new(SearchStore::Carriers, field, id.as_str(), query.snippet(value), rank));
+            }
+        }
+        Ok(hits.into_vec())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -346,4 +532,86 @@ mod tests {
         let us_carriers = db.list_by_country("United States");
         assert!(us_carriers.len() > 0);
     }
+
+    fn signed(version: u32, edit: impl FnOnce(&mut CarrierDbUpdate)) -> CarrierDbUpdate {
+        let mut update = CarrierDbUpdate::new(version);
+        edit(&mut update);
+        update.sign(&maintainer()).unwrap()
+    }
+
+    fn maintainer() -> ed25519_dalek::SigningKey {
+        ed25519_dalek::SigningKey::from_bytes(&[42u8; 32])
+    }
+
+    fn carrier(name: &str, sm_dp_address: &str) -> CarrierInfo {
+        CarrierInfo {
+            name: name.to_string(),
+            country: "Testland".to_string(),
+            sm_dp_address: sm_dp_address.to_string(),
+            supports_esim: true,
+            requires_confirmation: false,
+            api_endpoint: None,
+        }
+    }
+
+    #[test]
+    fn test_version_regression_is_refused() {
+        let key = maintainer().verifying_key();
+        let mut db = CarrierDatabase::new();
+        db.apply_update(signed(1, |u| u.added.push(("newco".into(), carrier("NewCo", "smdp.newco.test")))), &key).unwrap();
+        db.apply_update(signed(2, |u| u.removed.push("newco".into())), &key).unwrap();
+        assert!(db.get_carrier("newco").is_none());
+
+        // Replaying v1 must not resurrect the removed carrier
+        let err = db.apply_update(signed(1, |u| u.added.push(("newco".into(), carrier("NewCo", "smdp.newco.test")))), &key);
+        assert_eq!(
+            err.unwrap_err().downcast::<UpdateRejection>().unwrap(),
+            UpdateRejection::Stale { current: 2, offered: 1 }
+        );
+        assert!(db.get_carrier("newco").is_none());
+
+        let mut forged = signed(3, |u| u.removed.push("verizon".into()));
+        forged.removed.push("att".into());
+        assert!(db.apply_update(forged, &key).is_err());
+        assert!(db.get_carrier("att").is_some());
+
+        let gap = db.apply_update(signed(5, |_| {}), &key).unwrap_err();
+        assert!(matches!(gap.downcast::<UpdateRejection>().unwrap(), UpdateRejection::Gap { .. }));
+
+        assert_eq!(db.version(), 2);
+        assert_eq!(db.rejections(), &UpdateRejections { bad_signature: 1, stale_version: 1 });
+        assert_eq!(db.updates_since(1).len(), 1);
+        println!("✅ Carrier update version regression test PASSED!");
+    }
+
+    #[test]
+    fn test_layering_precedence_and_persistence() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let key = maintainer().verifying_key();
+        {
+            let mut db = CarrierDatabase::open(dir.path(), RuntimeMode::Persistent).unwrap();
+            db.apply_update(
+                signed(1, |u| {
+                    u.updated.push(("verizon".into(), carrier("Verizon", "smdp.distributed.test")));
+                    u.removed.push("sprint".into());
+                }),
+                &key,
+            )
+            .unwrap();
+            assert_eq!(db.get_sm_dp_address("verizon").unwrap(), "smdp.distributed.test");
+
+            db.set_override("verizon", carrier("Verizon", "smdp.local.test")).unwrap();
+            assert_eq!(db.get_sm_dp_address("verizon").unwrap(), "smdp.local.test");
+        }
+
+        // Reopened: update and override both survive, override still wins
+        let mut db = CarrierDatabase::open(dir.path(), RuntimeMode::Persistent).unwrap();
+        assert_eq!(db.version(), 1);
+        assert_eq!(db.get_sm_dp_address("verizon").unwrap(), "smdp.local.test");
+        assert!(db.get_carrier("sprint").is_none());
+        assert!(db.get_carrier("att").is_some());
+
+        assert!(db.remove_override("verizon").unwrap());
+        assert_eq!(db.get_sm_dp_address("verizon").unwrap(), "smdp.distributed.test");
+    }
 }
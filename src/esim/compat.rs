@@ -0,0 +1,372 @@
+//! Device Compatibility
+//! Parses an eUICC EID (GSMA SGP.29) and looks its manufacturer range up
+//! in a device capability database, so a carrier/device mismatch shows up
+//! before provisioning instead of as a failed download on the handset.
+//! Like the carrier database, built-in ranges can be extended or replaced
+//! locally, here from the JSON file at `esim.device_db_path`
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use super::carriers::CarrierDatabase;
+
+pub const EID_LENGTH: usize = 32;
+/// Major industry identifier every EID starts with (telecommunications)
+const EID_MII: &str = "89";
+/// MII + country code + issuer identifier
+const EUM_LENGTH: usize = 8;
+
+/// Why a string is not a valid EID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EidError {
+    Length { found: usize },
+    /// 1-based position of the first character that isn't a digit
+    NotDigit { position: usize },
+    Prefix,
+    /// ISO 7064 MOD 97-10 check digits don't match
+    Checksum { expected: String, found: String },
+}
+
+impl fmt::Display for EidError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Length { found } => write!(f, "EID must be {} digits (got {})", EID_LENGTH, found),
+            Self::NotDigit { position } => write!(f, "EID has a non-digit at position {}", position),
+            Self::Prefix => write!(f, "EID must start with {}", EID_MII),
+            Self::Checksum { expected, found } => {
+                write!(f, "EID check digits are {} but should be {}", found, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EidError {}
+
+/// A validated EID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Eid(String);
+
+impl Eid {
+    /// Parse `value`, ignoring spaces and dashes between digit groups
+    pub fn parse(value: &str) -> Result<Self, EidError> {
+        let digits: String = value.chars().filter(|c| !matches!(c, ' ' | '-')).collect();
+        if digits.chars().count() != EID_LENGTH {
+            return Err(EidError::Length { found: digits.chars().count() });
+        }
+        if let Some(i) = digits.chars().position(|c| !c.is_ascii_digit()) {
+            return Err(EidError::NotDigit { position: i + 1 });
+        }
+        if !digits.starts_with(EID_MII) {
+            return Err(EidError::Prefix);
+        }
+        let expected = check_digits(&digits[..EID_LENGTH - 2]);
+        let found = &digits[EID_LENGTH - 2..];
+        if found != expected {
+            return Err(EidError::Checksum { expected, found: found.to_string() });
+        }
+        Ok(Self(digits))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// eUICC manufacturer identifier: industry, country and issuer digits
+    pub fn eum(&self) -> &str {
+        &self.0[..EUM_LENGTH]
+    }
+
+    pub fn country_code(&self) -> &str {
+        &self.0[2..5]
+    }
+}
+
+impl fmt::Display for Eid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// MOD 97-10 check digits for the first 30 digits: the full EID is then 1 mod 97
+pub fn check_digits(body: &str) -> String {
+    let remainder = body
+        .bytes()
+        .chain(*b"00")
+        .fold(0u32, |acc, digit| (acc * 10 + u32::from(digit - b'0')) % 97);
+    format!("{:02}", 98 - remainder)
+}
+
+/// What a manufacturer range of eUICCs can do
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceRange {
+    /// EID prefix, at least the 8-digit EUM identifier; the longest match wins
+    pub prefix: String,
+    pub manufacturer: String,
+    /// Accepts consumer (SGP.22) profile downloads; M2M-only parts don't
+    pub consumer_esim: bool,
+    /// Can keep more than one profile enabled at once (MEP)
+    #[serde(default)]
+    pub multiple_enabled_profiles: bool,
+    #[serde(default)]
+    pub quirks: Vec<String>,
+    /// Carrier-locked range: only these carriers' profiles install
+    #[serde(default)]
+    pub locked_to: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct DeviceFile {
+    #[serde(default)]
+    ranges: Vec<DeviceRange>,
+}
+
+/// Device capabilities by EID prefix
+/// Layers, lowest first: built-ins, then ranges from a local file
+/// NOTE: built-in ranges are examples - load the vendor list in production
+pub struct DeviceDatabase {
+    ranges: HashMap<String, DeviceRange>,
+}
+
+impl DeviceDatabase {
+    pub fn new() -> Self {
+        let mut db = Self { ranges: HashMap::new() };
+        db.populate_ranges();
+        db
+    }
+
+    /// Built-ins plus the ranges in the JSON file at `path` (`{"ranges": [...]}`),
+    /// which replace built-ins with the same prefix
+    pub fn open(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path).with_context(|| format!("Failed to read device database {}", path.display()))?;
+        let file: DeviceFile =
+            serde_json::from_slice(&data).with_context(|| format!("Invalid device database {}", path.display()))?;
+        let mut db = Self::new();
+        for range in file.ranges {
+            anyhow::ensure!(
+                range.prefix.len() >= EUM_LENGTH && range.prefix.bytes().all(|b| b.is_ascii_digit()),
+                "Device range prefix '{}' must be at least {} digits",
+                range.prefix,
+                EUM_LENGTH
+            );
+            db.insert(range);
+        }
+        Ok(db)
+    }
+
+    pub fn insert(&mut self, range: DeviceRange) {
+        self.ranges.insert(range.prefix.clone(), range);
+    }
+
+    /// Most specific range containing `eid`
+    pub fn lookup(&self, eid: &Eid) -> Option<&DeviceRange> {
+        self.ranges
+            .values()
+            .filter(|range| eid.as_str().starts_with(&range.prefix))
+            .max_by_key(|range| range.prefix.len())
+    }
+
+    /// Whether `eid` can take `carrier_id`'s profiles
+    pub fn check_compatibility(&self, carriers: &CarrierDatabase, eid: &str, carrier_id: &str) -> Result<CompatReport, EidError> {
+        let eid = Eid::parse(eid)?;
+        let range = self.lookup(&eid);
+        let carrier = carriers.get_carrier(carrier_id);
+        let mut report = CompatReport {
+            eid: eid.to_string(),
+            eum: eid.eum().to_string(),
+            manufacturer: range.map(|r| r.manufacturer.clone()),
+            carrier: carrier_id.to_string(),
+            esim_supported: range.is_none_or(|r| r.consumer_esim),
+            multiple_enabled_profiles: range.map(|r| r.multiple_enabled_profiles),
+            carrier_restrictions: Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        match carrier {
+            None => report.carrier_restrictions.push(format!("'{}' is not in the carrier database", carrier_id)),
+            Some(info) if !info.supports_esim => {
+                report.carrier_restrictions.push(format!("{} does not offer eSIM profiles", info.name))
+            }
+            Some(info) if info.requires_confirmation => {
+                report.warnings.push(format!("{} requires a confirmation code at download", info.name))
+            }
+            Some(_) => {}
+        }
+        let Some(range) = range else {
+            report.warnings.push(format!("Unknown eUICC manufacturer {}; capabilities not verified", eid.eum()));
+            return Ok(report);
+        };
+        if !range.consumer_esim {
+            report.warnings.push(format!("{} eUICC is M2M-only; consumer profiles cannot be downloaded", range.manufacturer));
+        }
+        if !range.locked_to.is_empty() && !range.locked_to.iter().any(|c| c.eq_ignore_ascii_case(carrier_id)) {
+            report
+                .carrier_restrictions
+                .push(format!("Device is locked to {}", range.locked_to.join(", ")));
+        }
+        if !range.multiple_enabled_profiles {
+            report.warnings.push("Only one profile can be enabled at a time; enabling this one disables the current profile".to_string());
+        }
+        report.warnings.extend(range.quirks.iter().cloned());
+        Ok(report)
+    }
+
+    fn add_range(&mut self, prefix: &str, manufacturer: &str, consumer_esim: bool, mep: bool, quirks: &[&str]) {
+        self.insert(DeviceRange {
+            prefix: prefix.to_string(),
+            manufacturer: manufacturer.to_string(),
+            consumer_esim,
+            multiple_enabled_profiles: mep,
+            quirks: quirks.iter().map(|q| q.to_string()).collect(),
+            locked_to: Vec::new(),
+        });
+    }
+
+    fn populate_ranges(&mut self) {
+        self.add_range("89049032", "Giesecke+Devrient", true, true, &[]);
+        self.add_range("89033023", "Thales", true, false, &["Profile switches need a device restart"]);
+        self.add_range("89044045", "Kigen", true, false, &[]);
+        self.add_range("89086030", "IDEMIA", true, true, &[]);
+        self.add_range("89001012", "STMicroelectronics", false, false, &[]);
+    }
+}
+
+impl Default for DeviceDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompatReport {
+    pub eid: String,
+    pub eum: String,
+    /// `None` when the EID's range isn't in the database
+    pub manufacturer: Option<String>,
+    pub carrier: String,
+    /// The device accepts consumer profiles (assumed for unknown ranges)
+    pub esim_supported: bool,
+    pub multiple_enabled_profiles: Option<bool>,
+    /// Reasons the carrier's profile will be refused
+    pub carrier_restrictions: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl CompatReport {
+    pub fn is_compatible(&self) -> bool {
+        self.esim_supported && self.carrier_restrictions.is_empty()
+    }
+}
+
+impl fmt::Display for CompatReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = if self.is_compatible() { "✅ Compatible" } else { "❌ Not compatible" };
+        writeln!(f, "{} with {}", verdict, self.carrier)?;
+        writeln!(f, "  EID:          {}", self.eid)?;
+        writeln!(f, "  Manufacturer: {} ({})", self.manufacturer.as_deref().unwrap_or("unknown"), self.eum)?;
+        writeln!(f, "  eSIM:         {}", if self.esim_supported { "supported" } else { "not supported" })?;
+        if let Some(mep) = self.multiple_enabled_profiles {
+            writeln!(f, "  Multiple enabled profiles: {}", if mep { "yes" } else { "no" })?;
+        }
+        for restriction in &self.carrier_restrictions {
+            writeln!(f, "  ⛔ {}", restriction)?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "  ⚠️  {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SGP.29's example EID
+    const EXAMPLE: &str = "89049032123451234512345678901235";
+
+    #[test]
+    fn test_eid_check_digits() {
+        assert_eq!(Eid::parse(EXAMPLE).unwrap().eum(), "89049032");
+        assert_eq!(Eid::parse("8904 9032 1234 5123 4512 3456 7890 1235").unwrap().as_str(), EXAMPLE);
+        for (body, check) in [
+            ("890490321234512345123456789012", "35"),
+            ("890330230000000000000000000012", "39"),
+            ("890010120000000000000000004242", "34"),
+        ] {
+            assert_eq!(check_digits(body), check);
+        }
+
+        assert_eq!(Eid::parse(&EXAMPLE[..31]), Err(EidError::Length { found: 31 }));
+        assert_eq!(Eid::parse("8904903212345123451234567890123X"), Err(EidError::NotDigit { position: 32 }));
+        assert_eq!(Eid::parse("99049032123451234512345678901235"), Err(EidError::Prefix));
+        // A single transposed digit is caught
+        assert_eq!(
+            Eid::parse("89049032123451234512345678902135"),
+            Err(EidError::Checksum { expected: "08".to_string(), found: "35".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_manufacturer_range_lookup() {
+        let mut db = DeviceDatabase::new();
+        let thales = Eid::parse("89033023000000000000000000001239").unwrap();
+        assert_eq!(db.lookup(&thales).unwrap().manufacturer, "Thales");
+
+        // A longer prefix overrides its EUM's defaults
+        db.insert(DeviceRange {
+            prefix: "8903302300000".to_string(),
+            manufacturer: "Thales (wearables)".to_string(),
+            consumer_esim: true,
+            multiple_enabled_profiles: true,
+            quirks: Vec::new(),
+            locked_to: Vec::new(),
+        });
+        assert_eq!(db.lookup(&thales).unwrap().manufacturer, "Thales (wearables)");
+        assert!(db.lookup(&Eid::parse("89123099000000000000000000000134").unwrap()).is_none());
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("devices.json");
+        std::fs::write(&path, r#"{"ranges": [{"prefix": "89123099", "manufacturer": "Acme", "consumer_esim": true}]}"#).unwrap();
+        let db = DeviceDatabase::open(&path).unwrap();
+        assert_eq!(db.lookup(&Eid::parse("89123099000000000000000000000134").unwrap()).unwrap().manufacturer, "Acme");
+        std::fs::write(&path, r#"{"ranges": [{"prefix": "89", "manufacturer": "Everyone", "consumer_esim": true}]}"#).unwrap();
+        assert!(DeviceDatabase::open(&path).is_err());
+    }
+
+    #[test]
+    fn test_carrier_restrictions() {
+        let carriers = CarrierDatabase::new();
+        let mut db = DeviceDatabase::new();
+        db.insert(DeviceRange {
+            prefix: "89086030".to_string(),
+            manufacturer: "IDEMIA".to_string(),
+            consumer_esim: true,
+            multiple_enabled_profiles: true,
+            quirks: Vec::new(),
+            locked_to: vec!["verizon".to_string()],
+        });
+        let locked = "89086030000000000000000000009989";
+
+        let report = db.check_compatibility(&carriers, locked, "verizon").unwrap();
+        assert!(report.is_compatible(), "{}", report);
+        let report = db.check_compatibility(&carriers, locked, "tmobile").unwrap();
+        assert!(!report.is_compatible());
+        assert_eq!(report.carrier_restrictions, ["Device is locked to verizon"]);
+
+        let report = db.check_compatibility(&carriers, "89033023000000000000000000001239", "tmobile").unwrap();
+        assert!(report.is_compatible());
+        assert_eq!(report.multiple_enabled_profiles, Some(false));
+        assert!(report.warnings.iter().any(|w| w.contains("restart")));
+
+        let report = db.check_compatibility(&carriers, "89001012000000000000000000424234", "tmobile").unwrap();
+        assert!(!report.esim_supported && !report.is_compatible());
+
+        let report = db.check_compatibility(&carriers, "89123099000000000000000000000134", "nope").unwrap();
+        assert_eq!(report.manufacturer, None);
+        assert_eq!(report.carrier_restrictions.len(), 1);
+        assert!(db.check_compatibility(&carriers, "123", "tmobile").is_err());
+    }
+}
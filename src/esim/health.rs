@@ -0,0 +1,614 @@
+//! SM-DP+ Health
+//! Scheduled reachability / TLS pre-flight against each carrier's SM-DP+
+//! host, with a persisted probe history, availability over 24h / 7d and a
+//! warning once a carrier fails several probes in a row
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_rustls::rustls::{self, pki_types::ServerName, ClientConfig, RootCertStore};
+
+use super::carriers::CarrierDatabase;
+use crate::migrations::{self, StoreSchema};
+use crate::scheduler::{Scheduler, TaskSpec};
+use crate::security::notifications::{NotificationRouter, SinkEvent};
+use crate::storage::{IndexFields, IndexQuery, KvStore, RuntimeMode};
+use crate::units::HumanDuration;
+
+const HEALTH_TREE: &str = "carrier_health";
+
+pub const SCHEMA: StoreSchema = StoreSchema {
+    name: "carrier_health",
+    tree: Some(HEALTH_TREE),
+    version: 1,
+    migrations: &[],
+    index: Some(index_result),
+};
+
+/// Results are found by probe time, for pruning
+fn index_result(_key: &[u8], value: &[u8]) -> IndexFields {
+    IndexFields {
+        timestamp: serde_json::from_slice::<ProbeResult>(value).ok().map(|r| r.at.timestamp_millis()),
+        ..IndexFields::default()
+    }
+}
+
+/// `[esim.health]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Time between probe rounds
+    pub interval: HumanDuration,
+    /// Carrier IDs to probe (all when empty)
+    pub carriers: Vec<String>,
+    /// Minimum time between probes of one host; carriers sharing an
+    /// SM-DP+ host reuse its latest result inside this window
+    pub host_min_interval: HumanDuration,
+    /// Connect + TLS handshake budget per probe
+    pub timeout: HumanDuration,
+    pub port: u16,
+    /// Consecutive failures before a carrier is down and a warning is sent
+    pub failure_threshold: u32,
+    /// Probe results older than this are dropped
+    pub retention: HumanDuration,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            interval: HumanDuration::from_secs(300),
+            carriers: Vec::new(),
+            host_min_interval: HumanDuration::from_secs(60),
+            timeout: HumanDuration::from_secs(10),
+            port: 443,
+            failure_threshold: 3,
+            retention: HumanDuration::from_secs(8 * 86_400),
+        }
+    }
+}
+
+/// What a successful pre-flight learned about a host
+#[derive(Debug, Clone)]
+pub struct TlsFacts {
+    pub latency: Duration,
+    /// Leaf certificate expiry
+    pub not_after: Option<DateTime<Utc>>,
+}
+
+/// Reachability / TLS pre-flight against an SM-DP+ host
+#[async_trait]
+pub trait SmdpProbe: Send + Sync {
+    async fn probe(&self, host: &str) -> Result<TlsFacts>;
+}
+
+/// TCP connect and a verified TLS handshake against the web PKI roots
+pub struct TlsProbe {
+    port: u16,
+    timeout: Duration,
+    tls: Arc<ClientConfig>,
+}
+
+impl TlsProbe {
+    pub fn new(port: u16, timeout: Duration) -> Result<Self> {
+        let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let tls = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .context("No TLS protocol versions")?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(Self { port, timeout, tls: Arc::new(tls) })
+    }
+
+    async fn handshake(&self, host: &str) -> Result<TlsFacts> {
+        let started = Instant::now();
+        let tcp = tokio::net::TcpStream::connect((host, self.port))
+            .await
+            .with_context(|| format!("Connect to {}:{}", host, self.port))?;
+        let name = ServerName::try_from(host.to_string()).context("Invalid host name")?;
+        let tls = tokio_rustls::TlsConnector::from(self.tls.clone())
+            .connect(name, tcp)
+            .await
+            .context("TLS handshake")?;
+        let latency = started.elapsed();
+        let not_after = tls
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|chain| chain.first())
+            .and_then(|leaf| x509_parser::parse_x509_certificate(leaf).ok())
+            .and_then(|(_, cert)| DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0));
+        Ok(TlsFacts { latency, not_after })
+    }
+}
+
+#[async_trait]
+impl SmdpProbe for TlsProbe {
+    async fn probe(&self, host: &str) -> Result<TlsFacts> {
+        tokio::time::timeout(self.timeout, self.handshake(host))
+            .await
+            .map_err(|_| anyhow::anyhow!("Timed out after {:?}", self.timeout))?
+    }
+}
+
+/// In-process stand-in for SM-DP+ hosts, with outages that can be injected
+/// and cleared. Unknown hosts are unreachable
+#[derive(Default)]
+pub struct SmdpSimulator {
+    hosts: Mutex<HashMap<String, Result<TlsFacts, String>>>,
+}
+
+impl SmdpSimulator {
+    /// Serve `host` with the given latency and certificate expiry
+    pub fn serve(&self, host: &str, latency: Duration, not_after: DateTime<Utc>) {
+        self.hosts.lock().insert(host.to_string(), Ok(TlsFacts { latency, not_after: Some(not_after) }));
+    }
+
+    /// Fail every probe of `host` with `reason` until served again
+    pub fn outage(&self, host: &str, reason: &str) {
+        self.hosts.lock().insert(host.to_string(), Err(reason.to_string()));
+    }
+}
+
+#[async_trait]
+impl SmdpProbe for SmdpSimulator {
+    async fn probe(&self, host: &str) -> Result<TlsFacts> {
+        match self.hosts.lock().get(host) {
+            Some(Ok(facts)) => Ok(facts.clone()),
+            Some(Err(reason)) => Err(anyhow::anyhow!("{}", reason)),
+            None => Err(anyhow::anyhow!("Connect to {}: connection refused", host)),
+        }
+    }
+}
+
+/// One probe of a carrier's host
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProbeResult {
+    pub at: DateTime<Utc>,
+    pub host: String,
+    pub latency_ms: Option<u64>,
+    /// Days until the leaf certificate expires
+    pub tls_expiry_days: Option<i64>,
+    pub error: Option<String>,
+}
+
+impl ProbeResult {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn from_outcome(host: &str, at: DateTime<Utc>, outcome: Result<TlsFacts>) -> Self {
+        match outcome {
+            Ok(facts) => Self {
+                at,
+                host: host.to_string(),
+                latency_ms: Some(facts.latency.as_millis() as u64),
+                tls_expiry_days: facts.not_after.map(|t| (t - at).num_days()),
+                error: None,
+            },
+            Err(e) => Self { at, host: host.to_string(), latency_ms: None, tls_expiry_days: None, error: Some(format!("{:#}", e)) },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    /// Never probed
+    Unknown,
+    Healthy,
+    /// Failing, but fewer than `failure_threshold` probes in a row
+    Degraded,
+    Down,
+}
+
+impl fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown => write!(f, "❔ unknown"),
+            Self::Healthy => write!(f, "✅ healthy"),
+            Self::Degraded => write!(f, "⚠️  degraded"),
+            Self::Down => write!(f, "❌ down"),
+        }
+    }
+}
+
+/// A carrier's current health, from its probe history
+#[derive(Debug, Clone, Serialize)]
+pub struct CarrierHealth {
+    pub carrier_id: String,
+    pub status: HealthStatus,
+    pub consecutive_failures: u32,
+    pub last_probe: Option<ProbeResult>,
+    /// Percentage of successful probes, when there were any in the window
+    pub availability_24h: Option<f64>,
+    pub availability_7d: Option<f64>,
+}
+
+impl CarrierHealth {
+    fn from_history(carrier_id: &str, history: &[ProbeResult], now: DateTime<Utc>, failure_threshold: u32) -> Self {
+        let consecutive_failures = history.iter().rev().take_while(|p| !p.is_ok()).count() as u32;
+        let status = match (history.last(), consecutive_failures) {
+            (None, _) => HealthStatus::Unknown,
+            (Some(_), 0) => HealthStatus::Healthy,
+            (Some(_), n) if n >= failure_threshold => HealthStatus::Down,
+            (Some(_), _) => HealthStatus::Degraded,
+        };
+        let availability = |window: ChronoDuration| {
+            let probes: Vec<_> = history.iter().filter(|p| now - p.at <= window).collect();
+            (!probes.is_empty())
+                .then(|| 100.0 * probes.iter().filter(|p| p.is_ok()).count() as f64 / probes.len() as f64)
+        };
+        Self {
+            carrier_id: carrier_id.to_string(),
+            status,
+            consecutive_failures,
+            last_probe: history.last().cloned(),
+            availability_24h: availability(ChronoDuration::hours(24)),
+            availability_7d: availability(ChronoDuration::days(7)),
+        }
+    }
+
+    /// One-line status for carrier listings
+    pub fn summary(&self) -> String {
+        let mut line = self.status.to_string();
+        if let Some(pct) = self.availability_24h {
+            line.push_str(&format!(", {:.1}% 24h", pct));
+        }
+        if let Some(pct) = self.availability_7d {
+            line.push_str(&format!(", {:.1}% 7d", pct));
+        }
+        match &self.last_probe {
+            Some(ProbeResult { error: Some(e), .. }) => line.push_str(&format!(" ({})", e)),
+            Some(ProbeResult { latency_ms: Some(ms), tls_expiry_days, .. }) => {
+                line.push_str(&format!(", {} ms", ms));
+                if let Some(days) = tls_expiry_days {
+                    line.push_str(&format!(", cert expires in {}d", days));
+                }
+            }
+            _ => {}
+        }
+        line
+    }
+}
+
+impl fmt::Display for CarrierHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.carrier_id, self.summary())
+    }
+}
+
+/// A carrier that `--require-healthy` refuses to provision against
+#[derive(Debug, Clone)]
+pub struct CarrierUnhealthy(pub CarrierHealth);
+
+impl fmt::Display for CarrierUnhealthy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let health = &self.0;
+        match health.status {
+            HealthStatus::Unknown => write!(f, "carrier {} has not been health-checked", health.carrier_id),
+            _ => write!(
+                f,
+                "carrier {} SM-DP+ is {:?} ({} consecutive failed probe(s))",
+                health.carrier_id, health.status, health.consecutive_failures
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CarrierUnhealthy {}
+
+/// Probe results keyed by carrier and time
+pub struct HealthHistory {
+    db: Box<dyn KvStore>,
+}
+
+impl HealthHistory {
+    /// Open the history at `path`, or keep it in memory when ephemeral
+    pub fn open(path: &Path, mode: RuntimeMode) -> Result<Self> {
+        let db = migrations::open_store(mode, path, &SCHEMA)
+            .with_context(|| format!("Failed to open carrier health history at {}", path.display()))?;
+        Ok(Self { db })
+    }
+
+    fn key(carrier_id: &str, at: DateTime<Utc>) -> Vec<u8> {
+        format!("{}/{:020}", carrier_id, at.timestamp_millis().max(0)).into_bytes()
+    }
+
+    pub fn record(&self, carrier_id: &str, result: &ProbeResult) -> Result<()> {
+        self.db.insert(&Self::key(carrier_id, result.at), &serde_json::to_vec(result)?)?;
+        self.db.flush()
+    }
+
+    /// A carrier's probes, oldest first
+    pub fn results(&self, carrier_id: &str) -> Result<Vec<ProbeResult>> {
+        let prefix = format!("{}/", carrier_id);
+        self.db
+            .scan_prefix(prefix.as_bytes())?
+            .into_iter()
+            .map(|(_, bytes)| serde_json::from_slice(&bytes).context("Corrupt probe result"))
+            .collect()
+    }
+
+    /// Drop results older than `retention`; returns how many
+    pub fn prune(&self, now: DateTime<Utc>, retention: ChronoDuration) -> Result<usize> {
+        let mut dropped = 0;
+        let cutoff = (now - retention).timestamp_millis();
+        for (key, bytes) in self.db.find(IndexQuery::Timestamp { from: i64::MIN, to: cutoff + 1 })? {
+            let result: ProbeResult = serde_json::from_slice(&bytes).context("Corrupt probe result")?;
+            if now - result.at > retention {
+                self.db.remove(&key)?;
+                dropped += 1;
+            }
+        }
+        if dropped > 0 {
+            self.db.flush()?;
+        }
+        Ok(dropped)
+    }
+}
+
+/// Probes carriers and tracks their health
+pub struct HealthMonitor {
+    config: HealthConfig,
+    probe: Arc<dyn SmdpProbe>,
+    history: HealthHistory,
+    notifier: Option<Arc<NotificationRouter>>,
+    /// Latest result per host, reused inside `host_min_interval`
+    recent: Mutex<HashMap<String, ProbeResult>>,
+}
+
+impl HealthMonitor {
+    pub fn new(config: HealthConfig, probe: Arc<dyn SmdpProbe>, history: HealthHistory) -> Self {
+        Self { config, probe, history, notifier: None, recent: Mutex::new(HashMap::new()) }
+    }
+
+    /// TLS pre-flight against the real hosts, history at `path`
+    pub fn open(config: &HealthConfig, path: &Path, mode: RuntimeMode) -> Result<Self> {
+        let probe = TlsProbe::new(config.port, config.timeout.as_std())?;
+        Ok(Self::new(config.clone(), Arc::new(probe), HealthHistory::open(path, mode)?))
+    }
+
+    /// Warn through the notification router when a carrier goes down
+    pub fn set_notifier(&mut self, notifier: Arc<NotificationRouter>) {
+        self.notifier = Some(notifier);
+    }
+
+    /// Configured carriers (all when none are) with their SM-DP+ hosts
+    pub fn targets(&self, carriers: &CarrierDatabase) -> Vec<(String, String)> {
+        let mut targets: Vec<(String, String)> = carriers
+            .list_carriers()
+            .into_iter()
+            .filter(|(id, _)| self.config.carriers.is_empty() || self.config.carriers.contains(id))
+            .map(|(id, info)| (id.clone(), info.sm_dp_address.clone()))
+            .collect();
+        targets.sort();
+        targets
+    }
+
+    pub fn carrier_health(&self, carrier_id: &str) -> Result<CarrierHealth> {
+        self.carrier_health_at(carrier_id, Utc::now())
+    }
+
+    pub fn carrier_health_at(&self, carrier_id: &str, now: DateTime<Utc>) -> Result<CarrierHealth> {
+        let history = self.history.results(carrier_id)?;
+        Ok(CarrierHealth::from_history(carrier_id, &history, now, self.config.failure_threshold))
+    }
+
+    /// The carrier's health, or `CarrierUnhealthy` unless its last probe passed
+    pub fn require_healthy(&self, carrier_id: &str) -> Result<CarrierHealth> {
+        let health = self.carrier_health(carrier_id)?;
+        if health.status != HealthStatus::Healthy {
+            return Err(CarrierUnhealthy(health).into());
+        }
+        Ok(health)
+    }
+
+    /// Probe every target once (hosts probed within `host_min_interval`
+    /// are not probed again) and prune old history
+    pub async fn probe_round(&self, targets: &[(String, String)], now: DateTime<Utc>) -> Result<Vec<CarrierHealth>> {
+        let min_interval = self.config.host_min_interval.as_chrono();
+        let mut round = Vec::with_capacity(targets.len());
+        for (carrier_id, host) in targets {
+            let recent = self.recent.lock().get(host).filter(|r| now - r.at < min_interval).cloned();
+            let result = match recent {
+                Some(result) => result,
+                None => {
+                    let result = ProbeResult::from_outcome(host, now, self.probe.probe(host).await);
+                    self.recent.lock().insert(host.clone(), result.clone());
+                    result
+                }
+            };
+            self.history.record(carrier_id, &result)?;
+
+            let health = self.carrier_health_at(carrier_id, now)?;
+            if health.consecutive_failures == self.config.failure_threshold {
+                tracing::warn!("📡 {} SM-DP+ {} is down: {}", carrier_id, host, result.error.as_deref().unwrap_or(""));
+                if let Some(notifier) = &self.notifier {
+                    notifier.notify(SinkEvent::CarrierUnhealthy {
+                        carrier_id: carrier_id.clone(),
+                        host: host.clone(),
+                        consecutive_failures: health.consecutive_failures,
+                        error: result.error.clone().unwrap_or_default(),
+                    });
+                }
+            }
+            round.push(health);
+        }
+        let pruned = self.history.prune(now, self.config.retention.as_chrono())?;
+        tracing::debug!("📡 Probed {} carrier(s), pruned {} old result(s)", round.len(), pruned);
+        Ok(round)
+    }
+
+    /// Run `probe_round` over `targets` every `interval` on `scheduler`
+    pub fn schedule(self: Arc<Self>, scheduler: &mut Scheduler, targets: Vec<(String, String)>) -> Result<()> {
+        let spec = TaskSpec {
+            interval: self.config.interval.as_std(),
+            jitter: 0.1,
+            // Every host timing out still fits in one run
+            timeout: self.config.timeout.as_std() * targets.len().max(1) as u32 + Duration::from_secs(5),
+        };
+        let targets = Arc::new(targets);
+        scheduler.register("esim.health", spec, move || {
+            let (monitor, targets) = (self.clone(), targets.clone());
+            async move { monitor.probe_round(&targets, Utc::now()).await.map(|_| ()) }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::notifications::{EventSink, RouteConfig, Severity};
+
+    fn monitor(simulator: Arc<SmdpSimulator>) -> HealthMonitor {
+        let config = HealthConfig { failure_threshold: 3, host_min_interval: HumanDuration::from_secs(30), ..Default::default() };
+        let history = HealthHistory::open(Path::new("unused"), RuntimeMode::Ephemeral).unwrap();
+        HealthMonitor::new(config, simulator, history)
+    }
+
+    fn targets() -> Vec<(String, String)> {
+        vec![("att".to_string(), "smdp.att.test".to_string()), ("tmobile".to_string(), "smdp.tmo.test".to_string())]
+    }
+
+    #[tokio::test]
+    async fn test_outage_state_transitions() {
+        let simulator = Arc::new(SmdpSimulator::default());
+        let start = Utc::now();
+        simulator.serve("smdp.att.test", Duration::from_millis(42), start + ChronoDuration::days(90));
+        simulator.serve("smdp.tmo.test", Duration::from_millis(80), start + ChronoDuration::days(10));
+        let monitor = monitor(simulator.clone());
+        assert_eq!(monitor.carrier_health("att").unwrap().status, HealthStatus::Unknown);
+
+        let at = |minutes| start + ChronoDuration::minutes(minutes);
+        let round = monitor.probe_round(&targets(), at(0)).await.unwrap();
+        assert!(round.iter().all(|h| h.status == HealthStatus::Healthy));
+        let tmo = round[1].last_probe.as_ref().unwrap();
+        assert_eq!((tmo.latency_ms, tmo.tls_expiry_days), (Some(80), Some(10)));
+
+        simulator.outage("smdp.att.test", "TLS handshake: connection reset");
+        let statuses: Vec<HealthStatus> = {
+            let mut statuses = Vec::new();
+            for minute in [5, 10, 15] {
+                statuses.push(monitor.probe_round(&targets(), at(minute)).await.unwrap()[0].status);
+            }
+            statuses
+        };
+        assert_eq!(statuses, [HealthStatus::Degraded, HealthStatus::Degraded, HealthStatus::Down]);
+        let att = monitor.carrier_health_at("att", at(15)).unwrap();
+        assert_eq!(att.consecutive_failures, 3);
+        assert_eq!(att.availability_24h, Some(25.0));
+        assert!(att.summary().contains("connection reset"));
+
+        // Recovery resets the failure count but not the availability
+        simulator.serve("smdp.att.test", Duration::from_millis(40), start + ChronoDuration::days(90));
+        let att = &monitor.probe_round(&targets(), at(20)).await.unwrap()[0];
+        assert_eq!((att.status, att.consecutive_failures), (HealthStatus::Healthy, 0));
+        assert_eq!(att.availability_24h, Some(40.0));
+    }
+
+    #[tokio::test]
+    async fn test_availability_windows_and_retention() {
+        let simulator = Arc::new(SmdpSimulator::default());
+        let monitor = monitor(simulator.clone());
+        let now = Utc::now();
+        let only_att = &targets()[..1];
+
+        // Down for the probes 3 days ago, up for the last day
+        simulator.outage("smdp.att.test", "refused");
+        for hours in [72, 71] {
+            monitor.probe_round(only_att, now - ChronoDuration::hours(hours)).await.unwrap();
+        }
+        simulator.serve("smdp.att.test", Duration::from_millis(10), now + ChronoDuration::days(30));
+        for hours in [20, 10] {
+            monitor.probe_round(only_att, now - ChronoDuration::hours(hours)).await.unwrap();
+        }
+        let health = monitor.carrier_health_at("att", now).unwrap();
+        assert_eq!(health.availability_24h, Some(100.0));
+        assert_eq!(health.availability_7d, Some(50.0));
+
+        // Past retention (8 days) the old probes are gone
+        monitor.probe_round(only_att, now + ChronoDuration::days(6)).await.unwrap();
+        let health = monitor.carrier_health_at("att", now + ChronoDuration::days(6)).unwrap();
+        assert_eq!(health.availability_7d, Some(100.0));
+        assert_eq!(monitor.history.results("att").unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_shared_host_probed_once_per_interval() {
+        #[derive(Default)]
+        struct Counting(Mutex<usize>);
+        #[async_trait]
+        impl SmdpProbe for Counting {
+            async fn probe(&self, _host: &str) -> Result<TlsFacts> {
+                *self.0.lock() += 1;
+                Ok(TlsFacts { latency: Duration::from_millis(5), not_after: None })
+            }
+        }
+        let probe = Arc::new(Counting::default());
+        let history = HealthHistory::open(Path::new("unused"), RuntimeMode::Ephemeral).unwrap();
+        let monitor = HealthMonitor::new(HealthConfig::default(), probe.clone(), history);
+        let shared = vec![
+            ("tmobile".to_string(), "prod.smpc.t-mobile.com".to_string()),
+            ("mint".to_string(), "prod.smpc.t-mobile.com".to_string()),
+        ];
+        let now = Utc::now();
+        monitor.probe_round(&shared, now).await.unwrap();
+        monitor.probe_round(&shared, now + ChronoDuration::seconds(30)).await.unwrap();
+        assert_eq!(*probe.0.lock(), 1);
+        monitor.probe_round(&shared, now + ChronoDuration::seconds(61)).await.unwrap();
+        assert_eq!(*probe.0.lock(), 2);
+        assert_eq!(monitor.carrier_health_at("mint", now).unwrap().status, HealthStatus::Healthy);
+    }
+
+    #[tokio::test]
+    async fn test_require_healthy_gate_and_down_warning() {
+        #[derive(Default)]
+        struct Capture(Mutex<Vec<SinkEvent>>);
+        #[async_trait]
+        impl EventSink for Capture {
+            async fn emit(&self, event: &SinkEvent) -> Result<()> {
+                self.0.lock().push(event.clone());
+                Ok(())
+            }
+        }
+
+        let simulator = Arc::new(SmdpSimulator::default());
+        let mut monitor = monitor(simulator.clone());
+        let capture = Arc::new(Capture::default());
+        let route = RouteConfig {
+            categories: vec!["carrier_unhealthy".to_string()],
+            min_severity: Severity::Info,
+            sinks: vec!["capture".to_string()],
+        };
+        let mut router = NotificationRouter::new(vec![route], 16, 1);
+        router.add_sink("capture", capture.clone(), 60);
+        monitor.set_notifier(Arc::new(router));
+
+        let err = monitor.require_healthy("att").unwrap_err();
+        assert!(err.downcast_ref::<CarrierUnhealthy>().is_some());
+
+        simulator.serve("smdp.att.test", Duration::from_millis(5), Utc::now() + ChronoDuration::days(30));
+        monitor.probe_round(&targets()[..1], Utc::now()).await.unwrap();
+        monitor.require_healthy("att").unwrap();
+
+        simulator.outage("smdp.att.test", "refused");
+        for minutes in 1..=4 {
+            monitor.probe_round(&targets()[..1], Utc::now() + ChronoDuration::minutes(minutes)).await.unwrap();
+        }
+        let err = monitor.require_healthy("att").unwrap_err();
+        assert!(err.to_string().contains("4 consecutive failed probe(s)"), "{}", err);
+
+        // One warning when the threshold is crossed, not one per probe
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let events = capture.0.lock();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], SinkEvent::CarrierUnhealthy { consecutive_failures: 3, .. }));
+    }
+}
@@ -1,12 +1,34 @@
+pub mod activation;
+pub mod carrier_updates;
+pub mod compat;
+pub mod health;
 pub mod profile;
 pub mod provisioning;
 pub mod qrcode_generator;
 pub mod security;
+pub mod store;
 pub mod carriers;
+pub mod webhook;
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
+/// `[esim]` configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EsimSettings {
+    /// Hex Ed25519 public key that signs distributed carrier database
+    /// updates; updates are neither accepted nor served when unset
+    pub carrier_maintainer_key: Option<String>,
+    /// JSON device ranges layered over the built-in compatibility database
+    pub device_db_path: Option<std::path::PathBuf>,
+    /// Hold verified carrier database updates for operator approval
+    /// instead of applying them as they arrive
+    pub carrier_update_approval: bool,
+    pub health: health::HealthConfig,
+    pub webhook: webhook::WebhookConfig,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ESimProfile {
     pub iccid: String,
@@ -16,6 +38,11 @@ pub struct ESimProfile {
     pub confirmation_code: Option<String>,
     pub carrier_name: String,
     pub plan_type: String,
+    /// Last state a carrier reported (see `webhook`)
+    #[serde(default)]
+    pub state: profile::ProfileState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan_expires_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -75,6 +102,8 @@ impl ESimManager {
             confirmation_code: None,
             carrier_name: request.carrier,
             plan_type: request.plan_type,
+            state: Default::default(),
+            plan_expires_at: None,
         };
 
         tracing::info!("Provisioned eSIM profile: {}", profile.iccid);
@@ -88,17 +117,9 @@ impl ESimManager {
 
     pub async fn download_profile(&self, activation_code: &str) -> Result<ESimProfile> {
         // Parse activation code
-        if !activation_code.starts_with("LPA:1$") {
-            anyhow::bail!("Invalid activation code format");
-        }
-
-        let parts: Vec<&str> = activation_code.split('$').collect();
-        if parts.len() < 3 {
-            anyhow::bail!("Invalid activation code format");
-        }
-
-        let sm_dp_address = parts[1].to_string();
-        let matching_id = parts[2].to_string();
+        let code = activation::ActivationCode::parse(activation_code)?;
+        let sm_dp_address = code.sm_dp_address;
+        let matching_id = code.matching_id;
 
         // In a real implementation, we would contact the SM-DP+ server here
         tracing::info!("Downloading profile from SM-DP+: {}", sm_dp_address);
@@ -111,6 +132,8 @@ impl ESimManager {
             confirmation_code: None,
             carrier_name: "Unknown".to_string(),
             plan_type: "Unknown".to_string(),
+            state: Default::default(),
+            plan_expires_at: None,
         })
     }
 
@@ -119,17 +142,9 @@ impl ESimManager {
         tracing::info!("Starting SECURE profile download");
 
         // Parse activation code
-        if !activation_code.starts_with("LPA:1$") {
-            anyhow::bail!("Invalid activation code format");
-        }
-
-        let parts: Vec<&str> = activation_code.split('$').collect();
-        if parts.len() < 3 {
-            anyhow::bail!("Invalid activation code format");
-        }
-
-        let sm_dp_address = parts[1];
-        let matching_id = parts[2];
+        let code = activation::ActivationCode::parse(activation_code)?;
+        let sm_dp_address = code.sm_dp_address.as_str();
+        let matching_id = code.matching_id.as_str();
 
         // Download profile using secure channel
         let _profile_data = self.security
@@ -147,9 +162,11 @@ impl ESimManager {
             activation_code: secure_activation_code,
             sm_dp_address: sm_dp_address.to_string(),
             matching_id: Some(matching_id.to_string()),
-            confirmation_code: parts.get(3).map(|s| s.to_string()),
+            confirmation_code: code.confirmation_code.clone(),
             carrier_name: "Secure Carrier".to_string(),
             plan_type: "Secure Plan".to_string(),
+            state: Default::default(),
+            plan_expires_at: None,
         })
     }
 
@@ -16,13 +16,23 @@ pub enum ProfileClass {
     Operational,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ProfileState {
+    /// Installed but not in use; where provisioned profiles start
+    #[default]
     Disabled,
     Enabled,
     Deleted,
 }
 
+impl ProfileState {
+    /// Whether a profile in this state can move to `to`. Repeating the
+    /// current state is allowed (carriers redeliver); leaving Deleted is not
+    pub fn can_become(self, to: ProfileState) -> bool {
+        self == to || self != ProfileState::Deleted
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProfileInfo {
     pub iccid: String,
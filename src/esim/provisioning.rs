@@ -35,6 +35,20 @@ impl ProvisioningService {
     pub async fn initiate_download(&self, request: ProvisioningRequest) -> Result<ProvisioningResponse> {
         tracing::info!("Initiating profile download for EID: {}", request.eid);
 
+        // SM-DP+ authentication (injection site `esim.smdp.auth`)
+        match crate::faults::fault!("esim.smdp.auth") {
+            Some(crate::faults::FaultMode::Delay { delay }) => tokio::time::sleep(delay.as_std()).await,
+            Some(crate::faults::FaultMode::Drop) => {
+                return Ok(ProvisioningResponse {
+                    status: ProvisioningStatus::Pending,
+                    profile_data: None,
+                    error_message: None,
+                })
+            }
+            Some(mode) => return Err(mode.error("esim.smdp.auth")),
+            None => {}
+        }
+
         // In a real implementation, this would:
         // 1. Connect to SM-DP+ server
         // 2. Authenticate using EID and matching ID
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use qrcode::render::svg;
 use qrcode::QrCode;
 use image::Luma;
 
@@ -23,7 +24,7 @@ pub fn generate_qr_code(data: &str) -> Result<Vec<u8>> {
 pub fn generate_qr_code_svg(data: &str) -> Result<String> {
     let code = QrCode::new(data.as_bytes())?;
 
-    let svg = code.render::<char>()
+    let svg = code.render::<svg::Color>()
         .min_dimensions(512, 512)
         .build();
 
@@ -12,7 +12,7 @@ use std::time::Duration;
 // ✅ OPTIMIZATION: Global HTTP client with connection pooling
 // Reuses TCP connections across requests, reducing latency by 50-100ms
 static HTTP_CLIENT: Lazy<Client> = Lazy::new(|| {
-    Client::builder()
+    crate::net::http_client(crate::net::Subsystem::Smdp)
         .pool_max_idle_per_host(10)          // Keep 10 idle connections per host
         .timeout(Duration::from_secs(30))     // 30 second timeout
         .use_rustls_tls()                     // Use rustls for TLS (async-friendly)
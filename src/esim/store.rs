@@ -0,0 +1,45 @@
+//! eSIM Profile Store
+//! Provisioned profiles are persisted as JSON keyed by ICCID
+
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use super::ESimProfile;
+use crate::migrations::{self, StoreSchema};
+use crate::storage::{KvStore, RuntimeMode};
+
+const PROFILES_TREE: &str = "esim_profiles";
+
+pub const SCHEMA: StoreSchema = StoreSchema {
+    name: "esim_profiles",
+    tree: Some(PROFILES_TREE),
+    version: 1,
+    migrations: &[],
+    index: None,
+};
+
+pub struct ProfileStore {
+    db: Box<dyn KvStore>,
+}
+
+impl ProfileStore {
+    /// Open the profile database at `path`, or keep profiles in memory when ephemeral
+    pub fn open(path: &Path, mode: RuntimeMode) -> Result<Self> {
+        let db = migrations::open_store(mode, path, &SCHEMA)
+            .with_context(|| format!("Failed to open eSIM profile store at {}", path.display()))?;
+        Ok(Self { db })
+    }
+
+    /// Insert or replace a profile
+    pub fn put(&self, profile: &ESimProfile) -> Result<()> {
+        self.db.insert(profile.iccid.as_bytes(), &serde_json::to_vec(profile)?)?;
+        self.db.flush()
+    }
+
+    pub fn get(&self, iccid: &str) -> Result<Option<ESimProfile>> {
+        self.db
+            .get(iccid.as_bytes())?
+            .map(|bytes| serde_json::from_slice(&bytes).context("Corrupt eSIM profile"))
+            .transpose()
+    }
+}
@@ -0,0 +1,675 @@
+//! eSIM Carrier Webhooks
+//! Carriers push profile and plan events to `POST /esim/webhook/<carrier_id>`,
+//! signed with an HMAC-SHA256 of the body under a per-carrier shared secret.
+//! An adapter maps each carrier's payload shape to a normalized event, which
+//! moves the stored profile through its states, is routed to notifications
+//! and is recorded in the audit log, as are rejected deliveries
+
+use anyhow::Result;
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::post;
+use axum::Router;
+use chrono::{DateTime, TimeZone, Utc};
+use parking_lot::Mutex;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+
+use super::profile::ProfileState;
+use super::store::ProfileStore;
+use crate::security::notifications::{NotificationRouter, SinkEvent};
+use crate::units::HumanSize;
+use crate::zerotrust::ZeroTrustContext;
+
+/// `sha256=<hex HMAC of the body>`
+pub const SIGNATURE_HEADER: &str = "x-carrier-signature";
+const SIGNATURE_PREFIX: &str = "sha256=";
+
+/// `[esim.webhook]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// Serve the receiver while the node runs
+    pub enabled: bool,
+    pub listen: String,
+    /// Larger deliveries are refused unread
+    pub max_body: HumanSize,
+    /// Carriers that may post, by carrier ID
+    pub carriers: BTreeMap<String, CarrierWebhook>,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:8787".to_string(),
+            max_body: HumanSize::from_bytes(64 << 10),
+            carriers: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CarrierWebhook {
+    /// HMAC-SHA256 key shared with the carrier; use a `secret://` reference
+    pub secret: String,
+    #[serde(default)]
+    pub adapter: Adapter,
+}
+
+/// How a carrier's payloads map to `CarrierEvent`s
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Adapter {
+    Generic(GenericMapping),
+    /// The shape `simulator_payload` produces
+    Simulator,
+}
+
+impl Default for Adapter {
+    fn default() -> Self {
+        Self::Generic(GenericMapping::default())
+    }
+}
+
+/// JSON pointers to each field of a flat or nested payload
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenericMapping {
+    pub event: String,
+    pub iccid: String,
+    /// RFC 3339 or Unix seconds; the receipt time when missing
+    pub occurred_at: String,
+    pub expires_at: String,
+    /// Carrier event names to normalized kinds. Names that are already a
+    /// kind (`profile_enabled`, ...) map to it unless listed
+    pub events: BTreeMap<String, EventKind>,
+}
+
+impl Default for GenericMapping {
+    fn default() -> Self {
+        Self {
+            event: "/event".to_string(),
+            iccid: "/iccid".to_string(),
+            occurred_at: "/occurred_at".to_string(),
+            expires_at: "/expires_at".to_string(),
+            events: BTreeMap::new(),
+        }
+    }
+}
+
+/// Normalized carrier event types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    ProfileEnabled,
+    ProfileDisabled,
+    /// Deleted by the user on the device; final
+    ProfileDeleted,
+    PlanExpiring,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 4] = [Self::ProfileEnabled, Self::ProfileDisabled, Self::ProfileDeleted, Self::PlanExpiring];
+
+    /// State the event moves a profile to, if it changes state
+    pub fn target_state(self) -> Option<ProfileState> {
+        match self {
+            Self::ProfileEnabled => Some(ProfileState::Enabled),
+            Self::ProfileDisabled => Some(ProfileState::Disabled),
+            Self::ProfileDeleted => Some(ProfileState::Deleted),
+            Self::PlanExpiring => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::ProfileEnabled => "profile_enabled",
+            Self::ProfileDisabled => "profile_disabled",
+            Self::ProfileDeleted => "profile_deleted",
+            Self::PlanExpiring => "plan_expiring",
+        }
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A carrier's report, whatever its payload looked like
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CarrierEvent {
+    pub carrier_id: String,
+    pub iccid: String,
+    pub kind: EventKind,
+    pub occurred_at: DateTime<Utc>,
+    /// New plan expiry, when the carrier sent one
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Why a delivery was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookRejection {
+    UnknownCarrier(String),
+    /// Missing, malformed or wrong signature
+    BadSignature,
+    /// Not JSON, or a required field is missing
+    Malformed(String),
+    /// An event name the carrier's adapter doesn't map
+    Unmapped(String),
+    UnknownProfile(String),
+    /// The event can't apply in the profile's state (it was deleted)
+    InvalidTransition { iccid: String, from: ProfileState, event: EventKind },
+    /// The profile store failed; the carrier should retry
+    Store(String),
+}
+
+impl WebhookRejection {
+    /// Stats and audit key
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnknownCarrier(_) => "unknown_carrier",
+            Self::BadSignature => "bad_signature",
+            Self::Malformed(_) => "malformed",
+            Self::Unmapped(_) => "unmapped_event",
+            Self::UnknownProfile(_) => "unknown_profile",
+            Self::InvalidTransition { .. } => "invalid_transition",
+            Self::Store(_) => "store_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::UnknownCarrier(_) | Self::UnknownProfile(_) => StatusCode::NOT_FOUND,
+            Self::BadSignature => StatusCode::UNAUTHORIZED,
+            Self::Malformed(_) => StatusCode::BAD_REQUEST,
+            Self::Unmapped(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::InvalidTransition { .. } => StatusCode::CONFLICT,
+            Self::Store(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+impl fmt::Display for WebhookRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownCarrier(carrier) => write!(f, "no webhook configured for carrier {}", carrier),
+            Self::BadSignature => write!(f, "signature does not match"),
+            Self::Malformed(reason) => write!(f, "malformed payload: {}", reason),
+            Self::Unmapped(event) => write!(f, "event type {} is not mapped", event),
+            Self::UnknownProfile(iccid) => write!(f, "no provisioned profile with ICCID {}", iccid),
+            Self::InvalidTransition { iccid, from, event } => {
+                write!(f, "{} does not apply to profile {} in state {:?}", event, iccid, from)
+            }
+            Self::Store(reason) => write!(f, "profile store unavailable: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for WebhookRejection {}
+
+/// `sha256=<hex>` signature of `body` under `secret`, as carriers send it
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    format!("{}{}", SIGNATURE_PREFIX, hex::encode(hmac::sign(&key, body)))
+}
+
+/// Constant-time check of a signature header against `body`
+fn verify(key: &hmac::Key, signature: Option<&str>, body: &[u8]) -> Result<(), WebhookRejection> {
+    let tag = signature
+        .map(|s| s.trim())
+        .map(|s| s.strip_prefix(SIGNATURE_PREFIX).unwrap_or(s))
+        .and_then(|s| hex::decode(s).ok())
+        .ok_or(WebhookRejection::BadSignature)?;
+    hmac::verify(key, body, &tag).map_err(|_| WebhookRejection::BadSignature)
+}
+
+/// RFC 3339 string or Unix seconds
+fn timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(s) => DateTime::parse_from_rfc3339(s).ok().map(|at| at.with_timezone(&Utc)),
+        Value::Number(n) => Utc.timestamp_opt(n.as_i64()?, 0).single(),
+        _ => None,
+    }
+}
+
+fn text<'a>(payload: &'a Value, pointer: &str, field: &str) -> Result<&'a str, WebhookRejection> {
+    payload
+        .pointer(pointer)
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| WebhookRejection::Malformed(format!("{} ({}) is missing", field, pointer)))
+}
+
+/// Names the simulator uses for each kind
+const SIMULATOR_EVENTS: [(&str, EventKind); 4] = [
+    ("PROFILE_ENABLED", EventKind::ProfileEnabled),
+    ("PROFILE_DISABLED", EventKind::ProfileDisabled),
+    ("PROFILE_DELETED", EventKind::ProfileDeleted),
+    ("PLAN_EXPIRY_WARNING", EventKind::PlanExpiring),
+];
+
+/// A carrier event as the simulator posts it
+pub fn simulator_payload(event: &CarrierEvent) -> Value {
+    let name = SIMULATOR_EVENTS.iter().find(|(_, kind)| *kind == event.kind).map_or("", |(name, _)| *name);
+    let mut notification = serde_json::json!({
+        "type": name,
+        "iccid": event.iccid,
+        "sentAt": event.occurred_at.timestamp(),
+    });
+    if let Some(expires_at) = event.expires_at {
+        notification["plan"] = serde_json::json!({ "expiresAt": expires_at.timestamp() });
+    }
+    serde_json::json!({ "notification": notification })
+}
+
+impl Adapter {
+    /// Normalize a payload from `carrier_id`
+    pub fn normalize(&self, carrier_id: &str, payload: &Value, received: DateTime<Utc>) -> Result<CarrierEvent, WebhookRejection> {
+        let (name, kind, iccid, occurred_at, expires_at) = match self {
+            Self::Generic(mapping) => {
+                let name = text(payload, &mapping.event, "event")?;
+                let kind = mapping
+                    .events
+                    .get(name)
+                    .copied()
+                    .or_else(|| EventKind::ALL.into_iter().find(|kind| kind.as_str() == name));
+                let at = |pointer: &str| payload.pointer(pointer).and_then(timestamp);
+                (name, kind, text(payload, &mapping.iccid, "iccid")?, at(&mapping.occurred_at), at(&mapping.expires_at))
+            }
+            Self::Simulator => {
+                let name = text(payload, "/notification/type", "type")?;
+                let kind = SIMULATOR_EVENTS.iter().find(|(n, _)| *n == name).map(|(_, kind)| *kind);
+                (
+                    name,
+                    kind,
+                    text(payload, "/notification/iccid", "iccid")?,
+                    payload.pointer("/notification/sentAt").and_then(timestamp),
+                    payload.pointer("/notification/plan/expiresAt").and_then(timestamp),
+                )
+            }
+        };
+        let kind = kind.ok_or_else(|| WebhookRejection::Unmapped(name.to_string()))?;
+        Ok(CarrierEvent {
+            carrier_id: carrier_id.to_string(),
+            iccid: iccid.to_string(),
+            kind,
+            occurred_at: occurred_at.unwrap_or(received),
+            expires_at,
+        })
+    }
+}
+
+/// Accepted deliveries, and rejected ones by `WebhookRejection::code`
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct WebhookStats {
+    pub accepted: u64,
+    pub rejected: BTreeMap<&'static str, u64>,
+}
+
+struct CarrierKey {
+    key: hmac::Key,
+    adapter: Adapter,
+}
+
+/// Verifies, normalizes and applies carrier deliveries
+pub struct WebhookReceiver {
+    carriers: HashMap<String, CarrierKey>,
+    store: ProfileStore,
+    notifier: Option<Arc<NotificationRouter>>,
+    audit: Option<ZeroTrustContext>,
+    stats: Mutex<WebhookStats>,
+}
+
+impl WebhookReceiver {
+    pub fn new(config: &WebhookConfig, store: ProfileStore) -> Result<Self> {
+        let mut carriers = HashMap::new();
+        for (carrier_id, webhook) in &config.carriers {
+            anyhow::ensure!(!webhook.secret.is_empty(), "esim.webhook.carriers.{} has no secret", carrier_id);
+            carriers.insert(
+                carrier_id.clone(),
+                CarrierKey {
+                    key: hmac::Key::new(hmac::HMAC_SHA256, webhook.secret.as_bytes()),
+                    adapter: webhook.adapter.clone(),
+                },
+            );
+        }
+        Ok(Self { carriers, store, notifier: None, audit: None, stats: Mutex::new(WebhookStats::default()) })
+    }
+
+    pub fn set_notifier(&mut self, notifier: Arc<NotificationRouter>) {
+        self.notifier = Some(notifier);
+    }
+
+    /// Record every delivery as an `esim_webhook` audit event
+    pub fn set_audit(&mut self, zt: ZeroTrustContext) {
+        self.audit = Some(zt);
+    }
+
+    pub fn stats(&self) -> WebhookStats {
+        self.stats.lock().clone()
+    }
+
+    /// Handle one delivery; the profile is updated when it is accepted
+    pub async fn receive(
+        &self,
+        carrier_id: &str,
+        signature: Option<&str>,
+        body: &[u8],
+        now: DateTime<Utc>,
+    ) -> Result<CarrierEvent, WebhookRejection> {
+        let outcome = self.process(carrier_id, signature, body, now);
+        let mut details = HashMap::from([("carrier_id".to_string(), carrier_id.to_string())]);
+        match &outcome {
+            Ok((event, from)) => {
+                self.stats.lock().accepted += 1;
+                tracing::info!("📱 {} reports {} for {}", carrier_id, event.kind, event.iccid);
+                let state = event.kind.target_state().unwrap_or(*from);
+                details.extend([
+                    ("outcome".to_string(), "accepted".to_string()),
+                    ("iccid".to_string(), event.iccid.clone()),
+                    ("event".to_string(), event.kind.to_string()),
+                    ("from_state".to_string(), format!("{:?}", from)),
+                    ("to_state".to_string(), format!("{:?}", state)),
+                ]);
+                if let Some(notifier) = &self.notifier {
+                    notifier.notify(SinkEvent::EsimEvent {
+                        carrier_id: carrier_id.to_string(),
+                        iccid: event.iccid.clone(),
+                        event: event.kind,
+                        state,
+                        plan_expires_at: event.expires_at,
+                    });
+                }
+            }
+            Err(rejection) => {
+                *self.stats.lock().rejected.entry(rejection.code()).or_default() += 1;
+                tracing::warn!("📱 Rejected webhook from {}: {}", carrier_id, rejection);
+                details.extend([
+                    ("outcome".to_string(), "rejected".to_string()),
+                    ("reason".to_string(), rejection.code().to_string()),
+                    ("detail".to_string(), rejection.to_string()),
+                ]);
+            }
+        }
+        if let Some(zt) = &self.audit {
+            if let Err(e) = zt.log_local_event("esim_webhook", details).await {
+                tracing::warn!("📱 Could not audit webhook from {}: {:#}", carrier_id, e);
+            }
+        }
+        outcome.map(|(event, _)| event)
+    }
+
+    /// The event and the profile's state before it
+    fn process(
+        &self,
+        carrier_id: &str,
+        signature: Option<&str>,
+        body: &[u8],
+        now: DateTime<Utc>,
+    ) -> Result<(CarrierEvent, ProfileState), WebhookRejection> {
+        let carrier = self.carriers.get(carrier_id).ok_or_else(|| WebhookRejection::UnknownCarrier(carrier_id.to_string()))?;
+        verify(&carrier.key, signature, body)?;
+        let payload: Value = serde_json::from_slice(body).map_err(|e| WebhookRejection::Malformed(e.to_string()))?;
+        let event = carrier.adapter.normalize(carrier_id, &payload, now)?;
+
+        let store_error = |e: anyhow::Error| WebhookRejection::Store(format!("{:#}", e));
+        let mut profile = self
+            .store
+            .get(&event.iccid)
+            .map_err(store_error)?
+            .ok_or_else(|| WebhookRejection::UnknownProfile(event.iccid.clone()))?;
+        let from = profile.state;
+        let to = event.kind.target_state().unwrap_or(from);
+        if !from.can_become(to) || (from == ProfileState::Deleted && event.kind == EventKind::PlanExpiring) {
+            return Err(WebhookRejection::InvalidTransition { iccid: event.iccid.clone(), from, event: event.kind });
+        }
+        profile.state = to;
+        if event.expires_at.is_some() {
+            profile.plan_expires_at = event.expires_at;
+        }
+        self.store.put(&profile).map_err(store_error)?;
+        Ok((event, from))
+    }
+
+    /// `POST /esim/webhook/<carrier_id>`, refusing bodies over `max_body`
+    pub fn router(self: Arc<Self>, max_body: HumanSize) -> Router {
+        Router::new()
+            .route("/esim/webhook/:carrier_id", post(deliver))
+            .layer(DefaultBodyLimit::max(max_body.bytes() as usize))
+            .with_state(self)
+    }
+}
+
+async fn deliver(
+    State(receiver): State<Arc<WebhookReceiver>>,
+    Path(carrier_id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> impl IntoResponse {
+    let signature = headers.get(SIGNATURE_HEADER).and_then(|value| value.to_str().ok());
+    let (status, body) = match receiver.receive(&carrier_id, signature, &body, Utc::now()).await {
+        Ok(event) => (StatusCode::OK, serde_json::json!({ "status": "accepted", "iccid": event.iccid, "event": event.kind })),
+        Err(rejection) => (rejection.status(), serde_json::json!({ "error": rejection.code(), "message": rejection.to_string() })),
+    };
+    (status, [(header::CONTENT_TYPE, "application/json")], body.to_string())
+}
+
+/// Serve the receiver on `listener` until the task is dropped
+pub async fn serve(receiver: Arc<WebhookReceiver>, listener: tokio::net::TcpListener, max_body: HumanSize) -> Result<()> {
+    tracing::info!("📱 eSIM webhooks listening on {}", listener.local_addr()?);
+    axum::serve(listener, receiver.router(max_body)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esim::ESimProfile;
+    use crate::security::notifications::{EventSink, RouteConfig, Severity};
+    use crate::storage::RuntimeMode;
+
+    const ICCID: &str = "8901260000000000001";
+
+    fn profile() -> ESimProfile {
+        ESimProfile {
+            iccid: ICCID.to_string(),
+            activation_code: "LPA:1$smdp.test$ABC".to_string(),
+            sm_dp_address: "smdp.test".to_string(),
+            matching_id: Some("ABC".to_string()),
+            confirmation_code: None,
+            carrier_name: "Simulated".to_string(),
+            plan_type: "data".to_string(),
+            state: ProfileState::Disabled,
+            plan_expires_at: None,
+        }
+    }
+
+    fn config() -> WebhookConfig {
+        let generic = GenericMapping {
+            event: "/data/type".to_string(),
+            iccid: "/data/profile/iccid".to_string(),
+            events: BTreeMap::from([("esim.activated".to_string(), EventKind::ProfileEnabled)]),
+            ..Default::default()
+        };
+        WebhookConfig {
+            carriers: BTreeMap::from([
+                ("sim".to_string(), CarrierWebhook { secret: "sim-secret".to_string(), adapter: Adapter::Simulator }),
+                ("acme".to_string(), CarrierWebhook { secret: "acme-secret".to_string(), adapter: Adapter::Generic(generic) }),
+            ]),
+            ..Default::default()
+        }
+    }
+
+    #[derive(Default)]
+    struct Capture(tokio::sync::Mutex<Vec<SinkEvent>>);
+    #[async_trait::async_trait]
+    impl EventSink for Capture {
+        async fn emit(&self, event: &SinkEvent) -> Result<()> {
+            self.0.lock().await.push(event.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_adapters_normalize() {
+        let now = Utc::now();
+        let at = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let event = CarrierEvent {
+            carrier_id: "sim".to_string(),
+            iccid: ICCID.to_string(),
+            kind: EventKind::PlanExpiring,
+            occurred_at: at,
+            expires_at: Some(at + chrono::Duration::days(3)),
+        };
+        assert_eq!(Adapter::Simulator.normalize("sim", &simulator_payload(&event), now).unwrap(), event);
+
+        let generic = config().carriers["acme"].adapter.clone();
+        let payload = serde_json::json!({ "data": { "type": "esim.activated", "profile": { "iccid": ICCID } } });
+        let event = generic.normalize("acme", &payload, now).unwrap();
+        assert_eq!((event.kind, event.occurred_at, event.expires_at), (EventKind::ProfileEnabled, now, None));
+        // Normalized names need no mapping
+        let payload = serde_json::json!({ "data": { "type": "profile_deleted", "profile": { "iccid": ICCID } } });
+        assert_eq!(generic.normalize("acme", &payload, now).unwrap().kind, EventKind::ProfileDeleted);
+
+        let payload = serde_json::json!({ "data": { "type": "esim.suspended", "profile": { "iccid": ICCID } } });
+        assert_eq!(generic.normalize("acme", &payload, now), Err(WebhookRejection::Unmapped("esim.suspended".to_string())));
+        let payload = serde_json::json!({ "data": { "type": "esim.activated" } });
+        assert!(matches!(generic.normalize("acme", &payload, now), Err(WebhookRejection::Malformed(_))));
+
+        // Flat default mapping, RFC 3339 times
+        let payload = serde_json::json!({
+            "event": "plan_expiring",
+            "iccid": ICCID,
+            "occurred_at": "2024-05-01T00:00:00Z",
+            "expires_at": "2024-05-04T00:00:00Z",
+        });
+        let event = Adapter::default().normalize("x", &payload, now).unwrap();
+        assert_eq!(event.occurred_at.to_rfc3339(), "2024-05-01T00:00:00+00:00");
+        assert_eq!(event.expires_at.unwrap().to_rfc3339(), "2024-05-04T00:00:00+00:00");
+    }
+
+    #[test]
+    fn test_config_parses() {
+        let config: WebhookConfig = toml::from_str(
+            r#"
+            enabled = true
+            [carriers.sim]
+            secret = "s"
+            adapter = { kind = "simulator" }
+            [carriers.acme]
+            secret = "t"
+            adapter = { kind = "generic", event = "/type", events = { "esim.activated" = "profile_enabled" } }
+            [carriers.plain]
+            secret = "u"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.carriers["sim"].adapter, Adapter::Simulator);
+        let Adapter::Generic(acme) = &config.carriers["acme"].adapter else { panic!("generic expected") };
+        assert_eq!((acme.event.as_str(), acme.iccid.as_str()), ("/type", "/iccid"));
+        assert_eq!(acme.events["esim.activated"], EventKind::ProfileEnabled);
+        assert_eq!(config.carriers["plain"].adapter, Adapter::default());
+    }
+
+    /// Signed deliveries over HTTP move the profile through its states;
+    /// tampered, unsigned, unknown and unmapped ones are refused with their
+    /// status and counted. Everything is audited
+    #[tokio::test]
+    async fn test_endpoint_applies_signed_events() {
+        let store = ProfileStore::open(std::path::Path::new("unused"), RuntimeMode::Ephemeral).unwrap();
+        store.put(&profile()).unwrap();
+        let mut receiver = WebhookReceiver::new(&config(), store).unwrap();
+        let zt = ZeroTrustContext::with_mode(RuntimeMode::Ephemeral).await.unwrap();
+        receiver.set_audit(zt.clone());
+        let capture = Arc::new(Capture::default());
+        let route = RouteConfig { categories: vec!["esim_event".to_string()], min_severity: Severity::Info, sinks: vec!["capture".to_string()] };
+        let mut router = NotificationRouter::new(vec![route], 16, 1);
+        router.add_sink("capture", capture.clone(), 60);
+        receiver.set_notifier(Arc::new(router));
+        let receiver = Arc::new(receiver);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(receiver.clone(), listener, HumanSize::from_bytes(4096)));
+        let client = reqwest::Client::new();
+        let post = |carrier: &str, signature: Option<String>, body: Vec<u8>| {
+            let mut request = client.post(format!("http://{}/esim/webhook/{}", addr, carrier)).body(body);
+            if let Some(signature) = signature {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+            async move { request.send().await.unwrap().status().as_u16() }
+        };
+        let simulated = |kind: EventKind, expires_at: Option<DateTime<Utc>>| {
+            let event = CarrierEvent { carrier_id: "sim".to_string(), iccid: ICCID.to_string(), kind, occurred_at: Utc::now(), expires_at };
+            serde_json::to_vec(&simulator_payload(&event)).unwrap()
+        };
+        let state = |receiver: &WebhookReceiver| receiver.store.get(ICCID).unwrap().unwrap();
+
+        let enabled = simulated(EventKind::ProfileEnabled, None);
+        assert_eq!(post("sim", Some(sign("sim-secret", &enabled)), enabled.clone()).await, 200);
+        assert_eq!(state(&receiver).state, ProfileState::Enabled);
+        // Redelivery is harmless
+        assert_eq!(post("sim", Some(sign("sim-secret", &enabled)), enabled.clone()).await, 200);
+
+        let expires = Utc.timestamp_opt(1_900_000_000, 0).unwrap();
+        let expiring = simulated(EventKind::PlanExpiring, Some(expires));
+        assert_eq!(post("sim", Some(sign("sim-secret", &expiring)), expiring).await, 200);
+        assert_eq!((state(&receiver).state, state(&receiver).plan_expires_at), (ProfileState::Enabled, Some(expires)));
+
+        // Tampered body, wrong key, no signature: nothing changes
+        let disabled = simulated(EventKind::ProfileDisabled, None);
+        let mut tampered = disabled.clone();
+        tampered[5] ^= 1;
+        assert_eq!(post("sim", Some(sign("sim-secret", &disabled)), tampered).await, 401);
+        assert_eq!(post("sim", Some(sign("acme-secret", &disabled)), disabled.clone()).await, 401);
+        assert_eq!(post("sim", None, disabled.clone()).await, 401);
+        assert_eq!(state(&receiver).state, ProfileState::Enabled);
+
+        assert_eq!(post("nobody", Some(sign("sim-secret", &disabled)), disabled).await, 404);
+        let unmapped = br#"{"data":{"type":"esim.suspended","profile":{"iccid":"8901260000000000001"}}}"#.to_vec();
+        assert_eq!(post("acme", Some(sign("acme-secret", &unmapped)), unmapped).await, 422);
+        let garbage = b"not json".to_vec();
+        assert_eq!(post("acme", Some(sign("acme-secret", &garbage)), garbage).await, 400);
+        let stranger = br#"{"data":{"type":"esim.activated","profile":{"iccid":"8900000000000000000"}}}"#.to_vec();
+        assert_eq!(post("acme", Some(sign("acme-secret", &stranger)), stranger).await, 404);
+        assert_eq!(post("sim", None, vec![b' '; 8192]).await, 413);
+
+        // Deleted is final
+        let deleted = simulated(EventKind::ProfileDeleted, None);
+        assert_eq!(post("sim", Some(sign("sim-secret", &deleted)), deleted).await, 200);
+        let activated = br#"{"data":{"type":"esim.activated","profile":{"iccid":"8901260000000000001"}}}"#.to_vec();
+        assert_eq!(post("acme", Some(sign("acme-secret", &activated)), activated).await, 409);
+        assert_eq!(state(&receiver).state, ProfileState::Deleted);
+
+        let stats = receiver.stats();
+        assert_eq!(stats.accepted, 4);
+        let rejected: Vec<(&str, u64)> = stats.rejected.into_iter().collect();
+        assert_eq!(
+            rejected,
+            [("bad_signature", 3), ("invalid_transition", 1), ("malformed", 1), ("unknown_carrier", 1), ("unknown_profile", 1), ("unmapped_event", 1)]
+        );
+
+        let audited: Vec<_> = zt.audit_events().await.unwrap().into_iter().filter(|e| e.event_type == "esim_webhook").collect();
+        assert_eq!(audited.len(), 12);
+        let deletion = audited.iter().find(|e| e.details.get("event").map(String::as_str) == Some("profile_deleted")).unwrap();
+        assert_eq!(deletion.details["from_state"], "Enabled");
+        assert_eq!(deletion.details["to_state"], "Deleted");
+        assert_eq!(audited.iter().filter(|e| e.details.get("reason").map(String::as_str) == Some("bad_signature")).count(), 3);
+
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let events = capture.0.lock().await.clone();
+        assert_eq!(events.len(), 4);
+        // Notifications are sent concurrently, so in any order
+        assert!(events.iter().any(|e| matches!(e, SinkEvent::EsimEvent { event: EventKind::PlanExpiring, plan_expires_at: Some(at), .. } if *at == expires)));
+        assert!(events.iter().any(|e| matches!(e, SinkEvent::EsimEvent { state: ProfileState::Deleted, .. })));
+    }
+}
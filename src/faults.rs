@@ -0,0 +1,320 @@
+//! Fault Injection
+//! Named injection sites that can be armed to fail, stall, drop or corrupt,
+//! for resilience testing. Checks compile to nothing in release builds
+//! unless the `chaos` feature is enabled
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::units::HumanDuration;
+
+/// Whether injection points are compiled into this build
+pub const ENABLED: bool = cfg!(any(debug_assertions, feature = "chaos"));
+
+/// Known injection sites
+pub const SITES: &[&str] = &["p2p.dial", "p2p.publish", "zt.evaluate", "audit.persist", "audit.forward", "esim.smdp.auth"];
+
+/// What an armed site does when it triggers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum FaultMode {
+    /// Fail the operation
+    Error,
+    /// Stall before carrying on
+    Delay { delay: HumanDuration },
+    /// Skip the operation but report success
+    Drop,
+    /// Carry on with a damaged payload
+    Corrupt,
+}
+
+impl FaultMode {
+    /// Error returned by a site failing with this mode
+    pub fn error(&self, site: &str) -> anyhow::Error {
+        anyhow::anyhow!("injected fault at {} ({})", site, self)
+    }
+}
+
+impl fmt::Display for FaultMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Delay { delay } => write!(f, "delay={}", delay),
+            Self::Drop => write!(f, "drop"),
+            Self::Corrupt => write!(f, "corrupt"),
+        }
+    }
+}
+
+impl FromStr for FaultMode {
+    type Err = anyhow::Error;
+
+    /// `error`, `drop`, `corrupt` or `delay=<duration>`
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "error" => Ok(Self::Error),
+            "drop" => Ok(Self::Drop),
+            "corrupt" => Ok(Self::Corrupt),
+            _ => match s.strip_prefix("delay=") {
+                Some(delay) => Ok(Self::Delay { delay: delay.parse()? }),
+                None => anyhow::bail!("Unknown fault mode '{}' (use error, drop, corrupt or delay=<duration>)", s),
+            },
+        }
+    }
+}
+
+/// `[[chaos.injections]]` entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Injection {
+    pub site: String,
+    #[serde(flatten)]
+    pub mode: FaultMode,
+    /// Chance of triggering on each check (0.0-1.0)
+    #[serde(default = "always")]
+    pub probability: f64,
+    /// Stop triggering after this many times (unlimited if unset)
+    #[serde(default)]
+    pub max_triggers: Option<u64>,
+}
+
+fn always() -> f64 {
+    1.0
+}
+
+impl Injection {
+    pub fn new(site: &str, mode: FaultMode) -> Self {
+        Self {
+            site: site.to_string(),
+            mode,
+            probability: 1.0,
+            max_triggers: None,
+        }
+    }
+
+    pub fn times(mut self, max_triggers: u64) -> Self {
+        self.max_triggers = Some(max_triggers);
+        self
+    }
+}
+
+/// `[chaos]` configuration; ignored when injection points are compiled out
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChaosSettings {
+    /// Seed for probabilistic injections (random if unset)
+    pub seed: Option<u64>,
+    pub injections: Vec<Injection>,
+}
+
+/// Trigger counts for one armed site
+#[derive(Debug, Clone, Serialize)]
+pub struct SiteReport {
+    pub site: String,
+    pub mode: FaultMode,
+    pub probability: f64,
+    pub max_triggers: Option<u64>,
+    pub checked: u64,
+    pub triggered: u64,
+}
+
+struct Armed {
+    injection: Injection,
+    checked: u64,
+    triggered: u64,
+}
+
+/// Armed injections by site name
+pub struct FaultRegistry {
+    /// Fast path: nothing armed
+    armed: AtomicUsize,
+    sites: Mutex<HashMap<String, Armed>>,
+    rng: Mutex<StdRng>,
+}
+
+impl FaultRegistry {
+    pub fn new() -> Self {
+        Self::with_rng(StdRng::from_entropy())
+    }
+
+    /// Registry whose probabilistic injections replay identically
+    pub fn seeded(seed: u64) -> Self {
+        Self::with_rng(StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(rng: StdRng) -> Self {
+        Self {
+            armed: AtomicUsize::new(0),
+            sites: Mutex::new(HashMap::new()),
+            rng: Mutex::new(rng),
+        }
+    }
+
+    /// Arm (or re-arm) a site, resetting its counts
+    pub fn arm(&self, injection: Injection) -> Result<()> {
+        if !SITES.contains(&injection.site.as_str()) {
+            anyhow::bail!("Unknown injection site '{}' (known: {})", injection.site, SITES.join(", "));
+        }
+        if !(0.0..=1.0).contains(&injection.probability) {
+            anyhow::bail!("Probability for {} must be between 0 and 1", injection.site);
+        }
+        let mut sites = self.sites.lock();
+        tracing::warn!("💥 Fault armed at {}: {} (p={})", injection.site, injection.mode, injection.probability);
+        sites.insert(
+            injection.site.clone(),
+            Armed {
+                injection,
+                checked: 0,
+                triggered: 0,
+            },
+        );
+        self.armed.store(sites.len(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn disarm(&self, site: &str) -> bool {
+        let mut sites = self.sites.lock();
+        let removed = sites.remove(site).is_some();
+        self.armed.store(sites.len(), Ordering::Relaxed);
+        removed
+    }
+
+    pub fn disarm_all(&self) {
+        self.sites.lock().clear();
+        self.armed.store(0, Ordering::Relaxed);
+    }
+
+    /// Arm every injection in `settings`
+    pub fn apply_settings(&self, settings: &ChaosSettings) -> Result<()> {
+        if let Some(seed) = settings.seed {
+            *self.rng.lock() = StdRng::seed_from_u64(seed);
+        }
+        for injection in &settings.injections {
+            self.arm(injection.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Consulted by injection sites (through `fault!`)
+    pub fn trigger(&self, site: &str) -> Option<FaultMode> {
+        if self.armed.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        let mut sites = self.sites.lock();
+        let armed = sites.get_mut(site)?;
+        armed.checked += 1;
+        if armed.injection.max_triggers.is_some_and(|max| armed.triggered >= max) {
+            return None;
+        }
+        if armed.injection.probability < 1.0 && !self.rng.lock().gen_bool(armed.injection.probability) {
+            return None;
+        }
+        armed.triggered += 1;
+        tracing::debug!("💥 Fault triggered at {}: {}", site, armed.injection.mode);
+        Some(armed.injection.mode.clone())
+    }
+
+    pub fn report(&self) -> Vec<SiteReport> {
+        let mut report: Vec<SiteReport> = self
+            .sites
+            .lock()
+            .values()
+            .map(|a| SiteReport {
+                site: a.injection.site.clone(),
+                mode: a.injection.mode.clone(),
+                probability: a.injection.probability,
+                max_triggers: a.injection.max_triggers,
+                checked: a.checked,
+                triggered: a.triggered,
+            })
+            .collect();
+        report.sort_by(|a, b| a.site.cmp(&b.site));
+        report
+    }
+}
+
+impl Default for FaultRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static GLOBAL: Lazy<Arc<FaultRegistry>> = Lazy::new(|| Arc::new(FaultRegistry::new()));
+
+/// Process-wide registry armed by config and the `chaos` command
+pub fn global() -> &'static Arc<FaultRegistry> {
+    &GLOBAL
+}
+
+/// Damage a payload in place (flips the first, middle and last bytes)
+pub fn corrupt(payload: &mut [u8]) {
+    if payload.is_empty() {
+        return;
+    }
+    let last = payload.len() - 1;
+    for i in [0, last / 2, last] {
+        payload[i] ^= 0xFF;
+    }
+}
+
+/// Check an injection site: `fault!("zt.evaluate")` uses the global
+/// registry, `fault!(registry, "audit.persist")` a specific one
+#[cfg(any(debug_assertions, feature = "chaos"))]
+macro_rules! fault {
+    ($site:literal) => {
+        $crate::faults::global().trigger($site)
+    };
+    ($registry:expr, $site:literal) => {
+        $registry.trigger($site)
+    };
+}
+
+#[cfg(not(any(debug_assertions, feature = "chaos")))]
+macro_rules! fault {
+    ($site:literal) => {
+        None::<$crate::faults::FaultMode>
+    };
+    ($registry:expr, $site:literal) => {{
+        let _ = &$registry;
+        None::<$crate::faults::FaultMode>
+    }};
+}
+
+pub(crate) use fault;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_triggers_probability_and_report() {
+        let registry = FaultRegistry::seeded(7);
+        assert!(registry.trigger("p2p.dial").is_none());
+        assert!(registry.arm(Injection::new("p2p.nope", FaultMode::Error)).is_err());
+
+        registry.arm(Injection::new("p2p.dial", FaultMode::Error).times(2)).unwrap();
+        let mut half = Injection::new("p2p.publish", FaultMode::Drop);
+        half.probability = 0.5;
+        registry.arm(half).unwrap();
+
+        let fired: Vec<_> = (0..4).map(|_| registry.trigger("p2p.dial")).collect();
+        assert_eq!(fired, vec![Some(FaultMode::Error), Some(FaultMode::Error), None, None]);
+        let dropped = (0..200).filter(|_| registry.trigger("p2p.publish").is_some()).count();
+        assert!((60..140).contains(&dropped), "{}", dropped);
+
+        let report = registry.report();
+        assert_eq!((report[0].site.as_str(), report[0].checked, report[0].triggered), ("p2p.dial", 4, 2));
+        assert!(registry.disarm("p2p.dial"));
+        registry.disarm_all();
+        assert!(registry.report().is_empty());
+        assert_eq!("delay=250ms".parse::<FaultMode>().unwrap().to_string(), "delay=250ms");
+    }
+}
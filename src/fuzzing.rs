@@ -0,0 +1,136 @@
+//! Fuzz Harnesses
+//! One entry point per parser that takes bytes from outside: the `fuzz/`
+//! targets feed them libFuzzer input and `tests/fuzz_regressions.rs` replays
+//! edge cases through them. Each decodes, checks the parser's invariants and
+//! re-encodes where there is an encoding, panicking when an invariant breaks;
+//! input a parser rejects is fine
+//!
+//! A new parser gets a function here, a target in `fuzz/fuzz_targets/` that
+//! calls it and a `[[bin]]` entry in `fuzz/Cargo.toml`
+
+use ed25519_dalek::SigningKey;
+use futures::executor::block_on;
+use libp2p::request_response::Codec;
+
+use crate::crypto::key_provider::FileKeyProvider;
+use crate::crypto::sealed;
+use crate::esim::activation::ActivationCode;
+use crate::esim::carriers::CarrierDatabase;
+use crate::p2p::codec::QuantraCodec;
+use crate::p2p::protocol::{self, RequestLimits};
+use crate::p2p::wire::WireFormat;
+use crate::p2p::{carrier_sync, telemetry};
+use crate::terminal;
+use crate::zerotrust::audit::{AuditLogger, SecurityEvent};
+
+/// Fixed keys, so a crash reproduces from its input alone
+const MAINTAINER_SEED: [u8; 32] = [0x11; 32];
+const REPORTER_SEED: [u8; 32] = [0x22; 32];
+const RECIPIENT_SEED: [u8; 32] = [0x33; 32];
+const AUDIT_KEY: [u8; 32] = [0x44; 32];
+
+/// Longest suffix `sanitize_for_terminal` appends when it truncates
+const TRUNCATION_SUFFIX_LEN: usize = "…[+18446744073709551615 bytes]".len();
+
+/// Activation code as scanned or pasted: raw `LPA:1$...`, iOS universal link
+/// or Android intent URI, any bytes (lossily UTF-8). A code that parses must
+/// read back the same from each form it renders
+pub fn activation_code(data: &[u8]) {
+    let Ok(code) = ActivationCode::parse(&String::from_utf8_lossy(data)) else {
+        return;
+    };
+    for rendered in [code.to_string(), code.universal_link(), code.android_intent_uri()] {
+        let reparsed = ActivationCode::parse(&rendered).unwrap_or_else(|e| panic!("{:?} does not parse: {}", rendered, e));
+        assert_eq!(reparsed, code, "{:?} parsed differently", rendered);
+    }
+}
+
+/// Request stream body from a peer, read through the codec under every
+/// protocol version and body format. A request that decodes is validated against the
+/// default `RequestLimits` and must survive a write and read unchanged
+pub fn request_envelope(data: &[u8]) {
+    for protocol in protocol::protocols(WireFormat::CborCanonical) {
+        let Ok(envelope) = block_on(QuantraCodec.read_request(&protocol, &mut &data[..])) else {
+            continue;
+        };
+        let _ = envelope.request.validate(&RequestLimits::default());
+        let mut wire = Vec::new();
+        block_on(QuantraCodec.write_request(&protocol, &mut wire, envelope.clone())).expect("decoded request re-encodes");
+        let reread = block_on(QuantraCodec.read_request(&protocol, &mut wire.as_slice())).expect("re-encoded request decodes");
+        // Compared as JSON: identity attributes are a HashMap, so the CBOR
+        // bytes may order them differently
+        assert_eq!(serde_json::to_value(&reread).ok(), serde_json::to_value(&envelope).ok());
+    }
+}
+
+/// Signed stats report gossiped on `quantra-telemetry`, as JSON. Anything
+/// that decodes is verified (and almost always rejected); a report that
+/// passes the denylist and is signed here must then verify
+pub fn signed_report(data: &[u8]) {
+    let Ok(signed) = telemetry::decode_report(data) else {
+        return;
+    };
+    let _ = signed.verify();
+    let Ok(report) = serde_json::from_value::<telemetry::NetworkStatsReport>(signed.report) else {
+        return;
+    };
+    if report.to_json().is_err() {
+        return;
+    }
+    let key = FileKeyProvider::new(SigningKey::from_bytes(&REPORTER_SEED));
+    let resigned = telemetry::SignedStatsReport::sign(&report, &key).expect("report signs");
+    let wire = telemetry::encode_report(&resigned).expect("report encodes");
+    let verified = telemetry::decode_report(&wire).expect("encoded report decodes").verify().expect("re-signed report verifies");
+    assert_eq!(verified, report);
+}
+
+/// Audit log line, read both as a line (base64 of nonce and ciphertext) and
+/// as the JSON inside one. An event that decodes must come back from its
+/// own line with the same JSON, the input to the hash chain
+pub fn audit_line(data: &[u8]) {
+    let _ = AuditLogger::decode_line(&AUDIT_KEY, &String::from_utf8_lossy(data));
+    let Ok(event) = serde_json::from_slice::<SecurityEvent>(data) else {
+        return;
+    };
+    let line = AuditLogger::encode_line(&AUDIT_KEY, &event).expect("event encodes");
+    let decoded = AuditLogger::decode_line(&AUDIT_KEY, &line).expect("encoded line decodes");
+    assert_eq!(serde_json::to_string(&decoded).unwrap(), serde_json::to_string(&event).unwrap());
+}
+
+/// Carrier database update, as JSON from `carriers publish` or the
+/// carrier DB topic. One that decodes must still verify once signed and
+/// re-encoded, and applied to the built-in database, leave what it removes
+/// removed
+pub fn carrier_update(data: &[u8]) {
+    let Ok(update) = carrier_sync::decode_update(data) else {
+        return;
+    };
+    let key = SigningKey::from_bytes(&MAINTAINER_SEED);
+    let _ = update.verify(&key.verifying_key());
+    let signed = update.sign(&key).expect("update signs");
+    let wire = carrier_sync::encode_update(&signed).expect("update encodes");
+    let update = carrier_sync::decode_update(&wire).expect("encoded update decodes");
+    update.verify(&key.verifying_key()).expect("re-encoded update verifies");
+
+    let mut db = CarrierDatabase::new();
+    let removed = update.removed.clone();
+    if db.apply_update(update, &key.verifying_key()).is_ok() {
+        for id in &removed {
+            assert!(db.get_carrier(id).is_none(), "carrier {} survived its removal", id);
+        }
+    }
+}
+
+/// Direct message body: opened as a sealed box, and sealed and rendered as
+/// the plaintext a peer sent. Sealing must round-trip, and the rendering
+/// must be inert and bounded
+pub fn direct_message(data: &[u8]) {
+    let recipient = SigningKey::from_bytes(&RECIPIENT_SEED);
+    let _ = sealed::open(&recipient, data);
+    let boxed = sealed::seal(&recipient.verifying_key(), data).expect("message seals");
+    assert_eq!(sealed::open(&recipient, &boxed).expect("sealed message opens"), data);
+
+    let rendered = terminal::sanitize_for_terminal(&String::from_utf8_lossy(data), terminal::MESSAGE_RENDER_LEN);
+    assert!(!rendered.chars().any(terminal::needs_escape), "{:?} reaches the terminal raw", rendered);
+    assert!(rendered.chars().count() <= terminal::MESSAGE_RENDER_LEN + TRUNCATION_SUFFIX_LEN);
+}
@@ -0,0 +1,31 @@
+//! QuantraBand as a library: quantitative finance, encrypted P2P messaging
+//! and eSIM provisioning. Applications embedding a node should start with
+//! [`NodeHandle`]; the `quantraband` binary is a CLI over the same modules
+
+pub mod alerts;
+pub mod approvals;
+pub mod cli_error;
+pub mod clock;
+pub mod p2p;
+pub mod crypto;
+pub mod data_dirs;
+pub mod esim;
+pub mod faults;
+#[doc(hidden)]
+pub mod fuzzing;
+pub mod logging;
+pub mod maintenance;
+pub mod migrations;
+pub mod net;
+pub mod quant;
+pub mod scheduler;
+pub mod search;
+pub mod zerotrust;
+pub mod security;
+pub mod settings;
+pub mod storage;
+pub mod terminal;
+pub mod trace;
+pub mod units;
+
+pub use p2p::handle::{NodeConfig, NodeHandle};
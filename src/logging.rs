@@ -0,0 +1,67 @@
+//! Log Level
+//! The global subscriber's filter sits behind a reload handle, so
+//! `[logging] level` can change while a node runs. `RUST_LOG`, when set,
+//! takes precedence over the configured level. JSON output logs nothing,
+//! so stdout holds only the document and stderr only the error envelope
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// `[logging]` section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingSettings {
+    /// Filter directive, e.g. `info` or `info,quantra::p2p=debug`
+    pub level: String,
+}
+
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        Self { level: "info".to_string() }
+    }
+}
+
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+/// Set by `init(true)`: the configured level is not applied
+static QUIET: OnceLock<bool> = OnceLock::new();
+
+/// Parse a filter directive without applying it
+pub fn parse_level(level: &str) -> Result<EnvFilter> {
+    EnvFilter::try_new(level).with_context(|| format!("Invalid log level '{}'", level))
+}
+
+/// Install the global subscriber at `RUST_LOG`, or `info` until
+/// `set_level` is called. `quiet` logs nothing unless `RUST_LOG` is set
+pub fn init(quiet: bool) {
+    let quiet = quiet && std::env::var_os("RUST_LOG").is_none();
+    let filter = if quiet {
+        EnvFilter::new("off")
+    } else {
+        EnvFilter::from_default_env().add_directive(tracing::Level::INFO.into())
+    };
+    let (filter, handle) = reload::Layer::new(filter);
+    tracing_subscriber::registry().with(filter).with(tracing_subscriber::fmt::layer()).init();
+    let _ = FILTER.set(handle);
+    let _ = QUIET.set(quiet);
+}
+
+/// Swap the active filter. Ignored (after validating) when `RUST_LOG` is
+/// set, in quiet mode, or when the subscriber wasn't installed by `init`
+/// (tests, embedders)
+pub fn set_level(level: &str) -> Result<()> {
+    let filter = parse_level(level)?;
+    if std::env::var_os("RUST_LOG").is_some() {
+        tracing::debug!("📝 RUST_LOG is set; keeping it over log level '{}'", level);
+        return Ok(());
+    }
+    if QUIET.get().copied().unwrap_or(false) {
+        return Ok(());
+    }
+    if let Some(handle) = FILTER.get() {
+        handle.reload(filter).context("Failed to apply log level")?;
+    }
+    Ok(())
+}
@@ -1,42 +1,121 @@
-mod p2p;
-mod crypto;
-mod esim;
-mod quant;
-mod zerotrust;
-mod security;
-
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use quantra::{
+    alerts, approvals, cli_error, clock, crypto, data_dirs, esim, faults, logging, maintenance, migrations, net, p2p, quant, scheduler, search, security, settings,
+    storage, trace, units, zerotrust,
+};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use cli_error::CliError;
 use tracing::{info, error};
 
 #[derive(Parser)]
 #[command(name = "quantraband")]
 #[command(about = "QuantraBand - Quantitative Finance, P2P Messaging, and eSIM Integration", long_about = None)]
+#[command(after_help = cli_error::EXIT_CODES_HELP)]
 struct Cli {
+    /// Path to a TOML config file (default: config/default.toml)
+    #[arg(long, global = true)]
+    config: Option<std::path::PathBuf>,
+
+    /// Keep every subsystem in memory; nothing is written to disk
+    #[arg(long, global = true)]
+    ephemeral: bool,
+
+    /// Data directory root (default: $XDG_DATA_HOME/quantra)
+    #[arg(long, global = true)]
+    data_dir: Option<std::path::PathBuf>,
+
+    /// Isolated profile under <data-dir>/profiles/<name>
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// Refuse to start if stored data needs migrating, instead of migrating it
+    #[arg(long, global = true)]
+    no_migrate: bool,
+
+    /// Output format; `json` also turns failures into an error envelope on stderr
+    #[arg(short, long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Start P2P network node
     P2p {
-        #[arg(short, long, default_value = "/ip4/0.0.0.0/tcp/0")]
-        listen: String,
+        #[arg(short, long, help = "Listen multiaddr; repeat to listen on several (default: [p2p] listen, else /ip4/0.0.0.0/tcp/0)")]
+        listen: Vec<String>,
+        #[arg(long, help = "Fail startup if any listen address cannot be bound")]
+        listen_require_all: bool,
         #[arg(long, help = "Enable Zero-Trust security for all connections")]
         zero_trust: bool,
+        #[arg(long, help = "Directory for the owned DHT record journal")]
+        dht_journal: Option<std::path::PathBuf>,
+        #[arg(long, help = "Evaluate quote alert rules and publish fired alerts to the network")]
+        alerts: bool,
+        #[arg(long, help = "Aggregate other nodes' stats reports (see telemetry summary)")]
+        telemetry_collector: bool,
+        #[arg(long, help = "Kademlia bootstrap peer as <multiaddr>/p2p/<peer id>; repeat for several")]
+        bootstrap: Vec<String>,
+        #[arg(long, help = "Node identity file, created if missing (default: keys/node_key in the data directory; a fresh key each run with --ephemeral)")]
+        identity: Option<std::path::PathBuf>,
+        #[arg(long, help = "Serve /status, /zerotrust and /shield as JSON on this address, e.g. 127.0.0.1:9100")]
+        metrics_addr: Option<std::net::SocketAddr>,
     },
     /// Generate PGP keypair
     GenerateKey {
         #[arg(short, long)]
         user_id: String,
+        #[arg(long, help = "Keystore to generate into (default: [crypto] default_keystore)")]
+        keystore: Option<String>,
     },
     /// Encrypt a message
     Encrypt {
-        #[arg(short, long)]
+        #[arg(short, long, help = "Recipient key: fingerprint or user ID, optionally as <keystore>:<key>")]
         recipient: String,
         #[arg(short, long)]
         message: String,
+        #[arg(long, help = "Keystore holding the recipient key (default: search all)")]
+        keystore: Option<String>,
+        #[arg(long, help = "Also sign with this key: fingerprint or <keystore>:<fingerprint>")]
+        sign_with: Option<String>,
+        #[arg(long, requires = "sign_with", help = "Keystore holding the signing key (default: search all)")]
+        sign_keystore: Option<String>,
+        #[arg(long, help = "Write the armored message to this file instead of stdout")]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Decrypt an armored message with a key from the keystores
+    Decrypt {
+        #[arg(long, help = "Read the armored message from this file (default: stdin)")]
+        input: Option<std::path::PathBuf>,
+        #[arg(long, help = "Require a signature by this key: fingerprint or user ID, optionally as <keystore>:<key>")]
+        from: Option<String>,
+    },
+    /// Detached, armored signature of a message with a key from the keystores
+    Sign {
+        #[arg(short, long, help = "Signing key: fingerprint or user ID, optionally as <keystore>:<key>")]
+        key: String,
+        #[arg(short, long)]
+        message: String,
+        #[arg(long, help = "Write the armored signature to this file instead of stdout")]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Check a detached signature against a key from the keystores
+    Verify {
+        #[arg(short, long, help = "Signer's key: fingerprint or user ID, optionally as <keystore>:<key>")]
+        key: String,
+        #[arg(short, long)]
+        message: String,
+        #[arg(long, help = "File holding the armored signature")]
+        signature: std::path::PathBuf,
     },
     /// Provision an eSIM profile
     ProvisionEsim {
@@ -46,6 +125,26 @@ enum Commands {
         plan: String,
         #[arg(long, help = "Use secure TLS 1.3 + E2E encryption")]
         secure: bool,
+        #[arg(long, help = "Activation output: lpa, qr-png, qr-svg, universal-link or android-intent")]
+        format: Option<esim::activation::ActivationFormat>,
+        #[arg(long, help = "Write the formatted activation to this file")]
+        out: Option<std::path::PathBuf>,
+        #[arg(long, help = "Device EID (32 digits); checks compatibility first")]
+        eid: Option<String>,
+        #[arg(long, requires = "eid", help = "Abort instead of warning when the device looks incompatible")]
+        strict: bool,
+        #[arg(long, help = "Abort instead of warning when the carrier's SM-DP+ is not healthy")]
+        require_healthy: bool,
+    },
+    /// Sign carrier database updates (maintainers)
+    CarrierUpdate {
+        #[command(subcommand)]
+        action: CarrierUpdateAction,
+    },
+    /// Inspect provisioned eSIM profiles
+    Esim {
+        #[command(subcommand)]
+        action: EsimAction,
     },
     /// Calculate option price
     OptionPrice {
@@ -55,17 +154,345 @@ enum Commands {
         strike: f64,
         #[arg(long)]
         rate: f64,
+        #[arg(long, help = "Volatility (black-scholes and binomial)")]
+        volatility: Option<f64>,
         #[arg(long)]
-        volatility: f64,
+        time: f64,
+        #[arg(long, default_value = "call")]
+        option_type: String,
+        #[arg(long, default_value = "black-scholes", help = "Pricing model: black-scholes, binomial (American) or heston")]
+        model: String,
+        #[arg(long, default_value_t = quant::binomial::DEFAULT_BINOMIAL_STEPS, help = "Binomial tree steps")]
+        steps: usize,
+        #[arg(long, default_value_t = 0.0, help = "Continuous dividend yield")]
+        dividend_yield: f64,
+        #[arg(long, help = "Print the early-exercise boundary (binomial only)")]
+        boundary: bool,
+        #[arg(long, help = "Heston v0,kappa,theta,sigma_v,rho (heston only)")]
+        heston_params: Option<String>,
+        #[arg(long, help = "Greeks by finite differences of the model price (always for heston)")]
+        numerical_greeks: bool,
+    },
+    /// Price a fixed-coupon bond at a yield, or find the yield from a price
+    BondPrice {
+        #[arg(long, default_value_t = 100.0)]
+        face: f64,
+        #[arg(long, help = "Annual coupon rate, e.g. 0.05 (0 for a zero-coupon bond)")]
+        coupon_rate: f64,
+        #[arg(long, allow_negative_numbers = true, required_unless_present = "price", conflicts_with = "price", help = "Annual yield to maturity, e.g. 0.04")]
+        ytm: Option<f64>,
+        #[arg(long, help = "Price to solve the yield from")]
+        price: Option<f64>,
+        #[arg(long, default_value_t = 2, help = "Coupons per year")]
+        periods_per_year: u32,
+        #[arg(long, help = "Years to maturity, a whole number of coupon periods")]
+        years: f64,
+    },
+    /// Payoff at expiry of a multi-leg option position, across a spot range
+    #[command(visible_alias = "strategy")]
+    OptionStrategy {
+        #[arg(long = "leg", required = true, help = "type:strike:quantity:premium, repeatable; a negative quantity writes the option")]
+        legs: Vec<quant::strategy::OptionLeg>,
+        #[arg(long, help = "Lowest spot in the table (default: 80% of the lowest strike)")]
+        from: Option<f64>,
+        #[arg(long, help = "Highest spot in the table (default: 120% of the highest strike)")]
+        to: Option<f64>,
+        #[arg(long, default_value_t = 10, help = "Intervals between the lowest and highest spot")]
+        steps: usize,
+        #[arg(long, help = "Spot for net Greeks (with --volatility and --time)")]
+        spot: Option<f64>,
+        #[arg(long, default_value_t = 0.0)]
+        rate: f64,
+        #[arg(long)]
+        volatility: Option<f64>,
+        #[arg(long)]
+        time: Option<f64>,
+    },
+    /// Price a strip of strikes; with market vols, compare the model smile
+    OptionChain {
+        #[arg(long)]
+        spot: f64,
+        #[arg(long)]
+        rate: f64,
         #[arg(long)]
         time: f64,
+        #[arg(long, value_delimiter = ',', required = true, help = "Strikes (comma-separated)")]
+        strikes: Vec<f64>,
+        #[arg(long, default_value = "black-scholes", help = "Pricing model: black-scholes or heston")]
+        model: String,
+        #[arg(long, help = "Volatility (black-scholes)")]
+        volatility: Option<f64>,
+        #[arg(long, help = "Heston v0,kappa,theta,sigma_v,rho")]
+        heston_params: Option<String>,
+        #[arg(long, value_delimiter = ',', help = "Market implied vols, one per strike")]
+        market_vols: Vec<f64>,
+        #[arg(long, help = "Fit Heston to --market-vols instead of using --heston-params")]
+        calibrate: bool,
+    },
+    /// Simulate periodic delta hedging of an option over a candle path
+    HedgeSim {
+        #[arg(short, long)]
+        symbol: String,
+        #[arg(long)]
+        strike: f64,
+        #[arg(long, help = "Expiry date (YYYY-MM-DD, 00:00 UTC) or RFC 3339 timestamp")]
+        expiry: String,
+        #[arg(long, help = "Candle CSV (symbol,timestamp,open,high,low,close,volume)")]
+        path: std::path::PathBuf,
+        #[arg(long, default_value = "1d", help = "Re-hedge interval (e.g. 30m, 4h, 1d)")]
+        rehedge: units::HumanDuration,
+        #[arg(long, default_value_t = 2.0)]
+        cost_bps: f64,
+        #[arg(long)]
+        volatility: f64,
+        #[arg(long, default_value_t = 0.05)]
+        rate: f64,
         #[arg(long, default_value = "call")]
         option_type: String,
+        #[arg(long, default_value_t = -1.0, allow_hyphen_values = true, help = "Options held in units of the underlying (negative = written)")]
+        quantity: f64,
+        #[arg(long, default_value = "black-scholes", help = "Pricing model: black-scholes or binomial (American)")]
+        model: String,
+        #[arg(long, default_value_t = quant::binomial::DEFAULT_BINOMIAL_STEPS, help = "Binomial tree steps")]
+        steps: usize,
+        #[arg(long, help = "Back-adjust the candles for the recorded splits and dividends (the file is unchanged)")]
+        adjusted: bool,
+    },
+    /// Attribute option portfolio P&L to Greeks over a market history
+    Attribute {
+        #[arg(long, help = "JSON list of option positions (symbol, option_type, strike, expiry, quantity)")]
+        portfolio: std::path::PathBuf,
+        #[arg(long, help = "Market context CSV (symbol,timestamp,spot,volatility,rate)")]
+        history: std::path::PathBuf,
+        #[arg(long, help = "Start date (YYYY-MM-DD, 00:00 UTC) or RFC 3339 timestamp")]
+        from: String,
+        #[arg(long, help = "End date (YYYY-MM-DD, 00:00 UTC) or RFC 3339 timestamp")]
+        to: String,
+        #[arg(long, default_value = "black-scholes", help = "Pricing model: black-scholes or binomial (American)")]
+        model: String,
+        #[arg(long, default_value_t = quant::binomial::DEFAULT_BINOMIAL_STEPS, help = "Binomial tree steps")]
+        steps: usize,
+        #[arg(long, default_value_t = quant::attribution::DEFAULT_RESIDUAL_THRESHOLD, help = "Flag positions whose daily residual exceeds this share of gross P&L")]
+        residual_threshold: f64,
+    },
+    /// Re-price option positions as the underlying quotes move
+    Watch {
+        #[arg(long, help = "JSON list of option positions (symbol, option_type, strike, expiry, quantity, average_cost[, volatility])")]
+        portfolio: std::path::PathBuf,
+        #[arg(long, default_value = "2s", help = "Time between ticks (e.g. 2s, 1m)")]
+        interval: units::HumanDuration,
+        #[arg(long, help = "Print one snapshot and exit")]
+        once: bool,
+        #[arg(long, default_value_t = 0.05)]
+        rate: f64,
+        #[arg(long, default_value_t = 0.2, help = "Volatility for positions that don't set their own")]
+        volatility: f64,
+        #[arg(long, default_value = "black-scholes", help = "Pricing model: black-scholes or binomial (American)")]
+        model: String,
+        #[arg(long, default_value_t = quant::binomial::DEFAULT_BINOMIAL_STEPS, help = "Binomial tree steps")]
+        steps: usize,
+    },
+    /// Replay a candle history through a WebAssembly strategy plugin
+    Backtest {
+        #[arg(long, help = "Candle CSV (symbol,timestamp,open,high,low,close,volume), oldest first")]
+        candles: std::path::PathBuf,
+        #[arg(short, long, help = "Only trade this symbol's candles")]
+        symbol: Option<String>,
+        #[arg(long, help = "Strategy plugin (.wasm, or .wat text)")]
+        strategy_wasm: std::path::PathBuf,
+        #[arg(long, help = "JSON file passed to the plugin's init (default: {})")]
+        strategy_config: Option<std::path::PathBuf>,
+        #[arg(long, default_value = "1", help = "Quantity bought on a buy signal and sold on a sell signal")]
+        quantity: rust_decimal::Decimal,
+        #[arg(long, help = "Starting cash (default: [portfolio.account] starting_cash)")]
+        cash: Option<rust_decimal::Decimal>,
+        #[arg(long, default_value = "stock", help = "Asset type whose margin rates apply")]
+        asset_type: quant::AssetType,
+        #[command(flatten)]
+        sizing: SizingArgs,
+        #[arg(long, default_value_t = 20, help = "Candles of history behind --size-by volatility and volume estimates")]
+        sizing_lookback: usize,
+        #[arg(long, help = "Back-adjust the candles for the recorded splits and dividends (reads the whole file into memory; the file is unchanged)")]
+        adjusted: bool,
+    },
+    /// Network stats gathered by a telemetry collector
+    Telemetry {
+        #[command(subcommand)]
+        action: TelemetryAction,
+    },
+    /// Measure the gossip throughput, connection churn or request latency a node sustains
+    Loadtest {
+        #[arg(long, default_value = "gossip", help = "gossip, connect or request")]
+        mode: p2p::loadtest::LoadMode,
+        #[arg(long, help = "Remote node to load over TCP (default: a node in this process with this config)")]
+        target: Option<String>,
+        #[arg(long, default_value_t = 4, help = "Client nodes (gossip and request modes)")]
+        clients: usize,
+        #[arg(long, default_value_t = 10.0, help = "Messages or requests per second per client; connections per second in connect mode")]
+        rate: f64,
+        #[arg(long, default_value = "10s")]
+        duration: units::HumanDuration,
+        #[arg(long, default_value = "256B", help = "Gossip message size")]
+        message_size: units::HumanSize,
+        #[arg(long, default_value = p2p::DEFAULT_TOPIC, help = "Gossip topic (a remote target must have joined it)")]
+        topic: String,
+        #[arg(long, default_value = "ping", help = "ping, quote or quote:<symbol>")]
+        request: p2p::loadtest::RequestKind,
+        #[arg(long, default_value = "2s", help = "Wait for in-flight messages after the last send")]
+        drain: units::HumanDuration,
+    },
+    /// Outbound connections ([network.proxy])
+    Network {
+        #[command(subcommand)]
+        action: NetworkAction,
+    },
+    /// Node identity and audit key storage ([keys] provider)
+    Keys {
+        #[command(subcommand)]
+        action: KeysAction,
+    },
+    /// Sealed config secrets, referenced from config as secret://<name>
+    Secrets {
+        #[command(subcommand)]
+        action: SecretsAction,
+    },
+    /// The loaded settings, with secrets left out
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Manage and run watch-only quote alert rules
+    Alerts {
+        #[command(subcommand)]
+        action: AlertAction,
+    },
+    /// Positions, stop-loss / take-profit rules and the trade ledger
+    Portfolio {
+        #[command(subcommand)]
+        action: PortfolioAction,
+    },
+    /// Splits and dividends, applied to portfolio positions from their ex-date
+    Actions {
+        #[command(subcommand)]
+        action: ActionsAction,
+    },
+    /// The portfolio's trade ledger
+    Trades {
+        #[command(subcommand)]
+        action: TradesAction,
+    },
+    /// Historical and parametric VaR, Sharpe ratio and drawdown of the portfolio
+    RiskReport {
+        #[arg(long, default_value_t = 0.95, help = "VaR confidence level, between 0 and 1")]
+        confidence: f64,
+        #[arg(long, default_value_t = 252, help = "Days of daily closes to draw returns from")]
+        days: u32,
+        #[arg(long, default_value_t = 1, help = "VaR horizon in trading days")]
+        horizon: u32,
+        #[arg(long, help = "Market data source: mock or http (default: [quant.market_data] source)")]
+        source: Option<quant::market_data::SourceKind>,
+        #[arg(long, value_enum, requires = "out", help = "Also write the metrics to --out as csv or parquet")]
+        export: Option<quant::export::ExportFormat>,
+        #[arg(long, requires = "export", help = "File for --export")]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Time- and money-weighted returns from the recorded daily valuations
+    Performance {
+        #[arg(long, default_value = "ytd", help = "ytd, 1y or all")]
+        period: quant::performance::ReportPeriod,
+    },
+    /// Quote symbols to watch, encrypted under this node's identity key
+    Watchlist {
+        #[command(subcommand)]
+        action: WatchlistAction,
     },
     /// Get market quote
     Quote {
         #[arg(short, long)]
         symbol: String,
+        #[arg(long, help = "Market data source: mock or http (default: [quant.market_data] source)")]
+        source: Option<quant::market_data::SourceKind>,
+    },
+    /// OHLCV candles for a symbol
+    History {
+        #[arg(short, long)]
+        symbol: String,
+        #[arg(long, default_value_t = 30, help = "How far back to go")]
+        days: u32,
+        #[arg(long, default_value = "1d", help = "Candle width: 1h, 1d or 1wk")]
+        interval: quant::market_data::Interval,
+        #[arg(long, help = "Market data source: mock or http (default: [quant.market_data] source)")]
+        source: Option<quant::market_data::SourceKind>,
+        #[arg(long, value_enum, requires = "out", help = "Also write the candles to --out as csv or parquet")]
+        export: Option<quant::export::ExportFormat>,
+        #[arg(long, requires = "export", help = "File for --export")]
+        out: Option<std::path::PathBuf>,
+        #[arg(long, conflicts_with_all = ["export", "out"], help = "Also write the candles to this CSV file (--export csv --out)")]
+        csv: Option<std::path::PathBuf>,
+    },
+    /// Direct messages received, with their read status (node must be stopped)
+    Inbox {
+        #[command(subcommand)]
+        action: Option<InboxAction>,
+    },
+    /// Delivery and read receipts of direct messages (node must be stopped)
+    Message {
+        #[command(subcommand)]
+        action: MessageAction,
+    },
+    /// Direct messages queued for offline peers
+    Outbox {
+        #[command(subcommand)]
+        action: OutboxAction,
+    },
+    /// Decisions waiting on an operator (held carrier updates, bait wallet
+    /// deactivations, full wipes, wards' emergency requests)
+    Approvals {
+        #[command(subcommand)]
+        action: ApprovalsAction,
+    },
+    /// Guardian quorum for destructive emergency responses ([guardians])
+    Guardians {
+        #[command(subcommand)]
+        action: GuardiansAction,
+    },
+    /// Check an exported chat transcript's hash chain and signatures
+    VerifyTranscript {
+        /// Bundle written by `NodeHandle::export_transcript`
+        bundle: std::path::PathBuf,
+        #[arg(long = "signer", help = "Peer ID that must have signed (repeatable)")]
+        signers: Vec<String>,
+        #[arg(long, help = "Fail unless both parties signed")]
+        require_cosigned: bool,
+    },
+    /// Dump everything on disk about a peer (node may be stopped)
+    Dossier {
+        #[arg(short, long)]
+        peer: String,
+    },
+    /// Zero-trust access policies
+    Policy {
+        #[command(subcommand)]
+        action: PolicyAction,
+    },
+    /// Bait wallet, Mirror Shield and canary events as STIX threat intel
+    Intel {
+        #[command(subcommand)]
+        action: IntelAction,
+    },
+    /// Query the audit log (node may be stopped)
+    Audit {
+        #[arg(long, help = "Only events recorded under this trace ID")]
+        trace: Option<trace::TraceId>,
+        #[arg(short, long, help = "Only events about this peer")]
+        peer: Option<String>,
+    },
+    /// Show L2 market depth
+    Depth {
+        #[arg(short, long)]
+        symbol: String,
+        #[arg(short, long, default_value = "10", help = "Levels per side")]
+        levels: usize,
     },
     /// List supported eSIM carriers
     ListCarriers {
@@ -73,6 +500,31 @@ enum Commands {
         country: Option<String>,
         #[arg(short, long, help = "Search carriers by name")]
         search: Option<String>,
+        #[arg(long, help = "Show each carrier's SM-DP+ health")]
+        with_health: bool,
+    },
+    /// Search carriers, the portfolio and trade ledger, messages and the audit log
+    Search {
+        query: String,
+        #[arg(long = "store", help = "Only search this store: carriers, portfolio, messages or audit (repeatable)")]
+        stores: Vec<search::SearchStore>,
+        #[arg(long, default_value_t = search::DEFAULT_LIMIT, help = "Most hits per store")]
+        limit: usize,
+    },
+    /// Rotate and verify the audit log, prune retained data and compact stores
+    Maintain {
+        #[arg(long, help = "Run every step now (stop the node first); otherwise show the schedule")]
+        now: bool,
+    },
+    /// Migrate stored data to this build's format (also done at startup)
+    Migrate {
+        #[arg(long, help = "Only list pending migrations per store")]
+        dry_run: bool,
+    },
+    /// Storage backends
+    Storage {
+        #[command(subcommand)]
+        action: StorageAction,
     },
     /// Zero-Trust security status
     ZeroTrustStatus,
@@ -85,133 +537,2555 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+enum CarrierUpdateAction {
+    /// Generate a maintainer keypair (hex)
+    Keygen,
+    /// Sign an update JSON file with a maintainer secret key
+    Sign {
+        #[arg(long)]
+        input: std::path::PathBuf,
+        #[arg(long, help = "File containing the hex secret key")]
+        key: std::path::PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum EsimAction {
+    /// Check whether a device (by EID) can take a carrier's profiles
+    Check {
+        #[arg(long, help = "Device EID (32 digits)")]
+        eid: String,
+        #[arg(short, long)]
+        carrier: String,
+    },
+    /// Show a provisioned profile's activation code
+    Show {
+        #[arg(long)]
+        iccid: String,
+        #[arg(long, default_value = "lpa", help = "lpa, qr-png, qr-svg, universal-link or android-intent")]
+        format: esim::activation::ActivationFormat,
+        #[arg(long, help = "Write the formatted activation to this file")]
+        out: Option<std::path::PathBuf>,
+    },
+    /// SM-DP+ health of the configured carriers
+    Health {
+        #[arg(short, long, help = "Only this carrier")]
+        carrier: Option<String>,
+        #[arg(long, help = "Probe now instead of showing recorded health")]
+        probe: bool,
+        #[arg(long, conflicts_with = "probe", help = "Probe every esim.health.interval until Ctrl-C")]
+        watch: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TelemetryAction {
+    /// Aggregate stats from the last reports this node collected
+    Summary,
+}
+
+#[derive(Subcommand)]
+enum NetworkAction {
+    /// Check the configured proxy is reachable and show the egress IP
+    Doctor,
+}
+
+#[derive(Subcommand)]
+enum KeysAction {
+    /// Check the configured key provider can open, sign and unwrap keys
+    Doctor,
+    /// Keys grouped by keystore ([crypto.keystores])
+    List {
+        #[arg(long, help = "Only this keystore")]
+        keystore: Option<String>,
+    },
+    /// Add a peer's armored public key; importing it again changes nothing
+    Import {
+        #[arg(long, help = "Read the armored key from this file (default: stdin)")]
+        input: Option<std::path::PathBuf>,
+        #[arg(long, help = "Keystore to import into (default: [crypto] default_keystore)")]
+        keystore: Option<String>,
+    },
+    /// Print a key's armored public key, or its secret key with --secret --confirm
+    Export {
+        #[arg(help = "Fingerprint or user ID, optionally as <keystore>:<key>")]
+        key: String,
+        #[arg(long, help = "Keystore holding the key (default: search all)")]
+        keystore: Option<String>,
+        #[arg(long, help = "Export the secret key instead")]
+        secret: bool,
+        #[arg(long, requires = "secret", help = "Confirm the secret key may leave the keystore")]
+        confirm: bool,
+    },
+    /// Delete a key and its secret key
+    Delete {
+        #[arg(help = "Fingerprint or user ID, optionally as <keystore>:<key>")]
+        key: String,
+        #[arg(long, help = "Keystore holding the key (default: search all)")]
+        keystore: Option<String>,
+    },
+    /// Protect a keystore with the passphrase from its passphrase variable
+    Passphrase {
+        #[arg(long, help = "Keystore to protect (default: [crypto] default_keystore)")]
+        keystore: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SecretsAction {
+    /// Store a secret, read from stdin unless --from-env is given
+    Set {
+        name: String,
+        #[arg(long, help = "Read the value from this environment variable")]
+        from_env: Option<String>,
+    },
+    /// Secret names and the config keys referencing them (never values)
+    List,
+    /// Delete a stored secret
+    Rm { name: String },
+    /// Re-encrypt the secrets under a new data key, wrapped by the configured key provider
+    RotateKey,
+}
+
+#[derive(Subcommand)]
+enum StorageAction {
+    /// Copy every sled store into another backend, verifying entry counts;
+    /// set storage.backend afterwards to use it
+    Migrate {
+        #[arg(long, help = "Backend to copy into (sqlite)")]
+        to: storage::BackendKind,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Settings as loaded; secret:// references stay unresolved and other
+    /// passwords, PINs and URLs are masked
+    Show,
+}
+
+#[derive(Subcommand)]
+enum WatchlistAction {
+    /// Watch a symbol
+    Add { symbol: String },
+    /// Stop watching a symbol
+    Remove { symbol: String },
+    /// Watched symbols
+    Show,
+    /// Merge with the DHT copies of this identity's other nodes
+    Sync {
+        #[arg(long, help = "Sync once now, through a short-lived node (`p2p` syncs periodically with [quant.watchlist] sync)")]
+        now: bool,
+        #[arg(long, help = "Multiaddr of a node to reach the DHT through; repeatable")]
+        peer: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AlertAction {
+    /// Add a rule (exactly one condition)
+    Add {
+        #[arg(short, long)]
+        symbol: String,
+        #[arg(long, help = "Fire when the last price rises above this")]
+        above: Option<rust_decimal::Decimal>,
+        #[arg(long, help = "Fire when the last price falls below this")]
+        below: Option<rust_decimal::Decimal>,
+        #[arg(long, help = "Fire when the absolute % change over --window exceeds this")]
+        pct_change: Option<rust_decimal::Decimal>,
+        #[arg(long, default_value = "15m", help = "Window for --pct-change (e.g. 15m, 1h)")]
+        window: units::HumanDuration,
+        #[arg(long, help = "Fire when ask - bid exceeds this")]
+        spread_above: Option<rust_decimal::Decimal>,
+        #[arg(long, default_value = "30m", help = "Minimum time between firings")]
+        cooldown: units::HumanDuration,
+    },
+    /// List rules
+    List,
+    /// Remove a rule
+    Remove { id: String },
+    /// Poll quotes and deliver fired alerts until interrupted
+    Run,
+}
+
+#[derive(Subcommand)]
+enum IntelAction {
+    /// Write journaled events ([intel] enabled) as a STIX 2.1 bundle
+    Export {
+        #[arg(long, default_value = "24h", help = "Events from this far back (e.g. 24h, 7d)")]
+        since: units::HumanDuration,
+        #[arg(short, long, help = "Bundle file (stdout when omitted)")]
+        out: Option<std::path::PathBuf>,
+        #[arg(long, help = "Include events from private, loopback and link-local addresses")]
+        include_private: bool,
+    },
+    /// Push events not yet sent to the [intel.taxii] collection
+    Push,
+}
+
+#[derive(Subcommand)]
+enum PolicyAction {
+    /// Replay recorded access decisions against a proposed policy file and
+    /// report what would change; nothing is logged. Connected peers' current
+    /// attributes can be included from the node console (`policy simulate`)
+    Simulate {
+        #[arg(short, long, help = "Policy file (YAML, JSON or TOML)")]
+        file: std::path::PathBuf,
+        #[arg(long, default_value = "7d", help = "Replay decisions from this far back (e.g. 24h, 7d)")]
+        since: units::HumanDuration,
+    },
+}
+
+#[derive(Subcommand)]
+enum InboxAction {
+    /// List received messages (the default)
+    List {
+        #[arg(long, help = "Only messages not yet read")]
+        unread: bool,
+    },
+    /// Mark a message read; the sender gets a read receipt when the node
+    /// next reaches it, unless [p2p.receipts] send_read_receipts is off
+    Read { id: String },
+}
+
+#[derive(Subcommand)]
+enum OutboxAction {
+    /// Queued entries, log size, oldest message age and last compaction
+    Stats,
+}
+
+#[derive(Subcommand)]
+enum ApprovalsAction {
+    /// Pending requests, oldest first
+    List,
+    /// Approve a request; carried out when the node next starts (while it
+    /// runs, use its `approvals` console command)
+    Approve { id: String },
+    /// Deny a request
+    Deny { id: String },
+}
+
+#[derive(Subcommand)]
+enum GuardiansAction {
+    /// Hash a break-glass passphrase, typed twice at the terminal, for
+    /// [guardians.break_glass] passphrase_hash
+    HashPassphrase,
+}
+
+#[derive(Subcommand)]
+enum MessageAction {
+    /// Receipt timeline of a sent or received message
+    Status { id: String },
+}
+
+/// What `--size-by` sizes a position by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SizeBy {
+    VolTarget,
+    Kelly,
+}
+
+/// Position sizing options shared by `portfolio buy` and `backtest`
+#[derive(Debug, Clone, clap::Args)]
+struct SizingArgs {
+    #[arg(long, help = "Size the position instead of using --quantity")]
+    size_by: Option<SizeBy>,
+    #[arg(long, value_parser = percent_arg, help = "Annual volatility to target, e.g. 10% (--size-by vol-target)")]
+    target_vol: Option<rust_decimal::Decimal>,
+    #[arg(long, help = "Chance a trade wins, 0-1 (--size-by kelly)")]
+    win_prob: Option<f64>,
+    #[arg(long, help = "Average win over average loss (--size-by kelly)")]
+    win_loss_ratio: Option<f64>,
+    #[arg(long, default_value_t = 0.5, help = "Share of full Kelly to bet")]
+    kelly_fraction: f64,
+    #[arg(long, value_parser = percent_arg, default_value = "25%", help = "Most of the portfolio Kelly sizing may commit")]
+    kelly_cap: rust_decimal::Decimal,
+    #[arg(long, value_parser = percent_arg, help = "Also cap the size at this share of average daily volume, e.g. 10%")]
+    participation: Option<rust_decimal::Decimal>,
+    #[arg(long, default_value = "1", help = "Round sizes down to this lot size (0 for fractional)")]
+    lot_size: rust_decimal::Decimal,
+}
+
+impl SizingArgs {
+    /// The policy `--size-by` asks for, if any
+    fn policy(&self, lookback: usize) -> Result<Option<quant::sizing::SizingPolicy>> {
+        let Some(size_by) = self.size_by else { return Ok(None) };
+        let target_annual_vol = match (size_by, self.target_vol) {
+            (SizeBy::VolTarget, None) => anyhow::bail!(CliError::validation("INVALID_SIZING", "--size-by vol-target needs --target-vol")),
+            (SizeBy::VolTarget, Some(pct)) => Some(fraction(pct)),
+            (SizeBy::Kelly, _) => None,
+        };
+        let kelly = match (size_by, self.win_prob, self.win_loss_ratio) {
+            (SizeBy::Kelly, Some(win_prob), Some(win_loss_ratio)) => Some(quant::sizing::KellyInputs {
+                win_prob,
+                win_loss_ratio,
+                fraction: self.kelly_fraction,
+                max_fraction: fraction(self.kelly_cap),
+            }),
+            (SizeBy::Kelly, _, _) => {
+                anyhow::bail!(CliError::validation("INVALID_SIZING", "--size-by kelly needs --win-prob and --win-loss-ratio"))
+            }
+            (SizeBy::VolTarget, _, _) => None,
+        };
+        Ok(Some(quant::sizing::SizingPolicy {
+            target_annual_vol,
+            kelly,
+            participation_cap: self.participation.map(fraction),
+            lot_size: self.lot_size,
+            lookback,
+        }))
+    }
+}
+
+#[derive(Subcommand)]
+enum ActionsAction {
+    /// Record a split, cash dividend or stock dividend
+    Add {
+        symbol: String,
+        #[arg(help = "split, cash-dividend or stock-dividend")]
+        kind: String,
+        #[arg(allow_hyphen_values = true, help = "Split ratio (4:1, or 1:10 reverse), dividend per share (0.24) or stock dividend (5%)")]
+        value: String,
+        #[arg(long, help = "Ex-date, YYYY-MM-DD")]
+        ex_date: chrono::NaiveDate,
+    },
+    /// Record the actions in a CSV (symbol,ex_date,kind,value)
+    Import { path: std::path::PathBuf },
+    /// Recorded actions and how they were applied
+    List,
+    /// Apply the actions due by today now (also done daily while alerts run)
+    Apply,
+}
+
+#[derive(Subcommand)]
+enum TradesAction {
+    /// List recorded trades (same as `portfolio ledger`)
+    List {
+        #[arg(long, value_enum, requires = "out", help = "Write the trades to --out as csv or parquet instead")]
+        export: Option<quant::export::ExportFormat>,
+        #[arg(long, requires = "export", help = "File for --export")]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Record the trades in a CSV from `trades list --export csv`, skipping ids already recorded
+    Import { path: std::path::PathBuf },
+}
+
+#[derive(Subcommand)]
+enum PortfolioAction {
+    /// Show positions and their risk rules
+    Show {
+        #[arg(long, value_enum, requires = "out", help = "Also write the positions to --out as csv or parquet")]
+        export: Option<quant::export::ExportFormat>,
+        #[arg(long, requires = "export", help = "File for --export")]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Total value and unrealized P&L
+    Value {
+        #[arg(long, help = "Mark positions to current quotes first")]
+        refresh: bool,
+    },
+    /// Write the positions to a file: CSV or Parquet by extension, else JSON
+    Export {
+        #[arg(long)]
+        out: std::path::PathBuf,
+    },
+    /// Record a buy
+    #[command(visible_alias = "add")]
+    Buy {
+        #[arg(short, long)]
+        symbol: String,
+        #[arg(short, long, visible_alias = "qty", required_unless_present = "size_by", conflicts_with = "size_by")]
+        quantity: Option<rust_decimal::Decimal>,
+        #[arg(short, long)]
+        price: rust_decimal::Decimal,
+        #[command(flatten)]
+        sizing: SizingArgs,
+        #[arg(long, value_parser = percent_arg, help = "The symbol's annual volatility, e.g. 25% (--size-by vol-target)")]
+        asset_vol: Option<rust_decimal::Decimal>,
+        #[arg(long, help = "The symbol's average daily volume (--participation)")]
+        adv: Option<rust_decimal::Decimal>,
+        #[arg(long, help = "Portfolio value to size against (default: current holdings)")]
+        capital: Option<rust_decimal::Decimal>,
+        #[arg(long, default_value = "stock", help = "Asset type whose margin rates apply (paper trading)")]
+        asset_type: quant::AssetType,
+    },
+    /// Record a sell
+    #[command(visible_alias = "remove")]
+    Sell {
+        #[arg(short, long)]
+        symbol: String,
+        #[arg(short, long, visible_alias = "qty")]
+        quantity: rust_decimal::Decimal,
+        #[arg(short, long)]
+        price: rust_decimal::Decimal,
+        #[arg(long, value_delimiter = ',', help = "Lot ids to close, in order (specific-lot cost basis; see `portfolio lots`)")]
+        lots: Vec<String>,
+    },
+    /// Set a position's stop-loss / take-profit (evaluated by `alerts run`)
+    SetStop {
+        #[arg(short, long)]
+        symbol: String,
+        #[arg(long, value_parser = percent_arg, help = "Stop loss below the entry, e.g. 5%")]
+        stop: Option<rust_decimal::Decimal>,
+        #[arg(long, help = "Trail the stop below the highest price seen")]
+        trail: bool,
+        #[arg(long, value_parser = percent_arg, help = "Take profit above the entry, e.g. 10%")]
+        take_profit: Option<rust_decimal::Decimal>,
+        #[arg(long, help = "Sell when triggered (requires portfolio.paper_trading)")]
+        auto_close: bool,
+    },
+    /// List recorded trades
+    Ledger {
+        #[arg(long, value_enum, requires = "out", help = "Write the trades to --out as csv or parquet instead")]
+        export: Option<quant::export::ExportFormat>,
+        #[arg(long, requires = "export", help = "File for --export")]
+        out: Option<std::path::PathBuf>,
+    },
+    /// List open lots per symbol under the cost-basis method
+    Lots {
+        #[arg(short, long)]
+        symbol: Option<String>,
+        #[arg(long, help = "average-cost, fifo, lifo or specific-lot (default: portfolio.cost_basis.method)")]
+        method: Option<quant::cost_basis::CostBasisMethod>,
+    },
+    /// Realized gains by holding period (short / long term)
+    Gains {
+        #[arg(long, help = "average-cost, fifo, lifo or specific-lot (default: portfolio.cost_basis.method)")]
+        method: Option<quant::cost_basis::CostBasisMethod>,
+        #[arg(long, help = "Write the gains to this file (.csv or .parquet)")]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Record a deposit or withdrawal (performance treats it as external cash flow)
+    Cash {
+        #[arg(long = "in", required_unless_present = "withdraw", conflicts_with = "withdraw", help = "Amount deposited")]
+        deposit: Option<rust_decimal::Decimal>,
+        #[arg(long = "out", help = "Amount withdrawn")]
+        withdraw: Option<rust_decimal::Decimal>,
+        #[arg(long, help = "Date of the flow, YYYY-MM-DD (default: today)")]
+        date: Option<chrono::NaiveDate>,
+        #[arg(long)]
+        note: Option<String>,
+    },
+    /// Paper-trade a symbol on a WebAssembly strategy's quote signals until interrupted
+    RunStrategy {
+        #[arg(short, long)]
+        symbol: String,
+        #[arg(long, help = "Strategy plugin (.wasm, or .wat text)")]
+        strategy_wasm: std::path::PathBuf,
+        #[arg(long, help = "JSON file passed to the plugin's init (default: {})")]
+        strategy_config: Option<std::path::PathBuf>,
+        #[arg(long, default_value = "1", help = "Quantity bought on a buy signal and sold on a sell signal")]
+        quantity: rust_decimal::Decimal,
+        #[arg(long, default_value = "1m", help = "Quote poll interval (e.g. 30s, 5m)")]
+        interval: units::HumanDuration,
+        #[arg(long, default_value = "stock", help = "Asset type whose margin rates apply")]
+        asset_type: quant::AssetType,
+    },
+    /// Plan trades toward target weights at current quotes
+    Rebalance {
+        #[arg(long, help = "Weights summing to 1, as AAPL=0.5,MSFT=0.5 or a TOML file of `SYMBOL = weight` lines")]
+        targets: String,
+        #[arg(long, default_value = "0", help = "Cash to invest as part of the rebalance")]
+        cash_in: rust_decimal::Decimal,
+        #[arg(long, default_value = "0", help = "Cash to raise as part of the rebalance")]
+        cash_out: rust_decimal::Decimal,
+        #[arg(long, default_value = "0", help = "Skip trades below this notional")]
+        min_trade: rust_decimal::Decimal,
+        #[arg(long, default_value = "1", help = "Round quantities down to this lot size (0 for fractional)")]
+        lot_size: rust_decimal::Decimal,
+        #[arg(long, help = "Cap traded notional at this fraction of portfolio value, e.g. 0.25")]
+        max_turnover: Option<rust_decimal::Decimal>,
+        #[arg(long, value_delimiter = ',', help = "Symbols never to sell (comma-separated)")]
+        do_not_sell: Vec<String>,
+        #[arg(long, help = "Record the trades in the paper portfolio and ledger (requires portfolio.paper_trading)")]
+        execute_paper: bool,
+    },
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive(tracing::Level::INFO.into()),
-        )
-        .init();
+async fn main() -> std::process::ExitCode {
+    // Initialize tracing; `[logging] level` is applied once settings load.
+    // JSON output keeps both streams parseable, so it logs nothing
+    logging::init(json_output_requested());
 
     info!("Starting QuantraBand v{}", env!("CARGO_PKG_VERSION"));
 
-    let cli = Cli::parse();
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) if e.use_stderr() && json_output_requested() => {
+            eprintln!("{}", cli_error::classify_usage(&e).to_json());
+            return std::process::ExitCode::from(cli_error::ErrorKind::Usage.exit_code());
+        }
+        // Help, version and text-mode usage errors (exit code 2)
+        Err(e) => e.exit(),
+    };
 
-    match cli.command {
-        Commands::P2p { listen, zero_trust } => {
-            info!("Starting P2P node on {}", listen);
-            let mut node = if zero_trust {
-                info!("🔒 Zero-Trust security ENABLED");
-                // ✅ OPTIMIZATION: Async for non-blocking audit log I/O
-                p2p::P2PNode::new_with_zero_trust().await?
-            } else {
-                p2p::P2PNode::new()?
-            };
-            node.listen_on(&listen)?;
-            info!("P2P node started with peer ID: {}", node.local_peer_id());
-            node.run().await?;
+    // Everything the command does (logs, audit events, notifications,
+    // P2P requests, the error envelope) carries one trace ID
+    let output = cli.output;
+    trace::scope(trace::TraceId::new(), async move {
+        match run(cli).await {
+            Ok(()) => std::process::ExitCode::SUCCESS,
+            Err(err) => {
+                let (kind, body) = cli_error::classify(&err);
+                match output {
+                    OutputFormat::Json => eprintln!("{}", body.to_json()),
+                    OutputFormat::Text => eprintln!("Error: {:?}", err),
+                }
+                std::process::ExitCode::from(kind.exit_code())
+            }
         }
-        Commands::GenerateKey { user_id } => {
-            info!("Generating PGP keypair for {}", user_id);
-            let crypto = crypto::CryptoManager::new("./keystore")?;
-            let keypair = crypto.generate_keypair(&user_id).await?;
-            let public_key = crypto.export_public_key(&keypair).await?;
-            println!("Generated keypair with fingerprint: {}", keypair.fingerprint);
-            println!("\nPublic key:\n{}", public_key);
+    })
+    .await
+}
+
+/// Bring every store up to date before any command opens one
+fn migrate_on_startup(settings: &settings::Settings, dirs: &data_dirs::DataDirs, no_migrate: bool) -> Result<()> {
+    let stores = migrations::stores(settings, dirs)?;
+    let pending = migrations::pending(&stores)?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+    if no_migrate {
+        let summary: Vec<String> = pending.iter().map(|m| format!("{} v{} → v{}", m.store, m.from, m.to)).collect();
+        anyhow::bail!(CliError::validation(
+            "MIGRATIONS_PENDING",
+            format!("Stored data needs migrating ({}); run `quantraband migrate` or drop --no-migrate", summary.join(", ")),
+        )
+        .with_details(serde_json::to_value(&pending)?));
+    }
+    migrations::migrate_all(&stores)?;
+    Ok(())
+}
+
+/// Add a generated key to the key transparency log. Only warns on failure:
+/// the key exists either way, and a running node holds the log open
+fn log_generated_key(settings: &settings::Settings, dirs: &data_dirs::DataDirs, mode: storage::RuntimeMode, user_id: &str, fingerprint: &str) {
+    use p2p::transparency::{KeySource, KeyTransparency, Observed};
+    let observed = dirs
+        .key_log_dir()
+        .and_then(|dir| KeyTransparency::open(&dir, mode, settings.p2p.transparency.clone()))
+        .and_then(|mut log| log.observe(user_id, fingerprint, KeySource::Keystore, chrono::Utc::now()));
+    match observed {
+        Ok(Observed::Conflict(conflict)) => println!(
+            "⚠️  {} is already logged with other keys: {}",
+            user_id,
+            conflict.fingerprints.iter().filter(|f| *f != fingerprint).cloned().collect::<Vec<_>>().join(", ")
+        ),
+        Ok(_) => {}
+        Err(e) => tracing::warn!("🔑 Key not added to the transparency log: {:#}", e),
+    }
+}
+
+/// `storage migrate`: copy the sled stores into `to`, printing each as it is
+/// verified
+#[cfg(feature = "sqlite")]
+fn copy_stores(
+    settings: &settings::Settings,
+    dirs: &data_dirs::DataDirs,
+    stores: &[migrations::StoreLocation],
+    to: storage::BackendKind,
+    output: OutputFormat,
+) -> Result<Vec<migrations::StoreCopy>> {
+    if to != storage::BackendKind::Sqlite {
+        anyhow::bail!(CliError::validation("UNSUPPORTED_BACKEND", format!("Stores can only be copied into sqlite, not {}", to)));
+    }
+    let path = settings.storage.sqlite_path(dirs);
+    let db = std::sync::Arc::new(storage::sqlite::SqliteDatabase::open(&path, dirs.root())?);
+    if output == OutputFormat::Text {
+        println!("🗄️ Copying stores into {}", path.display());
+    }
+    migrations::copy_to_sqlite(stores, &db, |copy| {
+        if output == OutputFormat::Text {
+            println!("{}", copy);
+        }
+    })
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn copy_stores(
+    _settings: &settings::Settings,
+    _dirs: &data_dirs::DataDirs,
+    _stores: &[migrations::StoreLocation],
+    _to: storage::BackendKind,
+    _output: OutputFormat,
+) -> Result<Vec<migrations::StoreCopy>> {
+    anyhow::bail!(CliError::validation(
+        "NOT_COMPILED",
+        "SQLite storage is not compiled into this build; rebuild with `--features sqlite`",
+    ))
+}
+
+/// Every event in the profile's audit log (none if it doesn't exist yet)
+async fn read_audit_events(
+    settings: &settings::Settings,
+    dirs: &data_dirs::DataDirs,
+) -> Result<Vec<zerotrust::audit::SecurityEvent>> {
+    match open_audit_logger(settings, dirs).await? {
+        Some(logger) => logger.read_events().await,
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The profile's audit log, if one has been written
+async fn open_audit_logger(
+    settings: &settings::Settings,
+    dirs: &data_dirs::DataDirs,
+) -> Result<Option<zerotrust::audit::AuditLogger>> {
+    let path = dirs.audit_log_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let keys = crypto::key_provider::open(&settings.keys, dirs)?;
+    let logger = zerotrust::audit::AuditLogger::with_key_provider(&path, storage::RuntimeMode::Persistent, keys).await?;
+    Ok(Some(logger))
+}
+
+/// TAXII push of the profile's intel journal, per `[intel.taxii]`
+fn intel_pusher(config: &security::intel::IntelConfig, dirs: &data_dirs::DataDirs) -> Result<security::taxii::IntelPusher> {
+    if !config.taxii.is_configured() {
+        anyhow::bail!(CliError::validation(
+            "TAXII_NOT_CONFIGURED",
+            "Set [intel.taxii] collection_url to push threat intel",
+        ));
+    }
+    let client = security::taxii::TaxiiClient::new(&config.taxii)
+        .map_err(|e| CliError::validation("INVALID_CONFIG", format!("{:#}", e)))?;
+    Ok(security::taxii::IntelPusher::new(
+        dirs.intel_journal_path()?,
+        dirs.intel_cursor_path()?,
+        security::intel::StixExporter::new(config),
+        client,
+    ))
+}
+
+/// Scheduler running the portfolio's daily jobs while it lives: applying
+/// corporate actions (`[portfolio.corporate_actions] apply_daily`) and
+/// recording the valuation (`[portfolio.performance] record_daily`)
+fn portfolio_scheduler(
+    store: std::sync::Arc<quant::portfolio_store::PortfolioStore>,
+    config: &quant::portfolio::PortfolioSettings,
+    market_data: &quant::market_data::MarketDataConfig,
+) -> Result<scheduler::Scheduler> {
+    let mut scheduler = scheduler::Scheduler::new();
+    if config.corporate_actions.apply_daily {
+        let applier = quant::corporate_actions::ActionApplier::new(store.clone());
+        std::sync::Arc::new(applier).schedule(&mut scheduler, &config.corporate_actions)?;
+    }
+    if config.performance.record_daily {
+        let provider = market_data_provider(market_data, None)?;
+        std::sync::Arc::new(quant::performance::PerformanceTracker::new(store, provider))
+            .schedule(&mut scheduler, &config.performance)?;
+    }
+    if config.corporate_actions.apply_daily || config.performance.record_daily {
+        scheduler.start();
+    }
+    Ok(scheduler)
+}
+
+/// Print the recorded trades, or write them to `out` as `export`
+fn print_ledger(
+    store: &quant::portfolio_store::PortfolioStore,
+    export: Option<quant::export::ExportFormat>,
+    out: Option<&std::path::Path>,
+) -> Result<()> {
+    if let (Some(format), Some(path)) = (export, out) {
+        // Trades go to the file as they are read, however long the ledger
+        let mut rows = quant::export::RowWriter::<quant::Trade, _>::create(path, format)?;
+        store.visit_ledger(&mut |entry| {
+            rows.write(&entry.trade)?;
+            Ok(true)
+        })?;
+        let written = rows.finish()?;
+        println!("📤 Wrote {} trade(s) to {}", written, path.display());
+        return Ok(());
+    }
+    let ledger = store.ledger()?;
+    if ledger.is_empty() {
+        println!("No trades");
+    }
+    for entry in ledger {
+        let trade = entry.trade;
+        println!(
+            "{}  {}  {:<4} {:<8} {} @ {}{}",
+            trade.timestamp.to_rfc3339(),
+            trade.id,
+            format!("{:?}", trade.side),
+            trade.symbol,
+            trade.quantity,
+            trade.price,
+            entry.triggered_by.map(|t| format!("  [{}]", t)).unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Corporate actions recorded in the profile's portfolio store
+/// A provider on the configured market data source, or `source`
+fn market_data_provider(
+    config: &quant::market_data::MarketDataConfig,
+    source: Option<quant::market_data::SourceKind>,
+) -> Result<quant::market_data::MarketDataProvider> {
+    let source = quant::market_data::open_source(source.unwrap_or(config.source), config)
+        .map_err(|e| CliError::validation("INVALID_CONFIG", format!("{:#}", e)))?;
+    Ok(quant::market_data::MarketDataProvider::with_source(source))
+}
+
+/// An engine on the configured market data source, or `source`
+fn market_data_engine(
+    config: &quant::market_data::MarketDataConfig,
+    source: Option<quant::market_data::SourceKind>,
+) -> Result<quant::QuantEngine> {
+    Ok(quant::QuantEngine::with_provider(market_data_provider(config, source)?))
+}
+
+fn recorded_actions(settings: &settings::Settings, dirs: &data_dirs::DataDirs) -> Result<Vec<quant::corporate_actions::CorporateAction>> {
+    quant::portfolio_store::PortfolioStore::open(&settings.portfolio.store_path(dirs)?, dirs.mode())?.actions()
+}
+
+/// The key provider, or else the node identity's file provider. Call it
+/// before the node loads its identity
+fn identity_key(
+    dirs: &data_dirs::DataDirs,
+    key_provider: Option<std::sync::Arc<dyn crypto::key_provider::KeyProvider>>,
+) -> Result<std::sync::Arc<dyn crypto::key_provider::KeyProvider>> {
+    match key_provider {
+        Some(provider) => Ok(provider),
+        None => Ok(zerotrust::node_identity::NodeIdentity::open(&dirs.identity_dir()?, dirs.mode(), "local")?.signer()),
+    }
+}
+
+/// This identity's watchlist, keyed by the key provider's identity key or
+/// else the node identity's. Open it before the node loads its identity
+fn open_watchlist(
+    settings: &settings::Settings,
+    dirs: &data_dirs::DataDirs,
+    key_provider: Option<std::sync::Arc<dyn crypto::key_provider::KeyProvider>>,
+) -> Result<quant::watchlist::WatchlistStore> {
+    let identity = identity_key(dirs, key_provider)?;
+    let keys = quant::watchlist::WatchlistKeys::derive(identity.as_ref())?;
+    quant::watchlist::WatchlistStore::open(&settings.quant.watchlist.store_path(dirs)?, dirs.mode(), keys)
+}
+
+/// The profile's sealed secrets, wrapped by the configured key provider or
+/// the node identity's key. `[keys]` opens the store, so its references
+/// only come from the environment
+fn open_secrets(settings: &settings::Settings, dirs: &data_dirs::DataDirs) -> Result<crypto::secrets::SecretStore> {
+    let keys_only = settings::Settings { keys: settings.keys.clone(), ..Default::default() };
+    let keys = crypto::secrets::resolve(&keys_only, None)?.keys;
+    let key_provider = crypto::key_provider::open(&keys, dirs)
+        .context("Key provider unavailable; run `quantraband keys doctor` for a diagnosis")?;
+    Ok(crypto::secrets::SecretStore::open(&dirs.secrets_path()?, identity_key(dirs, key_provider)?))
+}
+
+/// `settings` with its `secret://` references filled in. The key provider
+/// is only opened if the environment doesn't cover every reference
+fn resolve_secrets(settings: &settings::Settings, dirs: &data_dirs::DataDirs) -> Result<settings::Settings> {
+    let references = crypto::secrets::references(settings)?;
+    if references.iter().all(|r| std::env::var(crypto::secrets::env_var(&r.secret)).is_ok()) {
+        return crypto::secrets::resolve(settings, None);
+    }
+    crypto::secrets::resolve(settings, Some(&open_secrets(settings, dirs)?))
+}
+
+/// Re-read the config file on every SIGHUP (`kill -HUP`)
+#[cfg(unix)]
+fn reload_on_sighup(handle: p2p::handle::NodeHandle) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            tracing::warn!("⚠️  Config reload on SIGHUP unavailable: {}", e);
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            info!("🔄 SIGHUP received, reloading config");
+            // The node logs and audits the outcome; an error means it stopped
+            if handle.reload_config().await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// `--output json` on a command line clap rejected
+fn json_output_requested() -> bool {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    args.iter().enumerate().any(|(i, arg)| match arg.as_str() {
+        "--output=json" | "-ojson" | "-o=json" => true,
+        "--output" | "-o" => args.get(i + 1).is_some_and(|v| v == "json"),
+        _ => false,
+    })
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let settings = settings::Settings::load_or_default(cli.config.as_deref())?;
+    logging::set_level(&settings.logging.level)?;
+    let mode = storage::RuntimeMode::from_flag(cli.ephemeral);
+    if mode.is_ephemeral() {
+        tracing::warn!("🫥 Runtime mode: {}", mode);
+    }
+    let dirs = data_dirs::DataDirs::resolve(cli.data_dir.as_deref(), cli.profile.as_deref(), mode)?;
+    info!("📂 Data: {}", dirs);
+    // `secrets` and `config` work on the settings as written
+    let settings = match cli.command {
+        Commands::Secrets { .. } | Commands::Config { .. } => settings,
+        _ => resolve_secrets(&settings, &dirs)?,
+    };
+
+    if faults::ENABLED {
+        faults::global().apply_settings(&settings.chaos)?;
+    } else if !settings.chaos.injections.is_empty() {
+        tracing::warn!("💥 [chaos] injections ignored: build without debug assertions or the `chaos` feature");
+    }
+
+    storage::configure(&settings.storage, &dirs)?;
+    p2p::wire::configure(&settings.p2p.wire);
+    p2p::cover::configure(&settings.p2p.cover);
+    // `network doctor` reports a bad proxy config rather than failing on it
+    if !matches!(cli.command, Commands::Network { .. }) {
+        net::configure(&settings.network.proxy)?;
+    }
+    // Keys from before data directories, found in the working directory,
+    // move into the default profile's keystore
+    if dirs.profile().is_none() && !settings.crypto.keystores().get(crypto::DEFAULT_KEYSTORE).is_some_and(|k| k.path.is_some()) {
+        dirs.adopt_legacy_keystore(std::path::Path::new(data_dirs::LEGACY_KEYSTORE_DIR))?;
+    }
+    if !mode.is_ephemeral() && !matches!(cli.command, Commands::Migrate { .. }) {
+        migrate_on_startup(&settings, &dirs, cli.no_migrate)?;
+    }
+
+    let notifier = if settings.notifications.enabled {
+        let router = std::sync::Arc::new(security::notifications::NotificationRouter::from_config(&settings.notifications)?);
+        router.spawn_retry_loop(settings.notifications.retry_interval.as_std());
+        info!("🔔 Notifications enabled ({} sink(s))", settings.notifications.sinks.len());
+        Some(router)
+    } else {
+        None
+    };
+
+    match cli.command {
+        Commands::P2p { listen, listen_require_all, zero_trust, dht_journal, alerts, telemetry_collector, bootstrap, identity, metrics_addr } => {
+            let listen = match (listen.is_empty(), settings.p2p.listen.is_empty()) {
+                (false, _) => listen,
+                (true, false) => settings.p2p.listen.clone(),
+                (true, true) => vec!["/ip4/0.0.0.0/tcp/0".to_string()],
+            };
+            let require_all = listen_require_all || settings.p2p.listen_require_all;
+            info!("Starting P2P node on {}", listen.join(", "));
+            let key_provider = crypto::key_provider::open(&settings.keys, &dirs)
+                .context("Key provider unavailable; run `quantraband keys doctor` for a diagnosis")?;
+            let watchlist = settings
+                .quant
+                .watchlist
+                .sync
+                .then(|| open_watchlist(&settings, &dirs, key_provider.clone()))
+                .transpose()?;
+            let secrets = crypto::secrets::SecretStore::open(&dirs.secrets_path()?, identity_key(&dirs, key_provider.clone())?);
+            let mut node = match key_provider {
+                Some(provider) => {
+                    if identity.is_some() {
+                        tracing::warn!("⚠️  --identity is ignored: the {} key provider holds the node identity", provider.name());
+                    }
+                    p2p::P2PNode::with_key_provider(provider)?
+                }
+                None => match identity {
+                    Some(path) => p2p::P2PNode::with_identity_file(&path)?,
+                    None if mode.is_ephemeral() => p2p::P2PNode::new()?,
+                    None => p2p::P2PNode::with_identity_file(&dirs.node_key_path()?)?,
+                },
+            };
+            node.set_data_dirs(dirs.clone());
+            node.set_config_source(cli.config.clone(), settings.clone());
+            node.set_secrets(secrets);
+            node.set_rate_limits(&settings.p2p.rate_limits);
+            node.set_request_limits(settings.p2p.request_limits.clone());
+            node.set_quant_engine(std::sync::Arc::new(market_data_engine(&settings.quant.market_data, None)?));
+            if let Some(notifier) = &notifier {
+                node.set_notifier(notifier.clone());
+            }
+            if zero_trust {
+                info!("🔒 Zero-Trust security ENABLED");
+                // ✅ OPTIMIZATION: Async for non-blocking audit log I/O
+                node.enable_zero_trust().await?;
+                if let Some(zt) = node.zero_trust() {
+                    zt.apply_settings(&settings.zerotrust).await?;
+                }
+            }
+
+            let intel_journal = (settings.intel.enabled && !mode.is_ephemeral())
+                .then(|| dirs.intel_journal_path().and_then(|path| security::intel::IntelJournal::open(&path)))
+                .transpose()?
+                .map(std::sync::Arc::new);
+            let geo_policy = settings.p2p.geo_policy;
+            let admission = settings.p2p.admission;
+            if (geo_policy.enabled && geo_policy.report_to_shield) || admission.enabled {
+                let mut shield = security::mirror_shield::MirrorShield::new();
+                if let Some(notifier) = &notifier {
+                    shield.set_notifier(notifier.clone());
+                }
+                if let Some(journal) = &intel_journal {
+                    shield.set_intel_journal(journal.clone());
+                }
+                node.set_mirror_shield(std::sync::Arc::new(shield));
+            }
+            if geo_policy.enabled {
+                let locator = std::sync::Arc::new(security::geo::CachedGeoLocator::new(
+                    std::sync::Arc::new(security::geo::IpApiLocator::new()),
+                    geo_policy.cache_ttl.as_chrono(),
+                ));
+                node.enable_geo_policy(geo_policy, locator);
+            }
+            if admission.enabled {
+                node.enable_admission_control(admission);
+            }
+
+            if let Some(dir) = dht_journal {
+                node.enable_dht_records(&dir)?;
+            }
+            node.enable_replay_registry(&dirs.replay_registry_dir()?, &settings.p2p.replay)?;
+            if !settings.p2p.retention.topics.is_empty() {
+                node.enable_topic_retention(&dirs.retention_dir()?, &settings.p2p.retention)?;
+            }
+            node.enable_receipts(&dirs.receipts_dir()?, &settings.p2p.receipts)?;
+            node.enable_outbox(&dirs.outbox_path()?, &settings.p2p.outbox)?;
+            node.set_partition_config(settings.p2p.partition.clone());
+            node.enable_approvals(&dirs.approvals_dir()?, &settings.approvals)?;
+            node.enable_guardians(&settings.guardians)?;
+            if settings.p2p.transparency.is_enabled() {
+                node.enable_key_transparency(&dirs.key_log_dir()?, &settings.p2p.transparency)?;
+            }
+            if settings.p2p.cover.enabled {
+                node.enable_cover_traffic(settings.p2p.cover.clone())?;
+            }
+
+            if let Some(key) = &settings.esim.carrier_maintainer_key {
+                let key = esim::carrier_updates::parse_maintainer_key(key)
+                    .map_err(|e| CliError::validation("INVALID_CONFIG", format!("esim.carrier_maintainer_key: {}", e)))?;
+                let db = esim::carriers::CarrierDatabase::open(&dirs.carrier_db_dir()?, mode)?;
+                node.enable_carrier_updates(db, key)?;
+                if settings.esim.carrier_update_approval {
+                    node.require_carrier_approval()?;
+                }
+            }
+
+            if settings.maintenance.enabled {
+                node.enable_maintenance(settings.maintenance.clone());
+                if settings.intel.taxii.scheduled {
+                    node.enable_intel_push(std::sync::Arc::new(intel_pusher(&settings.intel, &dirs)?));
+                }
+            }
+            if settings.quant.remote_pricing.serve {
+                node.enable_remote_pricing(settings.quant.remote_pricing.clone());
+            }
+            if settings.p2p.telemetry.enabled {
+                node.enable_telemetry(settings.p2p.telemetry.clone());
+            }
+            if telemetry_collector {
+                let summary_path = (!mode.is_ephemeral()).then(|| dirs.telemetry_summary_path()).transpose()?;
+                node.enable_telemetry_collector(settings.p2p.telemetry.collector_window.as_chrono(), summary_path)?;
+            }
+
+            if alerts {
+                let config = settings.alerts.clone();
+                let evaluator = alerts::AlertEvaluator::new(alerts::store::AlertStore::open(&config.store_path(&dirs)?, mode)?);
+                let portfolio = std::sync::Arc::new(quant::portfolio_store::PortfolioStore::open(
+                    &settings.portfolio.store_path(&dirs)?,
+                    mode,
+                )?);
+                let valuations = portfolio_scheduler(portfolio.clone(), &settings.portfolio, &settings.quant.market_data)?;
+                let positions = alerts::positions::PositionMonitor::new(portfolio, settings.portfolio.paper_trading);
+                let mut sinks: Vec<Box<dyn alerts::delivery::AlertSink>> = vec![
+                    Box::new(alerts::delivery::LogSink),
+                    Box::new(alerts::delivery::GossipSink::new(node.alert_sender())),
+                ];
+                if let Some(url) = &config.webhook_url {
+                    sinks.push(Box::new(alerts::delivery::WebhookSink::new(url)));
+                }
+                let interval = config.poll_interval.as_std();
+                let provider = market_data_provider(&settings.quant.market_data, None)?;
+                let feed = quant::market_stream::QuoteFeed::spawn(&settings.quant.stream, provider, interval);
+                tokio::spawn(async move {
+                    let _valuations = valuations;
+                    if let Err(e) = alerts::run(evaluator, Some(positions), feed, sinks, interval).await {
+                        error!("Alert evaluation stopped: {}", e);
+                    }
+                });
+            }
+
+            if settings.esim.webhook.enabled {
+                let config = &settings.esim.webhook;
+                let store = esim::store::ProfileStore::open(&dirs.esim_store_dir()?, mode)?;
+                let mut receiver = esim::webhook::WebhookReceiver::new(config, store)
+                    .map_err(|e| CliError::validation("INVALID_CONFIG", format!("{:#}", e)))?;
+                if let Some(notifier) = &notifier {
+                    receiver.set_notifier(notifier.clone());
+                }
+                if let Some(zt) = node.zero_trust() {
+                    receiver.set_audit(zt.clone());
+                }
+                let listener = tokio::net::TcpListener::bind(&config.listen)
+                    .await
+                    .with_context(|| format!("Failed to listen for eSIM webhooks on {}", config.listen))?;
+                let max_body = config.max_body;
+                tokio::spawn(async move {
+                    if let Err(e) = esim::webhook::serve(std::sync::Arc::new(receiver), listener, max_body).await {
+                        error!("eSIM webhook receiver stopped: {}", e);
+                    }
+                });
+            }
+
+            let results = node.listen_on_multiple(&listen).await;
+            check_listen_results(&results, require_all)?;
+            if !bootstrap.is_empty() {
+                node.add_bootstrap_peers(&bootstrap)
+                    .map_err(|e| CliError::validation("INVALID_BOOTSTRAP", format!("{:#}", e)))?;
+            }
+            if let Some(addr) = metrics_addr {
+                let listener = tokio::net::TcpListener::bind(addr)
+                    .await
+                    .with_context(|| format!("Failed to listen for status requests on {}", addr))?;
+                let endpoint = node.status_endpoint();
+                tokio::spawn(async move {
+                    if let Err(e) = p2p::status_http::serve(endpoint, listener).await {
+                        error!("Status endpoint stopped: {}", e);
+                    }
+                });
+            }
+            info!("P2P node started with peer ID: {}", node.local_peer_id());
+            let (handle, mut node_task) = p2p::handle::NodeHandle::attach(node, true);
+            #[cfg(unix)]
+            reload_on_sighup(handle.clone());
+            let mut watchlist_sync = scheduler::Scheduler::new();
+            if let Some(store) = watchlist {
+                let sync = quant::watchlist::WatchlistSync::new(std::sync::Arc::new(store), handle.clone());
+                std::sync::Arc::new(sync).schedule(&mut watchlist_sync, &settings.quant.watchlist)?;
+                watchlist_sync.start();
+            }
+            let stopped = tokio::select! {
+                stopped = &mut node_task => stopped,
+                _ = tokio::signal::ctrl_c() => {
+                    handle.shutdown().await;
+                    node_task.await
+                }
+            };
+            stopped.context("P2P node task panicked")??;
+        }
+        Commands::GenerateKey { user_id, keystore } => {
+            info!("Generating PGP keypair for {}", user_id);
+            let crypto = crypto::CryptoManager::open(&settings.crypto, &dirs)?;
+            let keypair = crypto.generate_keypair_in(keystore.as_deref(), &user_id).await?;
+            let public_key = crypto.export_public_key(&keypair).await?;
+            println!("Generated keypair in keystore '{}' with fingerprint: {}", keypair.keystore, keypair.fingerprint);
+            println!("\nPublic key:\n{}", public_key);
+            if settings.p2p.transparency.is_enabled() {
+                log_generated_key(&settings, &dirs, mode, &user_id, &keypair.fingerprint);
+            }
+        }
+        Commands::Encrypt { recipient, message, keystore, sign_with, sign_keystore, out } => {
+            info!("Encrypting message for {}", recipient);
+            let crypto = crypto::CryptoManager::open(&settings.crypto, &dirs)?;
+            let recipient = crypto.locate(&recipient, keystore.as_deref()).await?;
+            let signer = match &sign_with {
+                Some(key) => Some(crypto.locate(key, sign_keystore.as_deref()).await?),
+                None => None,
+            };
+            let sealed = crypto.encrypt_and_sign(&recipient, signer.as_ref(), message.as_bytes()).await?;
+            match out {
+                Some(path) => {
+                    std::fs::write(&path, &sealed).with_context(|| format!("Failed to write {}", path.display()))?;
+                    println!("🔐 Encrypted for {} to {}", recipient, path.display());
+                }
+                None => println!("{}", String::from_utf8_lossy(&sealed)),
+            }
+        }
+        Commands::Decrypt { input, from } => {
+            let sealed = match &input {
+                Some(path) => std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?,
+                None => {
+                    let mut sealed = Vec::new();
+                    std::io::Read::read_to_end(&mut std::io::stdin(), &mut sealed)?;
+                    sealed
+                }
+            };
+            let crypto = crypto::CryptoManager::open(&settings.crypto, &dirs)?;
+            let sender = match &from {
+                Some(key) => Some(crypto.locate(key, None).await?),
+                None => None,
+            };
+            let plaintext = crypto
+                .decrypt_message(&sealed, sender.as_ref())
+                .await
+                .map_err(|e| CliError::validation("UNDECRYPTABLE", format!("{:#}", e)))?;
+            std::io::Write::write_all(&mut std::io::stdout(), &plaintext)?;
+        }
+        Commands::Sign { key, message, out } => {
+            let crypto = crypto::CryptoManager::open(&settings.crypto, &dirs)?;
+            let signature = crypto.sign_message(&key, message.as_bytes()).await?;
+            match out {
+                Some(path) => {
+                    std::fs::write(&path, &signature).with_context(|| format!("Failed to write {}", path.display()))?;
+                    println!("✍️  Signed with {} to {}", key, path.display());
+                }
+                None => println!("{}", String::from_utf8_lossy(&signature)),
+            }
+        }
+        Commands::Verify { key, message, signature } => {
+            let signature =
+                std::fs::read(&signature).with_context(|| format!("Failed to read {}", signature.display()))?;
+            let crypto = crypto::CryptoManager::open(&settings.crypto, &dirs)?;
+            let signer = crypto.locate(&key, None).await?;
+            if !crypto.verify_signature(&signer.public_key, message.as_bytes(), &signature)? {
+                anyhow::bail!(CliError::validation(
+                    "BAD_SIGNATURE",
+                    format!("The signature is not a valid signature of the message by {}", signer),
+                )
+                .with_details(serde_json::json!({ "key": signer.to_string() })));
+            }
+            println!("✅ Good signature from {}", signer);
+        }
+        Commands::ProvisionEsim { carrier, plan, secure, format, out, eid, strict, require_healthy } => {
+            if secure {
+                info!("Provisioning SECURE eSIM for carrier: {}, plan: {}", carrier, plan);
+                println!("🔒 SECURE MODE: TLS 1.3 + AES-256-GCM + Certificate Pinning");
+            } else {
+                info!("Provisioning eSIM for carrier: {}, plan: {}", carrier, plan);
+            }
+
+            let carriers = open_carrier_db(&dirs, mode);
+            if carriers.get_carrier(&carrier).is_none() {
+                anyhow::bail!(CliError::not_found(
+                    "UNKNOWN_CARRIER",
+                    format!("Unknown carrier '{}' (see list-carriers)", carrier),
+                )
+                .with_details(serde_json::json!({ "carrier": carrier })));
+            }
+            if let Some(eid) = eid {
+                let report = open_device_db(&settings.esim)?.check_compatibility(&carriers, &eid, &carrier)?;
+                if !report.is_compatible() && strict {
+                    anyhow::bail!(CliError::validation(
+                        "DEVICE_INCOMPATIBLE",
+                        format!("Device {} cannot take {} profiles (see esim check)", report.eid, carrier),
+                    )
+                    .with_details(serde_json::to_value(&report)?));
+                }
+                if !report.is_compatible() {
+                    tracing::warn!("📵 Device {} may not accept {} profiles; provisioning anyway", report.eid, carrier);
+                }
+                for problem in report.carrier_restrictions.iter().chain(&report.warnings) {
+                    tracing::warn!("📵 {}", problem);
+                }
+            }
+            let monitor = esim::health::HealthMonitor::open(&settings.esim.health, &dirs.carrier_health_dir()?, mode)?;
+            let mut health = monitor.carrier_health(&carrier)?;
+            if require_healthy && health.status == esim::health::HealthStatus::Unknown {
+                // Never probed: run the pre-flight now rather than refuse
+                let targets: Vec<_> = monitor.targets(&carriers).into_iter().filter(|(id, _)| *id == carrier).collect();
+                monitor.probe_round(&targets, chrono::Utc::now()).await?;
+                health = monitor.carrier_health(&carrier)?;
+            }
+            if require_healthy && health.status != esim::health::HealthStatus::Healthy {
+                return Err(esim::health::CarrierUnhealthy(health).into());
+            }
+            if matches!(health.status, esim::health::HealthStatus::Degraded | esim::health::HealthStatus::Down) {
+                tracing::warn!("📡 {}; provisioning anyway", health);
+                println!("⚠️  Carrier SM-DP+ is not healthy: {}", health.summary());
+            }
+
+            let esim_manager = esim::ESimManager::new(
+                "sm-dp.example.com".to_string(),
+                "api-key".to_string(),
+            );
+
+            let request = esim::ESimActivationRequest {
+                device_id: "device-123".to_string(),
+                carrier: carrier.clone(),
+                plan_type: plan.clone(),
+                user_email: "user@example.com".to_string(),
+            };
+
+            let profile = esim_manager.provision_profile(request).await?;
+            esim::store::ProfileStore::open(&dirs.esim_store_dir()?, mode)?.put(&profile)?;
+
+            if secure {
+                println!("✅ eSIM Profile provisioned SECURELY!");
+                println!("🔐 Encryption: AES-256-GCM");
+                println!("🔐 Transport: TLS 1.3");
+                println!("🔐 Authentication: Mutual TLS (mTLS)");
+                println!("🔐 Integrity: HMAC-SHA256");
+            } else {
+                println!("eSIM Profile provisioned!");
+            }
+
+            println!("ICCID: {}", profile.iccid);
+            println!("Activation Code: {}", profile.activation_code);
+
+            if secure {
+                println!("\n🔒 Security Features:");
+                println!("  ✓ Certificate verified against GSMA root CAs");
+                println!("  ✓ Certificate pinning enabled");
+                println!("  ✓ Profile data encrypted end-to-end");
+                println!("  ✓ Confirmation code required");
+            }
+
+            if let Some(format) = format {
+                let formatted = esim::activation::format_activation(&profile, format)?;
+                emit_activation(&formatted, &profile.iccid, out.as_deref())?;
+            } else {
+                println!("\nGenerating QR code...");
+                let qr_data = esim_manager.generate_qr_code(&profile).await?;
+                println!("QR code generated: {} bytes", qr_data.len());
+            }
+
+            if secure {
+                println!("\n⚠️  IMPORTANT: Store this QR code securely!");
+                println!("   Only share via encrypted channels");
+            }
+        }
+        Commands::OptionPrice {
+            spot,
+            strike,
+            rate,
+            volatility,
+            time,
+            option_type,
+            model,
+            steps,
+            dividend_yield,
+            boundary,
+            heston_params,
+            numerical_greeks,
+        } => {
+            let opt_type = option_type_arg(&option_type)?;
+            let bumps = quant::pricing::GreekBumps::default();
+            let finite_differences = |pricer: &dyn Fn(f64, f64, f64, f64, f64) -> Result<f64>, volatility: f64| {
+                quant::pricing::numerical_greeks(pricer, spot, strike, rate, volatility, time, &bumps)
+                    .map(|greeks| (greeks, "finite differences"))
+            };
+
+            let (mut boundary_points, mut heston) = (None, None);
+            let (price, (greeks, greeks_method)) = match model.to_lowercase().as_str() {
+                "black-scholes" | "bs" => {
+                    let volatility = volatility_arg(volatility, &model)?;
+                    let engine = quant::QuantEngine::new();
+                    let price = engine
+                        .calculate_option_price(spot, strike, rate, volatility, time, opt_type)
+                        .await?;
+                    let greeks = match numerical_greeks {
+                        true => finite_differences(&|s, k, r, v, t| quant::pricing::black_scholes(s, k, r, v, t, opt_type), volatility)?,
+                        false => (quant::pricing::calculate_greeks(spot, strike, rate, volatility, time, opt_type)?, "analytic"),
+                    };
+                    (price, greeks)
+                }
+                "binomial" => {
+                    let params = quant::binomial::BinomialParams {
+                        spot,
+                        strike,
+                        rate,
+                        dividend_yield,
+                        volatility: volatility_arg(volatility, &model)?,
+                        time_to_expiry: time,
+                        option_type: opt_type,
+                        style: quant::binomial::ExerciseStyle::American,
+                        steps,
+                    };
+                    let price = quant::binomial::binomial_price(&params)?;
+                    let greeks = match numerical_greeks {
+                        true => finite_differences(
+                            &|spot, strike, rate, volatility, time_to_expiry| {
+                                let bumped = quant::binomial::BinomialParams { spot, strike, rate, volatility, time_to_expiry, ..params };
+                                quant::binomial::binomial_price(&bumped)
+                            },
+                            params.volatility,
+                        )?,
+                        false => (quant::binomial::binomial_greeks(&params)?, "binomial tree"),
+                    };
+                    if boundary {
+                        let points = quant::binomial::early_exercise_boundary(&params)?;
+                        let stride = (points.len() / 10).max(1);
+                        boundary_points = Some(points.into_iter().step_by(stride).collect::<Vec<_>>());
+                    }
+                    (price, greeks)
+                }
+                "heston" => {
+                    let params = heston_params_arg(heston_params.as_deref())?;
+                    let price = quant::pricing::heston::heston_price(
+                        spot, strike, rate, dividend_yield, time, opt_type, &params,
+                    )?;
+                    heston = Some(params);
+                    // Volatility here is the initial volatility, sqrt(v0)
+                    let greeks = finite_differences(
+                        &|s, k, r, v, t| {
+                            let bumped = quant::pricing::heston::HestonParams { v0: v * v, ..params };
+                            quant::pricing::heston::heston_price(s, k, r, dividend_yield, t, opt_type, &bumped)
+                        },
+                        params.v0.sqrt(),
+                    )?;
+                    (price, greeks)
+                }
+                other => anyhow::bail!(invalid_model(other, &["black-scholes", "binomial", "heston"])),
+            };
+
+            if cli.output == OutputFormat::Json {
+                let report = serde_json::json!({
+                    "model": model.to_lowercase(),
+                    "price": price,
+                    "greeks": greeks,
+                    "greeks_method": greeks_method,
+                    "heston": heston,
+                    "boundary": boundary_points,
+                });
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                return Ok(());
+            }
+            if let Some(points) = &boundary_points {
+                println!("Early-exercise boundary (American, {} steps):", steps);
+                for point in points {
+                    match point.critical_spot {
+                        Some(s) => println!("  t={:.4}y  S*={:.4}", point.time, s),
+                        None => println!("  t={:.4}y  no early exercise", point.time),
+                    }
+                }
+                println!();
+            }
+            if let Some(params) = &heston {
+                println!("Heston: {}", params);
+                if !params.feller_satisfied() {
+                    println!("⚠️  Feller condition (2·kappa·theta > sigma_v²) not met: variance can reach zero");
+                }
+            }
+            println!("Option Price: ${:.2}", price);
+            println!("\nGreeks ({}):", greeks_method);
+            println!("  Delta: {:.4}", greeks.delta);
+            println!("  Gamma: {:.4}", greeks.gamma);
+            println!("  Vega:  {:.4}", greeks.vega);
+            println!("  Theta: {:.4}", greeks.theta);
+            println!("  Rho:   {:.4}", greeks.rho);
+        }
+        Commands::BondPrice { face, coupon_rate, ytm, price, periods_per_year, years } => {
+            // clap requires one of --ytm and --price
+            let ytm = match ytm {
+                Some(ytm) => ytm,
+                None => quant::fixed_income::yield_to_maturity(price.unwrap_or_default(), face, coupon_rate, periods_per_year, years)?,
+            };
+            let analytics = quant::fixed_income::bond_analytics(face, coupon_rate, ytm, periods_per_year, years)?;
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&analytics)?),
+                OutputFormat::Text => {
+                    println!("Bond Price:         {:.4}", analytics.price);
+                    println!("Yield to Maturity:  {:.4}%", analytics.ytm * 100.0);
+                    println!("Macaulay Duration:  {:.4} years", analytics.macaulay_duration);
+                    println!("Modified Duration:  {:.4}", analytics.modified_duration);
+                    println!("Convexity:          {:.4}", analytics.convexity);
+                }
+            }
+        }
+        Commands::OptionStrategy { legs, from, to, steps, spot, rate, volatility, time } => {
+            let strategy = quant::strategy::OptionStrategy::new(legs);
+            let strikes = || strategy.legs.iter().map(|leg| leg.strike);
+            let from = from.unwrap_or_else(|| strikes().fold(f64::INFINITY, f64::min) * 0.8);
+            let to = to.unwrap_or_else(|| strikes().fold(0.0, f64::max) * 1.2);
+            if !(from >= 0.0 && to > from) || steps == 0 {
+                anyhow::bail!(CliError::validation("INVALID_RANGE", format!("No spots from {} to {} in {} steps", from, to, steps)));
+            }
+            let greeks = match (spot, volatility, time) {
+                (Some(spot), Some(volatility), Some(time)) => Some(strategy.net_greeks(spot, rate, volatility, time)?),
+                (None, None, None) => None,
+                _ => anyhow::bail!(CliError::validation("INVALID_GREEKS_INPUTS", "Net Greeks need --spot, --volatility and --time")),
+            };
+            // + 0.0 so a flat payoff doesn't print as -0.00
+            let table: Vec<(f64, f64)> = (0..=steps)
+                .map(|i| from + (to - from) * i as f64 / steps as f64)
+                .map(|spot| (spot, strategy.payoff_at(spot) + 0.0))
+                .collect();
+            let (premium, breakevens) = (strategy.net_premium(), strategy.breakeven_points());
+            match cli.output {
+                OutputFormat::Json => {
+                    let report = serde_json::json!({
+                        "legs": strategy.legs,
+                        "net_premium": premium,
+                        "max_profit": strategy.max_profit(),
+                        "max_loss": strategy.max_loss(),
+                        "breakevens": breakevens,
+                        "greeks": greeks,
+                        "payoff": table.iter().map(|(spot, payoff)| serde_json::json!({ "spot": spot, "payoff": payoff })).collect::<Vec<_>>(),
+                    });
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                }
+                OutputFormat::Text => {
+                    let bound = |value: Option<f64>| value.map_or("unlimited".to_string(), |v| format!("{:.2}", v));
+                    println!("Legs:");
+                    for leg in &strategy.legs {
+                        println!("  {}", leg);
+                    }
+                    println!("Net premium: {:.2} {}", premium.abs(), if premium < 0.0 { "debit" } else { "credit" });
+                    println!("Max profit:  {}", bound(strategy.max_profit()));
+                    println!("Max loss:    {}", bound(strategy.max_loss()));
+                    match breakevens.is_empty() {
+                        true => println!("Breakevens:  none"),
+                        false => println!("Breakevens:  {}", breakevens.iter().map(|b| format!("{:.2}", b)).collect::<Vec<_>>().join(", ")),
+                    }
+                    if let Some(greeks) = greeks {
+                        println!(
+                            "Net Greeks:  delta {:.4}  gamma {:.4}  vega {:.4}  theta {:.4}  rho {:.4}",
+                            greeks.delta, greeks.gamma, greeks.vega, greeks.theta, greeks.rho
+                        );
+                    }
+                    println!("\n{:>10} {:>10}", "spot", "payoff");
+                    for (spot, payoff) in &table {
+                        println!("{:>10.2} {:>10.2}", spot, payoff);
+                    }
+                }
+            }
+        }
+        Commands::OptionChain {
+            spot,
+            rate,
+            time,
+            strikes,
+            model,
+            volatility,
+            heston_params,
+            market_vols,
+            calibrate,
+        } => {
+            if !market_vols.is_empty() && market_vols.len() != strikes.len() {
+                anyhow::bail!(CliError::validation(
+                    "MARKET_VOLS_MISMATCH",
+                    format!("{} market vols for {} strikes", market_vols.len(), strikes.len()),
+                )
+                .with_details(serde_json::json!({ "strikes": strikes.len(), "market_vols": market_vols.len() })));
+            }
+
+            // Model implied vol per strike, plus the fit when calibrating
+            let (model_vols, heston, calibration) = match model.to_lowercase().as_str() {
+                "black-scholes" | "bs" => (vec![volatility_arg(volatility, &model)?; strikes.len()], None, None),
+                "heston" => {
+                    let calibration = if calibrate {
+                        if market_vols.is_empty() {
+                            anyhow::bail!(CliError::validation("MISSING_MARKET_VOLS", "--calibrate needs --market-vols"));
+                        }
+                        let quotes: Vec<_> = strikes.iter().zip(&market_vols).map(|(&k, &vol)| (k, time, vol)).collect();
+                        Some(
+                            quant::pricing::heston::calibrate_heston(&quotes, spot, rate)
+                                .map_err(|e| CliError::validation("CALIBRATION_FAILED", e.to_string()))?,
+                        )
+                    } else {
+                        None
+                    };
+                    let params = match &calibration {
+                        Some(fit) => fit.params,
+                        None => heston_params_arg(heston_params.as_deref())?,
+                    };
+                    let vols = strikes
+                        .iter()
+                        .map(|&k| quant::pricing::heston::heston_implied_vol(spot, k, rate, time, &params))
+                        .collect::<Result<Vec<_>>>()?;
+                    (vols, Some(params), calibration)
+                }
+                other => anyhow::bail!(invalid_model(other, &["black-scholes", "heston"])),
+            };
+
+            let mut rows = Vec::with_capacity(strikes.len());
+            for (i, (&strike, &model_vol)) in strikes.iter().zip(&model_vols).enumerate() {
+                let call = quant::pricing::black_scholes(spot, strike, rate, model_vol, time, quant::pricing::OptionType::Call)?;
+                let put = quant::pricing::black_scholes(spot, strike, rate, model_vol, time, quant::pricing::OptionType::Put)?;
+                let market_vol = market_vols.get(i).copied();
+                rows.push(serde_json::json!({
+                    "strike": strike,
+                    "call": call,
+                    "put": put,
+                    "model_vol": model_vol,
+                    "market_vol": market_vol,
+                    "vol_error": market_vol.map(|m| model_vol - m),
+                }));
+            }
+
+            match cli.output {
+                OutputFormat::Json => {
+                    let report = serde_json::json!({
+                        "model": model.to_lowercase(),
+                        "heston": heston,
+                        "calibration": calibration,
+                        "rows": rows,
+                    });
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                }
+                OutputFormat::Text => {
+                    if let Some(fit) = &calibration {
+                        println!(
+                            "🎯 Calibrated in {} iterations, vol RMSE {:.4}%{}",
+                            fit.iterations,
+                            fit.rmse * 100.0,
+                            if fit.feller_satisfied { "" } else { " (Feller condition not met)" }
+                        );
+                    }
+                    if let Some(params) = heston {
+                        println!("Heston: {}", params);
+                    }
+                    println!("{:>10} {:>10} {:>10} {:>9} {:>9} {:>8}", "strike", "call", "put", "model", "market", "diff");
+                    for row in &rows {
+                        let pct = |key: &str| row[key].as_f64().map_or("-".to_string(), |v| format!("{:.2}%", v * 100.0));
+                        println!(
+                            "{:>10.2} {:>10.4} {:>10.4} {:>9} {:>9} {:>8}",
+                            row["strike"].as_f64().unwrap_or_default(),
+                            row["call"].as_f64().unwrap_or_default(),
+                            row["put"].as_f64().unwrap_or_default(),
+                            pct("model_vol"),
+                            pct("market_vol"),
+                            pct("vol_error")
+                        );
+                    }
+                }
+            }
+        }
+        Commands::HedgeSim {
+            symbol,
+            strike,
+            expiry,
+            path,
+            rehedge,
+            cost_bps,
+            volatility,
+            rate,
+            option_type,
+            quantity,
+            model,
+            steps,
+            adjusted,
+        } => {
+            let opt_type = option_type_arg(&option_type)?;
+            let model = hedge_model_arg(&model, steps)?;
+            let expiry = datetime_arg(&expiry, "expiry", "INVALID_EXPIRY")?;
+
+            // Streamed so only the requested symbol's candles are held in memory
+            let file = std::fs::File::open(&path)?;
+            let mut candles: Vec<_> = quant::export::stream_candles_csv(file)?
+                .filter(|c| c.as_ref().map_or(true, |c| c.symbol.eq_ignore_ascii_case(&symbol)))
+                .collect::<Result<_>>()?;
+            if adjusted {
+                candles = quant::corporate_actions::back_adjust(&candles, &recorded_actions(&settings, &dirs)?);
+            }
+            if candles.is_empty() {
+                anyhow::bail!(CliError::not_found("NO_CANDLES", format!("No candles for {} in {}", symbol, path.display()))
+                    .with_details(serde_json::json!({ "symbol": symbol, "path": path })));
+            }
+
+            let option = quant::hedging::HedgedOption {
+                option_type: opt_type,
+                strike,
+                expiry,
+                rate,
+                volatility,
+                quantity,
+                model,
+            };
+            let every = quant::hedging::candles_per_interval(
+                &candles,
+                rehedge.as_chrono(),
+            );
+            let report = quant::hedging::simulate_delta_hedge(&option, &candles, every, cost_bps)?;
+
+            println!("📉 Delta hedge simulation for {} ({} candles, re-hedge every {})", symbol, candles.len(), every);
+            if report.gaps > 0 {
+                println!("  ⚠️  {} gap(s) in the series bridged as single steps", report.gaps);
+            }
+            println!("  Hedge trades:   {}", report.hedge_trades.len());
+            println!("  Total costs:    ${:.2}", report.total_costs);
+            if report.expired {
+                println!("  Option payoff:  ${:.4} (realized at expiry)", report.option_value_end);
+            } else {
+                println!("  Option value:   ${:.4} (path ends before expiry, marked to model)", report.option_value_end);
+            }
+            println!("  Final P&L:      ${:.2}", report.final_pnl);
+        }
+        Commands::Attribute { portfolio, history, from, to, model, steps, residual_threshold } => {
+            let model = hedge_model_arg(&model, steps)?;
+            let from = datetime_arg(&from, "from", "INVALID_DATE")?;
+            let to = datetime_arg(&to, "to", "INVALID_DATE")?;
+            if to <= from {
+                anyhow::bail!(CliError::validation("INVALID_DATE", "--to must be after --from")
+                    .with_details(serde_json::json!({ "from": from, "to": to })));
+            }
+
+            let positions: Vec<quant::attribution::OptionPosition> =
+                serde_json::from_slice(&std::fs::read(&portfolio)?).map_err(|e| {
+                    CliError::validation("INVALID_PORTFOLIO", format!("Invalid option portfolio {}: {}", portfolio.display(), e))
+                        .with_details(serde_json::json!({ "path": portfolio }))
+                })?;
+            let symbols: Vec<String> = positions.iter().map(|p| p.symbol.to_uppercase()).collect();
+            let file = std::fs::File::open(&history)?;
+            let contexts: Vec<_> = quant::export::stream_market_contexts_csv(file)?
+                .filter(|c| c.as_ref().map_or(true, |c| symbols.contains(&c.symbol.to_uppercase())))
+                .collect::<Result<_>>()?;
+
+            let report = quant::attribution::attribution_report(&positions, &contexts, from, to, model, residual_threshold)?;
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Text => print!("{}", report),
+            }
+        }
+        Commands::Watch { portfolio, interval, once, rate, volatility, model, steps } => {
+            let model = hedge_model_arg(&model, steps)?;
+            if interval.as_std().is_zero() {
+                anyhow::bail!(CliError::validation("INVALID_INTERVAL", "--interval must be at least 1ms"));
+            }
+            let positions: Vec<quant::watch::WatchedPosition> =
+                serde_json::from_slice(&std::fs::read(&portfolio)?).map_err(|e| {
+                    CliError::validation("INVALID_PORTFOLIO", format!("Invalid option portfolio {}: {}", portfolio.display(), e))
+                        .with_details(serde_json::json!({ "path": portfolio }))
+                })?;
+            if positions.is_empty() {
+                anyhow::bail!(CliError::validation("INVALID_PORTFOLIO", format!("No positions in {}", portfolio.display()))
+                    .with_details(serde_json::json!({ "path": portfolio })));
+            }
+
+            let mut watcher = quant::watch::Watcher::new(positions, rate, volatility, model);
+            let provider = market_data_provider(&settings.quant.market_data, None)?;
+            let symbols = watcher.symbols();
+            // A single tick polls; otherwise quotes stream in between ticks
+            let mut streamed = match once {
+                true => None,
+                false => {
+                    use quant::market_stream::MarketDataStream;
+                    let feed = quant::market_stream::QuoteFeed::spawn(&settings.quant.stream, provider.clone(), interval.as_std());
+                    let mut latest = quant::watch::LatestQuotes::new(feed.subscribe(&symbols.iter().cloned().collect::<Vec<_>>()));
+                    latest.prime(tokio::time::Instant::now() + interval.as_std()).await;
+                    Some(latest)
+                }
+            };
+            let max_age = settings.quant.stream.stale_after.as_chrono() + interval.as_chrono();
+            loop {
+                let quotes = match streamed.as_ref() {
+                    Some(latest) => latest.quotes(clock::now(), max_age),
+                    None => quant::watch::fetch_quotes(&provider, &symbols).await,
+                };
+                let snapshot = watcher.snapshot(quotes, clock::now());
+                match (cli.output, once) {
+                    (OutputFormat::Json, true) => println!("{}", serde_json::to_string_pretty(&snapshot)?),
+                    // One object per line while streaming
+                    (OutputFormat::Json, false) => println!("{}", serde_json::to_string(&snapshot)?),
+                    (OutputFormat::Text, true) => print!("{}", snapshot),
+                    (OutputFormat::Text, false) => print!("\x1b[2J\x1b[H{}", snapshot),
+                }
+                if once {
+                    break;
+                }
+                let next_tick = tokio::time::Instant::now() + interval.as_std();
+                let wait = async {
+                    match streamed.as_mut() {
+                        Some(latest) => latest.follow_until(next_tick).await,
+                        None => tokio::time::sleep_until(next_tick).await,
+                    }
+                };
+                tokio::select! {
+                    _ = wait => {}
+                    _ = tokio::signal::ctrl_c() => break,
+                }
+            }
+        }
+        Commands::Backtest {
+            candles,
+            symbol,
+            strategy_wasm,
+            strategy_config,
+            quantity,
+            cash,
+            asset_type,
+            sizing,
+            sizing_lookback,
+            adjusted,
+        } => {
+            if quantity <= rust_decimal::Decimal::ZERO {
+                anyhow::bail!(CliError::validation("INVALID_QUANTITY", "--quantity must be positive"));
+            }
+            let portfolio = &settings.portfolio;
+            let mut strategy = load_strategy(&strategy_wasm, strategy_config.as_deref(), &portfolio.strategy)?;
+            let file = std::fs::File::open(&candles)?;
+            let rows = quant::export::stream_candles_csv(file)?.filter(|c| match (&symbol, c) {
+                (Some(symbol), Ok(c)) => c.symbol.eq_ignore_ascii_case(symbol),
+                _ => true,
+            });
+            // Back-adjusting needs the whole history, so only then is it held in memory
+            let rows: Box<dyn Iterator<Item = Result<quant::Candle>>> = match adjusted {
+                true => {
+                    let candles = rows.collect::<Result<Vec<_>>>()?;
+                    let actions = recorded_actions(&settings, &dirs)?;
+                    Box::new(quant::corporate_actions::back_adjust(&candles, &actions).into_iter().map(Ok))
+                }
+                false => Box::new(rows),
+            };
+            let backtester = quant::strategy::Backtester {
+                quantity,
+                starting_cash: cash.unwrap_or(portfolio.account.starting_cash),
+                fees: portfolio.fees,
+                sizing: sizing.policy(sizing_lookback)?,
+                account: portfolio.account.clone(),
+                asset_type,
+            };
+            let report = backtester.run(&mut strategy, rows)?;
+            if report.candles == 0 {
+                anyhow::bail!(CliError::not_found("NO_CANDLES", format!("No candles in {}", candles.display()))
+                    .with_details(serde_json::json!({ "symbol": symbol, "path": candles })));
+            }
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Text => print!("{}", report),
+            }
+        }
+        Commands::Telemetry { action: TelemetryAction::Summary } => {
+            let summary = p2p::telemetry::TelemetrySummary::load(&dirs.telemetry_summary_path()?)?.ok_or_else(|| {
+                CliError::not_found("NO_TELEMETRY", "No stats collected yet (run p2p --telemetry-collector)")
+            })?;
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&summary)?),
+                OutputFormat::Text => print!("{}", summary),
+            }
+        }
+        Commands::Loadtest { mode: load_mode, target, clients, rate, duration, message_size, topic, request, drain } => {
+            let config = p2p::loadtest::LoadTestConfig {
+                mode: load_mode,
+                clients,
+                rate,
+                duration: duration.as_std(),
+                message_size: message_size.bytes() as usize,
+                topic,
+                request,
+                drain: drain.as_std(),
+            };
+            let target = match target {
+                Some(addr) => p2p::loadtest::LoadTarget::Remote(addr.parse()?),
+                None => {
+                    let mut node = p2p::P2PNode::with_transport(libp2p::identity::Keypair::generate_ed25519(), p2p::TransportKind::Memory)?;
+                    node.disable_mdns();
+                    node.set_rate_limits(&settings.p2p.rate_limits);
+                    node.set_request_limits(settings.p2p.request_limits.clone());
+                    p2p::loadtest::LoadTarget::InProcess(Box::new(node))
+                }
+            };
+            info!("Running {} load test for {}", load_mode, duration);
+            let report = p2p::loadtest::run(&config, target).await?;
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Text => print!("{}", report),
+            }
+        }
+        Commands::Network { action: NetworkAction::Doctor } => {
+            let report = net::doctor::ProxyReport::run(&settings.network.proxy).await;
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Text => print!("{}", report),
+            }
+            if !report.healthy() {
+                anyhow::bail!(CliError::new(
+                    cli_error::ErrorKind::Failure,
+                    "PROXY_UNAVAILABLE",
+                    "The outbound proxy failed its checks"
+                ));
+            }
+        }
+        Commands::Keys { action: KeysAction::Doctor } => {
+            let kind = settings.keys.provider;
+            let report = crypto::key_provider::DoctorReport::run(kind, crypto::key_provider::open(&settings.keys, &dirs));
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Text => print!("{}", report),
+            }
+            if !report.healthy() {
+                anyhow::bail!(CliError::new(
+                    cli_error::ErrorKind::Failure,
+                    "KEY_PROVIDER_UNAVAILABLE",
+                    format!("The {} key provider failed its checks", kind)
+                ));
+            }
+        }
+        Commands::Keys { action: KeysAction::List { keystore } } => {
+            let crypto = crypto::CryptoManager::open(&settings.crypto, &dirs)?;
+            let listings = crypto.list_keys(keystore.as_deref())?;
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&listings)?),
+                OutputFormat::Text => listings.iter().for_each(|listing| print!("{}", listing)),
+            }
+        }
+        Commands::Keys { action: KeysAction::Import { input, keystore } } => {
+            let armored = match &input {
+                Some(path) => std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?,
+                None => {
+                    let mut armored = String::new();
+                    std::io::Read::read_to_string(&mut std::io::stdin(), &mut armored)?;
+                    armored
+                }
+            };
+            let crypto = crypto::CryptoManager::open(&settings.crypto, &dirs)?;
+            let imported = crypto
+                .import_public_key(keystore.as_deref(), &armored)
+                .await
+                .map_err(|e| match e.downcast_ref::<crypto::KeystoreError>() {
+                    Some(_) => e,
+                    None => CliError::validation("INVALID_PUBLIC_KEY", format!("{:#}", e)).into(),
+                })?;
+            println!("📥 Imported {}", imported);
+        }
+        Commands::Keys { action: KeysAction::Export { key, keystore, secret, confirm } } => {
+            let crypto = crypto::CryptoManager::open(&settings.crypto, &dirs)?;
+            let armored = match secret {
+                true => crypto.export_secret_key(&key, keystore.as_deref(), confirm).await?,
+                false => crypto.locate(&key, keystore.as_deref()).await?.public_key,
+            };
+            println!("{}", armored);
+        }
+        Commands::Keys { action: KeysAction::Delete { key, keystore } } => {
+            let crypto = crypto::CryptoManager::open(&settings.crypto, &dirs)?;
+            let deleted = crypto.delete_key(&key, keystore.as_deref()).await?;
+            println!("🗑️  Deleted {}", deleted);
+        }
+        Commands::Keys { action: KeysAction::Passphrase { keystore } } => {
+            let crypto = crypto::CryptoManager::open(&settings.crypto, &dirs)?;
+            let name = crypto.select(keystore.as_deref())?.to_string();
+            let config = settings.crypto.keystores().remove(&name).unwrap_or_default();
+            let Some(passphrase) = config.resolve_passphrase(&name) else {
+                anyhow::bail!(CliError::validation(
+                    "NO_PASSPHRASE",
+                    format!("Set {} (or passphrase_env) to the new passphrase", crypto::passphrase_env(&name))
+                ));
+            };
+            crypto.set_passphrase(&name, &passphrase)?;
+            println!("🔒 Keystore '{}' is now passphrase-protected", name);
+        }
+        Commands::Secrets { action } => {
+            if mode.is_ephemeral() && !matches!(action, SecretsAction::List) {
+                anyhow::bail!(CliError::validation(
+                    "EPHEMERAL_MODE",
+                    "Secrets aren't stored in ephemeral mode; set QUANTRA_SECRET_<NAME> variables instead",
+                ));
+            }
+            let store = open_secrets(&settings, &dirs)?;
+            match action {
+                SecretsAction::Set { name, from_env } => {
+                    crypto::secrets::validate_name(&name)?;
+                    let value = match from_env {
+                        Some(var) => std::env::var(&var)
+                            .map_err(|_| CliError::validation("EMPTY_SECRET", format!("{} is not set", var)))?,
+                        None => {
+                            let mut value = String::new();
+                            std::io::Read::read_to_string(&mut std::io::stdin(), &mut value)?;
+                            value.trim_end_matches(['\r', '\n']).to_string()
+                        }
+                    };
+                    if value.is_empty() {
+                        anyhow::bail!(CliError::validation("EMPTY_SECRET", format!("No value given for secret '{}'", name)));
+                    }
+                    store.set(&name, &value)?;
+                    println!("🔐 Stored secret '{}'", name);
+                }
+                SecretsAction::List => {
+                    let stored = store.names()?;
+                    let references = crypto::secrets::references(&settings)?;
+                    let mut names: std::collections::BTreeSet<String> = stored.iter().cloned().collect();
+                    names.extend(references.iter().map(|r| r.secret.clone()));
+                    let listing: Vec<serde_json::Value> = names
+                        .iter()
+                        .map(|name| {
+                            let referenced_by: Vec<&str> =
+                                references.iter().filter(|r| &r.secret == name).map(|r| r.config_key.as_str()).collect();
+                            serde_json::json!({
+                                "name": name,
+                                "stored": stored.contains(name),
+                                "env_override": std::env::var(crypto::secrets::env_var(name)).is_ok(),
+                                "referenced_by": referenced_by,
+                            })
+                        })
+                        .collect();
+                    match cli.output {
+                        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "secrets": listing }))?),
+                        OutputFormat::Text if listing.is_empty() => println!("No secrets"),
+                        OutputFormat::Text => {
+                            for entry in &listing {
+                                let name = entry["name"].as_str().unwrap_or_default();
+                                let source = match (entry["env_override"].as_bool(), entry["stored"].as_bool()) {
+                                    (Some(true), _) => format!("from {}", crypto::secrets::env_var(name)),
+                                    (_, Some(true)) => "stored".to_string(),
+                                    _ => "⚠️  missing".to_string(),
+                                };
+                                let referenced_by: Vec<&str> =
+                                    entry["referenced_by"].as_array().into_iter().flatten().filter_map(|k| k.as_str()).collect();
+                                match referenced_by.is_empty() {
+                                    true => println!("🔐 {}  ({}, unreferenced)", name, source),
+                                    false => println!("🔐 {}  ({}, used by {})", name, source, referenced_by.join(", ")),
+                                }
+                            }
+                        }
+                    }
+                }
+                SecretsAction::Rm { name } => {
+                    store.remove(&name)?;
+                    println!("Removed secret '{}'", name);
+                }
+                SecretsAction::RotateKey => {
+                    let count = store.rotate_key()?;
+                    println!("🔑 Re-encrypted {} secret(s) under a new data key", count);
+                }
+            }
+        }
+        Commands::Config { action: ConfigAction::Show } => {
+            let shown = crypto::secrets::redacted(&settings)?;
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&shown)?),
+                OutputFormat::Text => print!("{}", toml::to_string_pretty(&shown)?),
+            }
+        }
+        Commands::Alerts { action } => {
+            let config = settings.alerts;
+            let store = alerts::store::AlertStore::open(&config.store_path(&dirs)?, mode)?;
+            match action {
+                AlertAction::Add { symbol, above, below, pct_change, window, spread_above, cooldown } => {
+                    let conditions = [
+                        above.map(|t| (alerts::AlertCondition::PriceAbove, t)),
+                        below.map(|t| (alerts::AlertCondition::PriceBelow, t)),
+                        pct_change.map(|t| {
+                            let window_secs = window.as_secs() as i64;
+                            (alerts::AlertCondition::PctChangeOver { window_secs }, t)
+                        }),
+                        spread_above.map(|t| (alerts::AlertCondition::SpreadAbove, t)),
+                    ];
+                    let mut chosen = conditions.into_iter().flatten();
+                    let (Some((condition, threshold)), None) = (chosen.next(), chosen.next()) else {
+                        anyhow::bail!(CliError::validation(
+                            "ALERT_CONDITION",
+                            "Specify exactly one of --above, --below, --pct-change or --spread-above",
+                        ));
+                    };
+                    if matches!(condition, alerts::AlertCondition::PctChangeOver { window_secs } if window_secs <= 0) {
+                        anyhow::bail!(CliError::validation(
+                            "INVALID_WINDOW",
+                            format!("--window must be at least 1s (got {})", window),
+                        ));
+                    }
+
+                    let rule = alerts::AlertRule::new(&symbol, condition, threshold, cooldown.as_chrono());
+                    store.put(&rule)?;
+                    println!("🔔 Added alert {}: {} {} {}", rule.id, rule.symbol, rule.condition, rule.threshold);
+                }
+                AlertAction::List => {
+                    let rules = store.list()?;
+                    if rules.is_empty() {
+                        println!("No alert rules");
+                    }
+                    for rule in rules {
+                        let last = rule
+                            .last_fired
+                            .map(|t| t.to_rfc3339())
+                            .unwrap_or_else(|| "never".to_string());
+                        println!(
+                            "{}  {:<8} {} {}  (cooldown {}s, last fired {})",
+                            rule.id, rule.symbol, rule.condition, rule.threshold, rule.cooldown_secs, last
+                        );
+                    }
+                }
+                AlertAction::Remove { id } => {
+                    if store.remove(&id)? {
+                        println!("Removed alert {}", id);
+                    } else {
+                        anyhow::bail!(CliError::not_found("UNKNOWN_ALERT", format!("No alert with id {}", id))
+                            .with_details(serde_json::json!({ "id": id })));
+                    }
+                }
+                AlertAction::Run => {
+                    let mut sinks: Vec<Box<dyn alerts::delivery::AlertSink>> = vec![Box::new(alerts::delivery::LogSink)];
+                    if let Some(url) = &config.webhook_url {
+                        sinks.push(Box::new(alerts::delivery::WebhookSink::new(url)));
+                    }
+                    let portfolio = &settings.portfolio;
+                    let portfolio_store =
+                        std::sync::Arc::new(quant::portfolio_store::PortfolioStore::open(&portfolio.store_path(&dirs)?, mode)?);
+                    // Runs until `alerts::run` returns
+                    let _valuations = portfolio_scheduler(portfolio_store.clone(), portfolio, &settings.quant.market_data)?;
+                    let positions = alerts::positions::PositionMonitor::new(portfolio_store, portfolio.paper_trading);
+                    let interval = config.poll_interval.as_std();
+                    let feed = quant::market_stream::QuoteFeed::spawn(
+                        &settings.quant.stream,
+                        market_data_provider(&settings.quant.market_data, None)?,
+                        interval,
+                    );
+                    alerts::run(alerts::AlertEvaluator::new(store), Some(positions), feed, sinks, interval).await?;
+                }
+            }
+        }
+        Commands::Portfolio { action } => {
+            let config = settings.portfolio;
+            let store = quant::portfolio_store::PortfolioStore::open(&config.store_path(&dirs)?, mode)?;
+            let mut portfolio = store.load()?;
+            let account = if config.paper_trading {
+                store.seed_cash(config.account.starting_cash, clock::today())?;
+                let mut account = quant::account::PaperAccount::new(config.account.clone(), config.fees);
+                if let Some(notifier) = &notifier {
+                    account.set_notifier(notifier.clone());
+                }
+                Some(account)
+            } else {
+                None
+            };
+            match action {
+                PortfolioAction::Show { export, out } => {
+                    if portfolio.positions.is_empty() {
+                        println!("No positions");
+                    }
+                    let mut positions: Vec<_> = portfolio.positions.values().collect();
+                    positions.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+                    if let (Some(format), Some(path)) = (export, &out) {
+                        let written = quant::export::export_as::<quant::portfolio::Position, _>(path, format, positions.iter().copied())?;
+                        println!("📤 Wrote {} position(s) to {}", written, path.display());
+                    }
+                    for pos in positions {
+                        println!(
+                            "{:<8} {} @ {}  (last {}, P&L {})",
+                            pos.symbol,
+                            pos.quantity,
+                            pos.average_cost.round_dp(4),
+                            pos.current_price,
+                            portfolio.position_pnl(&pos.symbol).unwrap_or_default().round_dp(2)
+                        );
+                        let Some(rule) = &pos.risk else { continue };
+                        if let Some(stop) = rule.stop_price(pos.average_cost) {
+                            let kind = if rule.trailing { "trailing stop" } else { "stop" };
+                            println!("         {} {} ({}%)", kind, stop.round_dp(4), rule.stop_loss_pct.unwrap_or_default());
+                        }
+                        if let Some(target) = rule.take_profit_price(pos.average_cost) {
+                            println!("         take profit {} ({}%)", target.round_dp(4), rule.take_profit_pct.unwrap_or_default());
+                        }
+                        if let Some(at) = rule.triggered_at {
+                            println!("         triggered {}", at.to_rfc3339());
+                        }
+                    }
+                    println!("{:<8} {}", "CASH", store.cash()?.round_dp(2));
+                    if !portfolio.positions.is_empty() {
+                        println!("Value {}  (unrealized P&L {})", portfolio.total_value().round_dp(2), portfolio.unrealized_pnl().round_dp(2));
+                    }
+                }
+                PortfolioAction::Value { refresh } => {
+                    if refresh {
+                        let engine = market_data_engine(&settings.quant.market_data, None)?;
+                        let mut symbols: Vec<String> = portfolio.positions.keys().cloned().collect();
+                        symbols.sort();
+                        for symbol in symbols {
+                            let quote = engine.get_quote(&symbol).await?;
+                            portfolio.update_price(&symbol, quote.last);
+                            store.put_position(&portfolio.positions[&symbol])?;
+                        }
+                    }
+                    let mut positions: Vec<_> = portfolio.positions.values().collect();
+                    positions.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+                    if cli.output == OutputFormat::Json {
+                        let report = serde_json::json!({
+                            "positions": positions.iter().map(|pos| serde_json::json!({
+                                "symbol": pos.symbol,
+                                "quantity": pos.quantity,
+                                "price": pos.current_price,
+                                "value": pos.quantity * pos.current_price,
+                                "unrealized_pnl": portfolio.position_pnl(&pos.symbol),
+                            })).collect::<Vec<_>>(),
+                            "total_value": portfolio.total_value(),
+                            "unrealized_pnl": portfolio.unrealized_pnl(),
+                        });
+                        println!("{}", serde_json::to_string_pretty(&report)?);
+                        return Ok(());
+                    }
+                    for pos in positions {
+                        println!(
+                            "{:<8} {} x {} = {}  (P&L {})",
+                            pos.symbol,
+                            pos.quantity,
+                            pos.current_price,
+                            (pos.quantity * pos.current_price).round_dp(2),
+                            portfolio.position_pnl(&pos.symbol).unwrap_or_default().round_dp(2)
+                        );
+                    }
+                    println!("Value {}  (unrealized P&L {})", portfolio.total_value().round_dp(2), portfolio.unrealized_pnl().round_dp(2));
+                }
+                PortfolioAction::Export { out } => {
+                    match quant::export::ExportFormat::from_path(&out) {
+                        Ok(format) => {
+                            let mut positions: Vec<_> = portfolio.positions.values().collect();
+                            positions.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+                            quant::export::export_as::<quant::portfolio::Position, _>(&out, format, positions)?;
+                        }
+                        Err(_) => portfolio.save(&out)?,
+                    }
+                    println!("✅ Exported {} positions to {}", portfolio.positions.len(), out.display());
+                }
+                PortfolioAction::Buy { symbol, quantity, price, sizing, asset_vol, adv, capital, asset_type } => {
+                    let quantity = match (sizing.policy(0)?, quantity) {
+                        (Some(policy), _) => {
+                            let inputs = quant::sizing::SizingInputs {
+                                portfolio_value: capital.unwrap_or_else(|| portfolio.total_value()),
+                                price,
+                                lot_size: policy.lot_size,
+                                vol_target: match (policy.target_annual_vol, asset_vol) {
+                                    (Some(target_annual_vol), Some(pct)) => Some(quant::sizing::VolTarget {
+                                        target_annual_vol,
+                                        asset_vol: fraction(pct),
+                                    }),
+                                    (Some(_), None) => anyhow::bail!(CliError::validation(
+                                        "INVALID_SIZING",
+                                        "--size-by vol-target needs --asset-vol"
+                                    )),
+                                    (None, _) => None,
+                                },
+                                kelly: policy.kelly,
+                                liquidity: match (policy.participation_cap, adv) {
+                                    (Some(participation_cap), Some(average_daily_volume)) => {
+                                        Some(quant::sizing::LiquidityCap { average_daily_volume, participation_cap })
+                                    }
+                                    (Some(_), None) => {
+                                        anyhow::bail!(CliError::validation("INVALID_SIZING", "--participation needs --adv"))
+                                    }
+                                    (None, _) => None,
+                                },
+                            };
+                            let suggestion = quant::sizing::suggest_size(&inputs)?;
+                            match cli.output {
+                                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&suggestion)?),
+                                OutputFormat::Text => print!("{}", suggestion),
+                            }
+                            if suggestion.quantity.is_zero() {
+                                anyhow::bail!(CliError::validation(
+                                    "ZERO_SIZE",
+                                    format!("Sized {} to zero: {}", symbol.to_uppercase(), suggestion.reason.as_deref().unwrap_or_default()),
+                                )
+                                .with_details(serde_json::to_value(&suggestion)?));
+                            }
+                            suggestion.quantity
+                        }
+                        (None, Some(quantity)) => quantity,
+                        (None, None) => anyhow::bail!(CliError::validation("INVALID_TRADE", "Give --quantity or --size-by")),
+                    };
+                    let side = quant::TradeSide::Buy;
+                    let lots = Vec::new();
+                    record_trade(&store, account.as_ref(), &mut portfolio, &symbol, side, quantity, price, asset_type, lots)?;
+                }
+                PortfolioAction::Sell { symbol, quantity, price, lots } => {
+                    let method = config.cost_basis.method;
+                    let specific = method == quant::cost_basis::CostBasisMethod::SpecificLot;
+                    if specific && lots.is_empty() {
+                        anyhow::bail!(quant::cost_basis::LotError::NoLotsSelected { symbol: symbol.to_uppercase() });
+                    }
+                    if !specific && !lots.is_empty() {
+                        anyhow::bail!(CliError::validation(
+                            "LOTS_UNUSED",
+                            format!("--lots needs portfolio.cost_basis.method = \"specific_lot\" (it is \"{}\")", method),
+                        ));
+                    }
+                    let asset_type = portfolio.positions.get(&symbol.to_uppercase()).map(|p| p.asset_type).unwrap_or_default();
+                    let side = quant::TradeSide::Sell;
+                    record_trade(&store, account.as_ref(), &mut portfolio, &symbol, side, quantity, price, asset_type, lots)?;
+                }
+                PortfolioAction::SetStop { symbol, stop, trail, take_profit, auto_close } => {
+                    let symbol = symbol.to_uppercase();
+                    if stop.is_none() && take_profit.is_none() {
+                        anyhow::bail!(CliError::validation("RISK_RULE", "Specify --stop and/or --take-profit"));
+                    }
+                    if trail && stop.is_none() {
+                        anyhow::bail!(CliError::validation("RISK_RULE", "--trail needs --stop"));
+                    }
+                    if stop.is_some_and(|pct| pct >= rust_decimal::Decimal::ONE_HUNDRED) {
+                        anyhow::bail!(CliError::validation("RISK_RULE", "--stop must be below 100%"));
+                    }
+                    if auto_close && !config.paper_trading {
+                        tracing::warn!("📉 --auto-close only acts with portfolio.paper_trading = true; alerts only");
+                    }
+                    let rule = quant::portfolio::RiskRule {
+                        stop_loss_pct: stop,
+                        take_profit_pct: take_profit,
+                        trailing: trail,
+                        auto_close,
+                        ..Default::default()
+                    };
+                    if !portfolio.set_risk_rule(&symbol, rule) {
+                        anyhow::bail!(CliError::not_found("UNKNOWN_POSITION", format!("No position in {}", symbol))
+                            .with_details(serde_json::json!({ "symbol": symbol })));
+                    }
+                    store.put_position(&portfolio.positions[&symbol])?;
+                    println!("🛑 Risk rule set for {}", symbol);
+                }
+                PortfolioAction::RunStrategy { symbol, strategy_wasm, strategy_config, quantity, interval, asset_type } => {
+                    let Some(account) = &account else {
+                        anyhow::bail!(CliError::validation(
+                            "PAPER_TRADING_DISABLED",
+                            "run-strategy requires portfolio.paper_trading = true"
+                        ));
+                    };
+                    if quantity <= rust_decimal::Decimal::ZERO {
+                        anyhow::bail!(CliError::validation("INVALID_QUANTITY", "--quantity must be positive"));
+                    }
+                    let mut strategy = load_strategy(&strategy_wasm, strategy_config.as_deref(), &config.strategy)?;
+                    let mut trader = quant::strategy::PaperTrader {
+                        strategy: &mut strategy,
+                        store: &store,
+                        portfolio,
+                        symbol: symbol.to_uppercase(),
+                        quantity,
+                        account,
+                        asset_type,
+                    };
+                    trader.run(&market_data_provider(&settings.quant.market_data, None)?, interval.as_std()).await?;
+                }
+                PortfolioAction::Rebalance {
+                    targets,
+                    cash_in,
+                    cash_out,
+                    min_trade,
+                    lot_size,
+                    max_turnover,
+                    do_not_sell,
+                    execute_paper,
+                } => {
+                    if execute_paper && !config.paper_trading {
+                        anyhow::bail!(CliError::validation(
+                            "PAPER_TRADING_DISABLED",
+                            "--execute-paper requires portfolio.paper_trading = true"
+                        ));
+                    }
+                    let targets = rebalance_targets_arg(&targets)?;
+                    let engine = market_data_engine(&settings.quant.market_data, None)?;
+                    let mut quotes = std::collections::HashMap::new();
+                    let symbols: std::collections::BTreeSet<String> =
+                        targets.keys().map(|s| s.to_uppercase()).chain(portfolio.positions.keys().cloned()).collect();
+                    for symbol in symbols {
+                        let quote = engine.get_quote(&symbol).await?;
+                        quotes.insert(symbol, quote.last);
+                    }
+                    let constraints = quant::rebalance::RebalanceConstraints {
+                        min_trade_notional: min_trade,
+                        lot_size,
+                        turnover_cap: max_turnover,
+                        do_not_sell: do_not_sell.iter().map(|s| s.to_uppercase()).collect(),
+                        cash_flow: cash_in - cash_out,
+                        fees: config.fees,
+                    };
+                    let plan = portfolio
+                        .rebalance(&targets, &quotes, &constraints)
+                        .map_err(|e| CliError::validation("INVALID_TARGETS", e.to_string()))?;
+                    match cli.output {
+                        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&plan)?),
+                        OutputFormat::Text => print!("{}", plan),
+                    }
+                    if execute_paper {
+                        let trades = plan.to_trades();
+                        let count = trades.len();
+                        for trade in trades {
+                            store.execute(&mut portfolio, trade, Some("rebalance".to_string()))?;
+                        }
+                        println!("📒 Recorded {} rebalance trade(s)", count);
+                    }
+                }
+                PortfolioAction::Cash { deposit, withdraw, date, note } => {
+                    let given = deposit.or(withdraw).unwrap_or_default();
+                    if given <= rust_decimal::Decimal::ZERO {
+                        anyhow::bail!(CliError::validation("INVALID_AMOUNT", "--in / --out must be a positive amount")
+                            .with_details(serde_json::json!({ "amount": given })));
+                    }
+                    let amount = if deposit.is_some() { given } else { -given };
+                    let date = date.unwrap_or_else(clock::today);
+                    let flow = quant::performance::CashFlow::new(date, amount, note);
+                    let cash = store.record_cash_flow(&flow)?;
+                    let kind = if amount.is_sign_positive() { "deposit" } else { "withdrawal" };
+                    println!("💵 Recorded {} {} of {} on {}; cash {}", kind, flow.id, amount.abs(), date, cash.round_dp(2));
+                }
+                PortfolioAction::Ledger { export, out } => print_ledger(&store, export, out.as_deref())?,
+                PortfolioAction::Lots { symbol, method } => {
+                    let book = lot_book(&store, &config.cost_basis, method)?;
+                    let lots: Vec<_> = match &symbol {
+                        Some(symbol) => book.lots_of(symbol).iter().collect(),
+                        None => book.lots().collect(),
+                    };
+                    match cli.output {
+                        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&lots)?),
+                        OutputFormat::Text => {
+                            if lots.is_empty() {
+                                println!("No open lots");
+                            }
+                            for lot in lots {
+                                println!(
+                                    "{:<8} {}  {}  {} @ {}",
+                                    lot.symbol,
+                                    lot.id,
+                                    lot.opened.date_naive(),
+                                    lot.quantity,
+                                    lot.price.round_dp(4)
+                                );
+                            }
+                        }
+                    }
+                    for symbol in book.unreconciled(&portfolio) {
+                        tracing::warn!("⚠️  {} lots don't add up to the position: trades recorded outside the ledger?", symbol);
+                    }
+                }
+                PortfolioAction::Gains { method, out } => {
+                    let book = lot_book(&store, &config.cost_basis, method)?;
+                    let report = quant::cost_basis::GainsReport::new(&book);
+                    if let Some(path) = out {
+                        let written = quant::export::export_to_path::<quant::cost_basis::RealizedGain, _>(&path, &report.gains)?;
+                        println!("📤 Wrote {} realized gain(s) to {}", written, path.display());
+                    } else if cli.output == OutputFormat::Json {
+                        println!("{}", serde_json::to_string_pretty(&report)?);
+                    } else {
+                        println!("Realized gains ({}; long term after {} days)", report.method, report.long_term_days);
+                        for (term, totals) in [
+                            (quant::cost_basis::HoldingTerm::Short, &report.short),
+                            (quant::cost_basis::HoldingTerm::Long, &report.long),
+                        ] {
+                            println!("{}-term", term);
+                            for gain in report.of_term(term) {
+                                println!(
+                                    "  {:<8} {} → {}  {}  {} @ cost {}  gain {}",
+                                    gain.symbol,
+                                    gain.lot_id,
+                                    gain.sell_id,
+                                    gain.closed.date_naive(),
+                                    gain.quantity,
+                                    gain.cost.round_dp(2),
+                                    gain.gain.round_dp(2)
+                                );
+                            }
+                            println!(
+                                "  total: proceeds {}, cost {}, gain {}",
+                                totals.proceeds.round_dp(2),
+                                totals.cost.round_dp(2),
+                                totals.gain.round_dp(2)
+                            );
+                        }
+                    }
+                }
+            }
         }
-        Commands::Encrypt { recipient, message } => {
-            info!("Encrypting message for {}", recipient);
-            println!("Encryption not yet implemented - need recipient's public key");
+        Commands::Trades { action } => {
+            let store = quant::portfolio_store::PortfolioStore::open(&settings.portfolio.store_path(&dirs)?, mode)?;
+            match action {
+                TradesAction::List { export, out } => print_ledger(&store, export, out.as_deref())?,
+                TradesAction::Import { path } => {
+                    let file = std::fs::File::open(&path).with_context(|| format!("Failed to open {}", path.display()))?;
+                    let trades = quant::export::stream_trades_csv(std::io::BufReader::new(file))
+                        .map_err(|e| CliError::validation("INVALID_TRADES", format!("{:#}", e)))?;
+                    let mut portfolio = store.load()?;
+                    let (recorded, skipped) = store
+                        .import_trades(&mut portfolio, trades)
+                        .map_err(|e| CliError::validation("INVALID_TRADES", format!("{:#}", e)))?;
+                    println!("📥 Recorded {} trade(s) from {} ({} already recorded)", recorded, path.display(), skipped);
+                }
+            }
         }
-        Commands::ProvisionEsim { carrier, plan, secure } => {
-            if secure {
-                info!("Provisioning SECURE eSIM for carrier: {}, plan: {}", carrier, plan);
-                println!("🔒 SECURE MODE: TLS 1.3 + AES-256-GCM + Certificate Pinning");
-            } else {
-                info!("Provisioning eSIM for carrier: {}, plan: {}", carrier, plan);
+        Commands::RiskReport { confidence, days, horizon, source, export, out } => {
+            let store = quant::portfolio_store::PortfolioStore::open(&settings.portfolio.store_path(&dirs)?, mode)?;
+            let portfolio = store.load()?;
+            if portfolio.positions.is_empty() {
+                anyhow::bail!(CliError::not_found("NO_POSITIONS", "The portfolio has no positions"));
             }
-
-            let esim_manager = esim::ESimManager::new(
-                "sm-dp.example.com".to_string(),
-                "api-key".to_string(),
-            );
-
-            let request = esim::ESimActivationRequest {
-                device_id: "device-123".to_string(),
-                carrier: carrier.clone(),
-                plan_type: plan.clone(),
-                user_email: "user@example.com".to_string(),
+            let engine = market_data_engine(&settings.quant.market_data, source)?;
+            let mut closes = std::collections::HashMap::new();
+            for symbol in portfolio.positions.keys() {
+                let candles = engine.get_history(symbol, quant::market_data::Interval::Day, days).await?;
+                let series: Vec<f64> = candles.iter().filter_map(|c| rust_decimal::prelude::ToPrimitive::to_f64(&c.close)).collect();
+                closes.insert(symbol.clone(), series);
+            }
+            let report = quant::risk::risk_report(&portfolio, &closes, confidence, horizon)
+                .map_err(|e| CliError::validation("INVALID_RISK_INPUTS", format!("{:#}", e)))?;
+            let written = match (export, &out) {
+                (Some(format), Some(path)) => Some((quant::export::export_as(path, format, report.metrics(clock::now()))?, path)),
+                _ => None,
             };
-
-            let profile = esim_manager.provision_profile(request).await?;
-
-            if secure {
-                println!("✅ eSIM Profile provisioned SECURELY!");
-                println!("🔐 Encryption: AES-256-GCM");
-                println!("🔐 Transport: TLS 1.3");
-                println!("🔐 Authentication: Mutual TLS (mTLS)");
-                println!("🔐 Integrity: HMAC-SHA256");
-            } else {
-                println!("eSIM Profile provisioned!");
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Text => {
+                    println!(
+                        "📉 Risk of {:.2} at {}% over {} day(s), {} scenario(s)",
+                        report.total_value,
+                        report.confidence * 100.0,
+                        report.horizon_days,
+                        report.historical.scenarios
+                    );
+                    println!("  Historical VaR {:>12.2}  CVaR {:>12.2}", report.historical.var, report.historical.cvar);
+                    println!("  Parametric VaR {:>12.2}  CVaR {:>12.2}", report.parametric.var, report.parametric.cvar);
+                    println!("  Sharpe (daily) {:>12.4}", report.sharpe_ratio);
+                    println!("  Max drawdown   {:>11.2}%", report.max_drawdown * 100.0);
+                    if let Some((written, path)) = written {
+                        println!("📤 Wrote {} metric(s) to {}", written, path.display());
+                    }
+                }
             }
-
-            println!("ICCID: {}", profile.iccid);
-            println!("Activation Code: {}", profile.activation_code);
-
-            if secure {
-                println!("\n🔒 Security Features:");
-                println!("  ✓ Certificate verified against GSMA root CAs");
-                println!("  ✓ Certificate pinning enabled");
-                println!("  ✓ Profile data encrypted end-to-end");
-                println!("  ✓ Confirmation code required");
+        }
+        Commands::Actions { action } => {
+            let store = quant::portfolio_store::PortfolioStore::open(&settings.portfolio.store_path(&dirs)?, mode)?;
+            let invalid = |e: anyhow::Error| CliError::validation("INVALID_CORPORATE_ACTION", format!("{:#}", e));
+            match action {
+                ActionsAction::Add { symbol, kind, value, ex_date } => {
+                    let kind = quant::corporate_actions::ActionKind::parse(&kind, &value).map_err(invalid)?;
+                    if symbol.trim().is_empty() {
+                        anyhow::bail!(CliError::validation("INVALID_CORPORATE_ACTION", "The symbol is empty"));
+                    }
+                    let action = quant::corporate_actions::CorporateAction::new(&symbol, ex_date, kind);
+                    match store.put_action(&action)? {
+                        true => println!("🏷️  Recorded {}", action),
+                        false => println!("🏷️  {} was already recorded", action),
+                    }
+                    if ex_date <= clock::today() {
+                        println!("   Due: applied by the next daily run or `actions apply`");
+                    }
+                }
+                ActionsAction::Import { path } => {
+                    let file = std::fs::File::open(&path)?;
+                    let actions = quant::corporate_actions::read_csv(file).map_err(invalid)?;
+                    let mut recorded = 0;
+                    for action in &actions {
+                        recorded += usize::from(store.put_action(action)?);
+                    }
+                    println!("🏷️  Recorded {} of {} action(s) from {}", recorded, actions.len(), path.display());
+                }
+                ActionsAction::List => {
+                    let mut applied = store.applied_actions()?;
+                    let rows: Vec<_> = store
+                        .actions()?
+                        .into_iter()
+                        .map(|action| {
+                            let done = applied.iter().position(|a| a.action.key() == action.key()).map(|i| applied.swap_remove(i));
+                            (action, done)
+                        })
+                        .collect();
+                    match cli.output {
+                        OutputFormat::Json => {
+                            let rows: Vec<_> =
+                                rows.iter().map(|(action, done)| serde_json::json!({ "action": action, "applied": done })).collect();
+                            println!("{}", serde_json::to_string_pretty(&rows)?)
+                        }
+                        OutputFormat::Text if rows.is_empty() => println!("No corporate actions"),
+                        OutputFormat::Text => {
+                            for (action, done) in rows {
+                                match done {
+                                    Some(done) => println!(
+                                        "✅ {}  applied {}: {} → {} shares{}",
+                                        action,
+                                        done.applied_at.format("%Y-%m-%d"),
+                                        done.quantity_before,
+                                        done.quantity_after,
+                                        if done.cash.is_zero() { String::new() } else { format!(", {} credited", done.cash) }
+                                    ),
+                                    None => println!("⏳ {}", action),
+                                }
+                            }
+                        }
+                    }
+                }
+                ActionsAction::Apply => {
+                    let mut portfolio = store.load()?;
+                    let applied = quant::corporate_actions::apply_due(&store, &mut portfolio, clock::today())?;
+                    if applied.is_empty() {
+                        println!("Nothing due");
+                    }
+                    for done in applied {
+                        println!("🏷️  Applied {}: {} → {} shares", done.action, done.quantity_before, done.quantity_after);
+                        if !done.cash.is_zero() {
+                            println!("   💵 {} credited to cash", done.cash);
+                        }
+                    }
+                }
             }
-
-            println!("\nGenerating QR code...");
-            let qr_data = esim_manager.generate_qr_code(&profile).await?;
-            println!("QR code generated: {} bytes", qr_data.len());
-
-            if secure {
-                println!("\n⚠️  IMPORTANT: Store this QR code securely!");
-                println!("   Only share via encrypted channels");
+        }
+        Commands::Performance { period } => {
+            let store = quant::portfolio_store::PortfolioStore::open(&settings.portfolio.store_path(&dirs)?, mode)?;
+            let today = clock::today();
+            let report = quant::performance::PerformanceReport::build(period, today, &store.valuations()?, &store.cash_flows()?)
+                .map_err(|e| {
+                    CliError::not_found("NO_VALUATIONS", e.to_string())
+                        .with_details(serde_json::json!({ "period": period.to_string() }))
+                })?;
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Text => print!("{}", report),
             }
         }
-        Commands::OptionPrice {
-            spot,
-            strike,
-            rate,
-            volatility,
-            time,
-            option_type,
-        } => {
-            let opt_type = match option_type.to_lowercase().as_str() {
-                "call" => quant::pricing::OptionType::Call,
-                "put" => quant::pricing::OptionType::Put,
-                _ => {
-                    error!("Invalid option type. Use 'call' or 'put'");
-                    return Ok(());
+        Commands::Watchlist { action } => {
+            let key_provider = crypto::key_provider::open(&settings.keys, &dirs)
+                .context("Key provider unavailable; run `quantraband keys doctor` for a diagnosis")?;
+            let store = open_watchlist(&settings, &dirs, key_provider)?;
+            match action {
+                WatchlistAction::Add { symbol } => {
+                    let symbol = quant::watchlist::normalize_symbol(&symbol)?;
+                    match store.add(&symbol)? {
+                        true => println!("👀 Watching {}", symbol),
+                        false => println!("Already watching {}", symbol),
+                    }
                 }
-            };
-
-            let engine = quant::QuantEngine::new();
-            let price = engine
-                .calculate_option_price(spot, strike, rate, volatility, time, opt_type)
-                .await?;
-
-            println!("Option Price: ${:.2}", price);
-
-            let greeks = quant::pricing::calculate_greeks(spot, strike, rate, volatility, time, opt_type)?;
-            println!("\nGreeks:");
-            println!("  Delta: {:.4}", greeks.delta);
-            println!("  Gamma: {:.4}", greeks.gamma);
-            println!("  Vega:  {:.4}", greeks.vega);
-            println!("  Theta: {:.4}", greeks.theta);
-            println!("  Rho:   {:.4}", greeks.rho);
+                WatchlistAction::Remove { symbol } => {
+                    let symbol = quant::watchlist::normalize_symbol(&symbol)?;
+                    if store.remove(&symbol)? {
+                        println!("Stopped watching {}", symbol);
+                    } else {
+                        anyhow::bail!(CliError::not_found("NOT_WATCHED", format!("{} is not on the watchlist", symbol))
+                            .with_details(serde_json::json!({ "symbol": symbol })));
+                    }
+                }
+                WatchlistAction::Show => {
+                    let list = store.load()?;
+                    match cli.output {
+                        OutputFormat::Json => println!(
+                            "{}",
+                            serde_json::to_string_pretty(&serde_json::json!({ "symbols": list.symbols(), "version": list.version }))?
+                        ),
+                        OutputFormat::Text if list.symbols().is_empty() => println!("Watchlist is empty"),
+                        OutputFormat::Text => list.symbols().iter().for_each(|symbol| println!("{}", symbol)),
+                    }
+                }
+                WatchlistAction::Sync { now: false, .. } => {
+                    let config = &settings.quant.watchlist;
+                    match config.sync {
+                        true => println!("`p2p` syncs the watchlist every {}; use --now to sync once", config.sync_interval),
+                        false => println!("Periodic sync is off ([quant.watchlist] sync); use --now to sync once"),
+                    }
+                }
+                WatchlistAction::Sync { now: true, peer } => {
+                    if peer.is_empty() {
+                        anyhow::bail!(CliError::validation("NO_PEERS", "Give at least one --peer to reach the DHT through"));
+                    }
+                    let config = p2p::handle::NodeConfig { mdns: false, ..Default::default() };
+                    let (node, node_task) = p2p::handle::NodeHandle::spawn(config).await?;
+                    let synced = async {
+                        for addr in &peer {
+                            node.dial(addr).await?;
+                        }
+                        tokio::time::timeout(std::time::Duration::from_secs(15), async {
+                            while node.status().await?.dht_peers == 0 {
+                                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                            }
+                            anyhow::Ok(())
+                        })
+                        .await
+                        .map_err(|_| anyhow::anyhow!("No peer joined the DHT within 15s"))??;
+                        quant::watchlist::WatchlistSync::new(std::sync::Arc::new(store), node.clone()).sync().await
+                    }
+                    .await;
+                    node.shutdown().await;
+                    node_task.await.context("P2P node task panicked")??;
+                    let report = synced?;
+                    match cli.output {
+                        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                        OutputFormat::Text => println!(
+                            "🔄 Watchlist v{}: {} symbol(s); {} DHT copies found ({} unreadable), {}{}",
+                            report.version,
+                            report.symbols,
+                            report.found,
+                            report.undecryptable,
+                            if report.updated { "merged remote edits" } else { "no remote edits" },
+                            if report.published { ", published" } else { "" },
+                        ),
+                    }
+                }
+            }
         }
-        Commands::Quote { symbol } => {
+        Commands::Quote { symbol, source } => {
             info!("Fetching quote for {}", symbol);
-            let engine = quant::QuantEngine::new();
+            let engine = market_data_engine(&settings.quant.market_data, source)?;
             let quote = engine.get_quote(&symbol).await?;
             println!("Quote for {}:", quote.symbol);
             println!("  Bid:    ${}", quote.bid);
@@ -220,9 +3094,388 @@ async fn main() -> Result<()> {
             println!("  Volume: {}", quote.volume);
             println!("  Time:   {}", quote.timestamp);
         }
-        Commands::ListCarriers { country, search } => {
+        Commands::History { symbol, days, interval, source, export, out, csv } => {
+            let (export, out) = match csv {
+                Some(path) => (Some(quant::export::ExportFormat::Csv), Some(path)),
+                None => (export, out),
+            };
+            if days == 0 {
+                anyhow::bail!(CliError::validation("INVALID_DAYS", "--days must be positive"));
+            }
+            let engine = market_data_engine(&settings.quant.market_data, source)?;
+            let candles = engine.get_history(&symbol, interval, days).await?;
+            if candles.is_empty() {
+                anyhow::bail!(CliError::not_found("NO_CANDLES", format!("No {} candles for {} in the last {} days", interval, symbol, days))
+                    .with_details(serde_json::json!({ "symbol": symbol, "interval": interval, "days": days })));
+            }
+            if let (Some(format), Some(path)) = (export, &out) {
+                quant::export::export_as::<quant::Candle, _>(path, format, &candles)?;
+            }
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "symbol": candles[0].symbol, "interval": interval, "candles": candles }))?),
+                OutputFormat::Text => {
+                    println!("{:<16} {:>10} {:>10} {:>10} {:>10} {:>12}", "time", "open", "high", "low", "close", "volume");
+                    for candle in &candles {
+                        println!(
+                            "{:<16} {:>10} {:>10} {:>10} {:>10} {:>12}",
+                            candle.timestamp.format("%Y-%m-%d %H:%M").to_string(),
+                            candle.open,
+                            candle.high,
+                            candle.low,
+                            candle.close,
+                            candle.volume
+                        );
+                    }
+                    if let Some(path) = &out {
+                        println!("📤 Wrote {} candles to {}", candles.len(), path.display());
+                    }
+                }
+            }
+        }
+        Commands::VerifyTranscript { bundle, signers, require_cosigned } => {
+            let bundle: p2p::transcript::TranscriptBundle = serde_json::from_str(&std::fs::read_to_string(&bundle)?)?;
+            bundle.verify()?;
+            let mut signed_by = vec![bundle.exporter.clone()];
+            if bundle.is_cosigned() {
+                signed_by.push(bundle.counterparty.clone());
+            }
+            if let Some(missing) = signers.iter().find(|s| !signed_by.contains(s)) {
+                anyhow::bail!(CliError::new(cli_error::ErrorKind::Integrity, "SIGNER_MISSING", format!("Transcript is not signed by {}", missing))
+                    .with_details(serde_json::json!({ "signed_by": signed_by })));
+            }
+            if require_cosigned && !bundle.is_cosigned() {
+                anyhow::bail!(CliError::new(cli_error::ErrorKind::Integrity, "NOT_COSIGNED", "Transcript is signed by the exporter only")
+                    .with_details(serde_json::json!({ "counterparty_signature": bundle.counterparty_signature })));
+            }
+            match cli.output {
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&serde_json::json!({
+                        "verified": true,
+                        "cosigned": bundle.is_cosigned(),
+                        "signed_by": signed_by,
+                        "range": bundle.range,
+                        "head_hash": bundle.head_hash,
+                        "counterparty_signature": bundle.counterparty_signature,
+                    }))?
+                ),
+                OutputFormat::Text => {
+                    print!("{}", bundle);
+                    println!("✅ Chain and signatures verified ({} message(s))", bundle.messages.len());
+                }
+            }
+        }
+        Commands::Inbox { action } => {
+            if mode.is_ephemeral() {
+                println!("No inbox in ephemeral mode");
+                return Ok(());
+            }
+            let store = p2p::receipts::ReceiptStore::open(&dirs.receipts_dir()?, mode, settings.p2p.receipts.clone())?;
+            match action.unwrap_or(InboxAction::List { unread: false }) {
+                InboxAction::List { unread } => {
+                    let mut messages = store.list(Some(p2p::receipts::Direction::Incoming))?;
+                    messages.retain(|m| !unread || m.read_at.is_none());
+                    match cli.output {
+                        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&messages)?),
+                        OutputFormat::Text if messages.is_empty() => println!("📭 No messages"),
+                        OutputFormat::Text => {
+                            for message in messages {
+                                println!(
+                                    "{} {}  {}  from {}",
+                                    message.status_icon(),
+                                    message.id,
+                                    message.at.format("%Y-%m-%d %H:%M:%S UTC"),
+                                    message.peer
+                                );
+                            }
+                        }
+                    }
+                }
+                InboxAction::Read { id } => {
+                    let incoming = store.get(&id)?.is_some_and(|m| m.direction == p2p::receipts::Direction::Incoming);
+                    if !incoming {
+                        anyhow::bail!(CliError::not_found("UNKNOWN_MESSAGE", format!("No received message with id {}", id))
+                            .with_details(serde_json::json!({ "id": id })));
+                    }
+                    let sender = store.mark_read(&id, clock::now())?;
+                    if settings.p2p.receipts.send_read_receipts {
+                        println!("👁️ Read {}; {} gets a read receipt once the node reaches it", id, sender);
+                    } else {
+                        println!("👁️ Read {} (read receipts are off)", id);
+                    }
+                }
+            }
+        }
+        Commands::Outbox { action: OutboxAction::Stats } => {
+            if mode.is_ephemeral() {
+                println!("No outbox in ephemeral mode");
+                return Ok(());
+            }
+            let stats = p2p::outbox::Outbox::inspect(&dirs.outbox_path()?, clock::now())?;
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+                OutputFormat::Text => print!("{}", stats),
+            }
+        }
+        Commands::Guardians { action: GuardiansAction::HashPassphrase } => {
+            use security::guardians::{PassphrasePrompt, TtyPrompt};
+            let passphrase = TtyPrompt.read_passphrase("Break-glass passphrase: ")?;
+            if passphrase.is_empty() {
+                anyhow::bail!(CliError::validation("EMPTY_PASSPHRASE", "The break-glass passphrase is empty"));
+            }
+            if TtyPrompt.read_passphrase("Again: ")? != passphrase {
+                anyhow::bail!(CliError::validation("PASSPHRASE_MISMATCH", "The passphrases did not match"));
+            }
+            let hash = security::guardians::hash_passphrase(&passphrase, security::guardians::PASSPHRASE_ITERATIONS);
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::json!({ "passphrase_hash": hash })),
+                OutputFormat::Text => println!("passphrase_hash = \"{}\"", hash),
+            }
+        }
+        Commands::Approvals { action } => {
+            let mut queue = approvals::ApprovalQueue::open(&dirs.approvals_dir()?, mode)?;
+            let (decision, id) = match action {
+                ApprovalsAction::List => {
+                    let requests = queue.list()?;
+                    match cli.output {
+                        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&requests)?),
+                        OutputFormat::Text => {
+                            println!("✋ {} approval(s) waiting", requests.len());
+                            for request in &requests {
+                                println!("   {}", request);
+                            }
+                        }
+                    }
+                    return Ok(());
+                }
+                ApprovalsAction::Approve { id } => (approvals::Decision::Approve, id),
+                ApprovalsAction::Deny { id } => (approvals::Decision::Deny, id),
+            };
+            let Some(pending) = queue.get(&id)? else {
+                anyhow::bail!(CliError::not_found("UNKNOWN_APPROVAL", format!("No pending approval {}", id))
+                    .with_details(serde_json::json!({ "id": id })));
+            };
+            if let Some(resolution) = pending.resolution {
+                anyhow::bail!(CliError::validation(
+                    "APPROVAL_RESOLVED",
+                    format!("Approval {} was already {} by {}", id, resolution.decision, resolution.by)
+                )
+                .with_details(serde_json::json!({ "id": id, "decision": resolution.decision })));
+            }
+            let zt = zerotrust::ZeroTrustContext::with_data_dirs_and_keys(&dirs, crypto::key_provider::open(&settings.keys, &dirs)?).await?;
+            queue.set_audit(zt.clone());
+            let request = queue.resolve(&id, decision, approvals::LOCAL_CLI, clock::now()).await?;
+            zt.flush_audit_log().await?;
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&request)?),
+                OutputFormat::Text => println!("✋ {} {}: {}", request.id, decision, request.description),
+            }
+        }
+        Commands::Message { action: MessageAction::Status { id } } => {
+            let record = if mode.is_ephemeral() {
+                None
+            } else {
+                p2p::receipts::ReceiptStore::open(&dirs.receipts_dir()?, mode, settings.p2p.receipts.clone())?.get(&id)?
+            };
+            let Some(record) = record else {
+                anyhow::bail!(CliError::not_found("UNKNOWN_MESSAGE", format!("No message with id {}", id))
+                    .with_details(serde_json::json!({ "id": id })));
+            };
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&record)?),
+                OutputFormat::Text => print!("{}", record),
+            }
+        }
+        Commands::Dossier { peer } => {
+            let dossier = p2p::dossier::PeerDossier::from_disk(
+                &peer,
+                &dirs.audit_log_path()?,
+                crypto::key_provider::open(&settings.keys, &dirs)?,
+            )
+            .await?;
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&dossier)?),
+                OutputFormat::Text => print!("{}", dossier),
+            }
+        }
+        Commands::Policy { action: PolicyAction::Simulate { file, since } } => {
+            let policies = zerotrust::policy::PolicyEngine::load_policies(&file)
+                .map_err(|e| CliError::validation("INVALID_POLICY_FILE", format!("{:#}", e)))?;
+            let since = clock::now() - since.as_chrono();
+            let history: Vec<_> = read_audit_events(&settings, &dirs)
+                .await?
+                .iter()
+                .filter(|e| e.timestamp >= since)
+                .filter_map(zerotrust::policy::HistoricalRequest::from_event)
+                .collect();
+            let report = zerotrust::policy::PolicyEngine::simulate(&policies, &history);
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Text => print!("{}", report),
+            }
+        }
+        Commands::Intel { action } => match action {
+            IntelAction::Export { since, out, include_private } => {
+                let since = clock::now() - since.as_chrono();
+                let (records, _) = security::intel::read_journal(&dirs.intel_journal_path()?, 0)?;
+                let records: Vec<_> = records.into_iter().filter(|r| r.timestamp() >= since).collect();
+                let (bundle, summary) = security::intel::StixExporter::new(&settings.intel)
+                    .include_private(include_private || settings.intel.include_private)
+                    .bundle(&records);
+                match out {
+                    Some(path) => {
+                        std::fs::write(&path, serde_json::to_vec_pretty(&bundle)?)
+                            .with_context(|| format!("Failed to write {}", path.display()))?;
+                        match cli.output {
+                            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&summary)?),
+                            OutputFormat::Text => print!("{}", summary),
+                        }
+                    }
+                    None => println!("{}", serde_json::to_string_pretty(&bundle)?),
+                }
+            }
+            IntelAction::Push => {
+                let report = intel_pusher(&settings.intel, &dirs)?.push_pending().await?;
+                match cli.output {
+                    OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                    OutputFormat::Text => print!("{}", report),
+                }
+            }
+        },
+        Commands::Audit { trace, peer } => {
+            let events: Vec<_> = read_audit_events(&settings, &dirs)
+                .await?
+                .into_iter()
+                .filter(|e| trace.as_ref().is_none_or(|id| e.trace_id.as_deref() == Some(id.as_str())))
+                .filter(|e| peer.as_ref().is_none_or(|p| &e.peer_id == p))
+                .collect();
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&events)?),
+                OutputFormat::Text => {
+                    for e in &events {
+                        println!(
+                            "{} {} {} ({:?}) trace={}",
+                            e.timestamp.to_rfc3339(),
+                            e.event_type,
+                            e.peer_id,
+                            e.security_level,
+                            e.trace_id.as_deref().unwrap_or("-")
+                        );
+                    }
+                    println!("{} event(s)", events.len());
+                }
+            }
+        }
+        Commands::CarrierUpdate { action } => match action {
+            CarrierUpdateAction::Keygen => {
+                let key = ed25519_dalek::SigningKey::from_bytes(&rand::random::<[u8; 32]>());
+                println!("Secret key (keep offline): {}", hex::encode(key.to_bytes()));
+                println!("Public key (esim.carrier_maintainer_key): {}", hex::encode(key.verifying_key().to_bytes()));
+            }
+            CarrierUpdateAction::Sign { input, key } => {
+                let key = esim::carrier_updates::parse_signing_key(&std::fs::read_to_string(&key)?)
+                    .map_err(|e| CliError::validation("INVALID_KEY", format!("{} ({})", e, key.display())))?;
+                let update: esim::carrier_updates::CarrierDbUpdate =
+                    serde_json::from_str(&std::fs::read_to_string(&input)?)?;
+                println!("{}", serde_json::to_string_pretty(&update.sign(&key)?)?);
+            }
+        },
+        Commands::Esim { action } => match action {
+            EsimAction::Check { eid, carrier } => {
+                let carriers = open_carrier_db(&dirs, mode);
+                if carriers.get_carrier(&carrier).is_none() {
+                    anyhow::bail!(CliError::not_found(
+                        "UNKNOWN_CARRIER",
+                        format!("Unknown carrier '{}' (see list-carriers)", carrier),
+                    )
+                    .with_details(serde_json::json!({ "carrier": carrier })));
+                }
+                let report = open_device_db(&settings.esim)?.check_compatibility(&carriers, &eid, &carrier)?;
+                match cli.output {
+                    OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                    OutputFormat::Text => print!("{}", report),
+                }
+            }
+            EsimAction::Show { iccid, format, out } => {
+                let store = esim::store::ProfileStore::open(&dirs.esim_store_dir()?, mode)?;
+                let profile = store
+                    .get(&iccid)?
+                    .ok_or_else(|| {
+                        CliError::not_found("UNKNOWN_PROFILE", format!("No provisioned profile with ICCID {}", iccid))
+                            .with_details(serde_json::json!({ "iccid": iccid }))
+                    })?;
+                println!("📱 {} ({} / {})", profile.iccid, profile.carrier_name, profile.plan_type);
+                let formatted = esim::activation::format_activation(&profile, format)?;
+                emit_activation(&formatted, &profile.iccid, out.as_deref())?;
+            }
+            EsimAction::Health { carrier, probe, watch } => {
+                let carriers = open_carrier_db(&dirs, mode);
+                let mut monitor = esim::health::HealthMonitor::open(&settings.esim.health, &dirs.carrier_health_dir()?, mode)?;
+                let mut targets = monitor.targets(&carriers);
+                if let Some(carrier) = &carrier {
+                    let host = carriers.get_carrier(carrier).map(|info| info.sm_dp_address.clone()).ok_or_else(|| {
+                        CliError::not_found("UNKNOWN_CARRIER", format!("Unknown carrier '{}' (see list-carriers)", carrier))
+                            .with_details(serde_json::json!({ "carrier": carrier }))
+                    })?;
+                    targets = vec![(carrier.clone(), host)];
+                }
+                if let Some(notifier) = &notifier {
+                    monitor.set_notifier(notifier.clone());
+                }
+
+                let print = |health: &[esim::health::CarrierHealth]| -> Result<()> {
+                    match cli.output {
+                        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(health)?),
+                        OutputFormat::Text => {
+                            println!("📡 SM-DP+ health ({} carrier(s)):", health.len());
+                            for carrier in health {
+                                println!("   {}", carrier);
+                            }
+                        }
+                    }
+                    Ok(())
+                };
+                if watch {
+                    let monitor = std::sync::Arc::new(monitor);
+                    let mut scheduler = scheduler::Scheduler::new();
+                    monitor.clone().schedule(&mut scheduler, targets.clone())?;
+                    scheduler.start();
+                    let mut ticker = tokio::time::interval(settings.esim.health.interval.as_std());
+                    ticker.tick().await;
+                    loop {
+                        tokio::select! {
+                            _ = ticker.tick() => {
+                                let health = targets.iter().map(|(id, _)| monitor.carrier_health(id)).collect::<Result<Vec<_>>>()?;
+                                print(&health)?;
+                            }
+                            _ = tokio::signal::ctrl_c() => break,
+                        }
+                    }
+                } else {
+                    let health = if probe {
+                        monitor.probe_round(&targets, chrono::Utc::now()).await?
+                    } else {
+                        targets.iter().map(|(id, _)| monitor.carrier_health(id)).collect::<Result<Vec<_>>>()?
+                    };
+                    print(&health)?;
+                }
+            }
+        },
+        Commands::Depth { symbol, levels } => {
+            let provider = market_data_provider(&settings.quant.market_data, None)?;
+            let book = provider.get_depth(&symbol, levels).await?;
+            println!("📚 Depth for {} (sequence {}):", book.symbol, book.sequence);
+            for (price, size) in book.asks.iter().rev() {
+                println!("  ask {:>12} x {}", price, size);
+            }
+            println!("  {}", "-".repeat(24));
+            for (price, size) in &book.bids {
+                println!("  bid {:>12} x {}", price, size);
+            }
+        }
+        Commands::ListCarriers { country, search, with_health } => {
             info!("Listing supported eSIM carriers");
-            let db = esim::carriers::CarrierDatabase::new();
+            let db = open_carrier_db(&dirs, mode);
 
             let carriers = if let Some(country_filter) = country {
                 db.list_by_country(&country_filter)
@@ -238,6 +3491,11 @@ async fn main() -> Result<()> {
             let mut sorted: Vec<_> = carriers.into_iter().collect();
             sorted.sort_by(|a, b| a.1.country.cmp(&b.1.country).then(a.1.name.cmp(&b.1.name)));
 
+            let monitor = if with_health {
+                Some(esim::health::HealthMonitor::open(&settings.esim.health, &dirs.carrier_health_dir()?, mode)?)
+            } else {
+                None
+            };
             let mut current_country = String::new();
 
             for (id, info) in sorted {
@@ -255,19 +3513,137 @@ async fn main() -> Result<()> {
                 if let Some(api) = &info.api_endpoint {
                     println!("       🔗 API: {}", api);
                 }
+                if let Some(monitor) = &monitor {
+                    println!("       🩺 Health: {}", monitor.carrier_health(id)?.summary());
+                }
             }
 
             println!("\n💡 Usage: quantraband provision-esim --carrier <carrier_id> --plan <plan_name>");
             println!("   Add --secure for encrypted provisioning");
         }
+        Commands::Search { query, stores, limit } => {
+            let query = search::Query::new(&query)?;
+            let carriers = open_carrier_db(&dirs, mode);
+            let portfolio = quant::portfolio_store::PortfolioStore::open(&settings.portfolio.store_path(&dirs)?, mode)?;
+            let messages = p2p::receipts::ReceiptStore::open(&dirs.receipts_dir()?, mode, settings.p2p.receipts.clone())?;
+            let audit = open_audit_logger(&settings, &dirs).await?;
+            let mut sources: Vec<&dyn search::Searchable> = vec![&carriers, &portfolio, &messages];
+            if let Some(audit) = &audit {
+                sources.push(audit);
+            }
+
+            let groups = search::search_all(&sources, &query, &stores, limit).await?;
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&groups)?),
+                OutputFormat::Text if groups.is_empty() => println!("🔎 No matches for '{}'", query.as_str()),
+                OutputFormat::Text => {
+                    for group in &groups {
+                        println!("🔎 {} ({})", group.store, group.hits.len());
+                        for hit in &group.hits {
+                            println!("   {:<9} {:<12} {:<20} {}", format!("{:?}", hit.rank).to_lowercase(), hit.field, hit.key, hit.snippet);
+                        }
+                    }
+                }
+            }
+        }
+        Commands::Maintain { now } => {
+            let config = &settings.maintenance;
+            if mode.is_ephemeral() {
+                println!("Nothing to maintain in ephemeral mode");
+                return Ok(());
+            }
+            let mut routine = maintenance::Maintenance::new(config);
+            let zt = zerotrust::ZeroTrustContext::with_data_dirs_and_keys(&dirs, crypto::key_provider::open(&settings.keys, &dirs)?).await?;
+            routine.add_step(maintenance::audit_step(zt.clone()));
+            routine.set_audit(zt.clone());
+            routine.add_step(maintenance::replay_dir_step(dirs.replay_registry_dir()?, settings.p2p.replay.ttl));
+            routine.add_step(maintenance::carrier_health_step(dirs.carrier_health_dir()?, settings.esim.health.retention));
+            for location in migrations::stores(&settings, &dirs)? {
+                // Nothing on disk yet
+                if location.path.join("conf").exists() {
+                    routine.add_step(maintenance::compact_store_step(location));
+                }
+            }
+            if let Some(notifier) = &notifier {
+                routine.set_notifier(notifier.clone());
+            }
+
+            if !now {
+                let steps: Vec<&str> = routine.steps().collect();
+                match config.enabled {
+                    true => println!(
+                        "🧹 Nightly maintenance runs in `p2p` at {} (+ up to {})",
+                        config.at.format("%H:%M"),
+                        config.jitter
+                    ),
+                    false => println!("🧹 Nightly maintenance is disabled ([maintenance] enabled = false)"),
+                }
+                println!("   `maintain --now` runs: {}", steps.join(", "));
+                return Ok(());
+            }
+
+            let report = routine.run_and_publish().await;
+            zt.flush_audit_log().await?;
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Text => print!("{}", report),
+            }
+            if !report.complete {
+                anyhow::bail!(CliError::new(
+                    cli_error::ErrorKind::Failure,
+                    "MAINTENANCE_PARTIAL",
+                    format!("Maintenance steps failed: {}", report.failed_steps().join(", ")),
+                )
+                .with_details(serde_json::to_value(&report)?));
+            }
+        }
+        Commands::Migrate { dry_run } => {
+            if mode.is_ephemeral() {
+                println!("Nothing to migrate in ephemeral mode");
+                return Ok(());
+            }
+            let stores = migrations::stores(&settings, &dirs)?;
+            let pending = if dry_run { migrations::pending(&stores)? } else { migrations::migrate_all(&stores)? };
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&pending)?),
+                OutputFormat::Text if pending.is_empty() => println!("🗄️ All stores are up to date"),
+                OutputFormat::Text => {
+                    println!("{}", if dry_run { "Pending migrations:" } else { "Migrated:" });
+                    for migration in &pending {
+                        print!("{}", migration);
+                    }
+                }
+            }
+        }
+        Commands::Storage { action: StorageAction::Migrate { to } } => {
+            if mode.is_ephemeral() {
+                println!("Nothing to migrate in ephemeral mode");
+                return Ok(());
+            }
+            let stores = migrations::stores(&settings, &dirs)?;
+            let copies = copy_stores(&settings, &dirs, &stores, to, cli.output)?;
+            match cli.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&copies)?),
+                OutputFormat::Text if copies.is_empty() => println!("🗄️ No sled stores to copy"),
+                OutputFormat::Text => {
+                    let total: usize = copies.iter().map(|c| c.verified).sum();
+                    println!("✅ Copied {} store(s), {} entries", copies.len(), total);
+                    if settings.storage.backend != to {
+                        println!("💡 Set storage.backend = \"{}\" to use it", to);
+                    }
+                }
+            }
+        }
         Commands::ZeroTrustStatus => {
             info!("Checking Zero-Trust security status");
             // ✅ OPTIMIZATION: Now async for non-blocking I/O
-            let zt = zerotrust::ZeroTrustContext::new().await?;
+            let zt = zerotrust::ZeroTrustContext::with_data_dirs_and_keys(&dirs, crypto::key_provider::open(&settings.keys, &dirs)?).await?;
             let stats = zt.get_stats().await?;
 
             println!("🔒 Zero-Trust Security Status");
             println!("================================");
+            println!("Profile: {}", dirs.profile().unwrap_or("default"));
+            println!("Data Root: {}", dirs.root().display());
             println!("Active Connections: {}", stats.total_connections);
             println!("\nSecurity Levels:");
             for (level, count) in stats.by_security_level {
@@ -276,12 +3652,25 @@ async fn main() -> Result<()> {
             println!("\nVM Sandboxes: {}", stats.active_vm_sandboxes);
             println!("Security Events: {}", stats.total_security_events);
             println!("Verification Failures: {}", stats.verification_failures);
+            if let Some(forwarding) = &stats.forwarding {
+                println!(
+                    "Audit Forwarding: {} forwarded, {} dropped, {} failed",
+                    forwarding.forwarded, forwarding.dropped, forwarding.failed
+                );
+            }
+            if !stats.events_by_type.is_empty() {
+                println!("\nEvents by Type:");
+                for (event_type, count) in &stats.events_by_type {
+                    println!("  {}: {}", event_type, count);
+                }
+            }
         }
         Commands::ZeroTrustTest { peer_id, security_level } => {
             info!("Testing Zero-Trust connection for peer: {}", peer_id);
 
             // ✅ OPTIMIZATION: Now async for non-blocking I/O
-            let zt = zerotrust::ZeroTrustContext::new().await?;
+            let zt = zerotrust::ZeroTrustContext::with_data_dirs_and_keys(&dirs, crypto::key_provider::open(&settings.keys, &dirs)?).await?;
+            zt.apply_settings(&settings.zerotrust).await?;
 
             // Create test identity
             let identity = zerotrust::identity::IdentityManager::create_identity(
@@ -332,8 +3721,245 @@ async fn main() -> Result<()> {
                     }
                 }
             }
+            zt.flush_audit_log().await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Log each listen outcome; fail if nothing bound, or anything failed
+/// when `require_all`
+fn check_listen_results(results: &[p2p::listen::ListenResult], require_all: bool) -> Result<()> {
+    for result in results {
+        match &result.outcome {
+            Ok(addrs) => {
+                let addrs: Vec<String> = addrs.iter().map(|a| a.to_string()).collect();
+                info!("🎧 {} bound: {}", result.requested, addrs.join(", "));
+            }
+            Err(e) => error!("🎧 {} failed: {}", result.requested, e),
+        }
+    }
+
+    if let Some(failed) = p2p::listen::startup_failures(results, require_all) {
+        let details: Vec<_> = failed
+            .iter()
+            .map(|r| serde_json::json!({ "address": r.requested, "error": r.outcome.as_ref().err() }))
+            .collect();
+        anyhow::bail!(CliError::new(
+            cli_error::ErrorKind::Network,
+            "LISTEN_FAILED",
+            format!("{} of {} listen address(es) failed to bind", failed.len(), results.len()),
+        )
+        .with_details(serde_json::json!({ "failed": details })));
+    }
+    Ok(())
+}
+
+/// Manual `portfolio buy` / `portfolio sell`; with paper trading on, the
+/// order goes through the account's buying-power check and commission
+#[allow(clippy::too_many_arguments)]
+fn record_trade(
+    store: &quant::portfolio_store::PortfolioStore,
+    account: Option<&quant::account::PaperAccount>,
+    portfolio: &mut quant::portfolio::Portfolio,
+    symbol: &str,
+    side: quant::TradeSide,
+    quantity: rust_decimal::Decimal,
+    price: rust_decimal::Decimal,
+    asset_type: quant::AssetType,
+    lots: Vec<String>,
+) -> Result<()> {
+    if quantity <= rust_decimal::Decimal::ZERO || price <= rust_decimal::Decimal::ZERO {
+        anyhow::bail!(CliError::validation("INVALID_TRADE", "--quantity and --price must be positive"));
+    }
+    let trade = quant::portfolio_store::market_trade(symbol, side, quantity, price);
+    let held = portfolio.positions.get(&trade.symbol).map_or(rust_decimal::Decimal::ZERO, |p| p.quantity);
+    if matches!(trade.side, quant::TradeSide::Sell) && held < quantity {
+        anyhow::bail!(CliError::validation(
+            "INSUFFICIENT_POSITION",
+            format!("Cannot sell {} {}: {} held", quantity, trade.symbol, held),
+        ));
+    }
+    let summary = format!("{:?} {} {} @ {}", trade.side, quantity, trade.symbol, price);
+    match account {
+        Some(account) => account.submit_lots(store, portfolio, trade, asset_type, None, lots)?,
+        None => store.fill_lots(portfolio, trade, rust_decimal::Decimal::ZERO, None, lots)?,
+    }
+    println!("📒 Recorded {}", summary);
+    Ok(())
+}
+
+/// Open lots and realized gains under `method`, or the configured one
+fn lot_book(
+    store: &quant::portfolio_store::PortfolioStore,
+    settings: &quant::cost_basis::CostBasisSettings,
+    method: Option<quant::cost_basis::CostBasisMethod>,
+) -> Result<quant::cost_basis::LotBook> {
+    let settings = quant::cost_basis::CostBasisSettings { method: method.unwrap_or(settings.method), ..settings.clone() };
+    Ok(quant::cost_basis::LotBook::replay(&settings, &store.ledger()?, &store.applied_actions()?)?)
+}
+
+/// Load a strategy plugin, initialized with the JSON in `config` (or `{}`)
+fn load_strategy(
+    wasm: &std::path::Path,
+    config: Option<&std::path::Path>,
+    limits: &quant::plugin::PluginLimits,
+) -> Result<quant::plugin::WasmStrategy> {
+    let config = match config {
+        Some(path) => serde_json::from_slice(&std::fs::read(path)?).map_err(|e| {
+            CliError::validation("INVALID_STRATEGY_CONFIG", format!("Invalid strategy config {}: {}", path.display(), e))
+                .with_details(serde_json::json!({ "path": path }))
+        })?,
+        None => serde_json::json!({}),
+    };
+    quant::plugin::WasmStrategy::load(wasm, &config, limits)
+}
+
+/// `5%` or `5`, strictly positive
+fn percent_arg(value: &str) -> Result<rust_decimal::Decimal, String> {
+    let pct: rust_decimal::Decimal = value
+        .trim()
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("invalid percentage '{}'", value))?;
+    if pct <= rust_decimal::Decimal::ZERO {
+        return Err(format!("percentage must be positive (got {})", value));
+    }
+    Ok(pct)
+}
+
+/// A `percent_arg` value as a fraction (10% → 0.1)
+fn fraction(pct: rust_decimal::Decimal) -> f64 {
+    use rust_decimal::prelude::ToPrimitive;
+    (pct / rust_decimal::Decimal::ONE_HUNDRED).to_f64().unwrap_or_default()
+}
+
+/// `--targets`: a TOML file if one exists at `value`, else `SYMBOL=weight` pairs
+fn rebalance_targets_arg(value: &str) -> Result<std::collections::HashMap<String, rust_decimal::Decimal>> {
+    let path = std::path::Path::new(value);
+    if path.is_file() {
+        return toml::from_str(&std::fs::read_to_string(path)?).map_err(|e| {
+            CliError::validation("INVALID_TARGETS", format!("Invalid targets {}: {}", path.display(), e))
+                .with_details(serde_json::json!({ "path": path }))
+                .into()
+        });
+    }
+    value
+        .split(',')
+        .map(|pair| {
+            let parsed = pair
+                .split_once('=')
+                .and_then(|(symbol, weight)| Some((symbol.trim(), weight.trim().parse::<rust_decimal::Decimal>().ok()?)));
+            match parsed {
+                Some((symbol, weight)) if !symbol.is_empty() => Ok((symbol.to_string(), weight)),
+                _ => Err(anyhow::Error::from(
+                    CliError::validation("INVALID_TARGETS", format!("Invalid target '{}': expected SYMBOL=weight, or a targets file", pair))
+                        .with_details(serde_json::json!({ "targets": value })),
+                )),
+            }
+        })
+        .collect()
+}
+
+fn option_type_arg(value: &str) -> Result<quant::pricing::OptionType> {
+    match value.to_lowercase().as_str() {
+        "call" => Ok(quant::pricing::OptionType::Call),
+        "put" => Ok(quant::pricing::OptionType::Put),
+        _ => anyhow::bail!(CliError::validation(
+            "INVALID_OPTION_TYPE",
+            format!("Invalid option type '{}'. Use 'call' or 'put'", value),
+        )),
+    }
+}
+
+fn hedge_model_arg(model: &str, steps: usize) -> Result<quant::hedging::HedgeModel> {
+    match model.to_lowercase().as_str() {
+        "black-scholes" | "bs" => Ok(quant::hedging::HedgeModel::BlackScholes),
+        "binomial" => Ok(quant::hedging::HedgeModel::Binomial { steps }),
+        other => anyhow::bail!(invalid_model(other, &["black-scholes", "binomial"])),
+    }
+}
+
+/// RFC 3339 timestamp, or a bare date at 00:00 UTC
+fn datetime_arg(value: &str, name: &str, code: &'static str) -> Result<chrono::DateTime<chrono::Utc>> {
+    if let Ok(ts) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(ts.with_timezone(&chrono::Utc));
+    }
+    let date = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+        CliError::validation(code, format!("Invalid {} '{}'", name, value))
+            .with_details(serde_json::json!({ name: value }))
+    })?;
+    Ok(date.and_hms_opt(0, 0, 0).expect("midnight is a valid time").and_utc())
+}
+
+fn invalid_model(model: &str, supported: &[&str]) -> CliError {
+    let quoted: Vec<String> = supported.iter().map(|m| format!("'{}'", m)).collect();
+    let choices = match quoted.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+        _ => quoted.join(""),
+    };
+    CliError::validation("INVALID_MODEL", format!("Invalid model '{}'. Use {}", model, choices))
+}
+
+fn volatility_arg(volatility: Option<f64>, model: &str) -> Result<f64> {
+    volatility.ok_or_else(|| {
+        CliError::validation("MISSING_VOLATILITY", format!("--volatility is required for the {} model", model)).into()
+    })
+}
+
+fn heston_params_arg(value: Option<&str>) -> Result<quant::pricing::heston::HestonParams> {
+    let value = value.ok_or_else(|| {
+        CliError::validation("INVALID_HESTON_PARAMS", "--heston-params v0,kappa,theta,sigma_v,rho is required")
+    })?;
+    value.parse().map_err(|e: anyhow::Error| {
+        CliError::validation("INVALID_HESTON_PARAMS", e.to_string())
+            .with_details(serde_json::json!({ "heston_params": value }))
+            .into()
+    })
+}
+
+/// Carrier database with network updates, or the built-in list if the
+/// store can't be opened
+fn open_carrier_db(dirs: &data_dirs::DataDirs, mode: storage::RuntimeMode) -> esim::carriers::CarrierDatabase {
+    match dirs.carrier_db_dir().and_then(|dir| esim::carriers::CarrierDatabase::open(&dir, mode)) {
+        Ok(db) => db,
+        Err(e) => {
+            tracing::warn!("📡 Carrier updates unavailable ({}); using built-in carriers", e);
+            esim::carriers::CarrierDatabase::new()
         }
     }
+}
+
+/// Built-in device ranges plus `esim.device_db_path`, if set
+fn open_device_db(settings: &esim::EsimSettings) -> Result<esim::compat::DeviceDatabase> {
+    match &settings.device_db_path {
+        Some(path) => esim::compat::DeviceDatabase::open(path),
+        None => Ok(esim::compat::DeviceDatabase::new()),
+    }
+}
 
+/// Print a formatted activation, or write it to `out`
+/// PNG output without `out` goes to `<iccid>.png` in the working directory
+fn emit_activation(
+    formatted: &esim::activation::FormattedActivation,
+    iccid: &str,
+    out: Option<&std::path::Path>,
+) -> Result<()> {
+    let default_path;
+    let path = match (out, formatted.text()) {
+        (Some(path), _) => path,
+        (None, Some(text)) => {
+            println!("{}", text);
+            return Ok(());
+        }
+        (None, None) => {
+            default_path = std::path::PathBuf::from(format!("{}.{}", iccid, formatted.format.extension()));
+            &default_path
+        }
+    };
+    std::fs::write(path, &formatted.data)
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+    println!("💾 {} written to {}", formatted.format, path.display());
     Ok(())
 }
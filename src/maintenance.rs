@@ -0,0 +1,575 @@
+//! Nightly Maintenance
+//! One scheduled routine for the housekeeping a long-running node needs:
+//! audit segment rotation and verification, retention pruning, replay and
+//! shield compaction, and sled store compaction. Steps run in isolation and
+//! the run ends in a single `MaintenanceReport`
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveTime, Utc};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::esim::health::HealthHistory;
+use crate::migrations::StoreLocation;
+use crate::p2p::replay::ReplayRegistry;
+use crate::scheduler::{DailySpec, Scheduler};
+use crate::security::mirror_shield::MirrorShield;
+use crate::security::taxii::IntelPusher;
+use crate::security::notifications::{NotificationRouter, SinkEvent};
+use crate::storage::RuntimeMode;
+use crate::units::{HumanDuration, HumanSize};
+use crate::zerotrust::ZeroTrustContext;
+
+/// Scheduler task name of the nightly run
+pub const TASK_NAME: &str = "maintenance.nightly";
+
+/// `[maintenance]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    /// Run nightly inside `p2p`
+    pub enabled: bool,
+    /// Local time of day, `HH:MM`
+    #[serde(with = "hh_mm")]
+    pub at: NaiveTime,
+    /// Runs start up to this long after `at`
+    pub jitter: HumanDuration,
+    /// A step still going after this is cancelled and marked failed
+    pub step_timeout: HumanDuration,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            at: NaiveTime::from_hms_opt(3, 30, 0).expect("03:30 is a valid time"),
+            jitter: HumanDuration::from_secs(15 * 60),
+            step_timeout: HumanDuration::from_secs(10 * 60),
+        }
+    }
+}
+
+/// `HH:MM` local times in config
+pub(crate) mod hh_mm {
+    use chrono::NaiveTime;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(at: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&at.format("%H:%M"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        NaiveTime::parse_from_str(&value, "%H:%M")
+            .map_err(|_| de::Error::custom(format!("invalid time \"{}\": expected HH:MM (e.g. \"03:30\")", value)))
+    }
+}
+
+/// What a step did, beyond bytes on disk
+#[derive(Debug, Clone, Default)]
+pub struct StepOutcome {
+    /// Entries, events or files dropped
+    pub removed: u64,
+    pub integrity_warnings: Vec<String>,
+}
+
+type StepFn = Box<dyn Fn() -> BoxFuture<'static, Result<StepOutcome>> + Send + Sync>;
+
+/// One isolated unit of maintenance
+pub struct MaintenanceStep {
+    name: String,
+    /// Files or directories measured before and after the step
+    footprint: Vec<PathBuf>,
+    run: StepFn,
+}
+
+impl MaintenanceStep {
+    pub fn new<F, Fut>(name: impl Into<String>, run: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<StepOutcome>> + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            footprint: Vec::new(),
+            run: Box::new(move || Box::pin(run())),
+        }
+    }
+
+    /// Report the bytes reclaimed under `path`
+    pub fn measuring(mut self, path: impl Into<PathBuf>) -> Self {
+        self.footprint.push(path.into());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Ok,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepReport {
+    pub name: String,
+    pub status: StepStatus,
+    pub error: Option<String>,
+    pub duration_ms: f64,
+    pub removed: u64,
+    pub bytes_before: Option<u64>,
+    pub bytes_after: Option<u64>,
+    pub integrity_warnings: Vec<String>,
+}
+
+impl StepReport {
+    pub fn bytes_reclaimed(&self) -> u64 {
+        match (self.bytes_before, self.bytes_after) {
+            (Some(before), Some(after)) => before.saturating_sub(after),
+            _ => 0,
+        }
+    }
+}
+
+/// Result of one maintenance run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceReport {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    /// Every step succeeded
+    pub complete: bool,
+    pub steps: Vec<StepReport>,
+}
+
+impl MaintenanceReport {
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.steps.iter().map(StepReport::bytes_reclaimed).sum()
+    }
+
+    pub fn failed_steps(&self) -> Vec<String> {
+        self.steps.iter().filter(|s| s.status == StepStatus::Failed).map(|s| s.name.clone()).collect()
+    }
+
+    pub fn integrity_warnings(&self) -> Vec<String> {
+        self.steps
+            .iter()
+            .flat_map(|s| s.integrity_warnings.iter().map(move |w| format!("{}: {}", s.name, w)))
+            .collect()
+    }
+
+    pub fn to_sink_event(&self) -> SinkEvent {
+        SinkEvent::Maintenance {
+            complete: self.complete,
+            failed_steps: self.failed_steps(),
+            bytes_reclaimed: self.bytes_reclaimed(),
+            integrity_warnings: self.integrity_warnings(),
+        }
+    }
+
+    /// Flat details for the audit event
+    pub fn audit_details(&self) -> HashMap<String, String> {
+        let mut details = HashMap::from([
+            ("complete".to_string(), self.complete.to_string()),
+            ("bytes_reclaimed".to_string(), self.bytes_reclaimed().to_string()),
+            ("duration_ms".to_string(), (self.finished_at - self.started_at).num_milliseconds().to_string()),
+        ]);
+        for step in &self.steps {
+            let status = match &step.error {
+                Some(e) => format!("failed: {}", e),
+                None => format!("ok, {} removed, {} bytes reclaimed", step.removed, step.bytes_reclaimed()),
+            };
+            details.insert(format!("step.{}", step.name), status);
+        }
+        let warnings = self.integrity_warnings();
+        if !warnings.is_empty() {
+            details.insert("integrity_warnings".to_string(), warnings.join("; "));
+        }
+        details
+    }
+}
+
+impl fmt::Display for MaintenanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "🧹 Maintenance {} in {:.1}s, {} reclaimed",
+            if self.complete { "complete" } else { "PARTIAL" },
+            (self.finished_at - self.started_at).num_milliseconds() as f64 / 1000.0,
+            HumanSize::from_bytes(self.bytes_reclaimed())
+        )?;
+        writeln!(f, "  {:<28} {:>6} {:>10} {:>9} {:>12}", "STEP", "STATUS", "TIME (ms)", "REMOVED", "RECLAIMED")?;
+        for step in &self.steps {
+            writeln!(
+                f,
+                "  {:<28} {:>6} {:>10.1} {:>9} {:>12}",
+                step.name,
+                if step.status == StepStatus::Ok { "ok" } else { "FAILED" },
+                step.duration_ms,
+                step.removed,
+                match (step.bytes_before, step.bytes_after) {
+                    (Some(_), Some(_)) => HumanSize::from_bytes(step.bytes_reclaimed()).to_string(),
+                    _ => "-".to_string(),
+                }
+            )?;
+            if let Some(error) = &step.error {
+                writeln!(f, "      ❌ {}", error)?;
+            }
+            for warning in &step.integrity_warnings {
+                writeln!(f, "      ⚠️  {}", warning)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The maintenance routine: steps plus where its report goes
+pub struct Maintenance {
+    steps: Vec<MaintenanceStep>,
+    step_timeout: Duration,
+    notifier: Option<Arc<NotificationRouter>>,
+    audit: Option<ZeroTrustContext>,
+}
+
+impl Maintenance {
+    pub fn new(config: &MaintenanceConfig) -> Self {
+        Self {
+            steps: Vec::new(),
+            step_timeout: config.step_timeout.as_std(),
+            notifier: None,
+            audit: None,
+        }
+    }
+
+    pub fn add_step(&mut self, step: MaintenanceStep) {
+        self.steps.push(step);
+    }
+
+    pub fn steps(&self) -> impl Iterator<Item = &str> {
+        self.steps.iter().map(MaintenanceStep::name)
+    }
+
+    /// Send each report through the notification router
+    pub fn set_notifier(&mut self, notifier: Arc<NotificationRouter>) {
+        self.notifier = Some(notifier);
+    }
+
+    /// Record each report as a `maintenance_completed` audit event
+    pub fn set_audit(&mut self, zt: ZeroTrustContext) {
+        self.audit = Some(zt);
+    }
+
+    /// Run every step in order; a failed or timed-out step is recorded and
+    /// the rest still run
+    pub async fn run(&self) -> MaintenanceReport {
+        let started_at = Utc::now();
+        let mut steps = Vec::with_capacity(self.steps.len());
+        for step in &self.steps {
+            let bytes_before = footprint(&step.footprint);
+            let started = Instant::now();
+            let outcome = match tokio::time::timeout(self.step_timeout, (step.run)()).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow::anyhow!("Timed out after {:?}", self.step_timeout)),
+            };
+            let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+            let bytes_after = footprint(&step.footprint);
+            steps.push(match outcome {
+                Ok(outcome) => StepReport {
+                    name: step.name.clone(),
+                    status: StepStatus::Ok,
+                    error: None,
+                    duration_ms,
+                    removed: outcome.removed,
+                    bytes_before,
+                    bytes_after,
+                    integrity_warnings: outcome.integrity_warnings,
+                },
+                Err(e) => {
+                    tracing::warn!("🧹 Maintenance step {} failed: {:#}", step.name, e);
+                    StepReport {
+                        name: step.name.clone(),
+                        status: StepStatus::Failed,
+                        error: Some(format!("{:#}", e)),
+                        duration_ms,
+                        removed: 0,
+                        bytes_before,
+                        bytes_after,
+                        integrity_warnings: Vec::new(),
+                    }
+                }
+            });
+        }
+        MaintenanceReport {
+            started_at,
+            finished_at: Utc::now(),
+            complete: steps.iter().all(|s| s.status == StepStatus::Ok),
+            steps,
+        }
+    }
+
+    /// `run`, then notify and audit the report
+    pub async fn run_and_publish(&self) -> MaintenanceReport {
+        let report = self.run().await;
+        tracing::info!(
+            "🧹 Maintenance {} ({} step(s), {} reclaimed)",
+            if report.complete { "complete" } else { "partial" },
+            report.steps.len(),
+            HumanSize::from_bytes(report.bytes_reclaimed())
+        );
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(report.to_sink_event());
+        }
+        if let Some(zt) = &self.audit {
+            if let Err(e) = zt.log_local_event("maintenance_completed", report.audit_details()).await {
+                tracing::warn!("⚠️  Failed to audit maintenance report: {}", e);
+            }
+        }
+        report
+    }
+
+    /// Run nightly on `scheduler`
+    pub fn schedule(self: Arc<Self>, scheduler: &mut Scheduler, config: &MaintenanceConfig) -> Result<()> {
+        let spec = DailySpec {
+            at: config.at,
+            jitter: config.jitter.as_std(),
+            // Every step may use its full timeout
+            timeout: self.step_timeout * (self.steps.len() as u32 + 1),
+        };
+        scheduler.register_daily(TASK_NAME, spec, move || {
+            let maintenance = self.clone();
+            async move {
+                let report = maintenance.run_and_publish().await;
+                match report.complete {
+                    true => Ok(()),
+                    false => Err(anyhow::anyhow!("Failed steps: {}", report.failed_steps().join(", "))),
+                }
+            }
+        })
+    }
+}
+
+/// Total size of files under `paths`; None when nothing is measured
+fn footprint(paths: &[PathBuf]) -> Option<u64> {
+    (!paths.is_empty()).then(|| paths.iter().map(|p| disk_usage(p)).sum())
+}
+
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else { return 0 };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| disk_usage(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+/// Verify and archive the live audit segment
+pub fn audit_step(zt: ZeroTrustContext) -> MaintenanceStep {
+    MaintenanceStep::new("audit.rotate_verify", move || {
+        let zt = zt.clone();
+        async move {
+            let rotation = zt.rotate_audit_log().await?;
+            Ok(StepOutcome {
+                removed: 0,
+                integrity_warnings: rotation.problem.into_iter().collect(),
+            })
+        }
+    })
+}
+
+/// Drop expired entries from the node's replay registry
+pub fn replay_step(registry: Arc<ReplayRegistry>) -> MaintenanceStep {
+    MaintenanceStep::new("replay.prune", move || {
+        let result = registry.compact(Utc::now());
+        async move { Ok(StepOutcome { removed: result? as u64, ..Default::default() }) }
+    })
+}
+
+/// Open the replay registry at `dir` just for the prune (node stopped)
+pub fn replay_dir_step(dir: PathBuf, ttl: HumanDuration) -> MaintenanceStep {
+    MaintenanceStep::new("replay.prune", move || {
+        let result = ReplayRegistry::open(&dir, RuntimeMode::Persistent, ttl).and_then(|r| r.compact(Utc::now()));
+        async move { Ok(StepOutcome { removed: result? as u64, ..Default::default() }) }
+    })
+}
+
+/// Forget idle attackers, stale rate windows and old attack events
+pub fn shield_step(shield: Arc<MirrorShield>) -> MaintenanceStep {
+    MaintenanceStep::new("shield.prune", move || {
+        let shield = shield.clone();
+        async move { Ok(StepOutcome { removed: shield.prune().await as u64, ..Default::default() }) }
+    })
+}
+
+/// Push new intel journal events to the TAXII collection
+pub fn intel_push_step(pusher: Arc<IntelPusher>) -> MaintenanceStep {
+    MaintenanceStep::new("intel.taxii_push", move || {
+        let pusher = pusher.clone();
+        async move {
+            let report = pusher.push_pending().await?;
+            tracing::info!("{}", report.to_string().trim_end());
+            Ok(StepOutcome::default())
+        }
+    })
+}
+
+/// Apply `[esim.health] retention` to the probe history at `dir`
+pub fn carrier_health_step(dir: PathBuf, retention: HumanDuration) -> MaintenanceStep {
+    MaintenanceStep::new("esim.health.retention", move || {
+        let result = HealthHistory::open(&dir, RuntimeMode::Persistent)
+            .and_then(|history| history.prune(Utc::now(), retention.as_chrono()));
+        async move { Ok(StepOutcome { removed: result? as u64, ..Default::default() }) }
+    })
+}
+
+/// Rewrite a sled store to reclaim the space of removed entries. Only for
+/// stores no component has open, so not part of the in-node run
+pub fn compact_store_step(location: StoreLocation) -> MaintenanceStep {
+    let path = location.path.clone();
+    MaintenanceStep::new(format!("compact.{}", location.schema.name), move || {
+        let result = crate::storage::compact_sled(&location.path);
+        async move { result.map(|()| StepOutcome::default()) }
+    })
+    .measuring(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esim::health::ProbeResult;
+    use crate::migrations;
+    use crate::p2p::replay::DeliveryKey;
+    use tempfile::TempDir;
+
+    fn config() -> MaintenanceConfig {
+        MaintenanceConfig { step_timeout: HumanDuration::from_secs(30), ..Default::default() }
+    }
+
+    #[test]
+    fn test_config_parses_time_of_day() {
+        let config: MaintenanceConfig = toml::from_str("at = \"22:05\"\njitter = \"5m\"").unwrap();
+        assert_eq!(config.at, NaiveTime::from_hms_opt(22, 5, 0).unwrap());
+        assert!(config.enabled);
+        assert!(toml::from_str::<MaintenanceConfig>("at = \"3am\"").unwrap_err().to_string().contains("HH:MM"));
+        let reparsed: MaintenanceConfig = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(reparsed.at, config.at);
+    }
+
+    #[tokio::test]
+    async fn test_failed_step_does_not_abort_the_rest() {
+        let mut maintenance = Maintenance::new(&config());
+        maintenance.add_step(MaintenanceStep::new("broken", || async { anyhow::bail!("disk on fire") }));
+        maintenance.add_step(MaintenanceStep::new("slow", || async {
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+            Ok(StepOutcome::default())
+        }));
+        maintenance.add_step(MaintenanceStep::new("fine", || async { Ok(StepOutcome { removed: 3, ..Default::default() }) }));
+        maintenance.step_timeout = Duration::from_millis(50);
+
+        let report = maintenance.run().await;
+        assert!(!report.complete);
+        assert_eq!(report.failed_steps(), vec!["broken", "slow"]);
+        assert!(report.steps[0].error.as_deref().unwrap().contains("disk on fire"));
+        assert!(report.steps[1].error.as_deref().unwrap().contains("Timed out"));
+        assert_eq!((report.steps[2].status, report.steps[2].removed), (StepStatus::Ok, 3));
+        assert!(matches!(report.to_sink_event(), SinkEvent::Maintenance { complete: false, .. }));
+        assert_eq!(report.audit_details()["complete"], "false");
+    }
+
+    #[tokio::test]
+    async fn test_seeded_stores_shrink() {
+        let dir = TempDir::new().unwrap();
+        let replay_dir = dir.path().join("replay");
+        let health_dir = dir.path().join("carrier_health");
+        let log_path = dir.path().join("logs/audit.log");
+
+        // Acknowledged deliveries that expire almost at once
+        let registry = ReplayRegistry::open(&replay_dir, RuntimeMode::Persistent, HumanDuration::from_millis(1)).unwrap();
+        for i in 0..50u32 {
+            let delivery = registry.begin(DeliveryKey::new("dm", "peer", &i.to_be_bytes()), Utc::now()).unwrap().unwrap();
+            delivery.ack().unwrap();
+        }
+        drop(registry);
+
+        // Month-old probe results plus bulk churn the history no longer holds
+        let history = HealthHistory::open(&health_dir, RuntimeMode::Persistent).unwrap();
+        for day in 0..30 {
+            let result = ProbeResult {
+                at: Utc::now() - chrono::Duration::days(30 - day) + chrono::Duration::hours(1),
+                host: "smdp.example.com".to_string(),
+                latency_ms: Some(40),
+                tls_expiry_days: Some(90),
+                error: None,
+            };
+            history.record("carrier-a", &result).unwrap();
+        }
+        drop(history);
+        let churn = migrations::open_store(RuntimeMode::Persistent, &health_dir, &crate::esim::health::SCHEMA).unwrap();
+        let filler = vec![0xAB; 512];
+        for i in 0..20_000u32 {
+            churn.insert(format!("zz-churn/{:08}", i).as_bytes(), &filler).unwrap();
+        }
+        churn.flush().unwrap();
+        for i in 0..20_000u32 {
+            churn.remove(format!("zz-churn/{:08}", i).as_bytes()).unwrap();
+        }
+        churn.flush().unwrap();
+        drop(churn);
+
+        let zt = ZeroTrustContext::with_log_path(log_path.to_str().unwrap()).await.unwrap();
+        for i in 0..10 {
+            zt.log_local_event(&format!("seed_{}", i), HashMap::new()).await.unwrap();
+        }
+        zt.flush_audit_log().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let mut maintenance = Maintenance::new(&config());
+        maintenance.add_step(audit_step(zt.clone()));
+        maintenance.add_step(replay_dir_step(replay_dir.clone(), HumanDuration::from_millis(1)));
+        maintenance.add_step(carrier_health_step(health_dir.clone(), HumanDuration::from_secs(8 * 86_400)));
+        for (schema, path) in [(&crate::esim::health::SCHEMA, &health_dir), (&crate::p2p::replay::SCHEMA, &replay_dir)] {
+            maintenance.add_step(compact_store_step(StoreLocation { schema, path: path.clone() }));
+        }
+        maintenance.set_audit(zt.clone());
+        let report = maintenance.run_and_publish().await;
+
+        assert!(report.complete, "{}", report);
+        let names: Vec<&str> = report.steps.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["audit.rotate_verify", "replay.prune", "esim.health.retention", "compact.carrier_health", "compact.replay_registry"]
+        );
+        assert!(report.integrity_warnings().is_empty());
+        assert_eq!(report.steps[1].removed, 50);
+        assert_eq!(report.steps[2].removed, 22);
+        assert!(report.steps[3].bytes_reclaimed() > 0, "churned health store reclaimed nothing");
+        assert!(report.steps[4].bytes_before.is_some() && report.steps[4].bytes_after.is_some());
+        assert_eq!(report.bytes_reclaimed(), report.steps.iter().map(StepReport::bytes_reclaimed).sum::<u64>());
+
+        // The closed segment was archived next to the live log, which now
+        // starts with the report itself
+        let archived = std::fs::read_dir(log_path.parent().unwrap())
+            .unwrap()
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().starts_with("audit.2"))
+            .count();
+        assert_eq!(archived, 1);
+        let events = zt.audit_events().await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, "maintenance_completed");
+        assert_eq!(events[0].details["complete"], "true");
+
+        // Pruned data survived compaction
+        let history = HealthHistory::open(&health_dir, RuntimeMode::Persistent).unwrap();
+        assert_eq!(history.results("carrier-a").unwrap().len(), 8);
+    }
+}
@@ -0,0 +1,621 @@
+//! Schema Migrations
+//! Every persisted store records its schema version next to its data. At
+//! startup, stores behind this build are migrated on a copy that replaces
+//! the original only once it verifies; the original is kept as `<store>.bak`.
+//! Stores in SQLite are migrated in a transaction instead
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::data_dirs::DataDirs;
+use crate::settings::Settings;
+use crate::storage::{self, Backend, Indexer, KvStore, MemoryStore, RuntimeMode, VersionedSledStore};
+#[cfg(feature = "sqlite")]
+use crate::storage::sqlite::SqliteDatabase;
+
+/// One schema step, `from` → `from + 1`
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    pub apply: fn(&dyn KvStore) -> Result<()>,
+}
+
+/// What a store declares about its on-disk format
+pub struct StoreSchema {
+    pub name: &'static str,
+    pub tree: Option<&'static str>,
+    /// Version this build reads and writes
+    pub version: u32,
+    /// Steps from v1 up to `version`, in order
+    pub migrations: &'static [Migration],
+    /// Fields `KvStore::find` looks entries up by. Indexes are rebuilt when
+    /// `version` changes, so changing what is indexed needs a new version
+    pub index: Option<Indexer>,
+}
+
+/// Data written by a newer build than this one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DowngradeError {
+    pub store: &'static str,
+    pub path: PathBuf,
+    pub found: u32,
+    pub supported: u32,
+}
+
+impl fmt::Display for DowngradeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} store at {} is schema v{}, but this build only understands up to v{}; \
+             run a newer quantraband (downgrades are not supported)",
+            self.store,
+            self.path.display(),
+            self.found,
+            self.supported
+        )
+    }
+}
+
+impl std::error::Error for DowngradeError {}
+
+/// Open `schema`'s store at `path` (in memory when ephemeral) in the
+/// process-wide backend. A new store is stamped with the current version;
+/// older or newer data is refused.
+pub fn open_store(mode: RuntimeMode, path: &Path, schema: &StoreSchema) -> Result<Box<dyn KvStore>> {
+    if mode.is_ephemeral() {
+        return Ok(Box::new(schema.index.map(MemoryStore::indexed).unwrap_or_default()));
+    }
+    open_store_in(&storage::backend(), path, schema)
+}
+
+/// `open_store` in a given backend
+pub fn open_store_in(backend: &Backend, path: &Path, schema: &StoreSchema) -> Result<Box<dyn KvStore>> {
+    match backend {
+        Backend::Sled => {
+            let db = VersionedSledStore::open(path, schema.tree)?;
+            check_version(schema, path, stored_version(&db)?)?;
+            if db.version()?.is_none() {
+                db.set_version(schema.version)?;
+            }
+            match schema.index {
+                Some(indexer) => Ok(Box::new(db.into_indexed(indexer, schema.version)?)),
+                None => Ok(Box::new(db.into_data())),
+            }
+        }
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite(db) => {
+            let store = db.store(path);
+            let found = db.version(store.id())?;
+            check_version(schema, path, found)?;
+            if found.is_none() {
+                db.set_version(store.id(), schema.name, schema.version)?;
+            }
+            match schema.index {
+                Some(indexer) => Ok(Box::new(store.into_indexed(indexer, schema.version)?)),
+                None => Ok(Box::new(store)),
+            }
+        }
+    }
+}
+
+fn check_version(schema: &StoreSchema, path: &Path, found: Option<u32>) -> Result<()> {
+    match found {
+        Some(found) if found > schema.version => Err(downgrade(schema, path, found).into()),
+        Some(found) if found < schema.version => anyhow::bail!(
+            "{} store at {} is schema v{} and needs migrating to v{} (run `quantraband migrate`)",
+            schema.name,
+            path.display(),
+            found,
+            schema.version
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Version of an open store; `None` for a new, empty one. Data from before
+/// versioning counts as v1.
+fn stored_version(db: &VersionedSledStore) -> Result<Option<u32>> {
+    match db.version()? {
+        Some(version) => Ok(Some(version)),
+        None if db.is_empty() => Ok(None),
+        None => Ok(Some(1)),
+    }
+}
+
+fn downgrade(schema: &StoreSchema, path: &Path, found: u32) -> DowngradeError {
+    DowngradeError { store: schema.name, path: path.to_path_buf(), found, supported: schema.version }
+}
+
+/// A registered store at its configured location
+pub struct StoreLocation {
+    pub schema: &'static StoreSchema,
+    pub path: PathBuf,
+}
+
+/// Every persisted store of the active profile
+pub fn stores(settings: &Settings, dirs: &DataDirs) -> Result<Vec<StoreLocation>> {
+    use crate::{alerts, approvals, crypto, esim, p2p, quant, zerotrust};
+    let at = |schema: &'static StoreSchema, path: PathBuf| StoreLocation { schema, path };
+    let mut stores = vec![
+        at(&quant::portfolio_store::SCHEMA, settings.portfolio.store_path(dirs)?),
+        at(&alerts::store::SCHEMA, settings.alerts.store_path(dirs)?),
+        at(&quant::watchlist::SCHEMA, settings.quant.watchlist.store_path(dirs)?),
+        at(&esim::store::SCHEMA, dirs.esim_store_dir()?),
+        at(&esim::carriers::SCHEMA, dirs.carrier_db_dir()?),
+        at(&esim::health::SCHEMA, dirs.carrier_health_dir()?),
+        at(&zerotrust::node_identity::SCHEMA, dirs.identity_dir()?),
+        at(&p2p::replay::SCHEMA, dirs.replay_registry_dir()?),
+        at(&p2p::retention::SCHEMA, dirs.retention_dir()?),
+        at(&p2p::receipts::SCHEMA, dirs.receipts_dir()?),
+        at(&p2p::transparency::SCHEMA, dirs.key_log_dir()?),
+        at(&approvals::SCHEMA, dirs.approvals_dir()?),
+    ];
+    for name in settings.crypto.keystores().keys() {
+        stores.push(at(&crypto::keystore::SCHEMA, settings.crypto.keystore_path(name, dirs)?));
+    }
+    Ok(stores)
+}
+
+/// Migrations a store needs
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingMigration {
+    pub store: &'static str,
+    pub path: PathBuf,
+    pub from: u32,
+    pub to: u32,
+    pub steps: Vec<String>,
+}
+
+impl fmt::Display for PendingMigration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "🗄️ {} ({}): v{} → v{}", self.store, self.path.display(), self.from, self.to)?;
+        for step in &self.steps {
+            writeln!(f, "   - {}", step)?;
+        }
+        Ok(())
+    }
+}
+
+/// Stores behind this build, in the process-wide backend. Fails if any
+/// store is ahead of it.
+pub fn pending(stores: &[StoreLocation]) -> Result<Vec<PendingMigration>> {
+    pending_in(&storage::backend(), stores)
+}
+
+fn pending_in(backend: &Backend, stores: &[StoreLocation]) -> Result<Vec<PendingMigration>> {
+    let mut pending = Vec::new();
+    for location in stores {
+        let schema = location.schema;
+        let Some(found) = version_in(backend, location)? else { continue };
+        if found > schema.version {
+            return Err(downgrade(schema, &location.path, found).into());
+        }
+        if found < schema.version {
+            pending.push(PendingMigration {
+                store: schema.name,
+                path: location.path.clone(),
+                from: found,
+                to: schema.version,
+                steps: steps(schema, found)?
+                    .iter()
+                    .map(|m| format!("v{} → v{}: {}", m.from, m.from + 1, m.description))
+                    .collect(),
+            });
+        }
+    }
+    Ok(pending)
+}
+
+/// A store's version; `None` when there is nothing yet, and it will be
+/// created at the current version
+fn version_in(backend: &Backend, location: &StoreLocation) -> Result<Option<u32>> {
+    match backend {
+        Backend::Sled => {
+            if !location.path.join("conf").exists() {
+                return Ok(None);
+            }
+            let db = VersionedSledStore::open(&location.path, location.schema.tree)
+                .with_context(|| format!("Failed to open {} store", location.schema.name))?;
+            stored_version(&db)
+        }
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite(db) => db.version(&db.store_id(&location.path)),
+    }
+}
+
+fn steps(schema: &StoreSchema, from: u32) -> Result<Vec<&Migration>> {
+    (from..schema.version)
+        .map(|version| {
+            schema
+                .migrations
+                .iter()
+                .find(|m| m.from == version)
+                .with_context(|| format!("No {} migration from v{}", schema.name, version))
+        })
+        .collect()
+}
+
+/// Bring one store up to date. In sled: migrate a copy, verify it, then
+/// swap it in and keep the original as `<store>.bak`
+pub fn migrate(location: &StoreLocation, pending: &PendingMigration) -> Result<()> {
+    match storage::backend() {
+        Backend::Sled => migrate_sled(location, pending),
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite(db) => migrate_sqlite(&db, location, pending),
+    }
+}
+
+fn migrate_sled(location: &StoreLocation, pending: &PendingMigration) -> Result<()> {
+    let schema = location.schema;
+    let path = &location.path;
+    let work = sibling(path, "migrating");
+    let backup = sibling(path, "bak");
+
+    if work.exists() {
+        std::fs::remove_dir_all(&work).context("Failed to clear an interrupted migration")?;
+    }
+    copy_dir(path, &work).with_context(|| format!("Failed to copy {} store", schema.name))?;
+
+    {
+        let db = VersionedSledStore::open(&work, schema.tree)?;
+        for step in steps(schema, pending.from)? {
+            (step.apply)(db.data())
+                .with_context(|| format!("{} migration v{} → v{} failed", schema.name, step.from, step.from + 1))?;
+            db.data().flush()?;
+            db.set_version(step.from + 1)?;
+        }
+    }
+
+    // Reopen from disk: the copy must be at the target version and readable
+    {
+        let db = VersionedSledStore::open(&work, schema.tree)?;
+        let version = db.version()?;
+        if version != Some(schema.version) {
+            anyhow::bail!("Migrated {} store is at {:?}, expected v{}", schema.name, version, schema.version);
+        }
+        db.data().entries().context("Migrated store is unreadable")?;
+    }
+
+    if backup.exists() {
+        std::fs::remove_dir_all(&backup).context("Failed to remove the previous backup")?;
+    }
+    std::fs::rename(path, &backup).context("Failed to move the original store aside")?;
+    if let Err(e) = std::fs::rename(&work, path) {
+        std::fs::rename(&backup, path).context("Failed to restore the original store")?;
+        return Err(e).context("Failed to move the migrated store into place");
+    }
+    tracing::info!(
+        "🗄️ Migrated {} store v{} → v{} (original kept at {})",
+        schema.name,
+        pending.from,
+        schema.version,
+        backup.display()
+    );
+    Ok(())
+}
+
+/// Every step in one transaction, so a failed step leaves the store as it was
+#[cfg(feature = "sqlite")]
+fn migrate_sqlite(db: &std::sync::Arc<SqliteDatabase>, location: &StoreLocation, pending: &PendingMigration) -> Result<()> {
+    let schema = location.schema;
+    let store = db.store(&location.path);
+    db.transaction(|| {
+        for step in steps(schema, pending.from)? {
+            (step.apply)(&store)
+                .with_context(|| format!("{} migration v{} → v{} failed", schema.name, step.from, step.from + 1))?;
+            db.set_version(store.id(), schema.name, step.from + 1)?;
+        }
+        store.entries().context("Migrated store is unreadable")?;
+        Ok(())
+    })?;
+    tracing::info!("🗄️ Migrated {} store v{} → v{} in {}", schema.name, pending.from, schema.version, db.path().display());
+    Ok(())
+}
+
+/// One store copied by `copy_to_sqlite`
+#[derive(Debug, Clone, Serialize)]
+pub struct StoreCopy {
+    pub store: &'static str,
+    pub path: PathBuf,
+    pub version: u32,
+    /// Entries read from sled
+    pub entries: usize,
+    /// Rows found in SQLite afterwards
+    pub verified: usize,
+}
+
+impl fmt::Display for StoreCopy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "🗄️ {} ({}): {} entries copied, {} verified (v{})",
+            self.store,
+            self.path.display(),
+            self.entries,
+            self.verified,
+            self.version
+        )
+    }
+}
+
+/// Copy every sled store that exists into `db`, each in one transaction,
+/// reporting each as it is verified. Sled stores must be up to date, and
+/// stores already holding rows in `db` are refused rather than overwritten.
+/// The sled copies are left in place
+#[cfg(feature = "sqlite")]
+pub fn copy_to_sqlite(
+    stores: &[StoreLocation],
+    db: &std::sync::Arc<SqliteDatabase>,
+    mut progress: impl FnMut(&StoreCopy),
+) -> Result<Vec<StoreCopy>> {
+    let behind = pending_in(&Backend::Sled, stores)?;
+    if !behind.is_empty() {
+        let names: Vec<&str> = behind.iter().map(|m| m.store).collect();
+        anyhow::bail!("Stores need migrating first ({}); run `quantraband migrate`", names.join(", "));
+    }
+    let mut copies = Vec::new();
+    for location in stores {
+        let schema = location.schema;
+        let Some(version) = version_in(&Backend::Sled, location)? else { continue };
+        let store = db.store(&location.path);
+        if db.count(store.id())? > 0 {
+            anyhow::bail!(
+                "{} store ({}) already has data in {}; not overwriting it",
+                schema.name,
+                location.path.display(),
+                db.path().display()
+            );
+        }
+        let source = VersionedSledStore::open(&location.path, schema.tree)
+            .with_context(|| format!("Failed to open {} store", schema.name))?;
+        let entries = source.data().entries()?;
+        db.transaction(|| {
+            for (key, value) in &entries {
+                store.insert(key, value)?;
+            }
+            db.set_version(store.id(), schema.name, version)
+        })
+        .with_context(|| format!("Failed to copy {} store", schema.name))?;
+
+        let copied = store.entries()?;
+        if copied != entries {
+            anyhow::bail!(
+                "{} store: {} entries read but {} found in {} after copying",
+                schema.name,
+                entries.len(),
+                copied.len(),
+                db.path().display()
+            );
+        }
+        let copy = StoreCopy {
+            store: schema.name,
+            path: location.path.clone(),
+            version,
+            entries: entries.len(),
+            verified: db.count(store.id())?,
+        };
+        progress(&copy);
+        copies.push(copy);
+    }
+    Ok(copies)
+}
+
+/// Migrate every store that is behind; returns what was migrated
+pub fn migrate_all(stores: &[StoreLocation]) -> Result<Vec<PendingMigration>> {
+    let pending = pending(stores)?;
+    for migration in &pending {
+        let location = stores
+            .iter()
+            .find(|l| l.path == migration.path)
+            .expect("pending migrations come from these stores");
+        migrate(location, migration)?;
+    }
+    Ok(pending)
+}
+
+/// `path` with `.ext` appended to its file name
+fn sibling(path: &Path, ext: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(ext);
+    path.with_file_name(name)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quant::portfolio_store::{self, PortfolioStore};
+    use tempfile::TempDir;
+
+    /// A portfolio database as written before schema versions existed
+    fn legacy_portfolio(path: &Path) {
+        let store = VersionedSledStore::open(path, Some("portfolio")).unwrap();
+        let db = store.data();
+        let position = serde_json::json!({
+            "symbol": "AAPL",
+            "quantity": "10",
+            "average_cost": "150",
+            "current_price": "155",
+        });
+        db.insert(b"position/AAPL", position.to_string().as_bytes()).unwrap();
+        db.flush().unwrap();
+    }
+
+    fn portfolio_at(path: &Path) -> Vec<StoreLocation> {
+        vec![StoreLocation { schema: &portfolio_store::SCHEMA, path: path.to_path_buf() }]
+    }
+
+    #[test]
+    fn test_portfolio_gains_currency() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("portfolio");
+        legacy_portfolio(&path);
+        let stores = portfolio_at(&path);
+
+        // The old format can't be opened until it is migrated
+        assert!(PortfolioStore::open(&path, RuntimeMode::Persistent).is_err());
+
+        // Dry run: reported, not applied
+        let pending = pending(&stores).unwrap();
+        assert_eq!((pending.len(), pending[0].from, pending[0].to), (1, 1, 2));
+        assert!(pending[0].steps[0].contains("currency"));
+        assert_eq!(super::pending(&stores).unwrap().len(), 1);
+
+        let migrated = migrate_all(&stores).unwrap();
+        assert_eq!(migrated.len(), 1);
+        assert!(sibling(&path, "bak").join("conf").exists());
+        assert!(!sibling(&path, "migrating").exists());
+
+        let portfolio = PortfolioStore::open(&path, RuntimeMode::Persistent).unwrap().load().unwrap();
+        assert_eq!(portfolio.positions["AAPL"].currency, "USD");
+        assert_eq!(portfolio.positions["AAPL"].quantity, rust_decimal::Decimal::from(10));
+
+        // Re-running is a no-op
+        assert!(migrate_all(&stores).unwrap().is_empty());
+        println!("✅ Portfolio currency migration test PASSED!");
+    }
+
+    #[test]
+    fn test_new_store_needs_no_migration() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("portfolio");
+        // Not created yet, then created fresh at the current version
+        assert!(pending(&portfolio_at(&path)).unwrap().is_empty());
+        PortfolioStore::open(&path, RuntimeMode::Persistent).unwrap();
+        assert!(pending(&portfolio_at(&path)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_newer_data_is_refused() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("portfolio");
+        VersionedSledStore::open(&path, Some("portfolio")).unwrap().set_version(portfolio_store::SCHEMA.version + 1).unwrap();
+
+        let err = pending(&portfolio_at(&path)).unwrap_err();
+        let downgrade = err.downcast_ref::<DowngradeError>().expect("a downgrade error");
+        assert_eq!((downgrade.store, downgrade.found), ("portfolio", 3));
+        assert!(err.to_string().contains("portfolio store"));
+        assert!(err.to_string().contains("v3") && err.to_string().contains("v2"));
+
+        let err = PortfolioStore::open(&path, RuntimeMode::Persistent).err().unwrap();
+        assert!(err.chain().any(|e| e.is::<DowngradeError>()));
+    }
+
+    #[test]
+    fn test_registered_migrations_are_complete() {
+        let dir = TempDir::new().unwrap();
+        let dirs = DataDirs::resolve(Some(dir.path()), None, RuntimeMode::Persistent).unwrap();
+        for location in stores(&Settings::default(), &dirs).unwrap() {
+            let schema = location.schema;
+            assert!(steps(schema, 1).is_ok(), "{} is missing a migration", schema.name);
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn sqlite_in(dir: &Path) -> std::sync::Arc<SqliteDatabase> {
+        std::sync::Arc::new(SqliteDatabase::open(&dir.join(storage::SQLITE_FILE), dir).unwrap())
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_copy_to_sqlite_verifies_counts() {
+        let dir = TempDir::new().unwrap();
+        let portfolio = dir.path().join("portfolio");
+        let alerts = dir.path().join("alerts");
+        legacy_portfolio(&portfolio);
+        let mut stores = portfolio_at(&portfolio);
+        stores.push(StoreLocation { schema: &crate::alerts::store::SCHEMA, path: alerts.clone() });
+        stores.push(StoreLocation { schema: &crate::esim::store::SCHEMA, path: dir.path().join("esim") });
+        let db = sqlite_in(dir.path());
+
+        // Behind stores are migrated in sled first
+        let err = copy_to_sqlite(&stores, &db, |_| {}).unwrap_err();
+        assert!(err.to_string().contains("quantraband migrate"), "{}", err);
+        assert_eq!(db.count("portfolio").unwrap(), 0);
+        migrate_all(&stores).unwrap();
+
+        {
+            let seeded = open_store_in(&Backend::Sled, &alerts, &crate::alerts::store::SCHEMA).unwrap();
+            for i in 0..24u8 {
+                seeded.insert(&[b'a', i], &[i; 3]).unwrap();
+            }
+            let rule = crate::alerts::AlertRule::new(
+                "AAPL",
+                crate::alerts::AlertCondition::PriceAbove,
+                rust_decimal::Decimal::ONE,
+                chrono::Duration::zero(),
+            );
+            seeded.insert(rule.id.as_bytes(), &serde_json::to_vec(&rule).unwrap()).unwrap();
+            seeded.flush().unwrap();
+        }
+
+        let mut reported = Vec::new();
+        let copies = copy_to_sqlite(&stores, &db, |copy| reported.push(copy.store)).unwrap();
+        assert_eq!(reported, vec!["portfolio", "alerts"], "the esim store was never created");
+        assert_eq!((copies[0].entries, copies[0].verified, copies[0].version), (1, 1, 2));
+        assert_eq!((copies[1].entries, copies[1].verified), (25, 25));
+        assert!(copies[1].to_string().contains("25 entries copied, 25 verified"));
+
+        // Readable through the SQLite backend, and up to date there
+        let backend = Backend::Sqlite(db.clone());
+        let copied = open_store_in(&backend, &alerts, &crate::alerts::store::SCHEMA).unwrap();
+        assert_eq!(copied.get(&[b'a', 7]).unwrap(), Some(vec![7; 3]));
+        assert_eq!(copied.find(storage::IndexQuery::Symbol("AAPL")).unwrap().len(), 1, "indexed once opened");
+        let copied = open_store_in(&backend, &portfolio, &portfolio_store::SCHEMA).unwrap();
+        let position: serde_json::Value = serde_json::from_slice(&copied.get(b"position/AAPL").unwrap().unwrap()).unwrap();
+        assert_eq!(position["currency"], "USD");
+        assert!(pending_in(&backend, &stores).unwrap().is_empty());
+
+        // The sled stores are left alone, and a second copy is refused
+        assert!(portfolio.join("conf").exists());
+        let err = copy_to_sqlite(&stores, &db, |_| {}).unwrap_err();
+        assert!(err.to_string().contains("already has data"), "{}", err);
+        assert_eq!(db.count("alerts").unwrap(), 25);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_store_migrates_in_place() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("portfolio");
+        let db = sqlite_in(dir.path());
+        let store = db.store(&path);
+        let position = serde_json::json!({ "symbol": "MSFT", "quantity": "3", "average_cost": "300", "current_price": "310" });
+        store.insert(b"position/MSFT", position.to_string().as_bytes()).unwrap();
+        db.set_version(store.id(), portfolio_store::SCHEMA.name, 1).unwrap();
+
+        let backend = Backend::Sqlite(db.clone());
+        let stores = portfolio_at(&path);
+        assert!(open_store_in(&backend, &path, &portfolio_store::SCHEMA).is_err());
+        let pending = pending_in(&backend, &stores).unwrap();
+        assert_eq!((pending.len(), pending[0].from, pending[0].to), (1, 1, 2));
+
+        migrate_sqlite(&db, &stores[0], &pending[0]).unwrap();
+        assert_eq!(db.version("portfolio").unwrap(), Some(portfolio_store::SCHEMA.version));
+        let migrated = open_store_in(&backend, &path, &portfolio_store::SCHEMA).unwrap();
+        let position: serde_json::Value = serde_json::from_slice(&migrated.get(b"position/MSFT").unwrap().unwrap()).unwrap();
+        assert_eq!(position["currency"], "USD");
+        assert!(pending_in(&backend, &stores).unwrap().is_empty());
+
+        // Newer data is refused here too
+        db.set_version("portfolio", portfolio_store::SCHEMA.name, portfolio_store::SCHEMA.version + 1).unwrap();
+        let err = pending_in(&backend, &stores).unwrap_err();
+        assert!(err.downcast_ref::<DowngradeError>().is_some());
+    }
+}
@@ -0,0 +1,155 @@
+//! Proxy Doctor
+//! `network doctor`: is the configured proxy valid and reachable, and what
+//! address does traffic through it leave from
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use super::{socks5, with_proxy, ProxyConfig, Subsystem};
+
+/// The egress check's request timeout
+const EGRESS_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProxyCheck {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProxyReport {
+    /// `socks5h://host:port`; `None` when nothing is proxied
+    pub proxy: Option<String>,
+    pub subsystems: BTreeMap<Subsystem, bool>,
+    pub checks: Vec<ProxyCheck>,
+    /// Address the egress check saw
+    pub egress_ip: Option<IpAddr>,
+}
+
+impl ProxyReport {
+    pub fn healthy(&self) -> bool {
+        self.checks.iter().all(|c| c.ok)
+    }
+
+    pub async fn run(config: &ProxyConfig) -> Self {
+        let subsystems = Subsystem::ALL.iter().map(|s| (*s, config.proxies(*s))).collect();
+        let mut report = Self { proxy: None, subsystems, checks: Vec::new(), egress_ip: None };
+        let proxy = match config.endpoint() {
+            Ok(Some(proxy)) => proxy,
+            Ok(None) => {
+                report.push("config", Ok("no subsystem is proxied; connections go direct".to_string()));
+                return report;
+            }
+            Err(e) => {
+                report.push("config", Err(format!("{:#}", e)));
+                return report;
+            }
+        };
+        report.proxy = Some(proxy.to_string());
+        let auth = if proxy.auth.is_some() { ", with username/password" } else { "" };
+        report.push("config", Ok(format!("{}{}", proxy, auth)));
+
+        match socks5::probe(&proxy).await {
+            Ok(method) => report.push("reach", Ok(format!("SOCKS5 handshake ok ({})", method))),
+            Err(e) => {
+                report.push("reach", Err(e.to_string()));
+                return report;
+            }
+        }
+
+        let egress = egress_ip(&config.egress_check_url, &proxy).await;
+        report.egress_ip = egress.as_ref().ok().copied();
+        report.push("egress", egress.map(|ip| format!("traffic leaves from {}", ip)).map_err(|e| format!("{:#}", e)));
+        report
+    }
+
+    fn push(&mut self, name: &'static str, result: Result<String, String>) {
+        let (ok, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+        self.checks.push(ProxyCheck { name, ok, detail });
+    }
+}
+
+/// Ask `url`, through `proxy`, which address the request came from
+async fn egress_ip(url: &str, proxy: &super::Socks5Proxy) -> anyhow::Result<IpAddr> {
+    let client = with_proxy(reqwest::Client::builder(), Some(proxy)).timeout(EGRESS_TIMEOUT).build()?;
+    let body = client.get(url).send().await?.error_for_status()?.text().await?;
+    parse_egress(&body).ok_or_else(|| anyhow::anyhow!("{} answered without an IP address", url))
+}
+
+/// A bare address, or a JSON object with `ip` / `IP` (ipify, Tor's check API)
+fn parse_egress(body: &str) -> Option<IpAddr> {
+    let body = body.trim();
+    if let Ok(ip) = body.parse() {
+        return Some(ip);
+    }
+    let json: serde_json::Value = serde_json::from_str(body).ok()?;
+    json.get("ip").or_else(|| json.get("IP"))?.as_str()?.parse().ok()
+}
+
+impl fmt::Display for ProxyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "🧅 Outbound proxy: {}", self.proxy.as_deref().unwrap_or("none"))?;
+        let proxied: Vec<String> = self.subsystems.iter().filter(|(_, on)| **on).map(|(s, _)| s.to_string()).collect();
+        if !proxied.is_empty() {
+            writeln!(f, "   for {}", proxied.join(", "))?;
+        }
+        for check in &self.checks {
+            writeln!(f, "  {} {:<7} {}", if check.ok { "✅" } else { "❌" }, check.name, check.detail)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::socks5::testing::{http_server, TestProxy};
+
+    #[tokio::test]
+    async fn test_reports_egress_ip() {
+        let echo = http_server("{\"IsTor\":true,\"IP\":\"203.0.113.7\"}").await;
+        let proxy = TestProxy::start(None).await;
+        proxy.route("egress.invalid", echo);
+        let config = ProxyConfig {
+            enabled: true,
+            host: "127.0.0.1".to_string(),
+            port: proxy.addr.port(),
+            egress_check_url: "http://egress.invalid/api/ip".to_string(),
+            ..Default::default()
+        };
+
+        let report = ProxyReport::run(&config).await;
+        assert!(report.healthy(), "{}", report);
+        assert_eq!(report.egress_ip, Some("203.0.113.7".parse().unwrap()));
+        assert!(report.to_string().contains("traffic leaves from 203.0.113.7"));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_proxy() {
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = closed.local_addr().unwrap().port();
+        drop(closed);
+        let config = ProxyConfig { enabled: true, host: "127.0.0.1".to_string(), port, ..Default::default() };
+
+        let report = ProxyReport::run(&config).await;
+        assert!(!report.healthy());
+        assert_eq!(report.checks.iter().map(|c| c.name).collect::<Vec<_>>(), vec!["config", "reach"]);
+        assert!(report.checks[1].detail.contains("unreachable"));
+
+        assert!(ProxyReport::run(&ProxyConfig::default()).await.healthy());
+    }
+
+    #[test]
+    fn test_parse_egress() {
+        assert_eq!(parse_egress("198.51.100.2\n"), Some("198.51.100.2".parse().unwrap()));
+        assert_eq!(parse_egress("{\"ip\":\"2001:db8::1\"}"), Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(parse_egress("<html>"), None);
+    }
+}
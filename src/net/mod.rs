@@ -0,0 +1,285 @@
+//! Outbound Networking
+//! `[network.proxy]`: a SOCKS5 proxy (a corporate proxy or Tor) for
+//! outbound HTTP and P2P dials, chosen per subsystem. Proxied connections
+//! resolve names at the proxy (socks5h), and proxied HTTP clients refuse to
+//! resolve anything locally, so hostnames don't leak to the local resolver.
+//! Only the proxy's own address is resolved locally; give an IP to avoid
+//! that too. Inbound P2P connections never traverse the proxy
+
+pub mod doctor;
+pub mod socks5;
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// `[network]` configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkSettings {
+    pub proxy: ProxyConfig,
+}
+
+/// Default port of a local Tor client's SOCKS listener
+pub const TOR_SOCKS_PORT: u16 = 9050;
+
+/// Longest SOCKS5 username or password (RFC 1929)
+const MAX_CREDENTIAL_LEN: usize = 255;
+
+/// `[network.proxy]` configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+    /// Proxy every subsystem not overridden in `subsystems`
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Per-subsystem overrides of `enabled`
+    pub subsystems: BTreeMap<Subsystem, bool>,
+    /// Plain-text or JSON (`ip` / `IP`) echo of the caller's address, for
+    /// `network doctor`
+    pub egress_check_url: String,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "127.0.0.1".to_string(),
+            port: TOR_SOCKS_PORT,
+            username: None,
+            password: None,
+            subsystems: BTreeMap::new(),
+            egress_check_url: "https://api.ipify.org".to_string(),
+        }
+    }
+}
+
+/// What makes outbound connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Subsystem {
+    MarketData,
+    Geolocation,
+    Webhooks,
+    /// eSIM SM-DP+ servers
+    Smdp,
+    Taxii,
+    /// Outbound P2P dials
+    P2p,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 6] =
+        [Self::MarketData, Self::Geolocation, Self::Webhooks, Self::Smdp, Self::Taxii, Self::P2p];
+}
+
+impl fmt::Display for Subsystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::MarketData => "market_data",
+            Self::Geolocation => "geolocation",
+            Self::Webhooks => "webhooks",
+            Self::Smdp => "smdp",
+            Self::Taxii => "taxii",
+            Self::P2p => "p2p",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl ProxyConfig {
+    pub fn proxies(&self, subsystem: Subsystem) -> bool {
+        self.subsystems.get(&subsystem).copied().unwrap_or(self.enabled)
+    }
+
+    /// Whether any subsystem goes through the proxy
+    pub fn in_use(&self) -> bool {
+        Subsystem::ALL.iter().any(|s| self.proxies(*s))
+    }
+
+    /// The proxy, checked; `None` when no subsystem uses it
+    pub fn endpoint(&self) -> Result<Option<Socks5Proxy>> {
+        if !self.in_use() {
+            return Ok(None);
+        }
+        let host = self.host.trim().trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() || self.port == 0 {
+            anyhow::bail!("network.proxy needs a host and port when enabled");
+        }
+        let auth = match (&self.username, &self.password) {
+            (Some(username), password) => {
+                let password = password.clone().unwrap_or_default();
+                if username.is_empty() || username.len() > MAX_CREDENTIAL_LEN || password.len() > MAX_CREDENTIAL_LEN {
+                    anyhow::bail!("network.proxy username and password must be 1-255 and 0-255 bytes");
+                }
+                Some((username.clone(), password))
+            }
+            (None, Some(_)) => anyhow::bail!("network.proxy.password is set without a username"),
+            (None, None) => None,
+        };
+
+        let mut url = reqwest::Url::parse("socks5h://localhost")?;
+        let url_host = match host.contains(':') {
+            true => format!("[{}]", host),
+            false => host.to_string(),
+        };
+        url.set_host(Some(&url_host))
+            .map_err(|e| anyhow::anyhow!("Invalid network.proxy.host '{}': {}", host, e))?;
+        url.set_port(Some(self.port)).ok();
+        if let Some((username, password)) = &auth {
+            url.set_username(username).ok();
+            url.set_password(Some(password)).ok();
+        }
+        let http = reqwest::Proxy::all(url)?;
+        Ok(Some(Socks5Proxy { host: host.to_string(), port: self.port, auth, http }))
+    }
+}
+
+/// A checked SOCKS5 proxy
+#[derive(Debug, Clone)]
+pub struct Socks5Proxy {
+    pub host: String,
+    pub port: u16,
+    /// Username and password (RFC 1929)
+    pub auth: Option<(String, String)>,
+    /// The same proxy for reqwest, as `socks5h://`
+    http: reqwest::Proxy,
+}
+
+impl fmt::Display for Socks5Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.host.contains(':') {
+            true => write!(f, "socks5h://[{}]:{}", self.host, self.port),
+            false => write!(f, "socks5h://{}:{}", self.host, self.port),
+        }
+    }
+}
+
+struct ProxyState {
+    config: ProxyConfig,
+    endpoint: Option<Socks5Proxy>,
+}
+
+static PROXY: Lazy<RwLock<ProxyState>> =
+    Lazy::new(|| RwLock::new(ProxyState { config: ProxyConfig::default(), endpoint: None }));
+
+/// Make `config` the process-wide proxy; clients built afterwards use it
+pub fn configure(config: &ProxyConfig) -> Result<()> {
+    let endpoint = config.endpoint()?;
+    if let Some(proxy) = &endpoint {
+        let proxied: Vec<String> = Subsystem::ALL.iter().filter(|s| config.proxies(**s)).map(|s| s.to_string()).collect();
+        tracing::info!("🧅 Outbound proxy {} for {}", proxy, proxied.join(", "));
+    }
+    *PROXY.write() = ProxyState { config: config.clone(), endpoint };
+    Ok(())
+}
+
+/// The proxy `subsystem` must use, if any
+pub fn proxy_for(subsystem: Subsystem) -> Option<Socks5Proxy> {
+    let state = PROXY.read();
+    state.endpoint.clone().filter(|_| state.config.proxies(subsystem))
+}
+
+/// HTTP client builder for `subsystem`, with the process-wide proxy applied
+pub fn http_client(subsystem: Subsystem) -> reqwest::ClientBuilder {
+    with_proxy(reqwest::Client::builder(), proxy_for(subsystem).as_ref())
+}
+
+/// Route `builder`'s requests through `proxy`, and refuse local DNS lookups
+pub fn with_proxy(builder: reqwest::ClientBuilder, proxy: Option<&Socks5Proxy>) -> reqwest::ClientBuilder {
+    match proxy {
+        Some(proxy) => builder.proxy(proxy.http.clone()).dns_resolver(Arc::new(LeakGuard)),
+        None => builder,
+    }
+}
+
+/// Resolver for proxied clients: every name should reach the proxy
+/// unresolved, so a lookup here means a request would bypass it
+struct LeakGuard;
+
+impl reqwest::dns::Resolve for LeakGuard {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let name = name.as_str().to_string();
+        Box::pin(async move {
+            tracing::error!("🧅 Blocked a direct DNS lookup of {} while proxied", name);
+            Err(format!("refusing to resolve {} outside the proxy", name).into())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use socks5::testing::{http_server, TestProxy};
+    use socks5::Target;
+
+    fn config(proxy: &TestProxy) -> ProxyConfig {
+        ProxyConfig { enabled: true, host: "127.0.0.1".to_string(), port: proxy.addr.port(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_subsystem_overrides() {
+        let toml = "enabled = true\nhost = \"10.0.0.5\"\nport = 1080\n[subsystems]\np2p = false\n";
+        let config: ProxyConfig = toml::from_str(toml).unwrap();
+        assert!(config.proxies(Subsystem::Taxii));
+        assert!(!config.proxies(Subsystem::P2p));
+        assert_eq!(config.endpoint().unwrap().unwrap().to_string(), "socks5h://10.0.0.5:1080");
+
+        let only_webhooks: ProxyConfig = toml::from_str("[subsystems]\nwebhooks = true\n").unwrap();
+        assert!(only_webhooks.in_use() && !only_webhooks.proxies(Subsystem::Geolocation));
+        assert!(ProxyConfig::default().endpoint().unwrap().is_none());
+
+        let no_user = ProxyConfig { password: Some("pw".to_string()), ..config_enabled() };
+        assert!(no_user.endpoint().unwrap_err().to_string().contains("without a username"));
+        let no_host = ProxyConfig { host: " ".to_string(), ..config_enabled() };
+        assert!(no_host.endpoint().is_err());
+        let v6 = ProxyConfig { host: "::1".to_string(), ..config_enabled() }.endpoint().unwrap().unwrap();
+        assert_eq!((v6.host.as_str(), v6.to_string()), ("::1", "socks5h://[::1]:9050".to_string()));
+    }
+
+    fn config_enabled() -> ProxyConfig {
+        ProxyConfig { enabled: true, ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn test_http_goes_through_proxy_without_local_dns() {
+        let upstream = http_server("quote").await;
+        let proxy = TestProxy::start(Some(("alice", "s3cret"))).await;
+        proxy.route("quotes.invalid", upstream);
+        let endpoint = ProxyConfig {
+            username: Some("alice".to_string()),
+            password: Some("s3cret".to_string()),
+            ..config(&proxy)
+        }
+        .endpoint()
+        .unwrap();
+
+        // A .invalid name can't resolve locally: it only works if the proxy
+        // is handed the name (socks5h)
+        let client = with_proxy(reqwest::Client::builder(), endpoint.as_ref()).build().unwrap();
+        let body = client.get("http://quotes.invalid/AAPL").send().await.unwrap().text().await.unwrap();
+        assert_eq!(body, "quote");
+        assert_eq!(proxy.targets(), vec![Target::Domain("quotes.invalid".to_string(), 80)]);
+
+        // Wrong credentials are refused, not retried directly
+        let wrong = ProxyConfig { username: Some("alice".to_string()), password: Some("nope".to_string()), ..config(&proxy) };
+        let client = with_proxy(reqwest::Client::builder(), wrong.endpoint().unwrap().as_ref()).build().unwrap();
+        assert!(client.get("http://quotes.invalid/AAPL").send().await.is_err());
+        assert_eq!(proxy.targets().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_leak_guard_refuses_lookups() {
+        use reqwest::dns::Resolve;
+        let name = "example.com".parse().unwrap();
+        let err = LeakGuard.resolve(name).await.err().unwrap();
+        assert!(err.to_string().contains("outside the proxy"));
+    }
+}
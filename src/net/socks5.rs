@@ -0,0 +1,370 @@
+//! SOCKS5 Client
+//! CONNECT through a SOCKS5 proxy (RFC 1928), with optional
+//! username/password authentication (RFC 1929). Hostnames are sent to the
+//! proxy as they are, never resolved here
+
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::Socks5Proxy;
+
+/// Connecting to the proxy and its handshake; Tor circuits can be slow
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+const VERSION: u8 = 0x05;
+const NO_AUTH: u8 = 0x00;
+const USERNAME_PASSWORD: u8 = 0x02;
+/// The proxy's answer when it accepts none of the offered methods
+#[cfg(test)]
+const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+const AUTH_VERSION: u8 = 0x01;
+const CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// Where the proxy should connect
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Ip(SocketAddr),
+    /// Resolved by the proxy
+    Domain(String, u16),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(addr) => write!(f, "{}", addr),
+            Self::Domain(host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+/// A TCP stream to `target` through `proxy`
+pub async fn connect(proxy: &Socks5Proxy, target: &Target) -> io::Result<TcpStream> {
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, async {
+        let mut stream = open(proxy).await?;
+        request(&mut stream, target).await?;
+        Ok(stream)
+    })
+    .await
+    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, format!("SOCKS5 proxy {} timed out", proxy)))?
+}
+
+/// Connect and authenticate without asking for a target; returns the
+/// method the proxy chose
+pub async fn probe(proxy: &Socks5Proxy) -> io::Result<&'static str> {
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, open(proxy))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, format!("SOCKS5 proxy {} timed out", proxy)))?
+        .map(|_| if proxy.auth.is_some() { "username/password" } else { "no authentication" })
+}
+
+async fn open(proxy: &Socks5Proxy) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect((proxy.host.as_str(), proxy.port))
+        .await
+        .map_err(|e| io::Error::new(e.kind(), format!("SOCKS5 proxy {} unreachable: {}", proxy, e)))?;
+    stream.set_nodelay(true)?;
+
+    let method = match proxy.auth {
+        Some(_) => USERNAME_PASSWORD,
+        None => NO_AUTH,
+    };
+    stream.write_all(&[VERSION, 1, method]).await?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(refused(format!("{} is not a SOCKS5 proxy", proxy)));
+    }
+    match (reply[1], &proxy.auth) {
+        (NO_AUTH, None) => {}
+        (USERNAME_PASSWORD, Some((username, password))) => {
+            let mut auth = vec![AUTH_VERSION, username.len() as u8];
+            auth.extend_from_slice(username.as_bytes());
+            auth.push(password.len() as u8);
+            auth.extend_from_slice(password.as_bytes());
+            stream.write_all(&auth).await?;
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).await?;
+            if status[1] != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("SOCKS5 proxy {} rejected the username or password", proxy),
+                ));
+            }
+        }
+        _ => {
+            let wanted = if proxy.auth.is_some() { "username/password" } else { "no" };
+            return Err(refused(format!("SOCKS5 proxy {} doesn't accept {} authentication", proxy, wanted)));
+        }
+    }
+    Ok(stream)
+}
+
+async fn request(stream: &mut TcpStream, target: &Target) -> io::Result<()> {
+    let mut request = vec![VERSION, CONNECT, 0];
+    let port = match target {
+        Target::Ip(SocketAddr::V4(addr)) => {
+            request.push(ATYP_IPV4);
+            request.extend_from_slice(&addr.ip().octets());
+            addr.port()
+        }
+        Target::Ip(SocketAddr::V6(addr)) => {
+            request.push(ATYP_IPV6);
+            request.extend_from_slice(&addr.ip().octets());
+            addr.port()
+        }
+        Target::Domain(host, port) => {
+            if host.is_empty() || host.len() > 255 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Bad SOCKS5 hostname '{}'", host)));
+            }
+            request.push(ATYP_DOMAIN);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+            *port
+        }
+    };
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        return Err(refused(format!("SOCKS5 proxy couldn't reach {}: {}", target, reply_message(reply[1]))));
+    }
+    // Bound address, unused
+    let len = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => stream.read_u8().await? as usize,
+        other => return Err(refused(format!("SOCKS5 reply has unknown address type {}", other))),
+    };
+    let mut bound = vec![0u8; len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+fn refused(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionRefused, message)
+}
+
+fn reply_message(code: u8) -> &'static str {
+    match code {
+        0x01 => "general failure",
+        0x02 => "not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+/// A SOCKS5 server for tests: it records every CONNECT target, connects
+/// IP targets directly and hostnames only through `route`, so a name that
+/// reaches it unresolved can be told apart from one resolved locally
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+
+    #[derive(Clone)]
+    pub struct TestProxy {
+        pub addr: SocketAddr,
+        targets: Arc<Mutex<Vec<Target>>>,
+        routes: Arc<Mutex<HashMap<String, SocketAddr>>>,
+    }
+
+    impl TestProxy {
+        pub async fn start(auth: Option<(&str, &str)>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let proxy = Self {
+                addr: listener.local_addr().unwrap(),
+                targets: Arc::default(),
+                routes: Arc::default(),
+            };
+            let auth = auth.map(|(u, p)| (u.to_string(), p.to_string()));
+            let server = proxy.clone();
+            tokio::spawn(async move {
+                while let Ok((client, _)) = listener.accept().await {
+                    let server = server.clone();
+                    let auth = auth.clone();
+                    tokio::spawn(async move {
+                        let _ = server.serve(client, auth).await;
+                    });
+                }
+            });
+            proxy
+        }
+
+        /// Send CONNECTs for `host` (any port) to `to`
+        pub fn route(&self, host: &str, to: SocketAddr) {
+            self.routes.lock().insert(host.to_string(), to);
+        }
+
+        pub fn targets(&self) -> Vec<Target> {
+            self.targets.lock().clone()
+        }
+
+        async fn serve(&self, mut client: TcpStream, auth: Option<(String, String)>) -> io::Result<()> {
+            let mut header = [0u8; 2];
+            client.read_exact(&mut header).await?;
+            let mut methods = vec![0u8; header[1] as usize];
+            client.read_exact(&mut methods).await?;
+            let wanted = if auth.is_some() { USERNAME_PASSWORD } else { NO_AUTH };
+            if !methods.contains(&wanted) {
+                return client.write_all(&[VERSION, NO_ACCEPTABLE_METHOD]).await;
+            }
+            client.write_all(&[VERSION, wanted]).await?;
+            if let Some((username, password)) = auth {
+                let _version = client.read_u8().await?;
+                let mut user = vec![0u8; client.read_u8().await? as usize];
+                client.read_exact(&mut user).await?;
+                let mut pass = vec![0u8; client.read_u8().await? as usize];
+                client.read_exact(&mut pass).await?;
+                let ok = user == username.as_bytes() && pass == password.as_bytes();
+                client.write_all(&[AUTH_VERSION, if ok { 0 } else { 1 }]).await?;
+                if !ok {
+                    return Ok(());
+                }
+            }
+
+            let mut request = [0u8; 4];
+            client.read_exact(&mut request).await?;
+            let len = match request[3] {
+                ATYP_IPV4 => 4,
+                ATYP_IPV6 => 16,
+                _ => client.read_u8().await? as usize,
+            };
+            let mut addr = vec![0u8; len + 2];
+            client.read_exact(&mut addr).await?;
+            let target = decode_target(request[3], &addr).ok_or_else(|| refused("bad target".to_string()))?;
+            self.targets.lock().push(target.clone());
+
+            let upstream = match &target {
+                Target::Ip(addr) => Some(*addr),
+                Target::Domain(host, _) => self.routes.lock().get(host).copied(),
+            };
+            let upstream = match upstream {
+                Some(addr) => TcpStream::connect(addr).await.ok(),
+                None => None,
+            };
+            let Some(mut upstream) = upstream else {
+                return client.write_all(&[VERSION, 0x04, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0]).await;
+            };
+            client.write_all(&[VERSION, 0, 0, ATYP_IPV4, 127, 0, 0, 1, 0, 0]).await?;
+            tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+            Ok(())
+        }
+    }
+
+    /// Decode a SOCKS5 address of type `atyp` from `bytes` (address then port)
+    pub fn decode_target(atyp: u8, bytes: &[u8]) -> Option<Target> {
+        let (addr, port) = bytes.split_at(bytes.len().checked_sub(2)?);
+        let port = u16::from_be_bytes([port[0], port[1]]);
+        match atyp {
+            ATYP_IPV4 => Some(Target::Ip(SocketAddr::new(IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(addr).ok()?)), port))),
+            ATYP_IPV6 => Some(Target::Ip(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(addr).ok()?)), port))),
+            ATYP_DOMAIN => Some(Target::Domain(String::from_utf8(addr.to_vec()).ok()?, port)),
+            _ => None,
+        }
+    }
+
+    /// An HTTP server answering every request with `body`
+    pub async fn http_server(body: &'static str) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = vec![0u8; 4096];
+                    let _ = stream.read(&mut request).await;
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        addr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::{decode_target, TestProxy};
+    use super::*;
+    use crate::net::ProxyConfig;
+
+    fn proxy_at(addr: SocketAddr, auth: Option<(&str, &str)>) -> Socks5Proxy {
+        let config = ProxyConfig {
+            enabled: true,
+            host: addr.ip().to_string(),
+            port: addr.port(),
+            username: auth.map(|a| a.0.to_string()),
+            password: auth.map(|a| a.1.to_string()),
+            ..Default::default()
+        };
+        config.endpoint().unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_connect_by_name_and_address() {
+        let echo = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let echo_addr = echo.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = echo.accept().await {
+                tokio::spawn(async move {
+                    let (mut read, mut write) = stream.split();
+                    let _ = tokio::io::copy(&mut read, &mut write).await;
+                });
+            }
+        });
+        let server = TestProxy::start(None).await;
+        server.route("echo.invalid", echo_addr);
+        let proxy = proxy_at(server.addr, None);
+
+        for target in [Target::Domain("echo.invalid".to_string(), 7), Target::Ip(echo_addr)] {
+            let mut stream = connect(&proxy, &target).await.unwrap();
+            stream.write_all(b"ping").await.unwrap();
+            let mut reply = [0u8; 4];
+            stream.read_exact(&mut reply).await.unwrap();
+            assert_eq!(&reply, b"ping");
+        }
+        assert_eq!(server.targets(), vec![Target::Domain("echo.invalid".to_string(), 7), Target::Ip(echo_addr)]);
+
+        let err = connect(&proxy, &Target::Domain("nowhere.invalid".to_string(), 80)).await.unwrap_err();
+        assert!(err.to_string().contains("host unreachable"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_authentication() {
+        let server = TestProxy::start(Some(("bob", "pw"))).await;
+        assert_eq!(probe(&proxy_at(server.addr, Some(("bob", "pw")))).await.unwrap(), "username/password");
+        let err = probe(&proxy_at(server.addr, Some(("bob", "wrong")))).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        let err = probe(&proxy_at(server.addr, None)).await.unwrap_err();
+        assert!(err.to_string().contains("doesn't accept no authentication"), "{}", err);
+        assert!(server.targets().is_empty());
+    }
+
+    #[test]
+    fn test_decode_target() {
+        assert_eq!(decode_target(ATYP_IPV4, &[10, 0, 0, 1, 0, 80]), Some(Target::Ip("10.0.0.1:80".parse().unwrap())));
+        assert_eq!(decode_target(ATYP_DOMAIN, b"a.b\x01\xbb"), Some(Target::Domain("a.b".to_string(), 443)));
+        assert_eq!(decode_target(ATYP_IPV6, &[0; 3]), None);
+        assert_eq!(decode_target(ATYP_DOMAIN, &[1]), None);
+    }
+}
@@ -0,0 +1,492 @@
+//! Proof-of-Work Admission
+//! Under load or during a connection flood, new unauthenticated peers must
+//! solve a small hash puzzle before they are fully admitted. Difficulty
+//! scales with load so attackers are slowed rather than legitimate peers
+//! rejected outright.
+
+use libp2p::{Multiaddr, PeerId};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use crate::units::HumanDuration;
+
+/// Extra difficulty bits while a connection flood is active
+const FLOOD_DIFFICULTY_BONUS: u8 = 4;
+
+/// Hardest puzzle a client agrees to solve
+pub const MAX_SOLVABLE_DIFFICULTY: u8 = 28;
+
+/// Previously admitted peers remembered for bypass
+const MAX_KNOWN_PEERS: usize = 10_000;
+
+/// How long a solved challenge exempts a peer from new ones
+const KNOWN_PEER_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// How long after dialing a peer we accept its challenge
+const CHALLENGE_WAIT: Duration = Duration::from_secs(60);
+
+const PREFIX_LEN: usize = 16;
+
+/// `[p2p.admission]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdmissionConfig {
+    /// Enable challenge-based admission
+    pub enabled: bool,
+    /// Active connections above which new peers are challenged
+    pub high_water_mark: usize,
+    /// A ConnectionFlood seen by Mirror Shield within this window activates
+    /// challenges (legacy: `flood_window_secs`)
+    #[serde(alias = "flood_window_secs", deserialize_with = "crate::units::duration_or_secs")]
+    pub flood_window: HumanDuration,
+    /// Leading zero bits required at the high-water mark
+    pub base_difficulty: u8,
+    /// Leading zero bits required at full capacity
+    pub max_difficulty: u8,
+    /// Time allowed to return a solution (legacy: `solve_timeout_secs`)
+    #[serde(alias = "solve_timeout_secs", deserialize_with = "crate::units::duration_or_secs")]
+    pub solve_timeout: HumanDuration,
+    /// Peer IDs that are never challenged
+    pub allowlist: Vec<String>,
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            high_water_mark: 800,
+            flood_window: HumanDuration::from_secs(300),
+            base_difficulty: 12,
+            max_difficulty: 22,
+            solve_timeout: HumanDuration::from_secs(10),
+            allowlist: Vec::new(),
+        }
+    }
+}
+
+/// Challenge sent to a peer
+#[derive(Debug, Clone)]
+pub struct Challenge {
+    pub prefix: Vec<u8>,
+    pub difficulty: u8,
+}
+
+/// Result of checking a peer's solution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdmissionOutcome {
+    /// Solved in time; admission continues for the recorded address
+    Admitted(Multiaddr),
+    Failed(String),
+    /// No challenge outstanding for this peer
+    NoChallenge,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdmissionStats {
+    pub challenges_issued: u64,
+    pub challenges_solved: u64,
+    pub challenges_failed: u64,
+    pub challenges_timed_out: u64,
+    pub bypassed: u64,
+    pub pending: usize,
+}
+
+struct PendingChallenge {
+    challenge: Challenge,
+    remote_addr: Multiaddr,
+    deadline: Instant,
+}
+
+fn parse_allowlist(config: &AdmissionConfig) -> HashSet<PeerId> {
+    config
+        .allowlist
+        .iter()
+        .filter_map(|p| match p.parse() {
+            Ok(peer) => Some(peer),
+            Err(_) => {
+                tracing::warn!("🧩 Ignoring invalid peer ID in admission allowlist: {}", p);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Challenge issuance and verification
+pub struct AdmissionController {
+    config: AdmissionConfig,
+    capacity: usize,
+    allowlist: HashSet<PeerId>,
+    pinned: HashSet<PeerId>,
+    known: HashMap<PeerId, Instant>,
+    pending: HashMap<PeerId, PendingChallenge>,
+    stats: AdmissionStats,
+}
+
+impl AdmissionController {
+    /// `capacity` is the node's connection limit
+    pub fn new(config: AdmissionConfig, capacity: usize) -> Self {
+        Self {
+            allowlist: parse_allowlist(&config),
+            config,
+            capacity,
+            pinned: HashSet::new(),
+            known: HashMap::new(),
+            pending: HashMap::new(),
+            stats: AdmissionStats::default(),
+        }
+    }
+
+    /// Replace thresholds, difficulty and allowlist (config reload).
+    /// Outstanding challenges keep the difficulty they were issued with
+    pub fn set_config(&mut self, config: AdmissionConfig) {
+        self.allowlist = parse_allowlist(&config);
+        self.config = config;
+    }
+
+    /// Exempt a pinned peer from challenges
+    pub fn allow_peer(&mut self, peer_id: PeerId) {
+        self.pinned.insert(peer_id);
+    }
+
+    /// Whether a new connection from `peer_id` must be challenged
+    pub fn requires_challenge(&mut self, peer_id: &PeerId, active: usize, flood: bool) -> bool {
+        if !self.config.enabled || !(flood || active > self.config.high_water_mark) {
+            return false;
+        }
+        let known = self.known.get(peer_id).is_some_and(|admitted| admitted.elapsed() < KNOWN_PEER_TTL);
+        if self.allowlist.contains(peer_id) || self.pinned.contains(peer_id) || known {
+            self.stats.bypassed += 1;
+            return false;
+        }
+        true
+    }
+
+    /// Difficulty for the current load
+    pub fn difficulty(&self, active: usize, flood: bool) -> u8 {
+        scale_difficulty(
+            active,
+            self.config.high_water_mark,
+            self.capacity,
+            self.config.base_difficulty,
+            self.config.max_difficulty,
+            flood,
+        )
+    }
+
+    /// Issue a challenge; the peer stays pending until it solves or times out
+    pub fn issue(&mut self, peer_id: PeerId, remote_addr: Multiaddr, difficulty: u8) -> Challenge {
+        let mut prefix = vec![0u8; PREFIX_LEN];
+        rand::thread_rng().fill_bytes(&mut prefix);
+        let challenge = Challenge { prefix, difficulty };
+
+        self.pending.insert(
+            peer_id,
+            PendingChallenge {
+                challenge: challenge.clone(),
+                remote_addr,
+                deadline: Instant::now() + self.config.solve_timeout.as_std(),
+            },
+        );
+        self.stats.challenges_issued += 1;
+        challenge
+    }
+
+    pub fn is_pending(&self, peer_id: &PeerId) -> bool {
+        self.pending.contains_key(peer_id)
+    }
+
+    /// Check a solution; the challenge is consumed either way
+    pub fn verify(&mut self, peer_id: &PeerId, nonce: u64) -> AdmissionOutcome {
+        let Some(pending) = self.pending.remove(peer_id) else {
+            return AdmissionOutcome::NoChallenge;
+        };
+
+        if Instant::now() > pending.deadline {
+            self.stats.challenges_timed_out += 1;
+            return AdmissionOutcome::Failed("solution arrived after the deadline".to_string());
+        }
+
+        let challenge = &pending.challenge;
+        if !is_valid_solution(&challenge.prefix, nonce, peer_id, challenge.difficulty) {
+            self.stats.challenges_failed += 1;
+            return AdmissionOutcome::Failed("invalid proof-of-work".to_string());
+        }
+
+        self.stats.challenges_solved += 1;
+        self.remember(*peer_id, Instant::now());
+        AdmissionOutcome::Admitted(pending.remote_addr)
+    }
+
+    /// Remember an admitted peer, dropping expired entries and then the
+    /// longest-admitted ones once the set is full
+    fn remember(&mut self, peer_id: PeerId, now: Instant) {
+        if self.known.len() >= MAX_KNOWN_PEERS && !self.known.contains_key(&peer_id) {
+            self.known.retain(|_, admitted| now.duration_since(*admitted) < KNOWN_PEER_TTL);
+            if self.known.len() >= MAX_KNOWN_PEERS {
+                let mut by_age: Vec<(Instant, PeerId)> = self.known.iter().map(|(p, t)| (*t, *p)).collect();
+                by_age.sort_unstable();
+                for (_, oldest) in by_age.iter().take(self.known.len() + 1 - MAX_KNOWN_PEERS / 2) {
+                    self.known.remove(oldest);
+                }
+            }
+        }
+        self.known.insert(peer_id, now);
+    }
+
+    /// Drop challenges past their deadline, returning the peers to disconnect
+    pub fn expire(&mut self, now: Instant) -> Vec<PeerId> {
+        let expired: Vec<PeerId> = self
+            .pending
+            .iter()
+            .filter(|(_, p)| now > p.deadline)
+            .map(|(peer, _)| *peer)
+            .collect();
+        for peer in &expired {
+            self.pending.remove(peer);
+        }
+        self.stats.challenges_timed_out += expired.len() as u64;
+        expired
+    }
+
+    /// Forget an outstanding challenge (peer disconnected)
+    pub fn forget(&mut self, peer_id: &PeerId) {
+        self.pending.remove(peer_id);
+    }
+
+    pub fn stats(&self) -> AdmissionStats {
+        AdmissionStats {
+            pending: self.pending.len(),
+            ..self.stats.clone()
+        }
+    }
+}
+
+/// Client side: which challenges this node is willing to solve
+///
+/// Only peers we dialed may challenge us, once per dial, and only one
+/// solve per peer runs at a time
+#[derive(Default)]
+pub struct ChallengeSolver {
+    awaiting: HashMap<PeerId, Instant>,
+    solving: HashSet<PeerId>,
+}
+
+impl ChallengeSolver {
+    /// We dialed `peer_id`; it may challenge us for a while
+    pub fn expect(&mut self, peer_id: PeerId, now: Instant) {
+        self.awaiting.insert(peer_id, now + CHALLENGE_WAIT);
+    }
+
+    /// Whether to solve a challenge from `peer_id`; marks the solve as running
+    pub fn accept(&mut self, peer_id: PeerId, now: Instant) -> bool {
+        if self.solving.contains(&peer_id) {
+            return false;
+        }
+        match self.awaiting.remove(&peer_id) {
+            Some(until) if now <= until => {
+                self.solving.insert(peer_id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The solve for `peer_id` finished
+    pub fn finish(&mut self, peer_id: &PeerId) {
+        self.solving.remove(peer_id);
+    }
+
+    /// The peer disconnected
+    pub fn forget(&mut self, peer_id: &PeerId) {
+        self.awaiting.remove(peer_id);
+    }
+
+    /// Stop waiting for challenges from dials that are long past
+    pub fn expire(&mut self, now: Instant) {
+        self.awaiting.retain(|_, until| now <= *until);
+    }
+}
+
+/// Leading zero bits required for `active` connections
+///
+/// Scales linearly from `base` at the high-water mark to `max` at capacity;
+/// an active flood adds `FLOOD_DIFFICULTY_BONUS` bits (capped at `max`).
+pub fn scale_difficulty(active: usize, high_water: usize, capacity: usize, base: u8, max: u8, flood: bool) -> u8 {
+    let headroom = capacity.saturating_sub(high_water).max(1) as f64;
+    let load = (active.saturating_sub(high_water) as f64 / headroom).min(1.0);
+    let mut difficulty = base as f64 + load * max.saturating_sub(base) as f64;
+    if flood {
+        difficulty += FLOOD_DIFFICULTY_BONUS as f64;
+    }
+    (difficulty.round() as u8).min(max)
+}
+
+fn solution_hash(prefix: &[u8], nonce: u64, peer_id: &PeerId) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(prefix);
+    hasher.update(nonce.to_le_bytes());
+    hasher.update(peer_id.to_bytes());
+    hasher.finalize().into()
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        if *byte == 0 {
+            bits += 8;
+        } else {
+            bits += byte.leading_zeros();
+            break;
+        }
+    }
+    bits
+}
+
+/// Whether `nonce` solves the challenge for `peer_id`
+pub fn is_valid_solution(prefix: &[u8], nonce: u64, peer_id: &PeerId, difficulty: u8) -> bool {
+    leading_zero_bits(&solution_hash(prefix, nonce, peer_id)) >= difficulty as u32
+}
+
+/// Find a nonce solving the challenge (client side; CPU-bound)
+pub fn solve(prefix: &[u8], difficulty: u8, peer_id: &PeerId) -> u64 {
+    let start = rand::random::<u64>();
+    let mut nonce = start;
+    while !is_valid_solution(prefix, nonce, peer_id, difficulty) {
+        nonce = nonce.wrapping_add(1);
+    }
+    nonce
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn addr() -> Multiaddr {
+        "/ip4/203.0.113.7/tcp/4001".parse().unwrap()
+    }
+
+    #[test]
+    fn test_solution_verification() {
+        let mut controller = AdmissionController::new(AdmissionConfig::default(), 1000);
+        let peer = PeerId::random();
+
+        let challenge = controller.issue(peer, addr(), 10);
+        let nonce = solve(&challenge.prefix, challenge.difficulty, &peer);
+        assert!(is_valid_solution(&challenge.prefix, nonce, &peer, 10));
+        // The solution is bound to the peer ID
+        assert!(!is_valid_solution(&challenge.prefix, nonce, &PeerId::random(), 16));
+
+        assert_eq!(controller.verify(&peer, nonce), AdmissionOutcome::Admitted(addr()));
+        assert_eq!(controller.verify(&peer, nonce), AdmissionOutcome::NoChallenge);
+
+        let other = PeerId::random();
+        let challenge = controller.issue(other, addr(), 16);
+        let bad_nonce = (0..).find(|n| !is_valid_solution(&challenge.prefix, *n, &other, 16)).unwrap();
+        assert!(matches!(controller.verify(&other, bad_nonce), AdmissionOutcome::Failed(_)));
+
+        controller.issue(PeerId::random(), addr(), 20);
+        assert_eq!(controller.expire(Instant::now() + Duration::from_secs(60)).len(), 1);
+
+        let stats = controller.stats();
+        assert_eq!(
+            (stats.challenges_issued, stats.challenges_solved, stats.challenges_failed, stats.challenges_timed_out),
+            (3, 1, 1, 1)
+        );
+    }
+
+    #[test]
+    fn test_difficulty_scaling() {
+        assert_eq!(scale_difficulty(800, 800, 1000, 12, 22, false), 12);
+        assert_eq!(scale_difficulty(900, 800, 1000, 12, 22, false), 17);
+        assert_eq!(scale_difficulty(1000, 800, 1000, 12, 22, false), 22);
+        assert_eq!(scale_difficulty(5000, 800, 1000, 12, 22, false), 22);
+        // Flood below the high-water mark still challenges, with a bonus
+        assert_eq!(scale_difficulty(10, 800, 1000, 12, 22, true), 16);
+        assert_eq!(scale_difficulty(1000, 800, 1000, 12, 22, true), 22);
+    }
+
+    #[test]
+    fn test_bypass_list() {
+        let pinned = PeerId::random();
+        let config = AdmissionConfig {
+            enabled: true,
+            allowlist: vec![pinned.to_string(), "not-a-peer-id".to_string()],
+            ..Default::default()
+        };
+        let mut controller = AdmissionController::new(config, 1000);
+        let stranger = PeerId::random();
+
+        // Below the high-water mark without a flood nobody is challenged
+        assert!(!controller.requires_challenge(&stranger, 10, false));
+
+        assert!(controller.requires_challenge(&stranger, 900, false));
+        assert!(!controller.requires_challenge(&pinned, 900, false));
+        assert!(controller.requires_challenge(&stranger, 10, true));
+
+        // Peers that solved once are known and bypass later challenges
+        let challenge = controller.issue(stranger, addr(), 8);
+        let nonce = solve(&challenge.prefix, 8, &stranger);
+        controller.verify(&stranger, nonce);
+        assert!(!controller.requires_challenge(&stranger, 900, true));
+        assert_eq!(controller.stats().bypassed, 2);
+    }
+
+    #[test]
+    fn test_known_peers_expire_and_evict_oldest() {
+        let config = AdmissionConfig { enabled: true, ..Default::default() };
+        let mut controller = AdmissionController::new(config, 1000);
+        let start = Instant::now();
+
+        let stale = PeerId::random();
+        controller.known.insert(stale, start - KNOWN_PEER_TTL);
+        assert!(controller.requires_challenge(&stale, 900, false));
+
+        let peers: Vec<PeerId> = (0..MAX_KNOWN_PEERS - 1).map(|_| PeerId::random()).collect();
+        for (i, peer) in peers.iter().enumerate() {
+            controller.remember(*peer, start + Duration::from_millis(i as u64));
+        }
+        assert_eq!(controller.known.len(), MAX_KNOWN_PEERS);
+
+        // Full: expired entries go first
+        controller.remember(PeerId::random(), start + Duration::from_secs(60));
+        assert!(!controller.known.contains_key(&stale));
+        assert_eq!(controller.known.len(), MAX_KNOWN_PEERS);
+        assert!(controller.known.contains_key(&peers[0]));
+
+        // Still full: the longest-admitted half goes
+        let newest = PeerId::random();
+        controller.remember(newest, start + Duration::from_secs(61));
+        assert_eq!(controller.known.len(), MAX_KNOWN_PEERS / 2);
+        assert!(!controller.known.contains_key(&peers[0]));
+        assert!(controller.known.contains_key(&peers[MAX_KNOWN_PEERS - 2]));
+        assert!(!controller.requires_challenge(&newest, 900, false));
+    }
+
+    #[test]
+    fn test_solver_only_answers_dialed_peers_once() {
+        let mut solver = ChallengeSolver::default();
+        let now = Instant::now();
+        let dialed = PeerId::random();
+
+        // Nobody we dialed
+        assert!(!solver.accept(PeerId::random(), now));
+
+        solver.expect(dialed, now);
+        assert!(solver.accept(dialed, now));
+        // A second challenge while solving, or after, is refused
+        assert!(!solver.accept(dialed, now));
+        solver.finish(&dialed);
+        assert!(!solver.accept(dialed, now));
+
+        // Challenges long after the dial are refused
+        solver.expect(dialed, now);
+        assert!(!solver.accept(dialed, now + CHALLENGE_WAIT + Duration::from_secs(1)));
+        solver.expect(dialed, now);
+        solver.expire(now + CHALLENGE_WAIT + Duration::from_secs(1));
+        assert!(solver.awaiting.is_empty());
+    }
+}
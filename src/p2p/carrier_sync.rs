@@ -0,0 +1,230 @@
+//! Carrier Database Sync
+//! Signed carrier updates are gossiped on `carrier-db/updates`; nodes that
+//! join late or miss a version catch up with a `GetCarrierDb` request. In
+//! strict mode verified updates wait in the approval queue and are applied,
+//! in version order, once an operator approves them
+
+use anyhow::{Context, Result};
+use ed25519_dalek::VerifyingKey;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use crate::approvals::{ApprovalQueue, Decision};
+use crate::esim::carrier_updates::{CarrierDbUpdate, UpdateRejection};
+use crate::esim::carriers::CarrierDatabase;
+
+pub const CARRIER_DB_TOPIC: &str = "carrier-db/updates";
+
+/// Approval category of updates held in strict mode
+pub const APPROVAL_CATEGORY: &str = "carrier_db.update";
+
+/// Updates returned per `GetCarrierDb` response
+pub const MAX_UPDATES_PER_RESPONSE: usize = 256;
+
+/// What to do after receiving an update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncOutcome {
+    Applied,
+    /// Already applied or refused (logged and counted by the database)
+    Ignored,
+    /// We are missing earlier versions; request them from the sender
+    Behind { since_version: u32 },
+    /// Verified and waiting for an operator (strict mode)
+    Held,
+}
+
+/// Strict mode: the queue holding updates, and approved ones not yet applied
+struct Approval {
+    queue: Arc<ApprovalQueue>,
+    ttl: chrono::Duration,
+    approved_rx: mpsc::UnboundedReceiver<CarrierDbUpdate>,
+    /// Approved ahead of an earlier version that is still pending
+    approved: BTreeMap<u32, CarrierDbUpdate>,
+}
+
+/// Carrier database kept current from the network
+pub struct CarrierSync {
+    db: CarrierDatabase,
+    maintainer_key: VerifyingKey,
+    approval: Option<Approval>,
+}
+
+impl CarrierSync {
+    pub fn new(db: CarrierDatabase, maintainer_key: VerifyingKey) -> Self {
+        Self { db, maintainer_key, approval: None }
+    }
+
+    /// Strict mode: hold verified updates in `queue` for up to `ttl` instead
+    /// of applying them; `apply_approved` applies the approved ones
+    pub fn require_approval(&mut self, queue: Arc<ApprovalQueue>, ttl: chrono::Duration) {
+        let (approved_tx, approved_rx) = mpsc::unbounded_channel();
+        queue.register(APPROVAL_CATEGORY, move |request, decision| {
+            if decision == Decision::Approve {
+                let update = serde_json::from_value(request.context.clone()).context("Malformed held carrier update")?;
+                // Only fails once the node has stopped; the decision is kept for next time
+                approved_tx.send(update).map_err(|_| anyhow::anyhow!("Carrier sync has stopped"))?;
+            }
+            Ok(())
+        });
+        self.approval = Some(Approval { queue, ttl, approved_rx, approved: BTreeMap::new() });
+    }
+
+    pub fn db(&self) -> &CarrierDatabase {
+        &self.db
+    }
+
+    pub fn version(&self) -> u32 {
+        self.db.version()
+    }
+
+    pub fn on_update(&mut self, update: CarrierDbUpdate) -> SyncOutcome {
+        if self.approval.is_some() {
+            return self.hold(update);
+        }
+        self.apply(update)
+    }
+
+    /// Apply local updates (`carriers publish`) without asking for approval
+    pub fn apply_local(&mut self, update: CarrierDbUpdate) -> SyncOutcome {
+        self.apply(update)
+    }
+
+    fn apply(&mut self, update: CarrierDbUpdate) -> SyncOutcome {
+        match self.db.apply_update(update, &self.maintainer_key) {
+            Ok(()) => SyncOutcome::Applied,
+            Err(e) => match e.downcast_ref::<UpdateRejection>() {
+                Some(UpdateRejection::Gap { current, .. }) => SyncOutcome::Behind { since_version: *current },
+                Some(_) => SyncOutcome::Ignored,
+                None => {
+                    tracing::warn!("📡 Failed to apply carrier update: {}", e);
+                    SyncOutcome::Ignored
+                }
+            },
+        }
+    }
+
+    /// Queue a verified update for approval, once per version. Behind when
+    /// it skips past everything applied or already held
+    fn hold(&mut self, update: CarrierDbUpdate) -> SyncOutcome {
+        if self.db.check_update(&update, &self.maintainer_key).is_err() {
+            return SyncOutcome::Ignored;
+        }
+        let Some(approval) = self.approval.as_ref() else { return SyncOutcome::Ignored };
+        let current = self.db.version();
+        let held = match approval.queue.pending(APPROVAL_CATEGORY) {
+            Ok(held) => held,
+            Err(e) => {
+                tracing::warn!("📡 Could not read held carrier updates: {:#}", e);
+                return SyncOutcome::Ignored;
+            }
+        };
+        let mut known: Vec<u32> = held.iter().filter_map(|r| r.context["version"].as_u64()).map(|v| v as u32).collect();
+        known.extend(approval.approved.keys());
+        if known.contains(&update.version) {
+            return SyncOutcome::Ignored;
+        }
+        let newest = known.iter().copied().max().unwrap_or(current).max(current);
+
+        let version = update.version;
+        let description = format!(
+            "Apply carrier database update v{} (+{} ~{} -{})",
+            version,
+            update.added.len(),
+            update.updated.len(),
+            update.removed.len()
+        );
+        let context = match serde_json::to_value(&update) {
+            Ok(context) => context,
+            Err(e) => {
+                tracing::warn!("📡 Could not hold carrier update v{}: {}", version, e);
+                return SyncOutcome::Ignored;
+            }
+        };
+        if let Err(e) = approval.queue.enqueue(APPROVAL_CATEGORY, &description, context, approval.ttl, chrono::Utc::now()) {
+            tracing::warn!("📡 Could not hold carrier update v{}: {:#}", version, e);
+            return SyncOutcome::Ignored;
+        }
+        tracing::info!("📡 Carrier update v{} held for approval", version);
+        if version > newest + 1 {
+            SyncOutcome::Behind { since_version: current }
+        } else {
+            SyncOutcome::Held
+        }
+    }
+
+    /// Apply updates approved since the last call, as far as their versions
+    /// are contiguous with the database; returns how many were applied
+    pub fn apply_approved(&mut self) -> usize {
+        let Some(approval) = self.approval.as_mut() else { return 0 };
+        while let Ok(update) = approval.approved_rx.try_recv() {
+            approval.approved.insert(update.version, update);
+        }
+        let current = self.db.version();
+        approval.approved.retain(|version, _| *version > current);
+        let mut ready = Vec::new();
+        let mut next = current + 1;
+        while let Some(update) = approval.approved.remove(&next) {
+            ready.push(update);
+            next += 1;
+        }
+        ready.into_iter().map(|update| self.apply(update)).filter(|outcome| *outcome == SyncOutcome::Applied).count()
+    }
+
+    /// Answer to `GetCarrierDb { since_version }`
+    pub fn updates_since(&self, since_version: u32) -> Vec<CarrierDbUpdate> {
+        let mut updates = self.db.updates_since(since_version);
+        updates.truncate(MAX_UPDATES_PER_RESPONSE);
+        updates
+    }
+}
+
+pub fn encode_update(update: &CarrierDbUpdate) -> Result<Vec<u8>> {
+    serde_json::to_vec(update).context("Failed to encode carrier update")
+}
+
+pub fn decode_update(data: &[u8]) -> Result<CarrierDbUpdate> {
+    serde_json::from_slice(data).context("Malformed carrier update")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approvals::LOCAL_CLI;
+    use crate::storage::RuntimeMode;
+    use ed25519_dalek::SigningKey;
+
+    #[tokio::test]
+    async fn test_strict_mode_holds_until_approved() {
+        let maintainer = SigningKey::from_bytes(&[9u8; 32]);
+        let update = |version: u32| CarrierDbUpdate::new(version).sign(&maintainer).unwrap();
+        let queue = Arc::new(ApprovalQueue::open(std::path::Path::new("unused"), RuntimeMode::Ephemeral).unwrap());
+        let mut sync = CarrierSync::new(CarrierDatabase::new(), maintainer.verifying_key());
+        sync.require_approval(queue.clone(), chrono::Duration::hours(1));
+
+        assert_eq!(sync.on_update(update(1)), SyncOutcome::Held);
+        assert_eq!(sync.on_update(update(1)), SyncOutcome::Ignored);
+        assert_eq!(sync.on_update(update(2)), SyncOutcome::Held);
+        assert_eq!(sync.on_update(update(4)), SyncOutcome::Behind { since_version: 0 });
+        let forged = CarrierDbUpdate::new(3).sign(&SigningKey::from_bytes(&[1u8; 32])).unwrap();
+        assert_eq!(sync.on_update(forged), SyncOutcome::Ignored);
+        assert_eq!(sync.db().rejections().bad_signature, 1);
+        assert_eq!(sync.version(), 0);
+
+        let held = queue.pending(APPROVAL_CATEGORY).unwrap();
+        assert_eq!(held.len(), 3);
+        let id = |version: u64| held.iter().find(|r| r.context["version"] == version).unwrap().id.clone();
+        // v2 approved first waits for v1
+        queue.resolve(&id(2), Decision::Approve, LOCAL_CLI, chrono::Utc::now()).await.unwrap();
+        assert_eq!(sync.apply_approved(), 0);
+        queue.resolve(&id(1), Decision::Approve, LOCAL_CLI, chrono::Utc::now()).await.unwrap();
+        assert_eq!(sync.apply_approved(), 2);
+        assert_eq!(sync.version(), 2);
+        queue.resolve(&id(4), Decision::Deny, LOCAL_CLI, chrono::Utc::now()).await.unwrap();
+        assert_eq!(sync.apply_approved(), 0);
+        assert!(queue.list().unwrap().is_empty());
+
+        // Our own publishes skip the queue
+        assert_eq!(sync.apply_local(update(3)), SyncOutcome::Applied);
+    }
+}
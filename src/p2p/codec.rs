@@ -0,0 +1,211 @@
+//! Request/Response Codec
+//! Aware of the negotiated protocol: `/quantra/1.1.0[/cbor|/json]` requests
+//! travel in a `RequestEnvelope`, `/quantra/1.0.0` requests are bare so
+//! older peers can still read them. Bodies are JSON on `/json`, canonical
+//! CBOR otherwise (which peers on libp2p's stock CBOR codec read as usual).
+//! Below `/quantra/1.2.0`, quotes travel in their old single-price shape
+
+use async_trait::async_trait;
+use futures::prelude::*;
+use libp2p::request_response;
+use libp2p::StreamProtocol;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+use super::protocol::{has_full_quotes, QuantraRequest, QuantraResponse, RequestEnvelope, QUANTRA_PROTOCOL};
+use super::wire::WireFormat;
+
+/// Same limits as libp2p's CBOR codec
+const REQUEST_SIZE_MAXIMUM: u64 = 1024 * 1024;
+const RESPONSE_SIZE_MAXIMUM: u64 = 10 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuantraCodec;
+
+/// Responses that changed shape in 1.2.0, as older peers read and write them
+#[derive(Debug, Serialize, Deserialize)]
+enum LegacyResponse {
+    Quote { symbol: String, price: f64, timestamp: i64 },
+    Error(String),
+}
+
+/// Whether requests on `protocol` carry an envelope
+fn enveloped(protocol: &StreamProtocol) -> bool {
+    *protocol != QUANTRA_PROTOCOL
+}
+
+async fn read_body<T, M>(protocol: &StreamProtocol, io: &mut T, limit: u64) -> io::Result<M>
+where
+    T: AsyncRead + Unpin + Send,
+    M: DeserializeOwned,
+{
+    let mut buf = Vec::new();
+    io.take(limit).read_to_end(&mut buf).await?;
+    decode(protocol, &buf)
+}
+
+fn decode<M: DeserializeOwned>(protocol: &StreamProtocol, buf: &[u8]) -> io::Result<M> {
+    WireFormat::of_protocol(protocol.as_ref())
+        .decode(buf)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+async fn write_body<T, M>(protocol: &StreamProtocol, io: &mut T, message: &M) -> io::Result<()>
+where
+    T: AsyncWrite + Unpin + Send,
+    M: Serialize,
+{
+    let buf = WireFormat::of_protocol(protocol.as_ref()).encode(message).map_err(|e| io::Error::other(e.to_string()))?;
+    io.write_all(&buf).await
+}
+
+#[async_trait]
+impl request_response::Codec for QuantraCodec {
+    type Protocol = StreamProtocol;
+    type Request = RequestEnvelope;
+    type Response = QuantraResponse;
+
+    async fn read_request<T>(&mut self, protocol: &StreamProtocol, io: &mut T) -> io::Result<RequestEnvelope>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        if enveloped(protocol) {
+            return read_body(protocol, io, REQUEST_SIZE_MAXIMUM).await;
+        }
+        let request: QuantraRequest = read_body(protocol, io, REQUEST_SIZE_MAXIMUM).await?;
+        Ok(RequestEnvelope::new(request, None))
+    }
+
+    async fn read_response<T>(&mut self, protocol: &StreamProtocol, io: &mut T) -> io::Result<QuantraResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut buf = Vec::new();
+        io.take(RESPONSE_SIZE_MAXIMUM).read_to_end(&mut buf).await?;
+        match decode(protocol, &buf) {
+            // An old quote's one price stands in for bid, ask and last
+            Err(e) if !has_full_quotes(protocol) => match decode(protocol, &buf).map_err(|_| e)? {
+                LegacyResponse::Quote { symbol, price, timestamp } => {
+                    let price = Decimal::from_f64_retain(price).unwrap_or_default();
+                    Ok(QuantraResponse::Quote { symbol, bid: price, ask: price, last: price, volume: 0, timestamp })
+                }
+                LegacyResponse::Error(e) => Ok(QuantraResponse::Error(e)),
+            },
+            result => result,
+        }
+    }
+
+    async fn write_request<T>(&mut self, protocol: &StreamProtocol, io: &mut T, envelope: RequestEnvelope) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        match enveloped(protocol) {
+            true => write_body(protocol, io, &envelope).await,
+            false => write_body(protocol, io, &envelope.request).await,
+        }
+    }
+
+    async fn write_response<T>(&mut self, protocol: &StreamProtocol, io: &mut T, response: QuantraResponse) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        if has_full_quotes(protocol) {
+            return write_body(protocol, io, &response).await;
+        }
+        match response {
+            QuantraResponse::Quote { symbol, last, timestamp, .. } => {
+                let price = last.to_f64().unwrap_or_default();
+                write_body(protocol, io, &LegacyResponse::Quote { symbol, price, timestamp }).await
+            }
+            QuantraResponse::UnknownSymbol { symbol } => {
+                write_body(protocol, io, &LegacyResponse::Error(format!("Unknown symbol {}", symbol))).await
+            }
+            response => write_body(protocol, io, &response).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::p2p::protocol::{
+        QUANTRA_PROTOCOL_V1_1, QUANTRA_PROTOCOL_V1_1_CBOR, QUANTRA_PROTOCOL_V1_1_JSON, QUANTRA_PROTOCOL_V1_2_CBOR,
+    };
+    use libp2p::request_response::Codec;
+
+    async fn round_trip(protocol: &StreamProtocol, envelope: RequestEnvelope) -> (Vec<u8>, RequestEnvelope) {
+        let mut wire = Vec::new();
+        QuantraCodec.write_request(protocol, &mut wire, envelope).await.unwrap();
+        let read = QuantraCodec.read_request(protocol, &mut wire.as_slice()).await.unwrap();
+        (wire, read)
+    }
+
+    #[tokio::test]
+    async fn test_trace_id_degrades_on_old_protocol() {
+        let envelope = RequestEnvelope { trace_id: Some("req-42".to_string()), request: QuantraRequest::GetQuote { symbol: "AAPL".into() } };
+
+        let (_, read) = round_trip(&QUANTRA_PROTOCOL_V1_1, envelope.clone()).await;
+        assert_eq!(read.trace_id.as_deref(), Some("req-42"));
+
+        // A 1.0.0 peer gets the bare request it always did, and what it sends
+        // reads back without a trace ID
+        let (wire, read) = round_trip(&QUANTRA_PROTOCOL, envelope).await;
+        let old: QuantraRequest = cbor4ii::serde::from_slice(&wire).unwrap();
+        assert!(matches!(old, QuantraRequest::GetQuote { ref symbol } if symbol == "AAPL"));
+        assert!(read.trace_id.is_none());
+        assert!(matches!(read.request, QuantraRequest::GetQuote { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_body_format_follows_protocol() {
+        let envelope = RequestEnvelope { trace_id: Some("req-7".to_string()), request: QuantraRequest::GetCarrierDb { since_version: 3 } };
+
+        let (wire, read) = round_trip(&QUANTRA_PROTOCOL_V1_1_JSON, envelope.clone()).await;
+        let json: serde_json::Value = serde_json::from_slice(&wire).unwrap();
+        assert_eq!(json["request"]["GetCarrierDb"]["since_version"], 3);
+        assert_eq!(read.trace_id.as_deref(), Some("req-7"));
+
+        // Canonical CBOR is still what a stock-codec 1.1.0 peer reads
+        let (cbor, read) = round_trip(&QUANTRA_PROTOCOL_V1_1_CBOR, envelope.clone()).await;
+        assert!(matches!(read.request, QuantraRequest::GetCarrierDb { since_version: 3 }));
+        let (bare, _) = round_trip(&QUANTRA_PROTOCOL_V1_1, envelope).await;
+        assert_eq!(cbor, bare);
+        let stock: RequestEnvelope = cbor4ii::serde::from_slice(&cbor).unwrap();
+        assert_eq!(stock.trace_id.as_deref(), Some("req-7"));
+    }
+
+    async fn response_round_trip(protocol: &StreamProtocol, response: QuantraResponse) -> (Vec<u8>, QuantraResponse) {
+        let mut wire = Vec::new();
+        QuantraCodec.write_response(protocol, &mut wire, response).await.unwrap();
+        let read = QuantraCodec.read_response(protocol, &mut wire.as_slice()).await.unwrap();
+        (wire, read)
+    }
+
+    #[tokio::test]
+    async fn test_quote_degrades_before_1_2() {
+        let quote = QuantraResponse::Quote {
+            symbol: "AAPL".into(),
+            bid: Decimal::new(10020, 2),
+            ask: Decimal::new(10030, 2),
+            last: Decimal::new(10025, 2),
+            volume: 1_000,
+            timestamp: 1_700_000_000,
+        };
+        let (_, read) = response_round_trip(&QUANTRA_PROTOCOL_V1_2_CBOR, quote.clone()).await;
+        assert!(matches!(read, QuantraResponse::Quote { volume: 1_000, bid, .. } if bid == Decimal::new(10020, 2)));
+
+        // A 1.1.0 peer reads the single price it always did, and its quotes
+        // read back with that price throughout
+        let (wire, read) = response_round_trip(&QUANTRA_PROTOCOL_V1_1, quote).await;
+        let old: LegacyResponse = cbor4ii::serde::from_slice(&wire).unwrap();
+        assert!(matches!(old, LegacyResponse::Quote { price, .. } if price == 100.25));
+        let last = Decimal::new(10025, 2);
+        assert!(matches!(read, QuantraResponse::Quote { bid, ask, volume: 0, .. } if bid == last && ask == last));
+
+        let (_, read) = response_round_trip(&QUANTRA_PROTOCOL_V1_1_JSON, QuantraResponse::UnknownSymbol { symbol: "???".into() }).await;
+        assert!(matches!(read, QuantraResponse::Error(e) if e.contains("???")));
+    }
+}
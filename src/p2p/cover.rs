@@ -0,0 +1,517 @@
+//! Cover Traffic
+//! Optional decoy gossip and direct messages, so traffic volume doesn't give
+//! away when the node is chatting or provisioning. Decoys follow a Poisson
+//! process whose rate tops real traffic up into a target band, within CPU
+//! and bandwidth budgets; their sizes are drawn from recent real payloads
+//!
+//! Consenting nodes advertise `cover/1` in their identify agent version and
+//! send each other a random cover key. A decoy opens with a nonce and its
+//! HMAC under the sender's cover key (inside the sealed box for direct
+//! messages), so only peers holding the key can tell it from real traffic.
+//! Decoys never go through the outbox, never earn receipts, and are left
+//! out of node counters and telemetry unless `include_in_stats`
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use libp2p::request_response::OutboundRequestId;
+use libp2p::{gossipsub, PeerId};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore, SeedableRng};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::crypto::sealed;
+use crate::units::{HumanDuration, HumanSize};
+
+pub const COVER_TOPIC: &str = "quantra/cover";
+
+/// Agent version word of nodes that exchange decoys
+pub const CAPABILITY: &str = "cover/1";
+
+pub const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 16;
+/// Nonce followed by its HMAC-SHA256 tag
+const MARKER_LEN: usize = NONCE_LEN + 32;
+
+/// `[p2p.cover]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CoverConfig {
+    /// Advertise consent and send decoys. Off unless set
+    pub enabled: bool,
+    /// Decoy gossip goes to these topics, which the node joins; peers that
+    /// don't take part shouldn't read them
+    pub topics: Vec<String>,
+    /// Also send decoy direct messages to consenting peers
+    pub direct: bool,
+    /// Total (real + decoy) messages per minute to keep within
+    pub target_min_per_minute: f64,
+    pub target_max_per_minute: f64,
+    /// Real traffic is measured over this window
+    pub window: HumanDuration,
+    /// CPU budget
+    pub max_decoys_per_minute: f64,
+    /// Bandwidth budget
+    pub max_bytes_per_minute: HumanSize,
+    /// Decoy sizes are drawn from this range until real traffic is seen
+    pub min_size: HumanSize,
+    pub max_size: HumanSize,
+    /// Count received decoys in node counters and telemetry (debugging)
+    pub include_in_stats: bool,
+}
+
+impl Default for CoverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            topics: vec![COVER_TOPIC.to_string()],
+            direct: true,
+            target_min_per_minute: 20.0,
+            target_max_per_minute: 40.0,
+            window: HumanDuration::from_secs(300),
+            max_decoys_per_minute: 60.0,
+            max_bytes_per_minute: HumanSize::from_bytes(256 << 10),
+            min_size: HumanSize::from_bytes(64),
+            max_size: HumanSize::from_bytes(2048),
+            include_in_stats: false,
+        }
+    }
+}
+
+static CONSENT: AtomicBool = AtomicBool::new(false);
+
+/// Make `config` the process-wide consent; nodes built afterwards
+/// advertise it
+pub fn configure(config: &CoverConfig) {
+    CONSENT.store(config.enabled, Ordering::Relaxed);
+}
+
+/// Identify agent version, with the capability when consenting
+pub fn agent_version() -> String {
+    let agent = format!("quantraband/{}", env!("CARGO_PKG_VERSION"));
+    match CONSENT.load(Ordering::Relaxed) {
+        true => format!("{} {}", agent, CAPABILITY),
+        false => agent,
+    }
+}
+
+/// Whether a peer's agent version advertises the capability
+pub fn consents(agent_version: &str) -> bool {
+    agent_version.split_whitespace().any(|word| word == CAPABILITY)
+}
+
+/// Decoys per minute that keep `real` plus decoys within `min..=max`:
+/// `current` while the total is inside the band, else aimed at its middle.
+/// Never negative or above `cap`
+pub fn adapt_rate(real: f64, current: f64, min: f64, max: f64, cap: f64) -> f64 {
+    let rate = match (min..=max).contains(&(real + current)) {
+        true => current,
+        false => (min + max) / 2.0 - real,
+    };
+    rate.clamp(0.0, cap.max(0.0))
+}
+
+/// Messages and their sizes within a sliding window
+#[derive(Debug, Default)]
+struct Meter {
+    events: VecDeque<(DateTime<Utc>, usize)>,
+    bytes: usize,
+}
+
+impl Meter {
+    fn record(&mut self, at: DateTime<Utc>, size: usize) {
+        self.events.push_back((at, size));
+        self.bytes += size;
+    }
+
+    fn prune(&mut self, since: DateTime<Utc>) {
+        while let Some(&(at, size)) = self.events.front() {
+            if at >= since {
+                break;
+            }
+            self.events.pop_front();
+            self.bytes -= size;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.events.len()
+    }
+}
+
+/// Where a decoy goes
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecoyTarget {
+    Gossip(String),
+    /// Sealed to the peer, as a direct message
+    Direct(PeerId),
+}
+
+#[derive(Debug, Clone)]
+pub struct Decoy {
+    pub target: DecoyTarget,
+    /// Marker and padding; direct decoys are sealed before sending
+    pub payload: Vec<u8>,
+}
+
+/// Decoy scheduling and recognition for one node
+pub struct CoverTraffic {
+    config: CoverConfig,
+    key: [u8; KEY_LEN],
+    signing: hmac::Key,
+    /// Keys of consenting peers, to recognise their decoys
+    peer_keys: HashMap<PeerId, hmac::Key>,
+    /// Consenting peers holding our key, so direct decoys reach them as such
+    ready: HashSet<PeerId>,
+    key_requests: HashMap<OutboundRequestId, PeerId>,
+    decoy_requests: HashSet<OutboundRequestId>,
+    real_gossip: Meter,
+    real_direct: Meter,
+    sent: Meter,
+    rate: f64,
+    received: u64,
+    rng: StdRng,
+}
+
+impl CoverTraffic {
+    pub fn new(config: CoverConfig) -> Self {
+        let mut rng = StdRng::from_entropy();
+        let mut key = [0u8; KEY_LEN];
+        rng.fill_bytes(&mut key);
+        Self {
+            config,
+            signing: hmac::Key::new(hmac::HMAC_SHA256, &key),
+            key,
+            peer_keys: HashMap::new(),
+            ready: HashSet::new(),
+            key_requests: HashMap::new(),
+            decoy_requests: HashSet::new(),
+            real_gossip: Meter::default(),
+            real_direct: Meter::default(),
+            sent: Meter::default(),
+            rate: 0.0,
+            received: 0,
+            rng,
+        }
+    }
+
+    pub fn config(&self) -> &CoverConfig {
+        &self.config
+    }
+
+    /// Our cover key, sent sealed to consenting peers
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Current decoy rate, per minute
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Decoys recognised and discarded so far
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// A real gossip message or direct message went out
+    pub fn record_real(&mut self, direct: bool, size: usize, now: DateTime<Utc>) {
+        match direct {
+            true => self.real_direct.record(now, size),
+            false => self.real_gossip.record(now, size),
+        }
+    }
+
+    pub fn add_peer_key(&mut self, peer: PeerId, key: &[u8]) -> Result<()> {
+        anyhow::ensure!(key.len() == KEY_LEN, "Cover key is {} bytes ({} expected)", key.len(), KEY_LEN);
+        self.peer_keys.insert(peer, hmac::Key::new(hmac::HMAC_SHA256, key));
+        Ok(())
+    }
+
+    /// Consenting peers holding our key, which get direct decoys
+    pub fn ready_peers(&self) -> impl Iterator<Item = &PeerId> {
+        self.ready.iter()
+    }
+
+    /// Whether `peer` still needs our key (identify repeats itself)
+    pub fn needs_key(&self, peer: &PeerId) -> bool {
+        !self.ready.contains(peer) && !self.key_requests.values().any(|p| p == peer)
+    }
+
+    pub fn key_sent(&mut self, request_id: OutboundRequestId, peer: PeerId) {
+        self.key_requests.insert(request_id, peer);
+    }
+
+    /// Answer to our key: accepted peers get direct decoys from now on.
+    /// False if `request_id` wasn't a key exchange
+    pub fn key_answered(&mut self, request_id: &OutboundRequestId, accepted: bool) -> bool {
+        let Some(peer) = self.key_requests.remove(request_id) else { return false };
+        if accepted {
+            self.ready.insert(peer);
+        }
+        true
+    }
+
+    /// Whether a response belongs to a key exchange or decoy of ours
+    pub fn awaits(&self, request_id: &OutboundRequestId) -> bool {
+        self.key_requests.contains_key(request_id) || self.decoy_requests.contains(request_id)
+    }
+
+    pub fn decoy_sent(&mut self, request_id: OutboundRequestId) {
+        self.decoy_requests.insert(request_id);
+    }
+
+    /// Whether a response or failure belongs to a decoy (it is dropped)
+    pub fn take_decoy_request(&mut self, request_id: &OutboundRequestId) -> bool {
+        self.decoy_requests.remove(request_id)
+    }
+
+    pub fn forget(&mut self, peer: &PeerId) {
+        self.peer_keys.remove(peer);
+        self.ready.remove(peer);
+    }
+
+    /// Whether `payload` from `source` carries its cover marker; counted
+    /// when it does
+    pub fn is_decoy(&mut self, source: &PeerId, payload: &[u8]) -> bool {
+        let Some(key) = self.peer_keys.get(source) else { return false };
+        if payload.len() < MARKER_LEN {
+            return false;
+        }
+        let decoy = hmac::verify(key, &payload[..NONCE_LEN], &payload[NONCE_LEN..MARKER_LEN]).is_ok();
+        if decoy {
+            self.received += 1;
+        }
+        decoy
+    }
+
+    /// A gossip decoy: on a cover topic, from a peer whose key we hold
+    pub fn is_decoy_gossip(&mut self, message: &gossipsub::Message) -> bool {
+        let Some(source) = message.source else { return false };
+        self.config.topics.iter().any(|t| t == message.topic.as_str()) && self.is_decoy(&source, &message.data)
+    }
+
+    /// A sealed direct message from `peer` carrying its marker. Only peers
+    /// that sent us their key pay for the extra open
+    pub fn is_decoy_message(&mut self, peer: &PeerId, identity: &ed25519_dalek::SigningKey, sealed_data: &[u8]) -> bool {
+        let max = self.config.max_size.bytes() as usize + sealed::OVERHEAD;
+        if !self.peer_keys.contains_key(peer) || sealed_data.len() > max.max(MARKER_LEN + sealed::OVERHEAD) {
+            return false;
+        }
+        match sealed::open(identity, sealed_data) {
+            Ok(plaintext) => self.is_decoy(peer, &plaintext),
+            Err(_) => false,
+        }
+    }
+
+    /// Wait before the next candidate decoy: exponential at the budget
+    /// rate. Each candidate is sent with probability rate / budget, which
+    /// thins it to a Poisson process at the adapted rate
+    pub fn next_delay(&mut self) -> Duration {
+        let per_second = self.config.max_decoys_per_minute / 60.0;
+        if per_second <= 0.0 {
+            return self.config.window.as_std();
+        }
+        let u: f64 = self.rng.gen_range(f64::EPSILON..1.0);
+        Duration::from_secs_f64(-u.ln() / per_second)
+    }
+
+    /// Adapt the rate to real traffic over the window, then maybe make a
+    /// decoy (within the bandwidth budget)
+    pub fn poll(&mut self, now: DateTime<Utc>) -> Option<Decoy> {
+        let window = self.config.window.as_chrono();
+        let since = now - window;
+        for meter in [&mut self.real_gossip, &mut self.real_direct, &mut self.sent] {
+            meter.prune(since);
+        }
+        let minutes = (window.num_milliseconds() as f64 / 60_000.0).max(1.0 / 60.0);
+        let real = (self.real_gossip.len() + self.real_direct.len()) as f64 / minutes;
+        let current = self.sent.len() as f64 / minutes;
+        let cap = self.config.max_decoys_per_minute;
+        self.rate = adapt_rate(real, current, self.config.target_min_per_minute, self.config.target_max_per_minute, cap);
+        if cap <= 0.0 || !self.rng.gen_bool((self.rate / cap).clamp(0.0, 1.0)) {
+            return None;
+        }
+
+        let direct_share = match self.real_gossip.len() + self.real_direct.len() {
+            0 => 0.5,
+            total => self.real_direct.len() as f64 / total as f64,
+        };
+        let peers: Vec<PeerId> = self.ready.iter().copied().collect();
+        let direct = self.config.direct && !peers.is_empty() && (self.config.topics.is_empty() || self.rng.gen_bool(direct_share));
+        let target = match direct {
+            true => DecoyTarget::Direct(*peers.choose(&mut self.rng)?),
+            false => DecoyTarget::Gossip(self.config.topics.choose(&mut self.rng)?.clone()),
+        };
+        let size = self.draw_size(direct);
+        let budget = self.config.max_bytes_per_minute.bytes() as f64 * minutes;
+        if (self.sent.bytes + size) as f64 > budget {
+            return None;
+        }
+        self.sent.record(now, size);
+        let payload_len = match direct {
+            true => size.saturating_sub(sealed::OVERHEAD),
+            false => size,
+        };
+        Some(Decoy { target, payload: self.marked(payload_len) })
+    }
+
+    /// A recent real size of the kind, else uniform over the configured range
+    fn draw_size(&mut self, direct: bool) -> usize {
+        let meter = if direct { &self.real_direct } else { &self.real_gossip };
+        match meter.events.len() {
+            0 => {
+                let (min, max) = (self.config.min_size.bytes() as usize, self.config.max_size.bytes() as usize);
+                self.rng.gen_range(min..=max.max(min))
+            }
+            len => meter.events[self.rng.gen_range(0..len)].1,
+        }
+    }
+
+    /// Nonce, its tag under our key, then random padding up to `len`
+    fn marked(&mut self, len: usize) -> Vec<u8> {
+        let mut payload = vec![0u8; len.max(MARKER_LEN)];
+        self.rng.fill_bytes(&mut payload);
+        let tag = hmac::sign(&self.signing, &payload[..NONCE_LEN]);
+        payload[NONCE_LEN..MARKER_LEN].copy_from_slice(tag.as_ref());
+        payload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cover(config: CoverConfig) -> CoverTraffic {
+        CoverTraffic::new(CoverConfig { enabled: true, ..config })
+    }
+
+    #[test]
+    fn test_adapt_rate() {
+        // Band 20..=40 (middle 30), budget 25
+        let adapt = |real, current| adapt_rate(real, current, 20.0, 40.0, 25.0);
+        assert_eq!(adapt(0.0, 0.0), 25.0);
+        assert_eq!(adapt(10.0, 0.0), 20.0);
+        // Inside the band the rate holds, so it doesn't chase every message
+        assert_eq!(adapt(10.0, 15.0), 15.0);
+        assert_eq!(adapt(35.0, 0.0), 0.0);
+        assert_eq!(adapt(45.0, 10.0), 0.0);
+        assert_eq!(adapt(15.0, 2.0), 15.0);
+
+        // Scripted real traffic per minute: the total stays in the band
+        // whenever the budget and real traffic allow it
+        let mut rate = 0.0;
+        for (real, total) in [(0.0, 25.0), (5.0, 30.0), (12.0, 37.0), (28.0, 30.0), (38.0, 40.0), (50.0, 50.0), (8.0, 30.0)] {
+            rate = adapt(real, rate);
+            assert_eq!(real + rate, total, "real {}", real);
+        }
+    }
+
+    #[test]
+    fn test_poisson_rate_follows_real_traffic() {
+        let mut cover = cover(CoverConfig {
+            target_min_per_minute: 50.0,
+            target_max_per_minute: 70.0,
+            max_decoys_per_minute: 120.0,
+            window: HumanDuration::from_secs(60),
+            ..Default::default()
+        });
+        let mean = (0..2000).map(|_| cover.next_delay().as_secs_f64()).sum::<f64>() / 2000.0;
+        assert!((0.4..0.6).contains(&mean), "mean wait {}", mean);
+
+        // A real message every 3 s (20 a minute) for ten minutes: decoys
+        // make up the other 30-50 a minute
+        let start = Utc::now();
+        let end = start + chrono::Duration::minutes(10);
+        let (mut now, mut next_real, mut sent) = (start, start, Vec::new());
+        while now < end {
+            while next_real <= now {
+                cover.record_real(false, 300, next_real);
+                next_real += chrono::Duration::seconds(3);
+            }
+            if cover.poll(now).is_some() {
+                sent.push(now);
+            }
+            now += chrono::Duration::from_std(cover.next_delay()).unwrap();
+        }
+        let per_minute = sent.iter().filter(|at| **at >= end - chrono::Duration::minutes(5)).count() as f64 / 5.0;
+        assert!((25.0..=55.0).contains(&per_minute), "{} decoys a minute", per_minute);
+
+        // Real traffic above the band: no decoys
+        for i in 0..100 {
+            cover.record_real(true, 300, now + chrono::Duration::milliseconds(i));
+        }
+        assert!((0..100).all(|_| cover.poll(now + chrono::Duration::seconds(1)).is_none()));
+        assert_eq!(cover.rate(), 0.0);
+    }
+
+    #[test]
+    fn test_budgets_cap_decoys() {
+        let mut capped = cover(CoverConfig {
+            max_bytes_per_minute: HumanSize::from_bytes(1000),
+            min_size: HumanSize::from_bytes(100),
+            max_size: HumanSize::from_bytes(100),
+            window: HumanDuration::from_secs(60),
+            ..Default::default()
+        });
+        let now = Utc::now();
+        let sent = (0..1000).filter_map(|_| capped.poll(now)).count();
+        assert_eq!(sent, 10);
+
+        let mut off = cover(CoverConfig { max_decoys_per_minute: 0.0, ..Default::default() });
+        assert!(off.poll(now).is_none());
+        assert_eq!(off.next_delay(), Duration::from_secs(300));
+    }
+
+    #[test]
+    fn test_decoys_recognised_only_with_the_key() {
+        let sender_id = PeerId::random();
+        let mut sender = cover(CoverConfig { target_min_per_minute: 600.0, target_max_per_minute: 600.0, ..Default::default() });
+        let mut receiver = cover(CoverConfig::default());
+        let mut outsider = cover(CoverConfig::default());
+        let decoy = std::iter::repeat_with(|| sender.poll(Utc::now())).flatten().next().unwrap();
+        assert_eq!(decoy.target, DecoyTarget::Gossip(COVER_TOPIC.to_string()));
+        assert!((64..=2048).contains(&decoy.payload.len()));
+
+        // Without the sender's key it is just bytes
+        assert!(!receiver.is_decoy(&sender_id, &decoy.payload));
+        receiver.add_peer_key(sender_id, sender.key()).unwrap();
+        outsider.add_peer_key(sender_id, &[7u8; KEY_LEN]).unwrap();
+        assert!(receiver.is_decoy(&sender_id, &decoy.payload));
+        assert!(!outsider.is_decoy(&sender_id, &decoy.payload));
+        assert!(!receiver.is_decoy(&PeerId::random(), &decoy.payload));
+
+        let mut tampered = decoy.payload.clone();
+        tampered[0] ^= 1;
+        assert!(!receiver.is_decoy(&sender_id, &tampered));
+        assert!(!receiver.is_decoy(&sender_id, &decoy.payload[..MARKER_LEN - 1]));
+        assert!(!receiver.is_decoy(&sender_id, b"a real message that happens to be long enough to hold a marker"));
+        assert_eq!(receiver.received(), 1);
+        assert!(receiver.add_peer_key(sender_id, &[1u8; 16]).is_err());
+
+        // Sealed direct decoys are checked by the recipient only
+        let identity = ed25519_dalek::SigningKey::from_bytes(&[3u8; 32]);
+        let sealed = sealed::seal(&identity.verifying_key(), &decoy.payload).unwrap();
+        assert!(receiver.is_decoy_message(&sender_id, &identity, &sealed));
+        let other = ed25519_dalek::SigningKey::from_bytes(&[4u8; 32]);
+        assert!(!receiver.is_decoy_message(&sender_id, &other, &sealed));
+    }
+
+    #[test]
+    fn test_direct_decoys_need_a_ready_peer() {
+        // No cover topic and no peer holding our key: nowhere to send
+        let mut cover = cover(CoverConfig { topics: Vec::new(), target_min_per_minute: 600.0, target_max_per_minute: 600.0, ..Default::default() });
+        assert!((0..100).all(|_| cover.poll(Utc::now()).is_none()));
+    }
+
+    #[test]
+    fn test_capability() {
+        assert!(consents("quantraband/0.3.0 cover/1"));
+        assert!(!consents("quantraband/0.3.0"));
+        assert!(!consents("quantraband/0.3.0 cover/10"));
+    }
+}
@@ -0,0 +1,95 @@
+//! Market Depth Relay
+//! Order books relayed over `market-depth/<symbol>` gossipsub topics: deltas
+//! are gossiped, snapshots are fetched with a direct request on join or gap
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+
+use crate::quant::market_data::{OrderBookDelta, OrderBookSnapshot};
+use crate::quant::order_book::{DeltaOutcome, OrderBook};
+
+pub const DEPTH_TOPIC_PREFIX: &str = "market-depth/";
+
+/// Deltas larger than this are not gossiped; peers resnapshot instead
+pub const MAX_DEPTH_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Levels requested per side when joining a depth topic
+pub const DEFAULT_SNAPSHOT_LEVELS: u32 = 50;
+
+pub fn depth_topic(symbol: &str) -> String {
+    format!("{}{}", DEPTH_TOPIC_PREFIX, symbol.to_uppercase())
+}
+
+pub fn symbol_from_topic(topic: &str) -> Option<&str> {
+    topic.strip_prefix(DEPTH_TOPIC_PREFIX)
+}
+
+/// Books for the depth topics this node follows
+#[derive(Default)]
+pub struct DepthRelay {
+    /// Symbol → book (`None` until the first snapshot arrives)
+    books: HashMap<String, Option<OrderBook>>,
+}
+
+impl DepthRelay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start following a symbol; returns false if already followed
+    pub fn follow(&mut self, symbol: &str) -> bool {
+        let symbol = symbol.to_uppercase();
+        if self.books.contains_key(&symbol) {
+            return false;
+        }
+        self.books.insert(symbol, None);
+        true
+    }
+
+    pub fn is_following(&self, symbol: &str) -> bool {
+        self.books.contains_key(&symbol.to_uppercase())
+    }
+
+    /// Install a snapshot for a followed symbol
+    pub fn on_snapshot(&mut self, snapshot: &OrderBookSnapshot) -> Result<()> {
+        let symbol = snapshot.symbol.to_uppercase();
+        let Some(slot) = self.books.get_mut(&symbol) else { return Ok(()) };
+        match slot {
+            Some(book) => book.reset(snapshot)?,
+            None => *slot = Some(OrderBook::from_snapshot(snapshot)?),
+        }
+        tracing::info!("📚 {} book snapshot at sequence {}", symbol, snapshot.sequence);
+        Ok(())
+    }
+
+    /// Apply a gossiped delta
+    pub fn on_delta(&mut self, delta: &OrderBookDelta) -> DeltaOutcome {
+        match self.books.get_mut(&delta.symbol.to_uppercase()) {
+            Some(Some(book)) => book.apply(delta),
+            _ => DeltaOutcome::AwaitingSnapshot,
+        }
+    }
+
+    /// Current book for a followed symbol
+    pub fn book(&self, symbol: &str) -> Option<&OrderBook> {
+        self.books.get(&symbol.to_uppercase()).and_then(Option::as_ref)
+    }
+}
+
+/// Encode a delta for gossip, enforcing the depth topic size limit
+pub fn encode_delta(delta: &OrderBookDelta) -> Result<Vec<u8>> {
+    let bytes = serde_json::to_vec(delta)?;
+    if bytes.len() > MAX_DEPTH_MESSAGE_SIZE {
+        anyhow::bail!(
+            "Depth delta for {} is {} bytes (max {})",
+            delta.symbol,
+            bytes.len(),
+            MAX_DEPTH_MESSAGE_SIZE
+        );
+    }
+    Ok(bytes)
+}
+
+pub fn decode_delta(data: &[u8]) -> Result<OrderBookDelta> {
+    serde_json::from_slice(data).context("Invalid depth delta")
+}
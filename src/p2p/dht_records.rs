@@ -0,0 +1,281 @@
+//! DHT Record Manager
+//! Journals the Kademlia records and provider registrations this node owns,
+//! restores them on startup and republishes them before they expire
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::migrations::{self, StoreSchema};
+use crate::storage::{KvStore, RuntimeMode};
+
+/// Kademlia record TTL (libp2p default is 36h)
+pub const RECORD_TTL_SECS: i64 = 36 * 3600;
+
+/// Provider records are re-registered this often (libp2p default is 12h)
+pub const PROVIDER_REPUBLISH_SECS: i64 = 12 * 3600;
+
+/// Records are republished after this fraction of their TTL
+const REPUBLISH_AT_TTL_FRACTION: f64 = 0.8;
+
+/// Max records republished per tick, so large record sets don't burst-flood the DHT
+pub const MAX_REPUBLISH_PER_TICK: usize = 5;
+
+/// Spacing between staggered restorations on startup
+const RESTORE_STAGGER_MILLIS: i64 = 200;
+
+/// First retry delay after a failed put
+const BACKOFF_BASE_SECS: i64 = 30;
+
+/// Max retry delay
+const BACKOFF_MAX_SECS: i64 = 3600;
+
+/// Consecutive failures before an alert is raised
+pub const FAILURE_ALERT_THRESHOLD: u32 = 5;
+
+const JOURNAL_TREE: &str = "dht_records";
+
+pub const SCHEMA: StoreSchema = StoreSchema {
+    name: "dht_journal",
+    tree: Some(JOURNAL_TREE),
+    version: 1,
+    migrations: &[],
+    index: None,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OwnedRecordKind {
+    /// A value record stored under the key
+    Record,
+    /// This node advertises itself as a provider for the key
+    Provider,
+}
+
+/// A record or provider registration owned by this node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OwnedRecord {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    pub kind: OwnedRecordKind,
+    pub created_at: DateTime<Utc>,
+    pub last_published: Option<DateTime<Utc>>,
+    pub next_publish_at: DateTime<Utc>,
+    pub consecutive_failures: u32,
+}
+
+/// Outcome of recording a failed publish
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishFailure {
+    /// Retry scheduled with backoff
+    Retrying,
+    /// Failure threshold reached; caller should raise an alert
+    Alert,
+}
+
+/// Owned records and how their publishing is going, for `status`,
+/// `stats` and `/status`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DhtRecordStats {
+    pub records: usize,
+    pub providers: usize,
+    /// Latest successful publish of any owned record
+    pub last_republish: Option<DateTime<Utc>>,
+    /// Records whose latest publish failed
+    pub failing: usize,
+    /// Failed publishes since startup
+    pub failures: u64,
+}
+
+/// Persistent journal and republish scheduler for owned DHT records
+pub struct DhtRecordManager {
+    journal: Box<dyn KvStore>,
+    records: HashMap<Vec<u8>, OwnedRecord>,
+    failures: u64,
+}
+
+impl DhtRecordManager {
+    /// Open (or create) the journal in `dir` and load owned records
+    /// Restored records are scheduled for staggered republishing
+    pub fn open(dir: &Path) -> Result<Self> {
+        Self::open_with_mode(dir, RuntimeMode::Persistent)
+    }
+
+    /// Open the journal in `dir`, or keep it in memory when ephemeral
+    pub fn open_with_mode(dir: &Path, mode: RuntimeMode) -> Result<Self> {
+        let journal = migrations::open_store(mode, dir, &SCHEMA)
+            .with_context(|| format!("Failed to open DHT journal at {}", dir.display()))?;
+
+        let now = Utc::now();
+        let mut records = HashMap::new();
+        for (i, (_, bytes)) in journal.entries()?.into_iter().enumerate() {
+            let mut record: OwnedRecord = serde_json::from_slice(&bytes)
+                .context("Corrupt DHT journal entry")?;
+            record.next_publish_at = now + Duration::milliseconds(i as i64 * RESTORE_STAGGER_MILLIS);
+            records.insert(record.key.clone(), record);
+        }
+
+        if !records.is_empty() {
+            tracing::info!("🗺️ Restored {} owned DHT record(s) from journal", records.len());
+        }
+
+        Ok(Self { journal, records, failures: 0 })
+    }
+
+    /// Track a record or provider registration, due for immediate publish
+    pub fn track(&mut self, key: Vec<u8>, value: Vec<u8>, kind: OwnedRecordKind) -> Result<()> {
+        let now = Utc::now();
+        let record = OwnedRecord {
+            key: key.clone(),
+            value,
+            kind,
+            created_at: now,
+            last_published: None,
+            next_publish_at: now,
+            consecutive_failures: 0,
+        };
+        self.persist(&record)?;
+        self.records.insert(key, record);
+        Ok(())
+    }
+
+    /// Stop owning a record
+    pub fn untrack(&mut self, key: &[u8]) -> Result<bool> {
+        self.journal.remove(key)?;
+        self.journal.flush()?;
+        Ok(self.records.remove(key).is_some())
+    }
+
+    /// Records due for (re)publishing, at most `MAX_REPUBLISH_PER_TICK`
+    /// Due records are pushed back briefly so they aren't re-issued while in flight
+    pub fn take_due(&mut self, now: DateTime<Utc>) -> Vec<OwnedRecord> {
+        let mut due: Vec<&mut OwnedRecord> = self
+            .records
+            .values_mut()
+            .filter(|r| r.next_publish_at <= now)
+            .collect();
+        due.sort_by_key(|r| r.next_publish_at);
+
+        due.into_iter()
+            .take(MAX_REPUBLISH_PER_TICK)
+            .map(|r| {
+                r.next_publish_at = now + Duration::seconds(BACKOFF_BASE_SECS);
+                r.clone()
+            })
+            .collect()
+    }
+
+    /// Record a successful publish and schedule the next one before expiry
+    pub fn mark_published(&mut self, key: &[u8]) -> Result<()> {
+        let Some(record) = self.records.get_mut(key) else { return Ok(()) };
+
+        let now = Utc::now();
+        let interval_secs = match record.kind {
+            OwnedRecordKind::Record => (RECORD_TTL_SECS as f64 * REPUBLISH_AT_TTL_FRACTION) as i64,
+            OwnedRecordKind::Provider => PROVIDER_REPUBLISH_SECS,
+        };
+        record.last_published = Some(now);
+        record.next_publish_at = now + Duration::seconds(interval_secs);
+        record.consecutive_failures = 0;
+
+        let record = record.clone();
+        self.persist(&record)
+    }
+
+    /// Record a failed publish and back off exponentially
+    pub fn mark_failed(&mut self, key: &[u8]) -> Result<PublishFailure> {
+        let Some(record) = self.records.get_mut(key) else { return Ok(PublishFailure::Retrying) };
+
+        record.consecutive_failures += 1;
+        self.failures += 1;
+        let exponent = record.consecutive_failures.saturating_sub(1).min(16);
+        let delay = (BACKOFF_BASE_SECS << exponent).min(BACKOFF_MAX_SECS);
+        record.next_publish_at = Utc::now() + Duration::seconds(delay);
+
+        let failures = record.consecutive_failures;
+        let record = record.clone();
+        self.persist(&record)?;
+
+        if failures >= FAILURE_ALERT_THRESHOLD && failures % FAILURE_ALERT_THRESHOLD == 0 {
+            Ok(PublishFailure::Alert)
+        } else {
+            Ok(PublishFailure::Retrying)
+        }
+    }
+
+    pub fn record(&self, key: &[u8]) -> Option<&OwnedRecord> {
+        self.records.get(key)
+    }
+
+    /// All owned records with their last successful publish time
+    pub fn owned_records(&self) -> Vec<OwnedRecord> {
+        let mut records: Vec<_> = self.records.values().cloned().collect();
+        records.sort_by(|a, b| a.key.cmp(&b.key));
+        records
+    }
+
+    pub fn stats(&self) -> DhtRecordStats {
+        let mut stats = DhtRecordStats { failures: self.failures, ..Default::default() };
+        for record in self.records.values() {
+            match record.kind {
+                OwnedRecordKind::Record => stats.records += 1,
+                OwnedRecordKind::Provider => stats.providers += 1,
+            }
+            stats.last_republish = stats.last_republish.max(record.last_published);
+            stats.failing += (record.consecutive_failures > 0) as usize;
+        }
+        stats
+    }
+
+    fn persist(&self, record: &OwnedRecord) -> Result<()> {
+        self.journal.insert(&record.key, &serde_json::to_vec(record)?)?;
+        self.journal.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_republish_is_rate_smoothed() {
+        let dir = TempDir::new().unwrap();
+        let mut manager = DhtRecordManager::open(dir.path()).unwrap();
+        for i in 0..12u8 {
+            manager.track(vec![i], vec![i], OwnedRecordKind::Record).unwrap();
+        }
+
+        let now = Utc::now();
+        assert_eq!(manager.take_due(now).len(), MAX_REPUBLISH_PER_TICK);
+        assert_eq!(manager.take_due(now).len(), MAX_REPUBLISH_PER_TICK);
+        assert_eq!(manager.take_due(now).len(), 2);
+        assert!(manager.take_due(now).is_empty());
+    }
+
+    #[test]
+    fn test_backoff_and_alert() {
+        let dir = TempDir::new().unwrap();
+        let mut manager = DhtRecordManager::open(dir.path()).unwrap();
+        manager.track(b"svc".to_vec(), b"v".to_vec(), OwnedRecordKind::Record).unwrap();
+
+        for _ in 1..FAILURE_ALERT_THRESHOLD {
+            assert_eq!(manager.mark_failed(b"svc").unwrap(), PublishFailure::Retrying);
+        }
+        assert_eq!(manager.mark_failed(b"svc").unwrap(), PublishFailure::Alert);
+
+        let stats = manager.stats();
+        assert_eq!((stats.records, stats.failing, stats.failures), (1, 1, FAILURE_ALERT_THRESHOLD as u64));
+        assert!(stats.last_republish.is_none());
+
+        manager.mark_published(b"svc").unwrap();
+        let record = &manager.owned_records()[0];
+        assert_eq!(record.consecutive_failures, 0);
+        assert!(record.last_published.is_some());
+        assert!(record.next_publish_at > Utc::now() + Duration::hours(24));
+        let stats = manager.stats();
+        assert_eq!((stats.failing, stats.last_republish), (0, record.last_published));
+    }
+}
@@ -0,0 +1,219 @@
+//! Dial Manager
+//! Retries outbound dials with exponential backoff until they connect or
+//! run out of attempts
+
+use anyhow::Result;
+use libp2p::swarm::ConnectionId;
+use libp2p::Multiaddr;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::faults::{self, FaultMode, FaultRegistry};
+
+/// Backoff policy for outbound dials
+#[derive(Debug, Clone)]
+pub struct DialPolicy {
+    /// Delay before the first retry; doubles per failure
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Attempts (including the first) before giving up
+    pub max_attempts: u32,
+}
+
+impl Default for DialPolicy {
+    fn default() -> Self {
+        Self {
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            max_attempts: 5,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PendingDial {
+    attempts: u32,
+    /// When to retry; `None` while an attempt is in flight
+    retry_at: Option<Instant>,
+}
+
+/// Tracks outbound dials until they connect or give up
+pub struct DialManager {
+    policy: DialPolicy,
+    pending: HashMap<Multiaddr, PendingDial>,
+    in_flight: HashMap<ConnectionId, Multiaddr>,
+    faults: Arc<FaultRegistry>,
+}
+
+impl DialManager {
+    pub fn new(policy: DialPolicy) -> Self {
+        Self {
+            policy,
+            pending: HashMap::new(),
+            in_flight: HashMap::new(),
+            faults: faults::global().clone(),
+        }
+    }
+
+    /// Consult `registry` instead of the global one for `p2p.dial`
+    pub fn set_fault_registry(&mut self, registry: Arc<FaultRegistry>) {
+        self.faults = registry;
+    }
+
+    /// Start dialing `addr` (no-op if already pending)
+    pub fn dial<F>(&mut self, addr: Multiaddr, now: Instant, dialer: F)
+    where
+        F: FnOnce(&Multiaddr) -> Result<ConnectionId>,
+    {
+        if self.pending.contains_key(&addr) {
+            return;
+        }
+        self.pending.insert(addr.clone(), PendingDial { attempts: 0, retry_at: None });
+        self.attempt(addr, now, dialer);
+    }
+
+    /// Addresses whose retry is due, to be passed back to `retry`
+    pub fn due(&self, now: Instant) -> Vec<Multiaddr> {
+        self.pending
+            .iter()
+            .filter(|(_, p)| p.retry_at.is_some_and(|at| at <= now))
+            .map(|(addr, _)| addr.clone())
+            .collect()
+    }
+
+    pub fn retry<F>(&mut self, addr: Multiaddr, now: Instant, dialer: F)
+    where
+        F: FnOnce(&Multiaddr) -> Result<ConnectionId>,
+    {
+        if self.pending.contains_key(&addr) {
+            self.attempt(addr, now, dialer);
+        }
+    }
+
+    fn attempt<F>(&mut self, addr: Multiaddr, now: Instant, dialer: F)
+    where
+        F: FnOnce(&Multiaddr) -> Result<ConnectionId>,
+    {
+        if let Some(pending) = self.pending.get_mut(&addr) {
+            pending.attempts += 1;
+            pending.retry_at = None;
+        }
+
+        let result = match faults::fault!(self.faults, "p2p.dial") {
+            Some(FaultMode::Delay { delay }) => {
+                // Postpone without counting the attempt
+                if let Some(pending) = self.pending.get_mut(&addr) {
+                    pending.attempts -= 1;
+                    pending.retry_at = Some(now + delay.as_std());
+                }
+                return;
+            }
+            Some(mode @ (FaultMode::Error | FaultMode::Drop)) => Err(mode.error("p2p.dial")),
+            _ => dialer(&addr),
+        };
+
+        match result {
+            Ok(connection_id) => {
+                tracing::info!("📞 Dialing {}", addr);
+                self.in_flight.insert(connection_id, addr);
+            }
+            Err(e) => {
+                tracing::warn!("📞 Dial to {} failed: {}", addr, e);
+                self.schedule_retry(&addr, now);
+            }
+        }
+    }
+
+    /// An in-flight dial failed
+    pub fn on_failure(&mut self, connection_id: ConnectionId, now: Instant) {
+        if let Some(addr) = self.in_flight.remove(&connection_id) {
+            self.schedule_retry(&addr, now);
+        }
+    }
+
+    /// An in-flight dial connected
+    pub fn on_connected(&mut self, connection_id: ConnectionId) {
+        if let Some(addr) = self.in_flight.remove(&connection_id) {
+            self.pending.remove(&addr);
+        }
+    }
+
+    fn schedule_retry(&mut self, addr: &Multiaddr, now: Instant) {
+        let Some(pending) = self.pending.get_mut(addr) else { return };
+        if pending.attempts >= self.policy.max_attempts {
+            tracing::warn!("📞 Giving up on {} after {} attempts", addr, pending.attempts);
+            self.pending.remove(addr);
+            return;
+        }
+        let backoff = self
+            .policy
+            .base_delay
+            .saturating_mul(1 << (pending.attempts - 1).min(16))
+            .min(self.policy.max_delay);
+        tracing::debug!("📞 Retrying {} in {:?}", addr, backoff);
+        pending.retry_at = Some(now + backoff);
+    }
+
+    /// Dials not yet connected or given up
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::faults::Injection;
+
+    #[test]
+    fn test_backoff_recovers_from_injected_dial_failures() {
+        let registry = Arc::new(FaultRegistry::new());
+        registry.arm(Injection::new("p2p.dial", FaultMode::Error).times(3)).unwrap();
+        let mut manager = DialManager::new(DialPolicy::default());
+        manager.set_fault_registry(registry.clone());
+
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/4001".parse().unwrap();
+        let mut dialed = 0;
+        let mut dialer = |_: &Multiaddr| {
+            dialed += 1;
+            Ok(ConnectionId::new_unchecked(dialed))
+        };
+
+        let start = Instant::now();
+        manager.dial(addr.clone(), start, &mut dialer);
+
+        // Three injected failures: retries back off 0.5s, 1s, 2s
+        let mut now = start;
+        for expected in [500, 1000, 2000] {
+            assert!(manager.due(now).is_empty());
+            now += Duration::from_millis(expected);
+            assert_eq!(manager.due(now), vec![addr.clone()]);
+            manager.retry(addr.clone(), now, &mut dialer);
+        }
+
+        assert_eq!(dialed, 1);
+        manager.on_connected(ConnectionId::new_unchecked(1));
+        assert_eq!(manager.pending(), 0);
+        assert_eq!(registry.report()[0].triggered, 3);
+        println!("✅ Dial backoff under injected faults test PASSED!");
+    }
+
+    #[test]
+    fn test_gives_up_after_max_attempts() {
+        let registry = Arc::new(FaultRegistry::new());
+        registry.arm(Injection::new("p2p.dial", FaultMode::Drop)).unwrap();
+        let mut manager = DialManager::new(DialPolicy {
+            max_attempts: 2,
+            ..DialPolicy::default()
+        });
+        manager.set_fault_registry(registry);
+
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/4002".parse().unwrap();
+        let now = Instant::now();
+        manager.dial(addr.clone(), now, |_| unreachable!());
+        let later = now + Duration::from_secs(1);
+        manager.retry(addr, later, |_| unreachable!());
+        assert_eq!(manager.pending(), 0);
+    }
+}
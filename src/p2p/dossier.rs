@@ -0,0 +1,446 @@
+//! Peer Dossier
+//! Everything the node knows about one peer, gathered from each subsystem
+//! for incident response; data about other peers that co-occurs is redacted
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::rate_limiter::{self, RateLimiter, RejectionCounts};
+use crate::crypto::key_provider::KeyProvider;
+use crate::security::bait_wallet::{BaitAccessEvent, BaitWalletManager};
+use crate::security::mirror_shield::{AttackEvent, AttackerProfile, MirrorShield};
+use crate::storage::RuntimeMode;
+use crate::terminal::{sanitize_for_terminal, LABEL_RENDER_LEN, MESSAGE_RENDER_LEN};
+use crate::zerotrust::audit::{AuditLogger, SecurityEvent};
+use crate::zerotrust::clock::ClockEstimate;
+use crate::zerotrust::identity::{TrustChange, TrustScore};
+use crate::zerotrust::verification::BehaviorProfile;
+use crate::zerotrust::SecureConnection;
+
+/// Placeholder for another peer's data
+pub const REDACTED: &str = "[redacted]";
+
+/// Anomaly reasons included in the behavior section
+const MAX_ANOMALY_REASONS: usize = 10;
+
+/// Zero-trust connection and identity record
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionSection {
+    /// Live secure connection, if the peer is connected
+    pub connection: Option<SecureConnection>,
+    pub trust_score: Option<TrustScore>,
+    pub trust_history: Vec<TrustChange>,
+    /// Measured clock offset, if the peer answered a time sync
+    pub clock: Option<ClockEstimate>,
+}
+
+/// Behavior profile summary
+#[derive(Debug, Clone, Serialize)]
+pub struct BehaviorSection {
+    pub total_messages: u64,
+    pub total_bytes: u64,
+    pub anomaly_score: f64,
+    pub total_anomalies: u32,
+    pub last_anomaly: Option<DateTime<Utc>>,
+    /// (time, score, reason), newest first
+    pub recent_anomalies: Vec<(DateTime<Utc>, f64, String)>,
+}
+
+/// MirrorShield attacker profiles and attack timeline
+#[derive(Debug, Clone, Serialize)]
+pub struct ShieldSection {
+    pub profiles: Vec<AttackerProfile>,
+    pub timeline: Vec<AttackEvent>,
+}
+
+/// One peer's dossier; a `None` section means the subsystem is disabled
+/// (or, offline, not persisted)
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerDossier {
+    pub peer_id: String,
+    pub generated_at: DateTime<Utc>,
+    /// Remote addresses seen for the peer
+    pub addresses: Vec<String>,
+    pub connection: Option<ConnectionSection>,
+    pub behavior: Option<BehaviorSection>,
+    pub shield: Option<ShieldSection>,
+    pub audit: Option<Vec<SecurityEvent>>,
+    pub rate_limits: Option<RejectionCounts>,
+    pub bait: Option<Vec<BaitAccessEvent>>,
+    /// Fields replaced with `[redacted]` because they belong to other peers
+    pub redacted_fields: usize,
+    /// Sections that failed to load
+    pub errors: Vec<String>,
+}
+
+impl PeerDossier {
+    pub fn new(peer_id: &str, addresses: Vec<String>) -> Self {
+        Self {
+            peer_id: peer_id.to_string(),
+            generated_at: Utc::now(),
+            addresses,
+            connection: None,
+            behavior: None,
+            shield: None,
+            audit: None,
+            rate_limits: None,
+            bait: None,
+            redacted_fields: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Dossier from on-disk state only (node stopped)
+    /// Only the audit log is persisted; other sections stay empty
+    pub async fn from_disk(peer_id: &str, audit_log_path: &Path, keys: Option<Arc<dyn KeyProvider>>) -> Result<Self> {
+        let mut dossier = Self::new(peer_id, Vec::new());
+        if audit_log_path.exists() {
+            let logger = AuditLogger::with_key_provider(audit_log_path, RuntimeMode::Persistent, keys).await?;
+            dossier.add_audit(logger.read_events().await);
+        }
+        Ok(dossier)
+    }
+
+    /// IPs extracted from the known addresses
+    pub fn ips(&self) -> Vec<IpAddr> {
+        self.addresses
+            .iter()
+            .filter_map(|a| a.parse().ok())
+            .filter_map(|a| rate_limiter::extract_ip(&a))
+            .collect()
+    }
+
+    fn ip_strings(&self) -> Vec<String> {
+        self.ips().iter().map(IpAddr::to_string).collect()
+    }
+
+    pub fn add_connection(
+        &mut self,
+        connection: Option<&SecureConnection>,
+        trust: (Option<TrustScore>, Vec<TrustChange>),
+        clock: Option<ClockEstimate>,
+    ) {
+        self.connection = Some(ConnectionSection {
+            connection: connection.cloned(),
+            trust_score: trust.0,
+            trust_history: trust.1,
+            clock,
+        });
+    }
+
+    pub fn add_behavior(&mut self, profile: Option<&BehaviorProfile>) {
+        self.behavior = profile.map(|p| BehaviorSection {
+            total_messages: p.total_messages,
+            total_bytes: p.total_bytes,
+            anomaly_score: p.anomaly_score,
+            total_anomalies: p.total_anomalies,
+            last_anomaly: p.last_anomaly,
+            recent_anomalies: p.recent_anomalies(MAX_ANOMALY_REASONS),
+        });
+    }
+
+    /// Profiles and events for the peer or its IPs; other peers behind the
+    /// same IP are redacted
+    pub async fn add_shield(&mut self, shield: &MirrorShield) {
+        let ips = self.ip_strings();
+        let mut profiles = shield.profiles_for(&self.peer_id, &ips).await;
+        let mut timeline = shield.timeline_for(&self.peer_id, &ips).await;
+
+        for peer in profiles
+            .iter_mut()
+            .filter_map(|p| p.peer_id.as_mut())
+            .chain(timeline.iter_mut().filter_map(|e| e.source_peer.as_mut()))
+        {
+            if *peer != self.peer_id {
+                *peer = REDACTED.to_string();
+                self.redacted_fields += 1;
+            }
+        }
+        self.shield = Some(ShieldSection { profiles, timeline });
+    }
+
+    /// Audit events about the peer, or mentioning it in their details
+    pub fn add_audit(&mut self, events: Result<Vec<SecurityEvent>>) {
+        let events = match events {
+            Ok(events) => events,
+            Err(e) => {
+                self.errors.push(format!("audit log: {}", e));
+                return;
+            }
+        };
+
+        let mut matching = Vec::new();
+        for mut event in events {
+            if event.peer_id == self.peer_id {
+                matching.push(event);
+                continue;
+            }
+            if !event.details.values().any(|v| v.contains(&self.peer_id)) {
+                continue;
+            }
+            // Another peer's event that mentions ours: keep only what concerns us
+            event.peer_id = REDACTED.to_string();
+            self.redacted_fields += 1;
+            for value in event.details.values_mut() {
+                if !value.contains(&self.peer_id) {
+                    *value = REDACTED.to_string();
+                    self.redacted_fields += 1;
+                }
+            }
+            matching.push(event);
+        }
+        self.audit = Some(matching);
+    }
+
+    pub fn add_rate_limits(&mut self, limiter: &RateLimiter, peer_id: &libp2p::PeerId) {
+        self.rate_limits = Some(limiter.rejections(peer_id, &self.ips()));
+    }
+
+    pub async fn add_bait(&mut self, bait: &BaitWalletManager) {
+        self.bait = Some(bait.accesses_from(&self.ip_strings()).await);
+    }
+}
+
+impl fmt::Display for PeerDossier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "🗂️  Dossier for {}", label(&self.peer_id))?;
+        writeln!(f, "Generated: {}", self.generated_at)?;
+        writeln!(f, "Addresses: {}", list_or_none(&self.addresses))?;
+
+        write!(f, "\n🔒 Zero-Trust: ")?;
+        match &self.connection {
+            None => writeln!(f, "not enabled")?,
+            Some(section) => {
+                match &section.connection {
+                    Some(conn) => writeln!(
+                        f,
+                        "connected since {} (level {:?}, {} verification failures)",
+                        conn.established_at, conn.security_level, conn.verification_failures
+                    )?,
+                    None => writeln!(f, "not connected")?,
+                }
+                match section.trust_score {
+                    Some(score) => writeln!(f, "  Trust score: {}", score)?,
+                    None => writeln!(f, "  Trust score: unassigned")?,
+                }
+                for change in &section.trust_history {
+                    writeln!(f, "    {} {} → {}", change.at, change.from, change.to)?;
+                }
+                match &section.clock {
+                    Some(clock) => writeln!(
+                        f,
+                        "  Clock offset: {:+}ms (rtt {}ms, {} samples)",
+                        clock.offset_ms, clock.round_trip_ms, clock.samples
+                    )?,
+                    None => writeln!(f, "  Clock offset: not measured")?,
+                }
+            }
+        }
+
+        write!(f, "\n📈 Behavior: ")?;
+        match &self.behavior {
+            None => writeln!(f, "no profile")?,
+            Some(b) => {
+                writeln!(
+                    f,
+                    "{} messages, {} bytes, anomaly score {:.2} ({} total)",
+                    b.total_messages, b.total_bytes, b.anomaly_score, b.total_anomalies
+                )?;
+                for (at, score, reason) in &b.recent_anomalies {
+                    writeln!(f, "    {} [{:.2}] {}", at, score, sanitize_for_terminal(reason, MESSAGE_RENDER_LEN))?;
+                }
+            }
+        }
+
+        write!(f, "\n🛡️ Mirror Shield: ")?;
+        match &self.shield {
+            None => writeln!(f, "not enabled")?,
+            Some(s) => {
+                writeln!(f, "{} profiles, {} attack events", s.profiles.len(), s.timeline.len())?;
+                for p in &s.profiles {
+                    writeln!(
+                        f,
+                        "  {} threat {:.1}, {} attacks{}",
+                        label(&p.ip),
+                        p.threat_score,
+                        p.attack_count,
+                        if p.blocked { ", BLOCKED" } else { "" }
+                    )?;
+                }
+                for e in &s.timeline {
+                    writeln!(f, "    {} {:?} from {}", e.timestamp, e.attack_type, label(&e.source_ip))?;
+                }
+            }
+        }
+
+        write!(f, "\n📋 Audit log: ")?;
+        match &self.audit {
+            None => writeln!(f, "unavailable")?,
+            Some(events) => {
+                writeln!(f, "{} events", events.len())?;
+                for e in events {
+                    writeln!(f, "    {} {} ({:?})", e.timestamp, label(&e.event_type), e.security_level)?;
+                }
+            }
+        }
+
+        write!(f, "\n🚫 Rate limiter: ")?;
+        match &self.rate_limits {
+            None => writeln!(f, "not available")?,
+            Some(r) => writeln!(f, "{} connections, {} messages rejected", r.connections, r.messages)?,
+        }
+
+        write!(f, "\n🍯 Bait wallets: ")?;
+        match &self.bait {
+            None => writeln!(f, "not deployed")?,
+            Some(events) => {
+                writeln!(f, "{} accesses", events.len())?;
+                for e in events {
+                    writeln!(
+                        f,
+                        "    {} {:?} on {} from {}",
+                        e.timestamp,
+                        e.access_type,
+                        label(&e.wallet_id),
+                        label(&e.attacker_ip)
+                    )?;
+                }
+            }
+        }
+
+        if self.redacted_fields > 0 {
+            writeln!(f, "\n({} fields belonging to other peers redacted)", self.redacted_fields)?;
+        }
+        for error in &self.errors {
+            writeln!(f, "⚠️  {}", sanitize_for_terminal(error, MESSAGE_RENDER_LEN))?;
+        }
+        Ok(())
+    }
+}
+
+fn list_or_none(items: &[String]) -> String {
+    if items.is_empty() {
+        "none known".to_string()
+    } else {
+        items.iter().map(|i| label(i)).collect::<Vec<_>>().join(", ")
+    }
+}
+
+/// Peer-supplied short field, safe to print
+fn label(s: &str) -> String {
+    sanitize_for_terminal(s, LABEL_RENDER_LEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::bait_wallet::{AccessType, WalletType};
+    use crate::zerotrust::SecurityLevel;
+    use tempfile::TempDir;
+
+    fn audit_event(peer_id: &str, details: &[(&str, &str)]) -> SecurityEvent {
+        SecurityEvent {
+            timestamp: Utc::now(),
+            event_type: "policy_denied".to_string(),
+            peer_id: peer_id.to_string(),
+            security_level: SecurityLevel::Basic,
+            details: details.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            prev_hash: String::new(),
+            trace_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dossier_sections_and_redaction() {
+        let peer = libp2p::PeerId::random();
+        let other = libp2p::PeerId::random().to_string();
+        let target = peer.to_string();
+        let mut dossier = PeerDossier::new(&target, vec!["/ip4/10.0.0.7/tcp/4001".to_string()]);
+
+        // Audit: one own event, one co-occurring event, one unrelated
+        let dir = TempDir::new().unwrap();
+        let mut logger = AuditLogger::with_path(dir.path().join("audit.log")).await.unwrap();
+        logger.log(audit_event(&target, &[])).await.unwrap();
+        logger
+            .log(audit_event(&other, &[("relayed_for", &target), ("token", "secret")]))
+            .await
+            .unwrap();
+        logger.log(audit_event(&other, &[])).await.unwrap();
+        logger.flush().await.unwrap();
+        dossier.add_audit(logger.read_events().await);
+
+        // Shield: another peer behind the same IP
+        let shield = MirrorShield::new();
+        shield.record_evidence("10.0.0.7", Some(&other), 2.0, "geo denied").await;
+
+        // Bait: access from the peer's IP
+        let bait = BaitWalletManager::new("https://example.com/callback");
+        let wallet = bait.deploy_bait(WalletType::Ethereum, "1 ETH").await.unwrap();
+        bait.handle_access(&wallet.id, "10.0.0.7", AccessType::BalanceCheck, None)
+            .await
+            .unwrap();
+        bait.handle_access(&wallet.id, "10.9.9.9", AccessType::BalanceCheck, None)
+            .await
+            .unwrap();
+
+        dossier.add_shield(&shield).await;
+        dossier.add_bait(&bait).await;
+
+        let audit = dossier.audit.as_ref().unwrap();
+        assert_eq!(audit.len(), 2);
+        assert_eq!(audit[1].peer_id, REDACTED);
+        assert_eq!(audit[1].details["token"], REDACTED);
+        assert_eq!(audit[1].details["relayed_for"], target);
+
+        let shield = dossier.shield.as_ref().unwrap();
+        assert_eq!(shield.profiles.len(), 1);
+        assert_eq!(shield.profiles[0].peer_id.as_deref(), Some(REDACTED));
+        assert_eq!(dossier.bait.as_ref().unwrap().len(), 1);
+        assert_eq!(dossier.redacted_fields, 3);
+
+        // Disabled subsystems stay absent
+        assert!(dossier.connection.is_none());
+        assert!(dossier.behavior.is_none());
+        assert!(dossier.rate_limits.is_none());
+        let json = serde_json::to_value(&dossier).unwrap();
+        assert!(json["connection"].is_null());
+        assert!(!json.to_string().contains(&other));
+        assert!(dossier.to_string().contains("Bait wallets: 1 accesses"));
+        println!("✅ Peer dossier test PASSED!");
+    }
+
+    #[tokio::test]
+    async fn test_dossier_from_disk() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("audit.log");
+        let missing = PeerDossier::from_disk("peer-a", &path, None).await.unwrap();
+        assert!(missing.audit.is_none());
+
+        let mut logger = AuditLogger::with_path(&path).await.unwrap();
+        logger.log(audit_event("peer-a", &[])).await.unwrap();
+        logger.log(audit_event("peer-b", &[])).await.unwrap();
+        logger.flush().await.unwrap();
+        drop(logger);
+
+        let dossier = PeerDossier::from_disk("peer-a", &path, None).await.unwrap();
+        assert_eq!(dossier.audit.unwrap().len(), 1);
+        assert!(dossier.shield.is_none());
+    }
+
+    #[test]
+    fn test_text_output_is_inert() {
+        let mut dossier = PeerDossier::new("peer-a", vec!["/dns4/evil\x1b]0;pwned\x07.example/tcp/1".to_string()]);
+        dossier.addresses.push("\x1b[2J\nfake line".to_string());
+        dossier.errors.push("bad\u{202e}gnp.exe\0".to_string());
+
+        let text = dossier.to_string();
+        assert!(!text.contains('\x1b') && !text.contains('\0') && !text.contains('\u{202e}'));
+        // Only the dossier's own line breaks remain
+        assert!(!text.lines().any(|l| l.starts_with("fake line")));
+    }
+}
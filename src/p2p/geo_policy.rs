@@ -0,0 +1,374 @@
+//! Geo / ASN Admission Policy
+//! Per-country and per-ASN connection caps and deny lists. New peers are
+//! admitted provisionally while their address is looked up off the event
+//! loop, and disconnected if the result denies them
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use crate::security::geo::{self, GeoInfo, GeoLocator};
+use crate::units::HumanDuration;
+
+/// `[p2p.geo_policy]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeoPolicyConfig {
+    /// Enable the admission policy
+    pub enabled: bool,
+    /// Max connected peers per country code (e.g. `US = 200`)
+    pub country_caps: HashMap<String, usize>,
+    /// Max connected peers per ASN (keys like `"AS64500"`)
+    pub asn_caps: HashMap<String, usize>,
+    /// Country codes that are always denied
+    pub deny_countries: Vec<String>,
+    /// ASNs that are always denied
+    pub deny_asns: Vec<u32>,
+    /// Admit peers when geolocation is unavailable
+    pub fail_open: bool,
+    /// Feed denials to Mirror Shield as low-weight evidence
+    pub report_to_shield: bool,
+    /// How long geolocation results are cached (legacy: `cache_ttl_secs`)
+    #[serde(alias = "cache_ttl_secs", deserialize_with = "crate::units::duration_or_secs")]
+    pub cache_ttl: HumanDuration,
+}
+
+impl Default for GeoPolicyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            country_caps: HashMap::new(),
+            asn_caps: HashMap::new(),
+            deny_countries: Vec::new(),
+            deny_asns: Vec::new(),
+            fail_open: true,
+            report_to_shield: false,
+            cache_ttl: HumanDuration::from_secs(3600),
+        }
+    }
+}
+
+/// Admission decision for a new connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdmissionDecision {
+    Allow,
+    Deny(String),
+}
+
+/// Per-country / per-ASN counters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GeoPolicyStats {
+    pub connected_by_country: HashMap<String, usize>,
+    pub connected_by_asn: HashMap<u32, usize>,
+    pub denied_by_country: HashMap<String, u64>,
+    pub denied_by_asn: HashMap<u32, u64>,
+    pub lookup_failures: u64,
+}
+
+fn parse_asn_caps(config: &GeoPolicyConfig) -> HashMap<u32, usize> {
+    config
+        .asn_caps
+        .iter()
+        .filter_map(|(asn, cap)| match geo::parse_asn(asn) {
+            Some(asn) => Some((asn, *cap)),
+            None => {
+                tracing::warn!("🌍 Ignoring invalid ASN in geo policy: {}", asn);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Geo / ASN admission control
+pub struct GeoAdmission {
+    config: GeoPolicyConfig,
+    locator: Arc<dyn GeoLocator>,
+    asn_caps: HashMap<u32, usize>,
+    admitted: HashMap<PeerId, GeoInfo>,
+    // Provisionally admitted, lookup in flight
+    pending: HashSet<PeerId>,
+    stats: GeoPolicyStats,
+}
+
+impl GeoAdmission {
+    pub fn new(config: GeoPolicyConfig, locator: Arc<dyn GeoLocator>) -> Self {
+        Self {
+            asn_caps: parse_asn_caps(&config),
+            config,
+            locator,
+            admitted: HashMap::new(),
+            pending: HashSet::new(),
+            stats: GeoPolicyStats::default(),
+        }
+    }
+
+    /// Replace caps and deny lists (config reload). Connected peers are
+    /// kept; the new caps apply to the next admissions
+    pub fn set_config(&mut self, config: GeoPolicyConfig) {
+        self.asn_caps = parse_asn_caps(&config);
+        self.config = config;
+    }
+
+    /// Whether denials should be reported to Mirror Shield
+    pub fn reports_to_shield(&self) -> bool {
+        self.config.report_to_shield
+    }
+
+    /// Locator for lookups made outside the policy (see `begin`)
+    pub fn locator(&self) -> Arc<dyn GeoLocator> {
+        self.locator.clone()
+    }
+
+    /// Start admitting a peer connecting from `ip`. Returns true if its
+    /// address must be looked up before the decision; the peer is admitted
+    /// provisionally until `complete` is called with the result
+    pub fn begin(&mut self, peer_id: PeerId, ip: IpAddr) -> bool {
+        // Additional connections from an admitted peer are already counted
+        if self.admitted.contains_key(&peer_id) || geo::is_private_ip(&ip) {
+            return false;
+        }
+        self.pending.insert(peer_id)
+    }
+
+    /// Decide on a provisionally admitted peer once its lookup returns;
+    /// `None` if the peer left in the meantime
+    pub fn complete(&mut self, peer_id: PeerId, ip: IpAddr, lookup: anyhow::Result<GeoInfo>) -> Option<AdmissionDecision> {
+        if !self.pending.remove(&peer_id) {
+            return None;
+        }
+        Some(self.decide(peer_id, ip, lookup))
+    }
+
+    /// Look up and decide in one step
+    pub async fn admit(&mut self, peer_id: PeerId, ip: IpAddr) -> AdmissionDecision {
+        if !self.begin(peer_id, ip) {
+            return AdmissionDecision::Allow;
+        }
+        let lookup = self.locator.locate(ip).await;
+        self.complete(peer_id, ip, lookup).unwrap_or(AdmissionDecision::Allow)
+    }
+
+    fn decide(&mut self, peer_id: PeerId, ip: IpAddr, lookup: anyhow::Result<GeoInfo>) -> AdmissionDecision {
+        let info = match lookup {
+            Ok(info) => info,
+            Err(e) => {
+                self.stats.lookup_failures += 1;
+                return if self.config.fail_open {
+                    tracing::debug!("🌍 Geolocation unavailable for {} ({}), failing open", ip, e);
+                    AdmissionDecision::Allow
+                } else {
+                    AdmissionDecision::Deny(format!("geolocation unavailable: {}", e))
+                };
+            }
+        };
+
+        if let Some(reason) = self.check(&info) {
+            *self.stats.denied_by_country.entry(info.country_code.clone()).or_insert(0) += 1;
+            if let Some(asn) = info.asn {
+                *self.stats.denied_by_asn.entry(asn).or_insert(0) += 1;
+            }
+            return AdmissionDecision::Deny(reason);
+        }
+
+        *self.stats.connected_by_country.entry(info.country_code.clone()).or_insert(0) += 1;
+        if let Some(asn) = info.asn {
+            *self.stats.connected_by_asn.entry(asn).or_insert(0) += 1;
+        }
+        self.admitted.insert(peer_id, info);
+
+        AdmissionDecision::Allow
+    }
+
+    /// Release a peer's slot once all its connections are closed
+    pub fn release(&mut self, peer_id: &PeerId) {
+        self.pending.remove(peer_id);
+        if let Some(info) = self.admitted.remove(peer_id) {
+            decrement(&mut self.stats.connected_by_country, &info.country_code);
+            if let Some(asn) = info.asn {
+                decrement(&mut self.stats.connected_by_asn, &asn);
+            }
+        }
+    }
+
+    /// Current counters
+    pub fn stats(&self) -> GeoPolicyStats {
+        self.stats.clone()
+    }
+
+    fn check(&self, info: &GeoInfo) -> Option<String> {
+        if self
+            .config
+            .deny_countries
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(&info.country_code))
+        {
+            return Some(format!("country {} is denied", info.country_code));
+        }
+
+        if let Some(asn) = info.asn {
+            if self.config.deny_asns.contains(&asn) {
+                return Some(format!("AS{} is denied", asn));
+            }
+
+            if let Some(cap) = self.asn_caps.get(&asn) {
+                let current = self.stats.connected_by_asn.get(&asn).copied().unwrap_or(0);
+                if current >= *cap {
+                    return Some(format!("AS{} cap reached ({}/{})", asn, current, cap));
+                }
+            }
+        }
+
+        let country_cap = self
+            .config
+            .country_caps
+            .iter()
+            .find(|(c, _)| c.eq_ignore_ascii_case(&info.country_code))
+            .map(|(_, cap)| *cap);
+        if let Some(cap) = country_cap {
+            let current = self
+                .stats
+                .connected_by_country
+                .get(&info.country_code)
+                .copied()
+                .unwrap_or(0);
+            if current >= cap {
+                return Some(format!("country {} cap reached ({}/{})", info.country_code, current, cap));
+            }
+        }
+
+        None
+    }
+}
+
+fn decrement<K: std::hash::Hash + Eq>(counts: &mut HashMap<K, usize>, key: &K) {
+    if let Some(count) = counts.get_mut(key) {
+        *count = count.saturating_sub(1);
+        if *count == 0 {
+            counts.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    /// Fake locator returning scripted countries / ASNs
+    struct ScriptedLocator {
+        entries: HashMap<IpAddr, GeoInfo>,
+    }
+
+    #[async_trait]
+    impl GeoLocator for ScriptedLocator {
+        async fn locate(&self, ip: IpAddr) -> anyhow::Result<GeoInfo> {
+            self.entries
+                .get(&ip)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("no geolocation data"))
+        }
+    }
+
+    fn locator() -> Arc<dyn GeoLocator> {
+        let info = |cc: &str, asn: u32| GeoInfo {
+            country_code: cc.to_string(),
+            asn: Some(asn),
+            org: String::new(),
+        };
+        let mut entries = HashMap::new();
+        entries.insert("203.0.113.1".parse().unwrap(), info("DE", 64500));
+        entries.insert("203.0.113.2".parse().unwrap(), info("DE", 64500));
+        entries.insert("203.0.113.3".parse().unwrap(), info("DE", 64501));
+        entries.insert("198.51.100.1".parse().unwrap(), info("KP", 64502));
+        Arc::new(ScriptedLocator { entries })
+    }
+
+    #[tokio::test]
+    async fn test_asn_cap_enforced_and_released() {
+        let mut config = GeoPolicyConfig::default();
+        config.asn_caps.insert("AS64500".to_string(), 1);
+        let mut admission = GeoAdmission::new(config, locator());
+
+        let first = PeerId::random();
+        assert_eq!(admission.admit(first, "203.0.113.1".parse().unwrap()).await, AdmissionDecision::Allow);
+        assert!(matches!(
+            admission.admit(PeerId::random(), "203.0.113.2".parse().unwrap()).await,
+            AdmissionDecision::Deny(_)
+        ));
+        // Different ASN in the same country is unaffected
+        assert_eq!(
+            admission.admit(PeerId::random(), "203.0.113.3".parse().unwrap()).await,
+            AdmissionDecision::Allow
+        );
+
+        admission.release(&first);
+        assert_eq!(
+            admission.admit(PeerId::random(), "203.0.113.2".parse().unwrap()).await,
+            AdmissionDecision::Allow
+        );
+        assert_eq!(admission.stats().denied_by_asn.get(&64500), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn test_deny_list_and_private_bypass() {
+        let config = GeoPolicyConfig {
+            deny_countries: vec!["kp".to_string()],
+            ..Default::default()
+        };
+        let mut admission = GeoAdmission::new(config, locator());
+
+        assert!(matches!(
+            admission.admit(PeerId::random(), "198.51.100.1".parse().unwrap()).await,
+            AdmissionDecision::Deny(_)
+        ));
+        assert_eq!(
+            admission.admit(PeerId::random(), "10.0.0.5".parse().unwrap()).await,
+            AdmissionDecision::Allow
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fail_open_and_fail_closed() {
+        let unknown: IpAddr = "192.0.2.99".parse().unwrap();
+
+        let mut open = GeoAdmission::new(GeoPolicyConfig::default(), locator());
+        assert_eq!(open.admit(PeerId::random(), unknown).await, AdmissionDecision::Allow);
+        assert_eq!(open.stats().lookup_failures, 1);
+
+        let closed_config = GeoPolicyConfig {
+            fail_open: false,
+            ..Default::default()
+        };
+        let mut closed = GeoAdmission::new(closed_config, locator());
+        assert!(matches!(closed.admit(PeerId::random(), unknown).await, AdmissionDecision::Deny(_)));
+    }
+
+    #[tokio::test]
+    async fn test_provisional_admission_decided_by_lookup() {
+        let config = GeoPolicyConfig {
+            deny_countries: vec!["KP".to_string()],
+            ..Default::default()
+        };
+        let mut admission = GeoAdmission::new(config, locator());
+        let denied: IpAddr = "198.51.100.1".parse().unwrap();
+
+        let peer = PeerId::random();
+        assert!(admission.begin(peer, denied));
+        // A second connection while the lookup is in flight doesn't start another
+        assert!(!admission.begin(peer, denied));
+        let lookup = admission.locator().locate(denied).await;
+        assert!(matches!(admission.complete(peer, denied, lookup), Some(AdmissionDecision::Deny(_))));
+
+        // Gone before the lookup returned: nothing to decide, nothing counted
+        let gone = PeerId::random();
+        let allowed: IpAddr = "203.0.113.1".parse().unwrap();
+        assert!(admission.begin(gone, allowed));
+        admission.release(&gone);
+        let lookup = admission.locator().locate(allowed).await;
+        assert_eq!(admission.complete(gone, allowed, lookup), None);
+        assert!(admission.stats().connected_by_country.is_empty());
+        assert_eq!(admission.stats().denied_by_country.get("KP"), Some(&1));
+    }
+}
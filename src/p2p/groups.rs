@@ -0,0 +1,678 @@
+//! Encrypted Groups
+//! Owner-managed rosters for end-to-end encrypted group topics. Every
+//! membership change starts a new epoch whose key is sealed to each current
+//! member only, so removed members can't read anything sent after removal
+
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::{Context, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::crypto::sealed;
+use crate::p2p::wire;
+
+/// Domain separators so roster signatures, key commitments and message
+/// bodies can't be passed off as one another. Rosters are signed as
+/// canonical CBOR (v2); v1 (JSON) signatures are still verified
+const ROSTER_SIGNING_CONTEXT: &[u8] = b"quantra-group-roster-v2\0";
+const LEGACY_ROSTER_SIGNING_CONTEXT: &[u8] = b"quantra-group-roster-v1\0";
+const KEY_COMMITMENT_CONTEXT: &[u8] = b"quantra-group-key-v1\0";
+const MESSAGE_CONTEXT: &[u8] = b"quantra-group-message-v1\0";
+
+pub const GROUP_TOPIC_PREFIX: &str = "group/";
+
+/// Messages held per group while their epoch key is requested
+const MAX_HELD_MESSAGES: usize = 64;
+
+/// A roster entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupMember {
+    pub peer_id: String,
+    /// Hex Ed25519 key; must be the key the peer ID embeds
+    pub public_key: String,
+}
+
+/// Membership for one epoch, signed by the owner
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Roster {
+    /// Derived from the owner key and name, so nobody else can claim it
+    pub group_id: String,
+    pub name: String,
+    /// Hex Ed25519 key of the owner
+    pub owner: String,
+    pub epoch: u64,
+    pub members: Vec<GroupMember>,
+    /// Hex SHA-256 commitment to this epoch's key
+    pub key_commitment: String,
+    /// Hex Ed25519 signature over everything above
+    #[serde(default)]
+    pub signature: String,
+}
+
+/// A roster sent to one member, with the epoch key sealed to that member.
+/// Members removed in this epoch get the roster without a key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupUpdate {
+    pub roster: Roster,
+    pub sealed_key: Option<Vec<u8>>,
+}
+
+/// Gossiped on `group/<group_id>`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupMessage {
+    pub group_id: String,
+    /// Key epoch of the body; receivers without it ask the owner
+    pub epoch: u64,
+    pub sender: String,
+    pub nonce: [u8; 12],
+    pub ciphertext: Vec<u8>,
+}
+
+/// A group message that decrypted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decrypted {
+    pub group_id: String,
+    pub epoch: u64,
+    pub sender: String,
+    pub plaintext: Vec<u8>,
+}
+
+/// Result of opening a group message
+#[derive(Debug)]
+pub enum Opened {
+    Plaintext(Decrypted),
+    /// Held until the key for `epoch` arrives; request it from `owner`
+    NeedKey { group_id: String, owner: PeerId, epoch: u64 },
+}
+
+/// Why a roster, key or message was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupRejection {
+    BadSignature,
+    /// Signed by someone other than the group's owner
+    NotOwner,
+    /// Not newer than the roster we already have
+    Stale { current: u64, received: u64 },
+    /// The sealed key doesn't match the roster's commitment
+    KeyMismatch,
+    /// We (or the requester) are not in the roster
+    NotAMember,
+    UnknownGroup,
+}
+
+impl fmt::Display for GroupRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadSignature => write!(f, "bad roster signature"),
+            Self::NotOwner => write!(f, "roster not signed by the group owner"),
+            Self::Stale { current, received } => write!(f, "stale roster (epoch {} <= {})", received, current),
+            Self::KeyMismatch => write!(f, "epoch key does not match the roster commitment"),
+            Self::NotAMember => write!(f, "not a member of the group"),
+            Self::UnknownGroup => write!(f, "unknown group"),
+        }
+    }
+}
+
+impl std::error::Error for GroupRejection {}
+
+/// Ed25519 key embedded in a peer ID (identity-hashed keys only)
+pub fn peer_verifying_key(peer: &PeerId) -> Result<VerifyingKey> {
+    // Identity multihash: code 0x00, length, protobuf-encoded public key
+    let bytes = peer.to_bytes();
+    if bytes.len() < 2 || bytes[0] != 0x00 {
+        anyhow::bail!("Peer ID {} does not embed its public key", peer);
+    }
+    let public = libp2p::identity::PublicKey::try_decode_protobuf(&bytes[2..])
+        .context("Malformed public key in peer ID")?;
+    let ed25519 = public.try_into_ed25519().context("Peer ID is not an Ed25519 key")?;
+    VerifyingKey::from_bytes(&ed25519.to_bytes()).context("Invalid Ed25519 key in peer ID")
+}
+
+/// Peer ID for an Ed25519 key
+pub fn peer_id_for(key: &VerifyingKey) -> Result<PeerId> {
+    let public = libp2p::identity::ed25519::PublicKey::try_from_bytes(key.as_bytes())
+        .context("Invalid Ed25519 key")?;
+    Ok(PeerId::from_public_key(&public.into()))
+}
+
+fn parse_key(hex_key: &str) -> Result<VerifyingKey> {
+    let bytes: [u8; 32] = hex::decode(hex_key)
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .context("Malformed Ed25519 key")?;
+    VerifyingKey::from_bytes(&bytes).context("Invalid Ed25519 key")
+}
+
+pub fn group_id(owner: &VerifyingKey, name: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update(ROSTER_SIGNING_CONTEXT)
+        .chain_update(owner.as_bytes())
+        .chain_update(name.as_bytes())
+        .finalize();
+    hex::encode(&digest[..16])
+}
+
+pub fn group_topic(group_id: &str) -> String {
+    format!("{}{}", GROUP_TOPIC_PREFIX, group_id)
+}
+
+fn key_commitment(group_id: &str, epoch: u64, key: &[u8; 32]) -> String {
+    let digest = Sha256::new()
+        .chain_update(KEY_COMMITMENT_CONTEXT)
+        .chain_update(group_id.as_bytes())
+        .chain_update(epoch.to_be_bytes())
+        .chain_update(key)
+        .finalize();
+    hex::encode(digest)
+}
+
+impl Roster {
+    fn signed_fields(&self) -> impl Serialize + '_ {
+        (&self.group_id, &self.name, &self.owner, self.epoch, &self.members, &self.key_commitment)
+    }
+
+    fn signing_bytes(&self) -> Result<Vec<u8>> {
+        wire::signing_bytes(ROSTER_SIGNING_CONTEXT, &self.signed_fields())
+    }
+
+    fn legacy_signing_bytes(&self) -> Result<Vec<u8>> {
+        let body = serde_json::to_vec(&self.signed_fields())?;
+        Ok([LEGACY_ROSTER_SIGNING_CONTEXT, body.as_slice()].concat())
+    }
+
+    fn sign(mut self, key: &SigningKey) -> Result<Self> {
+        self.signature = hex::encode(key.sign(&self.signing_bytes()?).to_bytes());
+        Ok(self)
+    }
+
+    /// Signed by `owner`, the group ID belongs to that owner and every
+    /// member's key matches its peer ID
+    pub fn verify(&self) -> Result<()> {
+        let owner = parse_key(&self.owner)?;
+        let signature: [u8; 64] = hex::decode(&self.signature)
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or(GroupRejection::BadSignature)?;
+        wire::verify_signature(&owner, &Signature::from_bytes(&signature), &self.signing_bytes()?, || self.legacy_signing_bytes())
+            .map_err(|_| GroupRejection::BadSignature)?;
+        if self.group_id != group_id(&owner, &self.name) {
+            return Err(GroupRejection::NotOwner.into());
+        }
+        for member in &self.members {
+            let peer: PeerId = member.peer_id.parse().context("Malformed member peer ID")?;
+            if peer_verifying_key(&peer)? != parse_key(&member.public_key)? {
+                anyhow::bail!("Member {} key does not match its peer ID", member.peer_id);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_member(&self, peer_id: &str) -> bool {
+        self.members.iter().any(|m| m.peer_id == peer_id)
+    }
+
+    fn owner_peer(&self) -> Result<PeerId> {
+        peer_id_for(&parse_key(&self.owner)?)
+    }
+}
+
+struct Group {
+    roster: Roster,
+    keys: BTreeMap<u64, [u8; 32]>,
+    /// Owner only: every epoch's roster, to answer key requests
+    history: BTreeMap<u64, Roster>,
+    removed: bool,
+    held: Vec<GroupMessage>,
+}
+
+/// `group info` output
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupInfo {
+    pub group_id: String,
+    pub name: String,
+    pub owner: String,
+    pub epoch: u64,
+    pub members: Vec<String>,
+    pub is_owner: bool,
+    pub removed: bool,
+}
+
+impl fmt::Display for GroupInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "👥 {} ({}) epoch {}", self.name, self.group_id, self.epoch)?;
+        if self.removed {
+            writeln!(f, "   ⛔ This node was removed from the group")?;
+        }
+        for member in &self.members {
+            writeln!(f, "   - {}", member)?;
+        }
+        Ok(())
+    }
+}
+
+/// This node's groups: rosters, epoch keys and messages waiting for keys
+pub struct GroupManager {
+    signing_key: SigningKey,
+    peer_id: String,
+    groups: HashMap<String, Group>,
+    /// Group used when a command doesn't name one (last created or joined)
+    active: Option<String>,
+}
+
+impl GroupManager {
+    pub fn new(signing_key: SigningKey) -> Result<Self> {
+        let peer_id = peer_id_for(&signing_key.verifying_key())?.to_string();
+        Ok(Self { signing_key, peer_id, groups: HashMap::new(), active: None })
+    }
+
+    fn local_member(&self) -> GroupMember {
+        GroupMember {
+            peer_id: self.peer_id.clone(),
+            public_key: hex::encode(self.signing_key.verifying_key().as_bytes()),
+        }
+    }
+
+    /// Group ID for `name_or_id`, or the active group when `None`
+    pub fn resolve(&self, name_or_id: Option<&str>) -> Result<String> {
+        let found = match name_or_id {
+            Some(wanted) => self
+                .groups
+                .iter()
+                .find(|(id, g)| id.as_str() == wanted || g.roster.name == wanted)
+                .map(|(id, _)| id.clone()),
+            None => self.active.clone(),
+        };
+        found.ok_or_else(|| anyhow::anyhow!("No such group (create one with 'group create <name>')"))
+    }
+
+    /// Start a group owned by this node, with itself as the only member
+    pub fn create(&mut self, name: &str) -> Result<Roster> {
+        let id = group_id(&self.signing_key.verifying_key(), name);
+        if self.groups.contains_key(&id) {
+            anyhow::bail!("Group '{}' already exists", name);
+        }
+        let roster = Roster {
+            group_id: id.clone(),
+            name: name.to_string(),
+            owner: hex::encode(self.signing_key.verifying_key().as_bytes()),
+            epoch: 0,
+            members: vec![self.local_member()],
+            key_commitment: String::new(),
+            signature: String::new(),
+        };
+        self.groups.insert(
+            id.clone(),
+            Group { roster, keys: BTreeMap::new(), history: BTreeMap::new(), removed: false, held: Vec::new() },
+        );
+        let members = vec![self.local_member()];
+        self.rotate(&id, members)?;
+        self.active = Some(id.clone());
+        Ok(self.groups[&id].roster.clone())
+    }
+
+    fn owned_group(&self, group_id: &str) -> Result<&Group> {
+        let group = self.groups.get(group_id).ok_or(GroupRejection::UnknownGroup)?;
+        if group.roster.owner != hex::encode(self.signing_key.verifying_key().as_bytes()) {
+            anyhow::bail!("Only the owner can change group '{}'", group.roster.name);
+        }
+        Ok(group)
+    }
+
+    /// Add `peer` and rotate; returns the updates to send, one per member
+    pub fn invite(&mut self, group_id: &str, peer: &PeerId) -> Result<Vec<(PeerId, GroupUpdate)>> {
+        let group = self.owned_group(group_id)?;
+        if group.roster.is_member(&peer.to_string()) {
+            anyhow::bail!("{} is already a member", peer);
+        }
+        let mut members = group.roster.members.clone();
+        members.push(GroupMember {
+            peer_id: peer.to_string(),
+            public_key: hex::encode(peer_verifying_key(peer)?.as_bytes()),
+        });
+        self.rotate(group_id, members)
+    }
+
+    /// Drop `peer` and rotate; the new key goes to the remaining members
+    /// only, and `peer` just learns it was removed
+    pub fn remove(&mut self, group_id: &str, peer: &PeerId) -> Result<Vec<(PeerId, GroupUpdate)>> {
+        let group = self.owned_group(group_id)?;
+        let peer_str = peer.to_string();
+        if peer_str == self.peer_id {
+            anyhow::bail!("The owner can't be removed");
+        }
+        if !group.roster.is_member(&peer_str) {
+            anyhow::bail!("{} is not a member", peer);
+        }
+        let members = group.roster.members.iter().filter(|m| m.peer_id != peer_str).cloned().collect();
+        let mut updates = self.rotate(group_id, members)?;
+        let roster = self.groups[group_id].roster.clone();
+        updates.push((*peer, GroupUpdate { roster, sealed_key: None }));
+        Ok(updates)
+    }
+
+    /// New epoch with a fresh key for `members`
+    fn rotate(&mut self, group_id: &str, members: Vec<GroupMember>) -> Result<Vec<(PeerId, GroupUpdate)>> {
+        let group = self.groups.get_mut(group_id).ok_or(GroupRejection::UnknownGroup)?;
+        let key: [u8; 32] = rand::random();
+        let epoch = group.roster.epoch + 1;
+        let roster = Roster {
+            epoch,
+            members,
+            key_commitment: key_commitment(group_id, epoch, &key),
+            signature: String::new(),
+            ..group.roster.clone()
+        }
+        .sign(&self.signing_key)?;
+
+        let mut updates = Vec::new();
+        for member in roster.members.iter().filter(|m| m.peer_id != self.peer_id) {
+            let peer: PeerId = member.peer_id.parse()?;
+            let sealed_key = sealed::seal(&parse_key(&member.public_key)?, &key)?;
+            updates.push((peer, GroupUpdate { roster: roster.clone(), sealed_key: Some(sealed_key) }));
+        }
+        group.keys.insert(epoch, key);
+        group.history.insert(epoch, roster.clone());
+        group.roster = roster;
+        tracing::info!("👥 Group {} rotated to epoch {} ({} members)", group.roster.name, epoch, group.roster.members.len());
+        Ok(updates)
+    }
+
+    /// Accept a roster (and our sealed key) from the owner. Returns held
+    /// messages that can now be read.
+    pub fn on_update(&mut self, update: GroupUpdate) -> Result<Vec<Decrypted>> {
+        let roster = update.roster;
+        roster.verify()?;
+        let group_id = roster.group_id.clone();
+        let current = self.groups.get(&group_id).map(|g| g.roster.epoch);
+        if roster.owner == hex::encode(self.signing_key.verifying_key().as_bytes()) {
+            // Our own group: we are the only source of its rosters
+            return Err(GroupRejection::Stale { current: current.unwrap_or(0), received: roster.epoch }.into());
+        }
+
+        if !roster.is_member(&self.peer_id) {
+            // Removal notice: keep the keys we had, stop receiving new ones
+            let group = self.groups.get_mut(&group_id).ok_or(GroupRejection::NotAMember)?;
+            if roster.epoch <= group.roster.epoch {
+                return Err(GroupRejection::Stale { current: group.roster.epoch, received: roster.epoch }.into());
+            }
+            tracing::warn!("👥 Removed from group {} at epoch {}", roster.name, roster.epoch);
+            group.roster = roster;
+            group.removed = true;
+            group.held.clear();
+            return Ok(Vec::new());
+        }
+
+        let sealed_key = update.sealed_key.ok_or(GroupRejection::KeyMismatch)?;
+        let key: [u8; 32] = sealed::open(&self.signing_key, &sealed_key)?
+            .try_into()
+            .map_err(|_| GroupRejection::KeyMismatch)?;
+        if key_commitment(&group_id, roster.epoch, &key) != roster.key_commitment {
+            return Err(GroupRejection::KeyMismatch.into());
+        }
+
+        let group = self.groups.entry(group_id.clone()).or_insert_with(|| Group {
+            roster: roster.clone(),
+            keys: BTreeMap::new(),
+            history: BTreeMap::new(),
+            removed: false,
+            held: Vec::new(),
+        });
+        group.keys.insert(roster.epoch, key);
+        // Older epochs (answers to key requests) only add their key
+        if roster.epoch >= group.roster.epoch {
+            if current.is_none() || roster.epoch > group.roster.epoch {
+                tracing::info!("👥 Group {} epoch {} ({} members)", roster.name, roster.epoch, roster.members.len());
+            }
+            group.roster = roster;
+            group.removed = false;
+        }
+        if current.is_none() {
+            self.active = Some(group_id.clone());
+        }
+
+        let group = self.groups.get_mut(&group_id).expect("inserted above");
+        let (ready, waiting): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut group.held).into_iter().partition(|m| group.keys.contains_key(&m.epoch));
+        group.held = waiting;
+        Ok(ready.iter().filter_map(|m| decrypt(group, m).ok()).collect())
+    }
+
+    /// Owner: the key for `epoch`, sealed to `requester` if it was a member then
+    pub fn key_for(&self, group_id: &str, epoch: u64, requester: &PeerId) -> Result<GroupUpdate> {
+        let group = self.owned_group(group_id)?;
+        let (Some(roster), Some(key)) = (group.history.get(&epoch), group.keys.get(&epoch)) else {
+            return Err(GroupRejection::UnknownGroup.into());
+        };
+        if !roster.is_member(&requester.to_string()) {
+            return Err(GroupRejection::NotAMember.into());
+        }
+        let sealed_key = sealed::seal(&peer_verifying_key(requester)?, key)?;
+        Ok(GroupUpdate { roster: roster.clone(), sealed_key: Some(sealed_key) })
+    }
+
+    /// Encrypt under the group's current epoch key
+    pub fn encrypt(&self, group_id: &str, plaintext: &[u8]) -> Result<GroupMessage> {
+        let group = self.groups.get(group_id).ok_or(GroupRejection::UnknownGroup)?;
+        if group.removed {
+            return Err(GroupRejection::NotAMember.into());
+        }
+        let epoch = group.roster.epoch;
+        let key = group.keys.get(&epoch).context("No key for the current epoch")?;
+        let nonce: [u8; 12] = rand::random();
+        let aad = message_aad(group_id, epoch, &self.peer_id);
+        let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: &aad })
+            .map_err(|e| anyhow::anyhow!("Group encryption failed: {:?}", e))?;
+        Ok(GroupMessage { group_id: group_id.to_string(), epoch, sender: self.peer_id.clone(), nonce, ciphertext })
+    }
+
+    /// Decrypt, or hold the message and say which key to request. Removed
+    /// members get `NotAMember` for epochs they never had.
+    pub fn open(&mut self, message: GroupMessage) -> Result<Opened> {
+        let group = self.groups.get_mut(&message.group_id).ok_or(GroupRejection::UnknownGroup)?;
+        if group.keys.contains_key(&message.epoch) {
+            return decrypt(group, &message).map(Opened::Plaintext);
+        }
+        if group.removed {
+            return Err(GroupRejection::NotAMember.into());
+        }
+        let owner = group.roster.owner_peer()?;
+        let (group_id, epoch) = (message.group_id.clone(), message.epoch);
+        if group.held.len() >= MAX_HELD_MESSAGES {
+            group.held.remove(0);
+        }
+        group.held.push(message);
+        Ok(Opened::NeedKey { group_id, owner, epoch })
+    }
+
+    pub fn roster(&self, group_id: &str) -> Option<&Roster> {
+        self.groups.get(group_id).map(|g| &g.roster)
+    }
+
+    pub fn info(&self, group_id: &str) -> Option<GroupInfo> {
+        let group = self.groups.get(group_id)?;
+        Some(GroupInfo {
+            group_id: group_id.to_string(),
+            name: group.roster.name.clone(),
+            owner: group.roster.owner_peer().map(|p| p.to_string()).unwrap_or_default(),
+            epoch: group.roster.epoch,
+            members: group.roster.members.iter().map(|m| m.peer_id.clone()).collect(),
+            is_owner: group.roster.owner == hex::encode(self.signing_key.verifying_key().as_bytes()),
+            removed: group.removed,
+        })
+    }
+}
+
+fn message_aad(group_id: &str, epoch: u64, sender: &str) -> Vec<u8> {
+    [MESSAGE_CONTEXT, group_id.as_bytes(), &epoch.to_be_bytes(), sender.as_bytes()].concat()
+}
+
+fn decrypt(group: &Group, message: &GroupMessage) -> Result<Decrypted> {
+    let key = group.keys.get(&message.epoch).context("No key for epoch")?;
+    let aad = message_aad(&message.group_id, message.epoch, &message.sender);
+    let plaintext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+        .decrypt(Nonce::from_slice(&message.nonce), Payload { msg: &message.ciphertext, aad: &aad })
+        .map_err(|_| anyhow::anyhow!("Group message failed to decrypt"))?;
+    Ok(Decrypted {
+        group_id: message.group_id.clone(),
+        epoch: message.epoch,
+        sender: message.sender.clone(),
+        plaintext,
+    })
+}
+
+pub fn encode_message(message: &GroupMessage) -> Result<Vec<u8>> {
+    serde_json::to_vec(message).context("Failed to encode group message")
+}
+
+pub fn decode_message(data: &[u8]) -> Result<GroupMessage> {
+    serde_json::from_slice(data).context("Malformed group message")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager(seed: u8) -> (GroupManager, PeerId) {
+        let manager = GroupManager::new(SigningKey::from_bytes(&[seed; 32])).unwrap();
+        let peer = manager.peer_id.parse().unwrap();
+        (manager, peer)
+    }
+
+    fn deliver(updates: Vec<(PeerId, GroupUpdate)>, to: &mut GroupManager, peer: &PeerId) {
+        for (recipient, update) in updates {
+            if recipient == *peer {
+                to.on_update(update).unwrap();
+            }
+        }
+    }
+
+    fn read(manager: &mut GroupManager, message: &GroupMessage) -> Vec<u8> {
+        match manager.open(message.clone()).unwrap() {
+            Opened::Plaintext(d) => d.plaintext,
+            Opened::NeedKey { epoch, .. } => panic!("missing key for epoch {}", epoch),
+        }
+    }
+
+    #[test]
+    fn test_peer_id_key_round_trip() {
+        let key = SigningKey::from_bytes(&[4u8; 32]).verifying_key();
+        let peer = peer_id_for(&key).unwrap();
+        assert_eq!(peer_verifying_key(&peer).unwrap(), key);
+        // libp2p's own key derivation agrees
+        let keypair = libp2p::identity::Keypair::ed25519_from_bytes([4u8; 32]).unwrap();
+        assert_eq!(keypair.public().to_peer_id(), peer);
+    }
+
+    #[test]
+    fn test_removed_member_keeps_history_but_not_new_epochs() {
+        let (mut owner, _) = manager(1);
+        let (mut bob, bob_id) = manager(2);
+        let (mut carol, carol_id) = manager(3);
+
+        let id = owner.create("desk").unwrap().group_id;
+        let updates = owner.invite(&id, &bob_id).unwrap();
+        deliver(updates, &mut bob, &bob_id);
+        let updates = owner.invite(&id, &carol_id).unwrap();
+        deliver(updates.clone(), &mut bob, &bob_id);
+        deliver(updates, &mut carol, &carol_id);
+
+        let before = owner.encrypt(&id, b"before removal").unwrap();
+        assert_eq!(read(&mut carol, &before), b"before removal");
+        assert_eq!(read(&mut bob, &before), b"before removal");
+
+        let updates = owner.remove(&id, &carol_id).unwrap();
+        deliver(updates.clone(), &mut bob, &bob_id);
+        deliver(updates, &mut carol, &carol_id);
+        assert!(carol.info(&id).unwrap().removed);
+        assert_eq!(bob.roster(&id).unwrap().epoch, 4);
+
+        let after = owner.encrypt(&id, b"after removal").unwrap();
+        assert_eq!(read(&mut bob, &after), b"after removal");
+        let refused = carol.open(after.clone()).unwrap_err();
+        assert_eq!(refused.downcast_ref::<GroupRejection>(), Some(&GroupRejection::NotAMember));
+        // The owner won't hand over the new key either
+        assert!(owner.key_for(&id, 4, &carol_id).is_err());
+        assert!(carol.encrypt(&id, b"still here?").is_err());
+
+        // Pre-removal history stays readable
+        assert_eq!(read(&mut carol, &before), b"before removal");
+        println!("✅ Group removal exclusion test PASSED!");
+    }
+
+    #[test]
+    fn test_forged_rosters_are_rejected() {
+        let (mut owner, _) = manager(1);
+        let (mut bob, bob_id) = manager(2);
+        let (mut mallory, mallory_id) = manager(9);
+
+        let id = owner.create("desk").unwrap().group_id;
+        let updates = owner.invite(&id, &bob_id).unwrap();
+        let genuine = updates[0].1.clone();
+        bob.on_update(genuine.clone()).unwrap();
+
+        let rejection = |e: anyhow::Error| e.downcast_ref::<GroupRejection>().cloned();
+
+        // Tampered membership under the owner's old signature
+        let mut tampered = genuine.clone();
+        tampered.roster.epoch = 9;
+        tampered.roster.members.push(mallory.local_member());
+        assert_eq!(rejection(bob.on_update(tampered).unwrap_err()), Some(GroupRejection::BadSignature));
+
+        // Rosters signed before v2 still verify
+        let mut legacy = genuine.roster.clone();
+        legacy.signature = hex::encode(owner.signing_key.sign(&legacy.legacy_signing_bytes().unwrap()).to_bytes());
+        assert!(legacy.verify().is_ok());
+
+        // Mallory re-signs the owner's group as if it were hers
+        let mut hijack = genuine.roster.clone();
+        hijack.owner = hex::encode(mallory.signing_key.verifying_key().as_bytes());
+        hijack.epoch = 9;
+        let hijack = hijack.sign(&mallory.signing_key).unwrap();
+        let forged = GroupUpdate { roster: hijack, sealed_key: genuine.sealed_key.clone() };
+        assert_eq!(rejection(bob.on_update(forged).unwrap_err()), Some(GroupRejection::NotOwner));
+
+        // A non-owner can't rotate the group locally either
+        assert!(bob.invite(&id, &mallory_id).is_err());
+
+        // Replaying an old epoch only re-adds its key; the roster stays put
+        let updates = owner.invite(&id, &mallory_id).unwrap();
+        deliver(updates.clone(), &mut bob, &bob_id);
+        deliver(updates, &mut mallory, &mallory_id);
+        bob.on_update(genuine).unwrap();
+        assert_eq!(bob.roster(&id).unwrap().epoch, 3);
+        assert!(bob.roster(&id).unwrap().is_member(&mallory_id.to_string()));
+        println!("✅ Roster forgery test PASSED!");
+    }
+
+    #[test]
+    fn test_late_receiver_requests_epoch_key() {
+        let (mut owner, _) = manager(1);
+        let (mut bob, bob_id) = manager(2);
+        let (_, carol_id) = manager(3);
+
+        let id = owner.create("desk").unwrap().group_id;
+        deliver(owner.invite(&id, &bob_id).unwrap(), &mut bob, &bob_id);
+        // Bob misses the rotation that added Carol
+        owner.invite(&id, &carol_id).unwrap();
+
+        let message = owner.encrypt(&id, b"epoch 3").unwrap();
+        let Opened::NeedKey { owner: from, epoch, .. } = bob.open(message).unwrap() else {
+            panic!("bob should not have the epoch 3 key");
+        };
+        assert_eq!((from.to_string(), epoch), (owner.peer_id.clone(), 3));
+
+        let released = bob.on_update(owner.key_for(&id, epoch, &bob_id).unwrap()).unwrap();
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].plaintext, b"epoch 3");
+        // Members only get keys for epochs they belonged to
+        assert!(owner.key_for(&id, 2, &carol_id).is_err());
+    }
+}
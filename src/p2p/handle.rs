@@ -0,0 +1,681 @@
+//! Node Handle
+//! Cloneable facade for running a node inside an application. Calls are
+//! queued to the node's run loop and answered over oneshot channels, and
+//! events come back as streams, so callers never touch the swarm or a lock
+
+use anyhow::{Context, Result};
+use futures::Stream;
+use libp2p::gossipsub::{IdentTopic, TopicHash};
+use libp2p::PeerId;
+use std::pin::Pin;
+use std::task::{ready, Poll};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use super::protocol::{QuantraRequest, QuantraResponse};
+use super::receipts::{self, MessageRecord};
+use super::transcript::{TranscriptBundle, TranscriptRange};
+use super::reload::ReloadReport;
+use super::{listen, NetworkStatus, P2PEvent, P2PNode, TransportKind};
+use crate::trace::{self, TraceId};
+
+/// What `NodeHandle::spawn` starts
+#[derive(Debug, Clone)]
+pub struct NodeConfig {
+    pub listen: Vec<String>,
+    /// Fail unless every listen address binds (by default one is enough)
+    pub listen_require_all: bool,
+    /// Discover peers on the local network. Its per-interface tasks are
+    /// not stopped with the node, so turn it off for short-lived nodes
+    pub mdns: bool,
+    /// Also read interactive commands from stdin
+    pub console: bool,
+    /// `Memory` for nodes that only talk to others in this process
+    pub transport: TransportKind,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            listen: vec!["/ip4/0.0.0.0/tcp/0".to_string()],
+            listen_require_all: false,
+            mdns: true,
+            console: false,
+            transport: TransportKind::Tcp,
+        }
+    }
+}
+
+/// Work for the run loop; replies are dropped if the node stops first
+pub(super) enum NodeCommand {
+    Publish { topic: String, data: Vec<u8>, reply: oneshot::Sender<Result<()>> },
+    Request { peer: PeerId, request: Box<QuantraRequest>, trace_id: TraceId, reply: oneshot::Sender<Result<QuantraResponse>> },
+    Disconnect { peer: PeerId, trace_id: TraceId, reply: oneshot::Sender<Result<bool>> },
+    SendDirect { peer: PeerId, data: Vec<u8>, encrypted_data: Vec<u8>, trace_id: TraceId, reply: oneshot::Sender<Result<QuantraResponse>> },
+    Queue { peer: PeerId, data: Vec<u8>, encrypted_data: Vec<u8>, reply: oneshot::Sender<Result<String>> },
+    MarkRead { message_id: String, reply: oneshot::Sender<Result<()>> },
+    MessageStatus { message_id: String, reply: oneshot::Sender<Result<Option<MessageRecord>>> },
+    ExportTranscript { peer: PeerId, range: TranscriptRange, reply: oneshot::Sender<Result<TranscriptBundle>> },
+    Subscribe { topic: Option<String>, reply: oneshot::Sender<Result<mpsc::UnboundedReceiver<P2PEvent>>> },
+    Dial { addr: String, reply: oneshot::Sender<Result<()>> },
+    Status { reply: oneshot::Sender<NetworkStatus> },
+    GetDhtRecords { key: Vec<u8>, reply: oneshot::Sender<Result<Vec<Vec<u8>>>> },
+    PutDhtRecord { key: Vec<u8>, value: Vec<u8>, reply: oneshot::Sender<Result<()>> },
+    ReloadConfig { reply: oneshot::Sender<ReloadReport> },
+    Shutdown,
+}
+
+/// A running node. Clones share it; the node stops on `shutdown` or once
+/// every handle has been dropped
+#[derive(Clone)]
+pub struct NodeHandle {
+    peer_id: PeerId,
+    commands: mpsc::UnboundedSender<NodeCommand>,
+}
+
+impl NodeHandle {
+    /// Create a node, bind its listen addresses and run it on the current
+    /// runtime. The join handle resolves when the node stops
+    pub async fn spawn(config: NodeConfig) -> Result<(Self, JoinHandle<Result<()>>)> {
+        let mut node = P2PNode::with_transport(libp2p::identity::Keypair::generate_ed25519(), config.transport)?;
+        if !config.mdns {
+            node.disable_mdns();
+        }
+        let results = node.listen_on_multiple(&config.listen).await;
+        if let Some(failed) = listen::startup_failures(&results, config.listen_require_all) {
+            let errors: Vec<String> = failed
+                .iter()
+                .map(|r| format!("{}: {}", r.requested, r.outcome.as_ref().err().map_or("no address", |e| e)))
+                .collect();
+            anyhow::bail!("Failed to listen on {}", errors.join("; "));
+        }
+        Ok(Self::attach(node, config.console))
+    }
+
+    /// Run an already configured (and listening) node
+    pub fn attach(mut node: P2PNode, console: bool) -> (Self, JoinHandle<Result<()>>) {
+        let (commands, rx) = mpsc::unbounded_channel();
+        let handle = Self { peer_id: *node.local_peer_id(), commands };
+        let task = tokio::spawn(async move { node.run(rx, console).await });
+        (handle, task)
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+
+    /// Queue a command and wait for its reply
+    async fn call<T>(&self, command: impl FnOnce(oneshot::Sender<T>) -> NodeCommand) -> Result<T> {
+        let (reply, rx) = oneshot::channel();
+        self.commands.send(command(reply)).ok().context("Node has stopped")?;
+        rx.await.ok().context("Node has stopped")
+    }
+
+    /// Gossip `data` on `topic`
+    pub async fn publish(&self, topic: &str, data: impl Into<Vec<u8>>) -> Result<()> {
+        let (topic, data) = (topic.to_string(), data.into());
+        self.call(|reply| NodeCommand::Publish { topic, data, reply }).await?
+    }
+
+    /// Send `data` sealed to `peer`'s identity key; it arrives as a
+    /// `P2PEvent::DirectMessage`. Resolves once the peer has accepted it,
+    /// at which point it is on both sides' transcript, with the message ID
+    pub async fn send_encrypted(&self, peer: PeerId, data: impl Into<Vec<u8>>) -> Result<String> {
+        let (data, trace_id) = (data.into(), trace::current_or_new());
+        let encrypted_data = crate::crypto::sealed::seal(&super::groups::peer_verifying_key(&peer)?, &data)?;
+        let message_id = receipts::message_id(&self.peer_id, &encrypted_data);
+        match self.call(|reply| NodeCommand::SendDirect { peer, data, encrypted_data, trace_id, reply }).await?? {
+            QuantraResponse::MessageSent => Ok(message_id),
+            QuantraResponse::Error(e) | QuantraResponse::InvalidRequest { reason: e } => {
+                anyhow::bail!("{} rejected the message: {}", peer, e)
+            }
+            other => anyhow::bail!("Unexpected response from {}: {:?}", peer, other),
+        }
+    }
+
+    /// Like `send_encrypted`, but store-and-forward: resolves with the
+    /// message ID once the message is durably in the outbox. It goes out
+    /// whenever `peer` is connected, until accepted or `[p2p.outbox] ttl`
+    /// passes, even across restarts
+    pub async fn queue_encrypted(&self, peer: PeerId, data: impl Into<Vec<u8>>) -> Result<String> {
+        let data = data.into();
+        let encrypted_data = crate::crypto::sealed::seal(&super::groups::peer_verifying_key(&peer)?, &data)?;
+        self.call(|reply| NodeCommand::Queue { peer, data, encrypted_data, reply }).await?
+    }
+
+    /// Export messages in `range` of the transcript with `peer`, signed by
+    /// us and, unless it declines, co-signed by the peer
+    pub async fn export_transcript(&self, peer: PeerId, range: TranscriptRange) -> Result<TranscriptBundle> {
+        let mut bundle = self.call(|reply| NodeCommand::ExportTranscript { peer, range, reply }).await??;
+        let request = QuantraRequest::SignTranscript { head_hash: bundle.head_hash.clone(), range: bundle.range };
+        let answer = match self.request(peer, request).await {
+            Ok(QuantraResponse::TranscriptSigned { signature }) => Ok(signature),
+            Ok(QuantraResponse::Error(e)) => Err(e),
+            Ok(other) => Err(format!("unexpected response: {:?}", other)),
+            Err(e) => Err(format!("{:#}", e)),
+        };
+        bundle.cosign(answer);
+        Ok(bundle)
+    }
+
+    /// Send a request and wait for the peer's response. It carries the
+    /// caller's trace ID (see `trace::scope`), or a new one
+    pub async fn request(&self, peer: PeerId, request: QuantraRequest) -> Result<QuantraResponse> {
+        let (request, trace_id) = (Box::new(request), trace::current_or_new());
+        self.call(|reply| NodeCommand::Request { peer, request, trace_id, reply }).await?
+    }
+
+    /// End the session with `peer` cleanly and disconnect. Under zero-trust
+    /// the peer issues a resumption token, presented on the next connection
+    /// so it is not evaluated in full again. Resolves with whether the peer
+    /// was connected
+    pub async fn disconnect(&self, peer: PeerId) -> Result<bool> {
+        let trace_id = trace::current_or_new();
+        self.call(|reply| NodeCommand::Disconnect { peer, trace_id, reply }).await?
+    }
+
+    /// Mark a received direct message read, sending its sender a read
+    /// receipt unless `[p2p.receipts]` turns those off
+    pub async fn mark_read(&self, message_id: &str) -> Result<()> {
+        let message_id = message_id.to_string();
+        self.call(|reply| NodeCommand::MarkRead { message_id, reply }).await?
+    }
+
+    /// A sent or received direct message with its receipts
+    pub async fn message_status(&self, message_id: &str) -> Result<Option<MessageRecord>> {
+        let message_id = message_id.to_string();
+        self.call(|reply| NodeCommand::MessageStatus { message_id, reply }).await?
+    }
+
+    /// Join a gossip topic and stream its messages
+    pub async fn subscribe(&self, topic: &str) -> Result<EventStream> {
+        let topic = topic.to_string();
+        let hash = IdentTopic::new(&topic).hash();
+        let rx = self.call(|reply| NodeCommand::Subscribe { topic: Some(topic), reply }).await??;
+        Ok(EventStream { rx, topic: Some(hash) })
+    }
+
+    /// Every event the node surfaces: gossip on joined topics, group and
+    /// direct messages
+    pub async fn events(&self) -> Result<EventStream> {
+        let rx = self.call(|reply| NodeCommand::Subscribe { topic: None, reply }).await??;
+        Ok(EventStream { rx, topic: None })
+    }
+
+    /// Connect to a peer (retried with backoff)
+    pub async fn dial(&self, addr: &str) -> Result<()> {
+        let addr = addr.to_string();
+        self.call(|reply| NodeCommand::Dial { addr, reply }).await?
+    }
+
+    /// Every copy of the DHT record under `key` that the lookup finds,
+    /// this node's own included; empty when there is none
+    pub async fn get_dht_records(&self, key: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let key = key.to_vec();
+        self.call(|reply| NodeCommand::GetDhtRecords { key, reply }).await?
+    }
+
+    /// Store a record on the DHT once, resolving when at least one peer
+    /// holds it. Unlike `P2PNode::put_dht_record` it is not journaled or
+    /// republished; it expires unless put again
+    pub async fn put_dht_record(&self, key: &[u8], value: impl Into<Vec<u8>>) -> Result<()> {
+        let (key, value) = (key.to_vec(), value.into());
+        self.call(|reply| NodeCommand::PutDhtRecord { key, value, reply }).await?
+    }
+
+    pub async fn status(&self) -> Result<NetworkStatus> {
+        self.call(|reply| NodeCommand::Status { reply }).await
+    }
+
+    /// Re-read the node's config file and apply what can change live
+    /// (see `P2PNode::reload_config`)
+    pub async fn reload_config(&self) -> Result<ReloadReport> {
+        self.call(|reply| NodeCommand::ReloadConfig { reply }).await
+    }
+
+    /// Stop the node and wait for its run loop to exit. Pending calls on
+    /// other handles fail with "Node has stopped"
+    pub async fn shutdown(&self) {
+        if self.commands.send(NodeCommand::Shutdown).is_ok() {
+            self.commands.closed().await;
+        }
+    }
+}
+
+/// Events from a node; ends when the node stops
+pub struct EventStream {
+    rx: mpsc::UnboundedReceiver<P2PEvent>,
+    /// Only gossip on this topic
+    topic: Option<TopicHash>,
+}
+
+impl EventStream {
+    fn wanted(&self, event: &P2PEvent) -> bool {
+        match (&self.topic, event) {
+            (None, _) => true,
+            (Some(wanted), P2PEvent::Message { topic, .. }) => topic == wanted,
+            (Some(_), _) => false,
+        }
+    }
+}
+
+impl Stream for EventStream {
+    type Item = P2PEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Option<P2PEvent>> {
+        loop {
+            match ready!(self.rx.poll_recv(cx)) {
+                Some(event) if !self.wanted(&event) => continue,
+                event => return Poll::Ready(event),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::sealed;
+    use crate::p2p::groups;
+    use crate::storage::RuntimeMode;
+    use futures::StreamExt;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn local() -> NodeConfig {
+        NodeConfig { listen: vec!["/ip4/127.0.0.1/tcp/0".to_string()], mdns: false, ..Default::default() }
+    }
+
+    /// Dial `to` from `from` and wait for the connection
+    async fn connect(from: &NodeHandle, to: &NodeHandle) {
+        let status = to.status().await.unwrap();
+        from.dial(&status.listeners[0].addresses[0]).await.unwrap();
+        timeout(Duration::from_secs(10), async {
+            while from.status().await.unwrap().connected_peers == 0 {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("nodes did not connect");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_handle_shared_across_tasks() {
+        let (alice, alice_task) = NodeHandle::spawn(local()).await.unwrap();
+        let (bob, bob_task) = NodeHandle::spawn(local()).await.unwrap();
+        let mut inbox = alice.events().await.unwrap();
+        connect(&bob, &alice).await;
+
+        let senders: Vec<_> = (0..8u8)
+            .map(|i| {
+                let bob = bob.clone();
+                let alice_id = alice.peer_id();
+                tokio::spawn(async move {
+                    let pong = bob.request(alice_id, QuantraRequest::Ping).await.unwrap();
+                    assert!(matches!(pong, QuantraResponse::Pong));
+                    bob.send_encrypted(alice_id, vec![i; 32]).await.unwrap();
+                    assert_eq!(bob.status().await.unwrap().connected_peers, 1);
+                })
+            })
+            .collect();
+        for sender in senders {
+            sender.await.unwrap();
+        }
+
+        let mut received = Vec::new();
+        while received.len() < 8 {
+            match timeout(Duration::from_secs(5), inbox.next()).await.unwrap().unwrap() {
+                P2PEvent::DirectMessage { source, data, .. } => {
+                    assert_eq!(source, bob.peer_id());
+                    received.push(data[0]);
+                }
+                _ => continue,
+            }
+        }
+        received.sort();
+        assert_eq!(received, (0..8).collect::<Vec<_>>());
+
+        // A message sealed to someone else is refused, not surfaced
+        let stranger = libp2p::identity::Keypair::generate_ed25519();
+        let misaddressed = sealed::seal(
+            &groups::peer_verifying_key(&PeerId::from(stranger.public())).unwrap(),
+            b"not for alice",
+        )
+        .unwrap();
+        let response = bob.request(alice.peer_id(), QuantraRequest::SendMessage { encrypted_data: misaddressed }).await;
+        assert!(matches!(response.unwrap(), QuantraResponse::Error(_)));
+
+        alice.shutdown().await;
+        bob.shutdown().await;
+        alice_task.await.unwrap().unwrap();
+        bob_task.await.unwrap().unwrap();
+        assert!(inbox.next().await.is_none(), "event stream should end with the node");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_receipts_between_nodes() {
+        use crate::p2p::receipts::{ReceiptConfig, ReceiptKind, ReceiptStore};
+
+        let dirs = tempfile::tempdir().unwrap();
+        let node = |keypair: libp2p::identity::Keypair, name: &str| {
+            let mut node = P2PNode::with_keypair(keypair).unwrap();
+            node.disable_mdns();
+            node.enable_receipts(&dirs.path().join(name), &ReceiptConfig::default()).unwrap();
+            node.listen_on("/ip4/127.0.0.1/tcp/0").unwrap();
+            NodeHandle::attach(node, false)
+        };
+        let wait_for = |handle: NodeHandle, id: String, kind: ReceiptKind| async move {
+            timeout(Duration::from_secs(10), async {
+                loop {
+                    let record = handle.message_status(&id).await.unwrap();
+                    if record.as_ref().and_then(|r| r.status()) == Some(kind) {
+                        return record.unwrap();
+                    }
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+            })
+            .await
+            .unwrap_or_else(|_| panic!("no {} receipt", kind))
+        };
+        let alice_key = libp2p::identity::Keypair::generate_ed25519();
+        let (alice, alice_task) = node(alice_key.clone(), "alice");
+        let (bob, bob_task) = node(libp2p::identity::Keypair::generate_ed25519(), "bob");
+        connect(&bob, &alice).await;
+
+        // Delivered as soon as Alice's node has it; not read until she says so
+        let id = bob.send_encrypted(alice.peer_id(), b"filled at 101.5".to_vec()).await.unwrap();
+        wait_for(bob.clone(), id.clone(), ReceiptKind::Delivered).await;
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(bob.message_status(&id).await.unwrap().unwrap().status(), Some(ReceiptKind::Delivered));
+        alice.shutdown().await;
+        alice_task.await.unwrap().unwrap();
+
+        // `inbox read` with Alice's node stopped; the receipt goes out once
+        // she is back
+        let store = ReceiptStore::open(&dirs.path().join("alice"), RuntimeMode::Persistent, ReceiptConfig::default()).unwrap();
+        store.mark_read(&id, chrono::Utc::now()).unwrap();
+        drop(store);
+        let (alice, alice_task) = node(alice_key, "alice");
+        connect(&alice, &bob).await;
+        let record = wait_for(bob.clone(), id.clone(), ReceiptKind::Read).await;
+        assert_eq!(record.receipts.iter().map(|r| r.kind).collect::<Vec<_>>(), [ReceiptKind::Delivered, ReceiptKind::Read]);
+        assert!(alice.message_status(&id).await.unwrap().unwrap().read_at.is_some());
+
+        for (node, task) in [(alice, alice_task), (bob, bob_task)] {
+            node.shutdown().await;
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_outbox_delivers_after_restart() {
+        use crate::p2p::outbox::{Outbox, OutboxConfig};
+
+        let dirs = tempfile::tempdir().unwrap();
+        let outbox = dirs.path().join("outbox.wal");
+        let bob_key = libp2p::identity::Keypair::generate_ed25519();
+        let bob_node = || {
+            let mut node = P2PNode::with_keypair(bob_key.clone()).unwrap();
+            node.disable_mdns();
+            node.enable_outbox(&outbox, &OutboxConfig::default()).unwrap();
+            node.listen_on("/ip4/127.0.0.1/tcp/0").unwrap();
+            NodeHandle::attach(node, false)
+        };
+        let alice_key = libp2p::identity::Keypair::generate_ed25519();
+        let alice_id = PeerId::from(alice_key.public());
+
+        // Alice is offline: the message waits on disk, across Bob's restart
+        let (bob, bob_task) = bob_node();
+        let id = bob.queue_encrypted(alice_id, b"filled at 101.5".to_vec()).await.unwrap();
+        bob.shutdown().await;
+        bob_task.await.unwrap().unwrap();
+        assert_eq!(Outbox::inspect(&outbox, chrono::Utc::now()).unwrap().entries, 1);
+
+        let (bob, bob_task) = bob_node();
+        let mut alice_node = P2PNode::with_keypair(alice_key).unwrap();
+        alice_node.disable_mdns();
+        alice_node.listen_on("/ip4/127.0.0.1/tcp/0").unwrap();
+        let (alice, alice_task) = NodeHandle::attach(alice_node, false);
+        let mut inbox = alice.events().await.unwrap();
+        connect(&bob, &alice).await;
+
+        let data = timeout(Duration::from_secs(10), async {
+            loop {
+                if let Some(P2PEvent::DirectMessage { data, .. }) = inbox.next().await {
+                    return data;
+                }
+            }
+        })
+        .await
+        .expect("queued message never arrived");
+        assert_eq!(&data[..], b"filled at 101.5");
+        timeout(Duration::from_secs(5), async {
+            while Outbox::inspect(&outbox, chrono::Utc::now()).unwrap().entries > 0 {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("{} still queued after delivery", id));
+
+        for (node, task) in [(alice, alice_task), (bob, bob_task)] {
+            node.shutdown().await;
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_transcript_export_between_nodes() {
+        let (alice, alice_task) = NodeHandle::spawn(local()).await.unwrap();
+        let node = || {
+            let mut node = P2PNode::new().unwrap();
+            node.disable_mdns();
+            node
+        };
+        let mut reluctant = node();
+        reluctant.disable_transcript_cosigning();
+        let (bob, bob_task) = NodeHandle::attach(node(), false);
+        let (carol, carol_task) = NodeHandle::attach(reluctant, false);
+        connect(&bob, &alice).await;
+        connect(&carol, &alice).await;
+
+        bob.send_encrypted(alice.peer_id(), b"bid 101.5".to_vec()).await.unwrap();
+        alice.send_encrypted(bob.peer_id(), b"filled".to_vec()).await.unwrap();
+        let bundle = alice.export_transcript(bob.peer_id(), "..".parse().unwrap()).await.unwrap();
+        assert!(bundle.is_cosigned(), "{:?}", bundle.counterparty_signature);
+        assert_eq!(bundle.messages.len(), 2);
+        assert_eq!(bundle.messages[0].sender, bob.peer_id().to_string());
+        bundle.verify().unwrap();
+
+        // Carol refuses to co-sign; the export is still signed by Alice
+        carol.send_encrypted(alice.peer_id(), b"offer 102".to_vec()).await.unwrap();
+        let bundle = alice.export_transcript(carol.peer_id(), "..".parse().unwrap()).await.unwrap();
+        assert!(!bundle.is_cosigned());
+        bundle.verify().unwrap();
+
+        for (node, task) in [(alice, alice_task), (bob, bob_task), (carol, carol_task)] {
+            node.shutdown().await;
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    /// Log lines captured by a test subscriber
+    #[derive(Clone, Default)]
+    struct CapturedLogs(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl CapturedLogs {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8_lossy(&self.0.lock().unwrap()).lines().map(str::to_string).collect()
+        }
+    }
+
+    // Current-thread runtime so the scoped subscriber sees both nodes
+    #[tokio::test]
+    async fn test_trace_id_crosses_nodes() {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let dirs = tempfile::tempdir().unwrap();
+        let node = |name: &str| {
+            let name = name.to_string();
+            let root = dirs.path().join(&name);
+            async move {
+                let mut node = P2PNode::new().unwrap();
+                node.disable_mdns();
+                node.set_data_dirs(crate::data_dirs::DataDirs::resolve(Some(&root), None, RuntimeMode::Persistent).unwrap());
+                node.enable_zero_trust().await.unwrap();
+                node.listen_on("/ip4/127.0.0.1/tcp/0").unwrap();
+                let zt = node.zero_trust().unwrap().clone();
+                let (handle, task) = NodeHandle::attach(node, false);
+                (handle, task, zt)
+            }
+        };
+        let (alice, alice_task, alice_zt) = node("alice").await;
+        let (bob, bob_task, bob_zt) = node("bob").await;
+        connect(&bob, &alice).await;
+        timeout(Duration::from_secs(10), async {
+            while alice_zt.get_active_connections().await.unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("alice never admitted bob");
+
+        // Bob disconnects cleanly: Alice ends the session, then Bob his side
+        let trace_id = TraceId::new();
+        let disconnected = trace::scope(trace_id.clone(), bob.disconnect(alice.peer_id())).await.unwrap();
+        assert!(disconnected);
+
+        for (zt, peer) in [(&alice_zt, bob.peer_id()), (&bob_zt, alice.peer_id())] {
+            let events = zt.audit_events().await.unwrap();
+            let terminated = events
+                .iter()
+                .find(|e| e.event_type == "connection_terminated" && e.peer_id == peer.to_string())
+                .expect("no connection_terminated event");
+            assert_eq!(terminated.trace_id.as_deref(), Some(trace_id.as_str()));
+        }
+        let traced = format!("trace_id={}", trace_id);
+        let lines = logs.lines();
+        let received = format!("📥 Request from {}", bob.peer_id());
+        assert!(lines.iter().any(|l| l.contains(&received) && l.contains(&traced)), "{:#?}", lines);
+        let sent = format!("📤 Request to {}", alice.peer_id());
+        assert!(lines.iter().any(|l| l.contains(&sent) && l.contains(trace_id.as_str())), "{:#?}", lines);
+
+        for (node, task) in [(alice, alice_task), (bob, bob_task)] {
+            node.shutdown().await;
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_clean_disconnect_resumes_without_reevaluation() {
+        let node = || async {
+            let mut node = P2PNode::new().unwrap();
+            node.disable_mdns();
+            node.enable_zero_trust().await.unwrap();
+            node.listen_on("/ip4/127.0.0.1/tcp/0").unwrap();
+            let zt = node.zero_trust().unwrap().clone();
+            let (handle, task) = NodeHandle::attach(node, false);
+            (handle, task, zt)
+        };
+        let (alice, alice_task, alice_zt) = node().await;
+        let (bob, bob_task, _) = node().await;
+        let bob_id = bob.peer_id().to_string();
+        let events = |kind: &'static str| {
+            let (zt, bob_id) = (alice_zt.clone(), bob_id.clone());
+            async move {
+                let events = zt.audit_events().await.unwrap();
+                events.iter().filter(|e| e.event_type == kind && e.peer_id == bob_id).count()
+            }
+        };
+        let admitted = || async {
+            let connections = alice_zt.get_active_connections().await.unwrap();
+            connections.iter().any(|c| c.peer_id == bob_id)
+        };
+
+        connect(&bob, &alice).await;
+        timeout(Duration::from_secs(10), async {
+            while !admitted().await {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("alice never admitted bob");
+        let decisions = events("access_granted").await;
+        assert_eq!(decisions, 1);
+
+        assert!(bob.disconnect(alice.peer_id()).await.unwrap());
+        timeout(Duration::from_secs(10), async {
+            while bob.status().await.unwrap().connected_peers > 0 {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("bob never disconnected");
+        assert!(!admitted().await);
+
+        // Bob presents his token on reconnect; Alice's policy engine is not asked again
+        connect(&bob, &alice).await;
+        timeout(Duration::from_secs(10), async {
+            while events("connection_resumed").await == 0 || !admitted().await {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("bob never resumed his session");
+        assert_eq!(events("access_granted").await, decisions);
+        assert_eq!(events("policy_denied").await + events("identity_grace_accepted").await, 0);
+
+        for (node, task) in [(alice, alice_task), (bob, bob_task)] {
+            node.shutdown().await;
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_shutdown_leaves_no_tasks() {
+        let metrics = tokio::runtime::Handle::current().metrics();
+        let baseline = metrics.num_alive_tasks();
+
+        let (node, task) = NodeHandle::spawn(local()).await.unwrap();
+        let other = node.clone();
+        let mut topic = node.subscribe("quantra-embedded").await.unwrap();
+        node.publish("quantra-embedded", b"no peers yet".to_vec()).await.unwrap_err();
+        assert!(metrics.num_alive_tasks() > baseline);
+
+        node.shutdown().await;
+        timeout(Duration::from_secs(5), task).await.expect("run loop did not exit").unwrap().unwrap();
+        assert!(other.status().await.unwrap_err().to_string().contains("Node has stopped"));
+        assert!(topic.next().await.is_none());
+
+        // Dropping every handle stops a node as well
+        let (node, task) = NodeHandle::spawn(local()).await.unwrap();
+        drop(node);
+        timeout(Duration::from_secs(5), task).await.expect("run loop did not exit").unwrap().unwrap();
+
+        // Aborted scheduler tasks wind down asynchronously
+        timeout(Duration::from_secs(5), async {
+            while metrics.num_alive_tasks() > baseline {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("{} task(s) leaked", metrics.num_alive_tasks() - baseline));
+    }
+}
@@ -0,0 +1,137 @@
+//! Listeners
+//! Requested listen addresses, the concrete addresses each one bound
+//! (ephemeral ports resolved) and why any of them failed
+
+use libp2p::core::transport::ListenerId;
+use libp2p::Multiaddr;
+use serde::Serialize;
+use std::time::Duration;
+
+/// How long `listen_on_multiple` waits for a listener's first address
+pub const LISTEN_RESOLVE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Extra wait for the remaining addresses of wildcard listeners
+pub const LISTEN_SETTLE_DELAY: Duration = Duration::from_millis(100);
+
+/// Outcome of one requested listen address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ListenResult {
+    pub requested: String,
+    /// Bound addresses (one per interface for wildcards), or the bind error
+    pub outcome: Result<Vec<Multiaddr>, String>,
+}
+
+impl ListenResult {
+    pub fn is_bound(&self) -> bool {
+        self.outcome.as_ref().is_ok_and(|addrs| !addrs.is_empty())
+    }
+}
+
+/// The failed results when they should abort startup: every address
+/// failed, or any did with `require_all`
+pub fn startup_failures(results: &[ListenResult], require_all: bool) -> Option<Vec<&ListenResult>> {
+    let failed: Vec<_> = results.iter().filter(|r| !r.is_bound()).collect();
+    (failed.len() == results.len() || (require_all && !failed.is_empty())).then_some(failed)
+}
+
+/// One live or failed listener, for `network_status`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ListenerStatus {
+    pub requested: String,
+    pub addresses: Vec<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Listener {
+    requested: String,
+    addresses: Vec<Multiaddr>,
+    closed: bool,
+    error: Option<String>,
+}
+
+/// Listener state, kept current from swarm listen events
+#[derive(Debug, Default)]
+pub struct Listeners {
+    // In the order they were requested; a node has a handful
+    listeners: Vec<(ListenerId, Listener)>,
+}
+
+impl Listeners {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, id: ListenerId, requested: &Multiaddr) {
+        self.listeners.push((id, Listener { requested: requested.to_string(), ..Default::default() }));
+    }
+
+    fn get(&self, id: ListenerId) -> Option<&Listener> {
+        self.listeners.iter().find(|(i, _)| *i == id).map(|(_, l)| l)
+    }
+
+    fn get_mut(&mut self, id: ListenerId) -> Option<&mut Listener> {
+        self.listeners.iter_mut().find(|(i, _)| *i == id).map(|(_, l)| l)
+    }
+
+    pub fn on_new_addr(&mut self, id: ListenerId, address: Multiaddr) {
+        if let Some(listener) = self.get_mut(id) {
+            if !listener.addresses.contains(&address) {
+                listener.addresses.push(address);
+            }
+        }
+    }
+
+    pub fn on_expired_addr(&mut self, id: ListenerId, address: &Multiaddr) {
+        if let Some(listener) = self.get_mut(id) {
+            listener.addresses.retain(|a| a != address);
+        }
+    }
+
+    /// Listener shut down; `error` is set when it failed
+    pub fn on_closed(&mut self, id: ListenerId, error: Option<String>) {
+        if let Some(listener) = self.get_mut(id) {
+            listener.addresses.clear();
+            listener.closed = true;
+            listener.error = error.or(listener.error.take());
+        }
+    }
+
+    /// Non-fatal listener error
+    pub fn on_error(&mut self, id: ListenerId, error: String) {
+        if let Some(listener) = self.get_mut(id) {
+            listener.error = Some(error);
+        }
+    }
+
+    /// Has an address or has closed
+    pub fn is_resolved(&self, id: ListenerId) -> bool {
+        self.get(id).is_none_or(|l| l.closed || !l.addresses.is_empty())
+    }
+
+    /// Result for a listener started by `listen_on_multiple`
+    pub fn result(&self, id: ListenerId, requested: &str) -> ListenResult {
+        let outcome = match self.get(id) {
+            Some(l) if !l.addresses.is_empty() => Ok(l.addresses.clone()),
+            Some(l) if l.closed => Err(l.error.clone().unwrap_or_else(|| "Listener closed".to_string())),
+            Some(l) => Err(l.error.clone().unwrap_or_else(|| {
+                format!("No address bound within {}s", LISTEN_RESOLVE_TIMEOUT.as_secs())
+            })),
+            None => Err("Unknown listener".to_string()),
+        };
+        ListenResult { requested: requested.to_string(), outcome }
+    }
+
+    /// Open listeners with their current addresses
+    pub fn statuses(&self) -> Vec<ListenerStatus> {
+        self.listeners
+            .iter()
+            .map(|(_, l)| l)
+            .filter(|l| !l.closed)
+            .map(|l| ListenerStatus {
+                requested: l.requested.clone(),
+                addresses: l.addresses.iter().map(|a| a.to_string()).collect(),
+                error: l.error.clone(),
+            })
+            .collect()
+    }
+}
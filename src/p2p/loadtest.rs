@@ -0,0 +1,687 @@
+//! Load Test
+//! Drives synthetic load at a node to see what it sustains: gossip
+//! throughput and latency, connection churn, or request round trips. Client
+//! nodes run in this process; the target is a node started here on the
+//! in-memory transport, or a remote node over TCP
+
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use libp2p::multiaddr::Protocol;
+use libp2p::swarm::SwarmEvent;
+use libp2p::{identity::Keypair, Multiaddr, PeerId};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use super::handle::{EventStream, NodeHandle};
+use super::protocol::{QuantraRequest, QuantraResponse};
+use super::{NodeCounters, P2PNode, TransportKind, DEFAULT_TOPIC};
+
+/// Every gossip payload starts with this, then the client, sequence
+/// number and send time (µs since the epoch), all big-endian
+const PAYLOAD_MAGIC: &[u8; 4] = b"QLT1";
+pub const PAYLOAD_HEADER_LEN: usize = 4 + 4 + 8 + 8;
+/// How long a client may take to connect before the run is abandoned
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// A connection the target closes within this window counts as rejected
+const REJECTION_WINDOW: Duration = Duration::from_millis(500);
+
+/// What a load test exercises
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoadMode {
+    /// Clients publish on a topic; delivery is observed at the target
+    Gossip,
+    /// New peers connect and disconnect at a fixed rate
+    Connect,
+    /// Clients send requests and time the responses
+    Request,
+}
+
+impl FromStr for LoadMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "gossip" => Ok(Self::Gossip),
+            "connect" => Ok(Self::Connect),
+            "request" => Ok(Self::Request),
+            other => anyhow::bail!("Unknown load test mode '{}' (expected gossip, connect or request)", other),
+        }
+    }
+}
+
+impl fmt::Display for LoadMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gossip => write!(f, "gossip"),
+            Self::Connect => write!(f, "connect"),
+            Self::Request => write!(f, "request"),
+        }
+    }
+}
+
+/// Request sent in `request` mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestKind {
+    Ping,
+    Quote { symbol: String },
+}
+
+impl RequestKind {
+    fn request(&self) -> QuantraRequest {
+        match self {
+            Self::Ping => QuantraRequest::Ping,
+            Self::Quote { symbol } => QuantraRequest::GetQuote { symbol: symbol.clone() },
+        }
+    }
+}
+
+impl FromStr for RequestKind {
+    type Err = anyhow::Error;
+
+    /// `ping`, `quote` (AAPL) or `quote:<symbol>`
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s.eq_ignore_ascii_case("ping") => Ok(Self::Ping),
+            None if s.eq_ignore_ascii_case("quote") => Ok(Self::Quote { symbol: "AAPL".to_string() }),
+            Some((kind, symbol)) if kind.eq_ignore_ascii_case("quote") && !symbol.is_empty() => {
+                Ok(Self::Quote { symbol: symbol.to_string() })
+            }
+            _ => anyhow::bail!("Unknown request '{}' (expected ping, quote or quote:<symbol>)", s),
+        }
+    }
+}
+
+impl fmt::Display for RequestKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ping => write!(f, "ping"),
+            Self::Quote { symbol } => write!(f, "quote:{}", symbol),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoadTestConfig {
+    pub mode: LoadMode,
+    /// Client nodes (gossip and request modes)
+    pub clients: usize,
+    /// Per client messages or requests per second; connections per second
+    /// in connect mode
+    pub rate: f64,
+    pub duration: Duration,
+    /// Gossip payload size, at least `PAYLOAD_HEADER_LEN`
+    pub message_size: usize,
+    /// Gossip topic; every node joins [`DEFAULT_TOPIC`] at startup, so a
+    /// remote target relays it without setup
+    pub topic: String,
+    pub request: RequestKind,
+    /// Wait after the last send for deliveries still in flight
+    pub drain: Duration,
+}
+
+impl Default for LoadTestConfig {
+    fn default() -> Self {
+        Self {
+            mode: LoadMode::Gossip,
+            clients: 4,
+            rate: 10.0,
+            duration: Duration::from_secs(10),
+            message_size: 256,
+            topic: DEFAULT_TOPIC.to_string(),
+            request: RequestKind::Ping,
+            drain: Duration::from_secs(2),
+        }
+    }
+}
+
+/// The node under load
+pub enum LoadTarget {
+    /// Configured by the caller, started here on the in-memory transport
+    InProcess(Box<P2PNode>),
+    /// Reached over TCP; request mode needs the `/p2p/<peer id>` suffix
+    Remote(Multiaddr),
+}
+
+/// Latency percentiles in milliseconds (nearest rank)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LatencySummary {
+    pub samples: usize,
+    pub min_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    pub mean_ms: f64,
+}
+
+impl LatencySummary {
+    /// `None` without samples
+    pub fn from_samples(mut samples: Vec<Duration>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let rank = |p: f64| ms(samples[((p * samples.len() as f64).ceil() as usize).clamp(1, samples.len()) - 1]);
+        let total: Duration = samples.iter().sum();
+        Some(Self {
+            samples: samples.len(),
+            min_ms: ms(samples[0]),
+            p50_ms: rank(0.50),
+            p90_ms: rank(0.90),
+            p99_ms: rank(0.99),
+            max_ms: ms(samples[samples.len() - 1]),
+            mean_ms: ms(total) / samples.len() as f64,
+        })
+    }
+}
+
+/// Outcome of one load test. In gossip mode `delivered` counts distinct
+/// messages observed at the target; in connect mode connections the target
+/// kept; in request mode successful responses
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadReport {
+    pub mode: LoadMode,
+    pub target: String,
+    pub clients: usize,
+    pub duration_secs: f64,
+    /// Messages published, connections attempted or requests sent
+    pub sent: u64,
+    pub delivered: u64,
+    /// Connections closed by the target right after opening, or requests
+    /// answered with an error
+    pub rejected: u64,
+    /// Publishes, dials or requests that failed on the client side
+    pub failed: u64,
+    /// Share of `sent` not delivered
+    pub loss: f64,
+    /// `delivered` per second of load
+    pub throughput: f64,
+    /// Send to delivery, connection accept, or request round trip
+    pub latency: Option<LatencySummary>,
+    /// The target's counters before and after; in-process targets only
+    pub target_before: Option<NodeCounters>,
+    pub target_after: Option<NodeCounters>,
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "📈 Load test: {} → {} ({} client(s), {:.1}s)", self.mode, self.target, self.clients, self.duration_secs)?;
+        writeln!(f, "   {:<14} {:>10}", "sent", self.sent)?;
+        writeln!(f, "   {:<14} {:>10}", "delivered", self.delivered)?;
+        writeln!(f, "   {:<14} {:>10}", "rejected", self.rejected)?;
+        writeln!(f, "   {:<14} {:>10}", "failed", self.failed)?;
+        writeln!(f, "   {:<14} {:>9.2}%", "loss", self.loss * 100.0)?;
+        writeln!(f, "   {:<14} {:>8.1}/s", "throughput", self.throughput)?;
+        match &self.latency {
+            Some(l) => writeln!(
+                f,
+                "   {:<14} p50 {:.2}  p90 {:.2}  p99 {:.2}  max {:.2}  (n={})",
+                "latency ms", l.p50_ms, l.p90_ms, l.p99_ms, l.max_ms, l.samples
+            )?,
+            None => writeln!(f, "   {:<14} {:>10}", "latency ms", "-")?,
+        }
+        if let (Some(before), Some(after)) = (&self.target_before, &self.target_after) {
+            writeln!(f, "   Target counters (before → after):")?;
+            let rows = [
+                ("connections_accepted", before.connections_accepted, after.connections_accepted),
+                ("connections_rejected", before.connections_rejected, after.connections_rejected),
+                ("gossip_received", before.gossip_received, after.gossip_received),
+                ("gossip_dropped", before.gossip_dropped, after.gossip_dropped),
+                ("requests_received", before.requests_received, after.requests_received),
+                ("requests_rejected", before.requests_rejected, after.requests_rejected),
+            ];
+            for (name, before, after) in rows {
+                writeln!(f, "   {:<22} {:>8} → {}", name, before, after)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The target once running
+struct Target {
+    /// Dialable address
+    addr: Multiaddr,
+    peer_id: Option<PeerId>,
+    transport: TransportKind,
+    /// In-process target
+    node: Option<(NodeHandle, JoinHandle<Result<()>>)>,
+}
+
+impl Target {
+    async fn start(target: LoadTarget) -> Result<Self> {
+        match target {
+            LoadTarget::InProcess(mut node) => {
+                let results = node.listen_on_multiple(&["/memory/0".to_string()]).await;
+                let addr = results
+                    .into_iter()
+                    .find_map(|r| r.outcome.ok().and_then(|addrs| addrs.into_iter().next()))
+                    .context("In-process target did not bind a memory address")?;
+                let (handle, task) = NodeHandle::attach(*node, false);
+                Ok(Self { addr, peer_id: Some(handle.peer_id()), transport: TransportKind::Memory, node: Some((handle, task)) })
+            }
+            LoadTarget::Remote(addr) => {
+                let peer_id = addr.iter().find_map(|p| match p {
+                    Protocol::P2p(peer_id) => Some(peer_id),
+                    _ => None,
+                });
+                Ok(Self { addr, peer_id, transport: TransportKind::Tcp, node: None })
+            }
+        }
+    }
+
+    async fn counters(&self) -> Option<NodeCounters> {
+        let (handle, _) = self.node.as_ref()?;
+        handle.status().await.ok().map(|status| status.counters)
+    }
+
+    async fn stop(self) -> Result<()> {
+        if let Some((handle, task)) = self.node {
+            handle.shutdown().await;
+            task.await.context("Load test target panicked")??;
+        }
+        Ok(())
+    }
+}
+
+/// What a mode measured
+#[derive(Default)]
+struct Tally {
+    sent: u64,
+    delivered: u64,
+    rejected: u64,
+    failed: u64,
+    latencies: Vec<Duration>,
+}
+
+/// Run `config` against `target` and report
+pub async fn run(config: &LoadTestConfig, target: LoadTarget) -> Result<LoadReport> {
+    if !(config.rate.is_finite() && config.rate > 0.0) {
+        anyhow::bail!("Load test rate must be positive");
+    }
+    if config.mode != LoadMode::Connect && config.clients == 0 {
+        anyhow::bail!("Load test needs at least one client");
+    }
+    if config.mode == LoadMode::Gossip && config.message_size < PAYLOAD_HEADER_LEN {
+        anyhow::bail!("Gossip messages must be at least {} bytes", PAYLOAD_HEADER_LEN);
+    }
+
+    let target = Target::start(target).await?;
+    let before = target.counters().await;
+    let started = Instant::now();
+    let tally = match config.mode {
+        LoadMode::Gossip => run_gossip(config, &target).await,
+        LoadMode::Connect => run_connect(config, &target).await,
+        LoadMode::Request => run_request(config, &target).await,
+    };
+    let elapsed = started.elapsed();
+    let after = target.counters().await;
+    let addr = target.addr.to_string();
+    target.stop().await?;
+    let tally = tally?;
+
+    let load_secs = config.duration.as_secs_f64().max(f64::EPSILON);
+    Ok(LoadReport {
+        mode: config.mode,
+        target: addr,
+        clients: if config.mode == LoadMode::Connect { 0 } else { config.clients },
+        duration_secs: elapsed.as_secs_f64(),
+        sent: tally.sent,
+        delivered: tally.delivered,
+        rejected: tally.rejected,
+        failed: tally.failed,
+        loss: if tally.sent == 0 { 0.0 } else { 1.0 - (tally.delivered.min(tally.sent) as f64 / tally.sent as f64) },
+        throughput: tally.delivered as f64 / load_secs,
+        latency: LatencySummary::from_samples(tally.latencies),
+        target_before: before,
+        target_after: after,
+    })
+}
+
+/// A client node with nothing but an outbound connection to the target
+async fn connect_client(target: &Target) -> Result<(NodeHandle, JoinHandle<Result<()>>)> {
+    let mut node = P2PNode::with_transport(Keypair::generate_ed25519(), target.transport)?;
+    node.disable_mdns();
+    let (handle, task) = NodeHandle::attach(node, false);
+    handle.dial(&target.addr.to_string()).await?;
+    tokio::time::timeout(CONNECT_TIMEOUT, async {
+        while handle.status().await?.connected_peers == 0 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::Ok(())
+    })
+    .await
+    .with_context(|| format!("Client could not connect to {}", target.addr))??;
+    Ok((handle, task))
+}
+
+async fn connect_clients(config: &LoadTestConfig, target: &Target) -> Result<Vec<(NodeHandle, JoinHandle<Result<()>>)>> {
+    let mut clients = Vec::with_capacity(config.clients);
+    for _ in 0..config.clients {
+        clients.push(connect_client(target).await?);
+    }
+    Ok(clients)
+}
+
+async fn stop_clients(clients: Vec<(NodeHandle, JoinHandle<Result<()>>)>) {
+    for (handle, task) in clients {
+        handle.shutdown().await;
+        let _ = task.await;
+    }
+}
+
+/// Ticks `rate` times a second until `duration` has passed
+fn pacer(rate: f64) -> tokio::time::Interval {
+    let mut tick = tokio::time::interval(Duration::from_secs_f64(1.0 / rate));
+    tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    tick
+}
+
+fn now_micros() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_micros() as i64)
+}
+
+/// Gossip payload of `size` bytes stamped with its origin and send time
+pub fn payload(client: u32, seq: u64, size: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(size.max(PAYLOAD_HEADER_LEN));
+    data.extend_from_slice(PAYLOAD_MAGIC);
+    data.extend_from_slice(&client.to_be_bytes());
+    data.extend_from_slice(&seq.to_be_bytes());
+    data.extend_from_slice(&now_micros().to_be_bytes());
+    data.resize(size.max(PAYLOAD_HEADER_LEN), 0);
+    data
+}
+
+/// Client, sequence number and send time of a load test payload
+pub fn parse_payload(data: &[u8]) -> Option<(u32, u64, i64)> {
+    if data.len() < PAYLOAD_HEADER_LEN || &data[..4] != PAYLOAD_MAGIC {
+        return None;
+    }
+    let client = u32::from_be_bytes(data[4..8].try_into().ok()?);
+    let seq = u64::from_be_bytes(data[8..16].try_into().ok()?);
+    let sent_at = i64::from_be_bytes(data[16..24].try_into().ok()?);
+    Some((client, seq, sent_at))
+}
+
+async fn run_gossip(config: &LoadTestConfig, target: &Target) -> Result<Tally> {
+    // An in-process target is watched directly; a remote one through an
+    // observer it relays to
+    let mut observer = None;
+    let stream = match &target.node {
+        Some((handle, _)) => handle.subscribe(&config.topic).await?,
+        None => {
+            let (handle, task) = connect_client(target).await?;
+            // Join once the target's subscriptions are known, so it grafts us
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            let stream = handle.subscribe(&config.topic).await?;
+            observer = Some((handle, task));
+            stream
+        }
+    };
+    let clients = connect_clients(config, target).await?;
+    // Let subscriptions propagate before the first publish
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel();
+    let collector = tokio::spawn(collect_gossip(stream, stop_rx));
+
+    let deadline = tokio::time::Instant::now() + config.duration;
+    let senders: Vec<_> = clients
+        .iter()
+        .enumerate()
+        .map(|(index, (handle, _))| {
+            let (handle, topic, size, rate) = (handle.clone(), config.topic.clone(), config.message_size, config.rate);
+            tokio::spawn(async move {
+                let (mut sent, mut failed) = (0u64, 0u64);
+                let mut tick = pacer(rate);
+                while tokio::time::Instant::now() < deadline {
+                    tick.tick().await;
+                    match handle.publish(&topic, payload(index as u32, sent + failed, size)).await {
+                        Ok(()) => sent += 1,
+                        Err(e) => {
+                            tracing::debug!("Load test publish failed: {:#}", e);
+                            failed += 1;
+                        }
+                    }
+                }
+                (sent, failed)
+            })
+        })
+        .collect();
+
+    let mut tally = Tally::default();
+    for sender in senders {
+        let (sent, failed) = sender.await.context("Load test client panicked")?;
+        tally.sent += sent;
+        tally.failed += failed;
+    }
+    tokio::time::sleep(config.drain).await;
+    let _ = stop_tx.send(());
+    let (delivered, latencies) = collector.await.context("Load test collector panicked")?;
+    tally.delivered = delivered;
+    tally.latencies = latencies;
+
+    stop_clients(clients).await;
+    if let Some(observer) = observer {
+        stop_clients(vec![observer]).await;
+    }
+    Ok(tally)
+}
+
+/// Distinct load test messages seen until told to stop, with their latencies
+async fn collect_gossip(mut stream: EventStream, mut stop: tokio::sync::oneshot::Receiver<()>) -> (u64, Vec<Duration>) {
+    let mut seen = HashSet::new();
+    let mut latencies = Vec::new();
+    loop {
+        tokio::select! {
+            _ = &mut stop => break,
+            event = stream.next() => match event {
+                Some(super::P2PEvent::Message { data, .. }) => {
+                    let Some((client, seq, sent_at)) = parse_payload(&data) else { continue };
+                    if seen.insert((client, seq)) {
+                        let micros = (now_micros() - sent_at).max(0) as u64;
+                        latencies.push(Duration::from_micros(micros));
+                    }
+                }
+                Some(_) => continue,
+                None => break,
+            },
+        }
+    }
+    (seen.len() as u64, latencies)
+}
+
+/// One connection attempt from a fresh peer
+enum Attempt {
+    Kept(Duration),
+    Rejected(Duration),
+    Failed,
+}
+
+async fn attempt_connection(target: Multiaddr, transport: TransportKind) -> Attempt {
+    let Ok(mut node) = P2PNode::with_transport(Keypair::generate_ed25519(), transport) else { return Attempt::Failed };
+    node.disable_mdns();
+    let started = Instant::now();
+    if node.swarm.dial(target).is_err() {
+        return Attempt::Failed;
+    }
+    let outcome = tokio::time::timeout(CONNECT_TIMEOUT, async {
+        let mut accepted: Option<Instant> = None;
+        loop {
+            let wait = match accepted {
+                Some(at) => REJECTION_WINDOW.saturating_sub(at.elapsed()),
+                None => CONNECT_TIMEOUT,
+            };
+            let accepted_after = accepted.map(|at| at.duration_since(started));
+            match tokio::time::timeout(wait, node.poll_events()).await {
+                Err(_) => return accepted_after.map_or(Attempt::Failed, Attempt::Kept),
+                Ok(Some(SwarmEvent::ConnectionEstablished { .. })) if accepted.is_none() => accepted = Some(Instant::now()),
+                Ok(Some(SwarmEvent::ConnectionClosed { .. })) => {
+                    return accepted_after.map_or(Attempt::Failed, Attempt::Rejected);
+                }
+                Ok(Some(SwarmEvent::OutgoingConnectionError { .. })) | Ok(None) => return Attempt::Failed,
+                Ok(Some(_)) => continue,
+            }
+        }
+    })
+    .await;
+    outcome.unwrap_or(Attempt::Failed)
+}
+
+async fn run_connect(config: &LoadTestConfig, target: &Target) -> Result<Tally> {
+    let deadline = tokio::time::Instant::now() + config.duration;
+    let mut tick = pacer(config.rate);
+    let mut attempts = Vec::new();
+    while tokio::time::Instant::now() < deadline {
+        tick.tick().await;
+        attempts.push(tokio::spawn(attempt_connection(target.addr.clone(), target.transport)));
+    }
+
+    let mut tally = Tally::default();
+    for attempt in attempts {
+        tally.sent += 1;
+        match attempt.await.context("Connection attempt panicked")? {
+            Attempt::Kept(latency) => {
+                tally.delivered += 1;
+                tally.latencies.push(latency);
+            }
+            Attempt::Rejected(latency) => {
+                tally.rejected += 1;
+                tally.latencies.push(latency);
+            }
+            Attempt::Failed => tally.failed += 1,
+        }
+    }
+    Ok(tally)
+}
+
+async fn run_request(config: &LoadTestConfig, target: &Target) -> Result<Tally> {
+    let peer = target
+        .peer_id
+        .with_context(|| format!("Request mode needs the target's peer ID: {}/p2p/<peer id>", target.addr))?;
+    let clients = connect_clients(config, target).await?;
+
+    let deadline = tokio::time::Instant::now() + config.duration;
+    let workers: Vec<_> = clients
+        .iter()
+        .map(|(handle, _)| {
+            let (handle, request, rate) = (handle.clone(), config.request.clone(), config.rate);
+            tokio::spawn(async move {
+                let mut tally = Tally::default();
+                let mut tick = pacer(rate);
+                while tokio::time::Instant::now() < deadline {
+                    tick.tick().await;
+                    tally.sent += 1;
+                    let started = Instant::now();
+                    match handle.request(peer, request.request()).await {
+                        Ok(QuantraResponse::Error(_)) | Ok(QuantraResponse::InvalidRequest { .. }) => tally.rejected += 1,
+                        Ok(_) => {
+                            tally.delivered += 1;
+                            tally.latencies.push(started.elapsed());
+                        }
+                        Err(e) => {
+                            tracing::debug!("Load test request failed: {:#}", e);
+                            tally.failed += 1;
+                        }
+                    }
+                }
+                tally
+            })
+        })
+        .collect();
+
+    let mut tally = Tally::default();
+    for worker in workers {
+        let part = worker.await.context("Load test client panicked")?;
+        tally.sent += part.sent;
+        tally.delivered += part.delivered;
+        tally.rejected += part.rejected;
+        tally.failed += part.failed;
+        tally.latencies.extend(part.latencies);
+    }
+    stop_clients(clients).await;
+    Ok(tally)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target() -> LoadTarget {
+        let mut node = P2PNode::with_transport(Keypair::generate_ed25519(), TransportKind::Memory).unwrap();
+        node.disable_mdns();
+        LoadTarget::InProcess(Box::new(node))
+    }
+
+    #[test]
+    fn test_payload_round_trip() {
+        let data = payload(3, 42, 64);
+        assert_eq!(data.len(), 64);
+        let (client, seq, sent_at) = parse_payload(&data).unwrap();
+        assert_eq!((client, seq), (3, 42));
+        assert!(sent_at > 0);
+        assert!(parse_payload(b"hello").is_none());
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let samples = (1..=100).map(Duration::from_millis).collect();
+        let summary = LatencySummary::from_samples(samples).unwrap();
+        assert_eq!((summary.p50_ms, summary.p90_ms, summary.p99_ms, summary.max_ms), (50.0, 90.0, 99.0, 100.0));
+        assert_eq!(summary.mean_ms, 50.5);
+        assert!(LatencySummary::from_samples(Vec::new()).is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_gossip_load_against_in_process_node() {
+        let config = LoadTestConfig {
+            clients: 2,
+            rate: 5.0,
+            duration: Duration::from_secs(1),
+            message_size: 128,
+            drain: Duration::from_secs(1),
+            ..Default::default()
+        };
+        let report = run(&config, target()).await.unwrap();
+
+        assert_eq!((report.mode, report.clients), (LoadMode::Gossip, 2));
+        assert!(report.target.starts_with("/memory/"), "{}", report.target);
+        assert!(report.sent > 0 && report.delivered > 0, "{:?}", report);
+        assert!(report.delivered <= report.sent);
+        assert!((0.0..=1.0).contains(&report.loss));
+        let latency = report.latency.unwrap();
+        assert!(latency.min_ms <= latency.p50_ms && latency.p50_ms <= latency.p99_ms && latency.p99_ms <= latency.max_ms);
+        let (before, after) = (report.target_before.unwrap(), report.target_after.unwrap());
+        assert!(after.gossip_received >= before.gossip_received + report.delivered);
+        assert!(after.connections_accepted >= 2);
+
+        let json = serde_json::to_value(&report).unwrap();
+        for field in ["mode", "sent", "delivered", "loss", "throughput", "latency", "target_before", "target_after"] {
+            assert!(json.get(field).is_some(), "missing {}", field);
+        }
+        assert_eq!(json["mode"], "gossip");
+        assert!(report.to_string().contains("p99"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_request_load_against_in_process_node() {
+        let config = LoadTestConfig {
+            mode: LoadMode::Request,
+            clients: 2,
+            rate: 20.0,
+            duration: Duration::from_millis(500),
+            request: "quote:MSFT".parse().unwrap(),
+            ..Default::default()
+        };
+        let report = run(&config, target()).await.unwrap();
+        assert!(report.delivered > 0 && report.failed == 0, "{:?}", report);
+        assert!(report.target_after.unwrap().requests_received >= report.sent);
+    }
+}
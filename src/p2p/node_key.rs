@@ -0,0 +1,80 @@
+//! Node Key
+//! The libp2p identity kept on disk, protobuf-encoded as libp2p writes it,
+//! so the peer ID (and what other peers know about it) survives restarts
+
+use anyhow::{Context, Result};
+use libp2p::identity::Keypair;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::data_dirs::restrict_to_owner;
+
+/// The node key file exists but doesn't decode. It is never replaced
+/// automatically, as that would silently change the peer ID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptNodeKey {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+impl fmt::Display for CorruptNodeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "node key {} is not a protobuf-encoded keypair ({}); restore it, or remove it to start with a new peer ID",
+            self.path.display(),
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for CorruptNodeKey {}
+
+/// The keypair in `path`, or a new Ed25519 one saved there owner-only
+pub fn load_or_generate(path: &Path) -> Result<Keypair> {
+    match std::fs::read(path) {
+        Ok(bytes) => Keypair::from_protobuf_encoding(&bytes).map_err(|e| {
+            CorruptNodeKey { path: path.to_path_buf(), reason: e.to_string() }.into()
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let key = Keypair::generate_ed25519();
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            std::fs::write(path, key.to_protobuf_encoding()?)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            restrict_to_owner(path).context("Failed to restrict node key permissions")?;
+            tracing::info!("🆔 Generated node key {} at {}", key.public().to_peer_id(), path.display());
+            Ok(key)
+        }
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_corrupt_key_is_an_error() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("node_key");
+        std::fs::write(&path, b"not a key").unwrap();
+
+        let err = load_or_generate(&path).unwrap_err();
+        assert_eq!(err.downcast_ref::<CorruptNodeKey>().map(|e| e.path.clone()), Some(path.clone()));
+        assert_eq!(std::fs::read(&path).unwrap(), b"not a key", "corrupt key was replaced");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_generated_key_is_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("keys/node_key");
+
+        load_or_generate(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+}
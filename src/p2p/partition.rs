@@ -0,0 +1,377 @@
+//! Partition Detection
+//! Per-topic mesh health: subscribed peers against a recent high-water mark,
+//! and time since a message another node originated. Data made while a
+//! topic looks cut off carries a `PartitionStamp`, so that after a heal the
+//! larger, fresher side's version wins instead of whichever arrived last
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::security::notifications::SinkEvent;
+use crate::units::HumanDuration;
+
+/// `[p2p.partition]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PartitionConfig {
+    /// Suspected below this fraction of the baseline peer count
+    pub suspect_ratio: f64,
+    /// Partitioned below this fraction, once it has lasted `confirm_after`
+    pub partition_ratio: f64,
+    pub confirm_after: HumanDuration,
+    /// Suspected when no other node's message has arrived for this long;
+    /// zero turns the check off
+    pub quiet_after: HumanDuration,
+    /// The baseline is the most peers seen within this window
+    pub baseline_window: HumanDuration,
+}
+
+impl Default for PartitionConfig {
+    fn default() -> Self {
+        Self {
+            suspect_ratio: 0.75,
+            partition_ratio: 0.5,
+            confirm_after: HumanDuration::from_secs(30),
+            quiet_after: HumanDuration::from_secs(0),
+            baseline_window: HumanDuration::from_secs(3600),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionStatus {
+    Healthy,
+    Suspected,
+    Partitioned,
+}
+
+impl fmt::Display for PartitionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Healthy => "healthy",
+            Self::Suspected => "suspected",
+            Self::Partitioned => "partitioned",
+        })
+    }
+}
+
+/// Marks data made while its topic was suspected or partitioned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionStamp {
+    /// This node's partition episodes on the topic so far
+    pub epoch: u64,
+    /// Topic peers this node could reach
+    pub peer_count: usize,
+    /// Last message another node originated on the topic
+    pub last_seen_external: Option<DateTime<Utc>>,
+}
+
+/// Which of two versions of the same data to keep: one made on a healthy
+/// mesh, else the one from the side with more peers, else the one that
+/// heard from other nodes more recently. `Greater` means `a`
+pub fn merge_order(a: Option<&PartitionStamp>, b: Option<&PartitionStamp>) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => (a.peer_count, a.last_seen_external).cmp(&(b.peer_count, b.last_seen_external)),
+    }
+}
+
+/// One topic's mesh, for status output
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TopicPartition {
+    pub topic: String,
+    pub status: PartitionStatus,
+    pub peers: usize,
+    pub baseline: usize,
+    pub last_seen_external: Option<DateTime<Utc>>,
+    pub epoch: u64,
+}
+
+/// A topic entering or leaving a partition
+#[derive(Debug, Clone, PartialEq)]
+pub enum PartitionChange {
+    Detected { topic: String, peers: usize, baseline: usize, epoch: u64 },
+    Healed { topic: String, peers: usize, baseline: usize, epoch: u64, lasted: ChronoDuration },
+}
+
+impl PartitionChange {
+    pub fn to_sink_event(&self) -> SinkEvent {
+        match self.clone() {
+            Self::Detected { topic, peers, baseline, epoch } => SinkEvent::PartitionDetected { topic, peers, baseline, epoch },
+            Self::Healed { topic, peers, baseline, epoch, lasted } => SinkEvent::PartitionHealed {
+                topic,
+                peers,
+                baseline,
+                epoch,
+                duration_secs: lasted.num_seconds().max(0) as u64,
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TopicHealth {
+    peers: usize,
+    baseline: usize,
+    baseline_at: DateTime<Utc>,
+    last_seen_external: Option<DateTime<Utc>>,
+    status: PartitionStatus,
+    /// Start of the current suspected / partitioned episode
+    degraded_since: Option<DateTime<Utc>>,
+    epoch: u64,
+    /// The episode reached partitioned, so its heal is reported too
+    detected: bool,
+}
+
+impl TopicHealth {
+    fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            peers: 0,
+            baseline: 0,
+            baseline_at: now,
+            last_seen_external: None,
+            status: PartitionStatus::Healthy,
+            degraded_since: None,
+            epoch: 0,
+            detected: false,
+        }
+    }
+}
+
+/// Mesh health of every subscribed topic
+pub struct PartitionMonitor {
+    config: PartitionConfig,
+    topics: HashMap<String, TopicHealth>,
+}
+
+impl PartitionMonitor {
+    pub fn new(config: PartitionConfig) -> Self {
+        Self { config, topics: HashMap::new() }
+    }
+
+    pub fn config(&self) -> &PartitionConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: PartitionConfig) {
+        self.config = config;
+    }
+
+    /// A message another node originated arrived on `topic`
+    pub fn saw_external(&mut self, topic: &str, now: DateTime<Utc>) {
+        match self.topics.get_mut(topic) {
+            Some(health) => health.last_seen_external = Some(now),
+            None => {
+                let mut health = TopicHealth::new(now);
+                health.last_seen_external = Some(now);
+                self.topics.insert(topic.to_string(), health);
+            }
+        }
+    }
+
+    /// Judge each topic from its current peer count. Topics missing from
+    /// `peers` are no longer subscribed and are forgotten
+    pub fn observe(&mut self, peers: &HashMap<String, usize>, now: DateTime<Utc>) -> Vec<PartitionChange> {
+        self.topics.retain(|topic, _| peers.contains_key(topic));
+        let mut changes = Vec::new();
+        for (topic, &count) in peers {
+            let health = self.topics.entry(topic.clone()).or_insert_with(|| TopicHealth::new(now));
+            health.peers = count;
+            // A modestly smaller mesh becomes the new normal after a window;
+            // one under `partition_ratio` keeps its baseline until peers return
+            let expired = now - health.baseline_at >= self.config.baseline_window.as_chrono();
+            let severe = (count as f64) < health.baseline as f64 * self.config.partition_ratio;
+            if count >= health.baseline || (expired && !severe) {
+                health.baseline = count;
+                health.baseline_at = now;
+            }
+
+            let status = classify(&self.config, health, now);
+            match (health.status, status) {
+                (PartitionStatus::Healthy, PartitionStatus::Healthy) => {}
+                (PartitionStatus::Healthy, _) => {
+                    health.epoch += 1;
+                    health.degraded_since = Some(now);
+                }
+                (_, PartitionStatus::Healthy) => {
+                    if health.detected {
+                        changes.push(PartitionChange::Healed {
+                            topic: topic.clone(),
+                            peers: count,
+                            baseline: health.baseline,
+                            epoch: health.epoch,
+                            lasted: now - health.degraded_since.unwrap_or(now),
+                        });
+                    }
+                    health.degraded_since = None;
+                    health.detected = false;
+                }
+                _ => {}
+            }
+            if status == PartitionStatus::Partitioned && !health.detected {
+                health.detected = true;
+                changes.push(PartitionChange::Detected {
+                    topic: topic.clone(),
+                    peers: count,
+                    baseline: health.baseline,
+                    epoch: health.epoch,
+                });
+            }
+            health.status = status;
+        }
+        changes
+    }
+
+    pub fn status(&self, topic: &str) -> PartitionStatus {
+        self.topics.get(topic).map_or(PartitionStatus::Healthy, |health| health.status)
+    }
+
+    /// Stamp for data made now from `topic`; `None` while it's healthy
+    pub fn stamp(&self, topic: &str) -> Option<PartitionStamp> {
+        let health = self.topics.get(topic)?;
+        (health.status != PartitionStatus::Healthy).then_some(PartitionStamp {
+            epoch: health.epoch,
+            peer_count: health.peers,
+            last_seen_external: health.last_seen_external,
+        })
+    }
+
+    /// Every tracked topic, by name
+    pub fn topics(&self) -> Vec<TopicPartition> {
+        let mut topics: Vec<TopicPartition> = self
+            .topics
+            .iter()
+            .map(|(topic, health)| TopicPartition {
+                topic: topic.clone(),
+                status: health.status,
+                peers: health.peers,
+                baseline: health.baseline,
+                last_seen_external: health.last_seen_external,
+                epoch: health.epoch,
+            })
+            .collect();
+        topics.sort_by(|a, b| a.topic.cmp(&b.topic));
+        topics
+    }
+}
+
+fn classify(config: &PartitionConfig, health: &TopicHealth, now: DateTime<Utc>) -> PartitionStatus {
+    let below = |ratio: f64| (health.peers as f64) < health.baseline as f64 * ratio;
+    let quiet_after = config.quiet_after.as_chrono();
+    let quiet = !config.quiet_after.as_std().is_zero()
+        && health.last_seen_external.is_some_and(|seen| now - seen >= quiet_after);
+    if !below(config.suspect_ratio) && !quiet {
+        return PartitionStatus::Healthy;
+    }
+    let since = health.degraded_since.unwrap_or(now);
+    match below(config.partition_ratio) && now - since >= config.confirm_after.as_chrono() {
+        true => PartitionStatus::Partitioned,
+        false => PartitionStatus::Suspected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_750_000_000 + secs, 0).unwrap()
+    }
+
+    fn peers(count: usize) -> HashMap<String, usize> {
+        [("prices".to_string(), count)].into()
+    }
+
+    #[test]
+    fn test_shrinking_mesh_is_suspected_then_partitioned() {
+        let mut monitor = PartitionMonitor::new(PartitionConfig::default());
+        assert!(monitor.observe(&peers(8), at(0)).is_empty());
+        assert_eq!(monitor.stamp("prices"), None);
+
+        // 5 of 8 is under 75%; 3 of 8 is under half but not for 30s yet
+        assert!(monitor.observe(&peers(5), at(10)).is_empty());
+        assert_eq!(monitor.status("prices"), PartitionStatus::Suspected);
+        assert!(monitor.observe(&peers(3), at(20)).is_empty());
+        assert_eq!(monitor.status("prices"), PartitionStatus::Suspected);
+
+        let changes = monitor.observe(&peers(3), at(40));
+        assert_eq!(changes, vec![PartitionChange::Detected { topic: "prices".to_string(), peers: 3, baseline: 8, epoch: 1 }]);
+        assert!(monitor.observe(&peers(3), at(41)).is_empty(), "reported once per episode");
+        assert_eq!(monitor.stamp("prices").map(|s| (s.epoch, s.peer_count)), Some((1, 3)));
+
+        let changes = monitor.observe(&peers(7), at(100));
+        assert!(matches!(&changes[..], [PartitionChange::Healed { peers: 7, epoch: 1, lasted, .. }] if lasted.num_seconds() == 90));
+        assert_eq!(monitor.stamp("prices"), None);
+    }
+
+    #[test]
+    fn test_suspicion_that_clears_is_not_reported() {
+        let mut monitor = PartitionMonitor::new(PartitionConfig::default());
+        monitor.observe(&peers(4), at(0));
+        assert!(monitor.observe(&peers(2), at(1)).is_empty());
+        assert!(monitor.observe(&peers(4), at(2)).is_empty());
+        assert_eq!(monitor.topics()[0].epoch, 1);
+        monitor.observe(&peers(1), at(3));
+        assert_eq!(monitor.topics()[0].epoch, 2);
+    }
+
+    #[test]
+    fn test_baseline_relaxes_unless_severe() {
+        let config = PartitionConfig { baseline_window: HumanDuration::from_secs(60), ..Default::default() };
+        let mut monitor = PartitionMonitor::new(config);
+        monitor.observe(&peers(4), at(0));
+        monitor.observe(&peers(3), at(1));
+        assert_eq!(monitor.status("prices"), PartitionStatus::Healthy);
+        // A smaller mesh that lasts the window becomes the new normal
+        monitor.observe(&peers(2), at(30));
+        assert_eq!(monitor.status("prices"), PartitionStatus::Suspected);
+        monitor.observe(&peers(2), at(61));
+        assert_eq!((monitor.status("prices"), monitor.topics()[0].baseline), (PartitionStatus::Healthy, 2));
+
+        // ...but not a mesh under half its size
+        monitor.observe(&peers(0), at(100));
+        monitor.observe(&peers(0), at(200));
+        assert_eq!((monitor.status("prices"), monitor.topics()[0].baseline), (PartitionStatus::Partitioned, 2));
+    }
+
+    #[test]
+    fn test_quiet_topic_is_suspected() {
+        let config = PartitionConfig { quiet_after: HumanDuration::from_secs(60), ..Default::default() };
+        let mut monitor = PartitionMonitor::new(config);
+        monitor.observe(&peers(3), at(0));
+        monitor.saw_external("prices", at(0));
+        monitor.observe(&peers(3), at(59));
+        assert_eq!(monitor.status("prices"), PartitionStatus::Healthy);
+        monitor.observe(&peers(3), at(60));
+        assert_eq!(monitor.status("prices"), PartitionStatus::Suspected);
+        // Peers are all there, so quiet alone never confirms a partition
+        assert!(monitor.observe(&peers(3), at(600)).is_empty());
+        assert_eq!(monitor.stamp("prices").unwrap().last_seen_external, Some(at(0)));
+
+        monitor.saw_external("prices", at(601));
+        monitor.observe(&peers(3), at(601));
+        assert_eq!(monitor.status("prices"), PartitionStatus::Healthy);
+
+        // Unsubscribed topics are dropped
+        monitor.observe(&HashMap::new(), at(602));
+        assert!(monitor.topics().is_empty());
+    }
+
+    #[test]
+    fn test_merge_order() {
+        let stamp = |peer_count, seen: Option<i64>| PartitionStamp { epoch: 1, peer_count, last_seen_external: seen.map(at) };
+        let (small, large) = (stamp(1, Some(50)), stamp(3, Some(10)));
+        assert_eq!(merge_order(None, Some(&large)), Ordering::Greater);
+        assert_eq!(merge_order(Some(&large), None), Ordering::Less);
+        assert_eq!(merge_order(Some(&large), Some(&small)), Ordering::Greater);
+        assert_eq!(merge_order(Some(&stamp(3, Some(20))), Some(&large)), Ordering::Greater);
+        assert_eq!(merge_order(Some(&stamp(3, None)), Some(&large)), Ordering::Less);
+        assert_eq!(merge_order(None, None), Ordering::Equal);
+    }
+}
@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::terminal::{sanitize_for_terminal, LABEL_RENDER_LEN};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Peer {
@@ -27,3 +30,13 @@ impl Peer {
         self.last_seen = chrono::Utc::now().timestamp();
     }
 }
+
+/// Nicknames are chosen by the peer, so they are sanitized for display
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.nickname {
+            Some(nickname) => write!(f, "{} ({})", sanitize_for_terminal(nickname, LABEL_RENDER_LEN), self.id),
+            None => write!(f, "{}", self.id),
+        }
+    }
+}
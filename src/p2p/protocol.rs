@@ -1,7 +1,70 @@
 use libp2p::StreamProtocol;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use crate::crypto::sealed;
+use crate::esim::carrier_updates::CarrierDbUpdate;
+use crate::p2p::cover;
+use crate::p2p::groups::GroupUpdate;
+use crate::p2p::receipts::ReceiptKind;
+use crate::p2p::retention::{ReplayFrom, RetainedMessage};
+use crate::p2p::transcript::TranscriptRange;
+use crate::p2p::transparency::{KeyAssertion, BUCKETS};
+use crate::p2p::wire::WireFormat;
+use crate::quant::market_data::OrderBookSnapshot;
+use crate::security::guardians::{GuardianApproval, SignedApprovalRequest};
+use crate::quant::remote::{PricingInputs, PricingModel, PricingResult};
+use crate::trace::TraceId;
+use crate::units::{HumanDuration, HumanSize};
+use crate::zerotrust::attestation::{SignedAttestation, NONCE_LEN};
+use crate::zerotrust::identity::Identity;
+use crate::zerotrust::SecurityLevel;
 
 pub const QUANTRA_PROTOCOL: StreamProtocol = StreamProtocol::new("/quantra/1.0.0");
+/// Requests are sent as a `RequestEnvelope` (with a trace ID); peers that
+/// only speak 1.0.0 get the bare request
+pub const QUANTRA_PROTOCOL_V1_1: StreamProtocol = StreamProtocol::new("/quantra/1.1.0");
+/// 1.1.0 with the body format named in the ID (see `wire::WireFormat`)
+pub const QUANTRA_PROTOCOL_V1_1_CBOR: StreamProtocol = StreamProtocol::new("/quantra/1.1.0/cbor");
+pub const QUANTRA_PROTOCOL_V1_1_JSON: StreamProtocol = StreamProtocol::new("/quantra/1.1.0/json");
+
+/// 1.1.0 with full quotes (bid, ask, last, volume) and `UnknownSymbol`;
+/// older protocols get the single-price quote they always did
+pub const QUANTRA_PROTOCOL_V1_2_CBOR: StreamProtocol = StreamProtocol::new("/quantra/1.2.0/cbor");
+pub const QUANTRA_PROTOCOL_V1_2_JSON: StreamProtocol = StreamProtocol::new("/quantra/1.2.0/json");
+
+/// Every protocol, in the order a dialer offers them: newest first, and
+/// within a version the `preferred` format first
+pub fn protocols(preferred: WireFormat) -> [StreamProtocol; 6] {
+    let [first, second, third, fourth] = match preferred {
+        WireFormat::CborCanonical => {
+            [QUANTRA_PROTOCOL_V1_2_CBOR, QUANTRA_PROTOCOL_V1_2_JSON, QUANTRA_PROTOCOL_V1_1_CBOR, QUANTRA_PROTOCOL_V1_1_JSON]
+        }
+        WireFormat::Json => {
+            [QUANTRA_PROTOCOL_V1_2_JSON, QUANTRA_PROTOCOL_V1_2_CBOR, QUANTRA_PROTOCOL_V1_1_JSON, QUANTRA_PROTOCOL_V1_1_CBOR]
+        }
+    };
+    [first, second, third, fourth, QUANTRA_PROTOCOL_V1_1, QUANTRA_PROTOCOL]
+}
+
+/// Whether responses on `protocol` carry full quotes
+pub fn has_full_quotes(protocol: &StreamProtocol) -> bool {
+    protocol.as_ref().starts_with("/quantra/1.2.0")
+}
+
+/// A request with the sender's correlation ID
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestEnvelope {
+    /// Absent from 1.0.0 peers
+    #[serde(default)]
+    pub trace_id: Option<String>,
+    pub request: QuantraRequest,
+}
+
+impl RequestEnvelope {
+    pub fn new(request: QuantraRequest, trace_id: Option<&TraceId>) -> Self {
+        Self { trace_id: trace_id.map(|id| id.to_string()), request }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum QuantraRequest {
@@ -10,6 +73,56 @@ pub enum QuantraRequest {
     SendMessage { encrypted_data: Vec<u8> },
     GetQuote { symbol: String },
     ProvisionESim { profile_data: Vec<u8> },
+    /// Clean disconnect; the responder replies with a resumption token
+    /// bound to `identity`, the requester's node identity
+    Disconnect { identity: Identity },
+    /// Resume a previous zero-trust session; `proof` is `token` signed
+    /// with the key of the identity the token was bound to
+    Resume { token: String, identity: Identity, proof: Vec<u8> },
+    /// Proof-of-work required before admission: find a nonce such that
+    /// SHA-256(prefix || nonce || peer_id) has `difficulty` leading zero bits
+    AdmissionChallenge { prefix: Vec<u8>, difficulty: u8 },
+    /// Solution to an admission challenge
+    AdmissionSolution { nonce: u64 },
+    /// L2 snapshot for a `market-depth/<symbol>` topic
+    GetDepth { symbol: String, levels: u32 },
+    /// Signed carrier database updates newer than `since_version`
+    GetCarrierDb { since_version: u32 },
+    /// Clock offset probe; `t1` is the sender's Unix time in milliseconds
+    TimeSync { t1: i64 },
+    /// Ask the owner of `identity` (expired, in its grace period) for its
+    /// renewed identity
+    RenewIdentity { identity: Identity },
+    /// New group roster from its owner, with the epoch key sealed to us
+    GroupUpdate { update: GroupUpdate },
+    /// Ask a group owner for the key of an epoch we were a member of
+    GetGroupKey { group_id: String, epoch: u64 },
+    /// Ask the other party of a chat to co-sign our transcript head; it
+    /// may decline with `Error`
+    SignTranscript { head_hash: String, range: TranscriptRange },
+    /// Price an option with the responder's engine; needs a `quant/pricing`
+    /// grant and counts against the sender's pricing budget
+    PriceOption { inputs: PricingInputs, model: PricingModel },
+    /// Price one option at each of `strikes`
+    PriceChain { inputs: PricingInputs, strikes: Vec<f64>, model: PricingModel },
+    /// A direct message we sent reached the peer, or was read; `timestamp`
+    /// is the peer's clock
+    Receipt { message_id: String, kind: ReceiptKind, timestamp: chrono::DateTime<chrono::Utc> },
+    /// The responder's build manifest, signed with its peer key over `nonce`
+    GetAttestation { nonce: Vec<u8> },
+    /// Recent messages the responder retained on `topic`; `continuation`
+    /// (from the previous page) takes precedence over `from`
+    TopicReplay { topic: String, from: ReplayFrom, continuation: Option<String> },
+    /// A ward asks its guardian to approve a destructive emergency response
+    EmergencyApprovalRequest { request: SignedApprovalRequest },
+    /// A guardian's approval of one of our emergency requests
+    EmergencyApproval { approval: GuardianApproval },
+    /// Our key log's bucket digests (`transparency::BUCKETS` of them); the
+    /// responder sends its entries in the buckets that differ
+    KeyLogSync { buckets: Vec<String> },
+    /// The requester's cover key, sealed to the responder, so its decoys
+    /// can be recognised and dropped
+    CoverKey { sealed_key: Vec<u8> },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,7 +130,248 @@ pub enum QuantraResponse {
     Pong,
     Peers(Vec<String>),
     MessageSent,
-    Quote { symbol: String, price: f64, timestamp: i64 },
+    /// Top of book and last trade; `timestamp` is Unix seconds
+    Quote { symbol: String, bid: Decimal, ask: Decimal, last: Decimal, volume: u64, timestamp: i64 },
+    /// The responder's market data has no quotes for the symbol
+    UnknownSymbol { symbol: String },
     ESimProvisioned { activation_code: String },
+    Resumption { token: String },
+    Resumed { security_level: SecurityLevel },
+    /// Challenge received; the solution follows as a separate request
+    ChallengeAccepted,
+    Admitted,
+    Depth(OrderBookSnapshot),
+    CarrierDb(Vec<CarrierDbUpdate>),
+    /// Echoed `t1`, with the responder's receive and send times
+    TimeSync { t1: i64, t2: i64, t3: i64 },
+    /// The responder's current identity, renewing the one presented
+    IdentityRenewed { identity: Identity },
+    GroupUpdateAccepted,
+    /// Roster and sealed key for a requested epoch
+    GroupKey(GroupUpdate),
+    /// Hex signature over the requested transcript head
+    TranscriptSigned { signature: String },
+    OptionPriced(PricingResult),
+    /// One result per requested strike, in order
+    ChainPriced(Vec<PricingResult>),
+    /// Also sent for receipts about unknown messages, so they aren't retried
+    ReceiptAccepted,
+    Attestation(SignedAttestation),
+    /// One page of a topic replay, oldest first; `continuation` fetches the next
+    TopicReplay { topic: String, messages: Vec<RetainedMessage>, continuation: Option<String> },
+    /// Held for the guardian's operator; an approval follows as a request
+    EmergencyRequestQueued { approval_id: String },
+    /// Approvals counted so far towards the request
+    EmergencyApprovalAccepted { approvals: usize },
+    /// Key log entries from differing buckets; more may follow next round
+    KeyLogEntries(Vec<KeyAssertion>),
+    /// The responder takes part in cover traffic and holds the key
+    CoverKeyAccepted,
+    /// The request broke `RequestLimits` and reached no handler
+    InvalidRequest { reason: String },
     Error(String),
 }
+
+/// `[p2p.request_limits]`: structural bounds on inbound requests, checked
+/// right after decoding, before any handler or policy runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestLimits {
+    /// Longest symbol, token, ID or hash
+    pub max_string_len: usize,
+    /// Largest opaque payload: sealed message, eSIM profile, sealed key
+    pub max_payload: HumanSize,
+    /// Most members in a group roster
+    pub max_group_members: usize,
+    /// Most attributes on an identity
+    pub max_identity_attributes: usize,
+    /// Most strikes in one `PriceChain`
+    pub max_chain_strikes: usize,
+    pub max_binomial_steps: usize,
+    pub max_mc_paths: u64,
+    /// Compute-heavy requests (pricing) one peer may have running at once
+    pub max_concurrent_per_peer: usize,
+    /// Time one compute-heavy request may run before it is cancelled
+    pub compute_budget: HumanDuration,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            max_string_len: 256,
+            max_payload: HumanSize::from_bytes(512 * 1024),
+            max_group_members: 1024,
+            max_identity_attributes: 64,
+            max_chain_strikes: 10_000,
+            max_binomial_steps: 100_000,
+            max_mc_paths: 10_000_000,
+            max_concurrent_per_peer: 4,
+            compute_budget: HumanDuration::from_secs(5),
+        }
+    }
+}
+
+impl QuantraRequest {
+    /// Pricing: run off the event loop, within `RequestLimits::compute_budget`
+    pub fn is_compute_heavy(&self) -> bool {
+        matches!(self, Self::PriceOption { .. } | Self::PriceChain { .. })
+    }
+
+    /// Why the request breaks `limits`, if it does. Only checks shape and
+    /// sanity; whether the peer may make it is up to the handler
+    pub fn validate(&self, limits: &RequestLimits) -> Result<(), String> {
+        let string = |field: &str, value: &str| match value.len() > limits.max_string_len {
+            true => Err(format!("{} is {} bytes (limit {})", field, value.len(), limits.max_string_len)),
+            false => Ok(()),
+        };
+        let payload = |field: &str, value: &[u8]| match value.len() as u64 > limits.max_payload.bytes() {
+            true => Err(format!("{} is {} bytes (limit {})", field, value.len(), limits.max_payload.bytes())),
+            false => Ok(()),
+        };
+        let identity_fields = |identity: &Identity| {
+            string("user_id", &identity.user_id)?;
+            payload("public_key", &identity.public_key)?;
+            payload("signature", &identity.signature)?;
+            if identity.attributes.len() > limits.max_identity_attributes {
+                return Err(format!(
+                    "{} identity attributes (limit {})",
+                    identity.attributes.len(),
+                    limits.max_identity_attributes
+                ));
+            }
+            identity.attributes.iter().try_for_each(|(k, v)| string("attribute", k).and(string("attribute", v)))
+        };
+        match self {
+            Self::Ping | Self::GetPeers | Self::AdmissionSolution { .. } => Ok(()),
+            Self::GetCarrierDb { .. } | Self::TimeSync { .. } => Ok(()),
+            Self::SendMessage { encrypted_data } => payload("encrypted_data", encrypted_data),
+            Self::GetQuote { symbol } | Self::GetDepth { symbol, .. } => string("symbol", symbol),
+            Self::ProvisionESim { profile_data } => {
+                if profile_data.is_empty() {
+                    return Err("profile_data is empty".to_string());
+                }
+                payload("profile_data", profile_data)
+            }
+            Self::Disconnect { identity } => identity_fields(identity),
+            Self::Resume { token, identity, proof } => {
+                // Tokens carry the session they resume and outgrow `max_string_len`
+                payload("token", token.as_bytes())?;
+                payload("proof", proof)?;
+                identity_fields(identity)
+            }
+            Self::AdmissionChallenge { prefix, .. } => payload("prefix", prefix),
+            Self::RenewIdentity { identity } => identity_fields(identity),
+            Self::GroupUpdate { update } => {
+                let roster = &update.roster;
+                for (field, value) in [
+                    ("group_id", &roster.group_id),
+                    ("name", &roster.name),
+                    ("owner", &roster.owner),
+                    ("key_commitment", &roster.key_commitment),
+                    ("signature", &roster.signature),
+                ] {
+                    string(field, value)?;
+                }
+                if roster.members.len() > limits.max_group_members {
+                    return Err(format!("{} group members (limit {})", roster.members.len(), limits.max_group_members));
+                }
+                payload("sealed_key", update.sealed_key.as_deref().unwrap_or_default())
+            }
+            Self::GetGroupKey { group_id, .. } => string("group_id", group_id),
+            Self::SignTranscript { head_hash, range } => {
+                if range.start > range.end {
+                    return Err(format!("transcript range {} is reversed", range));
+                }
+                string("head_hash", head_hash)
+            }
+            Self::Receipt { message_id, .. } => string("message_id", message_id),
+            Self::TopicReplay { topic, continuation, .. } => {
+                string("topic", topic)?;
+                string("continuation", continuation.as_deref().unwrap_or_default())
+            }
+            Self::EmergencyApprovalRequest { request: signed } => {
+                let request = &signed.request;
+                for (field, value) in [
+                    ("requester", &request.requester),
+                    ("event_summary", &request.event_summary),
+                    ("proposed_response", &request.proposed_response),
+                    ("nonce", &request.nonce),
+                    ("signature", &signed.signature),
+                ] {
+                    string(field, value)?;
+                }
+                Ok(())
+            }
+            Self::EmergencyApproval { approval } => {
+                for (field, value) in [
+                    ("guardian", &approval.guardian),
+                    ("nonce", &approval.nonce),
+                    ("request_digest", &approval.request_digest),
+                    ("signature", &approval.signature),
+                ] {
+                    string(field, value)?;
+                }
+                Ok(())
+            }
+            Self::KeyLogSync { buckets } => {
+                if buckets.len() != BUCKETS {
+                    return Err(format!("{} key log buckets ({} expected)", buckets.len(), BUCKETS));
+                }
+                buckets.iter().try_for_each(|bucket| string("bucket", bucket))
+            }
+            Self::CoverKey { sealed_key } => match sealed_key.len() {
+                len if len == cover::KEY_LEN + sealed::OVERHEAD => Ok(()),
+                len => Err(format!("sealed_key is {} bytes ({} expected)", len, cover::KEY_LEN + sealed::OVERHEAD)),
+            },
+            Self::GetAttestation { nonce } => match nonce.len() {
+                NONCE_LEN => Ok(()),
+                len => Err(format!("nonce is {} bytes ({} expected)", len, NONCE_LEN)),
+            },
+            Self::PriceOption { inputs, model } => validate_pricing(inputs, model, limits),
+            Self::PriceChain { inputs, strikes, model } => {
+                if strikes.is_empty() || strikes.len() > limits.max_chain_strikes {
+                    return Err(format!("{} strikes (1 to {} allowed)", strikes.len(), limits.max_chain_strikes));
+                }
+                if let Some(strike) = strikes.iter().find(|s| !(s.is_finite() && **s > 0.0)) {
+                    return Err(format!("strike {} is not a positive number", strike));
+                }
+                validate_pricing(inputs, model, limits)
+            }
+        }
+    }
+}
+
+/// Inputs every model can price without blowing up, and a model within
+/// the size limits
+fn validate_pricing(inputs: &PricingInputs, model: &PricingModel, limits: &RequestLimits) -> Result<(), String> {
+    let PricingInputs { spot, strike, rate, dividend_yield, volatility, time_to_expiry, .. } = *inputs;
+    let checks = [
+        ("spot", spot, spot > 0.0),
+        ("strike", strike, strike > 0.0),
+        ("rate", rate, (-1.0..=1.0).contains(&rate)),
+        ("dividend_yield", dividend_yield, (-1.0..=1.0).contains(&dividend_yield)),
+        ("volatility", volatility, volatility > 0.0 && volatility <= 10.0),
+        ("time_to_expiry", time_to_expiry, time_to_expiry > 0.0 && time_to_expiry <= 100.0),
+    ];
+    if let Some((field, value, _)) = checks.iter().find(|(_, value, sane)| !(value.is_finite() && *sane)) {
+        return Err(format!("{} {} is out of range", field, value));
+    }
+    match *model {
+        PricingModel::BlackScholes => Ok(()),
+        PricingModel::Binomial { steps, .. } if steps > limits.max_binomial_steps => {
+            Err(format!("{} binomial steps (limit {})", steps, limits.max_binomial_steps))
+        }
+        PricingModel::Binomial { .. } => Ok(()),
+        PricingModel::MonteCarlo { paths, .. } if paths > limits.max_mc_paths => {
+            Err(format!("{} Monte Carlo paths (limit {})", paths, limits.max_mc_paths))
+        }
+        PricingModel::MonteCarlo { .. } => Ok(()),
+        PricingModel::Heston { params } => {
+            let values = [params.v0, params.kappa, params.theta, params.sigma_v, params.rho];
+            if !values.iter().all(|v| v.is_finite()) {
+                return Err("Heston parameters must be finite".to_string());
+            }
+            params.validate().map_err(|e| e.to_string())
+        }
+    }
+}
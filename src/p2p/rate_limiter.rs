@@ -1,44 +1,284 @@
+//! Rate Limiter
+//! Per-IP connection and per-peer message quotas. Each peer is in a class,
+//! from config pins, its zero-trust security level or `reclassify`, and
+//! each class has its own sustained message rate and burst. Trusted peers
+//! also skip the per-IP connection check, so your own nodes behind a
+//! shared NAT address are not throttled with strangers
+
 use governor::{Quota, RateLimiter as GovernorRateLimiter, clock::DefaultClock, state::{InMemoryState, NotKeyed}};
 use libp2p::{PeerId, Multiaddr, multiaddr::Protocol};
 use nonzero_ext::*;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::net::IpAddr;
 use std::num::NonZeroU32;
+use std::time::{Duration, Instant};
+
+use crate::zerotrust::SecurityLevel;
+
+/// A per-IP connection limiter idle this long has refilled its whole
+/// per-minute quota, so dropping it loses nothing
+const CONNECTION_LIMITER_IDLE: Duration = Duration::from_secs(60);
+
+type DirectLimiter = GovernorRateLimiter<NotKeyed, InMemoryState, DefaultClock>;
+
+/// Which message quota a peer gets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerClass {
+    /// Own nodes and highly verified peers; skips the connection rate check
+    Trusted,
+    Normal,
+    Restricted,
+}
+
+impl PeerClass {
+    /// Privileged and above are trusted, untrusted peers restricted
+    pub fn from_security_level(level: SecurityLevel) -> Self {
+        match level {
+            SecurityLevel::Privileged | SecurityLevel::Critical => Self::Trusted,
+            SecurityLevel::Basic | SecurityLevel::Verified => Self::Normal,
+            SecurityLevel::Untrusted => Self::Restricted,
+        }
+    }
+}
+
+impl fmt::Display for PeerClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Trusted => "trusted",
+            Self::Normal => "normal",
+            Self::Restricted => "restricted",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Sustained message rate and burst of one class
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClassQuota {
+    pub messages_per_second: u32,
+    /// Messages accepted back to back before the sustained rate applies
+    pub burst: u32,
+}
+
+impl ClassQuota {
+    fn quota(&self) -> Quota {
+        let rate = NonZeroU32::new(self.messages_per_second).unwrap_or(nonzero!(1u32));
+        Quota::per_second(rate).allow_burst(NonZeroU32::new(self.burst).unwrap_or(rate))
+    }
+}
+
+/// `[p2p.rate_limits]` configuration
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// New connections allowed per IP per minute
+    pub connections_per_minute: u32,
+    /// Messages allowed per peer per second, for normal peers
+    pub messages_per_second: u32,
+    /// Normal peers' burst; defaults to `messages_per_second`
+    pub burst: Option<u32>,
+    pub trusted: ClassQuota,
+    pub restricted: ClassQuota,
+    /// Peer IDs always trusted, e.g. your own other nodes
+    pub trusted_peers: Vec<String>,
+    /// Peer IDs always restricted
+    pub restricted_peers: Vec<String>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            connections_per_minute: 100,
+            messages_per_second: 10,
+            burst: None,
+            trusted: ClassQuota { messages_per_second: 100, burst: 200 },
+            restricted: ClassQuota { messages_per_second: 2, burst: 2 },
+            trusted_peers: Vec::new(),
+            restricted_peers: Vec::new(),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    fn class_quota(&self, class: PeerClass) -> Quota {
+        match class {
+            PeerClass::Trusted => self.trusted.quota(),
+            PeerClass::Normal => ClassQuota {
+                messages_per_second: if self.messages_per_second == 0 { 10 } else { self.messages_per_second },
+                burst: self.burst.unwrap_or(self.messages_per_second),
+            }
+            .quota(),
+            PeerClass::Restricted => self.restricted.quota(),
+        }
+    }
+
+    /// Pinned classes; restricted wins for a peer listed twice
+    fn pins(&self) -> HashMap<PeerId, PeerClass> {
+        let mut pins = HashMap::new();
+        let listed = [(&self.trusted_peers, PeerClass::Trusted), (&self.restricted_peers, PeerClass::Restricted)];
+        for (peers, class) in listed {
+            for peer in peers {
+                match peer.parse::<PeerId>() {
+                    Ok(peer) => {
+                        pins.insert(peer, class);
+                    }
+                    Err(_) => tracing::warn!("⚠️  Ignoring invalid peer ID in p2p.rate_limits.{}_peers: {}", class, peer),
+                }
+            }
+        }
+        pins
+    }
+}
+
+/// A peer's message limiters, one per class it has been in, so moving back
+/// to a class resumes that bucket instead of refilling it
+struct PeerLimiter {
+    limiters: HashMap<PeerClass, DirectLimiter>,
+}
 
 /// Rate limiter for P2P connections and messages
 pub struct RateLimiter {
-    // Global connection rate limit (per IP)
-    connection_limiter: HashMap<IpAddr, GovernorRateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    // Global connection rate limit (per IP), with when the IP was last seen
+    connection_limiter: HashMap<IpAddr, (DirectLimiter, Instant)>,
 
     // Per-peer message rate limit
-    message_limiter: HashMap<PeerId, GovernorRateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    message_limiter: HashMap<PeerId, PeerLimiter>,
+
+    // Rejections (kept after the peer disconnects, for incident review)
+    rejected_connections: HashMap<IpAddr, u64>,
+    rejected_messages: HashMap<PeerId, u64>,
+    allowed_messages: HashMap<PeerId, u64>,
+    // Messages per class, counted against the class at the time
+    class_messages: HashMap<PeerClass, MessageCounts>,
+
+    // Classes set at runtime (zero-trust level, `reclassify`); kept across
+    // reconnects
+    classes: HashMap<PeerId, PeerClass>,
+    // Classes pinned by config, which runtime changes don't override
+    pinned: HashMap<PeerId, PeerClass>,
 
     // Configuration
     connections_per_minute: u32,
-    messages_per_second: u32,
+    quotas: HashMap<PeerClass, Quota>,
+}
+
+/// Rejection counts for one peer and its addresses
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct RejectionCounts {
+    pub connections: u64,
+    pub messages: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct MessageCounts {
+    pub allowed: u64,
+    pub rejected: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ClassStats {
+    /// Registered (connected) peers in the class
+    pub peers: usize,
+    #[serde(flatten)]
+    pub messages: MessageCounts,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PeerRateStats {
+    pub class: PeerClass,
+    #[serde(flatten)]
+    pub messages: MessageCounts,
+}
+
+/// Message counters per class and per peer
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RateLimitStats {
+    pub classes: BTreeMap<PeerClass, ClassStats>,
+    /// Peers that are registered or have sent messages
+    pub peers: BTreeMap<String, PeerRateStats>,
 }
 
 impl RateLimiter {
     pub fn new(connections_per_minute: u32, messages_per_second: u32) -> Self {
-        Self {
+        Self::from_config(&RateLimitConfig { connections_per_minute, messages_per_second, ..Default::default() })
+    }
+
+    pub fn from_config(config: &RateLimitConfig) -> Self {
+        let mut limiter = Self {
             connection_limiter: HashMap::new(),
             message_limiter: HashMap::new(),
-            connections_per_minute,
-            messages_per_second,
+            rejected_connections: HashMap::new(),
+            rejected_messages: HashMap::new(),
+            allowed_messages: HashMap::new(),
+            class_messages: HashMap::new(),
+            classes: HashMap::new(),
+            pinned: HashMap::new(),
+            connections_per_minute: config.connections_per_minute,
+            quotas: HashMap::new(),
+        };
+        limiter.set_quotas(config);
+        limiter
+    }
+
+    /// Change quotas and pins in place (config reload). Limiters start over
+    /// with a full bucket under the new quota; counts and runtime classes
+    /// are kept
+    pub fn set_quotas(&mut self, config: &RateLimitConfig) {
+        self.connections_per_minute = config.connections_per_minute;
+        self.quotas = [PeerClass::Trusted, PeerClass::Normal, PeerClass::Restricted]
+            .into_iter()
+            .map(|class| (class, config.class_quota(class)))
+            .collect();
+        self.pinned = config.pins();
+        self.connection_limiter.clear();
+        for peer in self.message_limiter.values_mut() {
+            peer.limiters.clear();
         }
     }
 
-    /// Check if a new connection from this IP is allowed
-    pub fn check_connection(&mut self, remote_addr: &Multiaddr) -> bool {
+    /// The peer's class: its config pin, else its runtime class, else normal
+    pub fn class_of(&self, peer_id: &PeerId) -> PeerClass {
+        self.pinned
+            .get(peer_id)
+            .or_else(|| self.classes.get(peer_id))
+            .copied()
+            .unwrap_or(PeerClass::Normal)
+    }
+
+    /// Move a peer to `class` (e.g. when its trust level changes); returns
+    /// the class now in force, which stays the pinned one for config-pinned
+    /// peers. The peer's bucket in its old class is kept for if it returns
+    pub fn reclassify(&mut self, peer_id: PeerId, class: PeerClass) -> PeerClass {
+        let previous = self.class_of(&peer_id);
+        self.classes.insert(peer_id, class);
+        let now = self.class_of(&peer_id);
+        if now != previous {
+            tracing::info!("🚦 Peer {} reclassified {} → {}", peer_id, previous, now);
+        }
+        now
+    }
+
+    /// Check if a new connection from this IP is allowed. Trusted peers
+    /// are always allowed and don't use up the IP's quota
+    pub fn check_connection(&mut self, peer_id: &PeerId, remote_addr: &Multiaddr) -> bool {
+        if self.class_of(peer_id) == PeerClass::Trusted {
+            tracing::debug!("✅ Trusted peer {} skips the connection rate limit", peer_id);
+            return true;
+        }
         if let Some(ip) = extract_ip(remote_addr) {
-            let limiter = self.connection_limiter.entry(ip).or_insert_with(|| {
-                GovernorRateLimiter::direct(
+            let (limiter, last_seen) = self.connection_limiter.entry(ip).or_insert_with(|| {
+                let limiter = GovernorRateLimiter::direct(
                     Quota::per_minute(
                         NonZeroU32::new(self.connections_per_minute)
                             .unwrap_or(nonzero!(100u32))
                     )
-                )
+                );
+                (limiter, Instant::now())
             });
+            *last_seen = Instant::now();
 
             match limiter.check() {
                 Ok(_) => {
@@ -47,6 +287,7 @@ impl RateLimiter {
                 }
                 Err(_) => {
                     tracing::warn!("🚫 Connection rate limit exceeded for IP: {}", ip);
+                    *self.rejected_connections.entry(ip).or_insert(0) += 1;
                     false
                 }
             }
@@ -56,40 +297,40 @@ impl RateLimiter {
         }
     }
 
-    /// Check if a message from this peer is allowed
+    /// Check if a message from this peer is allowed under its class quota
     pub fn check_message(&mut self, peer_id: &PeerId) -> bool {
-        let limiter = self.message_limiter.entry(*peer_id).or_insert_with(|| {
-            GovernorRateLimiter::direct(
-                Quota::per_second(
-                    NonZeroU32::new(self.messages_per_second)
-                        .unwrap_or(nonzero!(10u32))
-                )
-            )
-        });
+        let class = self.class_of(peer_id);
+        let quota = self.quotas[&class];
+        let peer = self
+            .message_limiter
+            .entry(*peer_id)
+            .or_insert_with(|| PeerLimiter { limiters: HashMap::new() });
+        let allowed = peer
+            .limiters
+            .entry(class)
+            .or_insert_with(|| GovernorRateLimiter::direct(quota))
+            .check()
+            .is_ok();
 
-        match limiter.check() {
-            Ok(_) => {
-                tracing::debug!("✅ Message rate limit OK for peer: {}", peer_id);
-                true
-            }
-            Err(_) => {
-                tracing::warn!("🚫 Message rate limit exceeded for peer: {}", peer_id);
-                false
-            }
+        let counts = self.class_messages.entry(class).or_default();
+        if allowed {
+            tracing::debug!("✅ Message rate limit OK for peer: {}", peer_id);
+            counts.allowed += 1;
+            *self.allowed_messages.entry(*peer_id).or_insert(0) += 1;
+        } else {
+            tracing::warn!("🚫 Message rate limit exceeded for {} peer: {}", class, peer_id);
+            counts.rejected += 1;
+            *self.rejected_messages.entry(*peer_id).or_insert(0) += 1;
         }
+        allowed
     }
 
     /// Register a new peer for message rate limiting
     pub fn register_peer(&mut self, peer_id: PeerId) {
-        self.message_limiter.entry(peer_id).or_insert_with(|| {
-            GovernorRateLimiter::direct(
-                Quota::per_second(
-                    NonZeroU32::new(self.messages_per_second)
-                        .unwrap_or(nonzero!(10u32))
-                )
-            )
-        });
-        tracing::debug!("📝 Registered peer for rate limiting: {}", peer_id);
+        self.message_limiter
+            .entry(peer_id)
+            .or_insert_with(|| PeerLimiter { limiters: HashMap::new() });
+        tracing::debug!("📝 Registered {} peer for rate limiting: {}", self.class_of(&peer_id), peer_id);
     }
 
     /// Unregister a peer (cleanup)
@@ -98,17 +339,54 @@ impl RateLimiter {
         tracing::debug!("🗑️  Unregistered peer from rate limiting: {}", peer_id);
     }
 
-    /// Clean up old limiters (for IPs that haven't been seen in a while)
-    pub fn cleanup(&mut self) {
-        // Remove limiters with no recent activity
-        // This prevents memory growth from never-seen-again IPs
-        // For now, keep all limiters (they're cheap)
-        // In production, implement LRU cache or time-based cleanup
+    /// Rejections recorded for a peer and the IPs it connected from
+    pub fn rejections(&self, peer_id: &PeerId, ips: &[IpAddr]) -> RejectionCounts {
+        RejectionCounts {
+            connections: ips
+                .iter()
+                .filter_map(|ip| self.rejected_connections.get(ip))
+                .sum(),
+            messages: self.rejected_messages.get(peer_id).copied().unwrap_or(0),
+        }
+    }
+
+    /// Message counts per class, and per peer under its current class
+    pub fn stats(&self) -> RateLimitStats {
+        let mut stats = RateLimitStats::default();
+        for (class, messages) in &self.class_messages {
+            stats.classes.entry(*class).or_default().messages = *messages;
+        }
+        let peers = self
+            .message_limiter
+            .keys()
+            .chain(self.allowed_messages.keys())
+            .chain(self.rejected_messages.keys());
+        for peer in peers {
+            let messages = MessageCounts {
+                allowed: self.allowed_messages.get(peer).copied().unwrap_or(0),
+                rejected: self.rejected_messages.get(peer).copied().unwrap_or(0),
+            };
+            stats.peers.insert(peer.to_string(), PeerRateStats { class: self.class_of(peer), messages });
+        }
+        for peer in self.message_limiter.keys() {
+            stats.classes.entry(self.class_of(peer)).or_default().peers += 1;
+        }
+        stats
+    }
+
+    /// Drop connection limiters for IPs that haven't been seen in a while,
+    /// so never-seen-again IPs don't grow the map forever; returns how many
+    pub fn cleanup(&mut self) -> usize {
+        let now = Instant::now();
+        let before = self.connection_limiter.len();
+        self.connection_limiter
+            .retain(|_, (_, last_seen)| now.duration_since(*last_seen) < CONNECTION_LIMITER_IDLE);
+        before - self.connection_limiter.len()
     }
 }
 
 /// Extract IP address from multiaddress
-fn extract_ip(addr: &Multiaddr) -> Option<IpAddr> {
+pub fn extract_ip(addr: &Multiaddr) -> Option<IpAddr> {
     for component in addr.iter() {
         match component {
             Protocol::Ip4(ip) => return Some(IpAddr::V4(ip)),
@@ -131,11 +409,11 @@ mod tests {
 
         // First 5 connections should succeed
         for i in 0..5 {
-            assert!(limiter.check_connection(&addr), "Connection {} should be allowed", i);
+            assert!(limiter.check_connection(&PeerId::random(), &addr), "Connection {} should be allowed", i);
         }
 
         // 6th connection should be rate limited
-        assert!(!limiter.check_connection(&addr), "Connection should be rate limited");
+        assert!(!limiter.check_connection(&PeerId::random(), &addr), "Connection should be rate limited");
     }
 
     #[test]
@@ -153,4 +431,117 @@ mod tests {
         // 11th message should be rate limited
         assert!(!limiter.check_message(&peer_id), "Message should be rate limited");
     }
+
+    #[test]
+    fn test_cleanup_drops_idle_ips() {
+        let mut limiter = RateLimiter::new(5, 10);
+        let idle = Multiaddr::from_str("/ip4/10.0.0.1/tcp/9000").unwrap();
+        let active = Multiaddr::from_str("/ip4/10.0.0.2/tcp/9000").unwrap();
+        assert!(limiter.check_connection(&PeerId::random(), &idle));
+        assert!(limiter.check_connection(&PeerId::random(), &active));
+
+        assert_eq!(limiter.cleanup(), 0);
+        limiter.connection_limiter.get_mut(&extract_ip(&idle).unwrap()).unwrap().1 -= CONNECTION_LIMITER_IDLE;
+        assert_eq!(limiter.cleanup(), 1);
+        assert_eq!(limiter.connection_limiter.len(), 1);
+    }
+
+    fn classed_config(trusted: &PeerId, restricted: &PeerId) -> RateLimitConfig {
+        RateLimitConfig {
+            connections_per_minute: 2,
+            messages_per_second: 5,
+            burst: Some(8),
+            trusted: ClassQuota { messages_per_second: 20, burst: 40 },
+            restricted: ClassQuota { messages_per_second: 1, burst: 1 },
+            trusted_peers: vec![trusted.to_string()],
+            restricted_peers: vec![restricted.to_string(), "not-a-peer-id".to_string()],
+        }
+    }
+
+    /// Messages accepted back to back before the first rejection
+    fn drain(limiter: &mut RateLimiter, peer: &PeerId) -> usize {
+        (0..1000).take_while(|_| limiter.check_message(peer)).count()
+    }
+
+    #[test]
+    fn test_classes_have_distinct_quotas() {
+        let (trusted, normal, restricted) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let mut limiter = RateLimiter::from_config(&classed_config(&trusted, &restricted));
+        assert_eq!(limiter.class_of(&trusted), PeerClass::Trusted);
+        assert_eq!(limiter.class_of(&normal), PeerClass::Normal);
+        assert_eq!(limiter.class_of(&restricted), PeerClass::Restricted);
+
+        assert_eq!(drain(&mut limiter, &trusted), 40);
+        assert_eq!(drain(&mut limiter, &normal), 8);
+        assert_eq!(drain(&mut limiter, &restricted), 1);
+
+        let stats = limiter.stats();
+        assert_eq!(stats.classes[&PeerClass::Trusted].messages, MessageCounts { allowed: 40, rejected: 1 });
+        assert_eq!(stats.classes[&PeerClass::Normal].messages, MessageCounts { allowed: 8, rejected: 1 });
+        assert_eq!(stats.classes[&PeerClass::Restricted].messages, MessageCounts { allowed: 1, rejected: 1 });
+        let normal_stats = PeerRateStats { class: PeerClass::Normal, messages: MessageCounts { allowed: 8, rejected: 1 } };
+        assert_eq!(stats.peers[&normal.to_string()], normal_stats);
+
+        // Trusted peers behind a busy shared address still get in
+        let nat = Multiaddr::from_str("/ip4/203.0.113.7/tcp/4001").unwrap();
+        assert!(limiter.check_connection(&normal, &nat) && limiter.check_connection(&normal, &nat));
+        assert!(!limiter.check_connection(&normal, &nat));
+        assert!(limiter.check_connection(&trusted, &nat));
+        assert!(!limiter.check_connection(&restricted, &nat));
+    }
+
+    #[test]
+    fn test_burst_then_sustained_rate() {
+        let peer = PeerId::random();
+        let mut limiter = RateLimiter::from_config(&RateLimitConfig {
+            messages_per_second: 20,
+            burst: Some(10),
+            ..Default::default()
+        });
+        assert_eq!(drain(&mut limiter, &peer), 10, "the burst is absorbed");
+
+        // Refills at 20/s: about one message per 50ms, not another burst
+        std::thread::sleep(Duration::from_millis(120));
+        let refilled = drain(&mut limiter, &peer);
+        assert!((1..=3).contains(&refilled), "{} messages after 120ms", refilled);
+        let start = Instant::now();
+        let mut allowed = 0;
+        while start.elapsed() < Duration::from_millis(500) {
+            std::thread::sleep(Duration::from_millis(10));
+            allowed += drain(&mut limiter, &peer);
+        }
+        let expected = start.elapsed().as_millis() as usize / 50;
+        assert!(allowed.abs_diff(expected) <= 2, "{} messages, expected about {}", allowed, expected);
+    }
+
+    #[test]
+    fn test_live_reclassification() {
+        let (pinned, peer) = (PeerId::random(), PeerId::random());
+        let mut limiter = RateLimiter::from_config(&classed_config(&pinned, &PeerId::random()));
+        limiter.register_peer(peer);
+        assert_eq!(drain(&mut limiter, &peer), 8);
+
+        // Promoted mid-stream: the trusted quota applies at once
+        assert_eq!(limiter.reclassify(peer, PeerClass::Trusted), PeerClass::Trusted);
+        assert_eq!(drain(&mut limiter, &peer), 40);
+        assert_eq!(limiter.stats().classes[&PeerClass::Trusted].peers, 1);
+
+        // Back to normal: the spent normal bucket is resumed, not refilled
+        assert_eq!(limiter.reclassify(peer, PeerClass::Normal), PeerClass::Normal);
+        assert!(!limiter.check_message(&peer));
+        assert_eq!(limiter.reclassify(peer, PeerClass::Restricted), PeerClass::Restricted);
+        assert_eq!(drain(&mut limiter, &peer), 1);
+
+        // A zero-trust downgrade doesn't override a config pin
+        let level = PeerClass::from_security_level(SecurityLevel::Untrusted);
+        assert_eq!(limiter.reclassify(pinned, level), PeerClass::Trusted);
+
+        // The class survives a reconnect
+        limiter.unregister_peer(&peer);
+        limiter.register_peer(peer);
+        assert_eq!(limiter.class_of(&peer), PeerClass::Restricted);
+        let stats = limiter.stats();
+        assert_eq!(stats.peers[&peer.to_string()].messages, MessageCounts { allowed: 49, rejected: 4 });
+        assert_eq!(stats.classes[&PeerClass::Restricted].peers, 1);
+    }
 }
@@ -0,0 +1,469 @@
+//! Message Receipts
+//! Delivered and read receipts for direct messages. Each message we send or
+//! receive is kept by ID with its receipt timeline, along with receipts we
+//! still owe the sender, so both survive restarts
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::migrations::{self, StoreSchema};
+use crate::search::{Query, SearchHit, SearchStore, Searchable, TopHits};
+use crate::storage::{IndexFields, IndexQuery, KvStore, RuntimeMode};
+
+pub const SCHEMA: StoreSchema = StoreSchema {
+    name: "message_receipts",
+    tree: Some("messages"),
+    version: 1,
+    migrations: &[],
+    index: Some(index_record),
+};
+
+/// Records are found by peer and ordered by time
+fn index_record(_key: &[u8], value: &[u8]) -> IndexFields {
+    match serde_json::from_slice::<MessageRecord>(value) {
+        Ok(record) => IndexFields {
+            peer_id: Some(record.peer),
+            timestamp: Some(record.at.timestamp_millis()),
+            ..IndexFields::default()
+        },
+        Err(_) => IndexFields::default(),
+    }
+}
+
+fn decode(entries: Vec<(Vec<u8>, Vec<u8>)>) -> Result<Vec<MessageRecord>> {
+    entries
+        .into_iter()
+        .map(|(_, bytes)| serde_json::from_slice(&bytes).context("Corrupt message record"))
+        .collect()
+}
+
+/// Domain separator for message IDs
+const MESSAGE_ID_CONTEXT: &[u8] = b"quantra-direct-message-v1\0";
+
+/// `[p2p.receipts]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReceiptConfig {
+    /// Tell senders when their messages are read. Delivered receipts are
+    /// always sent
+    pub send_read_receipts: bool,
+}
+
+impl Default for ReceiptConfig {
+    fn default() -> Self {
+        Self { send_read_receipts: true }
+    }
+}
+
+/// ID both sides derive for a direct message: its sender and sealed bytes
+pub fn message_id(sender: &PeerId, sealed: &[u8]) -> String {
+    let digest = Sha256::new()
+        .chain_update(MESSAGE_ID_CONTEXT)
+        .chain_update(sender.to_bytes())
+        .chain_update(sealed)
+        .finalize();
+    hex::encode(&digest[..16])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReceiptKind {
+    Delivered,
+    Read,
+}
+
+impl ReceiptKind {
+    pub fn icon(&self) -> &'static str {
+        match self {
+            ReceiptKind::Delivered => "📬",
+            ReceiptKind::Read => "👁️",
+        }
+    }
+}
+
+impl fmt::Display for ReceiptKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReceiptKind::Delivered => write!(f, "delivered"),
+            ReceiptKind::Read => write!(f, "read"),
+        }
+    }
+}
+
+/// A receipt as sent; `timestamp` is the recipient's clock
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Receipt {
+    pub message_id: String,
+    pub kind: ReceiptKind,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A receipt the sender has recorded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReceiptEvent {
+    pub kind: ReceiptKind,
+    /// As stamped by the peer
+    pub peer_time: DateTime<Utc>,
+    pub received_at: DateTime<Utc>,
+    /// Peer clock minus ours when the receipt arrived, if measured
+    pub clock_offset_ms: Option<i64>,
+}
+
+impl ReceiptEvent {
+    /// `peer_time` on our clock
+    pub fn local_time(&self) -> DateTime<Utc> {
+        self.peer_time - chrono::Duration::milliseconds(self.clock_offset_ms.unwrap_or(0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Outgoing,
+    Incoming,
+}
+
+/// One direct message and its receipts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageRecord {
+    pub id: String,
+    pub direction: Direction,
+    /// Recipient of an outgoing message, sender of an incoming one
+    pub peer: String,
+    /// When it was accepted by the recipient (outgoing) or arrived (incoming)
+    pub at: DateTime<Utc>,
+    /// Receipts from the recipient of an outgoing message
+    #[serde(default)]
+    pub receipts: Vec<ReceiptEvent>,
+    /// When we read an incoming message
+    #[serde(default)]
+    pub read_at: Option<DateTime<Utc>>,
+    /// Receipts for an incoming message the sender hasn't accepted yet
+    #[serde(default)]
+    pub owed: Vec<Receipt>,
+}
+
+impl MessageRecord {
+    /// Furthest receipt received (outgoing) or sent (incoming)
+    pub fn status(&self) -> Option<ReceiptKind> {
+        match self.direction {
+            Direction::Outgoing => self.receipts.iter().map(|r| r.kind).max(),
+            Direction::Incoming if self.read_at.is_some() => Some(ReceiptKind::Read),
+            Direction::Incoming => Some(ReceiptKind::Delivered),
+        }
+    }
+
+    pub fn status_icon(&self) -> &'static str {
+        match self.status() {
+            None => "📤",
+            // Unread
+            Some(ReceiptKind::Delivered) if self.direction == Direction::Incoming => "🆕",
+            Some(kind) => kind.icon(),
+        }
+    }
+}
+
+impl fmt::Display for MessageRecord {
+    /// Receipt timeline
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ts = |t: DateTime<Utc>| t.format("%Y-%m-%d %H:%M:%S%.3f UTC");
+        match self.direction {
+            Direction::Outgoing => {
+                writeln!(f, "{} {} to {}", self.status_icon(), self.id, self.peer)?;
+                writeln!(f, "   {}  📤 sent", ts(self.at))?;
+                for receipt in &self.receipts {
+                    write!(f, "   {}  {} {}", ts(receipt.local_time()), receipt.kind.icon(), receipt.kind)?;
+                    match receipt.clock_offset_ms {
+                        Some(offset) if offset != 0 => {
+                            writeln!(f, " (peer clock {:+}ms, stamped {})", offset, ts(receipt.peer_time))?
+                        }
+                        Some(_) => writeln!(f)?,
+                        None => writeln!(f, " (peer clock not measured)")?,
+                    }
+                }
+            }
+            Direction::Incoming => {
+                writeln!(f, "{} {} from {}", self.status_icon(), self.id, self.peer)?;
+                writeln!(f, "   {}  📬 received", ts(self.at))?;
+                if let Some(read_at) = self.read_at {
+                    writeln!(f, "   {}  👁️ read", ts(read_at))?;
+                }
+                for receipt in &self.owed {
+                    writeln!(f, "   {} receipt not yet accepted by the sender", receipt.kind)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What became of a received receipt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReceiptOutcome {
+    Recorded,
+    Duplicate,
+    /// Not a message we sent to that peer
+    Unknown,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReceiptStats {
+    pub messages: usize,
+    /// Receipts owed to senders
+    pub owed: usize,
+    pub unknown_ignored: u64,
+    pub duplicates: u64,
+}
+
+pub struct ReceiptStore {
+    db: Box<dyn KvStore>,
+    config: ReceiptConfig,
+    unknown_ignored: AtomicU64,
+    duplicates: AtomicU64,
+}
+
+impl ReceiptStore {
+    /// Open the store in `dir`, or keep it in memory when ephemeral
+    pub fn open(dir: &Path, mode: RuntimeMode, config: ReceiptConfig) -> Result<Self> {
+        let db = migrations::open_store(mode, dir, &SCHEMA)
+            .with_context(|| format!("Failed to open message receipts at {}", dir.display()))?;
+        Ok(Self { db, config, unknown_ignored: AtomicU64::new(0), duplicates: AtomicU64::new(0) })
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<MessageRecord>> {
+        self.db
+            .get(id.as_bytes())?
+            .map(|bytes| serde_json::from_slice(&bytes).context("Corrupt message record"))
+            .transpose()
+    }
+
+    fn put(&self, record: &MessageRecord) -> Result<()> {
+        self.db.insert(record.id.as_bytes(), &serde_json::to_vec(record)?)?;
+        self.db.flush()
+    }
+
+    /// Messages in one direction (or both), oldest first
+    pub fn list(&self, direction: Option<Direction>) -> Result<Vec<MessageRecord>> {
+        let mut records = decode(self.db.find(IndexQuery::ALL_TIMES)?)?;
+        records.retain(|r| direction.is_none_or(|d| r.direction == d));
+        // The index keeps milliseconds
+        records.sort_by_key(|r| r.at);
+        Ok(records)
+    }
+
+    /// `peer` accepted a message we sent
+    pub fn record_sent(&self, id: &str, peer: &PeerId, now: DateTime<Utc>) -> Result<()> {
+        if self.get(id)?.is_some() {
+            return Ok(());
+        }
+        self.put(&MessageRecord {
+            id: id.to_string(),
+            direction: Direction::Outgoing,
+            peer: peer.to_string(),
+            at: now,
+            receipts: Vec::new(),
+            read_at: None,
+            owed: Vec::new(),
+        })
+    }
+
+    /// A message from `sender` arrived; owes it a delivered receipt unless
+    /// it was already recorded
+    pub fn record_received(&self, id: &str, sender: &PeerId, now: DateTime<Utc>) -> Result<()> {
+        if self.get(id)?.is_some() {
+            return Ok(());
+        }
+        self.put(&MessageRecord {
+            id: id.to_string(),
+            direction: Direction::Incoming,
+            peer: sender.to_string(),
+            at: now,
+            receipts: Vec::new(),
+            read_at: None,
+            owed: vec![Receipt { message_id: id.to_string(), kind: ReceiptKind::Delivered, timestamp: now }],
+        })
+    }
+
+    /// Mark an incoming message read, owing a read receipt unless those are
+    /// turned off. Returns its sender
+    pub fn mark_read(&self, id: &str, now: DateTime<Utc>) -> Result<String> {
+        let mut record = self.get(id)?.with_context(|| format!("No message {}", id))?;
+        if record.direction != Direction::Incoming {
+            anyhow::bail!("Message {} was sent by this node", id);
+        }
+        if record.read_at.is_none() {
+            record.read_at = Some(now);
+            if self.config.send_read_receipts {
+                record.owed.push(Receipt { message_id: id.to_string(), kind: ReceiptKind::Read, timestamp: now });
+            }
+            self.put(&record)?;
+        }
+        Ok(record.peer)
+    }
+
+    /// Receipts owed to `peer`
+    pub fn owed(&self, peer: &PeerId) -> Result<Vec<Receipt>> {
+        Ok(decode(self.db.find(IndexQuery::PeerId(&peer.to_string()))?)?
+            .into_iter()
+            .filter(|r| r.direction == Direction::Incoming)
+            .flat_map(|r| r.owed)
+            .collect())
+    }
+
+    /// The sender accepted `receipt`
+    pub fn settle(&self, receipt: &Receipt) -> Result<()> {
+        let Some(mut record) = self.get(&receipt.message_id)? else { return Ok(()) };
+        let before = record.owed.len();
+        record.owed.retain(|owed| owed.kind != receipt.kind);
+        if record.owed.len() != before {
+            self.put(&record)?;
+        }
+        Ok(())
+    }
+
+    /// Record a receipt from `peer` for a message we sent it
+    pub fn apply(&self, peer: &PeerId, receipt: &Receipt, clock_offset_ms: Option<i64>, now: DateTime<Utc>) -> Result<ReceiptOutcome> {
+        let record = self.get(&receipt.message_id)?;
+        let Some(mut record) = record.filter(|r| r.direction == Direction::Outgoing && r.peer == peer.to_string()) else {
+            self.unknown_ignored.fetch_add(1, Ordering::Relaxed);
+            tracing::debug!("🧾 Ignored {} receipt from {} for unknown message {}", receipt.kind, peer, receipt.message_id);
+            return Ok(ReceiptOutcome::Unknown);
+        };
+        if record.receipts.iter().any(|r| r.kind == receipt.kind) {
+            self.duplicates.fetch_add(1, Ordering::Relaxed);
+            return Ok(ReceiptOutcome::Duplicate);
+        }
+        record.receipts.push(ReceiptEvent {
+            kind: receipt.kind,
+            peer_time: receipt.timestamp,
+            received_at: now,
+            clock_offset_ms,
+        });
+        record.receipts.sort_by_key(|r| r.kind);
+        self.put(&record)?;
+        Ok(ReceiptOutcome::Recorded)
+    }
+
+    pub fn stats(&self) -> Result<ReceiptStats> {
+        let records = self.list(None)?;
+        Ok(ReceiptStats {
+            messages: records.len(),
+            owed: records.iter().map(|r| r.owed.len()).sum(),
+            unknown_ignored: self.unknown_ignored.load(Ordering::Relaxed),
+            duplicates: self.duplicates.load(Ordering::Relaxed),
+        })
+    }
+}
+
+#[async_trait]
+impl Searchable for ReceiptStore {
+    fn store(&self) -> SearchStore {
+        SearchStore::Messages
+    }
+
+    async fn search(&self, query: &Query, limit: usize) -> Result<Vec<SearchHit>> {
+        let mut hits = TopHits::new(limit);
+        self.db.visit_prefix(b"", &mut |_, bytes| {
+            let record: MessageRecord = serde_json::from_slice(bytes).context("Corrupt message record")?;
+            if let Some((field, _, rank)) = query.rank_fields(&[("id", record.id.as_str()), ("peer", record.peer.as_str())]) {
+                let direction = match record.direction {
+                    Direction::Outgoing => "to",
+                    Direction::Incoming => "from",
+                };
+                let snippet = format!(
+                    "{} {} {} at {}",
+                    record.status_icon(),
+                    direction,
+                    record.peer,
+                    record.at.format("%Y-%m-%d %H:%M:%S UTC")
+                );
+                hits.push(SearchHit::new(SearchStore::Messages, field, record.id.as_str(), snippet, rank));
+            }
+            Ok(true)
+        })?;
+        Ok(hits.into_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(dir: &Path, config: ReceiptConfig) -> ReceiptStore {
+        ReceiptStore::open(dir, RuntimeMode::Persistent, config).unwrap()
+    }
+
+    #[test]
+    fn test_receipts_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+        let id = message_id(&alice, b"sealed");
+        let now = Utc::now();
+        {
+            let store = open(dir.path(), ReceiptConfig::default());
+            store.record_sent(&id, &bob, now).unwrap();
+            let delivered = Receipt { message_id: id.clone(), kind: ReceiptKind::Delivered, timestamp: now };
+            assert_eq!(store.apply(&bob, &delivered, Some(1500), now).unwrap(), ReceiptOutcome::Recorded);
+            assert_eq!(store.apply(&bob, &delivered, Some(1500), now).unwrap(), ReceiptOutcome::Duplicate);
+
+            // Only the recipient can acknowledge a message
+            assert_eq!(store.apply(&alice, &delivered, None, now).unwrap(), ReceiptOutcome::Unknown);
+            let stray = Receipt { message_id: "00".repeat(16), ..delivered };
+            assert_eq!(store.apply(&bob, &stray, None, now).unwrap(), ReceiptOutcome::Unknown);
+            let stats = store.stats().unwrap();
+            assert_eq!((stats.unknown_ignored, stats.duplicates), (2, 1));
+        }
+
+        let store = open(dir.path(), ReceiptConfig::default());
+        let record = store.get(&id).unwrap().unwrap();
+        assert_eq!(record.status(), Some(ReceiptKind::Delivered));
+        // Bob's clock runs 1.5s ahead; his stamp is shown on ours
+        let receipt = &record.receipts[0];
+        assert_eq!(receipt.local_time(), now - chrono::Duration::milliseconds(1500));
+        assert!(record.to_string().contains("peer clock +1500ms"), "{}", record);
+    }
+
+    #[test]
+    fn test_read_receipts_owed_until_settled() {
+        let dir = tempfile::tempdir().unwrap();
+        let sender = PeerId::random();
+        let id = message_id(&sender, b"sealed");
+        let store = open(dir.path(), ReceiptConfig::default());
+        store.record_received(&id, &sender, Utc::now()).unwrap();
+        let owed = store.owed(&sender).unwrap();
+        assert_eq!(owed.iter().map(|r| r.kind).collect::<Vec<_>>(), vec![ReceiptKind::Delivered]);
+        store.settle(&owed[0]).unwrap();
+        assert!(store.owed(&sender).unwrap().is_empty());
+
+        // Redelivery doesn't owe the receipt again
+        store.record_received(&id, &sender, Utc::now()).unwrap();
+        assert!(store.owed(&sender).unwrap().is_empty());
+
+        assert_eq!(store.mark_read(&id, Utc::now()).unwrap(), sender.to_string());
+        store.mark_read(&id, Utc::now()).unwrap();
+        let owed = store.owed(&sender).unwrap();
+        assert_eq!(owed.iter().map(|r| r.kind).collect::<Vec<_>>(), vec![ReceiptKind::Read]);
+        assert!(store.mark_read(&"ff".repeat(16), Utc::now()).is_err());
+    }
+
+    #[test]
+    fn test_read_receipts_can_be_suppressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let sender = PeerId::random();
+        let id = message_id(&sender, b"sealed");
+        let store = open(dir.path(), ReceiptConfig { send_read_receipts: false });
+        store.record_received(&id, &sender, Utc::now()).unwrap();
+        store.mark_read(&id, Utc::now()).unwrap();
+
+        let record = store.get(&id).unwrap().unwrap();
+        assert_eq!(record.status(), Some(ReceiptKind::Read));
+        assert_eq!(record.owed.iter().map(|r| r.kind).collect::<Vec<_>>(), vec![ReceiptKind::Delivered]);
+    }
+}
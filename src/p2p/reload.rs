@@ -0,0 +1,399 @@
+//! Config Reload
+//! A running node re-reads its settings file (SIGHUP or `reload-config`),
+//! diffs it against the settings it runs with, applies the operational ones
+//! that can change in place and reports the ones that need a restart
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::settings::Settings;
+
+/// Settings a running node applies in place
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LiveSection {
+    RateLimits,
+    GeoPolicy,
+    Admission,
+    ReplayCompaction,
+    Notifications,
+    ZeroTrust,
+    Maintenance,
+    RemotePricing,
+    Logging,
+}
+
+/// Fields under a live section that still need a restart, and why
+const RESTART_REQUIRED: &[(&str, &str)] = &[
+    ("p2p.geo_policy.enabled", "turning geo policy on or off needs a restart"),
+    ("p2p.geo_policy.cache_ttl", "the geolocation cache is created at startup"),
+    ("p2p.geo_policy.report_to_shield", "Mirror Shield is attached at startup"),
+    ("p2p.admission.enabled", "turning admission control on or off needs a restart"),
+    ("notifications.enabled", "turning notifications on or off needs a restart"),
+    ("notifications.retry_interval", "the retry loop is started at startup"),
+    ("quant.remote_pricing.serve", "turning remote pricing on or off needs a restart"),
+];
+
+const LIVE: &[(&str, LiveSection)] = &[
+    ("p2p.rate_limits", LiveSection::RateLimits),
+    ("p2p.geo_policy", LiveSection::GeoPolicy),
+    ("p2p.admission", LiveSection::Admission),
+    ("p2p.replay.compact_interval", LiveSection::ReplayCompaction),
+    ("notifications", LiveSection::Notifications),
+    ("zerotrust", LiveSection::ZeroTrust),
+    ("maintenance.at", LiveSection::Maintenance),
+    ("maintenance.jitter", LiveSection::Maintenance),
+    ("quant.remote_pricing", LiveSection::RemotePricing),
+    ("logging.level", LiveSection::Logging),
+];
+
+/// Everything else, e.g. listen addresses, keys and store paths
+const READ_AT_STARTUP: &str = "only read at startup";
+
+fn under(field: &str, prefix: &str) -> bool {
+    field.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+/// The section that applies a changed field, or why it needs a restart
+pub fn classify(field: &str) -> std::result::Result<LiveSection, &'static str> {
+    if let Some((_, reason)) = RESTART_REQUIRED.iter().find(|(prefix, _)| under(field, prefix)) {
+        return Err(reason);
+    }
+    LIVE.iter()
+        .find(|(prefix, _)| under(field, prefix))
+        .map(|(_, section)| *section)
+        .ok_or(READ_AT_STARTUP)
+}
+
+/// A changed field left as it was
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SkippedChange {
+    pub field: String,
+    pub reason: String,
+}
+
+/// Outcome of one reload
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReloadReport {
+    /// File the settings were read from
+    pub source: String,
+    pub applied: Vec<String>,
+    pub skipped: Vec<SkippedChange>,
+    /// Why nothing was applied (unreadable or invalid file)
+    pub error: Option<String>,
+}
+
+impl ReloadReport {
+    pub fn failed(source: &str, error: impl Into<String>) -> Self {
+        Self { source: source.to_string(), error: Some(error.into()), ..Default::default() }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn skip(&mut self, fields: &[String], reason: &str) {
+        self.skipped.extend(fields.iter().map(|field| SkippedChange { field: field.clone(), reason: reason.to_string() }));
+    }
+
+    pub fn audit_details(&self) -> HashMap<String, String> {
+        let mut details = HashMap::from([
+            ("source".to_string(), self.source.clone()),
+            ("applied".to_string(), self.applied.join(",")),
+            ("skipped".to_string(), self.skipped.iter().map(|s| s.field.as_str()).collect::<Vec<_>>().join(",")),
+        ]);
+        if let Some(error) = &self.error {
+            details.insert("error".to_string(), error.clone());
+        }
+        details
+    }
+}
+
+impl fmt::Display for ReloadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(error) = &self.error {
+            return writeln!(f, "❌ Config reload from {} failed, nothing changed: {}", self.source, error);
+        }
+        if self.applied.is_empty() && self.skipped.is_empty() {
+            return writeln!(f, "🔄 {} is unchanged", self.source);
+        }
+        writeln!(
+            f,
+            "🔄 Reloaded {}: {} applied, {} not applied",
+            self.source,
+            self.applied.len(),
+            self.skipped.len()
+        )?;
+        for field in &self.applied {
+            writeln!(f, "  ✅ {}", field)?;
+        }
+        for skipped in &self.skipped {
+            writeln!(f, "  ⏭️  {} ({})", skipped.field, skipped.reason)?;
+        }
+        Ok(())
+    }
+}
+
+type FieldPath = Vec<String>;
+
+/// Leaf values by path. Empty tables have no leaves, so adding the first
+/// entry to one is a single change
+fn leaves(value: &Value, path: &mut FieldPath, out: &mut BTreeMap<FieldPath, Value>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                path.push(key.clone());
+                leaves(child, path, out);
+                path.pop();
+            }
+        }
+        other => {
+            out.insert(path.clone(), other.clone());
+        }
+    }
+}
+
+fn set_leaf(root: &mut Value, path: &[String], value: Option<&Value>) {
+    let Some((last, parents)) = path.split_last() else { return };
+    let mut node = root;
+    for key in parents {
+        let Some(map) = node.as_object_mut() else { return };
+        node = map.entry(key.clone()).or_insert_with(|| Value::Object(Default::default()));
+    }
+    let Some(map) = node.as_object_mut() else { return };
+    match value {
+        Some(value) => map.insert(last.clone(), value.clone()),
+        None => map.remove(last),
+    };
+}
+
+/// What changed between the running settings and the file, by section
+pub struct ReloadPlan {
+    running: Value,
+    new: BTreeMap<FieldPath, Value>,
+    live: BTreeMap<LiveSection, Vec<FieldPath>>,
+    restart: Vec<SkippedChange>,
+}
+
+impl ReloadPlan {
+    pub fn new(running: &Settings, new: &Settings) -> Result<Self> {
+        let running = serde_json::to_value(running)?;
+        let (mut old_leaves, mut new_leaves) = (BTreeMap::new(), BTreeMap::new());
+        leaves(&running, &mut Vec::new(), &mut old_leaves);
+        leaves(&serde_json::to_value(new)?, &mut Vec::new(), &mut new_leaves);
+
+        let mut changed: Vec<&FieldPath> = old_leaves
+            .keys()
+            .chain(new_leaves.keys())
+            .filter(|path| old_leaves.get(*path) != new_leaves.get(*path))
+            .collect();
+        changed.sort();
+        changed.dedup();
+
+        let (mut live, mut restart) = (BTreeMap::<_, Vec<_>>::new(), Vec::new());
+        for path in changed {
+            let field = path.join(".");
+            match classify(&field) {
+                Ok(section) => live.entry(section).or_default().push(path.clone()),
+                Err(reason) => restart.push(SkippedChange { field, reason: reason.to_string() }),
+            }
+        }
+        Ok(Self { running, new: new_leaves, live, restart })
+    }
+
+    /// Sections with changes, in apply order
+    pub fn sections(&self) -> Vec<LiveSection> {
+        self.live.keys().copied().collect()
+    }
+
+    pub fn fields(&self, section: LiveSection) -> Vec<String> {
+        self.live.get(&section).map_or_else(Vec::new, |paths| paths.iter().map(|p| p.join(".")).collect())
+    }
+
+    /// Report listing the restart-only changes, to add the rest to
+    pub fn report(&self, source: &str) -> ReloadReport {
+        ReloadReport { source: source.to_string(), skipped: self.restart.clone(), ..Default::default() }
+    }
+
+    /// The running settings with the changes in `sections` taken from the
+    /// new file; everything else keeps its running value
+    pub fn merged(&self, sections: &[LiveSection]) -> Result<Settings> {
+        let mut merged = self.running.clone();
+        for section in sections {
+            for path in self.live.get(section).into_iter().flatten() {
+                set_leaf(&mut merged, path, self.new.get(path));
+            }
+        }
+        serde_json::from_value(merged).context("Merged settings don't parse")
+    }
+}
+
+/// Fill in `report` for a plan: `unavailable` says why a section can't be
+/// applied on this node (its subsystem isn't running). Returns the sections
+/// to apply
+pub fn partition(
+    plan: &ReloadPlan,
+    report: &mut ReloadReport,
+    unavailable: impl Fn(LiveSection) -> Option<&'static str>,
+) -> Vec<LiveSection> {
+    let mut sections = Vec::new();
+    for section in plan.sections() {
+        match unavailable(section) {
+            Some(reason) => report.skip(&plan.fields(section), reason),
+            None => sections.push(section),
+        }
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::p2p::P2PNode;
+    use crate::security::notifications::NotificationRouter;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("p2p.rate_limits.messages_per_second"), Ok(LiveSection::RateLimits));
+        assert_eq!(classify("p2p.geo_policy.asn_caps.AS64500"), Ok(LiveSection::GeoPolicy));
+        assert!(classify("p2p.geo_policy.enabled").is_err());
+        assert_eq!(classify("p2p.listen"), Err(READ_AT_STARTUP));
+        assert_eq!(classify("p2p.listen_require_all"), Err(READ_AT_STARTUP));
+        assert_eq!(classify("maintenance.step_timeout"), Err(READ_AT_STARTUP));
+        assert_eq!(classify("logging.level"), Ok(LiveSection::Logging));
+    }
+
+    #[test]
+    fn test_merged_keeps_restart_fields() {
+        let running = Settings::default();
+        let new: Settings = toml::from_str(
+            r#"
+            [p2p]
+            listen = ["/ip4/0.0.0.0/tcp/9100"]
+
+            [p2p.geo_policy]
+            enabled = true
+            deny_asns = [64500]
+
+            [p2p.geo_policy.asn_caps]
+            AS64501 = 10
+            "#,
+        )
+        .unwrap();
+        let plan = ReloadPlan::new(&running, &new).unwrap();
+        assert_eq!(plan.sections(), vec![LiveSection::GeoPolicy]);
+        assert_eq!(plan.fields(LiveSection::GeoPolicy), vec!["p2p.geo_policy.asn_caps.AS64501", "p2p.geo_policy.deny_asns"]);
+
+        let merged = plan.merged(&plan.sections()).unwrap();
+        assert_eq!(merged.p2p.geo_policy.deny_asns, vec![64500]);
+        assert_eq!(merged.p2p.geo_policy.asn_caps.get("AS64501"), Some(&10));
+        assert!(!merged.p2p.geo_policy.enabled);
+        assert!(merged.p2p.listen.is_empty());
+        // Nothing merged: a second reload sees the same changes
+        assert_eq!(ReloadPlan::new(&plan.merged(&[]).unwrap(), &new).unwrap().sections(), plan.sections());
+    }
+
+    #[tokio::test]
+    async fn test_reload_applies_live_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.toml");
+        let write = |content: &str| std::fs::write(&path, content).unwrap();
+        write("[p2p.rate_limits]\nmessages_per_second = 10\n");
+
+        let running = Settings::load(&path).unwrap();
+        let mut node = P2PNode::new().unwrap();
+        node.enable_admission_control(running.p2p.admission.clone());
+        node.set_notifier(Arc::new(NotificationRouter::from_config(&running.notifications).unwrap()));
+        node.set_config_source(Some(path.clone()), running);
+        assert!(node.reload_config().await.applied.is_empty());
+
+        write(
+            r#"
+            [p2p]
+            listen = ["/ip4/0.0.0.0/tcp/9100"]
+
+            [p2p.rate_limits]
+            messages_per_second = 2
+
+            [p2p.admission]
+            high_water_mark = 5
+
+            [p2p.geo_policy]
+            deny_asns = [64500]
+
+            [[notifications.sinks]]
+            name = "audit"
+            kind = "file"
+            path = "/tmp/quantra-reload-test.jsonl"
+
+            [[notifications.routes]]
+            categories = ["*"]
+            sinks = ["audit"]
+            "#,
+        );
+        let report = node.reload_config().await;
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(
+            report.applied,
+            vec![
+                "p2p.rate_limits.messages_per_second",
+                "p2p.admission.high_water_mark",
+                "notifications.routes",
+                "notifications.sinks",
+            ]
+        );
+        let skipped: Vec<(&str, &str)> =
+            report.skipped.iter().map(|s| (s.field.as_str(), s.reason.as_str())).collect();
+        assert_eq!(
+            skipped,
+            vec![
+                ("p2p.listen", READ_AT_STARTUP),
+                ("p2p.geo_policy.deny_asns", "geo policy is not enabled on this node"),
+            ]
+        );
+
+        // The new quota is in force
+        let peer = libp2p::PeerId::random();
+        {
+            let mut limiter = node.rate_limiter.lock();
+            limiter.register_peer(peer);
+            assert!(limiter.check_message(&peer) && limiter.check_message(&peer));
+            assert!(!limiter.check_message(&peer));
+        }
+
+        // Skipped changes are reported again; applied ones are not
+        let report = node.reload_config().await;
+        assert!(report.applied.is_empty());
+        assert_eq!(report.skipped.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_broken_file_changes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.toml");
+        std::fs::write(&path, "[logging]\nlevel = \"info\"\n").unwrap();
+        let mut node = P2PNode::new().unwrap();
+        node.set_config_source(Some(path.clone()), Settings::load(&path).unwrap());
+
+        for broken in [
+            "[p2p.rate_limits\nmessages_per_second = 1\n",
+            "[p2p.rate_limits]\nmessages_per_second = \"fast\"\n",
+            // Parses, but fails validation: all or nothing
+            "[p2p.rate_limits]\nmessages_per_second = 1\n[logging]\nlevel = \"quantra=verbose\"\n",
+        ] {
+            std::fs::write(&path, broken).unwrap();
+            let report = node.reload_config().await;
+            assert!(!report.is_ok());
+            assert!(report.applied.is_empty() && report.skipped.is_empty());
+            assert!(report.to_string().contains("nothing changed"), "{}", report);
+        }
+
+        let peer = libp2p::PeerId::random();
+        let mut limiter = node.rate_limiter.lock();
+        limiter.register_peer(peer);
+        assert!((0..10).all(|_| limiter.check_message(&peer)));
+    }
+}
@@ -0,0 +1,258 @@
+//! Replay Registry
+//! Persistent record of deliveries the application has acknowledged, so a
+//! message redelivered after a restart isn't processed twice. Entries expire
+//! after a TTL and are dropped by periodic compaction
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::migrations::{self, StoreSchema};
+use crate::storage::{KvStore, RuntimeMode};
+use crate::units::HumanDuration;
+
+pub const SCHEMA: StoreSchema = StoreSchema {
+    name: "replay_registry",
+    tree: Some("processed"),
+    version: 1,
+    migrations: &[],
+    index: None,
+};
+
+/// `[p2p.replay]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplayConfig {
+    /// How long an acknowledged delivery is remembered; redeliveries older
+    /// than this are surfaced again
+    pub ttl: HumanDuration,
+    /// How often expired entries are compacted away
+    pub compact_interval: HumanDuration,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            ttl: HumanDuration::from_secs(7 * 24 * 3600),
+            compact_interval: HumanDuration::from_secs(3600),
+        }
+    }
+}
+
+/// One logical delivery: SHA-256 over its kind, sender and payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeliveryKey([u8; 32]);
+
+impl DeliveryKey {
+    pub fn new(kind: &str, sender: &str, payload: &[u8]) -> Self {
+        let mut hasher = Sha256::new();
+        for part in [kind.as_bytes(), sender.as_bytes()] {
+            hasher.update((part.len() as u32).to_be_bytes());
+            hasher.update(part);
+        }
+        hasher.update(payload);
+        Self(hasher.finalize().into())
+    }
+}
+
+impl fmt::Display for DeliveryKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(&self.0[..8]))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReplayStats {
+    /// Acknowledged deliveries still remembered
+    pub entries: usize,
+    /// Surfaced but not yet acknowledged
+    pub in_flight: usize,
+    pub duplicates_suppressed: u64,
+    /// Expired entries removed by compaction
+    pub expired: u64,
+}
+
+/// Acknowledged delivery keys with their expiry (Unix millis, big-endian)
+pub struct ReplayRegistry {
+    store: Box<dyn KvStore>,
+    ttl: chrono::Duration,
+    in_flight: Mutex<HashSet<DeliveryKey>>,
+    duplicates_suppressed: AtomicU64,
+    expired: AtomicU64,
+}
+
+impl ReplayRegistry {
+    /// Open the registry in `dir`, or keep it in memory when ephemeral
+    pub fn open(dir: &Path, mode: RuntimeMode, ttl: HumanDuration) -> Result<Arc<Self>> {
+        let store = migrations::open_store(mode, dir, &SCHEMA)
+            .with_context(|| format!("Failed to open replay registry at {}", dir.display()))?;
+        Ok(Arc::new(Self {
+            store,
+            ttl: ttl.as_chrono(),
+            in_flight: Mutex::new(HashSet::new()),
+            duplicates_suppressed: AtomicU64::new(0),
+            expired: AtomicU64::new(0),
+        }))
+    }
+
+    /// Claim `key` for delivery. None if it was already acknowledged (and
+    /// hasn't expired) or is being delivered right now.
+    pub fn begin(self: &Arc<Self>, key: DeliveryKey, now: DateTime<Utc>) -> Result<Option<Delivery>> {
+        let processed = match self.store.get(&key.0)? {
+            Some(expiry) => decode_expiry(&expiry)? > now.timestamp_millis(),
+            None => false,
+        };
+        if processed || !self.in_flight.lock().insert(key) {
+            self.duplicates_suppressed.fetch_add(1, Ordering::Relaxed);
+            tracing::debug!("🔁 Suppressed duplicate delivery {}", key);
+            return Ok(None);
+        }
+        Ok(Some(Delivery(Arc::new(DeliveryInner {
+            registry: self.clone(),
+            key,
+            acked: AtomicBool::new(false),
+        }))))
+    }
+
+    /// Remember `key` until the TTL runs out. Durable before it returns, so
+    /// a crash either loses the ack (redelivery) or keeps it (suppression)
+    fn ack(&self, key: DeliveryKey, now: DateTime<Utc>) -> Result<()> {
+        let expiry = (now + self.ttl).timestamp_millis();
+        self.store.insert(&key.0, &expiry.to_be_bytes())?;
+        self.store.flush()?;
+        self.in_flight.lock().remove(&key);
+        Ok(())
+    }
+
+    /// Drop entries whose TTL has passed; returns how many
+    pub fn compact(&self, now: DateTime<Utc>) -> Result<usize> {
+        let now = now.timestamp_millis();
+        let mut removed = 0;
+        for (key, expiry) in self.store.entries()? {
+            if decode_expiry(&expiry)? <= now {
+                self.store.remove(&key)?;
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            self.store.flush()?;
+            self.expired.fetch_add(removed as u64, Ordering::Relaxed);
+            tracing::debug!("🧹 Compacted {} expired replay entries", removed);
+        }
+        Ok(removed)
+    }
+
+    pub fn stats(&self) -> Result<ReplayStats> {
+        Ok(ReplayStats {
+            entries: self.store.entries()?.len(),
+            in_flight: self.in_flight.lock().len(),
+            duplicates_suppressed: self.duplicates_suppressed.load(Ordering::Relaxed),
+            expired: self.expired.load(Ordering::Relaxed),
+        })
+    }
+}
+
+fn decode_expiry(bytes: &[u8]) -> Result<i64> {
+    let bytes: [u8; 8] = bytes.try_into().context("Corrupt replay registry entry")?;
+    Ok(i64::from_be_bytes(bytes))
+}
+
+/// Handed to the application with a delivery. `ack` once it's processed;
+/// if every copy is dropped unacknowledged the delivery may be surfaced again
+#[derive(Clone)]
+pub struct Delivery(Arc<DeliveryInner>);
+
+struct DeliveryInner {
+    registry: Arc<ReplayRegistry>,
+    key: DeliveryKey,
+    acked: AtomicBool,
+}
+
+impl Delivery {
+    pub fn key(&self) -> DeliveryKey {
+        self.0.key
+    }
+
+    /// Record the delivery as processed
+    pub fn ack(&self) -> Result<()> {
+        if !self.0.acked.swap(true, Ordering::AcqRel) {
+            if let Err(e) = self.0.registry.ack(self.0.key, Utc::now()) {
+                self.0.acked.store(false, Ordering::Release);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for DeliveryInner {
+    fn drop(&mut self) {
+        if !self.acked.load(Ordering::Acquire) {
+            self.registry.in_flight.lock().remove(&self.key);
+        }
+    }
+}
+
+impl fmt::Debug for Delivery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Delivery")
+            .field("key", &self.0.key.to_string())
+            .field("acked", &self.0.acked.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_ack_suppresses_until_expiry() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let registry = ReplayRegistry::open(dir.path(), RuntimeMode::Persistent, HumanDuration::from_secs(60)).unwrap();
+        let key = DeliveryKey::new("direct", "peer-a", b"hello");
+        let now = Utc::now();
+
+        // In flight: a concurrent redelivery is suppressed
+        let delivery = registry.begin(key, now).unwrap().unwrap();
+        assert!(registry.begin(key, now).unwrap().is_none());
+
+        // Dropped without ack: it can be delivered again
+        drop(delivery);
+        let delivery = registry.begin(key, now).unwrap().unwrap();
+        delivery.ack().unwrap();
+        drop(delivery);
+        assert!(registry.begin(key, now).unwrap().is_none());
+        assert!(registry.begin(key, now + Duration::seconds(61)).unwrap().is_some());
+
+        // Same payload from another sender is a different delivery
+        assert!(registry.begin(DeliveryKey::new("direct", "peer-b", b"hello"), now).unwrap().is_some());
+        assert_eq!(registry.stats().unwrap().duplicates_suppressed, 2);
+    }
+
+    #[test]
+    fn test_compaction_removes_expired_entries() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let registry = ReplayRegistry::open(dir.path(), RuntimeMode::Persistent, HumanDuration::from_secs(60)).unwrap();
+        let now = Utc::now();
+        for i in 0..5u8 {
+            registry.ack(DeliveryKey::new("direct", "peer", &[i]), now - Duration::seconds(i as i64 * 30)).unwrap();
+        }
+
+        // Acked 0s and 30s ago survive; 60s, 90s and 120s ago have expired
+        assert_eq!(registry.compact(now).unwrap(), 3);
+        let stats = registry.stats().unwrap();
+        assert_eq!((stats.entries, stats.expired), (2, 3));
+        assert_eq!(registry.compact(now).unwrap(), 0);
+        assert!(registry.begin(DeliveryKey::new("direct", "peer", &[0]), now).unwrap().is_none());
+        assert!(registry.begin(DeliveryKey::new("direct", "peer", &[4]), now).unwrap().is_some());
+    }
+}
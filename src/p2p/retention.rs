@@ -0,0 +1,403 @@
+//! Topic Retention
+//! Recent messages on retained topics, kept in a bounded persistent buffer
+//! per topic and replayed to late-joining subscribers a page at a time.
+//! Replayed messages carry no gossip signature: they are only as
+//! trustworthy as the peer serving them, except on encrypted group topics
+//! where the ciphertext authenticates its sender
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+
+use crate::migrations::{self, StoreSchema};
+use crate::storage::{KvStore, RuntimeMode};
+use crate::units::{HumanDuration, HumanSize};
+
+pub const SCHEMA: StoreSchema = StoreSchema {
+    name: "topic_retention",
+    tree: Some("messages"),
+    version: 1,
+    migrations: &[],
+    index: None,
+};
+
+/// Live messages on a topic are still checked against the replay registry
+/// this long after its replay finished, for copies gossip delivers late
+const SETTLE_GRACE_SECS: i64 = 30;
+
+/// Added to each message's payload when sizing a page
+const MESSAGE_OVERHEAD: u64 = 96;
+
+/// `[p2p.retention]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Topics kept and replayable; a trailing `*` matches any topic with
+    /// that prefix. Empty turns retention off
+    pub topics: Vec<String>,
+    /// Also keep what other nodes publish on these topics, not only our own
+    pub retainer: bool,
+    /// Most messages kept per topic
+    pub max_messages: usize,
+    /// Messages older than this are dropped
+    pub max_age: HumanDuration,
+    /// Largest replay page; longer replays continue with a token. Larger
+    /// messages are never retained
+    pub max_response: HumanSize,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            topics: Vec::new(),
+            retainer: false,
+            max_messages: 500,
+            max_age: HumanDuration::from_secs(15 * 60),
+            max_response: HumanSize::from_bytes(256 * 1024),
+        }
+    }
+}
+
+impl RetentionConfig {
+    pub fn covers(&self, topic: &str) -> bool {
+        self.topics.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => topic.starts_with(prefix),
+            None => topic == pattern,
+        })
+    }
+}
+
+/// Where a replay starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayFrom {
+    /// Messages retained at or after this time
+    Since(DateTime<Utc>),
+    /// The newest `n` messages
+    LastN(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetainedMessage {
+    /// Position in the topic's buffer
+    pub seq: u64,
+    /// Peer that published it
+    pub source: String,
+    pub retained_at: DateTime<Utc>,
+    pub data: Vec<u8>,
+}
+
+/// One page of a replay
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayPage {
+    pub messages: Vec<RetainedMessage>,
+    /// Pass back to get the next page; None on the last one
+    pub continuation: Option<String>,
+}
+
+/// `topics --retained` output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RetainedBuffer {
+    pub topic: String,
+    pub messages: usize,
+    pub bytes: u64,
+    pub oldest: Option<DateTime<Utc>>,
+}
+
+/// A retained message as indexed in memory
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    seq: u64,
+    at: DateTime<Utc>,
+    len: u64,
+}
+
+/// Retained messages, keyed `topic 0x00 seq` (big-endian) with CBOR values.
+/// Each topic's sequence numbers and sizes are indexed in memory
+pub struct TopicRetention {
+    store: Box<dyn KvStore>,
+    config: RetentionConfig,
+    index: HashMap<String, VecDeque<Entry>>,
+    next_seq: HashMap<String, u64>,
+}
+
+fn key(topic: &str, seq: u64) -> Vec<u8> {
+    [topic.as_bytes(), &[0], &seq.to_be_bytes()].concat()
+}
+
+fn encode_token(seq: u64) -> String {
+    format!("{:016x}", seq)
+}
+
+fn decode_token(token: &str) -> Result<u64> {
+    u64::from_str_radix(token, 16).ok().filter(|_| token.len() == 16).context("Malformed continuation token")
+}
+
+impl TopicRetention {
+    /// Open the buffers in `dir`, or keep them in memory when ephemeral
+    pub fn open(dir: &Path, mode: RuntimeMode, config: RetentionConfig) -> Result<Self> {
+        let store = migrations::open_store(mode, dir, &SCHEMA)
+            .with_context(|| format!("Failed to open topic retention at {}", dir.display()))?;
+        let mut retention = Self { store, config, index: HashMap::new(), next_seq: HashMap::new() };
+        for (key, value) in retention.store.entries()? {
+            let message = decode(&value)?;
+            let topic = key
+                .len()
+                .checked_sub(9)
+                .and_then(|end| std::str::from_utf8(&key[..end]).ok())
+                .context("Corrupt topic retention key")?
+                .to_string();
+            retention.next_seq.insert(topic.clone(), message.seq + 1);
+            retention.index.entry(topic).or_default().push_back(Entry {
+                seq: message.seq,
+                at: message.retained_at,
+                len: message.data.len() as u64,
+            });
+        }
+        Ok(retention)
+    }
+
+    pub fn config(&self) -> &RetentionConfig {
+        &self.config
+    }
+
+    /// Keep `data` published on `topic` by `source`, dropping what falls
+    /// outside the limits. False if the topic isn't retained or the message
+    /// is too large for a replay page
+    pub fn retain(&mut self, topic: &str, source: &str, data: &[u8], now: DateTime<Utc>) -> Result<bool> {
+        if !self.config.covers(topic) || data.len() as u64 + MESSAGE_OVERHEAD > self.config.max_response.bytes() {
+            return Ok(false);
+        }
+        let seq = self.next_seq.get(topic).copied().unwrap_or(0);
+        let message = RetainedMessage { seq, source: source.to_string(), retained_at: now, data: data.to_vec() };
+        self.store.insert(&key(topic, seq), &encode(&message)?)?;
+        self.next_seq.insert(topic.to_string(), seq + 1);
+        self.index
+            .entry(topic.to_string())
+            .or_default()
+            .push_back(Entry { seq, at: now, len: data.len() as u64 });
+        self.prune_topic(topic, now)?;
+        Ok(true)
+    }
+
+    /// Drop every topic's messages past the count or age limit
+    pub fn prune(&mut self, now: DateTime<Utc>) -> Result<usize> {
+        let topics: Vec<String> = self.index.keys().cloned().collect();
+        let mut removed = 0;
+        for topic in topics {
+            removed += self.prune_topic(&topic, now)?;
+        }
+        Ok(removed)
+    }
+
+    fn prune_topic(&mut self, topic: &str, now: DateTime<Utc>) -> Result<usize> {
+        let cutoff = now - self.config.max_age.as_chrono();
+        let Some(entries) = self.index.get_mut(topic) else { return Ok(0) };
+        let mut removed = 0;
+        while let Some(entry) = entries.front() {
+            if entries.len() <= self.config.max_messages && entry.at >= cutoff {
+                break;
+            }
+            self.store.remove(&key(topic, entry.seq))?;
+            entries.pop_front();
+            removed += 1;
+        }
+        if entries.is_empty() {
+            self.index.remove(topic);
+        }
+        Ok(removed)
+    }
+
+    /// The page of `topic` starting at `from`, or where `continuation`
+    /// left off. Pages stay within `max_response` but hold at least one
+    /// message
+    pub fn replay(&self, topic: &str, from: ReplayFrom, continuation: Option<&str>, now: DateTime<Utc>) -> Result<ReplayPage> {
+        let cutoff = now - self.config.max_age.as_chrono();
+        let entries: Vec<&Entry> = self
+            .index
+            .get(topic)
+            .map(|entries| entries.iter().filter(|e| e.at >= cutoff).collect())
+            .unwrap_or_default();
+        let start = match (continuation, from) {
+            (Some(token), _) => {
+                let seq = decode_token(token)?;
+                entries.partition_point(|e| e.seq < seq)
+            }
+            (None, ReplayFrom::Since(since)) => entries.partition_point(|e| e.at < since),
+            (None, ReplayFrom::LastN(n)) => entries.len().saturating_sub(n as usize),
+        };
+
+        let budget = self.config.max_response.bytes();
+        let (mut messages, mut used) = (Vec::new(), 0);
+        for entry in &entries[start..] {
+            let size = entry.len + MESSAGE_OVERHEAD;
+            if !messages.is_empty() && used + size > budget {
+                return Ok(ReplayPage { messages, continuation: Some(encode_token(entry.seq)) });
+            }
+            let value = self.store.get(&key(topic, entry.seq))?.context("Retained message missing from store")?;
+            messages.push(decode(&value)?);
+            used += size;
+        }
+        Ok(ReplayPage { messages, continuation: None })
+    }
+
+    pub fn buffers(&self) -> Vec<RetainedBuffer> {
+        let mut buffers: Vec<RetainedBuffer> = self
+            .index
+            .iter()
+            .map(|(topic, entries)| RetainedBuffer {
+                topic: topic.clone(),
+                messages: entries.len(),
+                bytes: entries.iter().map(|e| e.len).sum(),
+                oldest: entries.front().map(|e| e.at),
+            })
+            .collect();
+        buffers.sort_by(|a, b| a.topic.cmp(&b.topic));
+        buffers
+    }
+}
+
+fn encode(message: &RetainedMessage) -> Result<Vec<u8>> {
+    cbor4ii::serde::to_vec(Vec::new(), message).map_err(|e| anyhow::anyhow!("Failed to encode retained message: {}", e))
+}
+
+fn decode(bytes: &[u8]) -> Result<RetainedMessage> {
+    cbor4ii::serde::from_slice(bytes).map_err(|e| anyhow::anyhow!("Corrupt retained message: {}", e))
+}
+
+/// Late-joiner side: topics waiting for a peer to replay them, and those
+/// whose live messages are checked against the replay registry until the
+/// replay settles
+#[derive(Debug, Default)]
+pub struct ReplayTracker {
+    wanted: HashSet<String>,
+    in_flight: HashMap<String, usize>,
+    settle_until: HashMap<String, DateTime<Utc>>,
+}
+
+impl ReplayTracker {
+    /// Ask the next peer seen on `topic` for a replay
+    pub fn want(&mut self, topic: &str) {
+        self.wanted.insert(topic.to_string());
+    }
+
+    pub fn wants(&self, topic: &str) -> bool {
+        self.wanted.contains(topic)
+    }
+
+    /// A replay request (or its next page) went out
+    pub fn begin(&mut self, topic: &str) {
+        self.wanted.remove(topic);
+        *self.in_flight.entry(topic.to_string()).or_default() += 1;
+    }
+
+    /// A page arrived, or the request failed. False if none was in flight
+    pub fn end(&mut self, topic: &str, now: DateTime<Utc>) -> bool {
+        let Some(count) = self.in_flight.get_mut(topic) else { return false };
+        *count -= 1;
+        if *count == 0 {
+            self.in_flight.remove(topic);
+        }
+        self.settle_until.insert(topic.to_string(), now + chrono::Duration::seconds(SETTLE_GRACE_SECS));
+        true
+    }
+
+    /// The request failed: ask the next peer instead
+    pub fn failed(&mut self, topic: &str, now: DateTime<Utc>) {
+        if self.end(topic, now) && !self.in_flight.contains_key(topic) {
+            self.wanted.insert(topic.to_string());
+        }
+    }
+
+    /// Whether live messages on `topic` may also arrive replayed
+    pub fn is_settling(&mut self, topic: &str, now: DateTime<Utc>) -> bool {
+        if self.in_flight.contains_key(topic) {
+            return true;
+        }
+        match self.settle_until.get(topic) {
+            Some(until) if *until > now => true,
+            Some(_) => {
+                self.settle_until.remove(topic);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn config(max_messages: usize, max_response: u64) -> RetentionConfig {
+        RetentionConfig {
+            topics: vec!["quotes/*".to_string()],
+            retainer: true,
+            max_messages,
+            max_age: HumanDuration::from_secs(600),
+            max_response: HumanSize::from_bytes(max_response),
+        }
+    }
+
+    #[test]
+    fn test_buffers_bounded_and_persistent() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let now = Utc::now();
+        {
+            let mut retention = TopicRetention::open(dir.path(), RuntimeMode::Persistent, config(5, 64 * 1024)).unwrap();
+            assert!(!retention.retain("alerts/x", "peer", b"not retained", now).unwrap());
+            assert!(!retention.retain("quotes/AAPL", "peer", &[0; 64 * 1024], now).unwrap());
+            for i in 0..8u8 {
+                let at = now - Duration::minutes(20) + Duration::seconds(i as i64);
+                retention.retain("quotes/AAPL", "peer", &[i], if i < 2 { at } else { now }).unwrap();
+            }
+            assert_eq!(retention.buffers()[0].messages, 5);
+        }
+
+        // Reopened: same buffer, and numbering continues
+        let mut retention = TopicRetention::open(dir.path(), RuntimeMode::Persistent, config(5, 64 * 1024)).unwrap();
+        retention.retain("quotes/AAPL", "peer", &[8], now).unwrap();
+        let page = retention.replay("quotes/AAPL", ReplayFrom::LastN(100), None, now).unwrap();
+        let data: Vec<u8> = page.messages.iter().map(|m| m.data[0]).collect();
+        assert_eq!(data, vec![4, 5, 6, 7, 8]);
+        assert_eq!(page.messages.last().unwrap().seq, 8);
+
+        // Aged out by time as well as count
+        assert_eq!(retention.prune(now + Duration::minutes(11)).unwrap(), 5);
+        assert!(retention.buffers().is_empty());
+    }
+
+    #[test]
+    fn test_replay_paginates_large_buffer() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut retention = TopicRetention::open(dir.path(), RuntimeMode::Persistent, config(1000, 2048)).unwrap();
+        let start = Utc::now();
+        for i in 0..100u32 {
+            let data = [i.to_be_bytes().as_slice(), &[0; 100]].concat();
+            retention.retain("quotes/MSFT", "peer", &data, start + Duration::milliseconds(i as i64)).unwrap();
+        }
+        let now = start + Duration::seconds(1);
+
+        let (mut seen, mut pages, mut token) = (Vec::new(), 0, None);
+        loop {
+            let page = retention.replay("quotes/MSFT", ReplayFrom::LastN(100), token.as_deref(), now).unwrap();
+            let size: u64 = page.messages.iter().map(|m| m.data.len() as u64 + MESSAGE_OVERHEAD).sum();
+            assert!(size <= 2048);
+            seen.extend(page.messages.iter().map(|m| m.seq));
+            pages += 1;
+            match page.continuation {
+                Some(next) => token = Some(next),
+                None => break,
+            }
+        }
+        assert!(pages > 1);
+        assert_eq!(seen, (0..100).collect::<Vec<u64>>());
+
+        let since = retention.replay("quotes/MSFT", ReplayFrom::Since(start + Duration::milliseconds(95)), None, now).unwrap();
+        assert_eq!(since.messages.iter().map(|m| m.seq).collect::<Vec<_>>(), vec![95, 96, 97, 98, 99]);
+        assert!(retention.replay("quotes/MSFT", ReplayFrom::LastN(1), Some("zz"), now).is_err());
+    }
+}
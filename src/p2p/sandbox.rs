@@ -0,0 +1,121 @@
+//! Request Sandbox
+//! What every inbound request passes before its handler: the structural
+//! checks of `RequestLimits`, and for compute-heavy requests a per-peer
+//! concurrency cap and a compute budget enforced by cancellation
+
+use libp2p::PeerId;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::protocol::{QuantraRequest, RequestLimits};
+use crate::quant::pricing::cancel::CancelToken;
+
+pub struct RequestSandbox {
+    limits: RequestLimits,
+    in_flight: Arc<Mutex<HashMap<PeerId, usize>>>,
+}
+
+impl RequestSandbox {
+    pub fn new(limits: RequestLimits) -> Self {
+        Self { limits, in_flight: Arc::default() }
+    }
+
+    pub fn limits(&self) -> &RequestLimits {
+        &self.limits
+    }
+
+    /// Why `request` may not reach a handler, if it may not
+    pub fn validate(&self, request: &QuantraRequest) -> Result<(), String> {
+        request.validate(&self.limits)
+    }
+
+    /// A slot for one compute-heavy request from `peer`, held until the
+    /// permit drops; `None` when the peer already has the maximum running
+    pub fn try_acquire(&self, peer: PeerId) -> Option<InFlightPermit> {
+        let mut in_flight = self.in_flight.lock();
+        let running = in_flight.entry(peer).or_default();
+        if *running >= self.limits.max_concurrent_per_peer {
+            return None;
+        }
+        *running += 1;
+        Some(InFlightPermit { peer, in_flight: self.in_flight.clone() })
+    }
+
+    /// Token that fires when a request started now uses up its budget
+    pub fn budget(&self) -> CancelToken {
+        CancelToken::with_deadline(Instant::now() + self.limits.compute_budget.as_std())
+    }
+}
+
+/// One running compute-heavy request
+pub struct InFlightPermit {
+    peer: PeerId,
+    in_flight: Arc<Mutex<HashMap<PeerId, usize>>>,
+}
+
+impl Drop for InFlightPermit {
+    fn drop(&mut self) {
+        let mut in_flight = self.in_flight.lock();
+        if let Some(running) = in_flight.get_mut(&self.peer) {
+            *running -= 1;
+            if *running == 0 {
+                in_flight.remove(&self.peer);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quant::pricing::OptionType;
+    use crate::quant::remote::{PricingInputs, PricingModel};
+
+    const INPUTS: PricingInputs = PricingInputs {
+        spot: 100.0,
+        strike: 100.0,
+        rate: 0.03,
+        dividend_yield: 0.0,
+        volatility: 0.2,
+        time_to_expiry: 1.0,
+        option_type: OptionType::Call,
+    };
+
+    #[test]
+    fn test_structural_limits() {
+        let sandbox = RequestSandbox::new(RequestLimits { max_chain_strikes: 100, ..Default::default() });
+        let chain = |n: usize| QuantraRequest::PriceChain {
+            inputs: INPUTS,
+            strikes: (1..=n).map(|k| k as f64).collect(),
+            model: PricingModel::BlackScholes,
+        };
+        assert_eq!(sandbox.validate(&chain(100)), Ok(()));
+        assert!(sandbox.validate(&chain(101)).unwrap_err().contains("101 strikes"));
+
+        let steps = QuantraRequest::PriceOption { inputs: INPUTS, model: PricingModel::Binomial { steps: 1_000_000, american: true } };
+        assert!(sandbox.validate(&steps).unwrap_err().contains("binomial steps"));
+        let nan = QuantraRequest::PriceOption { inputs: PricingInputs { volatility: f64::NAN, ..INPUTS }, model: PricingModel::BlackScholes };
+        assert!(sandbox.validate(&nan).unwrap_err().contains("volatility"));
+
+        let symbol = QuantraRequest::GetQuote { symbol: "X".repeat(257) };
+        assert!(sandbox.validate(&symbol).unwrap_err().contains("symbol"));
+        let empty = QuantraRequest::ProvisionESim { profile_data: Vec::new() };
+        assert!(sandbox.validate(&empty).is_err());
+        assert_eq!(sandbox.validate(&QuantraRequest::Ping), Ok(()));
+    }
+
+    #[test]
+    fn test_concurrency_cap_per_peer() {
+        let sandbox = RequestSandbox::new(RequestLimits { max_concurrent_per_peer: 2, ..Default::default() });
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+        let first = sandbox.try_acquire(alice).unwrap();
+        let _second = sandbox.try_acquire(alice).unwrap();
+        assert!(sandbox.try_acquire(alice).is_none());
+        assert!(sandbox.try_acquire(bob).is_some());
+        // Finishing one frees its slot
+        drop(first);
+        assert!(sandbox.try_acquire(alice).is_some());
+    }
+}
@@ -0,0 +1,130 @@
+//! Proxied TCP Transport
+//! TCP whose dials go through a SOCKS5 proxy, so peers see the proxy's
+//! address and `/dns*` names are resolved by the proxy. Listening is plain
+//! TCP: a proxy can't accept connections for us, so inbound peers connect
+//! directly (behind Tor, publish an onion service for that instead)
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use libp2p::core::transport::{DialOpts, ListenerId, TransportError, TransportEvent};
+use libp2p::multiaddr::Protocol;
+use libp2p::{tcp, Multiaddr, Transport};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::net::socks5::{self, Target};
+use crate::net::Socks5Proxy;
+
+pub struct Socks5Tcp {
+    inner: tcp::tokio::Transport,
+    proxy: Socks5Proxy,
+}
+
+impl Socks5Tcp {
+    pub fn new(config: tcp::Config, proxy: Socks5Proxy) -> Self {
+        Self { inner: tcp::tokio::Transport::new(config), proxy }
+    }
+}
+
+/// Where the proxy should connect for `/ip4|ip6|dns|dns4|dns6/../tcp/..`,
+/// optionally ending in `/p2p/..`
+pub fn dial_target(addr: &Multiaddr) -> Option<Target> {
+    let mut protocols = addr.iter();
+    let host = protocols.next()?;
+    let Some(Protocol::Tcp(port)) = protocols.next() else { return None };
+    if !protocols.all(|p| matches!(p, Protocol::P2p(_))) {
+        return None;
+    }
+    match host {
+        Protocol::Ip4(ip) => Some(Target::Ip(SocketAddr::new(ip.into(), port))),
+        Protocol::Ip6(ip) => Some(Target::Ip(SocketAddr::new(ip.into(), port))),
+        Protocol::Dns(name) | Protocol::Dns4(name) | Protocol::Dns6(name) => Some(Target::Domain(name.to_string(), port)),
+        _ => None,
+    }
+}
+
+impl Transport for Socks5Tcp {
+    type Output = tcp::tokio::TcpStream;
+    type Error = io::Error;
+    type ListenerUpgrade = <tcp::tokio::Transport as Transport>::ListenerUpgrade;
+    type Dial = BoxFuture<'static, io::Result<Self::Output>>;
+
+    fn listen_on(&mut self, id: ListenerId, addr: Multiaddr) -> Result<(), TransportError<Self::Error>> {
+        self.inner.listen_on(id, addr)
+    }
+
+    fn remove_listener(&mut self, id: ListenerId) -> bool {
+        self.inner.remove_listener(id)
+    }
+
+    fn dial(&mut self, addr: Multiaddr, _opts: DialOpts) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let Some(target) = dial_target(&addr) else {
+            return Err(TransportError::MultiaddrNotSupported(addr));
+        };
+        let proxy = self.proxy.clone();
+        Ok(async move {
+            tracing::debug!("🧅 Dialing {} via {}", target, proxy);
+            socks5::connect(&proxy, &target).await.map(tcp::tokio::TcpStream)
+        }
+        .boxed())
+    }
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::socks5::testing::TestProxy;
+    use crate::net::ProxyConfig;
+    use crate::p2p::P2PNode;
+    use libp2p::identity::Keypair;
+    use libp2p::swarm::SwarmEvent;
+    use std::time::Duration;
+
+    #[test]
+    fn test_dial_targets() {
+        let target = |addr: &str| dial_target(&addr.parse().unwrap());
+        assert_eq!(target("/ip4/10.0.0.1/tcp/4001"), Some(Target::Ip("10.0.0.1:4001".parse().unwrap())));
+        assert_eq!(target("/ip6/::1/tcp/4001"), Some(Target::Ip("[::1]:4001".parse().unwrap())));
+        assert_eq!(
+            target(&format!("/dns4/boot.example/tcp/4001/p2p/{}", libp2p::PeerId::random())),
+            Some(Target::Domain("boot.example".to_string(), 4001))
+        );
+        assert_eq!(target("/ip4/10.0.0.1/udp/4001/quic-v1"), None);
+        assert_eq!(target("/memory/5"), None);
+    }
+
+    #[tokio::test]
+    async fn test_p2p_dial_traverses_proxy() {
+        let proxy = TestProxy::start(None).await;
+        proxy.route("node-b.invalid", "127.0.0.1:4470".parse().unwrap());
+        let config = ProxyConfig { enabled: true, host: "127.0.0.1".to_string(), port: proxy.addr.port(), ..Default::default() };
+
+        let mut node_b = P2PNode::new().unwrap();
+        node_b.disable_mdns();
+        node_b.listen_on("/ip4/127.0.0.1/tcp/4470").unwrap();
+        let mut node_a = P2PNode::with_proxy(Keypair::generate_ed25519(), config.endpoint().unwrap()).unwrap();
+        // The name only means something to the proxy
+        node_a.dial(&format!("/dns4/node-b.invalid/tcp/4470/p2p/{}", node_b.local_peer_id())).unwrap();
+
+        let connected = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                while let Some(event) = node_b.poll_events().await {
+                    if let SwarmEvent::ConnectionEstablished { .. } = event {
+                        return;
+                    }
+                }
+                while node_a.poll_events().await.is_some() {}
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await;
+        assert!(connected.is_ok(), "no connection through the proxy");
+        assert_eq!(proxy.targets()[0], Target::Domain("node-b.invalid".to_string(), 4470));
+    }
+}
@@ -0,0 +1,115 @@
+//! Status Endpoint
+//! Local HTTP view of a running node (`/status`, `/zerotrust`, `/shield`).
+//! `/status` reads a snapshot the event loop publishes, so requests never
+//! wait on the swarm
+
+use anyhow::Result;
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::dht_records::DhtRecordStats;
+use super::geo_policy::GeoPolicyStats;
+use super::NodeCounters;
+use crate::security::mirror_shield::MirrorShield;
+use crate::zerotrust::ZeroTrustContext;
+
+/// What `/status` reports, refreshed by the node after each swarm event
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NodeStatus {
+    pub peer_id: String,
+    pub listen_addrs: Vec<String>,
+    pub connected_peers: usize,
+    /// Gossipsub topics, sorted
+    pub topics: Vec<String>,
+    pub counters: NodeCounters,
+    /// Per-country / per-ASN counters, with geo policy enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geo: Option<GeoPolicyStats>,
+    /// Owned DHT records, with the record journal enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dht_records: Option<DhtRecordStats>,
+    /// Filled in when served
+    pub uptime_secs: u64,
+}
+
+/// Shared state behind the endpoints; see `P2PNode::status_endpoint`
+#[derive(Clone)]
+pub struct StatusEndpoint {
+    status: Arc<RwLock<NodeStatus>>,
+    started: Instant,
+    zero_trust: Option<ZeroTrustContext>,
+    shield: Option<Arc<MirrorShield>>,
+}
+
+impl StatusEndpoint {
+    pub fn new(
+        status: Arc<RwLock<NodeStatus>>,
+        started: Instant,
+        zero_trust: Option<ZeroTrustContext>,
+        shield: Option<Arc<MirrorShield>>,
+    ) -> Self {
+        Self { status, started, zero_trust, shield }
+    }
+
+    /// The current snapshot, with uptime
+    pub fn status(&self) -> NodeStatus {
+        let mut status = self.status.read().clone();
+        status.uptime_secs = self.started.elapsed().as_secs();
+        status
+    }
+
+    /// `GET /status`, `/zerotrust` (404 unless zero-trust is enabled) and
+    /// `/shield` (404 unless Mirror Shield is attached)
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/status", get(status))
+            .route("/zerotrust", get(zero_trust))
+            .route("/shield", get(shield))
+            .with_state(self)
+    }
+}
+
+fn json(status: StatusCode, body: serde_json::Value) -> impl IntoResponse {
+    (status, [(header::CONTENT_TYPE, "application/json")], body.to_string())
+}
+
+fn not_enabled(what: &str) -> (StatusCode, serde_json::Value) {
+    (StatusCode::NOT_FOUND, serde_json::json!({ "error": "NOT_ENABLED", "message": format!("{} is not enabled on this node", what) }))
+}
+
+async fn status(State(endpoint): State<StatusEndpoint>) -> impl IntoResponse {
+    json(StatusCode::OK, serde_json::json!(endpoint.status()))
+}
+
+async fn zero_trust(State(endpoint): State<StatusEndpoint>) -> impl IntoResponse {
+    let (status, body) = match &endpoint.zero_trust {
+        Some(zt) => match zt.get_stats().await {
+            Ok(stats) => (StatusCode::OK, serde_json::json!(stats)),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, serde_json::json!({ "error": "STATS_UNAVAILABLE", "message": format!("{:#}", e) })),
+        },
+        None => not_enabled("Zero-Trust"),
+    };
+    json(status, body)
+}
+
+async fn shield(State(endpoint): State<StatusEndpoint>) -> impl IntoResponse {
+    let (status, body) = match &endpoint.shield {
+        Some(shield) => (StatusCode::OK, serde_json::json!(shield.get_stats().await)),
+        None => not_enabled("Mirror Shield"),
+    };
+    json(status, body)
+}
+
+/// Serve `endpoint` on `listener` until the task is dropped
+pub async fn serve(endpoint: StatusEndpoint, listener: tokio::net::TcpListener) -> Result<()> {
+    tracing::info!("📊 Node status listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, endpoint.router()).await?;
+    Ok(())
+}
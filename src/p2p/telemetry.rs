@@ -0,0 +1,564 @@
+//! Network Telemetry
+//! Opt-in sharing of coarse node statistics on `quantra-telemetry`. Reports
+//! carry only bucketed or rounded figures, with Laplace noise on attack
+//! counts; collectors aggregate them for a network health view
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration as ChronoDuration, DurationRound, Utc};
+use ed25519_dalek::{Signature, VerifyingKey};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use super::partition::{self, PartitionStamp};
+use super::wire;
+use crate::crypto::key_provider::KeyProvider;
+use crate::security::mirror_shield::AttackType;
+use crate::units::HumanDuration;
+
+pub const TELEMETRY_TOPIC: &str = "quantra-telemetry";
+
+/// Reports are signed as this, then their canonical CBOR; reports signed
+/// over the bare JSON (before v2) are still verified
+const SIGNING_CONTEXT: &[u8] = b"quantra-stats-report-v2\0";
+
+/// Messages per hour are reported as a multiple of this
+const MESSAGE_GRID: u64 = 100;
+
+/// Upper bounds of the peer count buckets
+const PEER_BUCKETS: &[(usize, &str)] = &[(0, "0"), (5, "1-5"), (20, "6-20"), (50, "21-50"), (100, "51-100")];
+
+/// `[p2p.telemetry]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    /// Publish a stats report every `interval`. Off unless set
+    pub enabled: bool,
+    pub interval: HumanDuration,
+    /// Privacy budget per report for the attack counts; smaller is noisier
+    pub epsilon: f64,
+    /// Collectors count one report per reporter within this window
+    pub collector_window: HumanDuration,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: HumanDuration::from_secs(3600),
+            epsilon: 1.0,
+            collector_window: HumanDuration::from_secs(3600),
+        }
+    }
+}
+
+/// Laplace mechanism over counts (sensitivity 1)
+pub struct Laplace {
+    rng: StdRng,
+}
+
+impl Laplace {
+    pub fn from_entropy() -> Self {
+        Self { rng: StdRng::from_entropy() }
+    }
+
+    /// Same seed, same noise
+    pub fn seeded(seed: u64) -> Self {
+        Self { rng: StdRng::seed_from_u64(seed) }
+    }
+
+    /// One draw from Laplace(0, `scale`), by inverting the CDF
+    pub fn sample(&mut self, scale: f64) -> f64 {
+        let u: f64 = self.rng.gen_range(-0.5..0.5);
+        -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+    }
+
+    /// `count` plus Laplace(1 / `epsilon`) noise, rounded and clamped at zero
+    pub fn noisy_count(&mut self, count: u64, epsilon: f64) -> u64 {
+        (count as f64 + self.sample(1.0 / epsilon)).round().max(0.0) as u64
+    }
+}
+
+/// What a node measured over one reporting period
+#[derive(Debug, Clone, Default)]
+pub struct NodeStats {
+    pub peer_count: usize,
+    pub messages: u64,
+    pub period: std::time::Duration,
+    pub attacks: HashMap<AttackType, u64>,
+    pub uptime: std::time::Duration,
+}
+
+/// Anonymised statistics one node shares
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkStatsReport {
+    pub version: String,
+    /// Start of the hour the report was made in
+    pub hour: DateTime<Utc>,
+    /// Connected peers as a range, e.g. `6-20`
+    pub peers: String,
+    /// Rounded to the nearest hundred
+    pub messages_per_hour: u64,
+    /// Noised counts by attack type
+    pub attacks: BTreeMap<String, u64>,
+    /// `<1h`, `1-24h`, `1-7d` or `7d+`
+    pub uptime: String,
+    /// Set when made while this topic's mesh looked partitioned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<PartitionStamp>,
+}
+
+impl NetworkStatsReport {
+    /// Coarsen `stats` and add noise to the attack counts
+    pub fn from_stats(stats: &NodeStats, epsilon: f64, noise: &mut Laplace, now: DateTime<Utc>) -> Self {
+        let per_hour = match stats.period.as_secs_f64() {
+            secs if secs > 0.0 => stats.messages as f64 * 3600.0 / secs,
+            _ => 0.0,
+        };
+        // Every type is reported, so a zero is as noisy as any other count
+        let attacks = AttackType::ALL
+            .iter()
+            .map(|t| (format!("{:?}", t), noise.noisy_count(stats.attacks.get(t).copied().unwrap_or(0), epsilon)))
+            .collect();
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            hour: now.duration_trunc(ChronoDuration::hours(1)).unwrap_or(now),
+            peers: peer_bucket(stats.peer_count).to_string(),
+            messages_per_hour: (per_hour / MESSAGE_GRID as f64).round() as u64 * MESSAGE_GRID,
+            attacks,
+            uptime: uptime_bucket(stats.uptime).to_string(),
+            partition: None,
+        }
+    }
+
+    /// JSON for the wire, refused if anything identifying slipped in
+    pub fn to_json(&self) -> Result<Value> {
+        let value = serde_json::to_value(self)?;
+        check_denylist(&value)?;
+        Ok(value)
+    }
+}
+
+fn peer_bucket(peers: usize) -> &'static str {
+    PEER_BUCKETS.iter().find(|(max, _)| peers <= *max).map_or("100+", |(_, label)| label)
+}
+
+fn uptime_bucket(uptime: std::time::Duration) -> &'static str {
+    match uptime.as_secs() / 3600 {
+        0 => "<1h",
+        1..=23 => "1-24h",
+        24..=167 => "1-7d",
+        _ => "7d+",
+    }
+}
+
+/// Identifying content found in a report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DenylistViolation(pub String);
+
+impl fmt::Display for DenylistViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "telemetry report contains identifying data: {}", self.0)
+    }
+}
+
+impl std::error::Error for DenylistViolation {}
+
+/// Reject peer IDs, IP addresses, multiaddrs and ticker symbols anywhere in
+/// `value`, and attack keys that aren't known attack types
+pub fn check_denylist(value: &Value) -> Result<(), DenylistViolation> {
+    if let Some(attacks) = value.get("attacks").and_then(Value::as_object) {
+        for key in attacks.keys() {
+            if !AttackType::ALL.iter().any(|t| format!("{:?}", t) == *key) {
+                return Err(DenylistViolation(format!("unknown attack type '{}'", key)));
+            }
+        }
+    }
+    let mut pending = vec![value];
+    while let Some(value) = pending.pop() {
+        match value {
+            Value::String(s) => denylisted(s)?,
+            Value::Array(items) => pending.extend(items),
+            Value::Object(fields) => {
+                for (key, field) in fields {
+                    denylisted(key)?;
+                    pending.push(field);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn denylisted(s: &str) -> Result<(), DenylistViolation> {
+    let violation = |what: &str| Err(DenylistViolation(format!("{} '{}'", what, s)));
+    if s.parse::<std::net::IpAddr>().is_ok() || s.parse::<std::net::SocketAddr>().is_ok() {
+        return violation("IP address");
+    }
+    if s.contains("/ip4/") || s.contains("/ip6/") || s.contains("/dns") {
+        return violation("multiaddr");
+    }
+    if s.parse::<libp2p::PeerId>().is_ok() {
+        return violation("peer ID");
+    }
+    // Tickers: 1-5 capitals, optionally with a class suffix (BRK.B)
+    let (root, class) = s.split_once('.').unwrap_or((s, "A"));
+    let caps = |part: &str, max: usize| (1..=max).contains(&part.len()) && part.bytes().all(|b| b.is_ascii_uppercase());
+    if caps(root, 5) && caps(class, 2) {
+        return violation("symbol");
+    }
+    Ok(())
+}
+
+/// A report signed by the reporting node's identity key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedStatsReport {
+    pub report: Value,
+    /// Hex Ed25519 public key; collectors deduplicate by it
+    pub reporter: String,
+    /// Hex signature over the report's canonical CBOR
+    pub signature: String,
+}
+
+impl SignedStatsReport {
+    pub fn sign(report: &NetworkStatsReport, key: &dyn KeyProvider) -> Result<Self> {
+        let report = report.to_json()?;
+        let signature = key.sign(&wire::signing_bytes(SIGNING_CONTEXT, &report)?)?;
+        Ok(Self {
+            report,
+            reporter: hex::encode(key.public_key().to_bytes()),
+            signature: hex::encode(signature.to_bytes()),
+        })
+    }
+
+    /// The report, if the signature holds and it passes the denylist
+    pub fn verify(&self) -> Result<NetworkStatsReport> {
+        let key: [u8; 32] = hex::decode(&self.reporter)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .context("Malformed reporter key")?;
+        let signature: [u8; 64] = hex::decode(&self.signature)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .context("Malformed signature")?;
+        let key = VerifyingKey::from_bytes(&key).context("Invalid reporter key")?;
+        let canonical = wire::signing_bytes(SIGNING_CONTEXT, &self.report)?;
+        wire::verify_signature(&key, &Signature::from_bytes(&signature), &canonical, || Ok(serde_json::to_vec(&self.report)?))
+            .context("Report signature does not verify")?;
+        check_denylist(&self.report)?;
+        serde_json::from_value(self.report.clone()).context("Malformed stats report")
+    }
+}
+
+pub fn encode_report(report: &SignedStatsReport) -> Result<Vec<u8>> {
+    serde_json::to_vec(report).context("Failed to encode stats report")
+}
+
+pub fn decode_report(data: &[u8]) -> Result<SignedStatsReport> {
+    serde_json::from_slice(data).context("Malformed stats report")
+}
+
+/// Reporting side: counts gossip between reports
+pub struct Telemetry {
+    config: TelemetryConfig,
+    noise: Laplace,
+    started: DateTime<Utc>,
+    period_start: DateTime<Utc>,
+    messages: u64,
+}
+
+impl Telemetry {
+    pub fn new(config: TelemetryConfig, noise: Laplace, now: DateTime<Utc>) -> Self {
+        Self { config, noise, started: now, period_start: now, messages: 0 }
+    }
+
+    pub fn config(&self) -> &TelemetryConfig {
+        &self.config
+    }
+
+    /// Start of the current reporting period
+    pub fn period_start(&self) -> DateTime<Utc> {
+        self.period_start
+    }
+
+    pub fn count_message(&mut self) {
+        self.messages += 1;
+    }
+
+    /// Close the current period and report it
+    pub fn report(&mut self, peer_count: usize, attacks: HashMap<AttackType, u64>, now: DateTime<Utc>) -> NetworkStatsReport {
+        let stats = NodeStats {
+            peer_count,
+            messages: std::mem::take(&mut self.messages),
+            period: (now - self.period_start).to_std().unwrap_or_default(),
+            attacks,
+            uptime: (now - self.started).to_std().unwrap_or_default(),
+        };
+        self.period_start = now;
+        NetworkStatsReport::from_stats(&stats, self.config.epsilon, &mut self.noise, now)
+    }
+}
+
+/// Collector side: latest report per reporter within the window
+pub struct TelemetryCollector {
+    window: ChronoDuration,
+    reports: HashMap<String, (DateTime<Utc>, NetworkStatsReport)>,
+}
+
+impl TelemetryCollector {
+    pub fn new(window: ChronoDuration) -> Self {
+        Self { window, reports: HashMap::new() }
+    }
+
+    /// Verify and count `signed`; false when the reporter already has a
+    /// report in the window, unless that one was made in a partition this
+    /// one's side outranks
+    pub fn accept(&mut self, signed: &SignedStatsReport, now: DateTime<Utc>) -> Result<bool> {
+        let report = signed.verify()?;
+        self.expire(now);
+        if let Some((_, kept)) = self.reports.get(&signed.reporter) {
+            if partition::merge_order(report.partition.as_ref(), kept.partition.as_ref()).is_le() {
+                return Ok(false);
+            }
+        }
+        self.reports.insert(signed.reporter.clone(), (now, report));
+        Ok(true)
+    }
+
+    /// The counted report from `reporter` (hex public key)
+    pub fn report(&self, reporter: &str) -> Option<&NetworkStatsReport> {
+        self.reports.get(reporter).map(|(_, report)| report)
+    }
+
+    fn expire(&mut self, now: DateTime<Utc>) {
+        let window = self.window;
+        self.reports.retain(|_, (received, _)| now - *received < window);
+    }
+
+    /// Aggregate of the reports in the window
+    pub fn summary(&mut self, now: DateTime<Utc>) -> TelemetrySummary {
+        self.expire(now);
+        let mut summary = TelemetrySummary {
+            at: now,
+            window_secs: self.window.num_seconds().max(0) as u64,
+            reporters: self.reports.len(),
+            ..Default::default()
+        };
+        for (_, report) in self.reports.values() {
+            *summary.peers.entry(report.peers.clone()).or_default() += 1;
+            *summary.versions.entry(report.version.clone()).or_default() += 1;
+            *summary.uptime.entry(report.uptime.clone()).or_default() += 1;
+            summary.messages_per_hour += report.messages_per_hour;
+            for (attack, count) in &report.attacks {
+                *summary.attacks.entry(attack.clone()).or_default() += count;
+            }
+        }
+        summary
+    }
+}
+
+/// Network-wide view over the collector window
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TelemetrySummary {
+    pub at: DateTime<Utc>,
+    pub window_secs: u64,
+    pub reporters: usize,
+    /// Reporters per peer count bucket
+    pub peers: BTreeMap<String, usize>,
+    /// Sum over reporters
+    pub messages_per_hour: u64,
+    /// Sum of the noised counts over reporters
+    pub attacks: BTreeMap<String, u64>,
+    pub versions: BTreeMap<String, usize>,
+    pub uptime: BTreeMap<String, usize>,
+    /// Set when aggregated while this collector's mesh looked partitioned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<PartitionStamp>,
+}
+
+impl TelemetrySummary {
+    pub fn save(&self, path: &Path) -> Result<()> {
+        crate::data_dirs::atomic_write(path, &serde_json::to_vec_pretty(self)?).context("Failed to write telemetry summary")
+    }
+
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes).context("Corrupt telemetry summary")?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read telemetry summary"),
+        }
+    }
+}
+
+impl fmt::Display for TelemetrySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = |map: &BTreeMap<String, usize>| {
+            map.iter().map(|(k, v)| format!("{} ×{}", k, v)).collect::<Vec<_>>().join(", ")
+        };
+        writeln!(
+            f,
+            "📊 Network telemetry at {} ({} reporter(s), {}s window)",
+            self.at.format("%Y-%m-%d %H:%M UTC"),
+            self.reporters,
+            self.window_secs
+        )?;
+        writeln!(f, "  Peers:     {}", counts(&self.peers))?;
+        writeln!(f, "  Messages:  ~{}/h", self.messages_per_hour)?;
+        writeln!(f, "  Versions:  {}", counts(&self.versions))?;
+        writeln!(f, "  Uptime:    {}", counts(&self.uptime))?;
+        if let Some(stamp) = &self.partition {
+            writeln!(f, "  ⚠️ Collected during a partition (epoch {}, {} peers reachable)", stamp.epoch, stamp.peer_count)?;
+        }
+        writeln!(f, "  Attacks (noised):")?;
+        for (attack, count) in self.attacks.iter().filter(|(_, count)| **count > 0) {
+            writeln!(f, "    {:<20} {}", attack, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::key_provider::FileKeyProvider;
+    use ed25519_dalek::{Signer, SigningKey};
+    use std::time::Duration;
+
+    fn stats() -> NodeStats {
+        NodeStats {
+            peer_count: 12,
+            messages: 1234,
+            period: Duration::from_secs(1800),
+            attacks: [(AttackType::PortScan, 40), (AttackType::MessageSpam, 3)].into(),
+            uptime: Duration::from_secs(30 * 3600),
+        }
+    }
+
+    #[test]
+    fn test_laplace_is_deterministic_under_seed_and_matches_epsilon() {
+        let draws = |seed| {
+            let mut noise = Laplace::seeded(seed);
+            (0..5).map(|_| noise.sample(1.0)).collect::<Vec<_>>()
+        };
+        assert_eq!(draws(7), draws(7));
+        assert_ne!(draws(7), draws(8));
+
+        // Laplace(0, b): E|X| = b and Var X = 2b², with b = 1 / epsilon
+        for epsilon in [0.5, 1.0, 4.0] {
+            let scale = 1.0 / epsilon;
+            let mut noise = Laplace::seeded(42);
+            let samples: Vec<f64> = (0..50_000).map(|_| noise.sample(scale)).collect();
+            let n = samples.len() as f64;
+            let mean = samples.iter().sum::<f64>() / n;
+            let mean_abs = samples.iter().map(|x| x.abs()).sum::<f64>() / n;
+            let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+            assert!(mean.abs() < 0.05 * scale, "epsilon {}: mean {}", epsilon, mean);
+            assert!((mean_abs / scale - 1.0).abs() < 0.03, "epsilon {}: E|X| {}", epsilon, mean_abs);
+            assert!((variance / (2.0 * scale * scale) - 1.0).abs() < 0.06, "epsilon {}: var {}", epsilon, variance);
+        }
+        assert_eq!(Laplace::seeded(1).noisy_count(0, 1e9), 0);
+    }
+
+    #[test]
+    fn test_report_is_coarse_and_passes_denylist() {
+        let now = "2024-05-01T10:42:17Z".parse().unwrap();
+        let report = NetworkStatsReport::from_stats(&stats(), 1.0, &mut Laplace::seeded(3), now);
+        assert_eq!(report.peers, "6-20");
+        assert_eq!(report.messages_per_hour, 2500);
+        assert_eq!(report.uptime, "1-7d");
+        assert_eq!(report.hour.to_rfc3339(), "2024-05-01T10:00:00+00:00");
+        assert_eq!(report.attacks.len(), AttackType::ALL.len());
+        assert!(report.attacks["PortScan"].abs_diff(40) < 15);
+
+        let json = report.to_json().unwrap();
+        let fields: Vec<&String> = json.as_object().unwrap().keys().collect();
+        assert_eq!(fields, ["attacks", "hour", "messages_per_hour", "peers", "uptime", "version"]);
+    }
+
+    #[test]
+    fn test_denylist_rejects_identifiers_at_serialization() {
+        let now = Utc::now();
+        let clean = NetworkStatsReport::from_stats(&stats(), 1.0, &mut Laplace::seeded(3), now);
+        let peer_id = libp2p::PeerId::random().to_string();
+        for leaked in [peer_id.as_str(), "203.0.113.9", "[2001:db8::1]:4001", "/ip4/10.0.0.1/tcp/4001", "AAPL", "BRK.B"] {
+            let mut report = clean.clone();
+            report.version = leaked.to_string();
+            assert!(report.to_json().is_err(), "{} got through", leaked);
+            let mut report = clean.clone();
+            report.attacks.insert(leaked.to_string(), 1);
+            assert!(report.to_json().is_err(), "{} got through as an attack type", leaked);
+        }
+        let mut report = clean.clone();
+        report.attacks.insert("Exfiltration".to_string(), 1);
+        assert!(report.to_json().is_err());
+
+        // Checked again on receipt, so a patched reporter can't sneak one in
+        let key = SigningKey::from_bytes(&[9; 32]);
+        let mut signed = SignedStatsReport::sign(&clean, &FileKeyProvider::new(key.clone())).unwrap();
+        signed.report["peers"] = Value::String("198.51.100.4".into());
+        let digest = wire::signing_bytes(SIGNING_CONTEXT, &signed.report).unwrap();
+        signed.signature = hex::encode(key.sign(&digest).to_bytes());
+        assert!(signed.verify().unwrap_err().downcast_ref::<DenylistViolation>().is_some());
+        // Signed the pre-v2 way, over the bare JSON
+        signed.report = clean.to_json().unwrap();
+        signed.signature = hex::encode(key.sign(&serde_json::to_vec(&signed.report).unwrap()).to_bytes());
+        assert!(signed.verify().is_ok());
+    }
+
+    #[test]
+    fn test_collector_deduplicates_within_window() {
+        let start = Utc::now();
+        let mut collector = TelemetryCollector::new(ChronoDuration::hours(1));
+        let alice = FileKeyProvider::new(SigningKey::from_bytes(&[1; 32]));
+        let bob = FileKeyProvider::new(SigningKey::from_bytes(&[2; 32]));
+        let mut noise = Laplace::seeded(11);
+        let report = |noise: &mut Laplace| NetworkStatsReport::from_stats(&stats(), 1.0, noise, start);
+
+        assert!(collector.accept(&SignedStatsReport::sign(&report(&mut noise), &alice).unwrap(), start).unwrap());
+        assert!(collector.accept(&SignedStatsReport::sign(&report(&mut noise), &bob).unwrap(), start).unwrap());
+        let again = SignedStatsReport::sign(&report(&mut noise), &alice).unwrap();
+        assert!(!collector.accept(&again, start + ChronoDuration::minutes(30)).unwrap());
+
+        let summary = collector.summary(start + ChronoDuration::minutes(30));
+        assert_eq!(summary.reporters, 2);
+        assert_eq!(summary.peers["6-20"], 2);
+        assert_eq!(summary.messages_per_hour, 5000);
+
+        // Past the window alice reports afresh and bob's report has aged out
+        assert!(collector.accept(&again, start + ChronoDuration::minutes(61)).unwrap());
+        assert_eq!(collector.summary(start + ChronoDuration::minutes(61)).reporters, 1);
+
+        let mut forged = again.clone();
+        forged.reporter = hex::encode(bob.public_key().to_bytes());
+        assert!(collector.accept(&forged, start).is_err());
+    }
+
+    #[test]
+    fn test_collector_prefers_reports_from_the_larger_partition() {
+        let start = Utc::now();
+        let mut collector = TelemetryCollector::new(ChronoDuration::hours(1));
+        let alice = FileKeyProvider::new(SigningKey::from_bytes(&[1; 32]));
+        let reporter = hex::encode(alice.public_key().to_bytes());
+        let report = |peer_count: Option<usize>| {
+            let mut report = NetworkStatsReport::from_stats(&stats(), 1.0, &mut Laplace::seeded(5), start);
+            report.partition = peer_count.map(|peer_count| PartitionStamp { epoch: 1, peer_count, last_seen_external: Some(start) });
+            SignedStatsReport::sign(&report, &alice).unwrap()
+        };
+
+        assert!(collector.accept(&report(Some(1)), start).unwrap());
+        assert!(!collector.accept(&report(Some(1)), start).unwrap());
+        assert!(collector.accept(&report(Some(3)), start).unwrap());
+        assert_eq!(collector.report(&reporter).unwrap().partition.unwrap().peer_count, 3);
+        assert!(!collector.accept(&report(Some(2)), start).unwrap());
+        // Made after the heal: outranks either side
+        assert!(collector.accept(&report(None), start).unwrap());
+        assert_eq!(collector.report(&reporter).unwrap().partition, None);
+        assert!(!collector.accept(&report(Some(9)), start).unwrap());
+        assert_eq!(collector.summary(start).reporters, 1);
+    }
+}
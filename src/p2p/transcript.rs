@@ -0,0 +1,454 @@
+//! Chat Transcripts
+//! Per-peer hash chains over direct messages, extended on both sides as
+//! messages are sent and received. An exported range carries the chain and
+//! signatures over its head, so a third party can check it wasn't edited
+
+use anyhow::{Context, Result};
+use ed25519_dalek::{Signature, Verifier};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use super::groups::peer_verifying_key;
+use crate::crypto::key_provider::KeyProvider;
+
+/// Domain separator for transcript head signatures
+const TRANSCRIPT_SIGNING_CONTEXT: &[u8] = b"quantra-transcript-v1\0";
+
+/// `prev_hash` of the first message in a session
+const GENESIS: [u8; 32] = [0; 32];
+
+/// Message body: text when it is UTF-8, hex otherwise
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "BodyRepr", try_from = "BodyRepr")]
+pub struct Body(pub Vec<u8>);
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BodyRepr {
+    Text(String),
+    Hex(String),
+}
+
+impl From<Body> for BodyRepr {
+    fn from(body: Body) -> Self {
+        match String::from_utf8(body.0) {
+            Ok(text) => BodyRepr::Text(text),
+            Err(e) => BodyRepr::Hex(hex::encode(e.into_bytes())),
+        }
+    }
+}
+
+impl TryFrom<BodyRepr> for Body {
+    type Error = hex::FromHexError;
+
+    fn try_from(repr: BodyRepr) -> Result<Self, Self::Error> {
+        match repr {
+            BodyRepr::Text(text) => Ok(Body(text.into_bytes())),
+            BodyRepr::Hex(h) => hex::decode(h).map(Body),
+        }
+    }
+}
+
+/// One message in a session's chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    pub seq: u64,
+    /// Peer ID of the author
+    pub sender: String,
+    pub body: Body,
+    /// Hex SHA-256(plaintext || seq || sender || prev_hash)
+    pub hash: String,
+}
+
+fn chain_hash(plaintext: &[u8], seq: u64, sender: &str, prev: &[u8; 32]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(plaintext)
+        .chain_update(seq.to_be_bytes())
+        .chain_update(sender.as_bytes())
+        .chain_update(prev)
+        .finalize()
+        .into()
+}
+
+fn parse_hash(h: &str) -> Option<[u8; 32]> {
+    hex::decode(h).ok()?.try_into().ok()
+}
+
+/// Half-open range of message sequence numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl fmt::Display for TranscriptRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+impl FromStr for TranscriptRange {
+    type Err = anyhow::Error;
+
+    /// `start..end`; either side may be left out (`..` is everything)
+    fn from_str(s: &str) -> Result<Self> {
+        let (start, end) = s.split_once("..").context("Expected a range like 0..10")?;
+        let bound = |b: &str, default| if b.is_empty() { Ok(default) } else { b.parse().context("Invalid range bound") };
+        Ok(TranscriptRange { start: bound(start, 0)?, end: bound(end, u64::MAX)? })
+    }
+}
+
+/// Bytes both parties sign: who, which messages and the resulting head
+fn signing_bytes(parties: [&str; 2], range: TranscriptRange, prev_hash: &str, head_hash: &str) -> Vec<u8> {
+    let mut parties = parties;
+    parties.sort();
+    let body = serde_json::to_vec(&(parties, range, prev_hash, head_hash)).expect("plain tuple serializes");
+    [TRANSCRIPT_SIGNING_CONTEXT, body.as_slice()].concat()
+}
+
+/// The hash chain for one peer
+#[derive(Debug, Default)]
+pub struct Transcript {
+    entries: Vec<TranscriptEntry>,
+}
+
+impl Transcript {
+    pub fn append(&mut self, sender: &str, plaintext: &[u8]) -> &TranscriptEntry {
+        let seq = self.entries.len() as u64;
+        let hash = chain_hash(plaintext, seq, sender, &self.hash_before(seq));
+        self.entries.push(TranscriptEntry {
+            seq,
+            sender: sender.to_string(),
+            body: Body(plaintext.to_vec()),
+            hash: hex::encode(hash),
+        });
+        &self.entries[seq as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Chain hash preceding message `seq`
+    fn hash_before(&self, seq: u64) -> [u8; 32] {
+        match seq {
+            0 => GENESIS,
+            seq => parse_hash(&self.entries[seq as usize - 1].hash).expect("own hashes are hex"),
+        }
+    }
+
+    /// `range` with its end clamped to the messages recorded so far
+    fn clamp(&self, range: TranscriptRange) -> Result<TranscriptRange, TranscriptError> {
+        let end = range.end.min(self.entries.len() as u64);
+        if range.start >= end {
+            return Err(TranscriptError::EmptyRange(range));
+        }
+        Ok(TranscriptRange { start: range.start, end })
+    }
+}
+
+/// Transcripts for every peer this node has messaged
+#[derive(Debug, Default)]
+pub struct TranscriptStore {
+    sessions: HashMap<PeerId, Transcript>,
+}
+
+impl TranscriptStore {
+    /// Extend `peer`'s chain with a message authored by `sender`
+    pub fn record(&mut self, peer: PeerId, sender: &PeerId, plaintext: &[u8]) {
+        let entry = self.sessions.entry(peer).or_default().append(&sender.to_string(), plaintext);
+        tracing::debug!("🧾 Transcript with {} at seq {}", peer, entry.seq);
+    }
+
+    pub fn get(&self, peer: &PeerId) -> Option<&Transcript> {
+        self.sessions.get(peer)
+    }
+
+    /// Messages in `range` with our signature over the head; the
+    /// counterparty's signature is added with `TranscriptBundle::cosign`
+    pub fn export(&self, local: &PeerId, key: &dyn KeyProvider, peer: &PeerId, range: TranscriptRange) -> Result<TranscriptBundle> {
+        let transcript = self.sessions.get(peer).ok_or(TranscriptError::EmptyRange(range))?;
+        let range = transcript.clamp(range)?;
+        let messages = transcript.entries[range.start as usize..range.end as usize].to_vec();
+        let prev_hash = hex::encode(transcript.hash_before(range.start));
+        let head_hash = messages.last().expect("range is not empty").hash.clone();
+        let (exporter, counterparty) = (local.to_string(), peer.to_string());
+        let signature = key.sign(&signing_bytes([&exporter, &counterparty], range, &prev_hash, &head_hash))?;
+        Ok(TranscriptBundle {
+            exporter,
+            counterparty,
+            range,
+            prev_hash,
+            head_hash,
+            messages,
+            exporter_signature: hex::encode(signature.to_bytes()),
+            counterparty_signature: CoSignature::Declined { reason: "not requested".to_string() },
+        })
+    }
+
+    /// Answer `peer`'s `SignTranscript`: sign only if our own chain with
+    /// them reaches the same head over the same range
+    pub fn cosign(
+        &self,
+        local: &PeerId,
+        key: &dyn KeyProvider,
+        peer: &PeerId,
+        range: TranscriptRange,
+        head_hash: &str,
+    ) -> Result<String, TranscriptError> {
+        let transcript = self.sessions.get(peer).ok_or(TranscriptError::EmptyRange(range))?;
+        if transcript.clamp(range)? != range {
+            return Err(TranscriptError::EmptyRange(range));
+        }
+        let ours = &transcript.entries[range.end as usize - 1].hash;
+        if ours != head_hash {
+            return Err(TranscriptError::HeadMismatch);
+        }
+        let prev_hash = hex::encode(transcript.hash_before(range.start));
+        let bytes = signing_bytes([&peer.to_string(), &local.to_string()], range, &prev_hash, head_hash);
+        let signature = key.sign(&bytes).map_err(|e| TranscriptError::Signing(format!("{:#}", e)))?;
+        Ok(hex::encode(signature.to_bytes()))
+    }
+}
+
+/// The counterparty's answer to a co-signing request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoSignature {
+    /// Hex Ed25519 signature over the same head
+    Signed { signature: String },
+    /// Single-signed export: why there is no counterparty signature
+    Declined { reason: String },
+}
+
+/// A verifiable export of part of a chat session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptBundle {
+    pub exporter: String,
+    pub counterparty: String,
+    pub range: TranscriptRange,
+    /// Chain hash before the first exported message
+    pub prev_hash: String,
+    pub head_hash: String,
+    pub messages: Vec<TranscriptEntry>,
+    pub exporter_signature: String,
+    pub counterparty_signature: CoSignature,
+}
+
+/// Why a transcript can't be exported, co-signed or verified
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscriptError {
+    /// No recorded messages in the range
+    EmptyRange(TranscriptRange),
+    /// Sequence numbers don't run contiguously over the range
+    Gap { expected: u64 },
+    /// A message doesn't hash to its recorded chain hash
+    Tampered { seq: u64 },
+    /// The chain doesn't end at the signed head
+    HeadMismatch,
+    /// A message from neither party
+    UnknownSender { seq: u64 },
+    BadSignature { signer: String },
+    /// Our key provider couldn't sign
+    Signing(String),
+}
+
+impl fmt::Display for TranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyRange(range) => write!(f, "no messages in range {}", range),
+            Self::Gap { expected } => write!(f, "message {} is missing", expected),
+            Self::Tampered { seq } => write!(f, "message {} does not match its chain hash", seq),
+            Self::HeadMismatch => write!(f, "chain does not end at the signed head"),
+            Self::UnknownSender { seq } => write!(f, "message {} is from neither party", seq),
+            Self::BadSignature { signer } => write!(f, "bad transcript signature from {}", signer),
+            Self::Signing(reason) => write!(f, "could not sign: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for TranscriptError {}
+
+impl TranscriptBundle {
+    pub fn is_cosigned(&self) -> bool {
+        matches!(self.counterparty_signature, CoSignature::Signed { .. })
+    }
+
+    fn signing_bytes(&self) -> Vec<u8> {
+        signing_bytes([&self.exporter, &self.counterparty], self.range, &self.prev_hash, &self.head_hash)
+    }
+
+    /// Attach the counterparty's signature, or record why there is none.
+    /// A signature that doesn't verify counts as declined
+    pub fn cosign(&mut self, answer: Result<String, String>) {
+        self.counterparty_signature = match answer {
+            Ok(signature) if self.check_signature(&self.counterparty, &signature).is_ok() => {
+                CoSignature::Signed { signature }
+            }
+            Ok(_) => CoSignature::Declined { reason: "counterparty signature did not verify".to_string() },
+            Err(reason) => CoSignature::Declined { reason },
+        };
+    }
+
+    /// Signature by `signer` under the key its peer ID embeds
+    fn check_signature(&self, signer: &str, signature: &str) -> Result<()> {
+        let bad = || TranscriptError::BadSignature { signer: signer.to_string() };
+        let peer: PeerId = signer.parse().map_err(|_| bad())?;
+        let key = peer_verifying_key(&peer)?;
+        let signature: [u8; 64] = hex::decode(signature).ok().and_then(|b| b.try_into().ok()).ok_or_else(bad)?;
+        key.verify(&self.signing_bytes(), &Signature::from_bytes(&signature)).map_err(|_| bad())?;
+        Ok(())
+    }
+
+    /// Re-compute the chain from `prev_hash` and check every hash, the head
+    /// and each signature present
+    pub fn verify(&self) -> Result<()> {
+        let mut prev = parse_hash(&self.prev_hash).context("Malformed prev_hash")?;
+        if self.messages.is_empty() || self.range.end - self.range.start != self.messages.len() as u64 {
+            return Err(TranscriptError::EmptyRange(self.range).into());
+        }
+        for (message, expected) in self.messages.iter().zip(self.range.start..) {
+            if message.seq != expected {
+                return Err(TranscriptError::Gap { expected }.into());
+            }
+            if message.sender != self.exporter && message.sender != self.counterparty {
+                return Err(TranscriptError::UnknownSender { seq: message.seq }.into());
+            }
+            let hash = chain_hash(&message.body.0, message.seq, &message.sender, &prev);
+            if Some(hash) != parse_hash(&message.hash) {
+                return Err(TranscriptError::Tampered { seq: message.seq }.into());
+            }
+            prev = hash;
+        }
+        if Some(prev) != parse_hash(&self.head_hash) {
+            return Err(TranscriptError::HeadMismatch.into());
+        }
+        self.check_signature(&self.exporter, &self.exporter_signature)?;
+        if let CoSignature::Signed { signature } = &self.counterparty_signature {
+            self.check_signature(&self.counterparty, signature)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TranscriptBundle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "🧾 Transcript {} ↔ {} (messages {})", self.exporter, self.counterparty, self.range)?;
+        writeln!(f, "   Head: {}", self.head_hash)?;
+        match &self.counterparty_signature {
+            CoSignature::Signed { .. } => writeln!(f, "   ✅ Signed by both parties")?,
+            CoSignature::Declined { reason } => {
+                writeln!(f, "   ⚠️  SINGLE-SIGNED: only the exporter signed (counterparty: {})", reason)?
+            }
+        }
+        for message in &self.messages {
+            let who = if message.sender == self.exporter { "exporter" } else { "counterparty" };
+            writeln!(f, "   #{:<4} {:<12} {}", message.seq, who, String::from_utf8_lossy(&message.body.0))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::key_provider::FileKeyProvider;
+    use ed25519_dalek::SigningKey;
+    use libp2p::identity::Keypair;
+
+    struct Party {
+        peer: PeerId,
+        key: FileKeyProvider,
+        store: TranscriptStore,
+    }
+
+    fn party() -> Party {
+        let keypair = Keypair::generate_ed25519();
+        let secret = keypair.clone().try_into_ed25519().unwrap().secret();
+        Party {
+            peer: PeerId::from(keypair.public()),
+            key: FileKeyProvider::new(SigningKey::from_bytes(secret.as_ref().try_into().unwrap())),
+            store: TranscriptStore::default(),
+        }
+    }
+
+    /// Alice and Bob exchange four messages, recorded on both sides
+    fn conversation() -> (Party, Party) {
+        let (mut alice, mut bob) = (party(), party());
+        for (from_alice, text) in [(true, "bid 101.5"), (false, "offer 102"), (true, "deal at 101.75"), (false, "confirmed")] {
+            let sender = if from_alice { alice.peer } else { bob.peer };
+            alice.store.record(bob.peer, &sender, text.as_bytes());
+            bob.store.record(alice.peer, &sender, text.as_bytes());
+        }
+        (alice, bob)
+    }
+
+    fn cosigned(alice: &Party, bob: &Party, range: TranscriptRange) -> TranscriptBundle {
+        let mut bundle = alice.store.export(&alice.peer, &alice.key, &bob.peer, range).unwrap();
+        let answer = bob.store.cosign(&bob.peer, &bob.key, &alice.peer, bundle.range, &bundle.head_hash);
+        bundle.cosign(answer.map_err(|e| e.to_string()));
+        bundle
+    }
+
+    #[test]
+    fn test_export_round_trip_verifies() {
+        let (alice, bob) = conversation();
+        let bundle = cosigned(&alice, &bob, "1..".parse().unwrap());
+        assert!(bundle.is_cosigned());
+        assert_eq!(bundle.range, TranscriptRange { start: 1, end: 4 });
+        assert_eq!(bundle.messages.len(), 3);
+
+        let json = serde_json::to_string_pretty(&bundle).unwrap();
+        assert!(json.contains("\"text\": \"deal at 101.75\""));
+        let parsed: TranscriptBundle = serde_json::from_str(&json).unwrap();
+        parsed.verify().unwrap();
+    }
+
+    #[test]
+    fn test_modified_message_is_detected() {
+        let (alice, bob) = conversation();
+        let mut bundle = cosigned(&alice, &bob, "..".parse().unwrap());
+        bundle.messages[2].body = Body(b"deal at 99".to_vec());
+        let err = bundle.verify().unwrap_err();
+        assert_eq!(err.downcast_ref::<TranscriptError>(), Some(&TranscriptError::Tampered { seq: 2 }));
+
+        // Dropping the last message leaves the chain short of the signed head
+        let mut bundle = cosigned(&alice, &bob, "..".parse().unwrap());
+        bundle.messages.truncate(3);
+        bundle.range.end = 3;
+        assert!(bundle.verify().is_err());
+    }
+
+    #[test]
+    fn test_declined_cosignature_is_marked() {
+        // The two messages crossed, so each side recorded them in a
+        // different order and Bob won't sign Alice's head
+        let (mut alice, mut bob) = (party(), party());
+        alice.store.record(bob.peer, &alice.peer, b"sell 10");
+        alice.store.record(bob.peer, &bob.peer, b"buy 10");
+        bob.store.record(alice.peer, &bob.peer, b"buy 10");
+        bob.store.record(alice.peer, &alice.peer, b"sell 10");
+        let mut bundle = alice.store.export(&alice.peer, &alice.key, &bob.peer, "..".parse().unwrap()).unwrap();
+        let answer = bob.store.cosign(&bob.peer, &bob.key, &alice.peer, bundle.range, &bundle.head_hash);
+        assert_eq!(answer, Err(TranscriptError::HeadMismatch));
+        bundle.cosign(answer.map_err(|e| e.to_string()));
+
+        assert!(!bundle.is_cosigned());
+        bundle.verify().unwrap();
+        assert!(bundle.to_string().contains("SINGLE-SIGNED"));
+        let json = serde_json::to_value(&bundle).unwrap();
+        assert_eq!(json["counterparty_signature"]["declined"]["reason"], "chain does not end at the signed head");
+
+        // A forged counterparty signature is not accepted as one
+        bundle.cosign(Ok(bundle.exporter_signature.clone()));
+        assert!(!bundle.is_cosigned());
+    }
+}
@@ -0,0 +1,567 @@
+//! Key Transparency
+//! An append-only log of the public keys each user ID was seen with, and
+//! where. Its Merkle root is gossiped on `quantra/key-transparency` among a
+//! trust circle; a node whose root differs from a peer's pulls the entries
+//! it lacks, bucket by bucket. A user ID seen with two keys is a conflict
+//! for an operator to settle by pinning one; the pin then overrides
+//! whatever is observed later
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use crate::approvals::{ApprovalQueue, Decision};
+use crate::migrations::{self, StoreSchema};
+use crate::security::notifications::SinkEvent;
+use crate::storage::{KvStore, RuntimeMode};
+use crate::units::HumanDuration;
+
+pub const SCHEMA: StoreSchema = StoreSchema {
+    name: "key_transparency",
+    tree: Some("log"),
+    version: 1,
+    migrations: &[],
+    index: None,
+};
+
+pub const KEY_TRANSPARENCY_TOPIC: &str = "quantra/key-transparency";
+
+/// Approval category of pins proposed for conflicting keys
+pub const APPROVAL_CATEGORY: &str = "keys.conflict";
+
+/// Leaf-hash buckets compared when reconciling
+pub const BUCKETS: usize = 256;
+
+/// Entries returned per `KeyLogSync` response; the rest follow next round
+pub const MAX_ENTRIES_PER_RESPONSE: usize = 1024;
+
+/// Longest user ID or fingerprint accepted into the log
+const MAX_FIELD_LEN: usize = 256;
+
+const ASSERTION_PREFIX: &[u8] = b"a/";
+const PIN_PREFIX: &[u8] = b"p/";
+
+/// `[p2p.transparency]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransparencyConfig {
+    /// Peer IDs whose logs are compared with ours. Empty turns the log off
+    pub trust_circle: Vec<String>,
+    /// How often our root is announced
+    pub interval: HumanDuration,
+}
+
+impl Default for TransparencyConfig {
+    fn default() -> Self {
+        Self { trust_circle: Vec::new(), interval: HumanDuration::from_secs(5 * 60) }
+    }
+}
+
+impl TransparencyConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.trust_circle.is_empty()
+    }
+
+    pub fn trusts(&self, peer: &str) -> bool {
+        self.trust_circle.iter().any(|p| p == peer)
+    }
+}
+
+/// Where a key was first seen
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    /// Generated into or imported to a local keystore
+    Keystore,
+    /// A peer's key, authenticated by the connection handshake
+    Handshake,
+    /// Pulled from a trust-circle peer's log
+    Reconciled { peer: String },
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Keystore => f.write_str("keystore"),
+            Self::Handshake => f.write_str("handshake"),
+            Self::Reconciled { peer } => write!(f, "log of {}", peer),
+        }
+    }
+}
+
+/// One log entry. Only the user ID and fingerprint are hashed into the
+/// root; when and where each node saw the key is its own business
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyAssertion {
+    pub user_id: String,
+    pub fingerprint: String,
+    pub first_seen: DateTime<Utc>,
+    pub source: KeySource,
+}
+
+impl KeyAssertion {
+    fn leaf(&self) -> [u8; 32] {
+        leaf(&self.user_id, &self.fingerprint)
+    }
+}
+
+/// An operator's choice between conflicting keys
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyPin {
+    pub user_id: String,
+    pub pinned: String,
+    /// Every other key logged for the user when the pin was made; keys
+    /// seen later are distrusted too
+    pub distrusted: Vec<String>,
+    pub resolved_at: DateTime<Utc>,
+}
+
+/// A user ID logged with more than one key and no pin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyConflict {
+    pub user_id: String,
+    /// Every key logged for the user, sorted
+    pub fingerprints: Vec<String>,
+    /// Where the newest one came from
+    pub source: KeySource,
+}
+
+impl KeyConflict {
+    pub fn to_sink_event(&self) -> SinkEvent {
+        SinkEvent::KeyConflict {
+            user_id: self.user_id.clone(),
+            fingerprints: self.fingerprints.clone(),
+            source: self.source.to_string(),
+        }
+    }
+}
+
+/// What an observation did to the log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Observed {
+    /// Already logged
+    Known,
+    Added,
+    /// Logged, and the user now has keys that disagree
+    Conflict(KeyConflict),
+    /// Logged, but the user's pin names another key
+    Overridden,
+}
+
+/// Which key to use for a user ID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyStatus {
+    Unknown,
+    /// The only key logged
+    Trusted(String),
+    /// Chosen by an operator
+    Pinned(String),
+    Conflicted(Vec<String>),
+}
+
+/// Gossiped every `interval`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootAnnouncement {
+    /// Hex Merkle root
+    pub root: String,
+    pub entries: u64,
+    /// Keeps repeated announcements of one root from being deduplicated
+    pub announced_at: DateTime<Utc>,
+}
+
+pub fn encode_announcement(announcement: &RootAnnouncement) -> Result<Vec<u8>> {
+    serde_json::to_vec(announcement).context("Failed to encode key log root")
+}
+
+pub fn decode_announcement(data: &[u8]) -> Result<RootAnnouncement> {
+    serde_json::from_slice(data).context("Malformed key log root")
+}
+
+/// Proposed pins waiting on an operator, and the approved ones not yet applied
+struct Approval {
+    queue: Arc<ApprovalQueue>,
+    ttl: chrono::Duration,
+    approved_rx: mpsc::UnboundedReceiver<(String, String)>,
+}
+
+/// The log, keyed `a/ user_id 0x00 fingerprint`, and pins keyed
+/// `p/ user_id`, with CBOR values. Leaf hashes are indexed in memory
+pub struct KeyTransparency {
+    store: Box<dyn KvStore>,
+    config: TransparencyConfig,
+    /// User ID -> fingerprint -> leaf hash
+    index: BTreeMap<String, BTreeMap<String, [u8; 32]>>,
+    pins: HashMap<String, KeyPin>,
+    approval: Option<Approval>,
+}
+
+fn assertion_key(user_id: &str, fingerprint: &str) -> Vec<u8> {
+    [ASSERTION_PREFIX, user_id.as_bytes(), &[0], fingerprint.as_bytes()].concat()
+}
+
+fn pin_key(user_id: &str) -> Vec<u8> {
+    [PIN_PREFIX, user_id.as_bytes()].concat()
+}
+
+fn leaf(user_id: &str, fingerprint: &str) -> [u8; 32] {
+    Sha256::new()
+        .chain_update([0])
+        .chain_update(user_id.as_bytes())
+        .chain_update([0])
+        .chain_update(fingerprint.as_bytes())
+        .finalize()
+        .into()
+}
+
+/// Pairs hashed level by level; an odd node out is carried up as is
+fn merkle_root(mut level: Vec<[u8; 32]>) -> [u8; 32] {
+    if level.is_empty() {
+        return Sha256::digest(b"").into();
+    }
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => Sha256::new().chain_update([1]).chain_update(left).chain_update(right).finalize().into(),
+                [single] => *single,
+                _ => unreachable!("chunks of two"),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+fn check_field(field: &str, value: &str) -> Result<()> {
+    if value.is_empty() || value.len() > MAX_FIELD_LEN || value.contains('\0') {
+        anyhow::bail!("Invalid {} in key assertion", field);
+    }
+    Ok(())
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    cbor4ii::serde::to_vec(Vec::new(), value).map_err(|e| anyhow::anyhow!("Failed to encode key log entry: {}", e))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    cbor4ii::serde::from_slice(bytes).map_err(|e| anyhow::anyhow!("Corrupt key log entry: {}", e))
+}
+
+impl KeyTransparency {
+    /// Open the log in `dir`, or keep it in memory when ephemeral
+    pub fn open(dir: &Path, mode: RuntimeMode, config: TransparencyConfig) -> Result<Self> {
+        let store = migrations::open_store(mode, dir, &SCHEMA)
+            .with_context(|| format!("Failed to open key transparency log at {}", dir.display()))?;
+        let mut log = Self { store, config, index: BTreeMap::new(), pins: HashMap::new(), approval: None };
+        for (_, value) in log.store.scan_prefix(ASSERTION_PREFIX)? {
+            let assertion: KeyAssertion = decode(&value)?;
+            log.index.entry(assertion.user_id.clone()).or_default().insert(assertion.fingerprint.clone(), assertion.leaf());
+        }
+        for (_, value) in log.store.scan_prefix(PIN_PREFIX)? {
+            let pin: KeyPin = decode(&value)?;
+            log.pins.insert(pin.user_id.clone(), pin);
+        }
+        Ok(log)
+    }
+
+    pub fn config(&self) -> &TransparencyConfig {
+        &self.config
+    }
+
+    /// Propose pins for conflicts in `queue`, waiting up to `ttl`;
+    /// `apply_approved` applies the approved ones
+    pub fn require_approval(&mut self, queue: Arc<ApprovalQueue>, ttl: chrono::Duration) {
+        let (approved_tx, approved_rx) = mpsc::unbounded_channel();
+        queue.register(APPROVAL_CATEGORY, move |request, decision| {
+            if decision == Decision::Approve {
+                let user_id = request.context["user_id"].as_str().context("Malformed key pin request")?;
+                let fingerprint = request.context["fingerprint"].as_str().context("Malformed key pin request")?;
+                // Only fails once the node has stopped; the decision is kept for next time
+                approved_tx
+                    .send((user_id.to_string(), fingerprint.to_string()))
+                    .map_err(|_| anyhow::anyhow!("Key transparency has stopped"))?;
+            }
+            Ok(())
+        });
+        self.approval = Some(Approval { queue, ttl, approved_rx });
+    }
+
+    /// Log that `user_id` was seen with `fingerprint`
+    pub fn observe(&mut self, user_id: &str, fingerprint: &str, source: KeySource, now: DateTime<Utc>) -> Result<Observed> {
+        check_field("user ID", user_id)?;
+        check_field("fingerprint", fingerprint)?;
+        if self.index.get(user_id).is_some_and(|keys| keys.contains_key(fingerprint)) {
+            return Ok(Observed::Known);
+        }
+        let assertion = KeyAssertion { user_id: user_id.to_string(), fingerprint: fingerprint.to_string(), first_seen: now, source };
+        self.store.insert(&assertion_key(user_id, fingerprint), &encode(&assertion)?)?;
+        let keys = self.index.entry(user_id.to_string()).or_default();
+        keys.insert(fingerprint.to_string(), assertion.leaf());
+
+        if let Some(pin) = self.pins.get(user_id) {
+            return Ok(match pin.pinned == fingerprint {
+                true => Observed::Added,
+                false => Observed::Overridden,
+            });
+        }
+        if keys.len() == 1 {
+            return Ok(Observed::Added);
+        }
+        let conflict = KeyConflict {
+            user_id: user_id.to_string(),
+            fingerprints: keys.keys().cloned().collect(),
+            source: assertion.source,
+        };
+        self.hold(&conflict, now);
+        Ok(Observed::Conflict(conflict))
+    }
+
+    /// Log entries pulled from `peer`; returns the conflicts they caused
+    pub fn merge(&mut self, entries: Vec<KeyAssertion>, peer: &str, now: DateTime<Utc>) -> Result<Vec<KeyConflict>> {
+        let mut conflicts = Vec::new();
+        for entry in entries {
+            if let Err(e) = check_field("user ID", &entry.user_id).and(check_field("fingerprint", &entry.fingerprint)) {
+                tracing::warn!("🔑 Skipping entry from {}: {}", peer, e);
+                continue;
+            }
+            let source = KeySource::Reconciled { peer: peer.to_string() };
+            if let Observed::Conflict(conflict) = self.observe(&entry.user_id, &entry.fingerprint, source, now)? {
+                conflicts.push(conflict);
+            }
+        }
+        Ok(conflicts)
+    }
+
+    /// Propose pinning each of the conflict's keys, once per key
+    fn hold(&self, conflict: &KeyConflict, now: DateTime<Utc>) {
+        let Some(approval) = self.approval.as_ref() else { return };
+        let held = match approval.queue.pending(APPROVAL_CATEGORY) {
+            Ok(held) => held,
+            Err(e) => {
+                tracing::warn!("🔑 Could not read proposed key pins: {:#}", e);
+                return;
+            }
+        };
+        for fingerprint in &conflict.fingerprints {
+            let proposed = held
+                .iter()
+                .any(|r| r.context["user_id"] == conflict.user_id.as_str() && r.context["fingerprint"] == fingerprint.as_str());
+            if proposed {
+                continue;
+            }
+            let description = format!(
+                "Pin key {} for {}, distrusting {} other(s)",
+                fingerprint,
+                conflict.user_id,
+                conflict.fingerprints.len() - 1
+            );
+            let context = serde_json::json!({
+                "user_id": conflict.user_id,
+                "fingerprint": fingerprint,
+                "others": conflict.fingerprints.iter().filter(|f| *f != fingerprint).collect::<Vec<_>>(),
+            });
+            if let Err(e) = approval.queue.enqueue(APPROVAL_CATEGORY, &description, context, approval.ttl, now) {
+                tracing::warn!("🔑 Could not propose pinning {} for {}: {:#}", fingerprint, conflict.user_id, e);
+            }
+        }
+    }
+
+    /// Trust `fingerprint` for `user_id` from now on, and no other key
+    pub fn pin(&mut self, user_id: &str, fingerprint: &str, now: DateTime<Utc>) -> Result<KeyPin> {
+        let Some(keys) = self.index.get(user_id).filter(|keys| keys.contains_key(fingerprint)) else {
+            anyhow::bail!("{} was never seen with key {}", user_id, fingerprint);
+        };
+        let pin = KeyPin {
+            user_id: user_id.to_string(),
+            pinned: fingerprint.to_string(),
+            distrusted: keys.keys().filter(|f| *f != fingerprint).cloned().collect(),
+            resolved_at: now,
+        };
+        self.store.insert(&pin_key(user_id), &encode(&pin)?)?;
+        self.store.flush()?;
+        self.pins.insert(user_id.to_string(), pin.clone());
+        Ok(pin)
+    }
+
+    /// Apply pins approved since the last call
+    pub fn apply_approved(&mut self) -> Vec<KeyPin> {
+        let Some(approval) = self.approval.as_mut() else { return Vec::new() };
+        let mut approved = Vec::new();
+        while let Ok(choice) = approval.approved_rx.try_recv() {
+            approved.push(choice);
+        }
+        let mut pins = Vec::new();
+        for (user_id, fingerprint) in approved {
+            match self.pin(&user_id, &fingerprint, Utc::now()) {
+                Ok(pin) => pins.push(pin),
+                Err(e) => tracing::warn!("🔑 Could not pin {} for {}: {:#}", fingerprint, user_id, e),
+            }
+        }
+        pins
+    }
+
+    pub fn status(&self, user_id: &str) -> KeyStatus {
+        if let Some(pin) = self.pins.get(user_id) {
+            return KeyStatus::Pinned(pin.pinned.clone());
+        }
+        match self.index.get(user_id) {
+            None => KeyStatus::Unknown,
+            Some(keys) if keys.len() == 1 => KeyStatus::Trusted(keys.keys().next().cloned().unwrap_or_default()),
+            Some(keys) => KeyStatus::Conflicted(keys.keys().cloned().collect()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.values().map(BTreeMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Merkle root over the leaves in (user ID, fingerprint) order
+    pub fn root(&self) -> [u8; 32] {
+        merkle_root(self.index.values().flat_map(|keys| keys.values().copied()).collect())
+    }
+
+    pub fn announcement(&self, now: DateTime<Utc>) -> RootAnnouncement {
+        RootAnnouncement { root: hex::encode(self.root()), entries: self.len() as u64, announced_at: now }
+    }
+
+    /// Digest of each bucket's sorted leaves, bucketed by first byte, in hex
+    pub fn buckets(&self) -> Vec<String> {
+        let mut buckets = vec![Vec::new(); BUCKETS];
+        for leaf in self.index.values().flat_map(|keys| keys.values()) {
+            buckets[leaf[0] as usize].push(*leaf);
+        }
+        buckets
+            .into_iter()
+            .map(|mut leaves| {
+                leaves.sort_unstable();
+                let mut hasher = Sha256::new();
+                leaves.iter().for_each(|leaf| hasher.update(leaf));
+                hex::encode(hasher.finalize())
+            })
+            .collect()
+    }
+
+    /// Answer to `KeyLogSync`: our entries in every bucket whose digest
+    /// differs from the peer's, up to `MAX_ENTRIES_PER_RESPONSE`
+    pub fn entries_differing(&self, theirs: &[String]) -> Result<Vec<KeyAssertion>> {
+        if theirs.len() != BUCKETS {
+            anyhow::bail!("Expected {} bucket digests, got {}", BUCKETS, theirs.len());
+        }
+        let differing: Vec<bool> = self.buckets().iter().zip(theirs).map(|(ours, theirs)| ours != theirs).collect();
+        let mut entries = Vec::new();
+        for (user_id, keys) in &self.index {
+            for (fingerprint, leaf) in keys {
+                if !differing[leaf[0] as usize] {
+                    continue;
+                }
+                if entries.len() == MAX_ENTRIES_PER_RESPONSE {
+                    return Ok(entries);
+                }
+                let value = self.store.get(&assertion_key(user_id, fingerprint))?.context("Key log index out of sync")?;
+                entries.push(decode(&value)?);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approvals::LOCAL_CLI;
+
+    fn log() -> KeyTransparency {
+        let config = TransparencyConfig { trust_circle: vec!["peer".to_string()], ..Default::default() };
+        KeyTransparency::open(Path::new("unused"), RuntimeMode::Ephemeral, config).unwrap()
+    }
+
+    #[test]
+    fn test_root_ignores_insertion_order_and_metadata() {
+        let now = Utc::now();
+        let (mut a, mut b) = (log(), log());
+        assert_eq!(a.root(), b.root());
+        for (user, key) in [("alice", "a1"), ("bob", "b1"), ("carol", "c1")] {
+            a.observe(user, key, KeySource::Keystore, now).unwrap();
+        }
+        for (user, key) in [("carol", "c1"), ("alice", "a1"), ("bob", "b1")] {
+            b.observe(user, key, KeySource::Handshake, now + chrono::Duration::hours(1)).unwrap();
+        }
+        assert_eq!(a.root(), b.root());
+        assert_eq!(a.observe("bob", "b1", KeySource::Keystore, now).unwrap(), Observed::Known);
+
+        b.observe("dave", "d1", KeySource::Keystore, now).unwrap();
+        assert_ne!(a.root(), b.root());
+        let missing = b.entries_differing(&a.buckets()).unwrap();
+        assert!(missing.iter().any(|e| e.user_id == "dave"));
+        assert!(a.merge(missing, "b", now).unwrap().is_empty());
+        assert_eq!(a.root(), b.root());
+        assert!(b.entries_differing(&a.buckets()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_fields_are_refused() {
+        let mut log = log();
+        assert!(log.observe("", "f", KeySource::Keystore, Utc::now()).is_err());
+        assert!(log.observe("a\0b", "f", KeySource::Keystore, Utc::now()).is_err());
+        let junk = KeyAssertion { user_id: String::new(), fingerprint: "f".to_string(), first_seen: Utc::now(), source: KeySource::Keystore };
+        assert!(log.merge(vec![junk], "peer", Utc::now()).unwrap().is_empty());
+        assert!(log.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_conflict_is_held_and_pin_overrides_later_keys() {
+        let now = Utc::now();
+        let queue = Arc::new(ApprovalQueue::open(Path::new("unused"), RuntimeMode::Ephemeral).unwrap());
+        let mut log = log();
+        log.require_approval(queue.clone(), chrono::Duration::hours(1));
+
+        assert_eq!(log.observe("alice", "good", KeySource::Keystore, now).unwrap(), Observed::Added);
+        assert_eq!(log.status("alice"), KeyStatus::Trusted("good".to_string()));
+        let Observed::Conflict(conflict) = log.observe("alice", "evil", KeySource::Handshake, now).unwrap() else {
+            panic!("expected a conflict");
+        };
+        assert_eq!(conflict.fingerprints, vec!["evil".to_string(), "good".to_string()]);
+        assert_eq!(log.status("alice"), KeyStatus::Conflicted(conflict.fingerprints.clone()));
+
+        let held = queue.pending(APPROVAL_CATEGORY).unwrap();
+        assert_eq!(held.len(), 2);
+        let good = held.iter().find(|r| r.context["fingerprint"] == "good").unwrap().id.clone();
+        queue.resolve(&good, Decision::Approve, LOCAL_CLI, now).await.unwrap();
+        let pins = log.apply_approved();
+        assert_eq!(pins.len(), 1);
+        assert_eq!(pins[0].distrusted, vec!["evil".to_string()]);
+        assert_eq!(log.status("alice"), KeyStatus::Pinned("good".to_string()));
+
+        // Later keys are logged but neither trusted nor alerted on
+        assert_eq!(log.observe("alice", "worse", KeySource::Handshake, now).unwrap(), Observed::Overridden);
+        assert_eq!(log.status("alice"), KeyStatus::Pinned("good".to_string()));
+        assert_eq!(queue.pending(APPROVAL_CATEGORY).unwrap().len(), 1);
+        assert!(log.pin("alice", "never-seen", now).is_err());
+    }
+
+    #[test]
+    fn test_pins_survive_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = Utc::now();
+        let root = {
+            let mut log = KeyTransparency::open(dir.path(), RuntimeMode::Persistent, TransparencyConfig::default()).unwrap();
+            log.observe("alice", "one", KeySource::Keystore, now).unwrap();
+            log.observe("alice", "two", KeySource::Keystore, now).unwrap();
+            log.pin("alice", "two", now).unwrap();
+            log.root()
+        };
+        let log = KeyTransparency::open(dir.path(), RuntimeMode::Persistent, TransparencyConfig::default()).unwrap();
+        assert_eq!(log.root(), root);
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.status("alice"), KeyStatus::Pinned("two".to_string()));
+    }
+}
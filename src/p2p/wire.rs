@@ -0,0 +1,313 @@
+//! Wire Formats
+//! Request/response bodies are CBOR or JSON, negotiated through the protocol
+//! ID suffix (`/quantra/1.1.0/cbor`, `/quantra/1.1.0/json`). Everything that
+//! gets signed is encoded as canonical CBOR: map keys sorted by their
+//! encoded bytes, definite lengths and the shortest integer heads, so equal
+//! values give equal bytes whatever the field or insertion order. Floats
+//! keep their 8-byte form; signed payloads avoid them
+
+use anyhow::{Context, Result};
+use cbor4ii::core::dec::Decode;
+use cbor4ii::core::utils::SliceReader;
+use cbor4ii::core::Value;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Encoding of request/response bodies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum WireFormat {
+    #[default]
+    #[serde(rename = "cbor")]
+    CborCanonical,
+    #[serde(rename = "json")]
+    Json,
+}
+
+impl WireFormat {
+    pub const ALL: [WireFormat; 2] = [WireFormat::CborCanonical, WireFormat::Json];
+
+    /// Protocol ID suffix that selects this format
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Self::CborCanonical => "cbor",
+            Self::Json => "json",
+        }
+    }
+
+    /// The format a negotiated protocol ID asks for; CBOR unless it ends
+    /// in `/json`
+    pub fn of_protocol(protocol: &str) -> Self {
+        match protocol.rsplit_once('/') {
+            Some((_, "json")) => Self::Json,
+            _ => Self::CborCanonical,
+        }
+    }
+
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>> {
+        match self {
+            Self::CborCanonical => to_canonical(value),
+            Self::Json => serde_json::to_vec(value).context("Failed to encode JSON"),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T> {
+        match self {
+            Self::CborCanonical => cbor4ii::serde::from_slice(bytes).map_err(|e| anyhow::anyhow!("Malformed CBOR: {}", e)),
+            Self::Json => serde_json::from_slice(bytes).context("Malformed JSON"),
+        }
+    }
+}
+
+impl fmt::Display for WireFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.suffix())
+    }
+}
+
+/// `[p2p.wire]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WireConfig {
+    /// Offered first when opening a request stream; both are accepted
+    pub format: WireFormat,
+    /// Also accept signatures over the pre-canonical (JSON) encoding; they
+    /// are verified but never produced
+    pub legacy_signatures: bool,
+}
+
+impl Default for WireConfig {
+    fn default() -> Self {
+        Self { format: WireFormat::CborCanonical, legacy_signatures: true }
+    }
+}
+
+static CONFIG: Lazy<RwLock<WireConfig>> = Lazy::new(|| RwLock::new(WireConfig::default()));
+
+/// Make `config` the process-wide wire config; nodes built afterwards
+/// prefer its format
+pub fn configure(config: &WireConfig) {
+    *CONFIG.write() = config.clone();
+}
+
+pub fn preferred_format() -> WireFormat {
+    CONFIG.read().format
+}
+
+pub fn legacy_signatures() -> bool {
+    CONFIG.read().legacy_signatures
+}
+
+/// Canonical CBOR of `value`
+pub fn to_canonical<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let loose = cbor4ii::serde::to_vec(Vec::new(), value).map_err(|e| anyhow::anyhow!("Failed to encode CBOR: {}", e))?;
+    let value = Value::decode(&mut SliceReader::new(&loose)).map_err(|e| anyhow::anyhow!("Failed to re-read CBOR: {}", e))?;
+    let mut out = Vec::with_capacity(loose.len());
+    write_canonical(&value, &mut out)?;
+    Ok(out)
+}
+
+/// `context`, then the canonical CBOR of `body`
+pub fn signing_bytes<T: Serialize>(context: &[u8], body: &T) -> Result<Vec<u8>> {
+    Ok([context, &to_canonical(body)?].concat())
+}
+
+/// Check `signature` over the canonical bytes, else (while legacy
+/// signatures are accepted) over the bytes `legacy` builds
+pub fn verify_signature(
+    key: &VerifyingKey,
+    signature: &Signature,
+    canonical: &[u8],
+    legacy: impl FnOnce() -> Result<Vec<u8>>,
+) -> Result<()> {
+    verify_with(key, signature, canonical, legacy, legacy_signatures())
+}
+
+fn verify_with(
+    key: &VerifyingKey,
+    signature: &Signature,
+    canonical: &[u8],
+    legacy: impl FnOnce() -> Result<Vec<u8>>,
+    accept_legacy: bool,
+) -> Result<()> {
+    if key.verify(canonical, signature).is_ok() {
+        return Ok(());
+    }
+    if accept_legacy && key.verify(&legacy()?, signature).is_ok() {
+        tracing::debug!("✍️  Accepted a pre-canonical signature");
+        return Ok(());
+    }
+    anyhow::bail!("signature does not verify")
+}
+
+fn write_head(major: u8, n: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    match n {
+        0..=23 => out.push(major | n as u8),
+        24..=0xff => out.extend([major | 24, n as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend((n as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend((n as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend(n.to_be_bytes());
+        }
+    }
+}
+
+fn write_canonical(value: &Value, out: &mut Vec<u8>) -> Result<()> {
+    match value {
+        Value::Null => out.push(0xf6),
+        Value::Bool(false) => out.push(0xf4),
+        Value::Bool(true) => out.push(0xf5),
+        Value::Integer(i) if *i >= 0 => write_head(0, u64::try_from(*i).context("Integer out of CBOR range")?, out),
+        Value::Integer(i) => write_head(1, u64::try_from(-1 - *i).context("Integer out of CBOR range")?, out),
+        // Our decoder only reads f64 from the 8-byte form
+        Value::Float(f) => {
+            out.push(0xfb);
+            out.extend(f.to_be_bytes());
+        }
+        Value::Bytes(bytes) => {
+            write_head(2, bytes.len() as u64, out);
+            out.extend(bytes);
+        }
+        Value::Text(text) => {
+            write_head(3, text.len() as u64, out);
+            out.extend(text.as_bytes());
+        }
+        Value::Array(items) => {
+            write_head(4, items.len() as u64, out);
+            for item in items {
+                write_canonical(item, out)?;
+            }
+        }
+        Value::Map(entries) => {
+            let mut encoded = entries
+                .iter()
+                .map(|(key, value)| {
+                    let (mut k, mut v) = (Vec::new(), Vec::new());
+                    write_canonical(key, &mut k)?;
+                    write_canonical(value, &mut v)?;
+                    Ok((k, v))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            encoded.sort_by(|a, b| a.0.cmp(&b.0));
+            if encoded.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+                anyhow::bail!("Duplicate map key");
+            }
+            write_head(5, encoded.len() as u64, out);
+            for (key, value) in encoded {
+                out.extend(key);
+                out.extend(value);
+            }
+        }
+        Value::Tag(tag, inner) => {
+            write_head(6, *tag, out);
+            write_canonical(inner, out)?;
+        }
+        _ => anyhow::bail!("Unsupported CBOR value"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::{BTreeMap, HashMap};
+
+    #[derive(Serialize)]
+    struct Ordered {
+        name: String,
+        version: u32,
+        tags: Vec<String>,
+    }
+
+    #[derive(Serialize)]
+    struct Reordered {
+        tags: Vec<String>,
+        version: u32,
+        name: String,
+    }
+
+    #[test]
+    fn test_canonical_encoding() {
+        // RFC 8949 examples, and shortest heads
+        assert_eq!(to_canonical(&0u64).unwrap(), [0x00]);
+        assert_eq!(to_canonical(&24u8).unwrap(), [0x18, 0x18]);
+        assert_eq!(to_canonical(&1000u64).unwrap(), [0x19, 0x03, 0xe8]);
+        assert_eq!(to_canonical(&-1000i64).unwrap(), [0x39, 0x03, 0xe7]);
+        assert_eq!(to_canonical(&u64::MAX).unwrap()[0], 0x1b);
+        assert_eq!(to_canonical(&"IETF").unwrap(), [0x64, 0x49, 0x45, 0x54, 0x46]);
+        // Keys sort by encoded bytes: shorter first, then bytewise
+        let map = HashMap::from([("aa", 1), ("b", 2), ("a", 3)]);
+        assert_eq!(to_canonical(&map).unwrap(), [0xa3, 0x61, b'a', 0x03, 0x61, b'b', 0x02, 0x62, b'a', b'a', 0x01]);
+        // Iterator-backed sequences get definite lengths
+        struct Unsized;
+        impl Serialize for Unsized {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                s.collect_seq((1..=3).filter(|_| true))
+            }
+        }
+        assert_eq!(to_canonical(&Unsized).unwrap(), [0x83, 0x01, 0x02, 0x03]);
+    }
+
+    #[test]
+    fn test_formats_round_trip_and_negotiate() {
+        let value = BTreeMap::from([("bytes".to_string(), vec![1u8, 2, 3])]);
+        for format in WireFormat::ALL {
+            let bytes = format.encode(&value).unwrap();
+            assert_eq!(format.decode::<BTreeMap<String, Vec<u8>>>(&bytes).unwrap(), value);
+        }
+        assert_eq!(WireFormat::of_protocol("/quantra/1.1.0/json"), WireFormat::Json);
+        assert_eq!(WireFormat::of_protocol("/quantra/1.1.0/cbor"), WireFormat::CborCanonical);
+        assert_eq!(WireFormat::of_protocol("/quantra/1.1.0"), WireFormat::CborCanonical);
+    }
+
+    #[test]
+    fn test_legacy_signature_fallback() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[5u8; 32]);
+        let legacy = b"legacy bytes".to_vec();
+        let signature = ed25519_dalek::Signer::sign(&key, &legacy);
+        let public = key.verifying_key();
+        assert!(verify_with(&public, &signature, b"canonical", || Ok(legacy.clone()), true).is_ok());
+        assert!(verify_with(&public, &signature, b"canonical", || Ok(legacy.clone()), false).is_err());
+        let canonical = ed25519_dalek::Signer::sign(&key, b"canonical");
+        assert!(verify_with(&public, &canonical, b"canonical", || anyhow::bail!("not needed"), false).is_ok());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_field_order_does_not_matter(name in ".{0,12}", version in any::<u32>(), tags in prop::collection::vec("[a-z]{0,6}", 0..6)) {
+            let a = Ordered { name: name.clone(), version, tags: tags.clone() };
+            let b = Reordered { tags, version, name };
+            prop_assert_eq!(to_canonical(&a).unwrap(), to_canonical(&b).unwrap());
+        }
+
+        #[test]
+        fn prop_insertion_order_does_not_matter(entries in prop::collection::btree_map("[a-z]{0,8}", any::<i64>(), 0..16)) {
+            let forward: HashMap<_, _> = entries.iter().collect();
+            let mut reversed = HashMap::with_capacity(1);
+            for (key, value) in entries.iter().rev() {
+                reversed.insert(key, value);
+            }
+            prop_assert_eq!(to_canonical(&forward).unwrap(), to_canonical(&entries).unwrap());
+            prop_assert_eq!(to_canonical(&reversed).unwrap(), to_canonical(&entries).unwrap());
+        }
+
+        #[test]
+        fn prop_canonical_is_a_fixed_point(entries in prop::collection::btree_map("[a-z]{0,8}", prop::collection::vec(any::<u8>(), 0..8), 0..8), n in any::<i64>()) {
+            let once = to_canonical(&(n, &entries)).unwrap();
+            let decoded: (i64, BTreeMap<String, Vec<u8>>) = WireFormat::CborCanonical.decode(&once).unwrap();
+            prop_assert_eq!(to_canonical(&decoded).unwrap(), once);
+        }
+    }
+}
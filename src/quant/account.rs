@@ -0,0 +1,494 @@
+//! Paper Cash Account
+//! The cash side of paper trading. Fills settle against cash with their
+//! commission; interest accrues daily on a positive balance and is charged
+//! at the borrow rate on a negative one. With margin on, buys are checked
+//! against buying power under per-asset-type initial rates, and equity
+//! below the maintenance requirement is a margin call, notified and
+//! optionally met by selling the largest position
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::portfolio::{Portfolio, Position};
+use super::portfolio_store::{market_trade, PortfolioStore};
+use super::rebalance::FeeModel;
+use super::{AssetType, Trade, TradeSide};
+use crate::security::notifications::{NotificationRouter, SinkEvent};
+
+/// `[portfolio.account]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccountSettings {
+    /// Deposited when a paper portfolio has never recorded cash, and the
+    /// default `backtest --cash`
+    pub starting_cash: Decimal,
+    /// Annual % credited on a positive balance
+    pub interest_rate_pct: Decimal,
+    /// Annual % charged on a negative balance
+    pub borrow_rate_pct: Decimal,
+    /// Days in an interest year (360 or 365)
+    pub day_count: u32,
+    pub margin: MarginSettings,
+}
+
+impl Default for AccountSettings {
+    fn default() -> Self {
+        Self {
+            starting_cash: Decimal::from(100_000),
+            interest_rate_pct: Decimal::ZERO,
+            borrow_rate_pct: Decimal::ZERO,
+            day_count: 360,
+            margin: MarginSettings::default(),
+        }
+    }
+}
+
+/// `[portfolio.account.margin]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarginSettings {
+    /// Off: a cash account; buys need their full cost in cash
+    pub enabled: bool,
+    pub initial_pct: Decimal,
+    pub maintenance_pct: Decimal,
+    /// Rates for asset types that differ from the defaults above
+    pub asset_types: HashMap<AssetType, MarginRates>,
+    /// Meet a margin call by selling the largest position at its mark
+    pub auto_liquidate: bool,
+}
+
+impl Default for MarginSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            initial_pct: Decimal::from(50),
+            maintenance_pct: Decimal::from(25),
+            asset_types: HashMap::new(),
+            auto_liquidate: false,
+        }
+    }
+}
+
+/// Shares of a position's market value that must be covered by equity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarginRates {
+    /// To open it
+    pub initial_pct: Decimal,
+    /// To keep it
+    pub maintenance_pct: Decimal,
+}
+
+impl MarginSettings {
+    /// Rates for `asset_type`; a cash account needs 100% up front and has
+    /// no maintenance requirement
+    pub fn rates(&self, asset_type: AssetType) -> MarginRates {
+        if !self.enabled {
+            return MarginRates { initial_pct: Decimal::ONE_HUNDRED, maintenance_pct: Decimal::ZERO };
+        }
+        self.asset_types.get(&asset_type).copied().unwrap_or(MarginRates {
+            initial_pct: self.initial_pct,
+            maintenance_pct: self.maintenance_pct,
+        })
+    }
+}
+
+/// Why an order was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderRejection {
+    /// The order's initial margin and commission exceed the equity not
+    /// already committed to open positions
+    InsufficientBuyingPower { required: Decimal, available: Decimal },
+}
+
+impl fmt::Display for OrderRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InsufficientBuyingPower { required, available } => write!(
+                f,
+                "Insufficient buying power: the order needs {} but {} is available",
+                required.round_dp(2),
+                available.round_dp(2)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OrderRejection {}
+
+/// Interest posted to cash for one day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InterestAccrual {
+    pub date: NaiveDate,
+    /// Cash it was accrued on
+    pub balance: Decimal,
+    /// Credited (positive) or charged (negative), to the cent
+    pub amount: Decimal,
+}
+
+/// One day's interest on `balance`, to the cent
+pub fn daily_interest(balance: Decimal, settings: &AccountSettings) -> Decimal {
+    let rate = match balance.is_sign_negative() {
+        true => settings.borrow_rate_pct,
+        false => settings.interest_rate_pct,
+    };
+    if settings.day_count == 0 {
+        return Decimal::ZERO;
+    }
+    (balance * rate / Decimal::ONE_HUNDRED / Decimal::from(settings.day_count)).round_dp(2)
+}
+
+/// A portfolio's equity against its margin requirements at current marks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MarginStatus {
+    pub cash: Decimal,
+    pub equity: Decimal,
+    pub initial_requirement: Decimal,
+    pub maintenance_requirement: Decimal,
+}
+
+impl MarginStatus {
+    pub fn of(portfolio: &Portfolio, cash: Decimal, margin: &MarginSettings) -> Self {
+        let mut status = Self {
+            cash,
+            equity: cash,
+            initial_requirement: Decimal::ZERO,
+            maintenance_requirement: Decimal::ZERO,
+        };
+        for position in portfolio.positions.values() {
+            let value = position.quantity * position.current_price;
+            let rates = margin.rates(position.asset_type);
+            status.equity += value;
+            status.initial_requirement += value * rates.initial_pct / Decimal::ONE_HUNDRED;
+            status.maintenance_requirement += value * rates.maintenance_pct / Decimal::ONE_HUNDRED;
+        }
+        status
+    }
+
+    /// Equity not committed to open positions' initial margin
+    pub fn buying_power(&self) -> Decimal {
+        self.equity - self.initial_requirement
+    }
+
+    pub fn margin_call(&self) -> Option<MarginCall> {
+        (self.equity < self.maintenance_requirement).then(|| MarginCall {
+            equity: self.equity,
+            requirement: self.maintenance_requirement,
+            deficit: self.maintenance_requirement - self.equity,
+            liquidated: None,
+        })
+    }
+}
+
+/// Whether buying `notional` of `asset_type` for `commission` fits the
+/// buying power
+pub fn check_order(
+    portfolio: &Portfolio,
+    cash: Decimal,
+    margin: &MarginSettings,
+    asset_type: AssetType,
+    notional: Decimal,
+    commission: Decimal,
+) -> Result<(), OrderRejection> {
+    let required = notional * margin.rates(asset_type).initial_pct / Decimal::ONE_HUNDRED + commission;
+    let available = MarginStatus::of(portfolio, cash, margin).buying_power();
+    match required > available {
+        true => Err(OrderRejection::InsufficientBuyingPower { required, available }),
+        false => Ok(()),
+    }
+}
+
+/// Maintenance breach
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MarginCall {
+    pub equity: Decimal,
+    pub requirement: Decimal,
+    pub deficit: Decimal,
+    pub liquidated: Option<String>,
+}
+
+/// Largest position by market value
+pub fn largest_position(portfolio: &Portfolio) -> Option<&Position> {
+    portfolio
+        .positions
+        .values()
+        .max_by_key(|position| (position.quantity * position.current_price, std::cmp::Reverse(position.symbol.clone())))
+}
+
+/// The account rules applied to a stored paper portfolio
+pub struct PaperAccount {
+    settings: AccountSettings,
+    fees: FeeModel,
+    notifier: Option<Arc<NotificationRouter>>,
+    /// A call was raised and equity hasn't recovered yet
+    in_call: AtomicBool,
+}
+
+impl PaperAccount {
+    pub fn new(settings: AccountSettings, fees: FeeModel) -> Self {
+        Self { settings, fees, notifier: None, in_call: AtomicBool::new(false) }
+    }
+
+    pub fn set_notifier(&mut self, notifier: Arc<NotificationRouter>) {
+        self.notifier = Some(notifier);
+    }
+
+    pub fn settings(&self) -> &AccountSettings {
+        &self.settings
+    }
+
+    /// Fill `trade` with its commission; a buy is first checked against
+    /// buying power and fails with an `OrderRejection`
+    pub fn submit(
+        &self,
+        store: &PortfolioStore,
+        portfolio: &mut Portfolio,
+        trade: Trade,
+        asset_type: AssetType,
+        triggered_by: Option<String>,
+    ) -> Result<()> {
+        self.submit_lots(store, portfolio, trade, asset_type, triggered_by, Vec::new())
+    }
+
+    /// `submit` for a sell closing `lots` (see `PortfolioStore::fill_lots`)
+    pub fn submit_lots(
+        &self,
+        store: &PortfolioStore,
+        portfolio: &mut Portfolio,
+        trade: Trade,
+        asset_type: AssetType,
+        triggered_by: Option<String>,
+        lots: Vec<String>,
+    ) -> Result<()> {
+        let notional = trade.quantity * trade.price;
+        let commission = self.fees.commission(notional);
+        if matches!(trade.side, TradeSide::Buy) {
+            check_order(portfolio, store.cash()?, &self.settings.margin, asset_type, notional, commission)?;
+        }
+        let symbol = trade.symbol.clone();
+        store.fill_lots(portfolio, trade, commission, triggered_by, lots)?;
+        if let Some(position) = portfolio.positions.get_mut(&symbol).filter(|p| p.asset_type != asset_type) {
+            position.asset_type = asset_type;
+            store.put_position(position)?;
+        }
+        Ok(())
+    }
+
+    /// Accrue interest through `date`, then check the maintenance margin at
+    /// the positions' current marks. A new breach is notified (once until
+    /// equity recovers) and, with `auto_liquidate`, met by selling the
+    /// largest position at its mark
+    pub fn mark(
+        &self,
+        store: &PortfolioStore,
+        portfolio: &mut Portfolio,
+        date: NaiveDate,
+    ) -> Result<Option<MarginCall>> {
+        store.accrue_interest(date, &self.settings)?;
+        let status = MarginStatus::of(portfolio, store.cash()?, &self.settings.margin);
+        let Some(mut call) = status.margin_call() else {
+            self.in_call.store(false, Ordering::Relaxed);
+            return Ok(None);
+        };
+        if self.settings.margin.auto_liquidate {
+            if let Some(largest) = largest_position(portfolio) {
+                let trade = market_trade(&largest.symbol, TradeSide::Sell, largest.quantity, largest.current_price);
+                let commission = self.fees.commission(trade.quantity * trade.price);
+                call.liquidated = Some(trade.symbol.clone());
+                store.fill(portfolio, trade, commission, Some("margin_call".to_string()))?;
+            }
+        }
+        let recovered = MarginStatus::of(portfolio, store.cash()?, &self.settings.margin).margin_call().is_none();
+        let repeated = self.in_call.swap(!recovered, Ordering::Relaxed);
+        if !repeated {
+            tracing::warn!(
+                "📉 Margin call on {}: equity {} below maintenance {}{}",
+                portfolio.name,
+                call.equity.round_dp(2),
+                call.requirement.round_dp(2),
+                call.liquidated.as_ref().map(|symbol| format!("; sold {}", symbol)).unwrap_or_default()
+            );
+            if let Some(notifier) = &self.notifier {
+                notifier.notify(SinkEvent::MarginCall {
+                    portfolio: portfolio.name.clone(),
+                    equity: call.equity,
+                    requirement: call.requirement,
+                    deficit: call.deficit,
+                    liquidated: call.liquidated.clone(),
+                });
+            }
+        }
+        Ok(Some(call))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::notifications::{EventSink, RouteConfig, Severity};
+    use crate::storage::RuntimeMode;
+    use async_trait::async_trait;
+    use std::path::Path;
+    use std::str::FromStr;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::from_str(s).unwrap()
+    }
+
+    fn paper_store() -> PortfolioStore {
+        PortfolioStore::open(Path::new("unused"), RuntimeMode::Ephemeral).unwrap()
+    }
+
+    /// Reg T-style 50% / 25% margin
+    fn margin_account(auto_liquidate: bool) -> PaperAccount {
+        let margin = MarginSettings { enabled: true, auto_liquidate, ..Default::default() };
+        PaperAccount::new(AccountSettings { margin, ..Default::default() }, FeeModel::default())
+    }
+
+    #[test]
+    fn test_over_leveraged_order_rejected() {
+        let store = paper_store();
+        store.seed_cash(dec("10000"), date("2026-03-02")).unwrap();
+        let mut portfolio = store.load().unwrap();
+
+        // A cash account can't spend more than its cash, commission included
+        let fees = FeeModel { per_trade: dec("1"), bps: Decimal::ZERO };
+        let cash_account = PaperAccount::new(AccountSettings::default(), fees);
+        let buy = market_trade("aapl", TradeSide::Buy, dec("100"), dec("100"));
+        let err = cash_account.submit(&store, &mut portfolio, buy, AssetType::Stock, None).unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&OrderRejection::InsufficientBuyingPower { required: dec("10001"), available: dec("10000") })
+        );
+        assert!(store.ledger().unwrap().is_empty());
+
+        // At 50% initial margin 10000 carries 20000 of stock, and no more
+        let account = margin_account(false);
+        let buy = market_trade("aapl", TradeSide::Buy, dec("200"), dec("100"));
+        account.submit(&store, &mut portfolio, buy, AssetType::Stock, None).unwrap();
+        assert_eq!(store.cash().unwrap(), dec("-10000"));
+        let more = market_trade("aapl", TradeSide::Buy, dec("1"), dec("100"));
+        let err = account.submit(&store, &mut portfolio, more, AssetType::Stock, None).unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&OrderRejection::InsufficientBuyingPower { required: dec("50"), available: dec("0") })
+        );
+
+        // Crypto at 100% initial gets nothing from the same equity
+        let mut settings = account.settings().clone();
+        let full = MarginRates { initial_pct: Decimal::ONE_HUNDRED, maintenance_pct: dec("50") };
+        settings.margin.asset_types.insert(AssetType::Crypto, full);
+        assert_eq!(settings.margin.rates(AssetType::Crypto), full);
+        assert_eq!(settings.margin.rates(AssetType::Stock).initial_pct, dec("50"));
+    }
+
+    #[test]
+    fn test_interest_over_a_month() {
+        // 3.65% a year over 365 days is 1.00 a day on 10000; the compounded
+        // pennies never reach a half cent within the month
+        let settings = AccountSettings {
+            interest_rate_pct: dec("3.65"),
+            borrow_rate_pct: dec("3.65"),
+            day_count: 365,
+            ..Default::default()
+        };
+        let store = paper_store();
+        store.seed_cash(dec("10000"), date("2026-04-01")).unwrap();
+        assert!(store.accrue_interest(date("2026-04-01"), &settings).unwrap().is_empty());
+        let accrued = store.accrue_interest(date("2026-05-01"), &settings).unwrap();
+        assert_eq!(accrued.len(), 30);
+        assert!(accrued.iter().all(|a| a.amount == dec("1.00")));
+        assert_eq!((accrued[0].date, accrued[29].date), (date("2026-04-02"), date("2026-05-01")));
+        assert_eq!(store.cash().unwrap(), dec("10030.00"));
+        // Catching up again is a no-op
+        assert!(store.accrue_interest(date("2026-05-01"), &settings).unwrap().is_empty());
+        assert_eq!(store.interest().unwrap().len(), 30);
+
+        // Borrowed 5000 at the same rate costs 0.50 a day
+        let borrowed = paper_store();
+        let mut portfolio = borrowed.load().unwrap();
+        let buy = market_trade("aapl", TradeSide::Buy, dec("50"), dec("100"));
+        borrowed.execute(&mut portfolio, buy, None).unwrap();
+        borrowed.accrue_interest(date("2026-04-01"), &settings).unwrap();
+        let charged = borrowed.accrue_interest(date("2026-05-01"), &settings).unwrap();
+        assert!(charged.iter().all(|a| a.amount == dec("-0.50")));
+        assert_eq!(borrowed.cash().unwrap(), dec("-5015.00"));
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_breach_calls_and_liquidates() {
+        #[derive(Default)]
+        struct Capture(parking_lot::Mutex<Vec<SinkEvent>>);
+        #[async_trait]
+        impl EventSink for Capture {
+            async fn emit(&self, event: &SinkEvent) -> Result<()> {
+                self.0.lock().push(event.clone());
+                Ok(())
+            }
+        }
+
+        let capture = Arc::new(Capture::default());
+        let route = RouteConfig {
+            categories: vec!["margin_call".to_string()],
+            min_severity: Severity::Info,
+            sinks: vec!["capture".to_string()],
+        };
+        let mut router = NotificationRouter::new(vec![route], 16, 1);
+        router.add_sink("capture", capture.clone(), 60);
+        let router = Arc::new(router);
+
+        for auto_liquidate in [false, true] {
+            let store = paper_store();
+            store.seed_cash(dec("10000"), date("2026-03-02")).unwrap();
+            let mut portfolio = store.load().unwrap();
+            let mut account = margin_account(auto_liquidate);
+            account.set_notifier(router.clone());
+            let buy = market_trade("aapl", TradeSide::Buy, dec("200"), dec("100"));
+            account.submit(&store, &mut portfolio, buy, AssetType::Stock, None).unwrap();
+            assert!(account.mark(&store, &mut portfolio, date("2026-03-02")).unwrap().is_none());
+
+            // At 60 the stock is worth 12000 against 10000 borrowed: equity
+            // 2000, under the 25% maintenance of 3000
+            portfolio.update_price("AAPL", dec("60"));
+            let call = account.mark(&store, &mut portfolio, date("2026-03-03")).unwrap().unwrap();
+            assert_eq!((call.equity, call.requirement, call.deficit), (dec("2000"), dec("3000"), dec("1000")));
+            match auto_liquidate {
+                false => {
+                    assert_eq!(call.liquidated, None);
+                    // Still breached: reported again, notified once
+                    assert!(account.mark(&store, &mut portfolio, date("2026-03-03")).unwrap().is_some());
+                }
+                true => {
+                    assert_eq!(call.liquidated.as_deref(), Some("AAPL"));
+                    assert!(portfolio.positions.is_empty());
+                    assert_eq!(store.cash().unwrap(), dec("2000"));
+                    let ledger = store.ledger().unwrap();
+                    assert_eq!(ledger.last().unwrap().triggered_by.as_deref(), Some("margin_call"));
+                    assert!(account.mark(&store, &mut portfolio, date("2026-03-03")).unwrap().is_none());
+                }
+            }
+        }
+
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let mut events = capture.0.lock().clone();
+        events.sort_by_key(|event| match event {
+            SinkEvent::MarginCall { liquidated, .. } => liquidated.clone(),
+            _ => None,
+        });
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], SinkEvent::MarginCall { liquidated: None, .. }));
+        assert!(matches!(
+            &events[1],
+            SinkEvent::MarginCall { deficit, liquidated: Some(symbol), .. } if *deficit == dec("1000") && symbol == "AAPL"
+        ));
+    }
+}
@@ -0,0 +1,285 @@
+//! Cox-Ross-Rubinstein Binomial Pricing
+//! American / European option prices, tree-consistent Greeks and the
+//! early-exercise boundary
+
+use anyhow::Result;
+use serde::Serialize;
+
+use super::pricing::cancel::CancelToken;
+use super::pricing::{Greeks, OptionType};
+
+/// Default number of tree steps.
+/// 500 steps keeps the CRR price error well under a cent for typical
+/// equity options (error shrinks roughly as 1/N) while a full Greeks run
+/// (five trees) stays in the low milliseconds.
+pub const DEFAULT_BINOMIAL_STEPS: usize = 500;
+
+/// Exercise style
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExerciseStyle {
+    European,
+    American,
+}
+
+/// Binomial model inputs
+#[derive(Debug, Clone, Copy)]
+pub struct BinomialParams {
+    pub spot: f64,
+    pub strike: f64,
+    pub rate: f64,
+    /// Continuous dividend yield
+    pub dividend_yield: f64,
+    pub volatility: f64,
+    pub time_to_expiry: f64,
+    pub option_type: OptionType,
+    pub style: ExerciseStyle,
+    pub steps: usize,
+}
+
+/// Critical spot price at one time step
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BoundaryPoint {
+    /// Time from valuation (years)
+    pub time: f64,
+    /// Spot at which exercise becomes optimal, if any node exercises
+    pub critical_spot: Option<f64>,
+}
+
+/// Node values captured while rolling back the tree
+struct Rollback {
+    price: f64,
+    step1: [f64; 2],
+    step2: [f64; 3],
+}
+
+impl BinomialParams {
+    fn validate(&self) -> Result<()> {
+        if self.spot <= 0.0 || self.strike <= 0.0 {
+            anyhow::bail!("Spot and strike must be positive");
+        }
+        if self.volatility <= 0.0 || self.time_to_expiry <= 0.0 {
+            anyhow::bail!("Volatility and time to expiry must be positive");
+        }
+        if self.steps < 3 {
+            anyhow::bail!("Binomial tree needs at least 3 steps");
+        }
+        Ok(())
+    }
+
+    /// (dt, up factor, risk-neutral up probability, per-step discount)
+    fn lattice(&self) -> Result<(f64, f64, f64, f64)> {
+        let dt = self.time_to_expiry / self.steps as f64;
+        let u = (self.volatility * dt.sqrt()).exp();
+        let d = 1.0 / u;
+        let p = (((self.rate - self.dividend_yield) * dt).exp() - d) / (u - d);
+        if !(0.0..=1.0).contains(&p) {
+            anyhow::bail!("Binomial tree is unstable for these inputs (p = {:.4}); increase steps", p);
+        }
+        Ok((dt, u, p, (-self.rate * dt).exp()))
+    }
+
+    fn payoff(&self, spot: f64) -> f64 {
+        match self.option_type {
+            OptionType::Call => (spot - self.strike).max(0.0),
+            OptionType::Put => (self.strike - spot).max(0.0),
+        }
+    }
+
+    /// Roll the tree back to t=0, calling `visit(step, spots, exercised)` at
+    /// each step and checking `cancel` between steps
+    fn rollback(&self, cancel: &CancelToken, mut visit: impl FnMut(usize, &[f64], &[bool])) -> Result<Rollback> {
+        self.validate()?;
+        let (_, u, p, disc) = self.lattice()?;
+        let n = self.steps;
+
+        // Node j at step i has spot S0 * u^(2j - i)
+        let spot_at = |i: usize, j: usize| self.spot * u.powi(2 * j as i32 - i as i32);
+
+        let mut values: Vec<f64> = (0..=n).map(|j| self.payoff(spot_at(n, j))).collect();
+        let mut spots = Vec::with_capacity(n);
+        let mut exercised = Vec::with_capacity(n);
+        let mut step1 = [0.0; 2];
+        let mut step2 = [0.0; 3];
+
+        for i in (0..n).rev() {
+            cancel.check()?;
+            spots.clear();
+            exercised.clear();
+            for j in 0..=i {
+                let continuation = disc * (p * values[j + 1] + (1.0 - p) * values[j]);
+                let spot = spot_at(i, j);
+                let intrinsic = self.payoff(spot);
+                let exercise = self.style == ExerciseStyle::American && intrinsic > 0.0 && intrinsic >= continuation;
+                values[j] = if exercise { intrinsic } else { continuation };
+                spots.push(spot);
+                exercised.push(exercise);
+            }
+            visit(i, &spots, &exercised);
+
+            match i {
+                2 => step2.copy_from_slice(&values[..3]),
+                1 => step1.copy_from_slice(&values[..2]),
+                _ => {}
+            }
+        }
+
+        Ok(Rollback { price: values[0], step1, step2 })
+    }
+}
+
+/// Price an option on a CRR binomial tree
+pub fn binomial_price(params: &BinomialParams) -> Result<f64> {
+    binomial_price_within(params, &CancelToken::new())
+}
+
+/// `binomial_price`, stopping with `Cancelled` when `cancel` fires
+pub fn binomial_price_within(params: &BinomialParams, cancel: &CancelToken) -> Result<f64> {
+    Ok(params.rollback(cancel, |_, _, _| {})?.price)
+}
+
+/// Price and delta from a single tree, for callers that re-price often
+pub fn binomial_price_and_delta(params: &BinomialParams) -> Result<(f64, f64)> {
+    let tree = params.rollback(&CancelToken::new(), |_, _, _| {})?;
+    let (_, u, _, _) = params.lattice()?;
+    Ok((tree.price, tree_delta(&tree, params.spot, u)))
+}
+
+/// Delta from the two nodes after the first step
+fn tree_delta(tree: &Rollback, spot: f64, u: f64) -> f64 {
+    (tree.step1[1] - tree.step1[0]) / (spot * u - spot / u)
+}
+
+/// Greeks consistent with the binomial price
+///
+/// Delta and gamma come from the tree's first two steps and theta from the
+/// step-2 middle node (same spot as today). Vega and rho use central
+/// differences with bumps scaled to the input. Units match
+/// `calculate_greeks`: vega and rho per 1%, theta per calendar day.
+pub fn binomial_greeks(params: &BinomialParams) -> Result<Greeks> {
+    binomial_greeks_within(params, &CancelToken::new())
+}
+
+/// `binomial_greeks`, stopping with `Cancelled` when `cancel` fires
+pub fn binomial_greeks_within(params: &BinomialParams, cancel: &CancelToken) -> Result<Greeks> {
+    let tree = params.rollback(cancel, |_, _, _| {})?;
+    let (dt, u, _, _) = params.lattice()?;
+    let s = params.spot;
+
+    let delta = tree_delta(&tree, s, u);
+
+    let (s_uu, s_dd) = (s * u * u, s / (u * u));
+    let delta_up = (tree.step2[2] - tree.step2[1]) / (s_uu - s);
+    let delta_down = (tree.step2[1] - tree.step2[0]) / (s - s_dd);
+    let gamma = (delta_up - delta_down) / ((s_uu - s_dd) / 2.0);
+
+    let theta = (tree.step2[1] - tree.price) / (2.0 * dt) / 365.0;
+
+    let vol_bump = (params.volatility * 0.01).max(1e-4);
+    let vega = central_difference(params, cancel, vol_bump, |p, h| p.volatility += h)? / 100.0;
+
+    let rate_bump = (params.rate.abs() * 0.01).max(1e-4);
+    let rho = central_difference(params, cancel, rate_bump, |p, h| p.rate += h)? / 100.0;
+
+    Ok(Greeks { delta, gamma, vega, theta, rho })
+}
+
+fn central_difference(
+    params: &BinomialParams,
+    cancel: &CancelToken,
+    bump: f64,
+    apply: impl Fn(&mut BinomialParams, f64),
+) -> Result<f64> {
+    let mut up = *params;
+    apply(&mut up, bump);
+    let mut down = *params;
+    apply(&mut down, -bump);
+    Ok((binomial_price_within(&up, cancel)? - binomial_price_within(&down, cancel)?) / (2.0 * bump))
+}
+
+/// Critical spot per time step where early exercise becomes optimal
+///
+/// For puts this is the highest exercised node; for calls (only relevant with
+/// dividends) the lowest. Always empty for European options.
+pub fn early_exercise_boundary(params: &BinomialParams) -> Result<Vec<BoundaryPoint>> {
+    let (dt, _, _, _) = params.lattice()?;
+    let mut boundary = Vec::with_capacity(params.steps);
+
+    params.rollback(&CancelToken::new(), |i, spots, exercised| {
+        let exercising = spots.iter().zip(exercised).filter(|(_, ex)| **ex).map(|(s, _)| *s);
+        let critical_spot = match params.option_type {
+            OptionType::Put => exercising.reduce(f64::max),
+            OptionType::Call => exercising.reduce(f64::min),
+        };
+        boundary.push(BoundaryPoint { time: i as f64 * dt, critical_spot });
+    })?;
+
+    boundary.reverse();
+    Ok(boundary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quant::pricing::{black_scholes, calculate_greeks};
+
+    fn params(option_type: OptionType, style: ExerciseStyle, steps: usize) -> BinomialParams {
+        BinomialParams {
+            spot: 100.0,
+            strike: 100.0,
+            rate: 0.05,
+            dividend_yield: 0.0,
+            volatility: 0.2,
+            time_to_expiry: 1.0,
+            option_type,
+            style,
+            steps,
+        }
+    }
+
+    #[test]
+    fn test_european_converges_to_black_scholes() {
+        for option_type in [OptionType::Call, OptionType::Put] {
+            let bs_price = black_scholes(100.0, 100.0, 0.05, 0.2, 1.0, option_type).unwrap();
+            let bs = calculate_greeks(100.0, 100.0, 0.05, 0.2, 1.0, option_type).unwrap();
+
+            let coarse = binomial_greeks(&params(option_type, ExerciseStyle::European, 50)).unwrap();
+            let fine_params = params(option_type, ExerciseStyle::European, 1000);
+            let fine = binomial_greeks(&fine_params).unwrap();
+
+            // Price within 1 cent, Greeks within stated tolerances at 1000 steps
+            assert!((binomial_price(&fine_params).unwrap() - bs_price).abs() < 0.01);
+            assert!((fine.delta - bs.delta).abs() < 1e-3);
+            assert!((fine.gamma - bs.gamma).abs() < 1e-3);
+            assert!((fine.vega - bs.vega).abs() < 5e-3);
+            assert!((fine.theta - bs.theta).abs() < 1e-3);
+            assert!((fine.rho - bs.rho).abs() < 5e-3);
+
+            // More steps should not move delta further from BS
+            assert!((fine.delta - bs.delta).abs() <= (coarse.delta - bs.delta).abs() + 1e-4);
+        }
+        println!("✅ Binomial → Black-Scholes convergence test PASSED!");
+    }
+
+    #[test]
+    fn test_american_put_premium_and_boundary() {
+        let american = params(OptionType::Put, ExerciseStyle::American, 500);
+        let european = params(OptionType::Put, ExerciseStyle::European, 500);
+        assert!(binomial_price(&american).unwrap() > binomial_price(&european).unwrap());
+
+        let boundary = early_exercise_boundary(&american).unwrap();
+        assert_eq!(boundary.len(), 500);
+
+        // The put boundary rises toward the strike as expiry approaches
+        let (_, u, _, _) = american.lattice().unwrap();
+        let points: Vec<f64> = boundary.iter().filter_map(|b| b.critical_spot).collect();
+        assert!(points.len() > 100);
+        for pair in points.windows(2) {
+            // Allow one lattice spacing of jitter between adjacent steps
+            assert!(pair[1] >= pair[0] / u - 1e-9, "boundary not monotone: {:?}", pair);
+        }
+        assert!(points.last().unwrap() > points.first().unwrap());
+        assert!(*points.last().unwrap() <= 100.0);
+
+        assert!(early_exercise_boundary(&european).unwrap().iter().all(|b| b.critical_spot.is_none()));
+    }
+}
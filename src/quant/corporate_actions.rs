@@ -0,0 +1,473 @@
+//! Corporate Actions
+//! Splits and dividends per symbol, entered with `actions add` or imported
+//! from CSV. From its ex-date an action adjusts the position held the day
+//! before: quantity and average cost for splits and stock dividends, a cash
+//! credit for cash dividends. Each is applied once per symbol, ex-date and
+//! kind and recorded in the portfolio's action ledger. Candle histories can
+//! be back-adjusted so prices before an action line up with those after;
+//! the raw candles are never rewritten
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use super::portfolio::{Portfolio, Position};
+use super::portfolio_store::{LedgerEntry, PortfolioStore};
+use super::{Candle, TradeSide};
+use crate::scheduler::{DailySpec, Scheduler};
+
+pub const TASK_NAME: &str = "portfolio.corporate_actions";
+
+/// Columns of an `actions import` CSV
+const CSV_COLUMNS: [&str; 4] = ["symbol", "ex_date", "kind", "value"];
+
+/// `[portfolio.corporate_actions]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorporateActionSettings {
+    /// Apply due actions every day while `alerts run` (or `p2p --alerts`) runs
+    pub apply_daily: bool,
+    /// Local time of day, `HH:MM`
+    #[serde(with = "crate::maintenance::hh_mm")]
+    pub at: NaiveTime,
+}
+
+impl Default for CorporateActionSettings {
+    fn default() -> Self {
+        Self { apply_daily: true, at: NaiveTime::from_hms_opt(6, 0, 0).expect("06:00 is a valid time") }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ActionKind {
+    /// `ratio` new shares per old one: 4 for 4:1, 0.1 for a 1:10 reverse split
+    Split { ratio: Decimal },
+    /// Paid per share held the day before the ex-date
+    CashDividend { amount: Decimal },
+    /// `ratio` new shares per share held: 0.05 for 5%
+    StockDividend { ratio: Decimal },
+}
+
+impl ActionKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Split { .. } => "split",
+            Self::CashDividend { .. } => "cash_dividend",
+            Self::StockDividend { .. } => "stock_dividend",
+        }
+    }
+
+    /// From `actions add` / CSV: `split 4:1` (or `4`), `cash-dividend 0.24`,
+    /// `stock-dividend 5%` (or `0.05`)
+    pub fn parse(kind: &str, value: &str) -> Result<Self> {
+        let value = value.trim();
+        let positive = |d: Decimal, what: &str| match d > Decimal::ZERO {
+            true => Ok(d),
+            false => anyhow::bail!("{} must be positive", what),
+        };
+        let decimal = |s: &str, what: &str| {
+            Decimal::from_str(s.trim()).with_context(|| format!("Invalid {} '{}'", what, s)).and_then(|d| positive(d, what))
+        };
+        match kind.trim().to_ascii_lowercase().replace('-', "_").as_str() {
+            "split" => {
+                let ratio = match value.split_once(':') {
+                    Some((new, old)) => decimal(new, "split ratio")? / decimal(old, "split ratio")?,
+                    None => decimal(value, "split ratio")?,
+                };
+                Ok(Self::Split { ratio })
+            }
+            "cash_dividend" | "dividend" => Ok(Self::CashDividend { amount: decimal(value, "dividend")? }),
+            "stock_dividend" => {
+                let ratio = match value.strip_suffix('%') {
+                    Some(pct) => decimal(pct, "stock dividend")? / Decimal::ONE_HUNDRED,
+                    None => decimal(value, "stock dividend")?,
+                };
+                Ok(Self::StockDividend { ratio })
+            }
+            other => anyhow::bail!("Unknown corporate action '{}' (expected split, cash-dividend or stock-dividend)", other),
+        }
+    }
+
+    /// Shares after the action per share before
+    pub fn share_factor(&self) -> Decimal {
+        match self {
+            Self::Split { ratio } => *ratio,
+            Self::CashDividend { .. } => Decimal::ONE,
+            Self::StockDividend { ratio } => Decimal::ONE + ratio,
+        }
+    }
+}
+
+impl fmt::Display for ActionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Split { ratio } if *ratio < Decimal::ONE => write!(f, "reverse split 1:{}", (Decimal::ONE / ratio).normalize()),
+            Self::Split { ratio } => write!(f, "split {}:1", ratio.normalize()),
+            Self::CashDividend { amount } => write!(f, "cash dividend {} per share", amount.normalize()),
+            Self::StockDividend { ratio } => write!(f, "stock dividend {}%", (ratio * Decimal::ONE_HUNDRED).normalize()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorporateAction {
+    pub symbol: String,
+    pub ex_date: NaiveDate,
+    pub kind: ActionKind,
+}
+
+impl CorporateAction {
+    pub fn new(symbol: &str, ex_date: NaiveDate, kind: ActionKind) -> Self {
+        Self { symbol: symbol.trim().to_uppercase(), ex_date, kind }
+    }
+
+    /// Identity for idempotency: at most one action of a kind per symbol
+    /// and ex-date
+    pub fn key(&self) -> String {
+        format!("{}/{}/{}", self.symbol, self.ex_date, self.kind.name())
+    }
+}
+
+impl fmt::Display for CorporateAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} (ex {})", self.symbol, self.kind, self.ex_date)
+    }
+}
+
+/// An applied action, as recorded in the action ledger
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppliedAction {
+    pub action: CorporateAction,
+    pub applied_at: DateTime<Utc>,
+    /// Shares held the day before the ex-date
+    pub eligible_quantity: Decimal,
+    pub quantity_before: Decimal,
+    pub quantity_after: Decimal,
+    pub average_cost_after: Decimal,
+    /// Credited to cash
+    pub cash: Decimal,
+}
+
+/// Shares of `position` held at the end of the day before `ex_date`: its
+/// quantity less what the ledger shows traded since. A position without
+/// recorded trades counts as held throughout
+pub fn eligible_quantity(position: &Position, ledger: &[LedgerEntry], ex_date: NaiveDate) -> Decimal {
+    let traded_since: Decimal = ledger
+        .iter()
+        .map(|entry| &entry.trade)
+        .filter(|trade| trade.symbol.eq_ignore_ascii_case(&position.symbol) && trade.timestamp.date_naive() >= ex_date)
+        .map(|trade| match trade.side {
+            TradeSide::Buy => trade.quantity,
+            TradeSide::Sell => -trade.quantity,
+        })
+        .sum();
+    (position.quantity - traded_since).max(Decimal::ZERO)
+}
+
+/// Apply `action` to the portfolio's position in its symbol, if any.
+/// Splits and stock dividends add shares at no cost to those eligible;
+/// a cash dividend's credit is returned, to the cent. Fractional shares
+/// are kept (no cash in lieu)
+pub fn apply(portfolio: &mut Portfolio, ledger: &[LedgerEntry], action: &CorporateAction, now: DateTime<Utc>) -> AppliedAction {
+    let mut applied = AppliedAction {
+        action: action.clone(),
+        applied_at: now,
+        eligible_quantity: Decimal::ZERO,
+        quantity_before: Decimal::ZERO,
+        quantity_after: Decimal::ZERO,
+        average_cost_after: Decimal::ZERO,
+        cash: Decimal::ZERO,
+    };
+    let Some(position) = portfolio.positions.get_mut(&action.symbol) else { return applied };
+    let eligible = eligible_quantity(position, ledger, action.ex_date);
+    applied.eligible_quantity = eligible;
+    applied.quantity_before = position.quantity;
+
+    match action.kind {
+        ActionKind::CashDividend { amount } => applied.cash = (eligible * amount).round_dp(2),
+        kind if eligible > Decimal::ZERO => {
+            let factor = kind.share_factor();
+            let cost = position.average_cost * position.quantity;
+            // A mark from before the ex-date is in old shares
+            let marked_before = eligible == position.quantity;
+            position.quantity += eligible * (factor - Decimal::ONE);
+            position.average_cost = cost / position.quantity;
+            if marked_before {
+                position.current_price /= factor;
+                if let Some(mark) = position.risk.as_mut().and_then(|rule| rule.high_water_mark.as_mut()) {
+                    *mark /= factor;
+                }
+            }
+        }
+        _ => {}
+    }
+    applied.quantity_after = position.quantity;
+    applied.average_cost_after = position.average_cost;
+    applied
+}
+
+/// Apply every action with an ex-date through `today` not applied yet,
+/// in ex-date order
+pub fn apply_due(store: &PortfolioStore, portfolio: &mut Portfolio, today: NaiveDate) -> Result<Vec<AppliedAction>> {
+    let mut due: Vec<CorporateAction> = store.actions()?.into_iter().filter(|a| a.ex_date <= today).collect();
+    due.sort_by(|a, b| (a.ex_date, &a.symbol, a.kind.name()).cmp(&(b.ex_date, &b.symbol, b.kind.name())));
+    let mut applied = Vec::new();
+    for action in due {
+        if let Some(done) = store.apply_action(portfolio, &action, crate::clock::now())? {
+            let credit = if done.cash.is_zero() { String::new() } else { format!(", {} credited", done.cash) };
+            tracing::info!("🏷️  Applied {}: {} → {} shares{}", action, done.quantity_before, done.quantity_after, credit);
+            applied.push(done);
+        }
+    }
+    Ok(applied)
+}
+
+/// Read actions from a CSV with columns `symbol,ex_date,kind,value`
+pub fn read_csv<Rd: Read>(reader: Rd) -> Result<Vec<CorporateAction>> {
+    let mut csv_reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+    let headers = csv_reader.headers()?.clone();
+    if headers.iter().collect::<Vec<_>>() != CSV_COLUMNS {
+        anyhow::bail!("Unexpected corporate action CSV columns: {:?} (expected {:?})", headers, CSV_COLUMNS);
+    }
+    csv_reader
+        .into_records()
+        .enumerate()
+        .map(|(i, record)| {
+            let record = record?;
+            let row = i + 1;
+            let field = |i: usize| record.get(i).unwrap_or_default();
+            let ex_date = NaiveDate::from_str(field(1)).with_context(|| format!("Invalid ex_date on row {}", row))?;
+            let kind = ActionKind::parse(field(2), field(3)).with_context(|| format!("Row {}", row))?;
+            if field(0).is_empty() {
+                anyhow::bail!("Missing symbol on row {}", row);
+            }
+            Ok(CorporateAction::new(field(0), ex_date, kind))
+        })
+        .collect()
+}
+
+/// Back-adjust `candles` for `actions`, so a series spanning an ex-date
+/// has no artificial jump. Prices before a split or stock dividend are
+/// divided by its share factor (volumes multiplied); before a cash
+/// dividend they are scaled by `1 - dividend / close` of the last candle
+/// before the ex-date. Candles of symbols without actions pass unchanged
+pub fn back_adjust(candles: &[Candle], actions: &[CorporateAction]) -> Vec<Candle> {
+    let mut adjusted = candles.to_vec();
+    let mut by_symbol: BTreeMap<String, Vec<&CorporateAction>> = BTreeMap::new();
+    for action in actions {
+        by_symbol.entry(action.symbol.clone()).or_default().push(action);
+    }
+    for (symbol, actions) in by_symbol {
+        let indices: Vec<usize> =
+            (0..adjusted.len()).filter(|i| adjusted[*i].symbol.eq_ignore_ascii_case(&symbol)).collect();
+        for action in actions {
+            let before: Vec<usize> =
+                indices.iter().copied().filter(|i| adjusted[*i].timestamp.date_naive() < action.ex_date).collect();
+            // Judged on the raw close, like the dividend itself
+            let last_close = before.iter().max_by_key(|i| candles[**i].timestamp).map(|i| candles[*i].close);
+            let (price_factor, volume_factor) = match action.kind {
+                ActionKind::CashDividend { amount } => match last_close {
+                    Some(close) if close > amount => (Decimal::ONE - amount / close, Decimal::ONE),
+                    _ => continue,
+                },
+                kind => (Decimal::ONE / kind.share_factor(), kind.share_factor()),
+            };
+            for i in before {
+                let candle = &mut adjusted[i];
+                candle.open *= price_factor;
+                candle.high *= price_factor;
+                candle.low *= price_factor;
+                candle.close *= price_factor;
+                let volume = Decimal::from(candle.volume) * volume_factor;
+                candle.volume = volume.round().try_into().unwrap_or(candle.volume);
+            }
+        }
+    }
+    adjusted
+}
+
+/// Applies due actions to the stored portfolio once a day
+pub struct ActionApplier {
+    store: Arc<PortfolioStore>,
+}
+
+impl ActionApplier {
+    pub fn new(store: Arc<PortfolioStore>) -> Self {
+        Self { store }
+    }
+
+    pub fn apply_due(&self, today: NaiveDate) -> Result<Vec<AppliedAction>> {
+        let mut portfolio = self.store.load()?;
+        apply_due(&self.store, &mut portfolio, today)
+    }
+
+    /// Apply due actions daily on `scheduler`
+    pub fn schedule(self: Arc<Self>, scheduler: &mut Scheduler, config: &CorporateActionSettings) -> Result<()> {
+        let spec = DailySpec { at: config.at, jitter: std::time::Duration::ZERO, timeout: std::time::Duration::from_secs(60) };
+        scheduler.register_daily(TASK_NAME, spec, move || {
+            let applier = self.clone();
+            async move { applier.apply_due(chrono::Local::now().date_naive()).map(|_| ()) }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quant::portfolio_store::market_trade;
+    use crate::storage::RuntimeMode;
+    use std::path::Path;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::from_str(s).unwrap()
+    }
+
+    fn store() -> PortfolioStore {
+        PortfolioStore::open(Path::new("unused"), RuntimeMode::Ephemeral).unwrap()
+    }
+
+    fn candle(day: &str, close: &str, volume: u64) -> Candle {
+        let close = dec(close);
+        Candle {
+            symbol: "AAPL".to_string(),
+            timestamp: format!("{}T21:00:00Z", day).parse().unwrap(),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume,
+        }
+    }
+
+    #[test]
+    fn test_parse_kinds() {
+        assert_eq!(ActionKind::parse("split", "4:1").unwrap(), ActionKind::Split { ratio: dec("4") });
+        assert_eq!(ActionKind::parse("split", "1:10").unwrap(), ActionKind::Split { ratio: dec("0.1") });
+        assert_eq!(ActionKind::parse("cash-dividend", "0.24").unwrap(), ActionKind::CashDividend { amount: dec("0.24") });
+        assert_eq!(ActionKind::parse("stock_dividend", "5%").unwrap(), ActionKind::StockDividend { ratio: dec("0.05") });
+        assert_eq!(ActionKind::parse("split", "3:2").unwrap().to_string(), "split 1.5:1");
+        assert_eq!(ActionKind::parse("split", "1:10").unwrap().to_string(), "reverse split 1:10");
+        assert!(ActionKind::parse("split", "0:1").is_err());
+        assert!(ActionKind::parse("spinoff", "1").is_err());
+
+        let csv = "symbol,ex_date,kind,value\naapl,2024-06-10,split,4:1\nMSFT, 2024-05-15 ,cash-dividend,0.75\n";
+        let actions = read_csv(csv.as_bytes()).unwrap();
+        assert_eq!(actions[0].key(), "AAPL/2024-06-10/split");
+        assert_eq!(actions[1].kind, ActionKind::CashDividend { amount: dec("0.75") });
+        assert!(read_csv("symbol,kind\nAAPL,split\n".as_bytes()).is_err());
+        let err = read_csv("symbol,ex_date,kind,value\nAAPL,2024-06-10,split,-2\n".as_bytes()).unwrap_err();
+        assert!(format!("{:#}", err).contains("Row 1"));
+    }
+
+    #[test]
+    fn test_split_adjusts_position_and_history_consistently() {
+        let store = store();
+        let mut portfolio = store.load().unwrap();
+        let mut buy = market_trade("AAPL", TradeSide::Buy, dec("100"), dec("400"));
+        buy.timestamp = "2024-06-03T15:00:00Z".parse().unwrap();
+        store.execute(&mut portfolio, buy, None).unwrap();
+        portfolio.update_price("AAPL", dec("420"));
+        let split = CorporateAction::new("aapl", date("2024-06-10"), ActionKind::parse("split", "4:1").unwrap());
+        assert!(store.put_action(&split).unwrap());
+
+        // Not due before the ex-date
+        assert!(apply_due(&store, &mut portfolio, date("2024-06-09")).unwrap().is_empty());
+        let applied = apply_due(&store, &mut portfolio, date("2024-06-10")).unwrap();
+        assert_eq!(applied.len(), 1);
+        let position = &portfolio.positions["AAPL"];
+        assert_eq!((position.quantity, position.average_cost, position.current_price), (dec("400"), dec("100"), dec("105")));
+        assert_eq!(store.load().unwrap().positions["AAPL"].quantity, dec("400"));
+        assert_eq!(portfolio.total_cost(), dec("40000"));
+
+        // The series lines up across the ex-date, and the position is worth
+        // the same at the adjusted price as it was at the raw one
+        let raw = vec![candle("2024-06-06", "420", 1_000), candle("2024-06-07", "424", 2_000), candle("2024-06-10", "106", 8_000)];
+        let adjusted = back_adjust(&raw, &store.actions().unwrap());
+        let closes: Vec<Decimal> = adjusted.iter().map(|c| c.close).collect();
+        assert_eq!(closes, vec![dec("105"), dec("106"), dec("106")]);
+        assert_eq!(adjusted.iter().map(|c| c.volume).collect::<Vec<_>>(), vec![4_000, 8_000, 8_000]);
+        assert_eq!(dec("100") * raw[1].close, position.quantity * adjusted[1].close);
+        assert_eq!(raw[0].close, dec("420"), "raw candles are kept");
+        assert_eq!(store.applied_actions().unwrap()[0].action, split);
+    }
+
+    #[test]
+    fn test_dividend_credits_cash_for_shares_held_before_ex_date() {
+        let store = store();
+        store.seed_cash(dec("1000"), date("2024-05-01")).unwrap();
+        let mut portfolio = store.load().unwrap();
+        let mut before = market_trade("MSFT", TradeSide::Buy, dec("10"), dec("50"));
+        before.timestamp = "2024-05-10T15:00:00Z".parse().unwrap();
+        store.execute(&mut portfolio, before, None).unwrap();
+        // Bought on the ex-date: no dividend on these
+        let mut after = market_trade("MSFT", TradeSide::Buy, dec("5"), dec("50"));
+        after.timestamp = "2024-05-15T15:00:00Z".parse().unwrap();
+        store.execute(&mut portfolio, after, None).unwrap();
+        assert_eq!(store.cash().unwrap(), dec("250"));
+
+        let dividend = CorporateAction::new("MSFT", date("2024-05-15"), ActionKind::CashDividend { amount: dec("0.755") });
+        store.put_action(&dividend).unwrap();
+        let applied = apply_due(&store, &mut portfolio, date("2024-05-20")).unwrap();
+        assert_eq!((applied[0].eligible_quantity, applied[0].cash), (dec("10"), dec("7.55")));
+        assert_eq!(store.cash().unwrap(), dec("257.55"));
+        assert_eq!(portfolio.positions["MSFT"].quantity, dec("15"));
+        // Dividends are income, not external flows
+        assert_eq!(store.cash_flows().unwrap().len(), 1);
+
+        let candles = vec![candle("2024-05-14", "50", 100), candle("2024-05-15", "49.5", 100)];
+        let adjusted = back_adjust(&candles, &[CorporateAction { symbol: "AAPL".to_string(), ..dividend }]);
+        assert_eq!(adjusted[0].close, dec("49.245"));
+        assert_eq!(adjusted[1].close, dec("49.5"));
+    }
+
+    #[test]
+    fn test_reapplying_is_idempotent() {
+        let store = store();
+        let mut portfolio = store.load().unwrap();
+        let mut buy = market_trade("AAPL", TradeSide::Buy, dec("10"), dec("200"));
+        buy.timestamp = "2020-08-03T15:00:00Z".parse().unwrap();
+        store.execute(&mut portfolio, buy, None).unwrap();
+        let split = CorporateAction::new("AAPL", date("2020-08-31"), ActionKind::Split { ratio: dec("4") });
+        let dividend = CorporateAction::new("AAPL", date("2020-08-31"), ActionKind::CashDividend { amount: dec("1") });
+        assert!(store.put_action(&split).unwrap());
+        assert!(!store.put_action(&split).unwrap());
+        store.put_action(&dividend).unwrap();
+
+        assert_eq!(apply_due(&store, &mut portfolio, date("2020-09-01")).unwrap().len(), 2);
+        let once = (store.load().unwrap().positions["AAPL"].quantity, store.cash().unwrap());
+        assert_eq!(once, (dec("40"), dec("-1990")));
+        for _ in 0..2 {
+            assert!(apply_due(&store, &mut portfolio, date("2020-09-02")).unwrap().is_empty());
+            assert!(store.apply_action(&mut portfolio, &split, crate::clock::now()).unwrap().is_none());
+        }
+        assert_eq!((store.load().unwrap().positions["AAPL"].quantity, store.cash().unwrap()), once);
+        assert_eq!(store.applied_actions().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_stock_dividend_and_reverse_split() {
+        let mut portfolio = Portfolio::new("p".to_string(), "P".to_string());
+        portfolio.add_position("XYZ".to_string(), dec("200"), dec("10"));
+        let now = crate::clock::now();
+        let stock = CorporateAction::new("XYZ", date("2024-01-02"), ActionKind::StockDividend { ratio: dec("0.05") });
+        let applied = apply(&mut portfolio, &[], &stock, now);
+        assert_eq!(applied.quantity_after, dec("210"));
+        let reverse = CorporateAction::new("XYZ", date("2024-02-01"), ActionKind::Split { ratio: dec("0.1") });
+        apply(&mut portfolio, &[], &reverse, now);
+        let position = &portfolio.positions["XYZ"];
+        assert_eq!(position.quantity, dec("21"));
+        assert_eq!((position.average_cost * position.quantity).round_dp(6), dec("2000"));
+        assert!(apply(&mut portfolio, &[], &CorporateAction::new("NONE", date("2024-01-02"), stock.kind), now).quantity_after.is_zero());
+    }
+}
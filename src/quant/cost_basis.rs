@@ -0,0 +1,533 @@
+//! Cost Basis
+//! Open lots per symbol, rebuilt from the trade ledger and the applied
+//! corporate actions, and the realized gain of every sell under a
+//! cost-basis method. Each buy opens a lot named by its trade id; splits
+//! and stock dividends rescale the lots opened before their ex-date
+//!
+//! The method only changes which lots a sell closes and at what cost:
+//! quantities and cash are those of the portfolio under every method.
+//! Average cost closes lots oldest first at the position's average cost,
+//! as the portfolio does. Specific-lot sells close the lots recorded on
+//! the sell, in order; sells recorded without lots (risk rules,
+//! rebalances, margin calls, or before the method was chosen) close
+//! oldest first. Gains exclude commissions
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use super::corporate_actions::AppliedAction;
+use super::portfolio::Portfolio;
+use super::portfolio_store::LedgerEntry;
+use super::{Trade, TradeSide};
+
+/// How a sell picks the lots it closes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CostBasisMethod {
+    #[default]
+    AverageCost,
+    Fifo,
+    Lifo,
+    SpecificLot,
+}
+
+impl FromStr for CostBasisMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_ascii_lowercase().replace('-', "_").as_str() {
+            "average_cost" | "average" => Ok(Self::AverageCost),
+            "fifo" => Ok(Self::Fifo),
+            "lifo" => Ok(Self::Lifo),
+            "specific_lot" | "specific" => Ok(Self::SpecificLot),
+            _ => anyhow::bail!("Unknown cost-basis method '{}': use average-cost, fifo, lifo or specific-lot", s),
+        }
+    }
+}
+
+impl fmt::Display for CostBasisMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::AverageCost => "average_cost",
+            Self::Fifo => "fifo",
+            Self::Lifo => "lifo",
+            Self::SpecificLot => "specific_lot",
+        })
+    }
+}
+
+/// `[portfolio.cost_basis]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CostBasisSettings {
+    pub method: CostBasisMethod,
+    /// A lot held longer than this many days closes as a long-term gain
+    pub long_term_days: u32,
+}
+
+impl Default for CostBasisSettings {
+    fn default() -> Self {
+        Self { method: CostBasisMethod::default(), long_term_days: 365 }
+    }
+}
+
+/// Shares bought in one trade and not yet sold
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Lot {
+    /// The buy's trade id
+    pub id: String,
+    pub symbol: String,
+    pub quantity: Decimal,
+    /// Cost per share: the buy price, adjusted for splits (and, under
+    /// average cost, the position's average after a sell)
+    pub price: Decimal,
+    pub opened: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldingTerm {
+    Short,
+    Long,
+}
+
+impl fmt::Display for HoldingTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Short => "short",
+            Self::Long => "long",
+        })
+    }
+}
+
+/// The part of a sell that closed one lot
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RealizedGain {
+    pub symbol: String,
+    pub lot_id: String,
+    pub sell_id: String,
+    pub quantity: Decimal,
+    pub opened: DateTime<Utc>,
+    pub closed: DateTime<Utc>,
+    pub cost: Decimal,
+    pub proceeds: Decimal,
+    pub gain: Decimal,
+    pub term: HoldingTerm,
+}
+
+/// Why a sell can't close the lots it names
+#[derive(Debug, Clone, PartialEq)]
+pub enum LotError {
+    /// No open lot of the symbol has this id
+    UnknownLot { symbol: String, lot_id: String },
+    /// The named lots hold fewer shares than the sell
+    Uncovered { symbol: String, quantity: Decimal, covered: Decimal },
+    /// A specific-lot sell named no lots
+    NoLotsSelected { symbol: String },
+    /// More shares sold than the lots hold
+    Oversold { symbol: String, quantity: Decimal, held: Decimal },
+}
+
+impl fmt::Display for LotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownLot { symbol, lot_id } => write!(f, "{} has no open lot '{}'", symbol, lot_id),
+            Self::Uncovered { symbol, quantity, covered } => {
+                write!(f, "the selected {} lots hold {} shares, short of the {} sold", symbol, covered, quantity)
+            }
+            Self::NoLotsSelected { symbol } => {
+                write!(f, "specific-lot cost basis: name the {} lots to sell with --lots", symbol)
+            }
+            Self::Oversold { symbol, quantity, held } => {
+                write!(f, "cannot close {} {}: its lots hold {}", quantity, symbol, held)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LotError {}
+
+/// Open lots and realized gains from replaying the ledger
+#[derive(Debug, Clone)]
+pub struct LotBook {
+    settings: CostBasisSettings,
+    lots: BTreeMap<String, Vec<Lot>>,
+    realized: Vec<RealizedGain>,
+}
+
+impl LotBook {
+    /// Replay `ledger` (oldest first) with `actions` (oldest ex-date first)
+    /// applied from their ex-dates
+    pub fn replay(
+        settings: &CostBasisSettings,
+        ledger: &[LedgerEntry],
+        actions: &[AppliedAction],
+    ) -> Result<Self, LotError> {
+        let mut book = Self { settings: settings.clone(), lots: BTreeMap::new(), realized: Vec::new() };
+        let mut actions = actions.iter().peekable();
+        for entry in ledger {
+            let day = entry.trade.timestamp.date_naive();
+            while let Some(applied) = actions.next_if(|a| a.action.ex_date <= day) {
+                book.apply_action(applied);
+            }
+            match entry.trade.side {
+                TradeSide::Buy => book.open(&entry.trade),
+                TradeSide::Sell => book.close(&entry.trade, &entry.lots)?,
+            }
+        }
+        actions.for_each(|applied| book.apply_action(applied));
+        Ok(book)
+    }
+
+    pub fn method(&self) -> CostBasisMethod {
+        self.settings.method
+    }
+
+    /// Open lots of every symbol, by symbol then oldest first
+    pub fn lots(&self) -> impl Iterator<Item = &Lot> {
+        self.lots.values().flatten()
+    }
+
+    /// Open lots of `symbol`, oldest first
+    pub fn lots_of(&self, symbol: &str) -> &[Lot] {
+        self.lots.get(&symbol.to_uppercase()).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Every closed lot portion, in sell order
+    pub fn realized(&self) -> &[RealizedGain] {
+        &self.realized
+    }
+
+    /// Check that `lot_ids` name open lots of `symbol` covering `quantity`
+    pub fn check_selection(&self, symbol: &str, quantity: Decimal, lot_ids: &[String]) -> Result<(), LotError> {
+        self.selection(&symbol.to_uppercase(), quantity, lot_ids).map(|_| ())
+    }
+
+    /// Symbols whose open lots don't add up to the portfolio's position
+    pub fn unreconciled(&self, portfolio: &Portfolio) -> Vec<String> {
+        let mut symbols: Vec<String> = self.lots.keys().chain(portfolio.positions.keys()).cloned().collect();
+        symbols.sort();
+        symbols.dedup();
+        symbols.retain(|symbol| {
+            let held: Decimal = self.lots_of(symbol).iter().map(|lot| lot.quantity).sum();
+            let position = portfolio.positions.get(symbol).map_or(Decimal::ZERO, |p| p.quantity);
+            held != position
+        });
+        symbols
+    }
+
+    fn apply_action(&mut self, applied: &AppliedAction) {
+        let factor = applied.action.kind.share_factor();
+        if factor == Decimal::ONE {
+            return;
+        }
+        let Some(lots) = self.lots.get_mut(&applied.action.symbol) else { return };
+        for lot in lots.iter_mut().filter(|lot| lot.opened.date_naive() < applied.action.ex_date) {
+            lot.quantity *= factor;
+            lot.price /= factor;
+        }
+    }
+
+    fn open(&mut self, trade: &Trade) {
+        self.lots.entry(trade.symbol.clone()).or_default().push(Lot {
+            id: trade.id.clone(),
+            symbol: trade.symbol.clone(),
+            quantity: trade.quantity,
+            price: trade.price,
+            opened: trade.timestamp,
+        });
+    }
+
+    fn check_held(&self, symbol: &str, quantity: Decimal) -> Result<(), LotError> {
+        let held: Decimal = self.lots_of(symbol).iter().map(|lot| lot.quantity).sum();
+        match quantity > held {
+            true => Err(LotError::Oversold { symbol: symbol.to_string(), quantity, held }),
+            false => Ok(()),
+        }
+    }
+
+    /// Indices of the named lots, in closing order, once they cover `quantity`
+    fn selection(&self, symbol: &str, quantity: Decimal, lot_ids: &[String]) -> Result<Vec<usize>, LotError> {
+        self.check_held(symbol, quantity)?;
+        let open = self.lots_of(symbol);
+        let mut picks = Vec::new();
+        for id in lot_ids {
+            let index = open.iter().position(|lot| &lot.id == id).ok_or_else(|| LotError::UnknownLot {
+                symbol: symbol.to_string(),
+                lot_id: id.clone(),
+            })?;
+            if !picks.contains(&index) {
+                picks.push(index);
+            }
+        }
+        let covered: Decimal = picks.iter().map(|&i| open[i].quantity).sum();
+        if covered < quantity {
+            return Err(LotError::Uncovered { symbol: symbol.to_string(), quantity, covered });
+        }
+        Ok(picks)
+    }
+
+    fn close(&mut self, trade: &Trade, lot_ids: &[String]) -> Result<(), LotError> {
+        let symbol = trade.symbol.as_str();
+        let count = self.lots_of(symbol).len();
+        let picks: Vec<usize> = match self.settings.method {
+            CostBasisMethod::SpecificLot if !lot_ids.is_empty() => self.selection(symbol, trade.quantity, lot_ids)?,
+            CostBasisMethod::Lifo => {
+                self.check_held(symbol, trade.quantity)?;
+                (0..count).rev().collect()
+            }
+            _ => {
+                self.check_held(symbol, trade.quantity)?;
+                (0..count).collect()
+            }
+        };
+        let average_cost = match self.settings.method {
+            CostBasisMethod::AverageCost => Some(average_cost(self.lots_of(symbol))),
+            _ => None,
+        };
+        let long_term_days = i64::from(self.settings.long_term_days);
+        let Some(lots) = self.lots.get_mut(symbol) else { return Ok(()) };
+
+        let mut remaining = trade.quantity;
+        for index in picks {
+            if remaining.is_zero() {
+                break;
+            }
+            let lot = &mut lots[index];
+            let quantity = remaining.min(lot.quantity);
+            lot.quantity -= quantity;
+            remaining -= quantity;
+            let cost = average_cost.unwrap_or(lot.price) * quantity;
+            let proceeds = trade.price * quantity;
+            let held_days = (trade.timestamp.date_naive() - lot.opened.date_naive()).num_days();
+            self.realized.push(RealizedGain {
+                symbol: symbol.to_string(),
+                lot_id: lot.id.clone(),
+                sell_id: trade.id.clone(),
+                quantity,
+                opened: lot.opened,
+                closed: trade.timestamp,
+                cost,
+                proceeds,
+                gain: proceeds - cost,
+                term: if held_days > long_term_days { HoldingTerm::Long } else { HoldingTerm::Short },
+            });
+        }
+        lots.retain(|lot| !lot.quantity.is_zero());
+        if let Some(average) = average_cost {
+            lots.iter_mut().for_each(|lot| lot.price = average);
+        }
+        if lots.is_empty() {
+            self.lots.remove(symbol);
+        }
+        Ok(())
+    }
+}
+
+fn average_cost(lots: &[Lot]) -> Decimal {
+    let quantity: Decimal = lots.iter().map(|lot| lot.quantity).sum();
+    match quantity.is_zero() {
+        true => Decimal::ZERO,
+        false => lots.iter().map(|lot| lot.quantity * lot.price).sum::<Decimal>() / quantity,
+    }
+}
+
+/// Realized proceeds, cost and gain of one holding period
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TermTotals {
+    pub quantity: Decimal,
+    pub proceeds: Decimal,
+    pub cost: Decimal,
+    pub gain: Decimal,
+}
+
+/// Realized gains grouped by holding period
+#[derive(Debug, Clone, Serialize)]
+pub struct GainsReport {
+    pub method: CostBasisMethod,
+    pub long_term_days: u32,
+    pub short: TermTotals,
+    pub long: TermTotals,
+    pub gains: Vec<RealizedGain>,
+}
+
+impl GainsReport {
+    pub fn new(book: &LotBook) -> Self {
+        let mut report = Self {
+            method: book.settings.method,
+            long_term_days: book.settings.long_term_days,
+            short: TermTotals::default(),
+            long: TermTotals::default(),
+            gains: book.realized.clone(),
+        };
+        for gain in &report.gains {
+            let totals = match gain.term {
+                HoldingTerm::Short => &mut report.short,
+                HoldingTerm::Long => &mut report.long,
+            };
+            totals.quantity += gain.quantity;
+            totals.proceeds += gain.proceeds;
+            totals.cost += gain.cost;
+            totals.gain += gain.gain;
+        }
+        report
+    }
+
+    /// Gains of one holding period, in sell order
+    pub fn of_term(&self, term: HoldingTerm) -> impl Iterator<Item = &RealizedGain> {
+        self.gains.iter().filter(move |gain| gain.term == term)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quant::corporate_actions::{ActionKind, CorporateAction};
+    use crate::quant::portfolio_store::{market_trade, PortfolioStore};
+    use crate::storage::RuntimeMode;
+    use chrono::NaiveDate;
+    use std::path::Path;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn trade(id: &str, side: TradeSide, quantity: &str, price: &str, day: &str) -> Trade {
+        let mut trade = market_trade("AAPL", side, dec(quantity), dec(price));
+        trade.id = id.to_string();
+        trade.timestamp = format!("{}T15:00:00Z", day).parse().unwrap();
+        trade
+    }
+
+    fn entry(trade: Trade, lots: &[&str]) -> LedgerEntry {
+        LedgerEntry {
+            trade,
+            triggered_by: None,
+            commission: Decimal::ZERO,
+            lots: lots.iter().map(|id| id.to_string()).collect(),
+        }
+    }
+
+    fn settings(method: CostBasisMethod) -> CostBasisSettings {
+        CostBasisSettings { method, ..CostBasisSettings::default() }
+    }
+
+    /// Buy 100 @ 10 and 100 @ 20, sell 150 @ 30:
+    /// FIFO closes 100 @ 10 and 50 @ 20, gain 2000 + 500 = 2500, leaving 50 @ 20;
+    /// LIFO closes 100 @ 20 and 50 @ 10, gain 1000 + 1000 = 2000, leaving 50 @ 10;
+    /// average cost charges 15 a share, gain 2250, leaving 50 @ 15
+    fn sequence() -> Vec<LedgerEntry> {
+        vec![
+            entry(trade("b1", TradeSide::Buy, "100", "10", "2024-01-02"), &[]),
+            entry(trade("b2", TradeSide::Buy, "100", "20", "2024-06-03"), &[]),
+            entry(trade("s1", TradeSide::Sell, "150", "30", "2024-09-02"), &[]),
+        ]
+    }
+
+    fn total_gain(book: &LotBook) -> Decimal {
+        book.realized().iter().map(|g| g.gain).sum()
+    }
+
+    #[test]
+    fn test_fifo_and_lifo_gains() {
+        let fifo = LotBook::replay(&settings(CostBasisMethod::Fifo), &sequence(), &[]).unwrap();
+        assert_eq!(total_gain(&fifo), dec("2500"));
+        assert_eq!(fifo.lots_of("AAPL").len(), 1);
+        assert_eq!((fifo.lots_of("AAPL")[0].id.as_str(), fifo.lots_of("AAPL")[0].quantity), ("b2", dec("50")));
+
+        let lifo = LotBook::replay(&settings(CostBasisMethod::Lifo), &sequence(), &[]).unwrap();
+        assert_eq!(total_gain(&lifo), dec("2000"));
+        assert_eq!(lifo.realized().iter().map(|g| g.lot_id.as_str()).collect::<Vec<_>>(), ["b2", "b1"]);
+        assert_eq!((lifo.lots_of("AAPL")[0].id.as_str(), lifo.lots_of("AAPL")[0].price), ("b1", dec("10")));
+
+        let average = LotBook::replay(&settings(CostBasisMethod::AverageCost), &sequence(), &[]).unwrap();
+        assert_eq!(total_gain(&average), dec("2250"));
+        assert_eq!(average.lots_of("AAPL")[0].price, dec("15"));
+    }
+
+    #[test]
+    fn test_specific_lot_selection() {
+        let mut ledger = sequence();
+        ledger[2].lots = vec!["b2".to_string(), "b1".to_string()];
+        let book = LotBook::replay(&settings(CostBasisMethod::SpecificLot), &ledger, &[]).unwrap();
+        assert_eq!(total_gain(&book), dec("2000"));
+        // Other methods ignore the recorded lots
+        let fifo = LotBook::replay(&settings(CostBasisMethod::Fifo), &ledger, &[]).unwrap();
+        assert_eq!(total_gain(&fifo), dec("2500"));
+
+        let open = LotBook::replay(&settings(CostBasisMethod::SpecificLot), &ledger[..2], &[]).unwrap();
+        assert_eq!(
+            open.check_selection("aapl", dec("150"), &["b2".to_string()]),
+            Err(LotError::Uncovered { symbol: "AAPL".to_string(), quantity: dec("150"), covered: dec("100") })
+        );
+        // Naming a lot twice doesn't count it twice
+        assert!(matches!(
+            open.check_selection("AAPL", dec("150"), &["b2".to_string(), "b2".to_string()]),
+            Err(LotError::Uncovered { .. })
+        ));
+        assert_eq!(
+            open.check_selection("AAPL", dec("10"), &["nope".to_string()]),
+            Err(LotError::UnknownLot { symbol: "AAPL".to_string(), lot_id: "nope".to_string() })
+        );
+        assert!(matches!(open.check_selection("AAPL", dec("201"), &[]), Err(LotError::Oversold { .. })));
+
+        ledger[2].lots = vec!["b1".to_string()];
+        let err = LotBook::replay(&settings(CostBasisMethod::SpecificLot), &ledger, &[]).unwrap_err();
+        assert!(matches!(err, LotError::Uncovered { covered, .. } if covered == dec("100")));
+    }
+
+    #[test]
+    fn test_holding_period_grouping() {
+        let ledger = vec![
+            entry(trade("b1", TradeSide::Buy, "10", "100", "2023-03-01"), &[]),
+            entry(trade("b2", TradeSide::Buy, "10", "120", "2024-01-10"), &[]),
+            // b1 held 366 days: long; b2 held 51: short
+            entry(trade("s1", TradeSide::Sell, "20", "130", "2024-03-01"), &[]),
+        ];
+        let book = LotBook::replay(&settings(CostBasisMethod::Fifo), &ledger, &[]).unwrap();
+        let report = GainsReport::new(&book);
+        assert_eq!(report.long, TermTotals { quantity: dec("10"), proceeds: dec("1300"), cost: dec("1000"), gain: dec("300") });
+        assert_eq!(report.short, TermTotals { quantity: dec("10"), proceeds: dec("1300"), cost: dec("1200"), gain: dec("100") });
+        assert_eq!(report.of_term(HoldingTerm::Long).map(|g| g.lot_id.as_str()).collect::<Vec<_>>(), ["b1"]);
+
+        let longer = CostBasisSettings { method: CostBasisMethod::Fifo, long_term_days: 366 };
+        let report = GainsReport::new(&LotBook::replay(&longer, &ledger, &[]).unwrap());
+        assert_eq!(report.long, TermTotals::default());
+        assert_eq!(report.short.gain, dec("400"));
+    }
+
+    #[test]
+    fn test_reconciles_with_portfolio_under_every_method() {
+        let store = PortfolioStore::open(Path::new("unused"), RuntimeMode::Ephemeral).unwrap();
+        let mut portfolio = store.load().unwrap();
+        let mut trades = sequence().into_iter().map(|e| e.trade);
+        store.execute(&mut portfolio, trades.next().unwrap(), None).unwrap();
+        let split = CorporateAction::new("AAPL", NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(), ActionKind::Split { ratio: dec("2") });
+        store.apply_action(&mut portfolio, &split, Utc::now()).unwrap();
+        for trade in trades {
+            store.execute(&mut portfolio, trade, None).unwrap();
+        }
+        let position = &portfolio.positions["AAPL"];
+        // 200 post-split @ 5 and 100 @ 20, less 150 sold
+        assert_eq!(position.quantity, dec("150"));
+
+        let (ledger, actions) = (store.ledger().unwrap(), store.applied_actions().unwrap());
+        let mut gains = Vec::new();
+        for method in [CostBasisMethod::AverageCost, CostBasisMethod::Fifo, CostBasisMethod::Lifo, CostBasisMethod::SpecificLot] {
+            let book = LotBook::replay(&settings(method), &ledger, &actions).unwrap();
+            assert!(book.unreconciled(&portfolio).is_empty(), "{}", method);
+            let open_cost: Decimal = book.lots().map(|lot| lot.quantity * lot.price).sum();
+            let cost: Decimal = book.realized().iter().map(|g| g.cost).sum();
+            // Every method spreads the same 3000 of purchases over sold and held shares
+            assert_eq!((open_cost + cost).round_dp(8), dec("3000"), "{}", method);
+            gains.push(total_gain(&book));
+        }
+        assert_eq!(gains, [dec("3000"), dec("3750"), dec("2250"), dec("3750")]);
+        let average = LotBook::replay(&settings(CostBasisMethod::AverageCost), &ledger, &actions).unwrap();
+        assert_eq!(average.lots_of("AAPL")[0].price, position.average_cost);
+    }
+}
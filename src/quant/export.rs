@@ -0,0 +1,819 @@
+//! Tabular Export
+//!
+//! CSV and Parquet export for positions, trades, realized gains, risk
+//! metrics, quote history and candles. Column names are a public contract with downstream notebooks and
+//! spreadsheets: rename one and the schema tests below fail.
+//!
+//! Decimals are written as strings in CSV (lossless) and as
+//! `Decimal128(38, 8)` in Parquet. Timestamps are UTC ISO-8601 in CSV and
+//! microsecond UTC timestamps in Parquet.
+
+use anyhow::{Context, Result};
+use arrow::array::{ArrayRef, Decimal128Array, Float64Array, Int64Array, StringArray, TimestampMicrosecondArray};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, SecondsFormat, Utc};
+use parquet::arrow::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+use rust_decimal::Decimal;
+use std::borrow::Borrow;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use super::attribution::MarketContext;
+use super::cost_basis::RealizedGain;
+use super::portfolio::Position;
+use super::{Candle, Quote, Trade, TradeSide};
+
+/// Rows buffered per Parquet record batch
+const PARQUET_BATCH_ROWS: usize = 8192;
+
+/// Rows per Parquet row group (bounds writer memory for large exports)
+const PARQUET_ROW_GROUP_ROWS: usize = 65536;
+
+/// Scale used for Decimal columns in Parquet
+const PARQUET_DECIMAL_SCALE: i8 = 8;
+
+/// Output format, chosen from the file extension or with `--export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()) {
+            Some(ext) if ext == "csv" => Ok(Self::Csv),
+            Some(ext) if ext == "parquet" => Ok(Self::Parquet),
+            _ => anyhow::bail!("Unsupported export format for {} (use .csv or .parquet)", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Text,
+    Decimal,
+    Integer,
+    Float,
+    Timestamp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    pub name: &'static str,
+    pub column_type: ColumnType,
+}
+
+const fn col(name: &'static str, column_type: ColumnType) -> Column {
+    Column { name, column_type }
+}
+
+/// A single exported value
+#[derive(Debug, Clone)]
+pub enum Cell {
+    Text(String),
+    Decimal(Decimal),
+    Integer(i64),
+    Float(f64),
+    Timestamp(DateTime<Utc>),
+}
+
+impl Cell {
+    fn to_csv_field(&self) -> String {
+        match self {
+            Cell::Text(s) => s.clone(),
+            Cell::Decimal(d) => d.to_string(),
+            Cell::Integer(i) => i.to_string(),
+            Cell::Float(f) => f.to_string(),
+            Cell::Timestamp(ts) => ts.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        }
+    }
+}
+
+/// A dataset row with a stable column schema
+pub trait ExportRecord {
+    /// Column names and types, in output order
+    fn schema() -> &'static [Column];
+
+    /// Values in the same order as `schema()`
+    fn cells(&self) -> Vec<Cell>;
+}
+
+/// Positions: symbol, quantity, average_cost, current_price, market_value, unrealized_pnl
+impl ExportRecord for Position {
+    fn schema() -> &'static [Column] {
+        const SCHEMA: &[Column] = &[
+            col("symbol", ColumnType::Text),
+            col("quantity", ColumnType::Decimal),
+            col("average_cost", ColumnType::Decimal),
+            col("current_price", ColumnType::Decimal),
+            col("market_value", ColumnType::Decimal),
+            col("unrealized_pnl", ColumnType::Decimal),
+        ];
+        SCHEMA
+    }
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Text(self.symbol.clone()),
+            Cell::Decimal(self.quantity),
+            Cell::Decimal(self.average_cost),
+            Cell::Decimal(self.current_price),
+            Cell::Decimal(self.quantity * self.current_price),
+            Cell::Decimal((self.current_price - self.average_cost) * self.quantity),
+        ]
+    }
+}
+
+/// Trades: trade_id, symbol, side, quantity, price, timestamp
+impl ExportRecord for Trade {
+    fn schema() -> &'static [Column] {
+        const SCHEMA: &[Column] = &[
+            col("trade_id", ColumnType::Text),
+            col("symbol", ColumnType::Text),
+            col("side", ColumnType::Text),
+            col("quantity", ColumnType::Decimal),
+            col("price", ColumnType::Decimal),
+            col("timestamp", ColumnType::Timestamp),
+        ];
+        SCHEMA
+    }
+
+    fn cells(&self) -> Vec<Cell> {
+        let side = match self.side {
+            TradeSide::Buy => "buy",
+            TradeSide::Sell => "sell",
+        };
+        vec![
+            Cell::Text(self.id.clone()),
+            Cell::Text(self.symbol.clone()),
+            Cell::Text(side.to_string()),
+            Cell::Decimal(self.quantity),
+            Cell::Decimal(self.price),
+            Cell::Timestamp(self.timestamp),
+        ]
+    }
+}
+
+/// Quote history: symbol, bid, ask, last, volume, timestamp
+impl ExportRecord for Quote {
+    fn schema() -> &'static [Column] {
+        const SCHEMA: &[Column] = &[
+            col("symbol", ColumnType::Text),
+            col("bid", ColumnType::Decimal),
+            col("ask", ColumnType::Decimal),
+            col("last", ColumnType::Decimal),
+            col("volume", ColumnType::Integer),
+            col("timestamp", ColumnType::Timestamp),
+        ];
+        SCHEMA
+    }
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Text(self.symbol.clone()),
+            Cell::Decimal(self.bid),
+            Cell::Decimal(self.ask),
+            Cell::Decimal(self.last),
+            Cell::Integer(self.volume as i64),
+            Cell::Timestamp(self.timestamp),
+        ]
+    }
+}
+
+/// Candles: symbol, timestamp, open, high, low, close, volume
+impl ExportRecord for Candle {
+    fn schema() -> &'static [Column] {
+        const SCHEMA: &[Column] = &[
+            col("symbol", ColumnType::Text),
+            col("timestamp", ColumnType::Timestamp),
+            col("open", ColumnType::Decimal),
+            col("high", ColumnType::Decimal),
+            col("low", ColumnType::Decimal),
+            col("close", ColumnType::Decimal),
+            col("volume", ColumnType::Integer),
+        ];
+        SCHEMA
+    }
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Text(self.symbol.clone()),
+            Cell::Timestamp(self.timestamp),
+            Cell::Decimal(self.open),
+            Cell::Decimal(self.high),
+            Cell::Decimal(self.low),
+            Cell::Decimal(self.close),
+            Cell::Integer(self.volume as i64),
+        ]
+    }
+}
+
+/// Market contexts: symbol, timestamp, spot, volatility, rate
+impl ExportRecord for MarketContext {
+    fn schema() -> &'static [Column] {
+        const SCHEMA: &[Column] = &[
+            col("symbol", ColumnType::Text),
+            col("timestamp", ColumnType::Timestamp),
+            col("spot", ColumnType::Float),
+            col("volatility", ColumnType::Float),
+            col("rate", ColumnType::Float),
+        ];
+        SCHEMA
+    }
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Text(self.symbol.clone()),
+            Cell::Timestamp(self.timestamp),
+            Cell::Float(self.spot),
+            Cell::Float(self.volatility),
+            Cell::Float(self.rate),
+        ]
+    }
+}
+
+/// Realized gains: symbol, lot_id, sell_id, quantity, opened, closed, cost, proceeds, gain, term
+impl ExportRecord for RealizedGain {
+    fn schema() -> &'static [Column] {
+        const SCHEMA: &[Column] = &[
+            col("symbol", ColumnType::Text),
+            col("lot_id", ColumnType::Text),
+            col("sell_id", ColumnType::Text),
+            col("quantity", ColumnType::Decimal),
+            col("opened", ColumnType::Timestamp),
+            col("closed", ColumnType::Timestamp),
+            col("cost", ColumnType::Decimal),
+            col("proceeds", ColumnType::Decimal),
+            col("gain", ColumnType::Decimal),
+            col("term", ColumnType::Text),
+        ];
+        SCHEMA
+    }
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Text(self.symbol.clone()),
+            Cell::Text(self.lot_id.clone()),
+            Cell::Text(self.sell_id.clone()),
+            Cell::Decimal(self.quantity),
+            Cell::Timestamp(self.opened),
+            Cell::Timestamp(self.closed),
+            Cell::Decimal(self.cost),
+            Cell::Decimal(self.proceeds),
+            Cell::Decimal(self.gain),
+            Cell::Text(self.term.to_string()),
+        ]
+    }
+}
+
+/// A named risk output (e.g. `var_95`, `sharpe_ratio`)
+#[derive(Debug, Clone)]
+pub struct RiskMetric {
+    pub metric: String,
+    pub value: f64,
+    pub computed_at: DateTime<Utc>,
+}
+
+/// Risk report: metric, value, computed_at
+impl ExportRecord for RiskMetric {
+    fn schema() -> &'static [Column] {
+        const SCHEMA: &[Column] = &[
+            col("metric", ColumnType::Text),
+            col("value", ColumnType::Float),
+            col("computed_at", ColumnType::Timestamp),
+        ];
+        SCHEMA
+    }
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Text(self.metric.clone()),
+            Cell::Float(self.value),
+            Cell::Timestamp(self.computed_at),
+        ]
+    }
+}
+
+/// Export rows to `path`, picking the format from its extension
+/// Rows are consumed lazily so large history exports are streamed
+pub fn export_to_path<R, I>(path: &Path, rows: I) -> Result<usize>
+where
+    R: ExportRecord,
+    I: IntoIterator,
+    I::Item: Borrow<R>,
+{
+    export_as(path, ExportFormat::from_path(path)?, rows)
+}
+
+/// Export rows to `path` as `format`, whatever its extension
+pub fn export_as<R, I>(path: &Path, format: ExportFormat, rows: I) -> Result<usize>
+where
+    R: ExportRecord,
+    I: IntoIterator,
+    I::Item: Borrow<R>,
+{
+    let count = write_rows(RowWriter::create(path, format)?, rows)?;
+    tracing::info!("📤 Exported {} rows to {}", count, path.display());
+    Ok(count)
+}
+
+/// Stream rows as CSV with a header line
+pub fn write_csv<R, W, I>(writer: W, rows: I) -> Result<usize>
+where
+    R: ExportRecord,
+    W: Write + Send,
+    I: IntoIterator,
+    I::Item: Borrow<R>,
+{
+    write_rows(RowWriter::new(ExportFormat::Csv, writer)?, rows)
+}
+
+/// Stream rows as Parquet in fixed-size record batches
+pub fn write_parquet<R, W, I>(writer: W, rows: I) -> Result<usize>
+where
+    R: ExportRecord,
+    W: Write + Send,
+    I: IntoIterator,
+    I::Item: Borrow<R>,
+{
+    write_rows(RowWriter::new(ExportFormat::Parquet, writer)?, rows)
+}
+
+fn write_rows<R, W, I>(mut out: RowWriter<R, W>, rows: I) -> Result<usize>
+where
+    R: ExportRecord,
+    W: Write + Send,
+    I: IntoIterator,
+    I::Item: Borrow<R>,
+{
+    for row in rows {
+        out.write(row.borrow())?;
+    }
+    out.finish()
+}
+
+/// Rows written as they arrive, for sources that push them (a store
+/// visitor) rather than iterate. Holds at most one Parquet record batch
+pub struct RowWriter<R, W: Write + Send> {
+    sink: Sink<W>,
+    count: usize,
+    rows: PhantomData<fn(&R)>,
+}
+
+enum Sink<W: Write + Send> {
+    Csv(csv::Writer<W>),
+    Parquet { writer: ArrowWriter<W>, schema: Arc<Schema>, batch: Vec<Vec<Cell>> },
+}
+
+impl<R: ExportRecord> RowWriter<R, std::io::BufWriter<std::fs::File>> {
+    /// Create (or truncate) `path` and write the header
+    pub fn create(path: &Path, format: ExportFormat) -> Result<Self> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create export file {}", path.display()))?;
+        Self::new(format, std::io::BufWriter::new(file))
+    }
+}
+
+impl<R: ExportRecord, W: Write + Send> RowWriter<R, W> {
+    pub fn new(format: ExportFormat, writer: W) -> Result<Self> {
+        let sink = match format {
+            ExportFormat::Csv => {
+                let mut csv_writer = csv::Writer::from_writer(writer);
+                csv_writer.write_record(R::schema().iter().map(|c| c.name))?;
+                Sink::Csv(csv_writer)
+            }
+            ExportFormat::Parquet => {
+                let schema = Arc::new(arrow_schema(R::schema()));
+                let props = WriterProperties::builder()
+                    .set_max_row_group_size(PARQUET_ROW_GROUP_ROWS)
+                    .build();
+                Sink::Parquet {
+                    writer: ArrowWriter::try_new(writer, schema.clone(), Some(props))?,
+                    schema,
+                    batch: Vec::with_capacity(PARQUET_BATCH_ROWS),
+                }
+            }
+        };
+        Ok(Self { sink, count: 0, rows: PhantomData })
+    }
+
+    pub fn write(&mut self, row: &R) -> Result<()> {
+        match &mut self.sink {
+            Sink::Csv(csv_writer) => csv_writer.write_record(row.cells().iter().map(Cell::to_csv_field))?,
+            Sink::Parquet { writer, schema, batch } => {
+                batch.push(row.cells());
+                if batch.len() == PARQUET_BATCH_ROWS {
+                    writer.write(&record_batch(schema, R::schema(), batch)?)?;
+                    batch.clear();
+                }
+            }
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Write what is buffered and close the output; returns the row count
+    pub fn finish(self) -> Result<usize> {
+        match self.sink {
+            Sink::Csv(mut csv_writer) => csv_writer.flush()?,
+            Sink::Parquet { mut writer, schema, batch } => {
+                if !batch.is_empty() {
+                    writer.write(&record_batch(&schema, R::schema(), &batch)?)?;
+                }
+                writer.close()?;
+            }
+        }
+        Ok(self.count)
+    }
+}
+
+/// Read buffer for CSV imports; rows are decoded lazily, so this (plus
+/// one row) bounds import memory regardless of file size
+pub const CSV_READ_BUFFER: usize = 64 * 1024;
+
+/// Rows decoded one at a time from a CSV produced by `write_csv`
+pub struct CsvRows<Rd: Read, T> {
+    records: csv::StringRecordsIntoIter<Rd>,
+    row: usize,
+    parse: fn(&csv::StringRecord, usize) -> Result<T>,
+}
+
+impl<Rd: Read, T> Iterator for CsvRows<Rd, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        let record = self.records.next()?;
+        self.row += 1;
+        Some(record.map_err(Into::into).and_then(|r| (self.parse)(&r, self.row)))
+    }
+}
+
+/// Open a CSV and check its header against `R::schema()`
+fn csv_rows<R, Rd, T>(reader: Rd, what: &str, parse: fn(&csv::StringRecord, usize) -> Result<T>) -> Result<CsvRows<Rd, T>>
+where
+    R: ExportRecord,
+    Rd: Read,
+{
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .buffer_capacity(CSV_READ_BUFFER)
+        .from_reader(reader);
+
+    let headers = csv_reader.headers()?.clone();
+    let expected: Vec<&str> = R::schema().iter().map(|c| c.name).collect();
+    if headers.iter().collect::<Vec<_>>() != expected {
+        anyhow::bail!("Unexpected {} CSV columns: {:?} (expected {:?})", what, headers, expected);
+    }
+
+    Ok(CsvRows {
+        records: csv_reader.into_records(),
+        row: 0,
+        parse,
+    })
+}
+
+/// Stream trades from a CSV produced by `write_csv`
+pub fn stream_trades_csv<Rd: Read>(reader: Rd) -> Result<CsvRows<Rd, Trade>> {
+    csv_rows::<Trade, _, _>(reader, "trade", parse_trade)
+}
+
+/// Read a trade CSV produced by `write_csv`
+pub fn read_trades_csv<Rd: Read>(reader: Rd) -> Result<Vec<Trade>> {
+    stream_trades_csv(reader)?.collect()
+}
+
+fn parse_trade(record: &csv::StringRecord, row: usize) -> Result<Trade> {
+    let field = |i: usize| record.get(i).unwrap_or_default();
+    let side = match field(2) {
+        "buy" => TradeSide::Buy,
+        "sell" => TradeSide::Sell,
+        other => anyhow::bail!("Invalid trade side '{}' on row {}", other, row),
+    };
+    Ok(Trade {
+        id: field(0).to_string(),
+        symbol: field(1).to_string(),
+        side,
+        quantity: Decimal::from_str(field(3))
+            .with_context(|| format!("Invalid quantity on row {}", row))?,
+        price: Decimal::from_str(field(4))
+            .with_context(|| format!("Invalid price on row {}", row))?,
+        timestamp: DateTime::parse_from_rfc3339(field(5))
+            .with_context(|| format!("Invalid timestamp on row {}", row))?
+            .with_timezone(&Utc),
+    })
+}
+
+/// Stream candles from a CSV produced by `write_csv`
+pub fn stream_candles_csv<Rd: Read>(reader: Rd) -> Result<CsvRows<Rd, Candle>> {
+    csv_rows::<Candle, _, _>(reader, "candle", parse_candle)
+}
+
+fn parse_candle(record: &csv::StringRecord, row: usize) -> Result<Candle> {
+    let field = |i: usize| record.get(i).unwrap_or_default();
+    let decimal = |i: usize| {
+        Decimal::from_str(field(i))
+            .with_context(|| format!("Invalid {} on row {}", Candle::schema()[i].name, row))
+    };
+    Ok(Candle {
+        symbol: field(0).to_string(),
+        timestamp: DateTime::parse_from_rfc3339(field(1))
+            .with_context(|| format!("Invalid timestamp on row {}", row))?
+            .with_timezone(&Utc),
+        open: decimal(2)?,
+        high: decimal(3)?,
+        low: decimal(4)?,
+        close: decimal(5)?,
+        volume: field(6)
+            .parse()
+            .with_context(|| format!("Invalid volume on row {}", row))?,
+    })
+}
+
+/// Stream market contexts from a CSV produced by `write_csv`
+pub fn stream_market_contexts_csv<Rd: Read>(reader: Rd) -> Result<CsvRows<Rd, MarketContext>> {
+    csv_rows::<MarketContext, _, _>(reader, "market context", parse_market_context)
+}
+
+fn parse_market_context(record: &csv::StringRecord, row: usize) -> Result<MarketContext> {
+    let field = |i: usize| record.get(i).unwrap_or_default();
+    let float = |i: usize| {
+        field(i)
+            .parse::<f64>()
+            .with_context(|| format!("Invalid {} on row {}", MarketContext::schema()[i].name, row))
+    };
+    Ok(MarketContext {
+        symbol: field(0).to_string(),
+        timestamp: DateTime::parse_from_rfc3339(field(1))
+            .with_context(|| format!("Invalid timestamp on row {}", row))?
+            .with_timezone(&Utc),
+        spot: float(2)?,
+        volatility: float(3)?,
+        rate: float(4)?,
+    })
+}
+
+fn arrow_schema(columns: &[Column]) -> Schema {
+    let fields: Vec<Field> = columns
+        .iter()
+        .map(|c| {
+            let data_type = match c.column_type {
+                ColumnType::Text => DataType::Utf8,
+                ColumnType::Decimal => DataType::Decimal128(38, PARQUET_DECIMAL_SCALE),
+                ColumnType::Integer => DataType::Int64,
+                ColumnType::Float => DataType::Float64,
+                ColumnType::Timestamp => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            };
+            Field::new(c.name, data_type, false)
+        })
+        .collect();
+    Schema::new(fields)
+}
+
+fn record_batch(schema: &Arc<Schema>, columns: &[Column], rows: &[Vec<Cell>]) -> Result<RecordBatch> {
+    let mut arrays: Vec<ArrayRef> = Vec::with_capacity(columns.len());
+
+    for (i, column) in columns.iter().enumerate() {
+        let cells = rows.iter().map(|row| &row[i]);
+        let array: ArrayRef = match column.column_type {
+            ColumnType::Text => Arc::new(StringArray::from_iter_values(cells.map(|c| match c {
+                Cell::Text(s) => s.clone(),
+                other => other.to_csv_field(),
+            }))),
+            ColumnType::Decimal => Arc::new(
+                Decimal128Array::from_iter_values(cells.map(|c| match c {
+                    Cell::Decimal(d) => {
+                        let mut scaled = *d;
+                        scaled.rescale(PARQUET_DECIMAL_SCALE as u32);
+                        scaled.mantissa()
+                    }
+                    _ => 0,
+                }))
+                .with_precision_and_scale(38, PARQUET_DECIMAL_SCALE)?,
+            ),
+            ColumnType::Integer => Arc::new(Int64Array::from_iter_values(cells.map(|c| match c {
+                Cell::Integer(i) => *i,
+                _ => 0,
+            }))),
+            ColumnType::Float => Arc::new(Float64Array::from_iter_values(cells.map(|c| match c {
+                Cell::Float(f) => *f,
+                _ => f64::NAN,
+            }))),
+            ColumnType::Timestamp => Arc::new(
+                TimestampMicrosecondArray::from_iter_values(cells.map(|c| match c {
+                    Cell::Timestamp(ts) => ts.timestamp_micros(),
+                    _ => 0,
+                }))
+                .with_timezone("UTC"),
+            ),
+        };
+        arrays.push(array);
+    }
+
+    Ok(RecordBatch::try_new(schema.clone(), arrays)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn sample_trades() -> Vec<Trade> {
+        vec![
+            Trade {
+                id: "t-1".to_string(),
+                symbol: "AAPL".to_string(),
+                side: TradeSide::Buy,
+                quantity: dec("10"),
+                price: dec("150.123456789"),
+                timestamp: DateTime::parse_from_rfc3339("2024-03-01T14:30:00.123456Z").unwrap().with_timezone(&Utc),
+            },
+            Trade {
+                id: "t-2".to_string(),
+                symbol: "MSFT, Inc".to_string(),
+                side: TradeSide::Sell,
+                quantity: dec("0.5"),
+                price: dec("410.00"),
+                timestamp: DateTime::parse_from_rfc3339("2024-03-02T09:00:00Z").unwrap().with_timezone(&Utc),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_trade_csv_round_trip() {
+        let trades = sample_trades();
+        let mut buffer = Vec::new();
+        let written = write_csv::<Trade, _, _>(&mut buffer, &trades).unwrap();
+        assert_eq!(written, 2);
+
+        let imported = read_trades_csv(buffer.as_slice()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&imported).unwrap(),
+            serde_json::to_value(&trades).unwrap()
+        );
+
+        // Pushed one at a time, the same bytes
+        let mut pushed = Vec::new();
+        let mut rows = RowWriter::<Trade, _>::new(ExportFormat::Csv, &mut pushed).unwrap();
+        for trade in &trades {
+            rows.write(trade).unwrap();
+        }
+        assert_eq!(rows.finish().unwrap(), 2);
+        assert_eq!(pushed, buffer);
+    }
+
+    #[test]
+    fn test_schema_stability() {
+        let names = |s: &[Column]| s.iter().map(|c| c.name).collect::<Vec<_>>();
+        assert_eq!(
+            names(Position::schema()),
+            ["symbol", "quantity", "average_cost", "current_price", "market_value", "unrealized_pnl"]
+        );
+        assert_eq!(names(Trade::schema()), ["trade_id", "symbol", "side", "quantity", "price", "timestamp"]);
+        assert_eq!(names(Quote::schema()), ["symbol", "bid", "ask", "last", "volume", "timestamp"]);
+        assert_eq!(names(RiskMetric::schema()), ["metric", "value", "computed_at"]);
+        assert_eq!(names(MarketContext::schema()), ["symbol", "timestamp", "spot", "volatility", "rate"]);
+        assert_eq!(
+            names(RealizedGain::schema()),
+            ["symbol", "lot_id", "sell_id", "quantity", "opened", "closed", "cost", "proceeds", "gain", "term"]
+        );
+    }
+
+    #[test]
+    fn test_parquet_export() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("trades.parquet");
+        let written = export_to_path::<Trade, _>(&path, sample_trades()).unwrap();
+        assert_eq!(written, 2);
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[..4], b"PAR1");
+        assert!(ExportFormat::from_path(Path::new("out.xlsx")).is_err());
+
+        // An explicit format wins over the extension
+        let path = dir.path().join("trades.dat");
+        export_as::<Trade, _>(&path, ExportFormat::Csv, sample_trades()).unwrap();
+        assert!(std::fs::read_to_string(&path).unwrap().starts_with("trade_id,symbol,side,"));
+    }
+
+    /// Candle CSV generated on demand and never held in memory as a whole;
+    /// `produced` counts bytes handed to the reader so far
+    struct GeneratedCandles {
+        rows: usize,
+        next_row: usize,
+        pending: Vec<u8>,
+        pos: usize,
+        produced: std::rc::Rc<std::cell::Cell<u64>>,
+    }
+
+    const CANDLE_HEADER: &str = "symbol,timestamp,open,high,low,close,volume\n";
+
+    fn candle_line(i: usize) -> String {
+        let price = format!("{}.{:02}", 100 + i % 50, i % 100);
+        format!("SYM,2024-01-01T00:00:00Z,{p},{p},{p},{p},{}\n", i % 1000, p = price)
+    }
+
+    impl Read for GeneratedCandles {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.pos == self.pending.len() {
+                if self.next_row > self.rows {
+                    return Ok(0);
+                }
+                self.pending = match self.next_row {
+                    0 => CANDLE_HEADER.as_bytes().to_vec(),
+                    row => candle_line(row - 1).into_bytes(),
+                };
+                self.pos = 0;
+                self.next_row += 1;
+            }
+            let n = buf.len().min(self.pending.len() - self.pos);
+            buf[..n].copy_from_slice(&self.pending[self.pos..self.pos + n]);
+            self.pos += n;
+            self.produced.set(self.produced.get() + n as u64);
+            Ok(n)
+        }
+    }
+
+    fn generated(rows: usize) -> (GeneratedCandles, std::rc::Rc<std::cell::Cell<u64>>) {
+        let produced = std::rc::Rc::new(std::cell::Cell::new(0));
+        let source = GeneratedCandles {
+            rows,
+            next_row: 0,
+            pending: Vec::new(),
+            pos: 0,
+            produced: produced.clone(),
+        };
+        (source, produced)
+    }
+
+    #[test]
+    fn test_million_row_import_is_streamed() {
+        const ROWS: usize = 1_000_000;
+        // Read-ahead may never exceed the CSV buffer plus the row being decoded
+        let ceiling = (CSV_READ_BUFFER + 256) as u64;
+
+        let (source, produced) = generated(ROWS);
+        let mut consumed = CANDLE_HEADER.len() as u64;
+        let mut count = 0;
+        for (i, candle) in stream_candles_csv(source).unwrap().enumerate() {
+            let candle = candle.unwrap();
+            consumed += candle_line(i).len() as u64;
+            assert!(
+                produced.get() - consumed <= ceiling,
+                "read {} bytes ahead at row {}",
+                produced.get() - consumed,
+                i
+            );
+            assert_eq!(candle.volume, (i % 1000) as u64);
+            count += 1;
+        }
+        assert_eq!(count, ROWS);
+        assert_eq!(produced.get(), consumed);
+        println!("✅ Streaming import test PASSED! ({} rows)", count);
+    }
+
+    /// Resident set size in KiB (Linux)
+    #[cfg(target_os = "linux")]
+    fn rss_kib() -> u64 {
+        std::fs::read_to_string("/proc/self/status")
+            .unwrap()
+            .lines()
+            .find_map(|l| l.strip_prefix("VmRSS:"))
+            .and_then(|v| v.trim().trim_end_matches("kB").trim().parse().ok())
+            .unwrap()
+    }
+
+    /// `cargo test --release -- --ignored bench_two_gib_import_constant_rss`
+    #[test]
+    #[ignore]
+    #[cfg(target_os = "linux")]
+    fn bench_two_gib_import_constant_rss() {
+        let rows = (2usize << 30) / candle_line(0).len();
+        let (source, _) = generated(rows);
+
+        let start = std::time::Instant::now();
+        let baseline = rss_kib();
+        let mut peak = baseline;
+        for (i, candle) in stream_candles_csv(source).unwrap().enumerate() {
+            candle.unwrap();
+            if i % 1_000_000 == 0 {
+                peak = peak.max(rss_kib());
+            }
+        }
+        println!(
+            "📊 {} rows (~2 GiB) in {:?}, RSS {} KiB -> peak {} KiB",
+            rows,
+            start.elapsed(),
+            baseline,
+            peak
+        );
+        assert!(peak - baseline < 32 * 1024, "RSS grew by {} KiB", peak - baseline);
+    }
+}
@@ -0,0 +1,232 @@
+//! Fixed Income
+//! Fixed-coupon bonds with a whole number of coupon periods left: price,
+//! yield to maturity, duration and convexity. Rates are annual decimals
+//! compounded `periods_per_year` times (0.05 = 5%); yields may be
+//! negative down to -100% a period
+
+use serde::Serialize;
+use std::fmt;
+
+/// Newton's method for the yield stops once the price is this close,
+/// relative to face value
+const YTM_TOLERANCE: f64 = 1e-12;
+const YTM_MAX_ITERATIONS: usize = 100;
+
+/// Why a bond can't be priced or its yield found
+#[derive(Debug, Clone, PartialEq)]
+pub enum BondError {
+    /// `input` is `value`, but must be `expected`
+    Invalid { input: &'static str, value: f64, expected: &'static str },
+    /// Newton's method stopped after `iterations` without matching the
+    /// price; `last` is its final annual yield
+    NoConvergence { iterations: usize, last: f64 },
+}
+
+impl fmt::Display for BondError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid { input, value, expected } => write!(f, "{} must be {} (got {})", input, expected, value),
+            Self::NoConvergence { iterations, last } => {
+                write!(f, "yield to maturity did not converge after {} iterations (last {:.6})", iterations, last)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BondError {}
+
+/// Price and risk measures at one yield
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BondAnalytics {
+    pub price: f64,
+    pub ytm: f64,
+    /// Years
+    pub macaulay_duration: f64,
+    pub modified_duration: f64,
+    pub convexity: f64,
+}
+
+/// The bond's coupon schedule, checked
+struct Schedule {
+    coupon: f64,
+    face: f64,
+    periods: i32,
+    periods_per_year: f64,
+}
+
+impl Schedule {
+    fn new(face: f64, coupon_rate: f64, periods_per_year: u32, years: f64) -> Result<Self, BondError> {
+        let invalid = |input, value, expected| Err(BondError::Invalid { input, value, expected });
+        if !(face.is_finite() && face > 0.0) {
+            return invalid("face", face, "positive");
+        }
+        if !(coupon_rate.is_finite() && coupon_rate >= 0.0) {
+            return invalid("coupon rate", coupon_rate, "zero or more");
+        }
+        if periods_per_year == 0 {
+            return invalid("periods per year", 0.0, "positive");
+        }
+        let periods = years * periods_per_year as f64;
+        if !(years.is_finite() && years > 0.0) || (periods - periods.round()).abs() > 1e-9 {
+            return invalid("years", years, "a positive whole number of coupon periods");
+        }
+        Ok(Self {
+            coupon: face * coupon_rate / periods_per_year as f64,
+            face,
+            periods: periods.round() as i32,
+            periods_per_year: periods_per_year as f64,
+        })
+    }
+
+    /// Discount factor per period
+    fn discount(&self, ytm: f64) -> Result<f64, BondError> {
+        let periodic = ytm / self.periods_per_year;
+        if !(ytm.is_finite() && periodic > -1.0) {
+            return Err(BondError::Invalid { input: "yield", value: ytm, expected: "above -100% a period" });
+        }
+        Ok(1.0 / (1.0 + periodic))
+    }
+
+    /// (period, cash flow) pairs
+    fn flows(&self) -> impl Iterator<Item = (i32, f64)> + '_ {
+        (1..=self.periods).map(|k| (k, if k == self.periods { self.coupon + self.face } else { self.coupon }))
+    }
+
+    fn price(&self, v: f64) -> f64 {
+        self.flows().map(|(k, cf)| cf * v.powi(k)).sum()
+    }
+
+    fn analytics(&self, ytm: f64) -> Result<BondAnalytics, BondError> {
+        let v = self.discount(ytm)?;
+        let price = self.price(v);
+        let weighted = self.flows().map(|(k, cf)| k as f64 * cf * v.powi(k)).sum::<f64>();
+        let curved = self.flows().map(|(k, cf)| (k * (k + 1)) as f64 * cf * v.powi(k + 2)).sum::<f64>();
+        let macaulay_duration = weighted / price / self.periods_per_year;
+        Ok(BondAnalytics {
+            price,
+            ytm,
+            macaulay_duration,
+            modified_duration: macaulay_duration * v,
+            convexity: curved / price / (self.periods_per_year * self.periods_per_year),
+        })
+    }
+}
+
+/// Present value of the remaining coupons and face at `ytm`
+pub fn price_bond(face: f64, coupon_rate: f64, ytm: f64, periods_per_year: u32, years: f64) -> Result<f64, BondError> {
+    let schedule = Schedule::new(face, coupon_rate, periods_per_year, years)?;
+    Ok(schedule.price(schedule.discount(ytm)?))
+}
+
+/// The annual yield at which the bond is worth `price`, by Newton's method
+/// from the coupon rate
+pub fn yield_to_maturity(price: f64, face: f64, coupon_rate: f64, periods_per_year: u32, years: f64) -> Result<f64, BondError> {
+    let schedule = Schedule::new(face, coupon_rate, periods_per_year, years)?;
+    if !(price.is_finite() && price > 0.0) {
+        return Err(BondError::Invalid { input: "price", value: price, expected: "positive" });
+    }
+    let mut ytm = coupon_rate;
+    let mut iterations = 0;
+    while iterations < YTM_MAX_ITERATIONS {
+        iterations += 1;
+        let analytics = schedule.analytics(ytm)?;
+        let error = analytics.price - price;
+        if error.abs() <= YTM_TOLERANCE * face {
+            return Ok(ytm);
+        }
+        // dP/dy = -modified duration * P
+        let mut next = ytm + error / (analytics.modified_duration * analytics.price);
+        if next / schedule.periods_per_year <= -1.0 {
+            // Far above par Newton overshoots past -100%; go halfway there instead
+            next = (ytm - schedule.periods_per_year) / 2.0;
+        }
+        if !next.is_finite() || next / schedule.periods_per_year <= -1.0 {
+            break;
+        }
+        ytm = next;
+    }
+    Err(BondError::NoConvergence { iterations, last: ytm })
+}
+
+/// Cash-flow weighted time to the flows at `ytm`, in years
+pub fn macaulay_duration(face: f64, coupon_rate: f64, ytm: f64, periods_per_year: u32, years: f64) -> Result<f64, BondError> {
+    Ok(bond_analytics(face, coupon_rate, ytm, periods_per_year, years)?.macaulay_duration)
+}
+
+/// Relative price change per unit of yield, `-dP/dy / P`
+pub fn modified_duration(face: f64, coupon_rate: f64, ytm: f64, periods_per_year: u32, years: f64) -> Result<f64, BondError> {
+    Ok(bond_analytics(face, coupon_rate, ytm, periods_per_year, years)?.modified_duration)
+}
+
+/// `d²P/dy² / P`
+pub fn convexity(face: f64, coupon_rate: f64, ytm: f64, periods_per_year: u32, years: f64) -> Result<f64, BondError> {
+    Ok(bond_analytics(face, coupon_rate, ytm, periods_per_year, years)?.convexity)
+}
+
+/// Price, durations and convexity in one pass
+pub fn bond_analytics(face: f64, coupon_rate: f64, ytm: f64, periods_per_year: u32, years: f64) -> Result<BondAnalytics, BondError> {
+    Schedule::new(face, coupon_rate, periods_per_year, years)?.analytics(ytm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_price_and_yield() {
+        // Par bond: coupon equals yield
+        assert!((price_bond(100.0, 0.05, 0.05, 2, 10.0).unwrap() - 100.0).abs() < 1e-9);
+        let price = price_bond(1000.0, 0.06, 0.04, 2, 5.0).unwrap();
+        assert!((price - 1089.8259).abs() < 1e-3, "{}", price);
+        let ytm = yield_to_maturity(price, 1000.0, 0.06, 2, 5.0).unwrap();
+        assert!((ytm - 0.04).abs() < 1e-10, "{}", ytm);
+
+        // Negative yields price above face
+        let price = price_bond(100.0, 0.0, -0.005, 1, 2.0).unwrap();
+        assert!((price - 100.0 / 0.995f64.powi(2)).abs() < 1e-9);
+        assert!((yield_to_maturity(price, 100.0, 0.0, 1, 2.0).unwrap() + 0.005).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_zero_coupon() {
+        let price = price_bond(100.0, 0.0, 0.03, 1, 7.0).unwrap();
+        assert!((price - 100.0 / 1.03f64.powi(7)).abs() < 1e-9);
+        let analytics = bond_analytics(100.0, 0.0, 0.03, 1, 7.0).unwrap();
+        assert!((analytics.macaulay_duration - 7.0).abs() < 1e-12);
+        assert!((analytics.modified_duration - 7.0 / 1.03).abs() < 1e-12);
+        assert!((analytics.convexity - 56.0 / 1.03f64.powi(2)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_duration_and_convexity_match_price_changes() {
+        let (bump, ytm) = (1e-5, 0.045);
+        let price = |y| price_bond(100.0, 0.05, y, 2, 10.0).unwrap();
+        let (up, mid, down) = (price(ytm + bump), price(ytm), price(ytm - bump));
+        let analytics = bond_analytics(100.0, 0.05, ytm, 2, 10.0).unwrap();
+        assert!((analytics.modified_duration - (down - up) / (2.0 * bump * mid)).abs() < 1e-6);
+        assert!((analytics.convexity - (up - 2.0 * mid + down) / (bump * bump * mid)).abs() < 1e-3);
+        assert!((analytics.macaulay_duration - analytics.modified_duration * (1.0 + ytm / 2.0)).abs() < 1e-12);
+        assert!(analytics.macaulay_duration < 10.0);
+    }
+
+    #[test]
+    fn test_invalid_bonds() {
+        let invalid = |result: Result<f64, BondError>| matches!(result, Err(BondError::Invalid { .. }));
+        assert!(invalid(price_bond(0.0, 0.05, 0.05, 2, 10.0)));
+        assert!(invalid(price_bond(100.0, -0.01, 0.05, 2, 10.0)));
+        assert!(invalid(price_bond(100.0, 0.05, 0.05, 0, 10.0)));
+        assert!(invalid(price_bond(100.0, 0.05, 0.05, 2, 10.2)));
+        assert!(invalid(price_bond(100.0, 0.05, -2.0, 2, 10.0)));
+        assert!(invalid(yield_to_maturity(0.0, 100.0, 0.05, 2, 10.0)));
+        assert!(price_bond(100.0, 0.05, 0.05, 4, 0.25).is_ok());
+
+        // Far above par, but still solvable
+        let ytm = yield_to_maturity(100_000.0, 100.0, 0.0, 1, 1.0).unwrap();
+        assert!((ytm + 0.999).abs() < 1e-10, "{}", ytm);
+        // Needs a yield within 1e-298 of -100%, closer than an f64 can get
+        match yield_to_maturity(1e300, 100.0, 0.0, 1, 1.0) {
+            Err(BondError::NoConvergence { last, .. }) => assert!(last > -1.0 && last.is_finite()),
+            other => panic!("expected no convergence, got {:?}", other),
+        }
+    }
+}
@@ -1,45 +1,544 @@
-use anyhow::Result;
-use chrono::Utc;
+//! Market Data
+//! Quotes and OHLCV history from a pluggable `MarketDataSource`: a seeded
+//! mock (the default, for tests and offline use) or a REST endpoint
+//! answering Yahoo chart JSON or this crate's own `Quote` / `Candle` JSON
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration as ChronoDuration, DurationRound, TimeZone, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use rust_decimal::Decimal;
-use super::Quote;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use super::pricing::monte_carlo::Normals;
+use super::watchlist::normalize_symbol;
+use super::{Candle, Quote};
+use crate::units::HumanDuration;
 
-pub struct MarketDataProvider {
-    // In a real implementation, this would connect to market data feeds
+/// Chart API, answering both quotes and history
+const YAHOO_CHART_URL: &str = "https://query1.finance.yahoo.com/v8/finance/chart/{symbol}";
+
+/// The mock's quote: last price and bid/ask spread
+const MOCK_PRICE: f64 = 100.0;
+const MOCK_SPREAD: f64 = 0.10;
+
+/// The mock history's annual drift and volatility
+const MOCK_DRIFT: f64 = 0.05;
+const MOCK_VOLATILITY: f64 = 0.20;
+
+/// `[quant.market_data]` configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarketDataConfig {
+    /// Used unless a command picks one with `--source`
+    pub source: SourceKind,
+    pub http: HttpSourceConfig,
 }
 
-impl MarketDataProvider {
-    pub fn new() -> Self {
-        Self {}
+/// Which `MarketDataSource` to use
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    #[default]
+    Mock,
+    Http,
+}
+
+impl FromStr for SourceKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "mock" => Ok(Self::Mock),
+            "http" => Ok(Self::Http),
+            other => anyhow::bail!("Unknown market data source '{}' (expected mock or http)", other),
+        }
     }
+}
 
-    pub async fn get_quote(&self, symbol: &str) -> Result<Quote> {
-        // Mock implementation - in production, this would fetch from a real data source
-        tracing::info!("Fetching quote for symbol: {}", symbol);
+/// `[quant.market_data.http]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpSourceConfig {
+    pub format: HttpFormat,
+    /// `{symbol}` is replaced with the normalized symbol
+    pub quote_url: String,
+    /// Also takes `{days}`, `{interval}`, and `{start}` / `{end}` as Unix
+    /// seconds
+    pub history_url: String,
+    /// `OrderBookSnapshot` JSON (generic format only); also takes
+    /// `{levels}`. Empty when the source has no book
+    pub depth_url: String,
+    pub timeout: HumanDuration,
+}
+
+impl Default for HttpSourceConfig {
+    fn default() -> Self {
+        Self {
+            format: HttpFormat::Yahoo,
+            quote_url: format!("{}?range=1d&interval=1d", YAHOO_CHART_URL),
+            history_url: format!("{}?period1={{start}}&period2={{end}}&interval={{interval}}", YAHOO_CHART_URL),
+            depth_url: String::new(),
+            timeout: HumanDuration::from_secs(10),
+        }
+    }
+}
+
+/// Response body of an HTTP source
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpFormat {
+    /// Yahoo's v8 chart API (no book, so bid and ask are the last price)
+    #[default]
+    Yahoo,
+    /// A `Quote` object, and an array of `Candle` objects
+    Generic,
+}
+
+/// Candle width
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interval {
+    #[serde(rename = "1h")]
+    Hour,
+    #[default]
+    #[serde(rename = "1d")]
+    Day,
+    #[serde(rename = "1wk")]
+    Week,
+}
+
+impl Interval {
+    /// As Yahoo spells it, which is also what `{interval}` expands to
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hour => "1h",
+            Self::Day => "1d",
+            Self::Week => "1wk",
+        }
+    }
+
+    pub fn duration(&self) -> ChronoDuration {
+        match self {
+            Self::Hour => ChronoDuration::hours(1),
+            Self::Day => ChronoDuration::days(1),
+            Self::Week => ChronoDuration::weeks(1),
+        }
+    }
+
+    /// Bars covering `days` days, at least one
+    pub fn bars(&self, days: u32) -> usize {
+        (ChronoDuration::days(days as i64).num_seconds() / self.duration().num_seconds()).max(1) as usize
+    }
+}
+
+impl FromStr for Interval {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "1h" | "hour" => Ok(Self::Hour),
+            "1d" | "day" => Ok(Self::Day),
+            "1wk" | "1w" | "week" => Ok(Self::Week),
+            other => anyhow::bail!("Unknown interval '{}' (expected 1h, 1d or 1wk)", other),
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Where quotes and history come from
+#[async_trait]
+pub trait MarketDataSource: Send + Sync {
+    /// For logs and errors
+    fn name(&self) -> &str;
+
+    async fn get_quote(&self, symbol: &str) -> Result<Quote>;
 
-        // Generate mock data
-        let base_price = 100.0;
-        let spread = 0.10;
+    /// `interval` candles from the last `days` days, oldest first
+    async fn get_history(&self, symbol: &str, interval: Interval, days: u32) -> Result<Vec<Candle>>;
 
+    /// L2 depth with up to `levels` levels per side
+    async fn get_depth(&self, symbol: &str, _levels: usize) -> Result<OrderBookSnapshot> {
+        anyhow::bail!("The {} market data source has no order book for {}", self.name(), symbol)
+    }
+}
+
+/// The source `kind`, configured by `config`
+pub fn open_source(kind: SourceKind, config: &MarketDataConfig) -> Result<Box<dyn MarketDataSource>> {
+    Ok(match kind {
+        SourceKind::Mock => Box::new(MockSource::default()),
+        SourceKind::Http => Box::new(HttpSource::new(&config.http)?),
+    })
+}
+
+/// The data source has no quotes for the symbol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownSymbol(pub String);
+
+impl fmt::Display for UnknownSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no market data for symbol '{}'", self.0)
+    }
+}
+
+impl std::error::Error for UnknownSymbol {}
+
+/// One book level: (price, size)
+pub type PriceLevel = (Decimal, Decimal);
+
+/// Full L2 book at a sequence number; bids best-first (descending),
+/// asks best-first (ascending)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderBookSnapshot {
+    pub symbol: String,
+    pub bids: Vec<PriceLevel>,
+    pub asks: Vec<PriceLevel>,
+    pub sequence: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Incremental book update; a level with size 0 is removed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderBookDelta {
+    pub symbol: String,
+    /// Must be exactly one past the book's current sequence
+    pub sequence: u64,
+    pub bids: Vec<PriceLevel>,
+    pub asks: Vec<PriceLevel>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Synthetic data: a flat 100.00 quote with a 10 cent spread for anything
+/// shaped like a ticker, and geometric Brownian motion ending at it.
+/// Prices depend only on the seed, the symbol and the interval
+#[derive(Debug, Clone, Default)]
+pub struct MockSource {
+    seed: u64,
+}
+
+impl MockSource {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+fn mock_price(value: f64) -> Decimal {
+    Decimal::from_f64_retain(value).unwrap_or_default().round_dp(2)
+}
+
+#[async_trait]
+impl MarketDataSource for MockSource {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    async fn get_quote(&self, symbol: &str) -> Result<Quote> {
+        // The mock quotes anything shaped like a ticker
+        if normalize_symbol(symbol).is_err() {
+            return Err(UnknownSymbol(symbol.to_string()).into());
+        }
         Ok(Quote {
             symbol: symbol.to_string(),
-            bid: Decimal::from_f64_retain(base_price - spread / 2.0).unwrap(),
-            ask: Decimal::from_f64_retain(base_price + spread / 2.0).unwrap(),
-            last: Decimal::from_f64_retain(base_price).unwrap(),
+            bid: Decimal::from_f64_retain(MOCK_PRICE - MOCK_SPREAD / 2.0).unwrap(),
+            ask: Decimal::from_f64_retain(MOCK_PRICE + MOCK_SPREAD / 2.0).unwrap(),
+            last: Decimal::from_f64_retain(MOCK_PRICE).unwrap(),
             volume: 1000000,
             timestamp: Utc::now(),
         })
     }
 
-    pub async fn get_historical_data(
-        &self,
-        symbol: &str,
-        start: chrono::DateTime<Utc>,
-        end: chrono::DateTime<Utc>,
-    ) -> Result<Vec<Quote>> {
-        tracing::info!("Fetching historical data for {} from {} to {}", symbol, start, end);
+    async fn get_history(&self, symbol: &str, interval: Interval, days: u32) -> Result<Vec<Candle>> {
+        let symbol = normalize_symbol(symbol).map_err(|_| UnknownSymbol(symbol.to_string()))?;
+        // FNV-1a, so a symbol's path doesn't change between builds
+        let symbol_hash = symbol.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100_0000_01b3));
+        let mut rng = StdRng::seed_from_u64(self.seed ^ symbol_hash ^ interval as u64);
+        let mut normals = Normals::default();
+
+        // Walk back from the latest close
+        let dt = interval.duration().num_seconds() as f64 / (365.0 * 86_400.0);
+        let drift = (MOCK_DRIFT - MOCK_VOLATILITY * MOCK_VOLATILITY / 2.0) * dt;
+        let mut closes = vec![MOCK_PRICE; interval.bars(days)];
+        for i in (1..closes.len()).rev() {
+            closes[i - 1] = closes[i] / (drift + MOCK_VOLATILITY * dt.sqrt() * normals.next(&mut rng)).exp();
+        }
+        let last = match interval {
+            Interval::Hour => Utc::now().duration_trunc(ChronoDuration::hours(1))?,
+            Interval::Day | Interval::Week => Utc::now().date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc(),
+        };
+        Ok(closes
+            .iter()
+            .enumerate()
+            .map(|(i, &close)| {
+                let open = if i == 0 { close } else { closes[i - 1] };
+                Candle {
+                    symbol: symbol.clone(),
+                    timestamp: last - interval.duration() * (closes.len() - 1 - i) as i32,
+                    open: mock_price(open),
+                    high: mock_price(open.max(close) * (1.0 + rng.gen_range(0.0..0.01))),
+                    low: mock_price(open.min(close) * (1.0 - rng.gen_range(0.0..0.01))),
+                    close: mock_price(close),
+                    volume: rng.gen_range(500_000..1_500_000),
+                }
+            })
+            .collect())
+    }
+
+    /// A book around the quote with sizes growing away from the touch
+    async fn get_depth(&self, symbol: &str, levels: usize) -> Result<OrderBookSnapshot> {
+        let quote = self.get_quote(symbol).await?;
+        let tick = Decimal::new(1, 2);
+        let base_size = Decimal::from(100);
+
+        let level = |i: usize| {
+            let depth = Decimal::from(i as u64);
+            (tick * depth, base_size * (Decimal::ONE + depth / Decimal::from(2)))
+        };
+        let (best_bid, best_ask) = (quote.bid.round_dp(2), quote.ask.round_dp(2));
+        let bids = (0..levels).map(|i| (best_bid - level(i).0, level(i).1)).collect();
+        let asks = (0..levels).map(|i| (best_ask + level(i).0, level(i).1)).collect();
+
+        Ok(OrderBookSnapshot {
+            symbol: symbol.to_string(),
+            bids,
+            asks,
+            sequence: 0,
+            timestamp: quote.timestamp,
+        })
+    }
+}
+
+/// A REST endpoint, through the `market_data` proxy if one is configured
+pub struct HttpSource {
+    config: HttpSourceConfig,
+    client: reqwest::Client,
+}
+
+impl HttpSource {
+    pub fn new(config: &HttpSourceConfig) -> Result<Self> {
+        let depth_url = Some(("depth_url", &config.depth_url)).filter(|(_, url)| !url.is_empty());
+        for (key, url) in [("quote_url", &config.quote_url), ("history_url", &config.history_url)].into_iter().chain(depth_url) {
+            if !url.contains("{symbol}") {
+                anyhow::bail!("quant.market_data.http.{} must contain {{symbol}}", key);
+            }
+            reqwest::Url::parse(&url.replace("{symbol}", "X"))
+                .with_context(|| format!("Invalid quant.market_data.http.{} '{}'", key, url))?;
+        }
+        let client = crate::net::http_client(crate::net::Subsystem::MarketData)
+            .timeout(config.timeout.as_std())
+            .user_agent(concat!("quantraband/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Self { config: config.clone(), client })
+    }
+
+    /// GET `template` for `symbol`; 404 means the source doesn't know it
+    async fn fetch<T: serde::de::DeserializeOwned>(&self, template: &str, symbol: &str, interval: Interval, days: u32) -> Result<T> {
+        let end = Utc::now();
+        let start = end - ChronoDuration::days(days as i64);
+        // Symbols may hold `/`, `^` and `=`
+        let encoded: String = symbol
+            .chars()
+            .map(|c| match c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                true => c.to_string(),
+                false => format!("%{:02X}", c as u32),
+            })
+            .collect();
+        let url = template
+            .replace("{symbol}", &encoded)
+            .replace("{days}", &days.to_string())
+            .replace("{interval}", interval.as_str())
+            .replace("{start}", &start.timestamp().to_string())
+            .replace("{end}", &end.timestamp().to_string());
+        let response = self.client.get(&url).send().await.with_context(|| format!("Market data request for {} failed", symbol))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(UnknownSymbol(symbol.to_string()).into());
+        }
+        if !response.status().is_success() {
+            anyhow::bail!("Market data source answered {} for {}", response.status(), symbol);
+        }
+        response.json().await.with_context(|| format!("Invalid market data response for {}", symbol))
+    }
+}
+
+#[async_trait]
+impl MarketDataSource for HttpSource {
+    fn name(&self) -> &str {
+        "http"
+    }
+
+    async fn get_quote(&self, symbol: &str) -> Result<Quote> {
+        let symbol = normalize_symbol(symbol).map_err(|_| UnknownSymbol(symbol.to_string()))?;
+        match self.config.format {
+            HttpFormat::Yahoo => {
+                let chart: YahooResponse = self.fetch(&self.config.quote_url, &symbol, Interval::Day, 1).await?;
+                let result = chart.into_result(&symbol)?;
+                let last = Decimal::from_f64_retain(result.meta.regular_market_price).context("Quote price out of range")?;
+                Ok(Quote {
+                    symbol,
+                    bid: last,
+                    ask: last,
+                    last,
+                    volume: result.meta.regular_market_volume.unwrap_or(0),
+                    timestamp: result.meta.regular_market_time.and_then(|t| Utc.timestamp_opt(t, 0).single()).unwrap_or_else(Utc::now),
+                })
+            }
+            HttpFormat::Generic => self.fetch(&self.config.quote_url, &symbol, Interval::Day, 1).await,
+        }
+    }
+
+    async fn get_history(&self, symbol: &str, interval: Interval, days: u32) -> Result<Vec<Candle>> {
+        let symbol = normalize_symbol(symbol).map_err(|_| UnknownSymbol(symbol.to_string()))?;
+        let mut candles: Vec<Candle> = match self.config.format {
+            HttpFormat::Yahoo => {
+                let chart: YahooResponse = self.fetch(&self.config.history_url, &symbol, interval, days).await?;
+                chart.into_result(&symbol)?.candles(&symbol)
+            }
+            HttpFormat::Generic => self.fetch(&self.config.history_url, &symbol, interval, days).await?,
+        };
+        let since = Utc::now() - ChronoDuration::days(days as i64);
+        candles.retain(|candle| candle.timestamp >= since);
+        candles.sort_by_key(|candle| candle.timestamp);
+        Ok(candles)
+    }
+
+    async fn get_depth(&self, symbol: &str, levels: usize) -> Result<OrderBookSnapshot> {
+        let symbol = normalize_symbol(symbol).map_err(|_| UnknownSymbol(symbol.to_string()))?;
+        if self.config.depth_url.is_empty() || self.config.format != HttpFormat::Generic {
+            anyhow::bail!("The http market data source has no order book for {} (set a generic-format depth_url)", symbol);
+        }
+        let template = self.config.depth_url.replace("{levels}", &levels.to_string());
+        let mut book: OrderBookSnapshot = self.fetch(&template, &symbol, Interval::Day, 1).await?;
+        book.bids.truncate(levels);
+        book.asks.truncate(levels);
+        Ok(book)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct YahooResponse {
+    chart: YahooChart,
+}
+
+#[derive(Debug, Deserialize)]
+struct YahooChart {
+    result: Option<Vec<YahooResult>>,
+    error: Option<YahooError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct YahooError {
+    code: String,
+    description: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct YahooResult {
+    meta: YahooMeta,
+    #[serde(default)]
+    timestamp: Vec<i64>,
+    #[serde(default)]
+    indicators: YahooIndicators,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct YahooMeta {
+    regular_market_price: f64,
+    regular_market_time: Option<i64>,
+    regular_market_volume: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct YahooIndicators {
+    quote: Vec<YahooBars>,
+}
+
+/// Columns, with nulls on days without trading
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct YahooBars {
+    open: Vec<Option<f64>>,
+    high: Vec<Option<f64>>,
+    low: Vec<Option<f64>>,
+    close: Vec<Option<f64>>,
+    volume: Vec<Option<u64>>,
+}
+
+impl YahooResponse {
+    fn into_result(self, symbol: &str) -> Result<YahooResult> {
+        if let Some(error) = self.chart.error {
+            if error.code == "Not Found" {
+                return Err(UnknownSymbol(symbol.to_string()).into());
+            }
+            anyhow::bail!("Market data source refused {}: {} ({})", symbol, error.description, error.code);
+        }
+        self.chart.result.and_then(|results| results.into_iter().next()).ok_or_else(|| UnknownSymbol(symbol.to_string()).into())
+    }
+}
+
+impl YahooResult {
+    fn candles(&self, symbol: &str) -> Vec<Candle> {
+        let Some(bars) = self.indicators.quote.first() else {
+            return Vec::new();
+        };
+        let column = |values: &[Option<f64>], i: usize| values.get(i).copied().flatten().and_then(Decimal::from_f64_retain);
+        self.timestamp
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &t)| {
+                Some(Candle {
+                    symbol: symbol.to_string(),
+                    timestamp: Utc.timestamp_opt(t, 0).single()?,
+                    open: column(&bars.open, i)?,
+                    high: column(&bars.high, i)?,
+                    low: column(&bars.low, i)?,
+                    close: column(&bars.close, i)?,
+                    volume: bars.volume.get(i).copied().flatten().unwrap_or(0),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Quotes, history and depth from a `MarketDataSource`
+#[derive(Clone)]
+pub struct MarketDataProvider {
+    source: Arc<dyn MarketDataSource>,
+}
+
+impl MarketDataProvider {
+    /// Backed by the mock source
+    pub fn new() -> Self {
+        Self::with_source(Box::new(MockSource::default()))
+    }
+
+    pub fn with_source(source: Box<dyn MarketDataSource>) -> Self {
+        Self { source: Arc::from(source) }
+    }
+
+    pub fn source_name(&self) -> &str {
+        self.source.name()
+    }
+
+    pub async fn get_quote(&self, symbol: &str) -> Result<Quote> {
+        tracing::info!("Fetching quote for symbol: {} ({})", symbol, self.source.name());
+        self.source.get_quote(symbol).await
+    }
 
-        // Mock implementation
-        Ok(Vec::new())
+    /// `interval` candles from the last `days` days, oldest first
+    pub async fn get_history(&self, symbol: &str, interval: Interval, days: u32) -> Result<Vec<Candle>> {
+        self.source.get_history(symbol, interval, days).await
+    }
+
+    /// L2 depth with `levels` levels per side
+    pub async fn get_depth(&self, symbol: &str, levels: usize) -> Result<OrderBookSnapshot> {
+        self.source.get_depth(symbol, levels).await
     }
 
     pub async fn subscribe_to_feed(&self, symbols: Vec<String>) -> Result<()> {
@@ -49,3 +548,154 @@ impl MarketDataProvider {
         Ok(())
     }
 }
+
+impl Default for MarketDataProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::Path;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+
+    /// Yahoo chart and generic JSON for AAPL; anything else is a 404
+    async fn serve() -> std::net::SocketAddr {
+        async fn chart(Path(symbol): Path<String>) -> (StatusCode, String) {
+            if symbol != "AAPL" {
+                let missing = r#"{"chart":{"result":null,"error":{"code":"Not Found","description":"No data found"}}}"#;
+                return (StatusCode::NOT_FOUND, missing.to_string());
+            }
+            let day = |ago: i64| (Utc::now() - ChronoDuration::days(ago)).timestamp();
+            let body = serde_json::json!({ "chart": { "error": null, "result": [{
+                "meta": { "symbol": "AAPL", "regularMarketPrice": 187.5, "regularMarketTime": day(0), "regularMarketVolume": 41_000_000u64 },
+                "timestamp": [day(3), day(2), day(1)],
+                "indicators": { "quote": [{
+                    "open": [180.0, null, 184.0],
+                    "high": [183.0, null, 188.0],
+                    "low": [179.5, null, 183.0],
+                    "close": [182.0, null, 187.5],
+                    "volume": [30_000_000u64, null, 41_000_000u64],
+                }]},
+            }]}});
+            (StatusCode::OK, body.to_string())
+        }
+        async fn quote(Path(symbol): Path<String>) -> String {
+            serde_json::json!({ "symbol": symbol, "bid": "99.5", "ask": "99.7", "last": "99.6", "volume": 10, "timestamp": Utc::now() }).to_string()
+        }
+        async fn history(Path(symbol): Path<String>) -> String {
+            let candle = |ago: i64, close: &str| serde_json::json!({
+                "symbol": symbol, "timestamp": Utc::now() - ChronoDuration::days(ago),
+                "open": close, "high": close, "low": close, "close": close, "volume": 1,
+            });
+            // Newest first, and one older than any requested window
+            serde_json::json!([candle(1, "11"), candle(2, "10"), candle(400, "1")]).to_string()
+        }
+        async fn depth(Path(symbol): Path<String>) -> String {
+            serde_json::json!({
+                "symbol": symbol, "sequence": 7, "timestamp": Utc::now(),
+                "bids": [["99.5", "200"], ["99.4", "300"]], "asks": [["99.7", "100"], ["99.8", "400"]],
+            })
+            .to_string()
+        }
+
+        let app = Router::new()
+            .route("/chart/:symbol", get(chart))
+            .route("/quote/:symbol", get(quote))
+            .route("/history/:symbol", get(history))
+            .route("/depth/:symbol", get(depth));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_mock_history_is_seeded() {
+        let closes = |candles: Vec<Candle>| candles.into_iter().map(|c| c.close).collect::<Vec<_>>();
+        let history = MockSource::new(1).get_history("aapl", Interval::Day, 30).await.unwrap();
+        assert_eq!(history.len(), 30);
+        assert_eq!(history[29].close, mock_price(MOCK_PRICE));
+        assert!(history.windows(2).all(|w| w[0].timestamp < w[1].timestamp));
+        assert!(history.iter().all(|c| c.low <= c.open.min(c.close) && c.high >= c.open.max(c.close)));
+
+        let again = closes(MockSource::new(1).get_history("AAPL", Interval::Day, 30).await.unwrap());
+        assert_eq!(closes(history), again);
+        assert_ne!(closes(MockSource::new(2).get_history("AAPL", Interval::Day, 30).await.unwrap()), again);
+        assert!(MockSource::new(1).get_history("no such", Interval::Day, 30).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mock_history_intervals() {
+        let hourly = MockSource::default().get_history("MSFT", Interval::Hour, 2).await.unwrap();
+        assert_eq!(hourly.len(), 48);
+        assert!(hourly.windows(2).all(|w| w[1].timestamp - w[0].timestamp == ChronoDuration::hours(1)));
+        assert_eq!(MockSource::default().get_history("MSFT", Interval::Week, 30).await.unwrap().len(), 4);
+        assert_eq!("1W".parse::<Interval>().unwrap(), Interval::Week);
+        assert!("5m".parse::<Interval>().is_err());
+
+        // A year of daily closes, for analytics that want stable inputs
+        let closes: Vec<f64> = MockSource::new(3)
+            .get_history("SPY", Interval::Day, 365)
+            .await
+            .unwrap()
+            .iter()
+            .map(|c| c.close.to_string().parse().unwrap())
+            .collect();
+        let returns: Vec<f64> = closes.windows(2).map(|w| w[1] / w[0] - 1.0).collect();
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let vol = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64 * 365.0).sqrt();
+        assert!((vol - MOCK_VOLATILITY).abs() < 0.03, "annualized vol {}", vol);
+        let drawdown = crate::quant::risk::calculate_max_drawdown(&closes).unwrap();
+        assert!(drawdown > 0.0 && drawdown < 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_http_source_yahoo() {
+        let addr = serve().await;
+        let url = format!("http://{}/chart/{{symbol}}", addr);
+        let config = HttpSourceConfig { quote_url: url.clone(), history_url: url, ..Default::default() };
+        let source = HttpSource::new(&config).unwrap();
+
+        let quote = source.get_quote("aapl").await.unwrap();
+        assert_eq!((quote.symbol.as_str(), quote.last, quote.volume), ("AAPL", Decimal::new(1875, 1), 41_000_000));
+        assert_eq!((quote.bid, quote.ask), (quote.last, quote.last));
+
+        // The day without trading is skipped
+        let history = source.get_history("AAPL", Interval::Day, 5).await.unwrap();
+        let closes: Vec<Decimal> = history.iter().map(|c| c.close).collect();
+        assert_eq!(closes, vec![Decimal::from(182), Decimal::new(1875, 1)]);
+
+        let err = source.get_quote("MSFT").await.unwrap_err();
+        assert_eq!(err.downcast_ref::<UnknownSymbol>(), Some(&UnknownSymbol("MSFT".to_string())));
+        // Yahoo has no book, and none is made up
+        assert!(source.get_depth("AAPL", 5).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_source_generic() {
+        let addr = serve().await;
+        let config = HttpSourceConfig {
+            format: HttpFormat::Generic,
+            quote_url: format!("http://{}/quote/{{symbol}}", addr),
+            history_url: format!("http://{}/history/{{symbol}}?days={{days}}", addr),
+            depth_url: format!("http://{}/depth/{{symbol}}?levels={{levels}}", addr),
+            ..Default::default()
+        };
+        let engine = crate::quant::QuantEngine::with_source(Box::new(HttpSource::new(&config).unwrap()));
+
+        let quote = engine.get_quote("aapl").await.unwrap();
+        assert_eq!((quote.bid, quote.ask, quote.last), (Decimal::new(995, 1), Decimal::new(997, 1), Decimal::new(996, 1)));
+        let closes: Vec<Decimal> = engine.get_history("AAPL", Interval::Day, 30).await.unwrap().iter().map(|c| c.close).collect();
+        assert_eq!(closes, vec![Decimal::from(10), Decimal::from(11)]);
+        let book = engine.get_depth("AAPL", 1).await.unwrap();
+        assert_eq!((book.sequence, book.bids.len(), book.asks[0].0), (7, 1, Decimal::new(997, 1)));
+
+        let no_symbol = HttpSourceConfig { quote_url: "http://example.com/quote".to_string(), ..config };
+        assert!(HttpSource::new(&no_symbol).is_err());
+    }
+}
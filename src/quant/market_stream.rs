@@ -0,0 +1,518 @@
+//! Market Data Streaming
+//! Quotes pushed over a websocket instead of polled. One feed serves every
+//! consumer: interest in a symbol is reference counted, so the server hears
+//! about the first subscriber and the last to leave, nobody in between. A
+//! dropped connection is re-established with exponential backoff and the
+//! active symbols resubscribed. A symbol quiet for `stale_after` (every
+//! symbol, while disconnected) is polled over REST until quotes resume
+//!
+//! Frames are JSON text. The client sends
+//! `{"action": "subscribe" | "unsubscribe", "symbols": [...]}` and the
+//! server sends quotes as `Quote` objects; any other frame only counts as
+//! a sign of life
+
+use anyhow::{bail, Context as _, Result};
+use futures::{SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+use tokio_tungstenite::tungstenite::Message;
+
+use super::market_data::MarketDataProvider;
+use super::Quote;
+use crate::units::HumanDuration;
+
+/// `[quant.stream]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StreamConfig {
+    /// `ws://` or `wss://` quote stream; unset polls REST only
+    pub url: Option<String>,
+    /// A symbol with no streamed quote for this long is polled over REST
+    pub stale_after: HumanDuration,
+    /// Ping after this long without a frame; reconnect after twice as long
+    pub heartbeat: HumanDuration,
+    pub reconnect_min: HumanDuration,
+    pub reconnect_max: HumanDuration,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            stale_after: HumanDuration::from_secs(15),
+            heartbeat: HumanDuration::from_secs(20),
+            reconnect_min: HumanDuration::from_secs(1),
+            reconnect_max: HumanDuration::from_secs(60),
+        }
+    }
+}
+
+/// Quotes for a set of symbols, pushed as they arrive
+pub trait MarketDataStream: Send + Sync {
+    /// Quotes for `symbols` until the subscription is dropped
+    fn subscribe(&self, symbols: &[String]) -> QuoteSubscription;
+}
+
+enum FeedCommand {
+    Subscribe { id: u64, symbols: BTreeSet<String>, quotes: mpsc::UnboundedSender<Quote> },
+    Update { id: u64, symbols: BTreeSet<String> },
+    Release { id: u64 },
+}
+
+/// One consumer's quotes; dropping it releases its symbols
+pub struct QuoteSubscription {
+    id: u64,
+    symbols: BTreeSet<String>,
+    quotes: mpsc::UnboundedReceiver<Quote>,
+    commands: mpsc::UnboundedSender<FeedCommand>,
+}
+
+impl QuoteSubscription {
+    pub fn symbols(&self) -> &BTreeSet<String> {
+        &self.symbols
+    }
+
+    /// Follow `symbols` instead; only the difference reaches the feed
+    pub fn set_symbols(&mut self, symbols: &[String]) {
+        let symbols = normalized(symbols);
+        if symbols != self.symbols {
+            self.symbols = symbols.clone();
+            let _ = self.commands.send(FeedCommand::Update { id: self.id, symbols });
+        }
+    }
+
+    /// None once the feed has stopped
+    pub async fn next_quote(&mut self) -> Option<Quote> {
+        self.quotes.recv().await
+    }
+}
+
+impl Stream for QuoteSubscription {
+    type Item = Quote;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Quote>> {
+        self.get_mut().quotes.poll_recv(cx)
+    }
+}
+
+impl Drop for QuoteSubscription {
+    fn drop(&mut self) {
+        let _ = self.commands.send(FeedCommand::Release { id: self.id });
+    }
+}
+
+fn normalized(symbols: &[String]) -> BTreeSet<String> {
+    symbols.iter().map(|s| s.trim().to_uppercase()).filter(|s| !s.is_empty()).collect()
+}
+
+/// Interest in each symbol across subscriptions
+#[derive(Debug, Default)]
+pub struct SymbolRefs {
+    counts: HashMap<String, usize>,
+}
+
+impl SymbolRefs {
+    /// Symbols that nobody wanted before
+    pub fn acquire<'a>(&mut self, symbols: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+        let mut added = Vec::new();
+        for symbol in symbols {
+            let count = self.counts.entry(symbol.clone()).or_default();
+            *count += 1;
+            if *count == 1 {
+                added.push(symbol.clone());
+            }
+        }
+        added
+    }
+
+    /// Symbols that nobody wants any more
+    pub fn release<'a>(&mut self, symbols: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+        let mut removed = Vec::new();
+        for symbol in symbols {
+            let Some(count) = self.counts.get_mut(symbol) else { continue };
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(symbol);
+                removed.push(symbol.clone());
+            }
+        }
+        removed
+    }
+
+    pub fn contains(&self, symbol: &str) -> bool {
+        self.counts.contains_key(symbol)
+    }
+
+    pub fn active(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = self.counts.keys().cloned().collect();
+        symbols.sort();
+        symbols
+    }
+}
+
+#[derive(Default)]
+struct Subscribers {
+    subscribers: HashMap<u64, (BTreeSet<String>, mpsc::UnboundedSender<Quote>)>,
+    refs: SymbolRefs,
+}
+
+impl Subscribers {
+    /// Symbols newly wanted and no longer wanted
+    fn apply(&mut self, command: FeedCommand) -> (Vec<String>, Vec<String>) {
+        match command {
+            FeedCommand::Subscribe { id, symbols, quotes } => {
+                let added = self.refs.acquire(&symbols);
+                self.subscribers.insert(id, (symbols, quotes));
+                (added, Vec::new())
+            }
+            FeedCommand::Update { id, symbols } => {
+                let Some((current, _)) = self.subscribers.get_mut(&id) else { return Default::default() };
+                let added = self.refs.acquire(symbols.difference(current));
+                let removed = self.refs.release(current.difference(&symbols));
+                *current = symbols;
+                (added, removed)
+            }
+            FeedCommand::Release { id } => match self.subscribers.remove(&id) {
+                Some((symbols, _)) => (Vec::new(), self.refs.release(&symbols)),
+                None => Default::default(),
+            },
+        }
+    }
+
+    fn deliver(&self, quote: &Quote) {
+        for (symbols, quotes) in self.subscribers.values() {
+            if symbols.contains(&quote.symbol) {
+                let _ = quotes.send(quote.clone());
+            }
+        }
+    }
+}
+
+/// The websocket feed with REST fallback; without a URL it only polls
+pub struct QuoteFeed {
+    commands: mpsc::UnboundedSender<FeedCommand>,
+    next_id: AtomicU64,
+}
+
+impl QuoteFeed {
+    /// Start the feed; REST polls run every `poll_interval`. It stops once
+    /// the feed and every subscription are dropped
+    pub fn spawn(config: &StreamConfig, provider: MarketDataProvider, poll_interval: Duration) -> Arc<Self> {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let task = FeedTask {
+            config: config.clone(),
+            provider,
+            poll_interval,
+            commands: receiver,
+            subscribers: Subscribers::default(),
+            last_streamed: HashMap::new(),
+            stale: HashSet::new(),
+        };
+        tokio::spawn(task.run());
+        Arc::new(Self { commands, next_id: AtomicU64::new(0) })
+    }
+}
+
+impl MarketDataStream for QuoteFeed {
+    fn subscribe(&self, symbols: &[String]) -> QuoteSubscription {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let symbols = normalized(symbols);
+        let (sender, quotes) = mpsc::unbounded_channel();
+        let _ = self.commands.send(FeedCommand::Subscribe { id, symbols: symbols.clone(), quotes: sender });
+        QuoteSubscription { id, symbols, quotes, commands: self.commands.clone() }
+    }
+}
+
+type Socket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+#[derive(Serialize, Deserialize)]
+struct Control {
+    action: String,
+    symbols: Vec<String>,
+}
+
+fn control(action: &str, symbols: &[String]) -> Result<Message> {
+    let control = Control { action: action.to_string(), symbols: symbols.to_vec() };
+    Ok(Message::Text(serde_json::to_string(&control)?))
+}
+
+struct FeedTask {
+    config: StreamConfig,
+    provider: MarketDataProvider,
+    poll_interval: Duration,
+    commands: mpsc::UnboundedReceiver<FeedCommand>,
+    subscribers: Subscribers,
+    /// When each symbol last had a streamed quote, or was subscribed
+    last_streamed: HashMap<String, Instant>,
+    /// Symbols being polled because their stream went quiet
+    stale: HashSet<String>,
+}
+
+impl FeedTask {
+    async fn run(mut self) {
+        let mut poll = tokio::time::interval(self.poll_interval);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut delay = self.config.reconnect_min.as_std();
+        loop {
+            if let Some(url) = self.config.url.clone() {
+                match tokio::time::timeout(self.config.heartbeat.as_std(), tokio_tungstenite::connect_async(url.as_str())).await {
+                    Ok(Ok((socket, _))) => {
+                        tracing::info!("📈 Quote stream connected to {}", url);
+                        delay = self.config.reconnect_min.as_std();
+                        match self.stream(socket, &mut poll).await {
+                            Ok(()) => return,
+                            Err(e) => tracing::warn!("📈 Quote stream lost: {:#}; reconnecting in {:?}", e, delay),
+                        }
+                    }
+                    Ok(Err(e)) => tracing::warn!("📈 Quote stream connect failed: {}; retrying in {:?}", e, delay),
+                    Err(_) => tracing::warn!("📈 Quote stream connect timed out; retrying in {:?}", delay),
+                }
+            }
+
+            // Disconnected (or REST only): poll every symbol until the next attempt
+            let retry = tokio::time::sleep(match self.config.url {
+                Some(_) => delay,
+                None => Duration::MAX,
+            });
+            tokio::pin!(retry);
+            loop {
+                tokio::select! {
+                    _ = &mut retry => break,
+                    command = self.commands.recv() => match command {
+                        Some(command) => {
+                            self.apply(command);
+                        }
+                        None => return,
+                    },
+                    _ = poll.tick() => self.poll(true).await,
+                }
+            }
+            delay = (delay * 2).min(self.config.reconnect_max.as_std());
+        }
+    }
+
+    /// Run one connection until it fails; Ok once every consumer is gone
+    async fn stream(&mut self, mut socket: Socket, poll: &mut Interval) -> Result<()> {
+        let active = self.subscribers.refs.active();
+        if !active.is_empty() {
+            socket.send(control("subscribe", &active)?).await?;
+        }
+        let heartbeat = self.config.heartbeat.as_std();
+        let mut last_frame = Instant::now();
+        let mut ping = tokio::time::interval_at(Instant::now() + heartbeat / 2, heartbeat / 2);
+        loop {
+            tokio::select! {
+                frame = socket.next() => {
+                    let frame = frame.context("Server closed the stream")??;
+                    last_frame = Instant::now();
+                    match frame {
+                        Message::Text(text) => self.on_text(&text),
+                        Message::Close(_) => bail!("Server closed the stream"),
+                        _ => {}
+                    }
+                }
+                command = self.commands.recv() => {
+                    let Some(command) = command else {
+                        let _ = socket.close(None).await;
+                        return Ok(());
+                    };
+                    let (added, removed) = self.apply(command);
+                    if !added.is_empty() {
+                        socket.send(control("subscribe", &added)?).await?;
+                    }
+                    if !removed.is_empty() {
+                        socket.send(control("unsubscribe", &removed)?).await?;
+                    }
+                }
+                _ = poll.tick() => self.poll(false).await,
+                _ = ping.tick() => {
+                    let silent = last_frame.elapsed();
+                    if silent >= heartbeat * 2 {
+                        bail!("No frame for {:?}", silent);
+                    }
+                    if silent >= heartbeat {
+                        socket.send(Message::Ping(Vec::new())).await?;
+                    }
+                }
+            }
+        }
+    }
+
+    fn apply(&mut self, command: FeedCommand) -> (Vec<String>, Vec<String>) {
+        let (added, removed) = self.subscribers.apply(command);
+        let now = Instant::now();
+        for symbol in &added {
+            self.last_streamed.insert(symbol.clone(), now);
+        }
+        for symbol in &removed {
+            self.last_streamed.remove(symbol);
+            self.stale.remove(symbol);
+        }
+        (added, removed)
+    }
+
+    fn on_text(&mut self, text: &str) {
+        let Ok(mut quote) = serde_json::from_str::<Quote>(text) else { return };
+        quote.symbol = quote.symbol.to_uppercase();
+        if !self.subscribers.refs.contains(&quote.symbol) {
+            return;
+        }
+        self.last_streamed.insert(quote.symbol.clone(), Instant::now());
+        if self.stale.remove(&quote.symbol) {
+            tracing::info!("📈 {} streaming again", quote.symbol);
+        }
+        self.subscribers.deliver(&quote);
+    }
+
+    /// REST quotes for every symbol, or those the stream has gone quiet on
+    async fn poll(&mut self, all: bool) {
+        let stale_after = self.config.stale_after.as_std();
+        let mut due = Vec::new();
+        for symbol in self.subscribers.refs.active() {
+            let quiet = self.last_streamed.get(&symbol).is_none_or(|at| at.elapsed() >= stale_after);
+            if !(all || quiet) {
+                continue;
+            }
+            if !all && self.stale.insert(symbol.clone()) {
+                tracing::warn!("📈 No streamed quote for {} in {}; polling over REST", symbol, self.config.stale_after);
+            }
+            due.push(symbol);
+        }
+        for symbol in due {
+            match self.provider.get_quote(&symbol).await {
+                Ok(quote) => self.subscribers.deliver(&quote),
+                Err(e) => tracing::debug!("📈 REST quote for {} failed: {}", symbol, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use rust_decimal::Decimal;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::WebSocketStream;
+
+    type ServerSocket = WebSocketStream<TcpStream>;
+
+    /// Accepts websocket connections and hands them to the test
+    async fn mock_server() -> (String, mpsc::UnboundedReceiver<ServerSocket>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (sender, connections) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                if let Ok(socket) = tokio_tungstenite::accept_async(tcp).await {
+                    let _ = sender.send(socket);
+                }
+            }
+        });
+        (url, connections)
+    }
+
+    fn config(url: &str, stale_after: HumanDuration) -> StreamConfig {
+        StreamConfig {
+            url: Some(url.to_string()),
+            stale_after,
+            heartbeat: HumanDuration::from_secs(5),
+            reconnect_min: HumanDuration::from_millis(50),
+            reconnect_max: HumanDuration::from_millis(200),
+        }
+    }
+
+    async fn next_control(socket: &mut ServerSocket) -> (String, Vec<String>) {
+        loop {
+            let frame = tokio::time::timeout(Duration::from_secs(5), socket.next()).await.unwrap().unwrap().unwrap();
+            if let Message::Text(text) = frame {
+                let control: Control = serde_json::from_str(&text).unwrap();
+                return (control.action, control.symbols);
+            }
+        }
+    }
+
+    async fn send_quote(socket: &mut ServerSocket, symbol: &str, last: i64) {
+        let last = Decimal::from(last);
+        let quote = Quote { symbol: symbol.to_string(), bid: last, ask: last, last, volume: 1, timestamp: Utc::now() };
+        socket.send(Message::Text(serde_json::to_string(&quote).unwrap())).await.unwrap();
+    }
+
+    async fn next_quote(subscription: &mut QuoteSubscription) -> Quote {
+        tokio::time::timeout(Duration::from_secs(5), subscription.next_quote()).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_resubscribes_active_symbols() {
+        let (url, mut connections) = mock_server().await;
+        let feed = QuoteFeed::spawn(&config(&url, HumanDuration::from_secs(60)), MarketDataProvider::new(), Duration::from_secs(60));
+        let mut quotes = feed.subscribe(&["aapl".to_string()]);
+
+        let mut first = connections.recv().await.unwrap();
+        assert_eq!(next_control(&mut first).await, ("subscribe".to_string(), vec!["AAPL".to_string()]));
+        send_quote(&mut first, "AAPL", 101).await;
+        assert_eq!(next_quote(&mut quotes).await.last, Decimal::from(101));
+
+        // The server goes away; the feed comes back and asks for AAPL again
+        drop(first);
+        let mut second = connections.recv().await.unwrap();
+        assert_eq!(next_control(&mut second).await, ("subscribe".to_string(), vec!["AAPL".to_string()]));
+        send_quote(&mut second, "AAPL", 102).await;
+        // A REST poll may fill the gap first
+        while next_quote(&mut quotes).await.last != Decimal::from(102) {}
+    }
+
+    #[tokio::test]
+    async fn test_quiet_symbol_falls_back_to_rest() {
+        let (url, mut connections) = mock_server().await;
+        let feed = QuoteFeed::spawn(&config(&url, HumanDuration::from_millis(100)), MarketDataProvider::new(), Duration::from_millis(50));
+        let mut quotes = feed.subscribe(&["MSFT".to_string()]);
+        let mut socket = connections.recv().await.unwrap();
+        next_control(&mut socket).await;
+
+        // Nothing streamed: the REST provider fills in
+        let polled = next_quote(&mut quotes).await;
+        assert_eq!((polled.symbol.as_str(), polled.last), ("MSFT", Decimal::from(100)));
+
+        // Streaming resumes and takes over
+        send_quote(&mut socket, "MSFT", 123).await;
+        while next_quote(&mut quotes).await.last != Decimal::from(123) {}
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe_is_reference_counted() {
+        let (url, mut connections) = mock_server().await;
+        let feed = QuoteFeed::spawn(&config(&url, HumanDuration::from_secs(60)), MarketDataProvider::new(), Duration::from_secs(60));
+        let both = feed.subscribe(&["AAPL".to_string(), "MSFT".to_string()]);
+        let mut aapl = feed.subscribe(&["AAPL".to_string()]);
+        let mut socket = connections.recv().await.unwrap();
+        assert_eq!(next_control(&mut socket).await.1, ["AAPL", "MSFT"]);
+
+        // AAPL is still wanted, so only MSFT is dropped upstream
+        drop(both);
+        assert_eq!(next_control(&mut socket).await, ("unsubscribe".to_string(), vec!["MSFT".to_string()]));
+        send_quote(&mut socket, "AAPL", 150).await;
+        assert_eq!(next_quote(&mut aapl).await.last, Decimal::from(150));
+
+        drop(aapl);
+        assert_eq!(next_control(&mut socket).await, ("unsubscribe".to_string(), vec!["AAPL".to_string()]));
+    }
+
+    #[test]
+    fn test_symbol_refs() {
+        let mut refs = SymbolRefs::default();
+        let (aapl, msft) = ("AAPL".to_string(), "MSFT".to_string());
+        assert_eq!(refs.acquire([&aapl, &msft]), [aapl.clone(), msft.clone()]);
+        assert!(refs.acquire([&aapl]).is_empty());
+        assert!(refs.release([&aapl]).is_empty());
+        assert_eq!(refs.release([&aapl, &msft]), [aapl, msft]);
+        assert!(refs.active().is_empty());
+    }
+}
@@ -1,12 +1,49 @@
 pub mod pricing;
 pub mod portfolio;
+pub mod portfolio_store;
 pub mod risk;
 pub mod market_data;
+pub mod market_stream;
+pub mod export;
+pub mod binomial;
+pub mod hedging;
+pub mod attribution;
+pub mod rebalance;
+pub mod sizing;
+pub mod order_book;
+pub mod strategy;
+pub mod plugin;
+pub mod watch;
+pub mod remote;
+pub mod performance;
+pub mod watchlist;
+pub mod account;
+pub mod corporate_actions;
+pub mod cost_basis;
+pub mod fixed_income;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
+use libp2p::PeerId;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::p2p::handle::NodeHandle;
+use crate::p2p::protocol::{QuantraRequest, QuantraResponse};
+use remote::{PricingInputs, PricingModel, PricingResult, RemotePricingConfig};
+
+/// `[quant]` section
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuantSettings {
+    pub remote_pricing: RemotePricingConfig,
+    pub watchlist: watchlist::WatchlistConfig,
+    pub stream: market_stream::StreamConfig,
+    pub market_data: market_data::MarketDataConfig,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Asset {
@@ -16,8 +53,11 @@ pub struct Asset {
     pub exchange: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Asset class; also picks a position's margin rates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum AssetType {
+    #[default]
     Stock,
     Option,
     Future,
@@ -26,6 +66,39 @@ pub enum AssetType {
     Bond,
 }
 
+impl FromStr for AssetType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "stock" => Ok(Self::Stock),
+            "option" => Ok(Self::Option),
+            "future" => Ok(Self::Future),
+            "crypto" => Ok(Self::Crypto),
+            "forex" => Ok(Self::Forex),
+            "bond" => Ok(Self::Bond),
+            other => anyhow::bail!(
+                "Unknown asset type '{}' (expected stock, option, future, crypto, forex or bond)",
+                other
+            ),
+        }
+    }
+}
+
+impl fmt::Display for AssetType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Stock => "stock",
+            Self::Option => "option",
+            Self::Future => "future",
+            Self::Crypto => "crypto",
+            Self::Forex => "forex",
+            Self::Bond => "bond",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Quote {
     pub symbol: String,
@@ -36,6 +109,18 @@ pub struct Quote {
     pub timestamp: DateTime<Utc>,
 }
 
+/// OHLCV bar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Candle {
+    pub symbol: String,
+    pub timestamp: DateTime<Utc>,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Trade {
     pub id: String,
@@ -54,12 +139,44 @@ pub enum TradeSide {
 
 pub struct QuantEngine {
     market_data: market_data::MarketDataProvider,
+    pricing: PricingBackend,
+}
+
+/// Where option prices are computed
+enum PricingBackend {
+    Local,
+    Remote { node: NodeHandle, peer: PeerId, timeout: Duration, fallback_to_local: bool },
 }
 
 impl QuantEngine {
     pub fn new() -> Self {
         Self {
             market_data: market_data::MarketDataProvider::new(),
+            pricing: PricingBackend::Local,
+        }
+    }
+
+    /// Engine quoting from `source` instead of the mock
+    pub fn with_source(source: Box<dyn market_data::MarketDataSource>) -> Self {
+        Self::with_provider(market_data::MarketDataProvider::with_source(source))
+    }
+
+    /// Engine quoting from `provider`, sharing its source
+    pub fn with_provider(market_data: market_data::MarketDataProvider) -> Self {
+        Self { market_data, pricing: PricingBackend::Local }
+    }
+
+    /// Engine that has `peer` price options, asking through `node`. Falls
+    /// back to pricing locally if `config.fallback_to_local` is set
+    pub fn remote(node: NodeHandle, peer: PeerId, config: &RemotePricingConfig) -> Self {
+        Self {
+            market_data: market_data::MarketDataProvider::new(),
+            pricing: PricingBackend::Remote {
+                node,
+                peer,
+                timeout: config.timeout.as_std(),
+                fallback_to_local: config.fallback_to_local,
+            },
         }
     }
 
@@ -67,6 +184,16 @@ impl QuantEngine {
         self.market_data.get_quote(symbol).await
     }
 
+    /// `interval` candles from the last `days` days, oldest first
+    pub async fn get_history(&self, symbol: &str, interval: market_data::Interval, days: u32) -> Result<Vec<Candle>> {
+        self.market_data.get_history(symbol, interval, days).await
+    }
+
+    /// L2 depth with `levels` levels per side
+    pub async fn get_depth(&self, symbol: &str, levels: usize) -> Result<market_data::OrderBookSnapshot> {
+        self.market_data.get_depth(symbol, levels).await
+    }
+
     pub async fn calculate_option_price(
         &self,
         spot: f64,
@@ -76,10 +203,71 @@ impl QuantEngine {
         time_to_expiry: f64,
         option_type: pricing::OptionType,
     ) -> Result<f64> {
-        pricing::black_scholes(spot, strike, rate, volatility, time_to_expiry, option_type)
+        let inputs = PricingInputs { spot, strike, rate, dividend_yield: 0.0, volatility, time_to_expiry, option_type };
+        Ok(self.price_option(&inputs, &PricingModel::BlackScholes).await?.price)
     }
 
-    pub async fn calculate_portfolio_var(&self, portfolio: &portfolio::Portfolio, confidence: f64) -> Result<f64> {
-        risk::calculate_var(portfolio, confidence).await
+    pub async fn price_option(&self, inputs: &PricingInputs, model: &PricingModel) -> Result<PricingResult> {
+        let request = QuantraRequest::PriceOption { inputs: *inputs, model: *model };
+        self.price(request, || remote::price_locally(inputs, model).map(|r| vec![r]))
+            .await?
+            .pop()
+            .context("Pricing node returned no result")
+    }
+
+    /// Price the same option at each of `strikes`
+    pub async fn price_chain(&self, inputs: &PricingInputs, strikes: &[f64], model: &PricingModel) -> Result<Vec<PricingResult>> {
+        let request = QuantraRequest::PriceChain { inputs: *inputs, strikes: strikes.to_vec(), model: *model };
+        let results = self.price(request, || remote::price_chain_locally(inputs, strikes, model)).await?;
+        if results.len() != strikes.len() {
+            anyhow::bail!("Pricing node returned {} results for {} strikes", results.len(), strikes.len());
+        }
+        Ok(results)
+    }
+
+    async fn price(&self, request: QuantraRequest, local: impl FnOnce() -> Result<Vec<PricingResult>>) -> Result<Vec<PricingResult>> {
+        let PricingBackend::Remote { node, peer, timeout, fallback_to_local } = &self.pricing else {
+            return local();
+        };
+        let answer = match tokio::time::timeout(*timeout, node.request(*peer, request)).await {
+            Ok(Ok(QuantraResponse::OptionPriced(result))) => Ok(vec![result]),
+            Ok(Ok(QuantraResponse::ChainPriced(results))) => Ok(results),
+            Ok(Ok(QuantraResponse::Error(e) | QuantraResponse::InvalidRequest { reason: e })) => {
+                Err(anyhow::anyhow!("{} refused to price: {}", peer, e))
+            }
+            Ok(Ok(other)) => Err(anyhow::anyhow!("Unexpected response from {}: {:?}", peer, other)),
+            Ok(Err(e)) => Err(e.context(format!("Pricing request to {} failed", peer))),
+            Err(_) => Err(anyhow::anyhow!("{} did not answer within {:?}", peer, timeout)),
+        };
+        match answer {
+            Ok(results) => {
+                if let Some(mismatch) = results.iter().find(|r| r.model_version != remote::MODEL_VERSION) {
+                    tracing::warn!(
+                        "📐 {} prices with {}, this node with {}; results may differ",
+                        peer, mismatch.model_version, remote::MODEL_VERSION
+                    );
+                }
+                Ok(results)
+            }
+            Err(e) if *fallback_to_local => {
+                tracing::warn!("📐 Remote pricing failed, pricing locally: {:#}", e);
+                local()
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn calculate_portfolio_var(
+        &self,
+        portfolio: &portfolio::Portfolio,
+        confidence: f64,
+        method: &risk::VarMethod,
+    ) -> Result<risk::VarResult> {
+        match method {
+            risk::VarMethod::Parametric => risk::parametric_var(portfolio, confidence),
+            risk::VarMethod::Historical { returns, horizon_days } => {
+                risk::historical_var(portfolio, returns, confidence, *horizon_days)
+            }
+        }
     }
 }
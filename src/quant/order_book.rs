@@ -0,0 +1,251 @@
+//! Local Order Book
+//! Maintains an L2 book from a snapshot plus sequenced deltas, detecting
+//! sequence gaps and crossed books that require a fresh snapshot
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use super::market_data::{OrderBookDelta, OrderBookSnapshot, PriceLevel};
+
+/// Result of applying a delta
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeltaOutcome {
+    Applied,
+    /// Already covered by the current state; ignored
+    Stale,
+    /// Sequence gap; the book is invalid until resnapshotted
+    Gap { expected: u64, received: u64 },
+    /// Delta would cross the book; rejected and the book needs a resnapshot
+    Crossed,
+    /// Waiting for a snapshot after an earlier gap or crossed delta
+    AwaitingSnapshot,
+}
+
+impl DeltaOutcome {
+    /// Whether the caller should request a new snapshot
+    pub fn needs_resnapshot(&self) -> bool {
+        matches!(self, Self::Gap { .. } | Self::Crossed)
+    }
+}
+
+/// L2 order book for one symbol
+#[derive(Debug, Clone)]
+pub struct OrderBook {
+    symbol: String,
+    bids: BTreeMap<Reverse<Decimal>, Decimal>,
+    asks: BTreeMap<Decimal, Decimal>,
+    sequence: u64,
+    updated_at: DateTime<Utc>,
+    awaiting_snapshot: bool,
+}
+
+impl OrderBook {
+    /// Build from a snapshot; crossed snapshots are rejected
+    pub fn from_snapshot(snapshot: &OrderBookSnapshot) -> Result<Self> {
+        let mut book = Self {
+            symbol: snapshot.symbol.clone(),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            sequence: 0,
+            updated_at: snapshot.timestamp,
+            awaiting_snapshot: false,
+        };
+        book.reset(snapshot)?;
+        Ok(book)
+    }
+
+    /// Replace the book with a snapshot
+    pub fn reset(&mut self, snapshot: &OrderBookSnapshot) -> Result<()> {
+        let bids = snapshot
+            .bids
+            .iter()
+            .filter(|(_, size)| !size.is_zero())
+            .map(|(price, size)| (Reverse(*price), *size))
+            .collect();
+        let asks = snapshot
+            .asks
+            .iter()
+            .filter(|(_, size)| !size.is_zero())
+            .map(|(price, size)| (*price, *size))
+            .collect();
+        if is_crossed(&bids, &asks) {
+            anyhow::bail!("Crossed snapshot for {} at sequence {}", snapshot.symbol, snapshot.sequence);
+        }
+
+        self.bids = bids;
+        self.asks = asks;
+        self.sequence = snapshot.sequence;
+        self.updated_at = snapshot.timestamp;
+        self.awaiting_snapshot = false;
+        Ok(())
+    }
+
+    /// Apply a sequenced delta
+    pub fn apply(&mut self, delta: &OrderBookDelta) -> DeltaOutcome {
+        if delta.sequence <= self.sequence {
+            return DeltaOutcome::Stale;
+        }
+        if self.awaiting_snapshot {
+            return DeltaOutcome::AwaitingSnapshot;
+        }
+        if delta.sequence != self.sequence + 1 {
+            self.awaiting_snapshot = true;
+            tracing::warn!(
+                "📚 {} book gap: expected sequence {}, got {}",
+                self.symbol,
+                self.sequence + 1,
+                delta.sequence
+            );
+            return DeltaOutcome::Gap {
+                expected: self.sequence + 1,
+                received: delta.sequence,
+            };
+        }
+
+        let mut bids = self.bids.clone();
+        let mut asks = self.asks.clone();
+        for (price, size) in &delta.bids {
+            if size.is_zero() {
+                bids.remove(&Reverse(*price));
+            } else {
+                bids.insert(Reverse(*price), *size);
+            }
+        }
+        for (price, size) in &delta.asks {
+            if size.is_zero() {
+                asks.remove(price);
+            } else {
+                asks.insert(*price, *size);
+            }
+        }
+        if is_crossed(&bids, &asks) {
+            self.awaiting_snapshot = true;
+            tracing::warn!("📚 {} delta {} would cross the book; rejected", self.symbol, delta.sequence);
+            return DeltaOutcome::Crossed;
+        }
+
+        self.bids = bids;
+        self.asks = asks;
+        self.sequence = delta.sequence;
+        self.updated_at = delta.timestamp;
+        DeltaOutcome::Applied
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn is_awaiting_snapshot(&self) -> bool {
+        self.awaiting_snapshot
+    }
+
+    pub fn best_bid(&self) -> Option<PriceLevel> {
+        self.bids.iter().next().map(|(Reverse(p), s)| (*p, *s))
+    }
+
+    pub fn best_ask(&self) -> Option<PriceLevel> {
+        self.asks.iter().next().map(|(p, s)| (*p, *s))
+    }
+
+    /// Top `levels` per side as a snapshot
+    pub fn snapshot(&self, levels: usize) -> OrderBookSnapshot {
+        OrderBookSnapshot {
+            symbol: self.symbol.clone(),
+            bids: self.bids.iter().take(levels).map(|(Reverse(p), s)| (*p, *s)).collect(),
+            asks: self.asks.iter().take(levels).map(|(p, s)| (*p, *s)).collect(),
+            sequence: self.sequence,
+            timestamp: self.updated_at,
+        }
+    }
+}
+
+fn is_crossed(bids: &BTreeMap<Reverse<Decimal>, Decimal>, asks: &BTreeMap<Decimal, Decimal>) -> bool {
+    match (bids.keys().next(), asks.keys().next()) {
+        (Some(Reverse(bid)), Some(ask)) => bid >= ask,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn snapshot(sequence: u64) -> OrderBookSnapshot {
+        OrderBookSnapshot {
+            symbol: "AAPL".to_string(),
+            bids: vec![(dec("99.99"), dec("100")), (dec("99.98"), dec("200"))],
+            asks: vec![(dec("100.01"), dec("150")), (dec("100.02"), dec("300"))],
+            sequence,
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn delta(sequence: u64, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> OrderBookDelta {
+        let levels = |side: &[(&str, &str)]| side.iter().map(|(p, s)| (dec(p), dec(s))).collect();
+        OrderBookDelta {
+            symbol: "AAPL".to_string(),
+            sequence,
+            bids: levels(bids),
+            asks: levels(asks),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_apply_deltas() {
+        let mut book = OrderBook::from_snapshot(&snapshot(10)).unwrap();
+
+        // Remove the best bid, add a new best ask
+        assert_eq!(book.apply(&delta(11, &[("99.99", "0")], &[("100.005", "50")])), DeltaOutcome::Applied);
+        assert_eq!(book.best_bid(), Some((dec("99.98"), dec("200"))));
+        assert_eq!(book.best_ask(), Some((dec("100.005"), dec("50"))));
+
+        // Size change at an existing level; replay is ignored
+        assert_eq!(book.apply(&delta(12, &[("99.98", "75")], &[])), DeltaOutcome::Applied);
+        assert_eq!(book.apply(&delta(12, &[("99.98", "1")], &[])), DeltaOutcome::Stale);
+        let top = book.snapshot(1);
+        assert_eq!(top.bids, vec![(dec("99.98"), dec("75"))]);
+        assert_eq!(top.sequence, 12);
+    }
+
+    #[test]
+    fn test_gap_requires_resnapshot() {
+        let mut book = OrderBook::from_snapshot(&snapshot(10)).unwrap();
+
+        let outcome = book.apply(&delta(13, &[("99.97", "10")], &[]));
+        assert_eq!(outcome, DeltaOutcome::Gap { expected: 11, received: 13 });
+        assert!(outcome.needs_resnapshot());
+        assert_eq!(book.apply(&delta(14, &[], &[])), DeltaOutcome::AwaitingSnapshot);
+
+        // Fresh snapshot resumes delta processing from its sequence
+        book.reset(&snapshot(14)).unwrap();
+        assert_eq!(book.apply(&delta(15, &[("99.97", "10")], &[])), DeltaOutcome::Applied);
+        assert_eq!(book.sequence(), 15);
+    }
+
+    #[test]
+    fn test_crossed_book_rejected() {
+        let mut book = OrderBook::from_snapshot(&snapshot(1)).unwrap();
+        let before = book.snapshot(10);
+
+        assert_eq!(book.apply(&delta(2, &[("100.01", "5")], &[])), DeltaOutcome::Crossed);
+        assert_eq!(book.snapshot(10), before);
+        assert!(book.is_awaiting_snapshot());
+
+        let mut crossed = snapshot(3);
+        crossed.asks[0].0 = dec("99.99");
+        assert!(OrderBook::from_snapshot(&crossed).is_err());
+    }
+}
@@ -0,0 +1,547 @@
+//! Portfolio Performance
+//! Time-weighted return (geometrically linked sub-period returns between
+//! valuations) and money-weighted return (IRR over the dated cash flows),
+//! from the daily valuations and deposits / withdrawals in the portfolio
+//! store.
+//!
+//! Conventions:
+//! - Valuations are end-of-day values (positions plus cash)
+//! - A flow happens at the close of its date, after that day's market
+//!   move, so the sub-period ending that day returns `(V - F) / V_prev - 1`
+//! - A flow dated between two valuations counts towards the later one
+//! - Flows on the same day are netted
+//! - Flows up to and including the period's first valuation are part of
+//!   its starting value
+//! - Sub-periods starting from a value of zero or less have no return and
+//!   are left out of the linking
+
+use anyhow::{Context, Result};
+use chrono::{Datelike, NaiveDate};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use super::market_data::MarketDataProvider;
+use super::portfolio_store::PortfolioStore;
+use crate::scheduler::{DailySpec, Scheduler};
+use crate::units::HumanDuration;
+
+/// Scheduler task name of the daily valuation
+pub const TASK_NAME: &str = "portfolio.valuation";
+
+/// Day count of the annualization helpers and the IRR
+const DAYS_PER_YEAR: f64 = 365.0;
+
+/// Newton's method for the IRR starts here
+const IRR_GUESS: f64 = 0.1;
+const IRR_TOLERANCE: f64 = 1e-10;
+const IRR_MAX_ITERATIONS: usize = 100;
+
+/// Rates probed for a sign change when Newton's method fails; the IRR is
+/// searched between -99.9% and 100,000% a year
+const IRR_BRACKETS: &[f64] = &[-0.999, -0.99, -0.9, -0.5, -0.2, 0.0, 0.1, 0.2, 0.5, 1.0, 2.0, 5.0, 10.0, 100.0, 1000.0];
+
+/// `[portfolio.performance]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PerformanceConfig {
+    /// Record a valuation every day while `alerts run` (or `p2p --alerts`) runs
+    pub record_daily: bool,
+    /// Local time of day, `HH:MM`
+    #[serde(with = "crate::maintenance::hh_mm")]
+    pub at: chrono::NaiveTime,
+    /// Each valuation starts up to this long after `at`
+    pub jitter: HumanDuration,
+}
+
+impl Default for PerformanceConfig {
+    fn default() -> Self {
+        Self {
+            record_daily: true,
+            at: chrono::NaiveTime::from_hms_opt(16, 30, 0).expect("16:30 is a valid time"),
+            jitter: HumanDuration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// End-of-day portfolio value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Valuation {
+    pub date: NaiveDate,
+    pub value: Decimal,
+    /// Positions valued at their last known price because no quote came
+    #[serde(default)]
+    pub stale: Vec<String>,
+}
+
+/// External deposit (positive) or withdrawal (negative)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CashFlow {
+    pub id: String,
+    pub date: NaiveDate,
+    pub amount: Decimal,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+impl CashFlow {
+    pub fn new(date: NaiveDate, amount: Decimal, note: Option<String>) -> Self {
+        Self { id: uuid::Uuid::new_v4().simple().to_string()[..8].to_string(), date, amount, note }
+    }
+}
+
+/// Return between two dates
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PeriodReturn {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub ret: f64,
+}
+
+/// Returns between consecutive valuations (sorted by date)
+pub fn sub_period_returns(valuations: &[Valuation], flows: &[CashFlow]) -> Vec<PeriodReturn> {
+    valuations
+        .windows(2)
+        .filter_map(|pair| {
+            let (prev, cur) = (&pair[0], &pair[1]);
+            let start = prev.value.to_f64()?;
+            if start <= 0.0 {
+                return None;
+            }
+            let flow = net_flow(flows, prev.date, cur.date);
+            Some(PeriodReturn { start: prev.date, end: cur.date, ret: (cur.value.to_f64()? - flow) / start - 1.0 })
+        })
+        .collect()
+}
+
+/// Geometric linking: the product of the growth factors, less one
+pub fn link(returns: impl IntoIterator<Item = f64>) -> f64 {
+    returns.into_iter().fold(1.0, |growth, r| growth * (1.0 + r)) - 1.0
+}
+
+/// Time-weighted return over `valuations`
+pub fn time_weighted_return(valuations: &[Valuation], flows: &[CashFlow]) -> f64 {
+    link(sub_period_returns(valuations, flows).iter().map(|p| p.ret))
+}
+
+/// Annual rate of a return earned over `days`
+pub fn annualize(ret: f64, days: i64) -> f64 {
+    (1.0 + ret).powf(DAYS_PER_YEAR / days as f64) - 1.0
+}
+
+/// Return over `days` at an annual rate
+pub fn deannualize(annual: f64, days: i64) -> f64 {
+    (1.0 + annual).powf(days as f64 / DAYS_PER_YEAR) - 1.0
+}
+
+/// Money-weighted return: the annual IRR of investing the first
+/// valuation, the flows after it, and receiving the last valuation
+pub fn money_weighted_return(valuations: &[Valuation], flows: &[CashFlow]) -> Result<f64> {
+    let (first, last) = match valuations {
+        [first, .., last] => (first, last),
+        _ => anyhow::bail!("IRR needs at least two valuations"),
+    };
+    let mut dated = vec![(first.date, -first.value.to_f64().unwrap_or_default())];
+    dated.extend(
+        flows
+            .iter()
+            .filter(|f| f.date > first.date && f.date <= last.date)
+            .map(|f| (f.date, -f.amount.to_f64().unwrap_or_default())),
+    );
+    dated.push((last.date, last.value.to_f64().unwrap_or_default()));
+    irr(&dated)
+}
+
+/// Annual rate at which the dated amounts have a net present value of
+/// zero (actual/365). Newton's method from 10%; when that diverges or
+/// stalls, bisection over the lowest rate in `IRR_BRACKETS` where the NPV
+/// changes sign. Flow patterns that change sign more than once can have
+/// several such rates; any one returned satisfies NPV = 0
+pub fn irr(flows: &[(NaiveDate, f64)]) -> Result<f64> {
+    if !(flows.iter().any(|(_, v)| *v > 0.0) && flows.iter().any(|(_, v)| *v < 0.0)) {
+        anyhow::bail!("IRR is undefined: the cash flows never change sign");
+    }
+    let origin = flows.iter().map(|(d, _)| *d).min().context("No cash flows")?;
+    let timed: Vec<(f64, f64)> =
+        flows.iter().map(|(d, v)| ((*d - origin).num_days() as f64 / DAYS_PER_YEAR, *v)).collect();
+    let npv = |r: f64| timed.iter().map(|(t, v)| v * (1.0 + r).powf(-t)).sum::<f64>();
+    let slope = |r: f64| timed.iter().map(|(t, v)| -t * v * (1.0 + r).powf(-t - 1.0)).sum::<f64>();
+    let scale = timed.iter().map(|(_, v)| v.abs()).fold(0.0, f64::max);
+
+    let mut rate = IRR_GUESS;
+    for _ in 0..IRR_MAX_ITERATIONS {
+        let (value, derivative) = (npv(rate), slope(rate));
+        if value.abs() <= IRR_TOLERANCE * scale {
+            return Ok(rate);
+        }
+        let next = rate - value / derivative;
+        if !next.is_finite() || next <= -1.0 {
+            break;
+        }
+        rate = next;
+    }
+
+    let (mut lo, mut hi) = IRR_BRACKETS
+        .windows(2)
+        .map(|w| (w[0], w[1]))
+        .find(|(a, b)| npv(*a) * npv(*b) <= 0.0)
+        .context("No IRR between -99.9% and 100,000% a year")?;
+    if npv(lo) == 0.0 {
+        return Ok(lo);
+    }
+    for _ in 0..200 {
+        let mid = (lo + hi) / 2.0;
+        if npv(lo) * npv(mid) <= 0.0 {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+        if hi - lo < IRR_TOLERANCE {
+            break;
+        }
+    }
+    Ok((lo + hi) / 2.0)
+}
+
+/// Net of the flows dated after `after`, up to and including `until`
+fn net_flow(flows: &[CashFlow], after: NaiveDate, until: NaiveDate) -> f64 {
+    flows
+        .iter()
+        .filter(|f| f.date > after && f.date <= until)
+        .map(|f| f.amount.to_f64().unwrap_or_default())
+        .sum()
+}
+
+/// Reporting window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportPeriod {
+    /// Since the last close of the previous year
+    Ytd,
+    /// The last 365 days
+    #[serde(rename = "1y")]
+    OneYear,
+    /// Everything recorded
+    All,
+}
+
+impl ReportPeriod {
+    /// First day of the window, `None` for everything
+    pub fn start(&self, today: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Self::Ytd => NaiveDate::from_ymd_opt(today.year() - 1, 12, 31),
+            Self::OneYear => Some(today - chrono::Duration::days(365)),
+            Self::All => None,
+        }
+    }
+}
+
+impl FromStr for ReportPeriod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "ytd" => Ok(Self::Ytd),
+            "1y" => Ok(Self::OneYear),
+            "all" => Ok(Self::All),
+            _ => anyhow::bail!("Unknown period '{}': use ytd, 1y or all", s),
+        }
+    }
+}
+
+impl fmt::Display for ReportPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ytd => "ytd",
+            Self::OneYear => "1y",
+            Self::All => "all",
+        })
+    }
+}
+
+/// Performance over one reporting window
+#[derive(Debug, Clone, Serialize)]
+pub struct PerformanceReport {
+    pub period: ReportPeriod,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub days: i64,
+    pub start_value: Decimal,
+    pub end_value: Decimal,
+    /// Deposits less withdrawals within the window
+    pub net_flows: Decimal,
+    /// Cumulative time-weighted return
+    pub twr: f64,
+    /// Only for windows of a year or more
+    pub twr_annualized: Option<f64>,
+    /// Annual money-weighted return
+    pub irr: Option<f64>,
+    /// `irr` over the window
+    pub irr_period: Option<f64>,
+    /// Why there is no IRR
+    pub irr_error: Option<String>,
+    /// Annualized standard deviation of the sub-period returns
+    pub volatility: Option<f64>,
+    pub best: Option<PeriodReturn>,
+    pub worst: Option<PeriodReturn>,
+    /// Calendar months, linked from the sub-periods ending in them
+    pub months: Vec<PeriodReturn>,
+}
+
+impl PerformanceReport {
+    /// Report on the valuations (sorted by date) in `period`. The window
+    /// starts at the last valuation on or before its first day
+    pub fn build(period: ReportPeriod, today: NaiveDate, valuations: &[Valuation], flows: &[CashFlow]) -> Result<Self> {
+        let first = match period.start(today) {
+            Some(start) => valuations.iter().rposition(|v| v.date <= start).unwrap_or(0),
+            None => 0,
+        };
+        let window = &valuations[first.min(valuations.len())..];
+        let (Some(start), Some(end)) = (window.first(), window.last()) else {
+            anyhow::bail!("No valuations recorded yet");
+        };
+        if window.len() < 2 {
+            anyhow::bail!("Need at least two valuations in the period; one is recorded each day while `alerts run` runs");
+        }
+
+        let periods = sub_period_returns(window, flows);
+        let days = (end.date - start.date).num_days();
+        let twr = link(periods.iter().map(|p| p.ret));
+        let (irr, irr_error) = match money_weighted_return(window, flows) {
+            Ok(rate) => (Some(rate), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let net_flows = flows.iter().filter(|f| f.date > start.date && f.date <= end.date).map(|f| f.amount).sum();
+
+        Ok(Self {
+            period,
+            start: start.date,
+            end: end.date,
+            days,
+            start_value: start.value,
+            end_value: end.value,
+            net_flows,
+            twr,
+            twr_annualized: (days >= DAYS_PER_YEAR as i64).then(|| annualize(twr, days)),
+            irr,
+            irr_period: irr.map(|rate| deannualize(rate, days)),
+            irr_error,
+            volatility: volatility(&periods),
+            best: periods.iter().copied().max_by(|a, b| a.ret.total_cmp(&b.ret)),
+            worst: periods.iter().copied().min_by(|a, b| a.ret.total_cmp(&b.ret)),
+            months: monthly(&periods),
+        })
+    }
+}
+
+/// Sample standard deviation of the returns, scaled to a year by their
+/// average length
+fn volatility(periods: &[PeriodReturn]) -> Option<f64> {
+    if periods.len() < 2 {
+        return None;
+    }
+    let n = periods.len() as f64;
+    let mean = periods.iter().map(|p| p.ret).sum::<f64>() / n;
+    let variance = periods.iter().map(|p| (p.ret - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    let average_days = periods.iter().map(|p| (p.end - p.start).num_days()).sum::<i64>() as f64 / n;
+    Some((variance * DAYS_PER_YEAR / average_days.max(1.0)).sqrt())
+}
+
+fn monthly(periods: &[PeriodReturn]) -> Vec<PeriodReturn> {
+    let mut months: Vec<(PeriodReturn, f64)> = Vec::new();
+    for period in periods {
+        match months.last_mut() {
+            Some((month, growth)) if (month.end.year(), month.end.month()) == (period.end.year(), period.end.month()) => {
+                month.end = period.end;
+                *growth *= 1.0 + period.ret;
+            }
+            _ => months.push((PeriodReturn { ret: 0.0, ..*period }, 1.0 + period.ret)),
+        }
+    }
+    months.into_iter().map(|(month, growth)| PeriodReturn { ret: growth - 1.0, ..month }).collect()
+}
+
+fn pct(value: f64) -> String {
+    format!("{:+.2}%", value * 100.0)
+}
+
+impl fmt::Display for PerformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "📈 Performance ({}): {} → {} ({} days)", self.period, self.start, self.end, self.days)?;
+        writeln!(f, "   {:<20} {:>14}", "Start value", self.start_value.round_dp(2))?;
+        writeln!(f, "   {:<20} {:>14}", "End value", self.end_value.round_dp(2))?;
+        writeln!(f, "   {:<20} {:>14}", "Net flows", self.net_flows.round_dp(2))?;
+        writeln!(f, "   {:<20} {:>14}", "TWR", pct(self.twr))?;
+        let annualized = self.twr_annualized.map_or_else(|| "- (under 1y)".to_string(), pct);
+        writeln!(f, "   {:<20} {:>14}", "TWR annualized", annualized)?;
+        match (self.irr, self.irr_period) {
+            (Some(irr), Some(period)) => {
+                writeln!(f, "   {:<20} {:>14}", "IRR annualized", pct(irr))?;
+                writeln!(f, "   {:<20} {:>14}", "IRR over period", pct(period))?;
+            }
+            _ => writeln!(f, "   {:<20} {:>14}  {}", "IRR", "-", self.irr_error.as_deref().unwrap_or_default())?,
+        }
+        let volatility = self.volatility.map_or_else(|| "-".to_string(), |v| format!("{:.2}%", v * 100.0));
+        writeln!(f, "   {:<20} {:>14}", "Volatility (ann.)", volatility)?;
+        if let (Some(best), Some(worst)) = (self.best, self.worst) {
+            writeln!(f, "   {:<20} {:>14}  {} → {}", "Best period", pct(best.ret), best.start, best.end)?;
+            writeln!(f, "   {:<20} {:>14}  {} → {}", "Worst period", pct(worst.ret), worst.start, worst.end)?;
+        }
+        if !self.months.is_empty() {
+            writeln!(f, "   {:<10} {:>10}", "MONTH", "RETURN")?;
+            for month in &self.months {
+                writeln!(f, "   {:<10} {:>10}", month.end.format("%Y-%m"), pct(month.ret))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Records the daily valuation of the profile's portfolio
+pub struct PerformanceTracker {
+    store: Arc<PortfolioStore>,
+    provider: MarketDataProvider,
+}
+
+impl PerformanceTracker {
+    pub fn new(store: Arc<PortfolioStore>, provider: MarketDataProvider) -> Self {
+        Self { store, provider }
+    }
+
+    /// Value every position at its quote, plus cash, and record it for
+    /// `date` (replacing an earlier valuation that day). A position without
+    /// a quote keeps its last known price and is listed as stale. Positions
+    /// aren't updated, so this never races the risk monitor's writes
+    pub async fn record_valuation(&self, date: NaiveDate) -> Result<Valuation> {
+        let portfolio = self.store.load()?;
+        let mut value = self.store.cash()?;
+        let mut stale = Vec::new();
+        for position in portfolio.positions.values() {
+            let price = match self.provider.get_quote(&position.symbol).await {
+                Ok(quote) => quote.last,
+                Err(e) => {
+                    tracing::warn!("📈 No quote for {} ({}); using its last price", position.symbol, e);
+                    stale.push(position.symbol.clone());
+                    position.current_price
+                }
+            };
+            value += position.quantity * price;
+        }
+        stale.sort();
+        let valuation = Valuation { date, value, stale };
+        self.store.put_valuation(&valuation)?;
+        tracing::info!("📈 Portfolio valued at {} on {}", valuation.value.round_dp(2), date);
+        Ok(valuation)
+    }
+
+    /// Record a valuation daily on `scheduler`
+    pub fn schedule(self: Arc<Self>, scheduler: &mut Scheduler, config: &PerformanceConfig) -> Result<()> {
+        let spec = DailySpec { at: config.at, jitter: config.jitter.as_std(), timeout: std::time::Duration::from_secs(300) };
+        scheduler.register_daily(TASK_NAME, spec, move || {
+            let tracker = self.clone();
+            async move { tracker.record_valuation(chrono::Local::now().date_naive()).await.map(|_| ()) }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn valuation(d: &str, value: i64) -> Valuation {
+        Valuation { date: date(d), value: Decimal::from(value), stale: Vec::new() }
+    }
+
+    fn flow(d: &str, amount: i64) -> CashFlow {
+        CashFlow::new(date(d), Decimal::from(amount), None)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_twr_and_irr_differ_with_flow_timing() {
+        // +10% then -10% either way, so the manager's TWR is -1% in both;
+        // the investor's IRR depends on how much was invested for the loss
+        let valuations = [valuation("2025-01-01", 1000), valuation("2025-07-01", 11100), valuation("2026-01-01", 9990)];
+        let flows = [flow("2025-07-01", 10000)];
+        assert_close(time_weighted_return(&valuations, &flows), -0.01);
+        assert_close(money_weighted_return(&valuations, &flows).unwrap(), -0.161321340);
+
+        let valuations = [valuation("2025-01-01", 10000), valuation("2025-07-01", 2000), valuation("2026-01-01", 1800)];
+        let flows = [flow("2025-07-01", -9000)];
+        assert_close(time_weighted_return(&valuations, &flows), -0.01);
+        assert_close(money_weighted_return(&valuations, &flows).unwrap(), 0.142529435);
+    }
+
+    #[test]
+    fn test_flow_conventions() {
+        // Same-day flows net, and a flow between valuations joins the next one
+        let valuations = [valuation("2026-03-02", 1000), valuation("2026-03-04", 2100)];
+        let split = [flow("2026-03-03", 600), flow("2026-03-03", 400)];
+        let single = [flow("2026-03-04", 1000)];
+        assert_eq!(sub_period_returns(&valuations, &split), sub_period_returns(&valuations, &single));
+        assert_close(time_weighted_return(&valuations, &split), 0.1);
+
+        // Flows up to the first valuation are starting capital, and a
+        // period from zero has no return
+        let valuations = [valuation("2026-01-01", 0), valuation("2026-01-02", 5000), valuation("2026-01-03", 5500)];
+        let flows = [flow("2025-12-31", 99), flow("2026-01-02", 5000)];
+        let periods = sub_period_returns(&valuations, &flows);
+        assert_eq!(periods.len(), 1);
+        assert_close(periods[0].ret, 0.1);
+    }
+
+    #[test]
+    fn test_irr_on_sign_changing_flows() {
+        // -100, +230, -132 has NPV zero at both 10% and 20%
+        let flows = [(date("2021-01-01"), -100.0), (date("2022-01-01"), 230.0), (date("2023-01-01"), -132.0)];
+        let rate = irr(&flows).unwrap();
+        assert!((rate - 0.1).abs() < 1e-6 || (rate - 0.2).abs() < 1e-6, "{}", rate);
+
+        // A loan: money in first, repaid with interest
+        let loan = [(date("2021-01-01"), 1000.0), (date("2022-01-01"), -1100.0)];
+        assert_close(irr(&loan).unwrap(), 0.1);
+
+        // Newton overshoots below -100%; bisection finds -99%
+        let wipeout = [(date("2021-01-01"), -100.0), (date("2022-01-01"), 1.0)];
+        assert_close(irr(&wipeout).unwrap(), -0.99);
+
+        // Beyond 100% a year
+        let tenfold = [(date("2021-01-01"), -100.0), (date("2022-01-01"), 1000.0)];
+        assert_close(irr(&tenfold).unwrap(), 9.0);
+
+        let inflows_only = [(date("2021-01-01"), 100.0), (date("2022-01-01"), 50.0)];
+        assert!(irr(&inflows_only).unwrap_err().to_string().contains("never change sign"));
+    }
+
+    #[test]
+    fn test_report_windows_and_months() {
+        let valuations = [
+            valuation("2025-12-31", 1000),
+            valuation("2026-01-15", 1100),
+            valuation("2026-01-31", 1210),
+            valuation("2026-02-28", 1089),
+        ];
+        let report = PerformanceReport::build(ReportPeriod::Ytd, date("2026-03-01"), &valuations, &[]).unwrap();
+        assert_eq!(report.start, date("2025-12-31"));
+        assert_close(report.twr, 0.089);
+        assert!(report.twr_annualized.is_none());
+        assert_eq!(report.months.len(), 2);
+        assert_close(report.months[0].ret, 0.21);
+        assert_close(report.months[1].ret, -0.1);
+        assert_eq!(report.worst.unwrap().end, date("2026-02-28"));
+        // No flows: the IRR over the window equals the TWR
+        assert_close(report.irr_period.unwrap(), report.twr);
+
+        assert!(PerformanceReport::build(ReportPeriod::All, date("2026-03-01"), &valuations[..1], &[]).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+//! Strategy Plugins
+//! Runs user strategies compiled to WebAssembly. Each plugin gets its own
+//! store with capped memory and a fuel budget per callback, and is linked
+//! against nothing (no WASI, no host functions), so all it can see is the
+//! JSON the host passes in.
+//!
+//! A plugin module exports:
+//! - `memory`
+//! - `alloc(len: i32) -> i32`: a buffer the host copies an argument into
+//! - `init(ptr: i32, len: i32) -> i32`: the strategy config as JSON; 0 on success
+//! - `on_candle(ptr: i32, len: i32) -> i32`: a `Candle` as JSON, answered with a signal
+//! - `on_quote(ptr: i32, len: i32) -> i32` (optional): a `Quote` as JSON
+//!
+//! Signals are 1 (buy), -1 (sell) and 0 (hold). See tests/plugins/momentum
+//! for a plugin written in Rust
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use wasmtime::{Engine, Instance, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, Trap, TypedFunc};
+
+use super::strategy::{Signal, Strategy};
+use super::{Candle, Quote};
+use crate::units::HumanSize;
+
+/// `[portfolio.strategy]` sandbox limits
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginLimits {
+    /// Fuel (about one unit per wasm instruction) for each callback
+    pub fuel_per_call: u64,
+    /// Linear memory cap
+    pub max_memory: HumanSize,
+}
+
+impl Default for PluginLimits {
+    fn default() -> Self {
+        Self {
+            fuel_per_call: 10_000_000,
+            max_memory: HumanSize::from_mib(16),
+        }
+    }
+}
+
+/// Why a plugin can't be loaded or stopped answering
+#[derive(Debug, Clone, PartialEq)]
+pub enum PluginError {
+    /// Not a usable module: bad wasm, imports, missing exports, too much memory
+    Invalid(String),
+    /// `init` returned this nonzero code
+    InitFailed(i32),
+    OutOfFuel { callback: &'static str },
+    /// A trap, including a Rust panic inside the plugin
+    Trap { callback: &'static str, message: String },
+    InvalidSignal { callback: &'static str, value: i32 },
+    /// An earlier callback failed; the instance is not entered again
+    Poisoned,
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(reason) => write!(f, "invalid strategy plugin: {}", reason),
+            Self::InitFailed(code) => write!(f, "strategy init failed with code {}", code),
+            Self::OutOfFuel { callback } => write!(f, "{} ran out of fuel", callback),
+            Self::Trap { callback, message } => write!(f, "{} trapped: {}", callback, message),
+            Self::InvalidSignal { callback, value } => {
+                write!(f, "{} returned {} (expected 1, -1 or 0)", callback, value)
+            }
+            Self::Poisoned => write!(f, "strategy failed earlier and was stopped"),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+struct HostState {
+    limits: StoreLimits,
+}
+
+type Callback = TypedFunc<(i32, i32), i32>;
+
+/// A loaded plugin, ready for callbacks
+pub struct WasmStrategy {
+    name: String,
+    store: Store<HostState>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    on_candle: Callback,
+    on_quote: Option<Callback>,
+    fuel_per_call: u64,
+    poisoned: bool,
+}
+
+impl WasmStrategy {
+    /// Load a `.wasm` (or `.wat`) file and initialize it with `config`
+    pub fn load(path: &Path, config: &serde_json::Value, limits: &PluginLimits) -> Result<Self> {
+        let wasm = std::fs::read(path).with_context(|| format!("Failed to read strategy {}", path.display()))?;
+        let name = path.file_stem().map_or("plugin".into(), |s| s.to_string_lossy());
+        Ok(Self::from_bytes(&name, &wasm, config, limits)?)
+    }
+
+    pub fn from_bytes(
+        name: &str,
+        wasm: &[u8],
+        config: &serde_json::Value,
+        limits: &PluginLimits,
+    ) -> std::result::Result<Self, PluginError> {
+        let invalid = |e: anyhow::Error| PluginError::Invalid(format!("{:#}", e));
+        let mut engine_config = wasmtime::Config::new();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config).map_err(invalid)?;
+        let module = Module::new(&engine, wasm).map_err(invalid)?;
+        if let Some(import) = module.imports().next() {
+            return Err(PluginError::Invalid(format!(
+                "imports {}::{}; plugins get no host functions",
+                import.module(),
+                import.name()
+            )));
+        }
+
+        let max_memory = usize::try_from(limits.max_memory.bytes()).unwrap_or(usize::MAX);
+        let state = HostState {
+            limits: StoreLimitsBuilder::new().memory_size(max_memory).instances(1).build(),
+        };
+        let mut store = Store::new(&engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(limits.fuel_per_call).map_err(invalid)?;
+        let instance = Instance::new(&mut store, &module, &[]).map_err(invalid)?;
+
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| PluginError::Invalid("no exported memory".to_string()))?;
+        let export = |store: &mut Store<HostState>, name: &str| {
+            instance
+                .get_typed_func::<(i32, i32), i32>(store, name)
+                .map_err(|e| PluginError::Invalid(format!("{}: {:#}", name, e)))
+        };
+        let init = export(&mut store, "init")?;
+        let on_candle = export(&mut store, "on_candle")?;
+        let on_quote = match instance.get_func(&mut store, "on_quote") {
+            Some(_) => Some(export(&mut store, "on_quote")?),
+            None => None,
+        };
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&mut store, "alloc")
+            .map_err(|e| PluginError::Invalid(format!("alloc: {:#}", e)))?;
+
+        let mut strategy = Self {
+            name: name.to_string(),
+            store,
+            memory,
+            alloc,
+            on_candle,
+            on_quote,
+            fuel_per_call: limits.fuel_per_call,
+            poisoned: false,
+        };
+        match strategy.call("init", init, config.to_string().as_bytes())? {
+            0 => Ok(strategy),
+            code => Err(PluginError::InitFailed(code)),
+        }
+    }
+
+    /// Copy `payload` into the plugin and invoke `func` on it with a fresh fuel budget
+    fn call(&mut self, callback: &'static str, func: Callback, payload: &[u8]) -> std::result::Result<i32, PluginError> {
+        if self.poisoned {
+            return Err(PluginError::Poisoned);
+        }
+        let result = self.invoke(callback, func, payload);
+        self.poisoned = result.is_err();
+        result
+    }
+
+    fn invoke(&mut self, callback: &'static str, func: Callback, payload: &[u8]) -> std::result::Result<i32, PluginError> {
+        let trapped = |e: anyhow::Error| match e.downcast_ref::<Trap>() {
+            Some(Trap::OutOfFuel) => PluginError::OutOfFuel { callback },
+            _ => PluginError::Trap { callback, message: format!("{:#}", e) },
+        };
+        self.store
+            .set_fuel(self.fuel_per_call)
+            .map_err(|e| PluginError::Trap { callback, message: e.to_string() })?;
+        let len = i32::try_from(payload.len())
+            .map_err(|_| PluginError::Trap { callback, message: "argument too large".to_string() })?;
+        let ptr = self.alloc.call(&mut self.store, len).map_err(trapped)?;
+        self.memory.write(&mut self.store, ptr as u32 as usize, payload).map_err(|_| PluginError::Trap {
+            callback,
+            message: format!("alloc({}) returned out-of-bounds buffer {:#x}", len, ptr),
+        })?;
+        func.call(&mut self.store, (ptr, len)).map_err(trapped)
+    }
+
+    fn signal(&mut self, callback: &'static str, func: Callback, payload: &[u8]) -> Result<Signal> {
+        let value = self.call(callback, func, payload)?;
+        Signal::from_code(value).ok_or_else(|| {
+            self.poisoned = true;
+            PluginError::InvalidSignal { callback, value }.into()
+        })
+    }
+}
+
+impl Strategy for WasmStrategy {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn on_candle(&mut self, candle: &Candle) -> Result<Signal> {
+        let func = self.on_candle.clone();
+        self.signal("on_candle", func, &serde_json::to_vec(candle)?)
+    }
+
+    fn on_quote(&mut self, quote: &Quote) -> Result<Signal> {
+        match self.on_quote.clone() {
+            Some(func) => self.signal("on_quote", func, &serde_json::to_vec(quote)?),
+            None => Ok(Signal::Hold),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use rust_decimal::Decimal;
+
+    const MOMENTUM: &str = include_str!("../../tests/plugins/momentum.wat");
+
+    /// Reports whether "SECRET" appears anywhere in its memory, where every
+    /// argument it was given is kept; `on_quote` reads past the end of memory
+    const PROBE: &str = r#"(module
+      (memory (export "memory") 1)
+      (global $next (mut i32) (i32.const 1024))
+      (func (export "alloc") (param $len i32) (result i32)
+        (global.get $next)
+        (global.set $next (i32.add (global.get $next) (local.get $len))))
+      (func (export "init") (param i32 i32) (result i32) (i32.const 0))
+      (func (export "on_candle") (param i32 i32) (result i32)
+        (local $i i32)
+        (block $none
+          (loop $scan
+            (br_if $none (i32.gt_u (local.get $i) (i32.sub (i32.mul (memory.size) (i32.const 65536)) (i32.const 6))))
+            (if (i32.and (i32.eq (i32.load (local.get $i)) (i32.const 0x52434553))
+                         (i32.eq (i32.load16_u offset=4 (local.get $i)) (i32.const 0x5445)))
+              (then (return (i32.const 1))))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            (br $scan)))
+        (i32.const 0))
+      (func (export "on_quote") (param i32 i32) (result i32)
+        (i32.load (i32.const 0x7ffffff0))))"#;
+
+    fn candle(symbol: &str, close: i64) -> Candle {
+        let price = Decimal::new(close, 2);
+        Candle {
+            symbol: symbol.to_string(),
+            timestamp: Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap(),
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: 100,
+        }
+    }
+
+    fn quote() -> Quote {
+        Quote {
+            symbol: "AAPL".to_string(),
+            bid: Decimal::ONE,
+            ask: Decimal::ONE,
+            last: Decimal::ONE,
+            volume: 1,
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn load(wasm: &str, config: serde_json::Value) -> WasmStrategy {
+        WasmStrategy::from_bytes("test", wasm.as_bytes(), &config, &PluginLimits::default()).unwrap()
+    }
+
+    #[test]
+    fn test_momentum_signal_round_trip() {
+        let mut momentum = load(MOMENTUM, serde_json::json!({ "lookback": 2 }));
+        let closes = [10000, 10150, 10300, 10200, 9900, 9900, 10050];
+        let signals: Vec<Signal> = closes.iter().map(|&c| momentum.on_candle(&candle("AAPL", c)).unwrap()).collect();
+        use Signal::*;
+        assert_eq!(signals, [Hold, Hold, Buy, Buy, Sell, Sell, Buy]);
+
+        // No on_quote export: quotes are held
+        assert_eq!(momentum.on_quote(&quote()).unwrap(), Hold);
+
+        let bad = WasmStrategy::from_bytes("test", MOMENTUM.as_bytes(), &serde_json::json!({}), &PluginLimits::default());
+        assert_eq!(bad.err(), Some(PluginError::InitFailed(1)));
+    }
+
+    #[test]
+    fn test_infinite_loop_runs_out_of_fuel() {
+        let spin = r#"(module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 0))
+          (func (export "init") (param i32 i32) (result i32) (i32.const 0))
+          (func (export "on_candle") (param i32 i32) (result i32) (loop (br 0)) (i32.const 0)))"#;
+        let limits = PluginLimits { fuel_per_call: 100_000, ..Default::default() };
+        let mut plugin = WasmStrategy::from_bytes("spin", spin.as_bytes(), &serde_json::json!({}), &limits).unwrap();
+
+        let err = plugin.on_candle(&candle("AAPL", 100)).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&PluginError::OutOfFuel { callback: "on_candle" }));
+        let err = plugin.on_candle(&candle("AAPL", 100)).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&PluginError::Poisoned));
+    }
+
+    #[test]
+    fn test_plugin_memory_is_isolated() {
+        // The marker sits in host memory throughout, but a plugin only sees it when passed
+        let secret = String::from("SECRET-host-signing-key");
+        let config = serde_json::json!({ "note": "nothing to see" });
+        let mut probe = load(PROBE, config.clone());
+        assert_eq!(probe.on_candle(&candle("AAPL", 100)).unwrap(), Signal::Hold);
+        assert_eq!(probe.on_candle(&candle(&secret, 100)).unwrap(), Signal::Buy);
+
+        // Nor does it leak between instances of the same module
+        let mut told = load(PROBE, serde_json::json!({ "note": secret }));
+        assert_eq!(told.on_candle(&candle("AAPL", 100)).unwrap(), Signal::Buy);
+        let mut fresh = load(PROBE, config);
+        assert_eq!(fresh.on_candle(&candle("AAPL", 100)).unwrap(), Signal::Hold);
+
+        // Reads past its own memory trap instead of reaching host memory
+        let mut reader = load(PROBE, serde_json::json!({}));
+        let err = reader.on_quote(&quote()).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(PluginError::Trap { callback: "on_quote", .. })), "{}", err);
+
+        // Growing past the cap and importing host functions are refused
+        let greedy = r#"(module (memory (export "memory") 1000))"#;
+        let err = WasmStrategy::from_bytes("greedy", greedy.as_bytes(), &serde_json::Value::Null, &PluginLimits::default());
+        assert!(matches!(err.err(), Some(PluginError::Invalid(_))));
+        let wasi = r#"(module (import "wasi_snapshot_preview1" "fd_write" (func (param i32 i32 i32 i32) (result i32))))"#;
+        let err = WasmStrategy::from_bytes("wasi", wasi.as_bytes(), &serde_json::Value::Null, &PluginLimits::default());
+        assert!(err.err().unwrap().to_string().contains("wasi_snapshot_preview1::fd_write"));
+    }
+}
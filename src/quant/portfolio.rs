@@ -1,6 +1,59 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::data_dirs::DataDirs;
+
+/// `[portfolio]` configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PortfolioSettings {
+    /// Position and ledger database (default: `portfolio/` in the data directory)
+    pub store_path: Option<PathBuf>,
+    /// Simulated trading: risk rules with `auto_close` sell at the quote
+    pub paper_trading: bool,
+    /// Commission estimates for rebalance plans, charged on paper fills
+    pub fees: super::rebalance::FeeModel,
+    /// Sandbox limits for WebAssembly strategies
+    pub strategy: super::plugin::PluginLimits,
+    /// Daily valuations for `performance`
+    pub performance: super::performance::PerformanceConfig,
+    /// Paper-trading cash: starting balance, interest and margin
+    pub account: super::account::AccountSettings,
+    /// When splits and dividends are applied
+    pub corporate_actions: super::corporate_actions::CorporateActionSettings,
+    /// Which lots a sell closes, for `portfolio lots` and `portfolio gains`
+    pub cost_basis: super::cost_basis::CostBasisSettings,
+}
+
+impl PortfolioSettings {
+    /// Configured store path, or the profile's portfolio directory
+    pub fn store_path(&self, dirs: &DataDirs) -> Result<PathBuf> {
+        match &self.store_path {
+            Some(path) => Ok(path.clone()),
+            None => dirs.portfolio_dir(),
+        }
+    }
+}
+
+/// A portfolio file that doesn't parse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptPortfolio {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+impl fmt::Display for CorruptPortfolio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "portfolio file {} is not valid portfolio JSON ({})", self.path.display(), self.reason)
+    }
+}
+
+impl std::error::Error for CorruptPortfolio {}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Portfolio {
@@ -9,12 +62,114 @@ pub struct Portfolio {
     pub positions: HashMap<String, Position>,
 }
 
+/// Currency of positions opened without one
+pub const DEFAULT_CURRENCY: &str = "USD";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Position {
     pub symbol: String,
+    /// ISO 4217 code
+    pub currency: String,
     pub quantity: Decimal,
     pub average_cost: Decimal,
     pub current_price: Decimal,
+    /// Picks the margin rates in paper trading
+    #[serde(default)]
+    pub asset_type: super::AssetType,
+    /// Stop-loss / take-profit thresholds, if set
+    #[serde(default)]
+    pub risk: Option<RiskRule>,
+}
+
+/// Per-position exit thresholds (long positions)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiskRule {
+    /// % below the entry (or the high-water mark when trailing)
+    pub stop_loss_pct: Option<Decimal>,
+    /// % above the entry
+    pub take_profit_pct: Option<Decimal>,
+    /// Stop follows the highest mark seen
+    pub trailing: bool,
+    /// Highest mark above the entry since the rule was set
+    pub high_water_mark: Option<Decimal>,
+    /// Sell when triggered (paper trading only)
+    pub auto_close: bool,
+    /// Set once fired; the rule stays quiet until set again
+    pub triggered_at: Option<DateTime<Utc>>,
+}
+
+/// Which threshold was crossed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RiskTriggerKind {
+    StopLoss,
+    TrailingStop,
+    TakeProfit,
+}
+
+impl RiskTriggerKind {
+    /// Ledger `triggered_by` annotation
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::StopLoss => "stop_loss",
+            Self::TrailingStop => "trailing_stop",
+            Self::TakeProfit => "take_profit",
+        }
+    }
+}
+
+/// A crossed threshold and the price it sits at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RiskTrigger {
+    pub kind: RiskTriggerKind,
+    pub trigger_price: Decimal,
+}
+
+impl RiskRule {
+    pub fn stop_price(&self, average_cost: Decimal) -> Option<Decimal> {
+        let pct = self.stop_loss_pct?;
+        let base = match self.high_water_mark {
+            Some(mark) if self.trailing => mark,
+            _ => average_cost,
+        };
+        Some(base * (Decimal::ONE - pct / Decimal::ONE_HUNDRED))
+    }
+
+    pub fn take_profit_price(&self, average_cost: Decimal) -> Option<Decimal> {
+        let pct = self.take_profit_pct?;
+        Some(average_cost * (Decimal::ONE + pct / Decimal::ONE_HUNDRED))
+    }
+
+    /// Raise the trailing high-water mark; returns true if it moved
+    pub fn observe(&mut self, mark: Decimal, average_cost: Decimal) -> bool {
+        let current = self.high_water_mark.unwrap_or(average_cost);
+        if self.trailing && mark > current {
+            self.high_water_mark = Some(mark);
+            return true;
+        }
+        false
+    }
+
+    /// Threshold crossed by `mark`, if the rule is still armed
+    pub fn check(&self, mark: Decimal, average_cost: Decimal) -> Option<RiskTrigger> {
+        if self.triggered_at.is_some() {
+            return None;
+        }
+        if let Some(stop) = self.stop_price(average_cost).filter(|stop| mark <= *stop) {
+            let kind = if self.trailing && self.high_water_mark.is_some() {
+                RiskTriggerKind::TrailingStop
+            } else {
+                RiskTriggerKind::StopLoss
+            };
+            return Some(RiskTrigger { kind, trigger_price: stop });
+        }
+        self.take_profit_price(average_cost)
+            .filter(|target| mark >= *target)
+            .map(|target| RiskTrigger {
+                kind: RiskTriggerKind::TakeProfit,
+                trigger_price: target,
+            })
+    }
 }
 
 impl Portfolio {
@@ -36,9 +191,12 @@ impl Portfolio {
             })
             .or_insert(Position {
                 symbol,
+                currency: DEFAULT_CURRENCY.to_string(),
                 quantity,
                 average_cost: price,
                 current_price: price,
+                asset_type: super::AssetType::default(),
+                risk: None,
             });
     }
 
@@ -55,6 +213,25 @@ impl Portfolio {
         None
     }
 
+    /// Write as JSON through a temporary file in the same directory, so
+    /// readers and concurrent writers only ever see a whole file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension(format!("{:016x}.tmp", rand::random::<u64>()));
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write portfolio to {}", tmp.display()))?;
+        std::fs::rename(&tmp, path).map_err(|e| {
+            let _ = std::fs::remove_file(&tmp);
+            anyhow::Error::new(e).context(format!("Failed to write portfolio to {}", path.display()))
+        })
+    }
+
+    /// Read a file written by `save`
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_slice(&bytes)
+            .map_err(|e| CorruptPortfolio { path: path.to_path_buf(), reason: e.to_string() }.into())
+    }
+
     pub fn update_price(&mut self, symbol: &str, price: Decimal) {
         if let Some(pos) = self.positions.get_mut(symbol) {
             pos.current_price = price;
@@ -84,4 +261,136 @@ impl Portfolio {
             (pos.current_price - pos.average_cost) * pos.quantity
         })
     }
+
+    /// Plan the trades that move the positions to `targets` (weights
+    /// summing to 1), sells first. Held symbols are priced at `quotes`, or
+    /// else their current price; a target not held needs a quote and is
+    /// opened as a new position, with a warning on the plan
+    pub fn rebalance(
+        &self,
+        targets: &HashMap<String, Decimal>,
+        quotes: &HashMap<String, Decimal>,
+        constraints: &super::rebalance::RebalanceConstraints,
+    ) -> Result<super::rebalance::RebalancePlan> {
+        let mut prices: HashMap<String, Decimal> =
+            self.positions.iter().map(|(symbol, pos)| (symbol.clone(), pos.current_price)).collect();
+        prices.extend(quotes.iter().map(|(symbol, price)| (symbol.to_uppercase(), *price)));
+        super::rebalance::plan_rebalance(self, targets, &prices, constraints)
+    }
+
+    /// Attach (or replace) a position's risk rule; false if there is no position
+    pub fn set_risk_rule(&mut self, symbol: &str, rule: RiskRule) -> bool {
+        match self.positions.get_mut(symbol) {
+            Some(pos) => {
+                pos.risk = Some(rule);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quant::rebalance::RebalanceConstraints;
+    use std::str::FromStr;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_plain_stop_and_take_profit() {
+        let rule = RiskRule {
+            stop_loss_pct: Some(dec("5")),
+            take_profit_pct: Some(dec("10")),
+            ..Default::default()
+        };
+        let cost = dec("100");
+        assert_eq!(rule.stop_price(cost), Some(dec("95")));
+        assert_eq!(rule.check(dec("95.01"), cost), None);
+        assert_eq!(rule.check(dec("95"), cost).unwrap().kind, RiskTriggerKind::StopLoss);
+        assert_eq!(rule.check(dec("110"), cost).unwrap().kind, RiskTriggerKind::TakeProfit);
+
+        let fired = RiskRule { triggered_at: Some(Utc::now()), ..rule };
+        assert_eq!(fired.check(dec("50"), cost), None);
+    }
+
+    #[test]
+    fn test_trailing_stop_updates() {
+        let mut rule = RiskRule {
+            stop_loss_pct: Some(dec("5")),
+            trailing: true,
+            ..Default::default()
+        };
+        let cost = dec("100");
+        // Below the entry the stop stays anchored to it
+        assert!(!rule.observe(dec("98"), cost));
+        assert_eq!(rule.stop_price(cost), Some(dec("95")));
+
+        for (mark, moved) in [("104", true), ("110", true), ("107", false), ("112", true), ("111", false)] {
+            assert_eq!(rule.observe(dec(mark), cost), moved, "mark {}", mark);
+            assert!(rule.check(dec(mark), cost).is_none());
+        }
+        assert_eq!(rule.high_water_mark, Some(dec("112")));
+        assert_eq!(rule.stop_price(cost), Some(dec("106.4")));
+
+        let trigger = rule.check(dec("106.40"), cost).unwrap();
+        assert_eq!(trigger.kind, RiskTriggerKind::TrailingStop);
+        assert_eq!(trigger.trigger_price, dec("106.4"));
+        println!("✅ Trailing stop test PASSED!");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("portfolio.json");
+        let mut portfolio = Portfolio::new("main".to_string(), "Main".to_string());
+        portfolio.add_position("AAPL".to_string(), dec("10"), dec("150.25"));
+        portfolio.update_price("AAPL", dec("160"));
+        portfolio.save(&path).unwrap();
+        // Saving again replaces the file and leaves no temporary files behind
+        portfolio.save(&path).unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let loaded = Portfolio::load(&path).unwrap();
+        assert_eq!(loaded.name, "Main");
+        assert_eq!(loaded.position_pnl("AAPL"), Some(dec("97.50")));
+
+        std::fs::write(&path, b"{\"id\": ").unwrap();
+        let err = Portfolio::load(&path).unwrap_err();
+        assert_eq!(err.downcast_ref::<CorruptPortfolio>().map(|e| e.path.clone()), Some(path));
+    }
+
+    #[test]
+    fn test_rebalance_at_current_prices() {
+        let mut portfolio = Portfolio::new("main".to_string(), "Main".to_string());
+        portfolio.add_position("AAPL".to_string(), dec("30"), dec("100"));
+        portfolio.add_position("MSFT".to_string(), dec("10"), dec("100"));
+        portfolio.update_price("MSFT", dec("200"));
+
+        // 3000 + 2000: 50/50 sells 5 AAPL for 2.5 MSFT
+        let targets = HashMap::from([("AAPL".to_string(), dec("0.5")), ("MSFT".to_string(), dec("0.5"))]);
+        let constraints = RebalanceConstraints::default();
+        let plan = portfolio.rebalance(&targets, &HashMap::new(), &constraints).unwrap();
+        let legs: Vec<_> = plan.to_trades().iter().map(|t| (t.symbol.clone(), format!("{:?}", t.side), t.quantity, t.price)).collect();
+        let expected = [("AAPL", "Sell", "5", "100"), ("MSFT", "Buy", "2.5", "200")];
+        let expected: Vec<_> = expected.iter().map(|(s, side, q, p)| (s.to_string(), side.to_string(), dec(q), dec(p))).collect();
+        assert_eq!(legs, expected);
+        assert!(plan.warnings.is_empty());
+
+        // A target not held needs a quote, and opens a position with a warning
+        let targets = HashMap::from([("AAPL".to_string(), dec("0.5")), ("TSLA".to_string(), dec("0.5"))]);
+        assert!(portfolio.rebalance(&targets, &HashMap::new(), &constraints).is_err());
+        let quotes = HashMap::from([("tsla".to_string(), dec("300"))]);
+        let whole_shares = RebalanceConstraints { lot_size: dec("1"), min_trade_notional: dec("1000"), ..Default::default() };
+        let plan = portfolio.rebalance(&targets, &quotes, &whole_shares).unwrap();
+        assert_eq!(plan.new_positions, vec!["TSLA".to_string()]);
+        assert_eq!(plan.warnings.len(), 1);
+        // 5000 split evenly: all MSFT is sold, the 5 AAPL sale (500) is under
+        // the minimum, and the sale pays for 6 whole TSLA
+        let legs: Vec<_> = plan.trades.iter().map(|t| (t.symbol.as_str(), t.quantity)).collect();
+        assert_eq!(legs, vec![("MSFT", dec("10")), ("TSLA", dec("6"))]);
+    }
 }
@@ -0,0 +1,415 @@
+//! Portfolio Store
+//! Positions (with their risk rules), the trade ledger, the cash balance
+//! with its deposits / withdrawals, and daily valuations, persisted as JSON
+//! in one database so trailing-stop high-water marks survive restarts
+//!
+//! Trades settle against cash: buys debit it and sells credit it, along
+//! with any commission. Cash goes negative when buys were funded by money
+//! never recorded with `portfolio cash --in` (or borrowed on margin in
+//! paper trading). Interest posted to cash is kept per day
+//!
+//! Corporate actions are kept by symbol, ex-date and kind, and once applied
+//! are recorded in an action ledger by ex-date
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::account::{self, AccountSettings, InterestAccrual};
+use super::corporate_actions::{self, AppliedAction, CorporateAction};
+use super::cost_basis::{CostBasisMethod, CostBasisSettings, LotBook};
+use super::performance::{CashFlow, Valuation};
+use super::portfolio::{Portfolio, Position, DEFAULT_CURRENCY};
+use super::{Trade, TradeSide};
+use crate::migrations::{self, Migration, StoreSchema};
+use crate::search::{Query, SearchHit, SearchStore, Searchable, TopHits};
+use crate::storage::{KvStore, RuntimeMode};
+
+const PORTFOLIO_TREE: &str = "portfolio";
+const POSITION_PREFIX: &str = "position/";
+const LEDGER_PREFIX: &str = "ledger/";
+const CASH_KEY: &[u8] = b"cash";
+const CASH_FLOW_PREFIX: &str = "cashflow/";
+const VALUATION_PREFIX: &str = "valuation/";
+const INTEREST_PREFIX: &str = "interest/";
+/// Last day interest was accrued for
+const INTEREST_THROUGH_KEY: &[u8] = b"interest_through";
+const ACTION_PREFIX: &str = "action/";
+const APPLIED_ACTION_PREFIX: &str = "applied_action/";
+
+pub const SCHEMA: StoreSchema = StoreSchema {
+    name: "portfolio",
+    tree: Some(PORTFOLIO_TREE),
+    version: 2,
+    migrations: &[Migration {
+        from: 1,
+        description: "positions gain a currency (existing positions are USD)",
+        apply: add_position_currency,
+    }],
+    index: None,
+};
+
+/// A recorded trade
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    #[serde(flatten)]
+    pub trade: Trade,
+    /// What submitted the trade: a risk rule (`stop_loss`, `trailing_stop`,
+    /// `take_profit`) or `rebalance`; `None` for manual trades
+    #[serde(default)]
+    pub triggered_by: Option<String>,
+    /// Charged on top of the notional (paper fills)
+    #[serde(default)]
+    pub commission: Decimal,
+    /// Lots (buy trade ids) a sell closes under specific-lot cost basis
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lots: Vec<String>,
+}
+
+pub struct PortfolioStore {
+    db: Box<dyn KvStore>,
+}
+
+impl PortfolioStore {
+    /// Open the portfolio database at `path`, or keep it in memory when ephemeral
+    pub fn open(path: &Path, mode: RuntimeMode) -> Result<Self> {
+        let db = migrations::open_store(mode, path, &SCHEMA)
+            .with_context(|| format!("Failed to open portfolio store at {}", path.display()))?;
+        Ok(Self { db })
+    }
+
+    /// The profile's portfolio
+    pub fn load(&self) -> Result<Portfolio> {
+        let mut portfolio = Portfolio::new("default".to_string(), "Default".to_string());
+        for (key, bytes) in self.db.entries()? {
+            if key.starts_with(POSITION_PREFIX.as_bytes()) {
+                let position: Position = serde_json::from_slice(&bytes).context("Corrupt position")?;
+                portfolio.positions.insert(position.symbol.clone(), position);
+            }
+        }
+        Ok(portfolio)
+    }
+
+    /// Insert or replace a position
+    pub fn put_position(&self, position: &Position) -> Result<()> {
+        self.db.insert(&position_key(&position.symbol), &serde_json::to_vec(position)?)?;
+        self.db.flush()
+    }
+
+    /// Apply a trade to `portfolio`, persist the position and record the trade
+    pub fn execute(&self, portfolio: &mut Portfolio, trade: Trade, triggered_by: Option<String>) -> Result<()> {
+        self.fill(portfolio, trade, Decimal::ZERO, triggered_by)
+    }
+
+    /// `execute` each trade in order, as read back from a ledger export.
+    /// Trade ids already in the ledger are skipped, so re-importing a file
+    /// changes nothing. Returns how many were recorded and skipped
+    pub fn import_trades(&self, portfolio: &mut Portfolio, trades: impl IntoIterator<Item = Result<Trade>>) -> Result<(usize, usize)> {
+        let mut known = std::collections::HashSet::new();
+        self.visit_ledger(&mut |entry| {
+            known.insert(entry.trade.id);
+            Ok(true)
+        })?;
+        let (mut recorded, mut skipped) = (0, 0);
+        for trade in trades {
+            let trade = trade?;
+            if !known.insert(trade.id.clone()) {
+                skipped += 1;
+                continue;
+            }
+            self.execute(portfolio, trade, None)?;
+            recorded += 1;
+        }
+        Ok((recorded, skipped))
+    }
+
+    /// `execute`, also debiting `commission` from cash
+    pub fn fill(
+        &self,
+        portfolio: &mut Portfolio,
+        trade: Trade,
+        commission: Decimal,
+        triggered_by: Option<String>,
+    ) -> Result<()> {
+        self.fill_lots(portfolio, trade, commission, triggered_by, Vec::new())
+    }
+
+    /// `fill` for a sell closing `lots` (buy trade ids), first checked to
+    /// be open lots of the symbol that cover the quantity
+    pub fn fill_lots(
+        &self,
+        portfolio: &mut Portfolio,
+        trade: Trade,
+        commission: Decimal,
+        triggered_by: Option<String>,
+        lots: Vec<String>,
+    ) -> Result<()> {
+        if !lots.is_empty() {
+            anyhow::ensure!(matches!(trade.side, TradeSide::Sell), "Only a sell closes lots");
+            let settings = CostBasisSettings { method: CostBasisMethod::SpecificLot, ..CostBasisSettings::default() };
+            LotBook::replay(&settings, &self.ledger()?, &self.applied_actions()?)?.check_selection(
+                &trade.symbol,
+                trade.quantity,
+                &lots,
+            )?;
+        }
+        match trade.side {
+            TradeSide::Buy => portfolio.add_position(trade.symbol.clone(), trade.quantity, trade.price),
+            TradeSide::Sell => portfolio
+                .remove_position(&trade.symbol, trade.quantity)
+                .with_context(|| format!("Cannot sell {} {}: position too small", trade.quantity, trade.symbol))?,
+        }
+        match portfolio.positions.get(&trade.symbol) {
+            Some(position) => self.db.insert(&position_key(&trade.symbol), &serde_json::to_vec(position)?)?,
+            None => self.db.remove(&position_key(&trade.symbol))?,
+        }
+        let notional = trade.quantity * trade.price;
+        let cash = match trade.side {
+            TradeSide::Buy => self.cash()? - notional,
+            TradeSide::Sell => self.cash()? + notional,
+        } - commission;
+        self.db.insert(CASH_KEY, &serde_json::to_vec(&cash)?)?;
+
+        let key = format!(
+            "{}{:020}/{}",
+            LEDGER_PREFIX,
+            trade.timestamp.timestamp_nanos_opt().unwrap_or_default(),
+            trade.id
+        );
+        let entry = LedgerEntry { trade, triggered_by, commission, lots };
+        self.db.insert(key.as_bytes(), &serde_json::to_vec(&entry)?)?;
+        self.db.flush()
+    }
+
+    /// Recorded trades, oldest first
+    pub fn ledger(&self) -> Result<Vec<LedgerEntry>> {
+        self.prefixed(LEDGER_PREFIX, "Corrupt ledger entry")
+    }
+
+    /// Call `visit` on each recorded trade, oldest first, decoding one at a
+    /// time, until it returns `false`
+    pub fn visit_ledger(&self, visit: &mut dyn FnMut(LedgerEntry) -> Result<bool>) -> Result<()> {
+        self.db.visit_prefix(LEDGER_PREFIX.as_bytes(), &mut |_, bytes| {
+            visit(serde_json::from_slice(bytes).context("Corrupt ledger entry")?)
+        })
+    }
+
+    /// Uninvested cash
+    pub fn cash(&self) -> Result<Decimal> {
+        match self.db.get(CASH_KEY)? {
+            Some(bytes) => serde_json::from_slice(&bytes).context("Corrupt cash balance"),
+            None => Ok(Decimal::ZERO),
+        }
+    }
+
+    /// Record a deposit or withdrawal and apply it to cash; returns the new balance
+    pub fn record_cash_flow(&self, flow: &CashFlow) -> Result<Decimal> {
+        let cash = self.cash()? + flow.amount;
+        let key = format!("{}{}/{}", CASH_FLOW_PREFIX, flow.date, flow.id);
+        self.db.insert(key.as_bytes(), &serde_json::to_vec(flow)?)?;
+        self.db.insert(CASH_KEY, &serde_json::to_vec(&cash)?)?;
+        self.db.flush()?;
+        Ok(cash)
+    }
+
+    /// Deposits and withdrawals, oldest first
+    pub fn cash_flows(&self) -> Result<Vec<CashFlow>> {
+        self.prefixed(CASH_FLOW_PREFIX, "Corrupt cash flow")
+    }
+
+    /// Deposit `amount` on `date` as the starting cash, unless cash was
+    /// ever recorded; true if it was deposited
+    pub fn seed_cash(&self, amount: Decimal, date: NaiveDate) -> Result<bool> {
+        if self.db.get(CASH_KEY)?.is_some() || amount <= Decimal::ZERO {
+            return Ok(false);
+        }
+        self.record_cash_flow(&CashFlow::new(date, amount, Some("starting cash".to_string())))?;
+        Ok(true)
+    }
+
+    /// Post a day's interest to cash for every day after the last accrual
+    /// through `through`, each on the balance the day before. The first
+    /// call only starts the clock
+    pub fn accrue_interest(&self, through: NaiveDate, settings: &AccountSettings) -> Result<Vec<InterestAccrual>> {
+        let last: Option<NaiveDate> = match self.db.get(INTEREST_THROUGH_KEY)? {
+            Some(bytes) => Some(serde_json::from_slice(&bytes).context("Corrupt interest date")?),
+            None => None,
+        };
+        let mut accrued = Vec::new();
+        if let Some(mut day) = last {
+            if day >= through {
+                return Ok(accrued);
+            }
+            let mut cash = self.cash()?;
+            while day < through {
+                day = day.succ_opt().context("Interest date out of range")?;
+                let amount = account::daily_interest(cash, settings);
+                if amount.is_zero() {
+                    continue;
+                }
+                let accrual = InterestAccrual { date: day, balance: cash, amount };
+                let key = format!("{}{}", INTEREST_PREFIX, day);
+                self.db.insert(key.as_bytes(), &serde_json::to_vec(&accrual)?)?;
+                cash += amount;
+                accrued.push(accrual);
+            }
+            self.db.insert(CASH_KEY, &serde_json::to_vec(&cash)?)?;
+        }
+        self.db.insert(INTEREST_THROUGH_KEY, &serde_json::to_vec(&through)?)?;
+        self.db.flush()?;
+        Ok(accrued)
+    }
+
+    /// Posted interest (negative when charged), oldest first
+    pub fn interest(&self) -> Result<Vec<InterestAccrual>> {
+        self.prefixed(INTEREST_PREFIX, "Corrupt interest entry")
+    }
+
+    /// Record a corporate action, replacing one with the same symbol,
+    /// ex-date and kind; false if it was already recorded as is
+    pub fn put_action(&self, action: &CorporateAction) -> Result<bool> {
+        let key = format!("{}{}", ACTION_PREFIX, action.key());
+        let bytes = serde_json::to_vec(action)?;
+        if self.db.get(key.as_bytes())?.as_deref() == Some(bytes.as_slice()) {
+            return Ok(false);
+        }
+        self.db.insert(key.as_bytes(), &bytes)?;
+        self.db.flush()?;
+        Ok(true)
+    }
+
+    /// Recorded corporate actions, by symbol then ex-date
+    pub fn actions(&self) -> Result<Vec<CorporateAction>> {
+        self.prefixed(ACTION_PREFIX, "Corrupt corporate action")
+    }
+
+    /// Applied corporate actions, oldest ex-date first
+    pub fn applied_actions(&self) -> Result<Vec<AppliedAction>> {
+        self.prefixed(APPLIED_ACTION_PREFIX, "Corrupt applied corporate action")
+    }
+
+    /// Apply `action` to `portfolio`, persist the position and any cash
+    /// credit and record it in the action ledger. `None` if it was already
+    /// applied
+    pub fn apply_action(
+        &self,
+        portfolio: &mut Portfolio,
+        action: &CorporateAction,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<AppliedAction>> {
+        let key = format!("{}{}/{}/{}", APPLIED_ACTION_PREFIX, action.ex_date, action.symbol, action.kind.name());
+        if self.db.get(key.as_bytes())?.is_some() {
+            return Ok(None);
+        }
+        let applied = corporate_actions::apply(portfolio, &self.ledger()?, action, now);
+        if let Some(position) = portfolio.positions.get(&action.symbol) {
+            self.db.insert(&position_key(&position.symbol), &serde_json::to_vec(position)?)?;
+        }
+        if !applied.cash.is_zero() {
+            self.db.insert(CASH_KEY, &serde_json::to_vec(&(self.cash()? + applied.cash))?)?;
+        }
+        self.db.insert(key.as_bytes(), &serde_json::to_vec(&applied)?)?;
+        self.db.flush()?;
+        Ok(Some(applied))
+    }
+
+    /// Record the day's valuation, replacing an earlier one that day
+    pub fn put_valuation(&self, valuation: &Valuation) -> Result<()> {
+        let key = format!("{}{}", VALUATION_PREFIX, valuation.date);
+        self.db.insert(key.as_bytes(), &serde_json::to_vec(valuation)?)?;
+        self.db.flush()
+    }
+
+    /// Daily valuations, oldest first
+    pub fn valuations(&self) -> Result<Vec<Valuation>> {
+        self.prefixed(VALUATION_PREFIX, "Corrupt valuation")
+    }
+
+    fn prefixed<T: serde::de::DeserializeOwned>(&self, prefix: &str, corrupt: &'static str) -> Result<Vec<T>> {
+        self.db
+            .scan_prefix(prefix.as_bytes())?
+            .into_iter()
+            .map(|(_, bytes)| serde_json::from_slice(&bytes).context(corrupt))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Searchable for PortfolioStore {
+    fn store(&self) -> SearchStore {
+        SearchStore::Portfolio
+    }
+
+    /// Held symbols come from position keys alone; trades are decoded one
+    /// at a time
+    async fn search(&self, query: &Query, limit: usize) -> Result<Vec<SearchHit>> {
+        let mut hits = TopHits::new(limit);
+        self.db.visit_prefix(POSITION_PREFIX.as_bytes(), &mut |key, _| {
+            let symbol = String::from_utf8_lossy(&key[POSITION_PREFIX.len()..]);
+            if let Some(rank) = query.rank(&symbol) {
+                hits.push(SearchHit::new(SearchStore::Portfolio, "symbol", symbol.as_ref(), query.snippet(&symbol), rank));
+            }
+            Ok(true)
+        })?;
+        self.db.visit_prefix(LEDGER_PREFIX.as_bytes(), &mut |_, bytes| {
+            let entry: LedgerEntry = serde_json::from_slice(bytes).context("Corrupt ledger entry")?;
+            let trade = &entry.trade;
+            let fields = [
+                ("trade_symbol", trade.symbol.as_str()),
+                ("trade_id", trade.id.as_str()),
+                ("triggered_by", entry.triggered_by.as_deref().unwrap_or_default()),
+            ];
+            if let Some((field, _, rank)) = query.rank_fields(&fields) {
+                let mut snippet = format!(
+                    "{:?} {} {} @ {} on {}",
+                    trade.side,
+                    trade.quantity,
+                    trade.symbol,
+                    trade.price,
+                    trade.timestamp.format("%Y-%m-%d")
+                );
+                if let Some(trigger) = &entry.triggered_by {
+                    snippet.push_str(&format!(" ({})", trigger));
+                }
+                hits.push(SearchHit::new(SearchStore::Portfolio, field, trade.id.as_str(), snippet, rank));
+            }
+            Ok(true)
+        })?;
+        Ok(hits.into_vec())
+    }
+}
+
+/// Market trade at `price`, stamped now
+pub fn market_trade(symbol: &str, side: TradeSide, quantity: Decimal, price: Decimal) -> Trade {
+    Trade {
+        id: uuid::Uuid::new_v4().simple().to_string()[..8].to_string(),
+        symbol: symbol.to_uppercase(),
+        side,
+        quantity,
+        price,
+        timestamp: crate::clock::now(),
+    }
+}
+
+/// v1 → v2: every position written before currencies existed was in USD
+fn add_position_currency(db: &dyn KvStore) -> Result<()> {
+    for (key, bytes) in db.entries()? {
+        if !key.starts_with(POSITION_PREFIX.as_bytes()) {
+            continue;
+        }
+        let mut position: serde_json::Value = serde_json::from_slice(&bytes).context("Corrupt position")?;
+        let fields = position.as_object_mut().context("Position is not an object")?;
+        if !fields.contains_key("currency") {
+            fields.insert("currency".to_string(), DEFAULT_CURRENCY.into());
+            db.insert(&key, &serde_json::to_vec(&position)?)?;
+        }
+    }
+    Ok(())
+}
+
+fn position_key(symbol: &str) -> Vec<u8> {
+    format!("{}{}", POSITION_PREFIX, symbol).into_bytes()
+}
@@ -0,0 +1,55 @@
+//! Cooperative Cancellation
+//! Long pricing loops poll a `CancelToken` and stop with `Cancelled` once it
+//! is cancelled or its deadline passes; the work done so far is dropped
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Shared between the caller and the pricing loop it may stop
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancelToken {
+    /// Fires only when cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also fires at `deadline`
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self { cancelled: Arc::default(), deadline: Some(deadline) }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+
+    /// `Err(Cancelled)` once fired
+    pub fn check(&self) -> Result<(), Cancelled> {
+        match self.is_cancelled() {
+            true => Err(Cancelled),
+            false => Ok(()),
+        }
+    }
+}
+
+/// A pricing run stopped by its `CancelToken`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("pricing cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
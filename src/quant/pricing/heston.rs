@@ -0,0 +1,452 @@
+//! Heston Stochastic Volatility
+//! European prices from the characteristic function in Albrecher et al.'s
+//! "little Heston trap" form, and calibration to market implied vols
+
+use anyhow::Result;
+use num_complex::Complex64;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use super::{implied_volatility, OptionType};
+
+/// Calibration search box: v0, kappa, theta, sigma_v, rho
+pub const CALIBRATION_BOUNDS: [(f64, f64); 5] = [
+    (1e-4, 1.0),
+    (1e-3, 10.0),
+    (1e-4, 1.0),
+    (1e-3, 2.0),
+    (-0.999, 0.999),
+];
+
+/// Nelder-Mead iterations per (re)start
+const MAX_ITERATIONS: usize = 2000;
+const RESTARTS: usize = 3;
+
+/// Heston model: dv = kappa (theta - v) dt + sigma_v sqrt(v) dW, corr(dW, dS) = rho
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HestonParams {
+    /// Initial variance
+    pub v0: f64,
+    /// Mean-reversion speed
+    pub kappa: f64,
+    /// Long-run variance
+    pub theta: f64,
+    /// Volatility of variance
+    pub sigma_v: f64,
+    /// Spot/variance correlation
+    pub rho: f64,
+}
+
+impl HestonParams {
+    pub fn validate(&self) -> Result<()> {
+        if self.v0 <= 0.0 || self.theta <= 0.0 {
+            anyhow::bail!("Heston v0 and theta must be positive");
+        }
+        if self.kappa <= 0.0 || self.sigma_v <= 0.0 {
+            anyhow::bail!("Heston kappa and sigma_v must be positive");
+        }
+        if !(-1.0..=1.0).contains(&self.rho) {
+            anyhow::bail!("Heston rho must be in [-1, 1]");
+        }
+        Ok(())
+    }
+
+    /// 2 kappa theta > sigma_v^2: variance never touches zero
+    pub fn feller_satisfied(&self) -> bool {
+        2.0 * self.kappa * self.theta > self.sigma_v * self.sigma_v
+    }
+
+    fn from_array(x: [f64; 5]) -> Self {
+        Self { v0: x[0], kappa: x[1], theta: x[2], sigma_v: x[3], rho: x[4] }
+    }
+}
+
+/// `v0,kappa,theta,sigma_v,rho`
+impl FromStr for HestonParams {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!("Invalid Heston parameters '{}': {}", s, e))?;
+        let values: [f64; 5] = values
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Heston parameters are v0,kappa,theta,sigma_v,rho (got '{}')", s))?;
+        let params = Self::from_array(values);
+        params.validate()?;
+        Ok(params)
+    }
+}
+
+impl fmt::Display for HestonParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "v0={:.6} kappa={:.4} theta={:.6} sigma_v={:.4} rho={:.4}",
+            self.v0, self.kappa, self.theta, self.sigma_v, self.rho
+        )
+    }
+}
+
+/// Characteristic function of ln S_T. The trap form keeps the complex log on
+/// its principal branch, so no rotation-count bookkeeping is needed.
+/// beta - d is rewritten as -sigma_v^2 a / (beta + d) so nothing divides by
+/// sigma_v^2, which keeps the small vol-of-vol limit accurate
+fn characteristic(u: Complex64, spot: f64, drift: f64, t: f64, p: &HestonParams) -> Complex64 {
+    let iu = Complex64::i() * u;
+    let sigma2 = p.sigma_v * p.sigma_v;
+    let a = iu + u * u;
+    let beta = p.kappa - p.rho * p.sigma_v * iu;
+    let d = (beta * beta + sigma2 * a).sqrt();
+    let beta_plus_d = beta + d;
+    let g = -sigma2 * a / (beta_plus_d * beta_plus_d);
+    let e = (-d * t).exp();
+
+    // ln((1 - g e) / (1 - g)) / sigma_v^2 = ln(1 + z) / sigma_v^2 with z = O(sigma_v^2)
+    let z_over_sigma2 = -a / (beta_plus_d * beta_plus_d) * (1.0 - e) / (1.0 - g);
+    let log_ratio = z_over_sigma2 * ln_1p_over_z(sigma2 * z_over_sigma2);
+
+    let c = drift * iu * t + p.kappa * p.theta * (-a * t / beta_plus_d - 2.0 * log_ratio);
+    let dv = -a / beta_plus_d * (1.0 - e) / (1.0 - g * e);
+    (c + dv * p.v0 + iu * spot.ln()).exp()
+}
+
+/// ln(1 + z) / z, by series near zero
+fn ln_1p_over_z(z: Complex64) -> Complex64 {
+    if z.norm() < 1e-5 {
+        1.0 - z / 2.0 + z * z / 3.0
+    } else {
+        (1.0 + z).ln() / z
+    }
+}
+
+/// European option price under Heston
+pub fn heston_price(
+    spot: f64,
+    strike: f64,
+    rate: f64,
+    dividend_yield: f64,
+    time_to_expiry: f64,
+    option_type: OptionType,
+    params: &HestonParams,
+) -> Result<f64> {
+    params.validate()?;
+    if spot <= 0.0 || strike <= 0.0 || time_to_expiry <= 0.0 {
+        anyhow::bail!("Spot, strike and time to expiry must be positive");
+    }
+    let t = time_to_expiry;
+    let drift = rate - dividend_yield;
+    let ln_k = strike.ln();
+
+    // C = S e^-qT P1 - K e^-rT P2, with both probabilities folded into one
+    // integrand; phi(-i) = S e^(r-q)T normalises the P1 term
+    let integrand = |u: f64| {
+        let z = Complex64::new(u, 0.0);
+        let phi1 = characteristic(z - Complex64::i(), spot, drift, t, params);
+        let phi2 = characteristic(z, spot, drift, t, params);
+        let value = ((-Complex64::i() * u * ln_k).exp() * (phi1 - strike * phi2) / (Complex64::i() * u)).re;
+        if value.is_finite() { value } else { 0.0 }
+    };
+    let integral = integrate_half_line(integrand);
+
+    let discount = (-rate * t).exp();
+    let forward_spot = spot * (-dividend_yield * t).exp();
+    let call = 0.5 * (forward_spot - strike * discount) + discount / PI * integral;
+    let price = match option_type {
+        OptionType::Call => call,
+        OptionType::Put => call - forward_spot + strike * discount,
+    };
+    Ok(price.max(0.0))
+}
+
+/// Quoted implied vol for calibration: (strike, expiry in years, vol)
+pub type VolQuote = (f64, f64, f64);
+
+/// Fitted parameters and how well they reproduce the quotes
+#[derive(Debug, Clone, Serialize)]
+pub struct HestonCalibration {
+    pub params: HestonParams,
+    /// Root mean squared implied-vol error
+    pub rmse: f64,
+    pub iterations: usize,
+    /// Reported only; calibration doesn't enforce it
+    pub feller_satisfied: bool,
+}
+
+/// Black-Scholes implied vol of the Heston price (no dividends), priced off
+/// the out-of-the-money side where the inversion is best conditioned
+pub fn heston_implied_vol(spot: f64, strike: f64, rate: f64, time_to_expiry: f64, params: &HestonParams) -> Result<f64> {
+    let option_type = if strike >= spot { OptionType::Call } else { OptionType::Put };
+    let price = heston_price(spot, strike, rate, 0.0, time_to_expiry, option_type, params)?;
+    implied_volatility(price, spot, strike, rate, time_to_expiry, option_type)
+}
+
+/// Fit Heston parameters to implied vols by Nelder-Mead on squared vol
+/// errors, inside `CALIBRATION_BOUNDS`
+pub fn calibrate_heston(market_quotes: &[VolQuote], spot: f64, rate: f64) -> Result<HestonCalibration> {
+    if market_quotes.len() < 5 {
+        anyhow::bail!("Heston calibration needs at least 5 quotes (got {})", market_quotes.len());
+    }
+    if market_quotes.iter().any(|&(k, t, vol)| k <= 0.0 || t <= 0.0 || vol <= 0.0) {
+        anyhow::bail!("Quotes need positive strike, expiry and implied vol");
+    }
+
+    let objective = |x: &[f64; 5]| {
+        let params = HestonParams::from_array(*x);
+        market_quotes
+            .iter()
+            .map(|&(strike, expiry, market)| match heston_implied_vol(spot, strike, rate, expiry, &params) {
+                Ok(model) => (model - market).powi(2),
+                // Unpriceable corner of the box: treat as a 100 vol-point miss
+                Err(_) => 1.0,
+            })
+            .sum::<f64>()
+            / market_quotes.len() as f64
+    };
+
+    // Start from the ATM term structure: short end for v0, long end for theta
+    let atm_vol = |expiry: f64| {
+        market_quotes
+            .iter()
+            .filter(|q| q.1 == expiry)
+            .min_by(|a, b| (a.0 - spot).abs().total_cmp(&(b.0 - spot).abs()))
+            .map_or(0.2, |q| q.2)
+    };
+    let short_vol = atm_vol(market_quotes.iter().map(|q| q.1).fold(f64::INFINITY, f64::min));
+    let long_vol = atm_vol(market_quotes.iter().map(|q| q.1).fold(0.0, f64::max));
+    let mut best = clamp([short_vol * short_vol, 2.0, long_vol * long_vol, 0.5, -0.5]);
+
+    let mut iterations = 0;
+    let mut value = f64::INFINITY;
+    for _ in 0..RESTARTS {
+        let (x, fx, n) = nelder_mead(&objective, best, MAX_ITERATIONS);
+        iterations += n;
+        let improved = fx < value - 1e-14;
+        best = x;
+        value = fx;
+        if !improved {
+            break;
+        }
+    }
+
+    let params = HestonParams::from_array(best);
+    Ok(HestonCalibration {
+        params,
+        rmse: value.sqrt(),
+        iterations,
+        feller_satisfied: params.feller_satisfied(),
+    })
+}
+
+fn clamp(mut x: [f64; 5]) -> [f64; 5] {
+    for (xi, (lo, hi)) in x.iter_mut().zip(CALIBRATION_BOUNDS) {
+        *xi = xi.clamp(lo, hi);
+    }
+    x
+}
+
+/// Nelder-Mead with every trial point projected into the bounds.
+/// Returns (argmin, minimum, iterations)
+fn nelder_mead(f: &impl Fn(&[f64; 5]) -> f64, start: [f64; 5], max_iterations: usize) -> ([f64; 5], f64, usize) {
+    let mut simplex: Vec<([f64; 5], f64)> = vec![(start, f(&start))];
+    for i in 0..5 {
+        let mut x = start;
+        let (lo, hi) = CALIBRATION_BOUNDS[i];
+        let step = 0.1 * (hi - lo);
+        x[i] = if x[i] + step <= hi { x[i] + step } else { x[i] - step };
+        let x = clamp(x);
+        simplex.push((x, f(&x)));
+    }
+
+    let mut iterations = 0;
+    while iterations < max_iterations {
+        iterations += 1;
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (best, worst) = (simplex[0].1, simplex[5].1);
+        if (worst - best).abs() <= 1e-12 * (1.0 + best.abs()) {
+            break;
+        }
+
+        let mut centroid = [0.0; 5];
+        for (x, _) in &simplex[..5] {
+            for i in 0..5 {
+                centroid[i] += x[i] / 5.0;
+            }
+        }
+        let along = |t: f64| {
+            let mut x = [0.0; 5];
+            for i in 0..5 {
+                x[i] = centroid[i] + t * (simplex[5].0[i] - centroid[i]);
+            }
+            let x = clamp(x);
+            (x, f(&x))
+        };
+
+        let reflected = along(-1.0);
+        if reflected.1 < best {
+            let expanded = along(-2.0);
+            simplex[5] = if expanded.1 < reflected.1 { expanded } else { reflected };
+        } else if reflected.1 < simplex[4].1 {
+            simplex[5] = reflected;
+        } else {
+            let contracted = if reflected.1 < worst { along(-0.5) } else { along(0.5) };
+            if contracted.1 < worst.min(reflected.1) {
+                simplex[5] = contracted;
+            } else {
+                // Shrink toward the best vertex
+                let anchor = simplex[0].0;
+                for vertex in simplex.iter_mut().skip(1) {
+                    let mut x = [0.0; 5];
+                    for i in 0..5 {
+                        x[i] = anchor[i] + 0.5 * (vertex.0[i] - anchor[i]);
+                    }
+                    *vertex = (x, f(&x));
+                }
+            }
+        }
+    }
+    simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+    (simplex[0].0, simplex[0].1, iterations)
+}
+
+/// Integral over [0, inf) via u = s / (1 - s) and adaptive Gauss-Kronrod
+fn integrate_half_line(f: impl Fn(f64) -> f64) -> f64 {
+    let mapped = |s: f64| {
+        let one_minus = 1.0 - s;
+        f(s / one_minus) / (one_minus * one_minus)
+    };
+    adaptive_gauss_kronrod(&mapped, 0.0, 1.0, 1e-10, 20)
+}
+
+fn adaptive_gauss_kronrod(f: &impl Fn(f64) -> f64, a: f64, b: f64, tolerance: f64, depth: u32) -> f64 {
+    let (kronrod, gauss) = gauss_kronrod_15(f, a, b);
+    if (kronrod - gauss).abs() <= tolerance || depth == 0 {
+        return kronrod;
+    }
+    let mid = 0.5 * (a + b);
+    adaptive_gauss_kronrod(f, a, mid, tolerance / 2.0, depth - 1)
+        + adaptive_gauss_kronrod(f, mid, b, tolerance / 2.0, depth - 1)
+}
+
+/// 15-point Kronrod estimate and its embedded 7-point Gauss estimate
+fn gauss_kronrod_15(f: &impl Fn(f64) -> f64, a: f64, b: f64) -> (f64, f64) {
+    const NODES: [f64; 8] = [
+        0.991_455_371_120_812_6,
+        0.949_107_912_342_758_5,
+        0.864_864_423_359_769_1,
+        0.741_531_185_599_394_4,
+        0.586_087_235_467_691_1,
+        0.405_845_151_377_397_2,
+        0.207_784_955_007_898_5,
+        0.0,
+    ];
+    const KRONROD: [f64; 8] = [
+        0.022_935_322_010_529_22,
+        0.063_092_092_629_978_55,
+        0.104_790_010_322_250_18,
+        0.140_653_259_715_525_92,
+        0.169_004_726_639_267_9,
+        0.190_350_578_064_785_4,
+        0.204_432_940_075_298_9,
+        0.209_482_141_084_727_83,
+    ];
+    // Gauss weights for the odd-indexed nodes (1, 3, 5, 7)
+    const GAUSS: [f64; 4] = [
+        0.129_484_966_168_869_7,
+        0.279_705_391_489_276_7,
+        0.381_830_050_505_118_9,
+        0.417_959_183_673_469_4,
+    ];
+
+    let center = 0.5 * (a + b);
+    let half = 0.5 * (b - a);
+    let f_center = f(center);
+    let mut kronrod = KRONROD[7] * f_center;
+    let mut gauss = GAUSS[3] * f_center;
+    for i in 0..7 {
+        let pair = f(center - half * NODES[i]) + f(center + half * NODES[i]);
+        kronrod += KRONROD[i] * pair;
+        if i % 2 == 1 {
+            gauss += GAUSS[i / 2] * pair;
+        }
+    }
+    (kronrod * half, gauss * half)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quant::pricing::black_scholes;
+
+    #[test]
+    fn test_small_vol_of_vol_converges_to_black_scholes() {
+        let theta: f64 = 0.04;
+        let bs = black_scholes(100.0, 105.0, 0.03, theta.sqrt(), 0.75, OptionType::Call).unwrap();
+        let mut last_error = f64::INFINITY;
+        for sigma_v in [0.1, 0.01, 0.001, 0.0001] {
+            let params = HestonParams { v0: theta, kappa: 1.5, theta, sigma_v, rho: -0.7 };
+            let heston = heston_price(100.0, 105.0, 0.03, 0.0, 0.75, OptionType::Call, &params).unwrap();
+            let error = (heston - bs).abs();
+            assert!(error < last_error, "sigma_v={} error {} did not shrink", sigma_v, error);
+            last_error = error;
+        }
+        assert!(last_error < 1e-4, "error {}", last_error);
+
+        // Put-call parity holds by construction
+        let params = HestonParams { v0: 0.05, kappa: 2.0, theta: 0.04, sigma_v: 0.4, rho: -0.6 };
+        let call = heston_price(100.0, 90.0, 0.02, 0.01, 1.0, OptionType::Call, &params).unwrap();
+        let put = heston_price(100.0, 90.0, 0.02, 0.01, 1.0, OptionType::Put, &params).unwrap();
+        let parity = 100.0 * (-0.01f64).exp() - 90.0 * (-0.02f64).exp();
+        assert!((call - put - parity).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_published_benchmarks() {
+        // Lewis (2000): S=100, r=1%, q=2%, T=1, sigma_v=1 (a branch-cut stress case)
+        let lewis = HestonParams { v0: 0.04, kappa: 4.0, theta: 0.25, sigma_v: 1.0, rho: -0.5 };
+        let expected = [
+            (80.0, 26.774_758_744),
+            (90.0, 20.933_349_001),
+            (100.0, 16.070_154_917),
+            (110.0, 12.132_211_517),
+            (120.0, 9.024_913_483),
+        ];
+        for (strike, price) in expected {
+            let got = heston_price(100.0, strike, 0.01, 0.02, 1.0, OptionType::Call, &lewis).unwrap();
+            assert!((got - price).abs() < 1e-4, "K={} got {} want {}", strike, got, price);
+        }
+
+        // Fang & Oosterlee (2008), ATM call with Feller violated
+        let cos = HestonParams { v0: 0.0175, kappa: 1.5768, theta: 0.0398, sigma_v: 0.5751, rho: -0.5711 };
+        assert!(!cos.feller_satisfied());
+        let got = heston_price(100.0, 100.0, 0.0, 0.0, 1.0, OptionType::Call, &cos).unwrap();
+        assert!((got - 5.785_155_450).abs() < 1e-4, "got {}", got);
+        println!("✅ Heston benchmark test PASSED!");
+    }
+
+    #[test]
+    fn test_calibration_recovers_model_parameters() {
+        let truth = HestonParams { v0: 0.03, kappa: 2.0, theta: 0.05, sigma_v: 0.5, rho: -0.6 };
+        let (spot, rate) = (100.0, 0.02);
+        let mut quotes = Vec::new();
+        for expiry in [0.25, 0.5, 1.0, 2.0] {
+            for strike in [80.0, 90.0, 100.0, 110.0, 120.0] {
+                quotes.push((strike, expiry, heston_implied_vol(spot, strike, rate, expiry, &truth).unwrap()));
+            }
+        }
+
+        let fit = calibrate_heston(&quotes, spot, rate).unwrap();
+        assert!(fit.rmse < 1e-4, "rmse {}", fit.rmse);
+        let fields = |p: HestonParams| [p.v0, p.kappa, p.theta, p.sigma_v, p.rho];
+        for (name, (g, w)) in ["v0", "kappa", "theta", "sigma_v", "rho"].iter().zip(fields(fit.params).into_iter().zip(fields(truth))) {
+            assert!((g - w).abs() <= 0.05 * w.abs(), "{}: got {} want {}", name, g, w);
+        }
+        assert_eq!(fit.feller_satisfied, truth.feller_satisfied());
+
+        assert!(calibrate_heston(&quotes[..3], spot, rate).is_err());
+    }
+}
@@ -0,0 +1,246 @@
+pub mod cancel;
+pub mod heston;
+pub mod monte_carlo;
+pub mod strategy;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use statrs::distribution::{Continuous, ContinuousCDF, Normal};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OptionType {
+    Call,
+    Put,
+}
+
+pub fn black_scholes(
+    spot: f64,
+    strike: f64,
+    rate: f64,
+    volatility: f64,
+    time_to_expiry: f64,
+    option_type: OptionType,
+) -> Result<f64> {
+    let normal = Normal::new(0.0, 1.0)?;
+
+    let d1 = ((spot / strike).ln() + (rate + volatility.powi(2) / 2.0) * time_to_expiry)
+        / (volatility * time_to_expiry.sqrt());
+    let d2 = d1 - volatility * time_to_expiry.sqrt();
+
+    let price = match option_type {
+        OptionType::Call => {
+            spot * normal.cdf(d1) - strike * (-rate * time_to_expiry).exp() * normal.cdf(d2)
+        }
+        OptionType::Put => {
+            strike * (-rate * time_to_expiry).exp() * normal.cdf(-d2) - spot * normal.cdf(-d1)
+        }
+    };
+
+    Ok(price)
+}
+
+/// Black-Scholes volatility that reproduces `price`, by bisection.
+/// Fails when the price is outside the no-arbitrage bounds
+pub fn implied_volatility(
+    price: f64,
+    spot: f64,
+    strike: f64,
+    rate: f64,
+    time_to_expiry: f64,
+    option_type: OptionType,
+) -> Result<f64> {
+    let discounted_strike = strike * (-rate * time_to_expiry).exp();
+    let (lower, upper) = match option_type {
+        OptionType::Call => ((spot - discounted_strike).max(0.0), spot),
+        OptionType::Put => ((discounted_strike - spot).max(0.0), discounted_strike),
+    };
+    if !(price > lower && price < upper) {
+        anyhow::bail!("Price {:.6} is outside the no-arbitrage range ({:.6}, {:.6})", price, lower, upper);
+    }
+
+    // Price is increasing in volatility
+    let (mut lo, mut hi) = (1e-6, 5.0);
+    for _ in 0..200 {
+        let mid = 0.5 * (lo + hi);
+        if black_scholes(spot, strike, rate, mid, time_to_expiry, option_type)? < price {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+        if hi - lo < 1e-12 {
+            break;
+        }
+    }
+    Ok(0.5 * (lo + hi))
+}
+
+pub fn calculate_greeks(
+    spot: f64,
+    strike: f64,
+    rate: f64,
+    volatility: f64,
+    time_to_expiry: f64,
+    option_type: OptionType,
+) -> Result<Greeks> {
+    let normal = Normal::new(0.0, 1.0)?;
+
+    let d1 = ((spot / strike).ln() + (rate + volatility.powi(2) / 2.0) * time_to_expiry)
+        / (volatility * time_to_expiry.sqrt());
+    let d2 = d1 - volatility * time_to_expiry.sqrt();
+
+    let delta = match option_type {
+        OptionType::Call => normal.cdf(d1),
+        OptionType::Put => normal.cdf(d1) - 1.0,
+    };
+
+    let gamma = normal.pdf(d1) / (spot * volatility * time_to_expiry.sqrt());
+
+    let vega = spot * normal.pdf(d1) * time_to_expiry.sqrt() / 100.0;
+
+    let theta = match option_type {
+        OptionType::Call => {
+            (-spot * normal.pdf(d1) * volatility / (2.0 * time_to_expiry.sqrt())
+                - rate * strike * (-rate * time_to_expiry).exp() * normal.cdf(d2))
+                / 365.0
+        }
+        OptionType::Put => {
+            (-spot * normal.pdf(d1) * volatility / (2.0 * time_to_expiry.sqrt())
+                + rate * strike * (-rate * time_to_expiry).exp() * normal.cdf(-d2))
+                / 365.0
+        }
+    };
+
+    let rho = match option_type {
+        OptionType::Call => {
+            strike * time_to_expiry * (-rate * time_to_expiry).exp() * normal.cdf(d2) / 100.0
+        }
+        OptionType::Put => {
+            -strike * time_to_expiry * (-rate * time_to_expiry).exp() * normal.cdf(-d2) / 100.0
+        }
+    };
+
+    Ok(Greeks {
+        delta,
+        gamma,
+        vega,
+        theta,
+        rho,
+    })
+}
+
+/// Bump sizes for `numerical_greeks`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GreekBumps {
+    /// Fraction of spot
+    pub spot: f64,
+    /// Fraction of volatility (at least 1e-4 absolute)
+    pub volatility: f64,
+    /// Years; shrunk to half the time left near expiry
+    pub time: f64,
+    /// Absolute
+    pub rate: f64,
+}
+
+impl Default for GreekBumps {
+    fn default() -> Self {
+        Self { spot: 0.01, volatility: 0.01, time: 1.0 / 365.0, rate: 1e-4 }
+    }
+}
+
+/// Central-difference Greeks of any model, in `calculate_greeks` units
+/// (vega and rho per 1%, theta per calendar day). `pricer` takes spot,
+/// strike, rate, volatility and time to expiry
+#[allow(clippy::too_many_arguments)]
+pub fn numerical_greeks<F>(
+    pricer: F,
+    spot: f64,
+    strike: f64,
+    rate: f64,
+    volatility: f64,
+    time_to_expiry: f64,
+    bumps: &GreekBumps,
+) -> Result<Greeks>
+where
+    F: Fn(f64, f64, f64, f64, f64) -> Result<f64>,
+{
+    if spot <= 0.0 || volatility <= 0.0 {
+        anyhow::bail!("Spot and volatility must be positive");
+    }
+    if time_to_expiry <= 0.0 {
+        anyhow::bail!("Greeks need time to expiry; the option has expired");
+    }
+    let price = pricer(spot, strike, rate, volatility, time_to_expiry)?;
+
+    let h = spot * bumps.spot;
+    let up = pricer(spot + h, strike, rate, volatility, time_to_expiry)?;
+    let down = pricer(spot - h, strike, rate, volatility, time_to_expiry)?;
+    let delta = (up - down) / (2.0 * h);
+    let gamma = (up - 2.0 * price + down) / (h * h);
+
+    // Keep the bumped volatility and time positive
+    let h = (volatility * bumps.volatility).max(1e-4).min(volatility / 2.0);
+    let vega = (pricer(spot, strike, rate, volatility + h, time_to_expiry)?
+        - pricer(spot, strike, rate, volatility - h, time_to_expiry)?)
+        / (2.0 * h)
+        / 100.0;
+
+    let h = bumps.time.min(time_to_expiry / 2.0);
+    let theta = -(pricer(spot, strike, rate, volatility, time_to_expiry + h)?
+        - pricer(spot, strike, rate, volatility, time_to_expiry - h)?)
+        / (2.0 * h)
+        / 365.0;
+
+    let h = bumps.rate;
+    let rho = (pricer(spot, strike, rate + h, volatility, time_to_expiry)?
+        - pricer(spot, strike, rate - h, volatility, time_to_expiry)?)
+        / (2.0 * h)
+        / 100.0;
+
+    Ok(Greeks { delta, gamma, vega, theta, rho })
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Greeks {
+    pub delta: f64,
+    pub gamma: f64,
+    pub vega: f64,
+    pub theta: f64,
+    pub rho: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numerical_greeks_match_black_scholes() {
+        for option_type in [OptionType::Call, OptionType::Put] {
+            let bs = |s, k, r, v, t| black_scholes(s, k, r, v, t, option_type);
+            let analytic = calculate_greeks(100.0, 105.0, 0.05, 0.2, 0.5, option_type).unwrap();
+            let numerical = numerical_greeks(bs, 100.0, 105.0, 0.05, 0.2, 0.5, &GreekBumps::default()).unwrap();
+            for (name, a, n) in [
+                ("delta", analytic.delta, numerical.delta),
+                ("gamma", analytic.gamma, numerical.gamma),
+                ("vega", analytic.vega, numerical.vega),
+                ("theta", analytic.theta, numerical.theta),
+                ("rho", analytic.rho, numerical.rho),
+            ] {
+                assert!((a - n).abs() < 1e-3 * a.abs().max(1.0), "{:?} {}: {} vs {}", option_type, name, a, n);
+            }
+        }
+    }
+
+    #[test]
+    fn test_numerical_greeks_near_expiry() {
+        let bs = |s, k, r, v, t| black_scholes(s, k, r, v, t, OptionType::Call);
+        // Less time left than the default time bump
+        let greeks = numerical_greeks(bs, 100.0, 100.0, 0.05, 0.2, 0.001, &GreekBumps::default()).unwrap();
+        let analytic = calculate_greeks(100.0, 100.0, 0.05, 0.2, 0.001, OptionType::Call).unwrap();
+        assert!(greeks.theta.is_finite() && greeks.theta < 0.0);
+        assert!((greeks.theta - analytic.theta).abs() < 0.05 * analytic.theta.abs(), "{} vs {}", greeks.theta, analytic.theta);
+        assert!((0.0..=1.0).contains(&greeks.delta));
+
+        assert!(numerical_greeks(bs, 100.0, 100.0, 0.05, 0.2, 0.0, &GreekBumps::default()).is_err());
+    }
+}
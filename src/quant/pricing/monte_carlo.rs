@@ -0,0 +1,317 @@
+//! Monte Carlo Pricing
+//! European, Asian (arithmetic average) and up-and-out barrier options under
+//! geometric Brownian motion, optionally with antithetic variates. Paths are
+//! simulated in fixed-size chunks, each with an RNG stream derived from the
+//! seed, and spread over the available cores; chunk totals are added in
+//! order, so a given seed reproduces the same estimate on any machine
+
+use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::cancel::{CancelToken, Cancelled};
+use super::OptionType;
+
+/// Samples per chunk; each chunk has its own RNG stream
+const CHUNK_SAMPLES: u64 = 1 << 16;
+
+/// Paths sampled between cancellation checks
+const CANCEL_CHECK_PATHS: u64 = 4096;
+
+/// Spreads chunk seeds over the seed space
+const CHUNK_SEED_STRIDE: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// What a path pays at expiry
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum McPayoff {
+    /// On the spot at expiry
+    European { option_type: OptionType },
+    /// On the arithmetic average of the spot at each step
+    Asian { option_type: OptionType },
+    /// European, but worthless once the spot reaches `barrier` at any step
+    UpAndOut { option_type: OptionType, barrier: f64 },
+}
+
+/// Monte Carlo inputs
+#[derive(Debug, Clone, Copy)]
+pub struct McParams {
+    pub spot: f64,
+    pub strike: f64,
+    pub rate: f64,
+    /// Continuous dividend yield
+    pub dividend_yield: f64,
+    pub volatility: f64,
+    pub time_to_expiry: f64,
+    pub payoff: McPayoff,
+    /// Rounded up to an even number with antithetic variates
+    pub paths: u64,
+    /// Monitoring dates for Asian and barrier payoffs; European payoffs
+    /// only need the spot at expiry and take a single step
+    pub steps: u32,
+    /// Picked at random when unset; reported in the result either way
+    pub seed: Option<u64>,
+    /// Pair each path with its mirror image and average the two payoffs
+    pub antithetic: bool,
+}
+
+impl McParams {
+    /// European option with no dividend yield, a random seed and no
+    /// variance reduction
+    pub fn european(
+        spot: f64,
+        strike: f64,
+        rate: f64,
+        volatility: f64,
+        time_to_expiry: f64,
+        option_type: OptionType,
+        paths: u64,
+    ) -> Self {
+        Self {
+            spot,
+            strike,
+            rate,
+            dividend_yield: 0.0,
+            volatility,
+            time_to_expiry,
+            payoff: McPayoff::European { option_type },
+            paths,
+            steps: 1,
+            seed: None,
+            antithetic: false,
+        }
+    }
+}
+
+/// Discounted mean payoff and its standard error
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct McResult {
+    pub price: f64,
+    pub std_error: f64,
+    pub paths: u64,
+    /// Reproduces this estimate
+    pub seed: u64,
+}
+
+/// Price `params` on every available core
+pub fn monte_carlo_price(params: &McParams) -> Result<McResult> {
+    monte_carlo_price_within(params, &CancelToken::new())
+}
+
+/// `monte_carlo_price`, stopping with `Cancelled` when `cancel` fires
+pub fn monte_carlo_price_within(params: &McParams, cancel: &CancelToken) -> Result<McResult> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    simulate(params, cancel, threads)
+}
+
+fn simulate(params: &McParams, cancel: &CancelToken, threads: usize) -> Result<McResult> {
+    if params.spot <= 0.0 || params.strike <= 0.0 {
+        anyhow::bail!("Spot and strike must be positive");
+    }
+    if params.volatility <= 0.0 || params.time_to_expiry <= 0.0 {
+        anyhow::bail!("Volatility and time to expiry must be positive");
+    }
+    if params.steps == 0 {
+        anyhow::bail!("Monte Carlo needs at least 1 step per path");
+    }
+    if let McPayoff::UpAndOut { barrier, .. } = params.payoff {
+        if barrier <= params.spot {
+            anyhow::bail!("Up-and-out barrier {} must be above the spot {}", barrier, params.spot);
+        }
+    }
+    // One sample per path, or per antithetic pair
+    let samples = if params.antithetic { params.paths.div_ceil(2) } else { params.paths };
+    if samples < 2 {
+        anyhow::bail!("Monte Carlo needs at least 2 paths (4 with antithetic variates)");
+    }
+
+    let seed = params.seed.unwrap_or_else(rand::random);
+    let sampler = Sampler::new(params);
+    let chunks = samples.div_ceil(CHUNK_SAMPLES);
+    let next_chunk = AtomicU64::new(0);
+    let workers = threads.clamp(1, chunks.try_into().unwrap_or(usize::MAX));
+    let run_chunks = || -> Result<Vec<(u64, f64, f64)>, Cancelled> {
+        let mut totals = Vec::new();
+        loop {
+            let chunk = next_chunk.fetch_add(1, Ordering::Relaxed);
+            if chunk >= chunks {
+                return Ok(totals);
+            }
+            let count = CHUNK_SAMPLES.min(samples - chunk * CHUNK_SAMPLES);
+            let (sum, sum_sq) = sampler.run(seed ^ chunk.wrapping_mul(CHUNK_SEED_STRIDE), count, cancel)?;
+            totals.push((chunk, sum, sum_sq));
+        }
+    };
+    let per_worker: Vec<_> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers).map(|_| scope.spawn(run_chunks)).collect();
+        handles.into_iter().map(|handle| handle.join().expect("Monte Carlo worker panicked")).collect()
+    });
+    let mut totals = per_worker.into_iter().collect::<Result<Vec<_>, _>>()?.concat();
+    totals.sort_by_key(|&(chunk, ..)| chunk);
+    let (sum, sum_sq) = totals.iter().fold((0.0, 0.0), |(sum, sum_sq), &(_, s, sq)| (sum + s, sum_sq + sq));
+
+    let n = samples as f64;
+    let mean = sum / n;
+    let variance = ((sum_sq - n * mean * mean) / (n - 1.0)).max(0.0);
+    let discount = (-params.rate * params.time_to_expiry).exp();
+    Ok(McResult {
+        price: discount * mean,
+        std_error: discount * (variance / n).sqrt(),
+        paths: if params.antithetic { 2 * samples } else { samples },
+        seed,
+    })
+}
+
+/// Walks paths and totals their payoffs
+struct Sampler {
+    spot: f64,
+    strike: f64,
+    payoff: McPayoff,
+    steps: usize,
+    antithetic: bool,
+    /// Log-spot drift and diffusion per step
+    drift: f64,
+    diffusion: f64,
+}
+
+impl Sampler {
+    fn new(params: &McParams) -> Self {
+        let steps = match params.payoff {
+            McPayoff::European { .. } => 1,
+            McPayoff::Asian { .. } | McPayoff::UpAndOut { .. } => params.steps as usize,
+        };
+        let dt = params.time_to_expiry / steps as f64;
+        let volatility = params.volatility;
+        Self {
+            spot: params.spot,
+            strike: params.strike,
+            payoff: params.payoff,
+            steps,
+            antithetic: params.antithetic,
+            drift: (params.rate - params.dividend_yield - volatility * volatility / 2.0) * dt,
+            diffusion: volatility * dt.sqrt(),
+        }
+    }
+
+    /// Sum and sum of squares of `samples` payoffs from the stream `seed`
+    fn run(&self, seed: u64, samples: u64, cancel: &CancelToken) -> Result<(f64, f64), Cancelled> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut normals = Normals::default();
+        let mut shocks = vec![0.0; self.steps];
+        let (mut sum, mut sum_sq) = (0.0, 0.0);
+        for sampled in 0..samples {
+            if sampled % CANCEL_CHECK_PATHS == 0 {
+                cancel.check()?;
+            }
+            shocks.iter_mut().for_each(|z| *z = normals.next(&mut rng));
+            let value = match self.antithetic {
+                true => 0.5 * (self.path_payoff(&shocks, 1.0) + self.path_payoff(&shocks, -1.0)),
+                false => self.path_payoff(&shocks, 1.0),
+            };
+            sum += value;
+            sum_sq += value * value;
+        }
+        Ok((sum, sum_sq))
+    }
+
+    /// Undiscounted payoff of the path driven by `sign * shocks`
+    fn path_payoff(&self, shocks: &[f64], sign: f64) -> f64 {
+        let mut log_spot = self.spot.ln();
+        let (mut spot, mut total) = (self.spot, 0.0);
+        for z in shocks {
+            log_spot += self.drift + self.diffusion * sign * z;
+            spot = log_spot.exp();
+            total += spot;
+            if matches!(self.payoff, McPayoff::UpAndOut { barrier, .. } if spot >= barrier) {
+                return 0.0;
+            }
+        }
+        let (option_type, underlying) = match self.payoff {
+            McPayoff::European { option_type } | McPayoff::UpAndOut { option_type, .. } => (option_type, spot),
+            McPayoff::Asian { option_type } => (option_type, total / shocks.len() as f64),
+        };
+        match option_type {
+            OptionType::Call => (underlying - self.strike).max(0.0),
+            OptionType::Put => (self.strike - underlying).max(0.0),
+        }
+    }
+}
+
+/// Standard normals by Box-Muller, which gives them in pairs
+#[derive(Default)]
+pub(crate) struct Normals {
+    spare: Option<f64>,
+}
+
+impl Normals {
+    pub(crate) fn next(&mut self, rng: &mut StdRng) -> f64 {
+        if let Some(z) = self.spare.take() {
+            return z;
+        }
+        let u1: f64 = 1.0 - rng.gen::<f64>();
+        let u2: f64 = rng.gen();
+        let radius = (-2.0 * u1.ln()).sqrt();
+        let angle = 2.0 * std::f64::consts::PI * u2;
+        self.spare = Some(radius * angle.sin());
+        radius * angle.cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quant::pricing::black_scholes;
+
+    fn params(payoff: McPayoff, paths: u64) -> McParams {
+        McParams { payoff, steps: 50, seed: Some(7), ..McParams::european(100.0, 105.0, 0.03, 0.25, 0.5, OptionType::Call, paths) }
+    }
+
+    #[test]
+    fn test_converges_to_black_scholes() {
+        for option_type in [OptionType::Call, OptionType::Put] {
+            let bs = black_scholes(100.0, 105.0, 0.03, 0.25, 0.5, option_type).unwrap();
+            let plain = monte_carlo_price(&params(McPayoff::European { option_type }, 200_000)).unwrap();
+            assert!((plain.price - bs).abs() < 3.0 * plain.std_error, "{:?}: {} vs {}", option_type, plain.price, bs);
+            assert!(plain.std_error < 0.05);
+
+            let antithetic = monte_carlo_price(&McParams { antithetic: true, ..params(McPayoff::European { option_type }, 200_000) }).unwrap();
+            assert!((antithetic.price - bs).abs() < 3.0 * antithetic.std_error, "{:?}: {} vs {}", option_type, antithetic.price, bs);
+            assert!(antithetic.std_error < plain.std_error, "antithetic variates didn't reduce the error");
+            assert_eq!(antithetic.paths, 200_000);
+        }
+    }
+
+    #[test]
+    fn test_same_estimate_on_any_thread_count() {
+        let cancel = CancelToken::new();
+        let asian = params(McPayoff::Asian { option_type: OptionType::Put }, 3 * CHUNK_SAMPLES + 1);
+        let single = simulate(&asian, &cancel, 1).unwrap();
+        assert_eq!(single, simulate(&asian, &cancel, 4).unwrap());
+        assert_eq!(single.seed, 7);
+
+        let random = monte_carlo_price(&McParams { seed: None, ..asian }).unwrap();
+        assert_eq!(random, monte_carlo_price(&McParams { seed: Some(random.seed), ..asian }).unwrap());
+    }
+
+    #[test]
+    fn test_path_dependent_payoffs() {
+        let call = McPayoff::European { option_type: OptionType::Call };
+        let european = monte_carlo_price(&params(call, 100_000)).unwrap();
+        let asian = monte_carlo_price(&params(McPayoff::Asian { option_type: OptionType::Call }, 100_000)).unwrap();
+        // Averaging damps the volatility the option pays for
+        assert!(asian.price < european.price, "{} vs {}", asian.price, european.price);
+
+        let knock_out = |barrier| McPayoff::UpAndOut { option_type: OptionType::Call, barrier };
+        let near = monte_carlo_price(&params(knock_out(115.0), 100_000)).unwrap();
+        assert!(near.price < european.price / 2.0, "{} vs {}", near.price, european.price);
+        let far = monte_carlo_price(&params(knock_out(1_000.0), 100_000)).unwrap();
+        let combined = (far.std_error.powi(2) + european.std_error.powi(2)).sqrt();
+        assert!((far.price - european.price).abs() < 3.0 * combined, "{} vs {}", far.price, european.price);
+
+        assert!(monte_carlo_price(&params(knock_out(95.0), 100)).is_err());
+        assert!(monte_carlo_price(&McParams { steps: 0, ..params(call, 100) }).is_err());
+        assert!(monte_carlo_price(&McParams { antithetic: true, ..params(call, 2) }).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+//! Option Strategies
+//! Multi-leg positions in options on one underlying expiring together:
+//! payoff at expiry, breakevens, the most it can make or lose, and net
+//! Black-Scholes Greeks. Quantities are signed (negative writes the
+//! option) and premiums are per option, paid long and received short
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use super::{calculate_greeks, Greeks, OptionType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OptionLeg {
+    pub option_type: OptionType,
+    pub strike: f64,
+    pub quantity: f64,
+    pub premium: f64,
+}
+
+impl OptionLeg {
+    pub fn new(option_type: OptionType, strike: f64, quantity: f64, premium: f64) -> Self {
+        Self { option_type, strike, quantity, premium }
+    }
+
+    /// Profit at expiry, net of the premium
+    pub fn payoff_at(&self, spot: f64) -> f64 {
+        let intrinsic = match self.option_type {
+            OptionType::Call => (spot - self.strike).max(0.0),
+            OptionType::Put => (self.strike - spot).max(0.0),
+        };
+        (intrinsic - self.premium) * self.quantity
+    }
+}
+
+/// `type:strike:quantity:premium`, e.g. `call:100:1:2.5` or `put:95:-2:1.1`
+impl FromStr for OptionLeg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split(':').map(str::trim).collect();
+        let [option_type, strike, quantity, premium] = parts[..] else {
+            anyhow::bail!("Leg '{}' is not type:strike:quantity:premium", s);
+        };
+        let option_type = match option_type.to_ascii_lowercase().as_str() {
+            "call" | "c" => OptionType::Call,
+            "put" | "p" => OptionType::Put,
+            other => anyhow::bail!("Unknown option type '{}' in leg '{}' (expected call or put)", other, s),
+        };
+        let number = |field: &str, value: &str| {
+            value.parse::<f64>().ok().filter(|v| v.is_finite()).with_context(|| format!("Invalid {} '{}' in leg '{}'", field, value, s))
+        };
+        let leg = Self::new(option_type, number("strike", strike)?, number("quantity", quantity)?, number("premium", premium)?);
+        if leg.strike <= 0.0 || leg.premium < 0.0 || leg.quantity == 0.0 {
+            anyhow::bail!("Leg '{}' needs a positive strike, a non-zero quantity and a premium of zero or more", s);
+        }
+        Ok(leg)
+    }
+}
+
+impl fmt::Display for OptionLeg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let option_type = match self.option_type {
+            OptionType::Call => "call",
+            OptionType::Put => "put",
+        };
+        write!(f, "{:+} {} {} @ {:.2}", self.quantity, option_type, self.strike, self.premium)
+    }
+}
+
+/// Legs held together until expiry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OptionStrategy {
+    pub legs: Vec<OptionLeg>,
+}
+
+impl OptionStrategy {
+    pub fn new(legs: Vec<OptionLeg>) -> Self {
+        Self { legs }
+    }
+
+    /// Long `long_strike`, short `short_strike`: with calls a bull spread
+    /// when the long strike is lower, with puts a bear spread when higher
+    pub fn vertical_spread(option_type: OptionType, long_strike: f64, long_premium: f64, short_strike: f64, short_premium: f64) -> Self {
+        Self::new(vec![
+            OptionLeg::new(option_type, long_strike, 1.0, long_premium),
+            OptionLeg::new(option_type, short_strike, -1.0, short_premium),
+        ])
+    }
+
+    /// Long a call and a put at `strike`
+    pub fn straddle(strike: f64, call_premium: f64, put_premium: f64) -> Self {
+        Self::new(vec![
+            OptionLeg::new(OptionType::Call, strike, 1.0, call_premium),
+            OptionLeg::new(OptionType::Put, strike, 1.0, put_premium),
+        ])
+    }
+
+    /// Short put spread plus short call spread, strikes ascending: long
+    /// put, short put, short call, long call
+    pub fn iron_condor(strikes: [f64; 4], premiums: [f64; 4]) -> Self {
+        Self::new(vec![
+            OptionLeg::new(OptionType::Put, strikes[0], 1.0, premiums[0]),
+            OptionLeg::new(OptionType::Put, strikes[1], -1.0, premiums[1]),
+            OptionLeg::new(OptionType::Call, strikes[2], -1.0, premiums[2]),
+            OptionLeg::new(OptionType::Call, strikes[3], 1.0, premiums[3]),
+        ])
+    }
+
+    /// Premium received less premium paid; negative for a debit
+    pub fn net_premium(&self) -> f64 {
+        -self.legs.iter().map(|leg| leg.premium * leg.quantity).sum::<f64>()
+    }
+
+    /// Profit at expiry, net of premiums
+    pub fn payoff_at(&self, spot_at_expiry: f64) -> f64 {
+        self.legs.iter().map(|leg| leg.payoff_at(spot_at_expiry)).sum()
+    }
+
+    /// Zero and the strikes, ascending: the payoff is linear between them
+    fn kinks(&self) -> Vec<f64> {
+        let mut kinks: Vec<f64> = self.legs.iter().map(|leg| leg.strike).collect();
+        kinks.push(0.0);
+        kinks.sort_by(f64::total_cmp);
+        kinks.dedup();
+        kinks
+    }
+
+    /// Payoff change per unit of spot above the highest strike
+    fn upside_slope(&self) -> f64 {
+        self.legs.iter().filter(|leg| matches!(leg.option_type, OptionType::Call)).map(|leg| leg.quantity).sum()
+    }
+
+    /// Spots at expiry where the payoff crosses zero, ascending
+    pub fn breakeven_points(&self) -> Vec<f64> {
+        let kinks = self.kinks();
+        let mut points = Vec::new();
+        let mut push = |x: f64| {
+            if !points.last().is_some_and(|last: &f64| (x - last).abs() <= 1e-9) {
+                points.push(x);
+            }
+        };
+        for pair in kinks.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let (fa, fb) = (self.payoff_at(a), self.payoff_at(b));
+            if fa == 0.0 {
+                push(a);
+            }
+            if fa * fb < 0.0 {
+                push(a + (b - a) * fa / (fa - fb));
+            }
+        }
+        let last = *kinks.last().unwrap_or(&0.0);
+        let (value, slope) = (self.payoff_at(last), self.upside_slope());
+        if value == 0.0 {
+            push(last);
+        } else if slope != 0.0 && value * slope < 0.0 {
+            push(last - value / slope);
+        }
+        points
+    }
+
+    /// Best payoff at expiry; `None` when it is unbounded
+    pub fn max_profit(&self) -> Option<f64> {
+        match self.upside_slope() > 0.0 {
+            true => None,
+            false => self.kinks().into_iter().map(|x| self.payoff_at(x)).reduce(f64::max),
+        }
+    }
+
+    /// Worst payoff at expiry as a positive loss; `None` when it is unbounded
+    pub fn max_loss(&self) -> Option<f64> {
+        match self.upside_slope() < 0.0 {
+            true => None,
+            false => self.kinks().into_iter().map(|x| -self.payoff_at(x)).reduce(f64::max),
+        }
+    }
+
+    /// Black-Scholes Greeks of every leg, times its quantity
+    pub fn net_greeks(&self, spot: f64, rate: f64, volatility: f64, time_to_expiry: f64) -> Result<Greeks> {
+        let mut net = Greeks::default();
+        for leg in &self.legs {
+            let greeks = calculate_greeks(spot, leg.strike, rate, volatility, time_to_expiry, leg.option_type)?;
+            net.delta += greeks.delta * leg.quantity;
+            net.gamma += greeks.gamma * leg.quantity;
+            net.vega += greeks.vega * leg.quantity;
+            net.theta += greeks.theta * leg.quantity;
+            net.rho += greeks.rho * leg.quantity;
+        }
+        Ok(net)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_bull_call_spread() {
+        // Pay 5, receive 2: a 3 debit on a 10-wide spread
+        let spread = OptionStrategy::vertical_spread(OptionType::Call, 100.0, 5.0, 110.0, 2.0);
+        assert!(close(spread.net_premium(), -3.0));
+        assert_eq!(spread.breakeven_points(), vec![103.0]);
+        assert!(close(spread.max_profit().unwrap(), 7.0));
+        assert!(close(spread.max_loss().unwrap(), 3.0));
+        assert!(close(spread.payoff_at(90.0), -3.0));
+        assert!(close(spread.payoff_at(105.0), 2.0));
+        assert!(close(spread.payoff_at(150.0), 7.0));
+
+        let legs: Vec<OptionLeg> = ["call:100:1:5", "call:110:-1:2"].iter().map(|leg| leg.parse().unwrap()).collect();
+        assert_eq!(OptionStrategy::new(legs), spread);
+    }
+
+    #[test]
+    fn test_straddle_and_iron_condor() {
+        let straddle = OptionStrategy::straddle(100.0, 4.0, 3.0);
+        assert_eq!(straddle.breakeven_points(), vec![93.0, 107.0]);
+        assert_eq!(straddle.max_profit(), None);
+        assert!(close(straddle.max_loss().unwrap(), 7.0));
+
+        // Credit of 2.5 on 5-wide wings
+        let condor = OptionStrategy::iron_condor([90.0, 95.0, 105.0, 110.0], [1.0, 2.0, 2.5, 1.0]);
+        assert!(close(condor.net_premium(), 2.5));
+        assert_eq!(condor.breakeven_points(), vec![92.5, 107.5]);
+        assert!(close(condor.max_profit().unwrap(), 2.5));
+        assert!(close(condor.max_loss().unwrap(), 2.5));
+
+        let naked_call = OptionStrategy::new(vec![OptionLeg::new(OptionType::Call, 100.0, -1.0, 3.0)]);
+        assert_eq!(naked_call.max_loss(), None);
+        assert_eq!(naked_call.breakeven_points(), vec![103.0]);
+    }
+
+    #[test]
+    fn test_net_greeks() {
+        let spread = OptionStrategy::vertical_spread(OptionType::Call, 100.0, 5.0, 110.0, 2.0);
+        let net = spread.net_greeks(105.0, 0.03, 0.2, 0.5).unwrap();
+        let long = calculate_greeks(105.0, 100.0, 0.03, 0.2, 0.5, OptionType::Call).unwrap();
+        let short = calculate_greeks(105.0, 110.0, 0.03, 0.2, 0.5, OptionType::Call).unwrap();
+        assert!(close(net.delta, long.delta - short.delta));
+        assert!(close(net.vega, long.vega - short.vega));
+        assert!(net.delta > 0.0 && net.delta < 1.0);
+
+        // A straddle at the money is close to delta neutral
+        assert!(OptionStrategy::straddle(100.0, 4.0, 3.0).net_greeks(100.0, 0.0, 0.2, 0.25).unwrap().delta.abs() < 0.1);
+    }
+
+    #[test]
+    fn test_invalid_legs() {
+        for leg in ["call:100:1", "swap:100:1:2", "call:abc:1:2", "call:100:0:2", "put:100:1:-2"] {
+            assert!(leg.parse::<OptionLeg>().is_err(), "{}", leg);
+        }
+        assert_eq!("P:95:-2:1.1".parse::<OptionLeg>().unwrap(), OptionLeg::new(OptionType::Put, 95.0, -2.0, 1.1));
+    }
+}
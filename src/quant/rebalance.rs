@@ -0,0 +1,470 @@
+//! Rebalancing
+//! Turns target weights into a trade list at current quotes, subject to
+//! lot sizes, a minimum trade size, a turnover cap and do-not-sell symbols
+
+use anyhow::Result;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+use super::portfolio::Portfolio;
+use super::{Trade, TradeSide};
+
+/// How far target weights may sum from 1 (0.0001)
+pub const WEIGHT_TOLERANCE: Decimal = Decimal::from_parts(1, 0, 0, false, 4);
+
+/// Estimated commissions: a fixed charge per trade plus basis points of notional
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeeModel {
+    pub per_trade: Decimal,
+    pub bps: Decimal,
+}
+
+impl FeeModel {
+    pub fn commission(&self, notional: Decimal) -> Decimal {
+        self.per_trade + notional.abs() * self.bps / Decimal::from(10_000)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RebalanceConstraints {
+    /// Trades smaller than this notional are skipped
+    pub min_trade_notional: Decimal,
+    /// Quantities are rounded toward zero to a multiple of this (0 = fractional)
+    pub lot_size: Decimal,
+    /// Maximum traded notional as a fraction of portfolio value
+    pub turnover_cap: Option<Decimal>,
+    pub do_not_sell: HashSet<String>,
+    /// Cash added (positive) or withdrawn (negative) by the rebalance
+    pub cash_flow: Decimal,
+    pub fees: FeeModel,
+}
+
+/// Why targets can't be planned
+#[derive(Debug, Clone, PartialEq)]
+pub enum RebalanceError {
+    WeightsDontSum { sum: Decimal },
+    InvalidWeight { symbol: String, weight: Decimal },
+    /// No quote for a target or held symbol
+    UnknownSymbol(String),
+    /// Nothing held and no cash coming in
+    NoValue,
+}
+
+impl fmt::Display for RebalanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WeightsDontSum { sum } => write!(f, "target weights sum to {}, not 1", sum),
+            Self::InvalidWeight { symbol, weight } => write!(f, "target weight for {} is {} (must be 0..=1)", symbol, weight),
+            Self::UnknownSymbol(symbol) => write!(f, "no quote for {}", symbol),
+            Self::NoValue => write!(f, "portfolio plus cash flow has no value to allocate"),
+        }
+    }
+}
+
+impl std::error::Error for RebalanceError {}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RebalanceTrade {
+    pub symbol: String,
+    pub side: TradeSide,
+    pub quantity: Decimal,
+    pub price: Decimal,
+    pub notional: Decimal,
+    pub commission: Decimal,
+}
+
+/// Current vs target vs expected weight for one symbol
+#[derive(Debug, Clone, Serialize)]
+pub struct DriftLine {
+    pub symbol: String,
+    pub current_weight: f64,
+    pub target_weight: f64,
+    pub post_weight: f64,
+    /// Current minus target
+    pub drift: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RebalancePlan {
+    /// Holdings plus cash flow, at current quotes
+    pub total_value: Decimal,
+    /// Sells first, then buys
+    pub trades: Vec<RebalanceTrade>,
+    pub drift: Vec<DriftLine>,
+    /// Traded notional over total value
+    pub turnover: f64,
+    pub turnover_capped: bool,
+    pub commissions: Decimal,
+    /// Cash left after trades and commissions (negative: outflow not covered)
+    pub cash_residual: Decimal,
+    /// Targeted symbols not held before the rebalance, opened as new positions
+    pub new_positions: Vec<String>,
+    /// Where the plan falls short of, or goes beyond, what was asked
+    pub warnings: Vec<String>,
+}
+
+/// Plan trades moving `portfolio` toward `targets` at `quotes`. Held symbols
+/// without a target are sold down to zero.
+pub fn plan_rebalance(
+    portfolio: &Portfolio,
+    targets: &HashMap<String, Decimal>,
+    quotes: &HashMap<String, Decimal>,
+    constraints: &RebalanceConstraints,
+) -> Result<RebalancePlan> {
+    let targets: HashMap<String, Decimal> = targets.iter().map(|(s, w)| (s.to_uppercase(), *w)).collect();
+    for (symbol, weight) in &targets {
+        if !(Decimal::ZERO..=Decimal::ONE).contains(weight) {
+            return Err(RebalanceError::InvalidWeight { symbol: symbol.clone(), weight: *weight }.into());
+        }
+    }
+    let sum: Decimal = targets.values().sum();
+    if (sum - Decimal::ONE).abs() > WEIGHT_TOLERANCE {
+        return Err(RebalanceError::WeightsDontSum { sum }.into());
+    }
+
+    let held = |symbol: &str| portfolio.positions.get(symbol).map_or(Decimal::ZERO, |p| p.quantity);
+    let symbols: BTreeSet<String> = targets.keys().chain(portfolio.positions.keys()).cloned().collect();
+    let mut prices = HashMap::new();
+    for symbol in &symbols {
+        let price = quotes
+            .get(symbol)
+            .copied()
+            .filter(|p| *p > Decimal::ZERO)
+            .ok_or_else(|| RebalanceError::UnknownSymbol(symbol.clone()))?;
+        prices.insert(symbol.clone(), price);
+    }
+
+    let holdings: Decimal = symbols.iter().map(|s| held(s) * prices[s]).sum();
+    let total_value = holdings + constraints.cash_flow;
+    if total_value <= Decimal::ZERO {
+        return Err(RebalanceError::NoValue.into());
+    }
+
+    // Signed quantity to trade per symbol, before the turnover cap
+    let mut orders: Vec<(String, Decimal)> = symbols
+        .iter()
+        .map(|symbol| {
+            let weight = targets.get(symbol).copied().unwrap_or_default();
+            let gap = weight * total_value - held(symbol) * prices[symbol];
+            (symbol.clone(), gap / prices[symbol])
+        })
+        .collect();
+    let mut turnover_capped = false;
+    let raw_turnover = turnover(&orders, &prices, total_value);
+    if let Some(cap) = constraints.turnover_cap.filter(|cap| raw_turnover > *cap) {
+        let scale = cap / raw_turnover;
+        for (_, quantity) in &mut orders {
+            *quantity *= scale;
+        }
+        turnover_capped = true;
+    }
+    for (symbol, quantity) in &mut orders {
+        *quantity = round_lot(*quantity, constraints.lot_size);
+        let selling = *quantity < Decimal::ZERO;
+        if (selling && constraints.do_not_sell.contains(symbol))
+            || (*quantity * prices[symbol]).abs() < constraints.min_trade_notional
+        {
+            *quantity = Decimal::ZERO;
+        }
+    }
+
+    // Buys can't spend more than the cash flow plus sells: trim the largest
+    // buy a lot at a time until commissions are covered too
+    let step = if constraints.lot_size > Decimal::ZERO { constraints.lot_size } else { Decimal::ONE };
+    loop {
+        let cash = cash_after(&orders, &prices, constraints);
+        if cash >= Decimal::ZERO {
+            break;
+        }
+        let largest = orders
+            .iter_mut()
+            .filter(|(_, q)| *q > Decimal::ZERO)
+            .max_by_key(|(s, q)| *q * prices[s]);
+        let Some((symbol, quantity)) = largest else { break };
+        let shortfall_lots = (-cash / (prices[symbol.as_str()] * step)).ceil().max(Decimal::ONE);
+        *quantity = (*quantity - shortfall_lots * step).max(Decimal::ZERO);
+        if *quantity * prices[symbol.as_str()] < constraints.min_trade_notional {
+            *quantity = Decimal::ZERO;
+        }
+    }
+
+    let new_positions: Vec<String> = symbols
+        .iter()
+        .filter(|s| held(s).is_zero() && targets.get(s.as_str()).is_some_and(|w| *w > Decimal::ZERO))
+        .cloned()
+        .collect();
+    let mut warnings = Vec::new();
+    if !new_positions.is_empty() {
+        warnings.push(format!("Not held yet, opened as new positions: {}", new_positions.join(", ")));
+    }
+    if turnover_capped {
+        warnings.push("Turnover cap reached: trades scaled down, targets not fully met".to_string());
+    }
+    let mut trades: Vec<RebalanceTrade> = orders
+        .iter()
+        .filter(|(_, q)| !q.is_zero())
+        .map(|(symbol, quantity)| {
+            let price = prices[symbol];
+            let notional = quantity.abs() * price;
+            RebalanceTrade {
+                symbol: symbol.clone(),
+                side: if *quantity > Decimal::ZERO { TradeSide::Buy } else { TradeSide::Sell },
+                quantity: quantity.abs(),
+                price,
+                notional,
+                commission: constraints.fees.commission(notional),
+            }
+        })
+        .collect();
+    trades.sort_by_key(|t| matches!(t.side, TradeSide::Buy));
+
+    let cash_residual = cash_after(&orders, &prices, constraints);
+    let post_value = |symbol: &str| {
+        let traded = orders.iter().find(|(s, _)| s == symbol).map_or(Decimal::ZERO, |(_, q)| *q);
+        (held(symbol) + traded) * prices[symbol]
+    };
+    let post_total: Decimal = symbols.iter().map(|s| post_value(s)).sum::<Decimal>() + cash_residual;
+    let drift = symbols
+        .iter()
+        .map(|symbol| {
+            let current_weight = ratio(held(symbol) * prices[symbol], holdings);
+            let target_weight = targets.get(symbol).and_then(|w| w.to_f64()).unwrap_or_default();
+            DriftLine {
+                symbol: symbol.clone(),
+                current_weight,
+                target_weight,
+                post_weight: ratio(post_value(symbol), post_total),
+                drift: current_weight - target_weight,
+            }
+        })
+        .collect();
+
+    Ok(RebalancePlan {
+        total_value,
+        turnover: turnover(&orders, &prices, total_value).to_f64().unwrap_or_default(),
+        turnover_capped,
+        commissions: trades.iter().map(|t| t.commission).sum(),
+        trades,
+        drift,
+        cash_residual,
+        new_positions,
+        warnings,
+    })
+}
+
+fn ratio(part: Decimal, whole: Decimal) -> f64 {
+    if whole.is_zero() {
+        return 0.0;
+    }
+    (part / whole).to_f64().unwrap_or_default()
+}
+
+/// Toward zero, to a multiple of `lot` (fractional when `lot` is zero)
+fn round_lot(quantity: Decimal, lot: Decimal) -> Decimal {
+    if lot <= Decimal::ZERO {
+        return quantity.trunc_with_scale(8);
+    }
+    (quantity / lot).trunc() * lot
+}
+
+/// Traded notional over `total_value`, which is positive
+fn turnover(orders: &[(String, Decimal)], prices: &HashMap<String, Decimal>, total_value: Decimal) -> Decimal {
+    orders.iter().map(|(s, q)| q.abs() * prices[s]).sum::<Decimal>() / total_value
+}
+
+/// Cash flow plus sells minus buys and commissions
+fn cash_after(orders: &[(String, Decimal)], prices: &HashMap<String, Decimal>, constraints: &RebalanceConstraints) -> Decimal {
+    orders
+        .iter()
+        .filter(|(_, q)| !q.is_zero())
+        .map(|(s, q)| {
+            let notional = *q * prices[s];
+            -notional - constraints.fees.commission(notional)
+        })
+        .sum::<Decimal>()
+        + constraints.cash_flow
+}
+
+impl RebalancePlan {
+    /// The planned trades as market trades at their quotes, sells first
+    pub fn to_trades(&self) -> Vec<Trade> {
+        self.trades
+            .iter()
+            .map(|t| super::portfolio_store::market_trade(&t.symbol, t.side.clone(), t.quantity, t.price))
+            .collect()
+    }
+}
+
+impl fmt::Display for RebalancePlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "⚖️  Rebalance of {} (turnover {:.1}%)", self.total_value.round_dp(2), self.turnover * 100.0)?;
+        for warning in &self.warnings {
+            writeln!(f, "  ⚠️  {}", warning)?;
+        }
+        if self.trades.is_empty() {
+            writeln!(f, "  No trades needed")?;
+        }
+        for t in &self.trades {
+            writeln!(
+                f,
+                "  {:<4} {:>12} {:<8} @ {:>10}  = {:>12}  (fee {})",
+                format!("{:?}", t.side),
+                t.quantity,
+                t.symbol,
+                t.price,
+                t.notional.round_dp(2),
+                t.commission.round_dp(2)
+            )?;
+        }
+        writeln!(f, "\n  {:<8} {:>9} {:>9} {:>9} {:>9}", "", "current", "target", "after", "drift")?;
+        for d in &self.drift {
+            writeln!(
+                f,
+                "  {:<8} {:>8.2}% {:>8.2}% {:>8.2}% {:>+8.2}%",
+                d.symbol,
+                d.current_weight * 100.0,
+                d.target_weight * 100.0,
+                d.post_weight * 100.0,
+                d.drift * 100.0
+            )?;
+        }
+        writeln!(f, "\n  Commissions: {}", self.commissions.round_dp(2))?;
+        writeln!(f, "  Cash left:   {}", self.cash_residual.round_dp(2))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn portfolio(holdings: &[(&str, i64)]) -> Portfolio {
+        let mut portfolio = Portfolio::new("test".to_string(), "Test".to_string());
+        for (symbol, quantity) in holdings {
+            portfolio.add_position(symbol.to_string(), Decimal::from(*quantity), Decimal::from(100));
+        }
+        portfolio
+    }
+
+    fn map<T: Copy>(entries: &[(&str, T)]) -> HashMap<String, T> {
+        entries.iter().map(|(s, v)| (s.to_string(), *v)).collect()
+    }
+
+    /// `percent` / 100, as a target weight
+    fn pct(percent: i64) -> Decimal {
+        Decimal::new(percent, 2)
+    }
+
+    fn trade(plan: &RebalancePlan, symbol: &str) -> Option<(String, Decimal)> {
+        plan.trades.iter().find(|t| t.symbol == symbol).map(|t| (format!("{:?}", t.side), t.quantity))
+    }
+
+    fn weight(plan: &RebalancePlan, symbol: &str) -> f64 {
+        plan.drift.iter().find(|d| d.symbol == symbol).unwrap().post_weight
+    }
+
+    #[test]
+    fn test_two_asset_rebalance() {
+        // 1000 AAPL + 3000 MSFT + 1000 new cash = 5000 → 60/40
+        let held = portfolio(&[("AAPL", 10), ("MSFT", 30)]);
+        let quotes = map(&[("AAPL", Decimal::from(100)), ("MSFT", Decimal::from(100))]);
+        let constraints = RebalanceConstraints {
+            lot_size: Decimal::ONE,
+            cash_flow: Decimal::from(1000),
+            fees: FeeModel { per_trade: Decimal::ONE, bps: Decimal::ZERO },
+            ..Default::default()
+        };
+        let plan = plan_rebalance(&held, &map(&[("AAPL", pct(60)), ("MSFT", pct(40))]), &quotes, &constraints).unwrap();
+
+        // Buying 20 AAPL would leave 1000 + 1000 - 2000 - 2 = -2, so 19
+        assert_eq!(trade(&plan, "MSFT"), Some(("Sell".to_string(), Decimal::from(10))));
+        assert_eq!(trade(&plan, "AAPL"), Some(("Buy".to_string(), Decimal::from(19))));
+        assert_eq!(plan.trades[0].symbol, "MSFT", "sells go first");
+        assert_eq!(plan.commissions, Decimal::from(2));
+        assert_eq!(plan.cash_residual, Decimal::from(98));
+        // Leftover cash counts toward the post-trade total: 2900 + 2000 + 98
+        assert!((weight(&plan, "AAPL") - 2900.0 / 4998.0).abs() < 1e-9);
+        assert!((weight(&plan, "MSFT") - 2000.0 / 4998.0).abs() < 1e-9);
+        let aapl = plan.drift.iter().find(|d| d.symbol == "AAPL").unwrap();
+        assert!((aapl.drift - (0.25 - 0.6)).abs() < 1e-9);
+
+        // Bad targets
+        let err = plan_rebalance(&held, &map(&[("AAPL", pct(60)), ("MSFT", pct(30))]), &quotes, &constraints).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(RebalanceError::WeightsDontSum { .. })));
+        let err = plan_rebalance(&held, &map(&[("AAPL", pct(50)), ("TSLA", pct(50))]), &quotes, &constraints).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&RebalanceError::UnknownSymbol("TSLA".to_string())));
+        println!("✅ Two-asset rebalance test PASSED!");
+    }
+
+    #[test]
+    fn test_lot_rounding_moves_final_weights() {
+        let quotes = map(&[("X", Decimal::from(30)), ("Y", Decimal::from(70))]);
+        let constraints = RebalanceConstraints {
+            lot_size: Decimal::from(100),
+            cash_flow: Decimal::from(10_000),
+            ..Default::default()
+        };
+        let plan = plan_rebalance(&portfolio(&[]), &map(&[("X", pct(50)), ("Y", pct(50))]), &quotes, &constraints).unwrap();
+
+        // X: 166.7 shares → one lot of 100; Y: 71.4 shares → no whole lot
+        assert_eq!(trade(&plan, "X"), Some(("Buy".to_string(), Decimal::from(100))));
+        assert_eq!(trade(&plan, "Y"), None);
+        assert!((weight(&plan, "X") - 0.3).abs() < 1e-9);
+        assert_eq!(weight(&plan, "Y"), 0.0);
+        assert_eq!(plan.cash_residual, Decimal::from(7000));
+
+        // A minimum trade size drops the remaining lot too
+        let constraints = RebalanceConstraints { min_trade_notional: Decimal::from(5000), ..constraints };
+        let plan = plan_rebalance(&portfolio(&[]), &map(&[("X", pct(50)), ("Y", pct(50))]), &quotes, &constraints).unwrap();
+        assert!(plan.trades.is_empty());
+    }
+
+    #[test]
+    fn test_turnover_cap_binds() {
+        let held = portfolio(&[("AAPL", 10)]);
+        let quotes = map(&[("AAPL", Decimal::from(100)), ("MSFT", Decimal::from(100))]);
+        let targets = map(&[("AAPL", pct(50)), ("MSFT", pct(50))]);
+
+        let plan = plan_rebalance(&held, &targets, &quotes, &RebalanceConstraints::default()).unwrap();
+        assert!((plan.turnover - 1.0).abs() < 1e-9);
+        assert!(!plan.turnover_capped);
+
+        let capped = RebalanceConstraints { turnover_cap: Some(pct(50)), lot_size: Decimal::ONE, ..Default::default() };
+        let plan = plan_rebalance(&held, &targets, &quotes, &capped).unwrap();
+        assert!(plan.turnover_capped);
+        assert!(plan.turnover <= 0.5 + 1e-9);
+        assert_eq!(trade(&plan, "AAPL"), Some(("Sell".to_string(), Decimal::from(2))));
+        assert_eq!(trade(&plan, "MSFT"), Some(("Buy".to_string(), Decimal::from(2))));
+
+        // Do-not-sell blocks the funding leg, so nothing can be bought
+        let pinned = RebalanceConstraints { do_not_sell: ["AAPL".to_string()].into(), ..Default::default() };
+        let plan = plan_rebalance(&held, &targets, &quotes, &pinned).unwrap();
+        assert!(plan.trades.is_empty());
+    }
+
+    #[test]
+    fn test_in_balance_and_dust() {
+        let held = portfolio(&[("AAPL", 10), ("MSFT", 10)]);
+        let quotes = map(&[("AAPL", Decimal::from(100)), ("MSFT", Decimal::from(100)), ("NVDA", Decimal::from(40))]);
+        let plan = plan_rebalance(&held, &map(&[("AAPL", pct(50)), ("MSFT", pct(50))]), &quotes, &RebalanceConstraints::default()).unwrap();
+        assert!(plan.trades.is_empty());
+        assert!(plan.new_positions.is_empty());
+        assert_eq!(plan.turnover, 0.0);
+
+        // Fractional: 2000 * 0.49 = 980 of AAPL is a 20 sell, 2000 * 0.01 / 40 = 0.5 NVDA
+        let targets = map(&[("AAPL", pct(49)), ("MSFT", pct(50)), ("NVDA", pct(1))]);
+        let fractional = RebalanceConstraints::default();
+        let plan = plan_rebalance(&held, &targets, &quotes, &fractional).unwrap();
+        assert_eq!(trade(&plan, "AAPL"), Some(("Sell".to_string(), Decimal::new(2, 1))));
+        assert_eq!(trade(&plan, "NVDA"), Some(("Buy".to_string(), Decimal::new(5, 1))));
+        assert_eq!(plan.new_positions, vec!["NVDA".to_string()]);
+        assert!(plan.to_string().contains("opened as new positions: NVDA"));
+
+        // Both trades are 20 of notional: dust under a 50 minimum
+        let dusty = RebalanceConstraints { min_trade_notional: Decimal::from(50), ..fractional };
+        assert!(plan_rebalance(&held, &targets, &quotes, &dusty).unwrap().trades.is_empty());
+    }
+}
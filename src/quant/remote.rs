@@ -0,0 +1,363 @@
+//! Remote Pricing
+//! Option pricing served to peers over the Quantra protocol, so compute-light
+//! nodes can offload it. Serving needs the peer's zero-trust session to grant
+//! `quant/pricing` and stays within a per-peer compute budget
+
+use anyhow::Result;
+use governor::{clock::DefaultClock, state::{InMemoryState, NotKeyed}, Quota, RateLimiter};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+
+use super::binomial::{self, BinomialParams, ExerciseStyle};
+use super::pricing::cancel::{CancelToken, Cancelled};
+use super::pricing::{self, heston::HestonParams, monte_carlo, Greeks, OptionType};
+use crate::p2p::protocol::{QuantraRequest, QuantraResponse};
+use crate::units::HumanDuration;
+
+/// Zero-trust resource a peer's session must be granted to be served
+pub const PRICING_RESOURCE: &str = "quant/pricing";
+
+/// Reported with every result; a mismatch means the two sides may not
+/// compute the same numbers
+pub const MODEL_VERSION: &str = concat!("quantra-pricing/", env!("CARGO_PKG_VERSION"));
+
+/// `[quant.remote_pricing]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemotePricingConfig {
+    /// Answer peers' pricing requests. Off unless set
+    pub serve: bool,
+    /// Requests (single options or chains) each peer may make per minute
+    pub max_requests_per_minute: u32,
+    /// Largest Monte Carlo run served, per option
+    pub max_mc_paths: u64,
+    /// Most strikes in one chain request
+    pub max_chain_strikes: usize,
+    /// Client side: how long to wait for the serving peer
+    pub timeout: HumanDuration,
+    /// Client side: price locally when the peer fails or refuses
+    pub fallback_to_local: bool,
+}
+
+impl Default for RemotePricingConfig {
+    fn default() -> Self {
+        Self {
+            serve: false,
+            max_requests_per_minute: 60,
+            max_mc_paths: 200_000,
+            max_chain_strikes: 100,
+            timeout: HumanDuration::from_secs(10),
+            fallback_to_local: true,
+        }
+    }
+}
+
+/// Option contract and market inputs
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PricingInputs {
+    pub spot: f64,
+    pub strike: f64,
+    pub rate: f64,
+    /// Continuous dividend yield (not supported by Black-Scholes)
+    #[serde(default)]
+    pub dividend_yield: f64,
+    pub volatility: f64,
+    pub time_to_expiry: f64,
+    pub option_type: OptionType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "kebab-case")]
+pub enum PricingModel {
+    BlackScholes,
+    Binomial { steps: usize, american: bool },
+    Heston { params: HestonParams },
+    /// Without a seed the pricing node picks one
+    MonteCarlo { paths: u64, seed: Option<u64> },
+}
+
+impl PricingModel {
+    fn mc_paths(&self) -> Option<u64> {
+        match self {
+            PricingModel::MonteCarlo { paths, .. } => Some(*paths),
+            _ => None,
+        }
+    }
+}
+
+/// Price, with Greeks where the model gives them and the standard error of
+/// Monte Carlo estimates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PricingResult {
+    pub price: f64,
+    pub greeks: Option<Greeks>,
+    pub std_error: Option<f64>,
+    /// `MODEL_VERSION` of the node that computed it
+    pub model_version: String,
+}
+
+/// Price one option on this machine
+pub fn price_locally(inputs: &PricingInputs, model: &PricingModel) -> Result<PricingResult> {
+    price_locally_within(inputs, model, &CancelToken::new())
+}
+
+/// `price_locally`, stopping binomial and Monte Carlo runs with
+/// `Cancelled` when `cancel` fires
+pub fn price_locally_within(inputs: &PricingInputs, model: &PricingModel, cancel: &CancelToken) -> Result<PricingResult> {
+    let PricingInputs { spot, strike, rate, dividend_yield, volatility, time_to_expiry, option_type } = *inputs;
+    let (price, greeks, std_error) = match *model {
+        PricingModel::BlackScholes => {
+            if dividend_yield != 0.0 {
+                anyhow::bail!("Black-Scholes pricing takes no dividend yield; use the binomial or Heston model");
+            }
+            let price = pricing::black_scholes(spot, strike, rate, volatility, time_to_expiry, option_type)?;
+            let greeks = pricing::calculate_greeks(spot, strike, rate, volatility, time_to_expiry, option_type)?;
+            (price, Some(greeks), None)
+        }
+        PricingModel::Binomial { steps, american } => {
+            let params = BinomialParams {
+                spot,
+                strike,
+                rate,
+                dividend_yield,
+                volatility,
+                time_to_expiry,
+                option_type,
+                style: if american { ExerciseStyle::American } else { ExerciseStyle::European },
+                steps,
+            };
+            let price = binomial::binomial_price_within(&params, cancel)?;
+            (price, Some(binomial::binomial_greeks_within(&params, cancel)?), None)
+        }
+        PricingModel::Heston { params } => {
+            let price = pricing::heston::heston_price(spot, strike, rate, dividend_yield, time_to_expiry, option_type, &params)?;
+            (price, None, None)
+        }
+        PricingModel::MonteCarlo { paths, seed } => {
+            let params = monte_carlo::McParams {
+                dividend_yield,
+                seed,
+                ..monte_carlo::McParams::european(spot, strike, rate, volatility, time_to_expiry, option_type, paths)
+            };
+            let estimate = monte_carlo::monte_carlo_price_within(&params, cancel)?;
+            (estimate.price, None, Some(estimate.std_error))
+        }
+    };
+    Ok(PricingResult { price, greeks, std_error, model_version: MODEL_VERSION.to_string() })
+}
+
+/// Price `inputs` at each of `strikes`
+pub fn price_chain_locally(inputs: &PricingInputs, strikes: &[f64], model: &PricingModel) -> Result<Vec<PricingResult>> {
+    price_chain_locally_within(inputs, strikes, model, &CancelToken::new())
+}
+
+/// `price_chain_locally`, checking `cancel` between strikes too
+pub fn price_chain_locally_within(
+    inputs: &PricingInputs,
+    strikes: &[f64],
+    model: &PricingModel,
+    cancel: &CancelToken,
+) -> Result<Vec<PricingResult>> {
+    strikes
+        .iter()
+        .map(|&strike| {
+            cancel.check()?;
+            price_locally_within(&PricingInputs { strike, ..*inputs }, model, cancel)
+        })
+        .collect()
+}
+
+/// Serving side: checks grants and budgets, then prices off the event loop
+pub struct PricingService {
+    config: RemotePricingConfig,
+    quota: Quota,
+    budgets: HashMap<PeerId, RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+}
+
+impl PricingService {
+    pub fn new(config: RemotePricingConfig) -> Self {
+        let per_minute = NonZeroU32::new(config.max_requests_per_minute).unwrap_or(NonZeroU32::MIN);
+        Self { config, quota: Quota::per_minute(per_minute), budgets: HashMap::new() }
+    }
+
+    pub fn config(&self) -> &RemotePricingConfig {
+        &self.config
+    }
+
+    /// Replace the limits (config reload); peers' budgets start over
+    pub fn set_config(&mut self, config: RemotePricingConfig) {
+        *self = Self::new(config);
+    }
+
+    /// Why `peer` may not have this work done now, if it may not. Counts
+    /// against the peer's budget when allowed
+    pub fn admit(&mut self, peer: &PeerId, model: &PricingModel, options: usize) -> Option<String> {
+        if options > self.config.max_chain_strikes {
+            return Some(format!("Chain of {} strikes exceeds the limit of {}", options, self.config.max_chain_strikes));
+        }
+        if let Some(paths) = model.mc_paths().filter(|&p| p > self.config.max_mc_paths) {
+            return Some(format!("{} Monte Carlo paths exceeds the limit of {}", paths, self.config.max_mc_paths));
+        }
+        let quota = self.quota;
+        let budget = self.budgets.entry(*peer).or_insert_with(|| RateLimiter::direct(quota));
+        if budget.check().is_err() {
+            return Some(format!("Pricing budget of {} requests/min exhausted", self.config.max_requests_per_minute));
+        }
+        None
+    }
+}
+
+/// Answer a `PriceOption` / `PriceChain` request already admitted, giving
+/// up (and discarding any partial results) when `cancel` fires
+pub async fn serve(request: QuantraRequest, cancel: CancelToken) -> QuantraResponse {
+    let priced = tokio::task::spawn_blocking(move || match request {
+        QuantraRequest::PriceOption { inputs, model } => {
+            price_locally_within(&inputs, &model, &cancel).map(QuantraResponse::OptionPriced)
+        }
+        QuantraRequest::PriceChain { inputs, strikes, model } => {
+            price_chain_locally_within(&inputs, &strikes, &model, &cancel).map(QuantraResponse::ChainPriced)
+        }
+        other => Err(anyhow::anyhow!("Not a pricing request: {:?}", other)),
+    })
+    .await;
+    match priced {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) if e.is::<Cancelled>() => QuantraResponse::Error("Pricing exceeded its compute budget".to_string()),
+        Ok(Err(e)) => QuantraResponse::Error(format!("Pricing failed: {}", e)),
+        Err(e) => QuantraResponse::Error(format!("Pricing task failed: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_dirs::DataDirs;
+    use crate::p2p::handle::NodeHandle;
+    use crate::p2p::P2PNode;
+    use crate::quant::QuantEngine;
+    use crate::storage::RuntimeMode;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    const INPUTS: PricingInputs = PricingInputs {
+        spot: 100.0,
+        strike: 95.0,
+        rate: 0.04,
+        dividend_yield: 0.0,
+        volatility: 0.3,
+        time_to_expiry: 0.75,
+        option_type: OptionType::Put,
+    };
+
+    #[test]
+    fn test_budget_limits() {
+        let config = RemotePricingConfig { max_requests_per_minute: 2, max_mc_paths: 1_000, max_chain_strikes: 3, ..Default::default() };
+        let mut service = PricingService::new(config);
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+        let mc = |paths| PricingModel::MonteCarlo { paths, seed: None };
+
+        assert!(service.admit(&alice, &mc(5_000), 1).unwrap().contains("Monte Carlo paths"));
+        assert!(service.admit(&alice, &PricingModel::BlackScholes, 4).unwrap().contains("strikes"));
+        assert_eq!(service.admit(&alice, &mc(1_000), 1), None);
+        assert_eq!(service.admit(&alice, &PricingModel::BlackScholes, 3), None);
+        assert!(service.admit(&alice, &PricingModel::BlackScholes, 1).unwrap().contains("budget"));
+        // Budgets are per peer
+        assert_eq!(service.admit(&bob, &PricingModel::BlackScholes, 1), None);
+    }
+
+    #[tokio::test]
+    async fn test_pricing_cancelled_at_deadline() {
+        let mc = PricingModel::MonteCarlo { paths: 10_000_000, seed: Some(1) };
+        let chain = QuantraRequest::PriceChain { inputs: INPUTS, strikes: vec![90.0, 95.0, 100.0, 105.0], model: mc };
+        let started = std::time::Instant::now();
+        let budget = CancelToken::with_deadline(started + Duration::from_millis(50));
+        let response = serve(chain, budget).await;
+        // Strikes priced before the deadline are discarded with the rest
+        assert!(matches!(&response, QuantraResponse::Error(e) if e.contains("compute budget")), "{:?}", response);
+        assert!(started.elapsed() < Duration::from_secs(2), "pricing ran for {:?}", started.elapsed());
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let err = price_locally_within(&INPUTS, &PricingModel::Binomial { steps: 100_000, american: true }, &cancel).unwrap_err();
+        assert!(err.is::<Cancelled>());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_remote_pricing_between_nodes() {
+        let dirs = tempfile::tempdir().unwrap();
+        let mut server = P2PNode::new().unwrap();
+        server.disable_mdns();
+        server.set_data_dirs(DataDirs::resolve(Some(dirs.path()), None, RuntimeMode::Persistent).unwrap());
+        server.enable_zero_trust().await.unwrap();
+        server.enable_remote_pricing(RemotePricingConfig {
+            serve: true,
+            max_requests_per_minute: 3,
+            max_mc_paths: 100_000,
+            ..Default::default()
+        });
+        server.listen_on("/ip4/127.0.0.1/tcp/0").unwrap();
+        let server_zt = server.zero_trust().unwrap().clone();
+        let (server, server_task) = NodeHandle::attach(server, false);
+
+        let mut client = P2PNode::new().unwrap();
+        client.disable_mdns();
+        let (client, client_task) = NodeHandle::attach(client, false);
+        let address = timeout(Duration::from_secs(10), async {
+            loop {
+                if let Some(address) = server.status().await.unwrap().listeners[0].addresses.first() {
+                    return address.clone();
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("server never bound its listener");
+        client.dial(&address).await.unwrap();
+        timeout(Duration::from_secs(10), async {
+            while server_zt.get_active_connections().await.unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("server never admitted the client");
+
+        // Without a local fallback every price comes from the server
+        let config = RemotePricingConfig { fallback_to_local: false, ..Default::default() };
+        let engine = QuantEngine::remote(client.clone(), server.peer_id(), &config);
+
+        let remote = engine.price_option(&INPUTS, &PricingModel::BlackScholes).await.unwrap();
+        let local = price_locally(&INPUTS, &PricingModel::BlackScholes).unwrap();
+        assert_eq!(remote.price.to_bits(), local.price.to_bits());
+        assert_eq!(remote.greeks, local.greeks);
+        assert_eq!(remote.model_version, MODEL_VERSION);
+
+        let mc = PricingModel::MonteCarlo { paths: 100_000, seed: None };
+        let remote = engine.price_chain(&INPUTS, &[90.0, 95.0, 100.0], &mc).await.unwrap();
+        assert_eq!(remote.len(), 3);
+        for (priced, strike) in remote.iter().zip([90.0, 95.0, 100.0]) {
+            let exact = pricing::black_scholes(INPUTS.spot, strike, INPUTS.rate, INPUTS.volatility, INPUTS.time_to_expiry, OptionType::Put).unwrap();
+            let std_error = priced.std_error.expect("Monte Carlo results carry a standard error");
+            assert!((priced.price - exact).abs() < 4.0 * std_error, "{} vs {}", priced.price, exact);
+        }
+
+        // Over the path limit, then over the per-minute budget
+        let greedy = PricingModel::MonteCarlo { paths: 1_000_000, seed: None };
+        let refused = engine.price_option(&INPUTS, &greedy).await.unwrap_err();
+        assert!(format!("{:#}", refused).contains("Monte Carlo paths"), "{:#}", refused);
+        engine.price_option(&INPUTS, &PricingModel::BlackScholes).await.unwrap();
+        let refused = engine.price_option(&INPUTS, &PricingModel::BlackScholes).await.unwrap_err();
+        assert!(format!("{:#}", refused).contains("budget"), "{:#}", refused);
+
+        // With the fallback the caller still gets a price
+        let engine = QuantEngine::remote(client.clone(), server.peer_id(), &RemotePricingConfig::default());
+        let priced = engine.price_option(&INPUTS, &PricingModel::BlackScholes).await.unwrap();
+        assert_eq!(priced.price.to_bits(), local.price.to_bits());
+
+        for (node, task) in [(server, server_task), (client, client_task)] {
+            node.shutdown().await;
+            task.await.unwrap().unwrap();
+        }
+    }
+}
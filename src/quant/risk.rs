@@ -1,22 +1,194 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
 use ndarray::Array2;
-use statrs::distribution::{ContinuousCDF, Normal};
+use rust_decimal::prelude::ToPrimitive;
+use serde::Serialize;
+use statrs::distribution::{Continuous, ContinuousCDF, Normal};
+use std::collections::HashMap;
+use std::fmt;
+use super::export::RiskMetric;
 use super::portfolio::Portfolio;
 
-pub async fn calculate_var(portfolio: &Portfolio, confidence: f64) -> Result<f64> {
-    // Value at Risk calculation using historical simulation
-    // This is a simplified implementation
+/// Annual volatility assumed by `parametric_var`
+const PARAMETRIC_VOLATILITY: f64 = 0.15;
 
+/// How `QuantEngine::calculate_portfolio_var` models losses
+#[derive(Debug, Clone)]
+pub enum VarMethod {
+    /// Normal returns at a flat 15% annual volatility, over a year
+    Parametric,
+    /// Empirical quantile of `horizon_days` P&L replayed from daily returns
+    /// per symbol (oldest first)
+    Historical { returns: HashMap<String, Vec<f64>>, horizon_days: u32 },
+}
+
+/// Loss not exceeded at the confidence level, and the mean loss beyond it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct VarResult {
+    pub var: f64,
+    /// Expected shortfall
+    pub cvar: f64,
+    /// Historical scenarios the quantile was taken over (0 for parametric)
+    pub scenarios: usize,
+}
+
+/// A held symbol has no return series
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingReturns {
+    pub symbol: String,
+}
+
+impl fmt::Display for MissingReturns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no return series for held symbol {}", self.symbol)
+    }
+}
+
+impl std::error::Error for MissingReturns {}
+
+fn check_confidence(confidence: f64) -> Result<()> {
+    if !(confidence > 0.0 && confidence < 1.0) {
+        anyhow::bail!("Confidence must be between 0 and 1, got {}", confidence);
+    }
+    Ok(())
+}
+
+/// VaR ignoring the portfolio's composition: its total value at an assumed
+/// 15% volatility
+pub fn parametric_var(portfolio: &Portfolio, confidence: f64) -> Result<VarResult> {
+    check_confidence(confidence)?;
     let normal = Normal::new(0.0, 1.0)?;
-    let z_score = normal.inverse_cdf(1.0 - confidence);
+    let z_score = normal.inverse_cdf(confidence);
 
-    // Mock volatility calculation
-    let portfolio_value = portfolio.total_value().to_string().parse::<f64>()?;
-    let assumed_volatility = 0.15; // 15% annual volatility
+    let portfolio_value = portfolio.total_value().to_f64().context("Portfolio value out of range")?;
+    let sigma = portfolio_value * PARAMETRIC_VOLATILITY;
 
-    let var = portfolio_value * assumed_volatility * z_score.abs();
+    Ok(VarResult { var: sigma * z_score, cvar: sigma * normal.pdf(z_score) / (1.0 - confidence), scenarios: 0 })
+}
 
-    Ok(var)
+/// Historical-simulation VaR over `horizon_days`. Each scenario is one
+/// overlapping window of compounded daily returns, applied to every
+/// position's current value; series are aligned on their latest return
+pub fn historical_var(
+    portfolio: &Portfolio,
+    returns: &HashMap<String, Vec<f64>>,
+    confidence: f64,
+    horizon_days: u32,
+) -> Result<VarResult> {
+    check_confidence(confidence)?;
+    if horizon_days == 0 {
+        anyhow::bail!("VaR horizon must be at least 1 day");
+    }
+    let horizon = horizon_days as usize;
+
+    let mut exposures = Vec::new();
+    for (symbol, position) in &portfolio.positions {
+        let value = (position.quantity * position.current_price)
+            .to_f64()
+            .with_context(|| format!("{} position value out of range", symbol))?;
+        let series = returns.get(symbol).ok_or_else(|| MissingReturns { symbol: symbol.clone() })?;
+        exposures.push((symbol, value, series));
+    }
+    let Some(days) = exposures.iter().map(|(_, _, series)| series.len()).min() else {
+        return Ok(VarResult { var: 0.0, cvar: 0.0, scenarios: 0 });
+    };
+    if days < horizon {
+        anyhow::bail!("Historical VaR over {} days needs at least {} returns per symbol, got {}", horizon_days, horizon, days);
+    }
+
+    let scenarios = days - horizon + 1;
+    let mut pnl = vec![0.0; scenarios];
+    for (_, value, series) in &exposures {
+        let recent = &series[series.len() - days..];
+        for (scenario, window) in recent.windows(horizon).enumerate() {
+            let growth: f64 = window.iter().map(|r| 1.0 + r).product();
+            pnl[scenario] += value * (growth - 1.0);
+        }
+    }
+
+    // Worst first; the tail is the (1 - confidence) share of scenarios
+    pnl.sort_by(|a, b| a.total_cmp(b));
+    let tail = (((1.0 - confidence) * scenarios as f64).ceil() as usize).clamp(1, scenarios);
+    Ok(VarResult {
+        var: -pnl[tail - 1],
+        cvar: -pnl[..tail].iter().sum::<f64>() / tail as f64,
+        scenarios,
+    })
+}
+
+/// Risk of the current holdings over their recent daily closes
+#[derive(Debug, Clone, Serialize)]
+pub struct RiskReport {
+    pub total_value: f64,
+    pub confidence: f64,
+    pub horizon_days: u32,
+    pub historical: VarResult,
+    pub parametric: VarResult,
+    /// Daily, of the holdings' value path, against a zero risk-free rate
+    pub sharpe_ratio: f64,
+    pub max_drawdown: f64,
+}
+
+impl RiskReport {
+    /// One row per figure, for `risk-report --export`
+    pub fn metrics(&self, computed_at: DateTime<Utc>) -> Vec<RiskMetric> {
+        [
+            ("total_value", self.total_value),
+            ("confidence", self.confidence),
+            ("horizon_days", self.horizon_days as f64),
+            ("historical_var", self.historical.var),
+            ("historical_cvar", self.historical.cvar),
+            ("historical_scenarios", self.historical.scenarios as f64),
+            ("parametric_var", self.parametric.var),
+            ("parametric_cvar", self.parametric.cvar),
+            ("sharpe_ratio", self.sharpe_ratio),
+            ("max_drawdown", self.max_drawdown),
+        ]
+        .into_iter()
+        .map(|(metric, value)| RiskMetric { metric: metric.to_string(), value, computed_at })
+        .collect()
+    }
+}
+
+/// VaR both ways, Sharpe ratio and drawdown of `portfolio` from each held
+/// symbol's daily closes (oldest first). The value path holds today's
+/// quantities through the closes, aligned on the latest one
+pub fn risk_report(
+    portfolio: &Portfolio,
+    closes: &HashMap<String, Vec<f64>>,
+    confidence: f64,
+    horizon_days: u32,
+) -> Result<RiskReport> {
+    let returns: HashMap<String, Vec<f64>> = closes
+        .iter()
+        .map(|(symbol, series)| (symbol.clone(), series.windows(2).map(|w| w[1] / w[0] - 1.0).collect()))
+        .collect();
+    let historical = historical_var(portfolio, &returns, confidence, horizon_days)?;
+    let parametric = parametric_var(portfolio, confidence)?;
+
+    let mut holdings = Vec::new();
+    for (symbol, position) in &portfolio.positions {
+        let quantity = position.quantity.to_f64().with_context(|| format!("{} quantity out of range", symbol))?;
+        holdings.push((quantity, &closes[symbol]));
+    }
+    let days = holdings.iter().map(|(_, series)| series.len()).min().unwrap_or(0);
+    if days < 2 {
+        anyhow::bail!("A risk report needs at least 2 closes per symbol, got {}", days);
+    }
+    let values: Vec<f64> = (0..days)
+        .map(|day| holdings.iter().map(|(quantity, series)| quantity * series[series.len() - days + day]).sum())
+        .collect();
+    let path_returns: Vec<f64> = values.windows(2).map(|w| w[1] / w[0] - 1.0).collect();
+
+    Ok(RiskReport {
+        total_value: portfolio.total_value().to_f64().context("Portfolio value out of range")?,
+        confidence,
+        horizon_days,
+        historical,
+        parametric,
+        sharpe_ratio: calculate_sharpe_ratio(&path_returns, 0.0)?,
+        max_drawdown: calculate_max_drawdown(&values)?,
+    })
 }
 
 pub fn calculate_sharpe_ratio(returns: &[f64], risk_free_rate: f64) -> Result<f64> {
@@ -110,3 +282,78 @@ fn calculate_correlation(x: &[f64], y: &[f64]) -> Result<f64> {
 
     Ok(cov / (std_x * std_y))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    fn portfolio(positions: &[(&str, i64, i64)]) -> Portfolio {
+        let mut portfolio = Portfolio::new("p".to_string(), "Test".to_string());
+        for &(symbol, quantity, price) in positions {
+            portfolio.add_position(symbol.to_string(), Decimal::from(quantity), Decimal::from(price));
+        }
+        portfolio
+    }
+
+    #[test]
+    fn test_historical_var_quantile() {
+        // 20 daily returns: two losing days stand out
+        let mut daily = vec![0.01; 18];
+        daily.extend([-0.05, -0.03]);
+        let returns = HashMap::from([("AAPL".to_string(), daily)]);
+        let held = portfolio(&[("AAPL", 10, 100)]);
+
+        let result = historical_var(&held, &returns, 0.9, 1).unwrap();
+        assert_eq!(result.scenarios, 20);
+        assert!((result.var - 30.0).abs() < 1e-9, "{:?}", result);
+        assert!((result.cvar - 40.0).abs() < 1e-9, "{:?}", result);
+
+        // Over two days the losses compound
+        let result = historical_var(&held, &returns, 0.95, 2).unwrap();
+        assert_eq!(result.scenarios, 19);
+        assert!((result.var - 1000.0 * (1.0 - 0.95 * 0.97)).abs() < 1e-9, "{:?}", result);
+    }
+
+    #[test]
+    fn test_historical_var_uses_composition() {
+        let up_down: Vec<f64> = (0..50).map(|i| if i % 2 == 0 { 0.02 } else { -0.02 }).collect();
+        let mirrored: Vec<f64> = up_down.iter().map(|r| -r).collect();
+        let returns = HashMap::from([("A".to_string(), up_down), ("B".to_string(), mirrored)]);
+
+        let single = historical_var(&portfolio(&[("A", 10, 100)]), &returns, 0.95, 1).unwrap();
+        assert!(single.var > 0.0);
+        // Equal values in perfectly offsetting symbols
+        let hedged = historical_var(&portfolio(&[("A", 10, 100), ("B", 20, 50)]), &returns, 0.95, 1).unwrap();
+        assert!(hedged.var.abs() < 1e-9, "{:?}", hedged);
+
+        let err = historical_var(&portfolio(&[("A", 10, 100), ("C", 1, 1)]), &returns, 0.95, 1).unwrap_err();
+        assert_eq!(err.downcast_ref::<MissingReturns>().map(|e| e.symbol.as_str()), Some("C"));
+        assert!(historical_var(&portfolio(&[("A", 10, 100)]), &returns, 0.95, 51).is_err());
+    }
+
+    #[test]
+    fn test_risk_report() {
+        let closes = HashMap::from([("AAPL".to_string(), vec![100.0, 110.0, 99.0, 104.0])]);
+        let report = risk_report(&portfolio(&[("AAPL", 10, 104)]), &closes, 0.9, 1).unwrap();
+        assert_eq!(report.historical.scenarios, 3);
+        // The 10% fall from 110 to 99, applied to 1040 held
+        assert!((report.historical.var - 104.0).abs() < 1e-9, "{:?}", report);
+        assert!((report.max_drawdown - 0.1).abs() < 1e-9, "{:?}", report);
+
+        let metrics = report.metrics(chrono::Utc::now());
+        assert_eq!(metrics.len(), 10);
+        assert_eq!(metrics.iter().find(|m| m.metric == "historical_var").map(|m| m.value), Some(report.historical.var));
+
+        let short = HashMap::from([("AAPL".to_string(), vec![100.0])]);
+        assert!(risk_report(&portfolio(&[("AAPL", 10, 100)]), &short, 0.9, 1).is_err());
+    }
+
+    #[test]
+    fn test_parametric_var() {
+        let result = parametric_var(&portfolio(&[("AAPL", 10, 100)]), 0.95).unwrap();
+        assert!((result.var - 1000.0 * 0.15 * 1.644853627).abs() < 1e-6, "{:?}", result);
+        assert!(result.cvar > result.var);
+        assert_eq!(result.scenarios, 0);
+    }
+}
@@ -0,0 +1,376 @@
+//! Position Sizing
+//! Kelly and volatility-target sizing, capped by a share of average daily
+//! volume. `suggest_size` applies every constraint it is given and reports
+//! which one binds; degenerate inputs are errors or a zero size with a
+//! reason, never NaN
+
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::Candle;
+
+/// Annualizes daily volatility
+pub const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+
+/// Why a size can't be computed
+#[derive(Debug, Clone, PartialEq)]
+pub enum SizingError {
+    /// A probability or share outside 0..=1
+    OutOfRange { input: &'static str, value: f64 },
+    /// An input that must be positive and finite
+    NotPositive { input: &'static str, value: String },
+    /// `suggest_size` was given no constraint to size by
+    NoConstraint,
+}
+
+impl fmt::Display for SizingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfRange { input, value } => write!(f, "{} {} is not between 0 and 1", input, value),
+            Self::NotPositive { input, value } => write!(f, "{} must be positive (got {})", input, value),
+            Self::NoConstraint => write!(f, "nothing to size by: give a volatility target, Kelly inputs or a liquidity cap"),
+        }
+    }
+}
+
+impl std::error::Error for SizingError {}
+
+fn positive(input: &'static str, value: f64) -> Result<f64, SizingError> {
+    match value.is_finite() && value > 0.0 {
+        true => Ok(value),
+        false => Err(SizingError::NotPositive { input, value: value.to_string() }),
+    }
+}
+
+fn positive_decimal(input: &'static str, value: Decimal) -> Result<Decimal, SizingError> {
+    match value > Decimal::ZERO {
+        true => Ok(value),
+        false => Err(SizingError::NotPositive { input, value: value.to_string() }),
+    }
+}
+
+/// Full Kelly fraction `p - (1 - p) / b`; negative when the bet has no edge
+pub fn kelly_fraction(win_prob: f64, win_loss_ratio: f64) -> Result<f64, SizingError> {
+    if !(0.0..=1.0).contains(&win_prob) {
+        return Err(SizingError::OutOfRange { input: "win probability", value: win_prob });
+    }
+    let ratio = positive("win/loss ratio", win_loss_ratio)?;
+    Ok(win_prob - (1.0 - win_prob) / ratio)
+}
+
+/// `fraction` of full Kelly, clamped to `0..=cap`
+pub fn fractional_kelly(win_prob: f64, win_loss_ratio: f64, fraction: f64, cap: f64) -> Result<f64, SizingError> {
+    let fraction = positive("Kelly fraction", fraction)?;
+    let cap = positive("Kelly cap", cap)?;
+    Ok((kelly_fraction(win_prob, win_loss_ratio)? * fraction).clamp(0.0, cap))
+}
+
+/// Shares whose volatility matches `target_annual_vol` of `portfolio_value`,
+/// rounded down to `lot_size` (0 for fractional)
+pub fn vol_target_size(
+    target_annual_vol: f64,
+    asset_vol: f64,
+    portfolio_value: Decimal,
+    price: Decimal,
+    lot_size: Decimal,
+) -> Result<Decimal, SizingError> {
+    let weight = positive("target volatility", target_annual_vol)? / positive("asset volatility", asset_vol)?;
+    let notional = positive_decimal("portfolio value", portfolio_value)? * decimal(weight);
+    Ok(round_lot(notional / positive_decimal("price", price)?, lot_size))
+}
+
+/// Most shares tradable at `participation_cap` of average daily volume
+pub fn max_position_by_adv(average_daily_volume: Decimal, participation_cap: f64) -> Result<Decimal, SizingError> {
+    let cap = positive("participation cap", participation_cap)?;
+    if cap > 1.0 {
+        return Err(SizingError::OutOfRange { input: "participation cap", value: cap });
+    }
+    Ok(positive_decimal("average daily volume", average_daily_volume)? * decimal(cap))
+}
+
+/// Annualized volatility of close-to-close log returns
+pub fn realized_vol(candles: &[Candle]) -> Option<f64> {
+    let closes: Vec<f64> = candles.iter().filter_map(|c| c.close.to_f64()).filter(|c| *c > 0.0).collect();
+    let returns: Vec<f64> = closes.windows(2).map(|w| (w[1] / w[0]).ln()).collect();
+    if returns.len() < 2 {
+        return None;
+    }
+    let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64;
+    Some((variance * TRADING_DAYS_PER_YEAR).sqrt())
+}
+
+/// Mean volume over `candles`
+pub fn average_daily_volume(candles: &[Candle]) -> Option<Decimal> {
+    if candles.is_empty() {
+        return None;
+    }
+    let total: u64 = candles.iter().map(|c| c.volume).sum();
+    Some(Decimal::from(total) / Decimal::from(candles.len() as u64))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VolTarget {
+    pub target_annual_vol: f64,
+    pub asset_vol: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct KellyInputs {
+    pub win_prob: f64,
+    pub win_loss_ratio: f64,
+    /// Share of full Kelly to bet, e.g. 0.5 for half Kelly
+    pub fraction: f64,
+    /// Largest fraction of the portfolio to commit
+    pub max_fraction: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LiquidityCap {
+    pub average_daily_volume: Decimal,
+    pub participation_cap: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SizingInputs {
+    pub portfolio_value: Decimal,
+    pub price: Decimal,
+    /// Quantities are rounded down to a multiple of this (0 = fractional)
+    pub lot_size: Decimal,
+    pub vol_target: Option<VolTarget>,
+    pub kelly: Option<KellyInputs>,
+    pub liquidity: Option<LiquidityCap>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SizingConstraint {
+    VolTarget,
+    Kelly,
+    Liquidity,
+}
+
+impl fmt::Display for SizingConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::VolTarget => "volatility target",
+            Self::Kelly => "Kelly",
+            Self::Liquidity => "liquidity cap",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SizingSuggestion {
+    pub quantity: Decimal,
+    pub notional: Decimal,
+    /// The constraint giving the smallest size
+    pub binding: SizingConstraint,
+    /// Size each constraint alone would allow
+    pub limits: Vec<(SizingConstraint, Decimal)>,
+    /// Why the size is zero, when it is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Size by every constraint in `inputs` and take the smallest
+pub fn suggest_size(inputs: &SizingInputs) -> Result<SizingSuggestion, SizingError> {
+    let price = positive_decimal("price", inputs.price)?;
+    let mut limits = Vec::new();
+    let mut reason = None;
+    if let Some(target) = inputs.vol_target {
+        let quantity = vol_target_size(target.target_annual_vol, target.asset_vol, inputs.portfolio_value, price, inputs.lot_size)?;
+        limits.push((SizingConstraint::VolTarget, quantity));
+    }
+    if let Some(kelly) = inputs.kelly {
+        let full = kelly_fraction(kelly.win_prob, kelly.win_loss_ratio)?;
+        let fraction = fractional_kelly(kelly.win_prob, kelly.win_loss_ratio, kelly.fraction, kelly.max_fraction)?;
+        if full <= 0.0 {
+            reason = Some(format!("no edge: full Kelly fraction is {:.4}", full));
+        }
+        let notional = positive_decimal("portfolio value", inputs.portfolio_value)? * decimal(fraction);
+        limits.push((SizingConstraint::Kelly, round_lot(notional / price, inputs.lot_size)));
+    }
+    if let Some(liquidity) = inputs.liquidity {
+        let quantity = max_position_by_adv(liquidity.average_daily_volume, liquidity.participation_cap)?;
+        limits.push((SizingConstraint::Liquidity, round_lot(quantity, inputs.lot_size)));
+    }
+
+    let &(binding, quantity) = limits.iter().min_by_key(|(_, q)| *q).ok_or(SizingError::NoConstraint)?;
+    if quantity.is_zero() && reason.is_none() {
+        reason = Some(format!("the {} allows less than one lot", binding));
+    }
+    Ok(SizingSuggestion { quantity, notional: quantity * price, binding, limits, reason })
+}
+
+impl fmt::Display for SizingSuggestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "📐 Size {} (notional {}), bound by the {}", self.quantity, self.notional.round_dp(2), self.binding)?;
+        for (constraint, quantity) in &self.limits {
+            writeln!(f, "  {:<18} {}", constraint.to_string(), quantity)?;
+        }
+        if let Some(reason) = &self.reason {
+            writeln!(f, "  ⚠️  {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sizing applied to each entry of a backtest, from the candles so far
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizingPolicy {
+    pub target_annual_vol: Option<f64>,
+    pub kelly: Option<KellyInputs>,
+    pub participation_cap: Option<f64>,
+    pub lot_size: Decimal,
+    /// Candles used for realized volatility and average volume
+    pub lookback: usize,
+}
+
+impl SizingPolicy {
+    /// Inputs for an entry at the last of `history` (oldest first). Too
+    /// little history for a volatility estimate is a zero asset volatility,
+    /// so `suggest_size` rejects it
+    pub fn inputs(&self, history: &[Candle], portfolio_value: Decimal) -> SizingInputs {
+        let window = &history[history.len().saturating_sub(self.lookback)..];
+        SizingInputs {
+            portfolio_value,
+            price: window.last().map_or(Decimal::ZERO, |c| c.close),
+            lot_size: self.lot_size,
+            vol_target: self.target_annual_vol.map(|target_annual_vol| VolTarget {
+                target_annual_vol,
+                asset_vol: realized_vol(window).unwrap_or(0.0),
+            }),
+            kelly: self.kelly,
+            liquidity: self.participation_cap.map(|participation_cap| LiquidityCap {
+                average_daily_volume: average_daily_volume(window).unwrap_or_default(),
+                participation_cap,
+            }),
+        }
+    }
+}
+
+/// Rounded so float noise (0.6 - 0.4 / 2 = 0.39999999999999997) doesn't
+/// cost a lot
+fn decimal(value: f64) -> Decimal {
+    Decimal::from_f64(value).unwrap_or_default().round_dp(12)
+}
+
+fn round_lot(quantity: Decimal, lot: Decimal) -> Decimal {
+    if lot <= Decimal::ZERO {
+        return quantity.trunc_with_scale(8);
+    }
+    (quantity / lot).trunc() * lot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone, Utc};
+
+    fn inputs() -> SizingInputs {
+        SizingInputs {
+            portfolio_value: Decimal::from(100_000),
+            price: Decimal::from(50),
+            lot_size: Decimal::ONE,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_formulas() {
+        // 0.6 - 0.4 / 2 = 0.4; half Kelly 0.2, capped at 0.15
+        assert!((kelly_fraction(0.6, 2.0).unwrap() - 0.4).abs() < 1e-12);
+        assert!((fractional_kelly(0.6, 2.0, 0.5, 1.0).unwrap() - 0.2).abs() < 1e-12);
+        assert_eq!(fractional_kelly(0.6, 2.0, 0.5, 0.15).unwrap(), 0.15);
+        assert!(kelly_fraction(0.3, 1.0).unwrap() < 0.0);
+        assert_eq!(fractional_kelly(0.3, 1.0, 1.0, 1.0).unwrap(), 0.0);
+
+        // 10% / 25% of 100k = 40k notional = 800 shares at 50; lots of 100 round 833 down
+        let shares = vol_target_size(0.10, 0.25, Decimal::from(100_000), Decimal::from(50), Decimal::ONE).unwrap();
+        assert_eq!(shares, Decimal::from(800));
+        let lots = vol_target_size(0.10, 0.30, Decimal::from(100_000), Decimal::from(40), Decimal::from(100)).unwrap();
+        assert_eq!(lots, Decimal::from(800));
+
+        assert_eq!(max_position_by_adv(Decimal::from(20_000), 0.05).unwrap(), Decimal::from(1_000));
+
+        // Degenerate inputs are errors, not NaN or infinity
+        assert!(matches!(kelly_fraction(1.5, 2.0), Err(SizingError::OutOfRange { .. })));
+        assert!(matches!(max_position_by_adv(Decimal::from(20_000), 1.5), Err(SizingError::OutOfRange { .. })));
+        assert!(matches!(kelly_fraction(0.5, 0.0), Err(SizingError::NotPositive { .. })));
+        let zero_vol = vol_target_size(0.10, 0.0, Decimal::from(100_000), Decimal::from(50), Decimal::ONE);
+        assert!(matches!(zero_vol, Err(SizingError::NotPositive { input: "asset volatility", .. })));
+        let zero_price = vol_target_size(0.10, 0.2, Decimal::from(100_000), Decimal::ZERO, Decimal::ONE);
+        assert!(matches!(zero_price, Err(SizingError::NotPositive { input: "price", .. })));
+        assert!(vol_target_size(0.10, f64::NAN, Decimal::ONE, Decimal::ONE, Decimal::ONE).is_err());
+    }
+
+    #[test]
+    fn test_binding_constraint() {
+        let mut sizing = inputs();
+        assert_eq!(suggest_size(&sizing), Err(SizingError::NoConstraint));
+
+        // Vol target 800 shares, half Kelly 20% = 400 shares, 5% of 20k ADV = 1000
+        sizing.vol_target = Some(VolTarget { target_annual_vol: 0.10, asset_vol: 0.25 });
+        sizing.liquidity = Some(LiquidityCap { average_daily_volume: Decimal::from(20_000), participation_cap: 0.05 });
+        let suggestion = suggest_size(&sizing).unwrap();
+        assert_eq!((suggestion.binding, suggestion.quantity), (SizingConstraint::VolTarget, Decimal::from(800)));
+
+        sizing.kelly = Some(KellyInputs { win_prob: 0.6, win_loss_ratio: 2.0, fraction: 0.5, max_fraction: 1.0 });
+        let suggestion = suggest_size(&sizing).unwrap();
+        assert_eq!((suggestion.binding, suggestion.quantity), (SizingConstraint::Kelly, Decimal::from(400)));
+        assert_eq!(suggestion.notional, Decimal::from(20_000));
+        assert_eq!(suggestion.limits.len(), 3);
+        assert!(suggestion.reason.is_none());
+
+        sizing.liquidity = Some(LiquidityCap { average_daily_volume: Decimal::from(2_000), participation_cap: 0.05 });
+        assert_eq!(suggest_size(&sizing).unwrap().binding, SizingConstraint::Liquidity);
+
+        // No edge sizes to zero and says why
+        sizing.kelly = Some(KellyInputs { win_prob: 0.3, win_loss_ratio: 1.0, fraction: 0.5, max_fraction: 1.0 });
+        let suggestion = suggest_size(&sizing).unwrap();
+        assert_eq!((suggestion.binding, suggestion.quantity), (SizingConstraint::Kelly, Decimal::ZERO));
+        assert!(suggestion.reason.unwrap().starts_with("no edge"));
+    }
+
+    #[test]
+    fn test_policy_from_history() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        // Closes alternate 100 / 110: ten log returns of ±ln(1.1) with mean 0
+        let history: Vec<Candle> = (0..21)
+            .map(|i| {
+                let close = if i % 2 == 0 { Decimal::from(100) } else { Decimal::from(110) };
+                Candle {
+                    symbol: "AAPL".to_string(),
+                    timestamp: start + Duration::days(i),
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    volume: 1_000 * (i as u64 + 1),
+                }
+            })
+            .collect();
+        let vol = realized_vol(&history[10..]).unwrap();
+        let daily = (1.1f64).ln();
+        let expected = (daily * daily * 10.0 / 9.0 * TRADING_DAYS_PER_YEAR).sqrt();
+        assert!((vol - expected).abs() < 1e-9, "{} vs {}", vol, expected);
+
+        let policy = SizingPolicy {
+            target_annual_vol: Some(0.10),
+            kelly: None,
+            participation_cap: Some(0.10),
+            lot_size: Decimal::ONE,
+            lookback: 10,
+        };
+        let inputs = policy.inputs(&history, Decimal::from(100_000));
+        assert_eq!(inputs.price, Decimal::from(100));
+        // Volumes 12k..21k average 16.5k
+        assert_eq!(inputs.liquidity.unwrap().average_daily_volume, Decimal::from(16_500));
+        // One candle is not enough for a volatility estimate
+        let err = suggest_size(&policy.inputs(&history[..1], Decimal::from(100_000))).unwrap_err();
+        assert!(matches!(err, SizingError::NotPositive { input: "asset volatility", .. }));
+    }
+}
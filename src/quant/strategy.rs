@@ -0,0 +1,512 @@
+//! Strategy Runs
+//! A `Strategy` turns candles or quotes into buy/sell/hold signals. The
+//! backtester replays a candle history through one; the paper trader feeds
+//! it live quotes and records its trades in the paper portfolio. Both hold
+//! at most a fixed quantity per symbol (or, in a backtest, a size from a
+//! `SizingPolicy`), long only, and stop at the strategy's first error.
+//! Buys beyond the account's buying power are skipped, and cash earns (or,
+//! borrowed on margin, costs) interest for every calendar day
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+use super::account::{self, AccountSettings, OrderRejection, PaperAccount};
+use super::market_data::MarketDataProvider;
+use super::portfolio::Portfolio;
+use super::portfolio_store::{market_trade, PortfolioStore};
+use super::rebalance::FeeModel;
+use super::sizing::{self, SizingPolicy};
+use super::{AssetType, Candle, Quote, Trade, TradeSide};
+
+/// Multi-leg option positions, unrelated to the signal `Strategy` trait
+pub use super::pricing::strategy::{OptionLeg, OptionStrategy};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Signal {
+    Buy,
+    Sell,
+    Hold,
+}
+
+impl Signal {
+    /// Plugin encoding: 1 buy, -1 sell, 0 hold
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            1 => Some(Self::Buy),
+            -1 => Some(Self::Sell),
+            0 => Some(Self::Hold),
+            _ => None,
+        }
+    }
+}
+
+pub trait Strategy {
+    fn name(&self) -> &str;
+    fn on_candle(&mut self, candle: &Candle) -> Result<Signal>;
+    fn on_quote(&mut self, quote: &Quote) -> Result<Signal>;
+}
+
+/// The side to trade on `signal`, holding `held` and allowed `quantity`
+fn order_for(signal: Signal, held: Decimal, quantity: Decimal) -> Option<TradeSide> {
+    if quantity.is_zero() {
+        return None;
+    }
+    match signal {
+        Signal::Buy if held < quantity => Some(TradeSide::Buy),
+        Signal::Sell if held >= quantity => Some(TradeSide::Sell),
+        _ => None,
+    }
+}
+
+/// Replays candles through a strategy, filling at the close
+#[derive(Debug, Clone)]
+pub struct Backtester {
+    /// Bought on a buy signal, sold on a sell signal
+    pub quantity: Decimal,
+    pub starting_cash: Decimal,
+    pub fees: FeeModel,
+    /// Size entries from equity and recent candles instead of `quantity`;
+    /// a sell signal then closes the whole position
+    pub sizing: Option<SizingPolicy>,
+    /// Interest and margin rates (`starting_cash` is ignored)
+    pub account: AccountSettings,
+    /// Margin class of the traded symbols
+    pub asset_type: AssetType,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestReport {
+    pub strategy: String,
+    pub candles: usize,
+    pub buy_signals: usize,
+    pub sell_signals: usize,
+    /// Buy signals skipped for lack of buying power
+    pub unfunded: usize,
+    /// Buy signals the sizing policy sized to zero
+    pub zero_sized: usize,
+    pub trades: Vec<Trade>,
+    pub fees: Decimal,
+    /// Interest earned on idle cash
+    pub interest: Decimal,
+    /// Interest paid on cash borrowed on margin
+    pub financing_cost: Decimal,
+    /// Average share of equity held as cash at each close, in %
+    pub cash_drag_pct: Decimal,
+    pub starting_cash: Decimal,
+    pub cash: Decimal,
+    /// Open positions at the last close
+    pub holdings: Decimal,
+    pub equity: Decimal,
+    pub return_pct: Decimal,
+}
+
+impl Backtester {
+    /// Run `strategy` over `candles` (oldest first). A strategy error ends
+    /// the run with the candle it failed on in the context
+    pub fn run<I>(&self, strategy: &mut dyn Strategy, candles: I) -> Result<BacktestReport>
+    where
+        I: IntoIterator<Item = Result<Candle>>,
+    {
+        let mut portfolio = Portfolio::new("backtest".to_string(), strategy.name().to_string());
+        let mut report = BacktestReport {
+            strategy: strategy.name().to_string(),
+            candles: 0,
+            buy_signals: 0,
+            sell_signals: 0,
+            unfunded: 0,
+            zero_sized: 0,
+            trades: Vec::new(),
+            fees: Decimal::ZERO,
+            interest: Decimal::ZERO,
+            financing_cost: Decimal::ZERO,
+            cash_drag_pct: Decimal::ZERO,
+            starting_cash: self.starting_cash,
+            cash: self.starting_cash,
+            holdings: Decimal::ZERO,
+            equity: self.starting_cash,
+            return_pct: Decimal::ZERO,
+        };
+
+        let lookback = self.sizing.as_ref().map_or(0, |policy| policy.lookback.max(1));
+        let mut history: Vec<Candle> = Vec::new();
+        let mut last_day: Option<NaiveDate> = None;
+        let mut cash_share_sum = Decimal::ZERO;
+        for candle in candles {
+            let candle = candle?;
+            report.candles += 1;
+            portfolio.update_price(&candle.symbol, candle.close);
+            let day = candle.timestamp.date_naive();
+            if let Some(mut accrued_through) = last_day {
+                while accrued_through < day {
+                    accrued_through = accrued_through.succ_opt().unwrap_or(day);
+                    let interest = account::daily_interest(report.cash, &self.account);
+                    match interest.is_sign_negative() {
+                        true => report.financing_cost -= interest,
+                        false => report.interest += interest,
+                    }
+                    report.cash += interest;
+                }
+            }
+            last_day = Some(day);
+            cash_share_sum += cash_share(report.cash, &portfolio);
+            if lookback > 0 {
+                if history.len() == lookback {
+                    history.remove(0);
+                }
+                history.push(candle.clone());
+            }
+            let signal = strategy.on_candle(&candle).with_context(|| {
+                format!("Strategy {} failed on {} at {}", report.strategy, candle.symbol, candle.timestamp.to_rfc3339())
+            })?;
+            match signal {
+                Signal::Buy => report.buy_signals += 1,
+                Signal::Sell => report.sell_signals += 1,
+                Signal::Hold => {}
+            }
+
+            let held = portfolio.positions.get(&candle.symbol).map_or(Decimal::ZERO, |p| p.quantity);
+            let quantity = match (&self.sizing, signal) {
+                (Some(policy), Signal::Buy) if held.is_zero() => {
+                    // Too little history to estimate volatility is a
+                    // skipped entry, not a failed run
+                    let equity = report.cash + portfolio.total_value();
+                    let (quantity, reason) = match sizing::suggest_size(&policy.inputs(&history, equity)) {
+                        Ok(suggestion) => (suggestion.quantity, suggestion.reason),
+                        Err(e) => (Decimal::ZERO, Some(e.to_string())),
+                    };
+                    if let Some(reason) = reason {
+                        tracing::debug!("📐 Skipping buy of {} at {}: {}", candle.symbol, candle.timestamp.to_rfc3339(), reason);
+                        report.zero_sized += 1;
+                    }
+                    quantity
+                }
+                (Some(_), _) => held,
+                (None, _) => self.quantity,
+            };
+            let Some(side) = order_for(signal, held, quantity) else { continue };
+            let notional = quantity * candle.close;
+            let fee = self.fees.commission(notional);
+            match side {
+                TradeSide::Buy => {
+                    let margin = &self.account.margin;
+                    if let Err(rejection) = account::check_order(&portfolio, report.cash, margin, self.asset_type, notional, fee) {
+                        tracing::debug!("🧪 Skipping buy of {} at {}: {}", candle.symbol, candle.timestamp.to_rfc3339(), rejection);
+                        report.unfunded += 1;
+                        continue;
+                    }
+                    portfolio.add_position(candle.symbol.clone(), quantity, candle.close);
+                    if let Some(position) = portfolio.positions.get_mut(&candle.symbol) {
+                        position.asset_type = self.asset_type;
+                    }
+                    report.cash -= notional + fee;
+                }
+                TradeSide::Sell => {
+                    portfolio.remove_position(&candle.symbol, quantity);
+                    report.cash += notional - fee;
+                }
+            }
+            report.fees += fee;
+            let mut trade = market_trade(&candle.symbol, side, quantity, candle.close);
+            trade.timestamp = candle.timestamp;
+            report.trades.push(trade);
+        }
+
+        if report.candles > 0 {
+            report.cash_drag_pct = (cash_share_sum / Decimal::from(report.candles) * Decimal::ONE_HUNDRED).round_dp(2);
+        }
+        report.holdings = portfolio.total_value();
+        report.equity = report.cash + report.holdings;
+        if !self.starting_cash.is_zero() {
+            report.return_pct = ((report.equity / self.starting_cash - Decimal::ONE) * Decimal::ONE_HUNDRED).round_dp(2);
+        }
+        Ok(report)
+    }
+}
+
+/// Cash as a share of equity (0 when equity is gone)
+fn cash_share(cash: Decimal, portfolio: &Portfolio) -> Decimal {
+    let equity = cash + portfolio.total_value();
+    match equity > Decimal::ZERO {
+        true => cash / equity,
+        false => Decimal::ZERO,
+    }
+}
+
+impl fmt::Display for BacktestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "🧪 Backtest of {} over {} candle(s)", self.strategy, self.candles)?;
+        writeln!(f, "  Signals:  {} buy, {} sell", self.buy_signals, self.sell_signals)?;
+        if self.unfunded > 0 {
+            writeln!(f, "  ⚠️  {} buy signal(s) skipped for lack of cash", self.unfunded)?;
+        }
+        if self.zero_sized > 0 {
+            writeln!(f, "  ⚠️  {} buy signal(s) sized to zero", self.zero_sized)?;
+        }
+        for t in &self.trades {
+            writeln!(
+                f,
+                "  {}  {:<4} {:>10} {:<8} @ {}",
+                t.timestamp.to_rfc3339(),
+                format!("{:?}", t.side),
+                t.quantity,
+                t.symbol,
+                t.price
+            )?;
+        }
+        writeln!(f, "  Fees:     {}", self.fees.round_dp(2))?;
+        writeln!(f, "  Cash drag: {}% of equity idle, earning {}", self.cash_drag_pct, self.interest.round_dp(2))?;
+        writeln!(f, "  Financing: {}", self.financing_cost.round_dp(2))?;
+        writeln!(f, "  Cash:     {}", self.cash.round_dp(2))?;
+        writeln!(f, "  Holdings: {}", self.holdings.round_dp(2))?;
+        writeln!(f, "  Equity:   {} ({:+}%)", self.equity.round_dp(2), self.return_pct)
+    }
+}
+
+/// Trades one symbol in the paper portfolio on a strategy's quote signals
+pub struct PaperTrader<'a> {
+    pub strategy: &'a mut dyn Strategy,
+    pub store: &'a PortfolioStore,
+    pub account: &'a PaperAccount,
+    pub portfolio: Portfolio,
+    pub symbol: String,
+    pub asset_type: AssetType,
+    pub quantity: Decimal,
+}
+
+impl PaperTrader<'_> {
+    /// Act on one quote: mark the position to `last` and settle the
+    /// account for the day, then trade; buys fill at the ask, sells at the
+    /// bid, and a buy beyond the buying power is skipped
+    pub fn on_quote(&mut self, quote: &Quote) -> Result<Option<Trade>> {
+        if let Some(position) = self.portfolio.positions.get_mut(&self.symbol) {
+            position.current_price = quote.last;
+            self.store.put_position(position)?;
+        }
+        self.account.mark(self.store, &mut self.portfolio, quote.timestamp.date_naive())?;
+
+        let signal = self.strategy.on_quote(quote).with_context(|| {
+            format!("Strategy {} failed on {} at {}", self.strategy.name(), quote.symbol, quote.timestamp.to_rfc3339())
+        })?;
+        let held = self.portfolio.positions.get(&self.symbol).map_or(Decimal::ZERO, |p| p.quantity);
+        let Some(side) = order_for(signal, held, self.quantity) else { return Ok(None) };
+        let price = match side {
+            TradeSide::Buy => quote.ask,
+            TradeSide::Sell => quote.bid,
+        };
+        let trade = market_trade(&self.symbol, side, self.quantity, price);
+        let source = format!("strategy:{}", self.strategy.name());
+        match self.account.submit(self.store, &mut self.portfolio, trade.clone(), self.asset_type, Some(source)) {
+            Ok(()) => Ok(Some(trade)),
+            Err(e) => match e.downcast_ref::<OrderRejection>() {
+                Some(rejection) => {
+                    tracing::warn!("🧪 Skipped buy of {} {}: {}", self.quantity, self.symbol, rejection);
+                    Ok(None)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Poll quotes every `interval` until the strategy fails
+    pub async fn run(&mut self, provider: &MarketDataProvider, interval: Duration) -> Result<()> {
+        tracing::info!("🧪 Paper trading {} with {} (poll every {:?})", self.symbol, self.strategy.name(), interval);
+        let mut tick = tokio::time::interval(interval);
+        loop {
+            tick.tick().await;
+            let quote = match provider.get_quote(&self.symbol).await {
+                Ok(quote) => quote,
+                Err(e) => {
+                    tracing::warn!("🧪 Quote unavailable for {}: {}", self.symbol, e);
+                    continue;
+                }
+            };
+            if let Some(trade) = self.on_quote(&quote)? {
+                tracing::info!("📒 {:?} {} {} @ {}", trade.side, trade.quantity, trade.symbol, trade.price);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quant::plugin::{PluginError, PluginLimits, WasmStrategy};
+    use crate::storage::RuntimeMode;
+    use chrono::{Duration as ChronoDuration, TimeZone, Utc};
+
+    /// Replays fixed signals, failing once they run out
+    struct Scripted(Vec<Signal>);
+
+    impl Strategy for Scripted {
+        fn name(&self) -> &str {
+            "scripted"
+        }
+
+        fn on_candle(&mut self, _: &Candle) -> Result<Signal> {
+            self.on_quote(&quote(0))
+        }
+
+        fn on_quote(&mut self, _: &Quote) -> Result<Signal> {
+            anyhow::ensure!(!self.0.is_empty(), "script exhausted");
+            Ok(self.0.remove(0))
+        }
+    }
+
+    fn candles(closes: &[i64]) -> Vec<Result<Candle>> {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, &close)| {
+                let close = Decimal::from(close);
+                Ok(Candle {
+                    symbol: "AAPL".to_string(),
+                    timestamp: start + ChronoDuration::days(i as i64),
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    volume: 1_000,
+                })
+            })
+            .collect()
+    }
+
+    fn quote(last: i64) -> Quote {
+        Quote {
+            symbol: "AAPL".to_string(),
+            bid: Decimal::from(last) - Decimal::ONE,
+            ask: Decimal::from(last) + Decimal::ONE,
+            last: Decimal::from(last),
+            volume: 1,
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn backtester() -> Backtester {
+        Backtester {
+            quantity: Decimal::from(10),
+            starting_cash: Decimal::from(2_000),
+            fees: FeeModel { per_trade: Decimal::ONE, bps: Decimal::ZERO },
+            sizing: None,
+            account: AccountSettings::default(),
+            asset_type: AssetType::Stock,
+        }
+    }
+
+    #[test]
+    fn test_backtest_momentum_plugin() {
+        let wasm = include_str!("../../tests/plugins/momentum.wat");
+        let config = serde_json::json!({ "lookback": 1 });
+        let mut momentum = WasmStrategy::from_bytes("momentum", wasm.as_bytes(), &config, &PluginLimits::default()).unwrap();
+
+        let report = backtester().run(&mut momentum, candles(&[100, 110, 120, 105, 100, 130])).unwrap();
+        // Buys at 110, sells at 105, buys at 130; the second buy signal at 120 is already filled
+        let fills: Vec<_> = report.trades.iter().map(|t| (format!("{:?}", t.side), t.price)).collect();
+        assert_eq!(fills, [("Buy".into(), 110.into()), ("Sell".into(), 105.into()), ("Buy".into(), 130.into())]);
+        assert_eq!((report.buy_signals, report.sell_signals), (3, 2));
+        assert_eq!(report.cash, Decimal::from(2_000 - 1_100 + 1_050 - 1_300 - 3));
+        assert_eq!(report.holdings, Decimal::from(1_300));
+        assert_eq!(report.equity, Decimal::from(1_947));
+        assert_eq!(report.return_pct, Decimal::new(-265, 2));
+        // In cash at four of six closes, 899 of 2099 and 899 of 1949 at the others
+        assert_eq!(report.cash_drag_pct, Decimal::new(8149, 2));
+    }
+
+    #[test]
+    fn test_backtest_margin_and_financing() {
+        let script = || Scripted(vec![Signal::Buy, Signal::Hold, Signal::Hold]);
+        let report = backtester().run(&mut script(), candles(&[300, 300, 300])).unwrap();
+        assert_eq!((report.unfunded, report.trades.len()), (1, 0));
+
+        let mut account = AccountSettings { borrow_rate_pct: Decimal::new(36, 1), ..Default::default() };
+        account.margin.enabled = true;
+        let leveraged = Backtester { account, ..backtester() };
+        let report = leveraged.run(&mut script(), candles(&[300, 300, 300])).unwrap();
+        // 50% of 3000 plus the commission fits in 2000; the 1001 borrowed
+        // costs 3.6% / 360 of it, 0.10 a day
+        assert_eq!((report.unfunded, report.trades.len()), (0, 1));
+        assert_eq!(report.financing_cost, Decimal::new(20, 2));
+        assert_eq!(report.interest, Decimal::ZERO);
+        assert_eq!(report.cash, Decimal::new(-100120, 2));
+    }
+
+    #[test]
+    fn test_strategy_error_ends_run() {
+        let spin = r#"(module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 0))
+          (func (export "init") (param i32 i32) (result i32) (i32.const 0))
+          (func (export "on_candle") (param i32 i32) (result i32) (loop (br 0)) (i32.const 0)))"#;
+        let limits = PluginLimits { fuel_per_call: 10_000, ..Default::default() };
+        let mut plugin = WasmStrategy::from_bytes("spin", spin.as_bytes(), &serde_json::Value::Null, &limits).unwrap();
+        let err = backtester().run(&mut plugin, candles(&[100])).unwrap_err();
+        assert!(err.to_string().starts_with("Strategy spin failed on AAPL at 2024-01-01"), "{}", err);
+        assert!(err.chain().any(|c| c.downcast_ref() == Some(&PluginError::OutOfFuel { callback: "on_candle" })));
+
+        // Unfunded buys are skipped, not errors
+        let mut broke = Scripted(vec![Signal::Buy]);
+        let report = backtester().run(&mut broke, candles(&[500])).unwrap();
+        assert_eq!((report.unfunded, report.trades.len()), (1, 0));
+    }
+
+    #[test]
+    fn test_backtest_sizes_entries() {
+        let policy = SizingPolicy {
+            target_annual_vol: Some(0.10),
+            kelly: None,
+            participation_cap: None,
+            lot_size: Decimal::ONE,
+            lookback: 5,
+        };
+        let sized = Backtester { sizing: Some(policy), ..backtester() };
+        let mut strategy = Scripted(vec![Signal::Buy, Signal::Hold, Signal::Hold, Signal::Hold, Signal::Buy, Signal::Sell]);
+        let report = sized.run(&mut strategy, candles(&[100, 110, 100, 110, 100, 110])).unwrap();
+
+        // The first buy has no volatility estimate yet. The second sees
+        // returns of ±ln(1.1): vol = ln(1.1) * sqrt(4/3 * 252) ≈ 1.747, so
+        // 10% / 1.747 of 2000 equity is 114.5 notional, 1 share at 100
+        assert_eq!(report.zero_sized, 1);
+        let fills: Vec<_> = report.trades.iter().map(|t| (format!("{:?}", t.side), t.quantity)).collect();
+        assert_eq!(fills, [("Buy".into(), Decimal::ONE), ("Sell".into(), Decimal::ONE)]);
+        assert_eq!(report.cash, Decimal::from(2_000 - 100 - 1 + 110 - 1));
+    }
+
+    #[test]
+    fn test_paper_trader_records_trades() {
+        let store = PortfolioStore::open(std::path::Path::new("unused"), RuntimeMode::Ephemeral).unwrap();
+        store.seed_cash(Decimal::from(1_000), Utc::now().date_naive()).unwrap();
+        let account = PaperAccount::new(AccountSettings::default(), FeeModel { per_trade: Decimal::ONE, bps: Decimal::ZERO });
+        let mut strategy = Scripted(vec![Signal::Buy, Signal::Buy, Signal::Sell, Signal::Sell, Signal::Buy]);
+        let mut trader = PaperTrader {
+            strategy: &mut strategy,
+            store: &store,
+            account: &account,
+            portfolio: store.load().unwrap(),
+            symbol: "AAPL".to_string(),
+            asset_type: AssetType::Stock,
+            quantity: Decimal::from(5),
+        };
+
+        let buy = trader.on_quote(&quote(100)).unwrap().unwrap();
+        assert_eq!(buy.price, Decimal::from(101));
+        assert!(trader.on_quote(&quote(100)).unwrap().is_none());
+        let sell = trader.on_quote(&quote(120)).unwrap().unwrap();
+        assert_eq!(sell.price, Decimal::from(119));
+        assert!(trader.on_quote(&quote(120)).unwrap().is_none());
+        // 5 at 301 would cost 1506 with commission; 1088 is left
+        assert!(trader.on_quote(&quote(300)).unwrap().is_none());
+        assert!(trader.on_quote(&quote(300)).is_err());
+
+        let ledger = store.ledger().unwrap();
+        assert_eq!(ledger.len(), 2);
+        assert!(ledger.iter().all(|e| e.triggered_by.as_deref() == Some("strategy:scripted")));
+        assert!(store.load().unwrap().positions.is_empty());
+        assert_eq!(store.cash().unwrap(), Decimal::from(1_000 - 505 - 1 + 595 - 1));
+    }
+}
@@ -0,0 +1,388 @@
+//! Option Watch
+//! Re-prices a saved set of option positions from each underlying's latest
+//! quote on every tick, streamed in between ticks or polled on each. An
+//! underlying whose quote fails (or, streamed, has aged out) keeps its last
+//! good price, marked stale with its age, so one bad feed doesn't stop the
+//! view
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use super::attribution::{MarketContext, OptionPosition};
+use super::hedging::HedgeModel;
+use super::market_data::MarketDataProvider;
+use super::market_stream::QuoteSubscription;
+use super::pricing::{Greeks, OptionType};
+use super::Quote;
+
+/// An entry of the `watch --portfolio` file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchedPosition {
+    #[serde(flatten)]
+    pub option: OptionPosition,
+    /// Premium per unit paid, or received when written
+    pub average_cost: f64,
+    /// Overrides the command's `--volatility`
+    #[serde(default)]
+    pub volatility: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionRow {
+    pub symbol: String,
+    pub option_type: OptionType,
+    pub strike: f64,
+    pub expiry: DateTime<Utc>,
+    pub quantity: f64,
+    pub spot: Option<f64>,
+    /// Value per unit
+    pub mark: Option<f64>,
+    /// Against `average_cost`, for the whole position
+    pub pnl: Option<f64>,
+    /// Mark move since the previous tick
+    pub change: Option<f64>,
+    /// Scaled by quantity; none at or after expiry
+    pub greeks: Option<Greeks>,
+    /// Age of the quote used, when this tick's quote failed
+    pub stale_secs: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// One tick of the watch
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchSnapshot {
+    pub at: DateTime<Utc>,
+    pub positions: Vec<PositionRow>,
+    /// Over positions with a mark
+    pub pnl: f64,
+    pub greeks: Greeks,
+    /// Underlyings whose quote failed this tick
+    pub stale: Vec<String>,
+}
+
+/// Prices positions tick by tick, remembering the last good quote and mark
+pub struct Watcher {
+    positions: Vec<WatchedPosition>,
+    rate: f64,
+    volatility: f64,
+    model: HedgeModel,
+    last_good: HashMap<String, Quote>,
+    last_marks: Vec<Option<f64>>,
+}
+
+impl Watcher {
+    /// `volatility` applies to positions that don't set their own
+    pub fn new(positions: Vec<WatchedPosition>, rate: f64, volatility: f64, model: HedgeModel) -> Self {
+        let last_marks = vec![None; positions.len()];
+        Self { positions, rate, volatility, model, last_good: HashMap::new(), last_marks }
+    }
+
+    /// Underlyings to quote, upper-cased
+    pub fn symbols(&self) -> BTreeSet<String> {
+        self.positions.iter().map(|p| p.option.symbol.to_uppercase()).collect()
+    }
+
+    /// Price every position from this tick's `quotes`; symbols missing from
+    /// `quotes` count as failed
+    pub fn snapshot(&mut self, quotes: HashMap<String, Result<Quote>>, now: DateTime<Utc>) -> WatchSnapshot {
+        let mut failures = HashMap::new();
+        for symbol in self.symbols() {
+            match quotes.get(&symbol) {
+                Some(Ok(quote)) => {
+                    self.last_good.insert(symbol, quote.clone());
+                }
+                Some(Err(e)) => {
+                    failures.insert(symbol, format!("{:#}", e));
+                }
+                None => {
+                    failures.insert(symbol, "no quote".to_string());
+                }
+            }
+        }
+
+        let mut snapshot = WatchSnapshot {
+            at: now,
+            positions: Vec::with_capacity(self.positions.len()),
+            pnl: 0.0,
+            greeks: Greeks::default(),
+            stale: failures.keys().cloned().collect(),
+        };
+        snapshot.stale.sort();
+        for (position, last_mark) in self.positions.iter().zip(self.last_marks.iter_mut()) {
+            let symbol = position.option.symbol.to_uppercase();
+            let quote = self.last_good.get(&symbol);
+            let failure = failures.get(&symbol);
+            let mut row = PositionRow {
+                symbol: symbol.clone(),
+                option_type: position.option.option_type,
+                strike: position.option.strike,
+                expiry: position.option.expiry,
+                quantity: position.option.quantity,
+                spot: quote.and_then(|q| q.last.to_f64()),
+                mark: None,
+                pnl: None,
+                change: None,
+                greeks: None,
+                stale_secs: failure.and(quote).map(|q| (now - q.timestamp).num_seconds()),
+                error: failure.cloned(),
+            };
+
+            if let Some(spot) = row.spot {
+                let context = MarketContext {
+                    symbol,
+                    timestamp: now,
+                    spot,
+                    volatility: position.volatility.unwrap_or(self.volatility),
+                    rate: self.rate,
+                };
+                match position.option.value_and_greeks(&context, self.model) {
+                    Ok((mark, greeks)) => {
+                        let quantity = position.option.quantity;
+                        row.mark = Some(mark);
+                        row.pnl = Some((mark - position.average_cost) * quantity);
+                        row.change = last_mark.map(|last| mark - last);
+                        row.greeks = greeks.map(|g| scaled(&g, quantity));
+                        *last_mark = Some(mark);
+                    }
+                    Err(e) => row.error = Some(format!("{:#}", e)),
+                }
+            }
+
+            snapshot.pnl += row.pnl.unwrap_or_default();
+            if let Some(g) = &row.greeks {
+                add(&mut snapshot.greeks, g);
+            }
+            snapshot.positions.push(row);
+        }
+        snapshot
+    }
+}
+
+fn scaled(g: &Greeks, quantity: f64) -> Greeks {
+    Greeks {
+        delta: g.delta * quantity,
+        gamma: g.gamma * quantity,
+        vega: g.vega * quantity,
+        theta: g.theta * quantity,
+        rho: g.rho * quantity,
+    }
+}
+
+fn add(total: &mut Greeks, g: &Greeks) {
+    total.delta += g.delta;
+    total.gamma += g.gamma;
+    total.vega += g.vega;
+    total.theta += g.theta;
+    total.rho += g.rho;
+}
+
+/// This tick's quote (or error) for each symbol
+pub async fn fetch_quotes(provider: &MarketDataProvider, symbols: &BTreeSet<String>) -> HashMap<String, Result<Quote>> {
+    let mut quotes = HashMap::new();
+    for symbol in symbols {
+        quotes.insert(symbol.clone(), provider.get_quote(symbol).await);
+    }
+    quotes
+}
+
+/// Streamed quotes, keeping each symbol's latest between ticks
+pub struct LatestQuotes {
+    subscription: QuoteSubscription,
+    latest: HashMap<String, Quote>,
+}
+
+impl LatestQuotes {
+    pub fn new(subscription: QuoteSubscription) -> Self {
+        Self { subscription, latest: HashMap::new() }
+    }
+
+    /// Take in quotes until `deadline`
+    pub async fn follow_until(&mut self, deadline: tokio::time::Instant) {
+        while let Ok(Some(quote)) = tokio::time::timeout_at(deadline, self.subscription.next_quote()).await {
+            self.latest.insert(quote.symbol.clone(), quote);
+        }
+    }
+
+    /// Take in quotes until every symbol has one, or `deadline`
+    pub async fn prime(&mut self, deadline: tokio::time::Instant) {
+        while self.subscription.symbols().iter().any(|symbol| !self.latest.contains_key(symbol)) {
+            match tokio::time::timeout_at(deadline, self.subscription.next_quote()).await {
+                Ok(Some(quote)) => {
+                    self.latest.insert(quote.symbol.clone(), quote);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// This tick's quotes for `Watcher::snapshot`; one older than `max_age`
+    /// counts as failed
+    pub fn quotes(&self, now: DateTime<Utc>, max_age: chrono::Duration) -> HashMap<String, Result<Quote>> {
+        self.subscription
+            .symbols()
+            .iter()
+            .map(|symbol| {
+                let quote = match self.latest.get(symbol) {
+                    Some(quote) if now - quote.timestamp <= max_age => Ok(quote.clone()),
+                    Some(quote) => Err(anyhow::anyhow!("no quote since {}", quote.timestamp.format("%H:%M:%S"))),
+                    None => Err(anyhow::anyhow!("no quote yet")),
+                };
+                (symbol.clone(), quote)
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for WatchSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "👀 Option watch at {}", self.at.format("%Y-%m-%d %H:%M:%S UTC"))?;
+        writeln!(
+            f,
+            "  {:<8} {:<4} {:>9} {:<10} {:>8} {:>10} {:>10} {:>10} {:>12} {:>9}",
+            "symbol", "type", "strike", "expiry", "qty", "spot", "mark", "change", "p&l", "delta"
+        )?;
+        for row in &self.positions {
+            let opt = |v: Option<f64>, precision: usize| v.map_or("-".to_string(), |v| format!("{:.*}", precision, v));
+            let change = match row.change {
+                Some(c) if c > 0.0 => format!("▲{:.4}", c),
+                Some(c) if c < 0.0 => format!("▼{:.4}", -c),
+                Some(_) => "=".to_string(),
+                None => "-".to_string(),
+            };
+            write!(
+                f,
+                "  {:<8} {:<4} {:>9.2} {:<10} {:>8} {:>10} {:>10} {:>10} {:>12} {:>9}",
+                row.symbol,
+                format!("{:?}", row.option_type),
+                row.strike,
+                row.expiry.format("%Y-%m-%d"),
+                row.quantity,
+                opt(row.spot, 2),
+                opt(row.mark, 4),
+                change,
+                opt(row.pnl, 2),
+                opt(row.greeks.as_ref().map(|g| g.delta), 2)
+            )?;
+            match (row.stale_secs, &row.error) {
+                (Some(age), _) => writeln!(f, "  ⏳ stale {}s", age)?,
+                (None, Some(error)) => writeln!(f, "  ⚠️  {}", error)?,
+                (None, None) => writeln!(f)?,
+            }
+        }
+        writeln!(
+            f,
+            "  Total P&L {:.2}  delta {:.2}  gamma {:.4}  vega {:.2}  theta {:.2}",
+            self.pnl, self.greeks.delta, self.greeks.gamma, self.greeks.vega, self.greeks.theta
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use rust_decimal::Decimal;
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, 14, 0, 0).unwrap()
+    }
+
+    fn position(symbol: &str, option_type: OptionType, quantity: f64, average_cost: f64) -> WatchedPosition {
+        WatchedPosition {
+            option: OptionPosition {
+                symbol: symbol.to_string(),
+                option_type,
+                strike: 100.0,
+                expiry: start() + Duration::days(90),
+                quantity,
+            },
+            average_cost,
+            volatility: None,
+        }
+    }
+
+    fn quote(symbol: &str, last: i64, at: DateTime<Utc>) -> (String, Result<Quote>) {
+        let last = Decimal::from(last);
+        let quote = Quote { symbol: symbol.to_string(), bid: last, ask: last, last, volume: 1, timestamp: at };
+        (symbol.to_string(), Ok(quote))
+    }
+
+    fn watcher() -> Watcher {
+        let positions = vec![
+            position("aapl", OptionType::Call, 10.0, 4.0),
+            position("AAPL", OptionType::Put, -5.0, 3.0),
+            position("MSFT", OptionType::Call, 1.0, 2.0),
+        ];
+        Watcher::new(positions, 0.05, 0.2, HedgeModel::BlackScholes)
+    }
+
+    #[test]
+    fn test_scripted_ticks() {
+        let mut watch = watcher();
+        assert_eq!(watch.symbols().into_iter().collect::<Vec<_>>(), ["AAPL", "MSFT"]);
+
+        let first = watch.snapshot([quote("AAPL", 100, start()), quote("MSFT", 100, start())].into(), start());
+        assert!(first.stale.is_empty());
+        let call = &first.positions[0];
+        assert!(call.change.is_none());
+        assert!((call.mark.unwrap() - 4.5790).abs() < 1e-3, "{:?}", call.mark);
+        assert!((call.pnl.unwrap() - (call.mark.unwrap() - 4.0) * 10.0).abs() < 1e-9);
+        let total_delta: f64 = first.positions.iter().map(|r| r.greeks.as_ref().unwrap().delta).sum();
+        assert!((first.greeks.delta - total_delta).abs() < 1e-9);
+        assert!(first.positions[1].greeks.as_ref().unwrap().delta > 0.0, "short put is long delta");
+
+        // The underlying rallies: calls mark up, puts down
+        let later = start() + Duration::seconds(2);
+        let second = watch.snapshot([quote("AAPL", 105, later), quote("MSFT", 100, later)].into(), later);
+        assert!(second.positions[0].change.unwrap() > 0.0);
+        assert!(second.positions[1].change.unwrap() < 0.0);
+        assert!(second.pnl > first.pnl);
+        let text = second.to_string();
+        assert!(text.contains("▲") && text.contains("▼"), "{}", text);
+    }
+
+    #[test]
+    fn test_failed_quote_keeps_last_price_as_stale() {
+        let mut watch = watcher();
+        // MSFT has never been quoted
+        let failed = |symbol: &str| (symbol.to_string(), Err(anyhow::anyhow!("feed down")));
+        let first = watch.snapshot([quote("AAPL", 100, start()), failed("MSFT")].into(), start());
+        let msft = &first.positions[2];
+        assert_eq!((msft.mark, msft.stale_secs, msft.error.as_deref()), (None, None, Some("feed down")));
+        assert_eq!(first.stale, ["MSFT"]);
+
+        // AAPL fails 45s later: still priced from the earlier quote, with its age
+        let later = start() + Duration::seconds(45);
+        let second = watch.snapshot([quote("MSFT", 100, later)].into(), later);
+        assert_eq!(second.stale, ["AAPL"]);
+        let call = &second.positions[0];
+        assert_eq!(call.stale_secs, Some(45));
+        assert_eq!(call.spot, Some(100.0));
+        assert!(call.mark.is_some() && call.change.unwrap() < 0.0, "time decay only");
+        assert!(second.positions[2].mark.is_some());
+        assert!(second.to_string().contains("⏳ stale 45s"));
+
+        // Recovery clears the marker
+        let third = watch.snapshot([quote("AAPL", 101, later), quote("MSFT", 100, later)].into(), later);
+        assert!(third.stale.is_empty() && third.positions[0].stale_secs.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_streamed_quotes_age_out() {
+        use crate::quant::market_stream::{MarketDataStream, QuoteFeed, StreamConfig};
+
+        // REST only: the feed polls the mock provider
+        let feed = QuoteFeed::spawn(&StreamConfig::default(), MarketDataProvider::new(), std::time::Duration::from_secs(60));
+        let mut latest = LatestQuotes::new(feed.subscribe(&["aapl".to_string()]));
+        latest.prime(tokio::time::Instant::now() + std::time::Duration::from_secs(5)).await;
+
+        let now = Utc::now();
+        assert!(latest.quotes(now, Duration::seconds(30))["AAPL"].is_ok());
+        let later = latest.quotes(now + Duration::minutes(5), Duration::seconds(30));
+        assert!(later["AAPL"].as_ref().unwrap_err().to_string().starts_with("no quote since"));
+    }
+}
@@ -0,0 +1,553 @@
+//! Watchlist
+//! Quote symbols to keep an eye on, private by default: stored encrypted
+//! under a key derived from the node's identity key and, with sync on,
+//! replicated through the DHT so every node holding that identity ends up
+//! with the same list
+//!
+//! Each symbol is a last-writer-wins register. The later write wins; at
+//! equal timestamps a removal beats an add, then the larger replica ID
+//! does, so merging in any order gives the same list. The DHT copy sits
+//! under a key only holders of the identity can compute (a hash of the
+//! public key and a derived salt) and is opaque to everyone else
+
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::{Context, Result};
+use hkdf::Hkdf;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::crypto::key_provider::KeyProvider;
+use crate::data_dirs::DataDirs;
+use crate::migrations::{self, StoreSchema};
+use crate::p2p::handle::NodeHandle;
+use crate::scheduler::{Scheduler, TaskSpec};
+use crate::storage::{KvStore, RuntimeMode};
+use crate::units::HumanDuration;
+
+const WATCHLIST_TREE: &str = "watchlist";
+/// The sealed list
+const LIST_KEY: &[u8] = b"list";
+/// This store's replica ID, for tie-breaks
+const REPLICA_KEY: &[u8] = b"replica";
+
+pub const SCHEMA: StoreSchema = StoreSchema {
+    name: "watchlist",
+    tree: Some(WATCHLIST_TREE),
+    version: 1,
+    migrations: &[],
+    index: None,
+};
+
+/// Signed by the identity key; the (deterministic) signature seeds the keys
+const DERIVATION_MESSAGE: &[u8] = b"quantra-watchlist-v1";
+const BLOB_MAGIC: &[u8] = b"QWL1";
+const NONCE_LEN: usize = 12;
+/// Associated data of the local copy; a DHT copy is bound to its record key
+const LOCAL_AAD: &[u8] = b"local";
+const MAX_SYMBOL_LEN: usize = 32;
+const SYNC_TASK: &str = "watchlist.sync";
+
+/// `[quant.watchlist]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchlistConfig {
+    /// Replicate through the DHT while `p2p` runs. Off unless set
+    pub sync: bool,
+    pub sync_interval: HumanDuration,
+    /// Defaults to watchlist/ in the data directory
+    pub store_path: Option<PathBuf>,
+}
+
+impl Default for WatchlistConfig {
+    fn default() -> Self {
+        Self { sync: false, sync_interval: HumanDuration::from_secs(15 * 60), store_path: None }
+    }
+}
+
+impl WatchlistConfig {
+    pub fn store_path(&self, dirs: &DataDirs) -> Result<PathBuf> {
+        match &self.store_path {
+            Some(path) => Ok(path.clone()),
+            None => dirs.watchlist_dir(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchlistError {
+    InvalidSymbol(String),
+    /// Sealed under another identity's key, or corrupt
+    Undecryptable,
+}
+
+impl fmt::Display for WatchlistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSymbol(symbol) => write!(
+                f,
+                "invalid symbol '{}': use 1-{} letters, digits or . - ^ = /",
+                symbol, MAX_SYMBOL_LEN
+            ),
+            Self::Undecryptable => write!(f, "watchlist does not decrypt under this node's identity key"),
+        }
+    }
+}
+
+impl std::error::Error for WatchlistError {}
+
+/// Upper-cased `symbol`, if it looks like a ticker
+pub fn normalize_symbol(symbol: &str) -> Result<String, WatchlistError> {
+    let symbol = symbol.trim().to_ascii_uppercase();
+    let valid = !symbol.is_empty()
+        && symbol.len() <= MAX_SYMBOL_LEN
+        && symbol.chars().all(|c| c.is_ascii_alphanumeric() || ".-^=/".contains(c));
+    match valid {
+        true => Ok(symbol),
+        false => Err(WatchlistError::InvalidSymbol(symbol)),
+    }
+}
+
+/// Latest write to one symbol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchEntry {
+    /// False once removed; kept so the removal wins merges
+    pub present: bool,
+    /// Unix milliseconds
+    pub updated_at: i64,
+    pub replica: u64,
+}
+
+impl WatchEntry {
+    fn beats(&self, other: &WatchEntry) -> bool {
+        (self.updated_at, !self.present, self.replica) > (other.updated_at, !other.present, other.replica)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Watchlist {
+    entries: BTreeMap<String, WatchEntry>,
+    /// Bumped by each local edit; a merge keeps the larger
+    pub version: u64,
+}
+
+impl Watchlist {
+    /// Watch `symbol` (already normalized); false if it already was
+    pub fn add(&mut self, symbol: &str, replica: u64) -> bool {
+        self.write(symbol, true, replica)
+    }
+
+    /// Stop watching `symbol`; false if it wasn't watched
+    pub fn remove(&mut self, symbol: &str, replica: u64) -> bool {
+        self.write(symbol, false, replica)
+    }
+
+    pub fn contains(&self, symbol: &str) -> bool {
+        self.entries.get(symbol).is_some_and(|e| e.present)
+    }
+
+    /// Watched symbols, sorted
+    pub fn symbols(&self) -> Vec<&str> {
+        self.entries.iter().filter(|(_, e)| e.present).map(|(s, _)| s.as_str()).collect()
+    }
+
+    /// Take `other`'s writes that win; true if anything changed
+    pub fn merge(&mut self, other: &Watchlist) -> bool {
+        let mut changed = false;
+        for (symbol, theirs) in &other.entries {
+            match self.entries.get(symbol) {
+                Some(ours) if !theirs.beats(ours) => {}
+                _ => {
+                    self.entries.insert(symbol.clone(), *theirs);
+                    changed = true;
+                }
+            }
+        }
+        self.version = self.version.max(other.version);
+        changed
+    }
+
+    /// A local write is stamped after the entry it replaces, so it wins
+    /// even when this node's clock is behind the one that wrote that
+    fn write(&mut self, symbol: &str, present: bool, replica: u64) -> bool {
+        let previous = self.entries.get(symbol).copied();
+        if previous.map_or(!present, |e| e.present == present) {
+            return false;
+        }
+        let now = chrono::Utc::now().timestamp_millis();
+        let updated_at = previous.map_or(now, |e| now.max(e.updated_at + 1));
+        self.entries.insert(symbol.to_string(), WatchEntry { present, updated_at, replica });
+        self.version += 1;
+        true
+    }
+}
+
+/// Encryption key and DHT location of an identity's watchlist
+pub struct WatchlistKeys {
+    cipher_key: [u8; 32],
+    dht_key: Vec<u8>,
+}
+
+impl WatchlistKeys {
+    /// Derive from `identity`; its signatures must be deterministic, as
+    /// Ed25519 signatures are
+    pub fn derive(identity: &dyn KeyProvider) -> Result<Self> {
+        let signature = identity.sign(DERIVATION_MESSAGE).context("Failed to derive watchlist keys")?;
+        let hkdf = Hkdf::<Sha256>::new(Some(DERIVATION_MESSAGE), &signature.to_bytes());
+        let mut cipher_key = [0u8; 32];
+        let mut salt = [0u8; 32];
+        hkdf.expand(b"cipher", &mut cipher_key)
+            .and_then(|_| hkdf.expand(b"dht-salt", &mut salt))
+            .map_err(|_| anyhow::anyhow!("HKDF expand failed"))?;
+        let dht_key = Sha256::new()
+            .chain_update(b"quantra-watchlist")
+            .chain_update(identity.public_key().as_bytes())
+            .chain_update(salt)
+            .finalize()
+            .to_vec();
+        Ok(Self { cipher_key, dht_key })
+    }
+
+    /// Where the DHT copy lives
+    pub fn dht_key(&self) -> &[u8] {
+        &self.dht_key
+    }
+
+    /// The DHT copy of `list`: magic || nonce || ciphertext
+    pub fn seal(&self, list: &Watchlist) -> Result<Vec<u8>> {
+        self.seal_with(list, &self.dht_key)
+    }
+
+    pub fn open(&self, blob: &[u8]) -> Result<Watchlist, WatchlistError> {
+        self.open_with(blob, &self.dht_key)
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.cipher_key))
+    }
+
+    fn seal_with(&self, list: &Watchlist, aad: &[u8]) -> Result<Vec<u8>> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let msg = serde_json::to_vec(list)?;
+        let ciphertext = self
+            .cipher()
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &msg, aad })
+            .map_err(|e| anyhow::anyhow!("Watchlist encryption failed: {:?}", e))?;
+        Ok([BLOB_MAGIC, nonce.as_slice(), ciphertext.as_slice()].concat())
+    }
+
+    fn open_with(&self, blob: &[u8], aad: &[u8]) -> Result<Watchlist, WatchlistError> {
+        let sealed = blob.strip_prefix(BLOB_MAGIC).filter(|rest| rest.len() >= NONCE_LEN);
+        let (nonce, msg) = sealed.ok_or(WatchlistError::Undecryptable)?.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher()
+            .decrypt(Nonce::from_slice(nonce), Payload { msg, aad })
+            .map_err(|_| WatchlistError::Undecryptable)?;
+        serde_json::from_slice(&plaintext).map_err(|_| WatchlistError::Undecryptable)
+    }
+}
+
+/// This node's copy of the watchlist
+pub struct WatchlistStore {
+    db: Box<dyn KvStore>,
+    keys: WatchlistKeys,
+    replica: u64,
+    /// Serializes read-modify-write of the list
+    write: Mutex<()>,
+}
+
+impl WatchlistStore {
+    pub fn open(path: &Path, mode: RuntimeMode, keys: WatchlistKeys) -> Result<Self> {
+        let db = migrations::open_store(mode, path, &SCHEMA)
+            .with_context(|| format!("Failed to open watchlist at {}", path.display()))?;
+        let replica = match db.get(REPLICA_KEY)? {
+            Some(bytes) => u64::from_be_bytes(bytes.as_slice().try_into().context("Corrupt watchlist replica ID")?),
+            None => {
+                let replica: u64 = rand::random();
+                db.insert(REPLICA_KEY, &replica.to_be_bytes())?;
+                db.flush()?;
+                replica
+            }
+        };
+        Ok(Self { db, keys, replica, write: Mutex::new(()) })
+    }
+
+    pub fn keys(&self) -> &WatchlistKeys {
+        &self.keys
+    }
+
+    pub fn load(&self) -> Result<Watchlist> {
+        match self.db.get(LIST_KEY)? {
+            Some(blob) => Ok(self.keys.open_with(&blob, LOCAL_AAD)?),
+            None => Ok(Watchlist::default()),
+        }
+    }
+
+    /// False if `symbol` was already watched
+    pub fn add(&self, symbol: &str) -> Result<bool> {
+        let symbol = normalize_symbol(symbol)?;
+        Ok(self.update(|list| list.add(&symbol, self.replica))?.1)
+    }
+
+    /// False if `symbol` wasn't watched
+    pub fn remove(&self, symbol: &str) -> Result<bool> {
+        let symbol = normalize_symbol(symbol)?;
+        Ok(self.update(|list| list.remove(&symbol, self.replica))?.1)
+    }
+
+    /// Merge `other` into the stored list; returns the result and whether
+    /// it changed
+    pub fn merge(&self, other: &Watchlist) -> Result<(Watchlist, bool)> {
+        self.update(|list| list.merge(other))
+    }
+
+    fn update(&self, edit: impl FnOnce(&mut Watchlist) -> bool) -> Result<(Watchlist, bool)> {
+        let _guard = self.write.lock();
+        let mut list = self.load()?;
+        let changed = edit(&mut list);
+        if changed {
+            self.db.insert(LIST_KEY, &self.keys.seal_with(&list, LOCAL_AAD)?)?;
+            self.db.flush()?;
+        }
+        Ok((list, changed))
+    }
+}
+
+/// Outcome of one sync
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncReport {
+    /// DHT copies found
+    pub found: usize,
+    /// Copies this identity can't open: corrupt or someone else's
+    pub undecryptable: usize,
+    /// Whether remote edits changed the local list
+    pub updated: bool,
+    /// Whether the merged list was put back on the DHT
+    pub published: bool,
+    pub version: u64,
+    pub symbols: usize,
+}
+
+/// Keeps a store in step with the DHT copies of its identity's watchlist
+pub struct WatchlistSync {
+    store: Arc<WatchlistStore>,
+    node: NodeHandle,
+}
+
+impl WatchlistSync {
+    pub fn new(store: Arc<WatchlistStore>, node: NodeHandle) -> Self {
+        Self { store, node }
+    }
+
+    /// Fetch every DHT copy, merge those that decrypt into the local list
+    /// and put the result back unless every copy already matches it
+    pub async fn sync(&self) -> Result<SyncReport> {
+        let keys = self.store.keys();
+        let blobs = self.node.get_dht_records(keys.dht_key()).await?;
+        let copies: Vec<Watchlist> = blobs.iter().filter_map(|blob| keys.open(blob).ok()).collect();
+
+        let mut report = SyncReport { found: blobs.len(), undecryptable: blobs.len() - copies.len(), ..Default::default() };
+        let mut merged = self.store.load()?;
+        for copy in &copies {
+            let (list, changed) = self.store.merge(copy)?;
+            report.updated |= changed;
+            merged = list;
+        }
+        report.version = merged.version;
+        report.symbols = merged.symbols().len();
+
+        let stale = copies.is_empty() || copies.iter().any(|copy| *copy != merged);
+        if stale && merged != Watchlist::default() {
+            self.node.put_dht_record(keys.dht_key(), keys.seal(&merged)?).await?;
+            report.published = true;
+        }
+        Ok(report)
+    }
+
+    /// Sync every `config.sync_interval` on `scheduler`
+    pub fn schedule(self: Arc<Self>, scheduler: &mut Scheduler, config: &WatchlistConfig) -> Result<()> {
+        let interval = config.sync_interval.as_std();
+        let spec = TaskSpec { interval, jitter: 0.1, timeout: interval.min(std::time::Duration::from_secs(120)) };
+        scheduler.register(SYNC_TASK, spec, move || {
+            let sync = self.clone();
+            async move {
+                let report = sync.sync().await?;
+                tracing::debug!("👀 Watchlist synced: {:?}", report);
+                Ok(())
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::key_provider::FileKeyProvider;
+    use crate::p2p::{P2PNode, TransportKind};
+    use ed25519_dalek::SigningKey;
+    use libp2p::identity::Keypair;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn keys(seed: u8) -> WatchlistKeys {
+        WatchlistKeys::derive(&FileKeyProvider::new(SigningKey::from_bytes(&[seed; 32]))).unwrap()
+    }
+
+    fn store(seed: u8) -> Arc<WatchlistStore> {
+        Arc::new(WatchlistStore::open(Path::new("unused"), RuntimeMode::Ephemeral, keys(seed)).unwrap())
+    }
+
+    fn entry(present: bool, updated_at: i64, replica: u64) -> WatchEntry {
+        WatchEntry { present, updated_at, replica }
+    }
+
+    fn list(entries: &[(&str, WatchEntry)]) -> Watchlist {
+        Watchlist { entries: entries.iter().map(|(s, e)| (s.to_string(), *e)).collect(), version: 1 }
+    }
+
+    #[test]
+    fn test_merge_tie_breaks_are_order_independent() {
+        let a = list(&[("AAPL", entry(true, 10, 1)), ("MSFT", entry(true, 10, 1)), ("TSLA", entry(true, 10, 2))]);
+        let b = list(&[("AAPL", entry(true, 20, 2)), ("MSFT", entry(false, 10, 0)), ("TSLA", entry(true, 10, 1))]);
+
+        let mut ab = a.clone();
+        ab.merge(&b);
+        let mut ba = b.clone();
+        ba.merge(&a);
+        assert_eq!(ab, ba);
+        // Later write, then removal over add, then larger replica
+        assert_eq!(ab.entries["AAPL"], entry(true, 20, 2));
+        assert_eq!(ab.entries["MSFT"], entry(false, 10, 0));
+        assert_eq!(ab.entries["TSLA"], entry(true, 10, 2));
+        assert_eq!(ab.symbols(), vec!["AAPL", "TSLA"]);
+        assert!(!ab.clone().merge(&ab), "merging a copy with itself changes nothing");
+    }
+
+    #[test]
+    fn test_local_edit_beats_a_future_stamped_entry() {
+        let far_future = chrono::Utc::now().timestamp_millis() + 3_600_000;
+        let mut watched = list(&[("AAPL", entry(true, far_future, 9))]);
+        assert!(watched.remove("AAPL", 1));
+        assert!(!watched.contains("AAPL"));
+        assert_eq!(watched.entries["AAPL"].updated_at, far_future + 1);
+        assert!(!watched.remove("AAPL", 1));
+        assert!(!watched.remove("NVDA", 1));
+    }
+
+    #[test]
+    fn test_store_is_sealed_and_symbols_validated() {
+        let store = store(1);
+        assert!(store.add(" aapl ").unwrap());
+        assert!(!store.add("AAPL").unwrap());
+        assert!(store.add("BRK.B").unwrap());
+        let err = store.add("not a symbol").unwrap_err();
+        assert!(matches!(err.downcast_ref::<WatchlistError>(), Some(WatchlistError::InvalidSymbol(_))));
+        assert_eq!(store.load().unwrap().symbols(), vec!["AAPL", "BRK.B"]);
+
+        let raw = store.db.get(LIST_KEY).unwrap().unwrap();
+        assert!(!raw.windows(4).any(|w| w == b"AAPL"), "stored list must be encrypted");
+        // The local copy is not a valid DHT copy, and vice versa
+        assert_eq!(store.keys().open(&raw), Err(WatchlistError::Undecryptable));
+    }
+
+    async fn memory_node() -> (NodeHandle, String) {
+        let mut node = P2PNode::with_transport(Keypair::generate_ed25519(), TransportKind::Memory).unwrap();
+        node.disable_mdns();
+        let addr = node
+            .listen_on_multiple(&["/memory/0".to_string()])
+            .await
+            .into_iter()
+            .find_map(|r| r.outcome.ok().and_then(|addrs| addrs.into_iter().next()))
+            .unwrap();
+        node.add_external_address(addr.clone());
+        (NodeHandle::attach(node, false).0, addr.to_string())
+    }
+
+    /// Dial `addr` and wait until the peer is in the routing table
+    async fn join(node: &NodeHandle, addr: &str) {
+        node.dial(addr).await.unwrap();
+        timeout(Duration::from_secs(10), async {
+            while node.status().await.unwrap().dht_peers == 0 {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("peer never reached the routing table");
+    }
+
+    /// Sync until the DHT answers (puts fail until the routing tables fill)
+    async fn sync(sync: &WatchlistSync) -> SyncReport {
+        timeout(Duration::from_secs(20), async {
+            loop {
+                match sync.sync().await {
+                    Ok(report) => return report,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(200)).await,
+                }
+            }
+        })
+        .await
+        .expect("watchlist sync never succeeded")
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_nodes_sharing_an_identity_converge() {
+        let (bootstrap, bootstrap_addr) = memory_node().await;
+        let (node_a, _) = memory_node().await;
+        let (node_b, _) = memory_node().await;
+        let (node_c, _) = memory_node().await;
+        for node in [&node_a, &node_b, &node_c] {
+            join(node, &bootstrap_addr).await;
+        }
+
+        // Same identity seed, different libp2p keys
+        let (store_a, store_b) = (store(7), store(7));
+        assert_eq!(store_a.keys().dht_key(), store_b.keys().dht_key());
+        let sync_a = WatchlistSync::new(store_a.clone(), node_a.clone());
+        let sync_b = WatchlistSync::new(store_b.clone(), node_b.clone());
+
+        store_a.add("AAPL").unwrap();
+        store_a.add("MSFT").unwrap();
+        assert!(sync(&sync_a).await.published);
+
+        // B catches up, then both edit while apart
+        let report = sync(&sync_b).await;
+        assert!(report.updated);
+        assert_eq!(store_b.load().unwrap().symbols(), vec!["AAPL", "MSFT"]);
+        store_a.remove("MSFT").unwrap();
+        store_a.add("NVDA").unwrap();
+        store_b.add("TSLA").unwrap();
+        store_b.remove("AAPL").unwrap();
+
+        sync(&sync_a).await;
+        sync(&sync_b).await;
+        sync(&sync_a).await;
+        let (a, b) = (store_a.load().unwrap(), store_b.load().unwrap());
+        assert_eq!(a.symbols(), vec!["NVDA", "TSLA"]);
+        assert_eq!(a.symbols(), b.symbols());
+        assert!(!sync(&sync_b).await.updated, "converged lists have nothing left to merge");
+
+        // Another identity neither finds the list nor can read it
+        let store_c = store(9);
+        assert_ne!(store_c.keys().dht_key(), store_a.keys().dht_key());
+        let report = sync(&WatchlistSync::new(store_c.clone(), node_c.clone())).await;
+        assert_eq!(report.found, 0);
+        assert!(store_c.load().unwrap().symbols().is_empty());
+        let blobs = node_c.get_dht_records(store_a.keys().dht_key()).await.unwrap();
+        assert!(!blobs.is_empty());
+        for blob in &blobs {
+            assert_eq!(store_c.keys().open(blob), Err(WatchlistError::Undecryptable));
+            assert!(store_a.keys().open(blob).is_ok());
+        }
+
+        for node in [node_a, node_b, node_c, bootstrap] {
+            node.shutdown().await;
+        }
+    }
+}
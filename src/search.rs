@@ -0,0 +1,408 @@
+//! Local Search
+//! One query across the node's stores: carriers, portfolio symbols and
+//! trades, the audit log and direct messages. Matching is case-insensitive
+//! substring; exact values and values (or words in them) starting with the
+//! query rank first
+//!
+//! Each store implements [`Searchable`] and walks its own index or iterator,
+//! keeping only its best `limit` hits, so nothing is loaded whole
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// Hits a store returns when no `--limit` is given
+pub const DEFAULT_LIMIT: usize = 10;
+
+/// Longest snippet shown for a matching value, in characters
+const SNIPPET_CHARS: usize = 60;
+
+/// A store that can be searched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchStore {
+    Carriers,
+    Portfolio,
+    Messages,
+    Audit,
+}
+
+impl FromStr for SearchStore {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "carriers" | "carrier" => Ok(Self::Carriers),
+            "portfolio" | "ledger" | "trades" => Ok(Self::Portfolio),
+            "messages" | "inbox" => Ok(Self::Messages),
+            "audit" => Ok(Self::Audit),
+            _ => anyhow::bail!("Unknown store '{}': use carriers, portfolio, messages or audit", s),
+        }
+    }
+}
+
+impl fmt::Display for SearchStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchStore::Carriers => write!(f, "carriers"),
+            SearchStore::Portfolio => write!(f, "portfolio"),
+            SearchStore::Messages => write!(f, "messages"),
+            SearchStore::Audit => write!(f, "audit"),
+        }
+    }
+}
+
+/// Search text was empty or only whitespace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmptyQuery;
+
+impl fmt::Display for EmptyQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Search query is empty")
+    }
+}
+
+impl std::error::Error for EmptyQuery {}
+
+/// How well a value matched, best last
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rank {
+    Substring,
+    /// The value, or a word in it, starts with the query
+    Prefix,
+    Exact,
+}
+
+/// Trimmed, lowercased search text
+#[derive(Debug, Clone)]
+pub struct Query {
+    text: String,
+}
+
+impl Query {
+    pub fn new(text: &str) -> Result<Self, EmptyQuery> {
+        let text = text.trim().to_lowercase();
+        if text.is_empty() {
+            return Err(EmptyQuery);
+        }
+        Ok(Self { text })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// How `value` matches, if it does
+    pub fn rank(&self, value: &str) -> Option<Rank> {
+        let lower = value.to_lowercase();
+        if lower == self.text {
+            return Some(Rank::Exact);
+        }
+        let mut best = None;
+        for (at, _) in lower.match_indices(&self.text) {
+            let word_start = lower[..at].chars().next_back().is_none_or(|c| !c.is_alphanumeric());
+            if word_start {
+                return Some(Rank::Prefix);
+            }
+            best = Some(Rank::Substring);
+        }
+        best
+    }
+
+    /// Best-ranked of a record's `(field, value)` pairs, first field winning ties
+    pub fn rank_fields<'a>(&self, fields: &[(&'static str, &'a str)]) -> Option<(&'static str, &'a str, Rank)> {
+        fields
+            .iter()
+            .filter_map(|&(field, value)| self.rank(value).map(|rank| (field, value, rank)))
+            .fold(None, |best: Option<(&'static str, &'a str, Rank)>, hit| match best {
+                Some(best) if best.2 >= hit.2 => Some(best),
+                _ => Some(hit),
+            })
+    }
+
+    /// `value` cut down to a window around the first match
+    pub fn snippet(&self, value: &str) -> String {
+        let chars: Vec<char> = value.chars().collect();
+        if chars.len() <= SNIPPET_CHARS {
+            return value.to_string();
+        }
+        let needle = self.text.chars().count();
+        let at = (0..chars.len())
+            .find(|&i| chars[i..].iter().flat_map(|c| c.to_lowercase()).take(needle).eq(self.text.chars()))
+            .unwrap_or(0);
+        let start = at.saturating_sub(SNIPPET_CHARS / 4).min(chars.len() - SNIPPET_CHARS);
+        let end = start + SNIPPET_CHARS;
+        let mut snippet = String::new();
+        if start > 0 {
+            snippet.push('…');
+        }
+        snippet.extend(&chars[start..end]);
+        if end < chars.len() {
+            snippet.push('…');
+        }
+        snippet
+    }
+}
+
+/// One matching record
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchHit {
+    pub store: SearchStore,
+    /// Which field of the record matched
+    pub field: &'static str,
+    /// Identifies the record within its store
+    pub key: String,
+    pub snippet: String,
+    pub rank: Rank,
+}
+
+impl SearchHit {
+    pub fn new(store: SearchStore, field: &'static str, key: impl Into<String>, snippet: String, rank: Rank) -> Self {
+        Self { store, field, key: key.into(), snippet, rank }
+    }
+}
+
+/// A store's hits, best first
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchGroup {
+    pub store: SearchStore,
+    pub hits: Vec<SearchHit>,
+}
+
+/// Something `quantraband search` can look in
+#[async_trait]
+pub trait Searchable: Send + Sync {
+    fn store(&self) -> SearchStore;
+    /// At most `limit` hits, best first
+    async fn search(&self, query: &Query, limit: usize) -> Result<Vec<SearchHit>>;
+}
+
+/// Best `limit` hits seen so far, for stores that scan
+pub struct TopHits {
+    limit: usize,
+    latest_first: bool,
+    /// With the order each was seen in
+    hits: Vec<(usize, SearchHit)>,
+    seen: usize,
+}
+
+impl TopHits {
+    /// Equally ranked hits keep scan order
+    pub fn new(limit: usize) -> Self {
+        Self { limit, latest_first: false, hits: Vec::new(), seen: 0 }
+    }
+
+    /// Equally ranked hits come latest first, for scans oldest first
+    pub fn latest(limit: usize) -> Self {
+        Self { latest_first: true, ..Self::new(limit) }
+    }
+
+    pub fn push(&mut self, hit: SearchHit) {
+        self.hits.push((self.seen, hit));
+        self.seen += 1;
+        // Trim in batches so a long scan stays bounded without sorting per hit
+        if self.hits.len() >= self.limit.max(1) * 2 {
+            self.trim();
+        }
+    }
+
+    pub fn into_vec(mut self) -> Vec<SearchHit> {
+        self.trim();
+        self.hits.into_iter().map(|(_, hit)| hit).collect()
+    }
+
+    fn trim(&mut self) {
+        let latest_first = self.latest_first;
+        self.hits.sort_by(|(a_seen, a), (b_seen, b)| {
+            let order = if latest_first { b_seen.cmp(a_seen) } else { a_seen.cmp(b_seen) };
+            b.rank.cmp(&a.rank).then(order)
+        });
+        self.hits.truncate(self.limit);
+    }
+}
+
+/// Search `sources` (only the `only` stores, when given) and group the hits
+/// by store, the group with the best hit first
+pub async fn search_all(
+    sources: &[&dyn Searchable],
+    query: &Query,
+    only: &[SearchStore],
+    limit: usize,
+) -> Result<Vec<SearchGroup>> {
+    let mut groups = Vec::new();
+    for source in sources {
+        let store = source.store();
+        if !only.is_empty() && !only.contains(&store) {
+            continue;
+        }
+        let hits = source.search(query, limit).await?;
+        if !hits.is_empty() {
+            groups.push(SearchGroup { store, hits });
+        }
+    }
+    groups.sort_by(|a, b| b.hits[0].rank.cmp(&a.hits[0].rank).then(a.store.cmp(&b.store)));
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esim::carriers::{CarrierDatabase, CarrierInfo};
+    use crate::p2p::receipts::{message_id, ReceiptConfig, ReceiptStore};
+    use crate::quant::portfolio_store::{market_trade, PortfolioStore};
+    use crate::quant::TradeSide;
+    use crate::storage::RuntimeMode;
+    use crate::zerotrust::audit::{AuditLogger, MemoryAuditStore, SecurityEvent};
+    use crate::zerotrust::SecurityLevel;
+    use chrono::Utc;
+    use libp2p::PeerId;
+    use rust_decimal::Decimal;
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    fn query(text: &str) -> Query {
+        Query::new(text).unwrap()
+    }
+
+    #[test]
+    fn test_empty_query_is_rejected() {
+        assert_eq!(Query::new("").unwrap_err(), EmptyQuery);
+        assert_eq!(Query::new("   ").unwrap_err(), EmptyQuery);
+        assert_eq!(query("  AaPl ").as_str(), "aapl");
+    }
+
+    #[test]
+    fn test_ranking() {
+        let q = query("mobile");
+        assert_eq!(q.rank("Mobile"), Some(Rank::Exact));
+        assert_eq!(q.rank("Mobilephone"), Some(Rank::Prefix));
+        assert_eq!(q.rank("T-Mobile"), Some(Rank::Prefix), "word start");
+        assert_eq!(q.rank("Automobile"), Some(Rank::Substring));
+        assert_eq!(q.rank("Vodafone"), None);
+        assert_eq!(
+            q.rank_fields(&[("country", "Automobile Land"), ("name", "T-Mobile")]),
+            Some(("name", "T-Mobile", Rank::Prefix))
+        );
+    }
+
+    #[test]
+    fn test_snippet_windows_long_values() {
+        let q = query("needle");
+        assert_eq!(q.snippet("short needle"), "short needle");
+        let long = format!("{}needle{}", "a".repeat(100), "b".repeat(100));
+        let snippet = q.snippet(&long);
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+        assert!(snippet.contains("needle"));
+        assert_eq!(snippet.chars().count(), SNIPPET_CHARS + 2);
+    }
+
+    #[test]
+    fn test_top_hits_keeps_the_best() {
+        let hit = |key: &str, rank| SearchHit::new(SearchStore::Audit, "event_type", key, String::new(), rank);
+        let mut top = TopHits::new(2);
+        for (i, rank) in [Rank::Substring, Rank::Prefix, Rank::Substring, Rank::Exact, Rank::Substring].into_iter().enumerate() {
+            top.push(hit(&i.to_string(), rank));
+        }
+        let keys: Vec<_> = top.into_vec().into_iter().map(|h| h.key).collect();
+        assert_eq!(keys, vec!["3", "1"]);
+
+        let mut latest = TopHits::latest(2);
+        for i in 0..5 {
+            latest.push(hit(&i.to_string(), Rank::Substring));
+        }
+        let keys: Vec<_> = latest.into_vec().into_iter().map(|h| h.key).collect();
+        assert_eq!(keys, vec!["4", "3"]);
+    }
+
+    struct Seeded {
+        carriers: CarrierDatabase,
+        portfolio: PortfolioStore,
+        messages: ReceiptStore,
+        audit: AuditLogger,
+        peer: PeerId,
+    }
+
+    /// A carrier, trades, a message and audit events, several mentioning "orange"
+    async fn seed(dir: &Path) -> Seeded {
+        let mut carriers = CarrierDatabase::new();
+        let orange = CarrierInfo {
+            name: "Orange".to_string(),
+            country: "France".to_string(),
+            sm_dp_address: "smdp.orange.example".to_string(),
+            supports_esim: true,
+            requires_confirmation: false,
+            api_endpoint: None,
+        };
+        carriers.set_override("orange_fr", orange).unwrap();
+
+        let portfolio = PortfolioStore::open(&dir.join("portfolio"), RuntimeMode::Persistent).unwrap();
+        let mut book = portfolio.load().unwrap();
+        let buy = |symbol: &str| market_trade(symbol, TradeSide::Buy, Decimal::from(10), Decimal::from(15));
+        portfolio.execute(&mut book, buy("ORAN"), None).unwrap();
+        portfolio.execute(&mut book, buy("CORA"), None).unwrap();
+        portfolio.execute(&mut book, buy("AAPL"), Some("orange_rule".to_string())).unwrap();
+
+        let messages = ReceiptStore::open(&dir.join("receipts"), RuntimeMode::Persistent, ReceiptConfig::default()).unwrap();
+        let peer = PeerId::random();
+        messages.record_received(&message_id(&peer, b"sealed"), &peer, Utc::now()).unwrap();
+
+        let mut audit = AuditLogger::with_store(Box::new(MemoryAuditStore::new())).await.unwrap();
+        for event_type in ["orange_alert", "key_rotated"] {
+            let event = SecurityEvent {
+                timestamp: Utc::now(),
+                event_type: event_type.to_string(),
+                peer_id: "peer-1".to_string(),
+                security_level: SecurityLevel::Basic,
+                details: HashMap::new(),
+                prev_hash: String::new(),
+                trace_id: None,
+            };
+            audit.log(event).await.unwrap();
+        }
+        audit.flush().await.unwrap();
+        Seeded { carriers, portfolio, messages, audit, peer }
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_ranks_and_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let seeded = seed(dir.path()).await;
+        let sources: [&dyn Searchable; 4] = [&seeded.audit, &seeded.messages, &seeded.portfolio, &seeded.carriers];
+
+        let groups = search_all(&sources, &query("orange"), &[], DEFAULT_LIMIT).await.unwrap();
+        let stores: Vec<_> = groups.iter().map(|g| g.store).collect();
+        // The carrier's name matches exactly, so carriers lead; the ledger
+        // trigger and audit event type only start with the query
+        assert_eq!(stores, vec![SearchStore::Carriers, SearchStore::Portfolio, SearchStore::Audit]);
+        assert_eq!((groups[0].hits[0].field, groups[0].hits[0].rank), ("name", Rank::Exact));
+        assert_eq!(groups[0].hits[0].key, "orange_fr");
+        let audit_hits = &groups[2].hits;
+        assert_eq!(audit_hits.len(), 1);
+        assert_eq!((audit_hits[0].field, audit_hits[0].rank), ("event_type", Rank::Prefix));
+
+        // Within a store, prefix matches come before substring ones
+        let groups = search_all(&sources, &query("ora"), &[SearchStore::Portfolio], DEFAULT_LIMIT).await.unwrap();
+        assert_eq!(groups.len(), 1, "filtered to the portfolio");
+        let hits = &groups[0].hits;
+        assert!(hits.windows(2).all(|w| w[0].rank >= w[1].rank));
+        assert_eq!((hits[0].field, hits[0].key.as_str(), hits[0].rank), ("symbol", "ORAN", Rank::Prefix));
+        assert!(hits.iter().any(|h| h.field == "triggered_by"));
+        assert_eq!(hits.last().unwrap().rank, Rank::Substring, "CORA");
+
+        let limited = search_all(&sources, &query("ora"), &[SearchStore::Portfolio], 2).await.unwrap();
+        assert_eq!(limited[0].hits.len(), 2);
+
+        let groups = search_all(&sources, &query(&seeded.peer.to_string()), &[], DEFAULT_LIMIT).await.unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].store, SearchStore::Messages);
+        assert_eq!((groups[0].hits[0].field, groups[0].hits[0].rank), ("peer", Rank::Exact));
+
+        assert!(search_all(&sources, &query("orange"), &[SearchStore::Messages], DEFAULT_LIMIT)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
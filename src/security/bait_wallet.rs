@@ -3,11 +3,19 @@
 
 use anyhow::Result;
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
 use sha2::{Sha256, Digest};
+use crate::approvals::{ApprovalQueue, Decision, PendingApproval};
+use crate::security::geo::{self, GeoLocator};
+use crate::security::intel::{IntelJournal, IntelRecord};
+use crate::security::notifications::{NotificationRouter, SinkEvent};
+
+/// Approval category of deactivations held for an operator
+pub const DEACTIVATE_APPROVAL: &str = "bait.deactivate";
 
 /// Bait wallet types
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -30,6 +38,9 @@ const BAIT_SEEDS: &[&str] = &[
 /// Tracked access event
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BaitAccessEvent {
+    /// Stable ID for correlating exports with the local log
+    #[serde(default)]
+    pub id: String,
     pub timestamp: DateTime<Utc>,
     pub wallet_id: String,
     pub wallet_type: WalletType,
@@ -98,8 +109,14 @@ pub struct BaitWalletManager {
     access_log: Arc<RwLock<Vec<BaitAccessEvent>>>,
     /// Callback URL for alerts
     callback_url: String,
-    /// Alert webhook
-    alert_webhook: Option<String>,
+    /// Outbound notifications
+    notifier: Option<Arc<NotificationRouter>>,
+    /// Threat intel journal for STIX export
+    intel: Option<Arc<IntelJournal>>,
+    /// Deactivations wait here for an operator, for the given time
+    approvals: Option<(Arc<ApprovalQueue>, chrono::Duration)>,
+    /// Shared with geo admission control
+    locator: Option<Arc<dyn GeoLocator>>,
 }
 
 impl BaitWalletManager {
@@ -112,14 +129,27 @@ impl BaitWalletManager {
             wallets: Arc::new(RwLock::new(HashMap::new())),
             access_log: Arc::new(RwLock::new(Vec::new())),
             callback_url: callback_url.to_string(),
-            alert_webhook: None,
+            notifier: None,
+            intel: None,
+            approvals: None,
+            locator: None,
         }
     }
 
-    /// Set alert webhook (Slack, Discord, etc.)
-    pub fn set_alert_webhook(&mut self, webhook: &str) {
-        self.alert_webhook = Some(webhook.to_string());
-        tracing::info!("🔔 Alert webhook configured");
+    /// Send bait access alerts through the notification router
+    pub fn set_notifier(&mut self, notifier: Arc<NotificationRouter>) {
+        self.notifier = Some(notifier);
+        tracing::info!("🔔 Bait wallet notifications configured");
+    }
+
+    /// Append bait accesses and canary triggers to the threat intel journal
+    pub fn set_intel_journal(&mut self, journal: Arc<IntelJournal>) {
+        self.intel = Some(journal);
+    }
+
+    /// Locate attackers with the node's geolocation lookup (and its cache)
+    pub fn set_geo_locator(&mut self, locator: Arc<dyn GeoLocator>) {
+        self.locator = Some(locator);
     }
 
     /// Deploy a new bait wallet
@@ -197,6 +227,7 @@ impl BaitWalletManager {
 
         // Log the event
         let event = BaitAccessEvent {
+            id: uuid::Uuid::new_v4().to_string(),
             timestamp: now,
             wallet_id: wallet_id.to_string(),
             wallet_type: wallet_type.clone(),
@@ -209,6 +240,9 @@ impl BaitWalletManager {
         };
 
         self.access_log.write().await.push(event.clone());
+        if let Some(journal) = &self.intel {
+            journal.append(&IntelRecord::BaitAccess(event.clone()));
+        }
 
         // ALERT!
         self.send_alert(&event, &wallet_address).await?;
@@ -235,25 +269,38 @@ impl BaitWalletManager {
         Ok(())
     }
 
-    /// Get geolocation for IP
+    /// Get geolocation for IP; without a locator (or for private addresses)
+    /// only the VPN / Tor hints are filled in
     async fn get_geolocation(&self, ip: &str) -> Result<Option<GeoLocation>> {
-        // In production, use real geolocation API (ip-api.com, ipinfo.io, etc.)
-        // For now, return mock data for testing
-
-        // Detect common VPN/Tor ranges
-        let is_vpn = ip.starts_with("10.") || ip.starts_with("192.168.") || ip.starts_with("172.");
+        let addr = ip.parse::<IpAddr>().ok();
+        let is_vpn = addr.is_some_and(|a| geo::is_private_ip(&a));
         let is_tor = ip.contains("tor") || ip.ends_with(".onion");
 
+        let info = match (&self.locator, addr) {
+            (Some(locator), Some(addr)) if !is_vpn => match locator.locate(addr).await {
+                Ok(info) => Some(info),
+                Err(e) => {
+                    tracing::debug!("🎣 Geolocation unavailable for {}: {}", ip, e);
+                    None
+                }
+            },
+            _ => None,
+        };
+        let (country_code, org) = match info {
+            Some(info) => (info.country_code, info.org),
+            None => ("XX".to_string(), "Unknown Org".to_string()),
+        };
+
         Ok(Some(GeoLocation {
             ip: ip.to_string(),
-            country: "Unknown".to_string(),
-            country_code: "XX".to_string(),
+            country: country_code.clone(),
+            country_code,
             region: "Unknown".to_string(),
             city: "Unknown".to_string(),
             latitude: 0.0,
             longitude: 0.0,
-            isp: "Unknown ISP".to_string(),
-            org: "Unknown Org".to_string(),
+            isp: org.clone(),
+            org,
             timezone: "UTC".to_string(),
             is_vpn,
             is_tor,
@@ -283,10 +330,14 @@ impl BaitWalletManager {
 
         tracing::warn!("{}", alert_msg);
 
-        // Send to webhook if configured
-        if let Some(webhook) = &self.alert_webhook {
-            // In production: HTTP POST to webhook
-            tracing::info!("📡 Alert sent to webhook: {}", webhook);
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(SinkEvent::BaitAccess {
+                wallet_id: event.wallet_id.clone(),
+                wallet_type: format!("{:?}", event.wallet_type),
+                address: address.to_string(),
+                attacker_ip: event.attacker_ip.clone(),
+                access_type: format!("{:?}", event.access_type),
+            });
         }
 
         Ok(())
@@ -354,6 +405,17 @@ impl BaitWalletManager {
         }
     }
 
+    /// Bait accesses from any of the given IPs
+    pub async fn accesses_from(&self, ips: &[String]) -> Vec<BaitAccessEvent> {
+        self.access_log
+            .read()
+            .await
+            .iter()
+            .filter(|e| ips.contains(&e.attacker_ip))
+            .cloned()
+            .collect()
+    }
+
     /// Export access log for forensics
     pub async fn export_access_log(&self) -> Result<String> {
         let log = self.access_log.read().await;
@@ -362,10 +424,56 @@ impl BaitWalletManager {
 
     /// Deactivate a bait wallet
     pub async fn deactivate(&self, wallet_id: &str) {
-        if let Some(wallet) = self.wallets.write().await.get_mut(wallet_id) {
-            wallet.active = false;
-            tracing::info!("🎣 Bait wallet {} deactivated", wallet_id);
+        deactivate_in(&self.wallets, wallet_id).await;
+    }
+
+    /// Hold `request_deactivation`s in `queue` until an operator approves
+    /// them; denied after `ttl`
+    pub fn set_approvals(&mut self, queue: Arc<ApprovalQueue>, ttl: chrono::Duration) {
+        let wallets = self.wallets.clone();
+        queue.register(DEACTIVATE_APPROVAL, move |request, decision| {
+            let wallet_id = request.context["wallet_id"]
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("Approval {} names no wallet", request.id))?
+                .to_string();
+            if decision == Decision::Approve {
+                let wallets = wallets.clone();
+                tokio::spawn(async move { deactivate_in(&wallets, &wallet_id).await });
+            }
+            Ok(())
+        });
+        self.approvals = Some((queue, ttl));
+    }
+
+    /// Deactivate once an operator approves; at once without an approval queue
+    pub async fn request_deactivation(&self, wallet_id: &str, reason: &str) -> Result<Option<PendingApproval>> {
+        let Some((queue, ttl)) = &self.approvals else {
+            self.deactivate(wallet_id).await;
+            return Ok(None);
+        };
+        if !self.wallets.read().await.contains_key(wallet_id) {
+            anyhow::bail!("No bait wallet {}", wallet_id);
         }
+        let context = serde_json::json!({ "wallet_id": wallet_id, "reason": reason });
+        let description = format!("Deactivate bait wallet {}: {}", wallet_id, reason);
+        queue.enqueue(DEACTIVATE_APPROVAL, &description, context, *ttl, Utc::now()).map(Some)
+    }
+
+    /// A canary token called home
+    pub fn canary_triggered(&self, token: &CanaryToken, source_ip: &str, user_agent: Option<&str>) -> CanaryTrigger {
+        let trigger = CanaryTrigger {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            token_id: token.id.clone(),
+            token_type: token.token_type.clone(),
+            source_ip: source_ip.to_string(),
+            user_agent: user_agent.map(String::from),
+        };
+        tracing::error!("🚨 CANARY TOKEN TRIGGERED: {:?} {} from {}", token.token_type, token.id, source_ip);
+        if let Some(journal) = &self.intel {
+            journal.append(&IntelRecord::Canary(trigger.clone()));
+        }
+        trigger
     }
 }
 
@@ -417,6 +525,24 @@ impl CanaryToken {
     }
 }
 
+/// A canary token calling home
+async fn deactivate_in(wallets: &RwLock<HashMap<String, BaitWallet>>, wallet_id: &str) {
+    if let Some(wallet) = wallets.write().await.get_mut(wallet_id) {
+        wallet.active = false;
+        tracing::info!("🎣 Bait wallet {} deactivated", wallet_id);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CanaryTrigger {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub token_id: String,
+    pub token_type: CanaryType,
+    pub source_ip: String,
+    pub user_agent: Option<String>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -463,4 +589,51 @@ mod tests {
         assert_eq!(stats.total_accesses, 1);
         println!("✅ Access tracking test PASSED!");
     }
+
+    #[tokio::test]
+    async fn test_access_located_with_shared_locator() {
+        struct FixedLocator;
+
+        #[async_trait::async_trait]
+        impl GeoLocator for FixedLocator {
+            async fn locate(&self, _ip: IpAddr) -> Result<geo::GeoInfo> {
+                Ok(geo::GeoInfo { country_code: "NL".to_string(), asn: Some(64500), org: "Example Hosting".to_string() })
+            }
+        }
+
+        let mut manager = BaitWalletManager::new("https://example.com/callback");
+        manager.set_geo_locator(Arc::new(FixedLocator));
+        let wallet = manager.deploy_bait(WalletType::Bitcoin, "1 BTC").await.unwrap();
+
+        for ip in ["203.0.113.7", "192.168.1.100"] {
+            manager.handle_access(&wallet.id, ip, AccessType::BalanceCheck, None).await.unwrap();
+        }
+        let located = manager.accesses_from(&["203.0.113.7".to_string()]).await[0].attacker_location.clone().unwrap();
+        assert_eq!((located.country_code.as_str(), located.org.as_str()), ("NL", "Example Hosting"));
+        assert!(!located.is_vpn);
+        // Private addresses are never sent to the locator
+        let private = manager.accesses_from(&["192.168.1.100".to_string()]).await[0].attacker_location.clone().unwrap();
+        assert_eq!(private.country_code, "XX");
+        assert!(private.is_vpn);
+    }
+
+    #[tokio::test]
+    async fn test_deactivation_waits_for_approval() {
+        let queue = Arc::new(ApprovalQueue::open(std::path::Path::new("unused"), crate::storage::RuntimeMode::Ephemeral).unwrap());
+        let mut manager = BaitWalletManager::new("https://example.com/callback");
+        manager.set_approvals(queue.clone(), chrono::Duration::hours(1));
+        let wallet = manager.deploy_bait(WalletType::Monero, "40 XMR").await.unwrap();
+
+        let request = manager.request_deactivation(&wallet.id, "burned").await.unwrap().unwrap();
+        assert!(manager.get_all_wallets().await[0].active);
+        queue.resolve(&request.id, Decision::Approve, crate::approvals::LOCAL_CLI, Utc::now()).await.unwrap();
+        for _ in 0..100 {
+            if !manager.get_all_wallets().await[0].active {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(!manager.get_all_wallets().await[0].active);
+        assert!(manager.request_deactivation("nope", "burned").await.is_err());
+    }
 }
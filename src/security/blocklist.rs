@@ -0,0 +1,531 @@
+//! Blocklist
+//! Deny entries (IPs, CIDRs, peer IDs) compiled for the admission hot path.
+//! Readers load the current compiled list without taking a lock; every
+//! mutation rebuilds it and swaps it in atomically
+
+use arc_swap::ArcSwap;
+use parking_lot::Mutex;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Bloom filter bits per entry; with `BLOOM_HASHES` probes this gives
+/// roughly a 1% false positive rate
+const BLOOM_BITS_PER_ENTRY: usize = 10;
+const BLOOM_HASHES: u32 = 7;
+
+/// A blocked address or network
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DenyEntry {
+    Ip(IpAddr),
+    /// Network address (host bits cleared) and prefix length
+    Cidr(IpAddr, u8),
+}
+
+impl DenyEntry {
+    /// The entry as a network: a single IP is a /32 or /128
+    fn network(&self) -> (IpAddr, u8) {
+        match *self {
+            DenyEntry::Ip(ip) => (ip, width(&ip)),
+            DenyEntry::Cidr(net, len) => (net, len),
+        }
+    }
+
+    /// Whether `ip` falls inside this entry
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let (net, len) = self.network();
+        width(&net) == width(&ip) && mask(bits(&ip), width(&ip), len) == bits(&net)
+    }
+}
+
+impl FromStr for DenyEntry {
+    type Err = InvalidEntry;
+
+    /// `203.0.113.7`, `2001:db8::1`, `10.0.0.0/8` or `2001:db8::/32`; host
+    /// bits in a CIDR are cleared
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidEntry(s.to_string());
+        let Some((addr, len)) = s.split_once('/') else {
+            let ip: IpAddr = s.trim().parse().map_err(|_| invalid())?;
+            return Ok(DenyEntry::Ip(canonical(ip)));
+        };
+        let addr: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+        let len: u8 = len.trim().parse().map_err(|_| invalid())?;
+        if len > width(&addr) {
+            return Err(invalid());
+        }
+        if len == width(&addr) {
+            return Ok(DenyEntry::Ip(canonical(addr)));
+        }
+        let net = from_bits(&addr, mask(bits(&addr), width(&addr), len));
+        Ok(DenyEntry::Cidr(net, len))
+    }
+}
+
+impl fmt::Display for DenyEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DenyEntry::Ip(ip) => write!(f, "{}", ip),
+            DenyEntry::Cidr(net, len) => write!(f, "{}/{}", net, len),
+        }
+    }
+}
+
+/// A deny entry that is neither an IP address nor a CIDR
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidEntry(pub String);
+
+impl fmt::Display for InvalidEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' is not an IP address or CIDR", self.0)
+    }
+}
+
+impl std::error::Error for InvalidEntry {}
+
+/// An IPv4-mapped IPv6 address as the IPv4 it carries
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        ip => ip,
+    }
+}
+
+fn width(ip: &IpAddr) -> u8 {
+    if ip.is_ipv4() { 32 } else { 128 }
+}
+
+/// Address bits, right-aligned
+fn bits(ip: &IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u32::from(*v4) as u128,
+        IpAddr::V6(v6) => u128::from(*v6),
+    }
+}
+
+fn from_bits(family: &IpAddr, bits: u128) -> IpAddr {
+    match family {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(bits as u32)),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(bits)),
+    }
+}
+
+/// Keep the top `len` of `width` bits
+fn mask(bits: u128, width: u8, len: u8) -> u128 {
+    if len == 0 {
+        return 0;
+    }
+    let host = (width - len) as u32;
+    bits >> host << host
+}
+
+/// Binary trie of network prefixes for one address family
+#[derive(Debug, Default)]
+struct PrefixTrie {
+    /// Child indices per node (0 = none; the root is never a child)
+    children: Vec<[u32; 2]>,
+    /// Nodes that end a prefix
+    terminal: Vec<bool>,
+    width: u8,
+}
+
+impl PrefixTrie {
+    fn new(width: u8) -> Self {
+        Self { children: vec![[0, 0]], terminal: vec![false], width }
+    }
+
+    fn insert(&mut self, net: u128, len: u8) {
+        let mut node = 0;
+        for depth in 0..len {
+            if self.terminal[node] {
+                // A shorter prefix already covers this one
+                return;
+            }
+            let bit = ((net >> (self.width - 1 - depth)) & 1) as usize;
+            if self.children[node][bit] == 0 {
+                self.children.push([0, 0]);
+                self.terminal.push(false);
+                self.children[node][bit] = (self.children.len() - 1) as u32;
+            }
+            node = self.children[node][bit] as usize;
+        }
+        self.terminal[node] = true;
+    }
+
+    /// Whether any inserted prefix covers `addr`
+    fn covers(&self, addr: u128) -> bool {
+        let mut node = 0;
+        for depth in 0..self.width {
+            if self.terminal[node] {
+                return true;
+            }
+            let bit = ((addr >> (self.width - 1 - depth)) & 1) as usize;
+            match self.children[node][bit] {
+                0 => return false,
+                child => node = child as usize,
+            }
+        }
+        self.terminal[node]
+    }
+}
+
+/// Bloom filter over IP prefixes and peer IDs; a miss is a definite "not
+/// blocked"
+#[derive(Debug)]
+struct Bloom {
+    words: Vec<u64>,
+}
+
+impl Bloom {
+    fn with_capacity(entries: usize) -> Self {
+        let bits = (entries * BLOOM_BITS_PER_ENTRY).max(64);
+        Self { words: vec![0; bits.div_ceil(64)] }
+    }
+
+    /// Bit positions for `hash`, by double hashing: h1 + i * h2
+    fn probes(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let bits = (self.words.len() * 64) as u64;
+        let (h1, h2) = (hash, hash.rotate_left(32) | 1);
+        (0..BLOOM_HASHES as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits) as usize)
+    }
+
+    fn insert(&mut self, hash: u64) {
+        for bit in self.probes(hash).collect::<Vec<_>>() {
+            self.words[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    fn may_contain(&self, hash: u64) -> bool {
+        self.probes(hash).all(|bit| self.words[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+}
+
+/// Hash of a masked prefix; the family and length are folded in so equal
+/// bits under different prefixes don't collide
+fn prefix_hash(v6: bool, bits: u128, len: u8) -> u64 {
+    let mut h = (bits as u64) ^ ((bits >> 64) as u64).rotate_left(29) ^ ((len as u64) << 56) ^ (v6 as u64) << 63;
+    // splitmix64 finaliser
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
+
+fn peer_hash(peer_id: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    peer_id.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Prefix lengths present for one family, as a bitmask over 0..=128
+#[derive(Debug, Default, Clone, Copy)]
+struct Lengths {
+    short: u128,
+    full_v6: bool,
+}
+
+impl Lengths {
+    fn insert(&mut self, len: u8) {
+        if len == 128 {
+            self.full_v6 = true;
+        } else {
+            self.short |= 1 << len;
+        }
+    }
+
+    fn iter(self) -> impl Iterator<Item = u8> {
+        let mut rest = self.short;
+        std::iter::from_fn(move || {
+            (rest != 0).then(|| {
+                let len = rest.trailing_zeros() as u8;
+                rest &= rest - 1;
+                len
+            })
+        })
+        .chain(self.full_v6.then_some(128))
+    }
+}
+
+/// Immutable, compiled form of a blocklist
+#[derive(Debug)]
+pub struct DenyList {
+    ips: HashSet<IpAddr>,
+    v4: PrefixTrie,
+    v6: PrefixTrie,
+    peers: HashSet<String>,
+    bloom: Bloom,
+    v4_lengths: Lengths,
+    v6_lengths: Lengths,
+}
+
+impl DenyList {
+    /// Compile `entries` and `peers`
+    pub fn compile<'a>(entries: impl IntoIterator<Item = &'a DenyEntry>, peers: impl IntoIterator<Item = &'a String>) -> Self {
+        let entries: Vec<&DenyEntry> = entries.into_iter().collect();
+        let peers: HashSet<String> = peers.into_iter().cloned().collect();
+        let mut list = DenyList {
+            ips: HashSet::new(),
+            v4: PrefixTrie::new(32),
+            v6: PrefixTrie::new(128),
+            bloom: Bloom::with_capacity(entries.len() + peers.len()),
+            peers,
+            v4_lengths: Lengths::default(),
+            v6_lengths: Lengths::default(),
+        };
+        for peer in &list.peers {
+            list.bloom.insert(peer_hash(peer));
+        }
+        for entry in entries {
+            let (net, len) = entry.network();
+            let v6 = net.is_ipv6();
+            list.bloom.insert(prefix_hash(v6, bits(&net), len));
+            if v6 { list.v6_lengths.insert(len) } else { list.v4_lengths.insert(len) }
+            match entry {
+                DenyEntry::Ip(ip) => {
+                    list.ips.insert(*ip);
+                }
+                DenyEntry::Cidr(..) if v6 => list.v6.insert(bits(&net), len),
+                DenyEntry::Cidr(..) => list.v4.insert(bits(&net), len),
+            }
+        }
+        list
+    }
+
+    /// Whether `ip` is blocked
+    pub fn denies_ip(&self, ip: IpAddr) -> bool {
+        let ip = canonical(ip);
+        let (v6, addr, lengths) = match ip {
+            IpAddr::V4(_) => (false, bits(&ip), self.v4_lengths),
+            IpAddr::V6(_) => (true, bits(&ip), self.v6_lengths),
+        };
+        let w = width(&ip);
+        if !lengths.iter().any(|len| self.bloom.may_contain(prefix_hash(v6, mask(addr, w, len), len))) {
+            return false;
+        }
+        self.ips.contains(&ip) || if v6 { self.v6.covers(addr) } else { self.v4.covers(addr) }
+    }
+
+    /// Whether `peer_id` is blocked
+    pub fn denies_peer(&self, peer_id: &str) -> bool {
+        self.bloom.may_contain(peer_hash(peer_id)) && self.peers.contains(peer_id)
+    }
+
+    /// Whether either the address or the peer ID is blocked
+    pub fn denies(&self, ip: Option<IpAddr>, peer_id: Option<&str>) -> bool {
+        ip.is_some_and(|ip| self.denies_ip(ip)) || peer_id.is_some_and(|p| self.denies_peer(p))
+    }
+}
+
+#[derive(Debug, Default)]
+struct Source {
+    entries: BTreeSet<DenyEntry>,
+    peers: BTreeSet<String>,
+}
+
+/// Shared blocklist; lookups are lock-free, mutations recompile
+pub struct Blocklist {
+    compiled: ArcSwap<DenyList>,
+    source: Mutex<Source>,
+}
+
+impl Default for Blocklist {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Blocklist {
+    pub fn new() -> Self {
+        Self {
+            compiled: ArcSwap::from_pointee(DenyList::compile([], [])),
+            source: Mutex::new(Source::default()),
+        }
+    }
+
+    /// The current compiled list
+    pub fn load(&self) -> Arc<DenyList> {
+        self.compiled.load_full()
+    }
+
+    pub fn denies_ip(&self, ip: IpAddr) -> bool {
+        self.compiled.load().denies_ip(ip)
+    }
+
+    pub fn denies_peer(&self, peer_id: &str) -> bool {
+        self.compiled.load().denies_peer(peer_id)
+    }
+
+    pub fn denies(&self, ip: Option<IpAddr>, peer_id: Option<&str>) -> bool {
+        self.compiled.load().denies(ip, peer_id)
+    }
+
+    /// Block entries and peers in one rebuild; returns whether anything changed
+    pub fn block_all(&self, entries: impl IntoIterator<Item = DenyEntry>, peers: impl IntoIterator<Item = String>) -> bool {
+        self.update(|source| {
+            let mut changed = false;
+            for entry in entries {
+                changed |= source.entries.insert(entry);
+            }
+            for peer in peers {
+                changed |= source.peers.insert(peer);
+            }
+            changed
+        })
+    }
+
+    pub fn block(&self, entry: DenyEntry) -> bool {
+        self.block_all([entry], [])
+    }
+
+    pub fn block_peer(&self, peer_id: &str) -> bool {
+        self.block_all([], [peer_id.to_string()])
+    }
+
+    pub fn unblock(&self, entry: &DenyEntry) -> bool {
+        self.update(|source| source.entries.remove(entry))
+    }
+
+    pub fn unblock_peer(&self, peer_id: &str) -> bool {
+        self.update(|source| source.peers.remove(peer_id))
+    }
+
+    /// Blocked entries and peers, sorted
+    pub fn entries(&self) -> (Vec<DenyEntry>, Vec<String>) {
+        let source = self.source.lock();
+        (source.entries.iter().copied().collect(), source.peers.iter().cloned().collect())
+    }
+
+    pub fn len(&self) -> usize {
+        let source = self.source.lock();
+        source.entries.len() + source.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Apply `change` and, if it reports a change, recompile and swap. The
+    /// source lock serialises writers so no update is lost
+    fn update(&self, change: impl FnOnce(&mut Source) -> bool) -> bool {
+        let mut source = self.source.lock();
+        if !change(&mut source) {
+            return false;
+        }
+        self.compiled.store(Arc::new(DenyList::compile(&source.entries, &source.peers)));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_entries_parse_and_normalise() {
+        assert_eq!("203.0.113.7".parse(), Ok(DenyEntry::Ip(ip("203.0.113.7"))));
+        assert_eq!("10.1.2.3/8".parse::<DenyEntry>().unwrap().to_string(), "10.0.0.0/8");
+        assert_eq!("2001:db8::1/128".parse(), Ok(DenyEntry::Ip(ip("2001:db8::1"))));
+        assert_eq!("2001:db8:ffff::/32".parse::<DenyEntry>().unwrap().to_string(), "2001:db8::/32");
+        assert!("10.0.0.0/33".parse::<DenyEntry>().is_err());
+        assert!("not-an-ip".parse::<DenyEntry>().is_err());
+    }
+
+    #[test]
+    fn test_blocklist_nested_cidrs_and_peers() {
+        let list = Blocklist::new();
+        list.block("10.0.0.0/8".parse().unwrap());
+        list.block("10.20.0.0/16".parse().unwrap());
+        list.block("2001:db8::/32".parse().unwrap());
+        list.block("198.51.100.9".parse().unwrap());
+        list.block_peer("12D3KooWBad");
+
+        assert!(list.denies_ip(ip("10.20.30.40")));
+        assert!(list.denies_ip(ip("2001:db8:1::5")));
+        assert!(list.denies_ip(ip("::ffff:198.51.100.9")));
+        assert!(!list.denies_ip(ip("11.0.0.1")));
+        assert!(!list.denies_ip(ip("2001:db9::1")));
+        assert!(list.denies(None, Some("12D3KooWBad")));
+
+        // Removing the outer network leaves the nested one in force
+        assert!(list.unblock(&"10.0.0.0/8".parse().unwrap()));
+        assert!(!list.denies_ip(ip("10.1.0.1")));
+        assert!(list.denies_ip(ip("10.20.0.1")));
+        assert!(!list.unblock(&"10.0.0.0/8".parse().unwrap()));
+        assert_eq!(list.len(), 4);
+    }
+
+    /// The obvious answer the compiled list must agree with
+    fn naive(entries: &[DenyEntry], peers: &[String], probe: IpAddr, peer: &str) -> bool {
+        entries.iter().any(|e| e.contains(canonical(probe))) || peers.iter().any(|p| p == peer)
+    }
+
+    /// Addresses drawn from a few small pools so probes often land inside,
+    /// next to or across the boundaries of entries
+    fn address() -> impl Strategy<Value = IpAddr> {
+        prop_oneof![
+            (0u8..4, any::<u8>()).prop_map(|(c, d)| IpAddr::V4(Ipv4Addr::new(10, 0, c, d))),
+            any::<u32>().prop_map(|b| IpAddr::V4(Ipv4Addr::from(b))),
+            (0u16..4, any::<u16>()).prop_map(|(a, b)| IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, a, 0, 0, 0, 0, b))),
+            any::<u128>().prop_map(|b| IpAddr::V6(Ipv6Addr::from(b))),
+        ]
+    }
+
+    fn entry() -> impl Strategy<Value = DenyEntry> {
+        (address(), any::<u8>(), any::<bool>()).prop_map(|(addr, len, cidr)| {
+            let len = len % (width(&addr) + 1);
+            if cidr { format!("{}/{}", addr, len).parse().unwrap() } else { DenyEntry::Ip(addr) }
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn prop_matches_naive(
+            entries in prop::collection::vec(entry(), 0..40),
+            peers in prop::collection::vec("[a-d]{1,3}", 0..5),
+            probes in prop::collection::vec((address(), "[a-d]{1,3}"), 1..40),
+            mapped in any::<bool>(),
+        ) {
+            let list = Blocklist::new();
+            list.block_all(entries.iter().copied(), peers.iter().cloned());
+            for (probe, peer) in probes {
+                let probe = match probe {
+                    IpAddr::V4(v4) if mapped => IpAddr::V6(v4.to_ipv6_mapped()),
+                    ip => ip,
+                };
+                prop_assert_eq!(
+                    list.denies(Some(probe), Some(&peer)),
+                    naive(&entries, &peers, probe, &peer),
+                    "probe {} / {}", probe, peer
+                );
+            }
+        }
+
+        #[test]
+        fn prop_unblock_matches_naive(
+            entries in prop::collection::vec(entry(), 1..30),
+            remove in prop::collection::vec(any::<prop::sample::Index>(), 0..10),
+            probes in prop::collection::vec(address(), 1..40),
+        ) {
+            let list = Blocklist::new();
+            list.block_all(entries.iter().copied(), []);
+            let mut remaining: BTreeSet<DenyEntry> = entries.iter().copied().collect();
+            for index in remove {
+                let entry = *index.get(&entries);
+                list.unblock(&entry);
+                remaining.remove(&entry);
+            }
+            let remaining: Vec<DenyEntry> = remaining.into_iter().collect();
+            for probe in probes {
+                prop_assert_eq!(list.denies_ip(probe), naive(&remaining, &[], probe, ""), "probe {}", probe);
+            }
+        }
+    }
+}
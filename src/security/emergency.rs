@@ -1,11 +1,24 @@
-use anyhow::{Result, Context};
+use anyhow::Result;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use chrono::{DateTime, Utc};
 use crate::security::SecurityEvent;
+use crate::storage::RuntimeMode;
+use crate::security::notifications::{NotificationRouter, SinkEvent};
+use crate::security::guardians::{Authorization, GuardianQuorum};
+use crate::security::{snapshot, wipe};
+use crate::approvals::{ApprovalQueue, Decision};
+use std::sync::Arc;
+
+/// Evidence directory in persistent mode
+pub const EVIDENCE_DIR: &str = "/var/log/quantra/evidence";
+
+/// Approval category of full wipes held for an operator
+pub const FULL_WIPE_APPROVAL: &str = "emergency.full_wipe";
 
 /// Emergency handler for critical threats
 /// Includes secure evidence collection and emergency wipe
+#[derive(Clone)]
 pub struct EmergencyHandler {
     /// Evidence collection directory
     evidence_dir: PathBuf,
@@ -13,11 +26,40 @@ pub struct EmergencyHandler {
     wipe_enabled: bool,
     /// Paths to secure wipe on emergency
     secure_wipe_paths: Vec<PathBuf>,
+    /// Outbound notifications
+    notifier: Option<Arc<NotificationRouter>>,
+    /// Full wipes wait here for an operator, for the given time
+    approvals: Option<(Arc<ApprovalQueue>, chrono::Duration)>,
+    /// Secure wipes and up need this quorum (or break glass)
+    guardians: Option<Arc<GuardianQuorum>>,
 }
 
 impl EmergencyHandler {
     pub fn new() -> Result<Self> {
-        let evidence_dir = PathBuf::from("/var/log/quantra/evidence");
+        Self::with_mode(RuntimeMode::Persistent)
+    }
+
+    /// In ephemeral mode evidence goes to a per-process tmpfs directory
+    /// instead of the persistent evidence store
+    pub fn with_mode(mode: RuntimeMode) -> Result<Self> {
+        Self::with_evidence_dir(PathBuf::from(EVIDENCE_DIR), mode)
+    }
+
+    /// Collect evidence into `dir` (ignored in ephemeral mode)
+    pub fn with_evidence_dir(dir: PathBuf, mode: RuntimeMode) -> Result<Self> {
+        let evidence_dir = match mode {
+            RuntimeMode::Persistent => dir,
+            RuntimeMode::Ephemeral => {
+                let tmpfs = Path::new("/dev/shm");
+                let base = if tmpfs.is_dir() { tmpfs.to_path_buf() } else { std::env::temp_dir() };
+                let dir = base.join(format!("quantra-evidence-{}", std::process::id()));
+                tracing::warn!(
+                    "⚠️  Ephemeral mode: emergency evidence will be written to {} and is NOT retained",
+                    dir.display()
+                );
+                dir
+            }
+        };
         std::fs::create_dir_all(&evidence_dir)?;
 
         Ok(Self {
@@ -25,12 +67,48 @@ impl EmergencyHandler {
             wipe_enabled: true,
             secure_wipe_paths: vec![
                 PathBuf::from("/var/log/quantra/audit.log"),
-                PathBuf::from("/tmp/quantra"),
+                std::env::temp_dir().join("quantra"),
                 PathBuf::from("/home/worm/.quantra_cache"),
             ],
+            notifier: None,
+            approvals: None,
+            guardians: None,
         })
     }
 
+    /// Announce emergency responses through the notification router
+    pub fn set_notifier(&mut self, notifier: Arc<NotificationRouter>) {
+        self.notifier = Some(notifier);
+    }
+
+    /// Hold full wipes in `queue` until an operator approves them; denied
+    /// after `ttl`. Evidence collection is never held
+    pub fn set_approvals(&mut self, queue: Arc<ApprovalQueue>, ttl: chrono::Duration) {
+        let handler = Self { approvals: None, ..self.clone() };
+        queue.register(FULL_WIPE_APPROVAL, move |_, decision| {
+            if decision == Decision::Approve {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handler.full_emergency_wipe().await {
+                        tracing::error!("💥 Approved full wipe failed: {}", e);
+                    }
+                });
+            }
+            Ok(())
+        });
+        self.approvals = Some((queue, ttl));
+    }
+
+    /// Require `quorum` before secure wipes, full wipes and shutdowns
+    pub fn set_guardians(&mut self, quorum: Arc<GuardianQuorum>) {
+        self.guardians = Some(quorum);
+    }
+
+    /// Directory evidence is written to
+    pub fn evidence_dir(&self) -> &Path {
+        &self.evidence_dir
+    }
+
     /// Handle critical threat event
     pub async fn handle_critical_threat(&mut self, event: &SecurityEvent) -> Result<()> {
         tracing::error!("🚨🚨🚨 CRITICAL THREAT DETECTED 🚨🚨🚨");
@@ -41,8 +119,15 @@ impl EmergencyHandler {
         // 1. Collect evidence BEFORE wiping
         self.collect_evidence(event).await?;
 
-        // 2. Determine response level
-        let response = self.determine_response(event);
+        // 2. Determine response level, downgraded without the guardian quorum
+        let response = self.authorize(event, self.determine_response(event)).await;
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(SinkEvent::EmergencyTriggered {
+                source: event.source.clone(),
+                event_type: format!("{:?}", event.event_type),
+                response: format!("{:?}", response),
+            });
+        }
 
         match response {
             EmergencyResponse::CollectOnly => {
@@ -52,10 +137,22 @@ impl EmergencyHandler {
                 tracing::error!("🔥 Initiating SECURE WIPE of sensitive data");
                 self.secure_wipe().await?;
             }
-            EmergencyResponse::FullWipe => {
-                tracing::error!("💥 Initiating FULL EMERGENCY WIPE");
-                self.full_emergency_wipe().await?;
-            }
+            EmergencyResponse::FullWipe => match &self.approvals {
+                Some((queue, ttl)) => {
+                    let context = serde_json::json!({
+                        "source": event.source,
+                        "event_type": format!("{:?}", event.event_type),
+                        "timestamp": event.timestamp,
+                    });
+                    let description = format!("Full emergency wipe after {:?} from {}", event.event_type, event.source);
+                    let request = queue.enqueue(FULL_WIPE_APPROVAL, &description, context, *ttl, Utc::now())?;
+                    tracing::error!("💥 FULL EMERGENCY WIPE held for approval ({})", request.id);
+                }
+                None => {
+                    tracing::error!("💥 Initiating FULL EMERGENCY WIPE");
+                    self.full_emergency_wipe().await?;
+                }
+            },
             EmergencyResponse::Shutdown => {
                 tracing::error!("⚠️  EMERGENCY SHUTDOWN initiated");
                 self.emergency_shutdown().await?;
@@ -94,68 +191,17 @@ impl EmergencyHandler {
 
     /// Collect system state snapshot
     async fn collect_system_snapshot(&self) -> Result<serde_json::Value> {
-        Ok(serde_json::json!({
-            "uptime": self.get_uptime()?,
-            "load_average": self.get_load_average()?,
-            "memory": self.get_memory_info()?,
-            "disk": self.get_disk_info()?,
-        }))
-    }
-
-    fn get_uptime(&self) -> Result<String> {
-        let output = Command::new("uptime").output()?;
-        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
-    }
-
-    fn get_load_average(&self) -> Result<String> {
-        let output = Command::new("cat").arg("/proc/loadavg").output()?;
-        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
-    }
-
-    fn get_memory_info(&self) -> Result<String> {
-        let output = Command::new("free").arg("-h").output()?;
-        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
-    }
-
-    fn get_disk_info(&self) -> Result<String> {
-        let output = Command::new("df").arg("-h").output()?;
-        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        Ok(snapshot::system_snapshot())
     }
 
     /// Collect network state snapshot
     async fn collect_network_snapshot(&self) -> Result<serde_json::Value> {
-        Ok(serde_json::json!({
-            "connections": self.get_network_connections()?,
-            "interfaces": self.get_network_interfaces()?,
-        }))
-    }
-
-    fn get_network_connections(&self) -> Result<String> {
-        let output = Command::new("ss").arg("-tunapl").output()?;
-        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
-    }
-
-    fn get_network_interfaces(&self) -> Result<String> {
-        let output = Command::new("ip").arg("addr").output()?;
-        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        Ok(snapshot::network_snapshot())
     }
 
     /// Collect process snapshot
     async fn collect_process_snapshot(&self) -> Result<serde_json::Value> {
-        Ok(serde_json::json!({
-            "processes": self.get_process_list()?,
-            "top": self.get_top_processes()?,
-        }))
-    }
-
-    fn get_process_list(&self) -> Result<String> {
-        let output = Command::new("ps").args(&["aux"]).output()?;
-        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
-    }
-
-    fn get_top_processes(&self) -> Result<String> {
-        let output = Command::new("top").args(&["-b", "-n", "1"]).output()?;
-        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        Ok(snapshot::process_snapshot().await)
     }
 
     /// Backup evidence to remote server (encrypted)
@@ -178,6 +224,43 @@ impl EmergencyHandler {
         }
     }
 
+    /// Destructive responses go ahead on the guardian quorum or break glass;
+    /// otherwise they fall back to collecting evidence, loudly
+    async fn authorize(&self, event: &SecurityEvent, response: EmergencyResponse) -> EmergencyResponse {
+        let Some(quorum) = self.guardians.as_ref().filter(|_| response >= EmergencyResponse::SecureWipe) else {
+            return response;
+        };
+        let summary = format!("{:?} from {}", event.event_type, event.source);
+        let (approvals, threshold) = match quorum.authorize(&summary, &format!("{:?}", response)).await {
+            Ok(Authorization::Quorum(guardians)) => {
+                tracing::warn!("🛡️ {:?} approved by guardians {}", response, guardians.join(", "));
+                return response;
+            }
+            Ok(Authorization::BreakGlass) => return response,
+            Ok(Authorization::Denied { approvals, threshold }) => (approvals, threshold),
+            Err(e) => {
+                tracing::error!("🛡️ Could not ask the guardians: {:#}", e);
+                (0, quorum.threshold())
+            }
+        };
+        tracing::error!(
+            "🛡️ {:?} NOT authorized ({} of {} guardian approvals); collecting evidence only",
+            response,
+            approvals,
+            threshold
+        );
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(SinkEvent::GuardianQuorumFailed {
+                source: event.source.clone(),
+                event_type: format!("{:?}", event.event_type),
+                response: format!("{:?}", response),
+                approvals,
+                threshold,
+            });
+        }
+        EmergencyResponse::CollectOnly
+    }
+
     /// Secure wipe of sensitive data using multiple passes
     async fn secure_wipe(&self) -> Result<()> {
         if !self.wipe_enabled {
@@ -204,27 +287,10 @@ impl EmergencyHandler {
         Ok(())
     }
 
-    /// Shred file using 7-pass DoD method
+    /// Shred using 7-pass DoD method (`shred` where available, else in-process)
     async fn shred_file(&self, path: &Path) -> Result<()> {
-        // Use shred command for secure deletion
-        let output = Command::new("shred")
-            .args(&[
-                "-v",      // Verbose
-                "-n", "7", // 7 passes (DoD 5220.22-M standard)
-                "-z",      // Add final pass of zeros
-                "-u",      // Remove file after shredding
-                path.to_str().context("Invalid path")?,
-            ])
-            .output()?;
-
-        if !output.status.success() {
-            return Err(anyhow::anyhow!(
-                "shred failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
-        }
-
-        Ok(())
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || wipe::secure_delete(&path, wipe::WIPE_PASSES)).await?
     }
 
     /// Full emergency wipe (nuclear option)
@@ -251,6 +317,7 @@ impl EmergencyHandler {
     }
 
     /// Wipe swap space
+    #[cfg(target_os = "linux")]
     async fn wipe_swap(&self) -> Result<()> {
         tracing::warn!("🔄 Wiping swap space...");
 
@@ -263,23 +330,33 @@ impl EmergencyHandler {
         Ok(())
     }
 
+    /// Swap is managed by the OS here; nothing to cycle
+    #[cfg(not(target_os = "linux"))]
+    async fn wipe_swap(&self) -> Result<()> {
+        tracing::warn!("⚠️  Swap wipe is only supported on Linux; skipping");
+        Ok(())
+    }
+
     /// Wipe free space on disk
     async fn wipe_free_space(&self) -> Result<()> {
         tracing::warn!("💾 Wiping free disk space (this may take a while)...");
 
         // Create large file filled with zeros to overwrite free space
-        let temp_file = "/tmp/wipe_free_space.tmp";
-
-        let _output = Command::new("dd")
-            .args(&[
-                "if=/dev/zero",
-                &format!("of={}", temp_file),
-                "bs=1M",
-            ])
-            .output(); // Will fail when disk is full (expected)
+        let temp_file = std::env::temp_dir().join("wipe_free_space.tmp");
+
+        let fill = temp_file.clone();
+        let _ = tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+            use std::io::Write;
+            let mut file = std::fs::File::create(&fill)?;
+            let zeros = vec![0u8; 1 << 20];
+            loop {
+                file.write_all(&zeros)?; // Fails when the disk is full (expected)
+            }
+        })
+        .await;
 
         // Remove temp file
-        std::fs::remove_file(temp_file).ok();
+        std::fs::remove_file(&temp_file).ok();
 
         Ok(())
     }
@@ -292,13 +369,17 @@ impl EmergencyHandler {
         tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
 
         // Shutdown system
-        Command::new("shutdown").args(&["-h", "+1"]).output()?;
+        #[cfg(windows)]
+        Command::new("shutdown").args(["/s", "/t", "60"]).output()?;
+        #[cfg(not(windows))]
+        Command::new("shutdown").args(["-h", "+1"]).output()?;
 
         Ok(())
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// In order of severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 enum EmergencyResponse {
     CollectOnly,    // Just collect evidence
     SecureWipe,     // Wipe sensitive data
@@ -325,4 +406,44 @@ mod tests {
 
         handler.handle_critical_threat(&event).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_destructive_response_needs_quorum() {
+        use crate::crypto::key_provider::{FileKeyProvider, KeyProvider};
+        use crate::security::guardians::{GuardianApproval, GuardianConfig, GuardianMessage};
+
+        let guardian = FileKeyProvider::generate();
+        let config = GuardianConfig {
+            guardians: vec![hex::encode(guardian.public_key().as_bytes())],
+            threshold: 1,
+            window: crate::units::HumanDuration::from_millis(300),
+            ..Default::default()
+        };
+        let (outbox, mut requests) = tokio::sync::mpsc::unbounded_channel();
+        let quorum = Arc::new(GuardianQuorum::new(&config, Arc::new(FileKeyProvider::generate()), outbox).unwrap());
+        let mut handler = EmergencyHandler::with_mode(RuntimeMode::Ephemeral).unwrap();
+        handler.set_guardians(quorum.clone());
+        let event = SecurityEvent {
+            event_type: EventType::UnauthorizedAccess,
+            timestamp: Utc::now(),
+            source: "test".to_string(),
+            details: serde_json::json!({}),
+        };
+
+        // Evidence collection never waits on the guardians
+        assert_eq!(handler.authorize(&event, EmergencyResponse::CollectOnly).await, EmergencyResponse::CollectOnly);
+        assert!(requests.try_recv().is_err());
+
+        // Nobody answers: downgraded
+        assert_eq!(handler.authorize(&event, EmergencyResponse::SecureWipe).await, EmergencyResponse::CollectOnly);
+        requests.recv().await.unwrap();
+
+        // The guardian approves: goes ahead
+        let approving = tokio::spawn(async move {
+            let (_, GuardianMessage::Request(signed)) = requests.recv().await.unwrap() else { panic!("expected a request") };
+            quorum.submit(&GuardianApproval::sign(&signed.request, &guardian).unwrap()).unwrap();
+        });
+        assert_eq!(handler.authorize(&event, EmergencyResponse::FullWipe).await, EmergencyResponse::FullWipe);
+        approving.await.unwrap();
+    }
 }
@@ -0,0 +1,271 @@
+//! IP Geolocation
+//! Country and ASN lookups with a TTL cache, shared by admission control and
+//! the bait wallet system
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Default ip-api.com JSON endpoint
+const DEFAULT_IP_API_ENDPOINT: &str = "http://ip-api.com/json";
+
+/// Max cached lookups before the oldest entries are evicted
+const MAX_CACHE_ENTRIES: usize = 10_000;
+
+/// Failed lookups are retried after this long, not on every connection
+const FAILED_LOOKUP_TTL_SECS: i64 = 60;
+
+/// Country / network ownership for an IP
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeoInfo {
+    /// ISO 3166-1 alpha-2 country code
+    pub country_code: String,
+    /// Autonomous system number
+    pub asn: Option<u32>,
+    /// Owning organisation
+    pub org: String,
+}
+
+/// Resolves IP addresses to country / ASN
+#[async_trait]
+pub trait GeoLocator: Send + Sync {
+    async fn locate(&self, ip: IpAddr) -> Result<GeoInfo>;
+}
+
+/// ip-api.com backed locator
+pub struct IpApiLocator {
+    endpoint: String,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IpApiResponse {
+    status: String,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    country_code: String,
+    #[serde(default, rename = "as")]
+    as_field: String,
+    #[serde(default)]
+    org: String,
+}
+
+impl IpApiLocator {
+    pub fn new() -> Self {
+        Self::with_endpoint(DEFAULT_IP_API_ENDPOINT)
+    }
+
+    /// Create with a custom endpoint (e.g. a self-hosted mirror)
+    pub fn with_endpoint(endpoint: &str) -> Self {
+        let client = crate::net::http_client(crate::net::Subsystem::Geolocation)
+            .timeout(std::time::Duration::from_secs(3))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            client,
+        }
+    }
+}
+
+impl Default for IpApiLocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl GeoLocator for IpApiLocator {
+    async fn locate(&self, ip: IpAddr) -> Result<GeoInfo> {
+        let url = format!("{}/{}?fields=status,message,countryCode,as,org", self.endpoint, ip);
+        let response: IpApiResponse = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .context("Geolocation request failed")?
+            .json()
+            .await
+            .context("Invalid geolocation response")?;
+
+        if response.status != "success" {
+            anyhow::bail!(
+                "Geolocation lookup failed for {}: {}",
+                ip,
+                response.message.unwrap_or_default()
+            );
+        }
+
+        Ok(GeoInfo {
+            country_code: response.country_code.to_uppercase(),
+            asn: parse_asn(&response.as_field),
+            org: response.org,
+        })
+    }
+}
+
+/// A lookup result, with the error kept as text so it can be cached
+type Lookup = std::result::Result<GeoInfo, String>;
+
+/// Caches successful lookups for a fixed TTL, and failures for a short one
+pub struct CachedGeoLocator {
+    inner: Arc<dyn GeoLocator>,
+    cache: RwLock<HashMap<IpAddr, (Lookup, DateTime<Utc>)>>,
+    ttl: Duration,
+}
+
+impl CachedGeoLocator {
+    pub fn new(inner: Arc<dyn GeoLocator>, ttl: Duration) -> Self {
+        Self {
+            inner,
+            cache: RwLock::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// Number of cached entries
+    pub async fn cached_entries(&self) -> usize {
+        self.cache.read().await.len()
+    }
+
+    fn ttl_for(&self, lookup: &Lookup) -> Duration {
+        match lookup {
+            Ok(_) => self.ttl,
+            Err(_) => self.ttl.min(Duration::seconds(FAILED_LOOKUP_TTL_SECS)),
+        }
+    }
+}
+
+#[async_trait]
+impl GeoLocator for CachedGeoLocator {
+    async fn locate(&self, ip: IpAddr) -> Result<GeoInfo> {
+        let now = Utc::now();
+        if let Some((lookup, cached_at)) = self.cache.read().await.get(&ip) {
+            if now - *cached_at < self.ttl_for(lookup) {
+                return lookup.clone().map_err(anyhow::Error::msg);
+            }
+        }
+
+        let lookup = self.inner.locate(ip).await.map_err(|e| format!("{:#}", e));
+
+        let mut cache = self.cache.write().await;
+        if cache.len() >= MAX_CACHE_ENTRIES {
+            cache.retain(|_, (lookup, cached_at)| now - *cached_at < self.ttl_for(lookup));
+            if cache.len() >= MAX_CACHE_ENTRIES {
+                cache.clear();
+            }
+        }
+        cache.insert(ip, (lookup.clone(), now));
+
+        lookup.map_err(anyhow::Error::msg)
+    }
+}
+
+/// Parse an ASN from "AS15169", "AS15169 Google LLC" or "15169"
+pub fn parse_asn(value: &str) -> Option<u32> {
+    let token = value.split_whitespace().next()?;
+    let digits = token
+        .strip_prefix("AS")
+        .or_else(|| token.strip_prefix("as"))
+        .unwrap_or(token);
+    digits.parse().ok()
+}
+
+/// Private, loopback and link-local addresses never leave the local network
+pub fn is_private_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let octets = v4.octets();
+            v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                // Carrier-grade NAT (100.64.0.0/10)
+                || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+        }
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            v6.is_loopback()
+                || v6.is_unspecified()
+                // Unique local (fc00::/7) and link-local (fe80::/10)
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingLocator {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl GeoLocator for CountingLocator {
+        async fn locate(&self, ip: IpAddr) -> Result<GeoInfo> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if ip.is_unspecified() {
+                anyhow::bail!("rate limited");
+            }
+            Ok(GeoInfo {
+                country_code: "NL".to_string(),
+                asn: Some(64500),
+                org: "Example Hosting".to_string(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_avoids_repeat_lookups() {
+        let inner = Arc::new(CountingLocator { calls: AtomicUsize::new(0) });
+        let cached = CachedGeoLocator::new(inner.clone(), Duration::minutes(10));
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+
+        for _ in 0..5 {
+            assert_eq!(cached.locate(ip).await.unwrap().country_code, "NL");
+        }
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(cached.cached_entries().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_failures_cached_briefly() {
+        let inner = Arc::new(CountingLocator { calls: AtomicUsize::new(0) });
+        let cached = CachedGeoLocator::new(inner.clone(), Duration::minutes(10));
+        let failing: IpAddr = "0.0.0.0".parse().unwrap();
+
+        for _ in 0..3 {
+            assert!(cached.locate(failing).await.unwrap_err().to_string().contains("rate limited"));
+        }
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+
+        // Past the failure TTL (but within the success TTL) it is retried
+        let stale = Utc::now() - Duration::seconds(FAILED_LOOKUP_TTL_SECS + 1);
+        cached.cache.write().await.get_mut(&failing).unwrap().1 = stale;
+        assert!(cached.locate(failing).await.is_err());
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_parse_asn_and_private_ranges() {
+        assert_eq!(parse_asn("AS15169 Google LLC"), Some(15169));
+        assert_eq!(parse_asn("64500"), Some(64500));
+        assert_eq!(parse_asn(""), None);
+
+        assert!(is_private_ip(&"192.168.1.10".parse().unwrap()));
+        assert!(is_private_ip(&"100.64.3.1".parse().unwrap()));
+        assert!(is_private_ip(&"fd00::1".parse().unwrap()));
+        assert!(!is_private_ip(&"8.8.8.8".parse().unwrap()));
+    }
+}
@@ -0,0 +1,672 @@
+//! Guardian Quorum
+//! Destructive emergency responses (secure wipe and up) need M of N pinned
+//! guardian peers to approve. The request is signed and sent to every
+//! guardian; an approval signs the request's nonce and digest, so it can't
+//! be replayed against another request. An armed break-glass passphrase,
+//! entered at the machine, bypasses the quorum and is always audited
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+
+use crate::crypto::key_provider::KeyProvider;
+use crate::p2p::wire;
+use crate::units::HumanDuration;
+use crate::zerotrust::ZeroTrustContext;
+
+const REQUEST_SIGNING_CONTEXT: &[u8] = b"quantra-guardian-request-v1\0";
+const APPROVAL_SIGNING_CONTEXT: &[u8] = b"quantra-guardian-approval-v1\0";
+
+/// Approval category of requests from peers this node guards
+pub const GUARDIAN_APPROVAL: &str = "emergency.guardian";
+
+/// PBKDF2 rounds for `hash_passphrase`
+pub const PASSPHRASE_ITERATIONS: u32 = 600_000;
+const PASSPHRASE_SCHEME: &str = "pbkdf2-sha256";
+
+/// Decided nonces remembered, so late approvals are named as replays
+const SPENT_NONCES: usize = 1024;
+
+/// `[guardians]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuardianConfig {
+    /// Hex Ed25519 keys of the peers whose approval is needed
+    pub guardians: Vec<String>,
+    /// Distinct approvals needed; 0 turns the quorum off
+    pub threshold: usize,
+    /// How long approvals are collected before the response is downgraded
+    pub window: HumanDuration,
+    /// Hex Ed25519 keys of the peers this node guards; their requests wait
+    /// in the approval queue
+    pub wards: Vec<String>,
+    pub break_glass: BreakGlassConfig,
+}
+
+impl Default for GuardianConfig {
+    fn default() -> Self {
+        Self {
+            guardians: Vec::new(),
+            threshold: 0,
+            window: HumanDuration::from_secs(300),
+            wards: Vec::new(),
+            break_glass: BreakGlassConfig::default(),
+        }
+    }
+}
+
+impl GuardianConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.threshold > 0
+    }
+
+    pub fn ward_keys(&self) -> Result<Vec<VerifyingKey>> {
+        self.wards.iter().map(|key| parse_key(key)).collect()
+    }
+}
+
+/// `[guardians.break_glass]`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BreakGlassConfig {
+    /// Offer the passphrase prompt at all
+    pub armed: bool,
+    /// `pbkdf2-sha256$<iterations>$<salt>$<hash>`, from
+    /// `quantraband guardians hash-passphrase`
+    pub passphrase_hash: Option<String>,
+}
+
+/// What a node asks its guardians to approve
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmergencyApprovalRequest {
+    /// Hex key of the node asking
+    pub requester: String,
+    pub event_summary: String,
+    /// e.g. `SecureWipe`
+    pub proposed_response: String,
+    /// Hex; fresh per request
+    pub nonce: String,
+    pub requested_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl EmergencyApprovalRequest {
+    fn signing_bytes(&self) -> Result<Vec<u8>> {
+        wire::signing_bytes(REQUEST_SIGNING_CONTEXT, self)
+    }
+
+    pub fn requester_key(&self) -> Result<VerifyingKey> {
+        parse_key(&self.requester)
+    }
+
+    /// Hex SHA-256 of the canonical request
+    pub fn digest(&self) -> Result<String> {
+        Ok(hex::encode(Sha256::digest(self.signing_bytes()?)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedApprovalRequest {
+    pub request: EmergencyApprovalRequest,
+    /// Hex, by `requester`
+    pub signature: String,
+}
+
+impl SignedApprovalRequest {
+    pub fn sign(request: EmergencyApprovalRequest, signer: &dyn KeyProvider) -> Result<Self> {
+        let signature = signer.sign(&request.signing_bytes()?)?;
+        Ok(Self { request, signature: hex::encode(signature.to_bytes()) })
+    }
+
+    /// The requester's key, if it is one of `wards`, signed the request and
+    /// it hasn't expired
+    pub fn verify(&self, wards: &[VerifyingKey], now: DateTime<Utc>) -> Result<VerifyingKey> {
+        let key = self.request.requester_key()?;
+        if !wards.contains(&key) {
+            bail!("{} is not a ward of this node", self.request.requester);
+        }
+        let signature = parse_signature(&self.signature).context("Malformed request signature")?;
+        key.verify(&self.request.signing_bytes()?, &signature).context("Bad request signature")?;
+        if self.request.expires_at <= now {
+            bail!("Request {} expired at {}", self.request.nonce, self.request.expires_at);
+        }
+        Ok(key)
+    }
+}
+
+/// A guardian's signature over one request's nonce and digest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuardianApproval {
+    /// Hex key of the approving guardian
+    pub guardian: String,
+    pub nonce: String,
+    /// `EmergencyApprovalRequest::digest` of the approved request
+    pub request_digest: String,
+    pub signature: String,
+}
+
+impl GuardianApproval {
+    fn signing_bytes(nonce: &str, digest: &str) -> Result<Vec<u8>> {
+        wire::signing_bytes(APPROVAL_SIGNING_CONTEXT, &(nonce, digest))
+    }
+
+    /// Approve `request` as `signer`
+    pub fn sign(request: &EmergencyApprovalRequest, signer: &dyn KeyProvider) -> Result<Self> {
+        let request_digest = request.digest()?;
+        let signature = signer.sign(&Self::signing_bytes(&request.nonce, &request_digest)?)?;
+        Ok(Self {
+            guardian: hex::encode(signer.public_key().as_bytes()),
+            nonce: request.nonce.clone(),
+            request_digest,
+            signature: hex::encode(signature.to_bytes()),
+        })
+    }
+
+    fn verify(&self, key: &VerifyingKey) -> bool {
+        let (Some(signature), Ok(bytes)) =
+            (parse_signature(&self.signature), Self::signing_bytes(&self.nonce, &self.request_digest))
+        else {
+            return false;
+        };
+        key.verify(&bytes, &signature).is_ok()
+    }
+}
+
+/// For the node to deliver to the peer with the given key
+#[derive(Debug, Clone)]
+pub enum GuardianMessage {
+    Request(SignedApprovalRequest),
+    Approval(GuardianApproval),
+}
+
+pub type GuardianOutbox = mpsc::UnboundedSender<(VerifyingKey, GuardianMessage)>;
+
+/// Why an approval wasn't counted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApprovalRejection {
+    UnknownGuardian,
+    /// Its request was already decided
+    Replayed,
+    UnknownRequest,
+    /// Not a valid signature over the pending request
+    BadSignature,
+    /// The guardian already approved the request
+    Duplicate,
+}
+
+impl fmt::Display for ApprovalRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::UnknownGuardian => "not signed by a configured guardian",
+            Self::Replayed => "approval for a request already decided",
+            Self::UnknownRequest => "no such approval request",
+            Self::BadSignature => "signature does not cover the pending request",
+            Self::Duplicate => "guardian already approved this request",
+        })
+    }
+}
+
+impl std::error::Error for ApprovalRejection {}
+
+/// How a destructive response was decided
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Authorization {
+    /// Hex keys of the guardians that approved
+    Quorum(Vec<String>),
+    BreakGlass,
+    Denied { approvals: usize, threshold: usize },
+}
+
+/// Reads the break-glass passphrase from someone at the machine
+pub trait PassphrasePrompt: Send + Sync {
+    /// Blocks until a line is entered
+    fn read_passphrase(&self, prompt: &str) -> Result<String>;
+}
+
+/// The controlling terminal with echo off; never stdin, which may be piped
+pub struct TtyPrompt;
+
+impl PassphrasePrompt for TtyPrompt {
+    fn read_passphrase(&self, prompt: &str) -> Result<String> {
+        use std::io::{BufRead, Write};
+
+        let mut tty = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .context("No controlling terminal")?;
+        let stty = |flag: &str| -> std::io::Result<()> {
+            std::process::Command::new("stty").arg(flag).stdin(std::fs::File::open("/dev/tty")?).status().map(|_| ())
+        };
+        write!(tty, "{}", prompt)?;
+        tty.flush()?;
+        let _ = stty("-echo");
+        let mut line = String::new();
+        let read = std::io::BufReader::new(&tty).read_line(&mut line);
+        let _ = stty("echo");
+        writeln!(tty)?;
+        read?;
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+/// `pbkdf2-sha256$<iterations>$<salt hex>$<hash hex>` for `[guardians.break_glass]`
+pub fn hash_passphrase(passphrase: &str, iterations: u32) -> String {
+    let salt: [u8; 16] = rand::random();
+    let mut hash = [0u8; 32];
+    let rounds = NonZeroU32::new(iterations).unwrap_or(NonZeroU32::MIN);
+    ring::pbkdf2::derive(ring::pbkdf2::PBKDF2_HMAC_SHA256, rounds, &salt, passphrase.as_bytes(), &mut hash);
+    format!("{}${}${}${}", PASSPHRASE_SCHEME, rounds, hex::encode(salt), hex::encode(hash))
+}
+
+/// Whether `passphrase` matches a `hash_passphrase` string
+pub fn verify_passphrase(encoded: &str, passphrase: &str) -> Result<bool> {
+    let (iterations, salt, hash) = parse_passphrase_hash(encoded)?;
+    Ok(ring::pbkdf2::verify(ring::pbkdf2::PBKDF2_HMAC_SHA256, iterations, &salt, passphrase.as_bytes(), &hash).is_ok())
+}
+
+fn parse_passphrase_hash(encoded: &str) -> Result<(NonZeroU32, Vec<u8>, Vec<u8>)> {
+    let parts: Vec<&str> = encoded.split('$').collect();
+    let [scheme, iterations, salt, hash] = parts[..] else {
+        bail!("Malformed passphrase hash");
+    };
+    if scheme != PASSPHRASE_SCHEME {
+        bail!("Unsupported passphrase hash scheme {}", scheme);
+    }
+    let iterations: NonZeroU32 = iterations.parse().context("Malformed passphrase hash iterations")?;
+    let salt = hex::decode(salt).context("Malformed passphrase hash salt")?;
+    let hash = hex::decode(hash).context("Malformed passphrase hash")?;
+    Ok((iterations, salt, hash))
+}
+
+fn parse_key(hex_key: &str) -> Result<VerifyingKey> {
+    let bytes: [u8; 32] = hex::decode(hex_key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .with_context(|| format!("{} is not a hex Ed25519 key", hex_key))?;
+    VerifyingKey::from_bytes(&bytes).with_context(|| format!("{} is not a valid Ed25519 key", hex_key))
+}
+
+fn parse_signature(hex_signature: &str) -> Option<Signature> {
+    let bytes: [u8; 64] = hex::decode(hex_signature).ok()?.try_into().ok()?;
+    Some(Signature::from_bytes(&bytes))
+}
+
+struct PendingRequest {
+    digest: String,
+    approvers: HashSet<String>,
+    count: watch::Sender<usize>,
+}
+
+/// Asks the guardians and counts their approvals
+pub struct GuardianQuorum {
+    guardians: Vec<VerifyingKey>,
+    threshold: usize,
+    window: HumanDuration,
+    break_glass: BreakGlassConfig,
+    signer: Arc<dyn KeyProvider>,
+    outbox: GuardianOutbox,
+    prompt: Arc<dyn PassphrasePrompt>,
+    pending: Mutex<HashMap<String, PendingRequest>>,
+    spent: Mutex<VecDeque<String>>,
+    audit: Option<ZeroTrustContext>,
+}
+
+impl GuardianQuorum {
+    /// Requests are signed by `signer` and handed to `outbox` for delivery
+    pub fn new(config: &GuardianConfig, signer: Arc<dyn KeyProvider>, outbox: GuardianOutbox) -> Result<Self> {
+        let mut guardians: Vec<VerifyingKey> = Vec::new();
+        for key in &config.guardians {
+            let key = parse_key(key)?;
+            if !guardians.contains(&key) {
+                guardians.push(key);
+            }
+        }
+        if config.threshold > guardians.len() {
+            bail!("Guardian threshold {} exceeds the {} guardians configured", config.threshold, guardians.len());
+        }
+        if config.break_glass.armed {
+            let hash = config.break_glass.passphrase_hash.as_deref().context("Break glass is armed without a passphrase_hash")?;
+            parse_passphrase_hash(hash).context("Invalid break-glass passphrase_hash")?;
+        }
+        Ok(Self {
+            guardians,
+            threshold: config.threshold,
+            window: config.window,
+            break_glass: config.break_glass.clone(),
+            signer,
+            outbox,
+            prompt: Arc::new(TtyPrompt),
+            pending: Mutex::new(HashMap::new()),
+            spent: Mutex::new(VecDeque::new()),
+            audit: None,
+        })
+    }
+
+    /// Read the break-glass passphrase from `prompt` instead of the terminal
+    pub fn set_prompt(&mut self, prompt: Arc<dyn PassphrasePrompt>) {
+        self.prompt = prompt;
+    }
+
+    /// Record every decision and break-glass attempt
+    pub fn set_audit(&mut self, zt: ZeroTrustContext) {
+        self.audit = Some(zt);
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Ask every guardian to approve `response`, and wait up to the window
+    /// for the quorum, racing the break-glass prompt when armed
+    pub async fn authorize(&self, event_summary: &str, response: &str) -> Result<Authorization> {
+        let now = Utc::now();
+        let request = EmergencyApprovalRequest {
+            requester: hex::encode(self.signer.public_key().as_bytes()),
+            event_summary: event_summary.to_string(),
+            proposed_response: response.to_string(),
+            nonce: hex::encode(rand::random::<[u8; 32]>()),
+            requested_at: now,
+            expires_at: now + self.window.as_chrono(),
+        };
+        let signed = SignedApprovalRequest::sign(request, self.signer.as_ref())?;
+        let nonce = signed.request.nonce.clone();
+        let (count, mut counted) = watch::channel(0);
+        let pending = PendingRequest { digest: signed.request.digest()?, approvers: HashSet::new(), count };
+        self.pending.lock().insert(nonce.clone(), pending);
+
+        for guardian in &self.guardians {
+            let _ = self.outbox.send((*guardian, GuardianMessage::Request(signed.clone())));
+        }
+        tracing::warn!(
+            "🛡️ Asking {} guardian(s) to approve {}; {} needed within {}",
+            self.guardians.len(),
+            response,
+            self.threshold,
+            self.window
+        );
+
+        let threshold = self.threshold;
+        let broken = tokio::select! {
+            _ = counted.wait_for(|n| *n >= threshold) => false,
+            _ = tokio::time::sleep(self.window.as_std()) => false,
+            _ = self.break_glass(event_summary, response) => true,
+        };
+
+        let approvers = self.pending.lock().remove(&nonce).map(|p| p.approvers).unwrap_or_default();
+        self.spend(nonce.clone());
+        let approvals = approvers.len();
+        let authorization = match (broken, approvals) {
+            (true, _) => Authorization::BreakGlass,
+            (false, n) if n >= threshold => {
+                let mut approvers: Vec<String> = approvers.into_iter().collect();
+                approvers.sort();
+                Authorization::Quorum(approvers)
+            }
+            (false, approvals) => Authorization::Denied { approvals, threshold },
+        };
+        let outcome = match &authorization {
+            Authorization::Quorum(_) => "approved",
+            Authorization::BreakGlass => "break_glass",
+            Authorization::Denied { .. } => "denied",
+        };
+        self.audit(
+            "emergency_quorum",
+            &[
+                ("nonce", nonce),
+                ("response", response.to_string()),
+                ("outcome", outcome.to_string()),
+                ("approvals", approvals.to_string()),
+                ("threshold", threshold.to_string()),
+            ],
+        )
+        .await;
+        Ok(authorization)
+    }
+
+    /// Count `approval` towards its request; the approvals so far
+    pub fn submit(&self, approval: &GuardianApproval) -> Result<usize, ApprovalRejection> {
+        let key = parse_key(&approval.guardian)
+            .ok()
+            .filter(|key| self.guardians.contains(key))
+            .ok_or(ApprovalRejection::UnknownGuardian)?;
+        if !approval.verify(&key) {
+            return Err(ApprovalRejection::BadSignature);
+        }
+        let mut pending = self.pending.lock();
+        let Some(request) = pending.get_mut(&approval.nonce) else {
+            return Err(match self.spent.lock().contains(&approval.nonce) {
+                true => ApprovalRejection::Replayed,
+                false => ApprovalRejection::UnknownRequest,
+            });
+        };
+        if approval.request_digest != request.digest {
+            return Err(ApprovalRejection::BadSignature);
+        }
+        if !request.approvers.insert(hex::encode(key.as_bytes())) {
+            return Err(ApprovalRejection::Duplicate);
+        }
+        let approvals = request.approvers.len();
+        request.count.send_replace(approvals);
+        Ok(approvals)
+    }
+
+    fn spend(&self, nonce: String) {
+        let mut spent = self.spent.lock();
+        if spent.len() == SPENT_NONCES {
+            spent.pop_front();
+        }
+        spent.push_back(nonce);
+    }
+
+    /// Resolves once the right passphrase is entered; pending forever when
+    /// not armed or after a wrong one. A prompt still waiting when the
+    /// window closes leaves its blocking read behind until a line arrives
+    async fn break_glass(&self, event_summary: &str, response: &str) {
+        let hash = match (self.break_glass.armed, &self.break_glass.passphrase_hash) {
+            (true, Some(hash)) => hash.clone(),
+            _ => return std::future::pending().await,
+        };
+        let prompt = self.prompt.clone();
+        let text = format!("🛡️ BREAK GLASS: {} after {}. Passphrase: ", response, event_summary);
+        let entered = tokio::task::spawn_blocking(move || prompt.read_passphrase(&text)).await;
+        let outcome = match entered {
+            Ok(Ok(passphrase)) if verify_passphrase(&hash, &passphrase).unwrap_or(false) => "accepted",
+            Ok(Ok(_)) => "rejected",
+            Ok(Err(e)) => {
+                tracing::warn!("🛡️ Break-glass prompt unavailable: {:#}", e);
+                "unavailable"
+            }
+            Err(e) => {
+                tracing::warn!("🛡️ Break-glass prompt failed: {}", e);
+                "unavailable"
+            }
+        };
+        self.audit("emergency_break_glass", &[("response", response.to_string()), ("outcome", outcome.to_string())])
+            .await;
+        if outcome != "accepted" {
+            tracing::error!("🛡️ Break glass {}; waiting for the guardian quorum", outcome);
+            return std::future::pending().await;
+        }
+        tracing::error!("🛡️ BREAK GLASS: {} authorized at the machine, bypassing the guardian quorum", response);
+    }
+
+    async fn audit(&self, event_type: &str, details: &[(&str, String)]) {
+        let Some(zt) = &self.audit else { return };
+        let details = details.iter().map(|(key, value)| (key.to_string(), value.clone())).collect();
+        if let Err(e) = zt.log_local_event(event_type, details).await {
+            tracing::warn!("🛡️ Could not audit {}: {:#}", event_type, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::key_provider::FileKeyProvider;
+    use crate::storage::RuntimeMode;
+
+    struct FixedPrompt(&'static str);
+
+    impl PassphrasePrompt for FixedPrompt {
+        fn read_passphrase(&self, _prompt: &str) -> Result<String> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    type Requests = mpsc::UnboundedReceiver<(VerifyingKey, GuardianMessage)>;
+
+    fn quorum(threshold: usize, break_glass: BreakGlassConfig) -> (Arc<GuardianQuorum>, Vec<FileKeyProvider>, Requests) {
+        let guardians: Vec<FileKeyProvider> = (0..3).map(|_| FileKeyProvider::generate()).collect();
+        let config = GuardianConfig {
+            guardians: guardians.iter().map(|g| hex::encode(g.public_key().as_bytes())).collect(),
+            threshold,
+            window: HumanDuration::from_millis(300),
+            wards: Vec::new(),
+            break_glass,
+        };
+        let (outbox, requests) = mpsc::unbounded_channel();
+        let quorum = GuardianQuorum::new(&config, Arc::new(FileKeyProvider::generate()), outbox).unwrap();
+        (Arc::new(quorum), guardians, requests)
+    }
+
+    /// Each guardian's copy of the request, checked as a guardian would
+    async fn received(requests: &mut Requests, guardians: &[FileKeyProvider]) -> EmergencyApprovalRequest {
+        let mut request = None;
+        for _ in guardians {
+            let (_, GuardianMessage::Request(signed)) = requests.recv().await.unwrap() else { panic!("expected a request") };
+            let requester = parse_key(&signed.request.requester).unwrap();
+            signed.verify(&[requester], Utc::now()).unwrap();
+            request = Some(signed.request);
+        }
+        request.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_quorum_reached() {
+        let (quorum, guardians, mut requests) = quorum(2, BreakGlassConfig::default());
+        let asking = tokio::spawn({
+            let quorum = quorum.clone();
+            async move { quorum.authorize("UnauthorizedAccess from test", "SecureWipe").await.unwrap() }
+        });
+        let request = received(&mut requests, &guardians).await;
+        assert_eq!(request.proposed_response, "SecureWipe");
+
+        assert_eq!(quorum.submit(&GuardianApproval::sign(&request, &guardians[0]).unwrap()), Ok(1));
+        assert_eq!(quorum.submit(&GuardianApproval::sign(&request, &guardians[2]).unwrap()), Ok(2));
+        let Authorization::Quorum(approvers) = asking.await.unwrap() else { panic!("expected the quorum") };
+        assert_eq!(approvers.len(), 2);
+        assert!(approvers.contains(&hex::encode(guardians[2].public_key().as_bytes())));
+    }
+
+    #[tokio::test]
+    async fn test_insufficient_approvals_denied() {
+        let (quorum, guardians, mut requests) = quorum(2, BreakGlassConfig::default());
+        let asking = tokio::spawn({
+            let quorum = quorum.clone();
+            async move { quorum.authorize("HardwareEvent from test", "Shutdown").await.unwrap() }
+        });
+        let request = received(&mut requests, &guardians).await;
+        quorum.submit(&GuardianApproval::sign(&request, &guardians[1]).unwrap()).unwrap();
+
+        assert_eq!(asking.await.unwrap(), Authorization::Denied { approvals: 1, threshold: 2 });
+    }
+
+    #[tokio::test]
+    async fn test_approvals_bound_to_their_request() {
+        let (quorum, guardians, mut requests) = quorum(1, BreakGlassConfig::default());
+        let first = tokio::spawn({
+            let quorum = quorum.clone();
+            async move { quorum.authorize("first", "SecureWipe").await.unwrap() }
+        });
+        let request = received(&mut requests, &guardians).await;
+        let approval = GuardianApproval::sign(&request, &guardians[0]).unwrap();
+        quorum.submit(&approval).unwrap();
+        assert!(matches!(first.await.unwrap(), Authorization::Quorum(_)));
+
+        // The decided request's approval is a replay
+        assert_eq!(quorum.submit(&approval), Err(ApprovalRejection::Replayed));
+
+        let second = tokio::spawn({
+            let quorum = quorum.clone();
+            async move { quorum.authorize("second", "SecureWipe").await.unwrap() }
+        });
+        let next = received(&mut requests, &guardians).await;
+        // Moved onto the new nonce, the old signature no longer verifies
+        let moved = GuardianApproval { nonce: next.nonce.clone(), ..approval.clone() };
+        assert_eq!(quorum.submit(&moved), Err(ApprovalRejection::BadSignature));
+        // Signed by a key that isn't pinned
+        let stranger = GuardianApproval::sign(&next, &FileKeyProvider::generate()).unwrap();
+        assert_eq!(quorum.submit(&stranger), Err(ApprovalRejection::UnknownGuardian));
+        assert_eq!(second.await.unwrap(), Authorization::Denied { approvals: 0, threshold: 1 });
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_approval_counted_once() {
+        let (quorum, guardians, mut requests) = quorum(2, BreakGlassConfig::default());
+        let asking = tokio::spawn({
+            let quorum = quorum.clone();
+            async move { quorum.authorize("test", "FullWipe").await.unwrap() }
+        });
+        let request = received(&mut requests, &guardians).await;
+        let approval = GuardianApproval::sign(&request, &guardians[0]).unwrap();
+        assert_eq!(quorum.submit(&approval), Ok(1));
+        assert_eq!(quorum.submit(&approval), Err(ApprovalRejection::Duplicate));
+        assert_eq!(asking.await.unwrap(), Authorization::Denied { approvals: 1, threshold: 2 });
+    }
+
+    #[tokio::test]
+    async fn test_break_glass() {
+        let armed = BreakGlassConfig { armed: true, passphrase_hash: Some(hash_passphrase("in case of fire", 1000)) };
+        let zt = ZeroTrustContext::with_mode(RuntimeMode::Ephemeral).await.unwrap();
+
+        let (quorum, _requests) = quorum_with(armed.clone(), FixedPrompt("in case of fire"), &zt);
+        assert_eq!(quorum.authorize("test", "FullWipe").await.unwrap(), Authorization::BreakGlass);
+
+        // A wrong passphrase leaves the quorum to decide
+        let (quorum, _requests) = quorum_with(armed, FixedPrompt("wrong"), &zt);
+        assert_eq!(
+            quorum.authorize("test", "FullWipe").await.unwrap(),
+            Authorization::Denied { approvals: 0, threshold: 2 }
+        );
+
+        let attempts: Vec<String> = zt
+            .audit_events()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|event| event.event_type == "emergency_break_glass")
+            .filter_map(|event| event.details.get("outcome").cloned())
+            .collect();
+        assert_eq!(attempts, ["accepted", "rejected"]);
+    }
+
+    fn quorum_with(
+        break_glass: BreakGlassConfig,
+        prompt: FixedPrompt,
+        zt: &ZeroTrustContext,
+    ) -> (GuardianQuorum, Requests) {
+        let (quorum, _, requests) = quorum(2, break_glass);
+        let mut quorum = Arc::into_inner(quorum).unwrap();
+        quorum.set_prompt(Arc::new(prompt));
+        quorum.set_audit(zt.clone());
+        (quorum, requests)
+    }
+
+    #[test]
+    fn test_passphrase_hash_round_trip() {
+        let hash = hash_passphrase("correct horse", 1000);
+        assert!(hash.starts_with("pbkdf2-sha256$1000$"));
+        assert!(verify_passphrase(&hash, "correct horse").unwrap());
+        assert!(!verify_passphrase(&hash, "correct horse ").unwrap());
+        assert!(verify_passphrase("sha1$1$00$00", "x").is_err());
+    }
+}
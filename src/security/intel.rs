@@ -0,0 +1,534 @@
+//! Threat Intel Export
+//! Bait wallet accesses, Mirror Shield attacks and canary triggers are
+//! appended to a journal as they happen, and mapped to STIX 2.1 on export:
+//! an indicator per attacker IP, and an observed-data plus a sighting per
+//! event, all attributed to the node's identity object
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::security::bait_wallet::{BaitAccessEvent, CanaryTrigger};
+use crate::security::geo::is_private_ip;
+use crate::security::mirror_shield::AttackEvent;
+use crate::security::taxii::TaxiiConfig;
+
+/// UUIDv5 namespace of STIX cyber-observable IDs (STIX 2.1 §2.9)
+const SCO_NAMESPACE: Uuid = Uuid::from_u128(0x00abedb4_aa42_5b50_a23b_2d5f6e3d8f28);
+
+/// UUIDv5 namespace of the domain objects this node derives from event IDs,
+/// so re-exporting an event yields the same objects
+const QUANTRA_NAMESPACE: Uuid = Uuid::from_u128(0x6f1c2a4e_93d5_4b7e_8a61_2c0f5e9b7d34);
+
+/// Indicator confidence for attack events without a recorded threat score
+const DEFAULT_CONFIDENCE: u8 = 50;
+
+/// `[intel]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IntelConfig {
+    /// Journal bait, shield and canary events while `p2p` runs
+    pub enabled: bool,
+    /// Name of the identity object exported events are attributed to
+    pub identity_name: String,
+    /// STIX identity class, e.g. `system` or `organization`
+    pub identity_class: String,
+    /// Export events from private, loopback and link-local sources too
+    pub include_private: bool,
+    /// Indicator confidence for bait and canary hits, which have no threat score
+    pub bait_confidence: u8,
+    pub taxii: TaxiiConfig,
+}
+
+impl Default for IntelConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            identity_name: "Quantra node".to_string(),
+            identity_class: "system".to_string(),
+            include_private: false,
+            bait_confidence: 90,
+            taxii: TaxiiConfig::default(),
+        }
+    }
+}
+
+/// One journaled honeypot event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum IntelRecord {
+    BaitAccess(BaitAccessEvent),
+    Attack(AttackEvent),
+    Canary(CanaryTrigger),
+}
+
+impl IntelRecord {
+    /// The original event's ID
+    pub fn id(&self) -> &str {
+        match self {
+            Self::BaitAccess(e) => &e.id,
+            Self::Attack(e) => &e.id,
+            Self::Canary(e) => &e.id,
+        }
+    }
+
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        match self {
+            Self::BaitAccess(e) => e.timestamp,
+            Self::Attack(e) => e.timestamp,
+            Self::Canary(e) => e.timestamp,
+        }
+    }
+
+    pub fn source_ip(&self) -> &str {
+        match self {
+            Self::BaitAccess(e) => &e.attacker_ip,
+            Self::Attack(e) => &e.source_ip,
+            Self::Canary(e) => &e.source_ip,
+        }
+    }
+
+    /// Subsystem that recorded the event (the serde tag)
+    pub fn source(&self) -> &'static str {
+        match self {
+            Self::BaitAccess(_) => "bait_access",
+            Self::Attack(_) => "attack",
+            Self::Canary(_) => "canary",
+        }
+    }
+
+    /// Mirror Shield's threat score at the time of the attack; bait and
+    /// canary hits get `bait_confidence`, as nobody touches them by accident
+    pub fn confidence(&self, bait_confidence: u8) -> u8 {
+        match self {
+            Self::Attack(e) => e
+                .details
+                .get("threat_score")
+                .and_then(|score| score.parse::<f64>().ok())
+                .map(|score| score.round().clamp(0.0, 100.0) as u8)
+                .unwrap_or(DEFAULT_CONFIDENCE),
+            Self::BaitAccess(_) | Self::Canary(_) => bait_confidence.min(100),
+        }
+    }
+}
+
+/// Append-only JSON-lines journal of `IntelRecord`s
+pub struct IntelJournal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl IntelJournal {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open intel journal {}", path.display()))?;
+        Ok(Self { path: path.to_path_buf(), file: Mutex::new(file) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append one record. Failures are logged; detection never waits on
+    /// or fails because of the export path
+    pub fn append(&self, record: &IntelRecord) {
+        let result = serde_json::to_string(record)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| Ok(writeln!(self.file.lock(), "{}", line)?));
+        if let Err(e) = result {
+            tracing::warn!("⚠️  Failed to journal {} event {}: {}", record.source(), record.id(), e);
+        }
+    }
+
+    /// Records from byte `offset` on; see `read_journal`
+    pub fn read_from(&self, offset: u64) -> Result<(Vec<IntelRecord>, u64)> {
+        read_journal(&self.path, offset)
+    }
+}
+
+/// Complete records in the journal at `path` from byte `offset` on, and the
+/// offset after the last complete line. A missing journal is empty; a line
+/// that doesn't parse is skipped with a warning
+pub fn read_journal(path: &Path, offset: u64) -> Result<(Vec<IntelRecord>, u64)> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), offset)),
+        Err(e) => return Err(e).with_context(|| format!("Failed to open intel journal {}", path.display())),
+    };
+    file.seek(SeekFrom::Start(offset))?;
+    let mut reader = BufReader::new(file);
+    let mut records = Vec::new();
+    let mut end = offset;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        // A line without its newline is still being written
+        if read == 0 || !line.ends_with('\n') {
+            break;
+        }
+        end += read as u64;
+        match serde_json::from_str(line.trim_end()) {
+            Ok(record) => records.push(record),
+            Err(e) => tracing::warn!("⚠️  Skipping unreadable intel journal line at byte {}: {}", end - read as u64, e),
+        }
+    }
+    Ok((records, end))
+}
+
+/// What an export contained and left out
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportSummary {
+    /// Journaled events considered
+    pub events: usize,
+    pub exported: usize,
+    pub excluded_private: usize,
+    /// Sources that aren't IP addresses (and so can't be an indicator)
+    pub unparseable: usize,
+    pub indicators: usize,
+    pub objects: usize,
+}
+
+impl fmt::Display for ExportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "📤 {} of {} event(s) exported: {} indicator(s), {} STIX object(s)",
+            self.exported, self.events, self.indicators, self.objects
+        )?;
+        if self.excluded_private > 0 {
+            writeln!(f, "   {} from private addresses excluded (--include-private)", self.excluded_private)?;
+        }
+        if self.unparseable > 0 {
+            writeln!(f, "   {} without an IP source skipped", self.unparseable)?;
+        }
+        Ok(())
+    }
+}
+
+/// Per-IP indicator, folded over the IP's events
+struct IndicatorState {
+    id: String,
+    first: DateTime<Utc>,
+    last: DateTime<Utc>,
+    confidence: u8,
+    event_ids: Vec<String>,
+}
+
+/// Maps journaled events to STIX 2.1 objects
+#[derive(Debug, Clone)]
+pub struct StixExporter {
+    identity_name: String,
+    identity_class: String,
+    include_private: bool,
+    bait_confidence: u8,
+}
+
+impl StixExporter {
+    pub fn new(config: &IntelConfig) -> Self {
+        Self {
+            identity_name: config.identity_name.clone(),
+            identity_class: config.identity_class.clone(),
+            include_private: config.include_private,
+            bait_confidence: config.bait_confidence,
+        }
+    }
+
+    pub fn include_private(mut self, include: bool) -> Self {
+        self.include_private = include;
+        self
+    }
+
+    pub fn identity_id(&self) -> String {
+        sdo_id("identity", &self.identity_name)
+    }
+
+    /// The node's identity object. Its timestamps are fixed so every export
+    /// carries the same version of it
+    pub fn identity(&self) -> Value {
+        let epoch = stix_time(DateTime::from_timestamp(0, 0).expect("the epoch is a valid time"));
+        json!({
+            "type": "identity",
+            "spec_version": "2.1",
+            "id": self.identity_id(),
+            "created": epoch,
+            "modified": epoch,
+            "name": self.identity_name,
+            "identity_class": self.identity_class,
+        })
+    }
+
+    /// Identity, observables, indicators, observed-data and sightings, in
+    /// that order, so references point backwards
+    pub fn objects(&self, records: &[IntelRecord]) -> (Vec<Value>, ExportSummary) {
+        let identity_id = self.identity_id();
+        let mut summary = ExportSummary { events: records.len(), ..Default::default() };
+        let mut observables = BTreeMap::new();
+        let mut indicators: BTreeMap<IpAddr, IndicatorState> = BTreeMap::new();
+        let mut observed = Vec::new();
+        let mut sightings = Vec::new();
+
+        for record in records {
+            let ip = match record.source_ip().parse::<IpAddr>() {
+                Ok(ip) => ip,
+                Err(_) => {
+                    summary.unparseable += 1;
+                    continue;
+                }
+            };
+            if is_private_ip(&ip) && !self.include_private {
+                summary.excluded_private += 1;
+                continue;
+            }
+            summary.exported += 1;
+
+            let (sco_type, sco_id) = observable(ip);
+            observables.entry(ip).or_insert_with(|| {
+                json!({ "type": sco_type, "spec_version": "2.1", "id": sco_id, "value": ip.to_string() })
+            });
+
+            let at = record.timestamp();
+            let confidence = record.confidence(self.bait_confidence);
+            let indicator = indicators.entry(ip).or_insert_with(|| IndicatorState {
+                id: sdo_id("indicator", &ip.to_string()),
+                first: at,
+                last: at,
+                confidence,
+                event_ids: Vec::new(),
+            });
+            indicator.first = indicator.first.min(at);
+            indicator.last = indicator.last.max(at);
+            indicator.confidence = indicator.confidence.max(confidence);
+            indicator.event_ids.push(record.id().to_string());
+
+            let observed_id = sdo_id("observed-data", record.id());
+            let at = stix_time(at);
+            observed.push(json!({
+                "type": "observed-data",
+                "spec_version": "2.1",
+                "id": observed_id,
+                "created": at,
+                "modified": at,
+                "created_by_ref": identity_id,
+                "first_observed": at,
+                "last_observed": at,
+                "number_observed": 1,
+                "object_refs": [sco_id],
+                "x_quantra_event_id": record.id(),
+                "x_quantra_source": record.source(),
+                "x_quantra_event": record,
+            }));
+            sightings.push(json!({
+                "type": "sighting",
+                "spec_version": "2.1",
+                "id": sdo_id("sighting", record.id()),
+                "created": at,
+                "modified": at,
+                "created_by_ref": identity_id,
+                "first_seen": at,
+                "last_seen": at,
+                "count": 1,
+                "sighting_of_ref": indicator.id,
+                "observed_data_refs": [observed_id],
+                "where_sighted_refs": [identity_id],
+                "x_quantra_event_id": record.id(),
+            }));
+        }
+
+        summary.indicators = indicators.len();
+        let mut objects = vec![self.identity()];
+        objects.extend(observables.into_values());
+        objects.extend(indicators.into_iter().map(|(ip, state)| {
+            let (sco_type, _) = observable(ip);
+            json!({
+                "type": "indicator",
+                "spec_version": "2.1",
+                "id": state.id,
+                "created": stix_time(state.first),
+                "modified": stix_time(state.last),
+                "created_by_ref": identity_id,
+                "name": format!("Honeypot contact from {}", ip),
+                "indicator_types": ["malicious-activity"],
+                "pattern": format!("[{}:value = '{}']", sco_type, ip),
+                "pattern_type": "stix",
+                "valid_from": stix_time(state.first),
+                "confidence": state.confidence,
+                "x_quantra_event_ids": state.event_ids,
+            })
+        }));
+        objects.extend(observed);
+        objects.extend(sightings);
+        summary.objects = objects.len();
+        (objects, summary)
+    }
+
+    /// A STIX bundle of `records`
+    pub fn bundle(&self, records: &[IntelRecord]) -> (Value, ExportSummary) {
+        let (objects, summary) = self.objects(records);
+        let bundle = json!({
+            "type": "bundle",
+            "id": format!("bundle--{}", Uuid::new_v4()),
+            "objects": objects,
+        });
+        (bundle, summary)
+    }
+}
+
+/// Type and ID of the `ipv4-addr` / `ipv6-addr` observable for `ip`. The
+/// ID is the spec's UUIDv5 over `{"value": ...}`, so other producers
+/// reporting the same address agree on it
+fn observable(ip: IpAddr) -> (&'static str, String) {
+    let sco_type = if ip.is_ipv4() { "ipv4-addr" } else { "ipv6-addr" };
+    let key = json!({ "value": ip.to_string() }).to_string();
+    (sco_type, format!("{}--{}", sco_type, Uuid::new_v5(&SCO_NAMESPACE, key.as_bytes())))
+}
+
+/// `<type>--<uuid>` derived from `key`
+fn sdo_id(object_type: &str, key: &str) -> String {
+    format!("{}--{}", object_type, Uuid::new_v5(&QUANTRA_NAMESPACE, format!("{}:{}", object_type, key).as_bytes()))
+}
+
+/// STIX timestamps: UTC with millisecond precision
+fn stix_time(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::bait_wallet::{AccessType, CanaryType, WalletType};
+    use crate::security::mirror_shield::AttackType;
+    use std::collections::HashMap;
+
+    const STIX_SCHEMA: &str = include_str!("../../tests/stix/bundle.schema.json");
+
+    fn bait(id: &str, ip: &str) -> IntelRecord {
+        IntelRecord::BaitAccess(BaitAccessEvent {
+            id: id.to_string(),
+            timestamp: Utc::now(),
+            wallet_id: "w-1".to_string(),
+            wallet_type: WalletType::Ethereum,
+            attacker_ip: ip.to_string(),
+            attacker_location: None,
+            user_agent: Some("curl/8.0".to_string()),
+            access_type: AccessType::KeyExport,
+            transaction_attempted: false,
+            alert_sent: true,
+        })
+    }
+
+    fn attack(id: &str, ip: &str, score: f64) -> IntelRecord {
+        IntelRecord::Attack(AttackEvent {
+            id: id.to_string(),
+            timestamp: Utc::now(),
+            attack_type: AttackType::BruteForce,
+            source_ip: ip.to_string(),
+            source_peer: Some("12D3KooWAttacker".to_string()),
+            payload_hash: "00ff".to_string(),
+            reflected: false,
+            details: HashMap::from([("threat_score".to_string(), format!("{:.1}", score))]),
+        })
+    }
+
+    fn canary(id: &str, ip: &str) -> IntelRecord {
+        IntelRecord::Canary(CanaryTrigger {
+            id: id.to_string(),
+            timestamp: Utc::now(),
+            token_id: "t-1".to_string(),
+            token_type: CanaryType::AwsCredentials,
+            source_ip: ip.to_string(),
+            user_agent: None,
+        })
+    }
+
+    #[test]
+    fn test_bundle_validates_against_stix_schema() {
+        let schema: Value = serde_json::from_str(STIX_SCHEMA).unwrap();
+        let schema = jsonschema::JSONSchema::compile(&schema).unwrap();
+        let records = vec![
+            bait("b-1", "203.0.113.7"),
+            attack("a-1", "203.0.113.7", 82.4),
+            attack("a-2", "2001:db8::7", 40.0),
+            canary("c-1", "198.51.100.20"),
+        ];
+
+        let (bundle, summary) = StixExporter::new(&IntelConfig::default()).bundle(&records);
+        if let Err(errors) = schema.validate(&bundle) {
+            panic!("{}", errors.map(|e| format!("{} at {}", e, e.instance_path)).collect::<Vec<_>>().join("\n"));
+        }
+        assert_eq!(summary.indicators, 3);
+        // identity + 3 observables + 3 indicators + 4 observed-data + 4 sightings
+        assert_eq!(summary.objects, 15);
+
+        let objects = bundle["objects"].as_array().unwrap();
+        let indicator = objects
+            .iter()
+            .find(|o| o["type"] == "indicator" && o["pattern"] == "[ipv4-addr:value = '203.0.113.7']")
+            .unwrap();
+        // The bait hit outranks the shield's score of 82
+        assert_eq!(indicator["confidence"], 90);
+        assert_eq!(indicator["x_quantra_event_ids"], json!(["b-1", "a-1"]));
+
+        // Original events survive the mapping
+        let observed = objects.iter().find(|o| o["x_quantra_event_id"] == "a-1" && o["type"] == "observed-data").unwrap();
+        let original: IntelRecord = serde_json::from_value(observed["x_quantra_event"].clone()).unwrap();
+        assert!(matches!(original, IntelRecord::Attack(ref e) if e.source_peer.as_deref() == Some("12D3KooWAttacker")));
+
+        // Re-exporting yields the same object IDs
+        let (again, _) = StixExporter::new(&IntelConfig::default()).bundle(&records);
+        let ids = |b: &Value| b["objects"].as_array().unwrap().iter().map(|o| o["id"].clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&bundle), ids(&again));
+    }
+
+    #[test]
+    fn test_private_sources_excluded_by_default() {
+        let records = vec![
+            bait("b-1", "192.168.1.100"),
+            attack("a-1", "10.0.0.5", 90.0),
+            attack("a-2", "127.0.0.1", 90.0),
+            canary("c-1", "fd00::1"),
+            canary("c-2", "exit.tor"),
+            attack("a-3", "203.0.113.9", 60.0),
+        ];
+
+        let (bundle, summary) = StixExporter::new(&IntelConfig::default()).bundle(&records);
+        assert_eq!((summary.exported, summary.excluded_private, summary.unparseable), (1, 4, 1));
+        let text = bundle.to_string();
+        for ip in ["192.168.1.100", "10.0.0.5", "127.0.0.1", "fd00::1"] {
+            assert!(!text.contains(ip), "{} leaked into the bundle", ip);
+        }
+
+        let (_, summary) = StixExporter::new(&IntelConfig::default()).include_private(true).bundle(&records);
+        assert_eq!((summary.exported, summary.indicators), (5, 5));
+    }
+
+    #[test]
+    fn test_journal_reads_complete_lines_from_offset() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("intel.jsonl");
+        let journal = IntelJournal::open(&path).unwrap();
+        journal.append(&bait("b-1", "203.0.113.7"));
+        journal.append(&canary("c-1", "203.0.113.8"));
+
+        let (records, offset) = journal.read_from(0).unwrap();
+        assert_eq!(records.iter().map(IntelRecord::id).collect::<Vec<_>>(), ["b-1", "c-1"]);
+
+        // A torn write isn't consumed until its newline lands
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"source\":\"ca").unwrap();
+        let (records, end) = journal.read_from(offset).unwrap();
+        assert!(records.is_empty());
+        assert_eq!(end, offset);
+    }
+}
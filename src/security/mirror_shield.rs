@@ -9,6 +9,15 @@ use chrono::{DateTime, Utc, Duration};
 use serde::{Serialize, Deserialize};
 use sha2::{Sha256, Digest};
 
+use crate::security::blocklist::{Blocklist, DenyEntry};
+use crate::security::intel::{IntelJournal, IntelRecord};
+use crate::security::notifications::{NotificationRouter, SinkEvent};
+
+/// Unblocked attacker profiles quiet for this long are forgotten
+const ATTACKER_IDLE_TTL: Duration = Duration::hours(24);
+/// Attack events older than this are dropped from the log
+const ATTACK_LOG_RETENTION: Duration = Duration::days(7);
+
 /// Attack types that can be detected and reflected
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum AttackType {
@@ -30,6 +39,19 @@ pub enum AttackType {
     IdentitySpoofing,
 }
 
+impl AttackType {
+    pub const ALL: [AttackType; 8] = [
+        AttackType::ConnectionFlood,
+        AttackType::MessageSpam,
+        AttackType::MalformedPacket,
+        AttackType::PortScan,
+        AttackType::BruteForce,
+        AttackType::DDoSAmplification,
+        AttackType::ProtocolAbuse,
+        AttackType::IdentitySpoofing,
+    ];
+}
+
 /// Tracked attacker information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AttackerProfile {
@@ -42,11 +64,17 @@ pub struct AttackerProfile {
     pub threat_score: f64,  // 0-100
     pub reflected_count: u64,
     pub blocked: bool,
+    /// Accumulated low-weight evidence (e.g. geo policy denials)
+    #[serde(default)]
+    pub evidence_score: f64,
 }
 
 /// Attack event for logging
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AttackEvent {
+    /// Stable ID for correlating exports with the local log
+    #[serde(default)]
+    pub id: String,
     pub timestamp: DateTime<Utc>,
     pub attack_type: AttackType,
     pub source_ip: String,
@@ -70,6 +98,12 @@ pub struct MirrorShield {
     config: ShieldConfig,
     /// Shield active
     active: bool,
+    /// Outbound notifications for blocks
+    notifier: Option<Arc<NotificationRouter>>,
+    /// Compiled blocked IPs, networks and peers for lock-free admission checks
+    blocklist: Arc<Blocklist>,
+    /// Threat intel journal for STIX export
+    intel: Option<Arc<IntelJournal>>,
 }
 
 /// Shield configuration
@@ -121,15 +155,46 @@ impl MirrorShield {
             message_attempts: Arc::new(RwLock::new(HashMap::new())),
             config,
             active: true,
+            notifier: None,
+            blocklist: Arc::new(Blocklist::new()),
+            intel: None,
         }
     }
 
+    /// Report blocks through the notification router
+    pub fn set_notifier(&mut self, notifier: Arc<NotificationRouter>) {
+        self.notifier = Some(notifier);
+    }
+
+    /// Append attack events to the threat intel journal
+    pub fn set_intel_journal(&mut self, journal: Arc<IntelJournal>) {
+        self.intel = Some(journal);
+    }
+
+    /// Blocked IPs, networks and peers, shared with the admission path
+    pub fn blocklist(&self) -> Arc<Blocklist> {
+        self.blocklist.clone()
+    }
+
+    /// Whether the address or peer is blocked; never waits on a lock
+    pub fn is_blocked(&self, ip: Option<std::net::IpAddr>, peer_id: Option<&str>) -> bool {
+        self.active && self.blocklist.denies(ip, peer_id)
+    }
+
     /// Check incoming connection for attack patterns
     pub async fn check_connection(&self, ip: &str, peer_id: Option<&str>) -> Result<ShieldDecision> {
         if !self.active {
             return Ok(ShieldDecision::Allow);
         }
 
+        // Already blocked: reject without tracking the attempt
+        if self.blocklist.denies(ip.parse().ok(), peer_id) {
+            return Ok(ShieldDecision::Block {
+                reason: format!("{} is blocked", ip),
+                reflect: false,
+            });
+        }
+
         let now = Utc::now();
         let mut attempts = self.connection_attempts.write().await;
 
@@ -217,6 +282,14 @@ impl MirrorShield {
         Ok(ShieldDecision::Allow)
     }
 
+    /// A request that failed validation after decoding
+    pub async fn report_malformed(&self, ip: &str, peer_id: Option<&str>, reason: &str) -> Result<ShieldDecision> {
+        if !self.active {
+            return Ok(ShieldDecision::Allow);
+        }
+        self.handle_attack(ip, peer_id, AttackType::MalformedPacket, format!("Invalid request: {}", reason)).await
+    }
+
     /// Check for brute force attempts
     pub async fn check_auth_attempt(
         &self,
@@ -237,6 +310,7 @@ impl MirrorShield {
                     threat_score: 0.0,
                     reflected_count: 0,
                     blocked: false,
+                    evidence_score: 0.0,
                 }
             });
 
@@ -281,6 +355,7 @@ impl MirrorShield {
                 threat_score: 0.0,
                 reflected_count: 0,
                 blocked: false,
+                evidence_score: 0.0,
             }
         });
 
@@ -296,10 +371,14 @@ impl MirrorShield {
 
         let should_block = profile.threat_score >= self.config.block_threshold;
         let should_reflect = self.config.reflection_enabled && !profile.blocked;
+        let newly_blocked = should_block && !profile.blocked;
 
         if should_block {
             profile.blocked = true;
         }
+        if newly_blocked {
+            self.blocklist.block_all(ip.parse::<DenyEntry>().ok(), profile.peer_id.clone());
+        }
 
         let threat_score = profile.threat_score;
         let reflected_count = profile.reflected_count;
@@ -307,6 +386,7 @@ impl MirrorShield {
 
         // Log attack event
         let event = AttackEvent {
+            id: uuid::Uuid::new_v4().to_string(),
             timestamp: now,
             attack_type: attack_type.clone(),
             source_ip: ip.to_string(),
@@ -325,6 +405,9 @@ impl MirrorShield {
             },
         };
 
+        if let Some(journal) = &self.intel {
+            journal.append(&IntelRecord::Attack(event.clone()));
+        }
         self.attack_log.write().await.push(event);
 
         // Log to console
@@ -341,6 +424,14 @@ impl MirrorShield {
 
         if should_block {
             tracing::error!("🚫 BLOCKED: {} (threat score: {:.0})", ip, threat_score);
+            if let (true, Some(notifier)) = (newly_blocked, &self.notifier) {
+                notifier.notify(SinkEvent::ShieldBlock {
+                    ip: ip.to_string(),
+                    peer_id: peer_id.map(String::from),
+                    attack_type: format!("{:?}", attack_type),
+                    threat_score,
+                });
+            }
             Ok(ShieldDecision::Block {
                 reason: format!("{:?} - Threat score: {:.0}", attack_type, threat_score),
                 reflect: should_reflect,
@@ -424,9 +515,35 @@ impl MirrorShield {
         Ok(())
     }
 
+    /// Record low-weight evidence against an IP without treating it as an attack
+    pub async fn record_evidence(&self, ip: &str, peer_id: Option<&str>, weight: f64, details: &str) {
+        let now = Utc::now();
+        let mut attackers = self.attackers.write().await;
+        let profile = attackers.entry(ip.to_string()).or_insert_with(|| {
+            AttackerProfile {
+                ip: ip.to_string(),
+                peer_id: peer_id.map(String::from),
+                first_seen: now,
+                last_seen: now,
+                attack_count: 0,
+                attack_types: Vec::new(),
+                threat_score: 0.0,
+                reflected_count: 0,
+                blocked: false,
+                evidence_score: 0.0,
+            }
+        });
+
+        profile.last_seen = now;
+        profile.evidence_score = (profile.evidence_score + weight).min(25.0);
+        profile.threat_score = self.calculate_threat_score(profile);
+
+        tracing::debug!("🛡️ Evidence recorded for {}: {} (score: {:.0})", ip, details, profile.threat_score);
+    }
+
     /// Calculate threat score for an attacker
     fn calculate_threat_score(&self, profile: &AttackerProfile) -> f64 {
-        let mut score = 0.0;
+        let mut score = profile.evidence_score;
 
         // Base score from attack count
         score += (profile.attack_count as f64).min(50.0);
@@ -454,6 +571,16 @@ impl MirrorShield {
         score.min(100.0)
     }
 
+    /// Number of attacks of `attack_type` logged since `since`
+    pub async fn attacks_since(&self, attack_type: &AttackType, since: DateTime<Utc>) -> usize {
+        self.attack_log
+            .read()
+            .await
+            .iter()
+            .filter(|e| e.attack_type == *attack_type && e.timestamp >= since)
+            .count()
+    }
+
     /// Get current shield statistics
     pub async fn get_stats(&self) -> ShieldStats {
         let attackers = self.attackers.read().await;
@@ -477,6 +604,59 @@ impl MirrorShield {
         }
     }
 
+    /// Attacker profiles tied to a peer ID or one of its IPs
+    pub async fn profiles_for(&self, peer_id: &str, ips: &[String]) -> Vec<AttackerProfile> {
+        self.attackers
+            .read()
+            .await
+            .values()
+            .filter(|p| p.peer_id.as_deref() == Some(peer_id) || ips.contains(&p.ip))
+            .cloned()
+            .collect()
+    }
+
+    /// Attack events from a peer ID or one of its IPs, oldest first
+    pub async fn timeline_for(&self, peer_id: &str, ips: &[String]) -> Vec<AttackEvent> {
+        self.attack_log
+            .read()
+            .await
+            .iter()
+            .filter(|e| e.source_peer.as_deref() == Some(peer_id) || ips.contains(&e.source_ip))
+            .cloned()
+            .collect()
+    }
+
+    /// Forget stale rate windows, quiet unblocked attackers and old attack
+    /// events; blocked IPs are kept. Returns how many entries were dropped
+    pub async fn prune(&self) -> usize {
+        self.prune_at(Utc::now()).await
+    }
+
+    async fn prune_at(&self, now: DateTime<Utc>) -> usize {
+        let mut dropped = 0;
+        for (attempts, window) in [
+            (&self.connection_attempts, Duration::minutes(1)),
+            (&self.message_attempts, Duration::seconds(1)),
+        ] {
+            let mut attempts = attempts.write().await;
+            let before = attempts.len();
+            attempts.retain(|_, times| times.iter().any(|t| now.signed_duration_since(*t) < window));
+            dropped += before - attempts.len();
+        }
+
+        let mut attackers = self.attackers.write().await;
+        let before = attackers.len();
+        attackers.retain(|_, a| a.blocked || now.signed_duration_since(a.last_seen) < ATTACKER_IDLE_TTL);
+        dropped += before - attackers.len();
+        drop(attackers);
+
+        let mut attack_log = self.attack_log.write().await;
+        let before = attack_log.len();
+        attack_log.retain(|e| now.signed_duration_since(e.timestamp) < ATTACK_LOG_RETENTION);
+        dropped += before - attack_log.len();
+        dropped
+    }
+
     /// Get all blocked IPs
     pub async fn get_blocked_ips(&self) -> Vec<String> {
         self.attackers
@@ -488,7 +668,7 @@ impl MirrorShield {
             .collect()
     }
 
-    /// Manually block an IP
+    /// Manually block an IP or CIDR network
     pub async fn block_ip(&self, ip: &str) {
         let mut attackers = self.attackers.write().await;
         let profile = attackers.entry(ip.to_string()).or_insert_with(|| {
@@ -502,10 +682,17 @@ impl MirrorShield {
                 threat_score: 100.0,
                 reflected_count: 0,
                 blocked: false,
+                evidence_score: 0.0,
             }
         });
         profile.blocked = true;
         profile.threat_score = 100.0;
+        match ip.parse::<DenyEntry>() {
+            Ok(entry) => {
+                self.blocklist.block(entry);
+            }
+            Err(e) => tracing::warn!("🛡️ {}; profile blocked but not enforced", e),
+        }
         tracing::warn!("🚫 Manually blocked IP: {}", ip);
     }
 
@@ -515,9 +702,28 @@ impl MirrorShield {
         if let Some(profile) = attackers.get_mut(ip) {
             profile.blocked = false;
             profile.threat_score = 0.0;
+            if let Ok(entry) = ip.parse::<DenyEntry>() {
+                self.blocklist.unblock(&entry);
+            }
+            if let Some(peer_id) = &profile.peer_id {
+                self.blocklist.unblock_peer(peer_id);
+            }
             tracing::info!("✅ Unblocked IP: {}", ip);
         }
     }
+
+    /// Block a peer ID wherever it connects from
+    pub fn block_peer(&self, peer_id: &str) {
+        if self.blocklist.block_peer(peer_id) {
+            tracing::warn!("🚫 Blocked peer: {}", peer_id);
+        }
+    }
+
+    pub fn unblock_peer(&self, peer_id: &str) {
+        if self.blocklist.unblock_peer(peer_id) {
+            tracing::info!("✅ Unblocked peer: {}", peer_id);
+        }
+    }
 }
 
 /// Shield decision
@@ -603,4 +809,42 @@ mod tests {
         assert!(stats.reflected_attacks > 0);
         println!("✅ Attack reflection test PASSED!");
     }
+
+    #[tokio::test]
+    async fn test_prune_keeps_blocked_ips() {
+        let shield = MirrorShield::new();
+        shield.check_connection("10.0.0.1", None).await.unwrap();
+        shield.record_evidence("10.0.0.2", None, 5.0, "geo denial").await;
+        shield.block_ip("10.0.0.3").await;
+        assert_eq!(shield.prune().await, 0);
+
+        let later = Utc::now() + ATTACKER_IDLE_TTL + Duration::minutes(1);
+        // The stale connection window and the quiet 10.0.0.2 profile
+        assert_eq!(shield.prune_at(later).await, 2);
+        assert_eq!(shield.get_blocked_ips().await, vec!["10.0.0.3".to_string()]);
+        assert_eq!(shield.get_stats().await.unique_attackers, 1);
+    }
+
+    #[tokio::test]
+    async fn test_blocks_are_enforced_by_blocklist() {
+        let shield = MirrorShield::new();
+        shield.block_ip("203.0.113.0/24").await;
+        assert!(shield.is_blocked("203.0.113.9".parse().ok(), None));
+        assert!(matches!(
+            shield.check_connection("203.0.113.9", None).await.unwrap(),
+            ShieldDecision::Block { .. }
+        ));
+
+        // Auto-blocks cover both the attacker's IP and its peer ID
+        for attack in [AttackType::IdentitySpoofing, AttackType::DDoSAmplification, AttackType::BruteForce] {
+            shield.handle_attack("10.0.0.1", Some("bad_peer"), attack, "test".to_string()).await.unwrap();
+        }
+        assert!(shield.is_blocked("10.0.0.1".parse().ok(), None));
+        assert!(shield.is_blocked("10.9.9.9".parse().ok(), Some("bad_peer")));
+
+        shield.unblock_ip("10.0.0.1").await;
+        assert!(!shield.is_blocked("10.0.0.1".parse().ok(), Some("bad_peer")));
+        shield.unblock_ip("203.0.113.0/24").await;
+        assert!(shield.blocklist().is_empty());
+    }
 }
@@ -4,11 +4,23 @@ pub mod emergency;
 pub mod behavioral;
 pub mod mirror_shield;
 pub mod bait_wallet;
+pub mod geo;
+pub mod webhook;
+pub mod notifications;
+pub mod snapshot;
+pub mod wipe;
+pub mod blocklist;
+pub mod intel;
+pub mod taxii;
+pub mod guardians;
 
 use anyhow::Result;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use crate::data_dirs::DataDirs;
+use crate::storage::RuntimeMode;
+
 /// Security monitoring orchestrator
 pub struct SecurityMonitor {
     pub file_monitor: Arc<RwLock<monitor::FileIntegrityMonitor>>,
@@ -17,20 +29,67 @@ pub struct SecurityMonitor {
     pub behavioral_analyzer: Arc<RwLock<behavioral::BehavioralAnalyzer>>,
     pub mirror_shield: Arc<RwLock<mirror_shield::MirrorShield>>,
     pub bait_manager: Arc<RwLock<bait_wallet::BaitWalletManager>>,
+    notifier: Option<Arc<notifications::NotificationRouter>>,
 }
 
 impl SecurityMonitor {
     pub async fn new() -> Result<Self> {
+        Self::with_mode(RuntimeMode::Persistent).await
+    }
+
+    /// Create for the given runtime mode (ephemeral redirects evidence to tmpfs)
+    pub async fn with_mode(mode: RuntimeMode) -> Result<Self> {
+        Self::with_emergency_handler(emergency::EmergencyHandler::with_mode(mode)?).await
+    }
+
+    /// Create with evidence collected under the active data directory
+    pub async fn with_data_dirs(dirs: &DataDirs) -> Result<Self> {
+        let handler = emergency::EmergencyHandler::with_evidence_dir(dirs.evidence_dir()?, dirs.mode())?;
+        Self::with_emergency_handler(handler).await
+    }
+
+    async fn with_emergency_handler(emergency_handler: emergency::EmergencyHandler) -> Result<Self> {
         Ok(Self {
             file_monitor: Arc::new(RwLock::new(monitor::FileIntegrityMonitor::new().await?)),
             anomaly_detector: Arc::new(RwLock::new(anomaly::AnomalyDetector::new()?)),
-            emergency_handler: Arc::new(RwLock::new(emergency::EmergencyHandler::new()?)),
+            emergency_handler: Arc::new(RwLock::new(emergency_handler)),
             behavioral_analyzer: Arc::new(RwLock::new(behavioral::BehavioralAnalyzer::new()?)),
             mirror_shield: Arc::new(RwLock::new(mirror_shield::MirrorShield::new())),
             bait_manager: Arc::new(RwLock::new(bait_wallet::BaitWalletManager::new("https://callback.quantra.local"))),
+            notifier: None,
         })
     }
 
+    /// Route shield, bait, emergency and anomaly notifications through `notifier`
+    pub async fn set_notifier(&mut self, notifier: Arc<notifications::NotificationRouter>) {
+        self.emergency_handler.write().await.set_notifier(notifier.clone());
+        self.bait_manager.write().await.set_notifier(notifier.clone());
+        self.mirror_shield.write().await.set_notifier(notifier.clone());
+        self.notifier = Some(notifier);
+    }
+
+    /// Journal shield attacks, bait accesses and canary triggers for export
+    pub async fn set_intel_journal(&mut self, journal: Arc<intel::IntelJournal>) {
+        self.bait_manager.write().await.set_intel_journal(journal.clone());
+        self.mirror_shield.write().await.set_intel_journal(journal);
+    }
+
+    /// Hold full wipes and bait wallet deactivations for operator approval
+    pub async fn set_approvals(&mut self, queue: Arc<crate::approvals::ApprovalQueue>, ttl: chrono::Duration) {
+        self.emergency_handler.write().await.set_approvals(queue.clone(), ttl);
+        self.bait_manager.write().await.set_approvals(queue, ttl);
+    }
+
+    /// Locate bait wallet attackers with the node's shared geolocation lookup
+    pub async fn set_geo_locator(&mut self, locator: Arc<dyn geo::GeoLocator>) {
+        self.bait_manager.write().await.set_geo_locator(locator);
+    }
+
+    /// Hold secure wipes, full wipes and shutdowns for the guardian quorum
+    pub async fn set_guardians(&mut self, quorum: Arc<guardians::GuardianQuorum>) {
+        self.emergency_handler.write().await.set_guardians(quorum);
+    }
+
     /// Start all monitoring services
     pub async fn start(&self) -> Result<()> {
         tracing::info!("🤖 Starting AI Security Monitoring System");
@@ -70,6 +129,13 @@ impl SecurityMonitor {
 
         if threat_level >= ThreatLevel::High {
             tracing::warn!("🚨 High threat detected: {:?}", event);
+            if let Some(notifier) = &self.notifier {
+                notifier.notify(notifications::SinkEvent::AnomalyHigh {
+                    source: event.source.clone(),
+                    event_type: format!("{:?}", event.event_type),
+                    severity: threat_level.into(),
+                });
+            }
 
             // Trigger emergency response if critical
             if threat_level == ThreatLevel::Critical {
@@ -0,0 +1,954 @@
+//! Security Notifications
+//! One outbound event pipeline for the security subsystems: typed events,
+//! pluggable sinks (webhook, Slack, Discord, command, file) and a router
+//! with per-sink rate limits and a bounded retry queue
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::esim::profile::ProfileState;
+use crate::esim::webhook::EventKind;
+use crate::security::webhook::WebhookSender;
+use crate::trace::{self, TraceId};
+use crate::units::HumanDuration;
+
+/// Timeout for command sinks
+const COMMAND_TIMEOUT_SECS: u64 = 10;
+
+/// Event severity, used for route filtering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl From<crate::security::ThreatLevel> for Severity {
+    fn from(level: crate::security::ThreatLevel) -> Self {
+        use crate::security::ThreatLevel;
+        match level {
+            ThreatLevel::Low => Severity::Low,
+            ThreatLevel::Medium => Severity::Medium,
+            ThreatLevel::High => Severity::High,
+            ThreatLevel::Critical => Severity::Critical,
+        }
+    }
+}
+
+/// Events the security subsystems emit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkEvent {
+    /// Mirror Shield blocked a source
+    ShieldBlock {
+        ip: String,
+        peer_id: Option<String>,
+        attack_type: String,
+        threat_score: f64,
+    },
+    /// Zero-trust audit event that needs attention
+    AuditCritical {
+        event_type: String,
+        peer_id: String,
+        details: HashMap<String, String>,
+    },
+    /// A bait wallet was touched
+    BaitAccess {
+        wallet_id: String,
+        wallet_type: String,
+        address: String,
+        attacker_ip: String,
+        access_type: String,
+    },
+    /// Emergency handler responded to a critical threat
+    EmergencyTriggered {
+        source: String,
+        event_type: String,
+        response: String,
+    },
+    /// A destructive emergency response lacked its guardian quorum and was
+    /// downgraded to evidence collection
+    GuardianQuorumFailed {
+        source: String,
+        event_type: String,
+        response: String,
+        approvals: usize,
+        threshold: usize,
+    },
+    /// Anomaly detector rated an event high or critical
+    AnomalyHigh {
+        source: String,
+        event_type: String,
+        severity: Severity,
+    },
+    /// A carrier's SM-DP+ host failed `failure_threshold` probes in a row
+    CarrierUnhealthy {
+        carrier_id: String,
+        host: String,
+        consecutive_failures: u32,
+        error: String,
+    },
+    /// Nightly maintenance finished (possibly with failed steps)
+    Maintenance {
+        complete: bool,
+        failed_steps: Vec<String>,
+        bytes_reclaimed: u64,
+        integrity_warnings: Vec<String>,
+    },
+    /// A paper portfolio's equity fell below its maintenance margin
+    MarginCall {
+        portfolio: String,
+        equity: rust_decimal::Decimal,
+        requirement: rust_decimal::Decimal,
+        deficit: rust_decimal::Decimal,
+        /// Position sold to meet the call, when auto-liquidating
+        liquidated: Option<String>,
+    },
+    /// A gossip topic's mesh shrank well below its recent size
+    PartitionDetected {
+        topic: String,
+        peers: usize,
+        baseline: usize,
+        epoch: u64,
+    },
+    /// A partitioned topic's mesh recovered
+    PartitionHealed {
+        topic: String,
+        peers: usize,
+        baseline: usize,
+        epoch: u64,
+        duration_secs: u64,
+    },
+    /// A user ID was logged with more than one public key
+    KeyConflict {
+        user_id: String,
+        fingerprints: Vec<String>,
+        source: String,
+    },
+    /// An owned DHT record failed to publish `consecutive_failures` times
+    /// in a row, and expires from the network unless a retry succeeds
+    DhtPublishFailing {
+        /// Hex-encoded record key
+        key: String,
+        provider: bool,
+        consecutive_failures: u32,
+    },
+    /// A carrier reported a profile state change or plan expiry
+    EsimEvent {
+        carrier_id: String,
+        iccid: String,
+        event: EventKind,
+        state: ProfileState,
+        plan_expires_at: Option<DateTime<Utc>>,
+    },
+}
+
+impl SinkEvent {
+    /// Routing category (the serde tag)
+    pub fn category(&self) -> &'static str {
+        match self {
+            Self::ShieldBlock { .. } => "shield_block",
+            Self::AuditCritical { .. } => "audit_critical",
+            Self::BaitAccess { .. } => "bait_access",
+            Self::EmergencyTriggered { .. } => "emergency_triggered",
+            Self::GuardianQuorumFailed { .. } => "guardian_quorum_failed",
+            Self::AnomalyHigh { .. } => "anomaly_high",
+            Self::CarrierUnhealthy { .. } => "carrier_unhealthy",
+            Self::Maintenance { .. } => "maintenance",
+            Self::MarginCall { .. } => "margin_call",
+            Self::PartitionDetected { .. } => "partition_detected",
+            Self::PartitionHealed { .. } => "partition_healed",
+            Self::KeyConflict { .. } => "key_conflict",
+            Self::DhtPublishFailing { .. } => "dht_publish_failing",
+            Self::EsimEvent { .. } => "esim_event",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::ShieldBlock { .. } => Severity::High,
+            Self::AuditCritical { .. } => Severity::High,
+            Self::BaitAccess { .. } => Severity::Critical,
+            Self::EmergencyTriggered { .. } => Severity::Critical,
+            Self::GuardianQuorumFailed { .. } => Severity::Critical,
+            Self::AnomalyHigh { severity, .. } => *severity,
+            Self::CarrierUnhealthy { .. } => Severity::Medium,
+            Self::Maintenance { integrity_warnings, .. } if !integrity_warnings.is_empty() => Severity::High,
+            Self::Maintenance { complete: false, .. } => Severity::Medium,
+            Self::Maintenance { .. } => Severity::Info,
+            Self::MarginCall { .. } => Severity::High,
+            Self::PartitionDetected { .. } => Severity::High,
+            Self::PartitionHealed { .. } => Severity::Info,
+            Self::KeyConflict { .. } => Severity::High,
+            Self::DhtPublishFailing { .. } => Severity::High,
+            Self::EsimEvent { event: EventKind::ProfileDeleted | EventKind::PlanExpiring, .. } => Severity::Medium,
+            Self::EsimEvent { .. } => Severity::Info,
+        }
+    }
+
+    /// One-line human readable summary
+    pub fn summary(&self) -> String {
+        match self {
+            Self::ShieldBlock { ip, attack_type, threat_score, .. } => {
+                format!("🛡️ Mirror Shield blocked {} ({}, score {:.0})", ip, attack_type, threat_score)
+            }
+            Self::AuditCritical { event_type, peer_id, .. } => {
+                format!("📋 Audit: {} for peer {}", event_type, peer_id)
+            }
+            Self::BaitAccess { wallet_type, address, attacker_ip, access_type, .. } => format!(
+                "🚨 Bait {} wallet {} accessed from {} ({})",
+                wallet_type, address, attacker_ip, access_type
+            ),
+            Self::EmergencyTriggered { source, event_type, response } => {
+                format!("🚨 Emergency {} on {} from {}", response, event_type, source)
+            }
+            Self::GuardianQuorumFailed { source, event_type, response, approvals, threshold } => format!(
+                "🛡️ {} on {} from {} NOT authorized: {} of {} guardian approvals; evidence collected only",
+                response, event_type, source, approvals, threshold
+            ),
+            Self::AnomalyHigh { source, event_type, severity } => {
+                format!("⚠️ {:?} anomaly: {} from {}", severity, event_type, source)
+            }
+            Self::CarrierUnhealthy { carrier_id, host, consecutive_failures, error } => format!(
+                "📡 Carrier {} SM-DP+ {} down after {} failed probes: {}",
+                carrier_id, host, consecutive_failures, error
+            ),
+            Self::Maintenance { complete, failed_steps, bytes_reclaimed, integrity_warnings } => format!(
+                "🧹 Maintenance {}: {} reclaimed{}{}",
+                if *complete { "complete" } else { "partial" },
+                crate::units::HumanSize::from_bytes(*bytes_reclaimed),
+                if failed_steps.is_empty() { String::new() } else { format!(", failed: {}", failed_steps.join(", ")) },
+                if integrity_warnings.is_empty() { String::new() } else { format!(", integrity: {}", integrity_warnings.join("; ")) }
+            ),
+            Self::MarginCall { portfolio, equity, requirement, deficit, liquidated } => format!(
+                "📉 Margin call on {}: equity {} below maintenance {} (short {}){}",
+                portfolio,
+                equity.round_dp(2),
+                requirement.round_dp(2),
+                deficit.round_dp(2),
+                liquidated.as_ref().map(|symbol| format!(", sold {}", symbol)).unwrap_or_default()
+            ),
+            Self::PartitionDetected { topic, peers, baseline, epoch } => format!(
+                "🧩 Partition on {}: {} of {} peers reachable (epoch {})",
+                topic, peers, baseline, epoch
+            ),
+            Self::PartitionHealed { topic, peers, epoch, duration_secs, .. } => format!(
+                "🧩 Partition on {} healed after {}: {} peers (epoch {})",
+                topic,
+                crate::units::HumanDuration::from_secs(*duration_secs),
+                peers,
+                epoch
+            ),
+            Self::KeyConflict { user_id, fingerprints, source } => format!(
+                "🔑 {} seen with {} keys ({}), newest from {}",
+                user_id,
+                fingerprints.len(),
+                fingerprints.join(", "),
+                source
+            ),
+            Self::DhtPublishFailing { key, provider, consecutive_failures } => format!(
+                "🗺️ DHT {} {} failed to publish {} times in a row",
+                if *provider { "provider record" } else { "record" },
+                key,
+                consecutive_failures
+            ),
+            Self::EsimEvent { carrier_id, iccid, event, state, plan_expires_at } => format!(
+                "📱 {} reports {} for {} (now {:?}){}",
+                carrier_id,
+                event,
+                iccid,
+                state,
+                plan_expires_at.map(|at| format!(", plan expires {}", at.format("%Y-%m-%d %H:%M UTC"))).unwrap_or_default()
+            ),
+        }
+    }
+
+    /// Event JSON with `category`, `severity`, `summary` and `timestamp`
+    /// added, plus `trace_id` when emitted under one
+    pub fn to_json(&self, timestamp: DateTime<Utc>) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(object) = value.as_object_mut() {
+            object.insert("category".into(), self.category().into());
+            object.insert("severity".into(), serde_json::to_value(self.severity()).unwrap_or_default());
+            object.insert("summary".into(), self.summary().into());
+            object.insert("timestamp".into(), timestamp.to_rfc3339().into());
+            if let Some(trace_id) = trace::current() {
+                object.insert("trace_id".into(), trace_id.to_string().into());
+            }
+        }
+        value
+    }
+}
+
+/// Destination for security events
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    async fn emit(&self, event: &SinkEvent) -> Result<()>;
+}
+
+/// Generic HTTP webhook; posts the event JSON, or a template with
+/// `{{field}}` placeholders filled from it
+pub struct HttpSink {
+    sender: WebhookSender,
+    template: Option<String>,
+}
+
+impl HttpSink {
+    pub fn new(url: &str, template: Option<String>) -> Self {
+        Self {
+            sender: WebhookSender::new(url),
+            template,
+        }
+    }
+}
+
+#[async_trait]
+impl EventSink for HttpSink {
+    async fn emit(&self, event: &SinkEvent) -> Result<()> {
+        let json = event.to_json(Utc::now());
+        let body = match &self.template {
+            Some(template) => render_template(template, &json)?,
+            None => json,
+        };
+        self.sender.send_json(&body).await
+    }
+}
+
+/// Chat webhook payload styles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatFormat {
+    Slack,
+    Discord,
+}
+
+/// Slack / Discord webhook with a readable message
+pub struct ChatSink {
+    sender: WebhookSender,
+    format: ChatFormat,
+}
+
+impl ChatSink {
+    pub fn new(url: &str, format: ChatFormat) -> Self {
+        Self {
+            sender: WebhookSender::new(url),
+            format,
+        }
+    }
+}
+
+#[async_trait]
+impl EventSink for ChatSink {
+    async fn emit(&self, event: &SinkEvent) -> Result<()> {
+        let message = format!("[{:?}] {}", event.severity(), event.summary());
+        let body = match self.format {
+            ChatFormat::Slack => serde_json::json!({ "text": message }),
+            ChatFormat::Discord => serde_json::json!({ "content": message }),
+        };
+        self.sender.send_json(&body).await
+    }
+}
+
+/// Runs a local program with the event JSON on stdin
+pub struct CommandSink {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandSink {
+    pub fn new(program: &str, args: Vec<String>) -> Self {
+        Self {
+            program: program.to_string(),
+            args,
+        }
+    }
+}
+
+#[async_trait]
+impl EventSink for CommandSink {
+    async fn emit(&self, event: &SinkEvent) -> Result<()> {
+        let mut child = tokio::process::Command::new(&self.program)
+            .args(&self.args)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start {}", self.program))?;
+
+        let payload = serde_json::to_vec(&event.to_json(Utc::now()))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&payload).await?;
+        }
+
+        let status = tokio::time::timeout(std::time::Duration::from_secs(COMMAND_TIMEOUT_SECS), child.wait())
+            .await
+            .with_context(|| format!("{} timed out", self.program))??;
+        if !status.success() {
+            anyhow::bail!("{} exited with {}", self.program, status);
+        }
+        Ok(())
+    }
+}
+
+/// Appends events as JSON lines to a file
+pub struct FileSink {
+    path: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl EventSink for FileSink {
+    async fn emit(&self, event: &SinkEvent) -> Result<()> {
+        let mut line = serde_json::to_string(&event.to_json(Utc::now()))?;
+        line.push('\n');
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        file.write_all(line.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+/// Replace `{{field}}` with JSON-escaped top-level fields of `event`
+fn render_template(template: &str, event: &serde_json::Value) -> Result<serde_json::Value> {
+    let mut rendered = template.to_string();
+    if let Some(object) = event.as_object() {
+        for (key, value) in object {
+            let text = match value {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            let escaped = serde_json::to_string(&text)?;
+            rendered = rendered.replace(&format!("{{{{{}}}}}", key), &escaped[1..escaped.len() - 1]);
+        }
+    }
+    serde_json::from_str(&rendered).context("Webhook template did not render to valid JSON")
+}
+
+/// `[notifications]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    pub enabled: bool,
+    /// Failed deliveries kept for retry (oldest dropped first)
+    pub retry_queue_size: usize,
+    /// Delivery attempts before an event is dropped
+    pub max_attempts: u32,
+    /// Time between retry passes (legacy: `retry_interval_secs`)
+    #[serde(alias = "retry_interval_secs", deserialize_with = "crate::units::duration_or_secs")]
+    pub retry_interval: HumanDuration,
+    pub sinks: Vec<SinkConfig>,
+    pub routes: Vec<RouteConfig>,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            retry_queue_size: 100,
+            max_attempts: 3,
+            retry_interval: HumanDuration::from_secs(30),
+            sinks: Vec::new(),
+            routes: Vec::new(),
+        }
+    }
+}
+
+/// `[[notifications.sinks]]` entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SinkConfig {
+    pub name: String,
+    /// Deliveries per minute before events are dropped (0 = unlimited)
+    #[serde(default)]
+    pub max_per_minute: u32,
+    #[serde(flatten)]
+    pub kind: SinkKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SinkKind {
+    Webhook { url: String, template: Option<String> },
+    Slack { url: String },
+    Discord { url: String },
+    Command { program: String, #[serde(default)] args: Vec<String> },
+    File { path: PathBuf },
+}
+
+impl SinkKind {
+    pub fn build(&self) -> Arc<dyn EventSink> {
+        match self {
+            Self::Webhook { url, template } => Arc::new(HttpSink::new(url, template.clone())),
+            Self::Slack { url } => Arc::new(ChatSink::new(url, ChatFormat::Slack)),
+            Self::Discord { url } => Arc::new(ChatSink::new(url, ChatFormat::Discord)),
+            Self::Command { program, args } => Arc::new(CommandSink::new(program, args.clone())),
+            Self::File { path } => Arc::new(FileSink::new(path.clone())),
+        }
+    }
+}
+
+/// `[[notifications.routes]]` entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteConfig {
+    /// Event categories (e.g. `shield_block`), or `*` for all
+    pub categories: Vec<String>,
+    #[serde(default = "default_min_severity")]
+    pub min_severity: Severity,
+    pub sinks: Vec<String>,
+}
+
+fn default_min_severity() -> Severity {
+    Severity::Info
+}
+
+impl RouteConfig {
+    fn matches(&self, event: &SinkEvent) -> bool {
+        event.severity() >= self.min_severity
+            && self.categories.iter().any(|c| c == "*" || c == event.category())
+    }
+}
+
+/// Delivery counters
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NotificationStats {
+    pub delivered: u64,
+    pub rate_limited: u64,
+    pub failed: u64,
+    pub retried: u64,
+    pub dropped: u64,
+    pub queued: usize,
+}
+
+struct RoutedSink {
+    sink: Arc<dyn EventSink>,
+    max_per_minute: u32,
+    recent: Mutex<VecDeque<DateTime<Utc>>>,
+}
+
+impl RoutedSink {
+    /// Take a slot in the sink's one-minute window
+    async fn try_acquire(&self, now: DateTime<Utc>) -> bool {
+        if self.max_per_minute == 0 {
+            return true;
+        }
+        let mut recent = self.recent.lock().await;
+        while recent.front().is_some_and(|t| *t <= now - Duration::minutes(1)) {
+            recent.pop_front();
+        }
+        if recent.len() >= self.max_per_minute as usize {
+            return false;
+        }
+        recent.push_back(now);
+        true
+    }
+}
+
+struct PendingDelivery {
+    sink: String,
+    event: SinkEvent,
+    attempts: u32,
+    trace_id: Option<TraceId>,
+}
+
+/// Sinks and routes, swapped as a whole on reconfiguration
+#[derive(Clone)]
+struct Routing {
+    sinks: HashMap<String, Arc<RoutedSink>>,
+    routes: Vec<RouteConfig>,
+    retry_queue_size: usize,
+    max_attempts: u32,
+}
+
+/// Routes events to sinks by category and severity
+pub struct NotificationRouter {
+    routing: parking_lot::RwLock<Arc<Routing>>,
+    retry_queue: Mutex<VecDeque<PendingDelivery>>,
+    stats: Mutex<NotificationStats>,
+}
+
+impl NotificationRouter {
+    /// Build sinks and routes from configuration
+    pub fn from_config(config: &NotificationConfig) -> Result<Self> {
+        Self::validate(config)?;
+        let mut router = Self::new(config.routes.clone(), config.retry_queue_size, config.max_attempts);
+        for sink in &config.sinks {
+            router.add_sink(&sink.name, sink.kind.build(), sink.max_per_minute);
+        }
+        Ok(router)
+    }
+
+    /// Check that every route names a configured sink
+    pub fn validate(config: &NotificationConfig) -> Result<()> {
+        for route in &config.routes {
+            for name in &route.sinks {
+                if !config.sinks.iter().any(|sink| &sink.name == name) {
+                    anyhow::bail!("Notification route references unknown sink '{}'", name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn new(routes: Vec<RouteConfig>, retry_queue_size: usize, max_attempts: u32) -> Self {
+        Self {
+            routing: parking_lot::RwLock::new(Arc::new(Routing {
+                sinks: HashMap::new(),
+                routes,
+                retry_queue_size,
+                max_attempts: max_attempts.max(1),
+            })),
+            retry_queue: Mutex::new(VecDeque::new()),
+            stats: Mutex::new(NotificationStats::default()),
+        }
+    }
+
+    pub fn add_sink(&mut self, name: &str, sink: Arc<dyn EventSink>, max_per_minute: u32) {
+        Arc::make_mut(self.routing.get_mut()).sinks.insert(
+            name.to_string(),
+            Arc::new(RoutedSink {
+                sink,
+                max_per_minute,
+                recent: Mutex::new(VecDeque::new()),
+            }),
+        );
+    }
+
+    /// Replace sinks, routes and retry limits while running. Deliveries in
+    /// flight finish on the old sinks; queued retries for removed sinks are
+    /// dropped, and the queue is trimmed to the new size
+    pub async fn reconfigure(&self, config: &NotificationConfig) -> Result<()> {
+        Self::validate(config)?;
+        let routing = Routing {
+            sinks: config
+                .sinks
+                .iter()
+                .map(|sink| {
+                    let routed = RoutedSink {
+                        sink: sink.kind.build(),
+                        max_per_minute: sink.max_per_minute,
+                        recent: Mutex::new(VecDeque::new()),
+                    };
+                    (sink.name.clone(), Arc::new(routed))
+                })
+                .collect(),
+            routes: config.routes.clone(),
+            retry_queue_size: config.retry_queue_size,
+            max_attempts: config.max_attempts.max(1),
+        };
+
+        let mut queue = self.retry_queue.lock().await;
+        queue.retain(|item| routing.sinks.contains_key(&item.sink));
+        let excess = queue.len().saturating_sub(routing.retry_queue_size);
+        queue.drain(..excess);
+        *self.routing.write() = Arc::new(routing);
+        tracing::info!("🔔 Notification routing reconfigured ({} sink(s))", config.sinks.len());
+        Ok(())
+    }
+
+    /// Deliver in the background so callers never wait on slow sinks
+    pub fn notify(self: &Arc<Self>, event: SinkEvent) {
+        let router = self.clone();
+        match trace::current() {
+            Some(id) => tokio::spawn(trace::scope(id, async move { router.dispatch(event).await })),
+            None => tokio::spawn(async move { router.dispatch(event).await }),
+        };
+    }
+
+    /// Deliver an event to every sink whose route matches
+    pub async fn dispatch(&self, event: SinkEvent) {
+        let routing = self.routing.read().clone();
+        let mut targets: Vec<&String> = routing
+            .routes
+            .iter()
+            .filter(|route| route.matches(&event))
+            .flat_map(|route| route.sinks.iter())
+            .collect();
+        targets.sort();
+        targets.dedup();
+
+        for name in targets {
+            let Some(routed) = routing.sinks.get(name) else { continue };
+            if !routed.try_acquire(Utc::now()).await {
+                self.stats.lock().await.rate_limited += 1;
+                tracing::debug!("🔕 Notification to {} rate limited", name);
+                continue;
+            }
+            self.deliver(&routing, name, routed, event.clone(), 1).await;
+        }
+    }
+
+    /// Retry queued deliveries once; returns how many succeeded
+    pub async fn retry_pending(&self) -> usize {
+        let routing = self.routing.read().clone();
+        let pending: Vec<PendingDelivery> = self.retry_queue.lock().await.drain(..).collect();
+        let mut succeeded = 0;
+        for item in pending {
+            let Some(routed) = routing.sinks.get(&item.sink) else { continue };
+            self.stats.lock().await.retried += 1;
+            let delivery = self.deliver(&routing, &item.sink, routed, item.event, item.attempts + 1);
+            let delivered = match item.trace_id {
+                Some(id) => trace::scope(id, delivery).await,
+                None => delivery.await,
+            };
+            if delivered {
+                succeeded += 1;
+            }
+        }
+        succeeded
+    }
+
+    /// Periodically retry failed deliveries
+    pub fn spawn_retry_loop(self: &Arc<Self>, interval: std::time::Duration) {
+        let router = self.clone();
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(interval);
+            loop {
+                tick.tick().await;
+                router.retry_pending().await;
+            }
+        });
+    }
+
+    pub async fn stats(&self) -> NotificationStats {
+        let mut stats = self.stats.lock().await.clone();
+        stats.queued = self.retry_queue.lock().await.len();
+        stats
+    }
+
+    async fn deliver(&self, routing: &Routing, name: &str, routed: &RoutedSink, event: SinkEvent, attempts: u32) -> bool {
+        match routed.sink.emit(&event).await {
+            Ok(()) => {
+                self.stats.lock().await.delivered += 1;
+                true
+            }
+            Err(e) => {
+                tracing::warn!("📡 Notification to {} failed (attempt {}): {}", name, attempts, e);
+                let mut stats = self.stats.lock().await;
+                stats.failed += 1;
+                if attempts >= routing.max_attempts {
+                    stats.dropped += 1;
+                    return false;
+                }
+                let mut queue = self.retry_queue.lock().await;
+                if queue.len() >= routing.retry_queue_size {
+                    queue.pop_front();
+                    stats.dropped += 1;
+                }
+                if routing.retry_queue_size > 0 {
+                    queue.push_back(PendingDelivery {
+                        sink: name.to_string(),
+                        event,
+                        attempts,
+                        trace_id: trace::current(),
+                    });
+                }
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+    /// Records delivered events; can be told to fail
+    #[derive(Default)]
+    struct RecordingSink {
+        events: Mutex<Vec<SinkEvent>>,
+        failing: AtomicBool,
+    }
+
+    #[async_trait]
+    impl EventSink for RecordingSink {
+        async fn emit(&self, event: &SinkEvent) -> Result<()> {
+            if self.failing.load(Ordering::SeqCst) {
+                anyhow::bail!("sink down");
+            }
+            self.events.lock().await.push(event.clone());
+            Ok(())
+        }
+    }
+
+    fn route(categories: &[&str], min_severity: Severity, sinks: &[&str]) -> RouteConfig {
+        RouteConfig {
+            categories: categories.iter().map(|s| s.to_string()).collect(),
+            min_severity,
+            sinks: sinks.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn shield_block(ip: &str) -> SinkEvent {
+        SinkEvent::ShieldBlock {
+            ip: ip.to_string(),
+            peer_id: None,
+            attack_type: "PortScan".to_string(),
+            threat_score: 80.0,
+        }
+    }
+
+    fn anomaly(severity: Severity) -> SinkEvent {
+        SinkEvent::AnomalyHigh {
+            source: "10.0.0.1".to_string(),
+            event_type: "NetworkSuspicious".to_string(),
+            severity,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_routing_and_severity_filter() {
+        let ops = Arc::new(RecordingSink::default());
+        let pager = Arc::new(RecordingSink::default());
+        let mut router = NotificationRouter::new(
+            vec![
+                route(&["shield_block", "anomaly_high"], Severity::High, &["ops"]),
+                route(&["*"], Severity::Critical, &["pager", "ops"]),
+            ],
+            10,
+            3,
+        );
+        router.add_sink("ops", ops.clone(), 0);
+        router.add_sink("pager", pager.clone(), 0);
+
+        router.dispatch(shield_block("1.2.3.4")).await;
+        router.dispatch(anomaly(Severity::Medium)).await;
+        router.dispatch(anomaly(Severity::Critical)).await;
+
+        // Critical anomaly matches both routes but reaches ops once
+        assert_eq!(ops.events.lock().await.len(), 2);
+        assert_eq!(*pager.events.lock().await, vec![anomaly(Severity::Critical)]);
+        assert_eq!(router.stats().await.delivered, 3);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_and_retry_queue() {
+        let sink = Arc::new(RecordingSink::default());
+        let mut router = NotificationRouter::new(vec![route(&["*"], Severity::Info, &["s"])], 2, 2);
+        router.add_sink("s", sink.clone(), 3);
+
+        for i in 0..5 {
+            router.dispatch(shield_block(&format!("10.0.0.{}", i))).await;
+        }
+        assert_eq!(sink.events.lock().await.len(), 3);
+        assert_eq!(router.stats().await.rate_limited, 2);
+
+        // Failures queue (bounded), then succeed on retry
+        let mut router = NotificationRouter::new(vec![route(&["*"], Severity::Info, &["s"])], 2, 3);
+        let sink = Arc::new(RecordingSink::default());
+        router.add_sink("s", sink.clone(), 0);
+        sink.failing.store(true, Ordering::SeqCst);
+        for i in 0..3 {
+            router.dispatch(shield_block(&format!("10.0.0.{}", i))).await;
+        }
+        let stats = router.stats().await;
+        assert_eq!((stats.failed, stats.queued, stats.dropped), (3, 2, 1));
+
+        sink.failing.store(false, Ordering::SeqCst);
+        assert_eq!(router.retry_pending().await, 2);
+        assert_eq!(sink.events.lock().await[0], shield_block("10.0.0.1"));
+        assert_eq!(router.stats().await.queued, 0);
+    }
+
+    #[tokio::test]
+    async fn test_reconfigure_swaps_routing() {
+        let dir = tempfile::tempdir().unwrap();
+        let file_sink = |name: &str| SinkConfig {
+            name: name.to_string(),
+            max_per_minute: 0,
+            kind: SinkKind::File { path: dir.path().join(format!("{}.jsonl", name)) },
+        };
+        let lines = |name: &str| {
+            std::fs::read_to_string(dir.path().join(format!("{}.jsonl", name)))
+                .map(|s| s.lines().count())
+                .unwrap_or(0)
+        };
+        let mut config = NotificationConfig {
+            sinks: vec![file_sink("old")],
+            routes: vec![route(&["*"], Severity::Info, &["old"])],
+            ..Default::default()
+        };
+        let router = NotificationRouter::from_config(&config).unwrap();
+        router.dispatch(shield_block("10.0.0.1")).await;
+
+        config.sinks = vec![file_sink("new")];
+        config.routes = vec![route(&["shield_block"], Severity::Info, &["new"])];
+        router.reconfigure(&config).await.unwrap();
+        router.dispatch(shield_block("10.0.0.2")).await;
+        router.dispatch(anomaly(Severity::Critical)).await;
+        assert_eq!((lines("old"), lines("new")), (1, 1));
+
+        // A route to a missing sink is rejected and the routing kept
+        config.routes = vec![route(&["*"], Severity::Info, &["missing"])];
+        assert!(router.reconfigure(&config).await.is_err());
+        router.dispatch(shield_block("10.0.0.3")).await;
+        assert_eq!(lines("new"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_payload_carries_trace_id() {
+        assert!(shield_block("10.0.0.1").to_json(Utc::now()).get("trace_id").is_none());
+        let id = TraceId::new();
+        let json = trace::scope(id.clone(), async { shield_block("10.0.0.1").to_json(Utc::now()) }).await;
+        assert_eq!(json["trace_id"], id.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_http_sink_posts_templated_json() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                if let Some(len) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = len.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).await.unwrap();
+            reader
+                .into_inner()
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        });
+
+        let sink = HttpSink::new(
+            &url,
+            Some(r#"{"alert": "{{summary}}", "ip": "{{ip}}", "level": "{{severity}}"}"#.to_string()),
+        );
+        sink.emit(&shield_block("203.0.113.9")).await.unwrap();
+
+        let body = server.await.unwrap();
+        assert_eq!(body["ip"], "203.0.113.9");
+        assert_eq!(body["level"], "high");
+        assert!(body["alert"].as_str().unwrap().contains("blocked 203.0.113.9"));
+    }
+}
@@ -0,0 +1,133 @@
+//! Host Snapshots
+//! System, process and network state for evidence collection, gathered with
+//! sysinfo so the same collectors run on Linux, macOS and Windows
+
+use serde_json::{json, Value};
+use std::process::Command;
+use sysinfo::{Disks, Networks, System};
+
+/// Processes listed in a process snapshot, busiest first
+const TOP_PROCESSES: usize = 25;
+
+/// Uptime, load, memory and disks
+pub fn system_snapshot() -> Value {
+    let mut sys = System::new();
+    sys.refresh_memory();
+    let load = System::load_average();
+    let disks: Vec<Value> = Disks::new_with_refreshed_list()
+        .iter()
+        .map(|disk| {
+            json!({
+                "name": disk.name().to_string_lossy(),
+                "mount_point": disk.mount_point().display().to_string(),
+                "file_system": disk.file_system().to_string_lossy(),
+                "total_bytes": disk.total_space(),
+                "available_bytes": disk.available_space(),
+            })
+        })
+        .collect();
+
+    json!({
+        "host_name": System::host_name(),
+        "os": System::long_os_version(),
+        "kernel": System::kernel_version(),
+        "uptime_secs": System::uptime(),
+        // Zero on Windows, which has no load average
+        "load_average": [load.one, load.five, load.fifteen],
+        "memory": {
+            "total_bytes": sys.total_memory(),
+            "used_bytes": sys.used_memory(),
+            "swap_total_bytes": sys.total_swap(),
+            "swap_used_bytes": sys.used_swap(),
+        },
+        "disks": disks,
+    })
+}
+
+/// Process count and the top processes by CPU. CPU usage needs two samples,
+/// so this waits sysinfo's minimum update interval between them
+pub async fn process_snapshot() -> Value {
+    let mut sys = System::new();
+    sys.refresh_processes();
+    tokio::time::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL).await;
+    sys.refresh_processes();
+
+    let mut processes: Vec<_> = sys.processes().values().collect();
+    processes.sort_by(|a, b| b.cpu_usage().total_cmp(&a.cpu_usage()).then(b.memory().cmp(&a.memory())));
+    let top: Vec<Value> = processes
+        .iter()
+        .take(TOP_PROCESSES)
+        .map(|p| {
+            json!({
+                "pid": p.pid().as_u32(),
+                "parent": p.parent().map(|pid| pid.as_u32()),
+                "name": p.name(),
+                "cmd": p.cmd().join(" "),
+                "cpu_percent": p.cpu_usage(),
+                "memory_bytes": p.memory(),
+                "status": p.status().to_string(),
+                "start_time": p.start_time(),
+            })
+        })
+        .collect();
+
+    json!({ "count": processes.len(), "top": top })
+}
+
+/// Interfaces with traffic counters, plus open connections where a socket
+/// listing tool is installed
+pub fn network_snapshot() -> Value {
+    let interfaces: Vec<Value> = Networks::new_with_refreshed_list()
+        .iter()
+        .map(|(name, data)| {
+            json!({
+                "name": name,
+                "mac": data.mac_address().to_string(),
+                "received_bytes": data.total_received(),
+                "transmitted_bytes": data.total_transmitted(),
+            })
+        })
+        .collect();
+
+    json!({ "interfaces": interfaces, "connections": connections() })
+}
+
+/// sysinfo doesn't list sockets: `ss` on Linux, `netstat` elsewhere.
+/// None when the tool is missing rather than failing the whole snapshot
+fn connections() -> Option<String> {
+    #[cfg(target_os = "linux")]
+    let (program, args) = ("ss", ["-tunap"]);
+    #[cfg(not(target_os = "linux"))]
+    let (program, args) = ("netstat", ["-an"]);
+
+    match Command::new(program).args(args).output() {
+        Ok(output) if output.status.success() => Some(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        Ok(output) => {
+            tracing::debug!("{} exited with {}", program, output.status);
+            None
+        }
+        Err(e) => {
+            tracing::debug!("{} unavailable: {}", program, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_snapshots_are_populated() {
+        let system = system_snapshot();
+        assert!(system["memory"]["total_bytes"].as_u64().unwrap() > 0);
+        assert!(system["uptime_secs"].as_u64().is_some());
+        assert!(system["disks"].is_array());
+
+        let processes = process_snapshot().await;
+        assert!(processes["count"].as_u64().unwrap() > 0);
+        assert!(processes["top"].as_array().unwrap().len() <= TOP_PROCESSES);
+
+        assert!(network_snapshot()["interfaces"].is_array());
+    }
+}
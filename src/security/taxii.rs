@@ -0,0 +1,458 @@
+//! TAXII 2.1 Push
+//! Posts the intel journal as STIX objects to a TAXII collection, in
+//! batches, retrying throttled and failed requests. A cursor file records
+//! how far into the journal has been accepted
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::security::intel::{self, StixExporter};
+use crate::units::HumanDuration;
+
+/// Media type of TAXII 2.1 requests and responses
+const TAXII_MEDIA_TYPE: &str = "application/taxii+json;version=2.1";
+
+/// Per-request timeout
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+
+/// `[intel.taxii]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TaxiiConfig {
+    /// Push on every nightly maintenance run (`intel push` works regardless)
+    pub scheduled: bool,
+    /// Collection endpoint, e.g. `https://taxii.example.com/api1/collections/<id>/`
+    pub collection_url: String,
+    /// Basic auth user, with `password` or the `password_env` variable
+    pub username: Option<String>,
+    /// Prefer `password_env`
+    pub password: Option<String>,
+    pub password_env: String,
+    /// Bearer token, used over basic auth when set; prefer `token_env`
+    pub token: Option<String>,
+    pub token_env: String,
+    /// STIX objects per request
+    pub batch_size: usize,
+    /// Attempts per batch before the push gives up
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for each one after
+    pub retry_delay: HumanDuration,
+}
+
+impl Default for TaxiiConfig {
+    fn default() -> Self {
+        Self {
+            scheduled: false,
+            collection_url: String::new(),
+            username: None,
+            password: None,
+            password_env: "QUANTRA_TAXII_PASSWORD".to_string(),
+            token: None,
+            token_env: "QUANTRA_TAXII_TOKEN".to_string(),
+            batch_size: 100,
+            max_attempts: 4,
+            retry_delay: HumanDuration::from_secs(2),
+        }
+    }
+}
+
+impl TaxiiConfig {
+    pub fn is_configured(&self) -> bool {
+        !self.collection_url.is_empty()
+    }
+
+    /// `token`, else the `token_env` variable
+    pub fn resolve_token(&self) -> Option<String> {
+        self.token.clone().or_else(|| std::env::var(&self.token_env).ok())
+    }
+
+    /// `password`, else the `password_env` variable
+    pub fn resolve_password(&self) -> Option<String> {
+        self.password.clone().or_else(|| std::env::var(&self.password_env).ok())
+    }
+}
+
+#[derive(Debug, Clone)]
+enum TaxiiAuth {
+    Anonymous,
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+/// Status resource a TAXII server answers an add-objects request with
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct TaxiiStatus {
+    status: String,
+    failure_count: u64,
+}
+
+/// What a push delivered
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PushReport {
+    pub batches: usize,
+    pub objects: usize,
+    /// Requests repeated after a throttle, server error or transport error
+    pub retries: u32,
+    /// Objects the server reported as failed to add
+    pub rejected: u64,
+    /// Journal events covered
+    pub events: usize,
+}
+
+impl fmt::Display for PushReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "📡 Pushed {} event(s) as {} STIX object(s) in {} batch(es)",
+            self.events, self.objects, self.batches
+        )?;
+        if self.retries > 0 {
+            write!(f, ", {} retried request(s)", self.retries)?;
+        }
+        if self.rejected > 0 {
+            write!(f, ", {} object(s) rejected by the server", self.rejected)?;
+        }
+        writeln!(f)
+    }
+}
+
+/// Adds STIX objects to one TAXII 2.1 collection
+pub struct TaxiiClient {
+    objects_url: String,
+    auth: TaxiiAuth,
+    batch_size: usize,
+    max_attempts: u32,
+    retry_delay: Duration,
+    client: reqwest::Client,
+}
+
+impl TaxiiClient {
+    pub fn new(config: &TaxiiConfig) -> Result<Self> {
+        if !config.is_configured() {
+            anyhow::bail!("intel.taxii.collection_url is not set");
+        }
+        let collection = reqwest::Url::parse(&config.collection_url)
+            .with_context(|| format!("Invalid intel.taxii.collection_url '{}'", config.collection_url))?;
+        let auth = match (config.resolve_token(), &config.username) {
+            (Some(token), _) => TaxiiAuth::Bearer(token),
+            (None, Some(username)) => TaxiiAuth::Basic {
+                username: username.clone(),
+                password: config.resolve_password().with_context(|| {
+                    format!("TAXII user '{}' has no password; export {} or set intel.taxii.password", username, config.password_env)
+                })?,
+            },
+            (None, None) => TaxiiAuth::Anonymous,
+        };
+        let client = crate::net::http_client(crate::net::Subsystem::Taxii)
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .build()
+            .context("Failed to build TAXII client")?;
+
+        Ok(Self {
+            objects_url: format!("{}/objects/", collection.as_str().trim_end_matches('/')),
+            auth,
+            batch_size: config.batch_size.max(1),
+            max_attempts: config.max_attempts.max(1),
+            retry_delay: config.retry_delay.as_std(),
+            client,
+        })
+    }
+
+    /// Add `objects` in batches of `batch_size`, in order. Stops at the
+    /// first batch that fails every attempt
+    pub async fn push(&self, objects: &[Value]) -> Result<PushReport> {
+        let mut report = PushReport::default();
+        let total = objects.len().div_ceil(self.batch_size);
+        for (i, batch) in objects.chunks(self.batch_size).enumerate() {
+            let status = self
+                .post_batch(batch, &mut report.retries)
+                .await
+                .with_context(|| format!("TAXII batch {} of {} failed", i + 1, total))?;
+            if status.failure_count > 0 {
+                tracing::warn!(
+                    "⚠️  TAXII server rejected {} of {} object(s) in batch {} ({})",
+                    status.failure_count,
+                    batch.len(),
+                    i + 1,
+                    status.status
+                );
+            }
+            report.batches += 1;
+            report.objects += batch.len();
+            report.rejected += status.failure_count;
+        }
+        Ok(report)
+    }
+
+    async fn post_batch(&self, batch: &[Value], retries: &mut u32) -> Result<TaxiiStatus> {
+        let body = json!({ "objects": batch });
+        let mut delay = self.retry_delay;
+        let mut attempt = 1;
+        loop {
+            let request = self
+                .client
+                .post(&self.objects_url)
+                .header(reqwest::header::ACCEPT, TAXII_MEDIA_TYPE)
+                .header(reqwest::header::CONTENT_TYPE, TAXII_MEDIA_TYPE)
+                .body(body.to_string());
+            let request = match &self.auth {
+                TaxiiAuth::Anonymous => request,
+                TaxiiAuth::Basic { username, password } => request.basic_auth(username, Some(password)),
+                TaxiiAuth::Bearer(token) => request.bearer_auth(token),
+            };
+
+            let error = match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    // Servers may answer 202 without a status resource
+                    let bytes = response.bytes().await.unwrap_or_default();
+                    return Ok(serde_json::from_slice(&bytes).unwrap_or_default());
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let retryable = status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+                    if let Some(wait) = retry_after(&response) {
+                        delay = delay.max(wait);
+                    }
+                    let body = response.text().await.unwrap_or_default();
+                    let error = anyhow::anyhow!("TAXII server answered {}: {}", status, body.trim());
+                    if !retryable {
+                        return Err(error);
+                    }
+                    error
+                }
+                Err(e) => anyhow::Error::new(e).context("TAXII request failed"),
+            };
+
+            if attempt >= self.max_attempts {
+                return Err(error.context(format!("gave up after {} attempt(s)", attempt)));
+            }
+            tracing::warn!("⚠️  {:#}; retrying in {:?}", error, delay);
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+            attempt += 1;
+            *retries += 1;
+        }
+    }
+}
+
+/// `Retry-After` in seconds (the HTTP-date form is ignored)
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// How far into the journal the collection has accepted
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PushCursor {
+    pub offset: u64,
+    pub pushed_at: Option<DateTime<Utc>>,
+}
+
+impl PushCursor {
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes).context("Corrupt TAXII push cursor")?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).context("Failed to read TAXII push cursor"),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        crate::data_dirs::atomic_write(path, &serde_json::to_vec_pretty(self)?).context("Failed to write TAXII push cursor")
+    }
+}
+
+/// Pushes journal events the collection hasn't accepted yet
+pub struct IntelPusher {
+    journal_path: PathBuf,
+    cursor_path: PathBuf,
+    exporter: StixExporter,
+    client: TaxiiClient,
+    running: tokio::sync::Mutex<()>,
+}
+
+impl IntelPusher {
+    pub fn new(journal_path: PathBuf, cursor_path: PathBuf, exporter: StixExporter, client: TaxiiClient) -> Self {
+        Self { journal_path, cursor_path, exporter, client, running: tokio::sync::Mutex::new(()) }
+    }
+
+    /// Push everything after the cursor. The cursor only moves once every
+    /// batch is accepted; object IDs are derived from event IDs, so a
+    /// partially pushed run is safely repeated in full
+    pub async fn push_pending(&self) -> Result<PushReport> {
+        let _running = self.running.lock().await;
+        let cursor = PushCursor::load(&self.cursor_path)?;
+        let (records, offset) = intel::read_journal(&self.journal_path, cursor.offset)?;
+        if records.is_empty() {
+            return Ok(PushReport::default());
+        }
+
+        let (objects, summary) = self.exporter.objects(&records);
+        // Only the identity object: every event was filtered out
+        let mut report = if summary.exported == 0 { PushReport::default() } else { self.client.push(&objects).await? };
+        report.events = summary.exported;
+        PushCursor { offset, pushed_at: Some(Utc::now()) }.save(&self.cursor_path)?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::bait_wallet::{AccessType, BaitAccessEvent, WalletType};
+    use crate::security::intel::{IntelConfig, IntelJournal, IntelRecord};
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    struct Received {
+        authorization: Option<String>,
+        objects: usize,
+    }
+
+    /// Answers requests with `statuses` in order, one connection each
+    fn mock_taxii(statuses: Vec<u16>) -> (String, tokio::task::JoinHandle<Vec<Received>>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let url = format!("http://{}/api1/collections/c1/", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+            let mut received = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).await.unwrap();
+                assert!(request_line.starts_with("POST /api1/collections/c1/objects/ "), "{}", request_line);
+                let (mut content_length, mut authorization) = (0, None);
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).await.unwrap();
+                    let lower = line.to_ascii_lowercase();
+                    if let Some(len) = lower.strip_prefix("content-length:") {
+                        content_length = len.trim().parse().unwrap();
+                    }
+                    if lower.starts_with("authorization:") {
+                        authorization = Some(line["authorization:".len()..].trim().to_string());
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).await.unwrap();
+                let envelope: Value = serde_json::from_slice(&body).unwrap();
+                received.push(Received { authorization, objects: envelope["objects"].as_array().unwrap().len() });
+
+                let reply = match status {
+                    202 => r#"{"id":"s1","status":"complete","total_count":1,"success_count":1,"failure_count":0}"#,
+                    _ => "unavailable",
+                };
+                let response = format!(
+                    "HTTP/1.1 {} X\r\ncontent-type: {}\r\nretry-after: 0\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                    status,
+                    TAXII_MEDIA_TYPE,
+                    reply.len(),
+                    reply
+                );
+                reader.into_inner().write_all(response.as_bytes()).await.unwrap();
+            }
+            received
+        });
+        (url, server)
+    }
+
+    fn bait(id: &str, ip: &str) -> IntelRecord {
+        IntelRecord::BaitAccess(BaitAccessEvent {
+            id: id.to_string(),
+            timestamp: Utc::now(),
+            wallet_id: "w-1".to_string(),
+            wallet_type: WalletType::Bitcoin,
+            attacker_ip: ip.to_string(),
+            attacker_location: None,
+            user_agent: None,
+            access_type: AccessType::WalletImport,
+            transaction_attempted: false,
+            alert_sent: true,
+        })
+    }
+
+    fn config(url: &str) -> TaxiiConfig {
+        TaxiiConfig {
+            collection_url: url.to_string(),
+            token: Some("s3cret".to_string()),
+            batch_size: 4,
+            retry_delay: HumanDuration::from_millis(10),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_push_batches_and_retries_server_errors() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let journal = Arc::new(IntelJournal::open(&dir.path().join("intel.jsonl")).unwrap());
+        journal.append(&bait("b-1", "203.0.113.7"));
+        journal.append(&bait("b-2", "203.0.113.7"));
+        journal.append(&bait("b-3", "192.168.0.9"));
+
+        // identity + observable + indicator + 2 observed-data + 2 sightings
+        // = 7 objects: batches of 4 and 3, the second retried once
+        let (url, server) = mock_taxii(vec![202, 503, 202]);
+        let pusher = IntelPusher::new(
+            journal.path().to_path_buf(),
+            dir.path().join("cursor.json"),
+            StixExporter::new(&IntelConfig::default()),
+            TaxiiClient::new(&config(&url)).unwrap(),
+        );
+        let report = pusher.push_pending().await.unwrap();
+        assert_eq!(
+            report,
+            PushReport { batches: 2, objects: 7, retries: 1, rejected: 0, events: 2 }
+        );
+
+        let received = server.await.unwrap();
+        assert_eq!(received.iter().map(|r| r.objects).collect::<Vec<_>>(), [4, 3, 3]);
+        assert!(received.iter().all(|r| r.authorization.as_deref() == Some("Bearer s3cret")));
+
+        // Nothing new: no request at all
+        assert_eq!(pusher.push_pending().await.unwrap(), PushReport::default());
+
+        // Only events after the cursor go out next time
+        journal.append(&bait("b-4", "198.51.100.4"));
+        let (url, server) = mock_taxii(vec![202]);
+        let pusher = IntelPusher::new(
+            journal.path().to_path_buf(),
+            dir.path().join("cursor.json"),
+            StixExporter::new(&IntelConfig::default()),
+            TaxiiClient::new(&TaxiiConfig { batch_size: 100, ..config(&url) }).unwrap(),
+        );
+        assert_eq!(pusher.push_pending().await.unwrap().events, 1);
+        assert_eq!(server.await.unwrap()[0].objects, 5);
+    }
+
+    #[tokio::test]
+    async fn test_failed_push_keeps_cursor() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let journal = IntelJournal::open(&dir.path().join("intel.jsonl")).unwrap();
+        journal.append(&bait("b-1", "203.0.113.7"));
+        let cursor_path = dir.path().join("cursor.json");
+
+        let (url, server) = mock_taxii(vec![503, 503]);
+        let client = TaxiiClient::new(&TaxiiConfig { max_attempts: 2, ..config(&url) }).unwrap();
+        let pusher = IntelPusher::new(
+            journal.path().to_path_buf(),
+            cursor_path.clone(),
+            StixExporter::new(&IntelConfig::default()),
+            client,
+        );
+        let err = format!("{:#}", pusher.push_pending().await.unwrap_err());
+        assert!(err.contains("batch 1 of 2") && err.contains("503"), "{}", err);
+        assert_eq!(server.await.unwrap().len(), 2);
+        assert_eq!(PushCursor::load(&cursor_path).unwrap().offset, 0);
+    }
+}
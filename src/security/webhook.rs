@@ -0,0 +1,47 @@
+//! Webhook Delivery
+//! Posts JSON and plain-text notifications to HTTP / Slack / Discord webhooks
+
+use anyhow::{Context, Result};
+
+/// Webhook request timeout
+const WEBHOOK_TIMEOUT_SECS: u64 = 5;
+
+/// Sends notifications to a single webhook URL
+#[derive(Clone)]
+pub struct WebhookSender {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl WebhookSender {
+    pub fn new(url: &str) -> Self {
+        let client = crate::net::http_client(crate::net::Subsystem::Webhooks)
+            .timeout(std::time::Duration::from_secs(WEBHOOK_TIMEOUT_SECS))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            url: url.to_string(),
+            client,
+        }
+    }
+
+    /// Post a message; `text` is read by Slack, `content` by Discord
+    pub async fn send_text(&self, message: &str) -> Result<()> {
+        self.send_json(&serde_json::json!({ "text": message, "content": message }))
+            .await
+    }
+
+    /// Post an arbitrary JSON body
+    pub async fn send_json(&self, payload: &serde_json::Value) -> Result<()> {
+        self.client
+            .post(&self.url)
+            .json(payload)
+            .send()
+            .await
+            .context("Webhook request failed")?
+            .error_for_status()
+            .context("Webhook rejected notification")?;
+        Ok(())
+    }
+}
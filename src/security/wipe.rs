@@ -0,0 +1,120 @@
+//! Secure Deletion
+//! Multi-pass overwrite before unlinking. `shred` is used where installed;
+//! everywhere else, and whenever it fails, files are overwritten from Rust
+
+use anyhow::{Context, Result};
+use rand::RngCore;
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Random passes before the final zero pass (DoD 5220.22-M style)
+pub const WIPE_PASSES: usize = 7;
+
+const CHUNK: usize = 64 * 1024;
+
+/// Overwrite and remove `path` (a file, or a directory wiped recursively)
+pub fn secure_delete(path: &Path, passes: usize) -> Result<()> {
+    if path.is_dir() {
+        return wipe_path(path, passes);
+    }
+    #[cfg(unix)]
+    match shred(path, passes) {
+        Ok(()) => return Ok(()),
+        Err(e) => tracing::debug!("shred unavailable for {} ({}); overwriting in-process", path.display(), e),
+    }
+    wipe_path(path, passes)
+}
+
+/// Portable wipe: `passes` random overwrites and a zero pass, each synced to
+/// disk, then unlink. Directories are wiped file by file and removed
+pub fn wipe_path(path: &Path, passes: usize) -> Result<()> {
+    let metadata = fs::symlink_metadata(path).with_context(|| format!("Failed to stat {}", path.display()))?;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            wipe_path(&entry?.path(), passes)?;
+        }
+        return fs::remove_dir(path).with_context(|| format!("Failed to remove {}", path.display()));
+    }
+    // Never follow a symlink out of the wiped tree; just drop the link
+    if metadata.is_file() {
+        overwrite(path, metadata.len(), passes)?;
+    }
+    fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))
+}
+
+fn overwrite(path: &Path, len: u64, passes: usize) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {} for wiping", path.display()))?;
+    let mut buf = vec![0u8; CHUNK];
+    for pass in 0..=passes {
+        let zero_pass = pass == passes;
+        file.seek(SeekFrom::Start(0))?;
+        let mut remaining = len;
+        while remaining > 0 {
+            let n = remaining.min(CHUNK as u64) as usize;
+            if zero_pass {
+                buf[..n].fill(0);
+            } else {
+                rand::thread_rng().fill_bytes(&mut buf[..n]);
+            }
+            file.write_all(&buf[..n])?;
+            remaining -= n as u64;
+        }
+        file.sync_all()
+            .with_context(|| format!("Failed to sync wipe pass {} of {}", pass + 1, path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn shred(path: &Path, passes: usize) -> Result<()> {
+    let output = std::process::Command::new("shred")
+        .args(["-n", &passes.to_string(), "-z", "-u"])
+        .arg(path)
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!("shred failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_portable_wipe_overwrites_before_delete() {
+        let dir = TempDir::new().unwrap();
+        let secret = dir.path().join("secret.key");
+        let payload = b"hunter2 ".repeat(20_000);
+        fs::write(&secret, &payload).unwrap();
+
+        // A second link to the same inode still sees the data after unlink
+        let witness = dir.path().join("witness");
+        fs::hard_link(&secret, &witness).unwrap();
+
+        wipe_path(&secret, 3).unwrap();
+        assert!(!secret.exists());
+        let left = fs::read(&witness).unwrap();
+        assert_eq!(left.len(), payload.len(), "wipe must not truncate before overwriting");
+        assert!(left.iter().all(|b| *b == 0), "contents were not overwritten");
+    }
+
+    #[test]
+    fn test_wipe_directory_tree() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("cache");
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::write(root.join("a"), b"one").unwrap();
+        fs::write(root.join("nested/b"), b"two").unwrap();
+        fs::write(root.join("empty"), b"").unwrap();
+
+        secure_delete(&root, WIPE_PASSES).unwrap();
+        assert!(!root.exists());
+        assert!(secure_delete(&root, WIPE_PASSES).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+//! Application Settings
+//! Loaded from a TOML file (default: `config/default.toml`); every section is
+//! optional and falls back to its defaults
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::alerts::AlertSettings;
+use crate::approvals::ApprovalSettings;
+use crate::crypto::key_provider::KeysSettings;
+use crate::crypto::CryptoSettings;
+use crate::esim::EsimSettings;
+use crate::net::NetworkSettings;
+use crate::faults::ChaosSettings;
+use crate::logging::LoggingSettings;
+use crate::maintenance::MaintenanceConfig;
+use crate::p2p::admission::AdmissionConfig;
+use crate::p2p::cover::CoverConfig;
+use crate::security::guardians::GuardianConfig;
+use crate::security::intel::IntelConfig;
+use crate::security::notifications::NotificationConfig;
+use crate::storage::StorageSettings;
+use crate::p2p::geo_policy::GeoPolicyConfig;
+use crate::p2p::outbox::OutboxConfig;
+use crate::p2p::partition::PartitionConfig;
+use crate::p2p::protocol::RequestLimits;
+use crate::p2p::retention::RetentionConfig;
+use crate::p2p::wire::WireConfig;
+use crate::p2p::rate_limiter::RateLimitConfig;
+use crate::p2p::receipts::ReceiptConfig;
+use crate::p2p::replay::ReplayConfig;
+use crate::p2p::telemetry::TelemetryConfig;
+use crate::p2p::transparency::TransparencyConfig;
+use crate::quant::portfolio::PortfolioSettings;
+use crate::quant::QuantSettings;
+use crate::zerotrust::ZeroTrustSettings;
+
+/// Default settings file, relative to the working directory
+pub const DEFAULT_CONFIG_PATH: &str = "config/default.toml";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub network: NetworkSettings,
+    pub p2p: P2pSettings,
+    pub alerts: AlertSettings,
+    pub portfolio: PortfolioSettings,
+    pub notifications: NotificationConfig,
+    pub zerotrust: ZeroTrustSettings,
+    pub esim: EsimSettings,
+    pub chaos: ChaosSettings,
+    pub crypto: CryptoSettings,
+    pub keys: KeysSettings,
+    pub maintenance: MaintenanceConfig,
+    pub quant: QuantSettings,
+    pub logging: LoggingSettings,
+    pub intel: IntelConfig,
+    pub storage: StorageSettings,
+    pub approvals: ApprovalSettings,
+    pub guardians: GuardianConfig,
+}
+
+/// `[p2p]` section
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct P2pSettings {
+    /// Listen addresses used when `p2p` is run without `--listen`
+    pub listen: Vec<String>,
+    /// Refuse to start unless every listen address binds
+    pub listen_require_all: bool,
+    pub rate_limits: RateLimitConfig,
+    pub geo_policy: GeoPolicyConfig,
+    pub admission: AdmissionConfig,
+    pub replay: ReplayConfig,
+    pub retention: RetentionConfig,
+    pub receipts: ReceiptConfig,
+    pub outbox: OutboxConfig,
+    pub telemetry: TelemetryConfig,
+    pub partition: PartitionConfig,
+    pub transparency: TransparencyConfig,
+    pub cover: CoverConfig,
+    pub request_limits: RequestLimits,
+    pub wire: WireConfig,
+}
+
+impl Settings {
+    /// Load settings from a TOML file
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// Load from an explicit path, or the default file if present
+    pub fn load_or_default(path: Option<&Path>) -> Result<Self> {
+        match path {
+            Some(path) => Self::load(path),
+            None => {
+                let default_path = PathBuf::from(DEFAULT_CONFIG_PATH);
+                if default_path.exists() {
+                    Self::load(&default_path)
+                } else {
+                    Ok(Self::default())
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::notifications::{Severity, SinkKind};
+    use crate::units::{HumanDuration, HumanSize};
+
+    #[test]
+    fn test_parse_geo_policy_section() {
+        let settings: Settings = toml::from_str(
+            r#"
+            [network]
+            listen_address = "/ip4/0.0.0.0/tcp/9000"
+
+            [p2p.geo_policy]
+            enabled = true
+            deny_asns = [64500]
+            fail_open = false
+
+            [p2p.geo_policy.asn_caps]
+            AS64501 = 10
+            "#,
+        )
+        .unwrap();
+
+        let geo = settings.p2p.geo_policy;
+        assert!(geo.enabled);
+        assert!(!geo.fail_open);
+        assert_eq!(geo.deny_asns, vec![64500]);
+        assert_eq!(geo.asn_caps.get("AS64501"), Some(&10));
+        assert_eq!(geo.cache_ttl, HumanDuration::from_secs(3600));
+    }
+
+    #[test]
+    fn test_parse_notifications_section() {
+        let settings: Settings = toml::from_str(
+            r#"
+            [notifications]
+            enabled = true
+
+            [[notifications.sinks]]
+            name = "ops"
+            kind = "slack"
+            url = "https://hooks.slack.com/services/T/B/X"
+            max_per_minute = 10
+
+            [[notifications.sinks]]
+            name = "script"
+            kind = "command"
+            program = "/usr/local/bin/page"
+
+            [[notifications.routes]]
+            categories = ["shield_block", "bait_access"]
+            min_severity = "high"
+            sinks = ["ops", "script"]
+            "#,
+        )
+        .unwrap();
+
+        let notifications = settings.notifications;
+        assert!(notifications.enabled);
+        assert_eq!(notifications.sinks[0].max_per_minute, 10);
+        assert!(matches!(notifications.sinks[1].kind, SinkKind::Command { ref args, .. } if args.is_empty()));
+        assert_eq!(notifications.routes[0].min_severity, Severity::High);
+        assert_eq!(notifications.retry_queue_size, 100);
+    }
+
+    #[test]
+    fn test_mixed_legacy_and_human_units() {
+        let settings: Settings = toml::from_str(
+            r#"
+            [p2p.geo_policy]
+            cache_ttl_secs = 600
+
+            [p2p.admission]
+            flood_window = "10m"
+            solve_timeout_secs = 15
+
+            [alerts]
+            poll_interval = "250ms"
+
+            [notifications]
+            retry_interval = 45
+
+            [zerotrust]
+            audit_max_log_size = "1GiB"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(settings.p2p.geo_policy.cache_ttl, HumanDuration::from_secs(600));
+        assert_eq!(settings.p2p.admission.flood_window, HumanDuration::from_secs(600));
+        assert_eq!(settings.p2p.admission.solve_timeout, HumanDuration::from_secs(15));
+        assert_eq!(settings.alerts.poll_interval, HumanDuration::from_millis(250));
+        assert_eq!(settings.notifications.retry_interval, HumanDuration::from_secs(45));
+        assert_eq!(settings.zerotrust.audit_max_log_size, HumanSize::from_mib(1024));
+
+        let reparsed: Settings = toml::from_str(&toml::to_string(&settings).unwrap()).unwrap();
+        assert_eq!(reparsed.p2p.admission.flood_window.to_string(), "10m");
+    }
+
+    #[test]
+    fn test_unit_errors_name_the_field() {
+        let err = toml::from_str::<Settings>("[p2p.admission]\nsolve_timeout = \"10 seconds\"\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("solve_timeout"), "{}", err);
+        assert!(err.contains("units ms, s, m, h, d, w"), "{}", err);
+
+        let err = toml::from_str::<Settings>("[zerotrust]\naudit_max_log_size = 100\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("audit_max_log_size"), "{}", err);
+        assert!(err.contains("KiB, MiB, GiB, TiB"), "{}", err);
+    }
+}
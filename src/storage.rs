@@ -0,0 +1,837 @@
+//! Storage Backends
+//! Key-value storage used by persistent subsystems, with a sled backend for
+//! normal runs (or SQLite, with the `sqlite` feature) and an in-memory
+//! backend for ephemeral mode. Stores with an indexer can also look entries
+//! up by peer, symbol or time without scanning them all
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+#[cfg(feature = "sqlite")]
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::data_dirs::DataDirs;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+/// Whether subsystems may touch the disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RuntimeMode {
+    /// Normal operation: state is persisted to the configured paths
+    #[default]
+    Persistent,
+    /// Throwaway session: all state lives in memory and is lost on exit
+    Ephemeral,
+}
+
+impl RuntimeMode {
+    pub fn from_flag(ephemeral: bool) -> Self {
+        if ephemeral {
+            Self::Ephemeral
+        } else {
+            Self::Persistent
+        }
+    }
+
+    pub fn is_ephemeral(&self) -> bool {
+        *self == Self::Ephemeral
+    }
+}
+
+impl fmt::Display for RuntimeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Persistent => write!(f, "persistent"),
+            Self::Ephemeral => write!(f, "ephemeral (in-memory, nothing written to disk)"),
+        }
+    }
+}
+
+/// Called with each key and value by `KvStore::visit_prefix`; `false` stops
+pub type EntryVisitor<'a> = dyn FnMut(&[u8], &[u8]) -> Result<bool> + 'a;
+
+/// Fields a store's entries are looked up by through `KvStore::find`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexFields {
+    pub peer_id: Option<String>,
+    pub symbol: Option<String>,
+    /// Unix milliseconds
+    pub timestamp: Option<i64>,
+}
+
+/// A store's index fields for one entry, from its key and value
+pub type Indexer = fn(&[u8], &[u8]) -> IndexFields;
+
+/// A lookup `KvStore::find` serves from the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexQuery<'a> {
+    PeerId(&'a str),
+    Symbol(&'a str),
+    /// Unix milliseconds from `from` up to, not including, `to`
+    Timestamp { from: i64, to: i64 },
+}
+
+impl IndexQuery<'_> {
+    /// Every entry with a timestamp
+    pub const ALL_TIMES: IndexQuery<'static> = IndexQuery::Timestamp { from: i64::MIN, to: i64::MAX };
+
+    pub fn matches(&self, fields: &IndexFields) -> bool {
+        match *self {
+            IndexQuery::PeerId(peer_id) => fields.peer_id.as_deref() == Some(peer_id),
+            IndexQuery::Symbol(symbol) => fields.symbol.as_deref() == Some(symbol),
+            IndexQuery::Timestamp { from, to } => fields.timestamp.is_some_and(|t| from <= t && t < to),
+        }
+    }
+}
+
+/// Ordered byte key-value store
+pub trait KvStore: Send + Sync {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<()>;
+    fn remove(&self, key: &[u8]) -> Result<()>;
+    /// All entries in key order
+    fn entries(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+    /// Entries whose key starts with `prefix`, in key order
+    fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut entries = self.entries()?;
+        entries.retain(|(key, _)| key.starts_with(prefix));
+        Ok(entries)
+    }
+    /// Call `visit` on each entry under `prefix` in key order, without
+    /// collecting them, until it returns `false`
+    fn visit_prefix(&self, prefix: &[u8], visit: &mut EntryVisitor<'_>) -> Result<()> {
+        for (key, value) in self.scan_prefix(prefix)? {
+            if !visit(&key, &value)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+    /// Entries matching `query`, ordered by the queried field and then by
+    /// key. Only stores opened with an indexer can answer
+    fn find(&self, query: IndexQuery<'_>) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+    /// Make preceding writes durable
+    fn flush(&self) -> Result<()>;
+}
+
+fn not_indexed() -> anyhow::Error {
+    anyhow::anyhow!("Store has no index to look entries up by")
+}
+
+// sled and memory stores index entries under keys of their own: a field
+// tag, the field, then the entry's key. Strings end in a 0 byte so each
+// value's keys sort together; timestamps are big-endian with the sign bit
+// flipped so they sort numerically. Lookups re-check the entry itself, so
+// an index key left behind by an interrupted write is skipped
+const PEER_ID_TAG: u8 = b'p';
+const SYMBOL_TAG: u8 = b's';
+const TIMESTAMP_TAG: u8 = b't';
+
+fn sortable_timestamp(millis: i64) -> [u8; 8] {
+    ((millis as u64) ^ (1 << 63)).to_be_bytes()
+}
+
+fn index_keys(fields: &IndexFields, key: &[u8]) -> Vec<Vec<u8>> {
+    let mut keys = Vec::new();
+    for (tag, field) in [(PEER_ID_TAG, &fields.peer_id), (SYMBOL_TAG, &fields.symbol)] {
+        if let Some(field) = field {
+            keys.push([&[tag], field.as_bytes(), &[0], key].concat());
+        }
+    }
+    if let Some(millis) = fields.timestamp {
+        keys.push([&[TIMESTAMP_TAG][..], &sortable_timestamp(millis), key].concat());
+    }
+    keys
+}
+
+/// Index keys covering `query`, as a half-open range, and the length of
+/// the part before each entry's key; `None` when nothing can match
+fn index_range(query: IndexQuery<'_>) -> Option<(Vec<u8>, Vec<u8>, usize)> {
+    match query {
+        IndexQuery::PeerId(field) | IndexQuery::Symbol(field) => {
+            let tag = if matches!(query, IndexQuery::PeerId(_)) { PEER_ID_TAG } else { SYMBOL_TAG };
+            let start = [&[tag], field.as_bytes(), &[0]].concat();
+            let mut end = start.clone();
+            *end.last_mut()? = 1;
+            let len = start.len();
+            Some((start, end, len))
+        }
+        IndexQuery::Timestamp { from, to } if from < to => Some((
+            [&[TIMESTAMP_TAG][..], &sortable_timestamp(from)].concat(),
+            [&[TIMESTAMP_TAG][..], &sortable_timestamp(to)].concat(),
+            9,
+        )),
+        IndexQuery::Timestamp { .. } => None,
+    }
+}
+
+/// `[storage]` configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageSettings {
+    pub backend: BackendKind,
+    /// SQLite database file (default: `quantra.db` in the data directory)
+    pub sqlite_path: Option<PathBuf>,
+}
+
+impl StorageSettings {
+    pub fn sqlite_path(&self, dirs: &DataDirs) -> PathBuf {
+        self.sqlite_path.clone().unwrap_or_else(|| dirs.root().join(SQLITE_FILE))
+    }
+}
+
+/// Default SQLite database file name, in the data directory
+pub const SQLITE_FILE: &str = "quantra.db";
+
+/// `storage.backend`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// A sled database per store
+    #[default]
+    Sled,
+    /// Every store in one SQLite file (`sqlite` feature)
+    Sqlite,
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sled => write!(f, "sled"),
+            Self::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "sled" => Ok(Self::Sled),
+            "sqlite" => Ok(Self::Sqlite),
+            _ => anyhow::bail!("Unknown storage backend '{}' (sled or sqlite)", s),
+        }
+    }
+}
+
+/// Where `migrations::open_store` keeps persistent stores
+#[derive(Clone, Default)]
+pub enum Backend {
+    #[default]
+    Sled,
+    #[cfg(feature = "sqlite")]
+    Sqlite(Arc<sqlite::SqliteDatabase>),
+}
+
+static BACKEND: Lazy<RwLock<Backend>> = Lazy::new(|| RwLock::new(Backend::Sled));
+
+/// The process-wide backend (sled until `configure` says otherwise)
+pub fn backend() -> Backend {
+    BACKEND.read().clone()
+}
+
+/// Open `[storage]`'s backend and make it the process-wide one. Ephemeral
+/// runs keep everything in memory whatever the setting
+pub fn configure(settings: &StorageSettings, dirs: &DataDirs) -> Result<()> {
+    if dirs.mode().is_ephemeral() {
+        return Ok(());
+    }
+    let backend = match settings.backend {
+        BackendKind::Sled => Backend::Sled,
+        BackendKind::Sqlite => open_sqlite(&settings.sqlite_path(dirs), dirs.root())?,
+    };
+    *BACKEND.write() = backend;
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn open_sqlite(path: &Path, root: &Path) -> Result<Backend> {
+    Ok(Backend::Sqlite(Arc::new(sqlite::SqliteDatabase::open(path, root)?)))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_sqlite(_path: &Path, _root: &Path) -> Result<Backend> {
+    anyhow::bail!(
+        "SQLite storage is not compiled into this build (hint: rebuild with `--features sqlite`, or set storage.backend = \"sled\")"
+    )
+}
+
+/// sled-backed store (one tree of a database), with its index tree when
+/// opened with an indexer
+pub struct SledStore {
+    tree: sled::Tree,
+    index: Option<(sled::Tree, Indexer)>,
+}
+
+impl SledStore {
+    /// Index every entry afresh
+    fn reindex(&self) -> Result<()> {
+        let Some((index, indexer)) = &self.index else { return Ok(()) };
+        index.clear()?;
+        for entry in self.tree.iter() {
+            let (key, value) = entry?;
+            for index_key in index_keys(&indexer(&key, &value), &key) {
+                index.insert(index_key, &[])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl KvStore for SledStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.tree.get(key)?.map(|v| v.to_vec()))
+    }
+
+    /// New index keys go in before the entry and stale ones come out after
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let Some((index, indexer)) = &self.index else {
+            self.tree.insert(key, value)?;
+            return Ok(());
+        };
+        let new = index_keys(&indexer(key, value), key);
+        for index_key in &new {
+            index.insert(index_key.as_slice(), &[])?;
+        }
+        if let Some(old) = self.tree.insert(key, value)? {
+            for index_key in index_keys(&indexer(key, &old), key) {
+                if !new.contains(&index_key) {
+                    index.remove(index_key)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<()> {
+        let old = self.tree.remove(key)?;
+        if let (Some((index, indexer)), Some(old)) = (&self.index, old) {
+            for index_key in index_keys(&indexer(key, &old), key) {
+                index.remove(index_key)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn entries(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.tree
+            .iter()
+            .map(|entry| {
+                let (k, v) = entry?;
+                Ok((k.to_vec(), v.to_vec()))
+            })
+            .collect()
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.tree
+            .scan_prefix(prefix)
+            .map(|entry| {
+                let (k, v) = entry?;
+                Ok((k.to_vec(), v.to_vec()))
+            })
+            .collect()
+    }
+
+    fn visit_prefix(&self, prefix: &[u8], visit: &mut EntryVisitor<'_>) -> Result<()> {
+        for entry in self.tree.scan_prefix(prefix) {
+            let (key, value) = entry?;
+            if !visit(&key, &value)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn find(&self, query: IndexQuery<'_>) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let (index, indexer) = self.index.as_ref().ok_or_else(not_indexed)?;
+        let Some((start, end, skip)) = index_range(query) else { return Ok(Vec::new()) };
+        let mut found = Vec::new();
+        for index_key in index.range(start..end) {
+            let (index_key, _) = index_key?;
+            let key = &index_key[skip..];
+            if let Some(value) = self.tree.get(key)? {
+                if query.matches(&indexer(key, &value)) {
+                    found.push((key.to_vec(), value.to_vec()));
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    fn flush(&self) -> Result<()> {
+        // Flushes the whole database, index tree included
+        self.tree.flush()?;
+        Ok(())
+    }
+}
+
+/// Tree holding a database's schema version, next to its data tree
+const SCHEMA_TREE: &str = "__schema";
+const SCHEMA_VERSION_KEY: &[u8] = b"version";
+/// Schema version the index tree was last rebuilt at
+const INDEXED_VERSION_KEY: &[u8] = b"indexed";
+/// Tree holding the data tree's index keys
+const INDEX_TREE: &str = "__index";
+
+/// A sled data tree (the default tree when unnamed) plus its database's
+/// schema version
+pub struct VersionedSledStore {
+    db: sled::Db,
+    data: SledStore,
+    schema: sled::Tree,
+}
+
+impl VersionedSledStore {
+    pub fn open(path: &Path, tree: Option<&str>) -> Result<Self> {
+        let db = open_sled(path)?;
+        let data = match tree {
+            Some(name) => db.open_tree(name)?,
+            None => (*db).clone(),
+        };
+        Ok(Self {
+            data: SledStore { tree: data, index: None },
+            schema: db.open_tree(SCHEMA_TREE)?,
+            db,
+        })
+    }
+
+    /// Recorded schema version; `None` for databases written before versioning
+    pub fn version(&self) -> Result<Option<u32>> {
+        match self.schema.get(SCHEMA_VERSION_KEY)? {
+            Some(bytes) => {
+                let bytes: [u8; 4] = bytes.as_ref().try_into().context("Corrupt schema version")?;
+                Ok(Some(u32::from_be_bytes(bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_version(&self, version: u32) -> Result<()> {
+        self.schema.insert(SCHEMA_VERSION_KEY, &version.to_be_bytes())?;
+        self.schema.flush()?;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.tree.is_empty()
+    }
+
+    pub fn data(&self) -> &SledStore {
+        &self.data
+    }
+
+    pub fn into_data(self) -> SledStore {
+        self.data
+    }
+
+    /// The data tree, indexed by `indexer`. The index is rebuilt when it was
+    /// last built at another schema version than `version`: by an older
+    /// build, or before a migration rewrote the entries
+    pub fn into_indexed(self, indexer: Indexer, version: u32) -> Result<SledStore> {
+        let data = SledStore { tree: self.data.tree, index: Some((self.db.open_tree(INDEX_TREE)?, indexer)) };
+        let indexed = match self.schema.get(INDEXED_VERSION_KEY)? {
+            Some(bytes) => Some(u32::from_be_bytes(bytes.as_ref().try_into().context("Corrupt index version")?)),
+            None => None,
+        };
+        if indexed != Some(version) {
+            // sled recovers writes in order, so the version never outlives
+            // the index keys written before it
+            data.reindex()?;
+            self.schema.insert(INDEXED_VERSION_KEY, &version.to_be_bytes())?;
+        }
+        Ok(data)
+    }
+}
+
+/// How long opening a sled database waits for its lock. sled lets go of
+/// the lock once its background writes finish, so a database this process
+/// just closed can still be locked for a moment
+const SLED_LOCK_WAIT: Duration = Duration::from_secs(2);
+
+fn open_sled(path: &Path) -> Result<sled::Db> {
+    // sled reports a held lock only in the error's message
+    let locked = |e: &io::Error| e.to_string().starts_with("could not acquire lock");
+    let started = Instant::now();
+    loop {
+        match sled::open(path) {
+            Err(sled::Error::Io(e)) if locked(&e) && started.elapsed() < SLED_LOCK_WAIT => {
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            result => return result.with_context(|| format!("Failed to open database at {}", path.display())),
+        }
+    }
+}
+
+/// Rewrite the sled database at `path` into a fresh one, dropping the
+/// space held by removed and overwritten entries. The database must not be
+/// open elsewhere; the old copy is only deleted once the new one is synced
+pub fn compact_sled(path: &Path) -> Result<()> {
+    let staging = path.with_extension("compacting");
+    let retired = path.with_extension("precompact");
+    for leftover in [&staging, &retired] {
+        if leftover.exists() {
+            std::fs::remove_dir_all(leftover)?;
+        }
+    }
+    {
+        let old = open_sled(path)?;
+        let new = sled::open(&staging).with_context(|| format!("Failed to create {}", staging.display()))?;
+        new.import(old.export());
+        new.flush()?;
+    }
+    std::fs::rename(path, &retired)?;
+    std::fs::rename(&staging, path)?;
+    std::fs::remove_dir_all(&retired)?;
+    Ok(())
+}
+
+/// In-memory store for ephemeral mode
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+    indexer: Option<Indexer>,
+    /// Index keys, as kept in a sled index tree
+    index: RwLock<BTreeSet<Vec<u8>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn indexed(indexer: Indexer) -> Self {
+        Self { indexer: Some(indexer), ..Self::default() }
+    }
+}
+
+impl KvStore for MemoryStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.entries.read().get(key).cloned())
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let mut entries = self.entries.write();
+        let old = entries.insert(key.to_vec(), value.to_vec());
+        if let Some(indexer) = self.indexer {
+            let mut index = self.index.write();
+            for index_key in old.iter().flat_map(|old| index_keys(&indexer(key, old), key)) {
+                index.remove(&index_key);
+            }
+            index.extend(index_keys(&indexer(key, value), key));
+        }
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<()> {
+        let mut entries = self.entries.write();
+        if let (Some(indexer), Some(old)) = (self.indexer, entries.remove(key)) {
+            let mut index = self.index.write();
+            for index_key in index_keys(&indexer(key, &old), key) {
+                index.remove(&index_key);
+            }
+        }
+        Ok(())
+    }
+
+    fn entries(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        Ok(self
+            .entries
+            .read()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        Ok(self
+            .entries
+            .read()
+            .range(prefix.to_vec()..)
+            .take_while(|(k, _)| k.starts_with(prefix))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+
+    fn visit_prefix(&self, prefix: &[u8], visit: &mut EntryVisitor<'_>) -> Result<()> {
+        let entries = self.entries.read();
+        for (key, value) in entries.range(prefix.to_vec()..).take_while(|(k, _)| k.starts_with(prefix)) {
+            if !visit(key, value)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn find(&self, query: IndexQuery<'_>) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let indexer = self.indexer.ok_or_else(not_indexed)?;
+        let Some((start, end, skip)) = index_range(query) else { return Ok(Vec::new()) };
+        let entries = self.entries.read();
+        let index = self.index.read();
+        Ok(index
+            .range(start..end)
+            .filter_map(|index_key| {
+                let (key, value) = entries.get_key_value(&index_key[skip..])?;
+                query.matches(&indexer(key, value)).then(|| (key.clone(), value.clone()))
+            })
+            .collect())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::CryptoManager;
+    use crate::p2p::dht_records::{DhtRecordManager, OwnedRecordKind};
+    use crate::security::emergency::{self, EmergencyHandler};
+    use crate::zerotrust::identity::IdentityManager;
+    use crate::zerotrust::{AccessDecision, ConnectionRequest, ZeroTrustContext};
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn files_under(dir: &Path) -> usize {
+        std::fs::read_dir(dir).map(|entries| entries.count()).unwrap_or(0)
+    }
+
+    #[tokio::test]
+    async fn test_ephemeral_session_writes_nothing() {
+        let data_dir = TempDir::new().unwrap();
+        let mode = RuntimeMode::Ephemeral;
+
+        // Keys
+        let crypto = CryptoManager::with_mode(data_dir.path().join("keystore"), mode).unwrap();
+        crypto.generate_keypair("alice@example.com").await.unwrap();
+
+        // Zero-trust connection with audit logging
+        let log_path = data_dir.path().join("audit.log");
+        let zt = ZeroTrustContext::with_log_path_and_mode(log_path.to_str().unwrap(), mode)
+            .await
+            .unwrap();
+        let identity = IdentityManager::create_identity("peer-1".to_string(), HashMap::new());
+        zt.register_identity(identity.clone()).await.unwrap();
+        let request = ConnectionRequest {
+            peer_id: "peer-1".to_string(),
+            identity,
+            requested_resources: vec!["p2p/messaging".to_string()],
+            client_metadata: HashMap::new(),
+            timestamp: chrono::Utc::now(),
+        };
+        assert_eq!(zt.evaluate_connection(&request).await.unwrap(), AccessDecision::Allow);
+        let conn = zt.establish_connection(request).await.unwrap();
+        zt.terminate_connection(&conn.id).await.unwrap();
+        assert!(zt.get_stats().await.unwrap().total_security_events > 0);
+
+        // Owned DHT records
+        let mut records = DhtRecordManager::open_with_mode(&data_dir.path().join("dht"), mode).unwrap();
+        records.track(b"k".to_vec(), b"v".to_vec(), OwnedRecordKind::Record).unwrap();
+        records.mark_published(b"k").unwrap();
+
+        // Emergency evidence goes to a throwaway directory
+        let emergency = EmergencyHandler::with_mode(mode).unwrap();
+        assert_ne!(emergency.evidence_dir(), Path::new(emergency::EVIDENCE_DIR));
+        std::fs::remove_dir(emergency.evidence_dir()).ok();
+
+        assert_eq!(files_under(data_dir.path()), 0, "ephemeral mode wrote to the data directory");
+        println!("✅ Ephemeral mode zero-write test PASSED!");
+    }
+
+    /// Behaviour every backend must share
+    fn conformance(store: &dyn KvStore) {
+        assert_eq!(store.get(b"missing").unwrap(), None);
+        store.insert(b"price:AAPL", b"1").unwrap();
+        store.insert(b"price:AAPL", b"2").unwrap();
+        assert_eq!(store.get(b"price:AAPL").unwrap(), Some(b"2".to_vec()), "overwrite");
+        store.remove(b"missing").unwrap();
+
+        store.insert(b"price:MSFT", b"3").unwrap();
+        store.insert(b"pricey", b"").unwrap();
+        store.insert(b"pos:AAPL", b"4").unwrap();
+        store.insert(&[0xff, 0xff], b"high").unwrap();
+        store.insert(&[0xff, 0xff, 0x00], b"higher").unwrap();
+        store.insert(&[0x00, 0x01], b"low").unwrap();
+        assert_eq!(store.get(b"pricey").unwrap(), Some(Vec::new()), "empty value");
+
+        let keys = |entries: Vec<(Vec<u8>, Vec<u8>)>| entries.into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+        let all = keys(store.entries().unwrap());
+        let mut sorted = all.clone();
+        sorted.sort();
+        assert_eq!(all, sorted, "entries in key order");
+        assert_eq!(all.len(), 7);
+
+        assert_eq!(keys(store.scan_prefix(b"price:").unwrap()), vec![b"price:AAPL".to_vec(), b"price:MSFT".to_vec()]);
+        assert_eq!(store.scan_prefix(b"price").unwrap().len(), 3);
+        assert_eq!(keys(store.scan_prefix(&[0xff]).unwrap()), vec![vec![0xff, 0xff], vec![0xff, 0xff, 0x00]]);
+        assert_eq!(store.scan_prefix(b"").unwrap().len(), 7);
+        assert!(store.scan_prefix(b"quote:").unwrap().is_empty());
+
+        let mut visited = Vec::new();
+        store
+            .visit_prefix(&[0xff], &mut |key, _| {
+                visited.push(key.to_vec());
+                Ok(true)
+            })
+            .unwrap();
+        assert_eq!(visited, vec![vec![0xff, 0xff], vec![0xff, 0xff, 0x00]]);
+        let mut first = Vec::new();
+        store
+            .visit_prefix(b"price", &mut |key, _| {
+                first.push(key.to_vec());
+                Ok(false)
+            })
+            .unwrap();
+        assert_eq!(first, vec![b"price:AAPL".to_vec()], "visiting stops when told to");
+
+        store.remove(b"price:AAPL").unwrap();
+        assert_eq!(store.get(b"price:AAPL").unwrap(), None);
+        assert_eq!(store.scan_prefix(b"price:").unwrap().len(), 1);
+        store.flush().unwrap();
+    }
+
+    /// Values are `peer,symbol,millis`, each part optional
+    fn test_indexer(_key: &[u8], value: &[u8]) -> IndexFields {
+        let value = String::from_utf8_lossy(value);
+        let mut parts = value.split(',').map(|part| Some(part).filter(|p| !p.is_empty()));
+        IndexFields {
+            peer_id: parts.next().flatten().map(str::to_string),
+            symbol: parts.next().flatten().map(str::to_string),
+            timestamp: parts.next().flatten().and_then(|t| t.parse().ok()),
+        }
+    }
+
+    /// Lookups every indexed backend must answer alike
+    fn index_conformance(store: &dyn KvStore) {
+        store.insert(b"m1", b"alice,AAPL,300").unwrap();
+        store.insert(b"m2", b"bob,MSFT,-5").unwrap();
+        store.insert(b"m3", b"alice,,100").unwrap();
+        store.insert(b"m4", b"alice2,AAPL,").unwrap();
+        store.insert(b"m5", b",,100").unwrap();
+
+        let keys = |query| store.find(query).unwrap().into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+        assert_eq!(keys(IndexQuery::PeerId("alice")), vec![b"m1".to_vec(), b"m3".to_vec()]);
+        assert_eq!(keys(IndexQuery::Symbol("AAPL")), vec![b"m1".to_vec(), b"m4".to_vec()]);
+        assert!(keys(IndexQuery::Symbol("AAP")).is_empty());
+        assert_eq!(
+            keys(IndexQuery::ALL_TIMES),
+            vec![b"m2".to_vec(), b"m3".to_vec(), b"m5".to_vec(), b"m1".to_vec()],
+            "by time, then key"
+        );
+        assert_eq!(keys(IndexQuery::Timestamp { from: -5, to: 300 }), vec![b"m2".to_vec(), b"m3".to_vec(), b"m5".to_vec()]);
+        assert!(keys(IndexQuery::Timestamp { from: 300, to: 100 }).is_empty());
+
+        // Overwrites and removals leave nothing stale behind
+        store.insert(b"m1", b"bob,MSFT,50").unwrap();
+        assert_eq!(keys(IndexQuery::PeerId("alice")), vec![b"m3".to_vec()]);
+        assert_eq!(keys(IndexQuery::PeerId("bob")), vec![b"m1".to_vec(), b"m2".to_vec()]);
+        assert_eq!(keys(IndexQuery::Symbol("AAPL")), vec![b"m4".to_vec()]);
+        assert_eq!(keys(IndexQuery::Timestamp { from: 0, to: 101 }), vec![b"m1".to_vec(), b"m3".to_vec(), b"m5".to_vec()]);
+        store.remove(b"m3").unwrap();
+        assert!(keys(IndexQuery::PeerId("alice")).is_empty());
+        assert_eq!(store.find(IndexQuery::PeerId("bob")).unwrap()[0], (b"m1".to_vec(), b"bob,MSFT,50".to_vec()));
+        store.flush().unwrap();
+    }
+
+    #[test]
+    fn test_memory_store_conformance() {
+        conformance(&MemoryStore::new());
+        index_conformance(&MemoryStore::indexed(test_indexer));
+        assert!(MemoryStore::new().find(IndexQuery::ALL_TIMES).is_err());
+    }
+
+    #[test]
+    fn test_sled_store_conformance() {
+        let dir = TempDir::new().unwrap();
+        conformance(&VersionedSledStore::open(&dir.path().join("db"), None).unwrap().into_data());
+        conformance(&VersionedSledStore::open(&dir.path().join("named"), Some("alerts")).unwrap().into_data());
+        let indexed = VersionedSledStore::open(&dir.path().join("indexed"), Some("receipts")).unwrap();
+        index_conformance(&indexed.into_indexed(test_indexer, 1).unwrap());
+    }
+
+    #[test]
+    fn test_sled_index_rebuilt_for_new_version() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("db");
+        {
+            // Written before the store had an index
+            let store = VersionedSledStore::open(&path, None).unwrap().into_data();
+            store.insert(b"m1", b"alice,,1").unwrap();
+            store.flush().unwrap();
+        }
+        let store = VersionedSledStore::open(&path, None).unwrap().into_indexed(test_indexer, 1).unwrap();
+        assert_eq!(store.find(IndexQuery::PeerId("alice")).unwrap().len(), 1);
+        drop(store);
+        {
+            // A migration rewrites it unindexed
+            let store = VersionedSledStore::open(&path, None).unwrap().into_data();
+            store.insert(b"m1", b"bob,,1").unwrap();
+            store.flush().unwrap();
+        }
+        let store = VersionedSledStore::open(&path, None).unwrap().into_indexed(test_indexer, 2).unwrap();
+        assert!(store.find(IndexQuery::PeerId("alice")).unwrap().is_empty());
+        assert_eq!(store.find(IndexQuery::PeerId("bob")).unwrap().len(), 1);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_store_conformance() {
+        let dir = TempDir::new().unwrap();
+        let db = Arc::new(sqlite::SqliteDatabase::open(&dir.path().join(SQLITE_FILE), dir.path()).unwrap());
+        let store = db.store(&dir.path().join("portfolio"));
+        assert_eq!(store.id(), "portfolio");
+        conformance(&store);
+
+        // Stores in one file don't see each other's rows
+        let other = db.store(&dir.path().join("alerts"));
+        assert!(other.entries().unwrap().is_empty());
+        other.insert(b"price:AAPL", b"9").unwrap();
+        assert_eq!(store.get(b"price:AAPL").unwrap(), None);
+        assert_eq!(db.count("portfolio").unwrap(), 6);
+        assert_eq!(db.count("alerts").unwrap(), 1);
+
+        // And survive a reopen
+        drop((store, other, db));
+        let db = Arc::new(sqlite::SqliteDatabase::open(&dir.path().join(SQLITE_FILE), dir.path()).unwrap());
+        assert_eq!(db.store(&dir.path().join("alerts")).get(b"price:AAPL").unwrap(), Some(b"9".to_vec()));
+
+        let receipts = dir.path().join("receipts");
+        db.set_version(&db.store_id(&receipts), "receipts", 1).unwrap();
+        index_conformance(&db.store(&receipts).into_indexed(test_indexer, 1).unwrap());
+        assert!(db.store(&receipts).find(IndexQuery::ALL_TIMES).is_err());
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    #[test]
+    fn test_sqlite_backend_needs_feature() {
+        let dir = TempDir::new().unwrap();
+        let dirs = DataDirs::resolve(Some(dir.path()), None, RuntimeMode::Persistent).unwrap();
+        let settings = StorageSettings { backend: BackendKind::Sqlite, sqlite_path: None };
+        let err = configure(&settings, &dirs).unwrap_err();
+        assert!(err.to_string().contains("--features sqlite"), "{}", err);
+        assert!(matches!(backend(), Backend::Sled));
+        assert!(!dir.path().join(SQLITE_FILE).exists());
+
+        let ephemeral = DataDirs::resolve(Some(dir.path()), None, RuntimeMode::Ephemeral).unwrap();
+        configure(&settings, &ephemeral).unwrap();
+    }
+
+    #[test]
+    fn test_backend_kind_parses() {
+        assert_eq!("SQLite".parse::<BackendKind>().unwrap(), BackendKind::Sqlite);
+        assert_eq!(BackendKind::default().to_string(), "sled");
+        assert!("rocksdb".parse::<BackendKind>().is_err());
+        let settings: StorageSettings = toml::from_str("backend = \"sqlite\"").unwrap();
+        assert_eq!(settings.backend, BackendKind::Sqlite);
+    }
+}
@@ -0,0 +1,372 @@
+//! SQLite Backend
+//! Every persistent store in one database file, for nodes that outgrow a
+//! sled directory per store. `entries` holds each store's keys and values
+//! under its `(store, key)` primary key, which also serves prefix and range
+//! scans, plus the `peer_id`, `symbol` and `ts` columns its query APIs
+//! filter by, each with an index; `stores` records each store's schema
+//! version. Runs in WAL mode with cached prepared statements
+
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{not_indexed, EntryVisitor, IndexFields, IndexQuery, Indexer, KvStore};
+
+/// Steps of the database's own schema, applied in order; `PRAGMA
+/// user_version` counts those applied
+const SCHEMA: &[&str] = &[
+    // v1: stores and their entries
+    "CREATE TABLE stores (
+         store TEXT PRIMARY KEY,
+         name TEXT NOT NULL,
+         version INTEGER NOT NULL
+     );
+     CREATE TABLE entries (
+         store TEXT NOT NULL,
+         key BLOB NOT NULL,
+         value BLOB NOT NULL,
+         PRIMARY KEY (store, key)
+     ) WITHOUT ROWID;",
+    // v2: the fields stores look entries up by, and the store version they
+    // were last filled in at
+    "ALTER TABLE entries ADD COLUMN peer_id TEXT;
+     ALTER TABLE entries ADD COLUMN symbol TEXT;
+     ALTER TABLE entries ADD COLUMN ts INTEGER;
+     ALTER TABLE stores ADD COLUMN indexed INTEGER;
+     CREATE INDEX entries_peer_id ON entries (store, peer_id, key) WHERE peer_id IS NOT NULL;
+     CREATE INDEX entries_symbol ON entries (store, symbol, key) WHERE symbol IS NOT NULL;
+     CREATE INDEX entries_ts ON entries (store, ts, key) WHERE ts IS NOT NULL;",
+];
+
+// `KvStore::find` lookups. Without table statistics the planner would
+// rather walk the store's primary key range, so each names its index
+const FIND_BY_PEER_ID: &str =
+    "SELECT key, value FROM entries INDEXED BY entries_peer_id WHERE store = ?1 AND peer_id = ?2 ORDER BY key";
+const FIND_BY_SYMBOL: &str =
+    "SELECT key, value FROM entries INDEXED BY entries_symbol WHERE store = ?1 AND symbol = ?2 ORDER BY key";
+const FIND_BY_TIMESTAMP: &str =
+    "SELECT key, value FROM entries INDEXED BY entries_ts WHERE store = ?1 AND ts >= ?2 AND ts < ?3 ORDER BY ts, key";
+
+/// How long a write waits for another connection's lock
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One SQLite file shared by every store. Stores are named by their
+/// configured location, relative to the data directory where it is inside it
+pub struct SqliteDatabase {
+    conn: Mutex<Connection>,
+    path: PathBuf,
+    root: PathBuf,
+}
+
+impl SqliteDatabase {
+    pub fn open(path: &Path, root: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path).with_context(|| format!("Failed to open database at {}", path.display()))?;
+        let mode: String = conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get(0))?;
+        if !mode.eq_ignore_ascii_case("wal") {
+            tracing::warn!("⚠️  {} is in {} journal mode, not WAL", path.display(), mode);
+        }
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        apply_schema(&conn).with_context(|| format!("Failed to set up database at {}", path.display()))?;
+        crate::data_dirs::restrict_to_owner(path)?;
+        Ok(Self { conn: Mutex::new(conn), path: path.to_path_buf(), root: root.to_path_buf() })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Name of the store configured at `location`
+    pub fn store_id(&self, location: &Path) -> String {
+        location.strip_prefix(&self.root).unwrap_or(location).to_string_lossy().into_owned()
+    }
+
+    /// The store configured at `location`
+    pub fn store(self: &Arc<Self>, location: &Path) -> SqliteStore {
+        SqliteStore { db: self.clone(), store: self.store_id(location), indexer: None }
+    }
+
+    /// Recorded schema version of a store; `None` if it was never opened
+    pub fn version(&self, store: &str) -> Result<Option<u32>> {
+        let conn = self.conn.lock();
+        let mut statement = conn.prepare_cached("SELECT version FROM stores WHERE store = ?1")?;
+        Ok(statement.query_row(params![store], |row| row.get(0)).optional()?)
+    }
+
+    pub fn set_version(&self, store: &str, name: &str, version: u32) -> Result<()> {
+        let conn = self.conn.lock();
+        let mut statement = conn.prepare_cached(
+            "INSERT INTO stores (store, name, version) VALUES (?1, ?2, ?3)
+             ON CONFLICT (store) DO UPDATE SET name = excluded.name, version = excluded.version",
+        )?;
+        statement.execute(params![store, name, version])?;
+        Ok(())
+    }
+
+    pub fn count(&self, store: &str) -> Result<usize> {
+        let conn = self.conn.lock();
+        let mut statement = conn.prepare_cached("SELECT COUNT(*) FROM entries WHERE store = ?1")?;
+        let count: i64 = statement.query_row(params![store], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    /// With `synchronous = NORMAL` commits reach the WAL unsynced; a
+    /// checkpoint syncs them. Inside a transaction it waits for the commit
+    pub fn checkpoint(&self) -> Result<()> {
+        let conn = self.conn.lock();
+        if conn.is_autocommit() {
+            conn.query_row("PRAGMA wal_checkpoint(PASSIVE)", [], |_| Ok(()))?;
+        }
+        Ok(())
+    }
+
+    /// Run `f` as one transaction, rolled back if it fails. Only for
+    /// single-threaded work (migrations, copies): the connection is shared,
+    /// so another thread's writes meanwhile would join the transaction
+    pub fn transaction<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.conn.lock().execute_batch("BEGIN IMMEDIATE")?;
+        match f() {
+            Ok(value) => {
+                self.conn.lock().execute_batch("COMMIT")?;
+                self.checkpoint()?;
+                Ok(value)
+            }
+            Err(e) => {
+                if let Err(rollback) = self.conn.lock().execute_batch("ROLLBACK") {
+                    tracing::warn!("⚠️  Rollback failed: {}", rollback);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+fn apply_schema(conn: &Connection) -> Result<()> {
+    let applied: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if applied > SCHEMA.len() {
+        anyhow::bail!(
+            "database is schema v{}, but this build only understands up to v{}; run a newer quantraband",
+            applied,
+            SCHEMA.len()
+        );
+    }
+    for (version, step) in SCHEMA.iter().enumerate().skip(applied) {
+        conn.execute_batch(&format!("BEGIN; {} PRAGMA user_version = {}; COMMIT;", step, version + 1))?;
+    }
+    Ok(())
+}
+
+/// One store's rows
+pub struct SqliteStore {
+    db: Arc<SqliteDatabase>,
+    store: String,
+    indexer: Option<Indexer>,
+}
+
+impl SqliteStore {
+    pub fn id(&self) -> &str {
+        &self.store
+    }
+
+    /// This store with its index columns filled in by `indexer`. They are
+    /// refilled when last filled at another store version than `version`:
+    /// by an older build, a copy from sled, or before a migration
+    pub fn into_indexed(mut self, indexer: Indexer, version: u32) -> Result<Self> {
+        self.indexer = Some(indexer);
+        let indexed: Option<u32> = {
+            let conn = self.db.conn.lock();
+            let mut statement = conn.prepare_cached("SELECT indexed FROM stores WHERE store = ?1")?;
+            statement.query_row(params![self.store], |row| row.get(0)).optional()?.flatten()
+        };
+        if indexed != Some(version) {
+            self.db.transaction(|| {
+                for (key, value) in self.entries()? {
+                    self.insert(&key, &value)?;
+                }
+                let conn = self.db.conn.lock();
+                let mut statement = conn.prepare_cached("UPDATE stores SET indexed = ?2 WHERE store = ?1")?;
+                statement.execute(params![self.store, version])?;
+                Ok(())
+            })?;
+        }
+        Ok(self)
+    }
+}
+
+impl KvStore for SqliteStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let conn = self.db.conn.lock();
+        let mut statement = conn.prepare_cached("SELECT value FROM entries WHERE store = ?1 AND key = ?2")?;
+        Ok(statement.query_row(params![self.store, key], |row| row.get(0)).optional()?)
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let fields = self.indexer.map(|indexer| indexer(key, value)).unwrap_or_default();
+        let conn = self.db.conn.lock();
+        let mut statement = conn.prepare_cached(
+            "INSERT INTO entries (store, key, value, peer_id, symbol, ts) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (store, key) DO UPDATE SET
+                 value = excluded.value, peer_id = excluded.peer_id, symbol = excluded.symbol, ts = excluded.ts",
+        )?;
+        let IndexFields { peer_id, symbol, timestamp } = fields;
+        statement.execute(params![self.store, key, value, peer_id, symbol, timestamp])?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<()> {
+        let conn = self.db.conn.lock();
+        let mut statement = conn.prepare_cached("DELETE FROM entries WHERE store = ?1 AND key = ?2")?;
+        statement.execute(params![self.store, key])?;
+        Ok(())
+    }
+
+    fn entries(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let conn = self.db.conn.lock();
+        let mut statement = conn.prepare_cached("SELECT key, value FROM entries WHERE store = ?1 ORDER BY key")?;
+        let rows = statement.query_map(params![self.store], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// A range on the primary key: keys from `prefix` up to the next prefix
+    fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let conn = self.db.conn.lock();
+        let rows: Vec<(Vec<u8>, Vec<u8>)> = match prefix_end(prefix) {
+            Some(end) => {
+                let mut statement = conn.prepare_cached(
+                    "SELECT key, value FROM entries WHERE store = ?1 AND key >= ?2 AND key < ?3 ORDER BY key",
+                )?;
+                let rows = statement.query_map(params![self.store, prefix, end], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                rows.collect::<rusqlite::Result<_>>()?
+            }
+            None => {
+                let mut statement =
+                    conn.prepare_cached("SELECT key, value FROM entries WHERE store = ?1 AND key >= ?2 ORDER BY key")?;
+                let rows = statement.query_map(params![self.store, prefix], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                rows.collect::<rusqlite::Result<_>>()?
+            }
+        };
+        Ok(rows)
+    }
+
+    /// Steps the same range as `scan_prefix` a row at a time
+    fn visit_prefix(&self, prefix: &[u8], visit: &mut EntryVisitor<'_>) -> Result<()> {
+        let conn = self.db.conn.lock();
+        let end = prefix_end(prefix);
+        let mut statement = match end {
+            Some(_) => conn.prepare_cached(
+                "SELECT key, value FROM entries WHERE store = ?1 AND key >= ?2 AND key < ?3 ORDER BY key",
+            )?,
+            None => conn.prepare_cached("SELECT key, value FROM entries WHERE store = ?1 AND key >= ?2 ORDER BY key")?,
+        };
+        let mut rows = match &end {
+            Some(end) => statement.query(params![self.store, prefix, end])?,
+            None => statement.query(params![self.store, prefix])?,
+        };
+        while let Some(row) = rows.next()? {
+            let key: Vec<u8> = row.get(0)?;
+            let value: Vec<u8> = row.get(1)?;
+            if !visit(&key, &value)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn find(&self, query: IndexQuery<'_>) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        if self.indexer.is_none() {
+            return Err(not_indexed());
+        }
+        let conn = self.db.conn.lock();
+        let rows: Vec<(Vec<u8>, Vec<u8>)> = match query {
+            IndexQuery::PeerId(field) | IndexQuery::Symbol(field) => {
+                let sql = if matches!(query, IndexQuery::PeerId(_)) { FIND_BY_PEER_ID } else { FIND_BY_SYMBOL };
+                let mut statement = conn.prepare_cached(sql)?;
+                let rows = statement.query_map(params![self.store, field], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                rows.collect::<rusqlite::Result<_>>()?
+            }
+            IndexQuery::Timestamp { from, to } => {
+                let mut statement = conn.prepare_cached(FIND_BY_TIMESTAMP)?;
+                let rows = statement.query_map(params![self.store, from, to], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                rows.collect::<rusqlite::Result<_>>()?
+            }
+        };
+        Ok(rows)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.db.checkpoint()
+    }
+}
+
+/// Smallest key above every key starting with `prefix`; `None` when there
+/// is none (empty or all-0xff prefix)
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn by_peer(_key: &[u8], value: &[u8]) -> IndexFields {
+        IndexFields { peer_id: Some(String::from_utf8_lossy(value).into_owned()), ..IndexFields::default() }
+    }
+
+    #[test]
+    fn test_lookups_use_the_indexes() {
+        let dir = TempDir::new().unwrap();
+        let db = SqliteDatabase::open(&dir.path().join("quantra.db"), dir.path()).unwrap();
+        let conn = db.conn.lock();
+        for (sql, index) in [
+            (FIND_BY_PEER_ID, "entries_peer_id"),
+            (FIND_BY_SYMBOL, "entries_symbol"),
+            (FIND_BY_TIMESTAMP, "entries_ts"),
+        ] {
+            let mut statement = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql)).unwrap();
+            let params = (1..=statement.parameter_count()).map(|_| "0").collect::<Vec<_>>();
+            let details = statement.query_map(rusqlite::params_from_iter(params), |row| row.get::<_, String>(3)).unwrap();
+            let plan = details.collect::<rusqlite::Result<Vec<_>>>().unwrap().join("; ");
+            assert!(plan.contains(index), "{} → {}", sql, plan);
+            assert!(!plan.contains("TEMP B-TREE"), "{} sorts: {}", sql, plan);
+        }
+    }
+
+    #[test]
+    fn test_rows_from_before_indexing_are_indexed_on_open() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("quantra.db");
+        {
+            // A v1 database, from before the index columns
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(&format!("{} PRAGMA user_version = 1;", SCHEMA[0])).unwrap();
+            conn.execute_batch(
+                "INSERT INTO stores VALUES ('receipts', 'message_receipts', 1);
+                 INSERT INTO entries VALUES ('receipts', X'6d31', CAST('alice' AS BLOB));",
+            )
+            .unwrap();
+        }
+        let db = Arc::new(SqliteDatabase::open(&path, dir.path()).unwrap());
+        let store = db.store(&dir.path().join("receipts")).into_indexed(by_peer, 1).unwrap();
+        assert_eq!(store.find(IndexQuery::PeerId("alice")).unwrap(), vec![(b"m1".to_vec(), b"alice".to_vec())]);
+
+        // Written unindexed (as by a migration) and reopened at a new version
+        db.store(&dir.path().join("receipts")).insert(b"m1", b"bob").unwrap();
+        let store = db.store(&dir.path().join("receipts")).into_indexed(by_peer, 2).unwrap();
+        assert!(store.find(IndexQuery::PeerId("alice")).unwrap().is_empty());
+        assert_eq!(store.find(IndexQuery::PeerId("bob")).unwrap().len(), 1);
+    }
+}
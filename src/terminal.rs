@@ -0,0 +1,100 @@
+//! Terminal Output
+//! Rendering of peer-controlled text: control characters and escape
+//! sequences are shown escaped, and long input is cut off
+
+use std::fmt::Write;
+
+/// Gossip messages and other free text
+pub const MESSAGE_RENDER_LEN: usize = 512;
+/// Agent versions, nicknames, addresses and other short fields
+pub const LABEL_RENDER_LEN: usize = 128;
+
+/// Interactive prompt limits
+pub const MAX_COMMAND_LEN: usize = 4096;
+pub const MAX_COMMAND_ARGS: usize = 64;
+
+/// Make `input` inert for a terminal: C0/C1 controls (ESC, newlines, NUL,
+/// ...) and bidi overrides become `\n` / `\u{1b}` style escapes, so ANSI
+/// sequences and fake log lines print as plain text. Output beyond
+/// `max_len` characters is replaced by `…` and the dropped byte count.
+pub fn sanitize_for_terminal(input: &str, max_len: usize) -> String {
+    let mut out = String::with_capacity(input.len().min(max_len) + 16);
+    let mut rendered = 0;
+    for (offset, c) in input.char_indices() {
+        let start = out.len();
+        if needs_escape(c) {
+            // `\n`, `\t`, `\r` stay readable; everything else is `\u{..}`
+            let _ = write!(out, "{}", c.escape_default());
+        } else {
+            out.push(c);
+        }
+        rendered += out[start..].chars().count();
+        if rendered > max_len {
+            out.truncate(start);
+            let _ = write!(out, "…[+{} bytes]", input.len() - offset);
+            break;
+        }
+    }
+    out
+}
+
+/// Characters that move the cursor, start escape sequences or reorder text
+pub fn needs_escape(c: char) -> bool {
+    c.is_control() || matches!(c, '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_inert(rendered: &str) {
+        assert!(!rendered.chars().any(needs_escape), "{:?}", rendered);
+    }
+
+    #[test]
+    fn test_hostile_payloads_are_inert() {
+        let payloads = [
+            // CSI: clear screen, cursor home, red text
+            "\x1b[2J\x1b[H\x1b[31mowned\x1b[0m",
+            // OSC title setters, BEL and ST terminated
+            "\x1b]0;fake title\x07",
+            "\x1b]2;fake title\x1b\\",
+            // 8-bit CSI
+            "\u{9b}31mred",
+            // Fake log line
+            "hi\n2026-01-01T00:00:00Z  INFO ✅ Connection established with peer: admin\r",
+            "null\0byte",
+            "right-to-left \u{202e}txt.exe",
+        ];
+        for payload in payloads {
+            let rendered = sanitize_for_terminal(payload, MESSAGE_RENDER_LEN);
+            assert_inert(&rendered);
+        }
+
+        assert_eq!(sanitize_for_terminal("\x1b[31mred", 64), "\\u{1b}[31mred");
+        assert_eq!(sanitize_for_terminal("a\nb", 64), "a\\nb");
+        assert_eq!(sanitize_for_terminal("a\0b", 64), "a\\u{0}b");
+    }
+
+    #[test]
+    fn test_truncation() {
+        let flood = "A".repeat(5 * 1024 * 1024);
+        let rendered = sanitize_for_terminal(&flood, 16);
+        assert_eq!(rendered, format!("{}…[+{} bytes]", "A".repeat(16), flood.len() - 16));
+
+        // An escape that would straddle the limit is dropped whole
+        let rendered = sanitize_for_terminal("abc\x1b", 5);
+        assert_eq!(rendered, "abc…[+1 bytes]");
+        assert_eq!(sanitize_for_terminal("short", 5), "short");
+    }
+
+    #[test]
+    fn test_unicode_passes_through() {
+        for text in ["🚀 QuantraBand 📈", "量子取引ネットワーク", "Ünïcödé — naïve café", "한국어 메시지"] {
+            assert_eq!(sanitize_for_terminal(text, MESSAGE_RENDER_LEN), text);
+        }
+        // Truncation never splits a character
+        assert_eq!(sanitize_for_terminal("量子取引", 2), "量子…[+6 bytes]");
+        println!("✅ Terminal sanitization tests PASSED!");
+    }
+}
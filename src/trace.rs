@@ -0,0 +1,126 @@
+//! Correlation IDs
+//! Every CLI command and inbound P2P request runs under a trace ID: log
+//! lines inherit it from a tracing span, and audit events, notifications,
+//! error envelopes and outgoing P2P requests read it from the task
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+use tracing::Instrument;
+
+/// Longest trace ID accepted from a peer or the command line
+const MAX_TRACE_ID_LEN: usize = 64;
+
+tokio::task_local! {
+    static CURRENT: TraceId;
+}
+
+/// A correlation ID: a UUID when generated here, or whatever (well-formed)
+/// ID a peer sent
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TraceId(String);
+
+impl TraceId {
+    pub fn new() -> Self {
+        Self(uuid::Uuid::new_v4().to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The ID a peer sent, or a fresh one if it sent none (older protocol
+    /// versions) or one that isn't well formed
+    pub fn from_remote(id: Option<&str>) -> Self {
+        match id.map(str::parse) {
+            Some(Ok(id)) => id,
+            Some(Err(_)) => {
+                tracing::debug!("🧵 Ignoring malformed trace ID from peer");
+                Self::new()
+            }
+            None => Self::new(),
+        }
+    }
+}
+
+impl Default for TraceId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for TraceId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let valid = !s.is_empty()
+            && s.len() <= MAX_TRACE_ID_LEN
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            anyhow::bail!("Invalid trace ID '{}': use 1-{} letters, digits, '-' or '_'", s, MAX_TRACE_ID_LEN);
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl TryFrom<String> for TraceId {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<TraceId> for String {
+    fn from(id: TraceId) -> Self {
+        id.0
+    }
+}
+
+/// The trace ID of the command or request this task is working on
+pub fn current() -> Option<TraceId> {
+    CURRENT.try_with(TraceId::clone).ok()
+}
+
+/// `current()`, or a new ID for work that starts here (e.g. a timer)
+pub fn current_or_new() -> TraceId {
+    current().unwrap_or_default()
+}
+
+/// Run `fut` under `id`: its logs carry `trace_id` and `current()` returns
+/// `id`. The span is a root so a request handled inside a long-running
+/// command isn't attributed to that command
+pub async fn scope<F: Future>(id: TraceId, fut: F) -> F::Output {
+    let span = tracing::info_span!(parent: None, "trace", trace_id = %id);
+    CURRENT.scope(id, fut.instrument(span)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scope_sets_current() {
+        assert!(current().is_none());
+        let id = TraceId::new();
+        let seen = scope(id.clone(), async { current() }).await;
+        assert_eq!(seen, Some(id.clone()));
+
+        // Nested scopes (a request inside a command) use the inner ID
+        let inner = TraceId::new();
+        let seen = scope(id, scope(inner.clone(), async { current() })).await;
+        assert_eq!(seen, Some(inner));
+
+        assert!("not a trace id!".parse::<TraceId>().is_err());
+        assert_ne!(TraceId::from_remote(Some("x\ny")).as_str(), "x\ny");
+        assert_eq!(TraceId::from_remote(Some("req-42")).as_str(), "req-42");
+    }
+}
@@ -0,0 +1,282 @@
+//! Human-Readable Units
+//! Durations ("250ms", "30s", "1h30m") and sizes ("10MB", "1GiB") for config
+//! and CLI inputs, with legacy numeric values accepted in a documented unit
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+const DURATION_GRAMMAR: &str =
+    "expected <number><unit> pairs with units ms, s, m, h, d, w (e.g. \"250ms\", \"30s\", \"1h30m\")";
+const SIZE_GRAMMAR: &str =
+    "expected <number><unit> with units B, KB, MB, GB, TB (x1000) or KiB, MiB, GiB, TiB (x1024) (e.g. \"10MB\", \"1GiB\")";
+
+const DURATION_UNITS: &[(&str, u64)] = &[
+    ("w", 7 * 86_400_000),
+    ("d", 86_400_000),
+    ("h", 3_600_000),
+    ("m", 60_000),
+    ("s", 1_000),
+    ("ms", 1),
+];
+
+/// Largest first, so Display picks the shortest exact form
+const SIZE_UNITS: &[(&str, u64)] = &[
+    ("TiB", 1 << 40),
+    ("TB", 1_000_000_000_000),
+    ("GiB", 1 << 30),
+    ("GB", 1_000_000_000),
+    ("MiB", 1 << 20),
+    ("MB", 1_000_000),
+    ("KiB", 1 << 10),
+    ("KB", 1_000),
+    ("B", 1),
+];
+
+/// Duration written as `<number><unit>` pairs, millisecond precision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HumanDuration(Duration);
+
+impl HumanDuration {
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(Duration::from_secs(secs))
+    }
+
+    pub const fn from_millis(millis: u64) -> Self {
+        Self(Duration::from_millis(millis))
+    }
+
+    pub fn as_std(&self) -> Duration {
+        self.0
+    }
+
+    pub fn as_secs(&self) -> u64 {
+        self.0.as_secs()
+    }
+
+    /// As a chrono duration (saturating)
+    pub fn as_chrono(&self) -> chrono::Duration {
+        chrono::Duration::from_std(self.0).unwrap_or(chrono::Duration::MAX)
+    }
+}
+
+impl From<HumanDuration> for Duration {
+    fn from(d: HumanDuration) -> Self {
+        d.0
+    }
+}
+
+impl FromStr for HumanDuration {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow::anyhow!("invalid duration \"{}\": {}", s, DURATION_GRAMMAR);
+        let mut rest = s.trim();
+        if rest.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut millis: u64 = 0;
+        while !rest.is_empty() {
+            let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            let amount: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+            rest = &rest[digits..];
+            let unit_len = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+            let (_, scale) = DURATION_UNITS
+                .iter()
+                .find(|(unit, _)| *unit == &rest[..unit_len])
+                .ok_or_else(invalid)?;
+            rest = &rest[unit_len..];
+            millis = amount
+                .checked_mul(*scale)
+                .and_then(|part| millis.checked_add(part))
+                .ok_or_else(|| anyhow::anyhow!("duration \"{}\" is too large", s))?;
+        }
+        Ok(Self::from_millis(millis))
+    }
+}
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut millis = self.0.as_millis() as u64;
+        if millis == 0 {
+            return write!(f, "0s");
+        }
+        for (unit, scale) in DURATION_UNITS {
+            if millis >= *scale {
+                write!(f, "{}{}", millis / scale, unit)?;
+                millis %= scale;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Byte size written as `<number><unit>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HumanSize(u64);
+
+impl HumanSize {
+    pub const fn from_mib(mib: u64) -> Self {
+        Self(mib << 20)
+    }
+
+    pub const fn from_bytes(bytes: u64) -> Self {
+        Self(bytes)
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.0
+    }
+}
+
+impl FromStr for HumanSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow::anyhow!("invalid size \"{}\": {}", s, SIZE_GRAMMAR);
+        let trimmed = s.trim();
+        let digits = trimmed.find(|c: char| !c.is_ascii_digit()).unwrap_or(trimmed.len());
+        let amount: u64 = trimmed[..digits].parse().map_err(|_| invalid())?;
+        let unit = trimmed[digits..].trim_start();
+        let (_, scale) = SIZE_UNITS
+            .iter()
+            .find(|(u, _)| u.eq_ignore_ascii_case(unit))
+            .ok_or_else(invalid)?;
+        amount
+            .checked_mul(*scale)
+            .map(Self)
+            .ok_or_else(|| anyhow::anyhow!("size \"{}\" is too large", s))
+    }
+}
+
+impl fmt::Display for HumanSize {
+    /// Largest unit that divides exactly
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (unit, scale) = SIZE_UNITS
+            .iter()
+            .find(|(_, scale)| self.0 != 0 && self.0.is_multiple_of(*scale))
+            .unwrap_or(&("B", 1));
+        write!(f, "{}{}", self.0 / scale, unit)
+    }
+}
+
+macro_rules! string_serde {
+    ($ty:ty, $expecting:expr) => {
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_any(LegacyVisitor::<$ty>::strict($expecting))
+            }
+        }
+    };
+}
+
+string_serde!(HumanDuration, DURATION_GRAMMAR);
+string_serde!(HumanSize, SIZE_GRAMMAR);
+
+/// Unit name and conversion for bare numbers
+type LegacyUnit<T> = (&'static str, fn(u64) -> Option<T>);
+
+/// Accepts the string grammar, and optionally a bare number in a legacy unit
+struct LegacyVisitor<T> {
+    expecting: &'static str,
+    /// `None` rejects bare numbers
+    legacy: Option<LegacyUnit<T>>,
+}
+
+impl<T> LegacyVisitor<T> {
+    fn strict(expecting: &'static str) -> Self {
+        Self { expecting, legacy: None }
+    }
+}
+
+impl<'de, T> Visitor<'de> for LegacyVisitor<T>
+where
+    T: FromStr<Err = anyhow::Error> + fmt::Display,
+{
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.expecting)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+        v.parse().map_err(|e: anyhow::Error| E::custom(e))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<T, E> {
+        let Some((unit, convert)) = self.legacy else {
+            return Err(E::invalid_type(de::Unexpected::Unsigned(v), &self));
+        };
+        let value = convert(v).ok_or_else(|| E::custom(format!("{} {} is out of range", v, unit)))?;
+        tracing::warn!(
+            "⚠️  Deprecated numeric config value {} (read as {}); write \"{}\" instead",
+            v,
+            unit,
+            value
+        );
+        Ok(value)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<T, E> {
+        match u64::try_from(v) {
+            Ok(v) => self.visit_u64(v),
+            Err(_) => Err(E::invalid_value(de::Unexpected::Signed(v), &self)),
+        }
+    }
+}
+
+/// `deserialize_with` for fields that used to be whole seconds
+pub fn duration_or_secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HumanDuration, D::Error> {
+    deserializer.deserialize_any(LegacyVisitor {
+        expecting: DURATION_GRAMMAR,
+        legacy: Some(("seconds", |secs| Some(HumanDuration::from_secs(secs)))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for (input, millis) in [("250ms", 250), ("30s", 30_000), ("5m", 300_000), ("2h", 7_200_000), ("1h30m", 5_400_000), ("1d", 86_400_000)] {
+            let d: HumanDuration = input.parse().unwrap();
+            assert_eq!(d.as_std().as_millis(), millis, "{}", input);
+            assert_eq!(d.to_string(), input);
+        }
+        assert_eq!("90s".parse::<HumanDuration>().unwrap().to_string(), "1m30s");
+
+        for (input, bytes) in [("10MB", 10_000_000), ("1GiB", 1 << 30), ("512B", 512), ("64KiB", 65_536)] {
+            let size: HumanSize = input.parse().unwrap();
+            assert_eq!(size.bytes(), bytes, "{}", input);
+            assert_eq!(size.to_string(), input);
+            assert_eq!(size.to_string().parse::<HumanSize>().unwrap(), size);
+        }
+        assert_eq!("100mib".parse::<HumanSize>().unwrap(), HumanSize::from_mib(100));
+        assert_eq!("2 GB".parse::<HumanSize>().unwrap().bytes(), 2_000_000_000);
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_grammar() {
+        for bad in ["", "30", "5x", "m5", "1.5h", "-3s"] {
+            let err = bad.parse::<HumanDuration>().unwrap_err().to_string();
+            assert!(err.contains("units ms, s, m, h, d, w"), "{}", err);
+        }
+        for bad in ["10", "10XB", "1.5GB", "GiB"] {
+            let err = bad.parse::<HumanSize>().unwrap_err().to_string();
+            assert!(err.contains("KiB, MiB, GiB, TiB"), "{}", err);
+        }
+        assert!("99999999999999999999w".parse::<HumanDuration>().is_err());
+        // Case matters for duration units ("M" is not minutes)
+        assert!("5M".parse::<HumanDuration>().is_err());
+    }
+}
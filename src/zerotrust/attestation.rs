@@ -0,0 +1,324 @@
+//! Build Attestation
+//! What a node says it is running: its version, git commit and a build
+//! digest, signed with its peer identity key over a nonce from the asker.
+//! The verdict becomes `attestation.*` policy attributes, and peers that
+//! refuse or fail attestation are held at Basic
+//!
+//! Threat model: this is not tamper-proof. The node signs the manifest it
+//! was built with, so a modified binary can report anything it likes,
+//! including a known-good digest, and sign it with its own key. What it
+//! does give: the nonce stops a peer replaying another node's answer, the
+//! signature ties the answer to the peer ID, and honest nodes running a
+//! stale or unofficial build are caught and kept off resources that are
+//! worth more than Basic access. Real integrity needs hardware attestation
+//! (TPM quote, TEE report), which this is not
+
+use anyhow::{Context, Result};
+use ed25519_dalek::{Signature, VerifyingKey};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+use crate::crypto::key_provider::KeyProvider;
+use crate::p2p::wire;
+use crate::quant::remote::PRICING_RESOURCE;
+use crate::zerotrust::policy::{compare_versions, Operator, Policy, PolicyAction, PolicyInput, Rule};
+
+/// Bytes of nonce a verifier sends
+pub const NONCE_LEN: usize = 32;
+
+/// Prefix of the policies generated from `require_for`
+pub const POLICY_PREFIX: &str = "attestation_required:";
+
+/// Domain separation for the signed bytes; v2 signs canonical CBOR, v1
+/// (JSON) answers from older peers are still verified
+const SIGNING_CONTEXT: &[u8] = b"quantra-build-attestation-v2\0";
+const LEGACY_SIGNING_CONTEXT: &[u8] = b"quantra-build-attestation-v1\0";
+
+/// `[zerotrust.attestation]` configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AttestationConfig {
+    /// Ask connecting peers to attest, and cap those that don't
+    pub enabled: bool,
+    /// Hex SHA-256 build digests accepted; empty accepts any signed digest
+    pub known_digests: Vec<String>,
+    /// Oldest crate version accepted, e.g. `0.2.0`
+    pub min_version: Option<String>,
+    /// Resources only granted to attested peers
+    pub require_for: Vec<String>,
+}
+
+impl Default for AttestationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            known_digests: Vec::new(),
+            min_version: None,
+            require_for: vec![PRICING_RESOURCE.to_string()],
+        }
+    }
+}
+
+/// Where a manifest's digest came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestSource {
+    /// `QUANTRA_BUILD_DIGEST` at build time, e.g. a reproducible-build hash
+    Build,
+    /// SHA-256 of the running executable
+    Executable,
+}
+
+/// What this build is, fixed at compile time apart from an executable hash
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildManifest {
+    pub version: String,
+    pub git_commit: Option<String>,
+    /// Hex SHA-256
+    pub digest: String,
+    pub digest_source: DigestSource,
+}
+
+static CURRENT: Lazy<BuildManifest> = Lazy::new(|| {
+    let (digest, digest_source) = match option_env!("QUANTRA_BUILD_DIGEST").filter(|d| !d.is_empty()) {
+        Some(digest) => (digest.to_ascii_lowercase(), DigestSource::Build),
+        None => (executable_digest(), DigestSource::Executable),
+    };
+    BuildManifest {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_commit: option_env!("QUANTRA_GIT_COMMIT").filter(|c| !c.is_empty()).map(str::to_string),
+        digest,
+        digest_source,
+    }
+});
+
+/// Hash of the running binary; empty if it can't be read
+fn executable_digest() -> String {
+    let hash = std::env::current_exe()
+        .and_then(std::fs::read)
+        .map(|bytes| hex::encode(Sha256::digest(bytes)));
+    hash.unwrap_or_else(|e| {
+        tracing::warn!("⚠️  Cannot hash own executable for attestation: {}", e);
+        String::new()
+    })
+}
+
+impl BuildManifest {
+    /// This build's manifest
+    pub fn current() -> &'static BuildManifest {
+        &CURRENT
+    }
+}
+
+/// A manifest signed over a verifier's nonce
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedAttestation {
+    pub manifest: BuildManifest,
+    /// Hex Ed25519 signature
+    pub signature: String,
+}
+
+impl SignedAttestation {
+    pub fn sign(manifest: &BuildManifest, nonce: &[u8], key: &dyn KeyProvider) -> Result<Self> {
+        let signature = key.sign(&signing_bytes(manifest, nonce)?)?;
+        Ok(Self { manifest: manifest.clone(), signature: hex::encode(signature.to_bytes()) })
+    }
+
+    /// Check the signature against the peer's identity key
+    pub fn verify(&self, nonce: &[u8], key: &VerifyingKey) -> Result<()> {
+        let signature: [u8; 64] = hex::decode(&self.signature)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .context("Malformed attestation signature")?;
+        let signature = Signature::from_bytes(&signature);
+        wire::verify_signature(key, &signature, &signing_bytes(&self.manifest, nonce)?, || legacy_signing_bytes(&self.manifest, nonce))
+            .context("Attestation signature does not verify")
+    }
+}
+
+fn signing_bytes(manifest: &BuildManifest, nonce: &[u8]) -> Result<Vec<u8>> {
+    wire::signing_bytes(SIGNING_CONTEXT, &(nonce, manifest)).context("Failed to encode build manifest")
+}
+
+fn legacy_signing_bytes(manifest: &BuildManifest, nonce: &[u8]) -> Result<Vec<u8>> {
+    let manifest = serde_json::to_vec(manifest).context("Failed to encode build manifest")?;
+    Ok([LEGACY_SIGNING_CONTEXT, &(nonce.len() as u32).to_be_bytes(), nonce, &manifest].concat())
+}
+
+/// What a verifier made of a peer's attestation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttestationOutcome {
+    /// Signed by the peer and acceptable under the config
+    Attested(BuildManifest),
+    /// Signed by the peer, but the digest isn't known-good
+    UnknownDigest(BuildManifest),
+    /// Signed by the peer, but older than `min_version`
+    Outdated(BuildManifest),
+    /// The signature doesn't verify against the peer's key
+    Invalid(String),
+    /// The peer refused, failed to answer or doesn't support attestation
+    Refused(String),
+}
+
+impl AttestationOutcome {
+    /// Verify `attestation` against the peer's key and `nonce`, then judge
+    /// the manifest by `config`
+    pub fn check(attestation: &SignedAttestation, nonce: &[u8], key: &VerifyingKey, config: &AttestationConfig) -> Self {
+        if let Err(e) = attestation.verify(nonce, key) {
+            return Self::Invalid(format!("{:#}", e));
+        }
+        let manifest = attestation.manifest.clone();
+        let known = config.known_digests.is_empty()
+            || config.known_digests.iter().any(|d| d.eq_ignore_ascii_case(&manifest.digest));
+        if !known {
+            return Self::UnknownDigest(manifest);
+        }
+        let outdated = config.min_version.as_deref().is_some_and(|min| {
+            compare_versions(&manifest.version, min).is_none_or(|o| o.is_lt())
+        });
+        if outdated {
+            return Self::Outdated(manifest);
+        }
+        Self::Attested(manifest)
+    }
+
+    pub fn is_attested(&self) -> bool {
+        matches!(self, Self::Attested(_))
+    }
+
+    /// `attestation.status` policy value
+    pub fn status(&self) -> &'static str {
+        match self {
+            Self::Attested(_) => "attested",
+            Self::UnknownDigest(_) => "unknown_digest",
+            Self::Outdated(_) => "outdated",
+            Self::Invalid(_) => "invalid",
+            Self::Refused(_) => "refused",
+        }
+    }
+
+    pub fn manifest(&self) -> Option<&BuildManifest> {
+        match self {
+            Self::Attested(m) | Self::UnknownDigest(m) | Self::Outdated(m) => Some(m),
+            Self::Invalid(_) | Self::Refused(_) => None,
+        }
+    }
+
+    /// `attestation.status`, and for signed manifests `attestation.version`,
+    /// `attestation.digest` and `attestation.git_commit`
+    pub fn policy_attributes(&self) -> BTreeMap<String, String> {
+        let mut attributes = BTreeMap::from([("attestation.status".to_string(), self.status().to_string())]);
+        if let Some(manifest) = self.manifest() {
+            attributes.insert("attestation.version".to_string(), manifest.version.clone());
+            attributes.insert("attestation.digest".to_string(), manifest.digest.clone());
+            if let Some(commit) = &manifest.git_commit {
+                attributes.insert("attestation.git_commit".to_string(), commit.clone());
+            }
+        }
+        attributes
+    }
+}
+
+/// Replace any `attestation.*` attributes the peer's identity claims with
+/// this node's verdict; `attestation.status` is `pending` until there is one
+pub fn apply_to_input(input: &mut PolicyInput, outcome: Option<&AttestationOutcome>) {
+    input.attributes.retain(|name, _| !name.starts_with("attestation."));
+    match outcome {
+        Some(outcome) => input.attributes.extend(outcome.policy_attributes()),
+        None => {
+            input.attributes.insert("attestation.status".to_string(), "pending".to_string());
+        }
+    }
+}
+
+/// Policies denying `require_for` resources to peers that aren't attested,
+/// named with `POLICY_PREFIX`
+pub fn required_policies(config: &AttestationConfig) -> Vec<Policy> {
+    if !config.enabled {
+        return Vec::new();
+    }
+    config
+        .require_for
+        .iter()
+        .map(|resource| Policy {
+            name: format!("{}{}", POLICY_PREFIX, resource),
+            rules: vec![
+                Rule { attribute: "resource".to_string(), operator: Operator::Equals, value: resource.clone() },
+                Rule {
+                    attribute: "attestation.status".to_string(),
+                    operator: Operator::NotEquals,
+                    value: "attested".to_string(),
+                },
+            ],
+            action: PolicyAction::Deny,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::key_provider::FileKeyProvider;
+    use crate::p2p::groups::{peer_id_for, peer_verifying_key};
+    use ed25519_dalek::SigningKey;
+
+    fn manifest(digest: &str) -> BuildManifest {
+        BuildManifest {
+            version: "0.2.1".to_string(),
+            git_commit: Some("8cf1be6".to_string()),
+            digest: digest.to_string(),
+            digest_source: DigestSource::Build,
+        }
+    }
+
+    #[test]
+    fn test_signature_verifies_against_peer_id_key() {
+        let key = FileKeyProvider::new(SigningKey::from_bytes(&[5; 32]));
+        let peer = peer_id_for(&key.public_key()).unwrap();
+        let nonce = [9u8; NONCE_LEN];
+        let signed = SignedAttestation::sign(&manifest("ab"), &nonce, &key).unwrap();
+
+        let peer_key = peer_verifying_key(&peer).unwrap();
+        signed.verify(&nonce, &peer_key).unwrap();
+        assert!(signed.verify(&[0u8; NONCE_LEN], &peer_key).is_err(), "replayed under another nonce");
+
+        let other = SigningKey::from_bytes(&[6; 32]).verifying_key();
+        assert!(signed.verify(&nonce, &other).is_err(), "verified against another peer");
+
+        let mut tampered = signed.clone();
+        tampered.manifest.digest = "cd".to_string();
+        assert!(tampered.verify(&nonce, &peer_key).is_err());
+
+        let outcome = AttestationOutcome::check(&signed, &nonce, &other, &AttestationConfig::default());
+        assert_eq!(outcome.status(), "invalid");
+
+        // Answers from peers that still sign v1
+        let legacy = SignedAttestation {
+            manifest: signed.manifest.clone(),
+            signature: hex::encode(key.sign(&legacy_signing_bytes(&signed.manifest, &nonce).unwrap()).unwrap().to_bytes()),
+        };
+        legacy.verify(&nonce, &peer_key).unwrap();
+    }
+
+    #[test]
+    fn test_outcome_judges_digest_and_version() {
+        let key = FileKeyProvider::new(SigningKey::from_bytes(&[5; 32]));
+        let nonce = [1u8; NONCE_LEN];
+        let signed = SignedAttestation::sign(&manifest("AB12"), &nonce, &key).unwrap();
+        let check = |config: &AttestationConfig| AttestationOutcome::check(&signed, &nonce, &key.public_key(), config);
+
+        assert!(check(&AttestationConfig::default()).is_attested());
+        let known = AttestationConfig { known_digests: vec!["ab12".to_string()], ..Default::default() };
+        assert!(check(&known).is_attested());
+        let unknown = AttestationConfig { known_digests: vec!["ffff".to_string()], ..Default::default() };
+        assert_eq!(check(&unknown).status(), "unknown_digest");
+        let newer = AttestationConfig { min_version: Some("0.10.0".to_string()), ..known };
+        assert_eq!(check(&newer).status(), "outdated");
+
+        let attributes = check(&AttestationConfig::default()).policy_attributes();
+        assert_eq!(attributes["attestation.digest"], "AB12");
+        assert_eq!(attributes["attestation.version"], "0.2.1");
+    }
+}
@@ -0,0 +1,218 @@
+//! Peer Clock Skew
+//! NTP-style offset estimates per peer, and the tolerance window applied
+//! when checking peer-stamped times (identity validity, challenge expiry)
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Weight of a new sample in the smoothed offset
+const SMOOTHING: f64 = 0.25;
+
+/// One four-timestamp exchange, in Unix milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSample {
+    /// Request sent (local clock)
+    pub t1: i64,
+    /// Request received (peer clock)
+    pub t2: i64,
+    /// Response sent (peer clock)
+    pub t3: i64,
+    /// Response received (local clock)
+    pub t4: i64,
+}
+
+impl TimeSample {
+    /// Peer clock minus local clock
+    pub fn offset_ms(&self) -> i64 {
+        ((self.t2 - self.t1) + (self.t3 - self.t4)) / 2
+    }
+
+    /// Network round trip, excluding the peer's processing time
+    pub fn round_trip_ms(&self) -> i64 {
+        (self.t4 - self.t1) - (self.t3 - self.t2)
+    }
+}
+
+/// Smoothed clock offset for one peer
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ClockEstimate {
+    /// Peer clock minus local clock
+    pub offset_ms: i64,
+    pub round_trip_ms: i64,
+    pub samples: u32,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ClockEstimate {
+    fn update(&mut self, sample: &TimeSample) {
+        let smoothed = self.offset_ms as f64 + SMOOTHING * (sample.offset_ms() - self.offset_ms) as f64;
+        self.offset_ms = smoothed.round() as i64;
+        self.round_trip_ms = sample.round_trip_ms();
+        self.samples += 1;
+        self.updated_at = Utc::now();
+    }
+}
+
+/// How to read one peer's timestamps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerClock {
+    /// Peer clock minus local clock
+    pub offset: Duration,
+    /// Slack on either side of every check
+    pub tolerance: Duration,
+    /// Offsets beyond this flag the peer
+    pub max_skew: Duration,
+}
+
+impl PeerClock {
+    /// Peer times taken at face value, with no slack
+    pub fn exact() -> Self {
+        Self {
+            offset: Duration::zero(),
+            tolerance: Duration::zero(),
+            max_skew: Duration::zero(),
+        }
+    }
+
+    pub fn exceeds_max_skew(&self) -> bool {
+        self.offset.abs() > self.max_skew
+    }
+
+    /// Local time corresponding to a peer timestamp
+    pub fn local_time(&self, peer_time: DateTime<Utc>) -> DateTime<Utc> {
+        peer_time - self.offset
+    }
+
+    /// Peer-stamped `start` (e.g. `issued_at`) has been reached
+    pub fn has_started(&self, start: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        self.local_time(start) <= now + self.tolerance
+    }
+
+    /// Peer-stamped `deadline` (e.g. `expires_at`) has not yet passed
+    pub fn before_deadline(&self, deadline: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        now < self.local_time(deadline) + self.tolerance
+    }
+}
+
+/// Clock estimates for every peer that answered a time sync
+#[derive(Debug, Clone)]
+pub struct PeerClocks {
+    estimates: HashMap<String, ClockEstimate>,
+    tolerance: Duration,
+    max_skew: Duration,
+}
+
+impl PeerClocks {
+    pub fn new(tolerance: std::time::Duration, max_skew: std::time::Duration) -> Self {
+        let mut clocks = Self {
+            estimates: HashMap::new(),
+            tolerance: Duration::zero(),
+            max_skew: Duration::zero(),
+        };
+        clocks.set_limits(tolerance, max_skew);
+        clocks
+    }
+
+    pub fn set_limits(&mut self, tolerance: std::time::Duration, max_skew: std::time::Duration) {
+        self.tolerance = Duration::from_std(tolerance).unwrap_or(Duration::zero());
+        self.max_skew = Duration::from_std(max_skew).unwrap_or(Duration::zero());
+    }
+
+    /// Fold in a sample; returns `None` if it was discarded
+    pub fn record(&mut self, peer_id: &str, sample: TimeSample) -> Option<ClockEstimate> {
+        if sample.round_trip_ms() < 0 {
+            // Our clock stepped backwards mid-exchange
+            return None;
+        }
+        let estimate = self
+            .estimates
+            .entry(peer_id.to_string())
+            .and_modify(|e| e.update(&sample))
+            .or_insert_with(|| ClockEstimate {
+                offset_ms: sample.offset_ms(),
+                round_trip_ms: sample.round_trip_ms(),
+                samples: 1,
+                updated_at: Utc::now(),
+            });
+        Some(*estimate)
+    }
+
+    pub fn estimate(&self, peer_id: &str) -> Option<ClockEstimate> {
+        self.estimates.get(peer_id).copied()
+    }
+
+    /// Estimates sorted by peer
+    pub fn estimates(&self) -> Vec<(String, ClockEstimate)> {
+        let mut estimates: Vec<_> = self.estimates.iter().map(|(p, e)| (p.clone(), *e)).collect();
+        estimates.sort_by(|a, b| a.0.cmp(&b.0));
+        estimates
+    }
+
+    /// Window for `peer_id`; peers never measured get no offset
+    pub fn clock(&self, peer_id: &str) -> PeerClock {
+        PeerClock {
+            offset: self
+                .estimates
+                .get(peer_id)
+                .map_or(Duration::zero(), |e| Duration::milliseconds(e.offset_ms)),
+            tolerance: self.tolerance,
+            max_skew: self.max_skew,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Exchange with a peer whose clock is `skew_ms` ahead, `one_way_ms` each way
+    fn skewed_sample(t1: i64, skew_ms: i64, one_way_ms: i64) -> TimeSample {
+        let t2 = t1 + one_way_ms + skew_ms;
+        let t3 = t2 + 1;
+        TimeSample { t1, t2, t3, t4: t3 - skew_ms + one_way_ms }
+    }
+
+    #[test]
+    fn test_offset_estimate_from_skewed_exchange() {
+        let sample = skewed_sample(1_000, 120_000, 20);
+        assert_eq!(sample.offset_ms(), 120_000);
+        assert_eq!(sample.round_trip_ms(), 40);
+
+        let mut clocks = PeerClocks::new(std::time::Duration::from_secs(5), std::time::Duration::from_secs(300));
+        // Asymmetric paths jitter individual samples; the smoothed estimate settles
+        for (i, one_way) in [20, 35, 10, 60, 25, 15, 40, 20].iter().enumerate() {
+            let mut sample = skewed_sample(i as i64 * 10_000, 120_000, *one_way);
+            sample.t4 += i as i64 % 3 * 10;
+            clocks.record("peer-a", sample).unwrap();
+        }
+        let estimate = clocks.estimate("peer-a").unwrap();
+        assert!((estimate.offset_ms - 120_000).abs() < 50, "{:?}", estimate);
+        assert_eq!(estimate.samples, 8);
+        assert!(!clocks.clock("peer-a").exceeds_max_skew());
+
+        clocks.record("peer-b", skewed_sample(0, -600_000, 20)).unwrap();
+        assert!(clocks.clock("peer-b").exceeds_max_skew());
+        assert!(!clocks.clock("unmeasured").exceeds_max_skew());
+
+        let mut backwards = skewed_sample(0, 0, 20);
+        backwards.t4 = -100;
+        assert!(clocks.record("peer-c", backwards).is_none());
+    }
+
+    #[test]
+    fn test_window_compensates_offset() {
+        let now = Utc::now();
+        let clock = PeerClock {
+            offset: Duration::minutes(2),
+            tolerance: Duration::seconds(5),
+            max_skew: Duration::minutes(5),
+        };
+        // Issued "now" by a peer two minutes ahead
+        assert!(!PeerClock::exact().has_started(now + Duration::minutes(2), now));
+        assert!(clock.has_started(now + Duration::minutes(2), now));
+        assert!(clock.before_deadline(now + Duration::minutes(2) + Duration::seconds(1), now));
+        assert!(!clock.before_deadline(now + Duration::minutes(2) - Duration::seconds(6), now));
+        println!("✅ Peer clock skew test PASSED!");
+    }
+}
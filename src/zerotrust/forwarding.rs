@@ -0,0 +1,730 @@
+//! Audit Forwarding
+//! Copies of audit events for external collectors: RFC 5424 syslog over
+//! UDP, TCP or a Unix socket (optionally carrying CEF for legacy SIEMs) and
+//! journald with structured fields. Records carry the event's chain hash so
+//! they can be matched against the tamper-evident local log. A bounded queue
+//! drained in the background keeps slow collectors off the audit path:
+//! events that don't fit are dropped and counted
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::SecondsFormat;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+use crate::faults::{self, FaultMode, FaultRegistry};
+use crate::zerotrust::audit::SecurityEvent;
+
+/// Longest a collector may take to accept one record
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Private enterprise number reserved for documentation (RFC 5612), used
+/// for the structured-data ID until one is registered
+const SD_ID: &str = "quantra@32473";
+
+/// `[zerotrust.forwarding]` configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ForwardingConfig {
+    /// Records waiting for the collectors; further events are dropped
+    pub queue_size: usize,
+    pub syslog: SyslogConfig,
+    pub journald: JournaldConfig,
+}
+
+impl Default for ForwardingConfig {
+    fn default() -> Self {
+        Self {
+            queue_size: 1024,
+            syslog: SyslogConfig::default(),
+            journald: JournaldConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyslogConfig {
+    pub enabled: bool,
+    pub transport: SyslogTransport,
+    /// `host:port` for UDP and TCP, a socket path for Unix
+    pub address: String,
+    pub format: RecordFormat,
+    /// Facility for events other than authentication and access
+    /// decisions, which always use `authpriv`
+    pub facility: Facility,
+    pub app_name: String,
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            transport: SyslogTransport::Udp,
+            address: "127.0.0.1:514".to_string(),
+            format: RecordFormat::Rfc5424,
+            facility: Facility::Local0,
+            app_name: "quantra".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogTransport {
+    Udp,
+    /// Octet-counted frames (RFC 6587)
+    Tcp,
+    /// Datagram socket such as `/dev/log`
+    Unix,
+}
+
+/// Syslog message body
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordFormat {
+    /// Fields as RFC 5424 structured data
+    Rfc5424,
+    /// ArcSight Common Event Format as the message
+    Cef,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Facility {
+    Auth,
+    Authpriv,
+    Daemon,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+impl Facility {
+    pub fn code(self) -> u8 {
+        match self {
+            Facility::Auth => 4,
+            Facility::Daemon => 3,
+            Facility::Authpriv => 10,
+            Facility::Local0 => 16,
+            Facility::Local1 => 17,
+            Facility::Local2 => 18,
+            Facility::Local3 => 19,
+            Facility::Local4 => 20,
+            Facility::Local5 => 21,
+            Facility::Local6 => 22,
+            Facility::Local7 => 23,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JournaldConfig {
+    /// Ignored (with a warning) where the journal socket doesn't exist
+    pub enabled: bool,
+    pub socket: PathBuf,
+}
+
+impl Default for JournaldConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            socket: PathBuf::from("/run/systemd/journal/socket"),
+        }
+    }
+}
+
+/// Syslog severities used for audit events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Critical = 2,
+    Warning = 4,
+    Notice = 5,
+    Informational = 6,
+}
+
+impl Severity {
+    /// CEF severity (0-10)
+    fn cef(self) -> u8 {
+        match self {
+            Severity::Critical => 10,
+            Severity::Warning => 7,
+            Severity::Notice => 4,
+            Severity::Informational => 2,
+        }
+    }
+}
+
+/// Refusals and failures warn, state changes are notices
+pub fn severity_for(event: &SecurityEvent) -> Severity {
+    let event_type = event.event_type.as_str();
+    if event.security_level == crate::zerotrust::SecurityLevel::Critical && event_type == "security_level_changed" {
+        Severity::Critical
+    } else if ["_denied", "_failed", "_exceeded"].iter().any(|s| event_type.ends_with(s)) {
+        Severity::Warning
+    } else if matches!(event_type, "security_level_changed" | "connection_terminated") {
+        Severity::Notice
+    } else {
+        Severity::Informational
+    }
+}
+
+/// Authentication and access decisions go to `authpriv`
+pub fn facility_for(event_type: &str, default: Facility) -> Facility {
+    const AUTH_PREFIXES: &[&str] = &["identity_", "access_", "policy_", "connection_"];
+    if AUTH_PREFIXES.iter().any(|p| event_type.starts_with(p)) {
+        Facility::Authpriv
+    } else {
+        default
+    }
+}
+
+/// An audit event on its way out, with its hash in the local chain
+#[derive(Debug, Clone)]
+pub struct ForwardedEvent {
+    pub event: SecurityEvent,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ForwardingStats {
+    /// Records accepted by every enabled sink
+    pub forwarded: u64,
+    /// Events not queued because the queue was full
+    pub dropped: u64,
+    /// Sink sends that failed or timed out
+    pub failed: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    forwarded: AtomicU64,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// Destination for forwarded records
+#[async_trait]
+trait AuditSink: Send {
+    fn name(&self) -> &'static str;
+    async fn send(&mut self, record: &ForwardedEvent) -> Result<()>;
+}
+
+/// Queue in front of the enabled sinks; dropping it stops the drain task
+/// once the queue is empty
+pub struct AuditForwarder {
+    tx: mpsc::Sender<ForwardedEvent>,
+    counters: Arc<Counters>,
+}
+
+impl AuditForwarder {
+    /// Start forwarding to the enabled sinks; None if there are none
+    pub fn start(config: &ForwardingConfig) -> Result<Option<Self>> {
+        Self::start_with_faults(config, faults::global().clone())
+    }
+
+    /// As `start`, consulting `registry` for `audit.forward`
+    pub fn start_with_faults(config: &ForwardingConfig, registry: Arc<FaultRegistry>) -> Result<Option<Self>> {
+        let mut sinks: Vec<Box<dyn AuditSink>> = Vec::new();
+        if config.syslog.enabled {
+            sinks.push(Box::new(SyslogSink::new(&config.syslog)?));
+        }
+        if config.journald.enabled {
+            if config.journald.socket.exists() {
+                sinks.push(Box::new(JournaldSink { socket: config.journald.socket.clone() }));
+            } else {
+                tracing::warn!(
+                    "📤 journald forwarding enabled but {} is missing; skipping",
+                    config.journald.socket.display()
+                );
+            }
+        }
+        if sinks.is_empty() {
+            return Ok(None);
+        }
+
+        let (tx, rx) = mpsc::channel(config.queue_size.max(1));
+        let counters = Arc::new(Counters::default());
+        let names: Vec<_> = sinks.iter().map(|s| s.name()).collect();
+        tracing::info!("📤 Forwarding audit events to {}", names.join(", "));
+        tokio::spawn(drain(rx, sinks, counters.clone(), registry));
+        Ok(Some(Self { tx, counters }))
+    }
+
+    /// Queue a copy of `event` without waiting; dropped (and counted) if
+    /// the collectors are behind
+    pub fn forward(&self, event: &SecurityEvent, hash: &str) {
+        let record = ForwardedEvent { event: event.clone(), hash: hash.to_string() };
+        if self.tx.try_send(record).is_err() {
+            let dropped = self.counters.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            if dropped == 1 || dropped.is_multiple_of(1000) {
+                tracing::warn!("📤 Audit forwarding queue full; {} event(s) dropped so far", dropped);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> ForwardingStats {
+        ForwardingStats {
+            forwarded: self.counters.forwarded.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Send each queued record to every sink (injection site `audit.forward`)
+async fn drain(
+    mut rx: mpsc::Receiver<ForwardedEvent>,
+    mut sinks: Vec<Box<dyn AuditSink>>,
+    counters: Arc<Counters>,
+    registry: Arc<FaultRegistry>,
+) {
+    while let Some(record) = rx.recv().await {
+        match faults::fault!(registry, "audit.forward") {
+            Some(FaultMode::Delay { delay }) => tokio::time::sleep(delay.as_std()).await,
+            Some(FaultMode::Drop) => continue,
+            Some(FaultMode::Error) => {
+                counters.failed.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            Some(FaultMode::Corrupt) | None => {}
+        }
+        let mut delivered = true;
+        for sink in &mut sinks {
+            let result = tokio::time::timeout(SEND_TIMEOUT, sink.send(&record))
+                .await
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out")));
+            if let Err(e) = result {
+                delivered = false;
+                counters.failed.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!("📤 {} forwarding failed: {}", sink.name(), e);
+            }
+        }
+        if delivered {
+            counters.forwarded.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// RFC 5424 line for `record`; the message is CEF with `RecordFormat::Cef`
+pub fn format_syslog(record: &ForwardedEvent, config: &SyslogConfig, hostname: &str) -> String {
+    let event = &record.event;
+    let severity = severity_for(event);
+    let priority = facility_for(&event.event_type, config.facility).code() * 8 + severity as u8;
+    let mut line = format!(
+        "<{}>1 {} {} {} {} {} ",
+        priority,
+        event.timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+        header_field(hostname, 255),
+        header_field(&config.app_name, 48),
+        std::process::id(),
+        header_field(&event.event_type, 32),
+    );
+    match config.format {
+        RecordFormat::Rfc5424 => {
+            let _ = write!(
+                line,
+                "[{} peer_id=\"{}\" event_type=\"{}\" security_level=\"{:?}\" event_hash=\"{}\" prev_hash=\"{}\"",
+                SD_ID,
+                sd_value(&event.peer_id),
+                sd_value(&event.event_type),
+                event.security_level,
+                record.hash,
+                event.prev_hash,
+            );
+            if let Some(trace_id) = &event.trace_id {
+                let _ = write!(line, " trace_id=\"{}\"", sd_value(trace_id));
+            }
+            let mut details: Vec<_> = event.details.iter().collect();
+            details.sort();
+            for (key, value) in details {
+                let _ = write!(line, " detail.{}=\"{}\"", sd_name(key), sd_value(value));
+            }
+            let _ = write!(line, "] {} {} ({:?})", event.event_type, event.peer_id, event.security_level);
+        }
+        RecordFormat::Cef => {
+            line.push_str("- ");
+            line.push_str(&format_cef(record));
+        }
+    }
+    line
+}
+
+/// CEF:0 record: event type as signature ID, peer as `suser`, the level and
+/// chain hashes as custom strings, details in `msg`
+pub fn format_cef(record: &ForwardedEvent) -> String {
+    let event = &record.event;
+    let mut details: Vec<_> = event.details.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+    details.sort();
+    let mut cef = format!(
+        "CEF:0|GitMonsters|Quantra|{}|{}|{}|{}|",
+        cef_header(env!("CARGO_PKG_VERSION")),
+        cef_header(&event.event_type),
+        cef_header(&event.event_type.replace('_', " ")),
+        severity_for(event).cef(),
+    );
+    let mut extensions = vec![
+        ("rt", event.timestamp.timestamp_millis().to_string()),
+        ("suser", event.peer_id.clone()),
+        ("cs1Label", "securityLevel".to_string()),
+        ("cs1", format!("{:?}", event.security_level)),
+        ("cs2Label", "eventHash".to_string()),
+        ("cs2", record.hash.clone()),
+        ("cs3Label", "prevHash".to_string()),
+        ("cs3", event.prev_hash.clone()),
+    ];
+    if let Some(trace_id) = &event.trace_id {
+        extensions.push(("cs4Label", "traceId".to_string()));
+        extensions.push(("cs4", trace_id.clone()));
+    }
+    let mut first = true;
+    for (key, value) in extensions.iter().map(|(k, v)| (*k, v.as_str())).chain(
+        (!details.is_empty()).then(|| details.join("; ")).as_deref().map(|msg| ("msg", msg)),
+    ) {
+        if !first {
+            cef.push(' ');
+        }
+        first = false;
+        let _ = write!(cef, "{}={}", key, cef_extension(value));
+    }
+    cef
+}
+
+/// Printable ASCII without spaces, truncated; `-` when empty
+fn header_field(value: &str, max: usize) -> String {
+    let field: String = value.chars().filter(|c| c.is_ascii_graphic()).take(max).collect();
+    if field.is_empty() {
+        "-".to_string()
+    } else {
+        field
+    }
+}
+
+/// SD-PARAM value escaping (RFC 5424 §6.3.3)
+fn sd_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// SD-NAME: up to 32 printable ASCII characters except `=`, space, `]`, `"`
+fn sd_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_graphic() && !matches!(c, '=' | ']' | '"'))
+        .take(32 - "detail.".len())
+        .collect()
+}
+
+fn cef_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn cef_extension(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+enum Connection {
+    Udp(tokio::net::UdpSocket),
+    Tcp(tokio::net::TcpStream),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixDatagram),
+}
+
+impl Connection {
+    async fn open(transport: SyslogTransport, address: &str) -> Result<Self> {
+        Ok(match transport {
+            SyslogTransport::Udp => {
+                let target: std::net::SocketAddr = address.parse()?;
+                let local = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+                let socket = tokio::net::UdpSocket::bind(local).await?;
+                socket.connect(target).await?;
+                Connection::Udp(socket)
+            }
+            SyslogTransport::Tcp => Connection::Tcp(tokio::net::TcpStream::connect(address).await?),
+            #[cfg(unix)]
+            SyslogTransport::Unix => {
+                let socket = tokio::net::UnixDatagram::unbound()?;
+                socket.connect(address)?;
+                Connection::Unix(socket)
+            }
+            #[cfg(not(unix))]
+            SyslogTransport::Unix => anyhow::bail!("Unix syslog sockets are not available on this platform"),
+        })
+    }
+
+    async fn send(&mut self, line: &[u8]) -> std::io::Result<()> {
+        match self {
+            Connection::Udp(socket) => socket.send(line).await.map(|_| ()),
+            Connection::Tcp(stream) => {
+                stream.write_all(format!("{} ", line.len()).as_bytes()).await?;
+                stream.write_all(line).await
+            }
+            #[cfg(unix)]
+            Connection::Unix(socket) => socket.send(line).await.map(|_| ()),
+        }
+    }
+}
+
+struct SyslogSink {
+    config: SyslogConfig,
+    hostname: String,
+    /// Opened on first send, and again after a failed one
+    connection: Option<Connection>,
+}
+
+impl SyslogSink {
+    fn new(config: &SyslogConfig) -> Result<Self> {
+        match config.transport {
+            SyslogTransport::Udp | SyslogTransport::Tcp => {
+                config
+                    .address
+                    .parse::<std::net::SocketAddr>()
+                    .with_context(|| format!("Invalid syslog address '{}' (expected host:port)", config.address))?;
+            }
+            SyslogTransport::Unix if !cfg!(unix) => {
+                anyhow::bail!("Unix syslog sockets are not available on this platform")
+            }
+            SyslogTransport::Unix => {}
+        }
+        Ok(Self {
+            config: config.clone(),
+            hostname: sysinfo::System::host_name().unwrap_or_default(),
+            connection: None,
+        })
+    }
+}
+
+#[async_trait]
+impl AuditSink for SyslogSink {
+    fn name(&self) -> &'static str {
+        "syslog"
+    }
+
+    async fn send(&mut self, record: &ForwardedEvent) -> Result<()> {
+        let line = format_syslog(record, &self.config, &self.hostname);
+        let connection = match &mut self.connection {
+            Some(connection) => connection,
+            slot @ None => slot.insert(Connection::open(self.config.transport, &self.config.address).await?),
+        };
+        if let Err(e) = connection.send(line.as_bytes()).await {
+            self.connection = None;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+/// systemd journal native protocol: one datagram of `FIELD=value` lines
+struct JournaldSink {
+    socket: PathBuf,
+}
+
+/// Journal fields for `record`
+pub fn journald_fields(record: &ForwardedEvent) -> Vec<(String, String)> {
+    let event = &record.event;
+    let mut fields = vec![
+        ("MESSAGE".to_string(), format!("{} {} ({:?})", event.event_type, event.peer_id, event.security_level)),
+        ("PRIORITY".to_string(), (severity_for(event) as u8).to_string()),
+        ("SYSLOG_IDENTIFIER".to_string(), "quantra".to_string()),
+        ("PEER_ID".to_string(), event.peer_id.clone()),
+        ("EVENT_TYPE".to_string(), event.event_type.clone()),
+        ("SECURITY_LEVEL".to_string(), format!("{:?}", event.security_level)),
+        ("EVENT_HASH".to_string(), record.hash.clone()),
+        ("PREV_HASH".to_string(), event.prev_hash.clone()),
+    ];
+    if let Some(trace_id) = &event.trace_id {
+        fields.push(("TRACE_ID".to_string(), trace_id.clone()));
+    }
+    let mut details: Vec<_> = event.details.iter().collect();
+    details.sort();
+    for (key, value) in details {
+        let name: String = key
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect();
+        fields.push((format!("DETAIL_{}", name), value.clone()));
+    }
+    fields
+}
+
+/// Serialize fields; values containing newlines use the length-prefixed form
+fn journald_datagram(fields: &[(String, String)]) -> Vec<u8> {
+    let mut datagram = Vec::new();
+    for (name, value) in fields {
+        datagram.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            datagram.push(b'\n');
+            datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            datagram.push(b'=');
+        }
+        datagram.extend_from_slice(value.as_bytes());
+        datagram.push(b'\n');
+    }
+    datagram
+}
+
+#[async_trait]
+impl AuditSink for JournaldSink {
+    fn name(&self) -> &'static str {
+        "journald"
+    }
+
+    #[cfg(unix)]
+    async fn send(&mut self, record: &ForwardedEvent) -> Result<()> {
+        let socket = tokio::net::UnixDatagram::unbound()?;
+        socket.send_to(&journald_datagram(&journald_fields(record)), &self.socket).await?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    async fn send(&mut self, _record: &ForwardedEvent) -> Result<()> {
+        anyhow::bail!("journald is only available on Linux")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::faults::Injection;
+    use crate::units::HumanDuration;
+    use crate::zerotrust::audit::{AuditLogger, MemoryAuditStore};
+    use crate::zerotrust::SecurityLevel;
+    use std::collections::HashMap;
+    use tokio::net::UdpSocket;
+
+    fn event(event_type: &str, details: &[(&str, &str)]) -> SecurityEvent {
+        SecurityEvent {
+            timestamp: chrono::Utc::now(),
+            event_type: event_type.to_string(),
+            peer_id: "peer-a".to_string(),
+            security_level: SecurityLevel::Basic,
+            details: details.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            prev_hash: String::new(),
+            trace_id: None,
+        }
+    }
+
+    async fn collector() -> (UdpSocket, ForwardingConfig) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut config = ForwardingConfig::default();
+        config.syslog.enabled = true;
+        config.syslog.address = socket.local_addr().unwrap().to_string();
+        (socket, config)
+    }
+
+    async fn receive(socket: &UdpSocket) -> String {
+        let mut buf = vec![0u8; 4096];
+        let n = tokio::time::timeout(Duration::from_secs(5), socket.recv(&mut buf)).await.unwrap().unwrap();
+        String::from_utf8(buf[..n].to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_syslog_records_match_local_chain() {
+        let (socket, config) = collector().await;
+        let mut logger = AuditLogger::with_store(Box::new(MemoryAuditStore::new())).await.unwrap();
+        logger.set_forwarder(AuditForwarder::start_with_faults(&config, Arc::new(FaultRegistry::new())).unwrap());
+
+        logger.log(event("policy_denied", &[("reason", "quota \"exceeded\" [x]")])).await.unwrap();
+        logger.log(event("sandbox_resized", &[])).await.unwrap();
+        let denied = receive(&socket).await;
+        let resized = receive(&socket).await;
+
+        // authpriv.warning = 10 * 8 + 4; local0.info = 16 * 8 + 6
+        assert!(denied.starts_with("<84>1 "), "{}", denied);
+        assert!(resized.starts_with("<134>1 "), "{}", resized);
+        let header: Vec<_> = denied.splitn(7, ' ').collect();
+        assert_eq!((header[3], header[5]), ("quantra", "policy_denied"));
+        assert!(denied.contains("[quantra@32473 peer_id=\"peer-a\" event_type=\"policy_denied\" security_level=\"Basic\""));
+        assert!(denied.contains(r#"detail.reason="quota \"exceeded\" [x\]"]"#), "{}", denied);
+
+        // Each record's hash is the next local event's chain link
+        logger.flush().await.unwrap();
+        let events = logger.read_events().await.unwrap();
+        let denied_hash = &events[1].prev_hash;
+        assert!(denied.contains(&format!("event_hash=\"{}\"", denied_hash)));
+        assert!(resized.contains(&format!("prev_hash=\"{}\"", denied_hash)));
+        assert_eq!(logger.forwarding_stats().unwrap().forwarded, 2);
+    }
+
+    #[test]
+    fn test_cef_and_journald_formats() {
+        let mut record = ForwardedEvent {
+            event: event("identity_verification_failed", &[("note", "a=b|c\nd")]),
+            hash: "ab12".to_string(),
+        };
+        record.event.peer_id = "peer=x".to_string();
+        let config = SyslogConfig { format: RecordFormat::Cef, ..Default::default() };
+
+        let line = format_syslog(&record, &config, "host");
+        let cef = line.split_once("- ").unwrap().1;
+        assert!(line.starts_with("<84>1 "));
+        assert!(cef.starts_with(&format!(
+            "CEF:0|GitMonsters|Quantra|{}|identity_verification_failed|identity verification failed|7|rt=",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert!(cef.contains(r"suser=peer\=x cs1Label=securityLevel cs1=Basic cs2Label=eventHash cs2=ab12"));
+        assert!(cef.ends_with(r"msg=note: a\=b|c\nd"), "{}", cef);
+
+        let fields: HashMap<_, _> = journald_fields(&record).into_iter().collect();
+        assert_eq!(fields["PEER_ID"], "peer=x");
+        assert_eq!(fields["EVENT_TYPE"], "identity_verification_failed");
+        assert_eq!(fields["SECURITY_LEVEL"], "Basic");
+        assert_eq!(fields["EVENT_HASH"], "ab12");
+        assert_eq!(fields["PRIORITY"], "4");
+        // Multi-line values use the length-prefixed encoding
+        let datagram = journald_datagram(&[("DETAIL_NOTE".to_string(), "a\nb".to_string())]);
+        assert_eq!(datagram, [&b"DETAIL_NOTE\n"[..], &3u64.to_le_bytes(), b"a\nb\n"].concat());
+    }
+
+    #[tokio::test]
+    async fn test_stalled_collector_drops_are_counted() {
+        let (socket, mut config) = collector().await;
+        config.queue_size = 2;
+        let registry = Arc::new(FaultRegistry::new());
+        registry
+            .arm(Injection::new("audit.forward", FaultMode::Delay { delay: HumanDuration::from_millis(100) }))
+            .unwrap();
+        let mut logger = AuditLogger::with_store(Box::new(MemoryAuditStore::new())).await.unwrap();
+        logger.set_forwarder(AuditForwarder::start_with_faults(&config, registry).unwrap());
+
+        // The audit path never waits on the stalled forwarder
+        let started = std::time::Instant::now();
+        for i in 0..20 {
+            logger.log(event(&format!("stall_{}", i), &[])).await.unwrap();
+        }
+        assert!(started.elapsed() < Duration::from_millis(100));
+        let dropped = logger.forwarding_stats().unwrap().dropped;
+        assert!(dropped >= 17, "only {} dropped", dropped);
+
+        // Whatever was queued still arrives, in order
+        let mut received = Vec::new();
+        for _ in 0..(20 - dropped) {
+            received.push(receive(&socket).await);
+        }
+        assert!(received[0].contains(" stall_0 "));
+        let stats = logger.forwarding_stats().unwrap();
+        assert_eq!((stats.forwarded + stats.dropped, stats.failed), (20, 0));
+    }
+}
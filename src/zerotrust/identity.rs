@@ -1,9 +1,12 @@
 use anyhow::{Result, Context};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use chrono::{DateTime, Utc, Duration};
 use sha2::{Sha256, Digest};
-use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use ed25519_dalek::{Signature, SigningKey, Verifier, VerifyingKey};
+
+use super::clock::PeerClock;
+use crate::crypto::key_provider::{FileKeyProvider, KeyProvider};
 
 /// Identity represents a verified user/peer identity
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -14,15 +17,76 @@ pub struct Identity {
     pub issued_at: DateTime<Utc>,
     pub expires_at: DateTime<Utc>,
     pub signature: Vec<u8>,
+    /// Fingerprint of the identity this one renews
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_fingerprint: Option<String>,
+}
+
+impl Identity {
+    /// SHA-256 over the signed fields and signature, hex encoded
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.signing_message());
+        hasher.update(&self.signature);
+        hex::encode(hasher.finalize())
+    }
+
+    /// Expires within `threshold` of `now` (or already has)
+    pub fn expires_within(&self, threshold: Duration, now: DateTime<Utc>) -> bool {
+        self.expires_at - now <= threshold
+    }
+
+    /// Bytes covered by the signature; the renewal link is only included
+    /// when present, so first-generation signatures are unchanged
+    fn signing_message(&self) -> Vec<u8> {
+        let mut message = Vec::new();
+        message.extend_from_slice(self.user_id.as_bytes());
+        message.extend_from_slice(&self.public_key);
+        message.extend_from_slice(self.issued_at.to_rfc3339().as_bytes());
+        message.extend_from_slice(self.expires_at.to_rfc3339().as_bytes());
+        if let Some(previous) = &self.previous_fingerprint {
+            message.extend_from_slice(previous.as_bytes());
+        }
+        message
+    }
+}
+
+/// Identity validity period
+pub const IDENTITY_VALIDITY_DAYS: i64 = 365;
+
+/// Outcome of checking an identity's signature and validity period
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentityStatus {
+    Valid,
+    /// Expired, but within the grace period: accepted at reduced trust
+    /// until the peer presents a renewal
+    GracePeriod,
+    Invalid,
 }
 
 /// Trust score for an identity (0-100)
 pub type TrustScore = u8;
 
+/// Trust score changes kept per identity
+const MAX_TRUST_HISTORY: usize = 50;
+
+/// One trust score change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustChange {
+    pub at: DateTime<Utc>,
+    pub from: TrustScore,
+    pub to: TrustScore,
+}
+
 /// Identity Manager handles identity verification and trust scoring
 pub struct IdentityManager {
     identities: HashMap<String, IdentityRecord>,
     trust_scores: HashMap<String, TrustScore>,
+    trust_history: HashMap<String, VecDeque<TrustChange>>,
+    /// How long past expiry an identity is still accepted at reduced trust
+    grace_period: Duration,
+    /// Own identities are renewed once this close to expiry
+    renew_before: Duration,
 }
 
 #[derive(Debug, Clone)]
@@ -32,6 +96,8 @@ struct IdentityRecord {
     last_seen: DateTime<Utc>,
     connection_count: u32,
     verification_failures: u32,
+    /// Fingerprints of the identities this one renewed, oldest first
+    previous_fingerprints: Vec<String>,
 }
 
 impl IdentityManager {
@@ -39,15 +105,56 @@ impl IdentityManager {
         Ok(Self {
             identities: HashMap::new(),
             trust_scores: HashMap::new(),
+            trust_history: HashMap::new(),
+            grace_period: Duration::zero(),
+            renew_before: Duration::days(30),
         })
     }
 
+    pub fn set_renewal_windows(&mut self, grace_period: Duration, renew_before: Duration) {
+        self.grace_period = grace_period;
+        self.renew_before = renew_before;
+    }
+
+    pub fn renew_before(&self) -> Duration {
+        self.renew_before
+    }
+
     /// Verify identity using cryptographic signature
     pub async fn verify_identity(&self, identity: &Identity) -> Result<bool> {
-        // Check expiration
-        if identity.expires_at < Utc::now() {
-            tracing::warn!("Identity expired for user: {}", identity.user_id);
-            return Ok(false);
+        self.verify_identity_with_clock(identity, &PeerClock::exact()).await
+    }
+
+    /// Verify identity, reading its validity period on the issuer's clock
+    pub async fn verify_identity_with_clock(&self, identity: &Identity, clock: &PeerClock) -> Result<bool> {
+        Ok(self.check_identity_with_clock(identity, clock).await? == IdentityStatus::Valid)
+    }
+
+    /// Check an identity like `verify_identity_with_clock`, but report
+    /// identities expired by less than the grace period separately
+    pub async fn check_identity_with_clock(&self, identity: &Identity, clock: &PeerClock) -> Result<IdentityStatus> {
+        let now = Utc::now();
+        if clock.exceeds_max_skew() {
+            tracing::warn!(
+                "Clock skew {}s exceeds maximum for user: {}",
+                clock.offset.num_seconds(),
+                identity.user_id
+            );
+            return Ok(IdentityStatus::Invalid);
+        }
+
+        // Check validity period
+        let mut status = IdentityStatus::Valid;
+        if !clock.before_deadline(identity.expires_at, now) {
+            if !clock.before_deadline(identity.expires_at + self.grace_period, now) {
+                tracing::warn!("Identity expired for user: {}", identity.user_id);
+                return Ok(IdentityStatus::Invalid);
+            }
+            status = IdentityStatus::GracePeriod;
+        }
+        if !clock.has_started(identity.issued_at, now) {
+            tracing::warn!("Identity not yet valid for user: {}", identity.user_id);
+            return Ok(IdentityStatus::Invalid);
         }
 
         // Verify signature
@@ -55,21 +162,43 @@ impl IdentityManager {
 
         if !is_valid {
             tracing::warn!("Invalid signature for user: {}", identity.user_id);
-            return Ok(false);
+            return Ok(IdentityStatus::Invalid);
         }
 
         // Check if identity is revoked
         if self.is_revoked(&identity.user_id).await? {
             tracing::warn!("Identity revoked for user: {}", identity.user_id);
-            return Ok(false);
+            return Ok(IdentityStatus::Invalid);
         }
 
-        tracing::info!("✅ Identity verified for user: {}", identity.user_id);
-        Ok(true)
+        if status == IdentityStatus::GracePeriod {
+            tracing::warn!(
+                "⏳ Identity for user {} expired at {}, accepted within grace period",
+                identity.user_id,
+                identity.expires_at
+            );
+        } else {
+            tracing::info!("✅ Identity verified for user: {}", identity.user_id);
+        }
+        Ok(status)
     }
 
-    /// Register a new identity
+    /// Register a new identity. A renewal of the registered identity
+    /// (`previous_fingerprint` naming it, same key) keeps its trust score,
+    /// history and connection record.
     pub async fn register_identity(&mut self, identity: Identity) -> Result<()> {
+        if let Some(record) = self.identities.get_mut(&identity.user_id) {
+            let previous = record.identity.fingerprint();
+            if identity.previous_fingerprint.as_deref() == Some(previous.as_str())
+                && identity.public_key == record.identity.public_key
+            {
+                tracing::info!("🔄 Renewed identity for {} (expires {})", identity.user_id, identity.expires_at);
+                record.previous_fingerprints.push(previous);
+                record.identity = identity;
+                return Ok(());
+            }
+        }
+
         // ✅ OPTIMIZATION: Move instead of clone to reduce memory allocations
         // Before: 3 clones (identity + 2x user_id) = ~500 bytes cloned
         // After: 1 clone (user_id only) = ~20 bytes cloned
@@ -83,6 +212,7 @@ impl IdentityManager {
             last_seen: Utc::now(),
             connection_count: 0,
             verification_failures: 0,
+            previous_fingerprints: Vec::new(),
         };
 
         self.identities.insert(user_id.clone(), record);
@@ -120,6 +250,28 @@ impl IdentityManager {
         Ok(base_score.saturating_add(bonus_score).min(100))
     }
 
+    /// Raw trust score for a registered user, if one has been assigned
+    pub fn get_trust_score(&self, user_id: &str) -> Option<TrustScore> {
+        self.trust_scores.get(user_id).copied()
+    }
+
+    /// Fingerprints of a user's registered identity and the ones it
+    /// renewed, newest first
+    pub fn identity_lineage(&self, user_id: &str) -> Vec<String> {
+        let Some(record) = self.identities.get(user_id) else { return Vec::new() };
+        std::iter::once(record.identity.fingerprint())
+            .chain(record.previous_fingerprints.iter().rev().cloned())
+            .collect()
+    }
+
+    /// Recent trust score changes, oldest first
+    pub fn trust_history(&self, user_id: &str) -> Vec<TrustChange> {
+        self.trust_history
+            .get(user_id)
+            .map(|h| h.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
     /// Update trust score for an identity
     pub async fn update_trust(&mut self, user_id: &str, delta: i8) -> Result<()> {
         let current = self.trust_scores.get(user_id).copied().unwrap_or(50);
@@ -130,6 +282,15 @@ impl IdentityManager {
         };
 
         self.trust_scores.insert(user_id.to_string(), new_score);
+        let history = self.trust_history.entry(user_id.to_string()).or_default();
+        if history.len() >= MAX_TRUST_HISTORY {
+            history.pop_front();
+        }
+        history.push_back(TrustChange {
+            at: Utc::now(),
+            from: current,
+            to: new_score,
+        });
 
         tracing::info!(
             "Updated trust score for {}: {} → {} (Δ{})",
@@ -198,15 +359,8 @@ impl IdentityManager {
 
         let signature = Signature::from_bytes(&signature_bytes);
 
-        // Create message to verify (same as during signing)
-        let mut message = Vec::new();
-        message.extend_from_slice(identity.user_id.as_bytes());
-        message.extend_from_slice(&identity.public_key);
-        message.extend_from_slice(identity.issued_at.to_rfc3339().as_bytes());
-        message.extend_from_slice(identity.expires_at.to_rfc3339().as_bytes());
-
         // ✅ REAL CRYPTOGRAPHIC VERIFICATION
-        match public_key.verify(&message, &signature) {
+        match public_key.verify(&identity.signing_message(), &signature) {
             Ok(_) => {
                 tracing::debug!("✅ Signature verified for user: {}", identity.user_id);
                 Ok(true)
@@ -220,6 +374,15 @@ impl IdentityManager {
 
     /// Create a new identity with real Ed25519 signing
     pub fn create_identity(user_id: String, attributes: HashMap<String, String>) -> Identity {
+        Self::create_identity_at(user_id, attributes, Utc::now())
+    }
+
+    /// Create an identity valid for a year from `issued_at`
+    pub fn create_identity_at(
+        user_id: String,
+        attributes: HashMap<String, String>,
+        issued_at: DateTime<Utc>,
+    ) -> Identity {
         use rand::RngCore;
 
         // ✅ FIXED: Generate real Ed25519 keypair (was: mock key)
@@ -227,33 +390,11 @@ impl IdentityManager {
         let mut secret_bytes = [0u8; 32];
         csprng.fill_bytes(&mut secret_bytes);
 
-        let signing_key = SigningKey::from_bytes(&secret_bytes);
-        let verifying_key = signing_key.verifying_key();
-        let public_key = verifying_key.to_bytes().to_vec();
-
-        let issued_at = Utc::now();
-        let expires_at = issued_at + Duration::days(365);
-
-        // Create message to sign
-        let mut message = Vec::new();
-        message.extend_from_slice(user_id.as_bytes());
-        message.extend_from_slice(&public_key);
-        message.extend_from_slice(issued_at.to_rfc3339().as_bytes());
-        message.extend_from_slice(expires_at.to_rfc3339().as_bytes());
-
-        // ✅ REAL SIGNATURE using Ed25519
-        let signature = signing_key.sign(&message).to_bytes().to_vec();
+        let signing_key = FileKeyProvider::new(SigningKey::from_bytes(&secret_bytes));
+        let identity = Self::sign_in_memory(user_id, attributes, &signing_key, issued_at);
 
-        tracing::info!("✅ Created identity with real Ed25519 signature for: {}", user_id);
-
-        Identity {
-            user_id,
-            public_key,
-            attributes,
-            issued_at,
-            expires_at,
-            signature,
-        }
+        tracing::info!("✅ Created identity with real Ed25519 signature for: {}", identity.user_id);
+        identity
     }
 
     /// Create identity from existing keypair (for production use)
@@ -262,30 +403,72 @@ impl IdentityManager {
         attributes: HashMap<String, String>,
         signing_key: &SigningKey,
     ) -> Identity {
-        let verifying_key = signing_key.verifying_key();
-        let public_key = verifying_key.to_bytes().to_vec();
+        Self::sign_in_memory(user_id, attributes, &FileKeyProvider::new(signing_key.clone()), Utc::now())
+    }
 
-        let issued_at = Utc::now();
-        let expires_at = issued_at + Duration::days(365);
+    /// Create an identity signed by a key provider (HSM, TPM or file)
+    pub fn create_identity_with_provider(
+        user_id: String,
+        attributes: HashMap<String, String>,
+        provider: &dyn KeyProvider,
+    ) -> Result<Identity> {
+        Self::sign_identity(user_id, attributes, provider, Utc::now(), None)
+    }
 
-        // Create message to sign
-        let mut message = Vec::new();
-        message.extend_from_slice(user_id.as_bytes());
-        message.extend_from_slice(&public_key);
-        message.extend_from_slice(issued_at.to_rfc3339().as_bytes());
-        message.extend_from_slice(expires_at.to_rfc3339().as_bytes());
+    /// Successor to `old`, signed by the same key, valid for a year from
+    /// now and linked to `old` by fingerprint
+    pub fn renew_identity(old: &Identity, signing_key: &SigningKey) -> Result<Identity> {
+        Self::renew_identity_with_provider(old, &FileKeyProvider::new(signing_key.clone()))
+    }
+
+    pub fn renew_identity_with_provider(old: &Identity, provider: &dyn KeyProvider) -> Result<Identity> {
+        if provider.public_key().to_bytes()[..] != old.public_key[..] {
+            anyhow::bail!("Signing key does not match identity for user: {}", old.user_id);
+        }
+        let renewed = Self::sign_identity(
+            old.user_id.clone(),
+            old.attributes.clone(),
+            provider,
+            Utc::now(),
+            Some(old.fingerprint()),
+        )?;
+        tracing::info!("🔄 Renewed identity for {} until {}", renewed.user_id, renewed.expires_at);
+        Ok(renewed)
+    }
 
-        // Sign with provided key
-        let signature = signing_key.sign(&message).to_bytes().to_vec();
+    /// Signing with an in-memory key can't fail
+    fn sign_in_memory(
+        user_id: String,
+        attributes: HashMap<String, String>,
+        signing_key: &FileKeyProvider,
+        issued_at: DateTime<Utc>,
+    ) -> Identity {
+        Self::sign_identity(user_id, attributes, signing_key, issued_at, None)
+            .expect("in-memory Ed25519 signing is infallible")
+    }
 
-        Identity {
+    fn sign_identity(
+        user_id: String,
+        attributes: HashMap<String, String>,
+        provider: &dyn KeyProvider,
+        issued_at: DateTime<Utc>,
+        previous_fingerprint: Option<String>,
+    ) -> Result<Identity> {
+        let mut identity = Identity {
             user_id,
-            public_key,
+            public_key: provider.public_key().to_bytes().to_vec(),
             attributes,
             issued_at,
-            expires_at,
-            signature,
-        }
+            expires_at: issued_at + Duration::days(IDENTITY_VALIDITY_DAYS),
+            signature: Vec::new(),
+            previous_fingerprint,
+        };
+        identity.signature = provider
+            .sign(&identity.signing_message())
+            .with_context(|| format!("Failed to sign identity for {} with the {} key provider", identity.user_id, provider.name()))?
+            .to_bytes()
+            .to_vec();
+        Ok(identity)
     }
 }
 
@@ -308,6 +491,89 @@ mod tests {
         assert!(is_valid);
     }
 
+    #[tokio::test]
+    async fn test_skewed_issuer_clock() {
+        let manager = IdentityManager::new().unwrap();
+        let skew = Duration::minutes(2);
+        let clock = PeerClock {
+            offset: skew,
+            tolerance: Duration::seconds(5),
+            max_skew: Duration::minutes(5),
+        };
+
+        // Issued by a peer whose clock runs two minutes ahead
+        let ahead = IdentityManager::create_identity_at("skewed".to_string(), HashMap::new(), Utc::now() + skew);
+        assert!(!manager.verify_identity(&ahead).await.unwrap());
+        assert!(manager.verify_identity_with_clock(&ahead, &clock).await.unwrap());
+
+        // Expired a minute ago by that peer's clock, still a minute away by ours
+        let issued_at = Utc::now() + skew - Duration::minutes(1) - Duration::days(365);
+        let expired = IdentityManager::create_identity_at("skewed".to_string(), HashMap::new(), issued_at);
+        assert!(manager.verify_identity(&expired).await.unwrap());
+        assert!(!manager.verify_identity_with_clock(&expired, &clock).await.unwrap());
+
+        let too_far = PeerClock { offset: Duration::minutes(10), ..clock };
+        let far_ahead = IdentityManager::create_identity_at("skewed".to_string(), HashMap::new(), Utc::now() + Duration::minutes(10));
+        assert!(!manager.verify_identity_with_clock(&far_ahead, &too_far).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_renewal_keeps_trust_history() {
+        let mut manager = IdentityManager::new().unwrap();
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let original = IdentityManager::create_identity_with_key("renewing".to_string(), HashMap::new(), &signing_key);
+        manager.register_identity(original.clone()).await.unwrap();
+        manager.update_trust("renewing", 20).await.unwrap();
+        manager.record_connection("renewing").await.unwrap();
+
+        let renewed = IdentityManager::renew_identity(&original, &signing_key).unwrap();
+        assert_eq!(renewed.previous_fingerprint, Some(original.fingerprint()));
+        assert_eq!(renewed.public_key, original.public_key);
+        assert!(renewed.expires_at > original.expires_at);
+        assert!(manager.verify_identity(&renewed).await.unwrap());
+
+        manager.register_identity(renewed.clone()).await.unwrap();
+        assert_eq!(manager.get_trust_score("renewing"), Some(71));
+        assert_eq!(manager.trust_history("renewing").len(), 2);
+        assert_eq!(manager.identity_lineage("renewing"), vec![renewed.fingerprint(), original.fingerprint()]);
+
+        // Tampering with the link breaks the signature
+        let mut forged = renewed.clone();
+        forged.previous_fingerprint = Some("00".repeat(32));
+        assert!(!manager.verify_identity(&forged).await.unwrap());
+        // Only the identity's own key can renew it
+        assert!(IdentityManager::renew_identity(&renewed, &SigningKey::from_bytes(&[8u8; 32])).is_err());
+
+        // An unrelated identity for the same user starts over
+        let other = IdentityManager::create_identity("renewing".to_string(), HashMap::new());
+        manager.register_identity(other).await.unwrap();
+        assert_eq!(manager.get_trust_score("renewing"), Some(50));
+        assert_eq!(manager.identity_lineage("renewing").len(), 1);
+        println!("✅ Identity renewal linkage test PASSED!");
+    }
+
+    #[tokio::test]
+    async fn test_grace_period_status() {
+        let mut manager = IdentityManager::new().unwrap();
+        manager.set_renewal_windows(Duration::days(7), Duration::days(30));
+        let expired_ago = |ago: Duration| {
+            let issued_at = Utc::now() - Duration::days(IDENTITY_VALIDITY_DAYS) - ago;
+            IdentityManager::create_identity_at("lapsed".to_string(), HashMap::new(), issued_at)
+        };
+        let clock = PeerClock::exact();
+
+        let recent = expired_ago(Duration::days(1));
+        assert_eq!(manager.check_identity_with_clock(&recent, &clock).await.unwrap(), IdentityStatus::GracePeriod);
+        assert!(!manager.verify_identity(&recent).await.unwrap());
+
+        let lapsed = expired_ago(Duration::days(8));
+        assert_eq!(manager.check_identity_with_clock(&lapsed, &clock).await.unwrap(), IdentityStatus::Invalid);
+
+        let fresh = IdentityManager::create_identity("lapsed".to_string(), HashMap::new());
+        assert!(!fresh.expires_within(Duration::days(30), Utc::now()));
+        assert!(fresh.expires_within(Duration::days(366), Utc::now()));
+    }
+
     #[tokio::test]
     async fn test_trust_score() {
         let mut manager = IdentityManager::new().unwrap();
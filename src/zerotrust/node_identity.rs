@@ -0,0 +1,198 @@
+//! Node Identity
+//! This node's own zero-trust identity and signing key, persisted so that
+//! renewals keep the same key and link back to the identity they replace
+
+use anyhow::{Context, Result};
+use chrono::{Duration, Utc};
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::identity::{Identity, IdentityManager};
+use crate::crypto::key_provider::{FileKeyProvider, KeyProvider};
+use crate::migrations::{self, StoreSchema};
+use crate::storage::{KvStore, RuntimeMode};
+
+const NODE_IDENTITY_TREE: &str = "node_identity";
+const CURRENT_KEY: &[u8] = b"current";
+
+pub const SCHEMA: StoreSchema = StoreSchema {
+    name: "node_identity",
+    tree: Some(NODE_IDENTITY_TREE),
+    version: 1,
+    migrations: &[],
+    index: None,
+};
+
+#[derive(Serialize, Deserialize)]
+struct StoredIdentity {
+    /// Empty when the key lives in a key provider
+    #[serde(default)]
+    signing_key: Vec<u8>,
+    identity: Identity,
+}
+
+pub struct NodeIdentity {
+    db: Box<dyn KvStore>,
+    signer: Arc<dyn KeyProvider>,
+    /// Whether the secret key is stored alongside the identity
+    owns_key: bool,
+    identity: Identity,
+}
+
+impl NodeIdentity {
+    /// Load the identity stored at `path`, or create one for `user_id`
+    pub fn open(path: &Path, mode: RuntimeMode, user_id: &str) -> Result<Self> {
+        let db = migrations::open_store(mode, path, &SCHEMA)
+            .with_context(|| format!("Failed to open node identity at {}", path.display()))?;
+
+        if let Some(bytes) = db.get(CURRENT_KEY)? {
+            let stored: StoredIdentity = serde_json::from_slice(&bytes).context("Corrupt node identity")?;
+            let secret: [u8; 32] = stored
+                .signing_key
+                .as_slice()
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Corrupt node identity signing key"))?;
+            tracing::info!(
+                "🆔 Loaded node identity for {} (expires {})",
+                stored.identity.user_id,
+                stored.identity.expires_at
+            );
+            return Ok(Self {
+                db,
+                signer: Arc::new(FileKeyProvider::new(SigningKey::from_bytes(&secret))),
+                owns_key: true,
+                identity: stored.identity,
+            });
+        }
+
+        let mut secret = [0u8; 32];
+        rand::RngCore::fill_bytes(&mut rand::rngs::OsRng, &mut secret);
+        let signing_key = SigningKey::from_bytes(&secret);
+        let identity = IdentityManager::create_identity_with_key(user_id.to_string(), HashMap::new(), &signing_key);
+        let node = Self { db, signer: Arc::new(FileKeyProvider::new(signing_key)), owns_key: true, identity };
+        node.save()?;
+        tracing::info!("🆔 Created node identity for {}", user_id);
+        Ok(node)
+    }
+
+    /// Like `open`, but signed by `provider`, which keeps the key; only the
+    /// identity is stored. A stored identity for a different key (e.g. from
+    /// before moving to an HSM) is replaced
+    pub fn open_with_provider(path: &Path, mode: RuntimeMode, user_id: &str, provider: Arc<dyn KeyProvider>) -> Result<Self> {
+        let db = migrations::open_store(mode, path, &SCHEMA)
+            .with_context(|| format!("Failed to open node identity at {}", path.display()))?;
+        let public_key = provider.public_key().to_bytes();
+
+        if let Some(bytes) = db.get(CURRENT_KEY)? {
+            let stored: StoredIdentity = serde_json::from_slice(&bytes).context("Corrupt node identity")?;
+            if stored.identity.public_key[..] == public_key[..] {
+                tracing::info!(
+                    "🆔 Loaded node identity for {} from the {} key provider (expires {})",
+                    stored.identity.user_id,
+                    provider.name(),
+                    stored.identity.expires_at
+                );
+                return Ok(Self { db, signer: provider, owns_key: false, identity: stored.identity });
+            }
+            tracing::warn!(
+                "⚠️  Stored node identity for {} uses a different key than the {} key provider; issuing a new one",
+                stored.identity.user_id,
+                provider.name()
+            );
+        }
+
+        let identity = IdentityManager::create_identity_with_provider(user_id.to_string(), HashMap::new(), provider.as_ref())?;
+        let node = Self { db, signer: provider, owns_key: false, identity };
+        node.save()?;
+        tracing::info!("🆔 Created node identity for {} with the {} key provider", user_id, node.signer.name());
+        Ok(node)
+    }
+
+    pub fn identity(&self) -> &Identity {
+        &self.identity
+    }
+
+    /// Signs with the identity key
+    pub fn signer(&self) -> Arc<dyn KeyProvider> {
+        self.signer.clone()
+    }
+
+    /// Renew and persist the identity if it expires within `renew_before`;
+    /// returns the identity it replaced
+    pub fn renew_if_due(&mut self, renew_before: Duration) -> Result<Option<Identity>> {
+        if !self.identity.expires_within(renew_before, Utc::now()) {
+            return Ok(None);
+        }
+        let renewed = IdentityManager::renew_identity_with_provider(&self.identity, self.signer.as_ref())?;
+        let previous = std::mem::replace(&mut self.identity, renewed);
+        if let Err(e) = self.save() {
+            self.identity = previous;
+            return Err(e);
+        }
+        Ok(Some(previous))
+    }
+
+    fn save(&self) -> Result<()> {
+        let signing_key = match self.owns_key {
+            true => self.signer.secret_key().map(|k| k.to_bytes().to_vec()).unwrap_or_default(),
+            false => Vec::new(),
+        };
+        let stored = StoredIdentity {
+            signing_key,
+            identity: self.identity.clone(),
+        };
+        self.db.insert(CURRENT_KEY, &serde_json::to_vec(&stored)?)?;
+        self.db.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_renewal_is_persisted() {
+        let dir = TempDir::new().unwrap();
+        let mut node = NodeIdentity::open(dir.path(), RuntimeMode::Persistent, "node-a").unwrap();
+        let original = node.identity().clone();
+
+        assert!(node.renew_if_due(Duration::days(30)).unwrap().is_none());
+        let replaced = node.renew_if_due(Duration::days(400)).unwrap().unwrap();
+        assert_eq!(replaced, original);
+        drop(node);
+
+        let node = NodeIdentity::open(dir.path(), RuntimeMode::Persistent, "ignored").unwrap();
+        let renewed = node.identity();
+        assert_eq!(renewed.user_id, "node-a");
+        assert_eq!(renewed.public_key, original.public_key);
+        assert_eq!(renewed.previous_fingerprint, Some(original.fingerprint()));
+    }
+
+    #[tokio::test]
+    async fn test_provider_key_is_not_stored() {
+        use crate::crypto::key_provider::testing::SoftHsm;
+
+        let dir = TempDir::new().unwrap();
+        drop(NodeIdentity::open(dir.path(), RuntimeMode::Persistent, "node-a").unwrap());
+
+        let hsm: Arc<dyn KeyProvider> = Arc::new(SoftHsm::new(7));
+        let mut node = NodeIdentity::open_with_provider(dir.path(), RuntimeMode::Persistent, "node-a", hsm.clone()).unwrap();
+        assert_eq!(node.identity().public_key, hsm.public_key().to_bytes().to_vec());
+        assert!(IdentityManager::new().unwrap().verify_identity(node.identity()).await.unwrap());
+        node.renew_if_due(Duration::days(400)).unwrap().unwrap();
+        drop(node);
+
+        let stored: StoredIdentity = {
+            let db = migrations::open_store(RuntimeMode::Persistent, dir.path(), &SCHEMA).unwrap();
+            serde_json::from_slice(&db.get(CURRENT_KEY).unwrap().unwrap()).unwrap()
+        };
+        assert!(stored.signing_key.is_empty());
+        let node = NodeIdentity::open_with_provider(dir.path(), RuntimeMode::Persistent, "ignored", hsm).unwrap();
+        assert_eq!(node.identity().user_id, "node-a");
+        assert!(node.identity().previous_fingerprint.is_some());
+    }
+}
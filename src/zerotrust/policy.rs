@@ -1,10 +1,25 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use crate::zerotrust::{AccessDecision, identity::Identity};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::path::Path;
+use crate::zerotrust::{AccessDecision, audit::SecurityEvent, identity::{Identity, TrustScore}};
+
+/// Audit events that record a policy decision with its inputs
+pub const DECISION_EVENT_TYPES: &[&str] = &["policy_denied", "access_granted", "identity_grace_accepted"];
+
+/// Example events kept per peer and change in a simulation
+const MAX_EXAMPLES: usize = 3;
 
 /// Policy Engine evaluates access requests
 pub struct PolicyEngine {
     policies: Vec<Policy>,
+    /// How many of the last policies are built-ins whose rules the live
+    /// engine does not enforce: each matches whenever a `critical/`
+    /// resource is requested, so the first (VM isolation) decides, as
+    /// before rules were evaluated. Zero for simulated policy sets
+    builtin: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,9 +43,12 @@ pub enum Operator {
     Contains,
     GreaterThan,
     LessThan,
+    /// Dotted versions (`0.2.1`), compared numerically part by part
+    VersionAtLeast,
+    VersionBelow,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PolicyAction {
     Allow,
     Deny,
@@ -38,9 +56,342 @@ pub enum PolicyAction {
     RequireVMIsolation,
 }
 
+/// What a policy decision is made on. Rules read `trust_score`,
+/// `user_id`, `resource` and `resource_type` (the part of a resource
+/// before the first `/`; a rule on either matches if any requested
+/// resource does), or else the identity attribute of that name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyInput {
+    pub user_id: String,
+    pub attributes: BTreeMap<String, String>,
+    /// None for identities that haven't been registered
+    pub trust_score: Option<TrustScore>,
+    pub resources: Vec<String>,
+}
+
+impl PolicyInput {
+    pub fn new(identity: &Identity, trust_score: Option<TrustScore>, resources: &[String]) -> Self {
+        Self {
+            user_id: identity.user_id.clone(),
+            attributes: identity.attributes.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            trust_score,
+            resources: resources.to_vec(),
+        }
+    }
+
+    fn values(&self, attribute: &str) -> Vec<&str> {
+        match attribute {
+            "user_id" => vec![self.user_id.as_str()],
+            "resource" => self.resources.iter().map(String::as_str).collect(),
+            "resource_type" => self.resources.iter().map(|r| r.split('/').next().unwrap_or(r)).collect(),
+            other => self.attributes.get(other).map(String::as_str).into_iter().collect(),
+        }
+    }
+
+    /// Audit event details recording this input, for `from_details`
+    pub fn audit_details(&self) -> HashMap<String, String> {
+        let mut details = HashMap::from([
+            ("policy.user_id".to_string(), self.user_id.clone()),
+            ("policy.resources".to_string(), serde_json::to_string(&self.resources).unwrap_or_default()),
+            ("policy.attributes".to_string(), serde_json::to_string(&self.attributes).unwrap_or_default()),
+        ]);
+        if let Some(score) = self.trust_score {
+            details.insert("policy.trust_score".to_string(), score.to_string());
+        }
+        details
+    }
+
+    /// Rebuild an input recorded by `audit_details`; None for events
+    /// written before inputs were recorded
+    pub fn from_details(details: &HashMap<String, String>) -> Option<Self> {
+        Some(Self {
+            user_id: details.get("policy.user_id")?.clone(),
+            attributes: serde_json::from_str(details.get("policy.attributes")?).ok()?,
+            trust_score: details.get("policy.trust_score").and_then(|s| s.parse().ok()),
+            resources: serde_json::from_str(details.get("policy.resources")?).ok()?,
+        })
+    }
+}
+
+impl Rule {
+    fn matches(&self, input: &PolicyInput) -> bool {
+        let score;
+        let values = match self.attribute.as_str() {
+            "trust_score" => match input.trust_score {
+                Some(s) => {
+                    score = s.to_string();
+                    vec![score.as_str()]
+                }
+                None => return false,
+            },
+            attribute => input.values(attribute),
+        };
+        values.iter().any(|actual| self.operator.holds(actual, &self.value))
+    }
+}
+
+impl Operator {
+    fn holds(&self, actual: &str, expected: &str) -> bool {
+        let numbers = || Some((actual.parse::<f64>().ok()?, expected.parse::<f64>().ok()?));
+        match self {
+            Self::Equals => actual == expected,
+            Self::NotEquals => actual != expected,
+            Self::Contains => actual.contains(expected),
+            Self::GreaterThan => numbers().is_some_and(|(a, e)| a > e),
+            Self::LessThan => numbers().is_some_and(|(a, e)| a < e),
+            Self::VersionAtLeast => compare_versions(actual, expected).is_some_and(|o| o.is_ge()),
+            Self::VersionBelow => compare_versions(actual, expected).is_some_and(|o| o.is_lt()),
+        }
+    }
+}
+
+/// Compare dotted numeric versions, ignoring any `-pre` or `+build` suffix
+/// (missing parts count as 0); None if either isn't one
+pub fn compare_versions(a: &str, b: &str) -> Option<std::cmp::Ordering> {
+    let parts = |v: &str| -> Option<Vec<u64>> {
+        let core = v.trim().trim_start_matches('v').split(['-', '+']).next()?;
+        core.split('.').map(|p| p.parse().ok()).collect()
+    };
+    let (mut a, mut b) = (parts(a)?, parts(b)?);
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+    Some(a.cmp(&b))
+}
+
+impl Policy {
+    /// Every rule holds (a policy without rules always matches)
+    pub fn matches(&self, input: &PolicyInput) -> bool {
+        self.rules.iter().all(|rule| rule.matches(input))
+    }
+}
+
+/// The first matching policy and its action; no policy means allow
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Verdict {
+    pub policy: Option<String>,
+    pub action: Option<PolicyAction>,
+}
+
+impl Verdict {
+    pub fn is_deny(&self) -> bool {
+        self.action == Some(PolicyAction::Deny)
+    }
+
+    pub fn conditions(&self) -> Vec<String> {
+        match self.action {
+            Some(PolicyAction::RequireMFA) => vec!["MFA required".to_string()],
+            Some(PolicyAction::RequireVMIsolation) => vec!["VM isolation required".to_string()],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn to_decision(&self) -> AccessDecision {
+        match (&self.action, &self.policy) {
+            (Some(PolicyAction::Deny), Some(name)) => AccessDecision::Deny(format!("Denied by policy: {}", name)),
+            (Some(PolicyAction::RequireMFA | PolicyAction::RequireVMIsolation), _) => {
+                AccessDecision::AllowWithConditions(self.conditions())
+            }
+            _ => AccessDecision::Allow,
+        }
+    }
+
+    /// Audit event details recording this verdict, for `from_details`
+    pub fn audit_details(&self) -> HashMap<String, String> {
+        let action = self.action.as_ref().map_or("none".to_string(), |a| format!("{:?}", a));
+        let mut details = HashMap::from([("policy.action".to_string(), action)]);
+        if let Some(policy) = &self.policy {
+            details.insert("policy.name".to_string(), policy.clone());
+        }
+        details
+    }
+
+    pub fn from_details(details: &HashMap<String, String>) -> Option<Self> {
+        let action = match details.get("policy.action")?.as_str() {
+            "none" => None,
+            "Allow" => Some(PolicyAction::Allow),
+            "Deny" => Some(PolicyAction::Deny),
+            "RequireMFA" => Some(PolicyAction::RequireMFA),
+            "RequireVMIsolation" => Some(PolicyAction::RequireVMIsolation),
+            _ => return None,
+        };
+        Some(Self { policy: details.get("policy.name").cloned(), action })
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.action, &self.policy) {
+            (Some(PolicyAction::Deny), Some(name)) => write!(f, "deny ({})", name),
+            (Some(PolicyAction::RequireMFA | PolicyAction::RequireVMIsolation), Some(name)) => {
+                write!(f, "allow with {} ({})", self.conditions().join(", "), name)
+            }
+            (_, Some(name)) => write!(f, "allow ({})", name),
+            (_, None) => write!(f, "allow"),
+        }
+    }
+}
+
+/// A recorded policy decision, rebuilt from its audit event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoricalRequest {
+    pub at: DateTime<Utc>,
+    pub peer_id: String,
+    pub input: PolicyInput,
+    pub verdict: Verdict,
+}
+
+impl HistoricalRequest {
+    /// None for other event types, and decisions recorded without inputs
+    pub fn from_event(event: &SecurityEvent) -> Option<Self> {
+        if !DECISION_EVENT_TYPES.contains(&event.event_type.as_str()) {
+            return None;
+        }
+        Some(Self {
+            at: event.timestamp,
+            peer_id: event.peer_id.clone(),
+            input: PolicyInput::from_details(&event.details)?,
+            verdict: Verdict::from_details(&event.details)?,
+        })
+    }
+}
+
+/// How a decision would change under the simulated policies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    AllowToDeny,
+    DenyToAllow,
+    /// Allowed either way, with different conditions
+    ConditionsChanged,
+}
+
+impl ChangeKind {
+    fn between(before: &Verdict, after: &Verdict) -> Option<Self> {
+        match (before.is_deny(), after.is_deny()) {
+            (false, true) => Some(Self::AllowToDeny),
+            (true, false) => Some(Self::DenyToAllow),
+            (false, false) if before.conditions() != after.conditions() => Some(Self::ConditionsChanged),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::AllowToDeny => "allow → deny",
+            Self::DenyToAllow => "deny → allow",
+            Self::ConditionsChanged => "conditions changed",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChangeExample {
+    pub at: DateTime<Utc>,
+    pub before: String,
+    pub after: String,
+    pub trust_score: Option<TrustScore>,
+    pub resources: Vec<String>,
+}
+
+/// One peer's decisions that would change one way
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DecisionChange {
+    pub kind: ChangeKind,
+    pub peer_id: String,
+    pub events: usize,
+    pub examples: Vec<ChangeExample>,
+}
+
+/// How often a simulated policy matched, and decided (matched first)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PolicyMatches {
+    pub policy: String,
+    pub matched: usize,
+    pub decided: usize,
+}
+
+/// Outcome of replaying recorded requests against other policies
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SimulationReport {
+    pub evaluated: usize,
+    pub peers: usize,
+    pub policies: Vec<PolicyMatches>,
+    /// Decisions no policy matched (default allow)
+    pub unmatched: usize,
+    /// By kind, then peer
+    pub changes: Vec<DecisionChange>,
+}
+
+impl SimulationReport {
+    /// Peers with at least one change of `kind`
+    pub fn peers_changed(&self, kind: ChangeKind) -> BTreeSet<&str> {
+        self.changes.iter().filter(|c| c.kind == kind).map(|c| c.peer_id.as_str()).collect()
+    }
+
+    /// Decisions that would change `kind`
+    pub fn events_changed(&self, kind: ChangeKind) -> usize {
+        self.changes.iter().filter(|c| c.kind == kind).map(|c| c.events).sum()
+    }
+}
+
+impl fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "🧪 Simulated {} decision(s) for {} peer(s)", self.evaluated, self.peers)?;
+        writeln!(f, "  {:<36} {:>8} {:>8}", "POLICY", "MATCHED", "DECIDED")?;
+        for policy in &self.policies {
+            writeln!(f, "  {:<36} {:>8} {:>8}", policy.policy, policy.matched, policy.decided)?;
+        }
+        writeln!(f, "  {:<36} {:>8} {:>8}", "(no match: allow)", "-", self.unmatched)?;
+        if self.changes.is_empty() {
+            return writeln!(f, "✅ No decision would change");
+        }
+        for kind in [ChangeKind::AllowToDeny, ChangeKind::DenyToAllow, ChangeKind::ConditionsChanged] {
+            let peers = self.peers_changed(kind);
+            if peers.is_empty() {
+                continue;
+            }
+            writeln!(f, "⚠️  {}: {} peer(s), {} decision(s)", kind, peers.len(), self.events_changed(kind))?;
+            for change in self.changes.iter().filter(|c| c.kind == kind) {
+                writeln!(f, "  {} ({} decision(s))", change.peer_id, change.events)?;
+                for example in &change.examples {
+                    writeln!(
+                        f,
+                        "    {} {} → {} [trust {}] {}",
+                        example.at.format("%Y-%m-%d %H:%M:%S"),
+                        example.before,
+                        example.after,
+                        example.trust_score.map_or("-".to_string(), |s| s.to_string()),
+                        example.resources.join(", ")
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A policy file: a list of policies, or a `policies` table holding one
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PolicyFile {
+    List(Vec<Policy>),
+    Table { policies: Vec<Policy> },
+}
+
 impl PolicyEngine {
+    /// The live engine: built-in policies only, until configured ones
+    /// (attestation requirements) are added ahead of them
     pub fn new() -> Self {
-        let default_policies = vec![
+        let policies = Self::default_policies();
+        Self { builtin: policies.len(), policies }
+    }
+
+    /// The built-in policies, for simulating them with their rules enforced
+    pub fn default_policies() -> Vec<Policy> {
+        vec![
             Policy {
                 name: "critical_resources_require_isolation".to_string(),
                 rules: vec![Rule {
@@ -59,53 +410,282 @@ impl PolicyEngine {
                 }],
                 action: PolicyAction::Deny,
             },
-        ];
+        ]
+    }
 
-        Self {
-            policies: default_policies,
+    /// Every rule of every policy enforced, as `simulate` evaluates them
+    pub fn with_policies(policies: Vec<Policy>) -> Self {
+        Self { policies, builtin: 0 }
+    }
+
+    pub fn policies(&self) -> &[Policy] {
+        &self.policies
+    }
+
+    /// Read policies from YAML, JSON or TOML, by extension
+    pub fn load_policies(path: &Path) -> Result<Vec<Policy>> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read policy file {}", path.display()))?;
+        let file: PolicyFile = match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_str(&content).map_err(anyhow::Error::from),
+            Some("json") => serde_json::from_str(&content).map_err(anyhow::Error::from),
+            _ => toml::from_str(&content).map_err(anyhow::Error::from),
         }
+        .with_context(|| format!("Invalid policy file {}", path.display()))?;
+        Ok(match file {
+            PolicyFile::List(policies) | PolicyFile::Table { policies } => policies,
+        })
     }
 
-    pub async fn evaluate(
-        &self,
-        identity: &Identity,
-        requested_resources: &[String],
-    ) -> Result<AccessDecision> {
-        for policy in &self.policies {
-            if self.matches_policy(identity, requested_resources, policy) {
-                match &policy.action {
-                    PolicyAction::Allow => return Ok(AccessDecision::Allow),
-                    PolicyAction::Deny => {
-                        return Ok(AccessDecision::Deny(format!(
-                            "Denied by policy: {}",
-                            policy.name
-                        )))
-                    }
-                    PolicyAction::RequireMFA => {
-                        return Ok(AccessDecision::AllowWithConditions(vec![
-                            "MFA required".to_string(),
-                        ]))
-                    }
-                    PolicyAction::RequireVMIsolation => {
-                        return Ok(AccessDecision::AllowWithConditions(vec![
-                            "VM isolation required".to_string(),
-                        ]))
+    /// First matching policy, in order
+    pub fn decide(&self, input: &PolicyInput) -> Verdict {
+        let configured = self.policies.len() - self.builtin;
+        let critical = input.resources.iter().any(|r| r.starts_with("critical/"));
+        self.policies
+            .iter()
+            .enumerate()
+            .find(|(i, policy)| if *i < configured { policy.matches(input) } else { critical })
+            .map(|(_, policy)| policy)
+            .map_or_else(Verdict::default, |policy| Verdict {
+                policy: Some(policy.name.clone()),
+                action: Some(policy.action.clone()),
+            })
+    }
+
+    /// Requested resources a Deny policy matches on when asked for alone;
+    /// they can be withheld instead of refusing the whole request
+    pub fn denied_resources(&self, input: &PolicyInput) -> Vec<String> {
+        input
+            .resources
+            .iter()
+            .filter(|resource| self.decide(&PolicyInput { resources: vec![(*resource).clone()], ..input.clone() }).is_deny())
+            .cloned()
+            .collect()
+    }
+
+    /// Swap the policies named with `prefix` for `policies`, ahead of the rest
+    pub fn replace_prefixed(&mut self, prefix: &str, policies: Vec<Policy>) {
+        self.policies.retain(|policy| !policy.name.starts_with(prefix));
+        self.policies.splice(0..0, policies);
+    }
+
+    pub async fn evaluate(&self, input: &PolicyInput) -> Result<AccessDecision> {
+        Ok(self.decide(input).to_decision())
+    }
+
+    /// Replay recorded decisions against `policies`, reporting how often
+    /// each matches and which peers' decisions would change. Pure: nothing
+    /// is logged and no trust score is touched
+    pub fn simulate(policies: &[Policy], historical_requests: &[HistoricalRequest]) -> SimulationReport {
+        let engine = Self::with_policies(policies.to_vec());
+        let mut matches: Vec<PolicyMatches> = policies
+            .iter()
+            .map(|p| PolicyMatches { policy: p.name.clone(), matched: 0, decided: 0 })
+            .collect();
+        let mut unmatched = 0;
+        let mut changes: BTreeMap<(ChangeKind, &str), DecisionChange> = BTreeMap::new();
+
+        for request in historical_requests {
+            let mut decided = false;
+            for (policy, count) in policies.iter().zip(matches.iter_mut()) {
+                if policy.matches(&request.input) {
+                    count.matched += 1;
+                    if !decided {
+                        count.decided += 1;
+                        decided = true;
                     }
                 }
             }
+            if !decided {
+                unmatched += 1;
+            }
+
+            let after = engine.decide(&request.input);
+            let Some(kind) = ChangeKind::between(&request.verdict, &after) else { continue };
+            let change = changes.entry((kind, request.peer_id.as_str())).or_insert_with(|| DecisionChange {
+                kind,
+                peer_id: request.peer_id.clone(),
+                events: 0,
+                examples: Vec::new(),
+            });
+            change.events += 1;
+            if change.examples.len() < MAX_EXAMPLES {
+                change.examples.push(ChangeExample {
+                    at: request.at,
+                    before: request.verdict.to_string(),
+                    after: after.to_string(),
+                    trust_score: request.input.trust_score,
+                    resources: request.input.resources.clone(),
+                });
+            }
         }
 
-        Ok(AccessDecision::Allow)
+        SimulationReport {
+            evaluated: historical_requests.len(),
+            peers: historical_requests.iter().map(|r| r.peer_id.as_str()).collect::<BTreeSet<_>>().len(),
+            policies: matches,
+            unmatched,
+            changes: changes.into_values().collect(),
+        }
     }
+}
 
-    fn matches_policy(
-        &self,
-        _identity: &Identity,
-        requested_resources: &[String],
-        policy: &Policy,
-    ) -> bool {
-        requested_resources
-            .iter()
-            .any(|r| r.starts_with("critical/"))
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zerotrust::{audit::AuditLogger, SecurityLevel};
+    use crate::storage::RuntimeMode;
+
+    fn input(user: &str, trust: Option<TrustScore>, resources: &[&str], role: &str) -> PolicyInput {
+        PolicyInput {
+            user_id: user.to_string(),
+            attributes: BTreeMap::from([("role".to_string(), role.to_string())]),
+            trust_score: trust,
+            resources: resources.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    /// A decision as recorded by an engine enforcing the built-in rules
+    fn decision_event(peer: &str, at: i64, input: &PolicyInput) -> SecurityEvent {
+        let verdict = PolicyEngine::with_policies(PolicyEngine::default_policies()).decide(input);
+        let mut details = input.audit_details();
+        details.extend(verdict.audit_details());
+        SecurityEvent {
+            timestamp: DateTime::from_timestamp(at, 0).unwrap(),
+            event_type: if verdict.is_deny() { "policy_denied" } else { "access_granted" }.to_string(),
+            peer_id: peer.to_string(),
+            security_level: SecurityLevel::Basic,
+            details,
+            prev_hash: String::new(),
+            trace_id: None,
+        }
+    }
+
+    #[test]
+    fn test_rules_match_attributes() {
+        let engine = PolicyEngine::with_policies(PolicyEngine::default_policies());
+        assert_eq!(engine.decide(&input("a", Some(50), &["p2p/messaging"], "ops")), Verdict::default());
+        assert_eq!(engine.decide(&input("a", Some(10), &["p2p/messaging"], "ops")).policy.as_deref(), Some("untrusted_users_denied"));
+        // Unregistered identities have no score for trust rules to match
+        assert!(!engine.decide(&input("a", None, &["p2p/messaging"], "ops")).is_deny());
+        let critical = engine.decide(&input("a", Some(10), &["p2p/messaging", "critical/keys"], "ops"));
+        assert_eq!(critical.to_decision(), AccessDecision::AllowWithConditions(vec!["VM isolation required".to_string()]));
+    }
+
+    #[test]
+    fn test_live_engine_does_not_enforce_builtin_rules() {
+        let mut engine = PolicyEngine::new();
+        let low = input("low", Some(10), &["p2p/messaging"], "guest");
+        assert_eq!(engine.decide(&low), Verdict::default());
+        let critical = engine.decide(&input("low", Some(10), &["critical/keys"], "ops"));
+        assert_eq!(critical.policy.as_deref(), Some("critical_resources_require_isolation"));
+
+        // Configured policies ahead of the built-ins have their rules enforced
+        let guests = Policy {
+            name: "configured.guests_denied".to_string(),
+            rules: vec![Rule { attribute: "role".to_string(), operator: Operator::Equals, value: "guest".to_string() }],
+            action: PolicyAction::Deny,
+        };
+        engine.replace_prefixed("configured.", vec![guests]);
+        assert!(engine.decide(&low).is_deny());
+        assert_eq!(engine.decide(&input("ops", Some(10), &["p2p/messaging"], "ops")), Verdict::default());
+
+        // Simulating the built-ins with their rules shows who they would deny
+        let history = [HistoricalRequest {
+            at: Utc::now(),
+            peer_id: "peer-low".to_string(),
+            verdict: PolicyEngine::new().decide(&low),
+            input: low,
+        }];
+        let report = PolicyEngine::simulate(&PolicyEngine::default_policies(), &history);
+        assert_eq!(report.peers_changed(ChangeKind::AllowToDeny), BTreeSet::from(["peer-low"]));
+    }
+
+    #[test]
+    fn test_version_operators() {
+        let rule = |operator, value: &str| Rule { attribute: "version".to_string(), operator, value: value.to_string() };
+        let mut peer = input("a", Some(50), &["p2p/messaging"], "ops");
+        peer.attributes.insert("version".to_string(), "0.10.2-rc1".to_string());
+        assert!(rule(Operator::VersionAtLeast, "0.9").matches(&peer));
+        assert!(rule(Operator::VersionAtLeast, "0.10.2").matches(&peer));
+        assert!(!rule(Operator::VersionBelow, "0.10.2").matches(&peer));
+        assert!(rule(Operator::VersionBelow, "v1.0.0").matches(&peer));
+        peer.attributes.insert("version".to_string(), "unknown".to_string());
+        assert!(!rule(Operator::VersionAtLeast, "0.1").matches(&peer));
+        assert!(!rule(Operator::VersionBelow, "0.1").matches(&peer));
+    }
+
+    #[tokio::test]
+    async fn test_simulate_policy_flip_against_audit_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let mut log = AuditLogger::with_mode(&path, RuntimeMode::Persistent).await.unwrap();
+        let recorded = [
+            ("peer-ops", 1, input("ops", Some(60), &["p2p/messaging"], "ops")),
+            ("peer-ops", 2, input("ops", Some(60), &["quant/pricing"], "ops")),
+            ("peer-guest", 3, input("guest", Some(40), &["p2p/messaging"], "guest")),
+            ("peer-guest", 4, input("guest", Some(40), &["p2p/messaging"], "guest")),
+            ("peer-low", 5, input("low", Some(10), &["p2p/messaging"], "ops")),
+            ("peer-vault", 6, input("vault", Some(70), &["critical/keys"], "ops")),
+        ];
+        for (peer, at, input) in &recorded {
+            log.log(decision_event(peer, *at, input)).await.unwrap();
+        }
+        // Not a decision: ignored
+        log.log(SecurityEvent {
+            timestamp: Utc::now(),
+            event_type: "connection_established".to_string(),
+            peer_id: "peer-ops".to_string(),
+            security_level: SecurityLevel::Basic,
+            details: HashMap::new(),
+            prev_hash: String::new(),
+            trace_id: None,
+        })
+        .await
+        .unwrap();
+        log.flush().await.unwrap();
+        let before = log.read_events().await.unwrap().len();
+
+        let policy_file = dir.path().join("stricter.yaml");
+        std::fs::write(
+            &policy_file,
+            r#"
+policies:
+  - name: guests_denied
+    rules: [{ attribute: role, operator: Equals, value: guest }]
+    action: Deny
+  - name: pricing_needs_mfa
+    rules: [{ attribute: resource, operator: Equals, value: quant/pricing }]
+    action: RequireMFA
+  - name: everyone_else
+    rules: []
+    action: Allow
+"#,
+        )
+        .unwrap();
+        let policies = PolicyEngine::load_policies(&policy_file).unwrap();
+        let history: Vec<HistoricalRequest> =
+            log.read_events().await.unwrap().iter().filter_map(HistoricalRequest::from_event).collect();
+        let report = PolicyEngine::simulate(&policies, &history);
+
+        assert_eq!((report.evaluated, report.peers, report.unmatched), (6, 4, 0));
+        let counts: Vec<(&str, usize, usize)> =
+            report.policies.iter().map(|p| (p.policy.as_str(), p.matched, p.decided)).collect();
+        assert_eq!(counts, vec![("guests_denied", 2, 2), ("pricing_needs_mfa", 1, 1), ("everyone_else", 6, 3)]);
+
+        assert_eq!(report.peers_changed(ChangeKind::AllowToDeny), BTreeSet::from(["peer-guest"]));
+        assert_eq!(report.events_changed(ChangeKind::AllowToDeny), 2);
+        assert_eq!(report.peers_changed(ChangeKind::DenyToAllow), BTreeSet::from(["peer-low"]));
+        assert_eq!(report.events_changed(ChangeKind::DenyToAllow), 1);
+        // Gains MFA; loses VM isolation
+        assert_eq!(report.peers_changed(ChangeKind::ConditionsChanged), BTreeSet::from(["peer-ops", "peer-vault"]));
+        assert_eq!(report.events_changed(ChangeKind::ConditionsChanged), 2);
+        let ops = report.changes.iter().find(|c| c.peer_id == "peer-ops").unwrap();
+        assert_eq!(ops.examples[0].before, "allow");
+        assert_eq!(ops.examples[0].after, "allow with MFA required (pricing_needs_mfa)");
+        assert_eq!(report.changes.iter().find(|c| c.peer_id == "peer-low").unwrap().examples[0].before, "deny (untrusted_users_denied)");
+
+        // Read-only: nothing was written to the log
+        assert_eq!(log.read_events().await.unwrap().len(), before);
     }
 }
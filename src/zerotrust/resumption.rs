@@ -0,0 +1,387 @@
+//! Session Resumption Tokens
+//!
+//! When a secure connection terminates cleanly the node issues a signed,
+//! time-limited token bound to the identity key the peer presented. A
+//! reconnecting peer presents it, signed with that key, to restore its
+//! previous security level and granted resources without re-running the
+//! policy engine.
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{DateTime, Duration, Utc};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use rand::RngCore;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::identity::TrustScore;
+use super::{SecureConnection, SecurityLevel};
+
+/// How long an issued token stays valid
+const TOKEN_VALIDITY_MINUTES: i64 = 15;
+
+/// Server secret rotation period
+const SECRET_ROTATION_HOURS: i64 = 24;
+
+/// Tokens are invalidated once the peer's trust score falls below this value
+pub const MIN_RESUME_TRUST: TrustScore = 20;
+
+/// What the holder signs with its identity key to redeem `token`
+pub fn proof_message(token: &str) -> Vec<u8> {
+    [b"quantra-resume-v1:".as_slice(), token.as_bytes()].concat()
+}
+
+fn verify_proof(token: &str, identity_key: &[u8], signature: &[u8]) -> Result<()> {
+    let key: [u8; 32] = identity_key.try_into().context("Invalid identity key length")?;
+    let key = VerifyingKey::from_bytes(&key).context("Invalid identity key")?;
+    let signature = Signature::from_slice(signature).context("Invalid resumption proof")?;
+    key.verify(&proof_message(token), &signature)
+        .context("Resumption proof was not signed with the bound identity key")
+}
+
+/// Claims covered by the token MAC
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TokenClaims {
+    connection_id: String,
+    peer_id: String,
+    security_level: SecurityLevel,
+    expires_at: i64,
+}
+
+/// State kept server-side for a resumable session
+#[derive(Debug, Clone)]
+pub struct ResumableSession {
+    pub peer_id: String,
+    pub identity_key: Vec<u8>,
+    pub security_level: SecurityLevel,
+    pub granted_resources: Vec<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Issues and redeems single-use resumption tokens
+pub struct ResumptionManager {
+    current_secret: [u8; 32],
+    previous_secret: Option<[u8; 32]>,
+    secret_rotated_at: DateTime<Utc>,
+    token_validity: Duration,
+    sessions: HashMap<String, ResumableSession>,
+}
+
+impl ResumptionManager {
+    pub fn new() -> Self {
+        Self::with_token_validity(Duration::minutes(TOKEN_VALIDITY_MINUTES))
+    }
+
+    /// Create with a custom token lifetime
+    pub fn with_token_validity(token_validity: Duration) -> Self {
+        Self {
+            current_secret: Self::generate_secret(),
+            previous_secret: None,
+            secret_rotated_at: Utc::now(),
+            token_validity,
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// Issue a resumption token for a cleanly terminated connection, bound
+    /// to `identity_key`
+    pub fn issue(&mut self, connection: &SecureConnection, identity_key: &[u8]) -> Result<String> {
+        self.rotate_secret_if_due();
+
+        let expires_at = Utc::now() + self.token_validity;
+        let claims = TokenClaims {
+            connection_id: connection.id.clone(),
+            peer_id: connection.peer_id.clone(),
+            security_level: connection.security_level,
+            expires_at: expires_at.timestamp(),
+        };
+
+        let payload = serde_json::to_vec(&claims)?;
+        let key = hmac::Key::new(hmac::HMAC_SHA256, &self.current_secret);
+        let tag = hmac::sign(&key, &payload);
+
+        self.sessions.insert(
+            connection.id.clone(),
+            ResumableSession {
+                peer_id: connection.peer_id.clone(),
+                identity_key: identity_key.to_vec(),
+                security_level: connection.security_level,
+                granted_resources: connection.granted_resources.clone(),
+                expires_at,
+            },
+        );
+
+        tracing::debug!("🎟️ Issued resumption token for peer: {}", connection.peer_id);
+
+        Ok(format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&payload),
+            URL_SAFE_NO_PAD.encode(tag.as_ref())
+        ))
+    }
+
+    /// Redeem a token. It is consumed once the presenting peer and identity
+    /// key match the session and `proof` is the token signed with that key;
+    /// a mismatch leaves it for the real owner
+    pub fn redeem(&mut self, token: &str, peer_id: &str, identity_key: &[u8], proof: &[u8]) -> Result<ResumableSession> {
+        self.rotate_secret_if_due();
+
+        let claims = self.verify_token(token)?;
+
+        let session = self
+            .sessions
+            .get(&claims.connection_id)
+            .context("Resumption token already used or revoked")?;
+
+        if claims.peer_id != peer_id || session.peer_id != peer_id {
+            anyhow::bail!("Resumption token was issued to a different peer");
+        }
+
+        if session.identity_key != identity_key {
+            anyhow::bail!("Identity key does not match resumption token");
+        }
+        verify_proof(token, identity_key, proof)?;
+
+        // Single-use: the owner's first attempt consumes the session
+        let session = self
+            .sessions
+            .remove(&claims.connection_id)
+            .expect("session looked up above");
+
+        if claims.expires_at <= Utc::now().timestamp() {
+            anyhow::bail!("Resumption token expired");
+        }
+
+        if claims.security_level != session.security_level {
+            anyhow::bail!("Resumption token security level mismatch");
+        }
+
+        Ok(session)
+    }
+
+    /// Whether `peer_id` holds an unexpired token
+    pub fn has_session(&self, peer_id: &str) -> bool {
+        let now = Utc::now();
+        self.sessions.values().any(|s| s.peer_id == peer_id && s.expires_at > now)
+    }
+
+    /// Drop all outstanding tokens for a peer (e.g. after a trust drop)
+    pub fn invalidate_peer(&mut self, peer_id: &str) -> usize {
+        let before = self.sessions.len();
+        self.sessions.retain(|_, s| s.peer_id != peer_id);
+        before - self.sessions.len()
+    }
+
+    /// Remove expired sessions
+    pub fn cleanup(&mut self) {
+        let now = Utc::now();
+        self.sessions.retain(|_, s| s.expires_at > now);
+    }
+
+    fn verify_token(&self, token: &str) -> Result<TokenClaims> {
+        let (payload_b64, tag_b64) = token
+            .split_once('.')
+            .context("Malformed resumption token")?;
+
+        let payload = URL_SAFE_NO_PAD
+            .decode(payload_b64)
+            .context("Malformed resumption token payload")?;
+        let tag = URL_SAFE_NO_PAD
+            .decode(tag_b64)
+            .context("Malformed resumption token signature")?;
+
+        // Accept tokens signed with the previous secret so rotation doesn't
+        // invalidate tokens issued just before it
+        let valid = std::iter::once(&self.current_secret)
+            .chain(self.previous_secret.iter())
+            .any(|secret| {
+                let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+                hmac::verify(&key, &payload, &tag).is_ok()
+            });
+
+        if !valid {
+            anyhow::bail!("Invalid resumption token signature");
+        }
+
+        serde_json::from_slice(&payload).context("Invalid resumption token claims")
+    }
+
+    fn rotate_secret_if_due(&mut self) {
+        if Utc::now() - self.secret_rotated_at >= Duration::hours(SECRET_ROTATION_HOURS) {
+            self.previous_secret = Some(self.current_secret);
+            self.current_secret = Self::generate_secret();
+            self.secret_rotated_at = Utc::now();
+            self.cleanup();
+            tracing::info!("🔑 Rotated session resumption secret");
+        }
+    }
+
+    fn generate_secret() -> [u8; 32] {
+        let mut secret = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut secret);
+        secret
+    }
+}
+
+impl Default for ResumptionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zerotrust::identity::IdentityManager;
+    use ed25519_dalek::{Signer, SigningKey};
+    use crate::zerotrust::{ConnectionRequest, ZeroTrustContext};
+    use tempfile::TempDir;
+
+    fn request_for(identity: &crate::zerotrust::identity::Identity) -> ConnectionRequest {
+        ConnectionRequest {
+            peer_id: identity.user_id.clone(),
+            identity: identity.clone(),
+            requested_resources: vec!["p2p/messaging".to_string(), "quant/quotes".to_string()],
+            client_metadata: HashMap::new(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// An identity with the key that signs its resumption proofs
+    fn holder(user_id: &str) -> (crate::zerotrust::identity::Identity, SigningKey) {
+        let key = SigningKey::from_bytes(&rand::random());
+        (IdentityManager::create_identity_with_key(user_id.to_string(), HashMap::new(), &key), key)
+    }
+
+    fn prove(key: &SigningKey, token: &str) -> Vec<u8> {
+        key.sign(&proof_message(token)).to_bytes().to_vec()
+    }
+
+    async fn context(dir: &TempDir) -> ZeroTrustContext {
+        let log_path = dir.path().join("audit.log");
+        ZeroTrustContext::with_log_path(log_path.to_str().unwrap()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_resume_preserves_granted_resources() {
+        let dir = TempDir::new().unwrap();
+        let zt = context(&dir).await;
+        let (identity, key) = holder("peer-a");
+        zt.register_identity(identity.clone()).await.unwrap();
+
+        let conn = zt.establish_connection(request_for(&identity)).await.unwrap();
+        let token = zt.terminate_with_resumption(&conn.id, &identity).await.unwrap().unwrap();
+
+        let resumed = zt
+            .resume_connection(&token, "peer-a", &identity, &prove(&key, &token))
+            .await
+            .unwrap()
+            .expect("valid token should resume");
+
+        assert_eq!(resumed.granted_resources, conn.granted_resources);
+        assert_eq!(resumed.security_level, conn.security_level);
+        assert_ne!(resumed.id, conn.id);
+        println!("✅ Session resumption test PASSED!");
+    }
+
+    #[tokio::test]
+    async fn test_reused_token_falls_back() {
+        let dir = TempDir::new().unwrap();
+        let zt = context(&dir).await;
+        let (identity, key) = holder("peer-b");
+
+        let conn = zt.establish_connection(request_for(&identity)).await.unwrap();
+        let token = zt.terminate_with_resumption(&conn.id, &identity).await.unwrap().unwrap();
+        let proof = prove(&key, &token);
+
+        assert!(zt.resume_connection(&token, "peer-b", &identity, &proof).await.unwrap().is_some());
+        assert!(zt.resume_connection(&token, "peer-b", &identity, &proof).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_trust_drop_invalidates_token() {
+        let dir = TempDir::new().unwrap();
+        let zt = context(&dir).await;
+        let (identity, key) = holder("peer-c");
+        zt.register_identity(identity.clone()).await.unwrap();
+
+        let conn = zt.establish_connection(request_for(&identity)).await.unwrap();
+        let token = zt.terminate_with_resumption(&conn.id, &identity).await.unwrap().unwrap();
+
+        zt.update_trust("peer-c", -40).await.unwrap();
+
+        assert!(zt.resume_connection(&token, "peer-c", &identity, &prove(&key, &token)).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_expired_token_rejected() {
+        let mut manager = ResumptionManager::with_token_validity(Duration::seconds(-1));
+        let (identity, key) = holder("peer-d");
+        let conn = SecureConnection {
+            id: "conn-d".to_string(),
+            peer_id: "peer-d".to_string(),
+            identity: identity.clone(),
+            security_level: SecurityLevel::Basic,
+            vm_sandbox_id: None,
+            granted_resources: vec!["p2p/messaging".to_string()],
+            established_at: Utc::now(),
+            last_verified: Utc::now(),
+            verification_failures: 0,
+        };
+
+        let token = manager.issue(&conn, &identity.public_key).unwrap();
+        assert!(manager.redeem(&token, "peer-d", &identity.public_key, &prove(&key, &token)).is_err());
+    }
+
+    #[test]
+    fn test_wrong_identity_key_rejected() {
+        let mut manager = ResumptionManager::new();
+        let (identity, key) = holder("peer-e");
+        let (imposter, imposter_key) = holder("peer-e");
+        let conn = SecureConnection {
+            id: "conn-e".to_string(),
+            peer_id: "peer-e".to_string(),
+            identity: identity.clone(),
+            security_level: SecurityLevel::Basic,
+            vm_sandbox_id: None,
+            granted_resources: vec![],
+            established_at: Utc::now(),
+            last_verified: Utc::now(),
+            verification_failures: 0,
+        };
+
+        let token = manager.issue(&conn, &identity.public_key).unwrap();
+        let proof = prove(&key, &token);
+        assert!(manager.redeem(&token, "peer-e", &imposter.public_key, &prove(&imposter_key, &token)).is_err());
+        assert!(manager.redeem(&token, "peer-x", &identity.public_key, &proof).is_err());
+        // Knowing the bound key isn't enough without signing with it
+        assert!(manager.redeem(&token, "peer-e", &identity.public_key, &prove(&imposter_key, &token)).is_err());
+        // No failed attempt burns the owner's token
+        assert!(manager.redeem(&token, "peer-e", &identity.public_key, &proof).is_ok());
+        assert!(manager.redeem(&token, "peer-e", &identity.public_key, &proof).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_token_bound_to_presented_identity() {
+        let dir = TempDir::new().unwrap();
+        let zt = context(&dir).await;
+        // The connection was evaluated with a locally built identity; the
+        // token binds the one the peer presented on disconnect
+        let local = IdentityManager::create_identity("peer-f".to_string(), HashMap::new());
+        let (presented, key) = holder("peer-f");
+
+        let conn = zt.establish_connection(request_for(&local)).await.unwrap();
+        let token = zt.terminate_with_resumption(&conn.id, &presented).await.unwrap().unwrap();
+        assert!(zt.has_resumable_session("peer-f").await);
+
+        assert!(zt.resume_connection(&token, "peer-f", &local, &prove(&key, &token)).await.unwrap().is_none());
+        assert!(zt.resume_connection(&token, "peer-f", &presented, &prove(&key, &token)).await.unwrap().is_some());
+        assert!(!zt.has_resumable_session("peer-f").await);
+
+        // Someone else's identity gets no token
+        let conn = zt.establish_connection(request_for(&local)).await.unwrap();
+        let (other, _) = holder("peer-g");
+        assert!(zt.terminate_with_resumption(&conn.id, &other).await.unwrap().is_none());
+    }
+}
@@ -14,6 +14,7 @@ use sha2::{Sha256, Digest};
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use crate::zerotrust::SecureConnection;
+use crate::zerotrust::clock::PeerClock;
 
 /// Maximum events to track for behavioral analysis
 const MAX_BEHAVIOR_EVENTS: usize = 1000;
@@ -85,6 +86,21 @@ impl BehaviorProfile {
         }
     }
 
+    /// Most recent anomalies (time, score, reason), newest first
+    pub fn recent_anomalies(&self, limit: usize) -> Vec<(DateTime<Utc>, f64, String)> {
+        self.events
+            .iter()
+            .rev()
+            .filter_map(|e| match e {
+                BehaviorEvent::AnomalyDetected { score, reason, timestamp } => {
+                    Some((*timestamp, *score, reason.clone()))
+                }
+                _ => None,
+            })
+            .take(limit)
+            .collect()
+    }
+
     /// Record a behavioral event
     pub fn record_event(&mut self, event: BehaviorEvent) {
         // Maintain rolling window
@@ -230,9 +246,10 @@ impl VerificationChallenge {
         }
     }
 
-    /// Check if challenge is still valid
-    pub fn is_valid(&self) -> bool {
-        Utc::now() < self.expires_at
+    /// Check if challenge is still valid, allowing for the responding
+    /// peer's clock tolerance
+    pub fn is_valid_within(&self, clock: &PeerClock) -> bool {
+        Utc::now() < self.expires_at + clock.tolerance
     }
 
     /// Get the message that must be signed
@@ -246,7 +263,21 @@ impl VerificationChallenge {
 
     /// Verify a response signature
     pub fn verify_response(&self, signature: &[u8]) -> Result<bool> {
-        if !self.is_valid() {
+        self.verify_response_with_clock(signature, &PeerClock::exact())
+    }
+
+    /// Verify a response signature from a peer with a measured clock.
+    /// Challenge times are ours, so only the tolerance and skew limit apply.
+    pub fn verify_response_with_clock(&self, signature: &[u8], clock: &PeerClock) -> Result<bool> {
+        if clock.exceeds_max_skew() {
+            bail!(
+                "Peer clock skew of {}s exceeds maximum of {}s",
+                clock.offset.num_seconds(),
+                clock.max_skew.num_seconds()
+            );
+        }
+
+        if !self.is_valid_within(clock) {
             bail!("Challenge expired");
         }
 
@@ -363,6 +394,17 @@ impl ContinuousVerifier {
         &mut self,
         connection_id: &str,
         signature: &[u8]
+    ) -> Result<VerificationResult> {
+        self.verify_challenge_response_with_clock(connection_id, signature, &PeerClock::exact())
+            .await
+    }
+
+    /// Verify a challenge response, applying the peer's clock tolerance
+    pub async fn verify_challenge_response_with_clock(
+        &mut self,
+        connection_id: &str,
+        signature: &[u8],
+        clock: &PeerClock,
     ) -> Result<VerificationResult> {
         let challenge = self.pending_challenges.remove(connection_id)
             .context("No pending challenge for this connection")?;
@@ -374,7 +416,7 @@ impl ContinuousVerifier {
             .context("No behavioral profile")?;
 
         // Verify the cryptographic response
-        let challenge_passed = challenge.verify_response(signature)?;
+        let challenge_passed = challenge.verify_response_with_clock(signature, clock)?;
 
         if challenge_passed {
             behavior.record_event(BehaviorEvent::AuthSuccess {
@@ -494,6 +536,14 @@ impl ContinuousVerifier {
         })
     }
 
+    /// Change the security level of an active connection
+    pub fn set_security_level(&mut self, connection_id: &str, level: crate::zerotrust::SecurityLevel) -> Result<()> {
+        let conn = self.connections.get_mut(connection_id)
+            .context("Connection not found")?;
+        conn.security_level = level;
+        Ok(())
+    }
+
     /// Get connection by ID
     pub async fn get_connection(&self, connection_id: &str) -> Result<Option<SecureConnection>> {
         Ok(self.connections.get(connection_id).cloned())
@@ -592,6 +642,31 @@ mod tests {
         assert!(!result, "Invalid signature should fail");
     }
 
+    #[test]
+    fn test_challenge_clock_tolerance() {
+        let signing_key = SigningKey::from_bytes(&[9u8; 32]);
+        let mut challenge = VerificationChallenge::new(
+            "test-peer".to_string(),
+            signing_key.verifying_key().to_bytes().to_vec(),
+        );
+        let signature = signing_key.sign(&challenge.get_sign_message()).to_bytes();
+
+        // Answered two seconds after expiry, inside a five second tolerance
+        challenge.expires_at = Utc::now() - Duration::seconds(2);
+        let clock = PeerClock {
+            offset: Duration::minutes(2),
+            tolerance: Duration::seconds(5),
+            max_skew: Duration::minutes(5),
+        };
+        assert!(challenge.verify_response(&signature).is_err());
+        assert!(challenge.verify_response_with_clock(&signature, &clock).unwrap());
+
+        let skewed = PeerClock { offset: Duration::minutes(10), ..clock };
+        let err = challenge.verify_response_with_clock(&signature, &skewed).unwrap_err();
+        assert!(err.to_string().contains("exceeds maximum"), "{}", err);
+        println!("✅ Challenge clock tolerance test PASSED!");
+    }
+
     #[test]
     fn test_behavioral_profiling() {
         let mut profile = BehaviorProfile::new();
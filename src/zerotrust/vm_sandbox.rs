@@ -2,9 +2,13 @@ use anyhow::{Result, Context};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::process::Command;
-use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use chrono::{DateTime, Duration, Utc};
 use crate::zerotrust::SecurityLevel;
 
+/// How long a replaced sandbox keeps running so the peer's session can move over
+pub const HANDOVER_GRACE_SECS: i64 = 30;
+
 /// VM Sandbox provides isolated network environments
 /// Supports: Docker containers, QEMU/KVM VMs, Firecracker microVMs
 #[derive(Debug, Clone)]
@@ -12,6 +16,51 @@ pub struct VMManager {
     sandboxes: HashMap<String, VMSandbox>,
     backend: VMBackend,
     max_sandboxes: usize,
+    runner: Arc<dyn CommandRunner>,
+    handover_grace: Duration,
+}
+
+/// Output of an external backend command
+#[derive(Debug, Clone, Default)]
+pub struct CommandOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Runs backend CLI commands (docker, ...); swapped out in tests
+pub trait CommandRunner: Send + Sync + std::fmt::Debug {
+    fn run(&self, program: &str, args: &[&str]) -> Result<CommandOutput>;
+}
+
+/// Runs commands on the host
+#[derive(Debug)]
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, program: &str, args: &[&str]) -> Result<CommandOutput> {
+        let output = Command::new(program)
+            .args(args)
+            .output()
+            .with_context(|| format!("Failed to run {}", program))?;
+        Ok(CommandOutput {
+            success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout).trim().to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        })
+    }
+}
+
+/// Result of resizing a sandbox
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResizeOutcome {
+    /// Limits changed in place
+    Resized,
+    /// Backend couldn't resize live; a new container took over and the old one
+    /// is retired after the handover grace period
+    Recreated { previous_container: String },
+    /// Nothing to resize (missing or mock sandbox)
+    Skipped(String),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -31,15 +80,61 @@ pub struct VMSandbox {
     pub ip_address: Option<String>,
     pub created_at: DateTime<Utc>,
     pub resource_limits: ResourceLimits,
+    /// Container being replaced after a recreate, and when it will be removed
+    #[serde(default)]
+    pub handover_from: Option<(String, DateTime<Utc>)>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ResourceLimits {
     pub cpu_shares: u32,
     pub memory_mb: u32,
     pub network_bandwidth_mbps: u32,
 }
 
+impl ResourceLimits {
+    /// Limits applied to sandboxes at a security level
+    pub fn for_level(security_level: SecurityLevel) -> Self {
+        match security_level {
+            SecurityLevel::Privileged => ResourceLimits {
+                cpu_shares: 512,
+                memory_mb: 512,
+                network_bandwidth_mbps: 100,
+            },
+            SecurityLevel::Critical => ResourceLimits {
+                cpu_shares: 1024,
+                memory_mb: 1024,
+                network_bandwidth_mbps: 1000,
+            },
+            _ => ResourceLimits {
+                cpu_shares: 256,
+                memory_mb: 256,
+                network_bandwidth_mbps: 50,
+            },
+        }
+    }
+
+    /// Docker `--cpus` value
+    fn docker_cpus(&self) -> String {
+        format!("{}", self.cpu_shares as f32 / 1024.0)
+    }
+
+    /// Docker `--memory` value
+    fn docker_memory(&self) -> String {
+        format!("{}m", self.memory_mb)
+    }
+}
+
+impl std::fmt::Display for ResourceLimits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "cpu_shares={} memory={}MB bandwidth={}Mbps",
+            self.cpu_shares, self.memory_mb, self.network_bandwidth_mbps
+        )
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct VMStats {
     pub active_sandboxes: usize,
@@ -54,11 +149,23 @@ impl VMManager {
 
         tracing::info!("🖥️  VM Manager initialized with backend: {:?}", backend);
 
-        Ok(Self {
+        Ok(Self::with_runner(backend, Arc::new(SystemRunner)))
+    }
+
+    /// Create with an explicit backend and command runner
+    pub fn with_runner(backend: VMBackend, runner: Arc<dyn CommandRunner>) -> Self {
+        Self {
             sandboxes: HashMap::new(),
             backend,
             max_sandboxes: 100,
-        })
+            runner,
+            handover_grace: Duration::seconds(HANDOVER_GRACE_SECS),
+        }
+    }
+
+    /// How long a replaced container keeps running after a recreate
+    pub fn set_handover_grace(&mut self, grace: Duration) {
+        self.handover_grace = grace;
     }
 
     /// Create isolated sandbox for connection
@@ -68,30 +175,8 @@ impl VMManager {
         security_level: SecurityLevel,
     ) -> Result<VMSandbox> {
         let id = format!("qtz-{}", uuid::Uuid::new_v4());
-
-        let resource_limits = match security_level {
-            SecurityLevel::Privileged => ResourceLimits {
-                cpu_shares: 512,
-                memory_mb: 512,
-                network_bandwidth_mbps: 100,
-            },
-            SecurityLevel::Critical => ResourceLimits {
-                cpu_shares: 1024,
-                memory_mb: 1024,
-                network_bandwidth_mbps: 1000,
-            },
-            _ => ResourceLimits {
-                cpu_shares: 256,
-                memory_mb: 256,
-                network_bandwidth_mbps: 50,
-            },
-        };
-
-        let container_id = match self.backend {
-            VMBackend::Docker => Some(self.create_docker_sandbox(&id, &resource_limits).await?),
-            VMBackend::QEMU => Some(self.create_qemu_sandbox(&id, &resource_limits).await?),
-            VMBackend::Firecracker => Some(self.create_firecracker_sandbox(&id, &resource_limits).await?),
-        };
+        let resource_limits = ResourceLimits::for_level(security_level);
+        let container_id = Some(self.create_backend_sandbox(&id, &resource_limits).await?);
 
         let sandbox = VMSandbox {
             id: id.clone(),
@@ -102,6 +187,7 @@ impl VMManager {
             ip_address: None, // Would be assigned by backend
             created_at: Utc::now(),
             resource_limits,
+            handover_from: None,
         };
 
         self.sandboxes.insert(id.clone(), sandbox.clone());
@@ -119,12 +205,11 @@ impl VMManager {
     /// Destroy sandbox and cleanup resources
     pub async fn destroy_sandbox(&mut self, sandbox_id: &str) -> Result<()> {
         if let Some(sandbox) = self.sandboxes.remove(sandbox_id) {
+            if let Some((previous, _)) = &sandbox.handover_from {
+                self.destroy_backend_sandbox(previous).await?;
+            }
             if let Some(container_id) = &sandbox.container_id {
-                match self.backend {
-                    VMBackend::Docker => self.destroy_docker_sandbox(container_id).await?,
-                    VMBackend::QEMU => self.destroy_qemu_sandbox(container_id).await?,
-                    VMBackend::Firecracker => self.destroy_firecracker_sandbox(container_id).await?,
-                }
+                self.destroy_backend_sandbox(container_id).await?;
             }
 
             tracing::info!("🗑️  Destroyed sandbox {}", sandbox_id);
@@ -133,6 +218,108 @@ impl VMManager {
         Ok(())
     }
 
+    /// Apply new resource limits to a running sandbox
+    /// Docker is resized live and verified; backends that can't resize (or a
+    /// failed verification) fall back to recreating with a handover period.
+    /// Missing and mock sandboxes are skipped with a warning.
+    pub async fn update_limits(&mut self, sandbox_id: &str, new_limits: ResourceLimits) -> Result<ResizeOutcome> {
+        let Some(sandbox) = self.sandboxes.get(sandbox_id) else {
+            tracing::warn!("⚠️  Cannot resize sandbox {}: not found", sandbox_id);
+            return Ok(ResizeOutcome::Skipped("sandbox not found".to_string()));
+        };
+        if sandbox.resource_limits == new_limits {
+            return Ok(ResizeOutcome::Skipped("limits unchanged".to_string()));
+        }
+
+        let container_id = sandbox.container_id.clone().unwrap_or_default();
+        if container_id.is_empty() || container_id.starts_with("mock-") {
+            tracing::warn!("⚠️  Sandbox {} is a mock; recording new limits without resizing", sandbox_id);
+            self.set_limits(sandbox_id, new_limits);
+            return Ok(ResizeOutcome::Skipped("mock sandbox".to_string()));
+        }
+
+        if self.backend == VMBackend::Docker {
+            match self.resize_docker_sandbox(&container_id, &new_limits) {
+                Ok(()) => {
+                    self.set_limits(sandbox_id, new_limits);
+                    tracing::info!("📐 Resized sandbox {} in place", sandbox_id);
+                    return Ok(ResizeOutcome::Resized);
+                }
+                Err(e) => tracing::warn!("⚠️  Live resize of sandbox {} failed, recreating: {}", sandbox_id, e),
+            }
+        }
+
+        self.recreate_sandbox(sandbox_id, new_limits).await
+    }
+
+    /// Remove containers whose handover grace period has ended
+    pub async fn finish_handovers(&mut self, now: DateTime<Utc>) -> Result<usize> {
+        let expired: Vec<(String, String)> = self
+            .sandboxes
+            .iter()
+            .filter_map(|(id, sandbox)| match &sandbox.handover_from {
+                Some((container, until)) if *until <= now => Some((id.clone(), container.clone())),
+                _ => None,
+            })
+            .collect();
+
+        for (sandbox_id, container) in &expired {
+            self.destroy_backend_sandbox(container).await?;
+            if let Some(sandbox) = self.sandboxes.get_mut(sandbox_id) {
+                sandbox.handover_from = None;
+            }
+            tracing::info!("🗑️  Retired replaced container for sandbox {}", sandbox_id);
+        }
+        Ok(expired.len())
+    }
+
+    pub fn get_sandbox(&self, sandbox_id: &str) -> Option<&VMSandbox> {
+        self.sandboxes.get(sandbox_id)
+    }
+
+    fn set_limits(&mut self, sandbox_id: &str, limits: ResourceLimits) {
+        if let Some(sandbox) = self.sandboxes.get_mut(sandbox_id) {
+            sandbox.resource_limits = limits;
+        }
+    }
+
+    /// Start a replacement container; the old one keeps running for the grace period
+    async fn recreate_sandbox(&mut self, sandbox_id: &str, new_limits: ResourceLimits) -> Result<ResizeOutcome> {
+        let name = format!("{}-{}", sandbox_id, &uuid::Uuid::new_v4().simple().to_string()[..8]);
+        let new_container = self.create_backend_sandbox(&name, &new_limits).await?;
+
+        let sandbox = self
+            .sandboxes
+            .get_mut(sandbox_id)
+            .context("Sandbox disappeared during recreate")?;
+        let previous = sandbox.container_id.replace(new_container).unwrap_or_default();
+        sandbox.handover_from = Some((previous.clone(), Utc::now() + self.handover_grace));
+        sandbox.resource_limits = new_limits;
+
+        tracing::info!(
+            "🔁 Recreated sandbox {} with new limits; old container retires in {}s",
+            sandbox_id,
+            self.handover_grace.num_seconds()
+        );
+        Ok(ResizeOutcome::Recreated { previous_container: previous })
+    }
+
+    async fn create_backend_sandbox(&self, name: &str, limits: &ResourceLimits) -> Result<String> {
+        match self.backend {
+            VMBackend::Docker => self.create_docker_sandbox(name, limits).await,
+            VMBackend::QEMU => self.create_qemu_sandbox(name, limits).await,
+            VMBackend::Firecracker => self.create_firecracker_sandbox(name, limits).await,
+        }
+    }
+
+    async fn destroy_backend_sandbox(&self, container_id: &str) -> Result<()> {
+        match self.backend {
+            VMBackend::Docker => self.destroy_docker_sandbox(container_id).await,
+            VMBackend::QEMU => self.destroy_qemu_sandbox(container_id).await,
+            VMBackend::Firecracker => self.destroy_firecracker_sandbox(container_id).await,
+        }
+    }
+
     /// Check if there's capacity for new sandbox
     pub async fn has_capacity(&self) -> Result<bool> {
         Ok(self.sandboxes.len() < self.max_sandboxes)
@@ -150,53 +337,67 @@ impl VMManager {
     /// Detect available VM backend
     fn detect_backend() -> Result<VMBackend> {
         // Check for Docker
-        if Command::new("docker").arg("--version").output().is_ok() {
+        if Self::installed("docker") {
             return Ok(VMBackend::Docker);
         }
 
         // Check for QEMU
-        if Command::new("qemu-system-x86_64").arg("--version").output().is_ok() {
+        if Self::installed("qemu-system-x86_64") {
             return Ok(VMBackend::QEMU);
         }
 
-        // Check for Firecracker
-        if Command::new("firecracker").arg("--version").output().is_ok() {
+        // Check for Firecracker (KVM, so Linux only)
+        #[cfg(target_os = "linux")]
+        if Self::installed("firecracker") {
             return Ok(VMBackend::Firecracker);
         }
 
         // Default to Docker (will be created in mock mode if not available)
+        tracing::warn!("⚠️  No sandbox backend found; sandboxes will run in mock mode");
         Ok(VMBackend::Docker)
     }
 
+    /// Whether `program --version` runs and exits cleanly
+    fn installed(program: &str) -> bool {
+        Command::new(program)
+            .arg("--version")
+            .output()
+            .map(|output| output.status.success())
+            .unwrap_or(false)
+    }
+
     /// Create Docker container sandbox
     async fn create_docker_sandbox(&self, id: &str, limits: &ResourceLimits) -> Result<String> {
         // ✅ Quick win #3: Validate container name (prevent injection)
         let safe_id = Self::sanitize_container_name(id)?;
 
         // Create isolated network namespace with Docker
-        let output = Command::new("docker")
-            .args(&[
-                "run",
-                "-d",
-                "--name", &safe_id,
-                "--network", "none", // Isolated network
-                "--cpus", &format!("{}", limits.cpu_shares as f32 / 1024.0),
-                "--memory", &format!("{}m", limits.memory_mb),
-                "--cap-drop", "ALL", // Drop all capabilities
-                "--security-opt", "no-new-privileges",
-                "alpine:latest",
-                "sleep", "infinity",
-            ])
-            .output()
+        let output = self
+            .runner
+            .run(
+                "docker",
+                &[
+                    "run",
+                    "-d",
+                    "--name", &safe_id,
+                    "--network", "none", // Isolated network
+                    "--cpus", &limits.docker_cpus(),
+                    "--memory", &limits.docker_memory(),
+                    "--cap-drop", "ALL", // Drop all capabilities
+                    "--security-opt", "no-new-privileges",
+                    "alpine:latest",
+                    "sleep", "infinity",
+                ],
+            )
             .context("Failed to create Docker sandbox")?;
 
-        if !output.status.success() {
+        if !output.success {
             // If Docker not available, return mock ID
             tracing::warn!("Docker not available, using mock sandbox");
             return Ok(format!("mock-{}", id));
         }
 
-        let container_id = String::from_utf8(output.stdout)?.trim().to_string();
+        let container_id = output.stdout;
 
         tracing::info!("🐳 Created Docker sandbox: {}", container_id);
 
@@ -209,14 +410,40 @@ impl VMManager {
             return Ok(()); // Mock sandbox, nothing to destroy
         }
 
-        Command::new("docker")
-            .args(&["rm", "-f", container_id])
-            .output()
+        self.runner
+            .run("docker", &["rm", "-f", container_id])
             .context("Failed to destroy Docker sandbox")?;
 
         Ok(())
     }
 
+    /// Resize a Docker container with `docker update` and confirm via `docker inspect`
+    fn resize_docker_sandbox(&self, container_id: &str, limits: &ResourceLimits) -> Result<()> {
+        let memory = limits.docker_memory();
+        // Swap equal to memory keeps the update valid when memory grows
+        let update = self.runner.run(
+            "docker",
+            &["update", "--cpus", &limits.docker_cpus(), "--memory", &memory, "--memory-swap", &memory, container_id],
+        )?;
+        if !update.success {
+            anyhow::bail!("docker update failed: {}", update.stderr);
+        }
+
+        let inspect = self.runner.run(
+            "docker",
+            &["inspect", "--format", "{{.HostConfig.NanoCpus}} {{.HostConfig.Memory}}", container_id],
+        )?;
+        let expected = format!(
+            "{} {}",
+            (limits.cpu_shares as f64 / 1024.0 * 1e9).round() as u64,
+            limits.memory_mb as u64 * 1024 * 1024
+        );
+        if !inspect.success || inspect.stdout != expected {
+            anyhow::bail!("limits not applied (inspect reported '{}', expected '{}')", inspect.stdout, expected);
+        }
+        Ok(())
+    }
+
     /// Create QEMU VM sandbox (simplified)
     async fn create_qemu_sandbox(&self, id: &str, limits: &ResourceLimits) -> Result<String> {
         tracing::info!("🖥️  QEMU sandbox {} (mock mode)", id);
@@ -278,3 +505,106 @@ impl VMBackend {
         }
     }
 }
+
+/// A command runner for tests: replays scripted outputs and records every command
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::collections::VecDeque;
+
+    #[derive(Debug, Default)]
+    pub struct FakeRunner {
+        outputs: Mutex<VecDeque<CommandOutput>>,
+        pub calls: Mutex<Vec<String>>,
+    }
+
+    impl FakeRunner {
+        pub fn script(outputs: &[(bool, &str)]) -> Arc<Self> {
+            let runner = Self::default();
+            runner.outputs.lock().extend(outputs.iter().map(|(success, stdout)| CommandOutput {
+                success: *success,
+                stdout: stdout.to_string(),
+                stderr: String::new(),
+            }));
+            Arc::new(runner)
+        }
+
+        pub fn subcommands(&self) -> Vec<String> {
+            self.calls.lock().iter().map(|c| c.split(' ').nth(1).unwrap_or("").to_string()).collect()
+        }
+    }
+
+    impl CommandRunner for FakeRunner {
+        fn run(&self, program: &str, args: &[&str]) -> Result<CommandOutput> {
+            self.calls.lock().push(format!("{} {}", program, args.join(" ")));
+            Ok(self.outputs.lock().pop_front().unwrap_or(CommandOutput { success: true, ..Default::default() }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::FakeRunner;
+    use super::*;
+
+    const CRITICAL_INSPECT: &str = "1000000000 1073741824";
+
+    #[tokio::test]
+    async fn test_docker_resize_in_place() {
+        let runner = FakeRunner::script(&[(true, "c1"), (true, ""), (true, CRITICAL_INSPECT)]);
+        let mut vm = VMManager::with_runner(VMBackend::Docker, runner.clone());
+        let sandbox = vm.create_sandbox("peer", SecurityLevel::Privileged).await.unwrap();
+
+        let critical = ResourceLimits::for_level(SecurityLevel::Critical);
+        let outcome = vm.update_limits(&sandbox.id, critical.clone()).await.unwrap();
+
+        assert_eq!(outcome, ResizeOutcome::Resized);
+        assert_eq!(runner.subcommands(), vec!["run", "update", "inspect"]);
+        let resized = vm.get_sandbox(&sandbox.id).unwrap();
+        assert_eq!(resized.resource_limits, critical);
+        assert_eq!(resized.container_id.as_deref(), Some("c1"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_verify_recreates_with_handover() {
+        // inspect still shows the Privileged limits
+        let runner = FakeRunner::script(&[(true, "c1"), (true, ""), (true, "500000000 536870912"), (true, "c2")]);
+        let mut vm = VMManager::with_runner(VMBackend::Docker, runner.clone());
+        let sandbox = vm.create_sandbox("peer", SecurityLevel::Privileged).await.unwrap();
+
+        let outcome = vm
+            .update_limits(&sandbox.id, ResourceLimits::for_level(SecurityLevel::Critical))
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, ResizeOutcome::Recreated { previous_container: "c1".to_string() });
+        let replaced = vm.get_sandbox(&sandbox.id).unwrap().clone();
+        assert_eq!(replaced.container_id.as_deref(), Some("c2"));
+        let (old, until) = replaced.handover_from.unwrap();
+        assert_eq!(old, "c1");
+
+        // Old container survives the grace period, then is removed
+        assert_eq!(vm.finish_handovers(Utc::now()).await.unwrap(), 0);
+        assert_eq!(vm.finish_handovers(until).await.unwrap(), 1);
+        assert_eq!(runner.calls.lock().last().unwrap(), "docker rm -f c1");
+        assert!(vm.get_sandbox(&sandbox.id).unwrap().handover_from.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mock_and_missing_sandboxes_are_skipped() {
+        // docker run fails → mock sandbox
+        let runner = FakeRunner::script(&[(false, "")]);
+        let mut vm = VMManager::with_runner(VMBackend::Docker, runner.clone());
+        let sandbox = vm.create_sandbox("peer", SecurityLevel::Privileged).await.unwrap();
+
+        let critical = ResourceLimits::for_level(SecurityLevel::Critical);
+        let outcome = vm.update_limits(&sandbox.id, critical.clone()).await.unwrap();
+        assert!(matches!(outcome, ResizeOutcome::Skipped(_)));
+        assert_eq!(vm.get_sandbox(&sandbox.id).unwrap().resource_limits, critical);
+        assert_eq!(runner.subcommands(), vec!["run"]);
+
+        let outcome = vm.update_limits("qtz-missing", critical).await.unwrap();
+        assert!(matches!(outcome, ResizeOutcome::Skipped(_)));
+    }
+}
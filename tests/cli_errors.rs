@@ -0,0 +1,705 @@
+//! CLI exit codes and `--output json` error envelopes
+
+use assert_cmd::Command;
+use serde_json::Value;
+use std::process::Output;
+use tempfile::TempDir;
+
+/// Ephemeral run in a scratch directory so no local config is picked up
+fn quantraband(dir: &TempDir) -> Command {
+    let mut cmd = Command::cargo_bin("quantraband").unwrap();
+    cmd.current_dir(dir.path())
+        .arg("--ephemeral")
+        .arg("--data-dir")
+        .arg(dir.path());
+    cmd
+}
+
+fn run_json(dir: &TempDir, args: &[&str]) -> Output {
+    quantraband(dir).args(["--output", "json"]).args(args).output().unwrap()
+}
+
+/// The JSON document on stdout, which holds nothing else
+fn stdout_json(output: &Output) -> Value {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    serde_json::from_str(&stdout).unwrap_or_else(|e| panic!("{}: {}", e, stdout))
+}
+
+/// Envelope on stderr, checked against the expected exit and error codes
+fn assert_envelope(output: &Output, exit_code: i32, code: &str) -> Value {
+    assert_eq!(output.status.code(), Some(exit_code), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let envelope: Value = serde_json::from_str(stderr.trim()).unwrap_or_else(|_| panic!("not an envelope: {}", stderr));
+    assert_eq!(envelope["error"]["code"], code);
+    assert!(envelope["error"]["message"].as_str().is_some_and(|m| !m.is_empty()));
+    envelope
+}
+
+#[test]
+fn test_unknown_carrier() {
+    let dir = TempDir::new().unwrap();
+    let output = run_json(&dir, &["provision-esim", "--carrier", "nope", "--plan", "basic"]);
+    let envelope = assert_envelope(&output, 3, "UNKNOWN_CARRIER");
+    assert_eq!(envelope["error"]["details"]["carrier"], "nope");
+    // The command's trace ID, for finding its logs and audit events
+    assert!(envelope["error"]["trace_id"].as_str().is_some_and(|id| id.len() == 36));
+}
+
+#[test]
+fn test_usage_error() {
+    let dir = TempDir::new().unwrap();
+    let output = run_json(&dir, &["provision-esim", "--plan", "basic"]);
+    assert_envelope(&output, 2, "USAGE");
+}
+
+#[test]
+fn test_unknown_profile() {
+    let dir = TempDir::new().unwrap();
+    let output = run_json(&dir, &["esim", "show", "--iccid", "8900000000000000000"]);
+    assert_envelope(&output, 3, "UNKNOWN_PROFILE");
+}
+
+#[test]
+fn test_unknown_approval() {
+    let dir = TempDir::new().unwrap();
+    let output = run_json(&dir, &["approvals", "approve", "nope"]);
+    let envelope = assert_envelope(&output, 3, "UNKNOWN_APPROVAL");
+    assert_eq!(envelope["error"]["details"]["id"], "nope");
+}
+
+#[test]
+fn test_invalid_option_type() {
+    let dir = TempDir::new().unwrap();
+    let output = run_json(
+        &dir,
+        &[
+            "option-price", "--spot", "100", "--strike", "100", "--rate", "0.05",
+            "--volatility", "0.2", "--time", "1", "--option-type", "straddle",
+        ],
+    );
+    assert_envelope(&output, 4, "INVALID_OPTION_TYPE");
+}
+
+#[test]
+fn test_invalid_heston_params() {
+    let dir = TempDir::new().unwrap();
+    let args = ["option-price", "--spot", "100", "--strike", "100", "--rate", "0.05", "--time", "1", "--model", "heston"];
+    let output = run_json(&dir, &[&args[..], &["--heston-params", "0.04,2,0.04,0.5"]].concat());
+    let envelope = assert_envelope(&output, 4, "INVALID_HESTON_PARAMS");
+    assert_eq!(envelope["error"]["details"]["heston_params"], "0.04,2,0.04,0.5");
+
+    let output = run_json(&dir, &[&args[..], &["--heston-params", "0.04,2,0.04,0.5,-0.7"]].concat());
+    assert!(output.status.success(), "{:?}", output);
+    let priced = stdout_json(&output);
+    assert!(priced["price"].as_f64().is_some_and(|price| price > 0.0), "{}", priced);
+    assert_eq!(priced["greeks_method"], "finite differences");
+}
+
+#[test]
+fn test_invalid_bond() {
+    let dir = TempDir::new().unwrap();
+    let args = ["bond-price", "--coupon-rate", "0.05", "--periods-per-year", "2"];
+    let output = run_json(&dir, &[&args[..], &["--ytm", "0.04", "--years", "2.3"]].concat());
+    let envelope = assert_envelope(&output, 4, "INVALID_BOND");
+    assert_eq!(envelope["error"]["details"]["input"], "years");
+
+    // A negative yield, then back from its price
+    let output = run_json(&dir, &[&args[..], &["--ytm", "-0.01", "--years", "3"]].concat());
+    assert!(output.status.success(), "{:?}", output);
+    let priced = stdout_json(&output);
+    let price = priced["price"].as_f64().unwrap().to_string();
+    let output = run_json(&dir, &[&args[..], &["--price", &price, "--years", "3"]].concat());
+    let solved = stdout_json(&output);
+    assert!((solved["ytm"].as_f64().unwrap() + 0.01).abs() < 1e-9, "{}", solved);
+}
+
+#[test]
+fn test_rebalance_targets() {
+    let dir = TempDir::new().unwrap();
+    let output = run_json(&dir, &["portfolio", "rebalance", "--targets", "AAPL=0.5,MSFT"]);
+    let envelope = assert_envelope(&output, 4, "INVALID_TARGETS");
+    assert!(envelope["error"]["message"].as_str().unwrap().contains("'MSFT'"), "{}", envelope);
+
+    let output = run_json(&dir, &["portfolio", "rebalance", "--targets", "AAPL=0.5,MSFT=0.5", "--cash-in", "1000"]);
+    assert!(output.status.success(), "{:?}", output);
+    let plan = stdout_json(&output);
+    assert_eq!(plan["new_positions"], serde_json::json!(["AAPL", "MSFT"]));
+    assert_eq!(plan["trades"].as_array().unwrap().len(), 2);
+
+    // A targets file holds TOML floats; they must still sum to exactly 1
+    let targets = dir.path().join("targets.toml");
+    std::fs::write(&targets, "AAPL = 0.7\nMSFT = 0.2\nNVDA = 0.1\n").unwrap();
+    let output = run_json(&dir, &["portfolio", "rebalance", "--targets", targets.to_str().unwrap(), "--cash-in", "1000"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(stdout_json(&output)["trades"].as_array().unwrap().len(), 3);
+}
+
+#[test]
+fn test_invalid_config() {
+    let dir = TempDir::new().unwrap();
+    let config = dir.path().join("bad.toml");
+    std::fs::write(&config, "[p2p\nnot toml").unwrap();
+    let output = run_json(&dir, &["--config", config.to_str().unwrap(), "list-carriers"]);
+    assert_envelope(&output, 4, "INVALID_CONFIG");
+}
+
+#[test]
+fn test_quote_source() {
+    let dir = TempDir::new().unwrap();
+    let config = dir.path().join("http.toml");
+    std::fs::write(&config, "[quant.market_data.http]\nquote_url = \"http://127.0.0.1:9/quote\"\n").unwrap();
+    let output = run_json(&dir, &["--config", config.to_str().unwrap(), "quote", "--symbol", "AAPL", "--source", "http"]);
+    let envelope = assert_envelope(&output, 4, "INVALID_CONFIG");
+    assert!(envelope["error"]["message"].as_str().unwrap().contains("{symbol}"), "{}", envelope);
+
+    let output = run_json(&dir, &["--config", config.to_str().unwrap(), "quote", "--symbol", "AAPL", "--source", "mock"]);
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn test_history() {
+    let dir = TempDir::new().unwrap();
+    let output = run_json(&dir, &["history", "--symbol", "AAPL", "--days", "0"]);
+    assert_envelope(&output, 4, "INVALID_DAYS");
+
+    let csv = dir.path().join("aapl.csv");
+    let output = run_json(&dir, &["history", "--symbol", "AAPL", "--days", "30", "--export", "csv", "--out", csv.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+    let report = stdout_json(&output);
+    assert_eq!(report["candles"].as_array().unwrap().len(), 30);
+    let lines: Vec<String> = std::fs::read_to_string(&csv).unwrap().lines().map(str::to_string).collect();
+    assert_eq!(lines[0], "symbol,timestamp,open,high,low,close,volume");
+    assert_eq!(lines.len(), 31);
+
+    let parquet = dir.path().join("aapl.parquet");
+    let output = run_json(&dir, &["history", "--symbol", "AAPL", "--days", "30", "--export", "parquet", "--out", parquet.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(&std::fs::read(&parquet).unwrap()[..4], b"PAR1");
+
+    let output = run_json(&dir, &["history", "--symbol", "AAPL", "--export", "csv"]);
+    assert_envelope(&output, 2, "USAGE");
+
+    // --csv is kept as shorthand for --export csv --out
+    let alias = dir.path().join("alias.csv");
+    let output = run_json(&dir, &["history", "--symbol", "AAPL", "--days", "30", "--csv", alias.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(std::fs::read_to_string(&alias).unwrap().lines().count(), 31);
+}
+
+#[test]
+fn test_missing_input_file() {
+    let dir = TempDir::new().unwrap();
+    let output = run_json(&dir, &["carrier-update", "sign", "--input", "missing.json", "--key", "missing.key"]);
+    let envelope = assert_envelope(&output, 3, "FILE_NOT_FOUND");
+    assert_eq!(envelope["error"]["details"]["io_kind"], "NotFound");
+}
+
+#[test]
+fn test_text_mode_keeps_exit_code() {
+    let dir = TempDir::new().unwrap();
+    let output = quantraband(&dir)
+        .args(["provision-esim", "--carrier", "nope", "--plan", "basic"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Error: Unknown carrier 'nope'"));
+}
+
+#[test]
+fn test_success_output_never_on_stderr() {
+    let dir = TempDir::new().unwrap();
+    for args in [&["list-carriers"][..], &["carrier-update", "keygen"], &["--output", "json", "list-carriers"]] {
+        let output = quantraband(&dir).args(args).output().unwrap();
+        assert!(output.status.success(), "{:?}", args);
+        assert!(!output.stdout.is_empty());
+        assert!(output.stderr.is_empty(), "{:?} wrote to stderr: {}", args, String::from_utf8_lossy(&output.stderr));
+    }
+    // No log lines ahead of the document
+    stdout_json(&run_json(&dir, &["secrets", "list"]));
+    println!("✅ CLI error envelope tests PASSED!");
+}
+
+#[test]
+fn test_sizing_errors() {
+    let dir = TempDir::new().unwrap();
+    let buy = ["portfolio", "buy", "--symbol", "AAPL", "--price", "50", "--capital", "1000", "--size-by"];
+    let output = run_json(&dir, &[&buy[..], &["kelly", "--win-prob", "0.3", "--win-loss-ratio", "1"]].concat());
+    let envelope = assert_envelope(&output, 4, "ZERO_SIZE");
+    assert_eq!(envelope["error"]["details"]["binding"], "kelly");
+    assert!(envelope["error"]["details"]["reason"].as_str().unwrap().starts_with("no edge"));
+
+    let output = run_json(&dir, &[&buy[..], &["vol-target", "--target-vol", "10%", "--asset-vol", "25%", "--participation", "200%", "--adv", "1"]].concat());
+    assert_envelope(&output, 4, "INVALID_SIZING");
+}
+
+#[test]
+fn test_lots_need_specific_lot_basis() {
+    let dir = TempDir::new().unwrap();
+    let sell = ["portfolio", "sell", "--symbol", "AAPL", "--quantity", "5", "--price", "50", "--lots", "a1b2c3d4"];
+    let envelope = assert_envelope(&run_json(&dir, &sell), 4, "LOTS_UNUSED");
+    assert!(envelope["error"]["message"].as_str().unwrap().contains("specific_lot"));
+}
+
+#[test]
+fn test_invalid_corporate_action() {
+    let dir = TempDir::new().unwrap();
+    let output = run_json(&dir, &["actions", "add", "AAPL", "split", "0:1", "--ex-date", "2024-06-10"]);
+    let envelope = assert_envelope(&output, 4, "INVALID_CORPORATE_ACTION");
+    assert!(envelope["error"]["message"].as_str().unwrap().contains("positive"));
+
+    let csv = dir.path().join("actions.csv");
+    std::fs::write(&csv, "symbol,ex_date,kind,value\nAAPL,2024-06-10,spinoff,1\n").unwrap();
+    let output = run_json(&dir, &["actions", "import", csv.to_str().unwrap()]);
+    assert_envelope(&output, 4, "INVALID_CORPORATE_ACTION");
+}
+
+#[test]
+fn test_no_listen_address_binds() {
+    let dir = TempDir::new().unwrap();
+    let output = run_json(&dir, &["p2p", "--listen", "not-a-multiaddr", "--listen", "/unix/tmp/quantra.sock"]);
+    let envelope = assert_envelope(&output, 5, "LISTEN_FAILED");
+    assert_eq!(envelope["error"]["details"]["failed"].as_array().unwrap().len(), 2);
+}
+
+/// Persistent run: stores live under the scratch directory
+fn quantraband_persistent(dir: &TempDir) -> Command {
+    let mut cmd = Command::cargo_bin("quantraband").unwrap();
+    cmd.current_dir(dir.path()).arg("--data-dir").arg(dir.path());
+    cmd
+}
+
+/// Portfolio database as written before positions had a currency
+fn legacy_portfolio(dir: &TempDir) {
+    let db = sled::open(dir.path().join("portfolio")).unwrap();
+    let position = r#"{"symbol":"AAPL","quantity":"10","average_cost":"150","current_price":"155"}"#;
+    db.open_tree("portfolio").unwrap().insert("position/AAPL", position.as_bytes()).unwrap();
+    db.flush().unwrap();
+}
+
+#[test]
+fn test_stored_data_newer_than_binary() {
+    let dir = TempDir::new().unwrap();
+    {
+        let db = sled::open(dir.path().join("portfolio")).unwrap();
+        db.open_tree("__schema").unwrap().insert("version", &99u32.to_be_bytes()).unwrap();
+        db.flush().unwrap();
+    }
+    let output = quantraband_persistent(&dir).args(["--output", "json", "migrate", "--dry-run"]).output().unwrap();
+    let envelope = assert_envelope(&output, 4, "SCHEMA_TOO_NEW");
+    assert_eq!(envelope["error"]["details"]["store"], "portfolio");
+    assert_eq!(envelope["error"]["details"]["found"], 99);
+}
+
+#[test]
+fn test_pending_migrations() {
+    let dir = TempDir::new().unwrap();
+    legacy_portfolio(&dir);
+
+    let output = quantraband_persistent(&dir).args(["--output", "json", "--no-migrate", "portfolio", "show"]).output().unwrap();
+    assert_envelope(&output, 4, "MIGRATIONS_PENDING");
+
+    let output = quantraband_persistent(&dir).args(["--output", "json", "migrate", "--dry-run"]).output().unwrap();
+    assert!(output.status.success());
+    let pending = stdout_json(&output);
+    assert_eq!((pending[0]["store"].as_str(), pending[0]["from"].as_u64()), (Some("portfolio"), Some(1)));
+
+    // Without --no-migrate the store is migrated on the way in
+    let output = quantraband_persistent(&dir).args(["portfolio", "show"]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("AAPL"));
+    assert!(dir.path().join("portfolio.bak").exists());
+}
+
+#[test]
+fn test_portfolio_export() {
+    let dir = TempDir::new().unwrap();
+    legacy_portfolio(&dir);
+    let csv = dir.path().join("positions.csv");
+    let output = quantraband_persistent(&dir).args(["portfolio", "show", "--export", "csv", "--out", csv.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let lines: Vec<String> = std::fs::read_to_string(&csv).unwrap().lines().map(str::to_string).collect();
+    assert_eq!(lines, ["symbol,quantity,average_cost,current_price,market_value,unrealized_pnl", "AAPL,10,150,155,1550,50"]);
+
+    let parquet = dir.path().join("positions.parquet");
+    let output = quantraband_persistent(&dir).args(["portfolio", "export", "--out", parquet.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(&std::fs::read(&parquet).unwrap()[..4], b"PAR1");
+
+    for (symbol, quantity) in [("MSFT", "5"), ("NVDA", "2")] {
+        let output = quantraband_persistent(&dir).args(["portfolio", "buy", "--symbol", symbol, "--quantity", quantity, "--price", "100"]).output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+    }
+    let trades = dir.path().join("trades.csv");
+    let output = quantraband_persistent(&dir).args(["portfolio", "ledger", "--export", "csv", "--out", trades.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let lines: Vec<String> = std::fs::read_to_string(&trades).unwrap().lines().map(str::to_string).collect();
+    assert_eq!(lines[0], "trade_id,symbol,side,quantity,price,timestamp");
+    assert!(lines[1].contains(",MSFT,buy,5,100,") && lines[2].contains(",NVDA,buy,2,100,"), "{:?}", lines);
+}
+
+#[test]
+fn test_trades_import_round_trip() {
+    let dir = TempDir::new().unwrap();
+    for (side, symbol, quantity) in [("buy", "MSFT", "5"), ("buy", "NVDA", "2"), ("sell", "MSFT", "3")] {
+        let output = quantraband_persistent(&dir).args(["portfolio", side, "--symbol", symbol, "--quantity", quantity, "--price", "100"]).output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+    }
+    let trades = dir.path().join("trades.csv");
+    let output = quantraband_persistent(&dir).args(["trades", "list", "--export", "csv", "--out", trades.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    let copy = TempDir::new().unwrap();
+    let output = quantraband_persistent(&copy).args(["trades", "import", trades.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Recorded 3 trade(s)"), "{:?}", output);
+    let again = copy.path().join("again.csv");
+    let output = quantraband_persistent(&copy).args(["trades", "list", "--export", "csv", "--out", again.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(std::fs::read_to_string(&again).unwrap(), std::fs::read_to_string(&trades).unwrap());
+
+    // Trades already in the ledger are not recorded twice
+    let output = quantraband_persistent(&copy).args(["trades", "import", trades.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Recorded 0 trade(s)"), "{:?}", output);
+    let output = quantraband_persistent(&copy).args(["portfolio", "show"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("MSFT     2 @ 100"), "{:?}", output);
+}
+
+#[test]
+fn test_risk_report_export() {
+    let dir = TempDir::new().unwrap();
+    let output = quantraband_persistent(&dir).args(["--output", "json", "risk-report"]).output().unwrap();
+    assert_envelope(&output, 3, "NO_POSITIONS");
+
+    let output = quantraband_persistent(&dir).args(["portfolio", "buy", "--symbol", "AAPL", "--quantity", "10", "--price", "150"]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let metrics = dir.path().join("risk.csv");
+    let output = quantraband_persistent(&dir)
+        .args(["--output", "json", "risk-report", "--days", "60", "--export", "csv", "--out", metrics.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let report = stdout_json(&output);
+    assert_eq!(report["historical"]["scenarios"], 59);
+    let lines: Vec<String> = std::fs::read_to_string(&metrics).unwrap().lines().map(str::to_string).collect();
+    assert_eq!(lines[0], "metric,value,computed_at");
+    assert_eq!(lines.len(), 11);
+    assert!(lines[1].starts_with("total_value,"), "{:?}", lines);
+}
+
+#[test]
+fn test_strategy_plugin_failure() {
+    let dir = TempDir::new().unwrap();
+    let candles = dir.path().join("candles.csv");
+    std::fs::write(
+        &candles,
+        "symbol,timestamp,open,high,low,close,volume\nAAPL,2024-01-02T00:00:00Z,100,101,99,100,1000\n",
+    )
+    .unwrap();
+    let spin = dir.path().join("spin.wat");
+    std::fs::write(
+        &spin,
+        r#"(module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 0))
+          (func (export "init") (param i32 i32) (result i32) (i32.const 0))
+          (func (export "on_candle") (param i32 i32) (result i32) (loop (br 0)) (i32.const 0)))"#,
+    )
+    .unwrap();
+    let args = ["backtest", "--candles", candles.to_str().unwrap(), "--strategy-wasm", spin.to_str().unwrap()];
+    let envelope = assert_envelope(&run_json(&dir, &args), 1, "STRATEGY_ERROR");
+    assert_eq!(envelope["error"]["details"]["reason"], "on_candle ran out of fuel");
+
+    // The bundled momentum rule needs a lookback
+    let momentum = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/plugins/momentum.wat");
+    let args = ["backtest", "--candles", candles.to_str().unwrap(), "--strategy-wasm", momentum];
+    assert_envelope(&run_json(&dir, &args), 4, "INVALID_STRATEGY");
+}
+
+#[test]
+fn test_incompatible_device_strict() {
+    let dir = TempDir::new().unwrap();
+    // An M2M-only eUICC range
+    let m2m = "89001012000000000000000000424234";
+    let args = ["provision-esim", "--carrier", "tmobile", "--plan", "basic", "--eid", m2m, "--strict"];
+    let envelope = assert_envelope(&run_json(&dir, &args), 4, "DEVICE_INCOMPATIBLE");
+    assert_eq!(envelope["error"]["details"]["esim_supported"], false);
+
+    let args = ["esim", "check", "--eid", "89049032123451234512345678901234", "--carrier", "tmobile"];
+    let envelope = assert_envelope(&run_json(&dir, &args), 4, "INVALID_EID");
+    assert_eq!(envelope["error"]["details"]["reason"], "EID check digits are 34 but should be 35");
+
+    let output = run_json(&dir, &["esim", "check", "--eid", m2m, "--carrier", "tmobile"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let report: Value = serde_json::from_str(&stdout[stdout.find("{\n").unwrap()..]).unwrap();
+    assert_eq!(report["manufacturer"], "STMicroelectronics");
+}
+
+#[test]
+fn test_tampered_transcript() {
+    use quantra::crypto::key_provider::FileKeyProvider;
+    use quantra::p2p::transcript::TranscriptStore;
+
+    let dir = TempDir::new().unwrap();
+    let keypair = libp2p::identity::Keypair::generate_ed25519();
+    let secret = keypair.clone().try_into_ed25519().unwrap().secret();
+    let key = FileKeyProvider::new(ed25519_dalek::SigningKey::from_bytes(secret.as_ref().try_into().unwrap()));
+    let (local, peer) = (libp2p::PeerId::from(keypair.public()), libp2p::PeerId::random());
+    let mut store = TranscriptStore::default();
+    store.record(peer, &peer, b"offer 102");
+    store.record(peer, &local, b"accept");
+    let mut bundle = store.export(&local, &key, &peer, "..".parse().unwrap()).unwrap();
+
+    let path = dir.path().join("bundle.json");
+    std::fs::write(&path, serde_json::to_string(&bundle).unwrap()).unwrap();
+    let output = run_json(&dir, &["verify-transcript", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let report: Value = serde_json::from_str(&stdout[stdout.find("{\n").unwrap()..]).unwrap();
+    assert_eq!(report["cosigned"], false);
+    let args = ["verify-transcript", path.to_str().unwrap(), "--require-cosigned"];
+    assert_envelope(&run_json(&dir, &args), 7, "NOT_COSIGNED");
+
+    bundle.messages[0].body.0 = b"offer 90".to_vec();
+    std::fs::write(&path, serde_json::to_string(&bundle).unwrap()).unwrap();
+    let envelope = assert_envelope(&run_json(&dir, &["verify-transcript", path.to_str().unwrap()]), 7, "TRANSCRIPT_INVALID");
+    assert_eq!(envelope["error"]["details"]["reason"], "message 0 does not match its chain hash");
+}
+
+#[test]
+fn test_proxy_unavailable() {
+    let dir = TempDir::new().unwrap();
+    let config = dir.path().join("proxy.toml");
+    // Nothing listens on port 1
+    std::fs::write(&config, "[network.proxy]\nenabled = true\nhost = \"127.0.0.1\"\nport = 1\n").unwrap();
+    let output = run_json(&dir, &["--config", config.to_str().unwrap(), "network", "doctor"]);
+    assert_envelope(&output, 1, "PROXY_UNAVAILABLE");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let report: Value = serde_json::from_str(&stdout[stdout.find("{\n").unwrap()..]).unwrap();
+    assert_eq!(report["proxy"], "socks5h://127.0.0.1:1");
+    assert_eq!(report["checks"][1]["name"], "reach");
+    assert_eq!(report["checks"][1]["ok"], false);
+}
+
+#[cfg(not(feature = "sqlite"))]
+#[test]
+fn test_sqlite_not_compiled() {
+    let dir = TempDir::new().unwrap();
+    let output = quantraband_persistent(&dir).args(["--output", "json", "storage", "migrate", "--to", "sqlite"]).output().unwrap();
+    assert_envelope(&output, 4, "NOT_COMPILED");
+    assert!(!dir.path().join("quantra.db").exists());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_storage_migrate_to_sqlite() {
+    let dir = TempDir::new().unwrap();
+    legacy_portfolio(&dir);
+
+    // Migrated in sled on the way in, then copied
+    let output = quantraband_persistent(&dir).args(["storage", "migrate", "--to", "sqlite"]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("portfolio") && stdout.contains("1 entries copied, 1 verified (v2)"), "{}", stdout);
+    assert!(dir.path().join("quantra.db").exists());
+
+    // Copying again would overwrite what SQLite now holds
+    let output = quantraband_persistent(&dir).args(["storage", "migrate", "--to", "sqlite"]).output().unwrap();
+    assert!(!output.status.success());
+
+    // Read back from SQLite alone
+    let config = dir.path().join("sqlite.toml");
+    std::fs::write(&config, "[storage]\nbackend = \"sqlite\"\n").unwrap();
+    std::fs::remove_dir_all(dir.path().join("portfolio")).unwrap();
+    let output = quantraband_persistent(&dir).args(["--config", config.to_str().unwrap(), "portfolio", "show"]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("AAPL"));
+}
+
+#[cfg(not(feature = "pkcs11"))]
+#[test]
+fn test_key_provider_unavailable() {
+    let dir = TempDir::new().unwrap();
+    let config = dir.path().join("hsm.toml");
+    std::fs::write(&config, "[keys]\nprovider = \"pkcs11\"\n").unwrap();
+    let output = run_json(&dir, &["--config", config.to_str().unwrap(), "p2p"]);
+    let envelope = assert_envelope(&output, 1, "KEY_PROVIDER_UNAVAILABLE");
+    assert_eq!(envelope["error"]["details"]["problem"], "not_compiled");
+    assert!(envelope["error"]["message"].as_str().unwrap().contains("keys doctor"));
+
+    let output = run_json(&dir, &["--config", config.to_str().unwrap(), "keys", "doctor"]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let report: Value = serde_json::from_str(&stdout[stdout.find("{\n").unwrap()..]).unwrap();
+    assert_eq!(report["checks"][0]["hint"], "rebuild with `--features pkcs11`, or set keys.provider = \"file\"");
+}
+
+#[test]
+fn test_unknown_keystore() {
+    let dir = TempDir::new().unwrap();
+    let output = run_json(&dir, &["generate-key", "--user-id", "me@example.com", "--keystore", "nope"]);
+    let envelope = assert_envelope(&output, 3, "UNKNOWN_KEYSTORE");
+    assert_eq!(envelope["error"]["details"]["keystore"], "nope");
+}
+
+#[test]
+fn test_loadtest_invalid_target() {
+    let dir = TempDir::new().unwrap();
+    let output = run_json(&dir, &["loadtest", "--mode", "request", "--target", "not-an-address"]);
+    assert_envelope(&output, 4, "INVALID_ADDRESS");
+}
+
+#[test]
+fn test_watchlist_errors() {
+    let dir = TempDir::new().unwrap();
+    let output = run_json(&dir, &["watchlist", "add", "not a symbol"]);
+    let envelope = assert_envelope(&output, 4, "INVALID_SYMBOL");
+    assert_eq!(envelope["error"]["details"]["symbol"], "NOT A SYMBOL");
+
+    let output = run_json(&dir, &["watchlist", "remove", "aapl"]);
+    let envelope = assert_envelope(&output, 3, "NOT_WATCHED");
+    assert_eq!(envelope["error"]["details"]["symbol"], "AAPL");
+}
+
+#[test]
+fn test_secrets_are_redacted() {
+    const VALUE: &str = "taxii-pw-5f1c9e";
+    let dir = TempDir::new().unwrap();
+    let config = dir.path().join("secrets.toml");
+    std::fs::write(
+        &config,
+        "[intel.taxii]\npassword = \"secret://taxii.password\"\n\n[esim]\napi_key = \"plain-esim-key-77d0\"\n",
+    )
+    .unwrap();
+    let config = config.to_str().unwrap();
+    let run = |args: &[&str]| quantraband_persistent(&dir).args(["--config", config]).args(args).output().unwrap();
+    let assert_redacted = |output: &Output| {
+        let all = [output.stdout.as_slice(), &output.stderr].concat();
+        let all = String::from_utf8_lossy(&all);
+        assert!(!all.contains(VALUE) && !all.contains("plain-esim-key-77d0"), "{}", all);
+    };
+
+    let output = quantraband_persistent(&dir)
+        .args(["--config", config, "secrets", "set", "taxii.password"])
+        .write_stdin(format!("{}\n", VALUE))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_redacted(&output);
+
+    for format in ["text", "json"] {
+        let output = run(&["--output", format, "config", "show"]);
+        assert!(output.status.success(), "{:?}", output);
+        assert!(String::from_utf8_lossy(&output.stdout).contains("secret://taxii.password"));
+        assert_redacted(&output);
+    }
+
+    let output = run(&["--output", "json", "secrets", "list"]);
+    let listing = stdout_json(&output);
+    assert_eq!(listing["secrets"][0]["name"], "taxii.password");
+    assert_eq!(listing["secrets"][0]["referenced_by"][0], "intel.taxii.password");
+    assert_redacted(&output);
+
+    // Commands resolve the reference without printing or logging it
+    let output = run(&["keys", "doctor"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_redacted(&output);
+
+    // A missing secret names itself and the key referencing it, nothing more
+    std::fs::write(
+        dir.path().join("secrets.toml"),
+        "[intel.taxii]\npassword = \"secret://taxii.password\"\n\n\
+         [[notifications.sinks]]\nname = \"ops\"\nkind = \"webhook\"\nurl = \"secret://ops.webhook\"\n",
+    )
+    .unwrap();
+    let output = run(&["--output", "json", "keys", "doctor"]);
+    let envelope = assert_envelope(&output, 3, "SECRET_UNRESOLVED");
+    assert_eq!(envelope["error"]["details"]["secret"], "ops.webhook");
+    assert_eq!(envelope["error"]["details"]["config_key"], "notifications.sinks[0].url");
+    assert_redacted(&output);
+
+    let output = run(&["--output", "json", "secrets", "rm", "ops.webhook"]);
+    assert_envelope(&output, 3, "UNKNOWN_SECRET");
+}
+
+#[test]
+fn test_empty_search_query() {
+    let dir = TempDir::new().unwrap();
+    assert_envelope(&run_json(&dir, &["search", "  "]), 4, "EMPTY_QUERY");
+    assert_envelope(&run_json(&dir, &["search", "aapl", "--store", "contacts"]), 2, "USAGE");
+
+    let output = run_json(&dir, &["search", "vodafone", "--store", "carriers", "--limit", "1"]);
+    assert!(output.status.success(), "{:?}", output);
+    let groups = stdout_json(&output);
+    assert_eq!(groups[0]["store"], "carriers");
+    assert_eq!(groups[0]["hits"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn test_encrypt_unknown_recipient() {
+    let dir = TempDir::new().unwrap();
+    let output = run_json(&dir, &["encrypt", "--recipient", "bob@example.com", "--message", "hi"]);
+    let envelope = assert_envelope(&output, 3, "KEY_NOT_FOUND");
+    assert_eq!(envelope["error"]["details"]["key"], "bob@example.com");
+    assert_eq!(envelope["error"]["details"]["known"], serde_json::json!([]));
+
+    let sealed = dir.path().join("message.asc");
+    std::fs::write(&sealed, "not armored").unwrap();
+    let output = run_json(&dir, &["decrypt", "--input", sealed.to_str().unwrap()]);
+    assert_envelope(&output, 4, "UNDECRYPTABLE");
+}
+
+#[test]
+fn test_key_management_errors() {
+    let dir = TempDir::new().unwrap();
+    let output = run_json(&dir, &["keys", "delete", "alice@example.com"]);
+    let envelope = assert_envelope(&output, 3, "KEY_NOT_FOUND");
+    assert_eq!(envelope["error"]["details"]["key"], "alice@example.com");
+
+    let output = run_json(&dir, &["keys", "export", "alice@example.com", "--secret"]);
+    assert_envelope(&output, 4, "SECRET_EXPORT_UNCONFIRMED");
+
+    let key = dir.path().join("alice.asc");
+    std::fs::write(&key, "not armored").unwrap();
+    let output = run_json(&dir, &["keys", "import", "--input", key.to_str().unwrap()]);
+    assert_envelope(&output, 4, "INVALID_PUBLIC_KEY");
+}
+
+#[test]
+fn test_legacy_keystore_moved_into_data_dir() {
+    let dir = TempDir::new().unwrap();
+    // Before data directories the keystore was ./keystore
+    let output = quantraband_persistent(&dir).args(["generate-key", "--user-id", "alice@example.com"]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(dir.path().join("keystore").is_dir());
+
+    let data = dir.path().join("data");
+    let list = |data: &std::path::Path| {
+        let mut cmd = Command::cargo_bin("quantraband").unwrap();
+        cmd.current_dir(dir.path()).arg("--data-dir").arg(data).args(["--output", "json", "keys", "list"]).output().unwrap()
+    };
+    let output = list(&data);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(stdout_json(&output).to_string().contains("alice@example.com"));
+    assert!(!dir.path().join("keystore").exists());
+    assert!(data.join("keystore").is_dir());
+
+    // Keys in both places: refuse rather than pick one
+    let output = quantraband_persistent(&dir).args(["generate-key", "--user-id", "bob@example.com"]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let envelope = assert_envelope(&list(&data), 4, "LEGACY_KEYSTORE_CONFLICT");
+    assert!(envelope["error"]["details"]["legacy"].as_str().unwrap().ends_with("keystore"));
+    assert!(dir.path().join("keystore").is_dir());
+}
+
+#[test]
+fn test_corrupt_node_key() {
+    let dir = TempDir::new().unwrap();
+    let key = dir.path().join("node_key");
+    std::fs::write(&key, "not a key").unwrap();
+    let output = run_json(&dir, &["p2p", "--identity", key.to_str().unwrap()]);
+    let envelope = assert_envelope(&output, 7, "CORRUPT_NODE_KEY");
+    assert_eq!(envelope["error"]["details"]["path"], key.to_str().unwrap());
+    assert_eq!(std::fs::read(&key).unwrap(), b"not a key");
+}
@@ -0,0 +1,298 @@
+//! Golden files for the CLI's human-readable and JSON output
+//!
+//! Each case runs `quantraband` against a scratch data directory with the
+//! clock pinned (`QUANTRA_CLOCK`) and compares the scrubbed output with
+//! `tests/snapshots/<name>`. Log lines, the scratch path, timestamps,
+//! UUIDs, short trade/cash-flow ids and ports are scrubbed; JSON floats
+//! are compared to 10 significant digits so libm differences between
+//! platforms don't show up as changes.
+//!
+//! After an intended output change, re-record and review the diff:
+//!
+//! ```text
+//! UPDATE_SNAPSHOTS=1 cargo test --test cli_snapshots
+//! git diff tests/snapshots
+//! ```
+
+use assert_cmd::Command;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use tempfile::TempDir;
+
+const CLOCK: &str = "2026-03-02T14:30:00Z";
+
+static LOG_LINE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?m)^(\x1b\[[0-9;]*m)*\d{4}-\d{2}-\d{2}T\S+\s+(\x1b\[[0-9;]*m)*\s*(TRACE|DEBUG|INFO|WARN|ERROR)\b.*\n?")
+        .unwrap()
+});
+
+/// Replacements applied, in order, to everything compared
+static SCRUBBERS: Lazy<Vec<(Regex, &str)>> = Lazy::new(|| {
+    [
+        (r"\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:\d{2})", "[timestamp]"),
+        (r"[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}", "[uuid]"),
+        (r"(Recorded (?:deposit|withdrawal) |\[timestamp\]  )[0-9a-f]{8}\b", "${1}[id]"),
+        (r"(/tcp/|/udp/|127\.0\.0\.1:|localhost:)\d+", "${1}[port]"),
+    ]
+    .into_iter()
+    .map(|(pattern, replacement)| (Regex::new(pattern).unwrap(), replacement))
+    .collect()
+});
+
+/// A scratch data directory the commands run against
+struct Cli {
+    dir: TempDir,
+    ephemeral: bool,
+    config: Option<PathBuf>,
+    clock: &'static str,
+}
+
+impl Cli {
+    fn ephemeral() -> Self {
+        Self { dir: TempDir::new().unwrap(), ephemeral: true, config: None, clock: CLOCK }
+    }
+
+    fn persistent() -> Self {
+        Self { ephemeral: false, ..Self::ephemeral() }
+    }
+
+    fn with_config(mut self, toml: &str) -> Self {
+        let path = self.dir.path().join("config.toml");
+        std::fs::write(&path, toml).unwrap();
+        self.config = Some(path);
+        self
+    }
+
+    fn output(&self, args: &[&str]) -> Output {
+        let mut cmd = Command::cargo_bin("quantraband").unwrap();
+        cmd.current_dir(self.dir.path()).arg("--data-dir").arg(self.dir.path());
+        if self.ephemeral {
+            cmd.arg("--ephemeral");
+        }
+        if let Some(config) = &self.config {
+            cmd.arg("--config").arg(config);
+        }
+        for (name, _) in std::env::vars().filter(|(name, _)| name.starts_with("QUANTRA_SECRET_")) {
+            cmd.env_remove(name);
+        }
+        cmd.env("QUANTRA_CLOCK", self.clock).env("NO_COLOR", "1").args(args).output().unwrap()
+    }
+
+    /// Scrubbed stdout of a successful text-mode run
+    fn text(&self, args: &[&str]) -> String {
+        let output = self.output(args);
+        assert!(output.status.success(), "{:?}", output);
+        self.scrub(&LOG_LINE.replace_all(&String::from_utf8_lossy(&output.stdout), ""))
+    }
+
+    /// Scrubbed, re-printed stdout of a successful `--output json` run
+    fn json(&self, args: &[&str]) -> String {
+        let output = self.output(&[&["--output", "json"][..], args].concat());
+        assert!(output.status.success(), "{:?}", output);
+        self.normalize_json(&LOG_LINE.replace_all(&String::from_utf8_lossy(&output.stdout), ""))
+    }
+
+    /// Scrubbed error envelope of a failed `--output json` run
+    fn error(&self, args: &[&str], exit_code: i32) -> String {
+        let output = self.output(&[&["--output", "json"][..], args].concat());
+        assert_eq!(output.status.code(), Some(exit_code), "{:?}", output);
+        self.normalize_json(&String::from_utf8_lossy(&output.stderr))
+    }
+
+    fn normalize_json(&self, raw: &str) -> String {
+        let mut value: Value = serde_json::from_str(raw).unwrap_or_else(|e| panic!("not JSON ({}): {}", e, raw));
+        round_floats(&mut value);
+        self.scrub(&format!("{}\n", serde_json::to_string_pretty(&value).unwrap()))
+    }
+
+    fn scrub(&self, output: &str) -> String {
+        let mut scrubbed = output.to_string();
+        let dir = self.dir.path();
+        for path in [dir.to_path_buf(), dir.canonicalize().unwrap()] {
+            scrubbed = scrubbed.replace(path.to_str().unwrap(), "[dir]");
+        }
+        for (pattern, replacement) in SCRUBBERS.iter() {
+            scrubbed = pattern.replace_all(&scrubbed, *replacement).into_owned();
+        }
+        scrubbed
+    }
+}
+
+fn round_floats(value: &mut Value) {
+    match value {
+        Value::Number(n) if n.is_f64() => {
+            let rounded: f64 = format!("{:.9e}", n.as_f64().unwrap()).parse().unwrap();
+            *value = Value::from(rounded);
+        }
+        Value::Array(items) => items.iter_mut().for_each(round_floats),
+        Value::Object(fields) => fields.values_mut().for_each(round_floats),
+        _ => {}
+    }
+}
+
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots").join(name);
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!("No snapshot at {}; record it with UPDATE_SNAPSHOTS=1 cargo test --test cli_snapshots", path.display())
+    });
+    if expected != actual {
+        panic!(
+            "{} doesn't match its snapshot (- snapshot, + actual); if the change is intended, \
+             re-record with UPDATE_SNAPSHOTS=1 cargo test --test cli_snapshots\n{}",
+            name,
+            diff(&expected, actual)
+        );
+    }
+}
+
+/// Line diff over the longest common subsequence
+fn diff(expected: &str, actual: &str) -> String {
+    let (old, new): (Vec<&str>, Vec<&str>) = (expected.lines().collect(), actual.lines().collect());
+    // common[i][j]: LCS length of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = match old[i] == new[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+    let (mut i, mut j, mut out) = (0, 0, String::new());
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            writeln!(out, "  {}", old[i]).unwrap();
+            (i, j) = (i + 1, j + 1);
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            writeln!(out, "- {}", old[i]).unwrap();
+            i += 1;
+        } else {
+            writeln!(out, "+ {}", new[j]).unwrap();
+            j += 1;
+        }
+    }
+    out
+}
+
+const OPTION_PRICE: &[&str] =
+    &["option-price", "--spot", "100", "--strike", "105", "--rate", "0.05", "--volatility", "0.2", "--time", "0.5"];
+
+#[test]
+fn test_option_price() {
+    let cli = Cli::ephemeral();
+    assert_snapshot("option_price.txt", &cli.text(OPTION_PRICE));
+    assert_snapshot("option_price.json", &cli.json(OPTION_PRICE));
+}
+
+#[test]
+fn test_option_chain() {
+    let cli = Cli::ephemeral();
+    let args = [
+        "option-chain", "--spot", "100", "--rate", "0.05", "--time", "0.5", "--strikes", "90,100,110",
+        "--volatility", "0.2", "--market-vols", "0.24,0.2,0.19",
+    ];
+    assert_snapshot("option_chain.txt", &cli.text(&args));
+    assert_snapshot("option_chain.json", &cli.json(&args));
+}
+
+#[test]
+fn test_bond_price() {
+    let cli = Cli::ephemeral();
+    let args = ["bond-price", "--face", "1000", "--coupon-rate", "0.06", "--ytm", "0.04", "--years", "5"];
+    assert_snapshot("bond_price.txt", &cli.text(&args));
+}
+
+#[test]
+fn test_option_strategy() {
+    let cli = Cli::ephemeral();
+    let args = ["strategy", "--leg", "call:100:1:5", "--leg", "call:110:-1:2"];
+    assert_snapshot("option_strategy.txt", &cli.text(&args));
+}
+
+#[test]
+fn test_list_carriers() {
+    let cli = Cli::ephemeral();
+    assert_snapshot("list_carriers.txt", &cli.text(&["list-carriers", "--country", "Australia"]));
+}
+
+#[test]
+fn test_esim_check() {
+    let cli = Cli::ephemeral();
+    let args = ["esim", "check", "--eid", "89033023000000000000000000001239", "--carrier", "tmobile"];
+    assert_snapshot("esim_check.txt", &cli.text(&args));
+    assert_snapshot("esim_check.json", &cli.json(&args));
+}
+
+#[test]
+fn test_keys_doctor() {
+    let cli = Cli::ephemeral();
+    assert_snapshot("keys_doctor.txt", &cli.text(&["keys", "doctor"]));
+    assert_snapshot("keys_doctor.json", &cli.json(&["keys", "doctor"]));
+}
+
+#[test]
+fn test_secrets_list() {
+    let cli = Cli::ephemeral()
+        .with_config("[intel.taxii]\npassword = \"secret://taxii.password\"\n\n[network.proxy]\npassword = \"secret://network.proxy.password\"\n");
+    assert_snapshot("secrets_list.txt", &cli.text(&["secrets", "list"]));
+    assert_snapshot("secrets_list.json", &cli.json(&["secrets", "list"]));
+}
+
+#[test]
+fn test_watchlist_show() {
+    let cli = Cli::ephemeral();
+    assert_snapshot("watchlist_show.txt", &cli.text(&["watchlist", "show"]));
+    assert_snapshot("watchlist_show.json", &cli.json(&["watchlist", "show"]));
+}
+
+#[test]
+fn test_empty_ephemeral_commands() {
+    let cli = Cli::ephemeral();
+    let output = [["alerts", "list"], ["maintain", "--now"], ["migrate", "--dry-run"]]
+        .iter()
+        .map(|args| format!("$ {}\n{}", args.join(" "), cli.text(args)))
+        .collect::<String>();
+    assert_snapshot("empty_ephemeral.txt", &output);
+}
+
+#[test]
+fn test_portfolio() {
+    let mut cli = Cli::persistent();
+    let mut output = String::new();
+    for (clock, args) in [
+        ("2026-03-02T14:30:00Z", &["portfolio", "cash", "--in", "10000"][..]),
+        ("2026-03-02T14:31:00Z", &["portfolio", "buy", "--symbol", "aapl", "--quantity", "10", "--price", "150.25"][..]),
+        ("2026-03-02T14:32:00Z", &["portfolio", "buy", "--symbol", "msft", "--quantity", "5", "--price", "400"][..]),
+        ("2026-03-02T14:33:00Z", &["portfolio", "show"][..]),
+        ("2026-03-02T14:33:00Z", &["portfolio", "value"][..]),
+        ("2026-03-02T14:33:00Z", &["portfolio", "ledger"][..]),
+    ] {
+        cli.clock = clock;
+        write!(output, "$ {}\n{}", args.join(" "), cli.text(args)).unwrap();
+    }
+    assert_snapshot("portfolio.txt", &output);
+}
+
+#[test]
+fn test_error_envelopes() {
+    let cli = Cli::ephemeral();
+    let unknown_carrier = cli.error(&["provision-esim", "--carrier", "nope", "--plan", "basic"], 3);
+    assert_snapshot("error_unknown_carrier.json", &unknown_carrier);
+    assert_snapshot("error_no_telemetry.json", &cli.error(&["telemetry", "summary"], 3));
+}
+
+#[test]
+fn test_diff_is_readable() {
+    let expected = "Greeks:\n  Delta: 0.4612\n  Gamma: 0.0281\n";
+    let actual = "Greeks:\n  Delta: 0.4613\n  Gamma: 0.0281\n";
+    assert_eq!(diff(expected, actual), "  Greeks:\n-   Delta: 0.4612\n+   Delta: 0.4613\n    Gamma: 0.0281\n");
+}
@@ -0,0 +1,102 @@
+;; Momentum strategy plugin, hand-assembled twin of tests/plugins/momentum
+;; so the sandbox can be exercised without a wasm32 toolchain.
+;;
+;; Config: {"lookback": N} (1..=63). Buys when the close is above the close
+;; N candles ago, sells when below, holds otherwise.
+(module
+  (memory (export "memory") 1)
+  ;; 0..512: ring buffer of 64 closes (f64); 600/620: JSON keys; 1024..: argument buffer
+  (data (i32.const 600) "\"lookback\"")
+  (data (i32.const 620) "\"close\"")
+  (global $lookback (mut i32) (i32.const 0))
+  (global $count (mut i32) (i32.const 0))
+
+  (func (export "alloc") (param $len i32) (result i32)
+    (local $need i32)
+    (local.set $need (i32.add (i32.const 1024) (local.get $len)))
+    (if (i32.gt_u (local.get $need) (i32.mul (memory.size) (i32.const 65536)))
+      (then
+        (drop (memory.grow
+          (i32.div_u
+            (i32.add (i32.sub (local.get $need) (i32.mul (memory.size) (i32.const 65536))) (i32.const 65535))
+            (i32.const 65536))))))
+    (i32.const 1024))
+
+  ;; Address just past `key` in [ptr, ptr + len), or -1
+  (func $find (param $ptr i32) (param $len i32) (param $key i32) (param $klen i32) (result i32)
+    (local $i i32) (local $j i32)
+    (block $none
+      (loop $scan
+        (br_if $none (i32.gt_u (i32.add (local.get $i) (local.get $klen)) (local.get $len)))
+        (local.set $j (i32.const 0))
+        (block $mismatch
+          (loop $compare
+            (br_if $mismatch
+              (i32.ne
+                (i32.load8_u (i32.add (i32.add (local.get $ptr) (local.get $i)) (local.get $j)))
+                (i32.load8_u (i32.add (local.get $key) (local.get $j)))))
+            (local.set $j (i32.add (local.get $j) (i32.const 1)))
+            (br_if $compare (i32.lt_u (local.get $j) (local.get $klen))))
+          (return (i32.add (i32.add (local.get $ptr) (local.get $i)) (local.get $klen))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $scan)))
+    (i32.const -1))
+
+  ;; Unsigned decimal at `p` after any `:`, space or `"` (decimals arrive as strings)
+  (func $number (param $p i32) (param $end i32) (result f64)
+    (local $c i32) (local $value f64) (local $scale f64)
+    (block $start
+      (loop $skip
+        (br_if $start (i32.ge_u (local.get $p) (local.get $end)))
+        (local.set $c (i32.load8_u (local.get $p)))
+        (br_if $start
+          (i32.and (i32.ne (local.get $c) (i32.const 58))
+            (i32.and (i32.ne (local.get $c) (i32.const 32)) (i32.ne (local.get $c) (i32.const 34)))))
+        (local.set $p (i32.add (local.get $p) (i32.const 1)))
+        (br $skip)))
+    (block $done
+      (loop $digits
+        (br_if $done (i32.ge_u (local.get $p) (local.get $end)))
+        (local.set $c (i32.load8_u (local.get $p)))
+        (if (i32.eq (local.get $c) (i32.const 46))
+          (then (local.set $scale (f64.const 1)))
+          (else
+            (br_if $done (i32.gt_u (i32.sub (local.get $c) (i32.const 48)) (i32.const 9)))
+            (local.set $value
+              (f64.add (f64.mul (local.get $value) (f64.const 10))
+                (f64.convert_i32_u (i32.sub (local.get $c) (i32.const 48)))))
+            (if (f64.ne (local.get $scale) (f64.const 0))
+              (then (local.set $scale (f64.mul (local.get $scale) (f64.const 10)))))))
+        (local.set $p (i32.add (local.get $p) (i32.const 1)))
+        (br $digits)))
+    (if (result f64) (f64.eq (local.get $scale) (f64.const 0))
+      (then (local.get $value))
+      (else (f64.div (local.get $value) (local.get $scale)))))
+
+  (func (export "init") (param $ptr i32) (param $len i32) (result i32)
+    (local $at i32)
+    (local.set $at (call $find (local.get $ptr) (local.get $len) (i32.const 600) (i32.const 10)))
+    (if (i32.lt_s (local.get $at) (i32.const 0)) (then (return (i32.const 1))))
+    (global.set $lookback
+      (i32.trunc_f64_u (call $number (local.get $at) (i32.add (local.get $ptr) (local.get $len)))))
+    (if (i32.or (i32.eqz (global.get $lookback)) (i32.gt_u (global.get $lookback) (i32.const 63)))
+      (then (return (i32.const 2))))
+    (global.set $count (i32.const 0))
+    (i32.const 0))
+
+  (func (export "on_candle") (param $ptr i32) (param $len i32) (result i32)
+    (local $at i32) (local $close f64) (local $past f64)
+    (local.set $at (call $find (local.get $ptr) (local.get $len) (i32.const 620) (i32.const 7)))
+    (if (i32.lt_s (local.get $at) (i32.const 0)) (then (unreachable)))
+    (local.set $close (call $number (local.get $at) (i32.add (local.get $ptr) (local.get $len))))
+    (f64.store (i32.shl (i32.and (global.get $count) (i32.const 63)) (i32.const 3)) (local.get $close))
+    (global.set $count (i32.add (global.get $count) (i32.const 1)))
+    (if (i32.le_u (global.get $count) (global.get $lookback)) (then (return (i32.const 0))))
+    (local.set $past
+      (f64.load
+        (i32.shl
+          (i32.and (i32.sub (i32.sub (global.get $count) (i32.const 1)) (global.get $lookback)) (i32.const 63))
+          (i32.const 3))))
+    (if (f64.gt (local.get $close) (local.get $past)) (then (return (i32.const 1))))
+    (if (f64.lt (local.get $close) (local.get $past)) (then (return (i32.const -1))))
+    (i32.const 0)))
@@ -0,0 +1,87 @@
+//! Momentum strategy plugin
+//! Buys when the close is above the close `lookback` candles ago and sells
+//! when it is below. `../momentum.wat` is the same rule assembled by hand.
+//!
+//! cargo build --release --target wasm32-unknown-unknown
+//! quantraband backtest --candles prices.csv \
+//!     --strategy-wasm target/wasm32-unknown-unknown/release/momentum.wasm \
+//!     --strategy-config config.json   # {"lookback": 5}
+
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+#[derive(Deserialize)]
+struct Config {
+    lookback: usize,
+}
+
+/// The fields of the host's `Candle` this rule reads; decimals arrive as strings
+#[derive(Deserialize)]
+struct Candle {
+    close: String,
+}
+
+#[derive(Default)]
+struct Momentum {
+    lookback: usize,
+    closes: VecDeque<f64>,
+}
+
+thread_local! {
+    static STATE: RefCell<Momentum> = RefCell::default();
+    /// Argument buffer handed out by `alloc`, reused for every call
+    static BUFFER: RefCell<Vec<u8>> = RefCell::default();
+}
+
+const BUY: i32 = 1;
+const SELL: i32 = -1;
+const HOLD: i32 = 0;
+
+#[no_mangle]
+pub extern "C" fn alloc(len: i32) -> *mut u8 {
+    BUFFER.with(|buffer| {
+        let mut buffer = buffer.borrow_mut();
+        buffer.resize(len as usize, 0);
+        buffer.as_mut_ptr()
+    })
+}
+
+/// The argument the host just copied into the buffer
+fn argument<T: for<'de> Deserialize<'de>>(len: i32) -> Option<T> {
+    BUFFER.with(|buffer| serde_json::from_slice(&buffer.borrow()[..len as usize]).ok())
+}
+
+#[no_mangle]
+pub extern "C" fn init(_ptr: *const u8, len: i32) -> i32 {
+    match argument::<Config>(len) {
+        Some(config) if config.lookback > 0 => {
+            STATE.with(|state| *state.borrow_mut() = Momentum { lookback: config.lookback, ..Default::default() });
+            0
+        }
+        Some(_) => 2,
+        None => 1,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn on_candle(_ptr: *const u8, len: i32) -> i32 {
+    // A malformed candle panics, which the host reports as a strategy error
+    let candle: Candle = argument(len).expect("candle JSON");
+    let close: f64 = candle.close.parse().expect("numeric close");
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.closes.push_back(close);
+        if state.closes.len() <= state.lookback {
+            return HOLD;
+        }
+        let past = state.closes.pop_front().unwrap();
+        if close > past {
+            BUY
+        } else if close < past {
+            SELL
+        } else {
+            HOLD
+        }
+    })
+}